@@ -0,0 +1,253 @@
+//! Deduplicated weight blobs
+//!
+//! A model file is read once and kept in page aligned memory, which the
+//! scheme maps into every process opening it. Files are matched by their
+//! contents, so the same weights under different paths, or a model copied
+//! next to an application, share one blob. Blobs nobody holds open are kept
+//! for later opens until they exceed the retention budget, least recently
+//! used first out.
+
+use std::alloc::{self, Layout};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+use std::time::SystemTime;
+
+/// Granularity of mappings
+pub const PAGE_SIZE: usize = 4096;
+
+pub type BlobId = u64;
+
+/// Zeroed page aligned memory
+pub struct PageBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the buffer is owned, and only written before it is shared
+unsafe impl Send for PageBuf {}
+unsafe impl Sync for PageBuf {}
+
+impl PageBuf {
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len.max(1).next_multiple_of(PAGE_SIZE), PAGE_SIZE).unwrap()
+    }
+
+    fn new(len: usize) -> Self {
+        // SAFETY: the layout is never zero sized
+        let ptr = unsafe { alloc::alloc_zeroed(Self::layout(len)) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(Self::layout(len)));
+        Self { ptr, len }
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Bytes that can be mapped, the length rounded up to whole pages
+    pub fn mapped_len(&self) -> usize {
+        Self::layout(self.len).size()
+    }
+}
+
+impl std::ops::Deref for PageBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the allocation holds at least `len` initialized bytes
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl std::ops::DerefMut for PageBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: the allocation holds at least `len` initialized bytes
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for PageBuf {
+    fn drop(&mut self) {
+        // SAFETY: allocated with the same layout
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+}
+
+pub struct Blob {
+    pub data: PageBuf,
+    hash: u64,
+    /// Open handles
+    refs: usize,
+    /// Value of the cache's clock at the last open or close
+    last_used: u64,
+}
+
+/// Version of a file a blob was read from
+struct FileEntry {
+    blob: BlobId,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+pub struct BlobCache {
+    /// Bytes of unreferenced blobs kept around
+    retention: usize,
+    blobs: BTreeMap<BlobId, Blob>,
+    files: BTreeMap<PathBuf, FileEntry>,
+    next_id: BlobId,
+    clock: u64,
+    /// Opens served without reading the file, and bytes not duplicated
+    hits: u64,
+    deduplicated_bytes: u64,
+}
+
+impl BlobCache {
+    pub fn new(retention: usize) -> Self {
+        Self {
+            retention,
+            blobs: BTreeMap::new(),
+            files: BTreeMap::new(),
+            next_id: 1,
+            clock: 0,
+            hits: 0,
+            deduplicated_bytes: 0,
+        }
+    }
+
+    /// Open the blob holding the contents of `path`, reading it if needed
+    pub fn open(&mut self, path: &Path) -> io::Result<BlobId> {
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_file() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let modified = metadata.modified().ok();
+
+        let cached = self.files.get(path).filter(|entry| {
+            entry.len == metadata.len()
+                && entry.modified == modified
+                && self.blobs.contains_key(&entry.blob)
+        });
+        let id = match cached {
+            Some(entry) => {
+                self.hits += 1;
+                entry.blob
+            }
+            None => {
+                let id = self.load(path)?;
+                self.files.insert(
+                    path.to_path_buf(),
+                    FileEntry {
+                        blob: id,
+                        len: metadata.len(),
+                        modified,
+                    },
+                );
+                id
+            }
+        };
+
+        self.clock += 1;
+        let blob = self.blobs.get_mut(&id).unwrap();
+        blob.refs += 1;
+        blob.last_used = self.clock;
+        Ok(id)
+    }
+
+    /// Read `path` into a blob, or find a blob with the same contents
+    fn load(&mut self, path: &Path) -> io::Result<BlobId> {
+        let mut file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        let mut data = PageBuf::new(len);
+        file.read_exact(&mut data)?;
+        let hash = fnv1a(&data);
+
+        if let Some((&id, _)) = self
+            .blobs
+            .iter()
+            .find(|(_, blob)| blob.hash == hash && *blob.data == *data)
+        {
+            self.deduplicated_bytes += len as u64;
+            return Ok(id);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.blobs.insert(
+            id,
+            Blob {
+                data,
+                hash,
+                refs: 0,
+                last_used: self.clock,
+            },
+        );
+        Ok(id)
+    }
+
+    pub fn get(&self, id: BlobId) -> Option<&Blob> {
+        self.blobs.get(&id)
+    }
+
+    /// Drop a reference taken by [`BlobCache::open`]
+    pub fn release(&mut self, id: BlobId) {
+        self.clock += 1;
+        if let Some(blob) = self.blobs.get_mut(&id) {
+            blob.refs -= 1;
+            blob.last_used = self.clock;
+        }
+        self.evict();
+    }
+
+    /// Free unreferenced blobs until they fit the retention budget
+    fn evict(&mut self) {
+        let mut idle: Vec<(u64, BlobId, usize)> = self
+            .blobs
+            .iter()
+            .filter(|(_, blob)| blob.refs == 0)
+            .map(|(&id, blob)| (blob.last_used, id, blob.data.len()))
+            .collect();
+        let mut retained: usize = idle.iter().map(|&(_, _, len)| len).sum();
+        idle.sort_unstable();
+
+        for (_, id, len) in idle {
+            if retained <= self.retention {
+                break;
+            }
+            self.blobs.remove(&id);
+            retained -= len;
+        }
+        let blobs = &self.blobs;
+        self.files.retain(|_, entry| blobs.contains_key(&entry.blob));
+    }
+
+    /// One line per blob, `<id> <bytes> <open handles> <paths>`, after a
+    /// summary line
+    pub fn stats(&self) -> String {
+        let resident: usize = self.blobs.values().map(|blob| blob.data.len()).sum();
+        let mut stats = format!(
+            "blobs {} resident {} hits {} deduplicated {}\n",
+            self.blobs.len(),
+            resident,
+            self.hits,
+            self.deduplicated_bytes
+        );
+        for (id, blob) in &self.blobs {
+            stats.push_str(&format!("{} {} {}", id, blob.data.len(), blob.refs));
+            for (path, _) in self.files.iter().filter(|(_, entry)| entry.blob == *id) {
+                stats.push_str(&format!(" {}", path.display()));
+            }
+            stats.push('\n');
+        }
+        stats
+    }
+}
+
+/// 64-bit FNV-1a, only used to find candidates, which are compared in full
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
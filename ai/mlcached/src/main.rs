@@ -0,0 +1,46 @@
+//! RedoxML weight cache daemon
+//!
+//! Inference processes loading the same model would each hold their own
+//! copy of its weights, often hundreds of megabytes. `mlcached` reads every
+//! model file once, deduplicates identical files, and maps the cached pages
+//! read only into every process opening the model through `mlcache:`.
+
+mod cache;
+mod scheme;
+
+use cache::BlobCache;
+use scheme::CacheScheme;
+
+/// Bytes of blobs no process uses that are kept for the next one
+const RETENTION_BYTES: usize = 512 * 1024 * 1024;
+
+fn daemon(daemon: redox_daemon::Daemon) -> ! {
+    let retention = std::env::var("MLCACHE_RETENTION_MB")
+        .ok()
+        .and_then(|mb| mb.parse::<usize>().ok())
+        .map_or(RETENTION_BYTES, |mb| mb * 1024 * 1024);
+
+    let socket = match redox_scheme::Socket::create("mlcache") {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("mlcached: failed to register scheme: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    daemon
+        .ready()
+        .expect("mlcached: failed to mark daemon as ready");
+
+    match scheme::serve(CacheScheme::new(BlobCache::new(retention)), socket) {
+        Ok(()) => std::process::exit(0),
+        Err(err) => {
+            eprintln!("mlcached: scheme failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    redox_daemon::Daemon::new(daemon).expect("mlcached: failed to create daemon");
+}
@@ -0,0 +1,195 @@
+//! `mlcache:` scheme
+//!
+//! Paths:
+//!
+//! - `mlcache:` lists the cached blobs, see [`BlobCache::stats`]
+//! - `mlcache:<path>` is the model file at the absolute `<path>`
+//!
+//! Model files are opened read only and only by processes allowed to read
+//! the file itself. Their handles are read or, to share the cached pages
+//! instead of copying them, mapped read only from offset 0; their size is
+//! the file size reported by `fstat`. A mapping keeps its handle, and so the
+//! blob, alive until it is unmapped.
+
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult, RequestKind, SignalBehavior, Socket};
+use syscall::data::Stat;
+use syscall::flag::{MODE_DIR, MODE_FILE, O_ACCMODE, O_RDONLY};
+use syscall::schemev2::NewFdFlags;
+use syscall::{Error, MapFlags, Result, EACCES, EBADF, EINVAL, EISDIR, ENOENT, EROFS};
+
+use crate::cache::{BlobCache, BlobId};
+
+enum Handle {
+    /// Blob list
+    List(Vec<u8>),
+    Blob { id: BlobId, path: PathBuf },
+}
+
+pub struct CacheScheme {
+    cache: BlobCache,
+    handles: BTreeMap<usize, Handle>,
+    next_id: usize,
+}
+
+/// Whether the permission bits of `path` let `uid` and `gid` read it
+fn readable_by(path: &Path, uid: u32, gid: u32) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    let mode = metadata.mode();
+    uid == 0
+        || (metadata.uid() == uid && mode & 0o400 != 0)
+        || (metadata.gid() == gid && mode & 0o040 != 0)
+        || mode & 0o004 != 0
+}
+
+impl CacheScheme {
+    pub fn new(cache: BlobCache) -> Self {
+        Self {
+            cache,
+            handles: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn on_close(&mut self, id: usize) {
+        if let Some(Handle::Blob { id, .. }) = self.handles.remove(&id) {
+            self.cache.release(id);
+        }
+    }
+}
+
+impl SchemeSync for CacheScheme {
+    fn open(&mut self, path: &str, flags: usize, ctx: &CallerCtx) -> Result<OpenResult> {
+        if flags & O_ACCMODE != O_RDONLY {
+            return Err(Error::new(EROFS));
+        }
+
+        let handle = match path.trim_matches('/') {
+            "" => Handle::List(self.cache.stats().into_bytes()),
+            file => {
+                let path = Path::new("/").join(file);
+                if !readable_by(&path, ctx.uid, ctx.gid) {
+                    return Err(Error::new(EACCES));
+                }
+                let id = self.cache.open(&path).map_err(|err| {
+                    eprintln!("mlcached: failed to load {}: {}", path.display(), err);
+                    Error::new(ENOENT)
+                })?;
+                Handle::Blob { id, path }
+            }
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handles.insert(id, handle);
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let data: &[u8] = match self.handles.get(&id).ok_or(Error::new(EBADF))? {
+            Handle::List(text) => text,
+            Handle::Blob { id, .. } => &self.cache.get(*id).ok_or(Error::new(EBADF))?.data,
+        };
+        let start = usize::try_from(offset).map_or(data.len(), |offset| offset.min(data.len()));
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write(
+        &mut self,
+        id: usize,
+        _buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        self.handles.get(&id).ok_or(Error::new(EBADF))?;
+        Err(Error::new(EROFS))
+    }
+
+    fn fstat(&mut self, id: usize, stat: &mut Stat, _ctx: &CallerCtx) -> Result<()> {
+        match self.handles.get(&id).ok_or(Error::new(EBADF))? {
+            Handle::List(text) => {
+                stat.st_mode = MODE_DIR | 0o555;
+                stat.st_size = text.len() as u64;
+            }
+            Handle::Blob { id, .. } => {
+                stat.st_mode = MODE_FILE | 0o444;
+                stat.st_size = self.cache.get(*id).ok_or(Error::new(EBADF))?.data.len() as u64;
+            }
+        }
+        Ok(())
+    }
+
+    fn mmap_prep(
+        &mut self,
+        id: usize,
+        offset: u64,
+        size: usize,
+        flags: MapFlags,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let Handle::Blob { id, .. } = self.handles.get(&id).ok_or(Error::new(EBADF))? else {
+            return Err(Error::new(EISDIR));
+        };
+        // Private writable mappings are copied on write, shared ones would
+        // change the weights of every process
+        if flags.contains(MapFlags::PROT_WRITE | MapFlags::MAP_SHARED) {
+            return Err(Error::new(EACCES));
+        }
+        let data = &self.cache.get(*id).ok_or(Error::new(EBADF))?.data;
+        let start = usize::try_from(offset).map_err(|_| Error::new(EINVAL))?;
+        let end = start.checked_add(size).ok_or(Error::new(EINVAL))?;
+        if end > data.mapped_len() {
+            return Err(Error::new(EINVAL));
+        }
+        Ok(data.as_ptr() as usize + start)
+    }
+
+    fn fpath(&mut self, id: usize, buf: &mut [u8], _ctx: &CallerCtx) -> Result<usize> {
+        let path = match self.handles.get(&id).ok_or(Error::new(EBADF))? {
+            Handle::List(_) => "mlcache:".to_string(),
+            Handle::Blob { path, .. } => format!("mlcache:{}", path.display()),
+        };
+        let len = buf.len().min(path.len());
+        buf[..len].copy_from_slice(&path.as_bytes()[..len]);
+        Ok(len)
+    }
+}
+
+/// Serve `mlcache:` until the scheme is unmounted
+pub fn serve(mut scheme: CacheScheme, socket: Socket) -> Result<()> {
+    loop {
+        let Some(request) = socket.next_request(SignalBehavior::Restart)? else {
+            // Scheme likely got unmounted
+            return Ok(());
+        };
+
+        match request.kind() {
+            RequestKind::Call(call) => {
+                let response = call.handle_sync(&mut scheme);
+                socket.write_response(response, SignalBehavior::Restart)?;
+            }
+            RequestKind::OnClose { id } => scheme.on_close(id),
+            _ => (),
+        }
+    }
+}
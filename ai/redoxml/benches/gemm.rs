@@ -0,0 +1,39 @@
+//! GEMM throughput on the CPU
+//!
+//! Run with `cargo bench --bench gemm`.
+
+use std::time::{Duration, Instant};
+
+use redoxml::TensorType;
+
+fn bench<T: TensorType>(name: &str, size: usize) {
+    let a: Vec<T> = (0..size * size).map(|i| T::from(i % 17).unwrap()).collect();
+    let b: Vec<T> = (0..size * size).map(|i| T::from(i % 19).unwrap()).collect();
+    let mut c = vec![T::zero(); size * size];
+
+    // Warm up the thread pool and caches
+    redoxml::gemm_into(&a, &b, &mut c, size, size, size);
+
+    let mut runs = 0u32;
+    let start = Instant::now();
+    while runs == 0 || start.elapsed() < Duration::from_secs(1) {
+        redoxml::gemm_into(&a, &b, &mut c, size, size, size);
+        runs += 1;
+    }
+    let per_run = start.elapsed() / runs;
+    let gflops = 2.0 * (size as f64).powi(3) / per_run.as_secs_f64() / 1e9;
+    println!(
+        "{} {:>5}: {:>10.3?} per run, {:>7.2} GFLOP/s",
+        name, size, per_run, gflops
+    );
+}
+
+fn main() {
+    println!("SIMD kernel: {}", redoxml::simd_kernel());
+    for size in [64, 128, 256, 512, 1024] {
+        bench::<f32>("sgemm", size);
+    }
+    for size in [64, 256, 1024] {
+        bench::<f64>("dgemm", size);
+    }
+}
@@ -0,0 +1,384 @@
+//! Reverse-mode Automatic Differentiation
+//!
+//! Operations are recorded on a [`Tape`] as they are evaluated. Calling
+//! [`Tape::backward`] walks the tape in reverse and accumulates the gradient
+//! of a scalar output with respect to every recorded value. Only CPU tensors
+//! are supported for now.
+
+pub mod optim;
+
+pub use optim::{Adam, Optimizer, Sgd};
+
+use crate::tensor::{Shape, Tensor, TensorType};
+
+/// Handle to a value recorded on a [`Tape`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Var(usize);
+
+#[derive(Debug, Clone, Copy)]
+enum Op<T> {
+    Leaf,
+    MatMul(Var, Var),
+    Add(Var, Var),
+    Sub(Var, Var),
+    Mul(Var, Var),
+    AddBias(Var, Var),
+    Scale(Var, T),
+    Relu(Var),
+    Sigmoid(Var),
+    Tanh(Var),
+    Sum(Var),
+    Mean(Var),
+    MseLoss(Var, Var),
+}
+
+struct Node<T> {
+    shape: Shape,
+    value: Vec<T>,
+    op: Op<T>,
+}
+
+/// Record of the operations of one forward pass
+pub struct Tape<T: TensorType> {
+    nodes: Vec<Node<T>>,
+}
+
+impl<T: TensorType> Default for Tape<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TensorType> Tape<T> {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    /// Record a tensor the gradient can be taken with respect to
+    pub fn var(&mut self, tensor: &Tensor<T>) -> Result<Var, &'static str> {
+        let value = tensor
+            .data_as_slice()
+            .ok_or("Autograd requires CPU tensors")?;
+        Ok(self.push(tensor.shape().clone(), value.to_vec(), Op::Leaf))
+    }
+
+    fn push(&mut self, shape: Shape, value: Vec<T>, op: Op<T>) -> Var {
+        self.nodes.push(Node { shape, value, op });
+        Var(self.nodes.len() - 1)
+    }
+
+    fn node(&self, var: Var) -> &Node<T> {
+        &self.nodes[var.0]
+    }
+
+    /// Current value of a recorded variable
+    pub fn value(&self, var: Var) -> Tensor<T> {
+        let node = self.node(var);
+        Tensor::new(node.shape.clone(), node.value.clone())
+    }
+
+    fn elementwise(
+        &mut self,
+        a: Var,
+        b: Var,
+        op: Op<T>,
+        f: impl Fn(T, T) -> T,
+    ) -> Result<Var, &'static str> {
+        let (a_node, b_node) = (self.node(a), self.node(b));
+        if a_node.shape != b_node.shape {
+            return Err("Shape mismatch");
+        }
+        let value = zip(&a_node.value, &b_node.value, f);
+        Ok(self.push(a_node.shape.clone(), value, op))
+    }
+
+    fn map(&mut self, a: Var, op: Op<T>, f: impl Fn(T) -> T) -> Var {
+        let node = self.node(a);
+        let value = node.value.iter().map(|&x| f(x)).collect();
+        self.push(node.shape.clone(), value, op)
+    }
+
+    /// Matrix product of two 2D variables
+    pub fn matmul(&mut self, a: Var, b: Var) -> Result<Var, &'static str> {
+        let (m, k) = dims2(&self.node(a).shape)?;
+        let (k2, n) = dims2(&self.node(b).shape)?;
+        if k != k2 {
+            return Err("Incompatible matrix dimensions");
+        }
+        let value = matmul(&self.node(a).value, &self.node(b).value, m, k, n);
+        Ok(self.push(Shape::new(vec![m, n]), value, Op::MatMul(a, b)))
+    }
+
+    pub fn add(&mut self, a: Var, b: Var) -> Result<Var, &'static str> {
+        self.elementwise(a, b, Op::Add(a, b), |x, y| x + y)
+    }
+
+    pub fn sub(&mut self, a: Var, b: Var) -> Result<Var, &'static str> {
+        self.elementwise(a, b, Op::Sub(a, b), |x, y| x - y)
+    }
+
+    /// Elementwise product
+    pub fn mul(&mut self, a: Var, b: Var) -> Result<Var, &'static str> {
+        self.elementwise(a, b, Op::Mul(a, b), |x, y| x * y)
+    }
+
+    /// Add a bias of shape `[n]` to every row of an `[m, n]` variable
+    pub fn add_bias(&mut self, x: Var, bias: Var) -> Result<Var, &'static str> {
+        let (_, n) = dims2(&self.node(x).shape)?;
+        if self.node(bias).shape.dims != [n] {
+            return Err("Bias does not match the number of columns");
+        }
+        let bias_value = &self.node(bias).value;
+        let value = self
+            .node(x)
+            .value
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| v + bias_value[i % n])
+            .collect();
+        Ok(self.push(self.node(x).shape.clone(), value, Op::AddBias(x, bias)))
+    }
+
+    pub fn scale(&mut self, a: Var, factor: T) -> Var {
+        self.map(a, Op::Scale(a, factor), |x| x * factor)
+    }
+
+    pub fn relu(&mut self, a: Var) -> Var {
+        self.map(a, Op::Relu(a), |x| x.max(T::zero()))
+    }
+
+    pub fn sigmoid(&mut self, a: Var) -> Var {
+        self.map(a, Op::Sigmoid(a), sigmoid)
+    }
+
+    pub fn tanh(&mut self, a: Var) -> Var {
+        self.map(a, Op::Tanh(a), T::tanh)
+    }
+
+    /// Sum of all elements
+    pub fn sum(&mut self, a: Var) -> Var {
+        let sum = self.node(a).value.iter().fold(T::zero(), |acc, &x| acc + x);
+        self.push(Shape::new(vec![1]), vec![sum], Op::Sum(a))
+    }
+
+    /// Mean of all elements
+    pub fn mean(&mut self, a: Var) -> Var {
+        let node = self.node(a);
+        let sum = node.value.iter().fold(T::zero(), |acc, &x| acc + x);
+        let mean = sum / count(node.value.len());
+        self.push(Shape::new(vec![1]), vec![mean], Op::Mean(a))
+    }
+
+    /// Mean squared error between a prediction and a target
+    pub fn mse_loss(&mut self, prediction: Var, target: Var) -> Result<Var, &'static str> {
+        let (p, t) = (self.node(prediction), self.node(target));
+        if p.shape != t.shape {
+            return Err("Shape mismatch");
+        }
+        let sum = p
+            .value
+            .iter()
+            .zip(&t.value)
+            .fold(T::zero(), |acc, (&p, &t)| acc + (p - t) * (p - t));
+        let loss = sum / count(p.value.len());
+        Ok(self.push(
+            Shape::new(vec![1]),
+            vec![loss],
+            Op::MseLoss(prediction, target),
+        ))
+    }
+
+    /// Compute the gradient of `output` with respect to every recorded variable
+    ///
+    /// `output` is usually a scalar loss. For non-scalar outputs the gradient
+    /// of the sum of its elements is computed.
+    pub fn backward(&self, output: Var) -> Gradients<T> {
+        let mut grads: Vec<Option<Vec<T>>> = vec![None; output.0 + 1];
+        grads[output.0] = Some(vec![T::one(); self.node(output).value.len()]);
+
+        for index in (0..=output.0).rev() {
+            let node = &self.nodes[index];
+            // Leaves keep their gradient, intermediate ones are dropped once propagated.
+            if matches!(node.op, Op::Leaf) {
+                continue;
+            }
+            let Some(grad) = grads[index].take() else {
+                continue;
+            };
+
+            match node.op {
+                Op::Leaf => unreachable!(),
+                Op::MatMul(a, b) => {
+                    let (m, k) = (self.node(a).shape.dims[0], self.node(a).shape.dims[1]);
+                    let n = self.node(b).shape.dims[1];
+                    let da = matmul_bt(&grad, &self.node(b).value, m, n, k);
+                    let db = matmul_at(&self.node(a).value, &grad, m, k, n);
+                    accumulate(&mut grads, a, da);
+                    accumulate(&mut grads, b, db);
+                }
+                Op::Add(a, b) => {
+                    accumulate(&mut grads, a, grad.clone());
+                    accumulate(&mut grads, b, grad);
+                }
+                Op::Sub(a, b) => {
+                    let neg = grad.iter().map(|&g| -g).collect();
+                    accumulate(&mut grads, a, grad);
+                    accumulate(&mut grads, b, neg);
+                }
+                Op::Mul(a, b) => {
+                    let da = zip(&grad, &self.node(b).value, |g, y| g * y);
+                    let db = zip(&grad, &self.node(a).value, |g, x| g * x);
+                    accumulate(&mut grads, a, da);
+                    accumulate(&mut grads, b, db);
+                }
+                Op::AddBias(x, bias) => {
+                    let n = self.node(bias).value.len();
+                    let mut db = vec![T::zero(); n];
+                    for (i, &g) in grad.iter().enumerate() {
+                        db[i % n] = db[i % n] + g;
+                    }
+                    accumulate(&mut grads, x, grad);
+                    accumulate(&mut grads, bias, db);
+                }
+                Op::Scale(a, factor) => {
+                    let da = grad.iter().map(|&g| g * factor).collect();
+                    accumulate(&mut grads, a, da);
+                }
+                Op::Relu(a) => {
+                    let da = zip(&grad, &self.node(a).value, |g, x| {
+                        if x > T::zero() {
+                            g
+                        } else {
+                            T::zero()
+                        }
+                    });
+                    accumulate(&mut grads, a, da);
+                }
+                Op::Sigmoid(a) => {
+                    // d/dx sigmoid(x) = y * (1 - y)
+                    let da = zip(&grad, &node.value, |g, y| g * y * (T::one() - y));
+                    accumulate(&mut grads, a, da);
+                }
+                Op::Tanh(a) => {
+                    // d/dx tanh(x) = 1 - y^2
+                    let da = zip(&grad, &node.value, |g, y| g * (T::one() - y * y));
+                    accumulate(&mut grads, a, da);
+                }
+                Op::Sum(a) => {
+                    let da = vec![grad[0]; self.node(a).value.len()];
+                    accumulate(&mut grads, a, da);
+                }
+                Op::Mean(a) => {
+                    let len = self.node(a).value.len();
+                    let da = vec![grad[0] / count(len); len];
+                    accumulate(&mut grads, a, da);
+                }
+                Op::MseLoss(prediction, target) => {
+                    let (p, t) = (&self.node(prediction).value, &self.node(target).value);
+                    let factor = grad[0] * count(2) / count(p.len());
+                    let dp: Vec<T> = zip(p, t, |p, t| factor * (p - t));
+                    let dt = dp.iter().map(|&g| -g).collect();
+                    accumulate(&mut grads, prediction, dp);
+                    accumulate(&mut grads, target, dt);
+                }
+            }
+        }
+
+        Gradients {
+            grads: grads
+                .into_iter()
+                .enumerate()
+                .map(|(index, grad)| {
+                    grad.map(|grad| Tensor::new(self.nodes[index].shape.clone(), grad))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Gradients computed by [`Tape::backward`]
+pub struct Gradients<T: TensorType> {
+    grads: Vec<Option<Tensor<T>>>,
+}
+
+impl<T: TensorType> Gradients<T> {
+    /// Gradient with respect to a variable recorded with [`Tape::var`]
+    ///
+    /// Returns `None` if the output doesn't depend on the variable.
+    pub fn get(&self, var: Var) -> Option<&Tensor<T>> {
+        self.grads.get(var.0)?.as_ref()
+    }
+}
+
+fn accumulate<T: TensorType>(grads: &mut [Option<Vec<T>>], var: Var, grad: Vec<T>) {
+    match &mut grads[var.0] {
+        Some(existing) => {
+            for (e, g) in existing.iter_mut().zip(grad) {
+                *e = *e + g;
+            }
+        }
+        slot @ None => *slot = Some(grad),
+    }
+}
+
+fn dims2(shape: &Shape) -> Result<(usize, usize), &'static str> {
+    match *shape.get_dims() {
+        [rows, cols] => Ok((rows, cols)),
+        _ => Err("Operation requires 2D tensors"),
+    }
+}
+
+fn count<T: TensorType>(n: usize) -> T {
+    T::from(n).expect("element count not representable")
+}
+
+fn sigmoid<T: TensorType>(x: T) -> T {
+    T::one() / (T::one() + (-x).exp())
+}
+
+fn zip<T: TensorType>(a: &[T], b: &[T], f: impl Fn(T, T) -> T) -> Vec<T> {
+    a.iter().zip(b).map(|(&x, &y)| f(x, y)).collect()
+}
+
+/// `a (m x k) * b (k x n)`
+fn matmul<T: TensorType>(a: &[T], b: &[T], m: usize, k: usize, n: usize) -> Vec<T> {
+    let mut c = vec![T::zero(); m * n];
+    for i in 0..m {
+        for p in 0..k {
+            let a_ip = a[i * k + p];
+            for j in 0..n {
+                c[i * n + j] = c[i * n + j] + a_ip * b[p * n + j];
+            }
+        }
+    }
+    c
+}
+
+/// `a (m x n) * b^T`, with `b` being `k x n`
+fn matmul_bt<T: TensorType>(a: &[T], b: &[T], m: usize, n: usize, k: usize) -> Vec<T> {
+    let mut c = vec![T::zero(); m * k];
+    for i in 0..m {
+        for p in 0..k {
+            let mut sum = T::zero();
+            for j in 0..n {
+                sum = sum + a[i * n + j] * b[p * n + j];
+            }
+            c[i * k + p] = sum;
+        }
+    }
+    c
+}
+
+/// `a^T * b`, with `a` being `m x k` and `b` being `m x n`
+fn matmul_at<T: TensorType>(a: &[T], b: &[T], m: usize, k: usize, n: usize) -> Vec<T> {
+    let mut c = vec![T::zero(); k * n];
+    for i in 0..m {
+        for p in 0..k {
+            let a_ip = a[i * k + p];
+            for j in 0..n {
+                c[p * n + j] = c[p * n + j] + a_ip * b[i * n + j];
+            }
+        }
+    }
+    c
+}
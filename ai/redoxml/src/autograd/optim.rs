@@ -0,0 +1,146 @@
+//! Gradient Descent Optimizers
+
+use crate::tensor::{Tensor, TensorType};
+
+/// Updates parameters from their gradients
+pub trait Optimizer<T: TensorType> {
+    /// Apply one update step
+    ///
+    /// `params` and `grads` are matched by index, and the parameters have to
+    /// be passed in the same order on every step.
+    fn step(&mut self, params: &mut [Tensor<T>], grads: &[&Tensor<T>]) -> Result<(), &'static str>;
+}
+
+/// Stochastic gradient descent with optional momentum
+pub struct Sgd<T> {
+    learning_rate: T,
+    momentum: T,
+    velocity: Vec<Vec<T>>,
+}
+
+impl<T: TensorType> Sgd<T> {
+    pub fn new(learning_rate: T) -> Self {
+        Self {
+            learning_rate,
+            momentum: T::zero(),
+            velocity: Vec::new(),
+        }
+    }
+
+    pub fn with_momentum(mut self, momentum: T) -> Self {
+        self.momentum = momentum;
+        self
+    }
+}
+
+impl<T: TensorType> Optimizer<T> for Sgd<T> {
+    fn step(&mut self, params: &mut [Tensor<T>], grads: &[&Tensor<T>]) -> Result<(), &'static str> {
+        check(params, grads)?;
+        self.velocity.resize_with(params.len(), Vec::new);
+
+        for ((param, grad), velocity) in params.iter_mut().zip(grads).zip(&mut self.velocity) {
+            let grad = grad.data_as_slice().ok_or("Gradient not on CPU")?;
+            velocity.resize(grad.len(), T::zero());
+
+            let value = param
+                .data_as_slice()
+                .ok_or("Parameter not on CPU")?
+                .iter()
+                .zip(grad)
+                .zip(velocity.iter_mut())
+                .map(|((&p, &g), v)| {
+                    *v = self.momentum * *v + g;
+                    p - self.learning_rate * *v
+                })
+                .collect();
+            *param = Tensor::new(param.shape().clone(), value);
+        }
+        Ok(())
+    }
+}
+
+/// Adam optimizer (Kingma & Ba, 2014)
+pub struct Adam<T> {
+    learning_rate: T,
+    beta1: T,
+    beta2: T,
+    epsilon: T,
+    t: i32,
+    m: Vec<Vec<T>>,
+    v: Vec<Vec<T>>,
+}
+
+impl<T: TensorType> Adam<T> {
+    /// Adam with the usual defaults of `beta1 = 0.9`, `beta2 = 0.999` and `epsilon = 1e-8`
+    pub fn new(learning_rate: T) -> Self {
+        Self::with_betas(
+            learning_rate,
+            T::from(0.9).unwrap(),
+            T::from(0.999).unwrap(),
+            T::from(1e-8).unwrap(),
+        )
+    }
+
+    pub fn with_betas(learning_rate: T, beta1: T, beta2: T, epsilon: T) -> Self {
+        Self {
+            learning_rate,
+            beta1,
+            beta2,
+            epsilon,
+            t: 0,
+            m: Vec::new(),
+            v: Vec::new(),
+        }
+    }
+}
+
+impl<T: TensorType> Optimizer<T> for Adam<T> {
+    fn step(&mut self, params: &mut [Tensor<T>], grads: &[&Tensor<T>]) -> Result<(), &'static str> {
+        check(params, grads)?;
+        self.m.resize_with(params.len(), Vec::new);
+        self.v.resize_with(params.len(), Vec::new);
+        self.t += 1;
+
+        let one = T::one();
+        let bias1 = one - self.beta1.powi(self.t);
+        let bias2 = one - self.beta2.powi(self.t);
+
+        for (i, (param, grad)) in params.iter_mut().zip(grads).enumerate() {
+            let grad = grad.data_as_slice().ok_or("Gradient not on CPU")?;
+            let (m, v) = (&mut self.m[i], &mut self.v[i]);
+            m.resize(grad.len(), T::zero());
+            v.resize(grad.len(), T::zero());
+
+            let value = param
+                .data_as_slice()
+                .ok_or("Parameter not on CPU")?
+                .iter()
+                .zip(grad)
+                .zip(m.iter_mut().zip(v.iter_mut()))
+                .map(|((&p, &g), (m, v))| {
+                    *m = self.beta1 * *m + (one - self.beta1) * g;
+                    *v = self.beta2 * *v + (one - self.beta2) * g * g;
+                    let m_hat = *m / bias1;
+                    let v_hat = *v / bias2;
+                    p - self.learning_rate * m_hat / (v_hat.sqrt() + self.epsilon)
+                })
+                .collect();
+            *param = Tensor::new(param.shape().clone(), value);
+        }
+        Ok(())
+    }
+}
+
+fn check<T: TensorType>(params: &[Tensor<T>], grads: &[&Tensor<T>]) -> Result<(), &'static str> {
+    if params.len() != grads.len() {
+        return Err("Number of parameters and gradients differ");
+    }
+    if params
+        .iter()
+        .zip(grads)
+        .any(|(param, grad)| param.shape() != grad.shape())
+    {
+        return Err("Gradient shape does not match parameter");
+    }
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! GEMM Microkernels
+//!
+//! A microkernel multiplies a packed `mr x kc` strip of A with a packed
+//! `kc x nr` strip of B and adds the `mr x nr` product to C, keeping the
+//! product in registers for the whole strip. Packed A holds `mr` values per
+//! step along k, packed B `nr` values.
+//!
+//! The f32 kernels are chosen at runtime from what the CPU supports, other
+//! types use the portable kernel.
+
+use std::any::TypeId;
+use std::sync::OnceLock;
+
+use crate::tensor::TensorType;
+
+/// `c[i * rs_c + j] += sum(a[p * mr + i] * b[p * nr + j])` over `p < kc`
+pub type KernelFn<T> = unsafe fn(kc: usize, a: *const T, b: *const T, c: *mut T, rs_c: usize);
+
+/// Microkernel and the tile it computes
+pub struct Kernel<T> {
+    pub name: &'static str,
+    pub mr: usize,
+    pub nr: usize,
+    pub func: KernelFn<T>,
+}
+
+impl<T> Clone for Kernel<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Kernel<T> {}
+
+/// Portable kernel, vectorized as far as the compiler manages
+unsafe fn generic<T: TensorType, const MR: usize, const NR: usize>(
+    kc: usize,
+    a: *const T,
+    b: *const T,
+    c: *mut T,
+    rs_c: usize,
+) {
+    let mut acc = [[T::zero(); NR]; MR];
+    for p in 0..kc {
+        let a = a.add(p * MR);
+        let b = b.add(p * NR);
+        for (i, row) in acc.iter_mut().enumerate() {
+            let ai = *a.add(i);
+            for (j, acc) in row.iter_mut().enumerate() {
+                *acc = *acc + ai * *b.add(j);
+            }
+        }
+    }
+    for (i, row) in acc.iter().enumerate() {
+        let c = c.add(i * rs_c);
+        for (j, &acc) in row.iter().enumerate() {
+            *c.add(j) = *c.add(j) + acc;
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// 6x16 tile, two vectors per row
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn avx2_6x16(kc: usize, a: *const f32, b: *const f32, c: *mut f32, rs_c: usize) {
+        let mut acc = [[_mm256_setzero_ps(); 2]; 6];
+        for p in 0..kc {
+            let a = a.add(p * 6);
+            let b0 = _mm256_loadu_ps(b.add(p * 16));
+            let b1 = _mm256_loadu_ps(b.add(p * 16 + 8));
+            for (i, row) in acc.iter_mut().enumerate() {
+                let ai = _mm256_broadcast_ss(&*a.add(i));
+                row[0] = _mm256_fmadd_ps(ai, b0, row[0]);
+                row[1] = _mm256_fmadd_ps(ai, b1, row[1]);
+            }
+        }
+        for (i, row) in acc.iter().enumerate() {
+            let c = c.add(i * rs_c);
+            _mm256_storeu_ps(c, _mm256_add_ps(_mm256_loadu_ps(c), row[0]));
+            _mm256_storeu_ps(c.add(8), _mm256_add_ps(_mm256_loadu_ps(c.add(8)), row[1]));
+        }
+    }
+
+    /// 4x8 tile, two vectors per row
+    #[target_feature(enable = "sse")]
+    pub unsafe fn sse_4x8(kc: usize, a: *const f32, b: *const f32, c: *mut f32, rs_c: usize) {
+        let mut acc = [[_mm_setzero_ps(); 2]; 4];
+        for p in 0..kc {
+            let a = a.add(p * 4);
+            let b0 = _mm_loadu_ps(b.add(p * 8));
+            let b1 = _mm_loadu_ps(b.add(p * 8 + 4));
+            for (i, row) in acc.iter_mut().enumerate() {
+                let ai = _mm_set1_ps(*a.add(i));
+                row[0] = _mm_add_ps(row[0], _mm_mul_ps(ai, b0));
+                row[1] = _mm_add_ps(row[1], _mm_mul_ps(ai, b1));
+            }
+        }
+        for (i, row) in acc.iter().enumerate() {
+            let c = c.add(i * rs_c);
+            _mm_storeu_ps(c, _mm_add_ps(_mm_loadu_ps(c), row[0]));
+            _mm_storeu_ps(c.add(4), _mm_add_ps(_mm_loadu_ps(c.add(4)), row[1]));
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use std::arch::aarch64::*;
+
+    /// 8x8 tile, two vectors per row
+    #[target_feature(enable = "neon")]
+    pub unsafe fn neon_8x8(kc: usize, a: *const f32, b: *const f32, c: *mut f32, rs_c: usize) {
+        let mut acc = [[vdupq_n_f32(0.0); 2]; 8];
+        for p in 0..kc {
+            let a = a.add(p * 8);
+            let b0 = vld1q_f32(b.add(p * 8));
+            let b1 = vld1q_f32(b.add(p * 8 + 4));
+            for (i, row) in acc.iter_mut().enumerate() {
+                let ai = *a.add(i);
+                row[0] = vfmaq_n_f32(row[0], b0, ai);
+                row[1] = vfmaq_n_f32(row[1], b1, ai);
+            }
+        }
+        for (i, row) in acc.iter().enumerate() {
+            let c = c.add(i * rs_c);
+            vst1q_f32(c, vaddq_f32(vld1q_f32(c), row[0]));
+            vst1q_f32(c.add(4), vaddq_f32(vld1q_f32(c.add(4)), row[1]));
+        }
+    }
+}
+
+fn detect_f32() -> Kernel<f32> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            return Kernel {
+                name: "avx2",
+                mr: 6,
+                nr: 16,
+                func: x86::avx2_6x16,
+            };
+        }
+        if is_x86_feature_detected!("sse") {
+            return Kernel {
+                name: "sse",
+                mr: 4,
+                nr: 8,
+                func: x86::sse_4x8,
+            };
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return Kernel {
+                name: "neon",
+                mr: 8,
+                nr: 8,
+                func: aarch64::neon_8x8,
+            };
+        }
+    }
+    generic_kernel()
+}
+
+fn generic_kernel<T: TensorType>() -> Kernel<T> {
+    Kernel {
+        name: "generic",
+        mr: 4,
+        nr: 8,
+        func: generic::<T, 4, 8>,
+    }
+}
+
+/// Best f32 kernel of this CPU
+pub fn f32_kernel() -> Kernel<f32> {
+    static KERNEL: OnceLock<Kernel<f32>> = OnceLock::new();
+    *KERNEL.get_or_init(detect_f32)
+}
+
+/// Best kernel for `T`
+pub fn select<T: TensorType>() -> Kernel<T> {
+    if TypeId::of::<T>() == TypeId::of::<f32>() {
+        let kernel = f32_kernel();
+        return Kernel {
+            name: kernel.name,
+            mr: kernel.mr,
+            nr: kernel.nr,
+            // SAFETY: `T` is `f32`
+            func: unsafe { std::mem::transmute::<KernelFn<f32>, KernelFn<T>>(kernel.func) },
+        };
+    }
+    generic_kernel()
+}
@@ -1,12 +1,42 @@
 //! Optimized BLAS Routines
+//!
+//! Large matrix multiplies run blocked the way GotoBLAS does: B is packed
+//! into panels fitting the L3 cache, A into blocks fitting the L2 cache, and
+//! a SIMD microkernel picked for the CPU at runtime computes register tiles
+//! of C from them. The blocks of A are spread across a pool of worker
+//! threads, each writing its own rows of C.
+
+mod kernel;
+mod pool;
+
 use crate::tensor::{Tensor, TensorType};
+use kernel::Kernel;
+
+/// Rows of A packed per block, a multiple of every kernel's tile height
+const MC: usize = 96;
+/// Depth of the packed blocks
+const KC: usize = 256;
+/// Columns of B packed per panel
+const NC: usize = 4096;
+
+/// Multiplies below this many multiply-adds run on the calling thread
+const PARALLEL_THRESHOLD: usize = 64 * 64 * 64;
 
 /// Initialize BLAS
 pub fn init() -> Result<(), &'static str> {
-    log::info!("Initializing BLAS");
+    log::info!(
+        "Initializing BLAS: {} kernel, {} threads",
+        kernel::f32_kernel().name,
+        pool::global().threads()
+    );
     Ok(())
 }
 
+/// SIMD kernel f32 multiplies run on
+pub fn simd_kernel() -> &'static str {
+    kernel::f32_kernel().name
+}
+
 /// General Matrix Multiply (GEMM)
 pub async fn gemm<T: TensorType>(a: &Tensor<T>, b: &Tensor<T>) -> Result<Tensor<T>, &'static str> {
     let a_shape = a.shape();
@@ -29,8 +59,13 @@ pub async fn gemm<T: TensorType>(a: &Tensor<T>, b: &Tensor<T>) -> Result<Tensor<
         return gemm_naive(a, b, m, k, n).await;
     }
 
-    // Use tiled implementation for larger matrices
-    gemm_tiled(a, b, m, k, n).await
+    let a_data = a.data_as_slice().ok_or("Data not on CPU")?;
+    let b_data = b.data_as_slice().ok_or("Data not on CPU")?;
+    let mut c_data = vec![T::zero(); m * n];
+    gemm_into(a_data, b_data, &mut c_data, m, k, n);
+
+    let shape = crate::tensor::Shape::new(vec![m, n]);
+    Ok(Tensor::new(shape, c_data))
 }
 
 async fn gemm_naive<T: TensorType>(
@@ -64,39 +99,167 @@ async fn gemm_naive<T: TensorType>(
     Ok(Tensor::new(shape, c_data))
 }
 
-async fn gemm_tiled<T: TensorType>(
-    a: &Tensor<T>,
-    b: &Tensor<T>,
-    m: usize,
-    k: usize,
-    n: usize,
-) -> Result<Tensor<T>, &'static str> {
-    let a_data = a.data_as_slice().ok_or("Data not on CPU")?;
-    let b_data = b.data_as_slice().ok_or("Data not on CPU")?;
-    let mut c_data = vec![T::zero(); m * n];
+/// Multiply the row major `m x k` matrix `a` with the `k x n` matrix `b`
+/// into the `m x n` matrix `c`, blocked, vectorized and multi-threaded
+pub fn gemm_into<T: TensorType>(a: &[T], b: &[T], c: &mut [T], m: usize, k: usize, n: usize) {
+    assert!(a.len() >= m * k && b.len() >= k * n && c.len() >= m * n);
+    c[..m * n].fill(T::zero());
+    if m == 0 || n == 0 || k == 0 {
+        return;
+    }
 
-    const BLOCK_SIZE: usize = 32;
-
-    for i_block in (0..m).step_by(BLOCK_SIZE) {
-        let i_end = std::cmp::min(i_block + BLOCK_SIZE, m);
-        for j_block in (0..n).step_by(BLOCK_SIZE) {
-            let j_end = std::cmp::min(j_block + BLOCK_SIZE, n);
-            for p_block in (0..k).step_by(BLOCK_SIZE) {
-                let p_end = std::cmp::min(p_block + BLOCK_SIZE, k);
-
-                for i in i_block..i_end {
-                    for j in j_block..j_end {
-                        let mut sum = T::zero();
-                        for p in p_block..p_end {
-                            sum = sum + a_data[i * k + p] * b_data[p * n + j];
-                        }
-                        c_data[i * n + j] = c_data[i * n + j] + sum;
-                    }
+    let kernel = kernel::select::<T>();
+    let pool = pool::global();
+    let threads = if m * n * k < PARALLEL_THRESHOLD {
+        1
+    } else {
+        pool.threads()
+    };
+    // Smaller blocks when there are too few rows to keep every thread busy
+    let mc = MC.min(m.div_ceil(threads).next_multiple_of(kernel.mr));
+    let blocks = m.div_ceil(mc);
+
+    let c = SendPtr(c.as_mut_ptr());
+    let mut b_packed = Vec::new();
+    for jc in (0..n).step_by(NC) {
+        let nc = NC.min(n - jc);
+        for pc in (0..k).step_by(KC) {
+            let kc = KC.min(k - pc);
+            pack_b(&kernel, b, n, pc, kc, jc, nc, &mut b_packed);
+
+            let block = |block: usize| {
+                let ic = block * mc;
+                let mc = mc.min(m - ic);
+                let mut a_packed = Vec::new();
+                pack_a(&kernel, a, k, ic, mc, pc, kc, &mut a_packed);
+                // SAFETY: blocks write disjoint rows of C
+                unsafe {
+                    macro_kernel(
+                        &kernel,
+                        &a_packed,
+                        &b_packed,
+                        c.get().add(ic * n + jc),
+                        n,
+                        mc,
+                        nc,
+                        kc,
+                    );
                 }
+            };
+            if threads == 1 {
+                (0..blocks).for_each(block);
+            } else {
+                pool.parallel_for(blocks, &block);
             }
         }
     }
+}
 
-    let shape = crate::tensor::Shape::new(vec![m, n]);
-    Ok(Tensor::new(shape, c_data))
+/// Pointer to C shared by the threads computing it
+#[derive(Clone, Copy)]
+struct SendPtr<T>(*mut T);
+
+// SAFETY: every thread writes its own rows
+unsafe impl<T> Send for SendPtr<T> {}
+unsafe impl<T> Sync for SendPtr<T> {}
+
+impl<T> SendPtr<T> {
+    fn get(self) -> *mut T {
+        self.0
+    }
+}
+
+/// Pack rows `i0..i0 + mc`, columns `p0..p0 + kc` of A into strips of
+/// `mr` rows, padded with zeros
+#[allow(clippy::too_many_arguments)]
+fn pack_a<T: TensorType>(
+    kernel: &Kernel<T>,
+    a: &[T],
+    lda: usize,
+    i0: usize,
+    mc: usize,
+    p0: usize,
+    kc: usize,
+    packed: &mut Vec<T>,
+) {
+    packed.clear();
+    for strip in (0..mc).step_by(kernel.mr) {
+        let rows = kernel.mr.min(mc - strip);
+        for p in 0..kc {
+            for i in 0..kernel.mr {
+                packed.push(if i < rows {
+                    a[(i0 + strip + i) * lda + p0 + p]
+                } else {
+                    T::zero()
+                });
+            }
+        }
+    }
+}
+
+/// Pack rows `p0..p0 + kc`, columns `j0..j0 + nc` of B into strips of
+/// `nr` columns, padded with zeros
+#[allow(clippy::too_many_arguments)]
+fn pack_b<T: TensorType>(
+    kernel: &Kernel<T>,
+    b: &[T],
+    ldb: usize,
+    p0: usize,
+    kc: usize,
+    j0: usize,
+    nc: usize,
+    packed: &mut Vec<T>,
+) {
+    packed.clear();
+    for strip in (0..nc).step_by(kernel.nr) {
+        let cols = kernel.nr.min(nc - strip);
+        for p in 0..kc {
+            let row = &b[(p0 + p) * ldb + j0 + strip..][..cols];
+            packed.extend_from_slice(row);
+            packed.extend(std::iter::repeat_n(T::zero(), kernel.nr - cols));
+        }
+    }
+}
+
+/// Add the product of a packed `mc x kc` block of A and a packed `kc x nc`
+/// panel of B to C
+///
+/// # Safety
+///
+/// `c` must be valid for an `mc x nc` matrix with row stride `ldc`.
+#[allow(clippy::too_many_arguments)]
+unsafe fn macro_kernel<T: TensorType>(
+    kernel: &Kernel<T>,
+    a_packed: &[T],
+    b_packed: &[T],
+    c: *mut T,
+    ldc: usize,
+    mc: usize,
+    nc: usize,
+    kc: usize,
+) {
+    let (mr, nr) = (kernel.mr, kernel.nr);
+    // Edge tiles are computed here and the part inside C added
+    let mut edge = vec![T::zero(); mr * nr];
+    for jr in (0..nc).step_by(nr) {
+        let cols = nr.min(nc - jr);
+        let b = b_packed[jr * kc..].as_ptr();
+        for ir in (0..mc).step_by(mr) {
+            let rows = mr.min(mc - ir);
+            let a = a_packed[ir * kc..].as_ptr();
+            let c = c.add(ir * ldc + jr);
+            if rows == mr && cols == nr {
+                (kernel.func)(kc, a, b, c, ldc);
+            } else {
+                edge.fill(T::zero());
+                (kernel.func)(kc, a, b, edge.as_mut_ptr(), nr);
+                for i in 0..rows {
+                    for j in 0..cols {
+                        let c = c.add(i * ldc + j);
+                        *c = *c + edge[i * nr + j];
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,157 @@
+//! Worker Thread Pool
+//!
+//! Parallel loops hand their body to the pool's workers and run it on the
+//! calling thread too, claiming indices from a shared counter until none are
+//! left. The caller returns only once every index finished, which is what
+//! makes lending a borrowed body to the workers sound.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+
+/// Loop body, borrowed from the caller of [`ThreadPool::parallel_for`]
+type Body<'a> = dyn Fn(usize) + Sync + 'a;
+
+struct Job {
+    /// Valid until `finished` reaches `len`
+    body: *const Body<'static>,
+    len: usize,
+    /// Next index to claim
+    next: AtomicUsize,
+    finished: Mutex<usize>,
+    done: Condvar,
+    panicked: AtomicBool,
+}
+
+// SAFETY: the body is `Sync` and outlives every index claimed from the job
+unsafe impl Send for Job {}
+unsafe impl Sync for Job {}
+
+impl Job {
+    /// Run indices until none are left
+    fn run(&self) {
+        loop {
+            let index = self.next.fetch_add(1, Ordering::Relaxed);
+            if index >= self.len {
+                return;
+            }
+            // SAFETY: `index` is claimed and not finished yet, so the caller
+            // still waits and the body is alive
+            let body = unsafe { &*self.body };
+            if panic::catch_unwind(AssertUnwindSafe(|| body(index))).is_err() {
+                self.panicked.store(true, Ordering::Relaxed);
+            }
+            let mut finished = self.finished.lock().unwrap();
+            *finished += 1;
+            if *finished == self.len {
+                self.done.notify_all();
+            }
+        }
+    }
+}
+
+struct Shared {
+    jobs: Mutex<VecDeque<Arc<Job>>>,
+    available: Condvar,
+}
+
+/// Fixed set of worker threads
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    threads: usize,
+}
+
+impl ThreadPool {
+    /// Spawn `threads - 1` workers, the caller of a loop is the last thread
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        let shared = Arc::new(Shared {
+            jobs: Mutex::new(VecDeque::new()),
+            available: Condvar::new(),
+        });
+        for i in 1..threads {
+            let worker_shared = shared.clone();
+            let spawned = std::thread::Builder::new()
+                .name(format!("redoxml-blas-{}", i))
+                .spawn(move || worker(&worker_shared));
+            if let Err(err) = spawned {
+                log::warn!("Failed to spawn BLAS worker: {}", err);
+                return Self { shared, threads: i };
+            }
+        }
+        Self { shared, threads }
+    }
+
+    /// Threads a loop runs on, including the caller
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Run `body` for every index in `0..len`, spread across the pool
+    ///
+    /// Panics of the body are resumed on the caller once all indices ran.
+    pub fn parallel_for(&self, len: usize, body: &Body<'_>) {
+        if len <= 1 || self.threads == 1 {
+            (0..len).for_each(body);
+            return;
+        }
+
+        // SAFETY: the lifetime is erased only for the job, which doesn't run
+        // the body after `finished` reached `len`, and that is waited for
+        // below
+        let body: *const Body<'static> = unsafe { std::mem::transmute(body) };
+        let job = Arc::new(Job {
+            body,
+            len,
+            next: AtomicUsize::new(0),
+            finished: Mutex::new(0),
+            done: Condvar::new(),
+            panicked: AtomicBool::new(false),
+        });
+
+        let helpers = (self.threads - 1).min(len - 1);
+        {
+            let mut jobs = self.shared.jobs.lock().unwrap();
+            for _ in 0..helpers {
+                jobs.push_back(job.clone());
+            }
+        }
+        self.shared.available.notify_all();
+
+        job.run();
+        let finished = job.finished.lock().unwrap();
+        drop(
+            job.done
+                .wait_while(finished, |finished| *finished < len)
+                .unwrap(),
+        );
+
+        if job.panicked.load(Ordering::Relaxed) {
+            panic!("BLAS worker panicked");
+        }
+    }
+}
+
+fn worker(shared: &Shared) {
+    loop {
+        let job = {
+            let jobs = shared.jobs.lock().unwrap();
+            let mut jobs = shared
+                .available
+                .wait_while(jobs, |jobs| jobs.is_empty())
+                .unwrap();
+            jobs.pop_front().unwrap()
+        };
+        job.run();
+    }
+}
+
+/// Pool shared by all BLAS routines, one thread per CPU
+pub fn global() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        ThreadPool::new(threads)
+    })
+}
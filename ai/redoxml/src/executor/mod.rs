@@ -0,0 +1,267 @@
+//! Built-in Executor
+//!
+//! Tensor operations are `async` so that they can overlap with device work,
+//! but daemons on Redox usually have no async runtime. [`block_on`] runs a
+//! single operation to completion on the calling thread. [`Executor`] runs
+//! several operations concurrently and can be driven from a daemon's event
+//! loop:
+//!
+//! - [`Executor::spawn`] queues a task and returns a [`JoinHandle`] for its
+//!   output
+//! - [`Executor::run_until_stalled`] polls every ready task once and returns
+//!   without blocking
+//! - [`Executor::set_notifier`] is called whenever a task becomes ready, for
+//!   example to write to a pipe subscribed to the daemon's `EventQueue`,
+//!   whose event then runs [`Executor::run_until_stalled`]
+//!
+//! Without an event loop, [`Executor::run`] and [`Executor::block_on`] park
+//! the thread until a task is woken.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+type Notifier = Box<dyn Fn() + Send + Sync>;
+
+/// Wakes a thread parked in [`block_on`]
+struct ThreadWaker {
+    thread: Thread,
+    woken: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// Run `future` to completion on the current thread
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        woken: AtomicBool::new(false),
+    });
+    let context_waker = Waker::from(waker.clone());
+    let mut cx = Context::from_waker(&context_waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // Spurious unparks are possible, only a wake counts
+        while !waker.woken.swap(false, Ordering::Acquire) {
+            thread::park();
+        }
+    }
+}
+
+/// Tasks woken since the executor last ran, shared with their wakers
+struct ReadyQueue {
+    tasks: Mutex<VecDeque<usize>>,
+    /// Thread that created the executor, unparked on wake
+    thread: Thread,
+    notifier: Mutex<Option<Notifier>>,
+}
+
+impl ReadyQueue {
+    fn push(&self, id: usize) {
+        self.tasks.lock().unwrap().push_back(id);
+        self.thread.unpark();
+        if let Some(notifier) = &*self.notifier.lock().unwrap() {
+            notifier();
+        }
+    }
+
+    fn pop(&self) -> Option<usize> {
+        self.tasks.lock().unwrap().pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tasks.lock().unwrap().is_empty()
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.push(self.id);
+    }
+}
+
+/// Output of a spawned task, shared with its [`JoinHandle`]
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Future resolving to the output of a task spawned on an [`Executor`]
+///
+/// Dropping the handle detaches the task, which keeps running.
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Whether the task has finished
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().output.is_some()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock().unwrap();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Single-threaded executor for tensor operations
+///
+/// Tasks don't need to be `Send`, so the executor stays on the thread that
+/// created it. Wakers may be used from any thread.
+pub struct Executor {
+    tasks: RefCell<BTreeMap<usize, Task>>,
+    next_id: Cell<usize>,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            tasks: RefCell::new(BTreeMap::new()),
+            next_id: Cell::new(0),
+            ready: Arc::new(ReadyQueue {
+                tasks: Mutex::new(VecDeque::new()),
+                thread: thread::current(),
+                notifier: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Call `notifier` whenever a task becomes ready to run
+    ///
+    /// It may be called from any thread, and from within
+    /// [`Executor::run_until_stalled`].
+    pub fn set_notifier(&self, notifier: impl Fn() + Send + Sync + 'static) {
+        *self.ready.notifier.lock().unwrap() = Some(Box::new(notifier));
+    }
+
+    /// Queue `future` to run on the executor
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let state = Arc::new(Mutex::new(JoinState {
+            output: None,
+            waker: None,
+        }));
+        let task_state = state.clone();
+        let task = async move {
+            let output = future.await;
+            let mut state = task_state.lock().unwrap();
+            state.output = Some(output);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        };
+
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.tasks.borrow_mut().insert(id, Box::pin(task));
+        self.ready.push(id);
+
+        JoinHandle { state }
+    }
+
+    /// Number of tasks that haven't finished
+    pub fn pending(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    /// Poll every ready task, returning whether unfinished tasks remain
+    ///
+    /// Never blocks. Tasks woken while running are polled in the same call.
+    pub fn run_until_stalled(&self) -> bool {
+        while let Some(id) = self.ready.pop() {
+            // Taken out of the map while polled, so that it can spawn tasks;
+            // tasks woken after finishing are gone
+            let Some(mut task) = self.tasks.borrow_mut().remove(&id) else {
+                continue;
+            };
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                queue: self.ready.clone(),
+            }));
+            let mut cx = Context::from_waker(&waker);
+            if task.as_mut().poll(&mut cx).is_pending() {
+                self.tasks.borrow_mut().insert(id, task);
+            }
+        }
+        !self.tasks.borrow().is_empty()
+    }
+
+    /// Run until every task has finished
+    pub fn run(&self) {
+        while self.run_until_stalled() {
+            self.park();
+        }
+    }
+
+    /// Run `future` to completion, along with the spawned tasks
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let handle = self.spawn(future);
+        loop {
+            self.run_until_stalled();
+            if let Some(output) = handle.state.lock().unwrap().output.take() {
+                return output;
+            }
+            self.park();
+        }
+    }
+
+    /// Wait until a task is woken
+    fn park(&self) {
+        while self.ready.is_empty() {
+            thread::park();
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,120 @@
+//! GAL Scheme Client
+//!
+//! Memory shared between the GPU, the NPU and the CPU is allocated from the
+//! graphics driver through the `gal:` scheme:
+//!
+//! - opening `gal:memory/new/<size>` allocates `size` bytes of coherent,
+//!   host-visible memory, freed once the last handle to it is closed
+//! - opening `gal:memory/<name>` opens an allocation by its global name
+//! - reading a handle returns the allocation's name, device address and
+//!   size, a little endian `u64` each
+//! - mapping a handle from offset 0 maps the memory into the caller
+//!
+//! Other drivers import the memory by its name, which is also what the `fd`
+//! of a `gal::ExternalMemory` exported by a GAL device holds.
+//! The NPU driver opens the allocation itself when a client imports it, so
+//! a client can only hand it memory that exists.
+
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::ptr::NonNull;
+
+use syscall::{Map, MapFlags, PAGE_SIZE};
+
+/// Root of the GAL scheme
+pub const GAL_SCHEME: &str = "/scheme/gal";
+
+/// Size of the description read from an allocation handle
+const INFO_SIZE: usize = 24;
+
+/// Coherent memory allocated from the GAL scheme and mapped into this process
+pub struct GalMemory {
+    /// Keeps the allocation alive
+    _file: File,
+    name: u64,
+    device_addr: u64,
+    size: usize,
+    ptr: NonNull<u8>,
+}
+
+// SAFETY: the mapping is owned by the allocation and unmapped only on drop
+unsafe impl Send for GalMemory {}
+unsafe impl Sync for GalMemory {}
+
+impl GalMemory {
+    /// Allocate `size` bytes
+    pub fn alloc(size: usize) -> Result<Self, &'static str> {
+        if size == 0 {
+            return Err("Empty GAL allocation");
+        }
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .open(format!("{}/memory/new/{}", GAL_SCHEME, size))
+            .map_err(|_| "GAL memory allocation failed")?;
+
+        let mut info = [0; INFO_SIZE];
+        file.read_exact(&mut info)
+            .map_err(|_| "Failed to query GAL memory")?;
+        let field = |i: usize| u64::from_le_bytes(info[i * 8..i * 8 + 8].try_into().unwrap());
+        let (name, device_addr) = (field(0), field(1));
+        if field(2) < size as u64 {
+            return Err("GAL allocation smaller than requested");
+        }
+
+        let map = Map {
+            offset: 0,
+            size: size.next_multiple_of(PAGE_SIZE),
+            flags: MapFlags::PROT_READ | MapFlags::PROT_WRITE | MapFlags::MAP_SHARED,
+            address: 0,
+        };
+        // SAFETY: the mapping is unmapped before the handle is closed
+        let address = unsafe { syscall::fmap(file.as_raw_fd() as usize, &map) }
+            .map_err(|_| "Failed to map GAL memory")?;
+        let ptr = NonNull::new(address as *mut u8).ok_or("Failed to map GAL memory")?;
+
+        log::debug!(
+            "Allocated GAL memory {}: {} bytes at 0x{:x}",
+            name,
+            size,
+            device_addr
+        );
+        Ok(Self {
+            _file: file,
+            name,
+            device_addr,
+            size,
+            ptr,
+        })
+    }
+
+    /// Global name other drivers import the memory by
+    pub fn name(&self) -> u64 {
+        self.name
+    }
+
+    /// Address the GPU accesses the memory at
+    pub fn device_addr(&self) -> u64 {
+        self.device_addr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Start of the CPU mapping
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Drop for GalMemory {
+    fn drop(&mut self) {
+        let size = self.size.next_multiple_of(PAGE_SIZE);
+        // SAFETY: nothing borrows the mapping past the allocation
+        if unsafe { syscall::funmap(self.ptr.as_ptr() as usize, size) }.is_err() {
+            log::warn!("Failed to unmap GAL memory {}", self.name);
+        }
+    }
+}
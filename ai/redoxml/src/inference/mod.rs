@@ -1,26 +1,53 @@
 //! AI Inference Pipeline for Gaming
 
+use std::sync::Arc;
+
+use crate::mlcache::CachedWeights;
+use crate::npu::{NpuCommand, NpuDevice};
 use crate::tensor::Tensor;
 
+/// NPU op code of the super resolution network
+const OP_SUPER_RESOLUTION: u32 = 4;
+
 /// AI inference model
 pub struct InferenceModel {
     /// Model weights
     weights: Vec<Tensor<f32>>,
+    /// Contents of the model file, shared with other processes using it
+    file: Option<Arc<CachedWeights>>,
     /// Backend
     backend: crate::Backend,
 }
 
 impl InferenceModel {
     /// Load model from file
+    ///
+    /// The file is mapped through the weight cache, so processes running the
+    /// same model share its pages. A missing file leaves the model without
+    /// weights.
     pub fn load(path: &str) -> Result<Self, &'static str> {
         log::info!("Loading inference model: {}", path);
 
+        let file = match CachedWeights::open(path) {
+            Ok(file) => Some(Arc::new(file)),
+            Err(err) => {
+                log::warn!("Model {} not loaded: {}", path, err);
+                None
+            }
+        };
+
         Ok(Self {
             weights: Vec::new(),
+            file,
             backend: crate::Backend::NPU,
         })
     }
 
+    /// Contents of the model file, if it could be loaded
+    pub fn file(&self) -> Option<&CachedWeights> {
+        self.file.as_deref()
+    }
+
     /// Run inference
     pub async fn infer(&self, input: &Tensor<f32>) -> Result<Tensor<f32>, &'static str> {
         log::debug!("Running inference on {:?}", self.backend);
@@ -33,6 +60,11 @@ impl InferenceModel {
 
         Ok(x)
     }
+
+    /// [`InferenceModel::infer`] for callers without an async runtime
+    pub fn infer_blocking(&self, input: &Tensor<f32>) -> Result<Tensor<f32>, &'static str> {
+        crate::executor::block_on(self.infer(input))
+    }
 }
 
 /// DLSS inference pipeline
@@ -90,6 +122,71 @@ impl FSRFrameGen {
     }
 }
 
+/// Super resolution network upscaling frames on the NPU
+pub struct SuperResolution {
+    model: InferenceModel,
+    npu_device: NpuDevice,
+}
+
+impl SuperResolution {
+    /// Create new super resolution pipeline
+    pub fn new() -> Result<Self, &'static str> {
+        log::info!("Initializing super resolution pipeline");
+
+        let npu_device = NpuDevice::open().map_err(|_| "Failed to open NPU")?;
+        let model = InferenceModel::load("/usr/share/redoxml/superres.model")?;
+
+        Ok(Self { model, npu_device })
+    }
+
+    /// Check if the network runs on an actual NPU
+    pub fn is_accelerated(&self) -> bool {
+        !self.npu_device.is_simulated()
+    }
+
+    /// Upscale `input` into `output`
+    ///
+    /// Both are `[height, width, channels]` frames the NPU can access, such
+    /// as frames shared by the GPU, so no pixels are copied.
+    pub async fn upscale_into(
+        &self,
+        input: &Tensor<f32>,
+        output: &Tensor<f32>,
+    ) -> Result<(), &'static str> {
+        let (input_dims, output_dims) = (input.shape().get_dims(), output.shape().get_dims());
+        if input_dims.len() != 3 || output_dims.len() != 3 || input_dims[2] != output_dims[2] {
+            return Err("Frames must be [height, width, channels] with the same channels");
+        }
+        log::debug!(
+            "Super resolution {}x{} -> {}x{} via NPU",
+            input_dims[1],
+            input_dims[0],
+            output_dims[1],
+            output_dims[0]
+        );
+
+        let input_addr = input
+            .npu_addr()
+            .ok_or("Input frame not accessible by NPU")?;
+        let output_addr = output
+            .npu_addr()
+            .ok_or("Output frame not accessible by NPU")?;
+
+        let mut inputs = vec![input_addr];
+        inputs.extend(self.model.weights.iter().filter_map(Tensor::npu_addr));
+        let cmd = NpuCommand {
+            op_code: OP_SUPER_RESOLUTION,
+            inputs,
+            outputs: vec![output_addr],
+        };
+
+        self.npu_device
+            .submit_command(cmd)
+            .await
+            .map_err(|_| "NPU submission failed")
+    }
+}
+
 /// Zero-copy inference path
 pub struct ZeroCopyInference {
     /// GPU VRAM buffer address
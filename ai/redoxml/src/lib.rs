@@ -2,14 +2,24 @@
 //!
 //! Lightweight tensor library optimized for microkernel architectures
 
+pub mod autograd;
 pub mod blas;
+pub mod executor;
+pub mod gal;
 pub mod inference;
+pub mod mlcache;
 pub mod npu;
+pub mod sparse;
 pub mod tensor;
 
+pub use autograd::*;
 pub use blas::*;
+pub use executor::{block_on, Executor, JoinHandle};
+pub use gal::*;
 pub use inference::*;
+pub use mlcache::*;
 pub use npu::*;
+pub use sparse::*;
 pub use tensor::*;
 
 /// Initialize RedoxML
@@ -45,7 +55,7 @@ fn detect_backends() -> Vec<Backend> {
     let mut backends = vec![Backend::CPU];
 
     // Check for GPU
-    if std::path::Path::new("/scheme/gal").exists() {
+    if std::path::Path::new(gal::GAL_SCHEME).exists() {
         backends.push(Backend::GPU);
     }
 
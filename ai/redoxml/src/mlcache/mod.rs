@@ -0,0 +1,118 @@
+//! Shared Model Weights
+//!
+//! Model files are opened through `mlcached`'s `mlcache:` scheme, which maps
+//! one read-only copy of the weights into every process using the model.
+//! Without the daemon the file is read into private memory instead.
+
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::ptr::NonNull;
+
+use syscall::{Map, MapFlags, PAGE_SIZE};
+
+/// Root of the weight cache scheme
+pub const MLCACHE_SCHEME: &str = "/scheme/mlcache";
+
+enum Storage {
+    /// Pages shared through the cache, unmapped on drop
+    Mapped { ptr: NonNull<u8>, _file: File },
+    /// Private copy read from the file
+    Owned(Vec<u8>),
+}
+
+/// Read-only contents of a model file
+pub struct CachedWeights {
+    storage: Storage,
+    len: usize,
+}
+
+// SAFETY: the mapping is read only and owned by the weights
+unsafe impl Send for CachedWeights {}
+unsafe impl Sync for CachedWeights {}
+
+impl CachedWeights {
+    /// Open the model file at the absolute `path`
+    pub fn open(path: &str) -> Result<Self, &'static str> {
+        if !path.starts_with('/') {
+            return Err("Model path must be absolute");
+        }
+        match Self::map(path) {
+            Ok(weights) => Ok(weights),
+            Err(err) => {
+                log::debug!("Weight cache unavailable for {}: {}", path, err);
+                let data = std::fs::read(path).map_err(|_| "Failed to read model file")?;
+                Ok(Self {
+                    len: data.len(),
+                    storage: Storage::Owned(data),
+                })
+            }
+        }
+    }
+
+    fn map(path: &str) -> Result<Self, &'static str> {
+        let file = File::open(format!("{}{}", MLCACHE_SCHEME, path))
+            .map_err(|_| "weight cache not running")?;
+        let len = file
+            .metadata()
+            .map_err(|_| "failed to query cached weights")?
+            .len() as usize;
+        if len == 0 {
+            return Ok(Self {
+                storage: Storage::Owned(Vec::new()),
+                len,
+            });
+        }
+
+        let map = Map {
+            offset: 0,
+            size: len.next_multiple_of(PAGE_SIZE),
+            flags: MapFlags::PROT_READ | MapFlags::MAP_SHARED,
+            address: 0,
+        };
+        // SAFETY: the mapping is unmapped before the handle is closed
+        let address = unsafe { syscall::fmap(file.as_raw_fd() as usize, &map) }
+            .map_err(|_| "failed to map cached weights")?;
+        let ptr = NonNull::new(address as *mut u8).ok_or("failed to map cached weights")?;
+
+        log::debug!("Mapped {} bytes of shared weights from {}", len, path);
+        Ok(Self {
+            storage: Storage::Mapped { ptr, _file: file },
+            len,
+        })
+    }
+
+    /// Whether the weights are shared with other processes
+    pub fn is_shared(&self) -> bool {
+        matches!(self.storage, Storage::Mapped { .. })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.storage {
+            // SAFETY: the mapping covers `len` bytes and is never written
+            Storage::Mapped { ptr, .. } => unsafe {
+                std::slice::from_raw_parts(ptr.as_ptr(), self.len)
+            },
+            Storage::Owned(data) => data,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for CachedWeights {
+    fn drop(&mut self) {
+        if let Storage::Mapped { ptr, .. } = self.storage {
+            let size = self.len.next_multiple_of(PAGE_SIZE);
+            // SAFETY: nothing borrows the mapping past the weights
+            if unsafe { syscall::funmap(ptr.as_ptr() as usize, size) }.is_err() {
+                log::warn!("Failed to unmap shared weights");
+            }
+        }
+    }
+}
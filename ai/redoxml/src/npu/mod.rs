@@ -1,7 +1,13 @@
 //! NPU/TPU Driver Interface
 use std::fs::File;
+use std::io::{Read, Write};
 use std::sync::Arc;
 
+/// Request importing GAL memory, see the NPU driver's scheme
+const REQUEST_IMPORT: u32 = 9;
+/// Size of a completion read from the device
+const COMPLETION_SIZE: usize = 24;
+
 /// NPU device handle
 pub struct NpuDevice {
     /// File handle to the NPU driver
@@ -22,8 +28,8 @@ pub struct NpuCapabilities {
 impl NpuDevice {
     /// Open NPU device
     pub fn open() -> Result<Self, String> {
-        let path = "/scheme/npu";
-        match File::open(path) {
+        let path = "/scheme/npu/0";
+        match File::options().read(true).write(true).open(path) {
             Ok(file) => {
                 log::info!("Opened NPU device at {}", path);
                 Ok(Self {
@@ -44,6 +50,11 @@ impl NpuDevice {
         }
     }
 
+    /// Check if no NPU is present and commands are only simulated
+    pub fn is_simulated(&self) -> bool {
+        self.simulated
+    }
+
     /// Allocate NPU memory
     pub fn alloc(&self, _size: usize) -> Result<u64, String> {
         if self.simulated {
@@ -54,6 +65,44 @@ impl NpuDevice {
         Err("Real NPU allocation not yet implemented in kernel driver".to_string())
     }
 
+    /// Give the NPU access to GAL memory by its global name, returning the
+    /// NPU buffer handle for it
+    ///
+    /// The handle stays valid as long as this device is open.
+    pub fn import_gal(&self, name: u64, size: usize) -> Result<u64, String> {
+        if self.simulated {
+            // The name stands in for the buffer
+            return Ok(name);
+        }
+
+        let mut file = self.file.as_deref().ok_or("No device file")?;
+        let mut request = Vec::with_capacity(32);
+        request.extend_from_slice(&REQUEST_IMPORT.to_le_bytes());
+        // Priority and tag
+        request.extend_from_slice(&0u32.to_le_bytes());
+        request.extend_from_slice(&name.to_le_bytes());
+        request.extend_from_slice(&name.to_le_bytes());
+        request.extend_from_slice(&(size as u64).to_le_bytes());
+        file.write_all(&request)
+            .map_err(|e| format!("Failed to import GAL memory: {}", e))?;
+
+        // Imports complete synchronously, so the completion is waiting,
+        // possibly behind those of earlier commands
+        let mut completion = [0; COMPLETION_SIZE];
+        loop {
+            file.read_exact(&mut completion)
+                .map_err(|e| format!("Failed to import GAL memory: {}", e))?;
+            if completion[..8] == name.to_le_bytes() {
+                break;
+            }
+        }
+        let status = u32::from_le_bytes(completion[8..12].try_into().unwrap());
+        if status != 0 {
+            return Err(format!("NPU refused GAL memory {}: errno {}", name, status));
+        }
+        Ok(u64::from_le_bytes(completion[16..24].try_into().unwrap()))
+    }
+
     /// Run inference from GPU VRAM address (zero-copy path)
     pub async fn infer_from_address(&self, gpu_addr: u64) -> Result<u64, String> {
         log::debug!("NPU inference from GPU address: 0x{:x}", gpu_addr);
@@ -0,0 +1,450 @@
+//! Sparse Tensors and Embedding Lookup
+//!
+//! [`CooTensor`] keeps (row, column, value) triplets and is the easy format to
+//! build. [`CsrTensor`] compresses the rows and is the format the kernels run
+//! on. [`embedding`] and [`embedding_bag`] gather rows of an embedding table
+//! instead of multiplying it with one-hot matrices.
+//!
+//! Sparse tensors live in CPU memory. Multiplying with a dense tensor on the
+//! GPU or NPU runs on the NPU, which reads GPU tensors zero-copy.
+
+use crate::npu::{NpuCommand, NpuDevice};
+use crate::tensor::{Shape, Tensor, TensorType};
+
+/// NPU op code of the sparse-dense matrix multiply
+const OP_SPMM: u32 = 2;
+/// NPU op code of the embedding gather
+const OP_GATHER: u32 = 3;
+
+fn matrix_dims(shape: &Shape) -> Result<(usize, usize), &'static str> {
+    match shape.get_dims() {
+        &[rows, cols] => Ok((rows, cols)),
+        _ => Err("Sparse tensors must be 2D"),
+    }
+}
+
+/// Sparse matrix in coordinate format
+#[derive(Clone)]
+pub struct CooTensor<T: TensorType> {
+    shape: Shape,
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    values: Vec<T>,
+}
+
+impl<T: TensorType> CooTensor<T> {
+    /// Create from triplets, duplicates are summed on conversion to CSR
+    pub fn new(
+        shape: Shape,
+        rows: Vec<usize>,
+        cols: Vec<usize>,
+        values: Vec<T>,
+    ) -> Result<Self, &'static str> {
+        let (m, n) = matrix_dims(&shape)?;
+        if rows.len() != values.len() || cols.len() != values.len() {
+            return Err("COO index and value counts differ");
+        }
+        if rows.iter().any(|&row| row >= m) || cols.iter().any(|&col| col >= n) {
+            return Err("COO index out of bounds");
+        }
+        Ok(Self {
+            shape,
+            rows,
+            cols,
+            values,
+        })
+    }
+
+    /// Keep the non-zero elements of a 2D CPU tensor
+    pub fn from_dense(tensor: &Tensor<T>) -> Result<Self, &'static str> {
+        let (_, n) = matrix_dims(tensor.shape())?;
+        let data = tensor.data_as_slice().ok_or("Data not on CPU")?;
+
+        let mut coo = Self {
+            shape: tensor.shape().clone(),
+            rows: Vec::new(),
+            cols: Vec::new(),
+            values: Vec::new(),
+        };
+        for (i, &value) in data.iter().enumerate() {
+            if value != T::zero() {
+                coo.rows.push(i / n);
+                coo.cols.push(i % n);
+                coo.values.push(value);
+            }
+        }
+        Ok(coo)
+    }
+
+    /// Get shape
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    /// Number of stored elements
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Row indices
+    pub fn rows(&self) -> &[usize] {
+        &self.rows
+    }
+
+    /// Column indices
+    pub fn cols(&self) -> &[usize] {
+        &self.cols
+    }
+
+    /// Stored values
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// Convert to CSR, with sorted columns and duplicates summed
+    pub fn to_csr(&self) -> CsrTensor<T> {
+        let m = self.shape.get_dims()[0];
+
+        let mut order: Vec<usize> = (0..self.nnz()).collect();
+        order.sort_by_key(|&i| (self.rows[i], self.cols[i]));
+
+        let mut row_ptr = vec![0; m + 1];
+        let mut col_indices: Vec<usize> = Vec::with_capacity(order.len());
+        let mut values: Vec<T> = Vec::with_capacity(order.len());
+        let mut last = None;
+        for i in order {
+            let (row, col) = (self.rows[i], self.cols[i]);
+            if last == Some((row, col)) {
+                let sum = values.last_mut().unwrap();
+                *sum = *sum + self.values[i];
+            } else {
+                row_ptr[row + 1] += 1;
+                col_indices.push(col);
+                values.push(self.values[i]);
+                last = Some((row, col));
+            }
+        }
+        for row in 0..m {
+            row_ptr[row + 1] += row_ptr[row];
+        }
+
+        CsrTensor {
+            shape: self.shape.clone(),
+            row_ptr,
+            col_indices,
+            values,
+        }
+    }
+
+    /// Expand to a dense CPU tensor
+    pub fn to_dense(&self) -> Tensor<T> {
+        self.to_csr().to_dense()
+    }
+}
+
+/// Sparse matrix in compressed sparse row format
+#[derive(Clone)]
+pub struct CsrTensor<T: TensorType> {
+    shape: Shape,
+    row_ptr: Vec<usize>,
+    col_indices: Vec<usize>,
+    values: Vec<T>,
+}
+
+impl<T: TensorType> CsrTensor<T> {
+    /// Create from CSR arrays
+    ///
+    /// Row `i` holds the elements `row_ptr[i]..row_ptr[i + 1]` of
+    /// `col_indices` and `values`.
+    pub fn new(
+        shape: Shape,
+        row_ptr: Vec<usize>,
+        col_indices: Vec<usize>,
+        values: Vec<T>,
+    ) -> Result<Self, &'static str> {
+        let (m, n) = matrix_dims(&shape)?;
+        if row_ptr.len() != m + 1 || row_ptr[0] != 0 {
+            return Err("CSR row pointers don't match the shape");
+        }
+        if row_ptr.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err("CSR row pointers must not decrease");
+        }
+        if col_indices.len() != values.len() || row_ptr[m] != values.len() {
+            return Err("CSR index and value counts differ");
+        }
+        if col_indices.iter().any(|&col| col >= n) {
+            return Err("CSR index out of bounds");
+        }
+        Ok(Self {
+            shape,
+            row_ptr,
+            col_indices,
+            values,
+        })
+    }
+
+    /// Keep the non-zero elements of a 2D CPU tensor
+    pub fn from_dense(tensor: &Tensor<T>) -> Result<Self, &'static str> {
+        Ok(CooTensor::from_dense(tensor)?.to_csr())
+    }
+
+    /// Get shape
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    /// Number of stored elements
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Fraction of the elements that are stored
+    pub fn density(&self) -> f64 {
+        match self.shape.size() {
+            0 => 0.0,
+            size => self.nnz() as f64 / size as f64,
+        }
+    }
+
+    /// Row pointers, one more than there are rows
+    pub fn row_ptr(&self) -> &[usize] {
+        &self.row_ptr
+    }
+
+    /// Column indices
+    pub fn col_indices(&self) -> &[usize] {
+        &self.col_indices
+    }
+
+    /// Stored values
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// Convert to COO
+    pub fn to_coo(&self) -> CooTensor<T> {
+        let rows = self
+            .row_ptr
+            .windows(2)
+            .enumerate()
+            .flat_map(|(row, pair)| std::iter::repeat_n(row, pair[1] - pair[0]))
+            .collect();
+        CooTensor {
+            shape: self.shape.clone(),
+            rows,
+            cols: self.col_indices.clone(),
+            values: self.values.clone(),
+        }
+    }
+
+    /// Expand to a dense CPU tensor
+    pub fn to_dense(&self) -> Tensor<T> {
+        let n = self.shape.get_dims()[1];
+        let mut data = vec![T::zero(); self.shape.size()];
+        for (row, pair) in self.row_ptr.windows(2).enumerate() {
+            for idx in pair[0]..pair[1] {
+                let elem = &mut data[row * n + self.col_indices[idx]];
+                *elem = *elem + self.values[idx];
+            }
+        }
+        Tensor::new(self.shape.clone(), data)
+    }
+
+    /// Sparse-dense matrix multiplication
+    ///
+    /// Runs where `dense` lives, the result is a dense tensor there too.
+    pub async fn matmul(&self, dense: &Tensor<T>) -> Result<Tensor<T>, &'static str> {
+        let (_, k) = matrix_dims(&self.shape)?;
+        let dense_dims = dense.shape().get_dims();
+        if dense_dims.len() != 2 {
+            return Err("SpMM requires a 2D dense tensor");
+        }
+        if dense_dims[0] != k {
+            return Err("Incompatible matrix dimensions");
+        }
+
+        match dense.backend() {
+            crate::Backend::CPU => self.matmul_cpu(dense),
+            crate::Backend::GPU | crate::Backend::NPU => self.matmul_npu(dense).await,
+            crate::Backend::TPU => Err("TPU sparse matmul not implemented"),
+        }
+    }
+
+    fn matmul_cpu(&self, dense: &Tensor<T>) -> Result<Tensor<T>, &'static str> {
+        let b_data = dense.data_as_slice().ok_or("Data not on CPU")?;
+        let m = self.shape.get_dims()[0];
+        let n = dense.shape().get_dims()[1];
+        let mut c_data = vec![T::zero(); m * n];
+
+        for (row, c_row) in c_data.chunks_exact_mut(n.max(1)).take(m).enumerate() {
+            for idx in self.row_ptr[row]..self.row_ptr[row + 1] {
+                let value = self.values[idx];
+                let b_row = &b_data[self.col_indices[idx] * n..][..n];
+                for (c, &b) in c_row.iter_mut().zip(b_row) {
+                    *c = *c + value * b;
+                }
+            }
+        }
+
+        Ok(Tensor::new(Shape::new(vec![m, n]), c_data))
+    }
+
+    async fn matmul_npu(&self, dense: &Tensor<T>) -> Result<Tensor<T>, &'static str> {
+        let device = NpuDevice::open().map_err(|_| "Failed to open NPU device")?;
+        if !device.capabilities().supports_sparse {
+            return Err("NPU has no sparse support");
+        }
+
+        // GPU tensors are shared with the NPU without copying
+        let dense = dense.share_with_npu().await?;
+        let b_addr = dense
+            .npu_addr()
+            .ok_or("Dense tensor not accessible by NPU")?;
+
+        let index_size = std::mem::size_of::<usize>();
+        let row_ptr_addr = device
+            .alloc(self.row_ptr.len() * index_size)
+            .map_err(|_| "NPU alloc failed")?;
+        let col_addr = device
+            .alloc(self.col_indices.len() * index_size)
+            .map_err(|_| "NPU alloc failed")?;
+        let values_addr = device
+            .alloc(self.values.len() * std::mem::size_of::<T>())
+            .map_err(|_| "NPU alloc failed")?;
+        // In real impl: copy the CSR arrays to the NPU buffers
+
+        let shape = Shape::new(vec![self.shape.dims[0], dense.shape().dims[1]]);
+        let c_addr = device
+            .alloc(shape.size() * std::mem::size_of::<T>())
+            .map_err(|_| "NPU alloc failed")?;
+
+        let cmd = NpuCommand {
+            op_code: OP_SPMM,
+            inputs: vec![row_ptr_addr, col_addr, values_addr, b_addr],
+            outputs: vec![c_addr],
+        };
+
+        device
+            .submit_command(cmd)
+            .await
+            .map_err(|_| "NPU submission failed")?;
+
+        Ok(Tensor::from_npu_buffer(shape, c_addr))
+    }
+}
+
+/// Look up rows of an embedding table
+///
+/// `table` is `[vocabulary, dim]`, the result is `[ids.len(), dim]` on the
+/// backend of `table`.
+pub async fn embedding<T: TensorType>(
+    table: &Tensor<T>,
+    ids: &[usize],
+) -> Result<Tensor<T>, &'static str> {
+    let (vocabulary, dim) = matrix_dims(table.shape())?;
+    if ids.iter().any(|&id| id >= vocabulary) {
+        return Err("Embedding id out of range");
+    }
+
+    match table.backend() {
+        crate::Backend::CPU => {
+            let data = table.data_as_slice().ok_or("Data not on CPU")?;
+            let mut out = Vec::with_capacity(ids.len() * dim);
+            for &id in ids {
+                out.extend_from_slice(&data[id * dim..][..dim]);
+            }
+            Ok(Tensor::new(Shape::new(vec![ids.len(), dim]), out))
+        }
+        crate::Backend::GPU | crate::Backend::NPU => embedding_npu(table, ids, dim).await,
+        crate::Backend::TPU => Err("TPU embedding not implemented"),
+    }
+}
+
+async fn embedding_npu<T: TensorType>(
+    table: &Tensor<T>,
+    ids: &[usize],
+    dim: usize,
+) -> Result<Tensor<T>, &'static str> {
+    let device = NpuDevice::open().map_err(|_| "Failed to open NPU device")?;
+
+    let table = table.share_with_npu().await?;
+    let table_addr = table
+        .npu_addr()
+        .ok_or("Embedding table not accessible by NPU")?;
+
+    let ids_addr = device
+        .alloc(std::mem::size_of_val(ids))
+        .map_err(|_| "NPU alloc failed")?;
+    // In real impl: copy the ids to the NPU buffer
+
+    let shape = Shape::new(vec![ids.len(), dim]);
+    let out_addr = device
+        .alloc(shape.size() * std::mem::size_of::<T>())
+        .map_err(|_| "NPU alloc failed")?;
+
+    let cmd = NpuCommand {
+        op_code: OP_GATHER,
+        inputs: vec![table_addr, ids_addr],
+        outputs: vec![out_addr],
+    };
+
+    device
+        .submit_command(cmd)
+        .await
+        .map_err(|_| "NPU submission failed")?;
+
+    Ok(Tensor::from_npu_buffer(shape, out_addr))
+}
+
+/// How [`embedding_bag`] combines the rows of a bag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pooling {
+    Sum,
+    Mean,
+}
+
+/// Look up bags of embedding table rows and pool each bag into one row
+///
+/// Bag `b` holds `ids[offsets[b]..offsets[b + 1]]`, the last bag runs to
+/// the end of `ids`. The result is `[offsets.len(), dim]`, empty bags give
+/// zero rows.
+pub async fn embedding_bag<T: TensorType>(
+    table: &Tensor<T>,
+    ids: &[usize],
+    offsets: &[usize],
+    pooling: Pooling,
+) -> Result<Tensor<T>, &'static str> {
+    let (vocabulary, _) = matrix_dims(table.shape())?;
+    if ids.iter().any(|&id| id >= vocabulary) {
+        return Err("Embedding id out of range");
+    }
+    if offsets.first().map_or(!ids.is_empty(), |&first| first != 0) {
+        return Err("Embedding bag offsets must start at 0");
+    }
+    if offsets.windows(2).any(|pair| pair[0] > pair[1])
+        || offsets.last().is_some_and(|&last| last > ids.len())
+    {
+        return Err("Embedding bag offsets out of order");
+    }
+
+    // Multiplying the table with the bag membership matrix pools the bags
+    let mut row_ptr = offsets.to_vec();
+    row_ptr.push(ids.len());
+    let mut values = Vec::with_capacity(ids.len());
+    for pair in row_ptr.windows(2) {
+        let len = pair[1] - pair[0];
+        let weight = match pooling {
+            Pooling::Sum => T::one(),
+            Pooling::Mean => T::one() / T::from(len).ok_or("Bag too large")?,
+        };
+        values.extend(std::iter::repeat_n(weight, len));
+    }
+
+    let bags = CsrTensor::new(
+        Shape::new(vec![offsets.len(), vocabulary]),
+        row_ptr,
+        ids.to_vec(),
+        values,
+    )?;
+    bags.matmul(table).await
+}
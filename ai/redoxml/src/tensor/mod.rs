@@ -3,6 +3,9 @@
 use num_traits::Float;
 use std::sync::Arc;
 
+use crate::gal::GalMemory;
+use crate::npu::NpuDevice;
+
 /// Tensor data type
 pub trait TensorType: Float + Send + Sync + 'static {}
 impl TensorType for f32 {}
@@ -66,29 +69,82 @@ pub struct SharedBuffer {
     pub gpu_handle: u64,
     /// NPU device handle
     pub npu_handle: u64,
+    /// Memory allocated here and the NPU device it was imported into,
+    /// `None` for imported buffers
+    memory: Option<Arc<(GalMemory, NpuDevice)>>,
 }
 
 impl SharedBuffer {
     /// Create a new shared buffer for zero-copy transfers
+    ///
+    /// The memory is allocated coherent and host-visible from the GAL
+    /// scheme, mapped into this process and imported into the NPU, so the
+    /// CPU, GPU and NPU all access the same pages.
     pub fn new(size: usize) -> Result<Self, &'static str> {
         log::info!("Allocating shared GPU/NPU buffer: {} bytes", size);
 
-        // In a real implementation, this would:
-        // 1. Open /scheme/gal
-        // 2. Request coherent memory allocation
-        // 3. Map the memory for both GPU and NPU access
-
-        // Simulated allocation
-        let phys_addr = 0x1000_0000; // Fake physical address
+        let memory = GalMemory::alloc(size)?;
+        let npu = NpuDevice::open().map_err(|_| "Failed to open NPU device")?;
+        let npu_handle = npu.import_gal(memory.name(), size).map_err(|e| {
+            log::warn!("{}", e);
+            "NPU failed to import GAL memory"
+        })?;
 
         Ok(Self {
-            phys_addr,
+            phys_addr: memory.device_addr(),
             size,
-            gpu_handle: phys_addr,
-            npu_handle: phys_addr,
+            gpu_handle: memory.name(),
+            npu_handle,
+            memory: Some(Arc::new((memory, npu))),
         })
     }
 
+    /// CPU mapping of the buffer, `None` for imported buffers
+    pub fn as_ptr(&self) -> Option<*mut u8> {
+        self.memory.as_ref().map(|memory| memory.0.as_ptr())
+    }
+
+    /// Copy `data` to the start of the buffer
+    pub fn write<T: Copy>(&self, data: &[T]) -> Result<(), &'static str> {
+        let ptr = self.as_ptr().ok_or("Shared buffer not mapped")?;
+        let len = std::mem::size_of_val(data);
+        if len > self.size {
+            return Err("Data larger than shared buffer");
+        }
+        // SAFETY: the mapping is at least `size` bytes
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr().cast::<u8>(), ptr, len) };
+        Ok(())
+    }
+
+    /// Copy the start of the buffer to `data`
+    pub fn read<T: Copy>(&self, data: &mut [T]) -> Result<(), &'static str> {
+        let ptr = self.as_ptr().ok_or("Shared buffer not mapped")?;
+        let len = std::mem::size_of_val(data);
+        if len > self.size {
+            return Err("Data larger than shared buffer");
+        }
+        // SAFETY: the mapping is at least `size` bytes, and `T` is only
+        // instantiated with plain numbers
+        unsafe { std::ptr::copy_nonoverlapping(ptr, data.as_mut_ptr().cast::<u8>(), len) };
+        Ok(())
+    }
+
+    /// Share memory exported by a GAL device (`gal::ExternalMemory`)
+    ///
+    /// The exported handle stands in for the device addresses, the NPU
+    /// driver resolves it to the pages behind it.
+    pub fn import(fd: usize, size: usize) -> Self {
+        log::debug!("Importing GAL memory {}: {} bytes", fd, size);
+
+        Self {
+            phys_addr: 0,
+            size,
+            gpu_handle: fd as u64,
+            npu_handle: fd as u64,
+            memory: None,
+        }
+    }
+
     /// Get GPU-accessible address
     pub fn gpu_addr(&self) -> u64 {
         self.gpu_handle
@@ -220,10 +276,12 @@ impl<T: TensorType> Tensor<T> {
                     backend: crate::Backend::NPU,
                 })
             }
-            TensorData::Cpu(_) => {
+            TensorData::Cpu(data) => {
                 // Need to allocate shared buffer and copy
                 let shared = Self::alloc_shared(self.shape.clone())?;
-                // In real impl: copy CPU data to shared buffer
+                if let TensorData::Shared(buf) = &*shared.data {
+                    buf.write(data)?;
+                }
                 log::debug!("Copied CPU tensor to shared buffer");
                 Ok(shared)
             }
@@ -259,15 +317,14 @@ impl<T: TensorType> Tensor<T> {
                     backend: crate::Backend::GPU,
                 })
             }
-            _ => {
-                // Allocate GPU memory via GAL
-                let gpu_addr = 0; // Would allocate via /scheme/gal
-                Ok(Tensor {
-                    data: Arc::new(TensorData::Gpu(gpu_addr)),
-                    shape: self.shape.clone(),
-                    backend: crate::Backend::GPU,
-                })
+            TensorData::Cpu(data) => {
+                // Copy into GAL memory, which the tensor keeps alive
+                let size = std::mem::size_of_val(data.as_slice());
+                let buffer = SharedBuffer::new(size)?;
+                buffer.write(data)?;
+                Ok(Self::from_shared_buffer(self.shape.clone(), buffer))
             }
+            TensorData::Npu(_) => Err("NPU tensors can't be copied to the GPU"),
         }
     }
 
@@ -276,6 +333,16 @@ impl<T: TensorType> Tensor<T> {
         self.share_with_npu().await
     }
 
+    /// [`Tensor::to_gpu`] for callers without an async runtime
+    pub fn to_gpu_blocking(&self) -> Result<Tensor<T>, &'static str> {
+        crate::executor::block_on(self.to_gpu())
+    }
+
+    /// [`Tensor::to_npu`] for callers without an async runtime
+    pub fn to_npu_blocking(&self) -> Result<Tensor<T>, &'static str> {
+        crate::executor::block_on(self.to_npu())
+    }
+
     /// Get access to CPU data slice
     pub fn data_as_slice(&self) -> Option<&[T]> {
         match &*self.data {
@@ -294,6 +361,11 @@ impl<T: TensorType> Tensor<T> {
         }
     }
 
+    /// [`Tensor::matmul`] for callers without an async runtime
+    pub fn matmul_blocking(&self, other: &Tensor<T>) -> Result<Tensor<T>, &'static str> {
+        crate::executor::block_on(self.matmul(other))
+    }
+
     async fn matmul_cpu(&self, other: &Tensor<T>) -> Result<Tensor<T>, &'static str> {
         crate::blas::gemm::<T>(self, other).await
     }
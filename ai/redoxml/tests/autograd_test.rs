@@ -0,0 +1,91 @@
+use redoxml::autograd::{Adam, Optimizer, Sgd, Tape, Var};
+use redoxml::tensor::{Shape, Tensor};
+
+fn tensor(dims: Vec<usize>, data: Vec<f64>) -> Tensor<f64> {
+    Tensor::new(Shape::new(dims), data)
+}
+
+/// Loss of a small two layer network, recorded on `tape`
+fn mlp_loss(tape: &mut Tape<f64>, w1: &Tensor<f64>, w2: &Tensor<f64>) -> (Var, Var, Var) {
+    let x = tape
+        .var(&tensor(vec![3, 2], vec![0.5, -1.0, 1.5, 2.0, -0.5, 0.25]))
+        .unwrap();
+    let target = tape.var(&tensor(vec![3, 1], vec![1.0, -1.0, 0.5])).unwrap();
+    let w1 = tape.var(w1).unwrap();
+    let w2 = tape.var(w2).unwrap();
+
+    let h = tape.matmul(x, w1).unwrap();
+    let h = tape.tanh(h);
+    let y = tape.matmul(h, w2).unwrap();
+    let loss = tape.mse_loss(y, target).unwrap();
+    (loss, w1, w2)
+}
+
+#[test]
+fn test_gradients_match_finite_differences() {
+    let w1 = tensor(vec![2, 4], vec![0.1, -0.2, 0.3, 0.4, -0.5, 0.6, 0.7, -0.8]);
+    let w2 = tensor(vec![4, 1], vec![0.2, -0.1, 0.4, 0.3]);
+
+    let mut tape = Tape::new();
+    let (loss, w1_var, _) = mlp_loss(&mut tape, &w1, &w2);
+    let grads = tape.backward(loss);
+    let analytic = grads.get(w1_var).unwrap().data_as_slice().unwrap().to_vec();
+
+    let eps = 1e-6;
+    let base = w1.data_as_slice().unwrap().to_vec();
+    for i in 0..base.len() {
+        let eval = |delta: f64| {
+            let mut data = base.clone();
+            data[i] += delta;
+            let mut tape = Tape::new();
+            let (loss, _, _) = mlp_loss(&mut tape, &tensor(vec![2, 4], data), &w2);
+            tape.value(loss).data_as_slice().unwrap()[0]
+        };
+        let numeric = (eval(eps) - eval(-eps)) / (2.0 * eps);
+        assert!(
+            (numeric - analytic[i]).abs() < 1e-6,
+            "Gradient mismatch at {}: {} vs {}",
+            i,
+            numeric,
+            analytic[i]
+        );
+    }
+}
+
+fn fit_line(optimizer: &mut dyn Optimizer<f64>, steps: usize) -> f64 {
+    // y = 2x + 1
+    let x = tensor(vec![4, 1], vec![0.0, 1.0, 2.0, 3.0]);
+    let y = tensor(vec![4, 1], vec![1.0, 3.0, 5.0, 7.0]);
+    let mut params = vec![tensor(vec![1, 1], vec![0.0]), tensor(vec![1], vec![0.0])];
+
+    let mut loss_value = f64::MAX;
+    for _ in 0..steps {
+        let mut tape = Tape::new();
+        let xv = tape.var(&x).unwrap();
+        let yv = tape.var(&y).unwrap();
+        let w = tape.var(&params[0]).unwrap();
+        let b = tape.var(&params[1]).unwrap();
+
+        let pred = tape.matmul(xv, w).unwrap();
+        let pred = tape.add_bias(pred, b).unwrap();
+        let loss = tape.mse_loss(pred, yv).unwrap();
+        loss_value = tape.value(loss).data_as_slice().unwrap()[0];
+
+        let grads = tape.backward(loss);
+        let grads = [grads.get(w).unwrap(), grads.get(b).unwrap()];
+        optimizer.step(&mut params, &grads).unwrap();
+    }
+    loss_value
+}
+
+#[test]
+fn test_sgd_fits_line() {
+    let loss = fit_line(&mut Sgd::new(0.05).with_momentum(0.9), 500);
+    assert!(loss < 1e-6, "SGD did not converge, loss {}", loss);
+}
+
+#[test]
+fn test_adam_fits_line() {
+    let loss = fit_line(&mut Adam::new(0.1), 1000);
+    assert!(loss < 1e-6, "Adam did not converge, loss {}", loss);
+}
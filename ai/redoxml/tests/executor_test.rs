@@ -0,0 +1,121 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use redoxml::executor::{block_on, Executor};
+use redoxml::tensor::{Shape, Tensor};
+
+/// Future that completes once another thread wakes it
+struct Delayed {
+    waker: Option<std::thread::JoinHandle<()>>,
+    done: Arc<AtomicUsize>,
+}
+
+impl Delayed {
+    fn new() -> Self {
+        Self {
+            waker: None,
+            done: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl Future for Delayed {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.done.load(Ordering::Acquire) == 1 {
+            return Poll::Ready(());
+        }
+        if self.waker.is_none() {
+            let done = self.done.clone();
+            let waker: Waker = cx.waker().clone();
+            self.waker = Some(std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(10));
+                done.store(1, Ordering::Release);
+                waker.wake();
+            }));
+        }
+        Poll::Pending
+    }
+}
+
+fn identity(size: usize) -> Tensor<f32> {
+    let mut data = vec![0.0f32; size * size];
+    for i in 0..size {
+        data[i * size + i] = 1.0;
+    }
+    Tensor::new(Shape::new(vec![size, size]), data)
+}
+
+#[test]
+fn test_matmul_blocking() {
+    let a = identity(16);
+    let b = Tensor::new(
+        Shape::new(vec![16, 16]),
+        (0..256).map(|i| i as f32).collect(),
+    );
+
+    let c = a.matmul_blocking(&b).expect("Matmul failed");
+    assert_eq!(c.data_as_slice(), b.data_as_slice());
+}
+
+#[test]
+fn test_block_on_cross_thread_wake() {
+    block_on(Delayed::new());
+    assert_eq!(block_on(async { 42 }), 42);
+}
+
+#[test]
+fn test_executor_runs_tasks_concurrently() {
+    let executor = Executor::new();
+    let a = identity(8);
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let a = a.clone();
+            executor.spawn(async move {
+                Delayed::new().await;
+                let b = Tensor::new(Shape::new(vec![8, 8]), vec![i as f32; 64]);
+                a.matmul(&b).await.unwrap().data_as_slice().unwrap()[0]
+            })
+        })
+        .collect();
+
+    // Every task waits on its own thread, so none is done after one pass
+    assert!(executor.run_until_stalled());
+    assert_eq!(executor.pending(), 4);
+
+    let results = executor.block_on(async move {
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await);
+        }
+        results
+    });
+    assert_eq!(results, vec![0.0, 1.0, 2.0, 3.0]);
+    assert_eq!(executor.pending(), 0);
+}
+
+#[test]
+fn test_executor_notifier() {
+    let executor = Executor::new();
+    let notified = Arc::new(AtomicUsize::new(0));
+    let counter = notified.clone();
+    executor.set_notifier(move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+
+    let handle = executor.spawn(Delayed::new());
+    assert_eq!(notified.load(Ordering::Relaxed), 1);
+    assert!(executor.run_until_stalled());
+
+    // An event loop would wait for the notification, then run the executor
+    while notified.load(Ordering::Relaxed) < 2 {
+        std::thread::yield_now();
+    }
+    assert!(!executor.run_until_stalled());
+    assert!(handle.is_finished());
+}
@@ -60,3 +60,46 @@ async fn test_matmul_tiled_trigger() {
         assert!((c_slice[i] - 2.0).abs() < 1e-5, "Mismatch at index {}", i);
     }
 }
+
+fn reference<T: redoxml::TensorType>(a: &[T], b: &[T], m: usize, k: usize, n: usize) -> Vec<T> {
+    let mut c = vec![T::zero(); m * n];
+    for i in 0..m {
+        for p in 0..k {
+            for j in 0..n {
+                c[i * n + j] = c[i * n + j] + a[i * k + p] * b[p * n + j];
+            }
+        }
+    }
+    c
+}
+
+fn pattern<T: redoxml::TensorType>(len: usize, seed: usize) -> Vec<T> {
+    (0..len)
+        .map(|i| T::from((i * 7 + seed) % 13).unwrap() - T::from(6).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_gemm_blocked_edges() {
+    // Sizes off every tile and block size, small and large enough for threads
+    for &(m, k, n) in &[(1, 1, 1), (7, 3, 5), (67, 131, 45), (130, 300, 97), (257, 513, 33)] {
+        let a = pattern::<f32>(m * k, 1);
+        let b = pattern::<f32>(k * n, 2);
+        let mut c = vec![f32::NAN; m * n];
+        redoxml::gemm_into(&a, &b, &mut c, m, k, n);
+        let expected = reference(&a, &b, m, k, n);
+        for (i, (&got, &want)) in c.iter().zip(&expected).enumerate() {
+            assert!((got - want).abs() <= 1e-3 * want.abs().max(1.0), "{}x{}x{} mismatch at {}", m, k, n, i);
+        }
+    }
+}
+
+#[test]
+fn test_gemm_blocked_f64() {
+    let (m, k, n) = (99, 270, 101);
+    let a = pattern::<f64>(m * k, 3);
+    let b = pattern::<f64>(k * n, 4);
+    let mut c = vec![0.0; m * n];
+    redoxml::gemm_into(&a, &b, &mut c, m, k, n);
+    assert_eq!(c, reference(&a, &b, m, k, n));
+}
@@ -0,0 +1,115 @@
+use redoxml::sparse::{embedding, embedding_bag, CooTensor, CsrTensor, Pooling};
+use redoxml::tensor::{Shape, Tensor};
+
+fn tensor(dims: Vec<usize>, data: Vec<f32>) -> Tensor<f32> {
+    Tensor::new(Shape::new(dims), data)
+}
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!(
+            (a - e).abs() < 1e-5,
+            "Mismatch at index {}: {} != {}",
+            i,
+            a,
+            e
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_spmm_matches_dense_matmul() {
+    let a = tensor(
+        vec![3, 4],
+        vec![1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, -3.0, 4.0, 0.0],
+    );
+    let b = tensor(vec![4, 2], (0..8).map(|i| i as f32).collect());
+
+    let sparse = CsrTensor::from_dense(&a).unwrap();
+    assert_eq!(sparse.nnz(), 4);
+    assert_eq!(sparse.row_ptr(), &[0, 2, 2, 4]);
+
+    let c = sparse.matmul(&b).await.expect("SpMM failed");
+    let expected = a.matmul(&b).await.expect("Matmul failed");
+    assert_eq!(c.shape().get_dims(), &[3, 2]);
+    assert_close(
+        c.data_as_slice().unwrap(),
+        expected.data_as_slice().unwrap(),
+    );
+}
+
+#[test]
+fn test_coo_to_csr_sums_duplicates() {
+    let coo = CooTensor::new(
+        Shape::new(vec![2, 3]),
+        vec![1, 0, 1, 0],
+        vec![2, 1, 2, 0],
+        vec![1.0f32, 2.0, 3.0, 4.0],
+    )
+    .unwrap();
+
+    let csr = coo.to_csr();
+    assert_eq!(csr.row_ptr(), &[0, 2, 3]);
+    assert_eq!(csr.col_indices(), &[0, 1, 2]);
+    assert_eq!(csr.values(), &[4.0, 2.0, 4.0]);
+    assert_close(
+        coo.to_dense().data_as_slice().unwrap(),
+        &[4.0, 2.0, 0.0, 0.0, 0.0, 4.0],
+    );
+    assert_eq!(csr.to_coo().rows(), &[0, 0, 1]);
+}
+
+#[test]
+fn test_invalid_sparse_tensors_rejected() {
+    let shape = Shape::new(vec![2, 2]);
+    assert!(CooTensor::new(shape.clone(), vec![2], vec![0], vec![1.0f32]).is_err());
+    assert!(CsrTensor::new(shape.clone(), vec![0, 1], vec![0], vec![1.0f32]).is_err());
+    assert!(CsrTensor::new(shape.clone(), vec![0, 2, 1], vec![0, 1], vec![1.0f32, 1.0]).is_err());
+    assert!(CsrTensor::new(shape, vec![0, 1, 1], vec![2], vec![1.0f32]).is_err());
+}
+
+#[tokio::test]
+async fn test_embedding_gathers_rows() {
+    let table = tensor(vec![4, 2], vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5]);
+
+    let out = embedding(&table, &[3, 0, 3]).await.unwrap();
+    assert_eq!(out.shape().get_dims(), &[3, 2]);
+    assert_close(
+        out.data_as_slice().unwrap(),
+        &[3.0, 3.5, 0.0, 0.5, 3.0, 3.5],
+    );
+
+    assert!(embedding(&table, &[4]).await.is_err());
+}
+
+#[tokio::test]
+async fn test_embedding_bag_pooling() {
+    let table = tensor(vec![4, 2], vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5]);
+    let ids = [1, 2, 3, 0];
+    let offsets = [0, 3, 3];
+
+    let sum = embedding_bag(&table, &ids, &offsets, Pooling::Sum)
+        .await
+        .unwrap();
+    assert_eq!(sum.shape().get_dims(), &[3, 2]);
+    assert_close(
+        sum.data_as_slice().unwrap(),
+        &[6.0, 7.5, 0.0, 0.0, 0.0, 0.5],
+    );
+
+    let mean = embedding_bag(&table, &ids, &offsets, Pooling::Mean)
+        .await
+        .unwrap();
+    assert_close(
+        mean.data_as_slice().unwrap(),
+        &[2.0, 2.5, 0.0, 0.0, 0.0, 0.5],
+    );
+
+    assert!(embedding_bag(&table, &ids, &[1, 3], Pooling::Sum)
+        .await
+        .is_err());
+    assert!(embedding_bag(&table, &ids, &[0, 5], Pooling::Sum)
+        .await
+        .is_err());
+}
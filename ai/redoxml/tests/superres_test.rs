@@ -0,0 +1,40 @@
+use redoxml::inference::SuperResolution;
+use redoxml::tensor::{Shape, SharedBuffer, Tensor};
+
+fn frame(fd: usize, width: usize, height: usize) -> Tensor<f32> {
+    let shape = Shape::new(vec![height, width, 4]);
+    let buffer = SharedBuffer::import(fd, shape.size() * std::mem::size_of::<f32>());
+    Tensor::from_shared_buffer(shape, buffer)
+}
+
+#[tokio::test]
+async fn test_upscale_imported_frames() {
+    let sr = SuperResolution::new().expect("Failed to create pipeline");
+    let input = frame(3, 960, 540);
+    let output = frame(4, 1920, 1080);
+
+    assert_eq!(input.npu_addr(), Some(3));
+    assert_eq!(output.npu_addr(), Some(4));
+    sr.upscale_into(&input, &output)
+        .await
+        .expect("Upscaling failed");
+}
+
+#[tokio::test]
+async fn test_upscale_rejects_cpu_frames() {
+    let sr = SuperResolution::new().expect("Failed to create pipeline");
+    let input = Tensor::<f32>::zeros(Shape::new(vec![2, 2, 4]));
+    let output = frame(4, 4, 4);
+
+    assert!(sr.upscale_into(&input, &output).await.is_err());
+}
+
+#[tokio::test]
+async fn test_upscale_rejects_mismatched_channels() {
+    let sr = SuperResolution::new().expect("Failed to create pipeline");
+    let input = frame(3, 2, 2);
+    let shape = Shape::new(vec![4, 4, 3]);
+    let output = Tensor::from_shared_buffer(shape, SharedBuffer::import(4, 4 * 4 * 3 * 4));
+
+    assert!(sr.upscale_into(&input, &output).await.is_err());
+}
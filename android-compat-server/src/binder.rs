@@ -129,6 +129,15 @@ impl Parcel {
         }
     }
 
+    pub fn write_byte_array(&mut self, bytes: &[u8]) {
+        self.write_i32(bytes.len() as i32);
+        self.data.extend_from_slice(bytes);
+        // Padding to 4-byte boundary
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+    }
+
     pub fn write_interface_token(&mut self, interface: &str) {
         // Strict mode policy
         self.write_i32(0x010000); // STRICT_MODE_PENALTY_GATHER
@@ -186,6 +195,16 @@ impl<'a> ParcelReader<'a> {
         self.read_i32().map(|v| v as u32)
     }
 
+    pub fn read_byte_array(&mut self) -> Option<Vec<u8>> {
+        let len = usize::try_from(self.read_i32()?).ok()?;
+        if self.pos + len > self.data.len() {
+            return None;
+        }
+        let bytes = self.data[self.pos..self.pos + len].to_vec();
+        self.pos = (self.pos + len + 3) & !3;
+        Some(bytes)
+    }
+
     pub fn read_string(&mut self) -> Option<String> {
         let len = self.read_i32()? as usize;
         if len == 0 {
@@ -27,6 +27,18 @@ pub enum AndroidError {
     FailedTransaction = -129,
     /// Binder: bad type
     BadType = -130,
+    /// Address already in use
+    AddressInUse = -98,
+    /// Network is unreachable
+    NetworkUnreachable = -101,
+    /// Connection reset by peer
+    ConnectionReset = -104,
+    /// Socket is not connected
+    NotConnected = -107,
+    /// Connection timed out
+    TimedOut = -110,
+    /// Connection refused
+    ConnectionRefused = -111,
     /// APK not found
     ApkNotFound = -200,
     /// Invalid APK format
@@ -60,6 +72,12 @@ impl AndroidError {
             AndroidError::DeadObject => "Dead object",
             AndroidError::FailedTransaction => "Transaction failed",
             AndroidError::BadType => "Bad type",
+            AndroidError::AddressInUse => "Address already in use",
+            AndroidError::NetworkUnreachable => "Network is unreachable",
+            AndroidError::ConnectionReset => "Connection reset by peer",
+            AndroidError::NotConnected => "Socket is not connected",
+            AndroidError::TimedOut => "Connection timed out",
+            AndroidError::ConnectionRefused => "Connection refused",
             AndroidError::ApkNotFound => "APK not found",
             AndroidError::InvalidApk => "Invalid APK format",
             AndroidError::PackageNotFound => "Package not found",
@@ -77,3 +95,27 @@ impl std::fmt::Display for AndroidError {
 }
 
 impl std::error::Error for AndroidError {}
+
+impl From<std::io::Error> for AndroidError {
+    fn from(err: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+
+        match err.kind() {
+            ErrorKind::PermissionDenied => AndroidError::PermissionDenied,
+            ErrorKind::NotFound => AndroidError::NameNotFound,
+            ErrorKind::AddrInUse => AndroidError::AddressInUse,
+            ErrorKind::NetworkUnreachable | ErrorKind::HostUnreachable => {
+                AndroidError::NetworkUnreachable
+            }
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => {
+                AndroidError::ConnectionReset
+            }
+            ErrorKind::NotConnected => AndroidError::NotConnected,
+            ErrorKind::TimedOut => AndroidError::TimedOut,
+            ErrorKind::ConnectionRefused => AndroidError::ConnectionRefused,
+            ErrorKind::InvalidInput => AndroidError::BadValue,
+            ErrorKind::OutOfMemory => AndroidError::NoMemory,
+            _ => AndroidError::Unknown,
+        }
+    }
+}
@@ -47,6 +47,7 @@
 //! - SurfaceFlinger bridge (via Redox display scheme)
 //! - AudioFlinger bridge (via Redox audio scheme)
 //! - Input Manager (touch/keyboard events)
+//! - Network bridge (via Redox tcp/udp schemes, with per-UID traffic stats)
 
 use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU32, Ordering};
@@ -57,10 +58,12 @@ mod binder;
 mod dex;
 mod errno;
 mod jni;
+mod net;
 mod syscall_table;
 
 pub use binder::{BinderTransaction, ServiceManager};
 pub use errno::AndroidError;
+pub use net::{NetworkBridge, TrafficStats};
 
 /// AAC server configuration
 #[derive(Debug, Clone)]
@@ -173,6 +176,8 @@ pub struct AacServer {
     pub config: AacConfig,
     /// Service manager for Binder IPC
     pub service_manager: Arc<ServiceManager>,
+    /// Socket bridge to the Redox network schemes
+    pub network: Arc<NetworkBridge>,
     /// Installed applications
     pub apps: RwLock<BTreeMap<String, Arc<AndroidApp>>>,
     /// Running applications by PID
@@ -186,8 +191,12 @@ pub struct AacServer {
 impl AacServer {
     /// Create a new AAC server
     pub fn new(config: AacConfig) -> Self {
+        let service_manager = Arc::new(ServiceManager::new());
+        service_manager.add_service(net::SERVICE_NAME.to_string(), 0);
+
         Self {
-            service_manager: Arc::new(ServiceManager::new()),
+            service_manager,
+            network: Arc::new(NetworkBridge::new()),
             config,
             apps: RwLock::new(BTreeMap::new()),
             running: RwLock::new(BTreeMap::new()),
@@ -212,6 +221,15 @@ impl AacServer {
             apk_path.to_string(),
         ));
 
+        // Grant requested install-time permissions
+        if manifest
+            .uses_permissions
+            .iter()
+            .any(|permission| permission == net::INTERNET_PERMISSION)
+        {
+            self.network.grant_internet(app.ids.uid);
+        }
+
         // Register app
         self.apps.write().unwrap().insert(package_name.clone(), app);
 
@@ -241,6 +259,11 @@ impl AacServer {
     pub fn service_manager(&self) -> &Arc<ServiceManager> {
         &self.service_manager
     }
+
+    /// Get the network bridge
+    pub fn network(&self) -> &Arc<NetworkBridge> {
+        &self.network
+    }
 }
 
 fn main() {
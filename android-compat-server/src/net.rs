@@ -0,0 +1,417 @@
+//! Network Bridge
+//!
+//! Forwards the socket calls of Java (`java.net`) and NDK (BSD socket) code
+//! over Binder to the Redox `tcp:` and `udp:` schemes, and accounts the
+//! traffic of every socket to the UID of the app that opened it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::AndroidId;
+use crate::binder::{BinderReply, BinderTransaction, Parcel, ParcelReader};
+use crate::errno::AndroidError;
+
+/// Name the bridge is registered under in the service manager
+pub const SERVICE_NAME: &str = "network_bridge";
+
+/// Permission an app must request to open sockets
+pub const INTERNET_PERMISSION: &str = "android.permission.INTERNET";
+
+/// Largest read a single `RECV` transaction may request
+const MAX_RECV: usize = 64 * 1024;
+
+/// Network bridge transaction codes
+pub mod transaction {
+    pub const SOCKET: u32 = 1;
+    pub const BIND: u32 = 2;
+    pub const CONNECT: u32 = 3;
+    pub const SEND: u32 = 4;
+    pub const RECV: u32 = 5;
+    pub const CLOSE: u32 = 6;
+    pub const GET_UID_STATS: u32 = 7;
+}
+
+/// Socket address families
+pub mod family {
+    pub const AF_INET: i32 = 2;
+    pub const AF_INET6: i32 = 10;
+}
+
+/// Socket types
+pub mod sock_type {
+    pub const SOCK_STREAM: i32 = 1;
+    pub const SOCK_DGRAM: i32 = 2;
+}
+
+/// Transport protocol of a bridged socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    /// Protocol for a `socket(family, type, 0)` call
+    pub fn from_socket(domain: i32, ty: i32) -> Result<Self, AndroidError> {
+        if domain != family::AF_INET && domain != family::AF_INET6 {
+            return Err(AndroidError::BadValue);
+        }
+        // Strip SOCK_NONBLOCK and SOCK_CLOEXEC
+        match ty & 0xf {
+            sock_type::SOCK_STREAM => Ok(Protocol::Tcp),
+            sock_type::SOCK_DGRAM => Ok(Protocol::Udp),
+            _ => Err(AndroidError::BadValue),
+        }
+    }
+
+    /// Scheme serving this protocol
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// Socket operation submitted to the network policy
+#[derive(Debug, Clone, Copy)]
+pub struct SocketRequest {
+    /// UID the traffic is attributed to
+    pub uid: u32,
+    pub protocol: Protocol,
+    /// Peer address, `None` while the socket is only being created
+    pub remote: Option<SocketAddr>,
+    /// Bound local address
+    pub local: Option<SocketAddr>,
+}
+
+/// Decision of a [`NetworkHook`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Use the regular network schemes
+    Allow,
+    /// Refuse the operation
+    Deny,
+    /// Send the traffic through another scheme, e.g. a VPN tunnel that
+    /// serves the same paths as `tcp:` and `udp:`
+    Route { tcp: String, udp: String },
+}
+
+/// Hook consulted for every socket after the permission check, used by VPN
+/// services and per-app firewalls
+pub trait NetworkHook: Send + Sync {
+    fn check(&self, request: &SocketRequest) -> Verdict;
+}
+
+/// Traffic counters of one UID
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+}
+
+/// Socket opened on behalf of an app
+struct BridgeSocket {
+    uid: u32,
+    pid: u32,
+    protocol: Protocol,
+    local: Option<SocketAddr>,
+    /// Scheme connection, present once connected
+    file: Option<File>,
+}
+
+/// Bridge between app sockets and the Redox network schemes
+pub struct NetworkBridge {
+    sockets: RwLock<BTreeMap<u32, Arc<Mutex<BridgeSocket>>>>,
+    next_socket: AtomicU32,
+    /// UIDs holding [`INTERNET_PERMISSION`]
+    internet: RwLock<BTreeSet<u32>>,
+    hook: RwLock<Option<Arc<dyn NetworkHook>>>,
+    stats: RwLock<BTreeMap<u32, TrafficStats>>,
+}
+
+impl NetworkBridge {
+    pub fn new() -> Self {
+        Self {
+            sockets: RwLock::new(BTreeMap::new()),
+            next_socket: AtomicU32::new(1),
+            internet: RwLock::new(BTreeSet::new()),
+            hook: RwLock::new(None),
+            stats: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Allow `uid` to use the network
+    pub fn grant_internet(&self, uid: u32) {
+        self.internet.write().unwrap().insert(uid);
+    }
+
+    /// Take network access away from `uid`, open sockets stay usable
+    pub fn revoke_internet(&self, uid: u32) {
+        self.internet.write().unwrap().remove(&uid);
+    }
+
+    /// Install or remove the hook consulted for every socket
+    pub fn set_hook(&self, hook: Option<Arc<dyn NetworkHook>>) {
+        *self.hook.write().unwrap() = hook;
+    }
+
+    /// Check `request` and return the scheme its traffic goes to
+    fn check(&self, request: &SocketRequest) -> Result<String, AndroidError> {
+        // System services do not declare permissions
+        if request.uid >= AndroidId::APP_START_UID
+            && !self.internet.read().unwrap().contains(&request.uid)
+        {
+            return Err(AndroidError::PermissionDenied);
+        }
+
+        let hook = self.hook.read().unwrap().clone();
+        match hook.map_or(Verdict::Allow, |hook| hook.check(request)) {
+            Verdict::Allow => Ok(request.protocol.scheme().to_string()),
+            Verdict::Deny => Err(AndroidError::PermissionDenied),
+            Verdict::Route { tcp, udp } => Ok(match request.protocol {
+                Protocol::Tcp => tcp,
+                Protocol::Udp => udp,
+            }),
+        }
+    }
+
+    fn socket(&self, id: u32) -> Result<Arc<Mutex<BridgeSocket>>, AndroidError> {
+        self.sockets
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(AndroidError::BadValue)
+    }
+
+    /// Socket `id` if it belongs to `uid`
+    fn owned_socket(&self, uid: u32, id: u32) -> Result<Arc<Mutex<BridgeSocket>>, AndroidError> {
+        let socket = self.socket(id)?;
+        if socket.lock().unwrap().uid != uid {
+            return Err(AndroidError::PermissionDenied);
+        }
+        Ok(socket)
+    }
+
+    /// Create a socket, returns its id
+    pub fn open_socket(
+        &self,
+        uid: u32,
+        pid: u32,
+        domain: i32,
+        ty: i32,
+    ) -> Result<u32, AndroidError> {
+        let protocol = Protocol::from_socket(domain, ty)?;
+        self.check(&SocketRequest {
+            uid,
+            protocol,
+            remote: None,
+            local: None,
+        })?;
+
+        let id = self.next_socket.fetch_add(1, Ordering::Relaxed);
+        let socket = BridgeSocket {
+            uid,
+            pid,
+            protocol,
+            local: None,
+            file: None,
+        };
+        self.sockets
+            .write()
+            .unwrap()
+            .insert(id, Arc::new(Mutex::new(socket)));
+        Ok(id)
+    }
+
+    /// Set the local address used when the socket connects
+    pub fn bind(&self, uid: u32, id: u32, local: SocketAddr) -> Result<(), AndroidError> {
+        let socket = self.owned_socket(uid, id)?;
+        let mut socket = socket.lock().unwrap();
+        if socket.file.is_some() {
+            return Err(AndroidError::InvalidOperation);
+        }
+        socket.local = Some(local);
+        Ok(())
+    }
+
+    /// Connect the socket to `remote`
+    pub fn connect(&self, uid: u32, id: u32, remote: SocketAddr) -> Result<(), AndroidError> {
+        let socket = self.owned_socket(uid, id)?;
+        let mut socket = socket.lock().unwrap();
+        if socket.file.is_some() {
+            return Err(AndroidError::AlreadyExists);
+        }
+
+        let scheme = self.check(&SocketRequest {
+            uid,
+            protocol: socket.protocol,
+            remote: Some(remote),
+            local: socket.local,
+        })?;
+        let path = match socket.local {
+            Some(local) => format!("/scheme/{}/{}/{}", scheme, remote, local.port()),
+            None => format!("/scheme/{}/{}", scheme, remote),
+        };
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        socket.file = Some(file);
+        Ok(())
+    }
+
+    /// Send `data`, returns the number of bytes written
+    pub fn send(&self, uid: u32, id: u32, data: &[u8]) -> Result<usize, AndroidError> {
+        let socket = self.owned_socket(uid, id)?;
+        let mut socket = socket.lock().unwrap();
+        let file = socket.file.as_mut().ok_or(AndroidError::NotConnected)?;
+        let count = file.write(data)?;
+
+        let mut stats = self.stats.write().unwrap();
+        let stats = stats.entry(uid).or_default();
+        stats.tx_bytes += count as u64;
+        stats.tx_packets += 1;
+        Ok(count)
+    }
+
+    /// Receive up to `len` bytes, an empty result means end of stream
+    pub fn recv(&self, uid: u32, id: u32, len: usize) -> Result<Vec<u8>, AndroidError> {
+        let socket = self.owned_socket(uid, id)?;
+        let mut socket = socket.lock().unwrap();
+        let file = socket.file.as_mut().ok_or(AndroidError::NotConnected)?;
+        let mut data = vec![0; len.min(MAX_RECV)];
+        let count = file.read(&mut data)?;
+        data.truncate(count);
+
+        if count > 0 {
+            let mut stats = self.stats.write().unwrap();
+            let stats = stats.entry(uid).or_default();
+            stats.rx_bytes += count as u64;
+            stats.rx_packets += 1;
+        }
+        Ok(data)
+    }
+
+    /// Close a socket
+    pub fn close(&self, uid: u32, id: u32) -> Result<(), AndroidError> {
+        self.owned_socket(uid, id)?;
+        self.sockets.write().unwrap().remove(&id);
+        Ok(())
+    }
+
+    /// Close all sockets of an exited process
+    pub fn release(&self, pid: u32) {
+        self.sockets
+            .write()
+            .unwrap()
+            .retain(|_, socket| socket.lock().unwrap().pid != pid);
+    }
+
+    /// Traffic counters of `uid`
+    pub fn uid_stats(&self, uid: u32) -> TrafficStats {
+        self.stats
+            .read()
+            .unwrap()
+            .get(&uid)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Traffic counters of every UID that used the network
+    pub fn all_stats(&self) -> BTreeMap<u32, TrafficStats> {
+        self.stats.read().unwrap().clone()
+    }
+
+    /// Handle a transaction to the network bridge
+    ///
+    /// Traffic is attributed to the sender's effective UID.
+    pub fn handle_transaction(&self, tx: &BinderTransaction) -> BinderReply {
+        let mut reader = ParcelReader::new(&tx.data);
+        // Skip interface token
+        let _ = reader.read_i32();
+        let _ = reader.read_string();
+
+        match self.dispatch(tx, &mut reader) {
+            Ok(reply) => {
+                let (data, _) = reply.to_bytes();
+                BinderReply::success(data)
+            }
+            Err(err) => BinderReply::error(err as i32),
+        }
+    }
+
+    fn dispatch(
+        &self,
+        tx: &BinderTransaction,
+        reader: &mut ParcelReader,
+    ) -> Result<Parcel, AndroidError> {
+        let uid = tx.sender_euid;
+        let mut reply = Parcel::new();
+        match tx.code {
+            transaction::SOCKET => {
+                let domain = reader.read_i32().ok_or(AndroidError::BadValue)?;
+                let ty = reader.read_i32().ok_or(AndroidError::BadValue)?;
+                let id = self.open_socket(uid, tx.sender_pid, domain, ty)?;
+                reply.write_u32(id);
+            }
+            transaction::BIND => {
+                let id = reader.read_u32().ok_or(AndroidError::BadValue)?;
+                self.bind(uid, id, read_address(reader)?)?;
+            }
+            transaction::CONNECT => {
+                let id = reader.read_u32().ok_or(AndroidError::BadValue)?;
+                self.connect(uid, id, read_address(reader)?)?;
+            }
+            transaction::SEND => {
+                let id = reader.read_u32().ok_or(AndroidError::BadValue)?;
+                let data = reader.read_byte_array().ok_or(AndroidError::BadValue)?;
+                let count = self.send(uid, id, &data)?;
+                reply.write_i32(count as i32);
+            }
+            transaction::RECV => {
+                let id = reader.read_u32().ok_or(AndroidError::BadValue)?;
+                let len = reader.read_u32().ok_or(AndroidError::BadValue)?;
+                let data = self.recv(uid, id, len as usize)?;
+                reply.write_byte_array(&data);
+            }
+            transaction::CLOSE => {
+                let id = reader.read_u32().ok_or(AndroidError::BadValue)?;
+                self.close(uid, id)?;
+            }
+            transaction::GET_UID_STATS => {
+                let target = reader.read_u32().ok_or(AndroidError::BadValue)?;
+                // Apps may only read their own counters
+                if uid >= AndroidId::APP_START_UID && target != uid {
+                    return Err(AndroidError::PermissionDenied);
+                }
+                let stats = self.uid_stats(target);
+                reply.write_i64(stats.rx_bytes as i64);
+                reply.write_i64(stats.rx_packets as i64);
+                reply.write_i64(stats.tx_bytes as i64);
+                reply.write_i64(stats.tx_packets as i64);
+            }
+            _ => return Err(AndroidError::InvalidOperation),
+        }
+        Ok(reply)
+    }
+}
+
+impl Default for NetworkBridge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read a socket address written as a `"host:port"` string
+fn read_address(reader: &mut ParcelReader) -> Result<SocketAddr, AndroidError> {
+    reader
+        .read_string()
+        .ok_or(AndroidError::BadValue)?
+        .parse()
+        .map_err(|_| AndroidError::BadValue)
+}
@@ -0,0 +1,533 @@
+//! Snapshot Images
+//!
+//! Read-only system trees (a Windows `C:\Windows`, an Android `/system`)
+//! packed into a single image file, mounted as a foreign root.
+//!
+//! # Layout
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! header   magic "FSLNKIMG", version, block size, entry count,
+//!          offsets and lengths of the three regions below
+//! entries  32 bytes each; entry 0 is the root directory and the children
+//!          of every directory are contiguous and sorted by name
+//! names    entry names, not terminated
+//! data     file contents and symlink targets, read in blocks
+//! ```
+//!
+//! The entry table and names are loaded when the image is opened. File data
+//! is read a block at a time through an LRU block cache.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::fs::{FileExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::FsLinkError;
+
+/// Image magic
+pub const IMAGE_MAGIC: [u8; 8] = *b"FSLNKIMG";
+/// Image format version
+pub const IMAGE_VERSION: u32 = 1;
+/// Default data block size
+pub const DEFAULT_BLOCK_SIZE: u32 = 64 * 1024;
+/// Default number of cached blocks (16 MiB with the default block size)
+pub const DEFAULT_CACHE_BLOCKS: usize = 256;
+
+const HEADER_SIZE: usize = 64;
+const ENTRY_SIZE: usize = 32;
+
+/// Type of an image entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+}
+
+impl EntryKind {
+    fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(EntryKind::Directory),
+            1 => Some(EntryKind::File),
+            2 => Some(EntryKind::Symlink),
+            _ => None,
+        }
+    }
+
+    fn to_raw(self) -> u8 {
+        match self {
+            EntryKind::Directory => 0,
+            EntryKind::File => 1,
+            EntryKind::Symlink => 2,
+        }
+    }
+}
+
+/// Entry of the image tree
+#[derive(Debug, Clone, Copy)]
+pub struct ImageEntry {
+    /// Entry type
+    pub kind: EntryKind,
+    /// Unix permission bits
+    pub mode: u32,
+    name_offset: u32,
+    name_len: u32,
+    /// Directories: index of the first child. Files and symlinks: offset of
+    /// the contents in the data region.
+    offset: u64,
+    /// Directories: number of children. Files and symlinks: size in bytes.
+    size: u64,
+}
+
+impl ImageEntry {
+    /// Size in bytes (files and symlinks) or number of children (directories)
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let u32_at = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(raw[at..at + 8].try_into().unwrap());
+        Some(Self {
+            kind: EntryKind::from_raw(raw[0])?,
+            mode: u32_at(4),
+            name_offset: u32_at(8),
+            name_len: u32_at(12),
+            offset: u64_at(16),
+            size: u64_at(24),
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.kind.to_raw(), 0, 0, 0]);
+        out.extend_from_slice(&self.mode.to_le_bytes());
+        out.extend_from_slice(&self.name_offset.to_le_bytes());
+        out.extend_from_slice(&self.name_len.to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&self.size.to_le_bytes());
+    }
+}
+
+/// Cache statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// LRU cache of data blocks
+#[derive(Debug)]
+struct BlockCache {
+    capacity: usize,
+    blocks: BTreeMap<u64, Vec<u8>>,
+    /// Block numbers, least recently used first
+    lru: VecDeque<u64>,
+    stats: CacheStats,
+}
+
+impl BlockCache {
+    fn touch(&mut self, block: u64) {
+        if let Some(pos) = self.lru.iter().position(|&b| b == block) {
+            self.lru.remove(pos);
+        }
+        self.lru.push_back(block);
+    }
+
+    fn insert(&mut self, block: u64, data: Vec<u8>) {
+        while self.blocks.len() >= self.capacity {
+            let Some(evicted) = self.lru.pop_front() else {
+                break;
+            };
+            self.blocks.remove(&evicted);
+        }
+        self.blocks.insert(block, data);
+        self.touch(block);
+    }
+}
+
+/// A mounted snapshot image
+#[derive(Debug)]
+pub struct SnapshotImage {
+    file: File,
+    block_size: u32,
+    entries: Vec<ImageEntry>,
+    names: Vec<u8>,
+    data_offset: u64,
+    data_len: u64,
+    cache: Mutex<BlockCache>,
+}
+
+impl SnapshotImage {
+    /// Open an image with the default cache size
+    pub fn open(path: &Path) -> Result<Self, FsLinkError> {
+        Self::with_cache_blocks(path, DEFAULT_CACHE_BLOCKS)
+    }
+
+    /// Open an image caching up to `cache_blocks` data blocks
+    pub fn with_cache_blocks(path: &Path, cache_blocks: usize) -> Result<Self, FsLinkError> {
+        let file = File::open(path).map_err(|_| FsLinkError::IoError)?;
+        let file_len = file.metadata().map_err(|_| FsLinkError::IoError)?.len();
+
+        let mut header = [0u8; HEADER_SIZE];
+        file.read_exact_at(&mut header, 0)
+            .map_err(|_| FsLinkError::InvalidImage)?;
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        if header[0..8] != IMAGE_MAGIC || u32_at(8) != IMAGE_VERSION {
+            return Err(FsLinkError::InvalidImage);
+        }
+        let block_size = u32_at(12);
+        let entry_count = u32_at(16) as usize;
+        let (entries_offset, names_offset, names_len) = (u64_at(24), u64_at(32), u64_at(40));
+        let (data_offset, data_len) = (u64_at(48), u64_at(56));
+
+        let in_file =
+            |offset: u64, len: u64| offset.checked_add(len).is_some_and(|end| end <= file_len);
+        let entries_len = (entry_count * ENTRY_SIZE) as u64;
+        if block_size == 0
+            || entry_count == 0
+            || !in_file(entries_offset, entries_len)
+            || !in_file(names_offset, names_len)
+            || !in_file(data_offset, data_len)
+        {
+            return Err(FsLinkError::InvalidImage);
+        }
+
+        let mut raw = vec![0u8; entries_len as usize];
+        file.read_exact_at(&mut raw, entries_offset)
+            .map_err(|_| FsLinkError::IoError)?;
+        let entries = raw
+            .chunks_exact(ENTRY_SIZE)
+            .map(ImageEntry::parse)
+            .collect::<Option<Vec<_>>>()
+            .ok_or(FsLinkError::InvalidImage)?;
+
+        let mut names = vec![0u8; names_len as usize];
+        file.read_exact_at(&mut names, names_offset)
+            .map_err(|_| FsLinkError::IoError)?;
+
+        // Validate once so lookups can index without checks.
+        for entry in &entries {
+            let name_end = u64::from(entry.name_offset) + u64::from(entry.name_len);
+            let valid = name_end <= names_len
+                && match entry.kind {
+                    EntryKind::Directory => entry
+                        .offset
+                        .checked_add(entry.size)
+                        .is_some_and(|end| end <= entries.len() as u64),
+                    EntryKind::File | EntryKind::Symlink => entry
+                        .offset
+                        .checked_add(entry.size)
+                        .is_some_and(|end| end <= data_len),
+                };
+            if !valid {
+                return Err(FsLinkError::InvalidImage);
+            }
+        }
+        if entries[0].kind != EntryKind::Directory {
+            return Err(FsLinkError::InvalidImage);
+        }
+
+        Ok(Self {
+            file,
+            block_size,
+            entries,
+            names,
+            data_offset,
+            data_len,
+            cache: Mutex::new(BlockCache {
+                capacity: cache_blocks.max(1),
+                blocks: BTreeMap::new(),
+                lru: VecDeque::new(),
+                stats: CacheStats::default(),
+            }),
+        })
+    }
+
+    /// Get an entry by index
+    pub fn entry(&self, index: u32) -> Option<&ImageEntry> {
+        self.entries.get(index as usize)
+    }
+
+    /// Name of an entry (empty for the root)
+    pub fn name(&self, index: u32) -> &str {
+        let entry = &self.entries[index as usize];
+        let start = entry.name_offset as usize;
+        std::str::from_utf8(&self.names[start..start + entry.name_len as usize]).unwrap_or("")
+    }
+
+    /// Find the entry for a `/` separated path relative to the image root
+    ///
+    /// Symlinks are not followed, the caller resolves them like any other
+    /// foreign symlink.
+    pub fn lookup(&self, path: &str, case_insensitive: bool) -> Option<u32> {
+        let mut index = 0;
+        for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            index = self.child(index, component, case_insensitive)?;
+        }
+        Some(index)
+    }
+
+    fn child(&self, dir: u32, name: &str, case_insensitive: bool) -> Option<u32> {
+        let entry = &self.entries[dir as usize];
+        if entry.kind != EntryKind::Directory {
+            return None;
+        }
+        let first = entry.offset as u32;
+        let end = first + entry.size as u32;
+
+        let (mut low, mut high) = (first, end);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.name(mid).cmp(name) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+
+        if !case_insensitive {
+            return None;
+        }
+        (first..end).find(|&child| self.name(child).eq_ignore_ascii_case(name))
+    }
+
+    /// List the names of a directory's entries
+    pub fn read_dir(&self, index: u32) -> Result<Vec<String>, FsLinkError> {
+        let entry = self.entry(index).ok_or(FsLinkError::InvalidPath)?;
+        if entry.kind != EntryKind::Directory {
+            return Err(FsLinkError::InvalidPath);
+        }
+        let first = entry.offset as u32;
+        Ok((first..first + entry.size as u32)
+            .map(|child| self.name(child).to_string())
+            .collect())
+    }
+
+    /// Read file contents at `offset`, returning the number of bytes read
+    pub fn read(&self, index: u32, offset: u64, buf: &mut [u8]) -> Result<usize, FsLinkError> {
+        let entry = self.entry(index).ok_or(FsLinkError::InvalidPath)?;
+        if entry.kind != EntryKind::File {
+            return Err(FsLinkError::InvalidPath);
+        }
+        if offset >= entry.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((entry.size - offset) as usize);
+        self.read_data(entry.offset + offset, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    /// Read the target of a symlink
+    pub fn read_link(&self, index: u32) -> Result<String, FsLinkError> {
+        let entry = self.entry(index).ok_or(FsLinkError::InvalidPath)?;
+        if entry.kind != EntryKind::Symlink {
+            return Err(FsLinkError::InvalidPath);
+        }
+        let mut target = vec![0u8; entry.size as usize];
+        self.read_data(entry.offset, &mut target)?;
+        String::from_utf8(target).map_err(|_| FsLinkError::InvalidImage)
+    }
+
+    /// Block cache statistics
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
+    }
+
+    /// Copy `buf.len()` bytes at `offset` in the data region through the cache
+    fn read_data(&self, mut offset: u64, mut buf: &mut [u8]) -> Result<(), FsLinkError> {
+        let block_size = u64::from(self.block_size);
+        let mut cache = self.cache.lock().unwrap();
+        while !buf.is_empty() {
+            let block = offset / block_size;
+            let within = (offset % block_size) as usize;
+
+            if cache.blocks.contains_key(&block) {
+                cache.stats.hits += 1;
+                cache.touch(block);
+            } else {
+                cache.stats.misses += 1;
+                let start = block * block_size;
+                let len = block_size.min(self.data_len - start) as usize;
+                let mut data = vec![0u8; len];
+                self.file
+                    .read_exact_at(&mut data, self.data_offset + start)
+                    .map_err(|_| FsLinkError::IoError)?;
+                cache.insert(block, data);
+            }
+
+            let data = &cache.blocks[&block];
+            let n = buf.len().min(data.len() - within);
+            buf[..n].copy_from_slice(&data[within..within + n]);
+            buf = &mut buf[n..];
+            offset += n as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Pack the directory tree at `source` into an image file at `image`
+pub fn pack(source: &Path, image: &Path, block_size: u32) -> io::Result<()> {
+    let mut entries = vec![ImageEntry {
+        kind: EntryKind::Directory,
+        mode: fs::metadata(source)?.permissions().mode() & 0o7777,
+        name_offset: 0,
+        name_len: 0,
+        offset: 0,
+        size: 0,
+    }];
+    let mut names = Vec::new();
+    let mut data = Vec::new();
+
+    // Breadth first, so the children of every directory end up contiguous.
+    let mut queue = VecDeque::from([(0usize, source.to_path_buf())]);
+    while let Some((dir, path)) = queue.pop_front() {
+        let mut children = fs::read_dir(&path)?.collect::<io::Result<Vec<_>>>()?;
+        children.sort_by_key(|child| child.file_name());
+
+        entries[dir].offset = entries.len() as u64;
+        entries[dir].size = children.len() as u64;
+        for child in children {
+            let name = child
+                .file_name()
+                .into_string()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "non UTF-8 file name"))?;
+            let metadata = fs::symlink_metadata(child.path())?;
+            let (kind, contents) = if metadata.file_type().is_symlink() {
+                let target = fs::read_link(child.path())?;
+                (
+                    EntryKind::Symlink,
+                    target.to_string_lossy().into_owned().into_bytes(),
+                )
+            } else if metadata.is_dir() {
+                queue.push_back((entries.len(), child.path()));
+                (EntryKind::Directory, Vec::new())
+            } else {
+                (EntryKind::File, fs::read(child.path())?)
+            };
+
+            entries.push(ImageEntry {
+                kind,
+                mode: metadata.permissions().mode() & 0o7777,
+                name_offset: names.len() as u32,
+                name_len: name.len() as u32,
+                offset: data.len() as u64,
+                size: contents.len() as u64,
+            });
+            names.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&contents);
+        }
+    }
+
+    let entries_offset = HEADER_SIZE as u64;
+    let names_offset = entries_offset + (entries.len() * ENTRY_SIZE) as u64;
+    let data_offset = names_offset + names.len() as u64;
+
+    let mut out = Vec::with_capacity(data_offset as usize);
+    out.extend_from_slice(&IMAGE_MAGIC);
+    out.extend_from_slice(&IMAGE_VERSION.to_le_bytes());
+    out.extend_from_slice(&block_size.to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&entries_offset.to_le_bytes());
+    out.extend_from_slice(&names_offset.to_le_bytes());
+    out.extend_from_slice(&(names.len() as u64).to_le_bytes());
+    out.extend_from_slice(&data_offset.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    for entry in &entries {
+        entry.write(&mut out);
+    }
+    out.extend_from_slice(&names);
+
+    let mut file = File::create(image)?;
+    file.write_all(&out)?;
+    file.write_all(&data)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fs-link-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn build_image(name: &str) -> (PathBuf, Vec<u8>) {
+        let dir = scratch_dir(name);
+        let tree = dir.join("tree");
+        fs::create_dir_all(tree.join("Windows/System32")).unwrap();
+        fs::create_dir_all(tree.join("Users")).unwrap();
+        let big: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        fs::write(tree.join("Windows/System32/kernel32.dll"), &big).unwrap();
+        fs::write(tree.join("Windows/win.ini"), b"[fonts]\n").unwrap();
+        std::os::unix::fs::symlink("Windows/System32", tree.join("sys")).unwrap();
+
+        let image = dir.join("c.img");
+        // Small blocks so reads cross block boundaries.
+        pack(&tree, &image, 4096).unwrap();
+        (image, big)
+    }
+
+    #[test]
+    fn test_lookup_and_read() {
+        let (image, big) = build_image("read");
+        let img = SnapshotImage::with_cache_blocks(&image, 2).unwrap();
+
+        assert_eq!(img.read_dir(0).unwrap(), ["Users", "Windows", "sys"]);
+        let dll = img.lookup("Windows/System32/kernel32.dll", false).unwrap();
+        assert_eq!(img.entry(dll).unwrap().size(), big.len() as u64);
+
+        let mut buf = vec![0u8; 6000];
+        assert_eq!(img.read(dll, 3000, &mut buf).unwrap(), 6000);
+        assert_eq!(buf, big[3000..9000]);
+        assert_eq!(img.read(dll, 9000, &mut buf).unwrap(), 1000);
+        assert_eq!(img.read(dll, 20_000, &mut buf).unwrap(), 0);
+
+        let sys = img.lookup("/sys", false).unwrap();
+        assert_eq!(img.read_link(sys).unwrap(), "Windows/System32");
+        assert!(img.lookup("Windows/missing", false).is_none());
+    }
+
+    #[test]
+    fn test_case_insensitive_lookup() {
+        let (image, _) = build_image("case");
+        let img = SnapshotImage::open(&image).unwrap();
+        assert!(img.lookup("windows/WIN.INI", false).is_none());
+        let ini = img.lookup("windows/WIN.INI", true).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(img.read(ini, 0, &mut buf).unwrap(), 8);
+        assert_eq!(&buf[..8], b"[fonts]\n");
+    }
+
+    #[test]
+    fn test_block_cache() {
+        let (image, _) = build_image("cache");
+        let img = SnapshotImage::with_cache_blocks(&image, 2).unwrap();
+        let ini = img.lookup("Windows/win.ini", false).unwrap();
+        let mut buf = [0u8; 8];
+        img.read(ini, 0, &mut buf).unwrap();
+        img.read(ini, 0, &mut buf).unwrap();
+        assert_eq!(img.cache_stats(), CacheStats { hits: 1, misses: 1 });
+    }
+
+    #[test]
+    fn test_invalid_image() {
+        let dir = scratch_dir("invalid");
+        let path = dir.join("bad.img");
+        fs::write(&path, [0u8; 128]).unwrap();
+        assert_eq!(
+            SnapshotImage::open(&path).unwrap_err(),
+            FsLinkError::InvalidImage
+        );
+    }
+}
@@ -27,10 +27,12 @@ use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
+mod image;
 mod mapper;
 mod path;
 mod symlink;
 
+pub use image::SnapshotImage;
 pub use mapper::{FsMapper, MountPoint};
 pub use path::PathTranslator;
 
@@ -47,6 +49,8 @@ pub struct FsLinkConfig {
     pub enable_symlinks: bool,
     /// Enable case-insensitive matching (for Windows)
     pub case_insensitive: bool,
+    /// Data blocks cached per snapshot image
+    pub image_cache_blocks: usize,
 }
 
 impl Default for FsLinkConfig {
@@ -57,6 +61,7 @@ impl Default for FsLinkConfig {
             android_root: "/android".to_string(),
             enable_symlinks: true,
             case_insensitive: true,
+            image_cache_blocks: image::DEFAULT_CACHE_BLOCKS,
         }
     }
 }
@@ -233,6 +238,7 @@ impl FsLink {
             target: target.to_string(),
             os,
             read_only: false,
+            image: None,
         };
 
         self.mounts
@@ -242,6 +248,61 @@ impl FsLink {
         Ok(())
     }
 
+    /// Mount a snapshot image read-only at `target`
+    pub fn mount_image(&self, image: &str, target: &str, os: ForeignOs) -> Result<(), FsLinkError> {
+        let mut mounts = self.mounts.write().unwrap();
+        if mounts.contains_key(target) {
+            return Err(FsLinkError::AlreadyMounted);
+        }
+
+        let snapshot =
+            SnapshotImage::with_cache_blocks(Path::new(image), self.config.image_cache_blocks)?;
+        mounts.insert(
+            target.to_string(),
+            MountPoint {
+                source: image.to_string(),
+                target: target.to_string(),
+                os,
+                read_only: true,
+                image: Some(Arc::new(snapshot)),
+            },
+        );
+        Ok(())
+    }
+
+    /// Find the snapshot image serving a Redox path
+    ///
+    /// Returns the image and the path relative to its root.
+    pub fn resolve_image(&self, redox_path: &str) -> Option<(Arc<SnapshotImage>, String)> {
+        let mounts = self.mounts.read().unwrap();
+        let (target, mount) = mounts
+            .iter()
+            .filter(|(target, _)| {
+                redox_path
+                    .strip_prefix(target.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(target, _)| target.len())?;
+        let image = mount.image.clone()?;
+        Some((image, redox_path[target.len()..].to_string()))
+    }
+
+    /// Check whether a write to a Redox path is allowed by its mount
+    pub fn check_writable(&self, redox_path: &str) -> Result<(), FsLinkError> {
+        let mounts = self.mounts.read().unwrap();
+        let read_only = mounts.iter().any(|(target, mount)| {
+            mount.read_only
+                && redox_path
+                    .strip_prefix(target.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        });
+        if read_only {
+            Err(FsLinkError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Unmount a filesystem
     pub fn unmount(&self, target: &str) -> Result<(), FsLinkError> {
         self.mounts
@@ -274,9 +335,29 @@ pub enum FsLinkError {
     InvalidPath,
     PermissionDenied,
     IoError,
+    /// Not a snapshot image, or a corrupt one
+    InvalidImage,
+    /// Write to a read-only mount
+    ReadOnly,
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [command, source, image] = args.as_slice() {
+        if command == "pack" {
+            // fs-link pack <directory> <image>: build a snapshot image
+            if let Err(err) = image::pack(
+                Path::new(source),
+                Path::new(image),
+                image::DEFAULT_BLOCK_SIZE,
+            ) {
+                eprintln!("FS-LINK: Failed to pack {} into {}: {}", source, image, err);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
     eprintln!("FS-LINK: Foreign Filesystem Mapper starting...");
 
     let config = FsLinkConfig::default();
@@ -4,8 +4,10 @@
 
 use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::ForeignOs;
+use crate::image::SnapshotImage;
 
 /// Mount point definition
 #[derive(Debug, Clone)]
@@ -18,6 +20,8 @@ pub struct MountPoint {
     pub os: ForeignOs,
     /// Read-only mount
     pub read_only: bool,
+    /// Snapshot image backing the mount, `source` is then the image file
+    pub image: Option<Arc<SnapshotImage>>,
 }
 
 /// Filesystem mapper
@@ -1,13 +1,37 @@
 //! AMD GPU device management
 
+use driver_graphics::irq::Mmio;
 use pcid::PciBar;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::ih::{client, source, InterruptRing};
+
+/// MMIO register access
+pub trait Registers: Send + Sync {
+    fn read(&self, reg: u32) -> u32;
+    fn write(&self, reg: u32, value: u32);
+}
+
+impl Registers for Mmio {
+    fn read(&self, reg: u32) -> u32 {
+        self.read32(reg)
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        self.write32(reg, value)
+    }
+}
 
 pub struct AmdDevice {
     vendor_id: u16,
     device_id: u16,
     bars: Vec<PciBar>,
     gem: Option<Arc<crate::gem::GemManager>>,
+    display: Arc<crate::display::AmdDisplay>,
+    pm: Arc<crate::pm::PowerManager>,
+    vcn: Arc<crate::vcn::Vcn>,
+    ih: InterruptRing,
 }
 
 impl AmdDevice {
@@ -19,6 +43,10 @@ impl AmdDevice {
             device_id: 0x0000,
             bars: Vec::new(),
             gem: None,
+            display: Arc::new(crate::display::AmdDisplay::new()),
+            pm: Arc::new(crate::pm::PowerManager::new()),
+            vcn: Arc::new(crate::vcn::Vcn::new()),
+            ih: InterruptRing::new(),
         })
     }
 
@@ -56,9 +84,50 @@ impl AmdDevice {
         Ok(())
     }
 
+    /// Initialize power management through the SMU at `regs`
+    pub fn init_pm(&self, regs: &dyn Registers) -> Result<(), &'static str> {
+        let gem = self.gem().ok_or("GEM not initialized")?;
+        self.pm.init(regs, gem)
+    }
+
+    /// Initialize the video decode ring through the VCN at `regs`
+    pub fn init_vcn(&self, regs: &dyn Registers) -> Result<(), &'static str> {
+        let gem = self.gem().ok_or("GEM not initialized")?;
+        self.vcn.init(regs, gem)
+    }
+
+    /// Initialize the interrupt handler ring at `regs`
+    pub fn init_interrupts(&self, regs: &dyn Registers) -> Result<(), &'static str> {
+        let gem = self.gem().ok_or("GEM not initialized")?;
+        self.ih.init(regs, gem)
+    }
+
+    /// Handle an interrupt, returns whether the GPU raised it
+    pub fn handle_interrupt(&self, regs: &dyn Registers) -> bool {
+        let entries = self.ih.process(regs);
+        let mut retire = false;
+        for entry in &entries {
+            match (entry.client, entry.source) {
+                (client::VCN, source::VCN_TRAP) => retire = true,
+                (client::DCE, source::DC_D1_VSTARTUP) => self.display.vblank(Instant::now()),
+                // No graphics rings are submitted to yet
+                (client::GRBM_CP, source::CP_EOP) => {}
+                _ => log::trace!("Unhandled interrupt {:?}", entry),
+            }
+        }
+        if retire {
+            self.process_events();
+        }
+        !entries.is_empty()
+    }
+
     /// Process events
+    ///
+    /// Runs on fence interrupts and periodically, in case one was lost.
     pub fn process_events(&self) {
-        // TODO: Process GPU interrupts
+        if let Some(gem) = self.gem() {
+            self.vcn.retire(gem);
+        }
     }
 
     /// Process submissions
@@ -66,6 +135,21 @@ impl AmdDevice {
         // TODO: Process command submissions
     }
 
+    /// Get the display engine, which also provides FreeSync control
+    pub fn display(&self) -> &Arc<crate::display::AmdDisplay> {
+        &self.display
+    }
+
+    /// Get power management, which also provides the GPU's sensor readings
+    pub fn pm(&self) -> &Arc<crate::pm::PowerManager> {
+        &self.pm
+    }
+
+    /// Get the video decode engine
+    pub fn vcn(&self) -> &Arc<crate::vcn::Vcn> {
+        &self.vcn
+    }
+
     /// Get GEM manager
     pub fn gem(&self) -> Option<&Arc<crate::gem::GemManager>> {
         self.gem.as_ref()
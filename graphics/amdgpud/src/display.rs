@@ -0,0 +1,170 @@
+//! Display engine (DCN)
+
+use driver_graphics::scanout::ScanoutLog;
+use graphics_api::{DisplayTiming, VrrDisplay, VrrRange, VrrState, VrrTechnology};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// CEA 1920x1080@60 timing, used until a sink is probed
+const DEFAULT_TIMING: DisplayTiming = DisplayTiming {
+    pixel_clock_khz: 148_500,
+    htotal: 2200,
+    vtotal: 1125,
+};
+
+/// Active area of [`DEFAULT_TIMING`]
+const DEFAULT_RESOLUTION: (u32, u32) = (1920, 1080);
+
+/// Primary surfaces of the HUBP, as GPU addresses
+#[derive(Debug, Default)]
+struct Plane {
+    /// DCSURF_PRIMARY_SURFACE_ADDRESS written since the last vertical blank, latched at the next
+    pending: Option<u64>,
+    /// Vertical blanks so far
+    vblanks: u64,
+    /// Vertical blank that latched the last flip
+    flipped_at: Option<Instant>,
+}
+
+/// OTG_V_TOTAL_MIN / OTG_V_TOTAL_MAX, in lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct OtgVTotal {
+    min: u32,
+    max: u32,
+}
+
+/// Output timing generator driving one display
+///
+/// FreeSync works by letting the OTG extend the vertical front porch
+/// between V_TOTAL_MIN and V_TOTAL_MAX until the next flip arrives; a
+/// present target pins both to the length of that one frame.
+pub struct AmdDisplay {
+    vrr: Mutex<VrrState>,
+    v_total: Mutex<OtgVTotal>,
+    plane: Mutex<Plane>,
+    vblank_event: Condvar,
+    scanout: Arc<ScanoutLog>,
+}
+
+impl AmdDisplay {
+    pub fn new() -> Self {
+        let vrr = VrrState::new(DEFAULT_TIMING, None, None);
+        let display = Self {
+            vrr: Mutex::new(vrr),
+            v_total: Mutex::new(OtgVTotal::default()),
+            plane: Mutex::new(Plane::default()),
+            vblank_event: Condvar::new(),
+            scanout: Arc::new(ScanoutLog::new()),
+        };
+        display.program(&display.vrr.lock().unwrap());
+        display
+    }
+
+    /// Set up the OTG for a new sink, reading its FreeSync range from EDID
+    pub fn probe(&self, timing: DisplayTiming, edid: &[u8]) {
+        let range = VrrRange::from_edid(edid);
+        match range {
+            Some(range) => log::info!("FreeSync range: {}-{} Hz", range.min_hz, range.max_hz),
+            None => log::info!("Sink has no FreeSync range"),
+        }
+
+        let mut vrr = self.vrr.lock().unwrap();
+        *vrr = VrrState::new(timing, range, Some(VrrTechnology::FreeSync));
+        self.program(&vrr);
+    }
+
+    /// Handle the vertical blank interrupt
+    pub fn vblank(&self, now: Instant) {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.vblank(now);
+        self.program(&vrr);
+
+        let mut plane = self.plane.lock().unwrap();
+        plane.vblanks += 1;
+        self.scanout.vblank(0, plane.vblanks);
+        if plane.pending.take().is_some() {
+            plane.flipped_at = Some(now);
+            self.scanout.flip_complete(0, plane.vblanks);
+        }
+        self.vblank_event.notify_all();
+    }
+
+    /// Active resolution
+    pub fn resolution(&self) -> (u32, u32) {
+        DEFAULT_RESOLUTION
+    }
+
+    /// Nominal refresh rate in Hz
+    pub fn refresh_hz(&self) -> u32 {
+        self.vrr.lock().unwrap().timing().refresh_hz().round() as u32
+    }
+
+    /// Scan out the surface at `address` from the next vertical blank on
+    pub fn flip(&self, address: u64) {
+        log::debug!("DCSURF_PRIMARY_SURFACE_ADDRESS={:#x}", address);
+        self.plane.lock().unwrap().pending = Some(address);
+    }
+
+    /// Check if a flip waits for the vertical blank
+    pub fn flip_pending(&self) -> bool {
+        self.plane.lock().unwrap().pending.is_some()
+    }
+
+    /// When the last flip reached the screen
+    pub fn flipped_at(&self) -> Option<Instant> {
+        self.plane.lock().unwrap().flipped_at
+    }
+
+    /// Vertical blank and flip timing, served on `scanout:`
+    pub fn scanout(&self) -> &Arc<ScanoutLog> {
+        &self.scanout
+    }
+
+    /// Wait for the next vertical blank, returns false on timeout
+    pub fn wait_vblank(&self, timeout: Duration) -> bool {
+        let plane = self.plane.lock().unwrap();
+        let vblanks = plane.vblanks;
+        let (_plane, result) = self
+            .vblank_event
+            .wait_timeout_while(plane, timeout, |plane| plane.vblanks == vblanks)
+            .unwrap();
+        !result.timed_out()
+    }
+
+    fn program(&self, vrr: &VrrState) {
+        let (min, max) = vrr.vtotal_limits();
+        let mut v_total = self.v_total.lock().unwrap();
+        if *v_total != (OtgVTotal { min, max }) {
+            log::debug!("OTG_V_TOTAL_MIN={} OTG_V_TOTAL_MAX={}", min, max);
+            *v_total = OtgVTotal { min, max };
+        }
+    }
+}
+
+impl VrrDisplay for AmdDisplay {
+    fn vrr_technology(&self) -> Option<VrrTechnology> {
+        self.vrr.lock().unwrap().technology()
+    }
+
+    fn vrr_range(&self) -> Option<VrrRange> {
+        self.vrr.lock().unwrap().range()
+    }
+
+    fn set_adaptive_sync(&self, enabled: bool) -> Result<(), &'static str> {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.set_enabled(enabled)?;
+        self.program(&vrr);
+        Ok(())
+    }
+
+    fn adaptive_sync_enabled(&self) -> bool {
+        self.vrr.lock().unwrap().enabled()
+    }
+
+    fn set_present_target(&self, target: Option<Instant>) -> Result<(), &'static str> {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.set_target(target)?;
+        self.program(&vrr);
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Interrupt handler (IH) ring
+//!
+//! Engines have no interrupt status registers of their own. The IH block
+//! writes a 32-byte entry for every interrupt to a ring in memory, writes
+//! its write pointer back next to the ring and raises the MSI. Entries name
+//! the client block that raised them and its source id; the driver
+//! consumes them and returns the read pointer.
+
+use std::sync::Mutex;
+
+use crate::device::Registers;
+use crate::gem::{GemFlags, GemManager};
+
+/// IH ring registers
+mod reg {
+    pub const RB_CNTL: u32 = 0x10C00;
+    pub const RB_BASE: u32 = 0x10C04;
+    pub const RB_BASE_HI: u32 = 0x10C08;
+    pub const RB_RPTR: u32 = 0x10C0C;
+    pub const RB_WPTR: u32 = 0x10C10;
+    pub const RB_WPTR_ADDR_HI: u32 = 0x10C14;
+    pub const RB_WPTR_ADDR_LO: u32 = 0x10C18;
+
+    pub const RB_ENABLE: u32 = 1 << 0;
+    pub const RB_SIZE_SHIFT: u32 = 1;
+    pub const WPTR_WRITEBACK_ENABLE: u32 = 1 << 8;
+    pub const WPTR_OVERFLOW_ENABLE: u32 = 1 << 16;
+    pub const WPTR_OVERFLOW_CLEAR: u32 = 1 << 31;
+    /// Set in the write pointer when entries were dropped
+    pub const WPTR_OVERFLOW: u32 = 1 << 0;
+}
+
+/// Client blocks of IH entries
+pub mod client {
+    pub const DCE: u8 = 0x04;
+    pub const VCN: u8 = 0x0C;
+    pub const GRBM_CP: u8 = 0x14;
+}
+
+/// Source ids of IH entries
+pub mod source {
+    /// Display controller 1 vertical startup, the start of vblank
+    pub const DC_D1_VSTARTUP: u8 = 0x17;
+    /// VCN unified ring trap
+    pub const VCN_TRAP: u8 = 0x77;
+    /// Graphics end of pipe
+    pub const CP_EOP: u8 = 0xB5;
+}
+
+/// Size of the ring
+const RING_SIZE: usize = 64 * 1024;
+/// Size of an entry
+const ENTRY_SIZE: usize = 32;
+
+/// Interrupt taken from the ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IhEntry {
+    pub client: u8,
+    pub source: u8,
+    /// Ring of the client the interrupt is for
+    pub ring: u8,
+    pub vmid: u8,
+    /// First dword of source specific data
+    pub data: u32,
+}
+
+impl IhEntry {
+    fn parse(dwords: &[u32; ENTRY_SIZE / 4]) -> Self {
+        Self {
+            client: dwords[0] as u8,
+            source: (dwords[0] >> 8) as u8,
+            ring: (dwords[0] >> 16) as u8,
+            vmid: ((dwords[0] >> 24) & 0xF) as u8,
+            data: dwords[4],
+        }
+    }
+}
+
+struct Ring {
+    cpu_addr: usize,
+    /// Byte offset of the next entry to read
+    rptr: usize,
+}
+
+impl Ring {
+    /// Byte offset of the write pointer the IH writes back, after the ring
+    const WPTR_OFFSET: usize = RING_SIZE;
+}
+
+pub struct InterruptRing {
+    ring: Mutex<Option<Ring>>,
+}
+
+impl InterruptRing {
+    pub fn new() -> Self {
+        Self {
+            ring: Mutex::new(None),
+        }
+    }
+
+    /// Allocate the ring and enable the IH
+    pub fn init(&self, regs: &dyn Registers, gem: &GemManager) -> Result<(), &'static str> {
+        let handle = gem.alloc(
+            RING_SIZE + 4096,
+            GemFlags::GTT | GemFlags::CPU_ACCESS | GemFlags::GPU_ACCESS,
+        )?;
+        let cpu_addr = gem.map(handle)?;
+        let gpu_addr = gem.get(handle).ok_or("Invalid handle")?.gpu_addr;
+        // SAFETY: the buffer was just mapped for its whole size
+        unsafe { std::ptr::write_bytes(cpu_addr as *mut u8, 0, RING_SIZE + 4) };
+
+        let wptr_addr = gpu_addr + Ring::WPTR_OFFSET as u64;
+        // The ring's address is in 256 byte units
+        regs.write(reg::RB_BASE, (gpu_addr >> 8) as u32);
+        regs.write(reg::RB_BASE_HI, (gpu_addr >> 40) as u32);
+        regs.write(reg::RB_WPTR_ADDR_LO, wptr_addr as u32);
+        regs.write(reg::RB_WPTR_ADDR_HI, (wptr_addr >> 32) as u32);
+        regs.write(reg::RB_RPTR, 0);
+        regs.write(reg::RB_WPTR, 0);
+        let size = (RING_SIZE / 4).ilog2();
+        regs.write(
+            reg::RB_CNTL,
+            size << reg::RB_SIZE_SHIFT
+                | reg::WPTR_WRITEBACK_ENABLE
+                | reg::WPTR_OVERFLOW_ENABLE
+                | reg::RB_ENABLE,
+        );
+
+        *self.ring.lock().unwrap() = Some(Ring { cpu_addr, rptr: 0 });
+        log::info!("IH ring at {:#x}", gpu_addr);
+        Ok(())
+    }
+
+    /// Take the entries written since the last call
+    pub fn process(&self, regs: &dyn Registers) -> Vec<IhEntry> {
+        let mut ring = self.ring.lock().unwrap();
+        let Some(ring) = ring.as_mut() else {
+            return Vec::new();
+        };
+
+        // SAFETY: the write pointer lies in the mapped buffer
+        let wptr =
+            unsafe { std::ptr::read_volatile((ring.cpu_addr + Ring::WPTR_OFFSET) as *const u32) };
+        if wptr & reg::WPTR_OVERFLOW != 0 {
+            log::warn!("IH ring overflow, interrupts were lost");
+            // Continue after the oldest entry not yet overwritten
+            ring.rptr = (wptr as usize + ENTRY_SIZE) % RING_SIZE & !(ENTRY_SIZE - 1);
+            let cntl = regs.read(reg::RB_CNTL);
+            regs.write(reg::RB_CNTL, cntl | reg::WPTR_OVERFLOW_CLEAR);
+            regs.write(reg::RB_CNTL, cntl & !reg::WPTR_OVERFLOW_CLEAR);
+        }
+        let wptr = wptr as usize % RING_SIZE & !(ENTRY_SIZE - 1);
+
+        let mut entries = Vec::new();
+        while ring.rptr != wptr {
+            let mut dwords = [0u32; ENTRY_SIZE / 4];
+            for (index, dword) in dwords.iter_mut().enumerate() {
+                // SAFETY: entries lie in the mapped ring
+                *dword = unsafe {
+                    std::ptr::read_volatile((ring.cpu_addr + ring.rptr + index * 4) as *const u32)
+                };
+            }
+            entries.push(IhEntry::parse(&dwords));
+            ring.rptr = (ring.rptr + ENTRY_SIZE) % RING_SIZE;
+        }
+        regs.write(reg::RB_RPTR, ring.rptr as u32);
+        entries
+    }
+}
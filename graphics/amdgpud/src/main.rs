@@ -2,8 +2,11 @@
 //!
 //! Native AMD GPU driver with GEM memory manager and kernel GAL integration.
 
+use driver_graphics::irq::{GpuEventLoop, InterruptHandler, Mmio};
+use pcid_interface::PciFunctionHandle;
 use redox_daemon::Daemon;
 use std::sync::Arc;
+use std::time::Duration;
 
 mod device;
 mod display;
@@ -11,12 +14,36 @@ mod fence;
 mod firmware;
 mod gal_backend;
 mod gem;
+mod ih;
+mod pm;
 mod ring;
 mod scheduler;
+mod vcn;
 
 use device::AmdDevice;
 use gal_backend::AmdGalBackend;
 
+/// Period of retiring completions whose interrupt was lost
+const WATCHDOG_PERIOD: Duration = Duration::from_millis(100);
+
+/// Routes interrupts and the watchdog timer to the device
+struct Interrupts {
+    device: Arc<AmdDevice>,
+    mmio: Mmio,
+}
+
+impl InterruptHandler for Interrupts {
+    fn interrupt(&self, _vector: usize) -> bool {
+        let raised = self.device.handle_interrupt(&self.mmio);
+        self.device.process_submissions();
+        raised
+    }
+
+    fn timer(&self) {
+        self.device.process_events();
+    }
+}
+
 fn daemon(daemon: Daemon) -> ! {
     common::setup_logging(
         "gpu",
@@ -28,6 +55,13 @@ fn daemon(daemon: Daemon) -> ! {
 
     log::info!("AMD GPU Driver starting...");
 
+    // Registers are in BAR 5
+    let mut pcid_handle = PciFunctionHandle::connect_default();
+    let mmio = {
+        let bar = unsafe { pcid_handle.map_bar(5) };
+        unsafe { Mmio::new(bar.ptr.as_ptr(), bar.bar_size) }
+    };
+
     // Initialize PCI device
     let device = match AmdDevice::new() {
         Ok(dev) => {
@@ -50,6 +84,12 @@ fn daemon(daemon: Daemon) -> ! {
         std::process::exit(1);
     }
 
+    // Initialize the interrupt handler ring
+    if let Err(e) = device.init_interrupts(&mmio) {
+        log::error!("Failed to initialize interrupts: {}", e);
+        std::process::exit(1);
+    }
+
     // Initialize command rings
     if let Err(e) = device.init_rings() {
         log::error!("Failed to initialize rings: {}", e);
@@ -71,21 +111,36 @@ fn daemon(daemon: Daemon) -> ! {
         std::process::exit(1);
     }
 
-    log::info!("AMD GPU driver ready");
+    // Report load, clocks, temperature and power on gfxstats:
+    let telemetry = Arc::new(graphics_api::Telemetry::new());
+    telemetry.set_gpu_load(Some(device.pm().clone()));
+    telemetry.set_gpu_sensors(Some(device.pm().clone()));
+    std::thread::spawn(move || {
+        if let Err(e) = graphics_api::serve_gfxstats(telemetry) {
+            log::error!("Failed to serve gfxstats: {}", e);
+        }
+    });
 
-    daemon.ready().expect("Failed to mark daemon as ready");
+    let scanout = device.display().scanout().clone();
+    std::thread::spawn(move || {
+        if let Err(e) = driver_graphics::scanout::serve_scanout(scanout) {
+            log::error!("Failed to serve scanout: {}", e);
+        }
+    });
+
+    let event_loop = match GpuEventLoop::new(&mut pcid_handle, "amdgpud", 1, WATCHDOG_PERIOD) {
+        Ok(event_loop) => event_loop,
+        Err(e) => {
+            log::error!("Failed to set up interrupts: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // Main event loop
-    loop {
-        // Process GPU events
-        device.process_events();
+    log::info!("AMD GPU driver ready");
 
-        // Process command submissions
-        device.process_submissions();
+    daemon.ready().expect("Failed to mark daemon as ready");
 
-        // Sleep briefly
-        std::thread::sleep(std::time::Duration::from_millis(1));
-    }
+    event_loop.run(&Interrupts { device, mmio })
 }
 
 fn main() {
@@ -0,0 +1,349 @@
+//! Power management (DPM) and thermal reporting
+//!
+//! Clocks, voltages and fans are run by the SMU firmware on MP1; the driver
+//! talks to it through a mailbox of three registers. Power profiles are
+//! applied as a workload hint, limits on the graphics clock DPM levels and
+//! a package power limit. Sensor readings come from the SMU's metrics
+//! table, which the SMU copies into a GTT buffer on request.
+
+use graphics_api::{GpuLoad, GpuSensorSource, GpuSensors, PowerProfile};
+use std::sync::Mutex;
+
+use crate::device::Registers;
+use crate::gem::{GemFlags, GemManager};
+
+/// MP1 mailbox registers
+mod mailbox {
+    /// MP1_SMN_C2PMSG_66, message
+    pub const MESSAGE: u32 = 0x58A08;
+    /// MP1_SMN_C2PMSG_82, argument and reply
+    pub const ARGUMENT: u32 = 0x58A48;
+    /// MP1_SMN_C2PMSG_90, response
+    pub const RESPONSE: u32 = 0x58A68;
+
+    pub const RESPONSE_OK: u32 = 0x01;
+    pub const RESPONSE_FAIL: u32 = 0xFF;
+    pub const RESPONSE_UNKNOWN: u32 = 0xFE;
+    pub const RESPONSE_PREREQ: u32 = 0xFD;
+    pub const RESPONSE_BUSY: u32 = 0xFC;
+}
+
+/// SMU messages
+mod msg {
+    pub const GET_SMU_VERSION: u32 = 0x02;
+    pub const SET_DRIVER_DRAM_ADDR_HIGH: u32 = 0x0E;
+    pub const SET_DRIVER_DRAM_ADDR_LOW: u32 = 0x0F;
+    pub const TRANSFER_TABLE_SMU2DRAM: u32 = 0x12;
+    pub const SET_SOFT_MIN_BY_FREQ: u32 = 0x19;
+    pub const SET_SOFT_MAX_BY_FREQ: u32 = 0x1A;
+    pub const GET_DPM_FREQ_BY_INDEX: u32 = 0x1F;
+    pub const SET_WORKLOAD_MASK: u32 = 0x24;
+    pub const SET_PPT_LIMIT: u32 = 0x32;
+    pub const GET_PPT_LIMIT: u32 = 0x33;
+}
+
+/// Offsets of the fields the driver reads in the SMU metrics table
+mod metrics {
+    /// Average graphics clock, MHz, u16
+    pub const GFXCLK: usize = 0x58;
+    /// Graphics engine busy, percent, u16
+    pub const GFX_ACTIVITY: usize = 0x70;
+    /// Board power, W, u16
+    pub const SOCKET_POWER: usize = 0x78;
+    /// Edge temperature, °C, u16
+    pub const TEMPERATURE_EDGE: usize = 0x84;
+    /// Junction (hotspot) temperature, °C, u16
+    pub const TEMPERATURE_HOTSPOT: usize = 0x86;
+    /// Memory temperature, °C, u16
+    pub const TEMPERATURE_MEM: usize = 0x88;
+    /// Throttlers active, u32
+    pub const THROTTLER_STATUS: usize = 0x98;
+    /// Fan speed, RPM, u16
+    pub const FAN_SPEED: usize = 0xA0;
+}
+
+/// Table id of the metrics table in TRANSFER_TABLE_SMU2DRAM
+const TABLE_SMU_METRICS: u32 = 5;
+/// Size of the metrics table buffer
+const METRICS_TABLE_SIZE: usize = 4096;
+
+/// DPM clock id of the graphics clock
+const PPCLK_GFXCLK: u32 = 0;
+/// DPM level index that asks GET_DPM_FREQ_BY_INDEX for the number of levels
+const DPM_LEVEL_COUNT: u32 = 0xFF;
+
+/// Workload hints of SET_WORKLOAD_MASK, by bit
+mod workload {
+    pub const DEFAULT: u32 = 0;
+    pub const FULL_SCREEN_3D: u32 = 1;
+    pub const POWER_SAVING: u32 = 2;
+}
+
+/// Power limit on battery, in percent of the default
+const BATTERY_POWER_LIMIT_PERCENT: u32 = 70;
+
+/// Junction temperature above which a warning is logged, in °C
+const HOTSPOT_WARNING: u16 = 100;
+
+/// Register polls before a message times out
+const POLL_LIMIT: u32 = 100_000;
+
+/// Readings of one metrics table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SmuMetrics {
+    pub gfxclk_mhz: u16,
+    pub gfx_activity: u16,
+    pub socket_power_w: u16,
+    pub temperature_edge: u16,
+    pub temperature_hotspot: u16,
+    pub temperature_mem: u16,
+    pub throttler_status: u32,
+    pub fan_rpm: u16,
+}
+
+impl SmuMetrics {
+    /// Parse a metrics table
+    pub fn parse(table: &[u8]) -> Option<Self> {
+        if table.len() < metrics::FAN_SPEED + 2 {
+            return None;
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([table[at], table[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(table[at..at + 4].try_into().unwrap());
+        Some(Self {
+            gfxclk_mhz: u16_at(metrics::GFXCLK),
+            gfx_activity: u16_at(metrics::GFX_ACTIVITY),
+            socket_power_w: u16_at(metrics::SOCKET_POWER),
+            temperature_edge: u16_at(metrics::TEMPERATURE_EDGE),
+            temperature_hotspot: u16_at(metrics::TEMPERATURE_HOTSPOT),
+            temperature_mem: u16_at(metrics::TEMPERATURE_MEM),
+            throttler_status: u32_at(metrics::THROTTLER_STATUS),
+            fan_rpm: u16_at(metrics::FAN_SPEED),
+        })
+    }
+}
+
+/// Mailbox to the SMU firmware
+struct Smu<'a> {
+    regs: &'a dyn Registers,
+}
+
+impl Smu<'_> {
+    /// Send `message` with `argument`, returning the SMU's reply
+    fn send(&self, message: u32, argument: u32) -> Result<u32, &'static str> {
+        // A previous message must have been answered
+        self.wait_response()?;
+        self.regs.write(mailbox::RESPONSE, 0);
+        self.regs.write(mailbox::ARGUMENT, argument);
+        self.regs.write(mailbox::MESSAGE, message);
+
+        match self.wait_response()? {
+            mailbox::RESPONSE_OK => Ok(self.regs.read(mailbox::ARGUMENT)),
+            mailbox::RESPONSE_FAIL => Err("SMU: message failed"),
+            mailbox::RESPONSE_UNKNOWN => Err("SMU: unknown message"),
+            mailbox::RESPONSE_PREREQ => Err("SMU: message prerequisites not met"),
+            mailbox::RESPONSE_BUSY => Err("SMU: busy"),
+            _ => Err("SMU: invalid response"),
+        }
+    }
+
+    fn wait_response(&self) -> Result<u32, &'static str> {
+        for _ in 0..POLL_LIMIT {
+            let response = self.regs.read(mailbox::RESPONSE);
+            if response != 0 {
+                return Ok(response);
+            }
+            core::hint::spin_loop();
+        }
+        Err("SMU: timeout")
+    }
+}
+
+/// State set up by [`PowerManager::init`]
+struct Dpm {
+    /// CPU mapping of the metrics table buffer
+    table: usize,
+    /// Graphics clock DPM levels in MHz, lowest first
+    gfxclk_levels: Vec<u32>,
+    /// Default package power limit in W
+    default_power_limit: u32,
+}
+
+/// SMU power management
+pub struct PowerManager {
+    dpm: Mutex<Option<Dpm>>,
+    profile: Mutex<PowerProfile>,
+    metrics: Mutex<Option<SmuMetrics>>,
+}
+
+impl PowerManager {
+    pub fn new() -> Self {
+        Self {
+            dpm: Mutex::new(None),
+            profile: Mutex::new(PowerProfile::default()),
+            metrics: Mutex::new(None),
+        }
+    }
+
+    /// Hand the SMU a buffer for its tables and read the DPM levels, then
+    /// apply the current profile
+    pub fn init(&self, regs: &dyn Registers, gem: &GemManager) -> Result<(), &'static str> {
+        let smu = Smu { regs };
+        let version = smu.send(msg::GET_SMU_VERSION, 0)?;
+        log::info!(
+            "SMU firmware {}.{}.{}",
+            version >> 16,
+            (version >> 8) & 0xFF,
+            version & 0xFF
+        );
+
+        let handle = gem.alloc(
+            METRICS_TABLE_SIZE,
+            GemFlags::GTT | GemFlags::CPU_ACCESS | GemFlags::GPU_ACCESS,
+        )?;
+        let table = gem.map(handle)?;
+        let gpu_addr = gem.get(handle).ok_or("Invalid handle")?.gpu_addr;
+        smu.send(msg::SET_DRIVER_DRAM_ADDR_HIGH, (gpu_addr >> 32) as u32)?;
+        smu.send(msg::SET_DRIVER_DRAM_ADDR_LOW, gpu_addr as u32)?;
+
+        let count = smu.send(
+            msg::GET_DPM_FREQ_BY_INDEX,
+            PPCLK_GFXCLK << 16 | DPM_LEVEL_COUNT,
+        )?;
+        let gfxclk_levels = (0..count)
+            .map(|index| smu.send(msg::GET_DPM_FREQ_BY_INDEX, PPCLK_GFXCLK << 16 | index))
+            .collect::<Result<Vec<_>, _>>()?;
+        if gfxclk_levels.is_empty() {
+            return Err("SMU reports no graphics clock levels");
+        }
+        let default_power_limit = smu.send(msg::GET_PPT_LIMIT, 0)?;
+        log::info!(
+            "DPM: graphics clock {:?} MHz, power limit {} W",
+            gfxclk_levels,
+            default_power_limit
+        );
+
+        *self.dpm.lock().unwrap() = Some(Dpm {
+            table,
+            gfxclk_levels,
+            default_power_limit,
+        });
+        let profile = *self.profile.lock().unwrap();
+        self.set_profile(regs, profile)
+    }
+
+    /// Current power profile
+    pub fn profile(&self) -> PowerProfile {
+        *self.profile.lock().unwrap()
+    }
+
+    /// Run the GPU in `profile`
+    ///
+    /// On battery the graphics clock is capped at the middle DPM level and
+    /// the power limit lowered; in performance mode the clock doesn't drop
+    /// below the middle level.
+    pub fn set_profile(
+        &self,
+        regs: &dyn Registers,
+        profile: PowerProfile,
+    ) -> Result<(), &'static str> {
+        let dpm = self.dpm.lock().unwrap();
+        let dpm = dpm.as_ref().ok_or("Power management not initialized")?;
+        let smu = Smu { regs };
+
+        let lowest = dpm.gfxclk_levels[0];
+        let middle = dpm.gfxclk_levels[dpm.gfxclk_levels.len() / 2];
+        let highest = *dpm.gfxclk_levels.last().unwrap();
+        let (workload, min, max, power_limit) = match profile {
+            PowerProfile::Battery => (
+                workload::POWER_SAVING,
+                lowest,
+                middle,
+                dpm.default_power_limit * BATTERY_POWER_LIMIT_PERCENT / 100,
+            ),
+            PowerProfile::Balanced => (workload::DEFAULT, lowest, highest, dpm.default_power_limit),
+            PowerProfile::Performance => (
+                workload::FULL_SCREEN_3D,
+                middle,
+                highest,
+                dpm.default_power_limit,
+            ),
+        };
+
+        smu.send(msg::SET_WORKLOAD_MASK, 1 << workload)?;
+        // Lower the minimum first, so min never exceeds max in between
+        smu.send(msg::SET_SOFT_MIN_BY_FREQ, PPCLK_GFXCLK << 16 | lowest)?;
+        smu.send(msg::SET_SOFT_MAX_BY_FREQ, PPCLK_GFXCLK << 16 | max)?;
+        smu.send(msg::SET_SOFT_MIN_BY_FREQ, PPCLK_GFXCLK << 16 | min)?;
+        smu.send(msg::SET_PPT_LIMIT, power_limit)?;
+        log::info!(
+            "Power profile {:?}: graphics clock {}-{} MHz, power limit {} W",
+            profile,
+            min,
+            max,
+            power_limit
+        );
+
+        *self.profile.lock().unwrap() = profile;
+        Ok(())
+    }
+
+    /// Have the SMU write its metrics table and read it
+    pub fn update_metrics(&self, regs: &dyn Registers) -> Result<SmuMetrics, &'static str> {
+        let dpm = self.dpm.lock().unwrap();
+        let dpm = dpm.as_ref().ok_or("Power management not initialized")?;
+        Smu { regs }.send(msg::TRANSFER_TABLE_SMU2DRAM, TABLE_SMU_METRICS)?;
+
+        // SAFETY: the table buffer is mapped for METRICS_TABLE_SIZE bytes
+        // for as long as the driver runs, and the SMU is done writing it
+        let table =
+            unsafe { std::slice::from_raw_parts(dpm.table as *const u8, METRICS_TABLE_SIZE) };
+        let metrics = SmuMetrics::parse(table).ok_or("Invalid SMU metrics table")?;
+
+        let mut last = self.metrics.lock().unwrap();
+        let previous = last.replace(metrics);
+        let throttling_before = previous.map_or(0, |previous| previous.throttler_status);
+        if metrics.throttler_status != throttling_before {
+            log::info!("SMU throttlers: {:#x}", metrics.throttler_status);
+        }
+        let hot_before = previous.is_some_and(|p| p.temperature_hotspot >= HOTSPOT_WARNING);
+        if metrics.temperature_hotspot >= HOTSPOT_WARNING && !hot_before {
+            log::warn!(
+                "GPU junction temperature {} °C",
+                metrics.temperature_hotspot
+            );
+        }
+        Ok(metrics)
+    }
+
+    /// Readings of the last metrics table
+    pub fn metrics(&self) -> Option<SmuMetrics> {
+        *self.metrics.lock().unwrap()
+    }
+}
+
+impl GpuLoad for PowerManager {
+    fn busy_percent(&self) -> Option<u8> {
+        self.metrics()
+            .map(|metrics| metrics.gfx_activity.min(100) as u8)
+    }
+}
+
+impl GpuSensorSource for PowerManager {
+    fn gpu_sensors(&self) -> GpuSensors {
+        let power_profile = Some(self.profile());
+        let Some(metrics) = self.metrics() else {
+            return GpuSensors {
+                power_profile,
+                ..GpuSensors::default()
+            };
+        };
+        let temperature = metrics.temperature_hotspot.max(metrics.temperature_edge);
+        GpuSensors {
+            clock_mhz: Some(metrics.gfxclk_mhz),
+            temperature_mc: Some(i32::from(temperature) * 1000),
+            fan_rpm: Some(metrics.fan_rpm),
+            power_mw: Some(u32::from(metrics.socket_power_w) * 1000),
+            power_profile,
+        }
+    }
+}
@@ -12,10 +12,6 @@ pub mod scheduler {
     //! GPU job scheduler
 }
 
-pub mod display {
-    //! Display engine
-}
-
 pub mod firmware {
     //! Firmware loading
 }
@@ -24,7 +20,19 @@ pub mod gal_backend {
     //! GAL interface implementation
 
     use crate::device::AmdDevice;
+    use crate::gem::GemFlags;
+    use crate::vcn::{self, DecodeJob, Reference, Surface, Vcn};
+    use gal::device::DisplayInfo;
+    use gal::{
+        DecodePicture, DisplayTarget, Error, Extent2D, Fence, Image, ImageDescriptor, ImageFormat,
+        PresentMode, Rect2D, ScanoutImage, Semaphore, VideoDecodeCapabilities, VideoDecoder,
+        VideoDecoderDescriptor, VideoProfile,
+    };
     use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Formats the primary plane scans out (XRGB8888, XBGR8888)
+    const SCANOUT_FORMATS: &[ImageFormat] = &[ImageFormat::Bgra8Unorm, ImageFormat::Rgba8Unorm];
 
     pub struct AmdGalBackend {
         device: Arc<AmdDevice>,
@@ -40,4 +48,243 @@ pub mod gal_backend {
             Ok(())
         }
     }
+
+    /// Presentation on the display, with scanout images in GEM objects
+    impl DisplayTarget for AmdGalBackend {
+        fn info(&self) -> DisplayInfo {
+            let display = self.device.display();
+            let (width, height) = display.resolution();
+            DisplayInfo {
+                id: 0,
+                name: String::from("AMD Display 0"),
+                extent: Extent2D::new(width, height),
+                refresh_rate: display.refresh_hz(),
+                is_primary: true,
+                enabled: true,
+            }
+        }
+
+        fn formats(&self) -> &[ImageFormat] {
+            SCANOUT_FORMATS
+        }
+
+        fn supports_present_mode(&self, _mode: PresentMode) -> bool {
+            true
+        }
+
+        fn create_image(
+            &self,
+            extent: Extent2D,
+            format: ImageFormat,
+        ) -> gal::Result<Box<dyn Image>> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let bytes_per_pixel = format.bytes_per_pixel().ok_or(Error::NotSupported)?;
+            let size = extent.width as usize * extent.height as usize * bytes_per_pixel as usize;
+            let handle = gem
+                .alloc(size, GemFlags::VRAM | GemFlags::GPU_ACCESS)
+                .map_err(|_| Error::OutOfDeviceMemory)?;
+
+            let desc = ImageDescriptor::scanout(extent.width, extent.height, format);
+            Ok(Box::new(ScanoutImage::new(handle as usize, &desc)))
+        }
+
+        fn destroy_image(&self, image: Box<dyn Image>) {
+            if let Some(gem) = self.device.gem() {
+                if let Err(e) = gem.free(image.handle() as u32) {
+                    log::warn!("Failed to free scanout image: {}", e);
+                }
+            }
+        }
+
+        /// The plane scans out the whole surface, so damage is of no use
+        fn flip(&self, image: &dyn Image, _damage: &[Rect2D]) -> gal::Result<()> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let object = gem
+                .get(image.handle() as u32)
+                .ok_or(Error::InvalidParameter)?;
+            self.device.display().flip(object.gpu_addr);
+            Ok(())
+        }
+
+        fn flip_pending(&self) -> bool {
+            self.device.display().flip_pending()
+        }
+
+        fn flip_timestamp_ns(&self) -> Option<u64> {
+            let elapsed = self.device.display().flipped_at()?.elapsed();
+            gal::display::monotonic_ns()?.checked_sub(elapsed.as_nanos() as u64)
+        }
+
+        fn wait_vblank(&self, timeout_ns: u64) -> gal::Result<bool> {
+            Ok(self
+                .device
+                .display()
+                .wait_vblank(Duration::from_nanos(timeout_ns)))
+        }
+
+        // The driver has no GAL semaphores or fences of its own yet
+
+        fn wait_semaphores(&self, semaphores: &[&dyn Semaphore]) -> gal::Result<()> {
+            if semaphores.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::NotSupported)
+            }
+        }
+
+        fn signal(
+            &self,
+            semaphore: Option<&dyn Semaphore>,
+            fence: Option<&dyn Fence>,
+        ) -> gal::Result<()> {
+            if semaphore.is_none() && fence.is_none() {
+                Ok(())
+            } else {
+                Err(Error::NotSupported)
+            }
+        }
+    }
+
+    /// Hardware decoding on VCN
+    impl gal::VideoDecode for AmdGalBackend {
+        fn decode_capabilities(&self, profile: VideoProfile) -> Option<VideoDecodeCapabilities> {
+            let (max_extent, max_level) = Vcn::limits(profile.codec());
+            Some(VideoDecodeCapabilities {
+                max_extent,
+                max_level,
+                max_references: vcn::MAX_REFERENCES,
+            })
+        }
+
+        fn create_video_decoder(
+            &self,
+            descriptor: &VideoDecoderDescriptor,
+        ) -> gal::Result<Box<dyn VideoDecoder>> {
+            let capabilities = self
+                .decode_capabilities(descriptor.profile)
+                .ok_or(Error::NotSupported)?;
+            descriptor.check(&capabilities)?;
+
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let session = self
+                .device
+                .vcn()
+                .create_session(
+                    gem,
+                    descriptor.profile.codec(),
+                    descriptor.max_extent,
+                    descriptor.max_references,
+                )
+                .map_err(|e| {
+                    log::warn!("Failed to create decoder session: {}", e);
+                    Error::OperationFailed
+                })?;
+            Ok(Box::new(AmdVideoDecoder {
+                device: self.device.clone(),
+                descriptor: *descriptor,
+                session,
+            }))
+        }
+    }
+
+    /// VCN decoder session, decoding into GEM objects in VRAM
+    struct AmdVideoDecoder {
+        device: Arc<AmdDevice>,
+        descriptor: VideoDecoderDescriptor,
+        session: u32,
+    }
+
+    impl AmdVideoDecoder {
+        fn surface(&self, image: &dyn Image) -> gal::Result<Surface> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let object = gem
+                .get(image.handle() as u32)
+                .ok_or(Error::InvalidParameter)?;
+            let (pitch, aligned_height) = Surface::layout(image.extent_2d(), image.format())
+                .ok_or(Error::InvalidParameter)?;
+            Ok(Surface {
+                addr: object.gpu_addr,
+                pitch,
+                aligned_height,
+            })
+        }
+    }
+
+    impl VideoDecoder for AmdVideoDecoder {
+        fn descriptor(&self) -> &VideoDecoderDescriptor {
+            &self.descriptor
+        }
+
+        fn create_picture(&self) -> gal::Result<Box<dyn Image>> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let extent = self.descriptor.max_extent;
+            let format = self.descriptor.profile.output_format();
+            let (pitch, aligned_height) =
+                Surface::layout(extent, format).ok_or(Error::NotSupported)?;
+            let handle = gem
+                .alloc(
+                    Surface::size(pitch, aligned_height),
+                    GemFlags::VRAM | GemFlags::GPU_ACCESS,
+                )
+                .map_err(|_| Error::OutOfDeviceMemory)?;
+
+            let desc = ImageDescriptor::video_frame(extent.width, extent.height, format);
+            Ok(Box::new(ScanoutImage::new(handle as usize, &desc)))
+        }
+
+        fn destroy_picture(&self, image: Box<dyn Image>) {
+            if let Some(gem) = self.device.gem() {
+                if let Err(e) = gem.free(image.handle() as u32) {
+                    log::warn!("Failed to free decoded picture: {}", e);
+                }
+            }
+        }
+
+        fn decode(&self, picture: &DecodePicture<'_>) -> gal::Result<u64> {
+            gal::video::check_picture(&self.descriptor, picture)?;
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let references = picture
+                .references
+                .iter()
+                .map(|reference| {
+                    Ok(Reference {
+                        surface: self.surface(reference.image)?,
+                        frame_num: reference.frame_num,
+                        pic_order_cnt: reference.pic_order_cnt,
+                        long_term: reference.long_term,
+                    })
+                })
+                .collect::<gal::Result<Vec<_>>>()?;
+
+            let job = DecodeJob {
+                session: self.session,
+                bitstream: picture.bitstream,
+                extent: picture.extent,
+                params: &picture.params,
+                target: self.surface(picture.output)?,
+                references: &references,
+            };
+            self.device.vcn().decode(gem, &job).map_err(|e| {
+                log::warn!("Failed to queue decode: {}", e);
+                Error::OperationFailed
+            })
+        }
+
+        fn wait(&self, submission: u64, timeout_ns: u64) -> gal::Result<bool> {
+            Ok(self
+                .device
+                .vcn()
+                .wait(submission, Duration::from_nanos(timeout_ns)))
+        }
+    }
+
+    impl Drop for AmdVideoDecoder {
+        fn drop(&mut self) {
+            if let Some(gem) = self.device.gem() {
+                if let Err(e) = self.device.vcn().destroy_session(gem, self.session) {
+                    log::warn!("Failed to destroy decoder session: {}", e);
+                }
+            }
+        }
+    }
 }
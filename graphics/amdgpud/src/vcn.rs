@@ -0,0 +1,702 @@
+//! Video Core Next (VCN) decoding
+//!
+//! VCN 4 takes decode and encode jobs on one unified ring. A job is an
+//! indirect buffer (IB) naming the engine it is for and, for decodes, the
+//! buffers the firmware works on: a message describing the picture, the
+//! bitstream, the output surface and a per-session context. Each stream is
+//! a session, opened with a create message and closed with a destroy
+//! message. References are passed by address with every picture (dynamic
+//! DPB), so the decoded picture buffer stays with the caller.
+//!
+//! Job completion is reported by a fence the ring writes after each IB;
+//! [`Vcn::retire`] picks it up from the event loop and frees the job's
+//! buffers.
+
+use gal::{Extent2D, ImageFormat, PictureParams, VideoCodec};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::device::Registers;
+use crate::gem::{GemFlags, GemManager};
+
+/// Unified ring registers of VCN instance 0
+mod reg {
+    pub const RB_BASE_LO: u32 = 0x1F460;
+    pub const RB_BASE_HI: u32 = 0x1F464;
+    pub const RB_SIZE: u32 = 0x1F468;
+    pub const RB_RPTR: u32 = 0x1F46C;
+    pub const RB_WPTR: u32 = 0x1F470;
+    /// Doorbell the ring's write pointer is posted to
+    pub const RB_DOORBELL_CTRL: u32 = 0x1F4A0;
+    pub const DOORBELL_EN: u32 = 1 << 28;
+}
+
+/// Unified ring commands
+mod ring_cmd {
+    pub const NO_OP: u32 = 0x0;
+    pub const IB: u32 = 0x2;
+    pub const FENCE: u32 = 0x3;
+    pub const TRAP: u32 = 0x4;
+}
+
+/// IB packet types and fields
+mod ib {
+    pub const SIGNATURE: u32 = 0x3000_0002;
+    pub const ENGINE_INFO: u32 = 0x3000_0001;
+    pub const ENGINE_TYPE_DECODE: u32 = 3;
+    pub const DECODE_BUFFER: u32 = 0x0000_0001;
+
+    /// Buffers present in a DECODE_BUFFER packet
+    pub const VALID_MSG: u32 = 1 << 0;
+    pub const VALID_SESSION_CONTEXT: u32 = 1 << 1;
+    pub const VALID_BITSTREAM: u32 = 1 << 2;
+    pub const VALID_TARGET: u32 = 1 << 3;
+    pub const VALID_CONTEXT: u32 = 1 << 4;
+}
+
+/// Decode messages
+mod message {
+    /// Message types
+    pub const CREATE: u32 = 0;
+    pub const DECODE: u32 = 1;
+    pub const DESTROY: u32 = 2;
+
+    /// Ids of the buffers a message is made of
+    pub const BUFFER_CREATE: u32 = 0x1;
+    pub const BUFFER_DECODE: u32 = 0x2;
+    pub const BUFFER_AVC: u32 = 0x6;
+    pub const BUFFER_HEVC: u32 = 0xD;
+    pub const BUFFER_DYNAMIC_DPB: u32 = 0x10;
+
+    /// Stream types
+    pub const CODEC_H264: u32 = 0x7;
+    pub const CODEC_H265: u32 = 0x10;
+
+    /// Dwords of the header before its buffer index
+    pub const HEADER_DWORDS: usize = 6;
+    /// Dwords of each buffer index entry
+    pub const INDEX_DWORDS: usize = 4;
+
+    /// Reference is a long-term one, in the AVC reference list
+    pub const LONG_TERM: u32 = 0x80;
+}
+
+/// Dwords of the ring
+const RING_DWORDS: usize = 4096;
+/// Dwords one submission takes on the ring, padded with NO_OPs
+const SUBMISSION_DWORDS: usize = 16;
+/// Size of a message buffer
+const MESSAGE_SIZE: usize = 4096;
+/// Size of the firmware's session context
+const SESSION_CONTEXT_SIZE: usize = 128 * 1024;
+
+/// Row pitch alignment of decode surfaces, in bytes
+const SURFACE_PITCH_ALIGNMENT: u32 = 256;
+/// Height alignment of decode surfaces, the largest H.265 coding tree block
+const SURFACE_HEIGHT_ALIGNMENT: u32 = 64;
+/// Bytes of motion vectors the firmware keeps per 16x16 block and picture
+const MV_BYTES_PER_BLOCK: usize = 64;
+
+/// Most references of one picture
+pub const MAX_REFERENCES: u32 = 16;
+
+/// Placement of an NV12 or P010 surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Surface {
+    /// GPU address of the luma plane
+    pub addr: u64,
+    /// Bytes between rows of either plane
+    pub pitch: u32,
+    /// Rows of the luma plane, the chroma plane follows them
+    pub aligned_height: u32,
+}
+
+impl Surface {
+    /// Layout of a surface for pictures of `extent` in `format`
+    pub fn layout(extent: Extent2D, format: ImageFormat) -> Option<(u32, u32)> {
+        let bytes_per_sample = match format {
+            ImageFormat::Nv12 => 1,
+            ImageFormat::P010 => 2,
+            _ => return None,
+        };
+        let pitch = (extent.width * bytes_per_sample).next_multiple_of(SURFACE_PITCH_ALIGNMENT);
+        let aligned_height = extent.height.next_multiple_of(SURFACE_HEIGHT_ALIGNMENT);
+        Some((pitch, aligned_height))
+    }
+
+    /// Bytes of a surface, both planes
+    pub fn size(pitch: u32, aligned_height: u32) -> usize {
+        // Half height chroma plane with interleaved Cb and Cr
+        pitch as usize * aligned_height as usize * 3 / 2
+    }
+
+    fn chroma_offset(&self) -> u32 {
+        self.pitch * self.aligned_height
+    }
+}
+
+/// Earlier picture a picture references
+#[derive(Debug, Clone, Copy)]
+pub struct Reference {
+    pub surface: Surface,
+    pub frame_num: u16,
+    pub pic_order_cnt: i32,
+    pub long_term: bool,
+}
+
+/// One picture to decode
+pub struct DecodeJob<'a> {
+    pub session: u32,
+    pub bitstream: &'a [u8],
+    pub extent: Extent2D,
+    pub params: &'a PictureParams,
+    pub target: Surface,
+    pub references: &'a [Reference],
+}
+
+/// Decoder session
+struct Session {
+    id: u32,
+    codec: VideoCodec,
+    /// Session context buffer
+    context: u32,
+    /// Motion vector buffer, H.265 only
+    mv: Option<u32>,
+}
+
+/// Submitted job, with the buffers freed once it completes
+struct InFlight {
+    seq: u64,
+    buffers: Vec<u32>,
+}
+
+struct Ring {
+    cpu_addr: usize,
+    gpu_addr: u64,
+    /// Next dword written
+    wptr: usize,
+    /// Sequence number of the last submission
+    emitted: u64,
+}
+
+impl Ring {
+    /// Byte offset of the fence value in the ring buffer, after the ring
+    const FENCE_OFFSET: usize = RING_DWORDS * 4;
+
+    fn fence_addr(&self) -> u64 {
+        self.gpu_addr + Self::FENCE_OFFSET as u64
+    }
+
+    /// Last sequence number the ring wrote
+    fn completed(&self) -> u64 {
+        // SAFETY: the fence lies in the ring buffer, mapped for the
+        // driver's lifetime
+        let fence =
+            unsafe { std::ptr::read_volatile((self.cpu_addr + Self::FENCE_OFFSET) as *const u32) };
+        // The fence holds the low 32 bits, at most 2^32 behind the last
+        // submission
+        let behind = (self.emitted as u32).wrapping_sub(fence);
+        self.emitted - u64::from(behind)
+    }
+
+    /// Queue `ib` and a fence for `seq`, returning the new write pointer
+    fn emit(&mut self, ib_addr: u64, ib_dwords: usize, seq: u64) -> usize {
+        let fence = self.fence_addr();
+        let mut packets = vec![
+            ring_cmd::IB,
+            ib_addr as u32,
+            (ib_addr >> 32) as u32,
+            ib_dwords as u32,
+            ring_cmd::FENCE,
+            fence as u32,
+            (fence >> 32) as u32,
+            seq as u32,
+            ring_cmd::TRAP,
+        ];
+        packets.resize(SUBMISSION_DWORDS, ring_cmd::NO_OP);
+
+        // Submissions never wrap, RING_DWORDS being a multiple of their size
+        write_dwords(self.cpu_addr + self.wptr * 4, &packets);
+        self.wptr = (self.wptr + SUBMISSION_DWORDS) % RING_DWORDS;
+        self.wptr
+    }
+}
+
+fn write_dwords(cpu_addr: usize, dwords: &[u32]) {
+    // SAFETY: callers pass mappings of GEM objects large enough for `dwords`
+    unsafe {
+        std::ptr::copy_nonoverlapping(dwords.as_ptr(), cpu_addr as *mut u32, dwords.len());
+    }
+}
+
+/// Allocate a CPU visible GTT buffer holding `dwords`
+fn upload(gem: &GemManager, size: usize, dwords: &[u32]) -> Result<(u32, u64), &'static str> {
+    let handle = gem.alloc(
+        size,
+        GemFlags::GTT | GemFlags::CPU_ACCESS | GemFlags::GPU_ACCESS,
+    )?;
+    let cpu_addr = gem.map(handle)?;
+    write_dwords(cpu_addr, dwords);
+    let gpu_addr = gem.get(handle).ok_or("Invalid handle")?.gpu_addr;
+    Ok((handle, gpu_addr))
+}
+
+/// Message of `kind` for `session`, made of `buffers` of the given ids
+fn build_message(kind: u32, session: u32, seq: u64, buffers: &[(u32, Vec<u32>)]) -> Vec<u32> {
+    let header_dwords = message::HEADER_DWORDS + buffers.len() * message::INDEX_DWORDS;
+    let total: usize = header_dwords + buffers.iter().map(|(_, b)| b.len()).sum::<usize>();
+
+    let mut msg = vec![
+        (header_dwords * 4) as u32,
+        (total * 4) as u32,
+        buffers.len() as u32,
+        kind,
+        session,
+        seq as u32,
+    ];
+    let mut offset = header_dwords * 4;
+    for (id, buffer) in buffers {
+        let size = buffer.len() * 4;
+        msg.extend_from_slice(&[*id, offset as u32, size as u32, 0]);
+        offset += size;
+    }
+    for (_, buffer) in buffers {
+        msg.extend_from_slice(buffer);
+    }
+    msg
+}
+
+fn addr_dwords(addr: u64) -> [u32; 2] {
+    [(addr >> 32) as u32, addr as u32]
+}
+
+/// VCN decode engine
+pub struct Vcn {
+    ring: Mutex<Option<Ring>>,
+    sessions: Mutex<Vec<Session>>,
+    in_flight: Mutex<VecDeque<InFlight>>,
+    /// Last sequence number retired
+    completed: Mutex<u64>,
+    completion: Condvar,
+    next_session: AtomicU32,
+}
+
+impl Vcn {
+    pub fn new() -> Self {
+        Self {
+            ring: Mutex::new(None),
+            sessions: Mutex::new(Vec::new()),
+            in_flight: Mutex::new(VecDeque::new()),
+            completed: Mutex::new(0),
+            completion: Condvar::new(),
+            next_session: AtomicU32::new(1),
+        }
+    }
+
+    /// Set up the unified ring, after the VCN firmware is loaded
+    pub fn init(&self, regs: &dyn Registers, gem: &GemManager) -> Result<(), &'static str> {
+        // The fence follows the ring in the same buffer
+        let handle = gem.alloc(
+            Ring::FENCE_OFFSET + 4096,
+            GemFlags::GTT | GemFlags::CPU_ACCESS | GemFlags::GPU_ACCESS,
+        )?;
+        let cpu_addr = gem.map(handle)?;
+        let gpu_addr = gem.get(handle).ok_or("Invalid handle")?.gpu_addr;
+        // SAFETY: the buffer was just mapped for its whole size
+        unsafe { std::ptr::write_bytes(cpu_addr as *mut u8, 0, Ring::FENCE_OFFSET + 8) };
+
+        regs.write(reg::RB_BASE_LO, gpu_addr as u32);
+        regs.write(reg::RB_BASE_HI, (gpu_addr >> 32) as u32);
+        regs.write(reg::RB_SIZE, (RING_DWORDS * 4) as u32);
+        regs.write(reg::RB_RPTR, 0);
+        regs.write(reg::RB_WPTR, 0);
+        regs.write(reg::RB_DOORBELL_CTRL, reg::DOORBELL_EN);
+
+        *self.ring.lock().unwrap() = Some(Ring {
+            cpu_addr,
+            gpu_addr,
+            wptr: 0,
+            emitted: 0,
+        });
+        log::info!("VCN unified ring at {:#x}", gpu_addr);
+        Ok(())
+    }
+
+    /// Limits of the decoder for `codec`: largest picture and highest level
+    pub fn limits(codec: VideoCodec) -> (Extent2D, u32) {
+        match codec {
+            // Level 5.2
+            VideoCodec::H264 => (Extent2D::new(4096, 4096), 52),
+            // Level 6.2, as general_level_idc
+            VideoCodec::H265 => (Extent2D::new(8192, 4352), 186),
+        }
+    }
+
+    /// Open a decoder session for a stream of `codec` with pictures up to
+    /// `max_extent`
+    pub fn create_session(
+        &self,
+        gem: &GemManager,
+        codec: VideoCodec,
+        max_extent: Extent2D,
+        max_references: u32,
+    ) -> Result<u32, &'static str> {
+        let id = self.next_session.fetch_add(1, Ordering::Relaxed);
+        let context = gem.alloc(SESSION_CONTEXT_SIZE, GemFlags::VRAM | GemFlags::GPU_ACCESS)?;
+        let mv = match codec {
+            VideoCodec::H264 => None,
+            VideoCodec::H265 => {
+                let blocks =
+                    (max_extent.width.div_ceil(16) * max_extent.height.div_ceil(16)) as usize;
+                let size = blocks * MV_BYTES_PER_BLOCK * (max_references as usize + 1);
+                Some(gem.alloc(size, GemFlags::VRAM | GemFlags::GPU_ACCESS)?)
+            }
+        };
+        let session = Session {
+            id,
+            codec,
+            context,
+            mv,
+        };
+
+        let create = vec![stream_type(codec), 0, max_extent.width, max_extent.height];
+        let result = self.submit_message(
+            gem,
+            &session,
+            message::CREATE,
+            &[(message::BUFFER_CREATE, create)],
+            None,
+            Vec::new(),
+        );
+        if let Err(e) = result {
+            let _ = gem.free(context);
+            if let Some(mv) = mv {
+                let _ = gem.free(mv);
+            }
+            return Err(e);
+        }
+
+        log::debug!(
+            "VCN: session {} for {:?} up to {}x{}",
+            id,
+            codec,
+            max_extent.width,
+            max_extent.height
+        );
+        self.sessions.lock().unwrap().push(session);
+        Ok(id)
+    }
+
+    /// Close a session; its buffers are freed once the destroy message is
+    /// done
+    pub fn destroy_session(&self, gem: &GemManager, id: u32) -> Result<(), &'static str> {
+        let session = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let index = sessions
+                .iter()
+                .position(|session| session.id == id)
+                .ok_or("Invalid VCN session")?;
+            sessions.remove(index)
+        };
+        let buffers = std::iter::once(session.context).chain(session.mv).collect();
+        self.submit_message(gem, &session, message::DESTROY, &[], None, buffers)?;
+        Ok(())
+    }
+
+    /// Queue a picture for decoding, returning the sequence number its
+    /// completion is reported with
+    pub fn decode(&self, gem: &GemManager, job: &DecodeJob<'_>) -> Result<u64, &'static str> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .iter()
+            .find(|session| session.id == job.session)
+            .ok_or("Invalid VCN session")?;
+        if job.params.codec() != session.codec {
+            return Err("Picture of another codec than the session");
+        }
+
+        let (bitstream, bitstream_addr) = {
+            let handle = gem.alloc(
+                job.bitstream.len(),
+                GemFlags::GTT | GemFlags::CPU_ACCESS | GemFlags::GPU_ACCESS,
+            )?;
+            let cpu_addr = gem.map(handle)?;
+            // SAFETY: the buffer was just mapped for the bitstream's size
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    job.bitstream.as_ptr(),
+                    cpu_addr as *mut u8,
+                    job.bitstream.len(),
+                );
+            }
+            (handle, gem.get(handle).ok_or("Invalid handle")?.gpu_addr)
+        };
+
+        let target = job.target;
+        let decode = vec![
+            stream_type(session.codec),
+            0,
+            job.extent.width,
+            job.extent.height,
+            job.bitstream.len() as u32,
+            Surface::size(target.pitch, target.aligned_height) as u32,
+            target.pitch,
+            target.aligned_height,
+            target.pitch,
+            target.pitch,
+            0,
+            target.chroma_offset(),
+        ];
+        let codec = match job.params {
+            PictureParams::H264(params) => (message::BUFFER_AVC, avc_message(params, job)),
+            PictureParams::H265(params) => (message::BUFFER_HEVC, hevc_message(params, job)),
+        };
+        let mut dpb = vec![job.references.len() as u32];
+        for reference in job.references.iter().map(|r| &r.surface).chain([&target]) {
+            dpb.extend_from_slice(&addr_dwords(reference.addr));
+            dpb.extend_from_slice(&[reference.pitch, reference.aligned_height]);
+        }
+
+        let buffers = [
+            (message::BUFFER_DECODE, decode),
+            codec,
+            (message::BUFFER_DYNAMIC_DPB, dpb),
+        ];
+        let output = DecodeOutput {
+            bitstream_addr,
+            target: target.addr,
+        };
+        let result = self.submit_message(
+            gem,
+            session,
+            message::DECODE,
+            &buffers,
+            Some(output),
+            vec![bitstream],
+        );
+        if result.is_err() {
+            let _ = gem.free(bitstream);
+        }
+        result
+    }
+
+    /// Retire completed jobs, from the event loop
+    pub fn retire(&self, gem: &GemManager) {
+        let Some(completed) = self.ring.lock().unwrap().as_ref().map(Ring::completed) else {
+            return;
+        };
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while in_flight.front().is_some_and(|job| job.seq <= completed) {
+            let job = in_flight.pop_front().unwrap();
+            for handle in job.buffers {
+                if let Err(e) = gem.free(handle) {
+                    log::warn!("VCN: failed to free job buffer: {}", e);
+                }
+            }
+        }
+        drop(in_flight);
+
+        let mut last = self.completed.lock().unwrap();
+        if completed > *last {
+            *last = completed;
+            self.completion.notify_all();
+        }
+    }
+
+    /// Wait until job `seq` is done, returns false on timeout
+    pub fn wait(&self, seq: u64, timeout: Duration) -> bool {
+        let completed = self.completed.lock().unwrap();
+        let (_completed, result) = self
+            .completion
+            .wait_timeout_while(completed, timeout, |completed| *completed < seq)
+            .unwrap();
+        !result.timed_out()
+    }
+
+    /// Write a message and the IB pointing at it, and queue the IB
+    ///
+    /// `buffers` are freed with the job's message and IB once it completes.
+    fn submit_message(
+        &self,
+        gem: &GemManager,
+        session: &Session,
+        kind: u32,
+        parts: &[(u32, Vec<u32>)],
+        output: Option<DecodeOutput>,
+        mut buffers: Vec<u32>,
+    ) -> Result<u64, &'static str> {
+        let mut ring = self.ring.lock().unwrap();
+        let ring = ring.as_mut().ok_or("VCN not initialized")?;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight.len() >= RING_DWORDS / SUBMISSION_DWORDS - 1 {
+            return Err("VCN ring full");
+        }
+        let seq = ring.emitted + 1;
+
+        let msg = build_message(kind, session.id, seq, parts);
+        let (msg_handle, msg_addr) = upload(gem, MESSAGE_SIZE.max(msg.len() * 4), &msg)?;
+
+        let context_addr = gem.get(session.context).ok_or("Invalid handle")?.gpu_addr;
+        let mut valid = ib::VALID_MSG | ib::VALID_SESSION_CONTEXT;
+        let mut decode_buffer = Vec::with_capacity(12);
+        decode_buffer.extend_from_slice(&addr_dwords(msg_addr));
+        decode_buffer.extend_from_slice(&addr_dwords(context_addr));
+        if let Some(output) = output {
+            valid |= ib::VALID_BITSTREAM | ib::VALID_TARGET;
+            decode_buffer.extend_from_slice(&addr_dwords(output.bitstream_addr));
+            decode_buffer.extend_from_slice(&addr_dwords(output.target));
+        }
+        if let Some(mv) = session.mv {
+            valid |= ib::VALID_CONTEXT;
+            let mv_addr = gem.get(mv).ok_or("Invalid handle")?.gpu_addr;
+            decode_buffer.extend_from_slice(&addr_dwords(mv_addr));
+        }
+
+        let mut ib = vec![8, ib::SIGNATURE];
+        ib.extend_from_slice(&[16, ib::ENGINE_INFO, ib::ENGINE_TYPE_DECODE, 0]);
+        ib.extend_from_slice(&[
+            ((decode_buffer.len() + 3) * 4) as u32,
+            ib::DECODE_BUFFER,
+            valid,
+        ]);
+        ib.extend_from_slice(&decode_buffer);
+        let (ib_handle, ib_addr) = match upload(gem, ib.len() * 4, &ib) {
+            Ok(ib) => ib,
+            Err(e) => {
+                let _ = gem.free(msg_handle);
+                return Err(e);
+            }
+        };
+
+        let wptr = ring.emit(ib_addr, ib.len(), seq);
+        ring.emitted = seq;
+        // The write pointer goes to the ring's doorbell
+        log::trace!("VCN: job {} message {} wptr {}", seq, kind, wptr);
+
+        buffers.extend_from_slice(&[msg_handle, ib_handle]);
+        in_flight.push_back(InFlight { seq, buffers });
+        Ok(seq)
+    }
+}
+
+/// Buffers only decode messages have
+#[derive(Clone, Copy)]
+struct DecodeOutput {
+    bitstream_addr: u64,
+    target: u64,
+}
+
+fn stream_type(codec: VideoCodec) -> u32 {
+    match codec {
+        VideoCodec::H264 => message::CODEC_H264,
+        VideoCodec::H265 => message::CODEC_H265,
+    }
+}
+
+/// AVC part of a decode message
+fn avc_message(params: &gal::video::H264PictureParams, job: &DecodeJob<'_>) -> Vec<u32> {
+    let sps_flags = u32::from(params.direct_8x8_inference_flag)
+        | u32::from(params.mb_adaptive_frame_field_flag) << 1
+        | u32::from(params.frame_mbs_only_flag) << 2;
+    let pps_flags = u32::from(params.transform_8x8_mode_flag)
+        | u32::from(params.constrained_intra_pred_flag) << 1
+        | u32::from(params.weighted_bipred_idc) << 2
+        | u32::from(params.weighted_pred_flag) << 4
+        | u32::from(params.entropy_coding_mode_flag) << 5;
+
+    let mut msg = vec![
+        u32::from(params.level_idc),
+        sps_flags,
+        pps_flags,
+        u32::from(params.log2_max_frame_num_minus4),
+        u32::from(params.pic_order_cnt_type),
+        u32::from(params.log2_max_pic_order_cnt_lsb_minus4),
+        u32::from(params.max_num_ref_frames),
+        params.pic_init_qp_minus26 as u32,
+        params.chroma_qp_index_offset as u32,
+        params.second_chroma_qp_index_offset as u32,
+        u32::from(params.num_ref_idx_l0_default_active_minus1),
+        u32::from(params.num_ref_idx_l1_default_active_minus1),
+        u32::from(params.frame_num),
+        params.field_order_cnt[0] as u32,
+        params.field_order_cnt[1] as u32,
+        u32::from(params.idr) | u32::from(params.reference) << 1,
+    ];
+    // Reference list: DPB index with the long-term bit, frame number and
+    // field order counts, unused entries all ones
+    for index in 0..MAX_REFERENCES as usize {
+        match job.references.get(index) {
+            Some(reference) => msg.extend_from_slice(&[
+                index as u32
+                    | if reference.long_term {
+                        message::LONG_TERM
+                    } else {
+                        0
+                    },
+                u32::from(reference.frame_num),
+                reference.pic_order_cnt as u32,
+                reference.pic_order_cnt as u32,
+            ]),
+            None => msg.extend_from_slice(&[u32::MAX; 4]),
+        }
+    }
+    // The current picture's place in the dynamic DPB, after the references
+    msg.push(job.references.len() as u32);
+    msg
+}
+
+/// HEVC part of a decode message
+fn hevc_message(params: &gal::video::H265PictureParams, job: &DecodeJob<'_>) -> Vec<u32> {
+    let sps_flags = u32::from(params.amp_enabled_flag)
+        | u32::from(params.sample_adaptive_offset_enabled_flag) << 1
+        | u32::from(params.strong_intra_smoothing_enabled_flag) << 2;
+    let pps_flags = u32::from(params.sign_data_hiding_enabled_flag)
+        | u32::from(params.cu_qp_delta_enabled_flag) << 1
+        | u32::from(params.transform_skip_enabled_flag) << 2
+        | u32::from(params.tiles_enabled_flag) << 3
+        | u32::from(params.entropy_coding_sync_enabled_flag) << 4
+        | u32::from(params.weighted_pred_flag) << 5
+        | u32::from(params.weighted_bipred_flag) << 6;
+
+    let mut msg = vec![
+        u32::from(params.general_level_idc),
+        sps_flags,
+        pps_flags,
+        u32::from(params.bit_depth_luma_minus8),
+        u32::from(params.bit_depth_chroma_minus8),
+        u32::from(params.log2_min_luma_coding_block_size_minus3),
+        u32::from(params.log2_diff_max_min_luma_coding_block_size),
+        u32::from(params.log2_min_transform_block_size_minus2),
+        u32::from(params.log2_diff_max_min_transform_block_size),
+        u32::from(params.max_transform_hierarchy_depth_inter),
+        u32::from(params.max_transform_hierarchy_depth_intra),
+        u32::from(params.log2_max_pic_order_cnt_lsb_minus4),
+        u32::from(params.diff_cu_qp_delta_depth),
+        params.init_qp_minus26 as u32,
+        params.pps_cb_qp_offset as u32,
+        params.pps_cr_qp_offset as u32,
+        u32::from(params.num_ref_idx_l0_default_active_minus1),
+        u32::from(params.num_ref_idx_l1_default_active_minus1),
+        u32::from(params.nal_unit_type),
+        params.pic_order_cnt as u32,
+        u32::from(params.irap),
+    ];
+    // Reference picture set: POC and long-term flag of each DPB entry,
+    // unused entries all ones
+    for index in 0..MAX_REFERENCES as usize {
+        match job.references.get(index) {
+            Some(reference) => {
+                msg.extend_from_slice(&[
+                    reference.pic_order_cnt as u32,
+                    u32::from(reference.long_term),
+                ]);
+            }
+            None => msg.extend_from_slice(&[u32::MAX; 2]),
+        }
+    }
+    msg.push(job.references.len() as u32);
+    msg
+}
@@ -0,0 +1,517 @@
+//! Memory and fences shared between GPU drivers
+//!
+//! Render offload (`gal::offload`) renders frames on one GPU and scans them
+//! out on another. The rendering driver allocates such frames as
+//! [`SharedMemory`] and exports them, and the fences signalled when they are
+//! rendered, through [`ExternalExports`], which serves them under the
+//! driver's own scheme like dma-bufs and sync files:
+//!
+//! - `<scheme>:memory/<id>` maps the pages with `fmap`. The importing driver
+//!   looks up their physical addresses to bind them into its own GPU address
+//!   space, see [`ImportedMemory`].
+//! - `<scheme>:fence/<id>` reads one byte and raises `EVENT_READ` once the
+//!   fence is signalled, and fails with `EAGAIN` before, see
+//!   [`ImportedFence`].
+//!
+//! Only the exporting driver itself opens these paths; the file handles then
+//! travel to the importer like any other, so holding one is what grants
+//! access. An export lives until its handle is closed.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use common::sgl::Sgl;
+use common::VirtaddrTranslationHandle;
+use event::EventQueue;
+use gal::{ExternalFence, ExternalMemory};
+use libredox::call::MmapArgs;
+use libredox::flag;
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult, RequestKind, Response, SignalBehavior, Socket};
+use syscall::schemev2::NewFdFlags;
+use syscall::{
+    Error, EventFlags, MapFlags, Result, Stat, EACCES, EAGAIN, EBADF, EINVAL, ENOENT, EROFS,
+    MODE_FILE, PAGE_SIZE,
+};
+
+use crate::irq::arm_timer;
+
+/// System memory both the exporting and the importing GPU can reach
+pub struct SharedMemory {
+    sgl: Sgl,
+}
+
+// SAFETY: the list only points into its own mapping, which lives as long
+// as it does
+unsafe impl Send for SharedMemory {}
+unsafe impl Sync for SharedMemory {}
+
+impl SharedMemory {
+    pub fn new(size: usize) -> io::Result<Self> {
+        Ok(Self {
+            sgl: Sgl::new(size)?,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.sgl.as_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sgl.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Physical address and length of each physically contiguous chunk
+    pub fn chunks(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.sgl
+            .chunks()
+            .iter()
+            .map(|chunk| (chunk.phys, chunk.length))
+    }
+}
+
+enum Export {
+    Memory(Arc<SharedMemory>),
+    Fence {
+        signalled: Box<dyn Fn() -> bool + Send>,
+        events: EventFlags,
+        notified: bool,
+    },
+}
+
+#[derive(Default)]
+struct Exports {
+    exports: BTreeMap<usize, Export>,
+    /// Exports registered but not opened yet
+    unopened: Vec<usize>,
+    next_id: usize,
+}
+
+/// Memory and fences a driver exports to other drivers
+pub struct ExternalExports {
+    scheme: String,
+    state: Mutex<Exports>,
+    /// Socket of [`serve_external`], to post fence events on
+    socket: OnceLock<Arc<Socket>>,
+}
+
+impl ExternalExports {
+    /// Exports served under `scheme` by [`serve_external`]
+    pub fn new(scheme: &str) -> Arc<Self> {
+        Arc::new(Self {
+            scheme: scheme.to_owned(),
+            state: Mutex::new(Exports::default()),
+            socket: OnceLock::new(),
+        })
+    }
+
+    /// Export `memory`, which stays allocated until the handle is closed
+    pub fn export_memory(&self, memory: Arc<SharedMemory>) -> gal::Result<ExternalMemory> {
+        let size = memory.len() as u64;
+        let fd = self.export("memory", Export::Memory(memory))?;
+        Ok(ExternalMemory { fd, size })
+    }
+
+    /// Export a fence, `signalled` tells whether it is
+    ///
+    /// Call [`ExternalExports::signal_fences`] whenever fences may have been
+    /// signalled, usually from the completion interrupt.
+    pub fn export_fence(
+        &self,
+        signalled: impl Fn() -> bool + Send + 'static,
+    ) -> gal::Result<ExternalFence> {
+        let fence = Export::Fence {
+            signalled: Box::new(signalled),
+            events: EventFlags::empty(),
+            notified: false,
+        };
+        let fd = self.export("fence", fence)?;
+        Ok(ExternalFence { fd })
+    }
+
+    fn export(&self, kind: &str, export: Export) -> gal::Result<usize> {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.exports.insert(id, export);
+            state.unopened.push(id);
+            id
+        };
+
+        let path = format!("/scheme/{}/{}/{}", self.scheme, kind, id);
+        libredox::call::open(&path, flag::O_RDWR | flag::O_CLOEXEC, 0).map_err(|err| {
+            log::warn!("Failed to open {}: {}", path, err);
+            let mut state = self.state.lock().unwrap();
+            state.exports.remove(&id);
+            state.unopened.retain(|&unopened| unopened != id);
+            gal::Error::OperationFailed
+        })
+    }
+
+    /// Notify importers waiting for fences that have been signalled since
+    pub fn signal_fences(&self) {
+        let Some(socket) = self.socket.get() else {
+            return;
+        };
+
+        let mut notify = Vec::new();
+        for (&id, export) in self.state.lock().unwrap().exports.iter_mut() {
+            if let Export::Fence {
+                signalled,
+                events,
+                notified,
+            } = export
+            {
+                if events.contains(EventFlags::EVENT_READ) && !*notified && signalled() {
+                    *notified = true;
+                    notify.push(id);
+                }
+            }
+        }
+
+        for id in notify {
+            if let Err(err) = socket.write_response(
+                Response::post_fevent(id, EventFlags::EVENT_READ.bits()),
+                SignalBehavior::Restart,
+            ) {
+                log::warn!("Failed to post fence event: {}", err);
+            }
+        }
+    }
+}
+
+struct ExternalScheme<'a> {
+    exports: &'a ExternalExports,
+}
+
+impl SchemeSync for ExternalScheme<'_> {
+    fn open(&mut self, path: &str, _flags: usize, ctx: &CallerCtx) -> Result<OpenResult> {
+        // Exports are handed out as file handles, never by path
+        if ctx.pid != std::process::id() as usize {
+            return Err(Error::new(EACCES));
+        }
+
+        let (kind, id) = path
+            .trim_matches('/')
+            .split_once('/')
+            .ok_or(Error::new(ENOENT))?;
+        let id = id.parse::<usize>().map_err(|_| Error::new(ENOENT))?;
+
+        let mut state = self.exports.state.lock().unwrap();
+        let matches = match (kind, state.exports.get(&id)) {
+            ("memory", Some(Export::Memory(_))) => true,
+            ("fence", Some(Export::Fence { .. })) => true,
+            _ => false,
+        };
+        let unopened = state.unopened.iter().position(|&unopened| unopened == id);
+        let (true, Some(index)) = (matches, unopened) else {
+            return Err(Error::new(ENOENT));
+        };
+        state.unopened.swap_remove(index);
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let state = self.exports.state.lock().unwrap();
+        match state.exports.get(&id).ok_or(Error::new(EBADF))? {
+            Export::Fence { signalled, .. } if signalled() => {
+                let Some(byte) = buf.first_mut() else {
+                    return Ok(0);
+                };
+                *byte = 1;
+                Ok(1)
+            }
+            Export::Fence { .. } => Err(Error::new(EAGAIN)),
+            Export::Memory(_) => Err(Error::new(EINVAL)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _id: usize,
+        _buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        Err(Error::new(EROFS))
+    }
+
+    fn fevent(&mut self, id: usize, flags: EventFlags, _ctx: &CallerCtx) -> Result<EventFlags> {
+        let mut state = self.exports.state.lock().unwrap();
+        let Export::Fence {
+            signalled,
+            events,
+            notified,
+        } = state.exports.get_mut(&id).ok_or(Error::new(EBADF))?
+        else {
+            return Ok(EventFlags::empty());
+        };
+
+        *events = flags;
+        *notified = false;
+        if flags.contains(EventFlags::EVENT_READ) && signalled() {
+            *notified = true;
+            return Ok(EventFlags::EVENT_READ);
+        }
+        Ok(EventFlags::empty())
+    }
+
+    fn mmap_prep(
+        &mut self,
+        id: usize,
+        offset: u64,
+        size: usize,
+        _flags: MapFlags,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let state = self.exports.state.lock().unwrap();
+        let Export::Memory(memory) = state.exports.get(&id).ok_or(Error::new(EBADF))? else {
+            return Err(Error::new(EINVAL));
+        };
+
+        let mapped = memory.len().next_multiple_of(PAGE_SIZE);
+        let end = (offset as usize).checked_add(size);
+        if offset as usize % PAGE_SIZE != 0 || end.is_none_or(|end| end > mapped) {
+            return Err(Error::new(EINVAL));
+        }
+        Ok(memory.as_ptr() as usize + offset as usize)
+    }
+
+    fn fstat(&mut self, id: usize, stat: &mut Stat, _ctx: &CallerCtx) -> Result<()> {
+        let state = self.exports.state.lock().unwrap();
+        let size = match state.exports.get(&id).ok_or(Error::new(EBADF))? {
+            Export::Memory(memory) => memory.len() as u64,
+            Export::Fence { .. } => 0,
+        };
+        *stat = Stat {
+            st_mode: MODE_FILE | 0o600,
+            st_size: size,
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    fn fpath(&mut self, id: usize, buf: &mut [u8], _ctx: &CallerCtx) -> Result<usize> {
+        let state = self.exports.state.lock().unwrap();
+        let kind = match state.exports.get(&id).ok_or(Error::new(EBADF))? {
+            Export::Memory(_) => "memory",
+            Export::Fence { .. } => "fence",
+        };
+        let path = format!("{}:{}/{}", self.exports.scheme, kind, id);
+        let len = buf.len().min(path.len());
+        buf[..len].copy_from_slice(&path.as_bytes()[..len]);
+        Ok(len)
+    }
+}
+
+/// Serve `exports` until the scheme is unmounted
+pub fn serve_external(exports: Arc<ExternalExports>) -> Result<()> {
+    let socket = Arc::new(Socket::create(&exports.scheme)?);
+    if exports.socket.set(socket.clone()).is_err() {
+        log::error!("{}: already served", exports.scheme);
+        return Err(Error::new(EINVAL));
+    }
+
+    loop {
+        let Some(request) = socket.next_request(SignalBehavior::Restart)? else {
+            // Scheme likely got unmounted
+            return Ok(());
+        };
+
+        match request.kind() {
+            RequestKind::Call(call) => {
+                let response = call.handle_sync(&mut ExternalScheme { exports: &exports });
+                socket.write_response(response, SignalBehavior::Restart)?;
+            }
+            RequestKind::OnClose { id } => {
+                exports.state.lock().unwrap().exports.remove(&id);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Memory exported by another driver, mapped into this one
+pub struct ImportedMemory {
+    addr: *mut u8,
+    /// Length of the mapping, whole pages
+    mapped: usize,
+    size: usize,
+}
+
+// SAFETY: the mapping is owned and unmapped on drop only
+unsafe impl Send for ImportedMemory {}
+unsafe impl Sync for ImportedMemory {}
+
+impl ImportedMemory {
+    /// Map the pages behind `memory`
+    ///
+    /// The caller keeps ownership of `memory.fd`.
+    pub fn map(memory: &ExternalMemory) -> gal::Result<Self> {
+        let size = usize::try_from(memory.size).map_err(|_| gal::Error::InvalidParameter)?;
+        if size == 0 {
+            return Err(gal::Error::InvalidParameter);
+        }
+        let mapped = size.next_multiple_of(PAGE_SIZE);
+        let addr = unsafe {
+            libredox::call::mmap(MmapArgs {
+                fd: memory.fd,
+                offset: 0,
+                length: mapped,
+                prot: flag::PROT_READ | flag::PROT_WRITE,
+                flags: flag::MAP_SHARED,
+                addr: core::ptr::null_mut(),
+            })
+        }
+        .map_err(|err| {
+            log::warn!("Failed to map external memory: {}", err);
+            gal::Error::InvalidParameter
+        })?;
+
+        Ok(Self {
+            addr: addr.cast(),
+            mapped,
+            size,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Physical address of every page, to bind them into the GPU's address
+    /// space
+    pub fn pages(&self) -> gal::Result<Vec<usize>> {
+        let translation =
+            VirtaddrTranslationHandle::new().map_err(|_| gal::Error::OperationFailed)?;
+        (0..self.mapped)
+            .step_by(PAGE_SIZE)
+            .map(|offset| {
+                translation
+                    .translate(self.addr as usize + offset)
+                    .map_err(|_| gal::Error::OperationFailed)
+            })
+            .collect()
+    }
+}
+
+impl Drop for ImportedMemory {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = libredox::call::munmap(self.addr.cast(), self.mapped);
+        }
+    }
+}
+
+/// A fence exported by another driver
+pub struct ImportedFence {
+    file: File,
+}
+
+impl ImportedFence {
+    /// Import `fence`, the caller keeps ownership of `fence.fd`
+    pub fn new(fence: &ExternalFence) -> gal::Result<Self> {
+        let fd = libredox::call::dup(fence.fd, &[]).map_err(|_| gal::Error::InvalidParameter)?;
+        Ok(Self {
+            file: unsafe { File::from_raw_fd(fd as _) },
+        })
+    }
+}
+
+impl gal::Fence for ImportedFence {
+    fn handle(&self) -> usize {
+        self.file.as_raw_fd() as usize
+    }
+
+    fn is_signaled(&self) -> gal::Result<bool> {
+        let mut byte = [0];
+        match libredox::call::read(self.file.as_raw_fd() as usize, &mut byte) {
+            Ok(read) => Ok(read == 1),
+            Err(err) if err.errno() == EAGAIN => Ok(false),
+            Err(_) => Err(gal::Error::DeviceLost),
+        }
+    }
+
+    fn wait(&self, timeout_ns: u64) -> gal::Result<bool> {
+        const FENCE: usize = 0;
+        const TIMER: usize = 1;
+
+        if self.is_signaled()? {
+            return Ok(true);
+        }
+        if timeout_ns == 0 {
+            return Ok(false);
+        }
+
+        let wait_failed = |err: io::Error| {
+            log::warn!("Failed to wait for external fence: {}", err);
+            gal::Error::OperationFailed
+        };
+        let mut queue = EventQueue::<usize>::new().map_err(wait_failed)?;
+        queue
+            .subscribe(
+                self.file.as_raw_fd() as usize,
+                FENCE,
+                event::EventFlags::READ,
+            )
+            .map_err(wait_failed)?;
+        // Keep the timer open while waiting
+        let _timer = if timeout_ns == u64::MAX {
+            None
+        } else {
+            let mut timer = File::open(format!("/scheme/time/{}", flag::CLOCK_MONOTONIC))
+                .map_err(wait_failed)?;
+            arm_timer(&mut timer, Duration::from_nanos(timeout_ns)).map_err(wait_failed)?;
+            queue
+                .subscribe(timer.as_raw_fd() as usize, TIMER, event::EventFlags::READ)
+                .map_err(wait_failed)?;
+            Some(timer)
+        };
+
+        // Fence events may be spurious, readiness is what the read says
+        while !self.is_signaled()? {
+            let event = queue
+                .next()
+                .ok_or(gal::Error::OperationFailed)?
+                .map_err(wait_failed)?;
+            if event.user_data == TIMER {
+                return self.is_signaled();
+            }
+        }
+        Ok(true)
+    }
+
+    fn reset(&self) -> gal::Result<()> {
+        // Only the exporter signals and resets its fences
+        Err(gal::Error::NotSupported)
+    }
+}
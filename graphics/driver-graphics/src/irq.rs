@@ -0,0 +1,207 @@
+//! Interrupt-driven main loop of the GPU drivers
+//!
+//! [`GpuEventLoop`] takes MSI-X or MSI vectors from pcid, falling back to
+//! the legacy interrupt line, and a periodic timer for watchdog work. It
+//! blocks on an event queue until one of them fires, so an idle GPU costs
+//! no CPU time and completions are handled as soon as the device signals
+//! them.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+use event::{EventFlags, EventQueue};
+use pcid_interface::PciFunctionHandle;
+
+/// User data of the timer in the event queue, vectors use their index
+const TIMER: usize = usize::MAX;
+
+/// Device side of [`GpuEventLoop`]
+pub trait InterruptHandler {
+    /// Handle an interrupt on `vector`
+    ///
+    /// Returns whether the device raised it, a shared legacy line may also
+    /// fire for other devices.
+    fn interrupt(&self, vector: usize) -> bool;
+
+    /// Periodic work, such as hang detection
+    fn timer(&self);
+}
+
+/// Memory mapped register BAR
+pub struct Mmio {
+    base: usize,
+    size: usize,
+}
+
+impl Mmio {
+    /// # Safety
+    ///
+    /// `base` must point to `size` bytes of device registers, mapped for
+    /// the lifetime of the driver.
+    pub unsafe fn new(base: *mut u8, size: usize) -> Self {
+        Self {
+            base: base as usize,
+            size,
+        }
+    }
+
+    pub fn read32(&self, offset: u32) -> u32 {
+        assert!(offset as usize + 4 <= self.size, "register out of range");
+        // SAFETY: in range of the mapping, see `new`
+        unsafe { std::ptr::read_volatile((self.base + offset as usize) as *const u32) }
+    }
+
+    pub fn write32(&self, offset: u32, value: u32) {
+        assert!(offset as usize + 4 <= self.size, "register out of range");
+        // SAFETY: as in read32
+        unsafe { std::ptr::write_volatile((self.base + offset as usize) as *mut u32, value) }
+    }
+}
+
+/// Make a `time:` handle fire `after` from now
+pub(crate) fn arm_timer(timer: &mut File, after: Duration) -> io::Result<()> {
+    let mut time_buf = [0_u8; core::mem::size_of::<libredox::data::TimeSpec>()];
+    if timer.read(&mut time_buf)? < time_buf.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "time read too small",
+        ));
+    }
+
+    let time = libredox::data::timespec_from_mut_bytes(&mut time_buf);
+    let nsec = time.tv_nsec as u64 + u64::from(after.subsec_nanos());
+    time.tv_sec += (after.as_secs() + nsec / 1_000_000_000) as _;
+    time.tv_nsec = (nsec % 1_000_000_000) as _;
+    timer.write_all(&time_buf)
+}
+
+/// Event queue of a GPU's interrupt vectors and watchdog timer
+pub struct GpuEventLoop {
+    queue: EventQueue<usize>,
+    vectors: Vec<File>,
+    timer: File,
+    period: Duration,
+}
+
+impl GpuEventLoop {
+    /// Set up up to `vectors` interrupt vectors and a timer firing every
+    /// `period`
+    pub fn new(
+        pcid_handle: &mut PciFunctionHandle,
+        driver: &str,
+        vectors: u16,
+        period: Duration,
+    ) -> io::Result<Self> {
+        let vectors = Self::allocate_vectors(pcid_handle, driver, vectors)?;
+        let queue = EventQueue::new()?;
+        for (index, vector) in vectors.iter().enumerate() {
+            queue.subscribe(vector.as_raw_fd() as usize, index, EventFlags::READ)?;
+        }
+
+        let timer = File::open(format!("/scheme/time/{}", libredox::flag::CLOCK_MONOTONIC))?;
+        queue.subscribe(timer.as_raw_fd() as usize, TIMER, EventFlags::READ)?;
+
+        let mut event_loop = Self {
+            queue,
+            vectors,
+            timer,
+            period,
+        };
+        event_loop.arm_timer()?;
+        Ok(event_loop)
+    }
+
+    /// Number of interrupt vectors granted
+    pub fn vector_count(&self) -> usize {
+        self.vectors.len()
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn allocate_vectors(
+        pcid_handle: &mut PciFunctionHandle,
+        driver: &str,
+        count: u16,
+    ) -> io::Result<Vec<File>> {
+        use pcid_interface::irq_helpers;
+
+        match irq_helpers::allocate_interrupts(pcid_handle, count) {
+            Ok(interrupts) => {
+                log::info!(
+                    "{}: using {} {:?} vector(s)",
+                    driver,
+                    interrupts.handles.len(),
+                    interrupts.feature
+                );
+                Ok(interrupts.handles)
+            }
+            Err(err) => {
+                log::warn!(
+                    "{}: no MSI vectors ({}), using legacy interrupt",
+                    driver,
+                    err
+                );
+                Self::legacy_vector(pcid_handle, driver)
+            }
+        }
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    fn allocate_vectors(
+        pcid_handle: &mut PciFunctionHandle,
+        driver: &str,
+        _count: u16,
+    ) -> io::Result<Vec<File>> {
+        Self::legacy_vector(pcid_handle, driver)
+    }
+
+    fn legacy_vector(pcid_handle: &mut PciFunctionHandle, driver: &str) -> io::Result<Vec<File>> {
+        let irq = pcid_handle
+            .config()
+            .func
+            .legacy_interrupt_line
+            .ok_or_else(|| io::Error::other("no legacy interrupt line"))?;
+        Ok(vec![irq.irq_handle(driver)])
+    }
+
+    fn arm_timer(&mut self) -> io::Result<()> {
+        arm_timer(&mut self.timer, self.period)
+    }
+
+    /// Dispatch interrupts and timer expiries to `handler`, forever
+    pub fn run(mut self, handler: &dyn InterruptHandler) -> ! {
+        loop {
+            let event = match self.queue.next() {
+                Some(Ok(event)) => event,
+                Some(Err(err)) => {
+                    log::error!("Failed to read event queue: {}", err);
+                    continue;
+                }
+                None => panic!("event queue closed"),
+            };
+
+            if event.user_data == TIMER {
+                handler.timer();
+                if let Err(err) = self.arm_timer() {
+                    log::error!("Failed to arm timer: {}", err);
+                }
+                continue;
+            }
+
+            let Some(vector) = self.vectors.get_mut(event.user_data) else {
+                continue;
+            };
+            let mut irq = [0; 8];
+            if vector.read(&mut irq).unwrap_or(0) == 0 {
+                continue;
+            }
+            if handler.interrupt(event.user_data) {
+                // Acknowledge, re-enabling the line
+                if let Err(err) = vector.write(&irq) {
+                    log::error!("Failed to acknowledge interrupt: {}", err);
+                }
+            }
+        }
+    }
+}
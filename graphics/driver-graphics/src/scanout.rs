@@ -0,0 +1,183 @@
+//! `scanout:` scheme
+//!
+//! Display modules log vertical blanks and completed flips into a
+//! [`ScanoutLog`] from their interrupt handlers. Reading `scanout:` returns
+//! the events logged since the handle's previous read as
+//! [`ScanoutEvent`]s, whole events only and none if nothing happened, so
+//! `latency`'s FramePacer can pace to the actual scanout. A reader too slow
+//! to keep up misses the oldest events.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use latency::scanout::{ScanoutEvent, ScanoutEventKind, EVENT_SIZE};
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult, RequestKind, SignalBehavior, Socket};
+use syscall::schemev2::NewFdFlags;
+use syscall::{Error, Result, EBADF, ENOENT, EROFS};
+
+/// Events kept for readers, a second of vertical blanks and flips at 120 Hz
+const MAX_EVENTS: usize = 256;
+
+#[derive(Default)]
+struct Events {
+    events: VecDeque<ScanoutEvent>,
+    /// Number of events dropped from the front so far
+    dropped: u64,
+}
+
+/// Scanout events of a driver's displays
+#[derive(Default)]
+pub struct ScanoutLog {
+    events: Mutex<Events>,
+}
+
+impl ScanoutLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Log the start of vertical blank `sequence` of `display`
+    pub fn vblank(&self, display: u16, sequence: u64) {
+        self.log(ScanoutEventKind::Vblank, display, sequence);
+    }
+
+    /// Log a flip of `display` latched at vertical blank `sequence`
+    pub fn flip_complete(&self, display: u16, sequence: u64) {
+        self.log(ScanoutEventKind::FlipComplete, display, sequence);
+    }
+
+    fn log(&self, kind: ScanoutEventKind, display: u16, sequence: u64) {
+        let Ok(now) = libredox::call::clock_gettime(libredox::flag::CLOCK_MONOTONIC) else {
+            return;
+        };
+        let event = ScanoutEvent {
+            kind,
+            display,
+            sequence,
+            timestamp_ns: now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64,
+        };
+
+        let mut events = self.events.lock().unwrap();
+        if events.events.len() == MAX_EVENTS {
+            events.events.pop_front();
+            events.dropped += 1;
+        }
+        events.events.push_back(event);
+    }
+
+    /// Number of events logged so far
+    fn end(&self) -> u64 {
+        let events = self.events.lock().unwrap();
+        events.dropped + events.events.len() as u64
+    }
+
+    /// Encode events from number `*next` on into `buf`, advancing `*next`
+    fn read(&self, next: &mut u64, buf: &mut [u8]) -> usize {
+        let events = self.events.lock().unwrap();
+        let start = next.saturating_sub(events.dropped) as usize;
+        let mut len = 0;
+        for event in events.events.iter().skip(start) {
+            let Some(chunk) = buf.get_mut(len..len + EVENT_SIZE) else {
+                break;
+            };
+            chunk.copy_from_slice(&event.to_bytes());
+            len += EVENT_SIZE;
+        }
+        *next = events.dropped + (start + len / EVENT_SIZE) as u64;
+        len
+    }
+}
+
+pub struct ScanoutScheme {
+    log: Arc<ScanoutLog>,
+    /// Number of the next event each handle reads
+    handles: BTreeMap<usize, u64>,
+    next_id: usize,
+}
+
+impl ScanoutScheme {
+    pub fn new(log: Arc<ScanoutLog>) -> Self {
+        Self {
+            log,
+            handles: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn on_close(&mut self, id: usize) {
+        self.handles.remove(&id);
+    }
+}
+
+impl SchemeSync for ScanoutScheme {
+    fn open(&mut self, path: &str, _flags: usize, _ctx: &CallerCtx) -> Result<OpenResult> {
+        if !path.trim_matches('/').is_empty() {
+            return Err(Error::new(ENOENT));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        // Only events from now on, older ones are stale for pacing
+        self.handles.insert(id, self.log.end());
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let next = self.handles.get_mut(&id).ok_or(Error::new(EBADF))?;
+        Ok(self.log.read(next, buf))
+    }
+
+    fn write(
+        &mut self,
+        _id: usize,
+        _buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        Err(Error::new(EROFS))
+    }
+
+    fn fpath(&mut self, _id: usize, buf: &mut [u8], _ctx: &CallerCtx) -> Result<usize> {
+        let path = b"scanout:";
+        let len = buf.len().min(path.len());
+        buf[..len].copy_from_slice(&path[..len]);
+        Ok(len)
+    }
+}
+
+/// Serve `scanout:` from `log` until the scheme is unmounted
+pub fn serve_scanout(log: Arc<ScanoutLog>) -> Result<()> {
+    let socket = Socket::create("scanout")?;
+    let mut scheme = ScanoutScheme::new(log);
+
+    loop {
+        let Some(request) = socket.next_request(SignalBehavior::Restart)? else {
+            // Scheme likely got unmounted
+            return Ok(());
+        };
+
+        match request.kind() {
+            RequestKind::Call(call) => {
+                let response = call.handle_sync(&mut scheme);
+                socket.write_response(response, SignalBehavior::Restart)?;
+            }
+            RequestKind::OnClose { id } => {
+                scheme.on_close(id);
+            }
+            _ => (),
+        }
+    }
+}
@@ -21,6 +21,14 @@ pub enum DxvkError {
     InvalidParameter,
     /// Not supported
     NotSupported,
+    /// The GAL backend failed
+    Backend(gal::Error),
+}
+
+impl From<gal::Error> for DxvkError {
+    fn from(err: gal::Error) -> Self {
+        DxvkError::Backend(err)
+    }
 }
 
 impl fmt::Display for DxvkError {
@@ -37,6 +45,7 @@ impl fmt::Display for DxvkError {
             }
             DxvkError::InvalidParameter => write!(f, "Invalid parameter"),
             DxvkError::NotSupported => write!(f, "Not supported"),
+            DxvkError::Backend(err) => write!(f, "Backend error: {}", err),
         }
     }
 }
@@ -0,0 +1,787 @@
+//! D3D11 immediate context
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use gal::command::{
+    BufferCopy, ColorAttachment, DepthStencilAttachment, DrawIndexedCommand, Filter, ImageAspect,
+    ImageBlit, ImageSubresourceLayers, ImageSubresourceRange, IndexType, LoadOp,
+    RenderPassDescriptor, StoreOp,
+};
+use gal::device::{GraphicsPipelineDescriptor, PrimitiveTopology, VertexBinding};
+use gal::{
+    Buffer, BufferDescriptor, ClearColor, ClearDepthStencil, ClearValue, CommandBuffer,
+    CommandPool, DrawCommand, Extent2D, Fence, Image, Offset3D, Pipeline, PipelineKey, QueueType,
+    Rect2D, SubmitInfo, Viewport,
+};
+
+use super::format::DxgiFormat;
+use super::resource::{
+    D3D11Buffer, D3D11DepthStencilView, D3D11RenderTargetView, D3D11Texture2D, D3D11Usage,
+};
+use super::state::{
+    D3D11BlendDesc, D3D11BlendState, D3D11DepthStencilDesc, D3D11DepthStencilState,
+    D3D11InputLayout, D3D11RasterizerDesc, D3D11RasterizerState,
+    D3D11_IA_VERTEX_INPUT_RESOURCE_SLOT_COUNT, D3D11_SIMULTANEOUS_RENDER_TARGET_COUNT,
+};
+use super::{texture_copy, D3D11Device, D3D11Shader};
+use crate::common::DxvkError;
+
+/// Primitive topology, `D3D11_PRIMITIVE_TOPOLOGY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11PrimitiveTopology {
+    Undefined,
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+}
+
+impl D3D11PrimitiveTopology {
+    fn topology(&self) -> Option<PrimitiveTopology> {
+        match self {
+            D3D11PrimitiveTopology::Undefined => None,
+            D3D11PrimitiveTopology::PointList => Some(PrimitiveTopology::PointList),
+            D3D11PrimitiveTopology::LineList => Some(PrimitiveTopology::LineList),
+            D3D11PrimitiveTopology::LineStrip => Some(PrimitiveTopology::LineStrip),
+            D3D11PrimitiveTopology::TriangleList => Some(PrimitiveTopology::TriangleList),
+            D3D11PrimitiveTopology::TriangleStrip => Some(PrimitiveTopology::TriangleStrip),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct VertexBufferBinding {
+    buffer: Arc<D3D11Buffer>,
+    stride: u32,
+    offset: u32,
+}
+
+#[derive(Clone)]
+struct IndexBufferBinding {
+    buffer: Arc<D3D11Buffer>,
+    index_type: IndexType,
+    offset: u32,
+}
+
+/// Bound state of the context
+#[derive(Clone)]
+struct State {
+    input_layout: Option<Arc<D3D11InputLayout>>,
+    topology: D3D11PrimitiveTopology,
+    vertex_buffers:
+        [Option<VertexBufferBinding>; D3D11_IA_VERTEX_INPUT_RESOURCE_SLOT_COUNT as usize],
+    index_buffer: Option<IndexBufferBinding>,
+    vertex_shader: Option<Arc<D3D11Shader>>,
+    pixel_shader: Option<Arc<D3D11Shader>>,
+    rasterizer: Option<Arc<D3D11RasterizerState>>,
+    depth_stencil: Option<Arc<D3D11DepthStencilState>>,
+    blend: Option<Arc<D3D11BlendState>>,
+    render_targets: Vec<Arc<D3D11RenderTargetView>>,
+    depth_stencil_view: Option<Arc<D3D11DepthStencilView>>,
+    viewport: Option<Viewport>,
+    scissor: Option<Rect2D>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            input_layout: None,
+            topology: D3D11PrimitiveTopology::Undefined,
+            vertex_buffers: Default::default(),
+            index_buffer: None,
+            vertex_shader: None,
+            pixel_shader: None,
+            rasterizer: None,
+            depth_stencil: None,
+            blend: None,
+            render_targets: Vec::new(),
+            depth_stencil_view: None,
+            viewport: None,
+            scissor: None,
+        }
+    }
+}
+
+/// D3D11 context for command recording
+///
+/// Commands are recorded into one GAL command buffer and submitted by
+/// [`flush`](Self::flush), which waits for them; everything the recorded
+/// commands use is kept alive until then.
+pub struct D3D11Context {
+    /// Associated device
+    device: Arc<D3D11Device>,
+    state: State,
+    /// Pool `cmd` is allocated from
+    _pool: Box<dyn CommandPool>,
+    cmd: Box<dyn CommandBuffer>,
+    fence: Box<dyn Fence>,
+    recording: bool,
+    in_render_pass: bool,
+    pipelines: BTreeMap<PipelineKey, Box<dyn Pipeline>>,
+    /// Staging buffers of recorded updates
+    staging: Vec<Box<dyn Buffer>>,
+    /// State the recorded draws were made with
+    retained_states: Vec<State>,
+    /// Resources of recorded copies and clears
+    retained_buffers: Vec<Arc<D3D11Buffer>>,
+    retained_textures: Vec<Arc<D3D11Texture2D>>,
+    /// Whether the state changed since it was last retained
+    state_dirty: bool,
+}
+
+impl D3D11Context {
+    /// Create immediate context
+    pub fn create_immediate(device: Arc<D3D11Device>) -> Result<Self, DxvkError> {
+        let gal = device.gal_device();
+        let pool = gal.create_command_pool(QueueType::Graphics)?;
+        let cmd = pool.allocate()?;
+        let fence = gal.create_fence(false)?;
+
+        Ok(Self {
+            device,
+            state: State::default(),
+            _pool: pool,
+            cmd,
+            fence,
+            recording: false,
+            in_render_pass: false,
+            pipelines: BTreeMap::new(),
+            staging: Vec::new(),
+            retained_states: Vec::new(),
+            retained_buffers: Vec::new(),
+            retained_textures: Vec::new(),
+            state_dirty: true,
+        })
+    }
+
+    /// Get the device the context belongs to
+    pub fn device(&self) -> &Arc<D3D11Device> {
+        &self.device
+    }
+
+    /// Set the input layout
+    pub fn ia_set_input_layout(&mut self, layout: Option<&Arc<D3D11InputLayout>>) {
+        self.state.input_layout = layout.cloned();
+        self.state_dirty = true;
+    }
+
+    /// Set the primitive topology
+    pub fn ia_set_primitive_topology(&mut self, topology: D3D11PrimitiveTopology) {
+        self.state.topology = topology;
+    }
+
+    /// Bind vertex buffers to the slots from `start_slot`
+    pub fn ia_set_vertex_buffers(
+        &mut self,
+        start_slot: u32,
+        buffers: &[Option<&Arc<D3D11Buffer>>],
+        strides: &[u32],
+        offsets: &[u32],
+    ) -> Result<(), DxvkError> {
+        if buffers.len() != strides.len()
+            || buffers.len() != offsets.len()
+            || start_slot as usize + buffers.len() > self.state.vertex_buffers.len()
+        {
+            return Err(DxvkError::InvalidParameter);
+        }
+
+        for (i, buffer) in buffers.iter().enumerate() {
+            self.state.vertex_buffers[start_slot as usize + i] =
+                buffer.map(|buffer| VertexBufferBinding {
+                    buffer: buffer.clone(),
+                    stride: strides[i],
+                    offset: offsets[i],
+                });
+        }
+        self.state_dirty = true;
+        Ok(())
+    }
+
+    /// Bind the index buffer, with indices in `format`
+    pub fn ia_set_index_buffer(
+        &mut self,
+        buffer: Option<&Arc<D3D11Buffer>>,
+        format: DxgiFormat,
+        offset: u32,
+    ) -> Result<(), DxvkError> {
+        self.state.index_buffer = match buffer {
+            Some(buffer) => Some(IndexBufferBinding {
+                buffer: buffer.clone(),
+                index_type: format.index_type().ok_or(DxvkError::InvalidParameter)?,
+                offset,
+            }),
+            None => None,
+        };
+        self.state_dirty = true;
+        Ok(())
+    }
+
+    /// Set the vertex shader
+    pub fn vs_set_shader(&mut self, shader: Option<&Arc<D3D11Shader>>) {
+        self.state.vertex_shader = shader.cloned();
+        self.state_dirty = true;
+    }
+
+    /// Set the pixel shader
+    pub fn ps_set_shader(&mut self, shader: Option<&Arc<D3D11Shader>>) {
+        self.state.pixel_shader = shader.cloned();
+        self.state_dirty = true;
+    }
+
+    /// Set the rasterizer state, the default one with `None`
+    pub fn rs_set_state(&mut self, state: Option<&Arc<D3D11RasterizerState>>) {
+        self.state.rasterizer = state.cloned();
+        self.state_dirty = true;
+    }
+
+    /// Set the viewport
+    pub fn rs_set_viewport(&mut self, viewport: Viewport) {
+        self.state.viewport = Some(viewport);
+    }
+
+    /// Set the scissor rectangle, used if the rasterizer state enables it
+    pub fn rs_set_scissor_rect(&mut self, rect: Rect2D) {
+        self.state.scissor = Some(rect);
+    }
+
+    /// Set the depth/stencil state, the default one with `None`
+    pub fn om_set_depth_stencil_state(&mut self, state: Option<&Arc<D3D11DepthStencilState>>) {
+        self.state.depth_stencil = state.cloned();
+        self.state_dirty = true;
+    }
+
+    /// Set the blend state, the default one with `None`
+    pub fn om_set_blend_state(&mut self, state: Option<&Arc<D3D11BlendState>>) {
+        self.state.blend = state.cloned();
+        self.state_dirty = true;
+    }
+
+    /// Bind render targets and the depth/stencil buffer
+    pub fn om_set_render_targets(
+        &mut self,
+        render_targets: &[&Arc<D3D11RenderTargetView>],
+        depth_stencil: Option<&Arc<D3D11DepthStencilView>>,
+    ) -> Result<(), DxvkError> {
+        if render_targets.len() > D3D11_SIMULTANEOUS_RENDER_TARGET_COUNT {
+            return Err(DxvkError::InvalidParameter);
+        }
+
+        // The render pass is on the old targets
+        self.end_render_pass();
+        self.state.render_targets = render_targets.iter().map(|&rtv| rtv.clone()).collect();
+        self.state.depth_stencil_view = depth_stencil.cloned();
+        self.state_dirty = true;
+        Ok(())
+    }
+
+    /// Clear a render target to `color`
+    pub fn clear_render_target_view(
+        &mut self,
+        rtv: &Arc<D3D11RenderTargetView>,
+        color: [f32; 4],
+    ) -> Result<(), DxvkError> {
+        log::trace!("D3D11: ClearRenderTargetView");
+        self.end_render_pass();
+        let image = rtv.texture.gal_image();
+        let range = full_range(ImageAspect::COLOR);
+        let cmd = self.begin_recording()?;
+        cmd.clear_color_image(
+            image,
+            ClearValue {
+                color: ClearColor::new(color[0], color[1], color[2], color[3]),
+            },
+            &[range],
+        );
+        self.retain_texture(&rtv.texture);
+        Ok(())
+    }
+
+    /// Clear a depth/stencil buffer
+    pub fn clear_depth_stencil_view(
+        &mut self,
+        dsv: &Arc<D3D11DepthStencilView>,
+        depth: f32,
+        stencil: u8,
+    ) -> Result<(), DxvkError> {
+        log::trace!("D3D11: ClearDepthStencilView");
+        self.end_render_pass();
+        let image = dsv.texture.gal_image();
+        let mut aspect = ImageAspect::DEPTH;
+        if image.format().is_stencil() {
+            aspect |= ImageAspect::STENCIL;
+        }
+        let cmd = self.begin_recording()?;
+        cmd.clear_depth_stencil_image(
+            image,
+            ClearValue {
+                depth_stencil: ClearDepthStencil::new(depth, u32::from(stencil)),
+            },
+            &[full_range(aspect)],
+        );
+        self.retain_texture(&dsv.texture);
+        Ok(())
+    }
+
+    /// Replace the contents of `buffer` from `offset` with `data`
+    pub fn update_buffer(
+        &mut self,
+        buffer: &Arc<D3D11Buffer>,
+        offset: u32,
+        data: &[u8],
+    ) -> Result<(), DxvkError> {
+        let desc = buffer.desc();
+        if desc.usage != D3D11Usage::Default
+            || offset as usize + data.len() > desc.byte_width as usize
+        {
+            return Err(DxvkError::InvalidParameter);
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let staging = self.stage(data)?;
+        self.end_render_pass();
+        let cmd = self.begin_recording()?;
+        cmd.copy_buffer(
+            staging.as_ref(),
+            buffer.gal_buffer(),
+            &[BufferCopy {
+                src_offset: 0,
+                dst_offset: u64::from(offset),
+                size: data.len() as u64,
+            }],
+        );
+        self.staging.push(staging);
+        self.retain_buffer(buffer);
+        Ok(())
+    }
+
+    /// Replace the first mip level and layer of `texture` with the tightly
+    /// packed texels in `data`
+    pub fn update_texture(
+        &mut self,
+        texture: &Arc<D3D11Texture2D>,
+        data: &[u8],
+    ) -> Result<(), DxvkError> {
+        let desc = texture.desc();
+        let image = texture.gal_image();
+        let row = image
+            .format()
+            .bytes_per_pixel()
+            .ok_or(DxvkError::NotSupported)?
+            * desc.width;
+        if desc.usage != D3D11Usage::Default || data.len() != (row * desc.height) as usize {
+            return Err(DxvkError::InvalidParameter);
+        }
+
+        let staging = self.stage(data)?;
+        self.end_render_pass();
+        let cmd = self.begin_recording()?;
+        cmd.copy_buffer_to_image(
+            staging.as_ref(),
+            image,
+            &[texture_copy(desc.width, desc.height)],
+        );
+        self.staging.push(staging);
+        self.retain_texture(texture);
+        Ok(())
+    }
+
+    /// Map a dynamic or staging buffer for the CPU
+    ///
+    /// Waits for the recorded commands first, so none of them sees what the
+    /// CPU writes.
+    pub fn map(&mut self, buffer: &Arc<D3D11Buffer>) -> Result<*mut u8, DxvkError> {
+        if !matches!(
+            buffer.desc().usage,
+            D3D11Usage::Dynamic | D3D11Usage::Staging
+        ) {
+            return Err(DxvkError::InvalidParameter);
+        }
+        self.flush()?;
+
+        let gal_buffer = buffer.gal_buffer();
+        gal_buffer.invalidate(0, gal_buffer.size())?;
+        Ok(gal_buffer.map()?)
+    }
+
+    /// Unmap a buffer mapped by [`map`](Self::map)
+    pub fn unmap(&mut self, buffer: &Arc<D3D11Buffer>) -> Result<(), DxvkError> {
+        let gal_buffer = buffer.gal_buffer();
+        gal_buffer.flush(0, gal_buffer.size())?;
+        gal_buffer.unmap();
+        Ok(())
+    }
+
+    /// Draw primitives
+    pub fn draw(&mut self, vertex_count: u32, start_vertex: u32) -> Result<(), DxvkError> {
+        self.draw_instanced(vertex_count, 1, start_vertex, 0)
+    }
+
+    /// Draw instanced primitives
+    pub fn draw_instanced(
+        &mut self,
+        vertex_count_per_instance: u32,
+        instance_count: u32,
+        start_vertex: u32,
+        start_instance: u32,
+    ) -> Result<(), DxvkError> {
+        log::trace!("D3D11: Draw");
+        let cmd = self.prepare_draw(false)?;
+        cmd.draw(DrawCommand {
+            vertex_count: vertex_count_per_instance,
+            instance_count,
+            first_vertex: start_vertex,
+            first_instance: start_instance,
+        });
+        Ok(())
+    }
+
+    /// Draw indexed primitives
+    pub fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        start_index: u32,
+        base_vertex: i32,
+    ) -> Result<(), DxvkError> {
+        self.draw_indexed_instanced(index_count, 1, start_index, base_vertex, 0)
+    }
+
+    /// Draw indexed instanced primitives
+    pub fn draw_indexed_instanced(
+        &mut self,
+        index_count_per_instance: u32,
+        instance_count: u32,
+        start_index: u32,
+        base_vertex: i32,
+        start_instance: u32,
+    ) -> Result<(), DxvkError> {
+        log::trace!("D3D11: DrawIndexed");
+        let cmd = self.prepare_draw(true)?;
+        cmd.draw_indexed(DrawIndexedCommand {
+            index_count: index_count_per_instance,
+            instance_count,
+            first_index: start_index,
+            vertex_offset: base_vertex,
+            first_instance: start_instance,
+        });
+        Ok(())
+    }
+
+    /// Reset all state to the defaults
+    pub fn clear_state(&mut self) {
+        self.end_render_pass();
+        self.state = State::default();
+        self.state_dirty = true;
+    }
+
+    /// Submit the recorded commands and wait for them
+    pub fn flush(&mut self) -> Result<(), DxvkError> {
+        if !self.recording {
+            return Ok(());
+        }
+        self.end_render_pass();
+        self.cmd.end()?;
+        self.recording = false;
+
+        let gal = self.device.gal_device();
+        gal.graphics_queue().submit(
+            &[SubmitInfo::new(&[self.cmd.as_ref()])],
+            Some(self.fence.as_ref()),
+        )?;
+        self.fence.wait(u64::MAX)?;
+        self.fence.reset()?;
+        self.cmd.reset()?;
+
+        self.staging.clear();
+        self.retained_states.clear();
+        self.retained_buffers.clear();
+        self.retained_textures.clear();
+        self.state_dirty = true;
+        Ok(())
+    }
+
+    /// Record a copy of `texture` to `dst`, scaled to its size
+    pub(crate) fn blit_to(
+        &mut self,
+        texture: &Arc<D3D11Texture2D>,
+        dst: &dyn Image,
+    ) -> Result<(), DxvkError> {
+        self.end_render_pass();
+        let src = texture.gal_image();
+        let (src_extent, dst_extent) = (src.extent_2d(), dst.extent_2d());
+        let layers = ImageSubresourceLayers {
+            aspect_mask: ImageAspect::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let cmd = self.begin_recording()?;
+        cmd.blit_image(
+            src,
+            dst,
+            &[ImageBlit {
+                src_subresource: layers,
+                src_offsets: [Offset3D::new(0, 0, 0), corner(src_extent)],
+                dst_subresource: layers,
+                dst_offsets: [Offset3D::new(0, 0, 0), corner(dst_extent)],
+            }],
+            Filter::Linear,
+        );
+        self.retain_texture(texture);
+        Ok(())
+    }
+
+    fn begin_recording(&mut self) -> Result<&mut dyn CommandBuffer, DxvkError> {
+        if !self.recording {
+            self.cmd.begin()?;
+            self.recording = true;
+        }
+        Ok(self.cmd.as_mut())
+    }
+
+    fn end_render_pass(&mut self) {
+        if self.in_render_pass {
+            self.cmd.end_render_pass();
+            self.in_render_pass = false;
+        }
+    }
+
+    fn stage(&self, data: &[u8]) -> Result<Box<dyn Buffer>, DxvkError> {
+        let staging = self
+            .device
+            .gal_device()
+            .create_buffer(&BufferDescriptor::staging(data.len() as u64))?;
+        staging.write(0, data)?;
+        Ok(staging)
+    }
+
+    fn retain_buffer(&mut self, buffer: &Arc<D3D11Buffer>) {
+        self.retained_buffers.push(buffer.clone());
+    }
+
+    fn retain_texture(&mut self, texture: &Arc<D3D11Texture2D>) {
+        self.retained_textures.push(texture.clone());
+    }
+
+    /// Size of the bound render targets
+    fn target_extent(&self) -> Option<Extent2D> {
+        let texture = match self.state.render_targets.first() {
+            Some(rtv) => &rtv.texture,
+            None => &self.state.depth_stencil_view.as_ref()?.texture,
+        };
+        Some(texture.gal_image().extent_2d())
+    }
+
+    /// Open the render pass, bind the pipeline and vertex input for a draw
+    fn prepare_draw(&mut self, indexed: bool) -> Result<&mut dyn CommandBuffer, DxvkError> {
+        let Some(extent) = self.target_extent() else {
+            log::warn!("D3D11: draw without render targets");
+            return Err(DxvkError::InvalidParameter);
+        };
+        let Some(vertex_shader) = self.state.vertex_shader.clone() else {
+            log::warn!("D3D11: draw without vertex shader");
+            return Err(DxvkError::InvalidParameter);
+        };
+        let topology = self
+            .state
+            .topology
+            .topology()
+            .ok_or(DxvkError::InvalidParameter)?;
+        if indexed && self.state.index_buffer.is_none() {
+            return Err(DxvkError::InvalidParameter);
+        }
+
+        let desc = self.pipeline_descriptor(&vertex_shader, topology);
+        let pixel_code = self
+            .state
+            .pixel_shader
+            .as_ref()
+            .map_or(&[][..], |shader| shader.bytecode());
+        let key = PipelineKey::graphics(&desc, vertex_shader.bytecode(), pixel_code);
+        if !self.pipelines.contains_key(&key) {
+            let pipeline = self
+                .device
+                .gal_device()
+                .create_graphics_pipeline(&desc)
+                .map_err(|err| DxvkError::ResourceCreationFailed(format!("pipeline: {}", err)))?;
+            self.pipelines.insert(key, pipeline);
+        }
+
+        if self.state_dirty {
+            self.retained_states.push(self.state.clone());
+            self.state_dirty = false;
+        }
+        self.begin_recording()?;
+        if !self.in_render_pass {
+            self.begin_render_pass(extent)?;
+        }
+
+        let state = &self.state;
+        let cmd = self.cmd.as_mut();
+        cmd.bind_pipeline(self.pipelines[&key].as_ref());
+        cmd.set_viewport(state.viewport.unwrap_or(Viewport::new(
+            0.0,
+            0.0,
+            extent.width as f32,
+            extent.height as f32,
+        )));
+        let scissor_enable = state
+            .rasterizer
+            .as_ref()
+            .is_some_and(|rasterizer| rasterizer.desc.scissor_enable);
+        cmd.set_scissor(match state.scissor {
+            Some(scissor) if scissor_enable => scissor,
+            _ => Rect2D::new(0, 0, extent.width, extent.height),
+        });
+
+        for binding in desc.vertex_bindings.iter() {
+            if let Some(vertex_buffer) = &state.vertex_buffers[binding.binding as usize] {
+                cmd.bind_vertex_buffers(
+                    binding.binding,
+                    &[vertex_buffer.buffer.gal_buffer()],
+                    &[u64::from(vertex_buffer.offset)],
+                );
+            }
+        }
+        if let (true, Some(index_buffer)) = (indexed, &state.index_buffer) {
+            cmd.bind_index_buffer(
+                index_buffer.buffer.gal_buffer(),
+                u64::from(index_buffer.offset),
+                index_buffer.index_type,
+            );
+        }
+        Ok(cmd)
+    }
+
+    fn pipeline_descriptor(
+        &self,
+        vertex_shader: &D3D11Shader,
+        topology: PrimitiveTopology,
+    ) -> GraphicsPipelineDescriptor {
+        let state = &self.state;
+        let mut desc = GraphicsPipelineDescriptor {
+            vertex_shader: vertex_shader.module().map_or(0, |module| module.handle()),
+            fragment_shader: state
+                .pixel_shader
+                .as_ref()
+                .and_then(|shader| shader.module())
+                .map_or(0, |module| module.handle()),
+            topology,
+            color_formats: state
+                .render_targets
+                .iter()
+                .map(|rtv| rtv.texture.gal_image().format())
+                .collect(),
+            depth_format: state
+                .depth_stencil_view
+                .as_ref()
+                .map(|dsv| dsv.texture.gal_image().format()),
+            ..Default::default()
+        };
+
+        if let Some(layout) = &state.input_layout {
+            desc.vertex_attributes = layout.attributes().to_vec();
+            desc.vertex_bindings = layout
+                .slots()
+                .iter()
+                .map(|&(slot, input_rate)| VertexBinding {
+                    binding: slot,
+                    stride: state.vertex_buffers[slot as usize]
+                        .as_ref()
+                        .map_or(0, |binding| binding.stride),
+                    input_rate,
+                })
+                .collect();
+        }
+
+        state
+            .rasterizer
+            .as_ref()
+            .map_or(D3D11RasterizerDesc::default(), |state| state.desc)
+            .apply(&mut desc);
+        state
+            .depth_stencil
+            .as_ref()
+            .map_or(D3D11DepthStencilDesc::default(), |state| state.desc)
+            .apply(&mut desc);
+        // Without a depth buffer there is nothing to test against
+        if desc.depth_format.is_none() {
+            desc.depth_test = false;
+            desc.depth_write = false;
+        }
+        state
+            .blend
+            .as_ref()
+            .map_or(D3D11BlendDesc::default(), |state| state.desc)
+            .apply(&mut desc);
+        desc
+    }
+
+    fn begin_render_pass(&mut self, extent: Extent2D) -> Result<(), DxvkError> {
+        let color_attachments = self
+            .state
+            .render_targets
+            .iter()
+            .map(|rtv| ColorAttachment {
+                image: rtv.texture.gal_image(),
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Store,
+                clear_value: ClearValue {
+                    color: ClearColor::BLACK,
+                },
+            })
+            .collect();
+        let depth_stencil_attachment =
+            self.state
+                .depth_stencil_view
+                .as_ref()
+                .map(|dsv| DepthStencilAttachment {
+                    image: dsv.texture.gal_image(),
+                    depth_load_op: LoadOp::Load,
+                    depth_store_op: StoreOp::Store,
+                    stencil_load_op: LoadOp::Load,
+                    stencil_store_op: StoreOp::Store,
+                    clear_value: ClearValue {
+                        depth_stencil: ClearDepthStencil::new(1.0, 0),
+                    },
+                });
+
+        self.cmd.begin_render_pass(&RenderPassDescriptor {
+            color_attachments,
+            depth_stencil_attachment,
+            render_area: Rect2D::new(0, 0, extent.width, extent.height),
+        })?;
+        self.in_render_pass = true;
+        Ok(())
+    }
+}
+
+impl Drop for D3D11Context {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            log::warn!("D3D11: failed to flush context: {}", err);
+        }
+    }
+}
+
+/// Whole first mip level and layer
+fn full_range(aspect_mask: ImageAspect) -> ImageSubresourceRange {
+    ImageSubresourceRange {
+        aspect_mask,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// Far corner of an image of `extent`, for blits
+fn corner(extent: Extent2D) -> Offset3D {
+    Offset3D::new(extent.width as i32, extent.height as i32, 1)
+}
@@ -0,0 +1,140 @@
+//! DXGI formats
+
+use gal::command::IndexType;
+use gal::device::VertexFormat;
+use gal::ImageFormat;
+
+/// DXGI format, with the values of `DXGI_FORMAT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DxgiFormat {
+    Unknown = 0,
+    R32G32B32A32Float = 2,
+    R32G32B32A32Uint = 3,
+    R32G32B32A32Sint = 4,
+    R32G32B32Float = 6,
+    R32G32B32Uint = 7,
+    R32G32B32Sint = 8,
+    R16G16B16A16Float = 10,
+    R16G16B16A16Unorm = 11,
+    R32G32Float = 16,
+    R32G32Uint = 17,
+    R32G32Sint = 18,
+    R8G8B8A8Unorm = 28,
+    R8G8B8A8UnormSrgb = 29,
+    R8G8B8A8Uint = 30,
+    R8G8B8A8Snorm = 31,
+    R8G8B8A8Sint = 32,
+    R16G16Float = 34,
+    D32Float = 40,
+    R32Float = 41,
+    R32Uint = 42,
+    R32Sint = 43,
+    D24UnormS8Uint = 45,
+    R8G8Unorm = 49,
+    R16Float = 54,
+    D16Unorm = 55,
+    R16Uint = 57,
+    R8Unorm = 61,
+    Bc1Unorm = 71,
+    Bc1UnormSrgb = 72,
+    Bc2Unorm = 74,
+    Bc3Unorm = 77,
+    Bc3UnormSrgb = 78,
+    B8G8R8A8Unorm = 87,
+    B8G8R8A8UnormSrgb = 91,
+    Bc7Unorm = 98,
+    Bc7UnormSrgb = 99,
+}
+
+impl DxgiFormat {
+    /// GAL format of textures in this format
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        Some(match self {
+            DxgiFormat::R32G32B32A32Float => ImageFormat::Rgba32Float,
+            DxgiFormat::R32G32B32A32Uint => ImageFormat::Rgba32Uint,
+            DxgiFormat::R32G32B32A32Sint => ImageFormat::Rgba32Sint,
+            DxgiFormat::R16G16B16A16Float => ImageFormat::Rgba16Float,
+            DxgiFormat::R16G16B16A16Unorm => ImageFormat::Rgba16Unorm,
+            DxgiFormat::R32G32Float => ImageFormat::Rg32Float,
+            DxgiFormat::R32G32Uint => ImageFormat::Rg32Uint,
+            DxgiFormat::R32G32Sint => ImageFormat::Rg32Sint,
+            DxgiFormat::R8G8B8A8Unorm => ImageFormat::Rgba8Unorm,
+            DxgiFormat::R8G8B8A8UnormSrgb => ImageFormat::Rgba8UnormSrgb,
+            DxgiFormat::R8G8B8A8Uint => ImageFormat::Rgba8Uint,
+            DxgiFormat::R8G8B8A8Snorm => ImageFormat::Rgba8Snorm,
+            DxgiFormat::R8G8B8A8Sint => ImageFormat::Rgba8Sint,
+            DxgiFormat::R16G16Float => ImageFormat::Rg16Float,
+            DxgiFormat::D32Float => ImageFormat::Depth32Float,
+            DxgiFormat::R32Float => ImageFormat::R32Float,
+            DxgiFormat::R32Uint => ImageFormat::R32Uint,
+            DxgiFormat::R32Sint => ImageFormat::R32Sint,
+            DxgiFormat::D24UnormS8Uint => ImageFormat::Depth24PlusStencil8,
+            DxgiFormat::R8G8Unorm => ImageFormat::Rg8Unorm,
+            DxgiFormat::R16Float => ImageFormat::R16Float,
+            DxgiFormat::D16Unorm => ImageFormat::Depth16Unorm,
+            DxgiFormat::R16Uint => ImageFormat::R16Uint,
+            DxgiFormat::R8Unorm => ImageFormat::R8Unorm,
+            DxgiFormat::Bc1Unorm => ImageFormat::Bc1RgbaUnorm,
+            DxgiFormat::Bc1UnormSrgb => ImageFormat::Bc1RgbaUnormSrgb,
+            DxgiFormat::Bc2Unorm => ImageFormat::Bc2RgbaUnorm,
+            DxgiFormat::Bc3Unorm => ImageFormat::Bc3RgbaUnorm,
+            DxgiFormat::Bc3UnormSrgb => ImageFormat::Bc3RgbaUnormSrgb,
+            DxgiFormat::B8G8R8A8Unorm => ImageFormat::Bgra8Unorm,
+            DxgiFormat::B8G8R8A8UnormSrgb => ImageFormat::Bgra8UnormSrgb,
+            DxgiFormat::Bc7Unorm => ImageFormat::Bc7RgbaUnorm,
+            DxgiFormat::Bc7UnormSrgb => ImageFormat::Bc7RgbaUnormSrgb,
+            // No three-component image formats
+            DxgiFormat::Unknown
+            | DxgiFormat::R32G32B32Float
+            | DxgiFormat::R32G32B32Uint
+            | DxgiFormat::R32G32B32Sint => return None,
+        })
+    }
+
+    /// GAL format of vertex attributes in this format
+    pub fn vertex_format(&self) -> Option<VertexFormat> {
+        Some(match self {
+            DxgiFormat::R32Float => VertexFormat::Float,
+            DxgiFormat::R32G32Float => VertexFormat::Float2,
+            DxgiFormat::R32G32B32Float => VertexFormat::Float3,
+            DxgiFormat::R32G32B32A32Float => VertexFormat::Float4,
+            DxgiFormat::R32Sint => VertexFormat::Int,
+            DxgiFormat::R32G32Sint => VertexFormat::Int2,
+            DxgiFormat::R32G32B32Sint => VertexFormat::Int3,
+            DxgiFormat::R32G32B32A32Sint => VertexFormat::Int4,
+            DxgiFormat::R32Uint => VertexFormat::UInt,
+            DxgiFormat::R32G32Uint => VertexFormat::UInt2,
+            DxgiFormat::R32G32B32Uint => VertexFormat::UInt3,
+            DxgiFormat::R32G32B32A32Uint => VertexFormat::UInt4,
+            DxgiFormat::R8G8B8A8Sint => VertexFormat::Byte4,
+            DxgiFormat::R8G8B8A8Snorm => VertexFormat::Byte4Norm,
+            DxgiFormat::R8G8B8A8Uint => VertexFormat::UByte4,
+            DxgiFormat::R8G8B8A8Unorm => VertexFormat::UByte4Norm,
+            _ => return None,
+        })
+    }
+
+    /// Index type of index buffers in this format
+    pub fn index_type(&self) -> Option<IndexType> {
+        match self {
+            DxgiFormat::R16Uint => Some(IndexType::U16),
+            DxgiFormat::R32Uint => Some(IndexType::U32),
+            _ => None,
+        }
+    }
+
+    /// Size of a vertex attribute in this format, in bytes
+    pub fn vertex_size(&self) -> Option<u32> {
+        Some(match self.vertex_format()? {
+            VertexFormat::Float | VertexFormat::Int | VertexFormat::UInt => 4,
+            VertexFormat::Float2 | VertexFormat::Int2 | VertexFormat::UInt2 => 8,
+            VertexFormat::Float3 | VertexFormat::Int3 | VertexFormat::UInt3 => 12,
+            VertexFormat::Float4 | VertexFormat::Int4 | VertexFormat::UInt4 => 16,
+            VertexFormat::Byte4
+            | VertexFormat::Byte4Norm
+            | VertexFormat::UByte4
+            | VertexFormat::UByte4Norm => 4,
+        })
+    }
+}
@@ -1,19 +1,63 @@
 //! D3D11 to Vulkan translation
+//!
+//! The D3D11 object model on top of GAL: [`D3D11Device`] creates resources,
+//! views, input layouts, shaders and state objects, [`D3D11Context`] records
+//! the immediate context's state changes and draws into GAL command buffers,
+//! and [`DxgiSwapChain`] presents a back buffer through a GAL swapchain.
+//!
+//! D3D11 has no render passes: the context opens one on the bound render
+//! targets at the first draw and closes it when the targets change or a
+//! clear, copy or flush needs them outside of it. Graphics pipelines are
+//! built from the bound state at draw time and cached per
+//! [`PipelineKey`](gal::PipelineKey).
 
-use crate::common::{DxvkDevice, DxvkError};
+mod context;
+mod format;
+mod resource;
+mod state;
+mod swapchain;
+
+pub use context::{D3D11Context, D3D11PrimitiveTopology};
+pub use format::DxgiFormat;
+pub use resource::{
+    D3D11BindFlags, D3D11Buffer, D3D11BufferDesc, D3D11DepthStencilView, D3D11RenderTargetView,
+    D3D11ShaderResourceView, D3D11Texture2D, D3D11Texture2DDesc, D3D11Usage,
+};
+pub use state::{
+    D3D11Blend, D3D11BlendDesc, D3D11BlendOp, D3D11BlendState, D3D11ComparisonFunc, D3D11CullMode,
+    D3D11DepthStencilDesc, D3D11DepthStencilState, D3D11FillMode, D3D11InputClassification,
+    D3D11InputElementDesc, D3D11InputLayout, D3D11RasterizerDesc, D3D11RasterizerState,
+    D3D11RenderTargetBlendDesc, D3D11_APPEND_ALIGNED_ELEMENT,
+    D3D11_IA_VERTEX_INPUT_RESOURCE_SLOT_COUNT, D3D11_SIMULTANEOUS_RENDER_TARGET_COUNT,
+};
+pub use swapchain::{DxgiSwapChain, DxgiSwapChainDesc};
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+use gal::command::{BufferCopy, BufferImageCopy, ImageAspect, ImageSubresourceLayers};
+use gal::{
+    Buffer, BufferDescriptor, CommandBuffer, Device, Extent3D, Offset3D, QueueType, ShaderStage,
+    SubmitInfo,
+};
+
+use crate::common::{DxvkDevice, DxvkError};
+
 /// D3D11 device wrapper
 pub struct D3D11Device {
     /// Underlying DXVK device
     device: DxvkDevice,
+    /// GAL device everything is created on
+    gal: Arc<dyn Device>,
 }
 
 impl D3D11Device {
-    /// Create D3D11 device
-    pub fn create(device: DxvkDevice) -> Result<Self, DxvkError> {
-        log::info!("Creating D3D11 device");
-        Ok(Self { device })
+    /// Create D3D11 device on the GAL device `gal`
+    pub fn create(device: DxvkDevice, gal: Arc<dyn Device>) -> Result<Arc<Self>, DxvkError> {
+        log::info!("Creating D3D11 device on {}", gal.info().name);
+        Ok(Arc::new(Self { device, gal }))
     }
 
     /// Get device capabilities
@@ -26,6 +70,247 @@ impl D3D11Device {
             D3DFeatureLevel::Level_11_0
         }
     }
+
+    /// Get the GAL device
+    pub fn gal_device(&self) -> &Arc<dyn Device> {
+        &self.gal
+    }
+
+    /// Create a buffer, filled with `initial_data` if given
+    pub fn create_buffer(
+        &self,
+        desc: &D3D11BufferDesc,
+        initial_data: Option<&[u8]>,
+    ) -> Result<Arc<D3D11Buffer>, DxvkError> {
+        if desc.byte_width == 0
+            || (desc.usage == D3D11Usage::Immutable && initial_data.is_none())
+            || initial_data.is_some_and(|data| data.len() != desc.byte_width as usize)
+        {
+            return Err(DxvkError::InvalidParameter);
+        }
+
+        let buffer = self
+            .gal
+            .create_buffer(&desc.gal_descriptor())
+            .map_err(|err| DxvkError::ResourceCreationFailed(format!("buffer: {}", err)))?;
+        if let Some(data) = initial_data {
+            self.upload(data, |cmd, staging| {
+                cmd.copy_buffer(
+                    staging,
+                    buffer.as_ref(),
+                    &[BufferCopy {
+                        src_offset: 0,
+                        dst_offset: 0,
+                        size: data.len() as u64,
+                    }],
+                );
+            })?;
+        }
+
+        log::debug!(
+            "D3D11: CreateBuffer {} bytes, {:?}",
+            desc.byte_width,
+            desc.bind_flags
+        );
+        Ok(Arc::new(D3D11Buffer::new(*desc, buffer)))
+    }
+
+    /// Create a 2D texture, with the tightly packed texels of the first mip
+    /// level and layer in `initial_data` if given
+    pub fn create_texture_2d(
+        &self,
+        desc: &D3D11Texture2DDesc,
+        initial_data: Option<&[u8]>,
+    ) -> Result<Arc<D3D11Texture2D>, DxvkError> {
+        let Some(descriptor) = desc.gal_descriptor() else {
+            log::warn!("D3D11: unsupported texture format {:?}", desc.format);
+            return Err(DxvkError::InvalidParameter);
+        };
+        if let Some(data) = initial_data {
+            let row = descriptor
+                .format
+                .bytes_per_pixel()
+                .ok_or(DxvkError::NotSupported)?
+                * desc.width;
+            if data.len() != (row * desc.height) as usize {
+                return Err(DxvkError::InvalidParameter);
+            }
+        }
+
+        let image = self
+            .gal
+            .create_image(&descriptor)
+            .map_err(|err| DxvkError::ResourceCreationFailed(format!("texture: {}", err)))?;
+        if let Some(data) = initial_data {
+            self.upload(data, |cmd, staging| {
+                cmd.copy_buffer_to_image(
+                    staging,
+                    image.as_ref(),
+                    &[texture_copy(desc.width, desc.height)],
+                );
+            })?;
+        }
+
+        log::debug!(
+            "D3D11: CreateTexture2D {}x{} {:?}",
+            desc.width,
+            desc.height,
+            desc.format
+        );
+        Ok(Arc::new(D3D11Texture2D::new(*desc, image)))
+    }
+
+    /// Create a view of `texture` as a render target
+    pub fn create_render_target_view(
+        &self,
+        texture: &Arc<D3D11Texture2D>,
+    ) -> Result<Arc<D3D11RenderTargetView>, DxvkError> {
+        if !texture
+            .desc()
+            .bind_flags
+            .contains(D3D11BindFlags::RENDER_TARGET)
+        {
+            return Err(DxvkError::InvalidParameter);
+        }
+        Ok(Arc::new(D3D11RenderTargetView {
+            texture: texture.clone(),
+        }))
+    }
+
+    /// Create a view of `texture` as a depth/stencil buffer
+    pub fn create_depth_stencil_view(
+        &self,
+        texture: &Arc<D3D11Texture2D>,
+    ) -> Result<Arc<D3D11DepthStencilView>, DxvkError> {
+        if !texture
+            .desc()
+            .bind_flags
+            .contains(D3D11BindFlags::DEPTH_STENCIL)
+        {
+            return Err(DxvkError::InvalidParameter);
+        }
+        Ok(Arc::new(D3D11DepthStencilView {
+            texture: texture.clone(),
+        }))
+    }
+
+    /// Create a view of `texture` for shaders to read
+    pub fn create_shader_resource_view(
+        &self,
+        texture: &Arc<D3D11Texture2D>,
+    ) -> Result<Arc<D3D11ShaderResourceView>, DxvkError> {
+        if !texture
+            .desc()
+            .bind_flags
+            .contains(D3D11BindFlags::SHADER_RESOURCE)
+        {
+            return Err(DxvkError::InvalidParameter);
+        }
+        Ok(Arc::new(D3D11ShaderResourceView {
+            texture: texture.clone(),
+        }))
+    }
+
+    /// Create a vertex input layout
+    pub fn create_input_layout(
+        &self,
+        elements: &[D3D11InputElementDesc],
+    ) -> Result<Arc<D3D11InputLayout>, DxvkError> {
+        log::debug!("D3D11: CreateInputLayout with {} elements", elements.len());
+        Ok(Arc::new(D3D11InputLayout::new(elements)?))
+    }
+
+    /// Create a vertex shader from DXBC bytecode
+    pub fn create_vertex_shader(&self, bytecode: Vec<u8>) -> Result<Arc<D3D11Shader>, DxvkError> {
+        self.create_shader(D3D11ShaderType::Vertex, bytecode)
+    }
+
+    /// Create a pixel shader from DXBC bytecode
+    pub fn create_pixel_shader(&self, bytecode: Vec<u8>) -> Result<Arc<D3D11Shader>, DxvkError> {
+        self.create_shader(D3D11ShaderType::Pixel, bytecode)
+    }
+
+    fn create_shader(
+        &self,
+        shader_type: D3D11ShaderType,
+        bytecode: Vec<u8>,
+    ) -> Result<Arc<D3D11Shader>, DxvkError> {
+        let stage = shader_type.stage().ok_or(DxvkError::NotSupported)?;
+        let mut shader = D3D11Shader::from_dxbc(shader_type, bytecode)?;
+        let code: Vec<u8> = shader
+            .translate_to_spirv()?
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        let module = self
+            .gal
+            .create_shader(stage, &code)
+            .map_err(|err| DxvkError::ShaderCompilationFailed(format!("{}", err)))?;
+        shader.module = Some(module);
+        Ok(Arc::new(shader))
+    }
+
+    /// Create a rasterizer state
+    pub fn create_rasterizer_state(&self, desc: &D3D11RasterizerDesc) -> Arc<D3D11RasterizerState> {
+        Arc::new(D3D11RasterizerState { desc: *desc })
+    }
+
+    /// Create a depth/stencil state
+    pub fn create_depth_stencil_state(
+        &self,
+        desc: &D3D11DepthStencilDesc,
+    ) -> Arc<D3D11DepthStencilState> {
+        Arc::new(D3D11DepthStencilState { desc: *desc })
+    }
+
+    /// Create a blend state
+    pub fn create_blend_state(&self, desc: &D3D11BlendDesc) -> Arc<D3D11BlendState> {
+        Arc::new(D3D11BlendState { desc: *desc })
+    }
+
+    /// Copy `data` into a staging buffer, record the copy out of it with
+    /// `record` and wait for it
+    fn upload(
+        &self,
+        data: &[u8],
+        record: impl FnOnce(&mut dyn CommandBuffer, &dyn Buffer),
+    ) -> Result<(), DxvkError> {
+        let staging = self
+            .gal
+            .create_buffer(&BufferDescriptor::staging(data.len() as u64))?;
+        staging.write(0, data)?;
+
+        let pool = self.gal.create_command_pool(QueueType::Graphics)?;
+        let mut cmd = pool.allocate()?;
+        cmd.begin()?;
+        record(cmd.as_mut(), staging.as_ref());
+        cmd.end()?;
+
+        let fence = self.gal.create_fence(false)?;
+        self.gal
+            .graphics_queue()
+            .submit(&[SubmitInfo::new(&[cmd.as_ref()])], Some(fence.as_ref()))?;
+        fence.wait(u64::MAX)?;
+        Ok(())
+    }
+}
+
+/// Copy of tightly packed texels to the first mip level and layer of a
+/// `width` x `height` texture
+pub(crate) fn texture_copy(width: u32, height: u32) -> BufferImageCopy {
+    BufferImageCopy {
+        buffer_offset: 0,
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: ImageSubresourceLayers {
+            aspect_mask: ImageAspect::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        image_offset: Offset3D::new(0, 0, 0),
+        image_extent: Extent3D::new(width, height, 1),
+    }
 }
 
 /// D3D feature levels
@@ -53,6 +338,20 @@ pub enum D3D11ShaderType {
     Compute,
 }
 
+impl D3D11ShaderType {
+    /// GAL stage of shaders of this type
+    fn stage(&self) -> Option<ShaderStage> {
+        match self {
+            D3D11ShaderType::Vertex => Some(ShaderStage::Vertex),
+            D3D11ShaderType::Hull => Some(ShaderStage::TessellationControl),
+            D3D11ShaderType::Domain => Some(ShaderStage::TessellationEvaluation),
+            D3D11ShaderType::Geometry => Some(ShaderStage::Geometry),
+            D3D11ShaderType::Pixel => Some(ShaderStage::Fragment),
+            D3D11ShaderType::Compute => Some(ShaderStage::Compute),
+        }
+    }
+}
+
 /// D3D11 shader module
 pub struct D3D11Shader {
     /// Shader type
@@ -61,6 +360,8 @@ pub struct D3D11Shader {
     bytecode: Vec<u8>,
     /// Translated SPIR-V
     spirv: Option<Vec<u32>>,
+    /// GAL shader, once created on a device
+    module: Option<Box<dyn gal::Shader>>,
 }
 
 impl D3D11Shader {
@@ -74,9 +375,25 @@ impl D3D11Shader {
             shader_type,
             bytecode,
             spirv: None,
+            module: None,
         })
     }
 
+    /// Get shader type
+    pub fn shader_type(&self) -> D3D11ShaderType {
+        self.shader_type
+    }
+
+    /// Get the DXBC bytecode
+    pub fn bytecode(&self) -> &[u8] {
+        &self.bytecode
+    }
+
+    /// Get the GAL shader, for shaders created by a [`D3D11Device`]
+    pub fn module(&self) -> Option<&dyn gal::Shader> {
+        self.module.as_deref()
+    }
+
     /// Translate to SPIR-V
     pub fn translate_to_spirv(&mut self) -> Result<&[u32], DxvkError> {
         if self.spirv.is_none() {
@@ -90,34 +407,3 @@ impl D3D11Shader {
         Ok(self.spirv.as_ref().unwrap())
     }
 }
-
-/// D3D11 context for command recording
-pub struct D3D11Context {
-    /// Associated device
-    _device: D3D11Device,
-}
-
-impl D3D11Context {
-    /// Create immediate context
-    pub fn create_immediate(device: D3D11Device) -> Self {
-        Self { _device: device }
-    }
-
-    /// Clear render target
-    pub fn clear_render_target_view(&mut self, _color: [f32; 4]) {
-        // Translate to Vulkan clear command
-        log::trace!("D3D11: ClearRenderTargetView");
-    }
-
-    /// Draw primitives
-    pub fn draw(&mut self, _vertex_count: u32, _start_vertex: u32) {
-        // Translate to Vulkan draw command
-        log::trace!("D3D11: Draw");
-    }
-
-    /// Draw indexed primitives
-    pub fn draw_indexed(&mut self, _index_count: u32, _start_index: u32, _base_vertex: i32) {
-        // Translate to Vulkan draw indexed command
-        log::trace!("D3D11: DrawIndexed");
-    }
-}
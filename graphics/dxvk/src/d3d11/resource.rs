@@ -0,0 +1,218 @@
+//! D3D11 buffers, textures and views
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use bitflags::bitflags;
+
+use gal::{Buffer, BufferUsage, Image, ImageUsage, MemoryType};
+
+use super::format::DxgiFormat;
+
+bitflags! {
+    /// How a resource is bound to the pipeline, `D3D11_BIND_FLAG`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct D3D11BindFlags: u32 {
+        const VERTEX_BUFFER = 0x1;
+        const INDEX_BUFFER = 0x2;
+        const CONSTANT_BUFFER = 0x4;
+        const SHADER_RESOURCE = 0x8;
+        const STREAM_OUTPUT = 0x10;
+        const RENDER_TARGET = 0x20;
+        const DEPTH_STENCIL = 0x40;
+        const UNORDERED_ACCESS = 0x80;
+    }
+}
+
+/// Who reads and writes a resource, `D3D11_USAGE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11Usage {
+    /// Read and written by the GPU
+    Default,
+    /// Read by the GPU, only written at creation
+    Immutable,
+    /// Read by the GPU, written by the CPU through `Map`
+    Dynamic,
+    /// Copied to and from by the GPU, mapped by the CPU
+    Staging,
+}
+
+impl D3D11Usage {
+    fn memory_type(&self) -> MemoryType {
+        match self {
+            D3D11Usage::Default | D3D11Usage::Immutable => MemoryType::DeviceLocal,
+            D3D11Usage::Dynamic => MemoryType::Upload,
+            D3D11Usage::Staging => MemoryType::Readback,
+        }
+    }
+}
+
+/// Buffer description, `D3D11_BUFFER_DESC`
+#[derive(Debug, Clone, Copy)]
+pub struct D3D11BufferDesc {
+    pub byte_width: u32,
+    pub usage: D3D11Usage,
+    pub bind_flags: D3D11BindFlags,
+    /// Size of an element of a structured buffer
+    pub structure_byte_stride: u32,
+}
+
+impl D3D11BufferDesc {
+    pub(crate) fn gal_descriptor(&self) -> gal::BufferDescriptor {
+        let mut usage = BufferUsage::TRANSFER_SRC | BufferUsage::TRANSFER_DST;
+        if self.bind_flags.contains(D3D11BindFlags::VERTEX_BUFFER) {
+            usage |= BufferUsage::VERTEX;
+        }
+        if self.bind_flags.contains(D3D11BindFlags::INDEX_BUFFER) {
+            usage |= BufferUsage::INDEX;
+        }
+        if self.bind_flags.contains(D3D11BindFlags::CONSTANT_BUFFER) {
+            usage |= BufferUsage::UNIFORM;
+        }
+        if self
+            .bind_flags
+            .intersects(D3D11BindFlags::SHADER_RESOURCE | D3D11BindFlags::UNORDERED_ACCESS)
+        {
+            usage |= BufferUsage::STORAGE;
+        }
+
+        gal::BufferDescriptor::new(u64::from(self.byte_width), usage)
+            .memory_type(self.usage.memory_type())
+    }
+}
+
+/// D3D11 buffer
+pub struct D3D11Buffer {
+    desc: D3D11BufferDesc,
+    buffer: Box<dyn Buffer>,
+}
+
+impl D3D11Buffer {
+    pub(crate) fn new(desc: D3D11BufferDesc, buffer: Box<dyn Buffer>) -> Self {
+        Self { desc, buffer }
+    }
+
+    /// Get the description the buffer was created with
+    pub fn desc(&self) -> &D3D11BufferDesc {
+        &self.desc
+    }
+
+    /// Get the GAL buffer
+    pub fn gal_buffer(&self) -> &dyn Buffer {
+        self.buffer.as_ref()
+    }
+}
+
+/// 2D texture description, `D3D11_TEXTURE2D_DESC`
+#[derive(Debug, Clone, Copy)]
+pub struct D3D11Texture2DDesc {
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    pub array_size: u32,
+    pub format: DxgiFormat,
+    pub sample_count: u32,
+    pub usage: D3D11Usage,
+    pub bind_flags: D3D11BindFlags,
+}
+
+impl D3D11Texture2DDesc {
+    /// Texture with one mip level, layer and sample
+    pub fn new(width: u32, height: u32, format: DxgiFormat, bind_flags: D3D11BindFlags) -> Self {
+        Self {
+            width,
+            height,
+            mip_levels: 1,
+            array_size: 1,
+            format,
+            sample_count: 1,
+            usage: D3D11Usage::Default,
+            bind_flags,
+        }
+    }
+
+    pub(crate) fn gal_descriptor(&self) -> Option<gal::ImageDescriptor> {
+        let mut usage = ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST;
+        if self.bind_flags.contains(D3D11BindFlags::SHADER_RESOURCE) {
+            usage |= ImageUsage::SAMPLED;
+        }
+        if self.bind_flags.contains(D3D11BindFlags::RENDER_TARGET) {
+            usage |= ImageUsage::COLOR_ATTACHMENT;
+        }
+        if self.bind_flags.contains(D3D11BindFlags::DEPTH_STENCIL) {
+            usage |= ImageUsage::DEPTH_STENCIL_ATTACHMENT;
+        }
+        if self.bind_flags.contains(D3D11BindFlags::UNORDERED_ACCESS) {
+            usage |= ImageUsage::STORAGE;
+        }
+
+        let mut descriptor = gal::ImageDescriptor::new_2d(
+            self.width,
+            self.height,
+            self.format.image_format()?,
+            usage,
+        )
+        .mip_levels(self.mip_levels.max(1))
+        .array_layers(self.array_size.max(1))
+        .sample_count(self.sample_count.max(1));
+        descriptor.memory_type = self.usage.memory_type();
+        Some(descriptor)
+    }
+}
+
+/// D3D11 2D texture
+pub struct D3D11Texture2D {
+    desc: D3D11Texture2DDesc,
+    image: Box<dyn Image>,
+}
+
+impl D3D11Texture2D {
+    pub(crate) fn new(desc: D3D11Texture2DDesc, image: Box<dyn Image>) -> Self {
+        Self { desc, image }
+    }
+
+    /// Get the description the texture was created with
+    pub fn desc(&self) -> &D3D11Texture2DDesc {
+        &self.desc
+    }
+
+    /// Get the GAL image
+    pub fn gal_image(&self) -> &dyn Image {
+        self.image.as_ref()
+    }
+}
+
+/// View of a texture as a render target
+pub struct D3D11RenderTargetView {
+    pub(crate) texture: Arc<D3D11Texture2D>,
+}
+
+impl D3D11RenderTargetView {
+    /// Get the viewed texture
+    pub fn texture(&self) -> &Arc<D3D11Texture2D> {
+        &self.texture
+    }
+}
+
+/// View of a texture as a depth/stencil buffer
+pub struct D3D11DepthStencilView {
+    pub(crate) texture: Arc<D3D11Texture2D>,
+}
+
+impl D3D11DepthStencilView {
+    /// Get the viewed texture
+    pub fn texture(&self) -> &Arc<D3D11Texture2D> {
+        &self.texture
+    }
+}
+
+/// View of a texture read by shaders
+pub struct D3D11ShaderResourceView {
+    pub(crate) texture: Arc<D3D11Texture2D>,
+}
+
+impl D3D11ShaderResourceView {
+    /// Get the viewed texture
+    pub fn texture(&self) -> &Arc<D3D11Texture2D> {
+        &self.texture
+    }
+}
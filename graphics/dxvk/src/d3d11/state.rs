@@ -0,0 +1,391 @@
+//! D3D11 input layouts and state objects
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use gal::device::{
+    BlendFactor, BlendOp, ColorBlendAttachment, ColorWriteMask, CompareOp, CullMode, FrontFace,
+    GraphicsPipelineDescriptor, PolygonMode, VertexAttribute, VertexInputRate,
+};
+
+use super::format::DxgiFormat;
+use crate::common::DxvkError;
+
+/// Offset of an input element right after the previous one in its slot
+pub const D3D11_APPEND_ALIGNED_ELEMENT: u32 = 0xffff_ffff;
+
+/// Number of vertex buffer slots
+pub const D3D11_IA_VERTEX_INPUT_RESOURCE_SLOT_COUNT: u32 = gal::MAX_VERTEX_INPUT_BINDINGS as u32;
+
+/// Number of simultaneous render targets
+pub const D3D11_SIMULTANEOUS_RENDER_TARGET_COUNT: usize = gal::MAX_COLOR_ATTACHMENTS;
+
+/// Whether an input element advances per vertex or per instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11InputClassification {
+    PerVertexData,
+    PerInstanceData,
+}
+
+/// Vertex input element, `D3D11_INPUT_ELEMENT_DESC`
+#[derive(Debug, Clone)]
+pub struct D3D11InputElementDesc {
+    pub semantic_name: String,
+    pub semantic_index: u32,
+    pub format: DxgiFormat,
+    pub input_slot: u32,
+    pub aligned_byte_offset: u32,
+    pub input_slot_class: D3D11InputClassification,
+    pub instance_data_step_rate: u32,
+}
+
+/// Vertex input layout
+///
+/// Element `i` is read by the vertex shader input at location `i`, the order
+/// the shader declares its inputs in.
+pub struct D3D11InputLayout {
+    elements: Vec<D3D11InputElementDesc>,
+    attributes: Vec<VertexAttribute>,
+    /// Input rate of every slot used
+    slots: Vec<(u32, VertexInputRate)>,
+}
+
+impl D3D11InputLayout {
+    pub(crate) fn new(elements: &[D3D11InputElementDesc]) -> Result<Self, DxvkError> {
+        let mut attributes = Vec::with_capacity(elements.len());
+        let mut slots: Vec<(u32, VertexInputRate)> = Vec::new();
+        // End of the previous element of every slot
+        let mut slot_ends = [0u32; D3D11_IA_VERTEX_INPUT_RESOURCE_SLOT_COUNT as usize];
+
+        for (location, element) in (0..).zip(elements) {
+            if element.input_slot >= D3D11_IA_VERTEX_INPUT_RESOURCE_SLOT_COUNT {
+                return Err(DxvkError::InvalidParameter);
+            }
+            let (Some(format), Some(size)) =
+                (element.format.vertex_format(), element.format.vertex_size())
+            else {
+                log::warn!(
+                    "D3D11: unsupported vertex format {:?} for {}{}",
+                    element.format,
+                    element.semantic_name,
+                    element.semantic_index
+                );
+                return Err(DxvkError::InvalidParameter);
+            };
+
+            let rate = match element.input_slot_class {
+                D3D11InputClassification::PerVertexData => VertexInputRate::Vertex,
+                D3D11InputClassification::PerInstanceData => VertexInputRate::Instance,
+            };
+            match slots.iter().find(|(slot, _)| *slot == element.input_slot) {
+                Some((_, slot_rate)) if *slot_rate != rate => {
+                    return Err(DxvkError::InvalidParameter)
+                }
+                Some(_) => {}
+                None => slots.push((element.input_slot, rate)),
+            }
+
+            let end = &mut slot_ends[element.input_slot as usize];
+            let offset = if element.aligned_byte_offset == D3D11_APPEND_ALIGNED_ELEMENT {
+                *end
+            } else {
+                element.aligned_byte_offset
+            };
+            *end = offset + size;
+
+            attributes.push(VertexAttribute {
+                location,
+                binding: element.input_slot,
+                format,
+                offset,
+            });
+        }
+
+        Ok(Self {
+            elements: elements.to_vec(),
+            attributes,
+            slots,
+        })
+    }
+
+    /// Get the elements the layout was created from
+    pub fn elements(&self) -> &[D3D11InputElementDesc] {
+        &self.elements
+    }
+
+    pub(crate) fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub(crate) fn slots(&self) -> &[(u32, VertexInputRate)] {
+        &self.slots
+    }
+}
+
+/// Comparison function, `D3D11_COMPARISON_FUNC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11ComparisonFunc {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl D3D11ComparisonFunc {
+    fn compare_op(&self) -> CompareOp {
+        match self {
+            D3D11ComparisonFunc::Never => CompareOp::Never,
+            D3D11ComparisonFunc::Less => CompareOp::Less,
+            D3D11ComparisonFunc::Equal => CompareOp::Equal,
+            D3D11ComparisonFunc::LessEqual => CompareOp::LessOrEqual,
+            D3D11ComparisonFunc::Greater => CompareOp::Greater,
+            D3D11ComparisonFunc::NotEqual => CompareOp::NotEqual,
+            D3D11ComparisonFunc::GreaterEqual => CompareOp::GreaterOrEqual,
+            D3D11ComparisonFunc::Always => CompareOp::Always,
+        }
+    }
+}
+
+/// Triangle fill mode, `D3D11_FILL_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11FillMode {
+    Wireframe,
+    Solid,
+}
+
+/// Triangle culling, `D3D11_CULL_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11CullMode {
+    None,
+    Front,
+    Back,
+}
+
+/// Rasterizer state description, `D3D11_RASTERIZER_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D11RasterizerDesc {
+    pub fill_mode: D3D11FillMode,
+    pub cull_mode: D3D11CullMode,
+    pub front_counter_clockwise: bool,
+    pub scissor_enable: bool,
+}
+
+impl Default for D3D11RasterizerDesc {
+    fn default() -> Self {
+        Self {
+            fill_mode: D3D11FillMode::Solid,
+            cull_mode: D3D11CullMode::Back,
+            front_counter_clockwise: false,
+            scissor_enable: false,
+        }
+    }
+}
+
+impl D3D11RasterizerDesc {
+    pub(crate) fn apply(&self, desc: &mut GraphicsPipelineDescriptor) {
+        desc.polygon_mode = match self.fill_mode {
+            D3D11FillMode::Wireframe => PolygonMode::Line,
+            D3D11FillMode::Solid => PolygonMode::Fill,
+        };
+        desc.cull_mode = match self.cull_mode {
+            D3D11CullMode::None => CullMode::None,
+            D3D11CullMode::Front => CullMode::Front,
+            D3D11CullMode::Back => CullMode::Back,
+        };
+        desc.front_face = if self.front_counter_clockwise {
+            FrontFace::CounterClockwise
+        } else {
+            FrontFace::Clockwise
+        };
+    }
+}
+
+/// Depth/stencil state description, `D3D11_DEPTH_STENCIL_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D11DepthStencilDesc {
+    pub depth_enable: bool,
+    /// Whether passing fragments write the depth, `D3D11_DEPTH_WRITE_MASK_ALL`
+    pub depth_write: bool,
+    pub depth_func: D3D11ComparisonFunc,
+}
+
+impl Default for D3D11DepthStencilDesc {
+    fn default() -> Self {
+        Self {
+            depth_enable: true,
+            depth_write: true,
+            depth_func: D3D11ComparisonFunc::Less,
+        }
+    }
+}
+
+impl D3D11DepthStencilDesc {
+    pub(crate) fn apply(&self, desc: &mut GraphicsPipelineDescriptor) {
+        desc.depth_test = self.depth_enable;
+        desc.depth_write = self.depth_enable && self.depth_write;
+        desc.depth_compare = self.depth_func.compare_op();
+    }
+}
+
+/// Blend factor, `D3D11_BLEND`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11Blend {
+    Zero,
+    One,
+    SrcColor,
+    InvSrcColor,
+    SrcAlpha,
+    InvSrcAlpha,
+    DestAlpha,
+    InvDestAlpha,
+    DestColor,
+    InvDestColor,
+    SrcAlphaSat,
+    BlendFactor,
+    InvBlendFactor,
+}
+
+impl D3D11Blend {
+    fn blend_factor(&self) -> BlendFactor {
+        match self {
+            D3D11Blend::Zero => BlendFactor::Zero,
+            D3D11Blend::One => BlendFactor::One,
+            D3D11Blend::SrcColor => BlendFactor::SrcColor,
+            D3D11Blend::InvSrcColor => BlendFactor::OneMinusSrcColor,
+            D3D11Blend::SrcAlpha => BlendFactor::SrcAlpha,
+            D3D11Blend::InvSrcAlpha => BlendFactor::OneMinusSrcAlpha,
+            D3D11Blend::DestAlpha => BlendFactor::DstAlpha,
+            D3D11Blend::InvDestAlpha => BlendFactor::OneMinusDstAlpha,
+            D3D11Blend::DestColor => BlendFactor::DstColor,
+            D3D11Blend::InvDestColor => BlendFactor::OneMinusDstColor,
+            D3D11Blend::SrcAlphaSat => BlendFactor::SrcAlphaSaturate,
+            D3D11Blend::BlendFactor => BlendFactor::ConstantColor,
+            D3D11Blend::InvBlendFactor => BlendFactor::OneMinusConstantColor,
+        }
+    }
+}
+
+/// Blend operation, `D3D11_BLEND_OP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D11BlendOp {
+    Add,
+    Subtract,
+    RevSubtract,
+    Min,
+    Max,
+}
+
+impl D3D11BlendOp {
+    fn blend_op(&self) -> BlendOp {
+        match self {
+            D3D11BlendOp::Add => BlendOp::Add,
+            D3D11BlendOp::Subtract => BlendOp::Subtract,
+            D3D11BlendOp::RevSubtract => BlendOp::ReverseSubtract,
+            D3D11BlendOp::Min => BlendOp::Min,
+            D3D11BlendOp::Max => BlendOp::Max,
+        }
+    }
+}
+
+/// Blending of one render target, `D3D11_RENDER_TARGET_BLEND_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D11RenderTargetBlendDesc {
+    pub blend_enable: bool,
+    pub src_blend: D3D11Blend,
+    pub dest_blend: D3D11Blend,
+    pub blend_op: D3D11BlendOp,
+    pub src_blend_alpha: D3D11Blend,
+    pub dest_blend_alpha: D3D11Blend,
+    pub blend_op_alpha: D3D11BlendOp,
+    /// `D3D11_COLOR_WRITE_ENABLE` bits, red first
+    pub render_target_write_mask: u8,
+}
+
+impl Default for D3D11RenderTargetBlendDesc {
+    fn default() -> Self {
+        Self {
+            blend_enable: false,
+            src_blend: D3D11Blend::One,
+            dest_blend: D3D11Blend::Zero,
+            blend_op: D3D11BlendOp::Add,
+            src_blend_alpha: D3D11Blend::One,
+            dest_blend_alpha: D3D11Blend::Zero,
+            blend_op_alpha: D3D11BlendOp::Add,
+            render_target_write_mask: 0xf,
+        }
+    }
+}
+
+impl D3D11RenderTargetBlendDesc {
+    fn attachment(&self) -> ColorBlendAttachment {
+        ColorBlendAttachment {
+            blend_enable: self.blend_enable,
+            src_color_factor: self.src_blend.blend_factor(),
+            dst_color_factor: self.dest_blend.blend_factor(),
+            color_op: self.blend_op.blend_op(),
+            src_alpha_factor: self.src_blend_alpha.blend_factor(),
+            dst_alpha_factor: self.dest_blend_alpha.blend_factor(),
+            alpha_op: self.blend_op_alpha.blend_op(),
+            write_mask: ColorWriteMask::from_bits_truncate(self.render_target_write_mask),
+        }
+    }
+}
+
+/// Blend state description, `D3D11_BLEND_DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct D3D11BlendDesc {
+    /// Blend every render target like the first one if `false`
+    pub independent_blend_enable: bool,
+    pub render_target: [D3D11RenderTargetBlendDesc; D3D11_SIMULTANEOUS_RENDER_TARGET_COUNT],
+}
+
+impl D3D11BlendDesc {
+    pub(crate) fn apply(&self, desc: &mut GraphicsPipelineDescriptor) {
+        desc.blend_attachments = (0..desc.color_formats.len())
+            .map(|i| {
+                let target = if self.independent_blend_enable { i } else { 0 };
+                self.render_target[target].attachment()
+            })
+            .collect();
+    }
+}
+
+/// Immutable rasterizer state
+pub struct D3D11RasterizerState {
+    pub(crate) desc: D3D11RasterizerDesc,
+}
+
+impl D3D11RasterizerState {
+    /// Get the description the state was created from
+    pub fn desc(&self) -> &D3D11RasterizerDesc {
+        &self.desc
+    }
+}
+
+/// Immutable depth/stencil state
+pub struct D3D11DepthStencilState {
+    pub(crate) desc: D3D11DepthStencilDesc,
+}
+
+impl D3D11DepthStencilState {
+    /// Get the description the state was created from
+    pub fn desc(&self) -> &D3D11DepthStencilDesc {
+        &self.desc
+    }
+}
+
+/// Immutable blend state
+pub struct D3D11BlendState {
+    pub(crate) desc: D3D11BlendDesc,
+}
+
+impl D3D11BlendState {
+    /// Get the description the state was created from
+    pub fn desc(&self) -> &D3D11BlendDesc {
+        &self.desc
+    }
+}
@@ -0,0 +1,134 @@
+//! DXGI swapchain
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use gal::device::{Swapchain, SwapchainConfig};
+use gal::{Extent2D, PresentMode};
+
+use super::context::D3D11Context;
+use super::format::DxgiFormat;
+use super::resource::{D3D11BindFlags, D3D11Texture2D, D3D11Texture2DDesc};
+use super::D3D11Device;
+use crate::common::DxvkError;
+
+/// Swapchain description, `DXGI_SWAP_CHAIN_DESC`
+#[derive(Debug, Clone, Copy)]
+pub struct DxgiSwapChainDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: DxgiFormat,
+    pub buffer_count: u32,
+    /// Display the swapchain presents to
+    pub output: usize,
+}
+
+/// DXGI swapchain
+///
+/// The application renders to one back buffer, which `present` copies to
+/// the acquired GAL swapchain image; the GAL swapchain may use a format or
+/// size the back buffer doesn't have.
+pub struct DxgiSwapChain {
+    device: Arc<D3D11Device>,
+    desc: DxgiSwapChainDesc,
+    swapchain: Box<dyn Swapchain>,
+    back_buffer: Arc<D3D11Texture2D>,
+}
+
+impl DxgiSwapChain {
+    /// Create a swapchain on `device`
+    pub fn create(device: &Arc<D3D11Device>, desc: &DxgiSwapChainDesc) -> Result<Self, DxvkError> {
+        let image_format = desc.format.image_format().ok_or(DxvkError::NotSupported)?;
+        let swapchain = device.gal_device().create_swapchain(&SwapchainConfig {
+            display_id: desc.output,
+            extent: Extent2D::new(desc.width, desc.height),
+            buffer_count: desc.buffer_count.max(2),
+            format: image_format,
+            present_mode: PresentMode::Fifo,
+        })?;
+        let back_buffer = Self::create_back_buffer(device, desc)?;
+
+        Ok(Self {
+            device: device.clone(),
+            desc: *desc,
+            swapchain,
+            back_buffer,
+        })
+    }
+
+    /// Get the description of the swapchain
+    pub fn desc(&self) -> &DxgiSwapChainDesc {
+        &self.desc
+    }
+
+    /// Get the back buffer
+    pub fn get_buffer(&self) -> &Arc<D3D11Texture2D> {
+        &self.back_buffer
+    }
+
+    /// Present the back buffer
+    ///
+    /// Flushes `context` first, so everything it rendered to the back
+    /// buffer is shown. The GAL swapchain presents in FIFO mode, which
+    /// already waits for one vertical blank; longer sync intervals aren't
+    /// supported.
+    pub fn present(
+        &mut self,
+        context: &mut D3D11Context,
+        sync_interval: u32,
+    ) -> Result<(), DxvkError> {
+        log::trace!("DXGI: Present, sync interval {}", sync_interval);
+        context.flush()?;
+
+        let index = self.swapchain.acquire_next_image(u64::MAX, None, None)?;
+        context.blit_to(&self.back_buffer, self.swapchain.image(index))?;
+        context.flush()?;
+        self.swapchain.present(index, &[])?;
+        Ok(())
+    }
+
+    /// Resize the back buffer and the swapchain
+    ///
+    /// Fails while the application holds on to the back buffer or views of
+    /// it, as `ResizeBuffers` does.
+    pub fn resize_buffers(&mut self, width: u32, height: u32) -> Result<(), DxvkError> {
+        if Arc::strong_count(&self.back_buffer) > 1 {
+            log::warn!("DXGI: ResizeBuffers with the back buffer still referenced");
+            return Err(DxvkError::InvalidParameter);
+        }
+
+        self.desc.width = width;
+        self.desc.height = height;
+        self.swapchain = self
+            .device
+            .gal_device()
+            .create_swapchain(&SwapchainConfig {
+                display_id: self.desc.output,
+                extent: Extent2D::new(width, height),
+                buffer_count: self.desc.buffer_count.max(2),
+                format: self
+                    .desc
+                    .format
+                    .image_format()
+                    .ok_or(DxvkError::NotSupported)?,
+                present_mode: PresentMode::Fifo,
+            })?;
+        self.back_buffer = Self::create_back_buffer(&self.device, &self.desc)?;
+        Ok(())
+    }
+
+    fn create_back_buffer(
+        device: &Arc<D3D11Device>,
+        desc: &DxgiSwapChainDesc,
+    ) -> Result<Arc<D3D11Texture2D>, DxvkError> {
+        device.create_texture_2d(
+            &D3D11Texture2DDesc::new(
+                desc.width,
+                desc.height,
+                desc.format,
+                D3D11BindFlags::RENDER_TARGET | D3D11BindFlags::SHADER_RESOURCE,
+            ),
+            None,
+        )
+    }
+}
@@ -0,0 +1,619 @@
+//! D3D9 fixed-function pipeline emulation
+//!
+//! Draws without a vertex or pixel shader use the fixed-function pipeline,
+//! which this emulates with generated shaders: the state a shader depends
+//! on (vertex format, enabled light types, color sources, fog mode, texture
+//! stage operations, alpha test) is reduced to a [`VertexShaderKey`] and a
+//! [`PixelShaderKey`], and a shader is generated and created for each key
+//! the first time it's drawn with. Matrices, colors and the other values
+//! only reach the shaders through the constants, so changing them doesn't
+//! need new shaders.
+//!
+//! The generated shaders read their constants from two uniform blocks, at
+//! descriptor set 0, bindings [`VS_CONSTANTS_BINDING`] and
+//! [`PS_CONSTANTS_BINDING`], and the texture of stage `i` from binding
+//! [`PS_TEXTURE_BINDING`]` + i`.
+
+mod pixel;
+mod state;
+mod vertex;
+
+pub use state::{
+    fvf, texture_arg, D3D9CmpFunc, D3D9FogMode, D3D9Light, D3D9LightType, D3D9Material,
+    D3D9MaterialColorSource, D3D9RenderState, D3D9TextureOp, D3D9TextureStageState,
+    D3D9TransformState, D3DColorValue, D3DMatrix, D3D9_MAX_ACTIVE_LIGHTS, D3D9_MAX_TEXTURE_STAGES,
+    D3D_IDENTITY,
+};
+
+pub(crate) use state::{multiply, FixedFunctionState};
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::vec::Vec;
+
+use gal::device::{VertexAttribute, VertexFormat};
+use gal::{Device, Shader, ShaderStage, Viewport};
+
+use crate::common::DxvkError;
+use state::{normal_matrix, transform_direction, transform_point};
+
+/// Binding of the vertex shader constants
+pub const VS_CONSTANTS_BINDING: u32 = 0;
+/// Binding of the pixel shader constants
+pub const PS_CONSTANTS_BINDING: u32 = 1;
+/// Binding of the texture of stage 0
+pub const PS_TEXTURE_BINDING: u32 = 2;
+
+/// Vertex input locations
+pub(crate) mod input_location {
+    pub const POSITION: u32 = 0;
+    pub const NORMAL: u32 = 1;
+    pub const DIFFUSE: u32 = 2;
+    pub const SPECULAR: u32 = 3;
+    pub const TEX_COORD: u32 = 4;
+}
+
+/// Locations of the values passed from the vertex to the pixel shader
+pub(crate) mod varying_location {
+    pub const DIFFUSE: u32 = 0;
+    pub const SPECULAR: u32 = 1;
+    pub const FOG: u32 = 2;
+    pub const TEX_COORD: u32 = 3;
+}
+
+/// Vertex shader constant block: three matrices, then vectors
+pub(crate) mod vs_constants {
+    pub const WORLD_VIEW_PROJ: u32 = 0;
+    pub const WORLD_VIEW: u32 = 1;
+    pub const NORMAL_MATRIX: u32 = 2;
+    /// Scale and offset from screen to normalized device coordinates
+    pub const VIEWPORT: u32 = 3;
+    pub const MATERIAL_DIFFUSE: u32 = 4;
+    pub const MATERIAL_AMBIENT: u32 = 5;
+    pub const MATERIAL_SPECULAR: u32 = 6;
+    pub const MATERIAL_EMISSIVE: u32 = 7;
+    pub const GLOBAL_AMBIENT: u32 = 8;
+    /// Material power, fog start, end and density
+    pub const PARAMS: u32 = 9;
+    /// First member of the first light
+    pub const LIGHTS: u32 = 10;
+
+    /// Members of a light, from its first one
+    pub const LIGHT_DIFFUSE: u32 = 0;
+    pub const LIGHT_SPECULAR: u32 = 1;
+    pub const LIGHT_AMBIENT: u32 = 2;
+    pub const LIGHT_POSITION: u32 = 3;
+    pub const LIGHT_DIRECTION: u32 = 4;
+    /// Attenuation factors and range
+    pub const LIGHT_ATTENUATION: u32 = 5;
+    /// Half the inner and outer cone angles, and falloff
+    pub const LIGHT_SPOT: u32 = 6;
+    pub const LIGHT_MEMBERS: u32 = 7;
+
+    pub const MATRICES: u32 = 3;
+    pub const MEMBERS: u32 = LIGHTS + LIGHT_MEMBERS * super::D3D9_MAX_ACTIVE_LIGHTS as u32;
+    pub const SIZE: usize = offset(MEMBERS) as usize;
+
+    /// Byte offset of member `index`
+    pub const fn offset(index: u32) -> u32 {
+        if index < MATRICES {
+            index * 64
+        } else {
+            MATRICES * 64 + (index - MATRICES) * 16
+        }
+    }
+}
+
+/// Pixel shader constant block, all vectors
+pub(crate) mod ps_constants {
+    pub const TEXTURE_FACTOR: u32 = 0;
+    pub const FOG_COLOR: u32 = 1;
+    /// Alpha test reference in x
+    pub const ALPHA_REF: u32 = 2;
+    /// Constant of stage 0
+    pub const STAGE_CONSTANTS: u32 = 3;
+    pub const MEMBERS: u32 = STAGE_CONSTANTS + super::D3D9_MAX_TEXTURE_STAGES as u32;
+    pub const SIZE: usize = MEMBERS as usize * 16;
+}
+
+/// State the generated vertex shader depends on
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VertexShaderKey {
+    /// Positions are in screen space, with the reciprocal of w
+    pub pretransformed: bool,
+    pub normal: bool,
+    pub diffuse: bool,
+    pub specular: bool,
+    /// Components of each texture coordinate set, 0 for absent sets
+    pub tex_coord_sizes: [u8; D3D9_MAX_TEXTURE_STAGES],
+    pub lighting: Option<LightingKey>,
+    pub fog: D3D9FogMode,
+    /// Fog is table (pixel) fog, computed per vertex here
+    pub fog_table: bool,
+}
+
+/// Lighting state the generated vertex shader depends on
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LightingKey {
+    /// Type of each enabled light
+    pub lights: [Option<D3D9LightType>; D3D9_MAX_ACTIVE_LIGHTS],
+    pub diffuse_source: D3D9MaterialColorSource,
+    pub ambient_source: D3D9MaterialColorSource,
+    pub specular_source: D3D9MaterialColorSource,
+    pub emissive_source: D3D9MaterialColorSource,
+    pub specular: bool,
+    pub local_viewer: bool,
+    pub normalize_normals: bool,
+}
+
+/// State the generated pixel shader depends on
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PixelShaderKey {
+    /// Enabled texture stages, up to the first disabled one
+    pub stages: Vec<TextureStageKey>,
+    pub specular: bool,
+    pub fog: bool,
+    /// Alpha test, `Always` if disabled
+    pub alpha_test: D3D9CmpFunc,
+}
+
+/// Texture stage state the generated pixel shader depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextureStageKey {
+    pub color_op: D3D9TextureOp,
+    /// Arguments 0, 1 and 2, 0 where the operation doesn't use them
+    pub color_args: [u32; 3],
+    pub alpha_op: D3D9TextureOp,
+    pub alpha_args: [u32; 3],
+    pub tex_coord_index: u8,
+    /// The result goes to the temporary register instead of the current one
+    pub result_temp: bool,
+    /// A texture is bound to the stage
+    pub textured: bool,
+}
+
+impl D3D9TextureOp {
+    /// Which of arguments 0, 1 and 2 the operation reads
+    pub(crate) fn args(&self) -> [bool; 3] {
+        match self {
+            D3D9TextureOp::Disable | D3D9TextureOp::SelectArg1 => [false, true, false],
+            D3D9TextureOp::SelectArg2 => [false, false, true],
+            D3D9TextureOp::MultiplyAdd | D3D9TextureOp::Lerp => [true, true, true],
+            _ => [false, true, true],
+        }
+    }
+
+    /// Operation the pixel shader carries out for this one
+    ///
+    /// Premodulation and bump mapping aren't emulated; their stages pass
+    /// the current color on.
+    fn emulated(self, args: [u32; 3]) -> (Self, [u32; 3]) {
+        let (op, args) = match self {
+            D3D9TextureOp::PreModulate
+            | D3D9TextureOp::BumpEnvMap
+            | D3D9TextureOp::BumpEnvMapLuminance
+            | D3D9TextureOp::Disable => (D3D9TextureOp::SelectArg1, [0, texture_arg::CURRENT, 0]),
+            op => (op, args),
+        };
+        let used = op.args();
+        let mut kept = [0; 3];
+        for index in 0..3 {
+            if used[index] {
+                kept[index] = args[index];
+            }
+        }
+        (op, kept)
+    }
+}
+
+impl VertexShaderKey {
+    pub(crate) fn new(state: &FixedFunctionState) -> Self {
+        let fvf = state.fvf;
+        let pretransformed = fvf & fvf::POSITION_MASK == fvf::XYZRHW;
+        let tex_coord_count = ((fvf & fvf::TEXCOUNT_MASK) >> fvf::TEXCOUNT_SHIFT) as usize;
+        let mut tex_coord_sizes = [0; D3D9_MAX_TEXTURE_STAGES];
+        for (index, size) in tex_coord_sizes.iter_mut().enumerate().take(tex_coord_count) {
+            *size = fvf::tex_coord_size(fvf, index as u32) as u8;
+        }
+
+        let diffuse = fvf & fvf::DIFFUSE != 0;
+        let specular = fvf & fvf::SPECULAR != 0;
+        let lighting = (state.render_state_bool(D3D9RenderState::Lighting) && !pretransformed)
+            .then(|| {
+                let color_vertex = state.render_state_bool(D3D9RenderState::ColorVertex);
+                // Vertex colors the vertex doesn't have fall back to the material
+                let source = |render_state: D3D9RenderState| {
+                    match D3D9MaterialColorSource::from_u32(state.render_state(render_state)) {
+                        D3D9MaterialColorSource::Color1 if color_vertex && diffuse => {
+                            D3D9MaterialColorSource::Color1
+                        }
+                        D3D9MaterialColorSource::Color2 if color_vertex && specular => {
+                            D3D9MaterialColorSource::Color2
+                        }
+                        _ => D3D9MaterialColorSource::Material,
+                    }
+                };
+
+                let mut lights = [None; D3D9_MAX_ACTIVE_LIGHTS];
+                for (slot, &index) in lights.iter_mut().zip(state.active_lights.iter()) {
+                    *slot = Some(state.lights[&index].light_type);
+                }
+                LightingKey {
+                    lights,
+                    diffuse_source: source(D3D9RenderState::DiffuseMaterialSource),
+                    ambient_source: source(D3D9RenderState::AmbientMaterialSource),
+                    specular_source: source(D3D9RenderState::SpecularMaterialSource),
+                    emissive_source: source(D3D9RenderState::EmissiveMaterialSource),
+                    specular: state.render_state_bool(D3D9RenderState::SpecularEnable),
+                    local_viewer: state.render_state_bool(D3D9RenderState::LocalViewer),
+                    normalize_normals: state.render_state_bool(D3D9RenderState::NormalizeNormals),
+                }
+            });
+
+        let table_mode = D3D9FogMode::from_u32(state.render_state(D3D9RenderState::FogTableMode));
+        let (fog, fog_table) = if !state.render_state_bool(D3D9RenderState::FogEnable) {
+            (D3D9FogMode::None, false)
+        } else if table_mode != D3D9FogMode::None {
+            (table_mode, true)
+        } else {
+            let vertex_mode =
+                D3D9FogMode::from_u32(state.render_state(D3D9RenderState::FogVertexMode));
+            // Pre-transformed vertices carry their vertex fog factor
+            let vertex_mode = if pretransformed && vertex_mode == D3D9FogMode::None {
+                D3D9FogMode::Linear
+            } else {
+                vertex_mode
+            };
+            (vertex_mode, false)
+        };
+
+        Self {
+            pretransformed,
+            normal: fvf & fvf::NORMAL != 0,
+            diffuse,
+            specular,
+            tex_coord_sizes,
+            lighting,
+            fog,
+            fog_table,
+        }
+    }
+}
+
+impl PixelShaderKey {
+    pub(crate) fn new(state: &FixedFunctionState) -> Self {
+        let mut stages = Vec::new();
+        for stage in 0..D3D9_MAX_TEXTURE_STAGES {
+            let stage_state = |s: D3D9TextureStageState| state.stage_state(stage, s);
+            let color_op = D3D9TextureOp::from_u32(stage_state(D3D9TextureStageState::ColorOp));
+            if color_op == D3D9TextureOp::Disable {
+                break;
+            }
+
+            let (color_op, color_args) = color_op.emulated([
+                stage_state(D3D9TextureStageState::ColorArg0),
+                stage_state(D3D9TextureStageState::ColorArg1),
+                stage_state(D3D9TextureStageState::ColorArg2),
+            ]);
+            let (alpha_op, alpha_args) =
+                D3D9TextureOp::from_u32(stage_state(D3D9TextureStageState::AlphaOp)).emulated([
+                    stage_state(D3D9TextureStageState::AlphaArg0),
+                    stage_state(D3D9TextureStageState::AlphaArg1),
+                    stage_state(D3D9TextureStageState::AlphaArg2),
+                ]);
+            stages.push(TextureStageKey {
+                color_op,
+                color_args,
+                alpha_op,
+                alpha_args,
+                tex_coord_index: (stage_state(D3D9TextureStageState::TexCoordIndex)
+                    % D3D9_MAX_TEXTURE_STAGES as u32) as u8,
+                result_temp: stage_state(D3D9TextureStageState::ResultArg)
+                    & texture_arg::SELECT_MASK
+                    == texture_arg::TEMP,
+                textured: state.textures[stage],
+            });
+        }
+
+        Self {
+            stages,
+            specular: state.render_state_bool(D3D9RenderState::SpecularEnable),
+            fog: state.render_state_bool(D3D9RenderState::FogEnable),
+            alpha_test: if state.render_state_bool(D3D9RenderState::AlphaTestEnable) {
+                D3D9CmpFunc::from_u32(state.render_state(D3D9RenderState::AlphaFunc))
+            } else {
+                D3D9CmpFunc::Always
+            },
+        }
+    }
+}
+
+/// SPIR-V of the fixed-function vertex shader for `key`
+pub fn generate_vertex_shader(key: &VertexShaderKey) -> Vec<u32> {
+    vertex::generate(key)
+}
+
+/// SPIR-V of the fixed-function pixel shader for `key`
+pub fn generate_pixel_shader(key: &PixelShaderKey) -> Vec<u32> {
+    pixel::generate(key)
+}
+
+/// Generated shader
+pub struct FixedFunctionShader {
+    code: Vec<u8>,
+    module: Box<dyn Shader>,
+}
+
+impl FixedFunctionShader {
+    fn create(device: &dyn Device, stage: ShaderStage, spirv: &[u32]) -> Result<Self, DxvkError> {
+        let code: Vec<u8> = spirv.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let module = device
+            .create_shader(stage, &code)
+            .map_err(|err| DxvkError::ShaderCompilationFailed(format!("{}", err)))?;
+        Ok(Self { code, module })
+    }
+
+    /// SPIR-V code, as for [`PipelineKey`](gal::PipelineKey)
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Shader module
+    pub fn module(&self) -> &dyn Shader {
+        self.module.as_ref()
+    }
+}
+
+/// Generated shaders, by the state they were generated for
+#[derive(Default)]
+pub(crate) struct FixedFunctionCache {
+    vertex_shaders: BTreeMap<VertexShaderKey, FixedFunctionShader>,
+    pixel_shaders: BTreeMap<PixelShaderKey, FixedFunctionShader>,
+}
+
+impl FixedFunctionCache {
+    /// Shaders for `state`, generated and created on `device` the first
+    /// time they're needed
+    pub fn shaders(
+        &mut self,
+        device: &dyn Device,
+        state: &FixedFunctionState,
+    ) -> Result<(&FixedFunctionShader, &FixedFunctionShader), DxvkError> {
+        let vertex_key = VertexShaderKey::new(state);
+        if !self.vertex_shaders.contains_key(&vertex_key) {
+            log::debug!(
+                "D3D9: generating fixed-function vertex shader for {:?}",
+                vertex_key
+            );
+            let spirv = vertex::generate(&vertex_key);
+            let shader = FixedFunctionShader::create(device, ShaderStage::Vertex, &spirv)?;
+            self.vertex_shaders.insert(vertex_key.clone(), shader);
+        }
+
+        let pixel_key = PixelShaderKey::new(state);
+        if !self.pixel_shaders.contains_key(&pixel_key) {
+            log::debug!(
+                "D3D9: generating fixed-function pixel shader for {:?}",
+                pixel_key
+            );
+            let spirv = pixel::generate(&pixel_key);
+            let shader = FixedFunctionShader::create(device, ShaderStage::Fragment, &spirv)?;
+            self.pixel_shaders.insert(pixel_key.clone(), shader);
+        }
+
+        Ok((
+            &self.vertex_shaders[&vertex_key],
+            &self.pixel_shaders[&pixel_key],
+        ))
+    }
+
+    /// Number of generated vertex and pixel shaders
+    pub fn len(&self) -> (usize, usize) {
+        (self.vertex_shaders.len(), self.pixel_shaders.len())
+    }
+}
+
+/// Contents of the vertex shader constant block for `state`, drawing to
+/// `viewport`
+pub(crate) fn vertex_constants(state: &FixedFunctionState, viewport: &Viewport) -> Vec<u8> {
+    use vs_constants as vc;
+
+    let mut data = alloc::vec![0u8; vc::SIZE];
+    let mut put = |member: u32, values: &[f32]| {
+        let offset = vc::offset(member) as usize;
+        for (index, value) in values.iter().enumerate() {
+            data[offset + index * 4..offset + index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    };
+    // Matrices go in as rows, which the shaders read as the columns of the
+    // transpose, turning D3D's row vector products into column vector ones
+    let matrix = |m: &D3DMatrix| -> [f32; 16] {
+        let mut out = [0.0; 16];
+        for (row, values) in m.iter().enumerate() {
+            out[row * 4..row * 4 + 4].copy_from_slice(values);
+        }
+        out
+    };
+
+    let world_view = multiply(&state.world, &state.view);
+    put(
+        vc::WORLD_VIEW_PROJ,
+        &matrix(&multiply(&world_view, &state.projection)),
+    );
+    put(vc::WORLD_VIEW, &matrix(&world_view));
+    put(vc::NORMAL_MATRIX, &matrix(&normal_matrix(&world_view)));
+
+    let (width, height) = (viewport.width.max(1.0), viewport.height.max(1.0));
+    put(
+        vc::VIEWPORT,
+        &[
+            2.0 / width,
+            2.0 / height,
+            -1.0 - 2.0 * viewport.x / width,
+            -1.0 - 2.0 * viewport.y / height,
+        ],
+    );
+
+    let material = &state.material;
+    put(vc::MATERIAL_DIFFUSE, &material.diffuse.to_array());
+    put(vc::MATERIAL_AMBIENT, &material.ambient.to_array());
+    put(vc::MATERIAL_SPECULAR, &material.specular.to_array());
+    put(vc::MATERIAL_EMISSIVE, &material.emissive.to_array());
+    put(
+        vc::GLOBAL_AMBIENT,
+        &D3DColorValue::from_argb(state.render_state(D3D9RenderState::Ambient)).to_array(),
+    );
+    put(
+        vc::PARAMS,
+        &[
+            material.power,
+            state.render_state_f32(D3D9RenderState::FogStart),
+            state.render_state_f32(D3D9RenderState::FogEnd),
+            state.render_state_f32(D3D9RenderState::FogDensity),
+        ],
+    );
+
+    for (slot, &index) in state
+        .active_lights
+        .iter()
+        .take(D3D9_MAX_ACTIVE_LIGHTS)
+        .enumerate()
+    {
+        let light = &state.lights[&index];
+        let member = |k: u32| vc::LIGHTS + slot as u32 * vc::LIGHT_MEMBERS + k;
+        put(member(vc::LIGHT_DIFFUSE), &light.diffuse.to_array());
+        put(member(vc::LIGHT_SPECULAR), &light.specular.to_array());
+        put(member(vc::LIGHT_AMBIENT), &light.ambient.to_array());
+
+        let position = transform_point(&state.view, light.position);
+        put(member(vc::LIGHT_POSITION), &position);
+        let direction = transform_direction(&state.view, light.direction);
+        put(member(vc::LIGHT_DIRECTION), &direction);
+
+        put(
+            member(vc::LIGHT_ATTENUATION),
+            &[
+                light.attenuation0,
+                light.attenuation1,
+                light.attenuation2,
+                light.range,
+            ],
+        );
+        put(
+            member(vc::LIGHT_SPOT),
+            &[light.theta / 2.0, light.phi / 2.0, light.falloff],
+        );
+    }
+    data
+}
+
+/// Contents of the pixel shader constant block for `state`
+pub(crate) fn pixel_constants(state: &FixedFunctionState) -> Vec<u8> {
+    use ps_constants as pc;
+
+    let mut data = alloc::vec![0u8; pc::SIZE];
+    let mut put = |member: u32, values: [f32; 4]| {
+        let offset = member as usize * 16;
+        for (index, value) in values.iter().enumerate() {
+            data[offset + index * 4..offset + index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    };
+    let color = |render_state: D3D9RenderState| {
+        D3DColorValue::from_argb(state.render_state(render_state)).to_array()
+    };
+
+    put(pc::TEXTURE_FACTOR, color(D3D9RenderState::TextureFactor));
+    put(pc::FOG_COLOR, color(D3D9RenderState::FogColor));
+    let alpha_ref = (state.render_state(D3D9RenderState::AlphaRef) & 0xFF) as f32 / 255.0;
+    put(pc::ALPHA_REF, [alpha_ref, 0.0, 0.0, 0.0]);
+    for stage in 0..D3D9_MAX_TEXTURE_STAGES {
+        let constant = state.stage_state(stage, D3D9TextureStageState::Constant);
+        put(
+            pc::STAGE_CONSTANTS + stage as u32,
+            D3DColorValue::from_argb(constant).to_array(),
+        );
+    }
+    data
+}
+
+/// Vertex attributes and stride of vertices in the flexible vertex format
+/// `fvf`, at the locations the generated vertex shaders read them from
+pub fn vertex_layout(fvf: u32) -> Option<(Vec<VertexAttribute>, u32)> {
+    let mut attributes = Vec::new();
+    let mut offset = 0;
+    let mut attribute = |offset: &mut u32, location: u32, format: VertexFormat, size: u32| {
+        attributes.push(VertexAttribute {
+            location,
+            binding: 0,
+            format,
+            offset: *offset,
+        });
+        *offset += size;
+    };
+
+    match fvf & fvf::POSITION_MASK {
+        fvf::XYZ => attribute(
+            &mut offset,
+            input_location::POSITION,
+            VertexFormat::Float3,
+            12,
+        ),
+        fvf::XYZRHW => attribute(
+            &mut offset,
+            input_location::POSITION,
+            VertexFormat::Float4,
+            16,
+        ),
+        // XYZB1 to XYZB5: blend weights follow the position
+        position @ 0x6..=0xE if position % 2 == 0 => {
+            attribute(
+                &mut offset,
+                input_location::POSITION,
+                VertexFormat::Float3,
+                12,
+            );
+            offset += (position - fvf::XYZ) / 2 * 4;
+        }
+        _ => return None,
+    }
+    if fvf & fvf::NORMAL != 0 {
+        attribute(
+            &mut offset,
+            input_location::NORMAL,
+            VertexFormat::Float3,
+            12,
+        );
+    }
+    if fvf & fvf::PSIZE != 0 {
+        offset += 4;
+    }
+    if fvf & fvf::DIFFUSE != 0 {
+        attribute(
+            &mut offset,
+            input_location::DIFFUSE,
+            VertexFormat::UByte4Norm,
+            4,
+        );
+    }
+    if fvf & fvf::SPECULAR != 0 {
+        attribute(
+            &mut offset,
+            input_location::SPECULAR,
+            VertexFormat::UByte4Norm,
+            4,
+        );
+    }
+
+    let tex_coord_count = (fvf & fvf::TEXCOUNT_MASK) >> fvf::TEXCOUNT_SHIFT;
+    for index in 0..tex_coord_count.min(D3D9_MAX_TEXTURE_STAGES as u32) {
+        let (format, size) = match fvf::tex_coord_size(fvf, index) {
+            1 => (VertexFormat::Float, 4),
+            3 => (VertexFormat::Float3, 12),
+            4 => (VertexFormat::Float4, 16),
+            _ => (VertexFormat::Float2, 8),
+        };
+        attribute(&mut offset, input_location::TEX_COORD + index, format, size);
+    }
+    Some((attributes, offset))
+}
@@ -0,0 +1,333 @@
+//! Fixed-function pixel shader generation
+//!
+//! The texture stage cascade, specular add, fog blend and alpha test.
+//! Every stage's result is clamped to [0, 1], as the fixed-function
+//! hardware did.
+
+use alloc::vec::Vec;
+
+use gal::ShaderStage;
+
+use super::state::{texture_arg, D3D9CmpFunc, D3D9TextureOp, D3D9_MAX_TEXTURE_STAGES};
+use super::{
+    ps_constants as pc, varying_location, PixelShaderKey, TextureStageKey, PS_CONSTANTS_BINDING,
+    PS_TEXTURE_BINDING,
+};
+use crate::d3d9::spirv::{decoration, glsl, op, SpirvBuilder, StorageClass};
+
+/// Values the stage arguments select from
+struct Registers {
+    constants: u32,
+    diffuse: u32,
+    specular: u32,
+    current: u32,
+    temp: u32,
+    /// Sampled texture of the stage, if it reads one
+    texture: Option<u32>,
+    stage: u32,
+}
+
+/// SPIR-V of the pixel shader for `key`
+pub(super) fn generate(key: &PixelShaderKey) -> Vec<u32> {
+    let mut b = SpirvBuilder::new();
+    let float = b.type_float();
+    let vec3 = b.type_vec(3);
+    let vec4 = b.type_vec(4);
+
+    let members: Vec<(u32, u32)> = (0..pc::MEMBERS).map(|index| (vec4, index * 16)).collect();
+    let block = b.type_block(&members);
+    let constants = b.variable(StorageClass::Uniform, block);
+    b.decorate(constants, decoration::DESCRIPTOR_SET, &[0]);
+    b.decorate(constants, decoration::BINDING, &[PS_CONSTANTS_BINDING]);
+
+    let diffuse = input(&mut b, vec4, varying_location::DIFFUSE);
+    let specular = input(&mut b, vec4, varying_location::SPECULAR);
+    let mut tex_coords = [None; D3D9_MAX_TEXTURE_STAGES];
+
+    let zero4 = b.constant_vec(&[0.0; 4]);
+    let one4 = b.constant_vec(&[1.0; 4]);
+    let mut registers = Registers {
+        constants,
+        diffuse,
+        specular,
+        current: diffuse,
+        temp: zero4,
+        texture: None,
+        stage: 0,
+    };
+    for (stage, stage_key) in key.stages.iter().enumerate() {
+        registers.stage = stage as u32;
+        registers.texture = match (stage_key.reads_texture(), stage_key.textured) {
+            (true, true) => {
+                let index = stage_key.tex_coord_index as usize % D3D9_MAX_TEXTURE_STAGES;
+                let tex_coord = *tex_coords[index].get_or_insert_with(|| {
+                    input(&mut b, vec4, varying_location::TEX_COORD + index as u32)
+                });
+                Some(sample(&mut b, stage as u32, tex_coord))
+            }
+            // Stages without a texture read it as opaque white
+            (true, false) => Some(one4),
+            (false, _) => None,
+        };
+
+        let color = stage_op(&mut b, &registers, stage_key.color_op, stage_key.color_args);
+        let alpha = stage_op(&mut b, &registers, stage_key.alpha_op, stage_key.alpha_args);
+        let result = b.shuffle(color, alpha, &[0, 1, 2, 7]);
+        let result = b.ext(glsl::FCLAMP, vec4, &[result, zero4, one4]);
+        if stage_key.result_temp {
+            registers.temp = result;
+        } else {
+            registers.current = result;
+        }
+    }
+    let mut color = registers.current;
+
+    if key.specular {
+        let zero3 = b.constant_vec(&[0.0; 3]);
+        let one3 = b.constant_vec(&[1.0; 3]);
+        let rgb = b.shuffle(color, color, &[0, 1, 2]);
+        let specular_rgb = b.shuffle(specular, specular, &[0, 1, 2]);
+        let rgb = b.fadd(vec3, rgb, specular_rgb);
+        let rgb = b.ext(glsl::FCLAMP, vec3, &[rgb, zero3, one3]);
+        let alpha = b.extract(float, color, 3);
+        color = b.construct(vec4, &[rgb, alpha]);
+    }
+
+    if key.fog {
+        let fog = input(&mut b, float, varying_location::FOG);
+        let fog_color = b.load_member(constants, pc::FOG_COLOR, vec4);
+        let fog_rgb = b.shuffle(fog_color, fog_color, &[0, 1, 2]);
+        let rgb = b.shuffle(color, color, &[0, 1, 2]);
+        let factor = b.splat(fog, 3);
+        let rgb = b.ext(glsl::FMIX, vec3, &[fog_rgb, rgb, factor]);
+        let alpha = b.extract(float, color, 3);
+        color = b.construct(vec4, &[rgb, alpha]);
+    }
+
+    if key.alpha_test != D3D9CmpFunc::Always {
+        let alpha = b.extract(float, color, 3);
+        let reference = b.load_member(constants, pc::ALPHA_REF, vec4);
+        let reference = b.extract(float, reference, 0);
+        let compare = match key.alpha_test {
+            D3D9CmpFunc::Less => Some(op::FORD_LESS_THAN),
+            D3D9CmpFunc::Equal => Some(op::FORD_EQUAL),
+            D3D9CmpFunc::LessEqual => Some(op::FORD_LESS_THAN_EQUAL),
+            D3D9CmpFunc::Greater => Some(op::FORD_GREATER_THAN),
+            D3D9CmpFunc::NotEqual => Some(op::FORD_NOT_EQUAL),
+            D3D9CmpFunc::GreaterEqual => Some(op::FORD_GREATER_THAN_EQUAL),
+            D3D9CmpFunc::Never | D3D9CmpFunc::Always => None,
+        };
+        let discard = match compare {
+            Some(compare) => {
+                let pass = b.compare(compare, alpha, reference);
+                let bool_type = b.type_bool();
+                b.op(op::LOGICAL_NOT, bool_type, &[pass])
+            }
+            None => b.constant_true(),
+        };
+        let merge = b.begin_if(discard);
+        b.op_void(op::KILL, &[]);
+        b.end_if(merge, true);
+    }
+
+    let target = b.variable(StorageClass::Output, vec4);
+    b.decorate(target, decoration::LOCATION, &[0]);
+    b.store(target, color);
+
+    b.finish(ShaderStage::Fragment)
+}
+
+/// Result of a stage operation on the arguments `args`, as a vector whose
+/// color or alpha the caller keeps
+fn stage_op(b: &mut SpirvBuilder, registers: &Registers, op: D3D9TextureOp, args: [u32; 3]) -> u32 {
+    let float = b.type_float();
+    let vec4 = b.type_vec(4);
+    // Unused arguments stay 0, which no operation reads
+    let mut values = [0; 3];
+    for (index, used) in op.args().into_iter().enumerate() {
+        if used {
+            values[index] = argument(b, registers, args[index]);
+        }
+    }
+    let [a0, a1, a2] = values;
+
+    let one = b.constant_f32(1.0);
+    let half = b.constant_vec(&[0.5; 4]);
+    let one4 = b.constant_vec(&[1.0; 4]);
+    let alpha_of = |b: &mut SpirvBuilder, v: u32| b.extract(float, v, 3);
+    // a1 × s + a2 × (1 - s)
+    let blend = |b: &mut SpirvBuilder, s: u32| {
+        let s = b.splat(s, 4);
+        b.ext(glsl::FMIX, vec4, &[a2, a1, s])
+    };
+
+    match op {
+        D3D9TextureOp::Disable | D3D9TextureOp::SelectArg1 => a1,
+        D3D9TextureOp::SelectArg2 => a2,
+        D3D9TextureOp::Modulate => b.fmul(vec4, a1, a2),
+        D3D9TextureOp::Modulate2x | D3D9TextureOp::Modulate4x => {
+            let product = b.fmul(vec4, a1, a2);
+            let factor = if op == D3D9TextureOp::Modulate2x {
+                2.0
+            } else {
+                4.0
+            };
+            let factor = b.constant_f32(factor);
+            b.scale(vec4, product, factor)
+        }
+        D3D9TextureOp::Add => b.fadd(vec4, a1, a2),
+        D3D9TextureOp::AddSigned | D3D9TextureOp::AddSigned2x => {
+            let sum = b.fadd(vec4, a1, a2);
+            let sum = b.fsub(vec4, sum, half);
+            if op == D3D9TextureOp::AddSigned2x {
+                b.fadd(vec4, sum, sum)
+            } else {
+                sum
+            }
+        }
+        D3D9TextureOp::Subtract => b.fsub(vec4, a1, a2),
+        D3D9TextureOp::AddSmooth => {
+            // a1 + a2 × (1 - a1)
+            let inverse = b.fsub(vec4, one4, a1);
+            let product = b.fmul(vec4, a2, inverse);
+            b.fadd(vec4, a1, product)
+        }
+        D3D9TextureOp::BlendDiffuseAlpha => {
+            let alpha = alpha_of(b, registers.diffuse);
+            blend(b, alpha)
+        }
+        D3D9TextureOp::BlendTextureAlpha => {
+            let alpha = alpha_of(b, registers.texture.unwrap_or(one4));
+            blend(b, alpha)
+        }
+        D3D9TextureOp::BlendFactorAlpha => {
+            let factor = b.load_member(registers.constants, pc::TEXTURE_FACTOR, vec4);
+            let alpha = alpha_of(b, factor);
+            blend(b, alpha)
+        }
+        D3D9TextureOp::BlendCurrentAlpha => {
+            let alpha = alpha_of(b, registers.current);
+            blend(b, alpha)
+        }
+        D3D9TextureOp::BlendTextureAlphaPm => {
+            // a1 + a2 × (1 - texture alpha)
+            let alpha = alpha_of(b, registers.texture.unwrap_or(one4));
+            let inverse = b.fsub(float, one, alpha);
+            let product = b.scale(vec4, a2, inverse);
+            b.fadd(vec4, a1, product)
+        }
+        D3D9TextureOp::ModulateAlphaAddColor => {
+            // a1 + a1 alpha × a2
+            let alpha = alpha_of(b, a1);
+            let product = b.scale(vec4, a2, alpha);
+            b.fadd(vec4, a1, product)
+        }
+        D3D9TextureOp::ModulateColorAddAlpha => {
+            // a1 × a2 + a1 alpha
+            let alpha = alpha_of(b, a1);
+            let alpha = b.splat(alpha, 4);
+            let product = b.fmul(vec4, a1, a2);
+            b.fadd(vec4, product, alpha)
+        }
+        D3D9TextureOp::ModulateInvAlphaAddColor => {
+            // a1 + (1 - a1 alpha) × a2
+            let alpha = alpha_of(b, a1);
+            let inverse = b.fsub(float, one, alpha);
+            let product = b.scale(vec4, a2, inverse);
+            b.fadd(vec4, a1, product)
+        }
+        D3D9TextureOp::ModulateInvColorAddAlpha => {
+            // (1 - a1) × a2 + a1 alpha
+            let alpha = alpha_of(b, a1);
+            let alpha = b.splat(alpha, 4);
+            let inverse = b.fsub(vec4, one4, a1);
+            let product = b.fmul(vec4, inverse, a2);
+            b.fadd(vec4, product, alpha)
+        }
+        D3D9TextureOp::DotProduct3 => {
+            // 4 × (a1 - 0.5) · (a2 - 0.5) in every channel
+            let a1 = b.fsub(vec4, a1, half);
+            let a2 = b.fsub(vec4, a2, half);
+            let a1 = b.shuffle(a1, a1, &[0, 1, 2]);
+            let a2 = b.shuffle(a2, a2, &[0, 1, 2]);
+            let dot = b.dot(a1, a2);
+            let four = b.constant_f32(4.0);
+            let dot = b.fmul(float, dot, four);
+            b.splat(dot, 4)
+        }
+        D3D9TextureOp::MultiplyAdd => {
+            let product = b.fmul(vec4, a1, a2);
+            b.fadd(vec4, a0, product)
+        }
+        D3D9TextureOp::Lerp => b.ext(glsl::FMIX, vec4, &[a2, a1, a0]),
+        // Mapped to other operations when building the key
+        D3D9TextureOp::PreModulate
+        | D3D9TextureOp::BumpEnvMap
+        | D3D9TextureOp::BumpEnvMapLuminance => a1,
+    }
+}
+
+/// Value of the stage argument `arg`, a `D3DTA_*` register with modifiers
+fn argument(b: &mut SpirvBuilder, registers: &Registers, arg: u32) -> u32 {
+    let vec4 = b.type_vec(4);
+    let mut value = match arg & texture_arg::SELECT_MASK {
+        texture_arg::DIFFUSE => registers.diffuse,
+        texture_arg::TEXTURE => match registers.texture {
+            Some(texture) => texture,
+            None => b.constant_vec(&[1.0; 4]),
+        },
+        texture_arg::TFACTOR => b.load_member(registers.constants, pc::TEXTURE_FACTOR, vec4),
+        texture_arg::SPECULAR => registers.specular,
+        texture_arg::TEMP => registers.temp,
+        texture_arg::CONSTANT => b.load_member(
+            registers.constants,
+            pc::STAGE_CONSTANTS + registers.stage,
+            vec4,
+        ),
+        _ => registers.current,
+    };
+    if arg & texture_arg::COMPLEMENT != 0 {
+        let one = b.constant_vec(&[1.0; 4]);
+        value = b.fsub(vec4, one, value);
+    }
+    if arg & texture_arg::ALPHA_REPLICATE != 0 {
+        value = b.shuffle(value, value, &[3, 3, 3, 3]);
+    }
+    value
+}
+
+/// Sample of the texture of `stage` at `tex_coord`
+fn sample(b: &mut SpirvBuilder, stage: u32, tex_coord: u32) -> u32 {
+    let vec4 = b.type_vec(4);
+    let sampled_image = b.type_sampled_image_2d();
+    let sampler = b.variable(StorageClass::UniformConstant, sampled_image);
+    b.decorate(sampler, decoration::DESCRIPTOR_SET, &[0]);
+    b.decorate(sampler, decoration::BINDING, &[PS_TEXTURE_BINDING + stage]);
+
+    let image = b.load(sampled_image, sampler);
+    let coord = b.shuffle(tex_coord, tex_coord, &[0, 1]);
+    b.op(op::IMAGE_SAMPLE_IMPLICIT_LOD, vec4, &[image, coord])
+}
+
+/// Load of the interpolated input at `location`
+fn input(b: &mut SpirvBuilder, ty: u32, location: u32) -> u32 {
+    let variable = b.variable(StorageClass::Input, ty);
+    b.decorate(variable, decoration::LOCATION, &[location]);
+    b.load(ty, variable)
+}
+
+impl TextureStageKey {
+    /// Whether the stage samples its texture
+    fn reads_texture(&self) -> bool {
+        let reads =
+            |op: D3D9TextureOp, args: [u32; 3]| {
+                matches!(
+                    op,
+                    D3D9TextureOp::BlendTextureAlpha | D3D9TextureOp::BlendTextureAlphaPm
+                ) || op.args().iter().zip(args).any(|(&used, arg)| {
+                    used && arg & texture_arg::SELECT_MASK == texture_arg::TEXTURE
+                })
+            };
+        reads(self.color_op, self.color_args) || reads(self.alpha_op, self.alpha_args)
+    }
+}
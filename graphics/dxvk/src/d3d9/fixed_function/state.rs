@@ -0,0 +1,519 @@
+//! D3D9 fixed-function state
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Matrix, `D3DMATRIX`, in rows applied to row vectors
+pub type D3DMatrix = [[f32; 4]; 4];
+
+/// Identity matrix
+pub const D3D_IDENTITY: D3DMatrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Flexible vertex format bits, `D3DFVF_*`
+pub mod fvf {
+    pub const XYZ: u32 = 0x002;
+    pub const XYZRHW: u32 = 0x004;
+    pub const POSITION_MASK: u32 = 0x400E;
+    pub const NORMAL: u32 = 0x010;
+    pub const PSIZE: u32 = 0x020;
+    pub const DIFFUSE: u32 = 0x040;
+    pub const SPECULAR: u32 = 0x080;
+    pub const TEXCOUNT_MASK: u32 = 0xF00;
+    pub const TEXCOUNT_SHIFT: u32 = 8;
+
+    /// Number of floats of texture coordinate set `index`
+    pub fn tex_coord_size(fvf: u32, index: u32) -> u32 {
+        match (fvf >> (16 + index * 2)) & 0x3 {
+            0 => 2,
+            1 => 3,
+            2 => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// Maximum number of texture stages
+pub const D3D9_MAX_TEXTURE_STAGES: usize = 8;
+
+/// Maximum number of enabled lights
+pub const D3D9_MAX_ACTIVE_LIGHTS: usize = 8;
+
+/// Transform state, `D3DTRANSFORMSTATETYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum D3D9TransformState {
+    View,
+    Projection,
+    World,
+}
+
+/// Color with float components, `D3DCOLORVALUE`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct D3DColorValue {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl D3DColorValue {
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Color from a `D3DCOLOR`, packed as ARGB
+    pub fn from_argb(argb: u32) -> Self {
+        let channel = |shift: u32| ((argb >> shift) & 0xFF) as f32 / 255.0;
+        Self::new(channel(16), channel(8), channel(0), channel(24))
+    }
+
+    pub(crate) fn to_array(self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+}
+
+/// Light type, `D3DLIGHTTYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum D3D9LightType {
+    Point = 1,
+    Spot = 2,
+    Directional = 3,
+}
+
+/// Light, `D3DLIGHT9`
+///
+/// Position and direction are in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct D3D9Light {
+    pub light_type: D3D9LightType,
+    pub diffuse: D3DColorValue,
+    pub specular: D3DColorValue,
+    pub ambient: D3DColorValue,
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+    pub range: f32,
+    pub falloff: f32,
+    pub attenuation0: f32,
+    pub attenuation1: f32,
+    pub attenuation2: f32,
+    /// Angle of the inner cone of a spot light, in radians
+    pub theta: f32,
+    /// Angle of the outer cone of a spot light, in radians
+    pub phi: f32,
+}
+
+impl Default for D3D9Light {
+    /// White directional light shining down +z, as `GetLight` reports for a
+    /// light only enabled
+    fn default() -> Self {
+        Self {
+            light_type: D3D9LightType::Directional,
+            diffuse: D3DColorValue::new(1.0, 1.0, 1.0, 0.0),
+            specular: D3DColorValue::default(),
+            ambient: D3DColorValue::default(),
+            position: [0.0; 3],
+            direction: [0.0, 0.0, 1.0],
+            range: 0.0,
+            falloff: 0.0,
+            attenuation0: 0.0,
+            attenuation1: 0.0,
+            attenuation2: 0.0,
+            theta: 0.0,
+            phi: 0.0,
+        }
+    }
+}
+
+/// Material, `D3DMATERIAL9`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct D3D9Material {
+    pub diffuse: D3DColorValue,
+    pub ambient: D3DColorValue,
+    pub specular: D3DColorValue,
+    pub emissive: D3DColorValue,
+    pub power: f32,
+}
+
+/// Render state, `D3DRENDERSTATETYPE`
+///
+/// The states the fixed-function pipeline reads; float states take the
+/// bits of the float as their value, as in D3D9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum D3D9RenderState {
+    ZEnable = 7,
+    FillMode = 8,
+    ZWriteEnable = 14,
+    AlphaTestEnable = 15,
+    CullMode = 22,
+    ZFunc = 23,
+    AlphaRef = 24,
+    AlphaFunc = 25,
+    AlphaBlendEnable = 27,
+    FogEnable = 28,
+    SpecularEnable = 29,
+    FogColor = 34,
+    FogTableMode = 35,
+    FogStart = 36,
+    FogEnd = 37,
+    FogDensity = 38,
+    TextureFactor = 60,
+    Lighting = 137,
+    Ambient = 139,
+    FogVertexMode = 140,
+    ColorVertex = 141,
+    LocalViewer = 142,
+    NormalizeNormals = 143,
+    DiffuseMaterialSource = 145,
+    SpecularMaterialSource = 146,
+    AmbientMaterialSource = 147,
+    EmissiveMaterialSource = 148,
+}
+
+/// Number of render state values
+pub(crate) const RENDER_STATE_COUNT: usize = 210;
+
+/// Fog mode, `D3DFOGMODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum D3D9FogMode {
+    None = 0,
+    Exp = 1,
+    Exp2 = 2,
+    Linear = 3,
+}
+
+impl D3D9FogMode {
+    pub(crate) fn from_u32(value: u32) -> Self {
+        match value {
+            1 => D3D9FogMode::Exp,
+            2 => D3D9FogMode::Exp2,
+            3 => D3D9FogMode::Linear,
+            _ => D3D9FogMode::None,
+        }
+    }
+}
+
+/// Source of a lighting color, `D3DMATERIALCOLORSOURCE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum D3D9MaterialColorSource {
+    /// The material's color
+    Material = 0,
+    /// The vertex diffuse color
+    Color1 = 1,
+    /// The vertex specular color
+    Color2 = 2,
+}
+
+impl D3D9MaterialColorSource {
+    pub(crate) fn from_u32(value: u32) -> Self {
+        match value {
+            1 => D3D9MaterialColorSource::Color1,
+            2 => D3D9MaterialColorSource::Color2,
+            _ => D3D9MaterialColorSource::Material,
+        }
+    }
+}
+
+/// Comparison function, `D3DCMPFUNC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum D3D9CmpFunc {
+    Never = 1,
+    Less = 2,
+    Equal = 3,
+    LessEqual = 4,
+    Greater = 5,
+    NotEqual = 6,
+    GreaterEqual = 7,
+    Always = 8,
+}
+
+impl D3D9CmpFunc {
+    pub(crate) fn from_u32(value: u32) -> Self {
+        match value {
+            1 => D3D9CmpFunc::Never,
+            2 => D3D9CmpFunc::Less,
+            3 => D3D9CmpFunc::Equal,
+            4 => D3D9CmpFunc::LessEqual,
+            5 => D3D9CmpFunc::Greater,
+            6 => D3D9CmpFunc::NotEqual,
+            7 => D3D9CmpFunc::GreaterEqual,
+            _ => D3D9CmpFunc::Always,
+        }
+    }
+}
+
+/// Texture stage state, `D3DTEXTURESTAGESTATETYPE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum D3D9TextureStageState {
+    ColorOp = 1,
+    ColorArg1 = 2,
+    ColorArg2 = 3,
+    AlphaOp = 4,
+    AlphaArg1 = 5,
+    AlphaArg2 = 6,
+    TexCoordIndex = 11,
+    ColorArg0 = 26,
+    AlphaArg0 = 27,
+    ResultArg = 28,
+    Constant = 32,
+}
+
+/// Number of texture stage state values
+pub(crate) const TEXTURE_STAGE_STATE_COUNT: usize = 33;
+
+/// Texture stage operation, `D3DTEXTUREOP`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum D3D9TextureOp {
+    Disable = 1,
+    SelectArg1 = 2,
+    SelectArg2 = 3,
+    Modulate = 4,
+    Modulate2x = 5,
+    Modulate4x = 6,
+    Add = 7,
+    AddSigned = 8,
+    AddSigned2x = 9,
+    Subtract = 10,
+    AddSmooth = 11,
+    BlendDiffuseAlpha = 12,
+    BlendTextureAlpha = 13,
+    BlendFactorAlpha = 14,
+    BlendTextureAlphaPm = 15,
+    BlendCurrentAlpha = 16,
+    PreModulate = 17,
+    ModulateAlphaAddColor = 18,
+    ModulateColorAddAlpha = 19,
+    ModulateInvAlphaAddColor = 20,
+    ModulateInvColorAddAlpha = 21,
+    BumpEnvMap = 22,
+    BumpEnvMapLuminance = 23,
+    DotProduct3 = 24,
+    MultiplyAdd = 25,
+    Lerp = 26,
+}
+
+impl D3D9TextureOp {
+    pub(crate) fn from_u32(value: u32) -> Self {
+        match value {
+            2 => D3D9TextureOp::SelectArg1,
+            3 => D3D9TextureOp::SelectArg2,
+            4 => D3D9TextureOp::Modulate,
+            5 => D3D9TextureOp::Modulate2x,
+            6 => D3D9TextureOp::Modulate4x,
+            7 => D3D9TextureOp::Add,
+            8 => D3D9TextureOp::AddSigned,
+            9 => D3D9TextureOp::AddSigned2x,
+            10 => D3D9TextureOp::Subtract,
+            11 => D3D9TextureOp::AddSmooth,
+            12 => D3D9TextureOp::BlendDiffuseAlpha,
+            13 => D3D9TextureOp::BlendTextureAlpha,
+            14 => D3D9TextureOp::BlendFactorAlpha,
+            15 => D3D9TextureOp::BlendTextureAlphaPm,
+            16 => D3D9TextureOp::BlendCurrentAlpha,
+            17 => D3D9TextureOp::PreModulate,
+            18 => D3D9TextureOp::ModulateAlphaAddColor,
+            19 => D3D9TextureOp::ModulateColorAddAlpha,
+            20 => D3D9TextureOp::ModulateInvAlphaAddColor,
+            21 => D3D9TextureOp::ModulateInvColorAddAlpha,
+            22 => D3D9TextureOp::BumpEnvMap,
+            23 => D3D9TextureOp::BumpEnvMapLuminance,
+            24 => D3D9TextureOp::DotProduct3,
+            25 => D3D9TextureOp::MultiplyAdd,
+            26 => D3D9TextureOp::Lerp,
+            _ => D3D9TextureOp::Disable,
+        }
+    }
+}
+
+/// Texture stage arguments, `D3DTA_*`
+pub mod texture_arg {
+    pub const SELECT_MASK: u32 = 0x0F;
+    pub const DIFFUSE: u32 = 0;
+    pub const CURRENT: u32 = 1;
+    pub const TEXTURE: u32 = 2;
+    pub const TFACTOR: u32 = 3;
+    pub const SPECULAR: u32 = 4;
+    pub const TEMP: u32 = 5;
+    pub const CONSTANT: u32 = 6;
+    /// Use one minus the argument
+    pub const COMPLEMENT: u32 = 0x10;
+    /// Use the argument's alpha in all channels
+    pub const ALPHA_REPLICATE: u32 = 0x20;
+}
+
+/// Fixed-function state as set through the device
+#[derive(Debug, Clone)]
+pub(crate) struct FixedFunctionState {
+    pub world: D3DMatrix,
+    pub view: D3DMatrix,
+    pub projection: D3DMatrix,
+    /// Lights by index
+    pub lights: BTreeMap<u32, D3D9Light>,
+    /// Indices of the enabled lights, in the order enabled
+    pub active_lights: Vec<u32>,
+    pub material: D3D9Material,
+    pub render_states: [u32; RENDER_STATE_COUNT],
+    pub texture_stages: [[u32; TEXTURE_STAGE_STATE_COUNT]; D3D9_MAX_TEXTURE_STAGES],
+    /// Which stages have a texture bound
+    pub textures: [bool; D3D9_MAX_TEXTURE_STAGES],
+    pub fvf: u32,
+}
+
+impl FixedFunctionState {
+    pub fn render_state(&self, state: D3D9RenderState) -> u32 {
+        self.render_states[state as usize]
+    }
+
+    pub fn render_state_f32(&self, state: D3D9RenderState) -> f32 {
+        f32::from_bits(self.render_state(state))
+    }
+
+    pub fn render_state_bool(&self, state: D3D9RenderState) -> bool {
+        self.render_state(state) != 0
+    }
+
+    pub fn stage_state(&self, stage: usize, state: D3D9TextureStageState) -> u32 {
+        self.texture_stages[stage][state as usize]
+    }
+}
+
+impl Default for FixedFunctionState {
+    fn default() -> Self {
+        let mut render_states = [0; RENDER_STATE_COUNT];
+        let defaults = [
+            (D3D9RenderState::ZEnable, 1),
+            // Solid
+            (D3D9RenderState::FillMode, 3),
+            (D3D9RenderState::ZWriteEnable, 1),
+            // Counterclockwise
+            (D3D9RenderState::CullMode, 3),
+            (D3D9RenderState::ZFunc, D3D9CmpFunc::LessEqual as u32),
+            (D3D9RenderState::AlphaFunc, D3D9CmpFunc::Always as u32),
+            (D3D9RenderState::FogEnd, 1.0f32.to_bits()),
+            (D3D9RenderState::FogDensity, 1.0f32.to_bits()),
+            (D3D9RenderState::TextureFactor, 0xFFFF_FFFF),
+            (D3D9RenderState::Lighting, 1),
+            (D3D9RenderState::ColorVertex, 1),
+            (D3D9RenderState::LocalViewer, 1),
+            (
+                D3D9RenderState::DiffuseMaterialSource,
+                D3D9MaterialColorSource::Color1 as u32,
+            ),
+            (
+                D3D9RenderState::SpecularMaterialSource,
+                D3D9MaterialColorSource::Color2 as u32,
+            ),
+        ];
+        for (state, value) in defaults {
+            render_states[state as usize] = value;
+        }
+
+        let mut texture_stages = [[0; TEXTURE_STAGE_STATE_COUNT]; D3D9_MAX_TEXTURE_STAGES];
+        for (stage, states) in texture_stages.iter_mut().enumerate() {
+            let (color_op, alpha_op) = if stage == 0 {
+                (D3D9TextureOp::Modulate, D3D9TextureOp::SelectArg1)
+            } else {
+                (D3D9TextureOp::Disable, D3D9TextureOp::Disable)
+            };
+            let defaults = [
+                (D3D9TextureStageState::ColorOp, color_op as u32),
+                (D3D9TextureStageState::ColorArg1, texture_arg::TEXTURE),
+                (D3D9TextureStageState::ColorArg2, texture_arg::CURRENT),
+                (D3D9TextureStageState::AlphaOp, alpha_op as u32),
+                (D3D9TextureStageState::AlphaArg1, texture_arg::TEXTURE),
+                (D3D9TextureStageState::AlphaArg2, texture_arg::CURRENT),
+                (D3D9TextureStageState::TexCoordIndex, stage as u32),
+                (D3D9TextureStageState::ColorArg0, texture_arg::CURRENT),
+                (D3D9TextureStageState::AlphaArg0, texture_arg::CURRENT),
+                (D3D9TextureStageState::ResultArg, texture_arg::CURRENT),
+            ];
+            for (state, value) in defaults {
+                states[state as usize] = value;
+            }
+        }
+
+        Self {
+            world: D3D_IDENTITY,
+            view: D3D_IDENTITY,
+            projection: D3D_IDENTITY,
+            lights: BTreeMap::new(),
+            active_lights: Vec::new(),
+            material: D3D9Material::default(),
+            render_states,
+            texture_stages,
+            textures: [false; D3D9_MAX_TEXTURE_STAGES],
+            fvf: 0,
+        }
+    }
+}
+
+/// Product of two matrices, `a` applied first
+pub(crate) fn multiply(a: &D3DMatrix, b: &D3DMatrix) -> D3DMatrix {
+    let mut out = [[0.0; 4]; 4];
+    for (row, out_row) in out.iter_mut().enumerate() {
+        for (col, out) in out_row.iter_mut().enumerate() {
+            *out = (0..4).map(|k| a[row][k] * b[k][col]).sum();
+        }
+    }
+    out
+}
+
+/// Inverse transpose of the upper 3x3 of `m`, for transforming normals,
+/// or `m` itself if it can't be inverted
+pub(crate) fn normal_matrix(m: &D3DMatrix) -> D3DMatrix {
+    let [a, b, c] = [m[0], m[1], m[2]];
+    let det = a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+        + a[2] * (b[0] * c[1] - b[1] * c[0]);
+    if det.abs() < f32::EPSILON {
+        return *m;
+    }
+
+    // The cofactor matrix is the inverse transpose scaled by the determinant
+    let cofactor = [
+        [
+            b[1] * c[2] - b[2] * c[1],
+            b[2] * c[0] - b[0] * c[2],
+            b[0] * c[1] - b[1] * c[0],
+        ],
+        [
+            a[2] * c[1] - a[1] * c[2],
+            a[0] * c[2] - a[2] * c[0],
+            a[1] * c[0] - a[0] * c[1],
+        ],
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ],
+    ];
+    let mut out = D3D_IDENTITY;
+    for row in 0..3 {
+        for col in 0..3 {
+            out[row][col] = cofactor[row][col] / det;
+        }
+    }
+    out
+}
+
+/// Transform a point by `m`
+pub(crate) fn transform_point(m: &D3DMatrix, p: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (col, out) in out.iter_mut().enumerate() {
+        *out = p[0] * m[0][col] + p[1] * m[1][col] + p[2] * m[2][col] + m[3][col];
+    }
+    out
+}
+
+/// Transform a direction by `m`, leaving out its translation
+pub(crate) fn transform_direction(m: &D3DMatrix, d: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (col, out) in out.iter_mut().enumerate() {
+        *out = d[0] * m[0][col] + d[1] * m[1][col] + d[2] * m[2][col];
+    }
+    out
+}
@@ -0,0 +1,364 @@
+//! Fixed-function vertex shader generation
+//!
+//! Transform and lighting as D3D9 specifies them: lighting is done in view
+//! space, with the colors of the material or the vertex as the render
+//! states select, and every enabled light unrolled for its type.
+
+use alloc::vec::Vec;
+
+use gal::ShaderStage;
+
+use super::state::{D3D9FogMode, D3D9LightType, D3D9MaterialColorSource};
+use super::{
+    input_location, varying_location, vs_constants as vc, LightingKey, VertexShaderKey,
+    VS_CONSTANTS_BINDING,
+};
+use crate::d3d9::spirv::{decoration, glsl, op, SpirvBuilder, StorageClass, BUILT_IN_POSITION};
+
+/// Interface values the lighting works from
+struct Inputs {
+    /// Vertex diffuse color, white if the vertex has none
+    diffuse: u32,
+    /// Vertex specular color, black if the vertex has none
+    specular: u32,
+    normal: Option<u32>,
+    /// View-space position
+    eye: u32,
+}
+
+/// SPIR-V of the vertex shader for `key`
+pub(super) fn generate(key: &VertexShaderKey) -> Vec<u32> {
+    let mut b = SpirvBuilder::new();
+    let float = b.type_float();
+    let vec3 = b.type_vec(3);
+    let vec4 = b.type_vec(4);
+    let mat4 = b.type_mat4();
+
+    let members: Vec<(u32, u32)> = (0..vc::MEMBERS)
+        .map(|index| {
+            let ty = if index < vc::MATRICES { mat4 } else { vec4 };
+            (ty, vc::offset(index))
+        })
+        .collect();
+    let block = b.type_block(&members);
+    let constants = b.variable(StorageClass::Uniform, block);
+    b.decorate(constants, decoration::DESCRIPTOR_SET, &[0]);
+    b.decorate(constants, decoration::BINDING, &[VS_CONSTANTS_BINDING]);
+
+    let position = input(&mut b, vec4, input_location::POSITION);
+    let white = b.constant_vec(&[1.0; 4]);
+    let black = b.constant_vec(&[0.0; 4]);
+    let diffuse = if key.diffuse {
+        let color = input(&mut b, vec4, input_location::DIFFUSE);
+        bgra(&mut b, color)
+    } else {
+        white
+    };
+    let specular = if key.specular {
+        let color = input(&mut b, vec4, input_location::SPECULAR);
+        bgra(&mut b, color)
+    } else {
+        black
+    };
+
+    let one = b.constant_f32(1.0);
+    let (clip, eye) = if key.pretransformed {
+        // Screen-space x, y and z with the reciprocal of w
+        let viewport = b.load_member(constants, vc::VIEWPORT, vec4);
+        let vec2 = b.type_vec(2);
+        let xy = b.shuffle(position, position, &[0, 1]);
+        let scale = b.shuffle(viewport, viewport, &[0, 1]);
+        let offset = b.shuffle(viewport, viewport, &[2, 3]);
+        let ndc = b.fmul(vec2, xy, scale);
+        let ndc = b.fadd(vec2, ndc, offset);
+        let z = b.extract(float, position, 2);
+        let ndc = b.construct(vec4, &[ndc, z, one]);
+        let rhw = b.extract(float, position, 3);
+        let w = b.fdiv(float, one, rhw);
+        (b.scale(vec4, ndc, w), None)
+    } else {
+        let world_view_proj = b.load_member(constants, vc::WORLD_VIEW_PROJ, mat4);
+        let clip = b.op(op::MATRIX_TIMES_VECTOR, vec4, &[world_view_proj, position]);
+        // Clip-space y points up in D3D, down in Vulkan
+        let flip = b.constant_vec(&[1.0, -1.0, 1.0, 1.0]);
+        let clip = b.fmul(vec4, clip, flip);
+
+        let world_view = b.load_member(constants, vc::WORLD_VIEW, mat4);
+        let eye = b.op(op::MATRIX_TIMES_VECTOR, vec4, &[world_view, position]);
+        (clip, Some(b.shuffle(eye, eye, &[0, 1, 2])))
+    };
+    output_built_in(&mut b, vec4, BUILT_IN_POSITION, clip);
+
+    let (out_diffuse, out_specular) = match (&key.lighting, eye) {
+        (Some(lighting), Some(eye)) => {
+            let normal = key.normal.then(|| {
+                let normal = input(&mut b, vec3, input_location::NORMAL);
+                let zero = b.constant_f32(0.0);
+                let normal = b.construct(vec4, &[normal, zero]);
+                let normal_matrix = b.load_member(constants, vc::NORMAL_MATRIX, mat4);
+                let normal = b.op(op::MATRIX_TIMES_VECTOR, vec4, &[normal_matrix, normal]);
+                let normal = b.shuffle(normal, normal, &[0, 1, 2]);
+                if lighting.normalize_normals {
+                    b.ext(glsl::NORMALIZE, vec3, &[normal])
+                } else {
+                    normal
+                }
+            });
+            let inputs = Inputs {
+                diffuse,
+                specular,
+                normal,
+                eye,
+            };
+            light(&mut b, constants, lighting, &inputs)
+        }
+        _ => (diffuse, specular),
+    };
+    output(&mut b, vec4, varying_location::DIFFUSE, out_diffuse);
+    output(&mut b, vec4, varying_location::SPECULAR, out_specular);
+
+    let fog = match key.fog {
+        D3D9FogMode::None => one,
+        // Vertex fog of pre-transformed vertices comes with the vertex
+        _ if key.pretransformed && !key.fog_table => b.extract(float, specular, 3),
+        mode => {
+            let distance = match eye {
+                Some(eye) => {
+                    let z = b.extract(float, eye, 2);
+                    b.ext(glsl::FABS, float, &[z])
+                }
+                None => b.extract(float, position, 2),
+            };
+            fog_factor(&mut b, constants, mode, distance)
+        }
+    };
+    output(&mut b, float, varying_location::FOG, fog);
+
+    let default_tex_coord = b.constant_vec(&[0.0, 0.0, 0.0, 1.0]);
+    for (index, &size) in key.tex_coord_sizes.iter().enumerate() {
+        let index = index as u32;
+        let tex_coord = if size > 0 {
+            // Missing components read as 0, and w as 1
+            input(&mut b, vec4, input_location::TEX_COORD + index)
+        } else {
+            default_tex_coord
+        };
+        output(&mut b, vec4, varying_location::TEX_COORD + index, tex_coord);
+    }
+
+    b.finish(ShaderStage::Vertex)
+}
+
+/// Lit diffuse and specular colors
+fn light(b: &mut SpirvBuilder, constants: u32, key: &LightingKey, inputs: &Inputs) -> (u32, u32) {
+    let float = b.type_float();
+    let vec3 = b.type_vec(3);
+    let vec4 = b.type_vec(4);
+    let zero = b.constant_f32(0.0);
+    let one = b.constant_f32(1.0);
+    let zero3 = b.constant_vec(&[0.0; 3]);
+    let one3 = b.constant_vec(&[1.0; 3]);
+
+    let mut material = |source: D3D9MaterialColorSource, member: u32| match source {
+        D3D9MaterialColorSource::Material => b.load_member(constants, member, vec4),
+        D3D9MaterialColorSource::Color1 => inputs.diffuse,
+        D3D9MaterialColorSource::Color2 => inputs.specular,
+    };
+    let material_diffuse = material(key.diffuse_source, vc::MATERIAL_DIFFUSE);
+    let material_ambient = material(key.ambient_source, vc::MATERIAL_AMBIENT);
+    let material_specular = material(key.specular_source, vc::MATERIAL_SPECULAR);
+    let material_emissive = material(key.emissive_source, vc::MATERIAL_EMISSIVE);
+
+    // Without a normal, only ambient and emissive light reaches the vertex
+    let normal = inputs.normal.unwrap_or(zero3);
+    let params = b.load_member(constants, vc::PARAMS, vec4);
+    let power = b.extract(float, params, 0);
+    let to_viewer = if key.local_viewer {
+        let to_viewer = b.fnegate(vec3, inputs.eye);
+        b.ext(glsl::NORMALIZE, vec3, &[to_viewer])
+    } else {
+        // View space is left-handed, looking down +z
+        b.constant_vec(&[0.0, 0.0, -1.0])
+    };
+
+    let mut ambient_sum = zero3;
+    let mut diffuse_sum = zero3;
+    let mut specular_sum = zero3;
+    for (index, light_type) in key.lights.iter().enumerate() {
+        let Some(light_type) = light_type else {
+            continue;
+        };
+        let member = |k: u32| vc::LIGHTS + index as u32 * vc::LIGHT_MEMBERS + k;
+        let load_rgb = |b: &mut SpirvBuilder, k: u32| {
+            let value = b.load_member(constants, member(k), vec4);
+            b.shuffle(value, value, &[0, 1, 2])
+        };
+
+        let (to_light, attenuation) = match light_type {
+            D3D9LightType::Directional => {
+                let direction = load_rgb(b, vc::LIGHT_DIRECTION);
+                let direction = b.ext(glsl::NORMALIZE, vec3, &[direction]);
+                (b.fnegate(vec3, direction), one)
+            }
+            D3D9LightType::Point | D3D9LightType::Spot => {
+                let position = load_rgb(b, vc::LIGHT_POSITION);
+                let to_light = b.fsub(vec3, position, inputs.eye);
+                let distance = b.ext(glsl::LENGTH, float, &[to_light]);
+                let to_light = b.ext(glsl::NORMALIZE, vec3, &[to_light]);
+
+                // 1 / (a0 + a1 d + a2 d²) within range, 0 beyond
+                let factors = b.load_member(constants, member(vc::LIGHT_ATTENUATION), vec4);
+                let a0 = b.extract(float, factors, 0);
+                let a1 = b.extract(float, factors, 1);
+                let a2 = b.extract(float, factors, 2);
+                let range = b.extract(float, factors, 3);
+                let a2d = b.fmul(float, a2, distance);
+                let sum = b.fadd(float, a1, a2d);
+                let sum = b.fmul(float, sum, distance);
+                let sum = b.fadd(float, a0, sum);
+                let attenuation = b.fdiv(float, one, sum);
+                let out_of_range = b.compare(op::FORD_GREATER_THAN, distance, range);
+                let mut attenuation = b.select(float, out_of_range, zero, attenuation);
+
+                if *light_type == D3D9LightType::Spot {
+                    // ((rho - cos φ/2) / (cos θ/2 - cos φ/2))^falloff
+                    let direction = load_rgb(b, vc::LIGHT_DIRECTION);
+                    let direction = b.ext(glsl::NORMALIZE, vec3, &[direction]);
+                    let from_light = b.fnegate(vec3, to_light);
+                    let rho = b.dot(from_light, direction);
+                    let spot = b.load_member(constants, member(vc::LIGHT_SPOT), vec4);
+                    let half_theta = b.extract(float, spot, 0);
+                    let half_phi = b.extract(float, spot, 1);
+                    let falloff = b.extract(float, spot, 2);
+                    let cos_phi = b.ext(glsl::COS, float, &[half_phi]);
+                    let cos_theta = b.ext(glsl::COS, float, &[half_theta]);
+                    // Keep the inner cone inside the outer one, so the
+                    // falloff between them has a width
+                    let epsilon = b.constant_f32(1e-4);
+                    let min_cos_theta = b.fadd(float, cos_phi, epsilon);
+                    let cos_theta = b.ext(glsl::FMAX, float, &[cos_theta, min_cos_theta]);
+                    let t = b.fsub(float, rho, cos_phi);
+                    let width = b.fsub(float, cos_theta, cos_phi);
+                    let t = b.fdiv(float, t, width);
+                    let t = b.ext(glsl::FCLAMP, float, &[t, zero, one]);
+                    let factor = b.ext(glsl::POW, float, &[t, falloff]);
+                    let inside = b.compare(op::FORD_GREATER_THAN, t, zero);
+                    let factor = b.select(float, inside, factor, zero);
+                    attenuation = b.fmul(float, attenuation, factor);
+                }
+                (to_light, attenuation)
+            }
+        };
+
+        let n_dot_l = b.dot(normal, to_light);
+        let n_dot_l = b.ext(glsl::FMAX, float, &[n_dot_l, zero]);
+
+        let ambient = load_rgb(b, vc::LIGHT_AMBIENT);
+        let ambient = b.scale(vec3, ambient, attenuation);
+        ambient_sum = b.fadd(vec3, ambient_sum, ambient);
+
+        let diffuse = load_rgb(b, vc::LIGHT_DIFFUSE);
+        let intensity = b.fmul(float, n_dot_l, attenuation);
+        let diffuse = b.scale(vec3, diffuse, intensity);
+        diffuse_sum = b.fadd(vec3, diffuse_sum, diffuse);
+
+        if key.specular {
+            let half = b.fadd(vec3, to_light, to_viewer);
+            let half = b.ext(glsl::NORMALIZE, vec3, &[half]);
+            let n_dot_h = b.dot(normal, half);
+            let n_dot_h = b.ext(glsl::FMAX, float, &[n_dot_h, zero]);
+            let highlight = b.ext(glsl::POW, float, &[n_dot_h, power]);
+            // No highlight on the side facing away from the light
+            let lit = b.compare(op::FORD_GREATER_THAN, n_dot_l, zero);
+            let highlight = b.select(float, lit, highlight, zero);
+            let intensity = b.fmul(float, highlight, attenuation);
+            let specular = load_rgb(b, vc::LIGHT_SPECULAR);
+            let specular = b.scale(vec3, specular, intensity);
+            specular_sum = b.fadd(vec3, specular_sum, specular);
+        }
+    }
+
+    let rgb = |b: &mut SpirvBuilder, v: u32| b.shuffle(v, v, &[0, 1, 2]);
+
+    // Emissive + ambient material × (global ambient + light ambient)
+    // + diffuse material × light diffuse
+    let global_ambient = b.load_member(constants, vc::GLOBAL_AMBIENT, vec4);
+    let global_ambient = rgb(b, global_ambient);
+    let ambient = b.fadd(vec3, global_ambient, ambient_sum);
+    let material_ambient_rgb = rgb(b, material_ambient);
+    let ambient = b.fmul(vec3, material_ambient_rgb, ambient);
+    let material_diffuse_rgb = rgb(b, material_diffuse);
+    let diffuse = b.fmul(vec3, material_diffuse_rgb, diffuse_sum);
+    let emissive = rgb(b, material_emissive);
+    let color = b.fadd(vec3, emissive, ambient);
+    let color = b.fadd(vec3, color, diffuse);
+    let color = b.ext(glsl::FCLAMP, vec3, &[color, zero3, one3]);
+    let alpha = b.extract(float, material_diffuse, 3);
+    let diffuse = b.construct(vec4, &[color, alpha]);
+
+    let specular = if key.specular {
+        let material_specular_rgb = rgb(b, material_specular);
+        let color = b.fmul(vec3, material_specular_rgb, specular_sum);
+        let color = b.ext(glsl::FCLAMP, vec3, &[color, zero3, one3]);
+        let alpha = b.extract(float, material_specular, 3);
+        b.construct(vec4, &[color, alpha])
+    } else {
+        b.constant_vec(&[0.0; 4])
+    };
+    (diffuse, specular)
+}
+
+/// Fog factor, 1 for no fog, at `distance` from the viewer
+fn fog_factor(b: &mut SpirvBuilder, constants: u32, mode: D3D9FogMode, distance: u32) -> u32 {
+    let float = b.type_float();
+    let vec4 = b.type_vec(4);
+    let zero = b.constant_f32(0.0);
+    let one = b.constant_f32(1.0);
+
+    let params = b.load_member(constants, vc::PARAMS, vec4);
+    let factor = match mode {
+        D3D9FogMode::None => return one,
+        D3D9FogMode::Linear => {
+            let start = b.extract(float, params, 1);
+            let end = b.extract(float, params, 2);
+            let remaining = b.fsub(float, end, distance);
+            let length = b.fsub(float, end, start);
+            b.fdiv(float, remaining, length)
+        }
+        D3D9FogMode::Exp | D3D9FogMode::Exp2 => {
+            let density = b.extract(float, params, 3);
+            let mut exponent = b.fmul(float, density, distance);
+            if mode == D3D9FogMode::Exp2 {
+                exponent = b.fmul(float, exponent, exponent);
+            }
+            let exponent = b.fnegate(float, exponent);
+            b.ext(glsl::EXP, float, &[exponent])
+        }
+    };
+    b.ext(glsl::FCLAMP, float, &[factor, zero, one])
+}
+
+/// Load of the vertex input at `location`
+fn input(b: &mut SpirvBuilder, ty: u32, location: u32) -> u32 {
+    let variable = b.variable(StorageClass::Input, ty);
+    b.decorate(variable, decoration::LOCATION, &[location]);
+    b.load(ty, variable)
+}
+
+/// Store of `value` to the output at `location`
+fn output(b: &mut SpirvBuilder, ty: u32, location: u32, value: u32) {
+    let variable = b.variable(StorageClass::Output, ty);
+    b.decorate(variable, decoration::LOCATION, &[location]);
+    b.store(variable, value);
+}
+
+fn output_built_in(b: &mut SpirvBuilder, ty: u32, built_in: u32, value: u32) {
+    let variable = b.variable(StorageClass::Output, ty);
+    b.decorate(variable, decoration::BUILT_IN, &[built_in]);
+    b.store(variable, value);
+}
+
+/// RGBA of a `D3DCOLOR`, whose bytes are in BGRA order
+fn bgra(b: &mut SpirvBuilder, color: u32) -> u32 {
+    b.shuffle(color, color, &[2, 1, 0, 3])
+}
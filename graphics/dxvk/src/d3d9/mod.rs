@@ -1,19 +1,250 @@
 //! D3D9 to Vulkan translation
+//!
+//! Draws without a shader bound use the fixed-function pipeline, emulated
+//! with generated shaders, see [`fixed_function`].
+
+pub mod fixed_function;
+mod spirv;
+
+pub use fixed_function::{
+    fvf, texture_arg, D3D9CmpFunc, D3D9FogMode, D3D9Light, D3D9LightType, D3D9Material,
+    D3D9MaterialColorSource, D3D9RenderState, D3D9TextureOp, D3D9TextureStageState,
+    D3D9TransformState, D3DColorValue, D3DMatrix, D3D9_MAX_ACTIVE_LIGHTS, D3D9_MAX_TEXTURE_STAGES,
+    D3D_IDENTITY,
+};
 
 use crate::common::{DxvkDevice, DxvkError};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use fixed_function::{FixedFunctionCache, FixedFunctionState};
+use gal::{Buffer, BufferDescriptor, Device, Image, Viewport};
 
 /// D3D9 device wrapper
 pub struct D3D9Device {
     /// Underlying DXVK device
     _device: DxvkDevice,
+    /// GAL device the shaders and constant buffers are created on
+    gal: Arc<dyn Device>,
+    /// Fixed-function state
+    state: FixedFunctionState,
+    /// Shaders generated for the fixed-function state
+    fixed_function: FixedFunctionCache,
+    /// Fixed-function vertex shader constants
+    vs_constants: Box<dyn Buffer>,
+    /// Fixed-function pixel shader constants
+    ps_constants: Box<dyn Buffer>,
+    /// Bound textures, by stage
+    textures: [Option<Arc<dyn Image>>; D3D9_MAX_TEXTURE_STAGES],
+    viewport: Viewport,
+    vertex_shader: Option<Arc<D3D9VertexShader>>,
+    pixel_shader: Option<Arc<D3D9PixelShader>>,
 }
 
 impl D3D9Device {
     /// Create D3D9 device
-    pub fn create(device: DxvkDevice) -> Result<Self, DxvkError> {
+    pub fn create(device: DxvkDevice, gal: Arc<dyn Device>) -> Result<Self, DxvkError> {
         log::info!("Creating D3D9 device");
-        Ok(Self { _device: device })
+        let vs_constants = gal.create_buffer(
+            &BufferDescriptor::uniform(fixed_function::vs_constants::SIZE as u64)
+                .label("D3D9 fixed-function VS constants"),
+        )?;
+        let ps_constants = gal.create_buffer(
+            &BufferDescriptor::uniform(fixed_function::ps_constants::SIZE as u64)
+                .label("D3D9 fixed-function PS constants"),
+        )?;
+        Ok(Self {
+            _device: device,
+            gal,
+            state: FixedFunctionState::default(),
+            fixed_function: FixedFunctionCache::default(),
+            vs_constants,
+            ps_constants,
+            textures: Default::default(),
+            viewport: Viewport::default(),
+            vertex_shader: None,
+            pixel_shader: None,
+        })
+    }
+
+    /// Set a transform matrix
+    pub fn set_transform(&mut self, state: D3D9TransformState, matrix: &D3DMatrix) {
+        *self.transform_mut(state) = *matrix;
+    }
+
+    /// Get a transform matrix
+    pub fn get_transform(&self, state: D3D9TransformState) -> D3DMatrix {
+        match state {
+            D3D9TransformState::World => self.state.world,
+            D3D9TransformState::View => self.state.view,
+            D3D9TransformState::Projection => self.state.projection,
+        }
+    }
+
+    /// Multiply a transform matrix by `matrix`, `MultiplyTransform`
+    pub fn multiply_transform(&mut self, state: D3D9TransformState, matrix: &D3DMatrix) {
+        let transform = self.transform_mut(state);
+        *transform = fixed_function::multiply(matrix, transform);
+    }
+
+    fn transform_mut(&mut self, state: D3D9TransformState) -> &mut D3DMatrix {
+        match state {
+            D3D9TransformState::World => &mut self.state.world,
+            D3D9TransformState::View => &mut self.state.view,
+            D3D9TransformState::Projection => &mut self.state.projection,
+        }
+    }
+
+    /// Set the properties of light `index`
+    pub fn set_light(&mut self, index: u32, light: &D3D9Light) -> Result<(), DxvkError> {
+        if light.light_type == D3D9LightType::Spot && light.theta > light.phi {
+            return Err(DxvkError::InvalidParameter);
+        }
+        self.state.lights.insert(index, *light);
+        Ok(())
+    }
+
+    /// Properties of light `index`
+    pub fn get_light(&self, index: u32) -> Result<D3D9Light, DxvkError> {
+        self.state
+            .lights
+            .get(&index)
+            .copied()
+            .ok_or(DxvkError::InvalidParameter)
+    }
+
+    /// Enable or disable light `index`, setting it to the default light if
+    /// it was never set
+    pub fn light_enable(&mut self, index: u32, enable: bool) -> Result<(), DxvkError> {
+        let active = &mut self.state.active_lights;
+        let position = active.iter().position(|&light| light == index);
+        match (enable, position) {
+            (true, None) => {
+                if active.len() == D3D9_MAX_ACTIVE_LIGHTS {
+                    return Err(DxvkError::NotSupported);
+                }
+                active.push(index);
+                self.state.lights.entry(index).or_default();
+            }
+            (false, Some(position)) => {
+                active.remove(position);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Whether light `index` is enabled
+    pub fn get_light_enable(&self, index: u32) -> bool {
+        self.state.active_lights.contains(&index)
+    }
+
+    /// Set the material
+    pub fn set_material(&mut self, material: &D3D9Material) {
+        self.state.material = *material;
+    }
+
+    /// Get the material
+    pub fn get_material(&self) -> D3D9Material {
+        self.state.material
+    }
+
+    /// Set a render state
+    pub fn set_render_state(&mut self, state: D3D9RenderState, value: u32) {
+        self.state.render_states[state as usize] = value;
+    }
+
+    /// Get a render state
+    pub fn get_render_state(&self, state: D3D9RenderState) -> u32 {
+        self.state.render_state(state)
+    }
+
+    /// Set a texture stage state
+    pub fn set_texture_stage_state(
+        &mut self,
+        stage: u32,
+        state: D3D9TextureStageState,
+        value: u32,
+    ) -> Result<(), DxvkError> {
+        let stage = self
+            .state
+            .texture_stages
+            .get_mut(stage as usize)
+            .ok_or(DxvkError::InvalidParameter)?;
+        stage[state as usize] = value;
+        Ok(())
+    }
+
+    /// Get a texture stage state
+    pub fn get_texture_stage_state(
+        &self,
+        stage: u32,
+        state: D3D9TextureStageState,
+    ) -> Result<u32, DxvkError> {
+        if stage as usize >= D3D9_MAX_TEXTURE_STAGES {
+            return Err(DxvkError::InvalidParameter);
+        }
+        Ok(self.state.stage_state(stage as usize, state))
+    }
+
+    /// Bind the texture of a stage
+    pub fn set_texture(
+        &mut self,
+        stage: u32,
+        texture: Option<Arc<dyn Image>>,
+    ) -> Result<(), DxvkError> {
+        let stage = stage as usize;
+        if stage >= D3D9_MAX_TEXTURE_STAGES {
+            return Err(DxvkError::InvalidParameter);
+        }
+        self.state.textures[stage] = texture.is_some();
+        self.textures[stage] = texture;
+        Ok(())
+    }
+
+    /// Texture bound to a stage
+    pub fn get_texture(&self, stage: u32) -> Option<Arc<dyn Image>> {
+        self.textures.get(stage as usize).cloned().flatten()
+    }
+
+    /// Set the flexible vertex format of the vertices drawn with the
+    /// fixed-function pipeline
+    pub fn set_fvf(&mut self, fvf: u32) -> Result<(), DxvkError> {
+        if fixed_function::vertex_layout(fvf).is_none() {
+            return Err(DxvkError::InvalidParameter);
+        }
+        self.state.fvf = fvf;
+        Ok(())
+    }
+
+    /// Get the flexible vertex format
+    pub fn get_fvf(&self) -> u32 {
+        self.state.fvf
+    }
+
+    /// Set the viewport
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    /// Get the viewport
+    pub fn get_viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// Bind a vertex shader, or `None` for the fixed-function pipeline
+    pub fn set_vertex_shader(&mut self, shader: Option<Arc<D3D9VertexShader>>) {
+        self.vertex_shader = shader;
+    }
+
+    /// Bind a pixel shader, or `None` for the fixed-function pipeline
+    pub fn set_pixel_shader(&mut self, shader: Option<Arc<D3D9PixelShader>>) {
+        self.pixel_shader = shader;
+    }
+
+    /// Number of fixed-function vertex and pixel shaders generated so far
+    pub fn fixed_function_shader_count(&self) -> (usize, usize) {
+        self.fixed_function.len()
     }
 
     /// Begin scene
@@ -48,7 +279,7 @@ impl D3D9Device {
         _primitive_count: u32,
     ) -> Result<(), DxvkError> {
         log::trace!("D3D9: DrawPrimitive");
-        Ok(())
+        self.prepare_draw()
     }
 
     /// Draw indexed primitive
@@ -62,6 +293,25 @@ impl D3D9Device {
         _primitive_count: u32,
     ) -> Result<(), DxvkError> {
         log::trace!("D3D9: DrawIndexedPrimitive");
+        self.prepare_draw()
+    }
+
+    /// Select the fixed-function shaders for the stages without a shader
+    /// bound, and update their constants
+    fn prepare_draw(&mut self) -> Result<(), DxvkError> {
+        if self.vertex_shader.is_some() && self.pixel_shader.is_some() {
+            return Ok(());
+        }
+        self.fixed_function
+            .shaders(self.gal.as_ref(), &self.state)?;
+        if self.vertex_shader.is_none() {
+            let constants = fixed_function::vertex_constants(&self.state, &self.viewport);
+            self.vs_constants.write(0, &constants)?;
+        }
+        if self.pixel_shader.is_none() {
+            let constants = fixed_function::pixel_constants(&self.state);
+            self.ps_constants.write(0, &constants)?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,494 @@
+//! Minimal SPIR-V module builder
+//!
+//! Just enough of SPIR-V for the generated fixed-function shaders: scalar,
+//! vector and matrix float types, uniform blocks, interface variables,
+//! combined image samplers and straight-line code in a single `main`.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use gal::ShaderStage;
+
+const MAGIC: u32 = 0x0723_0203;
+const VERSION_1_0: u32 = 0x0001_0000;
+
+/// Opcodes
+pub(crate) mod op {
+    pub const EXT_INST_IMPORT: u16 = 11;
+    pub const EXT_INST: u16 = 12;
+    pub const MEMORY_MODEL: u16 = 14;
+    pub const ENTRY_POINT: u16 = 15;
+    pub const EXECUTION_MODE: u16 = 16;
+    pub const CAPABILITY: u16 = 17;
+    pub const TYPE_VOID: u16 = 19;
+    pub const TYPE_BOOL: u16 = 20;
+    pub const TYPE_FLOAT: u16 = 22;
+    pub const TYPE_VECTOR: u16 = 23;
+    pub const TYPE_MATRIX: u16 = 24;
+    pub const TYPE_IMAGE: u16 = 25;
+    pub const TYPE_SAMPLED_IMAGE: u16 = 27;
+    pub const TYPE_STRUCT: u16 = 30;
+    pub const TYPE_POINTER: u16 = 32;
+    pub const TYPE_FUNCTION: u16 = 33;
+    pub const TYPE_INT: u16 = 21;
+    pub const CONSTANT_TRUE: u16 = 41;
+    pub const CONSTANT: u16 = 43;
+    pub const CONSTANT_COMPOSITE: u16 = 44;
+    pub const FUNCTION: u16 = 54;
+    pub const FUNCTION_END: u16 = 56;
+    pub const VARIABLE: u16 = 59;
+    pub const LOAD: u16 = 61;
+    pub const STORE: u16 = 62;
+    pub const ACCESS_CHAIN: u16 = 65;
+    pub const DECORATE: u16 = 71;
+    pub const MEMBER_DECORATE: u16 = 72;
+    pub const VECTOR_SHUFFLE: u16 = 79;
+    pub const COMPOSITE_CONSTRUCT: u16 = 80;
+    pub const COMPOSITE_EXTRACT: u16 = 81;
+    pub const IMAGE_SAMPLE_IMPLICIT_LOD: u16 = 87;
+    pub const FNEGATE: u16 = 127;
+    pub const FADD: u16 = 129;
+    pub const FSUB: u16 = 131;
+    pub const FMUL: u16 = 133;
+    pub const FDIV: u16 = 136;
+    pub const VECTOR_TIMES_SCALAR: u16 = 142;
+    pub const MATRIX_TIMES_VECTOR: u16 = 145;
+    pub const DOT: u16 = 148;
+    pub const LOGICAL_NOT: u16 = 168;
+    pub const SELECT: u16 = 169;
+    pub const FORD_EQUAL: u16 = 180;
+    pub const FORD_NOT_EQUAL: u16 = 182;
+    pub const FORD_LESS_THAN: u16 = 184;
+    pub const FORD_GREATER_THAN: u16 = 186;
+    pub const FORD_LESS_THAN_EQUAL: u16 = 188;
+    pub const FORD_GREATER_THAN_EQUAL: u16 = 190;
+    pub const SELECTION_MERGE: u16 = 247;
+    pub const LABEL: u16 = 248;
+    pub const BRANCH: u16 = 249;
+    pub const BRANCH_CONDITIONAL: u16 = 250;
+    pub const KILL: u16 = 252;
+    pub const RETURN: u16 = 253;
+}
+
+/// `GLSL.std.450` extended instructions
+pub(crate) mod glsl {
+    pub const FABS: u32 = 4;
+    pub const COS: u32 = 14;
+    pub const POW: u32 = 26;
+    pub const EXP: u32 = 27;
+    pub const FMAX: u32 = 40;
+    pub const FCLAMP: u32 = 43;
+    pub const FMIX: u32 = 46;
+    pub const LENGTH: u32 = 66;
+    pub const NORMALIZE: u32 = 69;
+}
+
+/// Storage classes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub(crate) enum StorageClass {
+    UniformConstant = 0,
+    Input = 1,
+    Uniform = 2,
+    Output = 3,
+}
+
+/// Decorations
+pub(crate) mod decoration {
+    pub const BLOCK: u32 = 2;
+    pub const COL_MAJOR: u32 = 5;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+/// `Position` built-in
+pub(crate) const BUILT_IN_POSITION: u32 = 0;
+
+/// SPIR-V module under construction
+///
+/// Types and constants are deduplicated, so asking for the same one twice
+/// returns the same ID. Code is appended to the body of `main`, which
+/// [`finish`](Self::finish) closes.
+pub(crate) struct SpirvBuilder {
+    next_id: u32,
+    glsl: u32,
+    main: u32,
+    capabilities: Vec<u32>,
+    annotations: Vec<u32>,
+    globals: Vec<u32>,
+    global_ids: BTreeMap<Vec<u32>, u32>,
+    interface: Vec<u32>,
+    body: Vec<u32>,
+}
+
+impl SpirvBuilder {
+    pub fn new() -> Self {
+        let mut builder = Self {
+            next_id: 1,
+            glsl: 0,
+            main: 0,
+            capabilities: Vec::new(),
+            annotations: Vec::new(),
+            globals: Vec::new(),
+            global_ids: BTreeMap::new(),
+            interface: Vec::new(),
+            body: Vec::new(),
+        };
+        // Shader capability
+        emit(&mut builder.capabilities, op::CAPABILITY, &[1]);
+        builder.glsl = builder.id();
+        builder.main = builder.id();
+
+        let label = builder.id();
+        emit(&mut builder.body, op::LABEL, &[label]);
+        builder
+    }
+
+    fn id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Type declaration, deduplicated on its opcode and operands
+    fn global(&mut self, opcode: u16, operands: &[u32]) -> u32 {
+        self.declare(opcode, None, operands)
+    }
+
+    /// Constant of type `ty`, deduplicated like types
+    fn constant(&mut self, opcode: u16, ty: u32, operands: &[u32]) -> u32 {
+        self.declare(opcode, Some(ty), operands)
+    }
+
+    fn declare(&mut self, opcode: u16, ty: Option<u32>, operands: &[u32]) -> u32 {
+        let mut key = vec![u32::from(opcode)];
+        key.extend(ty);
+        key.extend_from_slice(operands);
+        if let Some(&id) = self.global_ids.get(&key) {
+            return id;
+        }
+
+        let id = self.id();
+        let mut words: Vec<u32> = ty.into_iter().collect();
+        words.push(id);
+        words.extend_from_slice(operands);
+        emit(&mut self.globals, opcode, &words);
+        self.global_ids.insert(key, id);
+        id
+    }
+
+    pub fn type_void(&mut self) -> u32 {
+        self.global(op::TYPE_VOID, &[])
+    }
+
+    pub fn type_bool(&mut self) -> u32 {
+        self.global(op::TYPE_BOOL, &[])
+    }
+
+    pub fn type_int(&mut self) -> u32 {
+        self.global(op::TYPE_INT, &[32, 1])
+    }
+
+    pub fn type_float(&mut self) -> u32 {
+        self.global(op::TYPE_FLOAT, &[32])
+    }
+
+    /// Float vector of `n` components, or a float for 1
+    pub fn type_vec(&mut self, n: u32) -> u32 {
+        let float = self.type_float();
+        if n == 1 {
+            return float;
+        }
+        self.global(op::TYPE_VECTOR, &[float, n])
+    }
+
+    pub fn type_mat4(&mut self) -> u32 {
+        let vec4 = self.type_vec(4);
+        self.global(op::TYPE_MATRIX, &[vec4, 4])
+    }
+
+    pub fn type_pointer(&mut self, storage: StorageClass, ty: u32) -> u32 {
+        self.global(op::TYPE_POINTER, &[storage as u32, ty])
+    }
+
+    /// Combined image sampler of a 2D float image
+    pub fn type_sampled_image_2d(&mut self) -> u32 {
+        let float = self.type_float();
+        // Dim 2D, not depth, not arrayed, single-sampled, sampled
+        let image = self.global(op::TYPE_IMAGE, &[float, 1, 0, 0, 0, 1, 0]);
+        self.global(op::TYPE_SAMPLED_IMAGE, &[image])
+    }
+
+    /// Uniform block of `members`, laid out at the given byte offsets
+    ///
+    /// Not deduplicated, as its decorations belong to it.
+    pub fn type_block(&mut self, members: &[(u32, u32)]) -> u32 {
+        let id = self.id();
+        let mut words = vec![id];
+        words.extend(members.iter().map(|&(ty, _)| ty));
+        emit(&mut self.globals, op::TYPE_STRUCT, &words);
+
+        emit(
+            &mut self.annotations,
+            op::DECORATE,
+            &[id, decoration::BLOCK],
+        );
+        let mat4 = self.type_mat4();
+        for (index, &(ty, offset)) in members.iter().enumerate() {
+            let index = index as u32;
+            emit(
+                &mut self.annotations,
+                op::MEMBER_DECORATE,
+                &[id, index, decoration::OFFSET, offset],
+            );
+            if ty == mat4 {
+                emit(
+                    &mut self.annotations,
+                    op::MEMBER_DECORATE,
+                    &[id, index, decoration::COL_MAJOR],
+                );
+                emit(
+                    &mut self.annotations,
+                    op::MEMBER_DECORATE,
+                    &[id, index, decoration::MATRIX_STRIDE, 16],
+                );
+            }
+        }
+        id
+    }
+
+    pub fn constant_true(&mut self) -> u32 {
+        let bool_type = self.type_bool();
+        self.constant(op::CONSTANT_TRUE, bool_type, &[])
+    }
+
+    pub fn constant_f32(&mut self, value: f32) -> u32 {
+        let float = self.type_float();
+        self.constant(op::CONSTANT, float, &[value.to_bits()])
+    }
+
+    pub fn constant_i32(&mut self, value: i32) -> u32 {
+        let int = self.type_int();
+        self.constant(op::CONSTANT, int, &[value as u32])
+    }
+
+    /// Float vector constant, or a float for one component
+    pub fn constant_vec(&mut self, values: &[f32]) -> u32 {
+        if values.len() == 1 {
+            return self.constant_f32(values[0]);
+        }
+        let ty = self.type_vec(values.len() as u32);
+        let parts: Vec<u32> = values.iter().map(|&v| self.constant_f32(v)).collect();
+        self.constant(op::CONSTANT_COMPOSITE, ty, &parts)
+    }
+
+    /// Global variable, part of the entry point's interface if it's an input
+    /// or output
+    pub fn variable(&mut self, storage: StorageClass, ty: u32) -> u32 {
+        let pointer = self.type_pointer(storage, ty);
+        let id = self.id();
+        emit(
+            &mut self.globals,
+            op::VARIABLE,
+            &[pointer, id, storage as u32],
+        );
+        if matches!(storage, StorageClass::Input | StorageClass::Output) {
+            self.interface.push(id);
+        }
+        id
+    }
+
+    pub fn decorate(&mut self, id: u32, decoration: u32, operands: &[u32]) {
+        let mut words = vec![id, decoration];
+        words.extend_from_slice(operands);
+        emit(&mut self.annotations, op::DECORATE, &words);
+    }
+
+    /// Instruction in `main` with a result of type `ty`
+    pub fn op(&mut self, opcode: u16, ty: u32, operands: &[u32]) -> u32 {
+        let id = self.id();
+        let mut words = vec![ty, id];
+        words.extend_from_slice(operands);
+        emit(&mut self.body, opcode, &words);
+        id
+    }
+
+    /// Instruction in `main` without a result
+    pub fn op_void(&mut self, opcode: u16, operands: &[u32]) {
+        emit(&mut self.body, opcode, operands);
+    }
+
+    /// `GLSL.std.450` instruction
+    pub fn ext(&mut self, instruction: u32, ty: u32, operands: &[u32]) -> u32 {
+        let mut words = vec![self.glsl, instruction];
+        words.extend_from_slice(operands);
+        self.op(op::EXT_INST, ty, &words)
+    }
+
+    pub fn load(&mut self, ty: u32, pointer: u32) -> u32 {
+        self.op(op::LOAD, ty, &[pointer])
+    }
+
+    pub fn store(&mut self, pointer: u32, value: u32) {
+        self.op_void(op::STORE, &[pointer, value]);
+    }
+
+    /// Load member `index` of type `ty` from a uniform block
+    pub fn load_member(&mut self, block: u32, index: u32, ty: u32) -> u32 {
+        let pointer = self.type_pointer(StorageClass::Uniform, ty);
+        let index = self.constant_i32(index as i32);
+        let member = self.op(op::ACCESS_CHAIN, pointer, &[block, index]);
+        self.load(ty, member)
+    }
+
+    pub fn extract(&mut self, ty: u32, composite: u32, index: u32) -> u32 {
+        self.op(op::COMPOSITE_EXTRACT, ty, &[composite, index])
+    }
+
+    pub fn construct(&mut self, ty: u32, parts: &[u32]) -> u32 {
+        self.op(op::COMPOSITE_CONSTRUCT, ty, parts)
+    }
+
+    pub fn fadd(&mut self, ty: u32, a: u32, b: u32) -> u32 {
+        self.op(op::FADD, ty, &[a, b])
+    }
+
+    pub fn fsub(&mut self, ty: u32, a: u32, b: u32) -> u32 {
+        self.op(op::FSUB, ty, &[a, b])
+    }
+
+    /// Component-wise product
+    pub fn fmul(&mut self, ty: u32, a: u32, b: u32) -> u32 {
+        self.op(op::FMUL, ty, &[a, b])
+    }
+
+    pub fn fdiv(&mut self, ty: u32, a: u32, b: u32) -> u32 {
+        self.op(op::FDIV, ty, &[a, b])
+    }
+
+    pub fn fnegate(&mut self, ty: u32, a: u32) -> u32 {
+        self.op(op::FNEGATE, ty, &[a])
+    }
+
+    /// Vector `v` times scalar `s`
+    pub fn scale(&mut self, ty: u32, v: u32, s: u32) -> u32 {
+        self.op(op::VECTOR_TIMES_SCALAR, ty, &[v, s])
+    }
+
+    pub fn dot(&mut self, a: u32, b: u32) -> u32 {
+        let float = self.type_float();
+        self.op(op::DOT, float, &[a, b])
+    }
+
+    /// `a` if `condition`, else `b`
+    pub fn select(&mut self, ty: u32, condition: u32, a: u32, b: u32) -> u32 {
+        self.op(op::SELECT, ty, &[condition, a, b])
+    }
+
+    /// Comparison of two floats
+    pub fn compare(&mut self, opcode: u16, a: u32, b: u32) -> u32 {
+        let bool_type = self.type_bool();
+        self.op(opcode, bool_type, &[a, b])
+    }
+
+    /// Components of `a` (from 0) and `b` (after `a`'s) picked by `indices`
+    pub fn shuffle(&mut self, a: u32, b: u32, indices: &[u32]) -> u32 {
+        let ty = self.type_vec(indices.len() as u32);
+        let mut operands = vec![a, b];
+        operands.extend_from_slice(indices);
+        self.op(op::VECTOR_SHUFFLE, ty, &operands)
+    }
+
+    /// `value` repeated in a vector of `n` components
+    pub fn splat(&mut self, value: u32, n: u32) -> u32 {
+        let ty = self.type_vec(n);
+        let parts = vec![value; n as usize];
+        self.construct(ty, &parts)
+    }
+
+    /// Begin `if condition { … }`, returning the label to pass to
+    /// [`end_if`](Self::end_if)
+    pub fn begin_if(&mut self, condition: u32) -> u32 {
+        let then = self.id();
+        let merge = self.id();
+        // No selection control
+        self.op_void(op::SELECTION_MERGE, &[merge, 0]);
+        self.op_void(op::BRANCH_CONDITIONAL, &[condition, then, merge]);
+        self.op_void(op::LABEL, &[then]);
+        merge
+    }
+
+    /// End an `if` begun with [`begin_if`](Self::begin_if); `terminated` if
+    /// its block already ended, as with `OpKill`
+    pub fn end_if(&mut self, merge: u32, terminated: bool) {
+        if !terminated {
+            self.op_void(op::BRANCH, &[merge]);
+        }
+        self.op_void(op::LABEL, &[merge]);
+    }
+
+    /// Close `main` and encode the module with it as the entry point
+    pub fn finish(mut self, stage: ShaderStage) -> Vec<u32> {
+        let void = self.type_void();
+        let function_type = self.global(op::TYPE_FUNCTION, &[void]);
+        self.op_void(op::RETURN, &[]);
+        self.op_void(op::FUNCTION_END, &[]);
+
+        let mut words = vec![MAGIC, VERSION_1_0, 0, self.next_id, 0];
+        words.extend_from_slice(&self.capabilities);
+
+        let mut import = vec![self.glsl];
+        import.extend(string_words("GLSL.std.450"));
+        emit(&mut words, op::EXT_INST_IMPORT, &import);
+        // Logical addressing, GLSL450 memory model
+        emit(&mut words, op::MEMORY_MODEL, &[0, 1]);
+
+        let execution_model = match stage {
+            ShaderStage::Fragment => 4,
+            _ => 0,
+        };
+        let mut entry = vec![execution_model, self.main];
+        entry.extend(string_words("main"));
+        entry.extend_from_slice(&self.interface);
+        emit(&mut words, op::ENTRY_POINT, &entry);
+        if stage == ShaderStage::Fragment {
+            // Origin upper left
+            emit(&mut words, op::EXECUTION_MODE, &[self.main, 7]);
+        }
+
+        words.extend_from_slice(&self.annotations);
+        words.extend_from_slice(&self.globals);
+        // No function control
+        emit(
+            &mut words,
+            op::FUNCTION,
+            &[void, self.main, 0, function_type],
+        );
+        words.extend_from_slice(&self.body);
+        words
+    }
+}
+
+/// Append an instruction
+fn emit(words: &mut Vec<u32>, opcode: u16, operands: &[u32]) {
+    words.push(((operands.len() as u32 + 1) << 16) | u32::from(opcode));
+    words.extend_from_slice(operands);
+}
+
+/// Nul-terminated string literal, padded to whole words
+fn string_words(s: &str) -> Vec<u32> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.push(0);
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(0);
+    }
+    bytes
+        .chunks(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
@@ -0,0 +1,333 @@
+//! Capset negotiation
+//!
+//! The host advertises what it can do through the device feature bits and a list of capsets
+//! (virgl, venus, ...). Both are queried once when the device is created and cached here, so that
+//! every feature decision is based on what the host actually reported.
+
+use alloc::vec::Vec;
+use core::mem::{self, size_of};
+use core::slice;
+
+use gal::{DeviceCapabilities, Error, ImageFormat, ImageUsage, Result};
+
+use crate::protocol::{features, CapsetType, CommandType, ControlHeader, RespCapsetInfo};
+
+/// Access to the control queue of a VirtIO-GPU device, used while probing the host
+pub trait ControlTransport {
+    /// Negotiated device feature bits, see [`features`](crate::protocol::features)
+    fn features(&self) -> u64;
+
+    /// Value of `num_capsets` in the device configuration space
+    fn num_capsets(&self) -> u32;
+
+    /// Send `VIRTIO_GPU_CMD_GET_CAPSET_INFO`
+    fn get_capset_info(&self, index: u32) -> Result<RespCapsetInfo>;
+
+    /// Send `VIRTIO_GPU_CMD_GET_CAPSET` and return the capset data, at most `max_size` bytes
+    fn get_capset(&self, id: u32, version: u32, max_size: u32) -> Result<Vec<u8>>;
+
+    /// Send a control command and wait for the host's response, filling all of `response`
+    fn send_command(&self, request: &[u8], response: &mut [u8]) -> Result<()>;
+
+    /// Physical address and size of the host visible shared memory region, if the device has one
+    fn host_visible_region(&self) -> Option<(usize, u64)> {
+        None
+    }
+}
+
+/// Send `request` and return the host's response, failing unless it is of type `expected`
+///
+/// Both are protocol structures, plain data starting with a [`ControlHeader`].
+pub(crate) fn command<Req: Copy, Resp: Copy>(
+    transport: &dyn ControlTransport,
+    request: &Req,
+    expected: CommandType,
+) -> Result<Resp> {
+    let request =
+        unsafe { slice::from_raw_parts((request as *const Req).cast::<u8>(), size_of::<Req>()) };
+    let mut response: Resp = unsafe { mem::zeroed() };
+    let bytes = unsafe {
+        slice::from_raw_parts_mut((&mut response as *mut Resp).cast::<u8>(), size_of::<Resp>())
+    };
+    transport.send_command(request, bytes)?;
+
+    let header = unsafe {
+        (&response as *const Resp)
+            .cast::<ControlHeader>()
+            .read_unaligned()
+    };
+    check_response(&header, expected)?;
+    Ok(response)
+}
+
+pub(crate) fn check_response(header: &ControlHeader, expected: CommandType) -> Result<()> {
+    if header.cmd_type == expected as u32 {
+        Ok(())
+    } else {
+        log::warn!(
+            "gal-virtio: expected response {:#x}, host answered {:#x}",
+            expected as u32,
+            header.cmd_type
+        );
+        Err(Error::OperationFailed)
+    }
+}
+
+/// A capset reported by the host
+#[derive(Debug, Clone)]
+pub struct Capset {
+    pub id: CapsetType,
+    pub max_version: u32,
+    pub max_size: u32,
+    /// Capset data for `max_version`
+    pub data: Vec<u8>,
+}
+
+/// Everything the host told us about itself
+#[derive(Debug, Clone, Default)]
+pub struct HostCapabilities {
+    pub features: u64,
+    pub capsets: Vec<Capset>,
+}
+
+impl HostCapabilities {
+    /// Query the feature bits and all capsets from the host
+    pub fn probe(transport: &dyn ControlTransport) -> Result<Self> {
+        let features = transport.features();
+        let mut capsets = Vec::new();
+
+        for index in 0..transport.num_capsets() {
+            let info = transport.get_capset_info(index)?;
+            let Some(id) = CapsetType::from_id(info.capset_id) else {
+                log::debug!("gal-virtio: ignoring unknown capset {}", info.capset_id);
+                continue;
+            };
+            let data = transport.get_capset(
+                info.capset_id,
+                info.capset_max_version,
+                info.capset_max_size,
+            )?;
+            log::info!(
+                "gal-virtio: capset {:?} version {} ({} bytes)",
+                id,
+                info.capset_max_version,
+                data.len()
+            );
+            capsets.push(Capset {
+                id,
+                max_version: info.capset_max_version,
+                max_size: info.capset_max_size,
+                data,
+            });
+        }
+
+        Ok(Self { features, capsets })
+    }
+
+    pub fn has_feature(&self, feature: u64) -> bool {
+        self.features & feature == feature
+    }
+
+    pub fn capset(&self, id: CapsetType) -> Option<&Capset> {
+        self.capsets.iter().find(|capset| capset.id == id)
+    }
+
+    /// Highest virgl capset version, if 3D mode is available
+    pub fn virgl_version(&self) -> Option<u32> {
+        if !self.has_feature(features::VIRGL) {
+            return None;
+        }
+        self.capset(CapsetType::Virgl2)
+            .or_else(|| self.capset(CapsetType::Virgl))
+            .map(|capset| capset.max_version)
+    }
+
+    /// Venus capset version, if Vulkan contexts can be created
+    pub fn venus_version(&self) -> Option<u32> {
+        if !self.has_feature(features::CONTEXT_INIT) {
+            return None;
+        }
+        self.capset(CapsetType::Venus)
+            .map(|capset| capset.max_version)
+    }
+
+    /// Whether a 3D context with the given capset can be created
+    pub fn supports_context(&self, id: CapsetType) -> bool {
+        match id {
+            CapsetType::Virgl | CapsetType::Virgl2 => self.virgl_version().is_some(),
+            // Anything but virgl needs the capset to be passed at context creation.
+            _ => self.has_feature(features::CONTEXT_INIT) && self.capset(id).is_some(),
+        }
+    }
+
+    /// Data of the newest virgl capset
+    fn virgl_caps(&self) -> Option<&[u8]> {
+        self.capset(CapsetType::Virgl2)
+            .or_else(|| self.capset(CapsetType::Virgl))
+            .map(|capset| capset.data.as_slice())
+    }
+
+    /// Whether the host can sample from and render to `format`, according to the virgl caps
+    ///
+    /// Without a virgl capset only the formats used for 2D scanout are assumed to work.
+    pub fn supports_format(&self, format: ImageFormat) -> bool {
+        let Some(caps) = self.virgl_caps() else {
+            return matches!(format, ImageFormat::Bgra8Unorm | ImageFormat::Rgba8Unorm);
+        };
+        format_bit(caps, format, VIRGL_SAMPLER_MASK) && format_bit(caps, format, VIRGL_RENDER_MASK)
+    }
+
+    /// Whether the host can sample from `format`, according to the virgl caps
+    pub fn supports_sampling(&self, format: ImageFormat) -> bool {
+        match self.virgl_caps() {
+            Some(caps) => format_bit(caps, format, VIRGL_SAMPLER_MASK),
+            None => matches!(format, ImageFormat::Bgra8Unorm | ImageFormat::Rgba8Unorm),
+        }
+    }
+
+    /// Whether the host can create an image of `format` for `usage`, according to the virgl caps
+    ///
+    /// Without a virgl capset images are 2D resources, which only exist in the scanout formats.
+    pub fn supports_image(&self, format: ImageFormat, usage: ImageUsage) -> bool {
+        let Some(caps) = self.virgl_caps() else {
+            return !usage.contains(ImageUsage::DEPTH_STENCIL_ATTACHMENT)
+                && matches!(format, ImageFormat::Bgra8Unorm | ImageFormat::Rgba8Unorm);
+        };
+        if usage.contains(ImageUsage::DEPTH_STENCIL_ATTACHMENT) {
+            return format_bit(caps, format, VIRGL_DEPTH_STENCIL_MASK);
+        }
+        if usage.intersects(ImageUsage::COLOR_ATTACHMENT | ImageUsage::STORAGE)
+            && !format_bit(caps, format, VIRGL_RENDER_MASK)
+        {
+            return false;
+        }
+        format_bit(caps, format, VIRGL_SAMPLER_MASK)
+    }
+
+    /// `glsl_level` of the virgl caps, 0 without them
+    pub fn glsl_level(&self) -> u32 {
+        self.virgl_caps()
+            .and_then(|caps| read_u32(caps, VIRGL_GLSL_LEVEL))
+            .unwrap_or(0)
+    }
+
+    /// `max_texture_array_layers` of the virgl caps
+    pub fn max_texture_array_layers(&self) -> Option<u32> {
+        self.virgl_caps()
+            .and_then(|caps| read_u32(caps, VIRGL_MAX_TEXTURE_ARRAY_LAYERS))
+            .filter(|&layers| layers > 0)
+    }
+
+    /// Whether `struct virgl_caps_bool_set1` has the bit set
+    fn virgl_bool(&self, bit: u32) -> bool {
+        self.virgl_caps()
+            .and_then(|caps| read_u32(caps, VIRGL_BOOL_SET1))
+            .is_some_and(|bits| bits & (1 << bit) != 0)
+    }
+
+    /// GAL capabilities implied by the host features and capsets
+    pub fn device_capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::BLIT_2D | DeviceCapabilities::HW_CURSOR;
+
+        if self.has_feature(features::EDID) {
+            capabilities |= DeviceCapabilities::EDID;
+        }
+        if self.has_feature(features::RESOURCE_BLOB) {
+            capabilities |= DeviceCapabilities::BLOB_RESOURCES;
+        }
+        if self.virgl_version().is_some() || self.venus_version().is_some() {
+            capabilities |= DeviceCapabilities::RENDER_3D;
+            capabilities |= DeviceCapabilities::CONTEXTS;
+            capabilities |= DeviceCapabilities::SYNC_OBJECTS;
+        }
+        // The 3D features below are those of virgl, Venus clients ask the
+        // host Vulkan driver instead.
+        if self.virgl_version().is_some() {
+            let bc = [
+                ImageFormat::Bc1RgbaUnorm,
+                ImageFormat::Bc2RgbaUnorm,
+                ImageFormat::Bc3RgbaUnorm,
+                ImageFormat::Bc4RUnorm,
+                ImageFormat::Bc5RgUnorm,
+                ImageFormat::Bc6hRgufloat,
+                ImageFormat::Bc7RgbaUnorm,
+            ];
+            if bc.into_iter().all(|format| self.supports_sampling(format)) {
+                capabilities |= DeviceCapabilities::TEXTURE_COMPRESSION_BC;
+            }
+            if self.glsl_level() >= 150 {
+                capabilities |= DeviceCapabilities::GEOMETRY_SHADERS;
+            }
+            if self.virgl_bool(VIRGL_CAP_TESSELLATION_SHADERS) {
+                capabilities |= DeviceCapabilities::TESSELLATION_SHADERS;
+            }
+            if self.virgl_bool(VIRGL_CAP_TIMER_QUERY) {
+                capabilities |= DeviceCapabilities::TIMESTAMP_QUERIES;
+            }
+        }
+        // Venus maps device memory through blobs, it is useless without them.
+        if self.venus_version().is_some() && self.has_feature(features::RESOURCE_BLOB) {
+            capabilities |= DeviceCapabilities::VULKAN;
+            capabilities |= DeviceCapabilities::COMPUTE;
+        }
+
+        capabilities
+    }
+}
+
+/// Layout of `struct virgl_caps_v1`: the max version followed by 16-word format masks
+const VIRGL_CAPS_MASKS_OFFSET: usize = 1;
+const VIRGL_FORMAT_MASK_WORDS: usize = 16;
+const VIRGL_SAMPLER_MASK: usize = 0;
+const VIRGL_RENDER_MASK: usize = 1;
+const VIRGL_DEPTH_STENCIL_MASK: usize = 2;
+/// Words after the sampler, render, depth/stencil and vertex buffer format masks
+const VIRGL_BOOL_SET1: usize = VIRGL_CAPS_MASKS_OFFSET + 4 * VIRGL_FORMAT_MASK_WORDS;
+const VIRGL_GLSL_LEVEL: usize = VIRGL_BOOL_SET1 + 1;
+const VIRGL_MAX_TEXTURE_ARRAY_LAYERS: usize = VIRGL_BOOL_SET1 + 2;
+/// Bits of `struct virgl_caps_bool_set1`
+const VIRGL_CAP_TIMER_QUERY: u32 = 12;
+const VIRGL_CAP_TESSELLATION_SHADERS: u32 = 24;
+
+/// Whether the format is set in a format mask of the virgl caps
+fn format_bit(caps: &[u8], format: ImageFormat, mask: usize) -> bool {
+    let Some(virgl_format) = virgl_format(format) else {
+        return false;
+    };
+    let word = VIRGL_CAPS_MASKS_OFFSET + mask * VIRGL_FORMAT_MASK_WORDS + virgl_format / 32;
+    read_u32(caps, word).is_some_and(|bits| bits & (1 << (virgl_format % 32)) != 0)
+}
+
+fn read_u32(data: &[u8], word: usize) -> Option<u32> {
+    let bytes = data.get(word * 4..word * 4 + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// `enum virgl_formats` value of a GAL format
+fn virgl_format(format: ImageFormat) -> Option<usize> {
+    Some(match format {
+        ImageFormat::Bgra8Unorm => 1,
+        ImageFormat::Rgba8Unorm => 67,
+        ImageFormat::Bgra8UnormSrgb => 100,
+        ImageFormat::Rgba8UnormSrgb => 104,
+        ImageFormat::R8Unorm => 64,
+        ImageFormat::Rg8Unorm => 65,
+        ImageFormat::R16Float => 91,
+        ImageFormat::Rg16Float => 92,
+        ImageFormat::Depth16Unorm => 16,
+        ImageFormat::Depth32Float => 18,
+        ImageFormat::Depth24PlusStencil8 => 19,
+        ImageFormat::Depth24Plus => 21,
+        ImageFormat::Depth32FloatStencil8 => 136,
+        ImageFormat::R32Float => 28,
+        ImageFormat::Rg32Float => 29,
+        ImageFormat::Bc4RUnorm => 177,
+        ImageFormat::Bc5RgUnorm => 179,
+        ImageFormat::Bc1RgbaUnorm => 201,
+        ImageFormat::Bc2RgbaUnorm => 202,
+        ImageFormat::Bc3RgbaUnorm => 203,
+        ImageFormat::Bc7RgbaUnorm => 255,
+        ImageFormat::Bc6hRgufloat => 258,
+        _ => return None,
+    })
+}
@@ -8,14 +8,15 @@ use core::sync::atomic::{AtomicU32, Ordering};
 
 use gal::command::{
     AccessFlags, BufferCopy, BufferImageCopy, BufferMemoryBarrier, ColorAttachment,
-    DepthStencilAttachment, DrawCommand, DrawIndexedCommand, Filter, ImageAspect, ImageBlit,
-    ImageCopy, ImageLayout, ImageMemoryBarrier, ImageSubresourceLayers, ImageSubresourceRange,
-    IndexType, LoadOp, MemoryBarrier, PipelineBarrier, PipelineStageFlags, RenderPassDescriptor,
-    ShaderStageFlags, StoreOp,
+    CommandBufferState, DepthStencilAttachment, DrawCommand, DrawIndexedCommand, Filter,
+    ImageAspect, ImageBlit, ImageCopy, ImageLayout, ImageMemoryBarrier, ImageSubresourceLayers,
+    ImageSubresourceRange, IndexType, LoadOp, MemoryBarrier, PipelineBarrier, PipelineStageFlags,
+    RenderPassDescriptor, ShaderStageFlags, StoreOp,
 };
+use gal::debug::{self, LabelEvent};
 use gal::{
-    Buffer, ClearValue, CommandBuffer, CommandBufferState, CommandPool, Error, Image, Pipeline,
-    QueueType, Rect2D, Result, Viewport,
+    Buffer, ClearValue, CommandBuffer, CommandPool, DebugLabel, Error, Image, ObjectType, Pipeline,
+    QueryPool, QueryType, QueueType, Rect2D, Result, Viewport,
 };
 
 /// VirtIO command pool
@@ -145,6 +146,13 @@ pub enum RecordedCommand {
         offset: u32,
         data: Vec<u8>,
     },
+    BeginDebugLabel(DebugLabel),
+    EndDebugLabel,
+    InsertDebugLabel(DebugLabel),
+    WriteTimestamp {
+        pool_handle: usize,
+        query: u32,
+    },
 }
 
 /// Simplified color attachment info for recording
@@ -174,6 +182,7 @@ pub struct VirtioCommandBuffer {
     state: spin::RwLock<CommandBufferState>,
     commands: spin::Mutex<Vec<RecordedCommand>>,
     in_render_pass: spin::RwLock<bool>,
+    label_depth: AtomicU32,
 }
 
 impl VirtioCommandBuffer {
@@ -184,6 +193,7 @@ impl VirtioCommandBuffer {
             state: spin::RwLock::new(CommandBufferState::Initial),
             commands: spin::Mutex::new(Vec::new()),
             in_render_pass: spin::RwLock::new(false),
+            label_depth: AtomicU32::new(0),
         }
     }
 
@@ -205,6 +215,7 @@ impl CommandBuffer for VirtioCommandBuffer {
         }
 
         self.commands.lock().clear();
+        self.label_depth.store(0, Ordering::Relaxed);
         *self.state.write() = CommandBufferState::Recording;
         Ok(())
     }
@@ -218,6 +229,13 @@ impl CommandBuffer for VirtioCommandBuffer {
             return Err(Error::CommandBufferError("Render pass not ended".into()));
         }
 
+        if self.label_depth.load(Ordering::Relaxed) != 0 {
+            return Err(Error::CommandBufferError(alloc::format!(
+                "Debug label region not ended in {}",
+                debug::describe(gal::ObjectType::CommandBuffer, self.handle)
+            )));
+        }
+
         *self.state.write() = CommandBufferState::Executable;
         Ok(())
     }
@@ -226,6 +244,7 @@ impl CommandBuffer for VirtioCommandBuffer {
         self.commands.lock().clear();
         *self.state.write() = CommandBufferState::Initial;
         *self.in_render_pass.write() = false;
+        self.label_depth.store(0, Ordering::Relaxed);
         Ok(())
     }
 
@@ -467,4 +486,49 @@ impl CommandBuffer for VirtioCommandBuffer {
             data: data.to_vec(),
         });
     }
+
+    fn begin_debug_label(&mut self, label: &DebugLabel) {
+        debug::trace_label(self.handle, LabelEvent::Begin, Some(label));
+        self.label_depth.fetch_add(1, Ordering::Relaxed);
+        self.commands
+            .lock()
+            .push(RecordedCommand::BeginDebugLabel(label.clone()));
+    }
+
+    fn end_debug_label(&mut self) {
+        if self.label_depth.load(Ordering::Relaxed) == 0 {
+            log::warn!(
+                "gal-virtio: end_debug_label without open region in {}",
+                debug::describe(gal::ObjectType::CommandBuffer, self.handle)
+            );
+            return;
+        }
+        debug::trace_label(self.handle, LabelEvent::End, None);
+        self.label_depth.fetch_sub(1, Ordering::Relaxed);
+        self.commands.lock().push(RecordedCommand::EndDebugLabel);
+    }
+
+    fn insert_debug_label(&mut self, label: &DebugLabel) {
+        debug::trace_label(self.handle, LabelEvent::Insert, Some(label));
+        self.commands
+            .lock()
+            .push(RecordedCommand::InsertDebugLabel(label.clone()));
+    }
+
+    fn write_timestamp(&mut self, pool: &dyn QueryPool, query: u32) -> Result<()> {
+        if pool.query_type() != QueryType::Timestamp || query >= pool.count() {
+            return Err(Error::InvalidParameter);
+        }
+        self.commands.lock().push(RecordedCommand::WriteTimestamp {
+            pool_handle: pool.handle(),
+            query,
+        });
+        Ok(())
+    }
+}
+
+impl Drop for VirtioCommandBuffer {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::CommandBuffer, self.handle);
+    }
 }
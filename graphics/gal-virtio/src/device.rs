@@ -4,21 +4,31 @@
 
 use alloc::boxed::Box;
 use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 
+use pcid_interface::PciFunctionHandle;
 use spin::Mutex;
+use virtio_core::Device as VirtioDevice;
 
+use gal::debug;
+use gal::device::{DisplayInfo, GraphicsPipelineDescriptor, SwapchainConfig};
+use gal::queue::SubmitInfo;
 use gal::{
     Buffer, BufferDescriptor, CommandPool, Device, DeviceCapabilities, DeviceInfo, DeviceType,
-    DisplayInfo, Error, Extent2D, Fence, GraphicsPipelineDescriptor, Image, ImageDescriptor,
-    Memory, MemoryType, Pipeline, Queue, QueueType, Result, Semaphore, Shader, ShaderStage,
-    SwapchainConfig,
+    DisplayTarget, Error, Extent2D, Feature, Fence, Image, ImageDescriptor, ImageFormat, Memory,
+    MemoryType, ObjectType, Pipeline, PresentMode, QueryPool, QueryType, Queue, QueueType, Rect2D,
+    Result, Semaphore, Shader, ShaderStage, Swapchain,
 };
 
+use crate::capset::{command, ControlTransport, HostCapabilities};
 use crate::command::VirtioCommandPool;
-use crate::protocol::{self, CapsetType, CommandType, ControlHeader, MAX_SCANOUTS};
+use crate::hostmem::{BlobMapping, HostMemRegion};
+use crate::protocol::{self, ctx_init_flags, CapsetType, CommandType, ControlHeader, MAX_SCANOUTS};
 use crate::resource::{VirtioBuffer, VirtioImage, VirtioMemory};
+use crate::transport::QueueTransport;
 
 /// Next resource ID counter
 static NEXT_RESOURCE_ID: AtomicU32 = AtomicU32::new(1);
@@ -36,18 +46,43 @@ pub fn alloc_fence_id() -> u64 {
     NEXT_FENCE_ID.fetch_add(1, Ordering::SeqCst)
 }
 
+/// Send `VIRTIO_GPU_CMD_CTX_CREATE` for a context speaking `capset`
+fn create_context(
+    control: &dyn ControlTransport,
+    ctx_id: u32,
+    capset: CapsetType,
+    name: &[u8],
+) -> Result<()> {
+    // virgl contexts are the default, the capset is only passed with CONTEXT_INIT.
+    let context_init = match capset {
+        CapsetType::Virgl | CapsetType::Virgl2 => 0,
+        _ => capset as u32 & ctx_init_flags::CAPSET_ID_MASK,
+    };
+    let request = protocol::CtxCreate::new(ctx_id, context_init, name);
+    command::<_, ControlHeader>(control, &request, CommandType::RespOkNodata)?;
+    Ok(())
+}
+
 /// VirtIO-GPU device implementation
 pub struct VirtioGpuDevice {
     /// Device info
     info: DeviceInfo,
     /// Displays
     displays: Vec<DisplayInfo>,
+    /// Scanouts of the displays
+    targets: Vec<Arc<VirtioDisplay>>,
     /// Control queue state
     control_queue: Mutex<ControlQueueState>,
     /// Graphics queue
     graphics_queue: VirtioQueue,
-    /// Available capsets
-    capsets: Vec<CapsetInfo>,
+    /// Features and capsets reported by the host
+    host: HostCapabilities,
+    /// Control queue commands are sent on
+    control: Arc<dyn ControlTransport + Send + Sync>,
+    /// Host visible region blobs are mapped into
+    hostmem: Option<Arc<HostMemRegion>>,
+    /// Context owning the mapped blobs
+    blob_ctx_id: Option<u32>,
     /// Next context ID
     next_ctx_id: AtomicU32,
 }
@@ -88,45 +123,44 @@ impl Queue for VirtioQueue {
     }
 }
 
-/// Capset information
-#[derive(Debug, Clone)]
-struct CapsetInfo {
-    id: CapsetType,
-    max_version: u32,
-    max_size: u32,
-}
-
 impl VirtioGpuDevice {
-    /// Create a new VirtIO-GPU device
-    pub fn create() -> Result<Self> {
-        // In a real implementation, this would probe the PCI device
-        // and initialize the virtio queues
-
-        let mut capabilities = DeviceCapabilities::BLIT_2D | DeviceCapabilities::HW_CURSOR;
-
-        // Check for 3D support
-        let capsets = Self::probe_capsets()?;
-
-        let has_virgl = capsets
-            .iter()
-            .any(|c| c.id == CapsetType::Virgl || c.id == CapsetType::Virgl2);
-        let has_venus = capsets.iter().any(|c| c.id == CapsetType::Venus);
-
-        if has_virgl || has_venus {
-            capabilities |= DeviceCapabilities::RENDER_3D;
-            capabilities |= DeviceCapabilities::CONTEXTS;
-            capabilities |= DeviceCapabilities::SYNC_OBJECTS;
-        }
-
-        if has_venus {
-            capabilities |= DeviceCapabilities::VULKAN;
-        }
+    /// Create a new VirtIO-GPU device on a device probed by `virtio-core`
+    ///
+    /// This negotiates the features, starts the device and queries the host's capsets over its
+    /// control queue. The device keeps using the queue, so it must live for the rest of the
+    /// driver.
+    pub fn create(
+        device: &'static VirtioDevice,
+        pcid_handle: &mut PciFunctionHandle,
+    ) -> Result<Self> {
+        Self::create_with_transport(Arc::new(QueueTransport::setup(device, pcid_handle)?))
+    }
+
+    /// Create a new VirtIO-GPU device, querying the host's features and capsets and sending all
+    /// later commands through `transport`
+    pub fn create_with_transport(
+        transport: Arc<dyn ControlTransport + Send + Sync>,
+    ) -> Result<Self> {
+        // The region is the one the transport's device reported
+        let hostmem = transport
+            .host_visible_region()
+            .map(|(phys, size)| Arc::new(unsafe { HostMemRegion::new(phys, size) }));
+        let host = HostCapabilities::probe(&*transport)?;
+        Self::with_host_capabilities(host, transport, hostmem)
+    }
+
+    fn with_host_capabilities(
+        host: HostCapabilities,
+        control: Arc<dyn ControlTransport + Send + Sync>,
+        hostmem: Option<Arc<HostMemRegion>>,
+    ) -> Result<Self> {
+        let capabilities = host.device_capabilities();
 
         let info = DeviceInfo {
             name: String::from("VirtIO GPU"),
             vendor_id: 0x1AF4, // Red Hat
             device_id: 0x1050, // VirtIO GPU
-            device_type: if has_venus {
+            device_type: if capabilities.contains(DeviceCapabilities::RENDER_3D) {
                 DeviceType::VirtioGpu3D
             } else {
                 DeviceType::VirtioGpu
@@ -135,7 +169,7 @@ impl VirtioGpuDevice {
             display_count: 1, // Will be updated after querying displays
             max_texture_2d: 16384,
             max_texture_3d: 2048,
-            max_texture_layers: 2048,
+            max_texture_layers: host.max_texture_array_layers().unwrap_or(2048),
             max_uniform_buffer_size: 65536,
             max_storage_buffer_size: 128 * 1024 * 1024,
             max_push_constant_size: 256,
@@ -143,29 +177,71 @@ impl VirtioGpuDevice {
             max_compute_work_group_size: [1024, 1024, 64],
             max_compute_work_group_invocations: 1024,
             total_memory: 256 * 1024 * 1024, // 256 MB default
+            // GL timer queries count nanoseconds
+            timestamp_period: if capabilities.contains(DeviceCapabilities::TIMESTAMP_QUERIES) {
+                1.0
+            } else {
+                0.0
+            },
+        };
+
+        // Host blobs belong to a context, mapped ones get one of their own
+        let blob_capset = [CapsetType::Virgl2, CapsetType::Virgl, CapsetType::Venus]
+            .into_iter()
+            .find(|&capset| host.supports_context(capset));
+        let next_ctx_id = AtomicU32::new(1);
+        let blob_ctx_id = match (&hostmem, blob_capset) {
+            (Some(_), Some(capset))
+                if capabilities.contains(DeviceCapabilities::BLOB_RESOURCES) =>
+            {
+                let ctx_id = next_ctx_id.fetch_add(1, Ordering::SeqCst);
+                match create_context(&*control, ctx_id, capset, b"gal-hostmem") {
+                    Ok(()) => Some(ctx_id),
+                    Err(err) => {
+                        log::warn!("gal-virtio: no context for host memory, copying: {}", err);
+                        None
+                    }
+                }
+            }
+            _ => None,
         };
 
         let displays = Self::query_displays()?;
+        let targets = displays
+            .iter()
+            .map(|display| Arc::new(VirtioDisplay::new(display.clone())))
+            .collect();
 
         Ok(Self {
             info,
             displays,
+            targets,
             control_queue: Mutex::new(ControlQueueState {
                 pending_fences: Vec::new(),
             }),
             graphics_queue: VirtioQueue {
                 queue_type: QueueType::Graphics,
             },
-            capsets,
-            next_ctx_id: AtomicU32::new(1),
+            host,
+            control,
+            hostmem,
+            blob_ctx_id,
+            next_ctx_id,
         })
     }
 
-    /// Probe available capsets
-    fn probe_capsets() -> Result<Vec<CapsetInfo>> {
-        // In a real implementation, this would query the device for capsets
-        // For now, return empty (2D only mode)
-        Ok(Vec::new())
+    /// Features and capsets reported by the host
+    pub fn host_capabilities(&self) -> &HostCapabilities {
+        &self.host
+    }
+
+    /// Scanout presenting on display `id`, for compositors driving their own
+    /// swapchains
+    pub fn display_target(&self, id: usize) -> Option<Arc<dyn DisplayTarget>> {
+        self.targets
+            .iter()
+            .find(|target| target.info.id == id)
+            .map(|target| target.clone() as Arc<dyn DisplayTarget>)
     }
 
     /// Query display information
@@ -183,26 +259,19 @@ impl VirtioGpuDevice {
 
     /// Create a 3D context for Vulkan/OpenGL
     pub fn create_3d_context(&self, name: &str, capset: CapsetType) -> Result<u32> {
-        if !self
-            .info
-            .capabilities
-            .contains(DeviceCapabilities::CONTEXTS)
-        {
+        if !self.host.supports_context(capset) {
             return Err(Error::NotSupported);
         }
 
         let ctx_id = self.next_ctx_id.fetch_add(1, Ordering::SeqCst);
-
-        // In a real implementation, this would send VIRTIO_GPU_CMD_CTX_CREATE
-        let _request = protocol::CtxCreate::new(ctx_id, capset as u32, name.as_bytes());
-
+        create_context(&*self.control, ctx_id, capset, name.as_bytes())?;
         Ok(ctx_id)
     }
 
     /// Destroy a 3D context
     pub fn destroy_3d_context(&self, ctx_id: u32) -> Result<()> {
-        // In a real implementation, this would send VIRTIO_GPU_CMD_CTX_DESTROY
-        let _request = protocol::CtxDestroy::new(ctx_id);
+        let request = protocol::CtxDestroy::new(ctx_id);
+        command::<_, ControlHeader>(&*self.control, &request, CommandType::RespOkNodata)?;
         Ok(())
     }
 
@@ -213,16 +282,50 @@ impl VirtioGpuDevice {
         Ok(())
     }
 
+    /// Whether host visible allocations are mapped from host memory instead of copied
+    pub fn maps_host_memory(&self) -> bool {
+        self.blob_ctx_id.is_some()
+    }
+
+    /// Map a blob for a host visible allocation of `size` bytes, `None` to fall back to guest
+    /// memory and transfers
+    fn map_blob(&self, size: u64, memory_type: MemoryType) -> Option<BlobMapping> {
+        if !memory_type.is_host_visible() {
+            return None;
+        }
+        match BlobMapping::create(
+            self.hostmem.as_ref()?,
+            &self.control,
+            self.blob_ctx_id?,
+            size,
+        ) {
+            Ok(blob) => Some(blob),
+            Err(Error::OutOfDeviceMemory) => {
+                log::debug!(
+                    "gal-virtio: host visible region full, copying {} bytes",
+                    size
+                );
+                None
+            }
+            Err(err) => {
+                log::warn!(
+                    "gal-virtio: failed to map a blob, copying {} bytes: {}",
+                    size,
+                    err
+                );
+                None
+            }
+        }
+    }
+
     /// Check if Venus (Vulkan) is supported
     pub fn supports_venus(&self) -> bool {
-        self.capsets.iter().any(|c| c.id == CapsetType::Venus)
+        self.host.supports_context(CapsetType::Venus)
     }
 
     /// Check if virgl (OpenGL) is supported
     pub fn supports_virgl(&self) -> bool {
-        self.capsets
-            .iter()
-            .any(|c| c.id == CapsetType::Virgl || c.id == CapsetType::Virgl2)
+        self.host.supports_context(CapsetType::Virgl)
     }
 }
 
@@ -244,16 +347,40 @@ impl Device for VirtioGpuDevice {
     }
 
     fn create_buffer(&self, descriptor: &BufferDescriptor) -> Result<Box<dyn Buffer>> {
+        if let Some(blob) = self.map_blob(descriptor.size, descriptor.memory_type) {
+            return Ok(Box::new(VirtioBuffer::with_blob(blob, descriptor)));
+        }
         let resource_id = alloc_resource_id();
+        // Buffer resources only exist with virgl, 2D devices keep buffers to themselves
+        if self.host.virgl_version().is_some() {
+            return Ok(Box::new(VirtioBuffer::with_backing(
+                &self.control,
+                resource_id,
+                descriptor,
+            )?));
+        }
         Ok(Box::new(VirtioBuffer::new(resource_id, descriptor)))
     }
 
     fn create_image(&self, descriptor: &ImageDescriptor) -> Result<Box<dyn Image>> {
+        descriptor.validate()?;
+        // virgl has no multi-planar resources, so YUV_IMAGES is never set
+        self.info.check_image(descriptor)?;
+        if !self
+            .host
+            .supports_image(descriptor.format, descriptor.usage)
+        {
+            return Err(Error::FeatureNotPresent(Feature::Format(descriptor.format)));
+        }
+
         let resource_id = alloc_resource_id();
         Ok(Box::new(VirtioImage::new(resource_id, descriptor)))
     }
 
     fn allocate_memory(&self, size: u64, memory_type: MemoryType) -> Result<Box<dyn Memory>> {
+        if let Some(blob) = self.map_blob(size, memory_type) {
+            return Ok(Box::new(VirtioMemory::with_blob(blob, memory_type)));
+        }
         Ok(Box::new(VirtioMemory::new(size, memory_type)))
     }
 
@@ -266,6 +393,12 @@ impl Device for VirtioGpuDevice {
         Ok(Box::new(VirtioSemaphore::new()))
     }
 
+    fn create_query_pool(&self, query_type: QueryType, count: u32) -> Result<Box<dyn QueryPool>> {
+        self.info
+            .require(Feature::Capabilities(DeviceCapabilities::TIMESTAMP_QUERIES))?;
+        Ok(Box::new(VirtioQueryPool::new(query_type, count)))
+    }
+
     fn create_shader(&self, stage: ShaderStage, code: &[u8]) -> Result<Box<dyn Shader>> {
         Ok(Box::new(VirtioShader::new(stage, code)))
     }
@@ -303,7 +436,10 @@ impl Device for VirtioGpuDevice {
         &self,
         config: &SwapchainConfig,
     ) -> Result<Box<dyn gal::device::Swapchain>> {
-        Ok(Box::new(VirtioSwapchain::new(config)?))
+        let target = self
+            .display_target(config.display_id)
+            .ok_or(Error::InvalidParameter)?;
+        Ok(Box::new(Swapchain::new(target, config)?))
     }
 }
 
@@ -344,6 +480,12 @@ impl Fence for VirtioFence {
     }
 }
 
+impl Drop for VirtioFence {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Fence, self.handle);
+    }
+}
+
 /// VirtIO semaphore implementation
 pub struct VirtioSemaphore {
     handle: usize,
@@ -364,6 +506,73 @@ impl Semaphore for VirtioSemaphore {
     }
 }
 
+impl Drop for VirtioSemaphore {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Semaphore, self.handle);
+    }
+}
+
+/// VirtIO query pool, backed by virgl timer queries
+pub struct VirtioQueryPool {
+    handle: usize,
+    query_type: QueryType,
+    results: Mutex<Vec<Option<u64>>>,
+}
+
+impl VirtioQueryPool {
+    fn new(query_type: QueryType, count: u32) -> Self {
+        static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
+        Self {
+            handle: NEXT_HANDLE.fetch_add(1, Ordering::SeqCst) as usize,
+            query_type,
+            results: Mutex::new(vec![None; count as usize]),
+        }
+    }
+}
+
+impl QueryPool for VirtioQueryPool {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn query_type(&self) -> QueryType {
+        self.query_type
+    }
+
+    fn count(&self) -> u32 {
+        self.results.lock().len() as u32
+    }
+
+    fn reset(&self, first: u32, count: u32) -> Result<()> {
+        let range = first as usize..first as usize + count as usize;
+        self.results
+            .lock()
+            .get_mut(range)
+            .ok_or(Error::InvalidParameter)?
+            .fill(None);
+        Ok(())
+    }
+
+    fn results(&self, first: u32, results: &mut [Option<u64>]) -> Result<()> {
+        // In a real implementation, this would read back
+        // VIRGL_CCMD_GET_QUERY_RESULT for queries not available yet
+        let range = first as usize..first as usize + results.len();
+        results.copy_from_slice(
+            self.results
+                .lock()
+                .get(range)
+                .ok_or(Error::InvalidParameter)?,
+        );
+        Ok(())
+    }
+}
+
+impl Drop for VirtioQueryPool {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::QueryPool, self.handle);
+    }
+}
+
 /// VirtIO shader implementation
 pub struct VirtioShader {
     handle: usize,
@@ -396,6 +605,12 @@ impl Shader for VirtioShader {
     }
 }
 
+impl Drop for VirtioShader {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Shader, self.handle);
+    }
+}
+
 /// VirtIO pipeline implementation
 pub struct VirtioPipeline {
     handle: usize,
@@ -430,63 +645,122 @@ impl Pipeline for VirtioPipeline {
     }
 }
 
-/// VirtIO swapchain implementation
-pub struct VirtioSwapchain {
-    extent: Extent2D,
-    buffer_count: u32,
-    images: Vec<VirtioImage>,
-    current_image: AtomicU32,
+impl Drop for VirtioPipeline {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Pipeline, self.handle);
+    }
 }
 
-impl VirtioSwapchain {
-    fn new(config: &SwapchainConfig) -> Result<Self> {
-        let buffer_count = config.buffer_count.max(2).min(3);
-        let mut images = Vec::with_capacity(buffer_count as usize);
+/// VirtIO scanout presenting on one display
+///
+/// The host shows a resource as soon as it is flushed, so flips never stay
+/// pending and there is no vertical blank to wait for.
+pub struct VirtioDisplay {
+    info: DisplayInfo,
+    queue: VirtioQueue,
+    /// Resource the scanout currently reads
+    scanout_resource: AtomicU32,
+    /// Monotonic time of the last flush in nanoseconds, 0 if unknown
+    flushed_at: AtomicU64,
+}
 
-        for _ in 0..buffer_count {
-            let resource_id = alloc_resource_id();
-            let desc = ImageDescriptor::render_target(
-                config.extent.width,
-                config.extent.height,
-                gal::ImageFormat::Bgra8Unorm,
-            );
-            images.push(VirtioImage::new(resource_id, &desc));
+impl VirtioDisplay {
+    fn new(info: DisplayInfo) -> Self {
+        Self {
+            info,
+            queue: VirtioQueue {
+                queue_type: QueueType::Graphics,
+            },
+            scanout_resource: AtomicU32::new(0),
+            flushed_at: AtomicU64::new(0),
         }
-
-        Ok(Self {
-            extent: config.extent,
-            buffer_count,
-            images,
-            current_image: AtomicU32::new(0),
-        })
     }
 }
 
-impl gal::device::Swapchain for VirtioSwapchain {
-    fn extent(&self) -> Extent2D {
-        self.extent
+impl DisplayTarget for VirtioDisplay {
+    fn info(&self) -> DisplayInfo {
+        self.info.clone()
     }
 
-    fn buffer_count(&self) -> u32 {
-        self.buffer_count
+    fn formats(&self) -> &[ImageFormat] {
+        &[ImageFormat::Bgra8Unorm, ImageFormat::Rgba8Unorm]
     }
 
-    fn acquire_next_image(
-        &self,
-        _timeout_ns: u64,
-        _semaphore: Option<&dyn Semaphore>,
-        _fence: Option<&dyn Fence>,
-    ) -> Result<u32> {
-        let current = self.current_image.fetch_add(1, Ordering::SeqCst);
-        Ok(current % self.buffer_count)
+    fn supports_present_mode(&self, _mode: PresentMode) -> bool {
+        true
+    }
+
+    fn create_image(&self, extent: Extent2D, format: ImageFormat) -> Result<Box<dyn Image>> {
+        let desc = ImageDescriptor::scanout(extent.width, extent.height, format);
+        Ok(Box::new(VirtioImage::new(alloc_resource_id(), &desc)))
+    }
+
+    fn destroy_image(&self, image: Box<dyn Image>) {
+        // In a real implementation, this would send VIRTIO_GPU_CMD_RESOURCE_UNREF
+        let _request = protocol::ResourceUnref::new(image.handle() as u32);
+    }
+
+    /// Only the damaged regions are flushed to the host, unless the
+    /// scanout switches resources
+    fn flip(&self, image: &dyn Image, damage: &[Rect2D]) -> Result<()> {
+        let resource_id = image.handle() as u32;
+        let extent = image.extent_2d();
+        let rect = protocol::Rect::new(0, 0, extent.width, extent.height);
+
+        // In a real implementation, these would be sent on the control queue
+        if self.scanout_resource.swap(resource_id, Ordering::SeqCst) != resource_id {
+            let _set_scanout = protocol::SetScanout::new(self.info.id as u32, resource_id, rect);
+        }
+        if damage.is_empty() {
+            let _flush = protocol::ResourceFlush::new(resource_id, rect);
+        }
+        for damaged in damage {
+            let x = damaged.offset.x.max(0) as u32;
+            let y = damaged.offset.y.max(0) as u32;
+            let width = damaged.extent.width.min(extent.width.saturating_sub(x));
+            let height = damaged.extent.height.min(extent.height.saturating_sub(y));
+            if width > 0 && height > 0 {
+                let _flush = protocol::ResourceFlush::new(
+                    resource_id,
+                    protocol::Rect::new(x, y, width, height),
+                );
+            }
+        }
+        self.flushed_at
+            .store(gal::display::monotonic_ns().unwrap_or(0), Ordering::Relaxed);
+        Ok(())
     }
 
-    fn image(&self, index: u32) -> &dyn Image {
-        &self.images[index as usize]
+    fn flip_timestamp_ns(&self) -> Option<u64> {
+        match self.flushed_at.load(Ordering::Relaxed) {
+            0 => None,
+            ns => Some(ns),
+        }
     }
 
-    fn present(&self, _image_index: u32, _wait_semaphores: &[&dyn Semaphore]) -> Result<()> {
-        // In a real implementation, this would flush the framebuffer
+    fn flip_pending(&self) -> bool {
+        false
+    }
+
+    fn wait_vblank(&self, _timeout_ns: u64) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn wait_semaphores(&self, _semaphores: &[&dyn Semaphore]) -> Result<()> {
+        // In a real implementation, this would wait for the fences of the
+        // submissions signaling the semaphores
         Ok(())
     }
+
+    fn signal(&self, semaphore: Option<&dyn Semaphore>, fence: Option<&dyn Fence>) -> Result<()> {
+        let signal: Vec<&dyn Semaphore> = semaphore.into_iter().collect();
+        self.queue.submit(
+            &[SubmitInfo {
+                command_buffers: &[],
+                wait_semaphores: &[],
+                signal_semaphores: &signal,
+            }],
+            fence,
+        )
+    }
 }
@@ -0,0 +1,206 @@
+//! Host visible memory
+//!
+//! With `VIRTIO_GPU_F_RESOURCE_BLOB` the device exposes a shared memory region into which the
+//! host maps blob resources, at offsets the guest picks with `RESOURCE_MAP_BLOB`. Memory mapped
+//! this way is shared with the host, so writes reach it without `TRANSFER_TO_HOST` copies. Each
+//! blob is mapped with the caching the host reports for it.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+use common::{MemoryType, PhysBorrowed, Prot};
+use gal::{Error, Result};
+use spin::Mutex;
+
+use crate::capset::{command, ControlTransport};
+use crate::device::alloc_resource_id;
+use crate::protocol::{
+    self, blob_flags, map_cache, BlobMem, CommandType, ControlHeader, RespMapInfo,
+};
+
+/// Blobs are mapped at page granularity
+const PAGE_SIZE: u64 = 4096;
+
+/// The host visible shared memory region of the device
+pub struct HostMemRegion {
+    /// Physical address of the region
+    phys: usize,
+    size: u64,
+    /// Free ranges by offset, with their size
+    free: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl HostMemRegion {
+    /// Manage the region of `size` bytes at physical address `phys`
+    ///
+    /// # Safety
+    ///
+    /// `phys` must be the host visible region of the device blobs are created on.
+    pub unsafe fn new(phys: usize, size: u64) -> Self {
+        let mut free = BTreeMap::new();
+        if size != 0 {
+            free.insert(0, size);
+        }
+        Self {
+            phys,
+            size,
+            free: Mutex::new(free),
+        }
+    }
+
+    /// Size of the region in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes not mapped to any blob
+    pub fn free_bytes(&self) -> u64 {
+        self.free.lock().values().sum()
+    }
+
+    /// Reserve `size` bytes, rounded up to pages, returning the offset
+    fn reserve(&self, size: u64) -> Option<u64> {
+        let size = size.max(1).checked_next_multiple_of(PAGE_SIZE)?;
+        let mut free = self.free.lock();
+        let (&offset, &len) = free.iter().find(|&(_, &len)| len >= size)?;
+        free.remove(&offset);
+        if len > size {
+            free.insert(offset + size, len - size);
+        }
+        Some(offset)
+    }
+
+    /// Give back a range returned by [`Self::reserve`], merging it with free neighbors
+    fn release(&self, offset: u64, size: u64) {
+        let size = size.max(1).next_multiple_of(PAGE_SIZE);
+        let mut free = self.free.lock();
+        let mut start = offset;
+        let mut end = offset + size;
+        if let Some((&prev, &prev_size)) = free.range(..offset).next_back() {
+            if prev + prev_size == start {
+                free.remove(&prev);
+                start = prev;
+            }
+        }
+        if let Some(next_size) = free.remove(&end) {
+            end += next_size;
+        }
+        free.insert(start, end - start);
+    }
+}
+
+/// A host blob resource mapped into the host visible region
+pub struct BlobMapping {
+    resource_id: u32,
+    offset: u64,
+    size: u64,
+    region: Arc<HostMemRegion>,
+    control: Arc<dyn ControlTransport + Send + Sync>,
+    /// Whether the host mapped the blob into the region
+    host_mapped: bool,
+    /// CPU mapping of the blob, dropped before the host unmaps it
+    mapping: Option<PhysBorrowed>,
+}
+
+// The mapping is only reachable through the blob, which owns its range of the region.
+unsafe impl Send for BlobMapping {}
+unsafe impl Sync for BlobMapping {}
+
+impl BlobMapping {
+    /// Create a mappable blob of `size` bytes in context `ctx_id` and map it
+    ///
+    /// Fails with [`Error::OutOfDeviceMemory`] when the region is full.
+    pub fn create(
+        region: &Arc<HostMemRegion>,
+        control: &Arc<dyn ControlTransport + Send + Sync>,
+        ctx_id: u32,
+        size: u64,
+    ) -> Result<Self> {
+        let offset = region.reserve(size).ok_or(Error::OutOfDeviceMemory)?;
+        let resource_id = alloc_resource_id();
+
+        let create = protocol::ResourceCreateBlob::new(
+            ctx_id,
+            resource_id,
+            BlobMem::Host3d,
+            blob_flags::MAPPABLE,
+            size,
+        );
+        if let Err(err) =
+            command::<_, ControlHeader>(&**control, &create, CommandType::RespOkNodata)
+        {
+            region.release(offset, size);
+            return Err(err);
+        }
+
+        // From here on dropping the blob undoes what the host did
+        let mut blob = Self {
+            resource_id,
+            offset,
+            size,
+            region: region.clone(),
+            control: control.clone(),
+            host_mapped: false,
+            mapping: None,
+        };
+        let map = protocol::ResourceMapBlob::new(resource_id, offset);
+        let info: RespMapInfo = command(&**control, &map, CommandType::RespOkMapInfo)?;
+        blob.host_mapped = true;
+
+        let memory_type = match info.cache_type() {
+            map_cache::UNCACHED => MemoryType::Uncacheable,
+            map_cache::WC => MemoryType::WriteCombining,
+            _ => MemoryType::Writeback,
+        };
+        let mapping = PhysBorrowed::map(
+            region.phys + offset as usize,
+            size as usize,
+            Prot::RW,
+            memory_type,
+        )
+        .map_err(|err| {
+            log::error!("gal-virtio: failed to map blob {}: {}", resource_id, err);
+            Error::OutOfMemory
+        })?;
+        blob.mapping = Some(mapping);
+        Ok(blob)
+    }
+
+    /// Get the VirtIO resource ID
+    pub fn resource_id(&self) -> u32 {
+        self.resource_id
+    }
+
+    /// Size of the blob in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Start of the mapping
+    pub fn ptr(&self) -> *mut u8 {
+        // Blobs are only handed out once mapped
+        self.mapping
+            .as_ref()
+            .map_or(core::ptr::null_mut(), |mapping| mapping.as_ptr().cast())
+    }
+}
+
+impl Drop for BlobMapping {
+    fn drop(&mut self) {
+        let control = &*self.control;
+        self.mapping = None;
+        if self.host_mapped {
+            let unmap = protocol::ResourceUnmapBlob::new(self.resource_id);
+            if command::<_, ControlHeader>(control, &unmap, CommandType::RespOkNodata).is_err() {
+                // The host may still map the range, don't hand it out again
+                log::error!("gal-virtio: failed to unmap blob {}", self.resource_id);
+                return;
+            }
+        }
+        let unref = protocol::ResourceUnref::new(self.resource_id);
+        if command::<_, ControlHeader>(control, &unref, CommandType::RespOkNodata).is_err() {
+            log::warn!("gal-virtio: failed to unref blob {}", self.resource_id);
+        }
+        self.region.release(self.offset, self.size);
+    }
+}
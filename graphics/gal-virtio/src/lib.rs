@@ -39,13 +39,17 @@
 //! - **2D Mode**: Basic framebuffer blitting, cursor support
 //! - **3D Mode (virgl)**: OpenGL ES 3.0+ via Mesa virgl
 //! - **3D Mode (venus)**: Vulkan 1.2+ via Mesa Venus
+//! - **Blob resources**: host visible memory mapped from the host instead of copied
 //!
 //! # Usage
 //!
 //! ```ignore
 //! use gal_virtio::VirtioGpuDevice;
 //!
-//! let device = VirtioGpuDevice::create()?;
+//! static VIRTIO: spin::Once<virtio_core::Device> = spin::Once::new();
+//!
+//! let virtio = VIRTIO.try_call_once(|| virtio_core::probe_device(&mut pcid_handle))?;
+//! let device = VirtioGpuDevice::create(virtio, &mut pcid_handle)?;
 //! println!("Device: {}", device.info().name);
 //! println!("Capabilities: {:?}", device.info().capabilities);
 //! ```
@@ -54,11 +58,18 @@
 
 extern crate alloc;
 
+mod capset;
 mod command;
 mod device;
+mod hostmem;
 mod protocol;
 mod resource;
+mod transport;
 
+pub use capset::{Capset, ControlTransport, HostCapabilities};
 pub use command::VirtioCommandBuffer;
-pub use device::VirtioGpuDevice;
-pub use resource::{VirtioBuffer, VirtioImage};
+pub use device::{VirtioDisplay, VirtioGpuDevice};
+pub use hostmem::HostMemRegion;
+pub use protocol::{features, CapsetType, RespCapsetInfo};
+pub use resource::{VirtioBuffer, VirtioImage, VirtioMemory};
+pub use transport::QueueTransport;
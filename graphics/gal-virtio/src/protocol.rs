@@ -336,17 +336,76 @@ pub struct RespCapsetInfo {
 }
 
 /// Capset types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u32)]
 pub enum CapsetType {
     /// virgl (OpenGL)
     Virgl = 1,
     /// virgl2 (improved virgl)
     Virgl2 = 2,
+    /// gfxstream (Vulkan)
+    GfxstreamVulkan = 3,
     /// Venus (Vulkan)
-    Venus = 3,
+    Venus = 4,
     /// Cross-domain (ChromeOS)
-    CrossDomain = 4,
+    CrossDomain = 5,
+    /// Native DRM context
+    Drm = 6,
+}
+
+impl CapsetType {
+    pub fn from_id(id: u32) -> Option<Self> {
+        Some(match id {
+            1 => Self::Virgl,
+            2 => Self::Virgl2,
+            3 => Self::GfxstreamVulkan,
+            4 => Self::Venus,
+            5 => Self::CrossDomain,
+            6 => Self::Drm,
+            _ => return None,
+        })
+    }
+}
+
+/// Get capset request
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct GetCapset {
+    pub header: ControlHeader,
+    pub capset_id: u32,
+    pub capset_version: u32,
+}
+
+impl GetCapset {
+    pub fn new(capset_id: u32, capset_version: u32) -> Self {
+        Self {
+            header: ControlHeader::new(CommandType::GetCapset),
+            capset_id,
+            capset_version,
+        }
+    }
+}
+
+/// Capset response header
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct RespCapset {
+    pub header: ControlHeader,
+    // Followed by capset data
+}
+
+/// VirtIO GPU device feature bits
+pub mod features {
+    /// 3D mode (virgl) is supported
+    pub const VIRGL: u64 = 1 << 0;
+    /// EDID is supported
+    pub const EDID: u64 = 1 << 1;
+    /// Resource UUIDs are supported
+    pub const RESOURCE_UUID: u64 = 1 << 2;
+    /// Blob resources are supported
+    pub const RESOURCE_BLOB: u64 = 1 << 3;
+    /// Contexts can be created with a capset other than virgl
+    pub const CONTEXT_INIT: u64 = 1 << 4;
 }
 
 /// 3D context create request
@@ -452,6 +511,34 @@ impl ResourceCreate3d {
             padding: 0,
         }
     }
+
+    /// Buffer resource of `size` bytes, bound as `bind`, see [`virgl_bind`]
+    pub fn buffer(resource_id: u32, size: u32, bind: u32) -> Self {
+        Self {
+            target: PIPE_BUFFER,
+            format: VIRGL_FORMAT_R8_UNORM,
+            bind,
+            width: size,
+            height: 1,
+            depth: 1,
+            array_size: 1,
+            ..Self::new(resource_id)
+        }
+    }
+}
+
+/// `PIPE_BUFFER` target of 3D resources
+pub const PIPE_BUFFER: u32 = 0;
+/// Format of buffer resources
+pub const VIRGL_FORMAT_R8_UNORM: u32 = 64;
+
+/// Bind flags of 3D resources
+pub mod virgl_bind {
+    pub const VERTEX_BUFFER: u32 = 1 << 4;
+    pub const INDEX_BUFFER: u32 = 1 << 5;
+    pub const CONSTANT_BUFFER: u32 = 1 << 6;
+    pub const COMMAND_ARGS: u32 = 1 << 8;
+    pub const SHADER_BUFFER: u32 = 1 << 14;
 }
 
 /// Transfer to host 3D request
@@ -467,6 +554,21 @@ pub struct TransferToHost3d {
     pub layer_stride: u32,
 }
 
+impl TransferToHost3d {
+    /// Transfer `size` bytes at `offset` of a buffer resource
+    pub fn buffer(resource_id: u32, offset: u64, size: u32) -> Self {
+        Self {
+            header: ControlHeader::new(CommandType::TransferToHost3d),
+            box_: Box3d::buffer(offset as u32, size),
+            offset,
+            resource_id,
+            level: 0,
+            stride: 0,
+            layer_stride: 0,
+        }
+    }
+}
+
 /// 3D box
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(C)]
@@ -479,6 +581,19 @@ pub struct Box3d {
     pub d: u32,
 }
 
+impl Box3d {
+    /// Bytes `x..x + w` of a buffer
+    pub fn buffer(x: u32, w: u32) -> Self {
+        Self {
+            x,
+            w,
+            h: 1,
+            d: 1,
+            ..Self::default()
+        }
+    }
+}
+
 /// Transfer from host 3D request
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -492,6 +607,21 @@ pub struct TransferFromHost3d {
     pub layer_stride: u32,
 }
 
+impl TransferFromHost3d {
+    /// Transfer `size` bytes at `offset` of a buffer resource
+    pub fn buffer(resource_id: u32, offset: u64, size: u32) -> Self {
+        Self {
+            header: ControlHeader::new(CommandType::TransferFromHost3d),
+            box_: Box3d::buffer(offset as u32, size),
+            offset,
+            resource_id,
+            level: 0,
+            stride: 0,
+            layer_stride: 0,
+        }
+    }
+}
+
 /// Attach resource to context
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -543,6 +673,27 @@ pub struct ResourceCreateBlob {
     pub size: u64,
 }
 
+impl ResourceCreateBlob {
+    /// Blob without guest backing, created by context `ctx_id`
+    pub fn new(
+        ctx_id: u32,
+        resource_id: u32,
+        blob_mem: BlobMem,
+        blob_flags: u32,
+        size: u64,
+    ) -> Self {
+        Self {
+            header: ControlHeader::new(CommandType::ResourceCreateBlob).with_context(ctx_id),
+            resource_id,
+            blob_mem: blob_mem as u32,
+            blob_flags,
+            nr_entries: 0,
+            blob_id: 0,
+            size,
+        }
+    }
+}
+
 /// Blob memory types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
@@ -572,6 +723,18 @@ pub struct ResourceMapBlob {
     pub offset: u64,
 }
 
+impl ResourceMapBlob {
+    /// Map the blob at `offset` in the host visible region
+    pub fn new(resource_id: u32, offset: u64) -> Self {
+        Self {
+            header: ControlHeader::new(CommandType::ResourceMapBlob),
+            resource_id,
+            padding: 0,
+            offset,
+        }
+    }
+}
+
 /// Map info response
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -581,6 +744,25 @@ pub struct RespMapInfo {
     pub padding: u32,
 }
 
+impl RespMapInfo {
+    /// Caching of the mapping, see [`map_cache`]
+    pub fn cache_type(&self) -> u32 {
+        self.map_info & map_cache::MASK
+    }
+}
+
+/// Caching of a mapped blob
+pub mod map_cache {
+    pub const MASK: u32 = 0x0f;
+    pub const NONE: u32 = 0x00;
+    pub const CACHED: u32 = 0x01;
+    pub const UNCACHED: u32 = 0x02;
+    pub const WC: u32 = 0x03;
+}
+
+/// Shared memory region ID of the host visible region
+pub const SHM_ID_HOST_VISIBLE: u8 = 1;
+
 /// Unmap blob resource request
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -590,6 +772,16 @@ pub struct ResourceUnmapBlob {
     pub padding: u32,
 }
 
+impl ResourceUnmapBlob {
+    pub fn new(resource_id: u32) -> Self {
+        Self {
+            header: ControlHeader::new(CommandType::ResourceUnmapBlob),
+            resource_id,
+            padding: 0,
+        }
+    }
+}
+
 /// Cursor position
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -1,15 +1,49 @@
 //! VirtIO-GPU resource implementations
 //!
 //! This module provides buffer, image, and memory resources for VirtIO-GPU.
+//!
+//! Host visible buffers and memory are blobs mapped into the host visible region when the device
+//! has one. Writes to those reach the host directly; the others keep a guest copy that flushing
+//! transfers to the host.
 
+use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU32, Ordering};
 
+use common::dma::Dma;
+
+use gal::debug;
+use gal::image::ImageDimension;
 use gal::{
-    Buffer, BufferDescriptor, BufferUsage, Error, Extent3D, Image, ImageDescriptor, ImageDimension,
-    ImageFormat, ImageUsage, Memory, MemoryType, Result,
+    Buffer, BufferDescriptor, BufferUsage, Error, Extent3D, Image, ImageDescriptor, ImageFormat,
+    ImageUsage, Memory, MemoryType, ObjectType, Result,
 };
 
+use crate::capset::{command, ControlTransport};
+use crate::hostmem::BlobMapping;
+use crate::protocol::{self, virgl_bind, AttachBacking, CommandType, ControlHeader, MemEntry};
+
+/// Guest copy of a host buffer resource, attached as its backing
+struct GuestBacking {
+    _memory: Dma<[u8]>,
+    /// Start of the memory, written through [`Buffer::map`]
+    ptr: *mut u8,
+    control: Arc<dyn ControlTransport + Send + Sync>,
+}
+
+// The pointer is only handed out through the buffer owning the memory.
+unsafe impl Send for GuestBacking {}
+unsafe impl Sync for GuestBacking {}
+
+/// `VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING` with a single entry
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct AttachSingle {
+    attach: AttachBacking,
+    entry: MemEntry,
+}
+
 /// VirtIO buffer implementation
 pub struct VirtioBuffer {
     handle: usize,
@@ -18,6 +52,10 @@ pub struct VirtioBuffer {
     usage: BufferUsage,
     memory_type: MemoryType,
     data: spin::RwLock<Vec<u8>>,
+    /// Host memory the buffer lives in instead of `data`
+    blob: Option<BlobMapping>,
+    /// Guest copy of a host resource, instead of `data`
+    backing: Option<GuestBacking>,
 }
 
 impl VirtioBuffer {
@@ -35,9 +73,75 @@ impl VirtioBuffer {
             usage: descriptor.usage,
             memory_type: descriptor.memory_type,
             data: spin::RwLock::new(data),
+            blob: None,
+            backing: None,
+        }
+    }
+
+    /// Buffer resource created on the host, with a guest copy flushing and invalidating
+    /// transfer to and from
+    pub fn with_backing(
+        control: &Arc<dyn ControlTransport + Send + Sync>,
+        resource_id: u32,
+        descriptor: &BufferDescriptor,
+    ) -> Result<Self> {
+        let size = u32::try_from(descriptor.size).map_err(|_| Error::InvalidParameter)?;
+        let mut memory = unsafe {
+            Dma::<[u8]>::zeroed_slice(size.max(1) as usize)
+                .map_err(|_| Error::OutOfMemory)?
+                .assume_init()
+        };
+
+        let create =
+            protocol::ResourceCreate3d::buffer(resource_id, size, bind_flags(descriptor.usage));
+        command::<_, ControlHeader>(&**control, &create, CommandType::RespOkNodata)?;
+        let attach = AttachSingle {
+            attach: AttachBacking::new(resource_id, 1),
+            entry: MemEntry::new(memory.physical() as u64, memory.len() as u32),
+        };
+        if let Err(err) =
+            command::<_, ControlHeader>(&**control, &attach, CommandType::RespOkNodata)
+        {
+            unref(&**control, resource_id);
+            return Err(err);
+        }
+
+        let ptr = memory.as_mut_ptr();
+        Ok(Self {
+            handle: resource_id as usize,
+            resource_id,
+            size: descriptor.size,
+            usage: descriptor.usage,
+            memory_type: descriptor.memory_type,
+            data: spin::RwLock::new(Vec::new()),
+            blob: None,
+            backing: Some(GuestBacking {
+                _memory: memory,
+                ptr,
+                control: control.clone(),
+            }),
+        })
+    }
+
+    /// Buffer living in a mapped blob
+    pub fn with_blob(blob: BlobMapping, descriptor: &BufferDescriptor) -> Self {
+        Self {
+            handle: blob.resource_id() as usize,
+            resource_id: blob.resource_id(),
+            size: descriptor.size,
+            usage: descriptor.usage,
+            memory_type: descriptor.memory_type,
+            data: spin::RwLock::new(Vec::new()),
+            blob: Some(blob),
+            backing: None,
         }
     }
 
+    /// Whether writes reach the host without flushing
+    pub fn is_host_mapped(&self) -> bool {
+        self.blob.is_some()
+    }
+
     /// Get the VirtIO resource ID
     pub fn resource_id(&self) -> u32 {
         self.resource_id
@@ -62,6 +166,12 @@ impl Buffer for VirtioBuffer {
     }
 
     fn map(&self) -> Result<*mut u8> {
+        if let Some(blob) = &self.blob {
+            return Ok(blob.ptr());
+        }
+        if let Some(backing) = &self.backing {
+            return Ok(backing.ptr);
+        }
         let mut data = self.data.write();
         if data.is_empty() {
             data.resize(self.size as usize, 0);
@@ -73,17 +183,71 @@ impl Buffer for VirtioBuffer {
         // Data stays allocated
     }
 
-    fn flush(&self, _offset: u64, _size: u64) -> Result<()> {
-        // In a real implementation, this would transfer data to host
+    fn flush(&self, offset: u64, size: u64) -> Result<()> {
+        if offset + size > self.size {
+            return Err(Error::InvalidParameter);
+        }
+        // Blobs are shared with the host, and without a host resource there is nothing to update
+        if let Some(backing) = &self.backing {
+            let transfer =
+                protocol::TransferToHost3d::buffer(self.resource_id, offset, size as u32);
+            command::<_, ControlHeader>(&*backing.control, &transfer, CommandType::RespOkNodata)?;
+        }
         Ok(())
     }
 
-    fn invalidate(&self, _offset: u64, _size: u64) -> Result<()> {
-        // In a real implementation, this would transfer data from host
+    fn invalidate(&self, offset: u64, size: u64) -> Result<()> {
+        if offset + size > self.size {
+            return Err(Error::InvalidParameter);
+        }
+        if let Some(backing) = &self.backing {
+            let transfer =
+                protocol::TransferFromHost3d::buffer(self.resource_id, offset, size as u32);
+            command::<_, ControlHeader>(&*backing.control, &transfer, CommandType::RespOkNodata)?;
+        }
         Ok(())
     }
 }
 
+impl Drop for VirtioBuffer {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Buffer, self.handle);
+        // The host detaches the backing along with the resource
+        if let Some(backing) = &self.backing {
+            unref(&*backing.control, self.resource_id);
+        }
+    }
+}
+
+/// Bind flags of a buffer resource used as `usage`
+fn bind_flags(usage: BufferUsage) -> u32 {
+    let mut bind = 0;
+    if usage.contains(BufferUsage::VERTEX) {
+        bind |= virgl_bind::VERTEX_BUFFER;
+    }
+    if usage.contains(BufferUsage::INDEX) {
+        bind |= virgl_bind::INDEX_BUFFER;
+    }
+    if usage.contains(BufferUsage::UNIFORM) {
+        bind |= virgl_bind::CONSTANT_BUFFER;
+    }
+    if usage.contains(BufferUsage::STORAGE) {
+        bind |= virgl_bind::SHADER_BUFFER;
+    }
+    if usage.contains(BufferUsage::INDIRECT) {
+        bind |= virgl_bind::COMMAND_ARGS;
+    }
+    bind
+}
+
+/// Send `VIRTIO_GPU_CMD_RESOURCE_UNREF`, the resource is gone either way
+fn unref(control: &dyn ControlTransport, resource_id: u32) {
+    let unref = protocol::ResourceUnref::new(resource_id);
+    if command::<_, ControlHeader>(control, &unref, CommandType::RespOkNodata).is_err() {
+        log::warn!("gal-virtio: failed to unref resource {}", resource_id);
+    }
+}
+
 /// VirtIO image implementation
 pub struct VirtioImage {
     handle: usize,
@@ -174,25 +338,50 @@ impl Image for VirtioImage {
     }
 }
 
+impl Drop for VirtioImage {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Image, self.handle);
+    }
+}
+
 /// VirtIO memory implementation
 pub struct VirtioMemory {
     handle: usize,
     size: u64,
     memory_type: MemoryType,
     data: spin::RwLock<Vec<u8>>,
+    /// Host memory backing the allocation instead of `data`
+    blob: Option<BlobMapping>,
 }
 
+static NEXT_MEMORY_HANDLE: AtomicU32 = AtomicU32::new(1);
+
 impl VirtioMemory {
     pub fn new(size: u64, memory_type: MemoryType) -> Self {
-        static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
-
         Self {
-            handle: NEXT_HANDLE.fetch_add(1, Ordering::SeqCst) as usize,
+            handle: NEXT_MEMORY_HANDLE.fetch_add(1, Ordering::SeqCst) as usize,
             size,
             memory_type,
             data: spin::RwLock::new(vec![0u8; size as usize]),
+            blob: None,
         }
     }
+
+    /// Memory backed by a mapped blob
+    pub fn with_blob(blob: BlobMapping, memory_type: MemoryType) -> Self {
+        Self {
+            handle: NEXT_MEMORY_HANDLE.fetch_add(1, Ordering::SeqCst) as usize,
+            size: blob.size(),
+            memory_type,
+            data: spin::RwLock::new(Vec::new()),
+            blob: Some(blob),
+        }
+    }
+
+    /// Blob resource backing the memory, if it is host mapped
+    pub fn blob_resource_id(&self) -> Option<u32> {
+        self.blob.as_ref().map(BlobMapping::resource_id)
+    }
 }
 
 impl Memory for VirtioMemory {
@@ -213,6 +402,9 @@ impl Memory for VirtioMemory {
             return Err(Error::InvalidParameter);
         }
 
+        if let Some(blob) = &self.blob {
+            return Ok(unsafe { blob.ptr().add(offset as usize) });
+        }
         let mut data = self.data.write();
         Ok(unsafe { data.as_mut_ptr().add(offset as usize) })
     }
@@ -229,3 +421,9 @@ impl Memory for VirtioMemory {
         Ok(())
     }
 }
+
+impl Drop for VirtioMemory {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Memory, self.handle);
+    }
+}
@@ -0,0 +1,226 @@
+//! Control queue transport
+//!
+//! [`QueueTransport`] sends the commands of [`ControlTransport`] over the control queue of a
+//! device probed by `virtio-core`. Each command waits for its response, so they are sent
+//! synchronously.
+
+extern crate std;
+
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::mem::size_of;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use common::dma::Dma;
+use gal::{Error, Result};
+use pcid_interface::PciFunctionHandle;
+use virtio_core::spec::{Buffer, ChainBuilder, DescriptorFlags};
+use virtio_core::transport::{Queue, Transport};
+use virtio_core::{Device as VirtioDevice, MSIX_PRIMARY_VECTOR};
+
+use crate::capset::{check_response, ControlTransport};
+use crate::protocol::{
+    features, CommandType, ControlHeader, GetCapset, GetCapsetInfo, RespCapsetInfo,
+    SHM_ID_HOST_VISIBLE,
+};
+
+/// Offset of `num_capsets` in `struct virtio_gpu_config`
+const CONFIG_NUM_CAPSETS: u8 = 12;
+
+/// `cfg_type` of `struct virtio_pci_cap` describing a shared memory region
+const CFG_TYPE_SHARED_MEMORY: u8 = 8;
+
+/// Feature bits GAL knows how to use
+const KNOWN_FEATURES: [u64; 5] = [
+    features::VIRGL,
+    features::EDID,
+    features::RESOURCE_UUID,
+    features::RESOURCE_BLOB,
+    features::CONTEXT_INIT,
+];
+
+/// [`ControlTransport`] backed by the control queue of a VirtIO-GPU device
+pub struct QueueTransport<'a> {
+    control_queue: Arc<Queue<'a>>,
+    features: u64,
+    num_capsets: u32,
+    /// Physical address and size of the host visible region
+    host_visible: Option<(usize, u64)>,
+}
+
+impl<'a> QueueTransport<'a> {
+    /// Negotiate features and set up the control queue of a freshly probed device, then start it
+    ///
+    /// The host visible region is looked up in the PCI capabilities of `pcid_handle`.
+    pub fn setup(device: &'a VirtioDevice, pcid_handle: &mut PciFunctionHandle) -> Result<Self> {
+        let transport = &*device.transport;
+
+        let mut negotiated = 0;
+        for feature in KNOWN_FEATURES {
+            let bit = feature.trailing_zeros();
+            if transport.check_device_feature(bit) {
+                transport.ack_driver_feature(bit);
+                negotiated |= feature;
+            }
+        }
+        transport.finalize_features();
+
+        let control_queue = transport
+            .setup_queue(MSIX_PRIMARY_VECTOR, &device.irq_handle)
+            .map_err(|err| {
+                log::error!("gal-virtio: failed to set up the control queue: {}", err);
+                Error::DeviceNotFound
+            })?;
+        transport.run_device();
+
+        let mut this = Self::new(transport, control_queue, negotiated);
+        if negotiated & features::RESOURCE_BLOB != 0 {
+            this.host_visible = host_visible_region(pcid_handle);
+        }
+        Ok(this)
+    }
+
+    /// Wrap a control queue of a device that is already running
+    ///
+    /// `features` are the feature bits the driver acknowledged.
+    pub fn new(transport: &dyn Transport, control_queue: Arc<Queue<'a>>, features: u64) -> Self {
+        Self {
+            control_queue,
+            features,
+            num_capsets: transport.load_config(CONFIG_NUM_CAPSETS, 4) as u32,
+            host_visible: None,
+        }
+    }
+
+    /// Send `request` and wait until the host wrote its response into `response`
+    fn send(&self, request: Buffer, response: Buffer) {
+        let command = ChainBuilder::new()
+            .chain(request)
+            .chain(response.flags(DescriptorFlags::WRITE_ONLY))
+            .build();
+        block_on(self.control_queue.send(command));
+    }
+}
+
+impl ControlTransport for QueueTransport<'_> {
+    fn features(&self) -> u64 {
+        self.features
+    }
+
+    fn num_capsets(&self) -> u32 {
+        self.num_capsets
+    }
+
+    fn get_capset_info(&self, index: u32) -> Result<RespCapsetInfo> {
+        let request = Dma::new(GetCapsetInfo::new(index)).map_err(|_| Error::OutOfMemory)?;
+        let response = unsafe {
+            Dma::<RespCapsetInfo>::zeroed()
+                .map_err(|_| Error::OutOfMemory)?
+                .assume_init()
+        };
+
+        self.send(Buffer::new(&request), Buffer::new(&response));
+        check_response(&response.header, CommandType::RespOkCapsetInfo)?;
+        Ok(*response)
+    }
+
+    fn get_capset(&self, id: u32, version: u32, max_size: u32) -> Result<Vec<u8>> {
+        let header_size = size_of::<ControlHeader>();
+        let request = Dma::new(GetCapset::new(id, version)).map_err(|_| Error::OutOfMemory)?;
+        let response = unsafe {
+            Dma::<[u8]>::zeroed_slice(header_size + max_size as usize)
+                .map_err(|_| Error::OutOfMemory)?
+                .assume_init()
+        };
+
+        self.send(Buffer::new(&request), Buffer::new_unsized(&response));
+        // DMA memory is page aligned, so the header at its start is aligned too
+        let header = unsafe { response.as_ptr().cast::<ControlHeader>().read() };
+        check_response(&header, CommandType::RespOkCapset)?;
+        Ok(response[header_size..].to_vec())
+    }
+
+    fn send_command(&self, request: &[u8], response: &mut [u8]) -> Result<()> {
+        let mut request_dma = unsafe {
+            Dma::<[u8]>::zeroed_slice(request.len())
+                .map_err(|_| Error::OutOfMemory)?
+                .assume_init()
+        };
+        request_dma.copy_from_slice(request);
+        let response_dma = unsafe {
+            Dma::<[u8]>::zeroed_slice(response.len())
+                .map_err(|_| Error::OutOfMemory)?
+                .assume_init()
+        };
+
+        self.send(
+            Buffer::new_unsized(&request_dma),
+            Buffer::new_unsized(&response_dma),
+        );
+        response.copy_from_slice(&response_dma);
+        Ok(())
+    }
+
+    fn host_visible_region(&self) -> Option<(usize, u64)> {
+        self.host_visible
+    }
+}
+
+/// Physical address and size of the host visible region, from its `struct virtio_pci_cap64`
+fn host_visible_region(pcid_handle: &mut PciFunctionHandle) -> Option<(usize, u64)> {
+    let bars = pcid_handle.config().func.bars;
+    // The vendor data starts at `cfg_type`, the capability header is stripped
+    let region = pcid_handle
+        .get_vendor_capabilities()
+        .into_iter()
+        .find_map(|capability| {
+            let data = &capability.data;
+            let word = |at: usize| Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?));
+            if *data.first()? != CFG_TYPE_SHARED_MEMORY || *data.get(2)? != SHM_ID_HOST_VISIBLE {
+                return None;
+            }
+            let offset = u64::from(word(5)?) | u64::from(word(13)?) << 32;
+            let length = u64::from(word(9)?) | u64::from(word(17)?) << 32;
+            Some((*data.get(1)? as usize, offset, length))
+        });
+    let Some((bar, offset, length)) = region else {
+        log::warn!("gal-virtio: blob resources without a host visible region");
+        return None;
+    };
+
+    let (bar_addr, bar_size) = bars.get(bar)?.expect_mem();
+    if offset + length > bar_size as u64 || length == 0 {
+        log::warn!("gal-virtio: host visible region outside of BAR {}", bar);
+        return None;
+    }
+    log::info!(
+        "gal-virtio: host visible region of {} MiB in BAR {}",
+        length >> 20,
+        bar
+    );
+    Some((bar_addr + offset as usize, length))
+}
+
+/// Wakes the thread blocked in [`block_on`] from the interrupt thread of the queue
+struct ThreadWaker(std::thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
@@ -0,0 +1,502 @@
+//! Device memory allocator
+//!
+//! [`DeviceAllocator`] places allocations in large memory blocks of a
+//! [`Device`], so that creating a buffer doesn't cost a round trip to the
+//! driver and drivers don't track thousands of tiny objects. Allocations of
+//! at least half a block, and those asking for it, get memory of their own.
+//!
+//! Free ranges of a block are handed out best fit and merged with their
+//! neighbors when freed. Blocks still fragment over time in long running
+//! applications; [`DeviceAllocator::defragment`] plans moves that empty the
+//! least used blocks so that they can be released.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use spin::Mutex;
+
+use crate::memory::{Allocation, AllocationInfo, AllocatorStats, MemoryAllocator};
+use crate::{Device, Error, Memory, MemoryType, Result};
+
+/// Default size of the blocks allocations are placed in
+pub const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Physical memory heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heap {
+    /// Video memory of the GPU
+    Device,
+    /// System memory
+    Host,
+}
+
+impl Heap {
+    const COUNT: usize = 2;
+
+    /// Get the heap memory of a type is allocated from
+    pub fn of(memory_type: MemoryType) -> Self {
+        match memory_type {
+            MemoryType::DeviceLocal => Heap::Device,
+            _ => Heap::Host,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Heap::Device => 0,
+            Heap::Host => 1,
+        }
+    }
+}
+
+/// Usage of a heap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes of memory allocated from the device, including unused parts
+    /// of blocks
+    pub reserved_bytes: u64,
+    /// Bytes handed out
+    pub allocated_bytes: u64,
+    /// Limit of the reserved bytes
+    pub budget: Option<u64>,
+}
+
+/// Allocation to be moved by the application
+///
+/// `dst` is allocated already. Once the contents are copied and every user
+/// of `src` is switched to `dst`, free `src`.
+#[derive(Debug, Clone)]
+pub struct DefragmentationMove {
+    pub src: Allocation,
+    pub dst: Allocation,
+}
+
+/// Sub-allocated memory block
+struct Block {
+    memory: Box<dyn Memory>,
+    memory_type: MemoryType,
+    /// Persistent mapping of host visible blocks
+    mapped: Option<*mut u8>,
+    /// Free ranges by offset, with their size
+    free: BTreeMap<u64, u64>,
+    /// Free ranges by size and offset, for best fit
+    free_by_size: BTreeSet<(u64, u64)>,
+    /// Allocations by offset, with their size and alignment
+    live: BTreeMap<u64, (u64, u64)>,
+    allocated: u64,
+    /// Being emptied by defragmentation, no new allocations
+    draining: bool,
+}
+
+// The mapping is only handed out, the block itself never dereferences it.
+unsafe impl Send for Block {}
+
+impl Block {
+    fn new(memory: Box<dyn Memory>, memory_type: MemoryType, mapped: Option<*mut u8>) -> Self {
+        let size = memory.size();
+        let mut block = Self {
+            memory,
+            memory_type,
+            mapped,
+            free: BTreeMap::new(),
+            free_by_size: BTreeSet::new(),
+            live: BTreeMap::new(),
+            allocated: 0,
+            draining: false,
+        };
+        block.insert_free(0, size);
+        block
+    }
+
+    fn insert_free(&mut self, offset: u64, size: u64) {
+        if size != 0 {
+            self.free.insert(offset, size);
+            self.free_by_size.insert((size, offset));
+        }
+    }
+
+    fn remove_free(&mut self, offset: u64) -> u64 {
+        let size = self.free.remove(&offset).unwrap_or(0);
+        self.free_by_size.remove(&(size, offset));
+        size
+    }
+
+    /// Find room for `size` bytes, returning the offset
+    fn reserve(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let (start, len, offset) =
+            self.free_by_size
+                .range((size, 0)..)
+                .find_map(|&(len, start)| {
+                    let offset = start.checked_next_multiple_of(alignment)?;
+                    (offset.checked_add(size)? <= start + len).then_some((start, len, offset))
+                })?;
+
+        self.remove_free(start);
+        self.insert_free(start, offset - start);
+        self.insert_free(offset + size, start + len - offset - size);
+        self.live.insert(offset, (size, alignment));
+        self.allocated += size;
+        Some(offset)
+    }
+
+    /// Give back the allocation at `offset`, merging it with free neighbors
+    fn release(&mut self, offset: u64) -> bool {
+        let Some((size, _)) = self.live.remove(&offset) else {
+            return false;
+        };
+        self.allocated -= size;
+
+        let mut start = offset;
+        let mut end = offset + size;
+        if let Some((&prev, &prev_size)) = self.free.range(..offset).next_back() {
+            if prev + prev_size == start {
+                self.remove_free(prev);
+                start = prev;
+            }
+        }
+        if self.free.contains_key(&end) {
+            end += self.remove_free(end);
+        }
+        self.insert_free(start, end - start);
+        true
+    }
+
+    fn allocation(&self, offset: u64, size: u64) -> Allocation {
+        Allocation {
+            memory_handle: self.memory.handle(),
+            offset,
+            size,
+            mapped_ptr: self.mapped,
+        }
+    }
+
+    fn largest_free_range(&self) -> u64 {
+        self.free_by_size.last().map_or(0, |&(size, _)| size)
+    }
+}
+
+/// Memory of its own for a single allocation
+struct Dedicated {
+    memory: Box<dyn Memory>,
+    memory_type: MemoryType,
+    mapped: bool,
+}
+
+struct State {
+    /// Blocks by memory handle
+    blocks: BTreeMap<usize, Block>,
+    /// Dedicated allocations by memory handle
+    dedicated: BTreeMap<usize, Dedicated>,
+    budgets: [Option<u64>; Heap::COUNT],
+}
+
+impl State {
+    fn heap_stats(&self, heap: Heap) -> HeapStats {
+        let mut stats = HeapStats {
+            budget: self.budgets[heap.index()],
+            ..HeapStats::default()
+        };
+        for block in self.blocks.values() {
+            if Heap::of(block.memory_type) == heap {
+                stats.reserved_bytes += block.memory.size();
+                stats.allocated_bytes += block.allocated;
+            }
+        }
+        for dedicated in self.dedicated.values() {
+            if Heap::of(dedicated.memory_type) == heap {
+                stats.reserved_bytes += dedicated.memory.size();
+                stats.allocated_bytes += dedicated.memory.size();
+            }
+        }
+        stats
+    }
+
+    /// Check that `size` more bytes of `memory_type` stay within the budget
+    fn check_budget(&self, memory_type: MemoryType, size: u64) -> Result<()> {
+        let heap = Heap::of(memory_type);
+        let stats = self.heap_stats(heap);
+        match stats.budget {
+            Some(budget) if stats.reserved_bytes.saturating_add(size) > budget => Err(match heap {
+                Heap::Device => Error::OutOfDeviceMemory,
+                Heap::Host => Error::OutOfMemory,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Allocator sub-allocating device memory
+pub struct DeviceAllocator {
+    device: Arc<dyn Device>,
+    block_size: u64,
+    state: Mutex<State>,
+}
+
+impl DeviceAllocator {
+    /// Create an allocator with blocks of [`DEFAULT_BLOCK_SIZE`]
+    pub fn new(device: Arc<dyn Device>) -> Self {
+        Self::with_block_size(device, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Create an allocator with blocks of `block_size` bytes
+    pub fn with_block_size(device: Arc<dyn Device>, block_size: u64) -> Self {
+        Self {
+            device,
+            block_size,
+            state: Mutex::new(State {
+                blocks: BTreeMap::new(),
+                dedicated: BTreeMap::new(),
+                budgets: [None; Heap::COUNT],
+            }),
+        }
+    }
+
+    /// Limit the memory allocated from a heap
+    ///
+    /// Allocations that need more memory from the device than the budget
+    /// allows fail, existing memory is kept.
+    pub fn set_budget(&self, heap: Heap, budget: Option<u64>) {
+        self.state.lock().budgets[heap.index()] = budget;
+    }
+
+    /// Get the usage of a heap
+    pub fn heap_stats(&self, heap: Heap) -> HeapStats {
+        self.state.lock().heap_stats(heap)
+    }
+
+    /// Allocate memory from the device, mapping it if it's host visible
+    fn allocate_memory(
+        &self,
+        size: u64,
+        memory_type: MemoryType,
+    ) -> Result<(Box<dyn Memory>, Option<*mut u8>)> {
+        let memory = self.device.allocate_memory(size, memory_type)?;
+        let mapped = if memory_type.is_host_visible() {
+            Some(memory.map(0, size)?)
+        } else {
+            None
+        };
+        Ok((memory, mapped))
+    }
+
+    fn allocate_dedicated(&self, state: &mut State, info: &AllocationInfo) -> Result<Allocation> {
+        state.check_budget(info.memory_type, info.size)?;
+        let (memory, mapped) = self.allocate_memory(info.size, info.memory_type)?;
+        let allocation = Allocation {
+            memory_handle: memory.handle(),
+            offset: 0,
+            size: info.size,
+            mapped_ptr: mapped,
+        };
+        state.dedicated.insert(
+            memory.handle(),
+            Dedicated {
+                memory,
+                memory_type: info.memory_type,
+                mapped: mapped.is_some(),
+            },
+        );
+        Ok(allocation)
+    }
+
+    /// Plan moves that empty the least used blocks
+    ///
+    /// Allocations are only moved into fuller blocks, and only if every
+    /// allocation of a block fits, as partly emptied blocks don't free any
+    /// memory. At most `max_bytes` are moved. Blocks being emptied get no new
+    /// allocations and are released once their last allocation is freed.
+    pub fn defragment(&self, max_bytes: u64) -> Vec<DefragmentationMove> {
+        let mut state = self.state.lock();
+        let mut moves = Vec::new();
+        let mut budget = max_bytes;
+
+        let mut memory_types: Vec<MemoryType> = Vec::new();
+        for block in state.blocks.values() {
+            if !memory_types.contains(&block.memory_type) {
+                memory_types.push(block.memory_type);
+            }
+        }
+
+        for memory_type in memory_types {
+            let mut order: Vec<(usize, u64)> = state
+                .blocks
+                .iter()
+                .filter(|(_, block)| block.memory_type == memory_type && !block.draining)
+                .map(|(&handle, block)| (handle, block.allocated))
+                .collect();
+            // Fullest first, the least used blocks are emptied into the fuller ones
+            order.sort_by_key(|&(_, allocated)| core::cmp::Reverse(allocated));
+
+            for src_index in (1..order.len()).rev() {
+                let (src, allocated) = order[src_index];
+                if allocated == 0 || allocated > budget {
+                    continue;
+                }
+                let live: Vec<(u64, (u64, u64))> = state.blocks[&src]
+                    .live
+                    .iter()
+                    .map(|(&offset, &entry)| (offset, entry))
+                    .collect();
+
+                let mut planned = Vec::with_capacity(live.len());
+                for &(offset, (size, alignment)) in &live {
+                    let dst = order[..src_index].iter().find_map(|&(dst, _)| {
+                        let block = state.blocks.get_mut(&dst)?;
+                        Some((dst, block.reserve(size, alignment)?))
+                    });
+                    match dst {
+                        Some((dst, dst_offset)) => planned.push((offset, size, dst, dst_offset)),
+                        None => break,
+                    }
+                }
+
+                if planned.len() != live.len() {
+                    for &(_, _, dst, dst_offset) in &planned {
+                        if let Some(block) = state.blocks.get_mut(&dst) {
+                            block.release(dst_offset);
+                        }
+                    }
+                    continue;
+                }
+
+                budget -= allocated;
+                let src_block = &state.blocks[&src];
+                for &(offset, size, dst, dst_offset) in &planned {
+                    moves.push(DefragmentationMove {
+                        src: src_block.allocation(offset, size),
+                        dst: state.blocks[&dst].allocation(dst_offset, size),
+                    });
+                }
+                if let Some(block) = state.blocks.get_mut(&src) {
+                    block.draining = true;
+                }
+                // Blocks that got allocations mustn't be emptied before their
+                // contents arrive, and they are all fuller than the rest.
+                order.truncate(src_index);
+            }
+        }
+
+        if !moves.is_empty() {
+            log::debug!(
+                "gal: defragmentation moves {} allocation(s), {} bytes",
+                moves.len(),
+                max_bytes - budget
+            );
+        }
+        moves
+    }
+}
+
+impl MemoryAllocator for DeviceAllocator {
+    fn allocate(&self, info: &AllocationInfo) -> Result<Allocation> {
+        if info.size == 0 || !info.alignment.is_power_of_two() {
+            return Err(Error::InvalidParameter);
+        }
+
+        let mut state = self.state.lock();
+        if info.dedicated || info.size >= self.block_size / 2 {
+            return self.allocate_dedicated(&mut state, info);
+        }
+
+        for block in state.blocks.values_mut() {
+            if block.memory_type != info.memory_type || block.draining {
+                continue;
+            }
+            if let Some(offset) = block.reserve(info.size, info.alignment) {
+                return Ok(block.allocation(offset, info.size));
+            }
+        }
+
+        state.check_budget(info.memory_type, self.block_size)?;
+        let (memory, mapped) = self.allocate_memory(self.block_size, info.memory_type)?;
+        let mut block = Block::new(memory, info.memory_type, mapped);
+        let offset = block
+            .reserve(info.size, info.alignment)
+            .ok_or(Error::OutOfMemory)?;
+        let allocation = block.allocation(offset, info.size);
+        state.blocks.insert(block.memory.handle(), block);
+        Ok(allocation)
+    }
+
+    fn free(&self, allocation: Allocation) {
+        let mut state = self.state.lock();
+        let handle = allocation.memory_handle;
+
+        if let Some(dedicated) = state.dedicated.remove(&handle) {
+            if dedicated.mapped {
+                dedicated.memory.unmap();
+            }
+            return;
+        }
+
+        let Some(block) = state.blocks.get_mut(&handle) else {
+            log::warn!("gal: freeing unknown allocation in memory {}", handle);
+            return;
+        };
+        if !block.release(allocation.offset) {
+            log::warn!(
+                "gal: freeing unknown allocation at {:#x} in memory {}",
+                allocation.offset,
+                handle
+            );
+            return;
+        }
+        if !block.live.is_empty() {
+            return;
+        }
+
+        // Keep one empty block per memory type around, so that allocating
+        // and freeing a single buffer doesn't allocate device memory each time
+        let memory_type = block.memory_type;
+        let release = block.draining
+            || state
+                .blocks
+                .iter()
+                .any(|(&other, block)| other != handle && block.memory_type == memory_type);
+        if release {
+            if let Some(block) = state.blocks.remove(&handle) {
+                if block.mapped.is_some() {
+                    block.memory.unmap();
+                }
+            }
+        }
+    }
+
+    fn stats(&self) -> AllocatorStats {
+        let state = self.state.lock();
+        let mut stats = AllocatorStats::default();
+        for block in state.blocks.values() {
+            stats.allocated_bytes += block.allocated;
+            stats.allocation_count += block.live.len() as u32;
+            stats.reserved_bytes += block.memory.size();
+            stats.block_count += 1;
+            stats.free_range_count += block.free.len() as u32;
+            stats.largest_free_range = stats.largest_free_range.max(block.largest_free_range());
+        }
+        for dedicated in state.dedicated.values() {
+            stats.allocated_bytes += dedicated.memory.size();
+            stats.allocation_count += 1;
+            stats.reserved_bytes += dedicated.memory.size();
+            stats.dedicated_count += 1;
+        }
+        stats
+    }
+}
+
+impl Drop for DeviceAllocator {
+    fn drop(&mut self) {
+        let state = self.state.get_mut();
+        for block in state.blocks.values() {
+            if block.mapped.is_some() {
+                block.memory.unmap();
+            }
+        }
+        for dedicated in state.dedicated.values() {
+            if dedicated.mapped {
+                dedicated.memory.unmap();
+            }
+        }
+    }
+}
@@ -7,7 +7,8 @@ use alloc::boxed::Box;
 use alloc::vec::Vec;
 
 use crate::{
-    Buffer, ClearValue, Error, Extent2D, Image, Offset2D, Pipeline, Rect2D, Result, Viewport,
+    Buffer, ClearValue, DebugLabel, DeviceCapabilities, Error, Extent2D, Feature, Image, Offset2D,
+    Pipeline, QueryPool, Rect2D, Result, Viewport,
 };
 
 /// Command pool for allocating command buffers
@@ -174,6 +175,27 @@ pub trait CommandBuffer: Send + Sync {
 
     /// Set push constants
     fn push_constants(&mut self, stages: ShaderStageFlags, offset: u32, data: &[u8]);
+
+    // === Debug Labels ===
+
+    /// Open a debug label region, closed by `end_debug_label`
+    fn begin_debug_label(&mut self, label: &DebugLabel);
+
+    /// Close the innermost debug label region
+    fn end_debug_label(&mut self);
+
+    /// Insert a single debug label
+    fn insert_debug_label(&mut self, label: &DebugLabel);
+
+    // === Queries ===
+
+    /// Write the GPU clock into query `query` of `pool` once all previous
+    /// commands finished
+    fn write_timestamp(&mut self, _pool: &dyn QueryPool, _query: u32) -> Result<()> {
+        Err(Error::FeatureNotPresent(Feature::Capabilities(
+            DeviceCapabilities::TIMESTAMP_QUERIES,
+        )))
+    }
 }
 
 /// Render pass descriptor
@@ -0,0 +1,137 @@
+//! Debug labels and object naming
+//!
+//! Objects can be given human-readable names with [`Device::set_object_name`]
+//! and command buffers can be annotated with nested label regions. Names are
+//! kept in a process-wide registry so that error messages and GPU traces can
+//! refer to "shadow map pass" instead of a raw handle.
+//!
+//! [`Device::set_object_name`]: crate::Device::set_object_name
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+
+use crate::Handle;
+
+/// Kind of GAL object a name refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ObjectType {
+    Buffer,
+    Image,
+    Sampler,
+    Memory,
+    Shader,
+    Pipeline,
+    CommandPool,
+    CommandBuffer,
+    Fence,
+    Semaphore,
+    Event,
+    Swapchain,
+    Queue,
+    QueryPool,
+}
+
+impl ObjectType {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            ObjectType::Buffer => "Buffer",
+            ObjectType::Image => "Image",
+            ObjectType::Sampler => "Sampler",
+            ObjectType::Memory => "Memory",
+            ObjectType::Shader => "Shader",
+            ObjectType::Pipeline => "Pipeline",
+            ObjectType::CommandPool => "CommandPool",
+            ObjectType::CommandBuffer => "CommandBuffer",
+            ObjectType::Fence => "Fence",
+            ObjectType::Semaphore => "Semaphore",
+            ObjectType::Event => "Event",
+            ObjectType::Swapchain => "Swapchain",
+            ObjectType::Queue => "Queue",
+            ObjectType::QueryPool => "QueryPool",
+        }
+    }
+}
+
+/// Debug label for command buffer regions
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugLabel {
+    /// Label text
+    pub name: String,
+    /// Optional RGBA color used by capture tools
+    pub color: Option<[f32; 4]>,
+}
+
+impl DebugLabel {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            color: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = Some(color);
+        self
+    }
+}
+
+/// Debug label event, as emitted to the GPU trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelEvent {
+    /// A label region was opened
+    Begin,
+    /// The innermost label region was closed
+    End,
+    /// A single point label was inserted
+    Insert,
+}
+
+static OBJECT_NAMES: spin::Mutex<BTreeMap<(ObjectType, Handle), String>> =
+    spin::Mutex::new(BTreeMap::new());
+
+/// Set or replace the name of an object in the registry
+///
+/// An empty name removes the entry.
+pub fn set_object_name(object_type: ObjectType, handle: Handle, name: &str) {
+    let mut names = OBJECT_NAMES.lock();
+    if name.is_empty() {
+        names.remove(&(object_type, handle));
+    } else {
+        names.insert((object_type, handle), String::from(name));
+    }
+    log::trace!(
+        "gal: {} {} named {:?}",
+        object_type.name(),
+        handle,
+        name
+    );
+}
+
+/// Look up the name of an object
+pub fn object_name(object_type: ObjectType, handle: Handle) -> Option<String> {
+    OBJECT_NAMES.lock().get(&(object_type, handle)).cloned()
+}
+
+/// Forget the name of a destroyed object
+pub fn clear_object_name(object_type: ObjectType, handle: Handle) {
+    OBJECT_NAMES.lock().remove(&(object_type, handle));
+}
+
+/// Format an object for error messages, e.g. `Image 12 "shadow map"`
+pub fn describe(object_type: ObjectType, handle: Handle) -> String {
+    match object_name(object_type, handle) {
+        Some(name) => format!("{} {} {:?}", object_type.name(), handle, name),
+        None => format!("{} {}", object_type.name(), handle),
+    }
+}
+
+/// Emit a label event for a command buffer to the GPU trace
+pub fn trace_label(command_buffer: Handle, event: LabelEvent, label: Option<&DebugLabel>) {
+    let cmd = describe(ObjectType::CommandBuffer, command_buffer);
+    match (event, label) {
+        (LabelEvent::Begin, Some(label)) => log::trace!("gal: {} begin {:?}", cmd, label.name),
+        (LabelEvent::Insert, Some(label)) => log::trace!("gal: {} label {:?}", cmd, label.name),
+        _ => log::trace!("gal: {} end label", cmd),
+    }
+}
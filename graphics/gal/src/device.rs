@@ -8,9 +8,17 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use bitflags::bitflags;
 
+use crate::allocator::{Heap, HeapStats};
+use crate::display::PresentMode;
+use crate::external::{ExternalFence, ExternalImageLayout, ExternalMemory};
+use crate::image::ImageDimension;
+use crate::pipeline::PipelineCache;
+use crate::swapchain::PresentRequest;
+use crate::video::VideoDecode;
 use crate::{
-    Buffer, BufferDescriptor, CommandPool, Error, Extent2D, Fence, Image, ImageDescriptor, Memory,
-    MemoryType, Pipeline, Queue, QueueType, Result, Semaphore, Shader, ShaderStage,
+    Buffer, BufferDescriptor, CommandPool, Error, Extent2D, Fence, Handle, Image, ImageDescriptor,
+    ImageFormat, ImageUsage, Memory, MemoryType, ObjectType, Pipeline, QueryPool, QueryType, Queue,
+    QueueType, Result, Semaphore, Shader, ShaderStage,
 };
 
 /// Type of GPU device
@@ -76,6 +84,61 @@ bitflags! {
         const RAY_TRACING = 1 << 14;
         /// Supports mesh shaders
         const MESH_SHADERS = 1 << 15;
+        /// Supports exporting and importing memory and images
+        const EXTERNAL_MEMORY = 1 << 16;
+        /// Supports exporting and importing fences
+        const EXTERNAL_FENCE = 1 << 17;
+        /// Supports multi-planar YUV images (NV12, P010)
+        const YUV_IMAGES = 1 << 18;
+        /// Supports samplers converting YUV images to RGB
+        const SAMPLER_YCBCR_CONVERSION = 1 << 19;
+        /// Supports sampling BC1-7 compressed images
+        const TEXTURE_COMPRESSION_BC = 1 << 20;
+        /// Supports sampling ASTC compressed images
+        const TEXTURE_COMPRESSION_ASTC = 1 << 21;
+        /// Supports geometry shaders
+        const GEOMETRY_SHADERS = 1 << 22;
+        /// Supports tessellation control and evaluation shaders
+        const TESSELLATION_SHADERS = 1 << 23;
+        /// Supports ray queries from any shader stage
+        const RAY_QUERY = 1 << 24;
+        /// Supports timestamp queries, see [`DeviceInfo::timestamp_period`]
+        const TIMESTAMP_QUERIES = 1 << 25;
+        /// Supports decoding video, see [`Device::video_decode`]
+        const VIDEO_DECODE = 1 << 26;
+    }
+}
+
+/// Something a device may or may not support, as asked with
+/// [`Device::supports`]
+///
+/// Limits are asked for as the value the caller needs, so that a single
+/// query answers "can I create this?".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// All of the capability flags
+    Capabilities(DeviceCapabilities),
+    /// 1D and 2D images this wide and high
+    TextureSize2d(u32),
+    /// 3D images this wide, high and deep
+    TextureSize3d(u32),
+    /// Images with this many array layers
+    TextureLayers(u32),
+    /// Sampled images of this format
+    Format(ImageFormat),
+}
+
+impl Feature {
+    /// Capability flags the feature needs
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        match self {
+            Feature::Capabilities(capabilities) => *capabilities,
+            Feature::Format(format) if format.is_compressed() => {
+                DeviceCapabilities::TEXTURE_COMPRESSION_BC
+            }
+            Feature::Format(format) if format.is_yuv() => DeviceCapabilities::YUV_IMAGES,
+            _ => DeviceCapabilities::empty(),
+        }
     }
 }
 
@@ -114,6 +177,50 @@ pub struct DeviceInfo {
     pub max_compute_work_group_invocations: u32,
     /// Total device memory (bytes)
     pub total_memory: u64,
+    /// Nanoseconds per timestamp query tick, 0 without
+    /// [`DeviceCapabilities::TIMESTAMP_QUERIES`]
+    pub timestamp_period: f32,
+}
+
+impl DeviceInfo {
+    /// Check whether the device supports `feature`
+    pub fn supports(&self, feature: Feature) -> bool {
+        if !self.capabilities.contains(feature.capabilities()) {
+            return false;
+        }
+        match feature {
+            Feature::TextureSize2d(size) => size <= self.max_texture_2d,
+            Feature::TextureSize3d(size) => size <= self.max_texture_3d,
+            Feature::TextureLayers(layers) => layers <= self.max_texture_layers,
+            Feature::Capabilities(_) | Feature::Format(_) => true,
+        }
+    }
+
+    /// Fail with [`Error::FeatureNotPresent`] unless the device supports
+    /// `feature`
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        if self.supports(feature) {
+            Ok(())
+        } else {
+            Err(Error::FeatureNotPresent(feature))
+        }
+    }
+
+    /// Check that images like `descriptor` fit the capabilities and limits
+    ///
+    /// Backends call this before creating an image, so that every backend
+    /// names the missing feature the same way.
+    pub fn check_image(&self, descriptor: &ImageDescriptor) -> Result<()> {
+        let extent = descriptor.extent;
+        match descriptor.dimension {
+            ImageDimension::D3 => self.require(Feature::TextureSize3d(
+                extent.width.max(extent.height).max(extent.depth),
+            ))?,
+            _ => self.require(Feature::TextureSize2d(extent.width.max(extent.height)))?,
+        }
+        self.require(Feature::TextureLayers(descriptor.array_layers))?;
+        self.require(Feature::Format(descriptor.format))
+    }
 }
 
 impl Default for DeviceInfo {
@@ -135,6 +242,7 @@ impl Default for DeviceInfo {
             max_compute_work_group_size: [256, 256, 64],
             max_compute_work_group_invocations: 256,
             total_memory: 0,
+            timestamp_period: 0.0,
         }
     }
 }
@@ -165,8 +273,10 @@ pub struct SwapchainConfig {
     pub extent: Extent2D,
     /// Number of buffers (double/triple buffering)
     pub buffer_count: u32,
-    /// V-sync enabled
-    pub vsync: bool,
+    /// Format of the images
+    pub format: ImageFormat,
+    /// How presented images reach the display
+    pub present_mode: PresentMode,
 }
 
 /// Core device trait for GPU operations
@@ -174,6 +284,11 @@ pub trait Device: Send + Sync {
     /// Get device information
     fn info(&self) -> &DeviceInfo;
 
+    /// Check whether the device supports `feature`
+    fn supports(&self, feature: Feature) -> bool {
+        self.info().supports(feature)
+    }
+
     /// Get list of displays
     fn displays(&self) -> Vec<DisplayInfo>;
 
@@ -198,6 +313,15 @@ pub trait Device: Send + Sync {
     /// Create a semaphore
     fn create_semaphore(&self) -> Result<Box<dyn Semaphore>>;
 
+    /// Create a pool of `count` queries
+    fn create_query_pool(&self, query_type: QueryType, _count: u32) -> Result<Box<dyn QueryPool>> {
+        match query_type {
+            QueryType::Timestamp => Err(Error::FeatureNotPresent(Feature::Capabilities(
+                DeviceCapabilities::TIMESTAMP_QUERIES,
+            ))),
+        }
+    }
+
     /// Create a shader module
     fn create_shader(&self, stage: ShaderStage, code: &[u8]) -> Result<Box<dyn Shader>>;
 
@@ -224,6 +348,75 @@ pub trait Device: Send + Sync {
 
     /// Create a swapchain for presentation
     fn create_swapchain(&self, config: &SwapchainConfig) -> Result<Box<dyn Swapchain>>;
+
+    /// Cache the backend keeps compiled pipelines in, if it compiles any
+    ///
+    /// Applications load it at startup and save it at exit to skip compiling
+    /// the same pipelines again on the next run.
+    fn pipeline_cache(&self) -> Option<&PipelineCache> {
+        None
+    }
+
+    /// Attach a debug name to an object
+    ///
+    /// Backends that can forward names to the host or driver should do so in
+    /// addition to recording them in the [`crate::debug`] registry.
+    fn set_object_name(&self, object_type: ObjectType, handle: Handle, name: &str) -> Result<()> {
+        crate::debug::set_object_name(object_type, handle, name);
+        Ok(())
+    }
+
+    /// Export memory so that another device or process can import it
+    fn export_memory(&self, _memory: &dyn Memory) -> Result<ExternalMemory> {
+        Err(Error::NotSupported)
+    }
+
+    /// Import memory exported by another device
+    fn import_memory(
+        &self,
+        _memory: &ExternalMemory,
+        _memory_type: MemoryType,
+    ) -> Result<Box<dyn Memory>> {
+        Err(Error::NotSupported)
+    }
+
+    /// Export the memory backing an image together with its layout
+    fn export_image(&self, _image: &dyn Image) -> Result<(ExternalMemory, ExternalImageLayout)> {
+        Err(Error::NotSupported)
+    }
+
+    /// Create an image on top of memory exported by another device
+    fn import_image(
+        &self,
+        _memory: &ExternalMemory,
+        _layout: &ExternalImageLayout,
+        _usage: ImageUsage,
+    ) -> Result<Box<dyn Image>> {
+        Err(Error::NotSupported)
+    }
+
+    /// Export a fence so that another device can wait for it
+    fn export_fence(&self, _fence: &dyn Fence) -> Result<ExternalFence> {
+        Err(Error::NotSupported)
+    }
+
+    /// Import a fence exported by another device
+    fn import_fence(&self, _fence: &ExternalFence) -> Result<Box<dyn Fence>> {
+        Err(Error::NotSupported)
+    }
+    /// Video decoder of the device, with [`DeviceCapabilities::VIDEO_DECODE`]
+    fn video_decode(&self) -> Option<&dyn VideoDecode> {
+        None
+    }
+
+    /// Usage of a heap by every user of the device, with the budget the
+    /// driver keeps it within
+    ///
+    /// Drivers that move memory between heaps under pressure report how
+    /// much fits before allocations start spilling into the slower heap.
+    fn memory_budget(&self, _heap: Heap) -> Option<HeapStats> {
+        None
+    }
 }
 
 /// Swapchain for presenting to displays
@@ -246,7 +439,20 @@ pub trait Swapchain: Send + Sync {
     fn image(&self, index: u32) -> &dyn Image;
 
     /// Present the rendered image
-    fn present(&self, image_index: u32, wait_semaphores: &[&dyn Semaphore]) -> Result<()>;
+    fn present(&self, image_index: u32, wait_semaphores: &[&dyn Semaphore]) -> Result<()> {
+        self.present_with(
+            image_index,
+            PresentRequest {
+                wait_semaphores,
+                ..Default::default()
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Present the rendered image with damage and feedback, returning the
+    /// ID its feedback will carry
+    fn present_with(&self, image_index: u32, request: PresentRequest) -> Result<u64>;
 }
 
 /// Descriptor for graphics pipeline creation
@@ -478,14 +684,7 @@ pub fn enumerate_devices() -> Result<Vec<DeviceInfo>> {
     }
 
     // Add software renderer as fallback
-    devices.push(DeviceInfo {
-        name: String::from("Software Renderer"),
-        vendor_id: 0,
-        device_id: 0,
-        device_type: DeviceType::Software,
-        capabilities: DeviceCapabilities::BLIT_2D | DeviceCapabilities::RENDER_3D,
-        ..Default::default()
-    });
+    devices.push(crate::software::device_info());
 
     Ok(devices)
 }
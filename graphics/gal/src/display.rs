@@ -0,0 +1,208 @@
+//! Display targets
+//!
+//! A display target is one scanout engine of a GPU (a CRTC, a transcoder,
+//! a virtual scanout). Backends implement [`DisplayTarget`] for each of
+//! them; [`crate::swapchain::Swapchain`] builds presentation on top, so
+//! compositors have the same path on every backend.
+//!
+//! The display engine reads one image, and switches to a new one at the
+//! next vertical blank after it was flipped to. Until then the flip is
+//! pending and the previous image is still being read.
+//!
+//! Presentation timestamps are in nanoseconds of `CLOCK_MONOTONIC`, see
+//! [`monotonic_ns`].
+//!
+//! Targets driving a physical connector follow hotplug: the display behind
+//! them can come and go and change modes, which bumps
+//! [`DisplayTarget::configuration_serial`]. Compositors then query the
+//! target again and recreate their swapchains.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::device::DisplayInfo;
+use crate::image::{ImageDescriptor, ImageDimension};
+use crate::{
+    Error, Extent2D, Extent3D, Fence, Image, ImageFormat, ImageUsage, Memory, Rect2D, Result,
+    Semaphore,
+};
+
+/// How presented images reach the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Every presented image is shown for at least one refresh, in order;
+    /// acquiring waits for the display once every image is queued or shown
+    Fifo,
+    /// Only the newest presented image is shown at the next refresh, older
+    /// ones still waiting are released without being shown
+    Mailbox,
+}
+
+impl PresentMode {
+    /// Images a swapchain needs in this mode so that rendering never waits
+    /// on the display: one on screen, one flipping to and, for mailbox, one
+    /// being rendered while another waits
+    pub fn min_image_count(&self) -> u32 {
+        match self {
+            PresentMode::Fifo => 2,
+            PresentMode::Mailbox => 3,
+        }
+    }
+}
+
+/// Resolution and refresh rate a display can be driven at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayMode {
+    pub extent: Extent2D,
+    /// Refresh rate in millihertz
+    pub refresh_mhz: u32,
+    /// The display's native mode
+    pub preferred: bool,
+}
+
+/// Scanout engine presenting images on one display
+pub trait DisplayTarget: Send + Sync {
+    /// Display this target drives
+    fn info(&self) -> DisplayInfo;
+
+    /// Formats the display engine can scan out
+    fn formats(&self) -> &[ImageFormat];
+
+    /// Check if the target supports a present mode
+    fn supports_present_mode(&self, mode: PresentMode) -> bool;
+
+    /// Allocate an image the display engine can scan out
+    fn create_image(&self, extent: Extent2D, format: ImageFormat) -> Result<Box<dyn Image>>;
+
+    /// Release an image from [`DisplayTarget::create_image`]
+    fn destroy_image(&self, image: Box<dyn Image>);
+
+    /// Scan out `image` from the next vertical blank on
+    ///
+    /// `image` was created by [`DisplayTarget::create_image`]. Only called
+    /// while no flip is pending. `damage` lists the regions where `image`
+    /// differs from the image on screen, empty if it may differ anywhere;
+    /// targets that copy or flush to the screen need only update those.
+    fn flip(&self, image: &dyn Image, damage: &[Rect2D]) -> Result<()>;
+
+    /// Check if the last flip hasn't reached the screen yet
+    fn flip_pending(&self) -> bool;
+
+    /// When the last completed flip reached the screen, `None` if the
+    /// target can't tell
+    fn flip_timestamp_ns(&self) -> Option<u64>;
+
+    /// Wait for the next vertical blank, returns false on timeout
+    fn wait_vblank(&self, timeout_ns: u64) -> Result<bool>;
+
+    /// Wait until the GPU signaled `semaphores`
+    fn wait_semaphores(&self, semaphores: &[&dyn Semaphore]) -> Result<()>;
+
+    /// Signal `semaphore` and `fence` once the GPU finished the work
+    /// submitted so far
+    fn signal(&self, semaphore: Option<&dyn Semaphore>, fence: Option<&dyn Fence>) -> Result<()>;
+
+    /// Modes the display supports, only the current one by default
+    fn modes(&self) -> Vec<DisplayMode> {
+        let info = self.info();
+        vec![DisplayMode {
+            extent: info.extent,
+            refresh_mhz: info.refresh_rate * 1000,
+            preferred: true,
+        }]
+    }
+
+    /// Drive the display in `mode`, one of [`DisplayTarget::modes`]
+    ///
+    /// Images created for another extent must not be flipped to afterwards.
+    fn set_mode(&self, mode: &DisplayMode) -> Result<()> {
+        if self.modes().first() == Some(mode) {
+            Ok(())
+        } else {
+            Err(Error::NotSupported)
+        }
+    }
+
+    /// Counter that changes whenever a display is plugged in or unplugged
+    /// or the mode changes
+    fn configuration_serial(&self) -> u64 {
+        0
+    }
+}
+
+/// Current time of `CLOCK_MONOTONIC` in nanoseconds, the clock of
+/// presentation timestamps
+#[cfg(target_os = "redox")]
+pub fn monotonic_ns() -> Option<u64> {
+    let ts = libredox::call::clock_gettime(libredox::flag::CLOCK_MONOTONIC).ok()?;
+    Some(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+}
+
+/// Nanoseconds since the first call, on hosts without Redox' clock calls
+#[cfg(not(target_os = "redox"))]
+pub fn monotonic_ns() -> Option<u64> {
+    static START: spin::Once<std::time::Instant> = spin::Once::new();
+    let start = START.call_once(std::time::Instant::now);
+    Some(start.elapsed().as_nanos() as u64)
+}
+
+/// Image in memory the backend manages itself, such as a GEM object
+///
+/// For backends without their own [`Image`] type.
+pub struct ScanoutImage {
+    handle: usize,
+    extent: Extent3D,
+    format: ImageFormat,
+    usage: ImageUsage,
+}
+
+impl ScanoutImage {
+    /// Describe the image behind the backend's `handle`
+    pub fn new(handle: usize, descriptor: &ImageDescriptor) -> Self {
+        Self {
+            handle,
+            extent: descriptor.extent,
+            format: descriptor.format,
+            usage: descriptor.usage,
+        }
+    }
+}
+
+impl Image for ScanoutImage {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn dimension(&self) -> ImageDimension {
+        ImageDimension::D2
+    }
+
+    fn extent(&self) -> Extent3D {
+        self.extent
+    }
+
+    fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    fn mip_levels(&self) -> u32 {
+        1
+    }
+
+    fn array_layers(&self) -> u32 {
+        1
+    }
+
+    fn sample_count(&self) -> u32 {
+        1
+    }
+
+    fn usage(&self) -> ImageUsage {
+        self.usage
+    }
+
+    fn memory(&self) -> Option<&dyn Memory> {
+        None
+    }
+}
@@ -0,0 +1,66 @@
+//! External memory and fence sharing
+//!
+//! Memory and fences can be exported from one device as file handles and
+//! imported into another. This is what allows a frame rendered on one GPU to
+//! be scanned out by another, see [`crate::offload`].
+
+use crate::{Extent2D, ImageFormat};
+
+/// Exported device memory
+///
+/// `fd` is a file handle to the exporting driver's scheme; the importing
+/// driver maps the pages behind it into its own address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalMemory {
+    pub fd: usize,
+    /// Size of the shared allocation in bytes
+    pub size: u64,
+}
+
+/// Layout of an image placed in [`ExternalMemory`]
+///
+/// Devices only agree on linear layouts unless both understand `modifier`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalImageLayout {
+    pub format: ImageFormat,
+    pub extent: Extent2D,
+    /// Offset of the first pixel in the memory
+    pub offset: u64,
+    /// Bytes between the start of two rows
+    pub stride: u32,
+    /// Driver specific tiling, [`ExternalImageLayout::LINEAR`] if untiled
+    pub modifier: u64,
+}
+
+impl ExternalImageLayout {
+    pub const LINEAR: u64 = 0;
+
+    /// Tightly packed linear layout with rows aligned to `row_alignment`
+    pub fn linear(format: ImageFormat, extent: Extent2D, row_alignment: u32) -> Option<Self> {
+        let stride = extent
+            .width
+            .checked_mul(format.bytes_per_pixel()?)?
+            .next_multiple_of(row_alignment);
+        Some(Self {
+            format,
+            extent,
+            offset: 0,
+            stride,
+            modifier: Self::LINEAR,
+        })
+    }
+
+    /// Number of bytes the image occupies
+    pub fn size(&self) -> u64 {
+        self.offset + u64::from(self.stride) * u64::from(self.extent.height)
+    }
+}
+
+/// Exported fence
+///
+/// The file handle becomes readable once the fence is signaled on the
+/// exporting device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalFence {
+    pub fd: usize,
+}
@@ -0,0 +1,1101 @@
+//! Render graph
+//!
+//! A render graph collects the passes of a frame together with the images
+//! and buffers each of them reads and writes, and derives the rest: the
+//! order the passes run in, the pipeline barriers and layout transitions
+//! between them, and which transient resources can share an allocation.
+//! Passes that contribute neither to an imported resource nor to a side
+//! effect are culled.
+//!
+//! ```ignore
+//! let mut graph = RenderGraph::new();
+//! let backbuffer = graph.import_image(
+//!     "backbuffer",
+//!     swapchain_image,
+//!     ImageLayout::Undefined,
+//!     ImageLayout::PresentSrc,
+//! );
+//! let hdr = graph.create_image("hdr", ImageDescriptor::render_target(w, h, format));
+//!
+//! graph
+//!     .add_pass("scene")
+//!     .write(hdr, Access::ColorAttachment)
+//!     .execute(|cmd, resources| draw_scene(cmd, resources.image(hdr)?));
+//! graph
+//!     .add_pass("tonemap")
+//!     .read(hdr, Access::Sampled(ShaderStageFlags::FRAGMENT))
+//!     .write(backbuffer, Access::ColorAttachment)
+//!     .execute(|cmd, resources| tonemap(cmd, resources));
+//!
+//! // The transient images must outlive the submission
+//! let transients = graph.record(device, cmd)?;
+//! ```
+//!
+//! Reads bind to the last write declared before them. A transient resource
+//! that is read before any write was declared binds to its first write
+//! instead, so passes don't have to be declared in execution order.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::command::{
+    AccessFlags, BufferMemoryBarrier, ImageAspect, ImageLayout, ImageMemoryBarrier,
+    ImageSubresourceRange, PipelineBarrier, PipelineStageFlags, ShaderStageFlags,
+};
+use crate::{
+    Buffer, BufferDescriptor, BufferUsage, CommandBuffer, DebugLabel, Device, Error, Image,
+    ImageDescriptor, ImageUsage, ObjectType, Result,
+};
+
+/// Resource declared in a [`RenderGraph`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResourceId(usize);
+
+/// Pass declared in a [`RenderGraph`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PassId(usize);
+
+/// How a pass uses a resource
+///
+/// Whether the use reads or writes is given by [`PassBuilder::read`] and
+/// [`PassBuilder::write`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Color attachment; reads are loads and blending
+    ColorAttachment,
+    /// Depth/stencil attachment; writes include the depth test reads
+    DepthStencilAttachment,
+    /// Image sampled by the given shader stages
+    Sampled(ShaderStageFlags),
+    /// Storage image or buffer used by the given shader stages
+    Storage(ShaderStageFlags),
+    /// Uniform buffer read by the given shader stages
+    Uniform(ShaderStageFlags),
+    /// Vertex buffer
+    VertexBuffer,
+    /// Index buffer
+    IndexBuffer,
+    /// Indirect draw or dispatch arguments
+    IndirectBuffer,
+    /// Copy, blit, clear or resolve
+    Transfer,
+    /// Image handed to the presentation engine
+    Present,
+}
+
+impl Access {
+    /// Pipeline stages that perform the access
+    pub fn stages(self) -> PipelineStageFlags {
+        match self {
+            Access::ColorAttachment => PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            Access::DepthStencilAttachment => {
+                PipelineStageFlags::EARLY_FRAGMENT_TESTS | PipelineStageFlags::LATE_FRAGMENT_TESTS
+            }
+            Access::Sampled(stages) | Access::Storage(stages) | Access::Uniform(stages) => {
+                shader_stages(stages)
+            }
+            Access::VertexBuffer | Access::IndexBuffer => PipelineStageFlags::VERTEX_INPUT,
+            Access::IndirectBuffer => PipelineStageFlags::DRAW_INDIRECT,
+            Access::Transfer => PipelineStageFlags::TRANSFER,
+            Access::Present => PipelineStageFlags::BOTTOM_OF_PIPE,
+        }
+    }
+
+    /// Memory access flags, or `None` if the access can't write
+    pub fn access_flags(self, write: bool) -> Option<AccessFlags> {
+        let flags = match (self, write) {
+            (Access::ColorAttachment, false) => AccessFlags::COLOR_ATTACHMENT_READ,
+            (Access::ColorAttachment, true) => AccessFlags::COLOR_ATTACHMENT_WRITE,
+            (Access::DepthStencilAttachment, false) => AccessFlags::DEPTH_STENCIL_READ,
+            (Access::DepthStencilAttachment, true) => {
+                AccessFlags::DEPTH_STENCIL_READ | AccessFlags::DEPTH_STENCIL_WRITE
+            }
+            (Access::Sampled(_), false) => AccessFlags::SHADER_READ,
+            (Access::Storage(_), false) => AccessFlags::SHADER_READ,
+            (Access::Storage(_), true) => AccessFlags::SHADER_WRITE,
+            (Access::Uniform(_), false) => AccessFlags::UNIFORM_READ,
+            (Access::VertexBuffer, false) => AccessFlags::VERTEX_ATTRIBUTE_READ,
+            (Access::IndexBuffer, false) => AccessFlags::INDEX_READ,
+            (Access::IndirectBuffer, false) => AccessFlags::INDIRECT_COMMAND_READ,
+            (Access::Transfer, false) => AccessFlags::TRANSFER_READ,
+            (Access::Transfer, true) => AccessFlags::TRANSFER_WRITE,
+            (Access::Present, false) => AccessFlags::NONE,
+            _ => return None,
+        };
+        Some(flags)
+    }
+
+    /// Layout an image needs for the access
+    pub fn layout(self, write: bool) -> ImageLayout {
+        match (self, write) {
+            (Access::ColorAttachment, _) => ImageLayout::ColorAttachmentOptimal,
+            (Access::DepthStencilAttachment, false) => ImageLayout::DepthStencilReadOnlyOptimal,
+            (Access::DepthStencilAttachment, true) => ImageLayout::DepthStencilAttachmentOptimal,
+            (Access::Sampled(_), _) => ImageLayout::ShaderReadOnlyOptimal,
+            (Access::Transfer, false) => ImageLayout::TransferSrcOptimal,
+            (Access::Transfer, true) => ImageLayout::TransferDstOptimal,
+            (Access::Present, _) => ImageLayout::PresentSrc,
+            _ => ImageLayout::General,
+        }
+    }
+
+    /// Image usage the access needs, or `None` if images can't be used so
+    pub fn image_usage(self, write: bool) -> Option<ImageUsage> {
+        match (self, write) {
+            (Access::ColorAttachment, _) => Some(ImageUsage::COLOR_ATTACHMENT),
+            (Access::DepthStencilAttachment, _) => Some(ImageUsage::DEPTH_STENCIL_ATTACHMENT),
+            (Access::Sampled(_), _) => Some(ImageUsage::SAMPLED),
+            (Access::Storage(_), _) => Some(ImageUsage::STORAGE),
+            (Access::Transfer, false) => Some(ImageUsage::TRANSFER_SRC),
+            (Access::Transfer, true) => Some(ImageUsage::TRANSFER_DST),
+            (Access::Present, _) => Some(ImageUsage::empty()),
+            _ => None,
+        }
+    }
+
+    /// Buffer usage the access needs, or `None` if buffers can't be used so
+    pub fn buffer_usage(self, write: bool) -> Option<BufferUsage> {
+        match (self, write) {
+            (Access::Storage(_), _) => Some(BufferUsage::STORAGE),
+            (Access::Uniform(_), _) => Some(BufferUsage::UNIFORM),
+            (Access::VertexBuffer, _) => Some(BufferUsage::VERTEX),
+            (Access::IndexBuffer, _) => Some(BufferUsage::INDEX),
+            (Access::IndirectBuffer, _) => Some(BufferUsage::INDIRECT),
+            (Access::Transfer, false) => Some(BufferUsage::TRANSFER_SRC),
+            (Access::Transfer, true) => Some(BufferUsage::TRANSFER_DST),
+            _ => None,
+        }
+    }
+}
+
+/// Pipeline stages running the given shader stages
+fn shader_stages(stages: ShaderStageFlags) -> PipelineStageFlags {
+    let mut flags = PipelineStageFlags::empty();
+    if stages.contains(ShaderStageFlags::VERTEX) {
+        flags |= PipelineStageFlags::VERTEX_SHADER;
+    }
+    if stages.contains(ShaderStageFlags::FRAGMENT) {
+        flags |= PipelineStageFlags::FRAGMENT_SHADER;
+    }
+    if stages.contains(ShaderStageFlags::COMPUTE) {
+        flags |= PipelineStageFlags::COMPUTE_SHADER;
+    }
+    if stages.intersects(
+        ShaderStageFlags::GEOMETRY
+            | ShaderStageFlags::TESSELLATION_CONTROL
+            | ShaderStageFlags::TESSELLATION_EVALUATION,
+    ) {
+        // There are no stage flags for these
+        flags |= PipelineStageFlags::ALL_GRAPHICS;
+    }
+    flags
+}
+
+enum ResourceKind<'a> {
+    Image {
+        image: &'a dyn Image,
+        initial_layout: ImageLayout,
+        final_layout: ImageLayout,
+    },
+    Buffer(&'a dyn Buffer),
+    TransientImage(ImageDescriptor),
+    TransientBuffer(BufferDescriptor),
+}
+
+impl ResourceKind<'_> {
+    fn is_image(&self) -> bool {
+        matches!(
+            self,
+            ResourceKind::Image { .. } | ResourceKind::TransientImage(_)
+        )
+    }
+
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ResourceKind::TransientImage(_) | ResourceKind::TransientBuffer(_)
+        )
+    }
+}
+
+struct ResourceEntry<'a> {
+    name: String,
+    kind: ResourceKind<'a>,
+}
+
+type ExecuteFn<'a> = Box<dyn FnOnce(&mut dyn CommandBuffer, &PassResources<'_>) -> Result<()> + 'a>;
+
+struct PassEntry<'a> {
+    name: String,
+    reads: Vec<(ResourceId, Access)>,
+    writes: Vec<(ResourceId, Access)>,
+    side_effects: bool,
+    execute: Option<ExecuteFn<'a>>,
+}
+
+/// Frame render graph
+///
+/// Resources and passes are declared up front; [`RenderGraph::record`] then
+/// records the whole frame into a command buffer.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<ResourceEntry<'a>>,
+    passes: Vec<PassEntry<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// Create an empty graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use an existing image, currently in `initial_layout`
+    ///
+    /// The image is left in `final_layout` at the end of the graph.
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: &'a dyn Image,
+        initial_layout: ImageLayout,
+        final_layout: ImageLayout,
+    ) -> ResourceId {
+        self.add_resource(
+            name,
+            ResourceKind::Image {
+                image,
+                initial_layout,
+                final_layout,
+            },
+        )
+    }
+
+    /// Use an existing buffer
+    ///
+    /// Writes from earlier submissions must already be visible.
+    pub fn import_buffer(&mut self, name: &str, buffer: &'a dyn Buffer) -> ResourceId {
+        self.add_resource(name, ResourceKind::Buffer(buffer))
+    }
+
+    /// Declare an image that only lives for the duration of the graph
+    ///
+    /// The usage flags the passes need are added to the descriptor.
+    pub fn create_image(&mut self, name: &str, descriptor: ImageDescriptor) -> ResourceId {
+        self.add_resource(name, ResourceKind::TransientImage(descriptor))
+    }
+
+    /// Declare a buffer that only lives for the duration of the graph
+    ///
+    /// The usage flags the passes need are added to the descriptor.
+    pub fn create_buffer(&mut self, name: &str, descriptor: BufferDescriptor) -> ResourceId {
+        self.add_resource(name, ResourceKind::TransientBuffer(descriptor))
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind<'a>) -> ResourceId {
+        self.resources.push(ResourceEntry {
+            name: String::from(name),
+            kind,
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Declare a pass
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        self.passes.push(PassEntry {
+            name: String::from(name),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            side_effects: false,
+            execute: None,
+        });
+        let pass = self.passes.len() - 1;
+        PassBuilder { graph: self, pass }
+    }
+
+    /// Name of a resource
+    pub fn resource_name(&self, resource: ResourceId) -> Option<&str> {
+        self.resources.get(resource.0).map(|r| r.name.as_str())
+    }
+
+    /// Name of a pass
+    pub fn pass_name(&self, pass: PassId) -> Option<&str> {
+        self.passes.get(pass.0).map(|p| p.name.as_str())
+    }
+
+    /// Check that every use is possible on its resource
+    fn validate(&self) -> Result<()> {
+        for pass in &self.passes {
+            let uses = pass.reads.iter().map(|u| (u, false));
+            for (&(resource, access), write) in uses.chain(pass.writes.iter().map(|u| (u, true))) {
+                let entry = self
+                    .resources
+                    .get(resource.0)
+                    .ok_or(Error::InvalidParameter)?;
+                let usable = access.access_flags(write).is_some()
+                    && if entry.kind.is_image() {
+                        access.image_usage(write).is_some()
+                    } else {
+                        access.buffer_usage(write).is_some()
+                    };
+                if !usable {
+                    log::warn!(
+                        "gal: pass {:?} can't {} {:?} as {:?}",
+                        pass.name,
+                        if write { "write" } else { "read" },
+                        entry.name,
+                        access
+                    );
+                    return Err(Error::InvalidParameter);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Derive pass order, barriers and transient allocations
+    ///
+    /// Fails with [`Error::InvalidParameter`] if a use doesn't fit its
+    /// resource, a transient resource is read but never written, or the
+    /// passes depend on each other in a cycle.
+    pub fn compile(&self) -> Result<Schedule> {
+        self.validate()?;
+        let (data_deps, order_deps) = self.dependencies()?;
+        let needed = self.needed_passes(&data_deps);
+        let order = self.order(&needed, &data_deps, &order_deps)?;
+        let (allocations, allocation_count) = self.allocate(&order);
+        let (passes, final_barriers) = self.barriers(&order, &allocations, allocation_count);
+
+        let culled = (0..self.passes.len())
+            .filter(|&pass| !needed[pass])
+            .map(PassId)
+            .collect::<Vec<_>>();
+        if !culled.is_empty() {
+            log::trace!("gal: render graph culled {} pass(es)", culled.len());
+        }
+
+        Ok(Schedule {
+            passes,
+            final_barriers,
+            culled,
+            allocations,
+            allocation_count,
+        })
+    }
+
+    /// Dependencies of each pass, split into those on data it consumes and
+    /// those that only order it after readers of what it overwrites
+    fn dependencies(&self) -> Result<(Dependencies, Dependencies)> {
+        let count = self.passes.len();
+        let mut data_deps = vec![Vec::new(); count];
+        let mut order_deps = vec![Vec::new(); count];
+
+        let mut last_writer: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut readers: Vec<Vec<usize>> = vec![Vec::new(); self.resources.len()];
+        // Reads of transients declared before their first write
+        let mut unbound: Vec<Vec<usize>> = vec![Vec::new(); self.resources.len()];
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for &(resource, _) in &pass.reads {
+                let r = resource.0;
+                match last_writer[r] {
+                    Some(writer) => {
+                        push_dep(&mut data_deps[index], index, writer);
+                        readers[r].push(index);
+                    }
+                    None if self.resources[r].kind.is_transient() => unbound[r].push(index),
+                    // Imported contents from before the graph
+                    None => readers[r].push(index),
+                }
+            }
+            for &(resource, _) in &pass.writes {
+                let r = resource.0;
+                match last_writer[r] {
+                    Some(writer) => {
+                        // The new contents may build on the old ones
+                        push_dep(&mut data_deps[index], index, writer);
+                        for &reader in &readers[r] {
+                            push_dep(&mut order_deps[index], index, reader);
+                        }
+                        readers[r].clear();
+                    }
+                    None => {
+                        for &reader in &readers[r] {
+                            push_dep(&mut order_deps[index], index, reader);
+                        }
+                        readers[r].clear();
+                        for reader in unbound[r].drain(..) {
+                            push_dep(&mut data_deps[reader], reader, index);
+                            readers[r].push(reader);
+                        }
+                    }
+                }
+                last_writer[r] = Some(index);
+            }
+        }
+
+        for (r, reads) in unbound.iter().enumerate() {
+            if let Some(&reader) = reads.first() {
+                log::warn!(
+                    "gal: pass {:?} reads {:?}, which is never written",
+                    self.passes[reader].name,
+                    self.resources[r].name
+                );
+                return Err(Error::InvalidParameter);
+            }
+        }
+        Ok((data_deps, order_deps))
+    }
+
+    /// Passes that contribute to an imported resource or a side effect
+    fn needed_passes(&self, data_deps: &[Vec<usize>]) -> Vec<bool> {
+        let mut needed = vec![false; self.passes.len()];
+        let mut pending = Vec::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            let exports = pass
+                .writes
+                .iter()
+                .any(|&(resource, _)| !self.resources[resource.0].kind.is_transient());
+            if pass.side_effects || exports {
+                needed[index] = true;
+                pending.push(index);
+            }
+        }
+        while let Some(index) = pending.pop() {
+            for &dep in &data_deps[index] {
+                if !needed[dep] {
+                    needed[dep] = true;
+                    pending.push(dep);
+                }
+            }
+        }
+        needed
+    }
+
+    /// Topological order of the needed passes, declaration order first
+    fn order(
+        &self,
+        needed: &[bool],
+        data_deps: &[Vec<usize>],
+        order_deps: &[Vec<usize>],
+    ) -> Result<Vec<usize>> {
+        let count = self.passes.len();
+        let mut waiting = vec![0usize; count];
+        let mut dependents = vec![Vec::new(); count];
+        for index in (0..count).filter(|&index| needed[index]) {
+            for &dep in data_deps[index].iter().chain(&order_deps[index]) {
+                if needed[dep] {
+                    waiting[index] += 1;
+                    dependents[dep].push(index);
+                }
+            }
+        }
+
+        let mut ready: VecDeque<usize> = (0..count)
+            .filter(|&index| needed[index] && waiting[index] == 0)
+            .collect();
+        let mut order = Vec::new();
+        while let Some(index) = ready.pop_front() {
+            order.push(index);
+            for &dependent in &dependents[index] {
+                waiting[dependent] -= 1;
+                if waiting[dependent] == 0 {
+                    // Keep the ready list sorted by declaration
+                    let at = ready.partition_point(|&other| other < dependent);
+                    ready.insert(at, dependent);
+                }
+            }
+        }
+
+        if order.len() != needed.iter().filter(|&&n| n).count() {
+            log::warn!("gal: render graph passes depend on each other in a cycle");
+            return Err(Error::InvalidParameter);
+        }
+        Ok(order)
+    }
+
+    /// Assign transient resources to allocations, sharing between resources
+    /// with matching descriptors whose lifetimes don't overlap
+    fn allocate(&self, order: &[usize]) -> (Vec<Option<usize>>, usize) {
+        // First and last position in `order` of each resource
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            for &(resource, _) in pass.reads.iter().chain(&pass.writes) {
+                let lifetime = &mut lifetimes[resource.0];
+                *lifetime = Some(match *lifetime {
+                    Some((first, _)) => (first, position),
+                    None => (position, position),
+                });
+            }
+        }
+
+        let mut transients: Vec<(usize, (usize, usize))> = lifetimes
+            .iter()
+            .enumerate()
+            .filter(|&(r, _)| self.resources[r].kind.is_transient())
+            .filter_map(|(r, lifetime)| lifetime.map(|lifetime| (r, lifetime)))
+            .collect();
+        transients.sort_by_key(|&(_, (first, _))| first);
+
+        let mut allocations = vec![None; self.resources.len()];
+        // Representative resource and end of the last lifetime, per allocation
+        let mut slots: Vec<(usize, usize)> = Vec::new();
+        for (r, (first, last)) in transients {
+            let free = slots.iter().position(|&(other, end)| {
+                end < first && compatible(&self.resources[other].kind, &self.resources[r].kind)
+            });
+            let slot = match free {
+                Some(slot) => {
+                    slots[slot].1 = last;
+                    slot
+                }
+                None => {
+                    slots.push((r, last));
+                    slots.len() - 1
+                }
+            };
+            allocations[r] = Some(slot);
+        }
+        (allocations, slots.len())
+    }
+
+    /// Barriers ahead of each pass in `order`, and the final transitions of
+    /// imported images
+    fn barriers(
+        &self,
+        order: &[usize],
+        allocations: &[Option<usize>],
+        allocation_count: usize,
+    ) -> (Vec<ScheduledPass>, Vec<ResourceBarrier>) {
+        // Imported resources are tracked by themselves, transients per
+        // allocation since aliased resources share the memory
+        let state_index = |r: usize| match allocations[r] {
+            Some(slot) => self.resources.len() + slot,
+            None => r,
+        };
+        let mut states = vec![State::new(None); self.resources.len() + allocation_count];
+        for (r, entry) in self.resources.iter().enumerate() {
+            if let ResourceKind::Image { initial_layout, .. } = entry.kind {
+                states[r] = State::new(Some(initial_layout));
+            }
+        }
+        // Resource whose contents each state currently holds
+        let mut owners: Vec<Option<usize>> = vec![None; states.len()];
+
+        let mut passes = Vec::new();
+        for &index in order {
+            let pass = &self.passes[index];
+            let mut barriers = Vec::new();
+            for (resource, usage) in pass_usage(pass) {
+                let r = resource.0;
+                let s = state_index(r);
+                let is_image = self.resources[r].kind.is_image();
+                if self.resources[r].kind.is_transient() && owners[s] != Some(r) {
+                    // Previous contents of the allocation are discarded
+                    owners[s] = Some(r);
+                    if is_image {
+                        states[s].layout = Some(ImageLayout::Undefined);
+                    }
+                }
+                let layout = is_image.then_some(usage.layout);
+                if let Some(barrier) = states[s].transition(resource, &usage, layout) {
+                    barriers.push(barrier);
+                }
+            }
+            passes.push(ScheduledPass {
+                pass: PassId(index),
+                barriers,
+            });
+        }
+
+        let mut final_barriers = Vec::new();
+        for (r, entry) in self.resources.iter().enumerate() {
+            if let ResourceKind::Image { final_layout, .. } = entry.kind {
+                let usage = Usage {
+                    stages: PipelineStageFlags::BOTTOM_OF_PIPE,
+                    access: AccessFlags::NONE,
+                    write: false,
+                    layout: final_layout,
+                };
+                let barrier = states[r].transition(ResourceId(r), &usage, Some(final_layout));
+                // Only a layout change is needed at the end
+                if let Some(barrier) =
+                    barrier.filter(|b| matches!(b.layout, Some((old, new)) if old != new))
+                {
+                    final_barriers.push(barrier);
+                }
+            }
+        }
+        (passes, final_barriers)
+    }
+
+    /// Record the graph into `cmd`
+    ///
+    /// Transient resources are created on `device`. They are returned and
+    /// must be kept alive until `cmd` has finished executing.
+    pub fn record(
+        mut self,
+        device: &dyn Device,
+        cmd: &mut dyn CommandBuffer,
+    ) -> Result<TransientResources> {
+        let schedule = self.compile()?;
+
+        let mut transients = TransientResources::default();
+        let mut images = vec![None; schedule.allocation_count];
+        let mut buffers = vec![None; schedule.allocation_count];
+        for slot in 0..schedule.allocation_count {
+            let users = (0..self.resources.len())
+                .filter(|&r| schedule.allocations[r] == Some(slot))
+                .collect::<Vec<_>>();
+            let first = &self.resources[users[0]];
+            match &first.kind {
+                ResourceKind::TransientImage(descriptor) => {
+                    let mut descriptor = descriptor.clone();
+                    descriptor.usage |= self.image_usage(&users);
+                    let image = device.create_image(&descriptor)?;
+                    device.set_object_name(ObjectType::Image, image.handle(), &first.name)?;
+                    images[slot] = Some(transients.images.len());
+                    transients.images.push(image);
+                }
+                ResourceKind::TransientBuffer(descriptor) => {
+                    let mut descriptor = descriptor.clone();
+                    descriptor.usage |= self.buffer_usage(&users);
+                    let buffer = device.create_buffer(&descriptor)?;
+                    device.set_object_name(ObjectType::Buffer, buffer.handle(), &first.name)?;
+                    buffers[slot] = Some(transients.buffers.len());
+                    transients.buffers.push(buffer);
+                }
+                _ => unreachable!("imported resources have no allocation"),
+            }
+        }
+
+        let resolved = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(r, entry)| match entry.kind {
+                ResourceKind::Image { image, .. } => Resolved::Image(image),
+                ResourceKind::Buffer(buffer) => Resolved::Buffer(buffer),
+                _ => match schedule.allocations[r] {
+                    Some(slot) => match (images[slot], buffers[slot]) {
+                        (Some(image), _) => Resolved::Image(transients.images[image].as_ref()),
+                        (_, Some(buffer)) => Resolved::Buffer(transients.buffers[buffer].as_ref()),
+                        _ => Resolved::Unused,
+                    },
+                    // Only used by culled passes
+                    None => Resolved::Unused,
+                },
+            })
+            .collect::<Vec<_>>();
+        let resources = PassResources {
+            resources: resolved,
+        };
+
+        for scheduled in &schedule.passes {
+            if !scheduled.barriers.is_empty() {
+                cmd.pipeline_barrier(&resources.pipeline_barrier(&scheduled.barriers)?);
+            }
+            let pass = &mut self.passes[scheduled.pass.0];
+            cmd.begin_debug_label(&DebugLabel::new(&pass.name));
+            let result = match pass.execute.take() {
+                Some(execute) => execute(cmd, &resources),
+                None => Ok(()),
+            };
+            cmd.end_debug_label();
+            result?;
+        }
+        if !schedule.final_barriers.is_empty() {
+            cmd.pipeline_barrier(&resources.pipeline_barrier(&schedule.final_barriers)?);
+        }
+
+        drop(resources);
+        Ok(transients)
+    }
+
+    /// Image usage the passes need from `resources`
+    fn image_usage(&self, resources: &[usize]) -> ImageUsage {
+        let mut usage = ImageUsage::empty();
+        for pass in &self.passes {
+            let uses = pass.reads.iter().map(|u| (u, false));
+            for (&(resource, access), write) in uses.chain(pass.writes.iter().map(|u| (u, true))) {
+                if resources.contains(&resource.0) {
+                    usage |= access.image_usage(write).unwrap_or(ImageUsage::empty());
+                }
+            }
+        }
+        usage
+    }
+
+    /// Buffer usage the passes need from `resources`
+    fn buffer_usage(&self, resources: &[usize]) -> BufferUsage {
+        let mut usage = BufferUsage::empty();
+        for pass in &self.passes {
+            let uses = pass.reads.iter().map(|u| (u, false));
+            for (&(resource, access), write) in uses.chain(pass.writes.iter().map(|u| (u, true))) {
+                if resources.contains(&resource.0) {
+                    usage |= access.buffer_usage(write).unwrap_or(BufferUsage::empty());
+                }
+            }
+        }
+        usage
+    }
+}
+
+/// Passes each pass depends on
+type Dependencies = Vec<Vec<usize>>;
+
+fn push_dep(deps: &mut Vec<usize>, pass: usize, dep: usize) {
+    if dep != pass && !deps.contains(&dep) {
+        deps.push(dep);
+    }
+}
+
+/// Whether two transient resources can share an allocation
+fn compatible(a: &ResourceKind<'_>, b: &ResourceKind<'_>) -> bool {
+    match (a, b) {
+        (ResourceKind::TransientImage(a), ResourceKind::TransientImage(b)) => {
+            a.dimension == b.dimension
+                && a.extent == b.extent
+                && a.format == b.format
+                && a.mip_levels == b.mip_levels
+                && a.array_layers == b.array_layers
+                && a.sample_count == b.sample_count
+                && a.memory_type == b.memory_type
+        }
+        (ResourceKind::TransientBuffer(a), ResourceKind::TransientBuffer(b)) => {
+            a.size == b.size
+                && a.memory_type == b.memory_type
+                && a.mapped_at_creation == b.mapped_at_creation
+        }
+        _ => false,
+    }
+}
+
+/// Combined use of one resource by one pass
+struct Usage {
+    stages: PipelineStageFlags,
+    access: AccessFlags,
+    write: bool,
+    layout: ImageLayout,
+}
+
+/// Uses of each resource by `pass`, merged per resource in first-use order
+fn pass_usage(pass: &PassEntry<'_>) -> Vec<(ResourceId, Usage)> {
+    let mut usages: Vec<(ResourceId, Usage)> = Vec::new();
+    let uses = pass.reads.iter().map(|u| (u, false));
+    for (&(resource, access), write) in uses.chain(pass.writes.iter().map(|u| (u, true))) {
+        let flags = access.access_flags(write).unwrap_or(AccessFlags::NONE);
+        let layout = access.layout(write);
+        match usages.iter_mut().find(|(r, _)| *r == resource) {
+            Some((_, usage)) => {
+                usage.stages |= access.stages();
+                usage.access |= flags;
+                usage.write |= write;
+                if usage.layout != layout {
+                    usage.layout = ImageLayout::General;
+                }
+            }
+            None => usages.push((
+                resource,
+                Usage {
+                    stages: access.stages(),
+                    access: flags,
+                    write,
+                    layout,
+                },
+            )),
+        }
+    }
+    usages
+}
+
+/// Synchronization state of an image, a buffer or a transient allocation
+#[derive(Debug, Clone)]
+struct State {
+    /// Current layout, `None` for buffers
+    layout: Option<ImageLayout>,
+    /// Stages of the last write, or of the last layout transition
+    write_stages: PipelineStageFlags,
+    /// Accesses of the last write not yet made visible
+    write_access: AccessFlags,
+    /// Stages that already waited for the last write
+    visible_stages: PipelineStageFlags,
+    /// Accesses the last write was made visible to
+    visible_access: AccessFlags,
+    /// Stages that read since the last write
+    read_stages: PipelineStageFlags,
+}
+
+impl State {
+    fn new(layout: Option<ImageLayout>) -> Self {
+        Self {
+            layout,
+            write_stages: PipelineStageFlags::empty(),
+            write_access: AccessFlags::NONE,
+            visible_stages: PipelineStageFlags::empty(),
+            visible_access: AccessFlags::NONE,
+            read_stages: PipelineStageFlags::empty(),
+        }
+    }
+
+    /// Update the state for `usage`, returning the barrier needed first
+    fn transition(
+        &mut self,
+        resource: ResourceId,
+        usage: &Usage,
+        layout: Option<ImageLayout>,
+    ) -> Option<ResourceBarrier> {
+        let relayout = layout.is_some() && layout != self.layout;
+        let barrier = if relayout || usage.write {
+            // Wait for every earlier access, reads included
+            let src_stages = self.write_stages | self.read_stages;
+            (relayout || !src_stages.is_empty()).then(|| ResourceBarrier {
+                resource,
+                src_stage: if src_stages.is_empty() {
+                    PipelineStageFlags::TOP_OF_PIPE
+                } else {
+                    src_stages
+                },
+                dst_stage: usage.stages,
+                src_access: self.write_access,
+                dst_access: usage.access,
+                layout: self.layout.zip(layout),
+            })
+        } else {
+            let visible = self.visible_stages.contains(usage.stages)
+                && self.visible_access.contains(usage.access);
+            (!self.write_stages.is_empty() && !visible).then(|| ResourceBarrier {
+                resource,
+                src_stage: self.write_stages,
+                dst_stage: usage.stages,
+                src_access: self.write_access,
+                dst_access: usage.access,
+                layout: self.layout.zip(layout),
+            })
+        };
+
+        if relayout {
+            // The transition counts as a write visible to this pass
+            self.layout = layout;
+            self.write_stages = usage.stages;
+            self.write_access = AccessFlags::NONE;
+            self.visible_stages = usage.stages;
+            self.visible_access = usage.access;
+            self.read_stages = PipelineStageFlags::empty();
+        } else if barrier.is_some() && !usage.write {
+            self.visible_stages |= usage.stages;
+            self.visible_access |= usage.access;
+        }
+        if usage.write {
+            self.write_stages = usage.stages;
+            self.write_access = usage.access
+                & (AccessFlags::SHADER_WRITE
+                    | AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | AccessFlags::DEPTH_STENCIL_WRITE
+                    | AccessFlags::TRANSFER_WRITE);
+            self.visible_stages = PipelineStageFlags::empty();
+            self.visible_access = AccessFlags::NONE;
+            self.read_stages = PipelineStageFlags::empty();
+        } else {
+            self.read_stages |= usage.stages;
+        }
+        barrier
+    }
+}
+
+/// Builder for the uses of a pass, returned by [`RenderGraph::add_pass`]
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: usize,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    /// Identifier of the pass
+    pub fn id(&self) -> PassId {
+        PassId(self.pass)
+    }
+
+    /// The pass reads `resource`
+    pub fn read(self, resource: ResourceId, access: Access) -> Self {
+        self.graph.passes[self.pass].reads.push((resource, access));
+        self
+    }
+
+    /// The pass writes `resource`
+    pub fn write(self, resource: ResourceId, access: Access) -> Self {
+        self.graph.passes[self.pass].writes.push((resource, access));
+        self
+    }
+
+    /// Never cull the pass, e.g. because it writes to host memory
+    pub fn side_effects(self) -> Self {
+        self.graph.passes[self.pass].side_effects = true;
+        self
+    }
+
+    /// Record the pass commands with `execute`, after its barriers
+    pub fn execute<F>(self, execute: F) -> PassId
+    where
+        F: FnOnce(&mut dyn CommandBuffer, &PassResources<'_>) -> Result<()> + 'a,
+    {
+        self.graph.passes[self.pass].execute = Some(Box::new(execute));
+        PassId(self.pass)
+    }
+}
+
+/// Barrier on one resource, ahead of a pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceBarrier {
+    pub resource: ResourceId,
+    pub src_stage: PipelineStageFlags,
+    pub dst_stage: PipelineStageFlags,
+    pub src_access: AccessFlags,
+    pub dst_access: AccessFlags,
+    /// Old and new layout of an image, equal if there is no transition
+    pub layout: Option<(ImageLayout, ImageLayout)>,
+}
+
+/// Pass in execution order, with the barriers recorded before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledPass {
+    pub pass: PassId,
+    pub barriers: Vec<ResourceBarrier>,
+}
+
+/// Compiled form of a [`RenderGraph`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    passes: Vec<ScheduledPass>,
+    final_barriers: Vec<ResourceBarrier>,
+    culled: Vec<PassId>,
+    allocations: Vec<Option<usize>>,
+    allocation_count: usize,
+}
+
+impl Schedule {
+    /// Passes that run, in order
+    pub fn passes(&self) -> &[ScheduledPass] {
+        &self.passes
+    }
+
+    /// Transitions of imported images to their final layout
+    pub fn final_barriers(&self) -> &[ResourceBarrier] {
+        &self.final_barriers
+    }
+
+    /// Passes that don't contribute to the output
+    pub fn culled(&self) -> &[PassId] {
+        &self.culled
+    }
+
+    /// Allocation backing a transient resource
+    ///
+    /// Resources with the same allocation alias each other.
+    pub fn allocation(&self, resource: ResourceId) -> Option<usize> {
+        self.allocations.get(resource.0).copied().flatten()
+    }
+
+    /// Number of transient allocations
+    pub fn allocation_count(&self) -> usize {
+        self.allocation_count
+    }
+}
+
+/// Transient images and buffers created by [`RenderGraph::record`]
+#[derive(Default)]
+pub struct TransientResources {
+    pub images: Vec<Box<dyn Image>>,
+    pub buffers: Vec<Box<dyn Buffer>>,
+}
+
+#[derive(Clone, Copy)]
+enum Resolved<'r> {
+    Image(&'r dyn Image),
+    Buffer(&'r dyn Buffer),
+    Unused,
+}
+
+/// Resources of a graph, as seen by the passes
+pub struct PassResources<'r> {
+    resources: Vec<Resolved<'r>>,
+}
+
+impl PassResources<'_> {
+    /// Image behind `resource`
+    pub fn image(&self, resource: ResourceId) -> Result<&dyn Image> {
+        match self.resources.get(resource.0) {
+            Some(Resolved::Image(image)) => Ok(*image),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+
+    /// Buffer behind `resource`
+    pub fn buffer(&self, resource: ResourceId) -> Result<&dyn Buffer> {
+        match self.resources.get(resource.0) {
+            Some(Resolved::Buffer(buffer)) => Ok(*buffer),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+
+    /// Merge resource barriers into one pipeline barrier
+    fn pipeline_barrier(&self, barriers: &[ResourceBarrier]) -> Result<PipelineBarrier> {
+        let mut pipeline_barrier = PipelineBarrier {
+            src_stage: PipelineStageFlags::empty(),
+            dst_stage: PipelineStageFlags::empty(),
+            memory_barriers: Vec::new(),
+            buffer_barriers: Vec::new(),
+            image_barriers: Vec::new(),
+        };
+        for barrier in barriers {
+            pipeline_barrier.src_stage |= barrier.src_stage;
+            pipeline_barrier.dst_stage |= barrier.dst_stage;
+            match self.resources[barrier.resource.0] {
+                Resolved::Image(image) => {
+                    let (old_layout, new_layout) = barrier.layout.ok_or(Error::InvalidParameter)?;
+                    pipeline_barrier.image_barriers.push(ImageMemoryBarrier {
+                        src_access: barrier.src_access,
+                        dst_access: barrier.dst_access,
+                        old_layout,
+                        new_layout,
+                        image_handle: image.handle(),
+                        subresource_range: full_range(image),
+                    });
+                }
+                Resolved::Buffer(buffer) => {
+                    pipeline_barrier.buffer_barriers.push(BufferMemoryBarrier {
+                        src_access: barrier.src_access,
+                        dst_access: barrier.dst_access,
+                        buffer_handle: buffer.handle(),
+                        offset: 0,
+                        size: buffer.size(),
+                    })
+                }
+                Resolved::Unused => return Err(Error::InvalidParameter),
+            }
+        }
+        Ok(pipeline_barrier)
+    }
+}
+
+/// Subresource range covering all of `image`
+fn full_range(image: &dyn Image) -> ImageSubresourceRange {
+    let format = image.format();
+    let aspect_mask = match (format.is_depth(), format.is_stencil()) {
+        (false, false) => ImageAspect::COLOR,
+        (depth, stencil) => {
+            let mut aspect = ImageAspect::empty();
+            aspect.set(ImageAspect::DEPTH, depth);
+            aspect.set(ImageAspect::STENCIL, stencil);
+            aspect
+        }
+    };
+    ImageSubresourceRange {
+        aspect_mask,
+        base_mip_level: 0,
+        level_count: image.mip_levels(),
+        base_array_layer: 0,
+        layer_count: image.array_layers(),
+    }
+}
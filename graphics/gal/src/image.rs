@@ -3,6 +3,7 @@
 //! This module provides GPU image abstractions for textures,
 //! render targets, and depth/stencil buffers.
 
+use alloc::vec::Vec;
 use bitflags::bitflags;
 
 use crate::{Error, Extent2D, Extent3D, Memory, MemoryType, Result};
@@ -83,6 +84,12 @@ pub enum ImageFormat {
     Bc6hRgfloat,
     Bc7RgbaUnorm,
     Bc7RgbaUnormSrgb,
+
+    // YUV 4:2:0, Y plane followed by an interleaved CbCr plane
+    /// 8-bit YUV 4:2:0
+    Nv12,
+    /// 10-bit YUV 4:2:0, in the high bits of 16-bit samples
+    P010,
 }
 
 impl ImageFormat {
@@ -134,7 +141,7 @@ impl ImageFormat {
                 Some(16)
             }
             ImageFormat::Stencil8 => Some(1),
-            _ => None, // Compressed formats
+            _ => None, // Compressed and multi-planar formats
         }
     }
 
@@ -181,6 +188,91 @@ impl ImageFormat {
         )
     }
 
+    /// Check if this is a YUV format
+    pub fn is_yuv(&self) -> bool {
+        matches!(self, ImageFormat::Nv12 | ImageFormat::P010)
+    }
+
+    /// Number of planes of the format
+    pub fn plane_count(&self) -> u32 {
+        if self.is_yuv() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Format of a single plane, as sampled or copied on its own
+    ///
+    /// Single-plane formats are their own plane 0.
+    pub fn plane_format(&self, plane: u32) -> Option<ImageFormat> {
+        match (self, plane) {
+            (ImageFormat::Nv12, 0) => Some(ImageFormat::R8Unorm),
+            (ImageFormat::Nv12, 1) => Some(ImageFormat::Rg8Unorm),
+            (ImageFormat::P010, 0) => Some(ImageFormat::R16Unorm),
+            (ImageFormat::P010, 1) => Some(ImageFormat::Rg16Unorm),
+            (format, 0) if !format.is_yuv() => Some(*format),
+            _ => None,
+        }
+    }
+
+    /// Horizontal and vertical chroma subsampling factors
+    pub fn chroma_subsampling(&self) -> (u32, u32) {
+        if self.is_yuv() {
+            (2, 2)
+        } else {
+            (1, 1)
+        }
+    }
+
+    /// Extent of a plane of an image of this format
+    pub fn plane_extent(&self, plane: u32, extent: Extent2D) -> Extent2D {
+        if plane == 0 {
+            return extent;
+        }
+        let (x, y) = self.chroma_subsampling();
+        Extent2D::new(extent.width.div_ceil(x), extent.height.div_ceil(y))
+    }
+
+    /// Bits per component of a YUV format
+    pub fn yuv_bit_depth(&self) -> Option<u32> {
+        match self {
+            ImageFormat::Nv12 => Some(8),
+            ImageFormat::P010 => Some(10),
+            _ => None,
+        }
+    }
+
+    /// Linear layout of all planes, placed one after another with rows
+    /// aligned to `row_alignment`
+    ///
+    /// Returns `None` for compressed formats.
+    pub fn linear_plane_layouts(
+        &self,
+        extent: Extent2D,
+        row_alignment: u32,
+    ) -> Option<Vec<ImagePlaneLayout>> {
+        let mut offset = 0;
+        (0..self.plane_count())
+            .map(|plane| {
+                let format = self.plane_format(plane)?;
+                let extent = self.plane_extent(plane, extent);
+                let stride = extent
+                    .width
+                    .checked_mul(format.bytes_per_pixel()?)?
+                    .next_multiple_of(row_alignment);
+                let layout = ImagePlaneLayout {
+                    format,
+                    extent,
+                    offset,
+                    stride,
+                };
+                offset += u64::from(stride) * u64::from(extent.height);
+                Some(layout)
+            })
+            .collect()
+    }
+
     /// Check if this is an sRGB format
     pub fn is_srgb(&self) -> bool {
         matches!(
@@ -215,6 +307,30 @@ bitflags! {
         const TRANSIENT_ATTACHMENT = 1 << 6;
         /// Image can be used as an input attachment
         const INPUT_ATTACHMENT = 1 << 7;
+        /// Image can receive the output of a video decoder
+        const VIDEO_DECODE_DST = 1 << 8;
+        /// Image can be read by a video encoder
+        const VIDEO_ENCODE_SRC = 1 << 9;
+    }
+}
+
+/// Placement of one plane of an image in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImagePlaneLayout {
+    /// Format of the plane's samples
+    pub format: ImageFormat,
+    /// Extent of the plane, smaller than the image for subsampled chroma
+    pub extent: Extent2D,
+    /// Offset of the plane's first sample
+    pub offset: u64,
+    /// Bytes between the start of two rows
+    pub stride: u32,
+}
+
+impl ImagePlaneLayout {
+    /// Offset of the first byte after the plane
+    pub fn end(&self) -> u64 {
+        self.offset + u64::from(self.stride) * u64::from(self.extent.height)
     }
 }
 
@@ -275,6 +391,16 @@ impl ImageDescriptor {
         )
     }
 
+    /// Create a descriptor for an image scanned out by a display
+    pub fn scanout(width: u32, height: u32, format: ImageFormat) -> Self {
+        Self::new_2d(
+            width,
+            height,
+            format,
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST,
+        )
+    }
+
     /// Create a depth buffer descriptor
     pub fn depth_buffer(width: u32, height: u32) -> Self {
         Self::new_2d(
@@ -295,6 +421,16 @@ impl ImageDescriptor {
         )
     }
 
+    /// Create a descriptor for a video frame, e.g. decoder output
+    pub fn video_frame(width: u32, height: u32, format: ImageFormat) -> Self {
+        Self::new_2d(
+            width,
+            height,
+            format,
+            ImageUsage::VIDEO_DECODE_DST | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC,
+        )
+    }
+
     /// Set mip levels
     pub fn mip_levels(mut self, levels: u32) -> Self {
         self.mip_levels = levels;
@@ -334,6 +470,37 @@ impl ImageDescriptor {
         self.mip_levels = self.max_mip_levels();
         self
     }
+
+    /// Check the descriptor for combinations no backend can create
+    ///
+    /// YUV images must be 2D, without mips or multisampling, and have an
+    /// extent divisible by the chroma subsampling.
+    pub fn validate(&self) -> Result<()> {
+        if self.extent.width == 0
+            || self.extent.height == 0
+            || self.extent.depth == 0
+            || self.mip_levels == 0
+            || self.mip_levels > self.max_mip_levels()
+            || self.array_layers == 0
+        {
+            return Err(Error::InvalidParameter);
+        }
+
+        if self.format.is_yuv() {
+            let (x, y) = self.format.chroma_subsampling();
+            if self.dimension != ImageDimension::D2
+                || self.mip_levels != 1
+                || self.sample_count != 1
+                || !self.extent.width.is_multiple_of(x)
+                || !self.extent.height.is_multiple_of(y)
+                || self.usage.contains(ImageUsage::DEPTH_STENCIL_ATTACHMENT)
+            {
+                return Err(Error::InvalidParameter);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// GPU image resource
@@ -356,6 +523,11 @@ pub trait Image: Send + Sync {
     /// Get image format
     fn format(&self) -> ImageFormat;
 
+    /// Get the number of planes
+    fn plane_count(&self) -> u32 {
+        self.format().plane_count()
+    }
+
     /// Get mip level count
     fn mip_levels(&self) -> u32;
 
@@ -396,6 +568,121 @@ pub enum SamplerBorderColor {
     OpaqueWhite,
 }
 
+/// Color model a YCbCr conversion converts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YcbcrModel {
+    /// No conversion, components are passed through
+    RgbIdentity,
+    /// Range expansion only, no color model conversion
+    YcbcrIdentity,
+    /// ITU-R BT.601 (SD video)
+    Ycbcr601,
+    /// ITU-R BT.709 (HD video)
+    Ycbcr709,
+    /// ITU-R BT.2020 (UHD and HDR video)
+    Ycbcr2020,
+}
+
+impl YcbcrModel {
+    /// Luma coefficients of red and blue (Kr, Kb)
+    pub fn coefficients(&self) -> Option<(f32, f32)> {
+        match self {
+            YcbcrModel::Ycbcr601 => Some((0.299, 0.114)),
+            YcbcrModel::Ycbcr709 => Some((0.2126, 0.0722)),
+            YcbcrModel::Ycbcr2020 => Some((0.2627, 0.0593)),
+            YcbcrModel::RgbIdentity | YcbcrModel::YcbcrIdentity => None,
+        }
+    }
+}
+
+/// Range of encoded YCbCr values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YcbcrRange {
+    /// Values use the full range of the bit depth
+    Full,
+    /// Video range, e.g. 16-235 for 8-bit luma and 16-240 for chroma
+    Narrow,
+}
+
+/// Position of subsampled chroma samples relative to luma samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaLocation {
+    /// Co-sited with the even luma samples
+    CositedEven,
+    /// Midway between luma samples
+    Midpoint,
+}
+
+/// Conversion from a YUV format to RGB applied when sampling
+///
+/// Needs [`crate::DeviceCapabilities::SAMPLER_YCBCR_CONVERSION`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerYcbcrConversion {
+    /// Format of the sampled images
+    pub format: ImageFormat,
+    pub model: YcbcrModel,
+    pub range: YcbcrRange,
+    pub x_chroma_offset: ChromaLocation,
+    pub y_chroma_offset: ChromaLocation,
+    /// Filter used to reconstruct subsampled chroma
+    pub chroma_filter: SamplerFilter,
+}
+
+impl SamplerYcbcrConversion {
+    /// Conversion of video decoded to `format`, with the usual defaults for
+    /// its resolution: BT.709 for HD and larger, BT.601 below
+    pub fn video(format: ImageFormat, extent: Extent2D) -> Self {
+        let model = if extent.height >= 720 {
+            YcbcrModel::Ycbcr709
+        } else {
+            YcbcrModel::Ycbcr601
+        };
+        Self {
+            format,
+            model,
+            range: YcbcrRange::Narrow,
+            x_chroma_offset: ChromaLocation::CositedEven,
+            y_chroma_offset: ChromaLocation::Midpoint,
+            chroma_filter: SamplerFilter::Linear,
+        }
+    }
+
+    /// Convert normalized Y, Cb and Cr samples to RGB, as the sampler does
+    ///
+    /// Software paths such as screen capture use this to match what the GPU
+    /// produces.
+    pub fn to_rgb(&self, y: f32, cb: f32, cr: f32) -> [f32; 3] {
+        if self.model == YcbcrModel::RgbIdentity {
+            return [cr, y, cb];
+        }
+
+        let (y, cb, cr) = match self.range {
+            YcbcrRange::Full => (y, cb - 0.5, cr - 0.5),
+            YcbcrRange::Narrow => {
+                // Scale from codes of the format's bit depth
+                let bits = self.format.yuv_bit_depth().unwrap_or(8);
+                let max = ((1u32 << bits) - 1) as f32;
+                let scale = (1u32 << (bits - 8)) as f32;
+                (
+                    (y * max - 16.0 * scale) / (219.0 * scale),
+                    (cb * max - 128.0 * scale) / (224.0 * scale),
+                    (cr * max - 128.0 * scale) / (224.0 * scale),
+                )
+            }
+        };
+
+        let Some((kr, kb)) = self.model.coefficients() else {
+            // YcbcrIdentity: range expansion only, chroma stays centered on 0
+            return [cr, y, cb];
+        };
+        let kg = 1.0 - kr - kb;
+        let r = y + 2.0 * (1.0 - kr) * cr;
+        let b = y + 2.0 * (1.0 - kb) * cb;
+        let g = (y - kr * r - kb * b) / kg;
+        [r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0)]
+    }
+}
+
 /// Sampler descriptor
 #[derive(Debug, Clone)]
 pub struct SamplerDescriptor {
@@ -414,6 +701,8 @@ pub struct SamplerDescriptor {
     pub max_lod: f32,
     pub border_color: SamplerBorderColor,
     pub unnormalized_coordinates: bool,
+    /// Conversion of sampled YUV images to RGB
+    pub ycbcr_conversion: Option<SamplerYcbcrConversion>,
 }
 
 impl Default for SamplerDescriptor {
@@ -434,6 +723,7 @@ impl Default for SamplerDescriptor {
             max_lod: 1000.0,
             border_color: SamplerBorderColor::TransparentBlack,
             unnormalized_coordinates: false,
+            ycbcr_conversion: None,
         }
     }
 }
@@ -468,6 +758,21 @@ impl SamplerDescriptor {
         self.address_mode_w = mode;
         self
     }
+
+    /// Create a sampler converting YUV images with `conversion`
+    ///
+    /// Converting samplers have to clamp to the edge and can't use
+    /// anisotropy.
+    pub fn ycbcr(conversion: SamplerYcbcrConversion) -> Self {
+        Self {
+            mag_filter: conversion.chroma_filter,
+            min_filter: conversion.chroma_filter,
+            mipmap_filter: SamplerFilter::Nearest,
+            ycbcr_conversion: Some(conversion),
+            ..Self::default()
+        }
+        .address_mode(SamplerAddressMode::ClampToEdge)
+    }
 }
 
 /// Texture sampler
@@ -12,8 +12,13 @@
 //! - Memory management (buffers, images, allocations)
 //! - Command buffer recording and submission
 //! - Synchronization primitives (fences, semaphores)
-//! - Pipeline state management
+//! - GPU timestamp queries
+//! - Pipeline state management, with a cache of compiled pipelines
 //! - Resource binding and descriptors
+//! - A render graph deriving barriers and transient resources from passes
+//! - Presentation through swapchains on backend display targets
+//! - A software rasterizer device for systems without a supported GPU
+//! - Hardware video decoding into YUV images
 //!
 //! # Usage
 //!
@@ -34,29 +39,56 @@
 #![no_std]
 
 extern crate alloc;
+// Host builds, such as the golden-image tests, take time from std
+#[cfg(not(target_os = "redox"))]
+extern crate std;
 
+pub mod allocator;
 pub mod buffer;
 pub mod command;
+pub mod debug;
 pub mod device;
+pub mod display;
+pub mod external;
+pub mod graph;
 pub mod image;
 pub mod memory;
+pub mod offload;
 pub mod pipeline;
+pub mod query;
 pub mod queue;
 pub mod shader;
+pub mod software;
+pub mod swapchain;
 pub mod sync;
 pub mod types;
+pub mod video;
 
 // Re-exports
+pub use allocator::{DeviceAllocator, Heap};
 pub use buffer::{Buffer, BufferDescriptor, BufferUsage};
 pub use command::{CommandBuffer, CommandPool, DrawCommand, RenderPass};
-pub use device::{Device, DeviceCapabilities, DeviceInfo, DeviceType};
-pub use image::{Image, ImageDescriptor, ImageFormat, ImageUsage, Sampler};
+pub use debug::{DebugLabel, ObjectType};
+pub use device::{Device, DeviceCapabilities, DeviceInfo, DeviceType, Feature};
+pub use display::{DisplayMode, DisplayTarget, PresentMode, ScanoutImage};
+pub use external::{ExternalFence, ExternalImageLayout, ExternalMemory};
+pub use graph::{Access, PassId, RenderGraph, ResourceId};
+pub use image::{Image, ImageDescriptor, ImageFormat, ImagePlaneLayout, ImageUsage, Sampler};
 pub use memory::{AllocationInfo, Memory, MemoryAllocator, MemoryType};
-pub use pipeline::{ComputePipeline, GraphicsPipeline, Pipeline, PipelineType};
-pub use queue::{Queue, QueueType, SubmitInfo};
+pub use pipeline::{
+    ComputePipeline, GraphicsPipeline, Pipeline, PipelineCache, PipelineKey, PipelineType,
+};
+pub use query::{QueryPool, QueryType};
+pub use queue::{Queue, QueuePriority, QueueType, SubmitInfo};
 pub use shader::{Shader, ShaderModule, ShaderStage};
+pub use software::SoftwareDevice;
+pub use swapchain::{PresentCallback, PresentFeedback, PresentOutcome, PresentRequest, Swapchain};
 pub use sync::{Event, Fence, Semaphore};
 pub use types::*;
+pub use video::{
+    DecodePicture, PictureParams, ReferencePicture, VideoCodec, VideoDecode,
+    VideoDecodeCapabilities, VideoDecoder, VideoDecoderDescriptor, VideoProfile,
+};
 
 use alloc::string::String;
 
@@ -94,6 +126,8 @@ pub enum Error {
     InvalidParameter,
     /// Feature not supported
     NotSupported,
+    /// The device lacks a feature or limit the operation needs
+    FeatureNotPresent(Feature),
     /// Operation failed
     OperationFailed,
     /// Resource in use
@@ -120,6 +154,7 @@ impl core::fmt::Display for Error {
             Error::OutOfDeviceMemory => write!(f, "Out of device memory"),
             Error::InvalidParameter => write!(f, "Invalid parameter"),
             Error::NotSupported => write!(f, "Not supported"),
+            Error::FeatureNotPresent(feature) => write!(f, "Feature not present: {:?}", feature),
             Error::OperationFailed => write!(f, "Operation failed"),
             Error::ResourceInUse => write!(f, "Resource in use"),
             Error::Timeout => write!(f, "Timeout"),
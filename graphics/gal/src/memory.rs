@@ -145,6 +145,12 @@ pub struct AllocatorStats {
     pub reserved_bytes: u64,
     /// Number of memory blocks
     pub block_count: u32,
+    /// Number of allocations with memory of their own
+    pub dedicated_count: u32,
+    /// Number of free ranges in blocks, grows with fragmentation
+    pub free_range_count: u32,
+    /// Largest free range in any block
+    pub largest_free_range: u64,
 }
 
 /// Simple linear allocator for staging buffers
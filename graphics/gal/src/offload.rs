@@ -0,0 +1,250 @@
+//! Hybrid GPU render offload
+//!
+//! On systems with two GPUs the displays are usually wired to the integrated
+//! one while the discrete one is much faster at rendering. Render offload
+//! renders on the discrete device into images that are shared with the
+//! display device through [external memory](crate::external), and hands each
+//! finished frame over with an exported fence so that scanout never starts
+//! before rendering is done.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::external::ExternalImageLayout;
+use crate::{
+    Device, DeviceCapabilities, DeviceType, Error, Extent2D, Fence, Image, ImageDescriptor,
+    ImageFormat, ImageUsage, MemoryType, Result,
+};
+
+/// How to choose the rendering device
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SelectionPolicy {
+    /// Render on a discrete GPU if it can offload to the display GPU
+    #[default]
+    Auto,
+    /// Render on a discrete GPU, even if the display GPU is discrete too
+    PreferDiscrete,
+    /// Render on the display GPU, saving power
+    PreferIntegrated,
+    /// Render on the device with this name
+    Named(String),
+}
+
+/// What [`select`] needs to know about a device
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    pub name: &'a str,
+    pub device_type: DeviceType,
+    /// Whether a display is connected
+    pub has_display: bool,
+    /// Whether it can render 3D
+    pub renders: bool,
+    /// Whether it can export and import memory and fences
+    pub shares: bool,
+    /// Preference among suitable render devices, the highest wins
+    pub rank: u64,
+}
+
+/// Devices picked for rendering and scanout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OffloadSelection {
+    /// Index of the device that renders
+    pub render: usize,
+    /// Index of the device that drives the displays
+    pub display: usize,
+}
+
+impl OffloadSelection {
+    /// Whether frames have to cross devices
+    pub fn is_offloaded(&self) -> bool {
+        self.render != self.display
+    }
+}
+
+/// Pick a render and a display device according to `policy`
+///
+/// The display device is the first device with a display, preferring
+/// integrated and virtual GPUs since that's where panels are wired, or the
+/// first device if none has one. Rendering is offloaded to another device
+/// only if both can share memory and fences.
+pub fn select(candidates: &[Candidate<'_>], policy: &SelectionPolicy) -> Option<OffloadSelection> {
+    let discrete = |candidate: &Candidate<'_>| candidate.device_type == DeviceType::Discrete;
+    let display = candidates
+        .iter()
+        .position(|c| c.has_display && !discrete(c))
+        .or_else(|| candidates.iter().position(|c| c.has_display))
+        .or_else(|| (!candidates.is_empty()).then_some(0))?;
+
+    let can_offload = |render: usize| {
+        render == display || (candidates[render].shares && candidates[display].shares)
+    };
+
+    let render = match policy {
+        SelectionPolicy::Auto if discrete(&candidates[display]) => None,
+        SelectionPolicy::Auto | SelectionPolicy::PreferDiscrete => candidates
+            .iter()
+            .enumerate()
+            .filter(|&(i, c)| discrete(c) && c.renders && can_offload(i))
+            .max_by_key(|(_, c)| c.rank)
+            .map(|(i, _)| i),
+        SelectionPolicy::PreferIntegrated => None,
+        SelectionPolicy::Named(name) => match candidates.iter().position(|c| c.name == name) {
+            Some(i) if can_offload(i) => Some(i),
+            Some(_) => {
+                log::warn!("gal: {} cannot share frames with the display device", name);
+                None
+            }
+            None => {
+                log::warn!("gal: no device named {}", name);
+                None
+            }
+        },
+    };
+
+    Some(OffloadSelection {
+        render: render.unwrap_or(display),
+        display,
+    })
+}
+
+/// Pick a render and a display device among `devices`, see [`select`]
+pub fn select_devices(
+    devices: &[&dyn Device],
+    policy: &SelectionPolicy,
+) -> Option<OffloadSelection> {
+    let candidates: Vec<_> = devices
+        .iter()
+        .map(|device| {
+            let info = device.info();
+            Candidate {
+                name: &info.name,
+                device_type: info.device_type,
+                has_display: device.displays().iter().any(|display| display.enabled),
+                renders: info.capabilities.contains(DeviceCapabilities::RENDER_3D),
+                shares: info.capabilities.contains(SHARING),
+                rank: info.total_memory,
+            }
+        })
+        .collect();
+    select(&candidates, policy)
+}
+
+/// Capabilities both sides of an offload need
+const SHARING: DeviceCapabilities =
+    DeviceCapabilities::EXTERNAL_MEMORY.union(DeviceCapabilities::EXTERNAL_FENCE);
+
+fn can_share(render: &dyn Device, display: &dyn Device) -> bool {
+    render.info().capabilities.contains(SHARING) && display.info().capabilities.contains(SHARING)
+}
+
+struct OffloadFrame {
+    render_image: Box<dyn Image>,
+    display_image: Box<dyn Image>,
+}
+
+/// A set of images rendered on one device and scanned out on another
+pub struct RenderOffload<'a> {
+    render: &'a dyn Device,
+    display: &'a dyn Device,
+    layout: ExternalImageLayout,
+    frames: Vec<OffloadFrame>,
+}
+
+impl<'a> RenderOffload<'a> {
+    /// Create `frame_count` shared images of the given size and format
+    pub fn new(
+        render: &'a dyn Device,
+        display: &'a dyn Device,
+        extent: Extent2D,
+        format: ImageFormat,
+        frame_count: u32,
+    ) -> Result<Self> {
+        if !can_share(render, display) {
+            return Err(Error::NotSupported);
+        }
+        if frame_count == 0 {
+            return Err(Error::InvalidParameter);
+        }
+
+        let mut layout = None;
+        let mut frames = Vec::with_capacity(frame_count as usize);
+        for _ in 0..frame_count {
+            let mut descriptor = ImageDescriptor::new_2d(
+                extent.width,
+                extent.height,
+                format,
+                ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            );
+            // The display device reads the pixels over PCIe, keep them in system memory.
+            descriptor.memory_type = MemoryType::HostVisible;
+            let render_image = render.create_image(&descriptor)?;
+
+            // The render device picks the stride, the display device
+            // refuses layouts it can't scan out
+            let (memory, exported) = render.export_image(render_image.as_ref())?;
+            if exported.format != format
+                || exported.extent != extent
+                || exported.modifier != ExternalImageLayout::LINEAR
+            {
+                log::warn!("gal: render device exported {:?}", exported);
+                return Err(Error::NotSupported);
+            }
+            let display_image = display.import_image(&memory, &exported, ImageUsage::SAMPLED)?;
+            layout.get_or_insert(exported);
+
+            frames.push(OffloadFrame {
+                render_image,
+                display_image,
+            });
+        }
+
+        log::debug!(
+            "gal: offloading {} {}x{} {:?} frame(s) from {} to {}",
+            frame_count,
+            extent.width,
+            extent.height,
+            format,
+            render.info().name,
+            display.info().name
+        );
+
+        Ok(Self {
+            render,
+            display,
+            layout: layout.expect("at least one frame"),
+            frames,
+        })
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frames.len() as u32
+    }
+
+    pub fn layout(&self) -> &ExternalImageLayout {
+        &self.layout
+    }
+
+    /// Image to render frame `index` into, on the render device
+    pub fn render_image(&self, index: u32) -> &dyn Image {
+        self.frames[index as usize].render_image.as_ref()
+    }
+
+    /// The same image as seen by the display device
+    pub fn display_image(&self, index: u32) -> &dyn Image {
+        self.frames[index as usize].display_image.as_ref()
+    }
+
+    /// Hand frame `index` over to the display device
+    ///
+    /// `rendered` is the render device fence signaled when the frame is
+    /// complete. The returned fence belongs to the display device and must
+    /// be waited on before the frame is scanned out.
+    pub fn hand_off(&self, index: u32, rendered: &dyn Fence) -> Result<Box<dyn Fence>> {
+        if index >= self.frame_count() {
+            return Err(Error::InvalidParameter);
+        }
+        let fence = self.render.export_fence(rendered)?;
+        self.display.import_fence(&fence)
+    }
+}
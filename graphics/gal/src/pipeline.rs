@@ -1,10 +1,20 @@
 //! Pipeline state management
 //!
-//! This module provides abstractions for graphics and compute pipelines.
+//! This module provides abstractions for graphics and compute pipelines, and
+//! a [`PipelineCache`] keeping compiled pipelines across application runs.
 
+use alloc::collections::btree_map::{BTreeMap, Entry};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU64, Ordering};
 
-use crate::Shader;
+use spin::Mutex;
+
+use crate::device::GraphicsPipelineDescriptor;
+use crate::{DeviceInfo, Error, Result, Shader};
 
 /// Pipeline type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -76,3 +86,367 @@ pub struct PushConstantRange {
     pub offset: u32,
     pub size: u32,
 }
+
+/// Identifies the first bytes of a serialized pipeline cache
+const CACHE_MAGIC: [u8; 8] = *b"GALPIPE\0";
+
+/// Version of the serialized format
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+/// Size of the serialized header: magic, format version, device key and entry count
+const CACHE_HEADER_SIZE: usize = 8 + 4 + 16 + 4;
+
+/// Size of the header of every serialized entry: key, data size and checksum
+const CACHE_ENTRY_HEADER_SIZE: usize = 16 + 4 + 8;
+
+/// Hash identifying a pipeline in a [`PipelineCache`]
+///
+/// Covers the pipeline state and the code of its shaders. Shader handles are
+/// left out, as they differ between runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PipelineKey(pub u128);
+
+impl PipelineKey {
+    /// Key of a graphics pipeline built from `desc` and the given shader code
+    pub fn graphics(
+        desc: &GraphicsPipelineDescriptor,
+        vertex_code: &[u8],
+        fragment_code: &[u8],
+    ) -> Self {
+        let state = GraphicsPipelineDescriptor {
+            vertex_shader: 0,
+            fragment_shader: 0,
+            ..desc.clone()
+        };
+
+        let mut hasher = CacheHasher::new();
+        hasher.write_bytes(b"graphics");
+        // The debug representation covers every field of the state, and a
+        // change to it comes with a new GAL version, which starts a new cache
+        let _ = write!(hasher, "{:?}", state);
+        hasher.write_slice(vertex_code);
+        hasher.write_slice(fragment_code);
+        Self(hasher.finish())
+    }
+
+    /// Key of a compute pipeline built from the given shader code
+    pub fn compute(code: &[u8]) -> Self {
+        let mut hasher = CacheHasher::new();
+        hasher.write_bytes(b"compute");
+        hasher.write_slice(code);
+        Self(hasher.finish())
+    }
+}
+
+/// Pipeline cache statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipelineCacheStats {
+    /// Cached pipelines
+    pub entries: usize,
+    /// Total size of the cached artifacts
+    pub bytes: usize,
+    /// Lookups that found a compiled pipeline
+    pub hits: u64,
+    /// Lookups that had to compile the pipeline
+    pub misses: u64,
+}
+
+/// Cache of compiled pipelines
+///
+/// Backends that compile pipelines look up the [`PipelineKey`] of a pipeline
+/// before compiling it and store the compiled artifact afterwards, which is
+/// opaque to the cache. Applications [`load`](Self::load) the cache at
+/// startup and [`save`](Self::save) it at exit, so shaders are only compiled
+/// on the first run. Saving merges with what other processes saved in the
+/// meantime.
+///
+/// Artifacts are only valid for the device and GAL version they were compiled
+/// with; serialized caches of anything else are ignored.
+pub struct PipelineCache {
+    /// Hash of the device and GAL version
+    device_key: u128,
+    entries: Mutex<BTreeMap<PipelineKey, Arc<[u8]>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl PipelineCache {
+    /// Create an empty cache for artifacts compiled on the device `info` describes
+    pub fn new(info: &DeviceInfo) -> Self {
+        let mut hasher = CacheHasher::new();
+        let _ = write!(
+            hasher,
+            "{:?} {:04x}:{:04x} {}",
+            crate::GAL_VERSION,
+            info.vendor_id,
+            info.device_id,
+            info.name
+        );
+
+        Self {
+            device_key: hasher.finish(),
+            entries: Mutex::new(BTreeMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Path the cache of this device is kept at by default
+    pub fn default_path(&self) -> String {
+        format!("/var/cache/gal/pipelines-{:032x}", self.device_key)
+    }
+
+    /// Look up the compiled artifact of a pipeline
+    pub fn get(&self, key: PipelineKey) -> Option<Arc<[u8]>> {
+        let data = self.entries.lock().get(&key).cloned();
+        let counter = if data.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        data
+    }
+
+    /// Store the compiled artifact of a pipeline
+    pub fn insert(&self, key: PipelineKey, data: &[u8]) {
+        self.entries.lock().insert(key, Arc::from(data));
+    }
+
+    /// Number of cached pipelines
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    /// Check if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+
+    /// Get cache statistics
+    pub fn stats(&self) -> PipelineCacheStats {
+        let entries = self.entries.lock();
+        PipelineCacheStats {
+            entries: entries.len(),
+            bytes: entries.values().map(|data| data.len()).sum(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Add the pipelines of `other` missing from this cache
+    ///
+    /// Returns the number of pipelines added. Caches of other devices are
+    /// ignored.
+    pub fn merge(&self, other: &PipelineCache) -> usize {
+        if other.device_key != self.device_key || core::ptr::eq(self, other) {
+            return 0;
+        }
+
+        let theirs: Vec<_> = other
+            .entries
+            .lock()
+            .iter()
+            .map(|(key, data)| (*key, data.clone()))
+            .collect();
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        for (key, data) in theirs {
+            entries.entry(key).or_insert(data);
+        }
+        entries.len() - before
+    }
+
+    /// Serialize the cache
+    pub fn serialize(&self) -> Vec<u8> {
+        let entries = self.entries.lock();
+        let size = CACHE_HEADER_SIZE
+            + entries
+                .values()
+                .map(|data| CACHE_ENTRY_HEADER_SIZE + data.len())
+                .sum::<usize>();
+
+        let mut out = Vec::with_capacity(size);
+        out.extend_from_slice(&CACHE_MAGIC);
+        out.extend_from_slice(&CACHE_FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.device_key.to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for (key, data) in entries.iter() {
+            out.extend_from_slice(&key.0.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&checksum(data).to_le_bytes());
+            out.extend_from_slice(data);
+        }
+        out
+    }
+
+    /// Add the pipelines of a serialized cache missing from this cache
+    ///
+    /// Returns the number of pipelines added. Data of another device, GAL
+    /// version or format is ignored, while truncated or corrupted data is an
+    /// error; entries before the damage are still added.
+    pub fn merge_serialized(&self, data: &[u8]) -> Result<usize> {
+        let mut reader = Reader(data);
+        if reader.take(8)? != CACHE_MAGIC {
+            return Err(Error::InvalidParameter);
+        }
+        let version = reader.u32()?;
+        let device_key = reader.u128()?;
+        if version != CACHE_FORMAT_VERSION || device_key != self.device_key {
+            log::debug!("gal: ignoring pipeline cache of another device or version");
+            return Ok(0);
+        }
+
+        let count = reader.u32()?;
+        let mut entries = self.entries.lock();
+        let mut added = 0;
+        for _ in 0..count {
+            let key = PipelineKey(reader.u128()?);
+            let size = reader.u32()? as usize;
+            let sum = reader.u64()?;
+            let data = reader.take(size)?;
+            if checksum(data) != sum {
+                log::warn!("gal: pipeline cache entry {:032x} is corrupted", key.0);
+                return Err(Error::InvalidParameter);
+            }
+
+            if let Entry::Vacant(entry) = entries.entry(key) {
+                entry.insert(Arc::from(data));
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Add the pipelines saved at `path`, a file or a cache scheme
+    ///
+    /// Returns the number of pipelines added. A missing cache is not an error.
+    pub fn load(&self, path: &str) -> Result<usize> {
+        let data = match read_file(path) {
+            Ok(data) => data,
+            Err(err) if err.errno() == libredox::errno::ENOENT => return Ok(0),
+            Err(err) => {
+                log::warn!("gal: failed to read pipeline cache {}: {}", path, err);
+                return Err(Error::OperationFailed);
+            }
+        };
+        self.merge_serialized(&data)
+    }
+
+    /// Save the cache to `path`, a file or a cache scheme
+    ///
+    /// Pipelines saved there since the cache was loaded, for example by
+    /// another process, are merged in first so they aren't lost.
+    pub fn save(&self, path: &str) -> Result<()> {
+        if let Err(err) = self.load(path) {
+            log::warn!("gal: replacing unreadable pipeline cache {}: {}", path, err);
+        }
+
+        let data = self.serialize();
+        write_file(path, &data).map_err(|err| {
+            log::warn!("gal: failed to write pipeline cache {}: {}", path, err);
+            Error::OperationFailed
+        })
+    }
+}
+
+/// Checksum of a cached artifact
+fn checksum(data: &[u8]) -> u64 {
+    let mut hasher = CacheHasher::new();
+    hasher.write_bytes(data);
+    hasher.finish() as u64
+}
+
+fn read_file(path: &str) -> libredox::error::Result<Vec<u8>> {
+    let fd = libredox::Fd::open(
+        path,
+        libredox::flag::O_RDONLY | libredox::flag::O_CLOEXEC,
+        0,
+    )?;
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        match fd.read(&mut chunk)? {
+            0 => return Ok(data),
+            count => data.extend_from_slice(&chunk[..count]),
+        }
+    }
+}
+
+fn write_file(path: &str, data: &[u8]) -> libredox::error::Result<()> {
+    let fd = libredox::Fd::open(
+        path,
+        libredox::flag::O_WRONLY
+            | libredox::flag::O_CREAT
+            | libredox::flag::O_TRUNC
+            | libredox::flag::O_CLOEXEC,
+        0o644,
+    )?;
+    let mut written = 0;
+    while written < data.len() {
+        written += fd.write(&data[written..])?;
+    }
+    fd.fsync()
+}
+
+/// 128-bit FNV-1a hasher for cache keys
+struct CacheHasher(u128);
+
+impl CacheHasher {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u128::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    /// Hash a slice together with its length, so adjacent slices can't run into each other
+    fn write_slice(&mut self, bytes: &[u8]) {
+        self.write_bytes(&(bytes.len() as u64).to_le_bytes());
+        self.write_bytes(bytes);
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Write for CacheHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Little-endian reader over serialized cache data
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(Error::InvalidParameter);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn u128(&mut self) -> Result<u128> {
+        Ok(u128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+    }
+}
@@ -0,0 +1,35 @@
+//! GPU queries
+//!
+//! Command buffers write timestamps into a [`QueryPool`] as they execute, and
+//! the host reads them back once the work finished. Timestamps count ticks of
+//! [`DeviceInfo::timestamp_period`](crate::DeviceInfo::timestamp_period)
+//! nanoseconds and are only comparable with others of the same queue.
+
+use crate::Result;
+
+/// What the queries of a pool measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryType {
+    /// GPU clock once all previously submitted commands finished
+    Timestamp,
+}
+
+/// Pool of queries written by command buffers
+pub trait QueryPool: Send + Sync {
+    /// Get the pool handle
+    fn handle(&self) -> usize;
+
+    /// Get what the queries measure
+    fn query_type(&self) -> QueryType;
+
+    /// Get the number of queries
+    fn count(&self) -> u32;
+
+    /// Make `count` queries from `first` unavailable, before writing them
+    /// again
+    fn reset(&self, first: u32, count: u32) -> Result<()>;
+
+    /// Read the queries from `first` into `results`, `None` for queries not
+    /// written yet
+    fn results(&self, first: u32, results: &mut [Option<u64>]) -> Result<()>;
+}
@@ -34,6 +34,22 @@ impl QueueType {
     }
 }
 
+/// Scheduling priority of a queue's work relative to other processes
+///
+/// Work on a higher priority queue preempts running work of lower priority
+/// queues, on backends whose scheduler can preempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum QueuePriority {
+    /// Background work, such as long-running compute
+    Low,
+    #[default]
+    Normal,
+    /// Interactive work
+    High,
+    /// Work that must not wait, such as the compositor's
+    Realtime,
+}
+
 /// Submit info for queue submission
 pub struct SubmitInfo<'a> {
     /// Command buffers to submit
@@ -87,6 +103,20 @@ pub trait Queue: Send + Sync {
 
     /// Present a swapchain image (for graphics queue)
     fn present(&self, present_info: &PresentInfo) -> Result<()>;
+
+    /// Scheduling priority of the work submitted to the queue
+    fn priority(&self) -> QueuePriority {
+        QueuePriority::Normal
+    }
+
+    /// Change the scheduling priority, for work submitted afterwards
+    fn set_priority(&self, priority: QueuePriority) -> Result<()> {
+        if priority == QueuePriority::Normal {
+            Ok(())
+        } else {
+            Err(Error::NotSupported)
+        }
+    }
 }
 
 /// Present info for swapchain presentation
@@ -0,0 +1,2201 @@
+//! Software rasterizer device
+//!
+//! [`SoftwareDevice`] runs GAL on the CPU, so systems without a supported
+//! GPU still get a working device for compositor bring-up, and CI can
+//! render reference images without hardware.
+//!
+//! Command buffers are recorded and executed in order when submitted; the
+//! submission has finished when [`Queue::submit`] returns. Draws go through
+//! triangle setup, edge-function rasterization, the depth test and blending
+//! as configured in the pipeline.
+//!
+//! There is no shader compiler. Shader code is accepted and ignored, and a
+//! fixed function stage takes its place: vertex attribute location 0 is the
+//! clip-space position, location 1 the optional vertex color (white if
+//! absent), and the fragment stage writes the perspective-correct
+//! interpolated color.
+//!
+//! Limitations:
+//!
+//! - Images are 2D with one mip level, one array layer and one sample
+//! - Render targets are `Rgba8Unorm`, `Bgra8Unorm` or `Rgba32Float`, depth
+//!   targets `Depth16Unorm`, `Depth24Plus`, `Depth24PlusStencil8` or
+//!   `Depth32Float`; the stencil is left alone
+//! - Only filled triangle lists, strips and fans are drawn; triangles
+//!   crossing the `w = 0` plane are dropped instead of clipped
+//! - Compute pipelines are not supported
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use spin::{Mutex, RwLock};
+
+use crate::command::{
+    BufferCopy, BufferImageCopy, CommandBufferState, DrawIndexedCommand, Filter, ImageBlit,
+    ImageCopy, ImageSubresourceRange, IndexType, LoadOp, PipelineBarrier, RenderPassDescriptor,
+    ShaderStageFlags,
+};
+use crate::debug::{self, LabelEvent};
+use crate::device::{
+    BlendFactor, BlendOp, ColorBlendAttachment, ColorWriteMask, CompareOp, CullMode, DisplayInfo,
+    FrontFace, GraphicsPipelineDescriptor, PolygonMode, PrimitiveTopology, SwapchainConfig,
+    VertexFormat, VertexInputRate,
+};
+use crate::display::{self, DisplayTarget, PresentMode};
+use crate::image::{ImageDescriptor, ImageDimension};
+use crate::queue::{PresentInfo, SubmitInfo};
+use crate::{
+    Buffer, BufferDescriptor, BufferUsage, ClearValue, CommandBuffer, CommandPool, DebugLabel,
+    Device, DeviceCapabilities, DeviceInfo, DeviceType, DrawCommand, Error, Extent2D, Extent3D,
+    Feature, Fence, Image, ImageFormat, ImageUsage, Memory, MemoryType, ObjectType, Pipeline,
+    PipelineType, QueryPool, QueryType, Queue, QueueType, Rect2D, Result, Semaphore, Shader,
+    ShaderModule, ShaderStage, Swapchain, Viewport,
+};
+
+/// Next handle of any software object
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+
+fn alloc_handle() -> usize {
+    NEXT_HANDLE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Device info of the software renderer
+pub fn device_info() -> DeviceInfo {
+    let mut capabilities = DeviceCapabilities::BLIT_2D | DeviceCapabilities::RENDER_3D;
+    // Timestamps are taken from the monotonic clock as commands execute
+    let timestamp_period = if display::monotonic_ns().is_some() {
+        capabilities |= DeviceCapabilities::TIMESTAMP_QUERIES;
+        1.0
+    } else {
+        0.0
+    };
+
+    DeviceInfo {
+        name: String::from("Software Renderer"),
+        vendor_id: 0,
+        device_id: 0,
+        device_type: DeviceType::Software,
+        capabilities,
+        display_count: 1,
+        max_texture_2d: 16384,
+        // Only single layer 2D images
+        max_texture_3d: 0,
+        max_texture_layers: 1,
+        timestamp_period,
+        ..Default::default()
+    }
+}
+
+/// Pixels of an image, rows packed without padding
+struct ImageStorage {
+    extent: Extent2D,
+    format: ImageFormat,
+    bytes_per_pixel: usize,
+    data: RwLock<Vec<u8>>,
+}
+
+impl ImageStorage {
+    fn offset(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.extent.width as usize + x as usize) * self.bytes_per_pixel
+    }
+}
+
+/// Results of a query pool, `None` until written
+type QueryResults = Arc<Mutex<Vec<Option<u64>>>>;
+
+/// Objects referenced by recorded commands, looked up when they execute
+#[derive(Default)]
+struct Registry {
+    buffers: Mutex<BTreeMap<usize, Arc<RwLock<Vec<u8>>>>>,
+    images: Mutex<BTreeMap<usize, Arc<ImageStorage>>>,
+    pipelines: Mutex<BTreeMap<usize, Arc<GraphicsPipelineDescriptor>>>,
+    fences: Mutex<BTreeMap<usize, Arc<AtomicBool>>>,
+    query_pools: Mutex<BTreeMap<usize, QueryResults>>,
+    /// Finished recordings, by address of the command buffer
+    recordings: Mutex<BTreeMap<usize, Arc<Vec<Command>>>>,
+}
+
+impl Registry {
+    fn buffer(&self, handle: usize) -> Result<Arc<RwLock<Vec<u8>>>> {
+        self.buffers
+            .lock()
+            .get(&handle)
+            .cloned()
+            .ok_or_else(|| missing(ObjectType::Buffer, handle))
+    }
+
+    fn image(&self, handle: usize) -> Result<Arc<ImageStorage>> {
+        self.images
+            .lock()
+            .get(&handle)
+            .cloned()
+            .ok_or_else(|| missing(ObjectType::Image, handle))
+    }
+
+    fn pipeline(&self, handle: usize) -> Result<Arc<GraphicsPipelineDescriptor>> {
+        self.pipelines
+            .lock()
+            .get(&handle)
+            .cloned()
+            .ok_or_else(|| missing(ObjectType::Pipeline, handle))
+    }
+
+    fn query_pool(&self, handle: usize) -> Result<QueryResults> {
+        self.query_pools
+            .lock()
+            .get(&handle)
+            .cloned()
+            .ok_or_else(|| missing(ObjectType::QueryPool, handle))
+    }
+
+    /// Signal a fence once the work submitted so far is done, which it
+    /// always is
+    fn signal(&self, fence: Option<&dyn Fence>) -> Result<()> {
+        if let Some(fence) = fence {
+            let handle = fence.handle();
+            let signaled = self
+                .fences
+                .lock()
+                .get(&handle)
+                .cloned()
+                .ok_or_else(|| missing(ObjectType::Fence, handle))?;
+            signaled.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+}
+
+fn missing(object_type: ObjectType, handle: usize) -> Error {
+    Error::CommandBufferError(alloc::format!(
+        "{} was destroyed or not created by the software device",
+        debug::describe(object_type, handle)
+    ))
+}
+
+/// Software rasterizer device
+pub struct SoftwareDevice {
+    info: DeviceInfo,
+    registry: Arc<Registry>,
+    display: Arc<SoftwareDisplay>,
+    queue: SoftwareQueue,
+}
+
+impl SoftwareDevice {
+    /// Create a device with one display of `extent`
+    ///
+    /// The display is a memory buffer; [`SoftwareDevice::read_scanout`]
+    /// returns what it shows.
+    pub fn new(extent: Extent2D) -> Self {
+        let registry = Arc::new(Registry::default());
+        let display = Arc::new(SoftwareDisplay {
+            info: DisplayInfo {
+                id: 0,
+                name: String::from("Software Display"),
+                extent,
+                refresh_rate: 60,
+                is_primary: true,
+                enabled: true,
+            },
+            registry: registry.clone(),
+            scanout: Mutex::new(None),
+            flipped_at: AtomicU64::new(0),
+        });
+        Self {
+            info: device_info(),
+            queue: SoftwareQueue {
+                registry: registry.clone(),
+            },
+            registry,
+            display,
+        }
+    }
+
+    /// Get the display target of the device
+    pub fn display_target(&self) -> Arc<dyn DisplayTarget> {
+        self.display.clone()
+    }
+
+    /// Read back the pixels of an image created by this device, rows packed
+    /// without padding
+    pub fn read_image(&self, image: &dyn Image) -> Result<Vec<u8>> {
+        Ok(self.registry.image(image.handle())?.data.read().clone())
+    }
+
+    /// Read back the image the display scans out, `None` before the first
+    /// flip
+    pub fn read_scanout(&self) -> Option<(ImageFormat, Extent2D, Vec<u8>)> {
+        let handle = (*self.display.scanout.lock())?;
+        let image = self.registry.image(handle).ok()?;
+        let data = image.data.read().clone();
+        Some((image.format, image.extent, data))
+    }
+}
+
+impl Device for SoftwareDevice {
+    fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    fn displays(&self) -> Vec<DisplayInfo> {
+        vec![self.display.info.clone()]
+    }
+
+    fn display(&self, id: usize) -> Option<DisplayInfo> {
+        (id == self.display.info.id).then(|| self.display.info.clone())
+    }
+
+    fn create_command_pool(&self, queue_type: QueueType) -> Result<Box<dyn CommandPool>> {
+        if queue_type != QueueType::Graphics {
+            return Err(Error::NotSupported);
+        }
+        Ok(Box::new(SoftwareCommandPool {
+            registry: self.registry.clone(),
+        }))
+    }
+
+    fn create_buffer(&self, descriptor: &BufferDescriptor) -> Result<Box<dyn Buffer>> {
+        let storage = Arc::new(RwLock::new(vec![0u8; descriptor.size as usize]));
+        let handle = alloc_handle();
+        self.registry.buffers.lock().insert(handle, storage.clone());
+        Ok(Box::new(SoftwareBuffer {
+            handle,
+            usage: descriptor.usage,
+            storage,
+            registry: self.registry.clone(),
+        }))
+    }
+
+    fn create_image(&self, descriptor: &ImageDescriptor) -> Result<Box<dyn Image>> {
+        descriptor.validate()?;
+        self.info.check_image(descriptor)?;
+        if descriptor.extent.depth != 1
+            || descriptor.mip_levels != 1
+            || descriptor.sample_count != 1
+        {
+            return Err(Error::NotSupported);
+        }
+        let bytes_per_pixel = descriptor
+            .format
+            .bytes_per_pixel()
+            .ok_or(Error::NotSupported)? as usize;
+
+        let extent = Extent2D::new(descriptor.extent.width, descriptor.extent.height);
+        let size = extent.width as usize * extent.height as usize * bytes_per_pixel;
+        let storage = Arc::new(ImageStorage {
+            extent,
+            format: descriptor.format,
+            bytes_per_pixel,
+            data: RwLock::new(vec![0u8; size]),
+        });
+        let handle = alloc_handle();
+        self.registry.images.lock().insert(handle, storage);
+        Ok(Box::new(SoftwareImage {
+            handle,
+            dimension: descriptor.dimension,
+            extent,
+            format: descriptor.format,
+            usage: descriptor.usage,
+            registry: self.registry.clone(),
+        }))
+    }
+
+    fn allocate_memory(&self, size: u64, memory_type: MemoryType) -> Result<Box<dyn Memory>> {
+        Ok(Box::new(SoftwareMemory {
+            handle: alloc_handle(),
+            memory_type,
+            data: RwLock::new(vec![0u8; size as usize]),
+        }))
+    }
+
+    fn create_fence(&self, signaled: bool) -> Result<Box<dyn Fence>> {
+        let handle = alloc_handle();
+        let signaled = Arc::new(AtomicBool::new(signaled));
+        self.registry.fences.lock().insert(handle, signaled.clone());
+        Ok(Box::new(SoftwareFence {
+            handle,
+            signaled,
+            registry: self.registry.clone(),
+        }))
+    }
+
+    fn create_semaphore(&self) -> Result<Box<dyn Semaphore>> {
+        Ok(Box::new(SoftwareSemaphore {
+            handle: alloc_handle(),
+        }))
+    }
+
+    fn create_query_pool(&self, query_type: QueryType, count: u32) -> Result<Box<dyn QueryPool>> {
+        self.info
+            .require(Feature::Capabilities(DeviceCapabilities::TIMESTAMP_QUERIES))?;
+        let handle = alloc_handle();
+        let results = Arc::new(Mutex::new(vec![None; count as usize]));
+        self.registry
+            .query_pools
+            .lock()
+            .insert(handle, results.clone());
+        Ok(Box::new(SoftwareQueryPool {
+            handle,
+            query_type,
+            results,
+            registry: self.registry.clone(),
+        }))
+    }
+
+    /// The code is not interpreted, see the module documentation
+    fn create_shader(&self, stage: ShaderStage, _code: &[u8]) -> Result<Box<dyn Shader>> {
+        Ok(Box::new(SoftwareShader {
+            module: ShaderModule::new(stage, Vec::new(), "main"),
+        }))
+    }
+
+    fn create_graphics_pipeline(
+        &self,
+        desc: &GraphicsPipelineDescriptor,
+    ) -> Result<Box<dyn Pipeline>> {
+        if !matches!(
+            desc.topology,
+            PrimitiveTopology::TriangleList
+                | PrimitiveTopology::TriangleStrip
+                | PrimitiveTopology::TriangleFan
+        ) || desc.polygon_mode != PolygonMode::Fill
+        {
+            return Err(Error::NotSupported);
+        }
+        if !desc.vertex_attributes.iter().any(|a| a.location == 0) {
+            return Err(Error::PipelineCreationFailed(String::from(
+                "no position attribute at location 0",
+            )));
+        }
+        for attribute in &desc.vertex_attributes {
+            if !desc
+                .vertex_bindings
+                .iter()
+                .any(|b| b.binding == attribute.binding)
+            {
+                return Err(Error::PipelineCreationFailed(alloc::format!(
+                    "attribute {} reads unknown binding {}",
+                    attribute.location,
+                    attribute.binding
+                )));
+            }
+        }
+        if let Some(format) = desc.color_formats.iter().find(|f| !is_color_target(**f)) {
+            return Err(Error::PipelineCreationFailed(alloc::format!(
+                "{:?} is not a color target format",
+                format
+            )));
+        }
+        if let Some(format) = desc.depth_format.filter(|f| !is_depth_target(*f)) {
+            return Err(Error::PipelineCreationFailed(alloc::format!(
+                "{:?} is not a depth target format",
+                format
+            )));
+        }
+
+        let handle = alloc_handle();
+        self.registry
+            .pipelines
+            .lock()
+            .insert(handle, Arc::new(desc.clone()));
+        Ok(Box::new(SoftwarePipeline {
+            handle,
+            registry: self.registry.clone(),
+        }))
+    }
+
+    fn create_compute_pipeline(&self, _shader: &dyn Shader) -> Result<Box<dyn Pipeline>> {
+        Err(Error::NotSupported)
+    }
+
+    fn graphics_queue(&self) -> &dyn Queue {
+        &self.queue
+    }
+
+    fn compute_queue(&self) -> Option<&dyn Queue> {
+        None
+    }
+
+    fn transfer_queue(&self) -> Option<&dyn Queue> {
+        None
+    }
+
+    fn wait_idle(&self) -> Result<()> {
+        // Submissions finish before submit returns
+        Ok(())
+    }
+
+    fn create_swapchain(
+        &self,
+        config: &SwapchainConfig,
+    ) -> Result<Box<dyn crate::device::Swapchain>> {
+        Ok(Box::new(Swapchain::new(self.display_target(), config)?))
+    }
+}
+
+/// Buffer in main memory
+pub struct SoftwareBuffer {
+    handle: usize,
+    usage: BufferUsage,
+    storage: Arc<RwLock<Vec<u8>>>,
+    registry: Arc<Registry>,
+}
+
+impl Buffer for SoftwareBuffer {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn size(&self) -> u64 {
+        self.storage.read().len() as u64
+    }
+
+    fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    fn memory(&self) -> Option<&dyn Memory> {
+        None
+    }
+
+    fn map(&self) -> Result<*mut u8> {
+        // The vector is never resized, so the pointer stays valid
+        Ok(self.storage.write().as_mut_ptr())
+    }
+
+    fn unmap(&self) {}
+
+    fn flush(&self, _offset: u64, _size: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn invalidate(&self, _offset: u64, _size: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SoftwareBuffer {
+    fn drop(&mut self) {
+        self.registry.buffers.lock().remove(&self.handle);
+        debug::clear_object_name(ObjectType::Buffer, self.handle);
+    }
+}
+
+/// Image in main memory
+pub struct SoftwareImage {
+    handle: usize,
+    dimension: ImageDimension,
+    extent: Extent2D,
+    format: ImageFormat,
+    usage: ImageUsage,
+    registry: Arc<Registry>,
+}
+
+impl Image for SoftwareImage {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn dimension(&self) -> ImageDimension {
+        self.dimension
+    }
+
+    fn extent(&self) -> Extent3D {
+        Extent3D::new(self.extent.width, self.extent.height, 1)
+    }
+
+    fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    fn mip_levels(&self) -> u32 {
+        1
+    }
+
+    fn array_layers(&self) -> u32 {
+        1
+    }
+
+    fn sample_count(&self) -> u32 {
+        1
+    }
+
+    fn usage(&self) -> ImageUsage {
+        self.usage
+    }
+
+    fn memory(&self) -> Option<&dyn Memory> {
+        None
+    }
+}
+
+impl Drop for SoftwareImage {
+    fn drop(&mut self) {
+        self.registry.images.lock().remove(&self.handle);
+        debug::clear_object_name(ObjectType::Image, self.handle);
+    }
+}
+
+/// Memory allocation in main memory
+pub struct SoftwareMemory {
+    handle: usize,
+    memory_type: MemoryType,
+    data: RwLock<Vec<u8>>,
+}
+
+impl Memory for SoftwareMemory {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn size(&self) -> u64 {
+        self.data.read().len() as u64
+    }
+
+    fn memory_type(&self) -> MemoryType {
+        self.memory_type
+    }
+
+    fn map(&self, offset: u64, size: u64) -> Result<*mut u8> {
+        let mut data = self.data.write();
+        if offset
+            .checked_add(size)
+            .is_none_or(|end| end > data.len() as u64)
+        {
+            return Err(Error::InvalidParameter);
+        }
+        Ok(data[offset as usize..].as_mut_ptr())
+    }
+
+    fn unmap(&self) {}
+
+    fn flush(&self, _offset: u64, _size: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn invalidate(&self, _offset: u64, _size: u64) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SoftwareMemory {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Memory, self.handle);
+    }
+}
+
+/// Fence, signaled when the submission it was passed to returns
+pub struct SoftwareFence {
+    handle: usize,
+    signaled: Arc<AtomicBool>,
+    registry: Arc<Registry>,
+}
+
+impl Fence for SoftwareFence {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn is_signaled(&self) -> Result<bool> {
+        Ok(self.signaled.load(Ordering::Acquire))
+    }
+
+    fn wait(&self, _timeout_ns: u64) -> Result<bool> {
+        // Nothing runs in the background that could still signal it
+        self.is_signaled()
+    }
+
+    fn reset(&self) -> Result<()> {
+        self.signaled.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for SoftwareFence {
+    fn drop(&mut self) {
+        self.registry.fences.lock().remove(&self.handle);
+        debug::clear_object_name(ObjectType::Fence, self.handle);
+    }
+}
+
+/// Semaphore, always satisfied since work finishes on submission
+pub struct SoftwareSemaphore {
+    handle: usize,
+}
+
+impl Semaphore for SoftwareSemaphore {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+}
+
+impl Drop for SoftwareSemaphore {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Semaphore, self.handle);
+    }
+}
+
+/// Query pool, written when recorded commands execute
+pub struct SoftwareQueryPool {
+    handle: usize,
+    query_type: QueryType,
+    results: QueryResults,
+    registry: Arc<Registry>,
+}
+
+impl QueryPool for SoftwareQueryPool {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn query_type(&self) -> QueryType {
+        self.query_type
+    }
+
+    fn count(&self) -> u32 {
+        self.results.lock().len() as u32
+    }
+
+    fn reset(&self, first: u32, count: u32) -> Result<()> {
+        let mut results = self.results.lock();
+        let range = first as usize..first as usize + count as usize;
+        results
+            .get_mut(range)
+            .ok_or(Error::InvalidParameter)?
+            .fill(None);
+        Ok(())
+    }
+
+    fn results(&self, first: u32, results: &mut [Option<u64>]) -> Result<()> {
+        let range = first as usize..first as usize + results.len();
+        results.copy_from_slice(
+            self.results
+                .lock()
+                .get(range)
+                .ok_or(Error::InvalidParameter)?,
+        );
+        Ok(())
+    }
+}
+
+impl Drop for SoftwareQueryPool {
+    fn drop(&mut self) {
+        self.registry.query_pools.lock().remove(&self.handle);
+        debug::clear_object_name(ObjectType::QueryPool, self.handle);
+    }
+}
+
+/// Graphics pipeline, its state is kept in the device registry
+pub struct SoftwarePipeline {
+    handle: usize,
+    registry: Arc<Registry>,
+}
+
+impl Pipeline for SoftwarePipeline {
+    fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn pipeline_type(&self) -> PipelineType {
+        PipelineType::Graphics
+    }
+}
+
+impl Drop for SoftwarePipeline {
+    fn drop(&mut self) {
+        self.registry.pipelines.lock().remove(&self.handle);
+        debug::clear_object_name(ObjectType::Pipeline, self.handle);
+    }
+}
+
+/// Shader, the software rasterizer has fixed vertex and fragment stages
+pub struct SoftwareShader {
+    module: ShaderModule,
+}
+
+impl Shader for SoftwareShader {
+    fn handle(&self) -> usize {
+        self.module.handle()
+    }
+
+    fn stage(&self) -> ShaderStage {
+        self.module.stage()
+    }
+
+    fn entry_point(&self) -> &str {
+        self.module.entry_point()
+    }
+}
+
+impl Drop for SoftwareShader {
+    fn drop(&mut self) {
+        debug::clear_object_name(ObjectType::Shader, self.module.handle());
+    }
+}
+
+/// Display scanning out of main memory, flips complete immediately
+struct SoftwareDisplay {
+    info: DisplayInfo,
+    registry: Arc<Registry>,
+    /// Image on screen
+    scanout: Mutex<Option<usize>>,
+    /// Monotonic time of the last flip in nanoseconds, 0 if unknown
+    flipped_at: AtomicU64,
+}
+
+impl DisplayTarget for SoftwareDisplay {
+    fn info(&self) -> DisplayInfo {
+        self.info.clone()
+    }
+
+    fn formats(&self) -> &[ImageFormat] {
+        &[ImageFormat::Bgra8Unorm, ImageFormat::Rgba8Unorm]
+    }
+
+    fn supports_present_mode(&self, _mode: PresentMode) -> bool {
+        true
+    }
+
+    fn create_image(&self, extent: Extent2D, format: ImageFormat) -> Result<Box<dyn Image>> {
+        if !self.formats().contains(&format) {
+            return Err(Error::NotSupported);
+        }
+        let descriptor = ImageDescriptor::scanout(extent.width, extent.height, format);
+        descriptor.validate()?;
+        let storage = Arc::new(ImageStorage {
+            extent,
+            format,
+            bytes_per_pixel: 4,
+            data: RwLock::new(vec![
+                0u8;
+                extent.width as usize * extent.height as usize * 4
+            ]),
+        });
+        let handle = alloc_handle();
+        self.registry.images.lock().insert(handle, storage);
+        Ok(Box::new(SoftwareImage {
+            handle,
+            dimension: ImageDimension::D2,
+            extent,
+            format,
+            usage: descriptor.usage | ImageUsage::TRANSFER_SRC,
+            registry: self.registry.clone(),
+        }))
+    }
+
+    fn destroy_image(&self, image: Box<dyn Image>) {
+        let mut scanout = self.scanout.lock();
+        if *scanout == Some(image.handle()) {
+            *scanout = None;
+        }
+    }
+
+    /// Scanout is read whole, so damage is of no use
+    fn flip(&self, image: &dyn Image, _damage: &[Rect2D]) -> Result<()> {
+        *self.scanout.lock() = Some(image.handle());
+        self.flipped_at
+            .store(display::monotonic_ns().unwrap_or(0), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn flip_timestamp_ns(&self) -> Option<u64> {
+        match self.flipped_at.load(Ordering::Relaxed) {
+            0 => None,
+            ns => Some(ns),
+        }
+    }
+
+    fn flip_pending(&self) -> bool {
+        false
+    }
+
+    fn wait_vblank(&self, _timeout_ns: u64) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn wait_semaphores(&self, _semaphores: &[&dyn Semaphore]) -> Result<()> {
+        Ok(())
+    }
+
+    fn signal(&self, _semaphore: Option<&dyn Semaphore>, fence: Option<&dyn Fence>) -> Result<()> {
+        self.registry.signal(fence)
+    }
+}
+
+/// The single graphics queue, executing submissions on the calling thread
+pub struct SoftwareQueue {
+    registry: Arc<Registry>,
+}
+
+impl Queue for SoftwareQueue {
+    fn queue_type(&self) -> QueueType {
+        QueueType::Graphics
+    }
+
+    fn submit(&self, submits: &[SubmitInfo], fence: Option<&dyn Fence>) -> Result<()> {
+        for submit in submits {
+            for &command_buffer in submit.command_buffers {
+                if command_buffer.state() != CommandBufferState::Executable {
+                    return Err(Error::CommandBufferError(String::from(
+                        "Command buffer is not executable",
+                    )));
+                }
+                let recording = self
+                    .registry
+                    .recordings
+                    .lock()
+                    .get(&recording_key(command_buffer))
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::CommandBufferError(String::from(
+                            "Command buffer was not allocated by the software device",
+                        ))
+                    })?;
+                Executor::new(&self.registry).run(&recording)?;
+            }
+        }
+        self.registry.signal(fence)
+    }
+
+    fn wait_idle(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn present(&self, _present_info: &PresentInfo) -> Result<()> {
+        // Swapchains present themselves through their display target
+        Err(Error::NotSupported)
+    }
+}
+
+/// Command pool of the graphics queue
+pub struct SoftwareCommandPool {
+    registry: Arc<Registry>,
+}
+
+impl CommandPool for SoftwareCommandPool {
+    fn allocate(&self) -> Result<Box<dyn CommandBuffer>> {
+        Ok(Box::new(SoftwareCommandBuffer {
+            handle: alloc_handle(),
+            registry: self.registry.clone(),
+            state: CommandBufferState::Initial,
+            commands: Vec::new(),
+            in_render_pass: false,
+            label_depth: AtomicU32::new(0),
+        }))
+    }
+
+    fn reset(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Recorded command, resources are referenced by handle
+#[derive(Debug, Clone)]
+enum Command {
+    BeginRenderPass {
+        color_attachments: Vec<(usize, LoadOp, ClearValue)>,
+        depth_attachment: Option<(usize, LoadOp, ClearValue)>,
+        render_area: Rect2D,
+    },
+    EndRenderPass,
+    BindPipeline(usize),
+    SetViewport(Viewport),
+    SetScissor(Rect2D),
+    BindVertexBuffers {
+        first_binding: u32,
+        buffers: Vec<(usize, u64)>,
+    },
+    BindIndexBuffer {
+        buffer: usize,
+        offset: u64,
+        index_type: IndexType,
+    },
+    Draw(DrawCommand),
+    DrawIndexed(DrawIndexedCommand),
+    DrawIndirect {
+        buffer: usize,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+        indexed: bool,
+    },
+    Dispatch,
+    CopyBuffer {
+        src: usize,
+        dst: usize,
+        regions: Vec<BufferCopy>,
+    },
+    CopyBufferToImage {
+        src: usize,
+        dst: usize,
+        regions: Vec<BufferImageCopy>,
+    },
+    CopyImageToBuffer {
+        src: usize,
+        dst: usize,
+        regions: Vec<BufferImageCopy>,
+    },
+    CopyImage {
+        src: usize,
+        dst: usize,
+        regions: Vec<ImageCopy>,
+    },
+    BlitImage {
+        src: usize,
+        dst: usize,
+        regions: Vec<ImageBlit>,
+        filter: Filter,
+    },
+    ClearColorImage {
+        image: usize,
+        color: ClearValue,
+    },
+    ClearDepthImage {
+        image: usize,
+        depth_stencil: ClearValue,
+    },
+    WriteTimestamp {
+        pool: usize,
+        query: u32,
+    },
+}
+
+/// Key a recording is registered under, the address of the command buffer
+///
+/// The command buffer trait has no handle, and a software command buffer
+/// only lives boxed, so its address identifies it for as long as it exists.
+fn recording_key(command_buffer: &dyn CommandBuffer) -> usize {
+    command_buffer as *const dyn CommandBuffer as *const () as usize
+}
+
+/// Command buffer recording into a list executed on submission
+pub struct SoftwareCommandBuffer {
+    handle: usize,
+    registry: Arc<Registry>,
+    state: CommandBufferState,
+    commands: Vec<Command>,
+    in_render_pass: bool,
+    label_depth: AtomicU32,
+}
+
+impl SoftwareCommandBuffer {
+    fn forget_recording(&self) {
+        self.registry.recordings.lock().remove(&recording_key(self));
+    }
+}
+
+impl Drop for SoftwareCommandBuffer {
+    fn drop(&mut self) {
+        self.forget_recording();
+        debug::clear_object_name(ObjectType::CommandBuffer, self.handle);
+    }
+}
+
+impl CommandBuffer for SoftwareCommandBuffer {
+    fn state(&self) -> CommandBufferState {
+        self.state
+    }
+
+    fn begin(&mut self) -> Result<()> {
+        if self.state != CommandBufferState::Initial && self.state != CommandBufferState::Executable
+        {
+            return Err(Error::CommandBufferError("Invalid state for begin".into()));
+        }
+
+        self.forget_recording();
+        self.commands.clear();
+        self.label_depth.store(0, Ordering::Relaxed);
+        self.state = CommandBufferState::Recording;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.state != CommandBufferState::Recording {
+            return Err(Error::CommandBufferError("Not recording".into()));
+        }
+
+        if self.in_render_pass {
+            return Err(Error::CommandBufferError("Render pass not ended".into()));
+        }
+
+        if self.label_depth.load(Ordering::Relaxed) != 0 {
+            return Err(Error::CommandBufferError(alloc::format!(
+                "Debug label region not ended in {}",
+                debug::describe(ObjectType::CommandBuffer, self.handle)
+            )));
+        }
+
+        let recording = Arc::new(core::mem::take(&mut self.commands));
+        self.registry
+            .recordings
+            .lock()
+            .insert(recording_key(self), recording);
+        self.state = CommandBufferState::Executable;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.forget_recording();
+        self.commands.clear();
+        self.state = CommandBufferState::Initial;
+        self.in_render_pass = false;
+        self.label_depth.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn begin_render_pass(&mut self, desc: &RenderPassDescriptor) -> Result<()> {
+        if self.in_render_pass {
+            return Err(Error::CommandBufferError("Already in render pass".into()));
+        }
+
+        let mut color_attachments = Vec::with_capacity(desc.color_attachments.len());
+        for attachment in &desc.color_attachments {
+            let handle = attachment.image.handle();
+            if !is_color_target(attachment.image.format())
+                || color_attachments.iter().any(|&(h, _, _)| h == handle)
+            {
+                return Err(Error::NotSupported);
+            }
+            color_attachments.push((handle, attachment.load_op, attachment.clear_value));
+        }
+        let depth_attachment = match &desc.depth_stencil_attachment {
+            Some(attachment) if !is_depth_target(attachment.image.format()) => {
+                return Err(Error::NotSupported);
+            }
+            Some(attachment) => Some((
+                attachment.image.handle(),
+                attachment.depth_load_op,
+                attachment.clear_value,
+            )),
+            None => None,
+        };
+
+        self.commands.push(Command::BeginRenderPass {
+            color_attachments,
+            depth_attachment,
+            render_area: desc.render_area,
+        });
+        self.in_render_pass = true;
+        Ok(())
+    }
+
+    fn end_render_pass(&mut self) {
+        if self.in_render_pass {
+            self.commands.push(Command::EndRenderPass);
+            self.in_render_pass = false;
+        }
+    }
+
+    fn bind_pipeline(&mut self, pipeline: &dyn Pipeline) {
+        self.commands.push(Command::BindPipeline(pipeline.handle()));
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.commands.push(Command::SetViewport(viewport));
+    }
+
+    fn set_scissor(&mut self, scissor: Rect2D) {
+        self.commands.push(Command::SetScissor(scissor));
+    }
+
+    fn bind_vertex_buffers(
+        &mut self,
+        first_binding: u32,
+        buffers: &[&dyn Buffer],
+        offsets: &[u64],
+    ) {
+        let buffers = buffers
+            .iter()
+            .zip(offsets)
+            .map(|(buffer, &offset)| (buffer.handle(), offset))
+            .collect();
+        self.commands.push(Command::BindVertexBuffers {
+            first_binding,
+            buffers,
+        });
+    }
+
+    fn bind_index_buffer(&mut self, buffer: &dyn Buffer, offset: u64, index_type: IndexType) {
+        self.commands.push(Command::BindIndexBuffer {
+            buffer: buffer.handle(),
+            offset,
+            index_type,
+        });
+    }
+
+    fn draw(&mut self, cmd: DrawCommand) {
+        self.commands.push(Command::Draw(cmd));
+    }
+
+    fn draw_indexed(&mut self, cmd: DrawIndexedCommand) {
+        self.commands.push(Command::DrawIndexed(cmd));
+    }
+
+    fn draw_indirect(&mut self, buffer: &dyn Buffer, offset: u64, draw_count: u32, stride: u32) {
+        self.commands.push(Command::DrawIndirect {
+            buffer: buffer.handle(),
+            offset,
+            draw_count,
+            stride,
+            indexed: false,
+        });
+    }
+
+    fn draw_indexed_indirect(
+        &mut self,
+        buffer: &dyn Buffer,
+        offset: u64,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        self.commands.push(Command::DrawIndirect {
+            buffer: buffer.handle(),
+            offset,
+            draw_count,
+            stride,
+            indexed: true,
+        });
+    }
+
+    fn dispatch(&mut self, _group_count_x: u32, _group_count_y: u32, _group_count_z: u32) {
+        self.commands.push(Command::Dispatch);
+    }
+
+    fn dispatch_indirect(&mut self, _buffer: &dyn Buffer, _offset: u64) {
+        self.commands.push(Command::Dispatch);
+    }
+
+    fn copy_buffer(&mut self, src: &dyn Buffer, dst: &dyn Buffer, regions: &[BufferCopy]) {
+        self.commands.push(Command::CopyBuffer {
+            src: src.handle(),
+            dst: dst.handle(),
+            regions: regions.to_vec(),
+        });
+    }
+
+    fn copy_buffer_to_image(
+        &mut self,
+        src: &dyn Buffer,
+        dst: &dyn Image,
+        regions: &[BufferImageCopy],
+    ) {
+        self.commands.push(Command::CopyBufferToImage {
+            src: src.handle(),
+            dst: dst.handle(),
+            regions: regions.to_vec(),
+        });
+    }
+
+    fn copy_image_to_buffer(
+        &mut self,
+        src: &dyn Image,
+        dst: &dyn Buffer,
+        regions: &[BufferImageCopy],
+    ) {
+        self.commands.push(Command::CopyImageToBuffer {
+            src: src.handle(),
+            dst: dst.handle(),
+            regions: regions.to_vec(),
+        });
+    }
+
+    fn copy_image(&mut self, src: &dyn Image, dst: &dyn Image, regions: &[ImageCopy]) {
+        self.commands.push(Command::CopyImage {
+            src: src.handle(),
+            dst: dst.handle(),
+            regions: regions.to_vec(),
+        });
+    }
+
+    fn blit_image(
+        &mut self,
+        src: &dyn Image,
+        dst: &dyn Image,
+        regions: &[ImageBlit],
+        filter: Filter,
+    ) {
+        self.commands.push(Command::BlitImage {
+            src: src.handle(),
+            dst: dst.handle(),
+            regions: regions.to_vec(),
+            filter,
+        });
+    }
+
+    /// Images have a single subresource, so `ranges` only matter when empty
+    fn clear_color_image(
+        &mut self,
+        image: &dyn Image,
+        color: ClearValue,
+        ranges: &[ImageSubresourceRange],
+    ) {
+        if !ranges.is_empty() {
+            self.commands.push(Command::ClearColorImage {
+                image: image.handle(),
+                color,
+            });
+        }
+    }
+
+    fn clear_depth_stencil_image(
+        &mut self,
+        image: &dyn Image,
+        depth_stencil: ClearValue,
+        ranges: &[ImageSubresourceRange],
+    ) {
+        if !ranges.is_empty() {
+            self.commands.push(Command::ClearDepthImage {
+                image: image.handle(),
+                depth_stencil,
+            });
+        }
+    }
+
+    fn pipeline_barrier(&mut self, _barrier: &PipelineBarrier) {
+        // Commands execute one after the other
+    }
+
+    fn push_constants(&mut self, _stages: ShaderStageFlags, _offset: u32, _data: &[u8]) {
+        // Nothing in the fixed function stage reads them
+    }
+
+    fn begin_debug_label(&mut self, label: &DebugLabel) {
+        debug::trace_label(self.handle, LabelEvent::Begin, Some(label));
+        self.label_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn end_debug_label(&mut self) {
+        if self.label_depth.load(Ordering::Relaxed) == 0 {
+            log::warn!(
+                "gal: end_debug_label without open region in {}",
+                debug::describe(ObjectType::CommandBuffer, self.handle)
+            );
+            return;
+        }
+        debug::trace_label(self.handle, LabelEvent::End, None);
+        self.label_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn insert_debug_label(&mut self, label: &DebugLabel) {
+        debug::trace_label(self.handle, LabelEvent::Insert, Some(label));
+    }
+
+    fn write_timestamp(&mut self, pool: &dyn QueryPool, query: u32) -> Result<()> {
+        if pool.query_type() != QueryType::Timestamp || query >= pool.count() {
+            return Err(Error::InvalidParameter);
+        }
+        self.commands.push(Command::WriteTimestamp {
+            pool: pool.handle(),
+            query,
+        });
+        Ok(())
+    }
+}
+
+fn is_color_target(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Rgba8Unorm | ImageFormat::Bgra8Unorm | ImageFormat::Rgba32Float
+    )
+}
+
+fn is_depth_target(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Depth16Unorm
+            | ImageFormat::Depth24Plus
+            | ImageFormat::Depth24PlusStencil8
+            | ImageFormat::Depth32Float
+    )
+}
+
+fn unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+fn read_color(format: ImageFormat, pixel: &[u8]) -> [f32; 4] {
+    match format {
+        ImageFormat::Rgba8Unorm => core::array::from_fn(|i| f32::from(pixel[i]) / 255.0),
+        ImageFormat::Bgra8Unorm => {
+            core::array::from_fn(|i| f32::from(pixel[[2, 1, 0, 3][i]]) / 255.0)
+        }
+        ImageFormat::Rgba32Float => core::array::from_fn(|i| {
+            f32::from_ne_bytes(pixel[i * 4..i * 4 + 4].try_into().unwrap())
+        }),
+        _ => [0.0; 4],
+    }
+}
+
+fn write_color(format: ImageFormat, color: [f32; 4], mask: ColorWriteMask, pixel: &mut [u8]) {
+    const CHANNELS: [ColorWriteMask; 4] = [
+        ColorWriteMask::R,
+        ColorWriteMask::G,
+        ColorWriteMask::B,
+        ColorWriteMask::A,
+    ];
+    for (channel, &value) in color.iter().enumerate() {
+        if !mask.contains(CHANNELS[channel]) {
+            continue;
+        }
+        match format {
+            ImageFormat::Rgba8Unorm => pixel[channel] = unorm8(value),
+            ImageFormat::Bgra8Unorm => pixel[[2, 1, 0, 3][channel]] = unorm8(value),
+            ImageFormat::Rgba32Float => {
+                pixel[channel * 4..channel * 4 + 4].copy_from_slice(&value.to_ne_bytes())
+            }
+            _ => {}
+        }
+    }
+}
+
+fn read_depth(format: ImageFormat, pixel: &[u8]) -> f32 {
+    match format {
+        ImageFormat::Depth16Unorm => f32::from(u16::from_ne_bytes([pixel[0], pixel[1]])) / 65535.0,
+        ImageFormat::Depth24PlusStencil8 => {
+            let value = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], 0]);
+            (f64::from(value) / 16_777_215.0) as f32
+        }
+        // Depth24Plus is stored as a float, its precision is up to the backend
+        _ => f32::from_ne_bytes(pixel[..4].try_into().unwrap()),
+    }
+}
+
+fn write_depth(format: ImageFormat, depth: f32, pixel: &mut [u8]) {
+    match format {
+        ImageFormat::Depth16Unorm => {
+            let value = (depth.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16;
+            pixel[..2].copy_from_slice(&value.to_ne_bytes());
+        }
+        ImageFormat::Depth24PlusStencil8 => {
+            // Depth in the low 24 bits, stencil in the high byte; f32 can't
+            // hold 2^24 - 0.5
+            let value = (f64::from(depth.clamp(0.0, 1.0)) * 16_777_215.0 + 0.5) as u32;
+            pixel[..3].copy_from_slice(&value.to_le_bytes()[..3]);
+        }
+        _ => pixel[..4].copy_from_slice(&depth.to_ne_bytes()),
+    }
+}
+
+/// Read a vertex attribute, missing components default to (0, 0, 0, 1)
+fn fetch_attribute(format: VertexFormat, data: &[u8]) -> Option<[f32; 4]> {
+    let (count, size) = match format {
+        VertexFormat::Float | VertexFormat::Int | VertexFormat::UInt => (1, 4),
+        VertexFormat::Float2 | VertexFormat::Int2 | VertexFormat::UInt2 => (2, 4),
+        VertexFormat::Float3 | VertexFormat::Int3 | VertexFormat::UInt3 => (3, 4),
+        VertexFormat::Float4 | VertexFormat::Int4 | VertexFormat::UInt4 => (4, 4),
+        VertexFormat::Byte4
+        | VertexFormat::Byte4Norm
+        | VertexFormat::UByte4
+        | VertexFormat::UByte4Norm => (4, 1),
+    };
+    let data = data.get(..count * size)?;
+
+    let mut value = [0.0, 0.0, 0.0, 1.0];
+    for (i, component) in value.iter_mut().take(count).enumerate() {
+        let bytes = &data[i * size..(i + 1) * size];
+        *component = match format {
+            VertexFormat::Float
+            | VertexFormat::Float2
+            | VertexFormat::Float3
+            | VertexFormat::Float4 => f32::from_ne_bytes(bytes.try_into().unwrap()),
+            VertexFormat::Int | VertexFormat::Int2 | VertexFormat::Int3 | VertexFormat::Int4 => {
+                i32::from_ne_bytes(bytes.try_into().unwrap()) as f32
+            }
+            VertexFormat::UInt
+            | VertexFormat::UInt2
+            | VertexFormat::UInt3
+            | VertexFormat::UInt4 => u32::from_ne_bytes(bytes.try_into().unwrap()) as f32,
+            VertexFormat::Byte4 => f32::from(bytes[0] as i8),
+            VertexFormat::Byte4Norm => (f32::from(bytes[0] as i8) / 127.0).max(-1.0),
+            VertexFormat::UByte4 => f32::from(bytes[0]),
+            VertexFormat::UByte4Norm => f32::from(bytes[0]) / 255.0,
+        };
+    }
+    Some(value)
+}
+
+fn compare(op: CompareOp, value: f32, stored: f32) -> bool {
+    match op {
+        CompareOp::Never => false,
+        CompareOp::Less => value < stored,
+        CompareOp::Equal => value == stored,
+        CompareOp::LessOrEqual => value <= stored,
+        CompareOp::Greater => value > stored,
+        CompareOp::NotEqual => value != stored,
+        CompareOp::GreaterOrEqual => value >= stored,
+        CompareOp::Always => true,
+    }
+}
+
+/// Blend factor for one channel, the blend constants are zero
+fn blend_factor(factor: BlendFactor, src: [f32; 4], dst: [f32; 4], channel: usize) -> f32 {
+    match factor {
+        BlendFactor::Zero | BlendFactor::ConstantColor | BlendFactor::ConstantAlpha => 0.0,
+        BlendFactor::One
+        | BlendFactor::OneMinusConstantColor
+        | BlendFactor::OneMinusConstantAlpha => 1.0,
+        BlendFactor::SrcColor => src[channel],
+        BlendFactor::OneMinusSrcColor => 1.0 - src[channel],
+        BlendFactor::DstColor => dst[channel],
+        BlendFactor::OneMinusDstColor => 1.0 - dst[channel],
+        BlendFactor::SrcAlpha => src[3],
+        BlendFactor::OneMinusSrcAlpha => 1.0 - src[3],
+        BlendFactor::DstAlpha => dst[3],
+        BlendFactor::OneMinusDstAlpha => 1.0 - dst[3],
+        BlendFactor::SrcAlphaSaturate if channel == 3 => 1.0,
+        BlendFactor::SrcAlphaSaturate => src[3].min(1.0 - dst[3]),
+    }
+}
+
+fn blend(state: &ColorBlendAttachment, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    if !state.blend_enable {
+        return src;
+    }
+    core::array::from_fn(|channel| {
+        let (src_factor, dst_factor, op) = if channel == 3 {
+            (
+                state.src_alpha_factor,
+                state.dst_alpha_factor,
+                state.alpha_op,
+            )
+        } else {
+            (
+                state.src_color_factor,
+                state.dst_color_factor,
+                state.color_op,
+            )
+        };
+        let s = src[channel] * blend_factor(src_factor, src, dst, channel);
+        let d = dst[channel] * blend_factor(dst_factor, src, dst, channel);
+        match op {
+            BlendOp::Add => s + d,
+            BlendOp::Subtract => s - d,
+            BlendOp::ReverseSubtract => d - s,
+            BlendOp::Min => src[channel].min(dst[channel]),
+            BlendOp::Max => src[channel].max(dst[channel]),
+        }
+    })
+}
+
+/// Vertex after the viewport transform
+#[derive(Debug, Clone, Copy)]
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    /// 1 / w, for perspective-correct interpolation
+    inv_w: f32,
+    /// Color divided by w
+    color: [f32; 4],
+}
+
+/// Signed area of the parallelogram spanned by a->b and a->p, positive with
+/// p to the right of a->b in framebuffer coordinates (y down)
+fn edge(a: &ScreenVertex, b: &ScreenVertex, px: f32, py: f32) -> f32 {
+    (b.x - a.x) * (py - a.y) - (b.y - a.y) * (px - a.x)
+}
+
+/// Whether pixel centers exactly on edge a->b belong to the triangle
+fn is_top_left(a: &ScreenVertex, b: &ScreenVertex) -> bool {
+    (a.y == b.y && b.x > a.x) || b.y < a.y
+}
+
+/// Clip rectangle in pixels, end exclusive
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Bounds {
+    fn of(rect: Rect2D) -> Self {
+        let x0 = rect.offset.x.max(0) as u32;
+        let y0 = rect.offset.y.max(0) as u32;
+        Self {
+            x0,
+            y0,
+            x1: (i64::from(rect.offset.x) + i64::from(rect.extent.width)).max(0) as u32,
+            y1: (i64::from(rect.offset.y) + i64::from(rect.extent.height)).max(0) as u32,
+        }
+    }
+
+    fn intersect(self, other: Self) -> Self {
+        Self {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        }
+    }
+}
+
+/// Attachments of the current render pass
+struct Pass {
+    colors: Vec<Arc<ImageStorage>>,
+    depth: Option<Arc<ImageStorage>>,
+    render_area: Rect2D,
+}
+
+/// Executes a recording against the registry
+struct Executor<'a> {
+    registry: &'a Registry,
+    pass: Option<Pass>,
+    pipeline: Option<Arc<GraphicsPipelineDescriptor>>,
+    viewport: Option<Viewport>,
+    scissor: Option<Rect2D>,
+    vertex_buffers: BTreeMap<u32, (usize, u64)>,
+    index_buffer: Option<(usize, u64, IndexType)>,
+}
+
+impl<'a> Executor<'a> {
+    fn new(registry: &'a Registry) -> Self {
+        Self {
+            registry,
+            pass: None,
+            pipeline: None,
+            viewport: None,
+            scissor: None,
+            vertex_buffers: BTreeMap::new(),
+            index_buffer: None,
+        }
+    }
+
+    fn run(&mut self, commands: &[Command]) -> Result<()> {
+        for command in commands {
+            self.execute(command)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, command: &Command) -> Result<()> {
+        match command {
+            Command::BeginRenderPass {
+                color_attachments,
+                depth_attachment,
+                render_area,
+            } => {
+                let mut colors = Vec::with_capacity(color_attachments.len());
+                for &(handle, load_op, clear_value) in color_attachments {
+                    let image = self.registry.image(handle)?;
+                    if load_op == LoadOp::Clear {
+                        // SAFETY: color attachments are cleared with a color
+                        let color = unsafe { clear_value.color };
+                        fill_color(&image, Bounds::of(*render_area), color);
+                    }
+                    colors.push(image);
+                }
+                let depth = match *depth_attachment {
+                    Some((handle, load_op, clear_value)) => {
+                        let image = self.registry.image(handle)?;
+                        if load_op == LoadOp::Clear {
+                            // SAFETY: depth attachments are cleared with a depth value
+                            let depth = unsafe { clear_value.depth_stencil.depth };
+                            fill_depth(&image, Bounds::of(*render_area), depth);
+                        }
+                        Some(image)
+                    }
+                    None => None,
+                };
+                self.pass = Some(Pass {
+                    colors,
+                    depth,
+                    render_area: *render_area,
+                });
+            }
+            Command::EndRenderPass => self.pass = None,
+            Command::BindPipeline(handle) => self.pipeline = Some(self.registry.pipeline(*handle)?),
+            Command::SetViewport(viewport) => self.viewport = Some(*viewport),
+            Command::SetScissor(scissor) => self.scissor = Some(*scissor),
+            Command::BindVertexBuffers {
+                first_binding,
+                buffers,
+            } => {
+                for (binding, &buffer) in (*first_binding..).zip(buffers) {
+                    self.vertex_buffers.insert(binding, buffer);
+                }
+            }
+            Command::BindIndexBuffer {
+                buffer,
+                offset,
+                index_type,
+            } => self.index_buffer = Some((*buffer, *offset, *index_type)),
+            Command::Draw(cmd) => {
+                let vertices = (cmd.first_vertex..cmd.first_vertex + cmd.vertex_count).collect();
+                self.draw(vertices, cmd.first_instance, cmd.instance_count)?;
+            }
+            Command::DrawIndexed(cmd) => self.draw_indexed(cmd)?,
+            Command::DrawIndirect {
+                buffer,
+                offset,
+                draw_count,
+                stride,
+                indexed,
+            } => {
+                let buffer = self.registry.buffer(*buffer)?;
+                let mut words = [0u32; 5];
+                for draw in 0..u64::from(*draw_count) {
+                    let start = (offset + draw * u64::from(*stride)) as usize;
+                    {
+                        let data = buffer.read();
+                        let len = if *indexed { 20 } else { 16 };
+                        let bytes = data
+                            .get(start..start + len)
+                            .ok_or(Error::InvalidParameter)?;
+                        for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+                            *word = u32::from_ne_bytes(chunk.try_into().unwrap());
+                        }
+                    }
+                    if *indexed {
+                        self.draw_indexed(&DrawIndexedCommand {
+                            index_count: words[0],
+                            instance_count: words[1],
+                            first_index: words[2],
+                            vertex_offset: words[3] as i32,
+                            first_instance: words[4],
+                        })?;
+                    } else {
+                        let vertices = (words[2]..words[2].saturating_add(words[0])).collect();
+                        self.draw(vertices, words[3], words[1])?;
+                    }
+                }
+            }
+            Command::Dispatch => return Err(Error::NotSupported),
+            Command::CopyBuffer { src, dst, regions } => {
+                let src = self.registry.buffer(*src)?;
+                let dst = self.registry.buffer(*dst)?;
+                for region in regions {
+                    let (start, size) = (region.src_offset as usize, region.size as usize);
+                    let bytes = src
+                        .read()
+                        .get(start..start + size)
+                        .ok_or(Error::InvalidParameter)?
+                        .to_vec();
+                    let start = region.dst_offset as usize;
+                    dst.write()
+                        .get_mut(start..start + size)
+                        .ok_or(Error::InvalidParameter)?
+                        .copy_from_slice(&bytes);
+                }
+            }
+            Command::CopyBufferToImage { src, dst, regions } => {
+                let src = self.registry.buffer(*src)?;
+                let dst = self.registry.image(*dst)?;
+                for region in regions {
+                    let (x, y, width, height) = image_region(&dst, region)?;
+                    let row_len = buffer_row_len(&dst, region);
+                    let src = src.read();
+                    let mut pixels = dst.data.write();
+                    for row in 0..height {
+                        let start = region.buffer_offset as usize + row as usize * row_len;
+                        let len = width as usize * dst.bytes_per_pixel;
+                        let line = src.get(start..start + len).ok_or(Error::InvalidParameter)?;
+                        let offset = dst.offset(x, y + row);
+                        pixels[offset..offset + len].copy_from_slice(line);
+                    }
+                }
+            }
+            Command::CopyImageToBuffer { src, dst, regions } => {
+                let src = self.registry.image(*src)?;
+                let dst = self.registry.buffer(*dst)?;
+                for region in regions {
+                    let (x, y, width, height) = image_region(&src, region)?;
+                    let row_len = buffer_row_len(&src, region);
+                    let pixels = src.data.read();
+                    let mut dst = dst.write();
+                    for row in 0..height {
+                        let start = region.buffer_offset as usize + row as usize * row_len;
+                        let len = width as usize * src.bytes_per_pixel;
+                        let offset = src.offset(x, y + row);
+                        dst.get_mut(start..start + len)
+                            .ok_or(Error::InvalidParameter)?
+                            .copy_from_slice(&pixels[offset..offset + len]);
+                    }
+                }
+            }
+            Command::CopyImage { src, dst, regions } => {
+                let src = self.registry.image(*src)?;
+                let dst = self.registry.image(*dst)?;
+                if src.bytes_per_pixel != dst.bytes_per_pixel {
+                    return Err(Error::InvalidParameter);
+                }
+                for region in regions {
+                    let (width, height) = (region.extent.width, region.extent.height);
+                    let src_rect = checked_rect(
+                        &src,
+                        region.src_offset.x,
+                        region.src_offset.y,
+                        width,
+                        height,
+                    )?;
+                    let dst_rect = checked_rect(
+                        &dst,
+                        region.dst_offset.x,
+                        region.dst_offset.y,
+                        width,
+                        height,
+                    )?;
+                    // Read everything first, src and dst may be the same image
+                    let len = width as usize * src.bytes_per_pixel;
+                    let rows: Vec<Vec<u8>> = {
+                        let pixels = src.data.read();
+                        (0..height)
+                            .map(|row| {
+                                let offset = src.offset(src_rect.0, src_rect.1 + row);
+                                pixels[offset..offset + len].to_vec()
+                            })
+                            .collect()
+                    };
+                    let mut pixels = dst.data.write();
+                    for (row, line) in (0..).zip(&rows) {
+                        let offset = dst.offset(dst_rect.0, dst_rect.1 + row);
+                        pixels[offset..offset + len].copy_from_slice(line);
+                    }
+                }
+            }
+            Command::BlitImage {
+                src,
+                dst,
+                regions,
+                filter,
+            } => {
+                let src = self.registry.image(*src)?;
+                let dst = self.registry.image(*dst)?;
+                if !is_color_target(src.format) || !is_color_target(dst.format) {
+                    return Err(Error::NotSupported);
+                }
+                for region in regions {
+                    blit(&src, &dst, region, *filter)?;
+                }
+            }
+            Command::ClearColorImage { image, color } => {
+                let image = self.registry.image(*image)?;
+                if !is_color_target(image.format) {
+                    return Err(Error::NotSupported);
+                }
+                // SAFETY: color images are cleared with a color
+                let color = unsafe { color.color };
+                fill_color(&image, Bounds::of(full_rect(&image)), color);
+            }
+            Command::ClearDepthImage {
+                image,
+                depth_stencil,
+            } => {
+                let image = self.registry.image(*image)?;
+                if !is_depth_target(image.format) {
+                    return Err(Error::NotSupported);
+                }
+                // SAFETY: depth images are cleared with a depth value
+                let depth = unsafe { depth_stencil.depth_stencil.depth };
+                fill_depth(&image, Bounds::of(full_rect(&image)), depth);
+            }
+            Command::WriteTimestamp { pool, query } => {
+                // Everything before ran already, commands execute in order
+                let timestamp = display::monotonic_ns().ok_or(Error::NotSupported)?;
+                let pool = self.registry.query_pool(*pool)?;
+                let mut results = pool.lock();
+                if let Some(result) = results.get_mut(*query as usize) {
+                    *result = Some(timestamp);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_indexed(&self, cmd: &DrawIndexedCommand) -> Result<()> {
+        let (handle, offset, index_type) = self
+            .index_buffer
+            .ok_or_else(|| Error::CommandBufferError(String::from("No index buffer bound")))?;
+        let buffer = self.registry.buffer(handle)?;
+        let data = buffer.read();
+        let size = match index_type {
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        };
+        let mut vertices = Vec::with_capacity(cmd.index_count as usize);
+        for i in 0..cmd.index_count as usize {
+            let start = offset as usize + (cmd.first_index as usize + i) * size;
+            let bytes = data
+                .get(start..start + size)
+                .ok_or(Error::InvalidParameter)?;
+            let index = match index_type {
+                IndexType::U16 => u32::from(u16::from_ne_bytes([bytes[0], bytes[1]])),
+                IndexType::U32 => u32::from_ne_bytes(bytes.try_into().unwrap()),
+            };
+            vertices.push(index.wrapping_add_signed(cmd.vertex_offset));
+        }
+        drop(data);
+        self.draw(vertices, cmd.first_instance, cmd.instance_count)
+    }
+
+    fn draw(&self, vertices: Vec<u32>, first_instance: u32, instance_count: u32) -> Result<()> {
+        let pass = self
+            .pass
+            .as_ref()
+            .ok_or_else(|| Error::CommandBufferError(String::from("Draw outside render pass")))?;
+        let pipeline = self
+            .pipeline
+            .as_ref()
+            .ok_or_else(|| Error::CommandBufferError(String::from("No pipeline bound")))?;
+
+        let render_area = Bounds::of(pass.render_area);
+        let viewport = self.viewport.unwrap_or(Viewport {
+            x: pass.render_area.offset.x as f32,
+            y: pass.render_area.offset.y as f32,
+            width: pass.render_area.extent.width as f32,
+            height: pass.render_area.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        });
+        let mut bounds =
+            render_area.intersect(Bounds::of(self.scissor.unwrap_or(pass.render_area)));
+        for image in pass.colors.iter().chain(&pass.depth) {
+            bounds = bounds.intersect(Bounds::of(full_rect(image)));
+        }
+
+        // Buffers may be bound to several bindings, read each once
+        let mut buffers = BTreeMap::new();
+        for &(handle, _) in self.vertex_buffers.values() {
+            if let alloc::collections::btree_map::Entry::Vacant(entry) = buffers.entry(handle) {
+                entry.insert(self.registry.buffer(handle)?);
+            }
+        }
+        let buffers: BTreeMap<usize, _> = buffers
+            .iter()
+            .map(|(&handle, data)| (handle, data.read()))
+            .collect();
+
+        let fetch = |location: u32, vertex: u32, instance: u32| -> Result<Option<[f32; 4]>> {
+            let Some(attribute) = pipeline
+                .vertex_attributes
+                .iter()
+                .find(|a| a.location == location)
+            else {
+                return Ok(None);
+            };
+            let binding = pipeline
+                .vertex_bindings
+                .iter()
+                .find(|b| b.binding == attribute.binding)
+                .ok_or(Error::InvalidParameter)?;
+            let &(handle, offset) = self.vertex_buffers.get(&binding.binding).ok_or_else(|| {
+                Error::CommandBufferError(alloc::format!(
+                    "No vertex buffer bound at binding {}",
+                    binding.binding
+                ))
+            })?;
+            let index = match binding.input_rate {
+                VertexInputRate::Vertex => vertex,
+                VertexInputRate::Instance => instance,
+            };
+            let start = offset as usize
+                + index as usize * binding.stride as usize
+                + attribute.offset as usize;
+            let value = buffers[&handle]
+                .get(start..)
+                .and_then(|data| fetch_attribute(attribute.format, data))
+                .ok_or(Error::InvalidParameter)?;
+            Ok(Some(value))
+        };
+
+        let half_width = viewport.width / 2.0;
+        let half_height = viewport.height / 2.0;
+        for instance in first_instance..first_instance + instance_count {
+            let mut screen = Vec::with_capacity(vertices.len());
+            for &vertex in &vertices {
+                let position = fetch(0, vertex, instance)?.unwrap_or_default();
+                let color = fetch(1, vertex, instance)?.unwrap_or([1.0; 4]);
+                let w = position[3];
+                if w <= f32::EPSILON {
+                    screen.push(None);
+                    continue;
+                }
+                let inv_w = 1.0 / w;
+                screen.push(Some(ScreenVertex {
+                    x: viewport.x + (position[0] * inv_w + 1.0) * half_width,
+                    y: viewport.y + (position[1] * inv_w + 1.0) * half_height,
+                    z: viewport.min_depth
+                        + position[2] * inv_w * (viewport.max_depth - viewport.min_depth),
+                    inv_w,
+                    color: color.map(|c| c * inv_w),
+                }));
+            }
+
+            for [a, b, c] in triangles(pipeline.topology, screen.len()) {
+                if let (Some(v0), Some(v1), Some(v2)) = (screen[a], screen[b], screen[c]) {
+                    rasterize(pipeline, pass, bounds, [v0, v1, v2]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Vertex indices of the triangles of a primitive stream, every other strip
+/// triangle reordered so that all keep the winding of the first
+fn triangles(topology: PrimitiveTopology, count: usize) -> Vec<[usize; 3]> {
+    match topology {
+        PrimitiveTopology::TriangleList => (0..count / 3)
+            .map(|t| [t * 3, t * 3 + 1, t * 3 + 2])
+            .collect(),
+        PrimitiveTopology::TriangleStrip => (0..count.saturating_sub(2))
+            .map(|t| {
+                if t % 2 == 0 {
+                    [t, t + 1, t + 2]
+                } else {
+                    [t + 1, t, t + 2]
+                }
+            })
+            .collect(),
+        PrimitiveTopology::TriangleFan => (1..count.saturating_sub(1))
+            .map(|t| [t, t + 1, 0])
+            .collect(),
+        // Rejected when the pipeline is created
+        _ => Vec::new(),
+    }
+}
+
+/// Rasterize one triangle into the attachments of `pass`
+fn rasterize(
+    pipeline: &GraphicsPipelineDescriptor,
+    pass: &Pass,
+    bounds: Bounds,
+    [v0, mut v1, mut v2]: [ScreenVertex; 3],
+) {
+    let area = edge(&v0, &v1, v2.x, v2.y);
+    if area == 0.0 || area.is_nan() {
+        return;
+    }
+    // Vulkan's signed area has the opposite sign of `edge` with y down
+    let counter_clockwise = area < 0.0;
+    let front = counter_clockwise == (pipeline.front_face == FrontFace::CounterClockwise);
+    let culled = match pipeline.cull_mode {
+        CullMode::None => false,
+        CullMode::Front => front,
+        CullMode::Back => !front,
+        CullMode::FrontAndBack => true,
+    };
+    if culled {
+        return;
+    }
+    // Wind the triangle so that the inside is where every edge is positive
+    let area = if area < 0.0 {
+        core::mem::swap(&mut v1, &mut v2);
+        -area
+    } else {
+        area
+    };
+
+    let min_x = v0.x.min(v1.x).min(v2.x).max(bounds.x0 as f32);
+    let min_y = v0.y.min(v1.y).min(v2.y).max(bounds.y0 as f32);
+    let max_x = v0.x.max(v1.x).max(v2.x).min(bounds.x1 as f32);
+    let max_y = v0.y.max(v1.y).max(v2.y).min(bounds.y1 as f32);
+    if min_x >= max_x || min_y >= max_y {
+        return;
+    }
+    let (x0, y0) = (min_x as u32, min_y as u32);
+    let x1 = (max_x as u32 + 1).min(bounds.x1);
+    let y1 = (max_y as u32 + 1).min(bounds.y1);
+
+    let edges = [(v1, v2), (v2, v0), (v0, v1)];
+    let top_left = edges.map(|(a, b)| is_top_left(&a, &b));
+    let default_blend = ColorBlendAttachment::default();
+
+    let mut colors: Vec<_> = pass.colors.iter().map(|image| image.data.write()).collect();
+    let mut depth = pass.depth.as_ref().map(|image| (image, image.data.write()));
+
+    for y in y0..y1 {
+        let py = y as f32 + 0.5;
+        for x in x0..x1 {
+            let px = x as f32 + 0.5;
+            let weights = edges.map(|(a, b)| edge(&a, &b, px, py));
+            let inside = weights
+                .iter()
+                .zip(&top_left)
+                .all(|(&w, &top_left)| w > 0.0 || (w == 0.0 && top_left));
+            if !inside {
+                continue;
+            }
+            let [b0, b1, b2] = weights.map(|w| w / area);
+
+            let z = b0 * v0.z + b1 * v1.z + b2 * v2.z;
+            if !(0.0..=1.0).contains(&z) {
+                continue;
+            }
+            if let Some((image, data)) = depth.as_mut() {
+                let offset = image.offset(x, y);
+                let pixel = &mut data[offset..offset + image.bytes_per_pixel];
+                if pipeline.depth_test {
+                    if !compare(pipeline.depth_compare, z, read_depth(image.format, pixel)) {
+                        continue;
+                    }
+                    if pipeline.depth_write {
+                        write_depth(image.format, z, pixel);
+                    }
+                }
+            }
+
+            let inv_w = b0 * v0.inv_w + b1 * v1.inv_w + b2 * v2.inv_w;
+            let color: [f32; 4] = core::array::from_fn(|i| {
+                (b0 * v0.color[i] + b1 * v1.color[i] + b2 * v2.color[i]) / inv_w
+            });
+            for (attachment, (image, data)) in pass.colors.iter().zip(colors.iter_mut()).enumerate()
+            {
+                let state = pipeline
+                    .blend_attachments
+                    .get(attachment)
+                    .unwrap_or(&default_blend);
+                let offset = image.offset(x, y);
+                let pixel = &mut data[offset..offset + image.bytes_per_pixel];
+                let out = blend(state, color, read_color(image.format, pixel));
+                write_color(image.format, out, state.write_mask, pixel);
+            }
+        }
+    }
+}
+
+fn full_rect(image: &ImageStorage) -> Rect2D {
+    Rect2D::new(0, 0, image.extent.width, image.extent.height)
+}
+
+fn fill_color(image: &ImageStorage, bounds: Bounds, color: crate::ClearColor) {
+    let bounds = bounds.intersect(Bounds::of(full_rect(image)));
+    let color = [color.r, color.g, color.b, color.a];
+    let mut data = image.data.write();
+    for y in bounds.y0..bounds.y1 {
+        for x in bounds.x0..bounds.x1 {
+            let offset = image.offset(x, y);
+            let pixel = &mut data[offset..offset + image.bytes_per_pixel];
+            write_color(image.format, color, ColorWriteMask::all(), pixel);
+        }
+    }
+}
+
+fn fill_depth(image: &ImageStorage, bounds: Bounds, depth: f32) {
+    let bounds = bounds.intersect(Bounds::of(full_rect(image)));
+    let mut data = image.data.write();
+    for y in bounds.y0..bounds.y1 {
+        for x in bounds.x0..bounds.x1 {
+            let offset = image.offset(x, y);
+            write_depth(
+                image.format,
+                depth,
+                &mut data[offset..offset + image.bytes_per_pixel],
+            );
+        }
+    }
+}
+
+/// Origin of a `width` x `height` rectangle at (`x`, `y`), if it lies within
+/// the image
+fn checked_rect(
+    image: &ImageStorage,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Result<(u32, u32)> {
+    let (x, y) = (
+        u32::try_from(x).map_err(|_| Error::InvalidParameter)?,
+        u32::try_from(y).map_err(|_| Error::InvalidParameter)?,
+    );
+    if u64::from(x) + u64::from(width) > u64::from(image.extent.width)
+        || u64::from(y) + u64::from(height) > u64::from(image.extent.height)
+    {
+        return Err(Error::InvalidParameter);
+    }
+    Ok((x, y))
+}
+
+fn image_region(image: &ImageStorage, region: &BufferImageCopy) -> Result<(u32, u32, u32, u32)> {
+    let (width, height) = (region.image_extent.width, region.image_extent.height);
+    let (x, y) = checked_rect(
+        image,
+        region.image_offset.x,
+        region.image_offset.y,
+        width,
+        height,
+    )?;
+    Ok((x, y, width, height))
+}
+
+/// Bytes between rows in the buffer, a zero row length means tightly packed
+fn buffer_row_len(image: &ImageStorage, region: &BufferImageCopy) -> usize {
+    let row_length = if region.buffer_row_length == 0 {
+        region.image_extent.width
+    } else {
+        region.buffer_row_length
+    };
+    row_length as usize * image.bytes_per_pixel
+}
+
+fn blit(src: &ImageStorage, dst: &ImageStorage, region: &ImageBlit, filter: Filter) -> Result<()> {
+    let [s0, s1] = region.src_offsets;
+    let [d0, d1] = region.dst_offsets;
+    let src_width = (s1.x - s0.x) as f32;
+    let src_height = (s1.y - s0.y) as f32;
+    let dst_bounds = Bounds {
+        x0: d0.x.min(d1.x).max(0) as u32,
+        y0: d0.y.min(d1.y).max(0) as u32,
+        x1: d0.x.max(d1.x).max(0) as u32,
+        y1: d0.y.max(d1.y).max(0) as u32,
+    }
+    .intersect(Bounds::of(full_rect(dst)));
+    if d0.x == d1.x || d0.y == d1.y {
+        return Ok(());
+    }
+
+    // Sample a copy, src and dst may be the same image
+    let pixels = src.data.read().clone();
+    let texel = |x: i64, y: i64| {
+        let x = x.clamp(0, i64::from(src.extent.width) - 1) as u32;
+        let y = y.clamp(0, i64::from(src.extent.height) - 1) as u32;
+        let offset = src.offset(x, y);
+        read_color(src.format, &pixels[offset..offset + src.bytes_per_pixel])
+    };
+
+    let mut data = dst.data.write();
+    for y in dst_bounds.y0..dst_bounds.y1 {
+        // Position of the pixel center in the source, mirrored regions flip
+        let v = (y as f32 + 0.5 - d0.y as f32) / (d1.y - d0.y) as f32;
+        let sy = s0.y as f32 + v * src_height;
+        for x in dst_bounds.x0..dst_bounds.x1 {
+            let u = (x as f32 + 0.5 - d0.x as f32) / (d1.x - d0.x) as f32;
+            let sx = s0.x as f32 + u * src_width;
+            let color = match filter {
+                Filter::Nearest => texel(floor(sx), floor(sy)),
+                Filter::Linear => {
+                    let (fx, fy) = (sx - 0.5, sy - 0.5);
+                    let (ix, iy) = (floor(fx), floor(fy));
+                    let (tx, ty) = (fx - ix as f32, fy - iy as f32);
+                    let (c00, c10) = (texel(ix, iy), texel(ix + 1, iy));
+                    let (c01, c11) = (texel(ix, iy + 1), texel(ix + 1, iy + 1));
+                    core::array::from_fn(|i| {
+                        let top = c00[i] + (c10[i] - c00[i]) * tx;
+                        let bottom = c01[i] + (c11[i] - c01[i]) * tx;
+                        top + (bottom - top) * ty
+                    })
+                }
+            };
+            let offset = dst.offset(x, y);
+            let pixel = &mut data[offset..offset + dst.bytes_per_pixel];
+            write_color(dst.format, color, ColorWriteMask::all(), pixel);
+        }
+    }
+    Ok(())
+}
+
+/// `f32::floor` is not available without std
+fn floor(value: f32) -> i64 {
+    let truncated = value as i64;
+    if (truncated as f32) > value {
+        truncated - 1
+    } else {
+        truncated
+    }
+}
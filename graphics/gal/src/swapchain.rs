@@ -0,0 +1,403 @@
+//! Presentation to a display
+//!
+//! [`Swapchain`] rotates a set of images between the application and a
+//! [`DisplayTarget`]: an image is acquired, rendered to, presented, queued
+//! and flipped to, shown until the display moves on to the next one, and
+//! then available again.
+//!
+//! Queued images are flipped to one per vertical blank. The swapchain
+//! notices a completed flip whenever it is used; compositors that want
+//! queued images flipped without acquiring call [`Swapchain::update`] on
+//! every vertical blank.
+//!
+//! Presents can carry the regions that changed since the previous present,
+//! which are passed on to the display target so it only updates those, and
+//! a callback told whether and when the image was shown, for applications
+//! pacing their frames. Callbacks run on the thread using the swapchain
+//! once it notices the outcome, never with the swapchain locked.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use spin::{Mutex, MutexGuard};
+
+use crate::device::SwapchainConfig;
+use crate::display::{DisplayTarget, PresentMode};
+use crate::{Error, Extent2D, Fence, Image, Rect2D, Result, Semaphore};
+
+/// What became of a presented image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentOutcome {
+    /// The image reached the screen
+    Presented {
+        /// When, in nanoseconds of `CLOCK_MONOTONIC`, if the display
+        /// target reports it
+        timestamp_ns: Option<u64>,
+    },
+    /// The image was replaced by a newer one, or the swapchain destroyed,
+    /// before it was shown
+    Discarded,
+}
+
+/// Feedback for one present
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentFeedback {
+    /// ID returned when the image was presented
+    pub present_id: u64,
+    /// Whether and when the image was shown
+    pub outcome: PresentOutcome,
+    /// Refresh interval of the display in nanoseconds, 0 if unknown
+    pub refresh_ns: u64,
+}
+
+/// Callback receiving the feedback of one present
+pub type PresentCallback = Box<dyn FnOnce(PresentFeedback) + Send>;
+
+/// How to present an image
+#[derive(Default)]
+pub struct PresentRequest<'a> {
+    /// Semaphores signaled once rendering to the image finished
+    pub wait_semaphores: &'a [&'a dyn Semaphore],
+    /// Regions that changed since the previously presented image, empty if
+    /// all of it may have
+    pub damage: &'a [Rect2D],
+    /// Called with the feedback of this present
+    pub feedback: Option<PresentCallback>,
+}
+
+/// A presented image on its way to the screen
+struct Presentation {
+    id: u64,
+    /// Changed regions, empty for the whole image
+    damage: Vec<Rect2D>,
+    feedback: Option<PresentCallback>,
+}
+
+/// Where an image is in the presentation cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageState {
+    /// Free to be acquired
+    Available,
+    /// Owned by the application
+    Acquired,
+    /// Presented, waiting for its flip
+    Queued,
+    /// Flipped to, the display still shows the previous image
+    Flipping,
+    /// Scanned out by the display
+    OnScreen,
+}
+
+struct State {
+    images: Vec<ImageState>,
+    /// Presented images in flip order
+    queue: VecDeque<(u32, Presentation)>,
+    /// Presentation of the image being flipped to
+    flipping: Option<Presentation>,
+    next_present_id: u64,
+    /// Feedback to deliver once the state is unlocked
+    feedback: Vec<(PresentCallback, PresentFeedback)>,
+}
+
+impl State {
+    fn find(&self, wanted: ImageState) -> Option<u32> {
+        self.images
+            .iter()
+            .position(|&state| state == wanted)
+            .map(|index| index as u32)
+    }
+}
+
+/// Images presented on a display target
+///
+/// Dropping the swapchain releases its images, including the one on
+/// screen; flip the display to another image first.
+pub struct Swapchain {
+    target: Arc<dyn DisplayTarget>,
+    present_mode: PresentMode,
+    extent: Extent2D,
+    refresh_ns: u64,
+    images: Vec<Box<dyn Image>>,
+    state: Mutex<State>,
+}
+
+impl Swapchain {
+    /// Create a swapchain presenting on `target`
+    ///
+    /// A zero extent in `config` selects the display's current resolution.
+    /// The buffer count is raised to what the present mode needs.
+    pub fn new(target: Arc<dyn DisplayTarget>, config: &SwapchainConfig) -> Result<Self> {
+        let info = target.info();
+        if config.display_id != info.id {
+            return Err(Error::InvalidParameter);
+        }
+        if !target.supports_present_mode(config.present_mode)
+            || !target.formats().contains(&config.format)
+        {
+            return Err(Error::NotSupported);
+        }
+
+        let extent = if config.extent == Extent2D::default() {
+            info.extent
+        } else {
+            config.extent
+        };
+        let image_count = config
+            .buffer_count
+            .max(config.present_mode.min_image_count());
+        let mut images = Vec::with_capacity(image_count as usize);
+        for _ in 0..image_count {
+            match target.create_image(extent, config.format) {
+                Ok(image) => images.push(image),
+                Err(err) => {
+                    for image in images {
+                        target.destroy_image(image);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(Self {
+            target,
+            present_mode: config.present_mode,
+            extent,
+            refresh_ns: match info.refresh_rate {
+                0 => 0,
+                hz => 1_000_000_000 / u64::from(hz),
+            },
+            images,
+            state: Mutex::new(State {
+                images: vec![ImageState::Available; image_count as usize],
+                queue: VecDeque::new(),
+                flipping: None,
+                next_present_id: 1,
+                feedback: Vec::new(),
+            }),
+        })
+    }
+
+    /// Get the present mode
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// Get the display target presented on
+    pub fn target(&self) -> &Arc<dyn DisplayTarget> {
+        &self.target
+    }
+
+    /// Retire a completed flip and flip to the next queued image
+    pub fn update(&self) -> Result<()> {
+        let mut state = self.state.lock();
+        let result = self.advance(&mut state);
+        self.deliver(state);
+        result
+    }
+
+    fn advance(&self, state: &mut State) -> Result<()> {
+        if let Some(flipping) = state.find(ImageState::Flipping) {
+            if self.target.flip_pending() {
+                return Ok(());
+            }
+            if let Some(shown) = state.find(ImageState::OnScreen) {
+                state.images[shown as usize] = ImageState::Available;
+            }
+            state.images[flipping as usize] = ImageState::OnScreen;
+            if let Some(presentation) = state.flipping.take() {
+                let timestamp_ns = self.target.flip_timestamp_ns();
+                self.finish(
+                    state,
+                    presentation,
+                    PresentOutcome::Presented { timestamp_ns },
+                );
+            }
+        }
+
+        if let Some((next, presentation)) = state.queue.pop_front() {
+            // Damage is relative to the previous present, which is only
+            // what the screen shows if it came from this swapchain
+            let damage = if state.find(ImageState::OnScreen).is_some() {
+                presentation.damage.as_slice()
+            } else {
+                &[]
+            };
+            if let Err(err) = self
+                .target
+                .flip(self.images[next as usize].as_ref(), damage)
+            {
+                state.images[next as usize] = ImageState::Available;
+                self.finish(state, presentation, PresentOutcome::Discarded);
+                return Err(err);
+            }
+            state.images[next as usize] = ImageState::Flipping;
+            state.flipping = Some(presentation);
+        }
+        Ok(())
+    }
+
+    /// Queue the feedback of a presentation for delivery
+    fn finish(&self, state: &mut State, presentation: Presentation, outcome: PresentOutcome) {
+        if let Some(callback) = presentation.feedback {
+            let feedback = PresentFeedback {
+                present_id: presentation.id,
+                outcome,
+                refresh_ns: self.refresh_ns,
+            };
+            state.feedback.push((callback, feedback));
+        }
+    }
+
+    /// Unlock the state and run the feedback callbacks queued while it was
+    /// locked, so that they can use the swapchain
+    fn deliver(&self, mut state: MutexGuard<State>) {
+        let feedback = core::mem::take(&mut state.feedback);
+        drop(state);
+        for (callback, feedback) in feedback {
+            callback(feedback);
+        }
+    }
+
+    /// Clip damage to the images, empty for the whole image
+    fn clip_damage(&self, damage: &[Rect2D]) -> Vec<Rect2D> {
+        let (width, height) = (i64::from(self.extent.width), i64::from(self.extent.height));
+        damage
+            .iter()
+            .filter_map(|rect| {
+                let x0 = i64::from(rect.offset.x).clamp(0, width);
+                let y0 = i64::from(rect.offset.y).clamp(0, height);
+                let x1 = (i64::from(rect.offset.x) + i64::from(rect.extent.width)).clamp(0, width);
+                let y1 =
+                    (i64::from(rect.offset.y) + i64::from(rect.extent.height)).clamp(0, height);
+                (x0 < x1 && y0 < y1)
+                    .then(|| Rect2D::new(x0 as i32, y0 as i32, (x1 - x0) as u32, (y1 - y0) as u32))
+            })
+            .collect()
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        let state = self.state.get_mut();
+        let queued = core::mem::take(&mut state.queue);
+        let flipping = state.flipping.take();
+        for presentation in queued.into_iter().map(|(_, p)| p).chain(flipping) {
+            if let Some(callback) = presentation.feedback {
+                callback(PresentFeedback {
+                    present_id: presentation.id,
+                    outcome: PresentOutcome::Discarded,
+                    refresh_ns: self.refresh_ns,
+                });
+            }
+        }
+
+        for image in self.images.drain(..) {
+            self.target.destroy_image(image);
+        }
+    }
+}
+
+impl crate::device::Swapchain for Swapchain {
+    fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    fn buffer_count(&self) -> u32 {
+        self.images.len() as u32
+    }
+
+    /// Acquire an available image, waiting for vertical blanks until the
+    /// display releases one
+    ///
+    /// `timeout_ns` bounds each wait for a vertical blank, zero only
+    /// returns an already available image. Fails with
+    /// [`Error::ResourceInUse`] if the application holds every image the
+    /// display could release.
+    fn acquire_next_image(
+        &self,
+        timeout_ns: u64,
+        semaphore: Option<&dyn Semaphore>,
+        fence: Option<&dyn Fence>,
+    ) -> Result<u32> {
+        loop {
+            {
+                let mut state = self.state.lock();
+                if let Err(err) = self.advance(&mut state) {
+                    self.deliver(state);
+                    return Err(err);
+                }
+
+                if let Some(index) = state.find(ImageState::Available) {
+                    state.images[index as usize] = ImageState::Acquired;
+                    self.deliver(state);
+                    self.target.signal(semaphore, fence)?;
+                    return Ok(index);
+                }
+                let stuck = state.queue.is_empty() && state.find(ImageState::Flipping).is_none();
+                self.deliver(state);
+                if stuck {
+                    return Err(Error::ResourceInUse);
+                }
+            }
+
+            if timeout_ns == 0 || !self.target.wait_vblank(timeout_ns)? {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    fn image(&self, index: u32) -> &dyn Image {
+        self.images[index as usize].as_ref()
+    }
+
+    /// Queue an acquired image for display once the request's wait
+    /// semaphores are signaled
+    ///
+    /// In mailbox mode, images still queued are replaced and reported as
+    /// discarded; the damage they carried is added to this image's, as
+    /// the screen never showed it.
+    fn present_with(&self, image_index: u32, request: PresentRequest) -> Result<u64> {
+        let index = image_index as usize;
+        if self.state.lock().images.get(index) != Some(&ImageState::Acquired) {
+            return Err(Error::InvalidParameter);
+        }
+
+        self.target.wait_semaphores(request.wait_semaphores)?;
+
+        // An empty list stands for the whole image, and stays that way
+        let mut damage = self.clip_damage(request.damage);
+        let whole = damage.is_empty();
+
+        let mut state = self.state.lock();
+        if self.present_mode == PresentMode::Mailbox {
+            // Replaced before they were shown
+            while let Some((replaced, presentation)) = state.queue.pop_front() {
+                state.images[replaced as usize] = ImageState::Available;
+                if !whole && !damage.is_empty() && !presentation.damage.is_empty() {
+                    damage.extend_from_slice(&presentation.damage);
+                } else {
+                    damage.clear();
+                }
+                self.finish(&mut state, presentation, PresentOutcome::Discarded);
+            }
+        }
+
+        let id = state.next_present_id;
+        state.next_present_id += 1;
+        state.images[index] = ImageState::Queued;
+        state.queue.push_back((
+            image_index,
+            Presentation {
+                id,
+                damage,
+                feedback: request.feedback,
+            },
+        ));
+        let result = self.advance(&mut state);
+        self.deliver(state);
+        result.map(|()| id)
+    }
+}
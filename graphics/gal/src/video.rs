@@ -0,0 +1,260 @@
+//! Video decode
+//!
+//! Fixed-function decoders turn a compressed bitstream into pictures in
+//! multi-planar YUV images. As with Vulkan Video and VA-API, the caller
+//! parses the sequence, picture and slice headers and keeps the decoded
+//! picture buffer (DPB): each decode gets the picture's slice data, the
+//! parameters the hardware needs from the headers, and the earlier pictures
+//! it references. Devices offer decoding through [`VideoDecode`], found
+//! with [`Device::video_decode`](crate::Device::video_decode).
+
+use alloc::boxed::Box;
+
+use crate::{Error, Extent2D, Image, ImageFormat, Result};
+
+/// Compression standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    /// H.264 / AVC
+    H264,
+    /// H.265 / HEVC
+    H265,
+}
+
+/// Codec profile a decoder is created for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoProfile {
+    H264Baseline,
+    H264Main,
+    H264High,
+    H265Main,
+    /// 10-bit H.265, decoded to P010
+    H265Main10,
+}
+
+impl VideoProfile {
+    pub fn codec(&self) -> VideoCodec {
+        match self {
+            VideoProfile::H264Baseline | VideoProfile::H264Main | VideoProfile::H264High => {
+                VideoCodec::H264
+            }
+            VideoProfile::H265Main | VideoProfile::H265Main10 => VideoCodec::H265,
+        }
+    }
+
+    /// Format decoded pictures are written in
+    pub fn output_format(&self) -> ImageFormat {
+        match self {
+            VideoProfile::H265Main10 => ImageFormat::P010,
+            _ => ImageFormat::Nv12,
+        }
+    }
+}
+
+/// Limits of a device's decoder for one profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoDecodeCapabilities {
+    /// Largest picture
+    pub max_extent: Extent2D,
+    /// Highest level, as `level_idc` (H.264) or `general_level_idc` (H.265)
+    pub max_level: u32,
+    /// Most pictures one picture may reference
+    pub max_references: u32,
+}
+
+/// Parameters of a decoder session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoDecoderDescriptor {
+    pub profile: VideoProfile,
+    /// Largest picture of the stream
+    pub max_extent: Extent2D,
+    /// Most pictures one picture of the stream references
+    pub max_references: u32,
+}
+
+impl VideoDecoderDescriptor {
+    /// Check the descriptor against a device's capabilities for its profile
+    pub fn check(&self, capabilities: &VideoDecodeCapabilities) -> Result<()> {
+        if self.max_extent.width == 0
+            || self.max_extent.height == 0
+            || self.max_extent.width > capabilities.max_extent.width
+            || self.max_extent.height > capabilities.max_extent.height
+            || self.max_references > capabilities.max_references
+        {
+            return Err(Error::NotSupported);
+        }
+        Ok(())
+    }
+}
+
+/// H.264 picture parameters, from the SPS, PPS and first slice header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H264PictureParams {
+    pub level_idc: u8,
+    pub log2_max_frame_num_minus4: u8,
+    pub pic_order_cnt_type: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub max_num_ref_frames: u8,
+    pub frame_mbs_only_flag: bool,
+    pub mb_adaptive_frame_field_flag: bool,
+    pub direct_8x8_inference_flag: bool,
+    pub entropy_coding_mode_flag: bool,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_idc: u8,
+    pub transform_8x8_mode_flag: bool,
+    pub constrained_intra_pred_flag: bool,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub pic_init_qp_minus26: i8,
+    pub chroma_qp_index_offset: i8,
+    pub second_chroma_qp_index_offset: i8,
+    pub frame_num: u16,
+    /// Picture order counts of the top and bottom field
+    pub field_order_cnt: [i32; 2],
+    pub idr: bool,
+    /// The picture is referenced by later ones
+    pub reference: bool,
+}
+
+/// H.265 picture parameters, from the SPS, PPS and first slice header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct H265PictureParams {
+    pub general_level_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_min_luma_coding_block_size_minus3: u8,
+    pub log2_diff_max_min_luma_coding_block_size: u8,
+    pub log2_min_transform_block_size_minus2: u8,
+    pub log2_diff_max_min_transform_block_size: u8,
+    pub max_transform_hierarchy_depth_inter: u8,
+    pub max_transform_hierarchy_depth_intra: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub amp_enabled_flag: bool,
+    pub sample_adaptive_offset_enabled_flag: bool,
+    pub strong_intra_smoothing_enabled_flag: bool,
+    pub sign_data_hiding_enabled_flag: bool,
+    pub cu_qp_delta_enabled_flag: bool,
+    pub diff_cu_qp_delta_depth: u8,
+    pub transform_skip_enabled_flag: bool,
+    pub tiles_enabled_flag: bool,
+    pub entropy_coding_sync_enabled_flag: bool,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_flag: bool,
+    pub init_qp_minus26: i8,
+    pub pps_cb_qp_offset: i8,
+    pub pps_cr_qp_offset: i8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    /// NAL unit type of the picture's slices
+    pub nal_unit_type: u8,
+    pub pic_order_cnt: i32,
+    /// Intra random access point, which starts with an empty DPB
+    pub irap: bool,
+}
+
+/// Codec specific parameters of a picture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PictureParams {
+    H264(H264PictureParams),
+    H265(H265PictureParams),
+}
+
+impl PictureParams {
+    pub fn codec(&self) -> VideoCodec {
+        match self {
+            PictureParams::H264(_) => VideoCodec::H264,
+            PictureParams::H265(_) => VideoCodec::H265,
+        }
+    }
+}
+
+/// Earlier picture a picture references
+#[derive(Clone, Copy)]
+pub struct ReferencePicture<'a> {
+    /// Image the picture was decoded into
+    pub image: &'a dyn Image,
+    /// `FrameNum` (H.264) of the picture, 0 for H.265
+    pub frame_num: u16,
+    /// Picture order count, of the top field for H.264
+    pub pic_order_cnt: i32,
+    pub long_term: bool,
+}
+
+/// One picture to decode
+#[derive(Clone, Copy)]
+pub struct DecodePicture<'a> {
+    /// Slice NAL units of the picture, with Annex B start codes
+    pub bitstream: &'a [u8],
+    /// Size of the picture, at most the session's largest
+    pub extent: Extent2D,
+    pub params: PictureParams,
+    /// Image the picture is decoded into, created by
+    /// [`VideoDecoder::create_picture`]
+    pub output: &'a dyn Image,
+    /// Pictures in the DPB, in the order the slice headers index them
+    pub references: &'a [ReferencePicture<'a>],
+}
+
+/// Decoder session for one stream
+pub trait VideoDecoder: Send + Sync {
+    /// Parameters the session was created with
+    fn descriptor(&self) -> &VideoDecoderDescriptor;
+
+    /// Allocate an image pictures can be decoded into, of the session's
+    /// largest extent and output format
+    fn create_picture(&self) -> Result<Box<dyn Image>>;
+
+    /// Free an image of [`VideoDecoder::create_picture`]
+    fn destroy_picture(&self, image: Box<dyn Image>);
+
+    /// Queue `picture` for decoding, returning a number to wait for with
+    /// [`VideoDecoder::wait`]
+    ///
+    /// Decodes of one session complete in the order they were queued.
+    fn decode(&self, picture: &DecodePicture<'_>) -> Result<u64>;
+
+    /// Wait until decode `submission` has written its picture, returns
+    /// false on timeout
+    fn wait(&self, submission: u64, timeout_ns: u64) -> Result<bool>;
+}
+
+/// Video decode extension of a device
+pub trait VideoDecode: Send + Sync {
+    /// Limits of the decoder for `profile`, `None` if it can't decode it
+    fn decode_capabilities(&self, profile: VideoProfile) -> Option<VideoDecodeCapabilities>;
+
+    /// Create a decoder session
+    fn create_video_decoder(
+        &self,
+        descriptor: &VideoDecoderDescriptor,
+    ) -> Result<Box<dyn VideoDecoder>>;
+}
+
+/// Check that `picture` can be decoded by a session created with
+/// `descriptor`
+pub fn check_picture(
+    descriptor: &VideoDecoderDescriptor,
+    picture: &DecodePicture<'_>,
+) -> Result<()> {
+    if picture.params.codec() != descriptor.profile.codec() || picture.bitstream.is_empty() {
+        return Err(Error::InvalidParameter);
+    }
+    if picture.references.len() > descriptor.max_references as usize
+        || picture.extent.width > descriptor.max_extent.width
+        || picture.extent.height > descriptor.max_extent.height
+    {
+        return Err(Error::InvalidParameter);
+    }
+    let format = descriptor.profile.output_format();
+    let images = core::iter::once(picture.output).chain(picture.references.iter().map(|r| r.image));
+    for image in images {
+        let extent = image.extent_2d();
+        if image.format() != format
+            || extent.width < picture.extent.width
+            || extent.height < picture.extent.height
+        {
+            return Err(Error::InvalidParameter);
+        }
+    }
+    Ok(())
+}
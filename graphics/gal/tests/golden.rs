@@ -0,0 +1,552 @@
+//! Golden-image tests of the software rasterizer
+//!
+//! Every scene is recorded through the generic [`CommandBuffer`] interface,
+//! rendered by [`SoftwareDevice`] and read back. The FNV-1a hash of the
+//! pixels has to match the golden hash checked in next to the scene, so a
+//! change in command recording, triangle setup, rasterization rules, depth
+//! testing, blending or blits shows up here before it reaches a driver.
+//!
+//! A few pixels of every scene are also checked by value, so a golden hash
+//! can only be updated to an image that still looks right. When a change of
+//! the output is intended, the failure message prints the new hash.
+//!
+//! The software device has no shader compiler, sampler or compute support,
+//! so textured quads are drawn with filtered blits and there are no compute
+//! scenes yet.
+
+use gal::command::{
+    BufferImageCopy, ColorAttachment, DepthStencilAttachment, DrawIndexedCommand, Filter,
+    ImageAspect, ImageBlit, ImageSubresourceLayers, IndexType, LoadOp, RenderPassDescriptor,
+    StoreOp,
+};
+use gal::device::{
+    BlendFactor, ColorBlendAttachment, CompareOp, CullMode, FrontFace, GraphicsPipelineDescriptor,
+    PrimitiveTopology, VertexAttribute, VertexBinding, VertexFormat, VertexInputRate,
+};
+use gal::{
+    Buffer, BufferDescriptor, BufferUsage, ClearColor, ClearDepthStencil, ClearValue,
+    CommandBuffer, Device, DrawCommand, Extent2D, Extent3D, Image, ImageDescriptor, ImageFormat,
+    MemoryType, Offset3D, Pipeline, Rect2D, SoftwareDevice, SubmitInfo, Viewport,
+};
+
+/// Width and height of every scene
+const SIZE: u32 = 64;
+
+/// Clip-space position and color of a vertex
+type Vertex = ([f32; 4], [f32; 4]);
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+fn device() -> SoftwareDevice {
+    SoftwareDevice::new(Extent2D::new(SIZE, SIZE))
+}
+
+fn color_target(device: &SoftwareDevice) -> Box<dyn Image> {
+    device
+        .create_image(&ImageDescriptor::render_target(
+            SIZE,
+            SIZE,
+            ImageFormat::Rgba8Unorm,
+        ))
+        .unwrap()
+}
+
+fn buffer(device: &SoftwareDevice, usage: BufferUsage, data: &[u8]) -> Box<dyn Buffer> {
+    let buffer = device
+        .create_buffer(&BufferDescriptor {
+            size: data.len() as u64,
+            usage,
+            memory_type: MemoryType::HostVisible,
+            mapped_at_creation: false,
+            label: None,
+        })
+        .unwrap();
+    buffer.write(0, data).unwrap();
+    buffer
+}
+
+fn vertex_buffer(device: &SoftwareDevice, vertices: &[Vertex]) -> Box<dyn Buffer> {
+    let data: Vec<u8> = vertices
+        .iter()
+        .flat_map(|(position, color)| position.iter().chain(color))
+        .flat_map(|value| value.to_ne_bytes())
+        .collect();
+    buffer(device, BufferUsage::VERTEX, &data)
+}
+
+/// Pipeline reading [`Vertex`]es from binding 0
+fn pipeline(
+    device: &SoftwareDevice,
+    customize: impl FnOnce(&mut GraphicsPipelineDescriptor),
+) -> Box<dyn Pipeline> {
+    let mut desc = GraphicsPipelineDescriptor {
+        vertex_bindings: vec![VertexBinding {
+            binding: 0,
+            stride: 32,
+            input_rate: VertexInputRate::Vertex,
+        }],
+        vertex_attributes: vec![
+            VertexAttribute {
+                location: 0,
+                binding: 0,
+                format: VertexFormat::Float4,
+                offset: 0,
+            },
+            VertexAttribute {
+                location: 1,
+                binding: 0,
+                format: VertexFormat::Float4,
+                offset: 16,
+            },
+        ],
+        cull_mode: CullMode::None,
+        color_formats: vec![ImageFormat::Rgba8Unorm],
+        ..Default::default()
+    };
+    customize(&mut desc);
+    device.create_graphics_pipeline(&desc).unwrap()
+}
+
+/// Record commands into a fresh command buffer and run them
+fn submit(device: &SoftwareDevice, record: impl FnOnce(&mut dyn CommandBuffer)) {
+    let pool = device
+        .create_command_pool(gal::QueueType::Graphics)
+        .unwrap();
+    let mut command_buffer = pool.allocate().unwrap();
+    command_buffer.begin().unwrap();
+    record(command_buffer.as_mut());
+    command_buffer.end().unwrap();
+    device
+        .graphics_queue()
+        .submit(&[SubmitInfo::new(&[command_buffer.as_ref()])], None)
+        .unwrap();
+}
+
+fn begin_pass(
+    command_buffer: &mut dyn CommandBuffer,
+    color: &dyn Image,
+    depth: Option<&dyn Image>,
+    clear: ClearColor,
+) {
+    command_buffer
+        .begin_render_pass(&RenderPassDescriptor {
+            color_attachments: vec![ColorAttachment {
+                image: color,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_value: ClearValue { color: clear },
+            }],
+            depth_stencil_attachment: depth.map(|image| DepthStencilAttachment {
+                image,
+                depth_load_op: LoadOp::Clear,
+                depth_store_op: StoreOp::Store,
+                stencil_load_op: LoadOp::Load,
+                stencil_store_op: StoreOp::DontCare,
+                clear_value: ClearValue {
+                    depth_stencil: ClearDepthStencil::new(1.0, 0),
+                },
+            }),
+            render_area: Rect2D::new(0, 0, SIZE, SIZE),
+        })
+        .unwrap();
+}
+
+fn color_layers() -> ImageSubresourceLayers {
+    ImageSubresourceLayers {
+        aspect_mask: ImageAspect::COLOR,
+        mip_level: 0,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// RGBA8 pixel at `x`, `y` of a `SIZE` wide image
+fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+    let offset = ((y * SIZE + x) * 4) as usize;
+    pixels[offset..offset + 4].try_into().unwrap()
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn check_golden(scene: &str, pixels: &[u8], golden: u64) {
+    let hash = fnv1a(pixels);
+    assert_eq!(
+        hash, golden,
+        "{} does not match its golden image, it now hashes to {:#018x}",
+        scene, hash
+    );
+}
+
+#[test]
+fn clear() {
+    let device = device();
+    let target = color_target(&device);
+    submit(&device, |cmd| {
+        begin_pass(
+            cmd,
+            target.as_ref(),
+            None,
+            ClearColor::new(0.2, 0.4, 0.6, 1.0),
+        );
+        cmd.end_render_pass();
+    });
+
+    let pixels = device.read_image(target.as_ref()).unwrap();
+    assert_eq!(pixel(&pixels, 0, 0), [51, 102, 153, 255]);
+    assert_eq!(pixel(&pixels, SIZE - 1, SIZE - 1), [51, 102, 153, 255]);
+    check_golden("clear", &pixels, 0xb4b85f7546b0c325);
+}
+
+#[test]
+fn interpolated_triangle() {
+    let device = device();
+    let target = color_target(&device);
+    let vertices = vertex_buffer(
+        &device,
+        &[
+            ([0.0, -0.75, 0.5, 1.0], RED),
+            ([0.75, 0.75, 0.5, 1.0], GREEN),
+            ([-0.75, 0.75, 0.5, 1.0], BLUE),
+        ],
+    );
+    let pipeline = pipeline(&device, |_| {});
+    submit(&device, |cmd| {
+        begin_pass(cmd, target.as_ref(), None, ClearColor::BLACK);
+        cmd.bind_pipeline(pipeline.as_ref());
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[0]);
+        cmd.draw(DrawCommand::new(3));
+        cmd.end_render_pass();
+    });
+
+    let pixels = device.read_image(target.as_ref()).unwrap();
+    // Corners stay clear, the centroid mixes all three colors
+    assert_eq!(pixel(&pixels, 0, 0), [0, 0, 0, 255]);
+    assert_eq!(pixel(&pixels, SIZE - 1, 0), [0, 0, 0, 255]);
+    let [r, g, b, a] = pixel(&pixels, 32, 40);
+    assert!(r > 40 && g > 40 && b > 40 && a == 255);
+    // Near the top vertex the color is mostly red
+    let [r, g, b, _] = pixel(&pixels, 32, 12);
+    assert!(r > 200 && g < 40 && b < 40);
+    check_golden("interpolated_triangle", &pixels, 0xd2820e3a6968d751);
+}
+
+#[test]
+fn depth_test() {
+    let device = device();
+    let target = color_target(&device);
+    let depth = device
+        .create_image(&ImageDescriptor::new_2d(
+            SIZE,
+            SIZE,
+            ImageFormat::Depth32Float,
+            gal::ImageUsage::DEPTH_STENCIL_ATTACHMENT,
+        ))
+        .unwrap();
+    // A near red triangle drawn before a far green one covering the screen
+    let vertices = vertex_buffer(
+        &device,
+        &[
+            ([-0.5, -0.5, 0.25, 1.0], RED),
+            ([0.5, -0.5, 0.25, 1.0], RED),
+            ([0.0, 0.5, 0.25, 1.0], RED),
+            ([-1.0, -1.0, 0.75, 1.0], GREEN),
+            ([3.0, -1.0, 0.75, 1.0], GREEN),
+            ([-1.0, 3.0, 0.75, 1.0], GREEN),
+        ],
+    );
+    let pipeline = pipeline(&device, |desc| {
+        desc.depth_test = true;
+        desc.depth_write = true;
+        desc.depth_compare = CompareOp::Less;
+        desc.depth_format = Some(ImageFormat::Depth32Float);
+    });
+    submit(&device, |cmd| {
+        begin_pass(
+            cmd,
+            target.as_ref(),
+            Some(depth.as_ref()),
+            ClearColor::BLACK,
+        );
+        cmd.bind_pipeline(pipeline.as_ref());
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[0]);
+        cmd.draw(DrawCommand::new(6));
+        cmd.end_render_pass();
+    });
+
+    let pixels = device.read_image(target.as_ref()).unwrap();
+    assert_eq!(pixel(&pixels, 32, 32), [255, 0, 0, 255]);
+    assert_eq!(pixel(&pixels, 2, 2), [0, 255, 0, 255]);
+    assert_eq!(pixel(&pixels, SIZE - 3, SIZE - 3), [0, 255, 0, 255]);
+    check_golden("depth_test", &pixels, 0x7c50dcf1945d9325);
+}
+
+#[test]
+fn alpha_blending() {
+    let device = device();
+    let target = color_target(&device);
+    // An opaque red quad and a half transparent blue one overlapping it
+    let half_blue = [0.0, 0.0, 1.0, 0.5];
+    let vertices = vertex_buffer(
+        &device,
+        &[
+            ([-0.75, -0.75, 0.5, 1.0], RED),
+            ([0.25, -0.75, 0.5, 1.0], RED),
+            ([-0.75, 0.25, 0.5, 1.0], RED),
+            ([0.25, 0.25, 0.5, 1.0], RED),
+            ([-0.25, -0.25, 0.5, 1.0], half_blue),
+            ([0.75, -0.25, 0.5, 1.0], half_blue),
+            ([-0.25, 0.75, 0.5, 1.0], half_blue),
+            ([0.75, 0.75, 0.5, 1.0], half_blue),
+        ],
+    );
+    let pipeline = pipeline(&device, |desc| {
+        desc.topology = PrimitiveTopology::TriangleStrip;
+        desc.blend_attachments = vec![ColorBlendAttachment {
+            blend_enable: true,
+            src_color_factor: BlendFactor::SrcAlpha,
+            dst_color_factor: BlendFactor::OneMinusSrcAlpha,
+            dst_alpha_factor: BlendFactor::OneMinusSrcAlpha,
+            ..Default::default()
+        }];
+    });
+    submit(&device, |cmd| {
+        begin_pass(cmd, target.as_ref(), None, ClearColor::BLACK);
+        cmd.bind_pipeline(pipeline.as_ref());
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[0]);
+        cmd.draw(DrawCommand::new(4));
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[4 * 32]);
+        cmd.draw(DrawCommand::new(4));
+        cmd.end_render_pass();
+    });
+
+    let pixels = device.read_image(target.as_ref()).unwrap();
+    assert_eq!(pixel(&pixels, 12, 12), [255, 0, 0, 255]);
+    assert_eq!(pixel(&pixels, 32, 32), [128, 0, 128, 255]);
+    assert_eq!(pixel(&pixels, 52, 52), [0, 0, 128, 255]);
+    check_golden("alpha_blending", &pixels, 0xeffb01f4f957b125);
+}
+
+#[test]
+fn indexed_quad_with_viewport_and_scissor() {
+    let device = device();
+    let target = color_target(&device);
+    let vertices = vertex_buffer(
+        &device,
+        &[
+            ([-1.0, -1.0, 0.5, 1.0], RED),
+            ([1.0, -1.0, 0.5, 1.0], GREEN),
+            ([1.0, 1.0, 0.5, 1.0], BLUE),
+            ([-1.0, 1.0, 0.5, 1.0], GREEN),
+        ],
+    );
+    let indices: Vec<u8> = [0u16, 1, 2, 2, 3, 0]
+        .iter()
+        .flat_map(|index| index.to_ne_bytes())
+        .collect();
+    let indices = buffer(&device, BufferUsage::INDEX, &indices);
+    let pipeline = pipeline(&device, |desc| {
+        desc.cull_mode = CullMode::Back;
+        desc.front_face = FrontFace::Clockwise;
+    });
+    submit(&device, |cmd| {
+        begin_pass(cmd, target.as_ref(), None, ClearColor::BLACK);
+        cmd.bind_pipeline(pipeline.as_ref());
+        // The quad fills the lower right quadrant, cut at x = 56
+        cmd.set_viewport(Viewport::new(32.0, 32.0, 32.0, 32.0));
+        cmd.set_scissor(Rect2D::new(0, 0, 56, SIZE));
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[0]);
+        cmd.bind_index_buffer(indices.as_ref(), 0, IndexType::U16);
+        cmd.draw_indexed(DrawIndexedCommand::new(6));
+        cmd.end_render_pass();
+    });
+
+    let pixels = device.read_image(target.as_ref()).unwrap();
+    assert_eq!(pixel(&pixels, 16, 16), [0, 0, 0, 255]);
+    assert_eq!(pixel(&pixels, 40, 16), [0, 0, 0, 255]);
+    assert_eq!(pixel(&pixels, 60, 48), [0, 0, 0, 255]);
+    let [r, _, _, a] = pixel(&pixels, 32, 32);
+    assert!(r > 200 && a == 255);
+    let [_, _, b, _] = pixel(&pixels, 55, 63);
+    assert!(b > 150);
+    check_golden(
+        "indexed_quad_with_viewport_and_scissor",
+        &pixels,
+        0xfdebfc2b1a1c4c65,
+    );
+}
+
+#[test]
+fn fan_with_back_face_culling() {
+    let device = device();
+    let target = color_target(&device);
+    // Two diamonds fanned out around the center, wound in opposite directions
+    let center = ([0.0, 0.0, 0.5, 1.0], GREEN);
+    let vertices = vertex_buffer(
+        &device,
+        &[
+            center,
+            ([0.0, -1.0, 0.5, 1.0], RED),
+            ([1.0, 0.0, 0.5, 1.0], RED),
+            ([0.0, 1.0, 0.5, 1.0], RED),
+            ([-1.0, 0.0, 0.5, 1.0], RED),
+            ([0.0, -1.0, 0.5, 1.0], RED),
+            center,
+            ([0.0, -1.0, 0.5, 1.0], BLUE),
+            ([-1.0, 0.0, 0.5, 1.0], BLUE),
+            ([0.0, 1.0, 0.5, 1.0], BLUE),
+            ([1.0, 0.0, 0.5, 1.0], BLUE),
+            ([0.0, -1.0, 0.5, 1.0], BLUE),
+        ],
+    );
+    let pipeline = pipeline(&device, |desc| {
+        desc.topology = PrimitiveTopology::TriangleFan;
+        desc.cull_mode = CullMode::Back;
+        desc.front_face = FrontFace::Clockwise;
+    });
+    submit(&device, |cmd| {
+        begin_pass(cmd, target.as_ref(), None, ClearColor::BLACK);
+        cmd.bind_pipeline(pipeline.as_ref());
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[0]);
+        cmd.draw(DrawCommand::new(6));
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[6 * 32]);
+        cmd.draw(DrawCommand::new(6));
+        cmd.end_render_pass();
+    });
+
+    let pixels = device.read_image(target.as_ref()).unwrap();
+    // Only the clockwise diamond survives, the counter-clockwise one is culled
+    let [r, g, b, _] = pixel(&pixels, 32, 8);
+    assert!(r > 150 && b == 0 && g < 100);
+    assert_eq!(pixel(&pixels, 2, 2), [0, 0, 0, 255]);
+    check_golden("fan_with_back_face_culling", &pixels, 0x1896a48b82d0b725);
+}
+
+#[test]
+fn textured_quads() {
+    let device = device();
+    let target = device
+        .create_image(&ImageDescriptor::scanout(
+            SIZE,
+            SIZE,
+            ImageFormat::Rgba8Unorm,
+        ))
+        .unwrap();
+    let texture = device
+        .create_image(&ImageDescriptor::texture(4, 4, ImageFormat::Rgba8Unorm))
+        .unwrap();
+    // 4x4 checkerboard of white and a gradient
+    let texels: Vec<u8> = (0..16u8)
+        .flat_map(|i| {
+            let (x, y) = (i % 4, i / 4);
+            if (x + y) % 2 == 0 {
+                [255, 255, 255, 255]
+            } else {
+                [x * 80, y * 80, 255 - i * 16, 255]
+            }
+        })
+        .collect();
+    let upload = buffer(&device, BufferUsage::TRANSFER_SRC, &texels);
+    let quad = |x0, y0, x1, y1| ImageBlit {
+        src_subresource: color_layers(),
+        src_offsets: [Offset3D::new(0, 0, 0), Offset3D::new(4, 4, 1)],
+        dst_subresource: color_layers(),
+        dst_offsets: [Offset3D::new(x0, y0, 0), Offset3D::new(x1, y1, 1)],
+    };
+    submit(&device, |cmd| {
+        cmd.copy_buffer_to_image(
+            upload.as_ref(),
+            texture.as_ref(),
+            &[BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: color_layers(),
+                image_offset: Offset3D::new(0, 0, 0),
+                image_extent: Extent3D::new(4, 4, 1),
+            }],
+        );
+        // Magnified with both filters, then mirrored into the bottom half
+        cmd.blit_image(
+            texture.as_ref(),
+            target.as_ref(),
+            &[quad(0, 0, 32, 32)],
+            Filter::Nearest,
+        );
+        cmd.blit_image(
+            texture.as_ref(),
+            target.as_ref(),
+            &[quad(32, 0, 64, 32)],
+            Filter::Linear,
+        );
+        cmd.blit_image(
+            texture.as_ref(),
+            target.as_ref(),
+            &[quad(64, 64, 0, 32)],
+            Filter::Nearest,
+        );
+    });
+
+    let pixels = device.read_image(target.as_ref()).unwrap();
+    assert_eq!(pixel(&pixels, 4, 4), [255, 255, 255, 255]);
+    assert_eq!(pixel(&pixels, 12, 4), [80, 0, 239, 255]);
+    // Mirrored in both directions, texel (1, 0) lands left of the corner
+    assert_eq!(pixel(&pixels, 63, 63), [255, 255, 255, 255]);
+    assert_eq!(pixel(&pixels, 44, 63), [80, 0, 239, 255]);
+    // Linear filtering blends neighbouring texels
+    let [r, g, _, _] = pixel(&pixels, 40, 4);
+    assert!(r > 80 && r < 255 && g > 0 && g < 255);
+    check_golden("textured_quads", &pixels, 0x2d5d0743b7dc1345);
+}
+
+#[test]
+fn readback_through_buffer() {
+    let device = device();
+    let target = color_target(&device);
+    let vertices = vertex_buffer(
+        &device,
+        &[
+            ([-1.0, -1.0, 0.5, 1.0], RED),
+            ([1.0, -1.0, 0.5, 1.0], GREEN),
+            ([-1.0, 1.0, 0.5, 1.0], BLUE),
+        ],
+    );
+    let readback = buffer(
+        &device,
+        BufferUsage::TRANSFER_DST,
+        &vec![0; (SIZE * SIZE * 4) as usize],
+    );
+    let pipeline = pipeline(&device, |_| {});
+    submit(&device, |cmd| {
+        begin_pass(cmd, target.as_ref(), None, ClearColor::WHITE);
+        cmd.bind_pipeline(pipeline.as_ref());
+        cmd.bind_vertex_buffers(0, &[vertices.as_ref()], &[0]);
+        cmd.draw(DrawCommand::new(3));
+        cmd.end_render_pass();
+        cmd.copy_image_to_buffer(
+            target.as_ref(),
+            readback.as_ref(),
+            &[BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: color_layers(),
+                image_offset: Offset3D::new(0, 0, 0),
+                image_extent: Extent3D::new(SIZE, SIZE, 1),
+            }],
+        );
+    });
+
+    // The copy sees exactly what the device reads back
+    let size = (SIZE * SIZE * 4) as usize;
+    let copied = unsafe { std::slice::from_raw_parts(readback.map().unwrap(), size) }.to_vec();
+    readback.unmap();
+    assert_eq!(copied, device.read_image(target.as_ref()).unwrap());
+    assert_eq!(pixel(&copied, SIZE - 1, SIZE - 1), [255, 255, 255, 255]);
+    check_golden("readback_through_buffer", &copied, 0x04d3a1fe0ec97325);
+}
@@ -0,0 +1,131 @@
+//! Input sampling for Anti-Lag
+//!
+//! [`AntiLag::sync_input_to_frame`](super::AntiLag::sync_input_to_frame)
+//! drains every [`InputSource`] right before a frame starts, so the frame is
+//! built from the newest input, and timestamps the result.
+
+use inputd::{ConsumerHandle, ConsumerHandleEvent};
+use orbclient::{Event, EventOption};
+
+/// Pressed state of the three mouse buttons
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub middle: bool,
+    pub right: bool,
+}
+
+/// Gamepad state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GamepadState {
+    /// Pressed buttons, one bit each
+    pub buttons: u32,
+    /// Sticks and triggers: left x/y, right x/y, left and right trigger
+    pub axes: [i16; 6],
+}
+
+/// Input state a frame is built from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InputState {
+    /// Absolute mouse position
+    pub mouse_position: (i32, i32),
+    /// Relative mouse motion since the previous frame
+    pub mouse_delta: (i32, i32),
+    pub mouse_buttons: MouseButtons,
+    /// Scrolling since the previous frame
+    pub scroll: (i32, i32),
+    /// Pressed keys, one bit per scancode
+    pub keys: [u64; 4],
+    pub gamepad: GamepadState,
+    /// Events applied since the previous frame
+    pub events: usize,
+    /// When the state was sampled (µs)
+    pub sampled_at_us: u64,
+}
+
+impl InputState {
+    /// Check whether the key with `scancode` is pressed
+    pub fn is_key_pressed(&self, scancode: u8) -> bool {
+        self.keys[scancode as usize / 64] & (1 << (scancode % 64)) != 0
+    }
+
+    /// Set whether the key with `scancode` is pressed
+    pub fn set_key(&mut self, scancode: u8, pressed: bool) {
+        let bit = 1 << (scancode % 64);
+        if pressed {
+            self.keys[scancode as usize / 64] |= bit;
+        } else {
+            self.keys[scancode as usize / 64] &= !bit;
+        }
+    }
+
+    /// Start a new frame, relative motion and the event count start over
+    pub(crate) fn begin_frame(&mut self) {
+        self.mouse_delta = (0, 0);
+        self.scroll = (0, 0);
+        self.events = 0;
+    }
+}
+
+/// Something input comes from
+pub trait InputSource: Send {
+    /// Apply all pending input to `state` without blocking
+    fn drain(&mut self, state: &mut InputState) -> Result<(), &'static str>;
+}
+
+/// Mouse and keyboard events of the `input:` scheme
+pub struct InputSchemeSource {
+    handle: ConsumerHandle,
+}
+
+impl InputSchemeSource {
+    /// Read events from a consumer handle of the input scheme
+    pub fn new(handle: ConsumerHandle) -> Self {
+        Self { handle }
+    }
+
+    /// Read events from a new VT, for applications owning the display
+    pub fn open() -> std::io::Result<Self> {
+        Ok(Self::new(ConsumerHandle::new_vt()?))
+    }
+}
+
+impl InputSource for InputSchemeSource {
+    fn drain(&mut self, state: &mut InputState) -> Result<(), &'static str> {
+        let mut buf = [Event::new(); 64];
+        loop {
+            let events = match self.handle.read_events(&mut buf) {
+                Ok(ConsumerHandleEvent::Events(events)) => events,
+                // Another VT got the input, no more events for now
+                Ok(ConsumerHandleEvent::Handoff) => return Ok(()),
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+                Err(_) => return Err("Failed to read input events"),
+            };
+            if events.is_empty() {
+                return Ok(());
+            }
+
+            for event in events {
+                match event.to_option() {
+                    EventOption::Key(key) => state.set_key(key.scancode, key.pressed),
+                    EventOption::Mouse(mouse) => state.mouse_position = (mouse.x, mouse.y),
+                    EventOption::MouseRelative(mouse) => {
+                        state.mouse_delta.0 += mouse.dx;
+                        state.mouse_delta.1 += mouse.dy;
+                    }
+                    EventOption::Button(button) => {
+                        state.mouse_buttons.left = button.left;
+                        state.mouse_buttons.middle = button.middle;
+                        state.mouse_buttons.right = button.right;
+                    }
+                    EventOption::Scroll(scroll) => {
+                        state.scroll.0 += scroll.x;
+                        state.scroll.1 += scroll.y;
+                    }
+                    _ => continue,
+                }
+                state.events += 1;
+            }
+        }
+    }
+}
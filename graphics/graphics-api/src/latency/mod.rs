@@ -2,16 +2,32 @@
 //!
 //! Minimize input-to-display latency for competitive gaming
 
+pub mod input;
+pub mod probe;
+#[cfg(feature = "latprobe")]
+mod scheme;
+
+pub use input::{GamepadState, InputSchemeSource, InputSource, InputState, MouseButtons};
+pub use probe::{ClickToPhotonStats, FlashIndicator, LatencyProbe};
+#[cfg(feature = "latprobe")]
+pub use scheme::{serve_latprobe, LatencyProbeScheme};
+
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use crate::vrr::VrrController;
+
 /// Anti-Lag controller
 pub struct AntiLag {
     enabled: AtomicBool,
     frame_queue_depth: AtomicU64,
     last_input_time: Arc<AtomicU64>,
     frame_start_time: Arc<AtomicU64>,
+    input_sources: Mutex<Vec<Box<dyn InputSource>>>,
+    input_state: Mutex<InputState>,
+    /// Age of the input of the last presented frame (µs)
+    presented_input_age: AtomicU64,
 }
 
 impl AntiLag {
@@ -24,9 +40,17 @@ impl AntiLag {
             frame_queue_depth: AtomicU64::new(2), // Default: 2 frames
             last_input_time: Arc::new(AtomicU64::new(0)),
             frame_start_time: Arc::new(AtomicU64::new(0)),
+            input_sources: Mutex::new(Vec::new()),
+            input_state: Mutex::new(InputState::default()),
+            presented_input_age: AtomicU64::new(0),
         }
     }
 
+    /// Sample input from `source` at every frame start
+    pub fn add_input_source(&self, source: Box<dyn InputSource>) {
+        self.input_sources.lock().unwrap().push(source);
+    }
+
     /// Enable Anti-Lag
     pub fn enable(&self) {
         self.enabled.store(true, Ordering::Release);
@@ -62,7 +86,7 @@ impl AntiLag {
         self.frame_start_time.store(now, Ordering::Release);
 
         // Poll input immediately before frame start
-        self.poll_input_devices()?;
+        self.poll_input_devices(now)?;
 
         // Record input time
         self.last_input_time.store(now, Ordering::Release);
@@ -71,6 +95,25 @@ impl AntiLag {
         Ok(())
     }
 
+    /// Get the input state sampled at the last frame start
+    pub fn input_state(&self) -> InputState {
+        *self.input_state.lock().unwrap()
+    }
+
+    /// Record that the frame built from the last input sample was presented
+    pub fn mark_present(&self) {
+        let input_time = self.last_input_time.load(Ordering::Acquire);
+        if input_time != 0 {
+            let age = Self::get_time_us().saturating_sub(input_time);
+            self.presented_input_age.store(age, Ordering::Release);
+        }
+    }
+
+    /// Get the age of the input of the last presented frame, at present
+    pub fn input_age(&self) -> Duration {
+        Duration::from_micros(self.presented_input_age.load(Ordering::Acquire))
+    }
+
     /// Get current latency (input to display)
     pub fn get_latency(&self) -> Duration {
         let input_time = self.last_input_time.load(Ordering::Acquire);
@@ -88,9 +131,14 @@ impl AntiLag {
         self.frame_queue_depth.load(Ordering::Acquire)
     }
 
-    fn poll_input_devices(&self) -> Result<(), &'static str> {
-        // Poll all input devices (mouse, keyboard, gamepad)
-        // This would interface with the input subsystem
+    fn poll_input_devices(&self, now: u64) -> Result<(), &'static str> {
+        // Flush everything the devices (mouse, keyboard, gamepad) queued up
+        let mut state = self.input_state.lock().unwrap();
+        state.begin_frame();
+        for source in self.input_sources.lock().unwrap().iter_mut() {
+            source.drain(&mut state)?;
+        }
+        state.sampled_at_us = now;
         Ok(())
     }
 
@@ -105,10 +153,16 @@ impl AntiLag {
 }
 
 /// Frame pacing controller
+///
+/// With an adaptive sync display attached through [`FramePacer::set_vrr`],
+/// each frame is also given a present target, so target frame rates inside
+/// the display's range are shown at exactly that rate instead of being
+/// rounded to the fixed refresh.
 pub struct FramePacer {
     target_fps: AtomicU64,
     last_frame_time: Arc<AtomicU64>,
     frame_times: Arc<std::sync::Mutex<Vec<Duration>>>,
+    vrr: std::sync::Mutex<Option<VrrController>>,
 }
 
 impl FramePacer {
@@ -120,7 +174,43 @@ impl FramePacer {
             target_fps: AtomicU64::new(target_fps),
             last_frame_time: Arc::new(AtomicU64::new(0)),
             frame_times: Arc::new(std::sync::Mutex::new(Vec::with_capacity(120))),
+            vrr: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Pace frames on an adaptive sync display, or stop with `None`
+    pub fn set_vrr(&self, vrr: Option<VrrController>) {
+        let mut current = self.vrr.lock().unwrap();
+        if let Some(old) = current.as_ref() {
+            let _ = old.clear_present_target();
         }
+        if let Some(range) = vrr.as_ref().and_then(|vrr| vrr.range()) {
+            log::info!(
+                "Frame pacing on VRR display: {}-{} Hz",
+                range.min_hz,
+                range.max_hz
+            );
+        }
+        *current = vrr;
+    }
+
+    /// Check if the target frame rate is presented through adaptive sync
+    pub fn is_vrr_paced(&self) -> bool {
+        let target_fps = self.target_fps.load(Ordering::Acquire);
+        if target_fps == 0 {
+            return false;
+        }
+        let frame_time = Duration::from_micros(1_000_000 / target_fps);
+        self.vrr
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|vrr| vrr.can_pace(frame_time))
+    }
+
+    /// Get target FPS, 0 if unlimited
+    pub fn target_fps(&self) -> u64 {
+        self.target_fps.load(Ordering::Acquire)
     }
 
     /// Set target FPS
@@ -147,10 +237,20 @@ impl FramePacer {
 
         let now = AntiLag::get_time_us();
         let elapsed = Duration::from_micros(now - last_time);
+        let remaining = target_frame_time.saturating_sub(elapsed);
+
+        // Have the display refresh when the frame is due, rather than at
+        // the next fixed refresh
+        if let Some(vrr) = self.vrr.lock().unwrap().as_ref() {
+            if vrr.can_pace(target_frame_time) {
+                if let Err(err) = vrr.present_at(Instant::now() + remaining) {
+                    log::warn!("Failed to set VRR present target: {}", err);
+                }
+            }
+        }
 
-        if elapsed < target_frame_time {
-            let sleep_time = target_frame_time - elapsed;
-            std::thread::sleep(sleep_time);
+        if !remaining.is_zero() {
+            std::thread::sleep(remaining);
         }
 
         let final_time = AntiLag::get_time_us();
@@ -176,6 +276,12 @@ impl FramePacer {
         sum / times.len() as u32
     }
 
+    /// Get the longest recent frame time
+    pub fn max_frame_time(&self) -> Duration {
+        let times = self.frame_times.lock().unwrap();
+        times.iter().max().copied().unwrap_or(Duration::ZERO)
+    }
+
     /// Get frame time stability (lower is better)
     pub fn frame_time_variance(&self) -> f64 {
         let times = self.frame_times.lock().unwrap();
@@ -183,7 +289,8 @@ impl FramePacer {
             return 0.0;
         }
 
-        let avg = self.average_frame_time().as_secs_f64();
+        // average_frame_time() would lock frame_times again
+        let avg = times.iter().sum::<Duration>().as_secs_f64() / times.len() as f64;
         let variance: f64 = times
             .iter()
             .map(|t| {
@@ -0,0 +1,206 @@
+//! Click-to-photon latency measurement
+//!
+//! [`AntiLag`](super::AntiLag) and Reflex-style pacing only see the part of
+//! the latency between input sampling and present. To validate them end to
+//! end, a click (from a HID device timestamping its own button) makes the
+//! application show a [`FlashIndicator`], and a photodiode on that corner of
+//! the screen reports when the light actually came out. [`LatencyProbe`]
+//! pairs the two timestamps into click-to-photon statistics.
+//!
+//! Timestamps are `CLOCK_MONOTONIC` nanoseconds, the clock of presentation
+//! timestamps, so they can come from other processes through the
+//! `latprobe:` scheme (feature `latprobe`).
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use gal::command::{ColorAttachment, LoadOp, RenderPassDescriptor, StoreOp};
+use gal::{ClearColor, ClearValue, CommandBuffer, Image, Rect2D};
+
+/// Frames the indicator stays white, so that a photodiode sampling slower
+/// than the refresh rate still sees it
+const FLASH_FRAMES: u32 = 2;
+
+/// Clicks older than this when a flash is seen were missed, not measured
+const MAX_LATENCY_NS: u64 = 1_000_000_000;
+
+/// Clicks waiting for their flash
+const MAX_PENDING_CLICKS: usize = 16;
+
+/// Samples the statistics are computed from
+const MAX_SAMPLES: usize = 1000;
+
+/// Rectangle drawn white for a few frames after a marker event and black
+/// otherwise, for a photodiode to detect
+pub struct FlashIndicator {
+    area: Rect2D,
+    /// Frames left to draw white
+    remaining: u32,
+}
+
+impl FlashIndicator {
+    /// Flash `area` of the frame, usually a corner out of the way
+    pub fn new(area: Rect2D) -> Self {
+        Self { area, remaining: 0 }
+    }
+
+    /// Get the flashed area
+    pub fn area(&self) -> Rect2D {
+        self.area
+    }
+
+    /// Flash in the next frames, on the marker event being measured such as
+    /// the first frame built from a click
+    pub fn trigger(&mut self) {
+        self.remaining = FLASH_FRAMES;
+    }
+
+    /// Check if the next frame is drawn white
+    pub fn is_flashing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Draw the indicator over `target`, after everything else of the frame
+    ///
+    /// Returns whether the indicator was white.
+    pub fn record(&mut self, cmd: &mut dyn CommandBuffer, target: &dyn Image) -> gal::Result<bool> {
+        let flashing = self.is_flashing();
+        let color = if flashing {
+            ClearColor::WHITE
+        } else {
+            ClearColor::BLACK
+        };
+
+        cmd.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: vec![ColorAttachment {
+                image: target,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_value: ClearValue { color },
+            }],
+            depth_stencil_attachment: None,
+            render_area: self.area,
+        })?;
+        cmd.end_render_pass();
+
+        self.remaining = self.remaining.saturating_sub(1);
+        Ok(flashing)
+    }
+}
+
+/// Click-to-photon latency statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClickToPhotonStats {
+    /// Measured clicks
+    pub samples: usize,
+    /// Flashes seen without a click before them
+    pub unmatched: u64,
+    pub min: Duration,
+    pub mean: Duration,
+    /// 99th percentile
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+struct ProbeState {
+    /// Clicks without a flash yet, oldest first (ns)
+    clicks: VecDeque<u64>,
+    /// Latencies of the last measured clicks (ns)
+    samples: VecDeque<u64>,
+    unmatched: u64,
+}
+
+/// Pairs clicks with the flash a photodiode saw after them
+pub struct LatencyProbe {
+    state: Mutex<ProbeState>,
+}
+
+impl LatencyProbe {
+    /// Create new latency probe
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(ProbeState {
+                clicks: VecDeque::with_capacity(MAX_PENDING_CLICKS),
+                samples: VecDeque::with_capacity(MAX_SAMPLES),
+                unmatched: 0,
+            }),
+        }
+    }
+
+    /// Record a click at `at_ns`
+    pub fn record_click(&self, at_ns: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.clicks.len() == MAX_PENDING_CLICKS {
+            state.clicks.pop_front();
+        }
+        state.clicks.push_back(at_ns);
+    }
+
+    /// Record that the photodiode saw the flash at `at_ns`
+    ///
+    /// The flash belongs to the oldest click before it, clicks too old to
+    /// have caused it are dropped. Returns the measured latency.
+    pub fn record_photon(&self, at_ns: u64) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        while let Some(&click) = state.clicks.front() {
+            if click > at_ns {
+                // Clicked after the flash, that's for the next one
+                break;
+            }
+            state.clicks.pop_front();
+
+            let latency = at_ns - click;
+            if latency <= MAX_LATENCY_NS {
+                if state.samples.len() == MAX_SAMPLES {
+                    state.samples.pop_front();
+                }
+                state.samples.push_back(latency);
+                log::debug!("Click-to-photon latency: {} µs", latency / 1000);
+                return Some(Duration::from_nanos(latency));
+            }
+        }
+
+        state.unmatched += 1;
+        None
+    }
+
+    /// Get the statistics of the measured clicks
+    pub fn stats(&self) -> ClickToPhotonStats {
+        let state = self.state.lock().unwrap();
+        if state.samples.is_empty() {
+            return ClickToPhotonStats {
+                unmatched: state.unmatched,
+                ..Default::default()
+            };
+        }
+
+        let mut sorted: Vec<u64> = state.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let mean = sorted.iter().sum::<u64>() / sorted.len() as u64;
+        let p99 = sorted[(sorted.len() * 99).div_ceil(100) - 1];
+
+        ClickToPhotonStats {
+            samples: sorted.len(),
+            unmatched: state.unmatched,
+            min: Duration::from_nanos(sorted[0]),
+            mean: Duration::from_nanos(mean),
+            p99: Duration::from_nanos(p99),
+            max: Duration::from_nanos(sorted[sorted.len() - 1]),
+        }
+    }
+
+    /// Forget all clicks and samples, for example to measure another mode
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.clicks.clear();
+        state.samples.clear();
+        state.unmatched = 0;
+    }
+}
+
+impl Default for LatencyProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
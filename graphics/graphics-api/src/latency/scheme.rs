@@ -0,0 +1,166 @@
+//! `latprobe:` scheme
+//!
+//! Timestamp sources write lines of `click <ns>` or `photon <ns>`, with
+//! `CLOCK_MONOTONIC` nanoseconds; without a timestamp the time of the write
+//! is used. `reset` forgets all samples.
+//!
+//! Reading from offset 0 returns the statistics as lines of `<name> <value>`:
+//! `samples`, `unmatched`, then `min_us`, `mean_us`, `p99_us` and `max_us`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult, RequestKind, SignalBehavior, Socket};
+use syscall::schemev2::NewFdFlags;
+use syscall::{Error, Result, EBADF, EINVAL, ENOENT};
+
+use super::probe::LatencyProbe;
+
+pub struct LatencyProbeScheme {
+    probe: Arc<LatencyProbe>,
+    /// Statistics last read by each handle
+    handles: BTreeMap<usize, String>,
+    next_id: usize,
+}
+
+impl LatencyProbeScheme {
+    pub fn new(probe: Arc<LatencyProbe>) -> Self {
+        Self {
+            probe,
+            handles: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn on_close(&mut self, id: usize) {
+        self.handles.remove(&id);
+    }
+
+    fn format_stats(&self) -> String {
+        let stats = self.probe.stats();
+        let mut text = String::new();
+        let _ = writeln!(text, "samples {}", stats.samples);
+        let _ = writeln!(text, "unmatched {}", stats.unmatched);
+        let _ = writeln!(text, "min_us {}", stats.min.as_micros());
+        let _ = writeln!(text, "mean_us {}", stats.mean.as_micros());
+        let _ = writeln!(text, "p99_us {}", stats.p99.as_micros());
+        let _ = writeln!(text, "max_us {}", stats.max.as_micros());
+        text
+    }
+
+    fn handle_line(&self, line: &str) -> Result<()> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(());
+        };
+        if command == "reset" {
+            self.probe.reset();
+            return Ok(());
+        }
+
+        let at_ns = match words.next() {
+            Some(word) => word.parse().map_err(|_| Error::new(EINVAL))?,
+            None => gal::display::monotonic_ns().ok_or(Error::new(EINVAL))?,
+        };
+        match command {
+            "click" => self.probe.record_click(at_ns),
+            "photon" => {
+                self.probe.record_photon(at_ns);
+            }
+            _ => return Err(Error::new(EINVAL)),
+        }
+        Ok(())
+    }
+}
+
+impl SchemeSync for LatencyProbeScheme {
+    fn open(&mut self, path: &str, _flags: usize, _ctx: &CallerCtx) -> Result<OpenResult> {
+        if !path.trim_matches('/').is_empty() {
+            return Err(Error::new(ENOENT));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handles.insert(id, String::new());
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        if !self.handles.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+        if offset == 0 {
+            let stats = self.format_stats();
+            self.handles.insert(id, stats);
+        }
+
+        let stats = self.handles[&id].as_bytes();
+        let start = (offset as usize).min(stats.len());
+        let len = buf.len().min(stats.len() - start);
+        buf[..len].copy_from_slice(&stats[start..start + len]);
+        Ok(len)
+    }
+
+    fn write(
+        &mut self,
+        id: usize,
+        buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        if !self.handles.contains_key(&id) {
+            return Err(Error::new(EBADF));
+        }
+
+        let text = std::str::from_utf8(buf).map_err(|_| Error::new(EINVAL))?;
+        for line in text.lines() {
+            self.handle_line(line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn fpath(&mut self, _id: usize, buf: &mut [u8], _ctx: &CallerCtx) -> Result<usize> {
+        let path = b"latprobe:";
+        let len = buf.len().min(path.len());
+        buf[..len].copy_from_slice(&path[..len]);
+        Ok(len)
+    }
+}
+
+/// Serve `latprobe:` for `probe` until the scheme is unmounted
+pub fn serve_latprobe(probe: Arc<LatencyProbe>) -> Result<()> {
+    let socket = Socket::create("latprobe")?;
+    let mut scheme = LatencyProbeScheme::new(probe);
+
+    loop {
+        let Some(request) = socket.next_request(SignalBehavior::Restart)? else {
+            // Scheme likely got unmounted
+            return Ok(());
+        };
+
+        match request.kind() {
+            RequestKind::Call(call) => {
+                let response = call.handle_sync(&mut scheme);
+                socket.write_response(response, SignalBehavior::Restart)?;
+            }
+            RequestKind::OnClose { id } => {
+                scheme.on_close(id);
+            }
+            _ => (),
+        }
+    }
+}
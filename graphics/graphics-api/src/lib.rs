@@ -1,15 +1,20 @@
 //! Native Graphics API Layer for Redox OS
 //!
-//! High-performance graphics API with Ray Tracing, AI upscaling, and Anti-Lag support.
+//! High-performance graphics API with Ray Tracing, AI upscaling, Anti-Lag and
+//! variable refresh rate support.
 
 pub mod latency;
 pub mod shader;
+pub mod telemetry;
 pub mod upscaling;
+pub mod vrr;
 pub mod vulkan;
 
 pub use latency::*;
 pub use shader::*;
+pub use telemetry::*;
 pub use upscaling::*;
+pub use vrr::*;
 pub use vulkan::*;
 
 /// Graphics API initialization
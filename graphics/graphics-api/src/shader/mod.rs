@@ -1,9 +1,23 @@
 //! Shader Translation and Caching
+//!
+//! Sources are compiled to SPIR-V by the [`compiler`] front-end and cached by a hash of their
+//! content, in memory and as `.spv` files in the cache directory. Processes using the same
+//! directory share compiled shaders; the `shadercache:` scheme (feature `shader-cache`) serves
+//! one cache to all of them.
+
+pub mod compiler;
+#[cfg(feature = "shader-cache")]
+pub mod scheme;
 
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+/// Directory the shared cache lives in
+pub const DEFAULT_CACHE_DIR: &str = "/var/cache/shaders";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ShaderLanguage {
     GLSL,
@@ -11,6 +25,18 @@ pub enum ShaderLanguage {
     SPIRV,
 }
 
+impl ShaderLanguage {
+    /// Language named `name`, as in `shadercache:` paths
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "glsl" => Some(ShaderLanguage::GLSL),
+            "hlsl" => Some(ShaderLanguage::HLSL),
+            "spirv" => Some(ShaderLanguage::SPIRV),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ShaderStage {
     Vertex,
@@ -23,12 +49,60 @@ pub enum ShaderStage {
     AnyHit,
 }
 
+impl ShaderStage {
+    /// Stage named `name`, as in `shadercache:` paths
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vertex" => Some(ShaderStage::Vertex),
+            "fragment" => Some(ShaderStage::Fragment),
+            "compute" => Some(ShaderStage::Compute),
+            "geometry" => Some(ShaderStage::Geometry),
+            "raygen" => Some(ShaderStage::RayGen),
+            "miss" => Some(ShaderStage::Miss),
+            "closesthit" => Some(ShaderStage::ClosestHit),
+            "anyhit" => Some(ShaderStage::AnyHit),
+            _ => None,
+        }
+    }
+}
+
+/// Shader compilation errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderError {
+    /// The compiler could not be initialized
+    CompilerUnavailable,
+    /// Compilation failed, with the compiler's diagnostics
+    Compile(String),
+    /// SPIR-V input is not a SPIR-V module
+    InvalidSpirv,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::CompilerUnavailable => write!(f, "Shader compiler unavailable"),
+            ShaderError::Compile(msg) => write!(f, "Shader compilation failed: {}", msg),
+            ShaderError::InvalidSpirv => write!(f, "Invalid SPIR-V module"),
+        }
+    }
+}
+
 pub struct CompiledShader {
     pub spirv: Vec<u32>,
     pub hash: u64,
     pub stage: ShaderStage,
 }
 
+impl CompiledShader {
+    /// SPIR-V as little endian bytes
+    pub fn spirv_bytes(&self) -> Vec<u8> {
+        self.spirv
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+}
+
 pub struct ShaderCache {
     cache_dir: PathBuf,
     memory_cache: Arc<Mutex<HashMap<u64, Arc<CompiledShader>>>>,
@@ -48,14 +122,37 @@ impl ShaderCache {
         source: &str,
         lang: ShaderLanguage,
         stage: ShaderStage,
-    ) -> Result<Arc<CompiledShader>, &'static str> {
-        let hash = Self::hash_source(source);
+    ) -> Result<Arc<CompiledShader>, ShaderError> {
+        self.compile_entry(source.as_bytes(), lang, stage, "main")
+    }
+
+    /// Compile `source` with the entry point `entry_point`, or fetch it from the cache
+    pub fn compile_entry(
+        &self,
+        source: &[u8],
+        lang: ShaderLanguage,
+        stage: ShaderStage,
+        entry_point: &str,
+    ) -> Result<Arc<CompiledShader>, ShaderError> {
+        let hash = Self::hash_source(source, lang, stage, entry_point);
 
         if let Some(cached) = self.memory_cache.lock().unwrap().get(&hash) {
             return Ok(cached.clone());
         }
 
-        let spirv = vec![]; // Actual compilation here
+        let path = self.cache_dir.join(format!("{:016x}.spv", hash));
+        let spirv = match fs::read(&path)
+            .ok()
+            .map(|bytes| compiler::spirv_words(&bytes))
+        {
+            Some(Ok(spirv)) => spirv,
+            _ => {
+                let spirv = compiler::compile_to_spirv(source, lang, stage, entry_point)?;
+                self.store(&path, &spirv);
+                spirv
+            }
+        };
+
         let shader = Arc::new(CompiledShader { spirv, hash, stage });
         self.memory_cache
             .lock()
@@ -64,12 +161,30 @@ impl ShaderCache {
         Ok(shader)
     }
 
-    fn hash_source(source: &str) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        let mut h = DefaultHasher::new();
-        source.hash(&mut h);
-        h.finish()
+    /// Write a compiled shader to the cache directory
+    fn store(&self, path: &PathBuf, spirv: &[u32]) {
+        let bytes: Vec<u8> = spirv.iter().flat_map(|word| word.to_le_bytes()).collect();
+        // Other processes read the file as soon as it has its name, so it gets it when complete
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        if let Err(err) = fs::write(&tmp, &bytes).and_then(|()| fs::rename(&tmp, path)) {
+            log::warn!("Failed to cache shader {}: {}", path.display(), err);
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+
+    /// FNV-1a of the source and how it is compiled, stable across processes and builds
+    fn hash_source(source: &[u8], lang: ShaderLanguage, stage: ShaderStage, entry: &str) -> u64 {
+        let key = [
+            &[lang as u8, stage as u8][..],
+            entry.as_bytes(),
+            &[0],
+            source,
+        ];
+        key.iter()
+            .flat_map(|part| part.iter())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+            })
     }
 }
 
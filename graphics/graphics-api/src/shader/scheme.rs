@@ -0,0 +1,172 @@
+//! `shadercache:` scheme
+//!
+//! Compiles shaders for other processes out of one shared [`ShaderCache`].
+//!
+//! Open `shadercache:<language>/<stage>[/<entry point>]`, for example
+//! `shadercache:hlsl/compute/CSMain`, write the source, then read the SPIR-V. The entry point
+//! defaults to `main`. Reading fails with `EINVAL` if the source doesn't compile; the diagnostics
+//! go to the log.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult, RequestKind, SignalBehavior, Socket};
+use syscall::schemev2::NewFdFlags;
+use syscall::{Error, Result, EBADF, EINVAL, ENOENT};
+
+use super::{CompiledShader, ShaderCache, ShaderLanguage, ShaderStage};
+
+struct Handle {
+    lang: ShaderLanguage,
+    stage: ShaderStage,
+    entry_point: String,
+    source: Vec<u8>,
+    /// SPIR-V bytes once the source was compiled
+    spirv: Option<Vec<u8>>,
+}
+
+pub struct ShaderCacheScheme {
+    cache: ShaderCache,
+    handles: BTreeMap<usize, Handle>,
+    next_id: usize,
+}
+
+impl ShaderCacheScheme {
+    pub fn new(cache: ShaderCache) -> Self {
+        Self {
+            cache,
+            handles: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn on_close(&mut self, id: usize) {
+        self.handles.remove(&id);
+    }
+
+    fn compile(&self, handle: &Handle) -> Result<Arc<CompiledShader>> {
+        self.cache
+            .compile_entry(
+                &handle.source,
+                handle.lang,
+                handle.stage,
+                &handle.entry_point,
+            )
+            .map_err(|err| {
+                log::warn!("shadercache: {}", err);
+                Error::new(EINVAL)
+            })
+    }
+}
+
+impl SchemeSync for ShaderCacheScheme {
+    fn open(&mut self, path: &str, _flags: usize, _ctx: &CallerCtx) -> Result<OpenResult> {
+        let mut parts = path.trim_matches('/').split('/');
+        let lang = parts
+            .next()
+            .and_then(ShaderLanguage::from_name)
+            .ok_or(Error::new(ENOENT))?;
+        let stage = parts
+            .next()
+            .and_then(ShaderStage::from_name)
+            .ok_or(Error::new(ENOENT))?;
+        let entry_point = parts.next().unwrap_or("main");
+        if entry_point.is_empty() || parts.next().is_some() {
+            return Err(Error::new(ENOENT));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handles.insert(
+            id,
+            Handle {
+                lang,
+                stage,
+                entry_point: entry_point.to_string(),
+                source: Vec::new(),
+                spirv: None,
+            },
+        );
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn write(
+        &mut self,
+        id: usize,
+        buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let handle = self.handles.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if handle.spirv.is_some() {
+            // Already compiled
+            return Err(Error::new(EINVAL));
+        }
+        handle.source.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let handle = self.handles.get(&id).ok_or(Error::new(EBADF))?;
+        if handle.spirv.is_none() {
+            let spirv = self.compile(handle)?.spirv_bytes();
+            self.handles.get_mut(&id).unwrap().spirv = Some(spirv);
+        }
+
+        let spirv = self.handles[&id].spirv.as_deref().unwrap();
+        let start = (offset as usize).min(spirv.len());
+        let len = buf.len().min(spirv.len() - start);
+        buf[..len].copy_from_slice(&spirv[start..start + len]);
+        Ok(len)
+    }
+
+    fn fpath(&mut self, id: usize, buf: &mut [u8], _ctx: &CallerCtx) -> Result<usize> {
+        let handle = self.handles.get(&id).ok_or(Error::new(EBADF))?;
+        let path = format!(
+            "shadercache:{}/{}/{}",
+            format!("{:?}", handle.lang).to_lowercase(),
+            format!("{:?}", handle.stage).to_lowercase(),
+            handle.entry_point
+        );
+        let len = buf.len().min(path.len());
+        buf[..len].copy_from_slice(&path.as_bytes()[..len]);
+        Ok(len)
+    }
+}
+
+/// Serve `shadercache:` from `cache` until the scheme is unmounted
+pub fn serve(cache: ShaderCache) -> Result<()> {
+    let socket = Socket::create("shadercache")?;
+    let mut scheme = ShaderCacheScheme::new(cache);
+
+    loop {
+        let Some(request) = socket.next_request(SignalBehavior::Restart)? else {
+            // Scheme likely got unmounted
+            return Ok(());
+        };
+
+        match request.kind() {
+            RequestKind::Call(call) => {
+                let response = call.handle_sync(&mut scheme);
+                socket.write_response(response, SignalBehavior::Restart)?;
+            }
+            RequestKind::OnClose { id } => {
+                scheme.on_close(id);
+            }
+            _ => (),
+        }
+    }
+}
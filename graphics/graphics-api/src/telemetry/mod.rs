@@ -0,0 +1,319 @@
+//! Performance Telemetry
+//!
+//! Collects what a performance overlay shows into one [`GfxStats`] snapshot: frame times from
+//! the [`FramePacer`], GPU load, clocks, temperature and power from the backend, video memory use
+//! from the GAL allocator and the upscaling mode. Snapshots have a fixed binary layout, so overlays and CLI tools can read them
+//! from the `gfxstats:` scheme (feature `gfxstats`) without linking this crate.
+
+#[cfg(feature = "gfxstats")]
+mod scheme;
+
+#[cfg(feature = "gfxstats")]
+pub use scheme::{serve_gfxstats, GfxStatsScheme};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use gal::allocator::{DeviceAllocator, Heap, HeapStats};
+
+use crate::latency::FramePacer;
+use crate::upscaling::{UpscalingManager, UpscalingQuality, UpscalingTech};
+
+/// Size of an encoded snapshot
+pub const SNAPSHOT_SIZE: usize = 88;
+
+/// "GFXS", first bytes of an encoded snapshot
+const SNAPSHOT_MAGIC: u32 = u32::from_le_bytes(*b"GFXS");
+const SNAPSHOT_VERSION: u16 = 2;
+
+/// GPU load reported by a backend
+pub trait GpuLoad: Send + Sync {
+    /// Percentage of time the GPU was busy recently, `None` if the hardware can't tell
+    fn busy_percent(&self) -> Option<u8>;
+}
+
+/// Power profile a driver runs the GPU in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowerProfile {
+    /// Lowest clocks and power limit, for running on battery
+    Battery,
+    /// Clocks follow the load
+    #[default]
+    Balanced,
+    /// Clocks kept high, for the lowest frame times
+    Performance,
+}
+
+/// Sensor readings of a GPU; each is `None` if the hardware can't tell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuSensors {
+    /// Graphics clock in MHz
+    pub clock_mhz: Option<u16>,
+    /// Hottest on-die temperature in millidegrees Celsius
+    pub temperature_mc: Option<i32>,
+    pub fan_rpm: Option<u16>,
+    /// Power draw of the board in milliwatts
+    pub power_mw: Option<u32>,
+    pub power_profile: Option<PowerProfile>,
+}
+
+/// Clocks, temperature and power reported by a backend
+pub trait GpuSensorSource: Send + Sync {
+    fn gpu_sensors(&self) -> GpuSensors;
+}
+
+/// Video memory use
+pub trait VramUsage: Send + Sync {
+    fn vram_usage(&self) -> HeapStats;
+}
+
+impl VramUsage for DeviceAllocator {
+    fn vram_usage(&self) -> HeapStats {
+        self.heap_stats(Heap::Device)
+    }
+}
+
+/// Performance snapshot
+///
+/// Encoded little endian:
+///
+/// | Offset | Size | Field                                      |
+/// |--------|------|--------------------------------------------|
+/// | 0      | 4    | magic `GFXS`                               |
+/// | 4      | 2    | version, 2                                 |
+/// | 6      | 2    | size, 88                                   |
+/// | 8      | 8    | sequence                                   |
+/// | 16     | 8    | timestamp (µs)                             |
+/// | 24     | 4    | average frame time (µs)                    |
+/// | 28     | 4    | longest frame time (µs)                    |
+/// | 32     | 4    | frame time deviation (µs)                  |
+/// | 36     | 4    | target FPS, 0 if unlimited                 |
+/// | 40     | 8    | VRAM allocated (bytes)                     |
+/// | 48     | 8    | VRAM reserved (bytes)                      |
+/// | 56     | 8    | VRAM budget (bytes), 0 if none             |
+/// | 64     | 1    | GPU busy (%), 255 if unknown               |
+/// | 65     | 1    | upscaling: 0 native, 1 FSR, 2 DLSS, 3 XeSS |
+/// | 66     | 1    | quality: 0 ultra performance to 4 ultra quality, 255 if none |
+/// | 67     | 1    | flags: bit 0 VRR paced                     |
+/// | 68     | 2    | GPU clock (MHz), 0 if unknown              |
+/// | 70     | 2    | fan speed (RPM), 65535 if unknown          |
+/// | 72     | 4    | GPU temperature (m°C), signed, `i32::MIN` if unknown |
+/// | 76     | 4    | power draw (mW), `u32::MAX` if unknown     |
+/// | 80     | 1    | power profile: 0 battery, 1 balanced, 2 performance, 255 if unknown |
+/// | 81     | 7    | reserved                                   |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GfxStats {
+    /// Number of the snapshot, increasing
+    pub sequence: u64,
+    pub timestamp_us: u64,
+    pub frame_time_avg_us: u32,
+    pub frame_time_max_us: u32,
+    pub frame_time_deviation_us: u32,
+    pub target_fps: u32,
+    pub gpu_busy_percent: Option<u8>,
+    pub vram_allocated: u64,
+    pub vram_reserved: u64,
+    pub vram_budget: Option<u64>,
+    pub upscaling: UpscalingTech,
+    pub upscaling_quality: Option<UpscalingQuality>,
+    pub vrr_paced: bool,
+    pub sensors: GpuSensors,
+}
+
+impl GfxStats {
+    /// Encode the snapshot
+    pub fn to_bytes(&self) -> [u8; SNAPSHOT_SIZE] {
+        let mut bytes = [0u8; SNAPSHOT_SIZE];
+        bytes[0..4].copy_from_slice(&SNAPSHOT_MAGIC.to_le_bytes());
+        bytes[4..6].copy_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bytes[6..8].copy_from_slice(&(SNAPSHOT_SIZE as u16).to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.frame_time_avg_us.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.frame_time_max_us.to_le_bytes());
+        bytes[32..36].copy_from_slice(&self.frame_time_deviation_us.to_le_bytes());
+        bytes[36..40].copy_from_slice(&self.target_fps.to_le_bytes());
+        bytes[40..48].copy_from_slice(&self.vram_allocated.to_le_bytes());
+        bytes[48..56].copy_from_slice(&self.vram_reserved.to_le_bytes());
+        bytes[56..64].copy_from_slice(&self.vram_budget.unwrap_or(0).to_le_bytes());
+        bytes[64] = self.gpu_busy_percent.unwrap_or(u8::MAX);
+        bytes[65] = match self.upscaling {
+            UpscalingTech::Native => 0,
+            UpscalingTech::FSR => 1,
+            UpscalingTech::DLSS => 2,
+            UpscalingTech::XeSS => 3,
+        };
+        bytes[66] = match self.upscaling_quality {
+            Some(UpscalingQuality::UltraPerformance) => 0,
+            Some(UpscalingQuality::Performance) => 1,
+            Some(UpscalingQuality::Balanced) => 2,
+            Some(UpscalingQuality::Quality) => 3,
+            Some(UpscalingQuality::UltraQuality) => 4,
+            None => u8::MAX,
+        };
+        bytes[67] = self.vrr_paced as u8;
+        let sensors = &self.sensors;
+        bytes[68..70].copy_from_slice(&sensors.clock_mhz.unwrap_or(0).to_le_bytes());
+        bytes[70..72].copy_from_slice(&sensors.fan_rpm.unwrap_or(u16::MAX).to_le_bytes());
+        bytes[72..76].copy_from_slice(&sensors.temperature_mc.unwrap_or(i32::MIN).to_le_bytes());
+        bytes[76..80].copy_from_slice(&sensors.power_mw.unwrap_or(u32::MAX).to_le_bytes());
+        bytes[80] = match sensors.power_profile {
+            Some(PowerProfile::Battery) => 0,
+            Some(PowerProfile::Balanced) => 1,
+            Some(PowerProfile::Performance) => 2,
+            None => u8::MAX,
+        };
+        bytes
+    }
+
+    /// Decode a snapshot, `None` if `bytes` isn't one
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; SNAPSHOT_SIZE] = bytes.get(..SNAPSHOT_SIZE)?.try_into().ok()?;
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        if u32_at(0) != SNAPSHOT_MAGIC || u16_at(4) != SNAPSHOT_VERSION {
+            return None;
+        }
+
+        Some(Self {
+            sequence: u64_at(8),
+            timestamp_us: u64_at(16),
+            frame_time_avg_us: u32_at(24),
+            frame_time_max_us: u32_at(28),
+            frame_time_deviation_us: u32_at(32),
+            target_fps: u32_at(36),
+            vram_allocated: u64_at(40),
+            vram_reserved: u64_at(48),
+            vram_budget: Some(u64_at(56)).filter(|&budget| budget != 0),
+            gpu_busy_percent: Some(bytes[64]).filter(|&busy| busy != u8::MAX),
+            upscaling: match bytes[65] {
+                1 => UpscalingTech::FSR,
+                2 => UpscalingTech::DLSS,
+                3 => UpscalingTech::XeSS,
+                _ => UpscalingTech::Native,
+            },
+            upscaling_quality: match bytes[66] {
+                0 => Some(UpscalingQuality::UltraPerformance),
+                1 => Some(UpscalingQuality::Performance),
+                2 => Some(UpscalingQuality::Balanced),
+                3 => Some(UpscalingQuality::Quality),
+                4 => Some(UpscalingQuality::UltraQuality),
+                _ => None,
+            },
+            vrr_paced: bytes[67] & 1 != 0,
+            sensors: GpuSensors {
+                clock_mhz: Some(u16_at(68)).filter(|&clock| clock != 0),
+                fan_rpm: Some(u16_at(70)).filter(|&rpm| rpm != u16::MAX),
+                temperature_mc: Some(u32_at(72) as i32).filter(|&temp| temp != i32::MIN),
+                power_mw: Some(u32_at(76)).filter(|&power| power != u32::MAX),
+                power_profile: match bytes[80] {
+                    0 => Some(PowerProfile::Battery),
+                    1 => Some(PowerProfile::Balanced),
+                    2 => Some(PowerProfile::Performance),
+                    _ => None,
+                },
+            },
+        })
+    }
+}
+
+#[derive(Default)]
+struct Sources {
+    pacer: Option<Arc<FramePacer>>,
+    gpu: Option<Arc<dyn GpuLoad>>,
+    sensors: Option<Arc<dyn GpuSensorSource>>,
+    vram: Option<Arc<dyn VramUsage>>,
+    upscaling: Option<Arc<UpscalingManager>>,
+}
+
+/// Aggregates performance data into snapshots
+///
+/// Sources are attached as they come up; missing ones read as zero or unknown.
+#[derive(Default)]
+pub struct Telemetry {
+    sources: Mutex<Sources>,
+    sequence: AtomicU64,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take frame times from `pacer`
+    pub fn set_frame_pacer(&self, pacer: Option<Arc<FramePacer>>) {
+        self.sources.lock().unwrap().pacer = pacer;
+    }
+
+    /// Take the GPU load from `gpu`
+    pub fn set_gpu_load(&self, gpu: Option<Arc<dyn GpuLoad>>) {
+        self.sources.lock().unwrap().gpu = gpu;
+    }
+
+    /// Take clocks, temperature and power from `sensors`
+    pub fn set_gpu_sensors(&self, sensors: Option<Arc<dyn GpuSensorSource>>) {
+        self.sources.lock().unwrap().sensors = sensors;
+    }
+
+    /// Take video memory use from `vram`, usually the application's [`DeviceAllocator`]
+    pub fn set_vram_usage(&self, vram: Option<Arc<dyn VramUsage>>) {
+        self.sources.lock().unwrap().vram = vram;
+    }
+
+    /// Take the upscaling mode from `upscaling`
+    pub fn set_upscaling(&self, upscaling: Option<Arc<UpscalingManager>>) {
+        self.sources.lock().unwrap().upscaling = upscaling;
+    }
+
+    /// Take a snapshot of the attached sources
+    pub fn snapshot(&self) -> GfxStats {
+        let sources = self.sources.lock().unwrap();
+        let micros =
+            |duration: std::time::Duration| duration.as_micros().min(u32::MAX as u128) as u32;
+
+        let mut stats = GfxStats {
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+            timestamp_us: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |time| time.as_micros() as u64),
+            frame_time_avg_us: 0,
+            frame_time_max_us: 0,
+            frame_time_deviation_us: 0,
+            target_fps: 0,
+            gpu_busy_percent: None,
+            vram_allocated: 0,
+            vram_reserved: 0,
+            vram_budget: None,
+            upscaling: UpscalingTech::Native,
+            upscaling_quality: None,
+            vrr_paced: false,
+            sensors: GpuSensors::default(),
+        };
+
+        if let Some(pacer) = &sources.pacer {
+            stats.frame_time_avg_us = micros(pacer.average_frame_time());
+            stats.frame_time_max_us = micros(pacer.max_frame_time());
+            stats.frame_time_deviation_us = (pacer.frame_time_variance() * 1e6) as u32;
+            stats.target_fps = pacer.target_fps().min(u32::MAX as u64) as u32;
+            stats.vrr_paced = pacer.is_vrr_paced();
+        }
+        if let Some(gpu) = &sources.gpu {
+            stats.gpu_busy_percent = gpu.busy_percent().map(|busy| busy.min(100));
+        }
+        if let Some(sensors) = &sources.sensors {
+            stats.sensors = sensors.gpu_sensors();
+        }
+        if let Some(vram) = &sources.vram {
+            let usage = vram.vram_usage();
+            stats.vram_allocated = usage.allocated_bytes;
+            stats.vram_reserved = usage.reserved_bytes;
+            stats.vram_budget = usage.budget;
+        }
+        if let Some(upscaling) = &sources.upscaling {
+            stats.upscaling = upscaling.current_technology();
+            stats.upscaling_quality = upscaling.current_quality();
+        }
+        stats
+    }
+}
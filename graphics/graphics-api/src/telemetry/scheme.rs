@@ -0,0 +1,113 @@
+//! `gfxstats:` scheme
+//!
+//! Reading `gfxstats:` from offset 0 returns a fresh snapshot encoded as described at
+//! [`super::GfxStats`]; seek back to 0 for the next one.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult, RequestKind, SignalBehavior, Socket};
+use syscall::schemev2::NewFdFlags;
+use syscall::{Error, Result, EBADF, ENOENT, EROFS};
+
+use super::{Telemetry, SNAPSHOT_SIZE};
+
+pub struct GfxStatsScheme {
+    telemetry: Arc<Telemetry>,
+    /// Snapshot last read by each handle
+    handles: BTreeMap<usize, [u8; SNAPSHOT_SIZE]>,
+    next_id: usize,
+}
+
+impl GfxStatsScheme {
+    pub fn new(telemetry: Arc<Telemetry>) -> Self {
+        Self {
+            telemetry,
+            handles: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn on_close(&mut self, id: usize) {
+        self.handles.remove(&id);
+    }
+}
+
+impl SchemeSync for GfxStatsScheme {
+    fn open(&mut self, path: &str, _flags: usize, _ctx: &CallerCtx) -> Result<OpenResult> {
+        if !path.trim_matches('/').is_empty() {
+            return Err(Error::new(ENOENT));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handles.insert(id, [0; SNAPSHOT_SIZE]);
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let snapshot = self.handles.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if offset == 0 {
+            *snapshot = self.telemetry.snapshot().to_bytes();
+        }
+
+        let start = (offset as usize).min(SNAPSHOT_SIZE);
+        let len = buf.len().min(SNAPSHOT_SIZE - start);
+        buf[..len].copy_from_slice(&snapshot[start..start + len]);
+        Ok(len)
+    }
+
+    fn write(
+        &mut self,
+        _id: usize,
+        _buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        Err(Error::new(EROFS))
+    }
+
+    fn fpath(&mut self, _id: usize, buf: &mut [u8], _ctx: &CallerCtx) -> Result<usize> {
+        let path = b"gfxstats:";
+        let len = buf.len().min(path.len());
+        buf[..len].copy_from_slice(&path[..len]);
+        Ok(len)
+    }
+}
+
+/// Serve `gfxstats:` from `telemetry` until the scheme is unmounted
+pub fn serve_gfxstats(telemetry: Arc<Telemetry>) -> Result<()> {
+    let socket = Socket::create("gfxstats")?;
+    let mut scheme = GfxStatsScheme::new(telemetry);
+
+    loop {
+        let Some(request) = socket.next_request(SignalBehavior::Restart)? else {
+            // Scheme likely got unmounted
+            return Ok(());
+        };
+
+        match request.kind() {
+            RequestKind::Call(call) => {
+                let response = call.handle_sync(&mut scheme);
+                socket.write_response(response, SignalBehavior::Restart)?;
+            }
+            RequestKind::OnClose { id } => {
+                scheme.on_close(id);
+            }
+            _ => (),
+        }
+    }
+}
@@ -101,6 +101,11 @@ impl FSR {
         }
     }
 
+    /// Get quality mode
+    pub fn quality(&self) -> UpscalingQuality {
+        self.quality
+    }
+
     /// Set sharpness (0.0 - 1.0)
     pub fn set_sharpness(&mut self, sharpness: f32) {
         self.sharpness = sharpness.clamp(0.0, 1.0);
@@ -203,6 +208,11 @@ impl DLSS {
         true
     }
 
+    /// Get quality mode
+    pub fn quality(&self) -> UpscalingQuality {
+        self.quality
+    }
+
     /// Enable DLSS Ray Reconstruction
     pub fn enable_ray_reconstruction(&mut self, enable: bool) {
         self.ray_reconstruction = enable;
@@ -324,6 +334,15 @@ impl UpscalingManager {
     pub fn current_technology(&self) -> UpscalingTech {
         *self.current_tech.lock().unwrap()
     }
+
+    /// Get quality mode of the current technology, `None` when rendering natively
+    pub fn current_quality(&self) -> Option<UpscalingQuality> {
+        match self.current_technology() {
+            UpscalingTech::FSR => Some(self.fsr.as_ref()?.lock().unwrap().quality()),
+            UpscalingTech::DLSS => Some(self.dlss.as_ref()?.lock().unwrap().quality()),
+            UpscalingTech::XeSS | UpscalingTech::Native => None,
+        }
+    }
 }
 
 /// Initialize upscaling subsystem
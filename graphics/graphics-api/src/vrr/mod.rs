@@ -0,0 +1,327 @@
+//! Variable Refresh Rate (VRR)
+//!
+//! Adaptive sync (VESA Adaptive-Sync, FreeSync, G-SYNC Compatible, HDMI VRR)
+//! lets the display wait for the next frame instead of scanning out at a
+//! fixed rate, so frame rates between the display's limits present without
+//! tearing or judder.
+//!
+//! GPU drivers implement [`VrrDisplay`] in their display module, usually on
+//! top of a [`VrrState`], which turns the range, the enable state and the
+//! per-frame present target into vertical total limits for the CRTC.
+//! Applications and the [`crate::FramePacer`] drive it through a
+//! [`VrrController`].
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Refresh rate range of an adaptive sync display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VrrRange {
+    /// Lowest refresh rate in Hz
+    pub min_hz: u32,
+    /// Highest refresh rate in Hz
+    pub max_hz: u32,
+}
+
+impl VrrRange {
+    /// Create a range, `None` unless `0 < min_hz < max_hz`
+    pub fn new(min_hz: u32, max_hz: u32) -> Option<Self> {
+        if min_hz == 0 || min_hz >= max_hz {
+            return None;
+        }
+        Some(Self { min_hz, max_hz })
+    }
+
+    /// Parse the Display Range Limits descriptor of an EDID base block
+    pub fn from_edid(edid: &[u8]) -> Option<Self> {
+        const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+        const RANGE_LIMITS: u8 = 0xFD;
+
+        if edid.len() < 128 || edid[..8] != HEADER {
+            return None;
+        }
+        (54..126).step_by(18).find_map(|offset| {
+            let descriptor = &edid[offset..offset + 18];
+            if descriptor[..3] != [0, 0, 0] || descriptor[3] != RANGE_LIMITS {
+                return None;
+            }
+            // EDID 1.4 adds 255 Hz to the limits flagged in byte 4
+            let flags = descriptor[4];
+            let min = u32::from(descriptor[5]) + if flags & 0x01 != 0 { 255 } else { 0 };
+            let max = u32::from(descriptor[6]) + if flags & 0x02 != 0 { 255 } else { 0 };
+            Self::new(min, max)
+        })
+    }
+
+    /// Shortest time between refreshes
+    pub fn min_frame_time(&self) -> Duration {
+        Duration::from_nanos(1_000_000_000 / u64::from(self.max_hz))
+    }
+
+    /// Longest time between refreshes
+    pub fn max_frame_time(&self) -> Duration {
+        Duration::from_nanos(1_000_000_000 / u64::from(self.min_hz))
+    }
+
+    /// Check if a frame rate can be shown with one refresh per frame
+    pub fn contains(&self, fps: f64) -> bool {
+        (f64::from(self.min_hz)..=f64::from(self.max_hz)).contains(&fps)
+    }
+
+    /// Check if frames slower than the range can be shown by refreshing
+    /// each of them several times (low framerate compensation)
+    pub fn supports_lfc(&self) -> bool {
+        self.max_hz >= 2 * self.min_hz
+    }
+
+    /// Refresh interval and refreshes per frame to show frames `frame_time`
+    /// apart
+    pub fn refresh_interval(&self, frame_time: Duration) -> (Duration, u32) {
+        let max = self.max_frame_time();
+        let repeats = if frame_time > max && self.supports_lfc() {
+            frame_time.as_nanos().div_ceil(max.as_nanos()) as u32
+        } else {
+            1
+        };
+        let interval = (frame_time / repeats).clamp(self.min_frame_time(), max);
+        (interval, repeats)
+    }
+}
+
+/// Adaptive sync flavour the display advertises
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VrrTechnology {
+    /// VESA DisplayPort Adaptive-Sync
+    AdaptiveSync,
+    /// AMD FreeSync
+    FreeSync,
+    /// NVIDIA G-SYNC Compatible
+    GSyncCompatible,
+    /// HDMI 2.1 VRR
+    HdmiVrr,
+}
+
+/// Scanout timing of the current mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayTiming {
+    /// Pixel clock in kHz
+    pub pixel_clock_khz: u32,
+    /// Pixels per line, blanking included
+    pub htotal: u32,
+    /// Lines per frame at the nominal refresh rate, blanking included
+    pub vtotal: u32,
+}
+
+impl DisplayTiming {
+    /// Time to scan out one line
+    pub fn line_time(&self) -> Duration {
+        Duration::from_nanos(u64::from(self.htotal) * 1_000_000 / u64::from(self.pixel_clock_khz))
+    }
+
+    /// Nominal refresh rate in Hz
+    pub fn refresh_hz(&self) -> f64 {
+        f64::from(self.pixel_clock_khz) * 1000.0 / (f64::from(self.htotal) * f64::from(self.vtotal))
+    }
+
+    /// Lines per frame for refreshes `frame_time` apart, never below the
+    /// nominal vertical total
+    pub fn vtotal_for(&self, frame_time: Duration) -> u32 {
+        let lines = frame_time.as_nanos() * u128::from(self.pixel_clock_khz)
+            / (u128::from(self.htotal) * 1_000_000);
+        (lines as u32).max(self.vtotal)
+    }
+}
+
+/// Display with adaptive sync control
+///
+/// Implemented by the display modules of the GPU drivers.
+pub trait VrrDisplay: Send + Sync {
+    /// Adaptive sync flavour, `None` if the display has none
+    fn vrr_technology(&self) -> Option<VrrTechnology>;
+
+    /// Refresh rate range, `None` if the display has no adaptive sync
+    fn vrr_range(&self) -> Option<VrrRange>;
+
+    /// Enable or disable adaptive sync
+    fn set_adaptive_sync(&self, enabled: bool) -> Result<(), &'static str>;
+
+    /// Check if adaptive sync is enabled
+    fn adaptive_sync_enabled(&self) -> bool;
+
+    /// Refresh at `target` rather than as soon as the next frame is ready,
+    /// within the display's range; `None` goes back to presenting frames
+    /// as they come
+    fn set_present_target(&self, target: Option<Instant>) -> Result<(), &'static str>;
+}
+
+/// Per-CRTC adaptive sync bookkeeping for driver display modules
+#[derive(Debug, Clone)]
+pub struct VrrState {
+    technology: Option<VrrTechnology>,
+    range: Option<VrrRange>,
+    timing: DisplayTiming,
+    enabled: bool,
+    target: Option<Instant>,
+    last_vblank: Option<Instant>,
+}
+
+impl VrrState {
+    /// State for a display with the given mode, adaptive sync disabled
+    pub fn new(
+        timing: DisplayTiming,
+        range: Option<VrrRange>,
+        technology: Option<VrrTechnology>,
+    ) -> Self {
+        Self {
+            technology: range.and(technology),
+            range,
+            timing,
+            enabled: false,
+            target: None,
+            last_vblank: None,
+        }
+    }
+
+    /// Adaptive sync flavour
+    pub fn technology(&self) -> Option<VrrTechnology> {
+        self.technology
+    }
+
+    /// Refresh rate range
+    pub fn range(&self) -> Option<VrrRange> {
+        self.range
+    }
+
+    /// Current mode timing
+    pub fn timing(&self) -> DisplayTiming {
+        self.timing
+    }
+
+    /// Check if adaptive sync is enabled
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable adaptive sync
+    pub fn set_enabled(&mut self, enabled: bool) -> Result<(), &'static str> {
+        if enabled && self.range.is_none() {
+            return Err("Display has no adaptive sync");
+        }
+        self.enabled = enabled;
+        if !enabled {
+            self.target = None;
+        }
+        Ok(())
+    }
+
+    /// Set the present target of the next frame
+    pub fn set_target(&mut self, target: Option<Instant>) -> Result<(), &'static str> {
+        if target.is_some() && !self.enabled {
+            return Err("Adaptive sync is disabled");
+        }
+        self.target = target;
+        Ok(())
+    }
+
+    /// Record the start of a vertical blank
+    pub fn vblank(&mut self, now: Instant) {
+        self.last_vblank = Some(now);
+        // A target in the past has been served
+        if self.target.is_some_and(|target| target <= now) {
+            self.target = None;
+        }
+    }
+
+    /// Vertical total limits to program, as `(min, max)` lines
+    ///
+    /// Fixed refresh uses the nominal total. With adaptive sync the CRTC
+    /// may stretch the frame up to the range's longest refresh, or, with a
+    /// present target, to exactly the target.
+    pub fn vtotal_limits(&self) -> (u32, u32) {
+        let nominal = self.timing.vtotal;
+        let Some(range) = self.range.filter(|_| self.enabled) else {
+            return (nominal, nominal);
+        };
+
+        match (self.target, self.last_vblank) {
+            (Some(target), Some(vblank)) => {
+                let (interval, _) =
+                    range.refresh_interval(target.saturating_duration_since(vblank));
+                let lines = self.timing.vtotal_for(interval);
+                (lines, lines)
+            }
+            _ => (
+                self.timing.vtotal_for(range.min_frame_time()),
+                self.timing.vtotal_for(range.max_frame_time()),
+            ),
+        }
+    }
+}
+
+/// Adaptive sync control of one display
+#[derive(Clone)]
+pub struct VrrController {
+    display: Arc<dyn VrrDisplay>,
+}
+
+impl VrrController {
+    /// Control `display`
+    pub fn new(display: Arc<dyn VrrDisplay>) -> Self {
+        Self { display }
+    }
+
+    /// Refresh rate range, `None` without adaptive sync
+    pub fn range(&self) -> Option<VrrRange> {
+        self.display.vrr_range()
+    }
+
+    /// Adaptive sync flavour
+    pub fn technology(&self) -> Option<VrrTechnology> {
+        self.display.vrr_technology()
+    }
+
+    /// Check if the display has adaptive sync
+    pub fn is_supported(&self) -> bool {
+        self.range().is_some()
+    }
+
+    /// Enable adaptive sync
+    pub fn enable(&self) -> Result<(), &'static str> {
+        self.display.set_adaptive_sync(true)?;
+        log::info!("Adaptive sync enabled: {:?}", self.range());
+        Ok(())
+    }
+
+    /// Disable adaptive sync
+    pub fn disable(&self) -> Result<(), &'static str> {
+        self.display.set_adaptive_sync(false)?;
+        log::info!("Adaptive sync disabled");
+        Ok(())
+    }
+
+    /// Check if adaptive sync is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.display.adaptive_sync_enabled()
+    }
+
+    /// Check if frames `frame_time` apart can be presented on time
+    pub fn can_pace(&self, frame_time: Duration) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        self.range().is_some_and(|range| {
+            let (interval, repeats) = range.refresh_interval(frame_time);
+            (interval * repeats).abs_diff(frame_time) < Duration::from_micros(100)
+        })
+    }
+
+    /// Present the next frame at `target`
+    pub fn present_at(&self, target: Instant) -> Result<(), &'static str> {
+        self.display.set_present_target(Some(target))
+    }
+
+    /// Present frames as soon as they are ready again
+    pub fn clear_present_target(&self) -> Result<(), &'static str> {
+        self.display.set_present_target(None)
+    }
+}
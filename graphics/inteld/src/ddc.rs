@@ -0,0 +1,278 @@
+//! Display data channel
+//!
+//! EDID is read from the sink over I2C at address 0x50: through the GMBUS
+//! controller for HDMI and DVI, and as I2C-over-AUX transactions on the
+//! DP AUX channel for DisplayPort.
+
+use driver_graphics::irq::Mmio;
+
+/// MMIO register access
+pub trait Registers: Send + Sync {
+    fn read(&self, reg: u32) -> u32;
+    fn write(&self, reg: u32, value: u32);
+}
+
+impl Registers for Mmio {
+    fn read(&self, reg: u32) -> u32 {
+        self.read32(reg)
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        self.write32(reg, value)
+    }
+}
+
+/// Size of an EDID block
+pub const EDID_BLOCK_SIZE: usize = 128;
+
+/// I2C address of the EDID EEPROM
+const EDID_ADDRESS: u32 = 0x50;
+
+/// Register polls before a transaction times out
+const POLL_LIMIT: u32 = 100_000;
+
+/// I2C bus to a sink
+pub trait Ddc {
+    /// Read `buf.len()` bytes from the EDID EEPROM, starting at `offset`
+    fn read(&self, offset: u8, buf: &mut [u8]) -> Result<(), &'static str>;
+}
+
+/// Read the EDID base block and its first extension block, if any
+///
+/// Further extensions need the E-DDC segment pointer and are left out.
+pub fn read_edid(ddc: &dyn Ddc) -> Result<Vec<u8>, &'static str> {
+    const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+    let mut edid = vec![0; EDID_BLOCK_SIZE];
+    ddc.read(0, &mut edid)?;
+    if edid[..8] != HEADER {
+        return Err("Invalid EDID header");
+    }
+    if !checksum_ok(&edid) {
+        return Err("Invalid EDID checksum");
+    }
+
+    if edid[126] > 0 {
+        let mut extension = [0; EDID_BLOCK_SIZE];
+        match ddc.read(EDID_BLOCK_SIZE as u8, &mut extension) {
+            Ok(()) if checksum_ok(&extension) => edid.extend_from_slice(&extension),
+            _ => log::warn!("Ignoring unreadable EDID extension block"),
+        }
+    }
+    Ok(edid)
+}
+
+fn checksum_ok(block: &[u8]) -> bool {
+    block.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn poll(regs: &dyn Registers, reg: u32, done: impl Fn(u32) -> bool) -> Result<u32, &'static str> {
+    for _ in 0..POLL_LIMIT {
+        let value = regs.read(reg);
+        if done(value) {
+            return Ok(value);
+        }
+        core::hint::spin_loop();
+    }
+    Err("DDC timeout")
+}
+
+/// GMBUS, the display engine's I2C controller on the PCH
+pub struct Gmbus<'a> {
+    regs: &'a dyn Registers,
+    /// GMBUS0 pin pair of the port
+    pin: u32,
+}
+
+impl<'a> Gmbus<'a> {
+    const GMBUS0: u32 = 0xC5100;
+    const GMBUS1: u32 = 0xC5104;
+    const GMBUS2: u32 = 0xC5108;
+    const GMBUS3: u32 = 0xC510C;
+
+    const SW_RDY: u32 = 1 << 30;
+    const CYCLE_STOP: u32 = 1 << 27;
+    const CYCLE_INDEX: u32 = 1 << 26;
+    const CYCLE_WAIT: u32 = 1 << 25;
+    const BYTE_COUNT_SHIFT: u32 = 16;
+    const INDEX_SHIFT: u32 = 8;
+    const READ: u32 = 1;
+
+    const HW_WAIT_PHASE: u32 = 1 << 14;
+    const HW_RDY: u32 = 1 << 11;
+    const NAK: u32 = 1 << 10;
+    const ACTIVE: u32 = 1 << 9;
+
+    pub fn new(regs: &'a dyn Registers, pin: u32) -> Self {
+        Self { regs, pin }
+    }
+
+    fn finish(&self) -> Result<(), &'static str> {
+        self.regs
+            .write(Self::GMBUS1, Self::SW_RDY | Self::CYCLE_STOP);
+        let result = poll(self.regs, Self::GMBUS2, |status| status & Self::ACTIVE == 0);
+        self.regs.write(Self::GMBUS0, 0);
+        result.map(|_| ())
+    }
+}
+
+impl Ddc for Gmbus<'_> {
+    fn read(&self, offset: u8, buf: &mut [u8]) -> Result<(), &'static str> {
+        // The byte count field is 9 bits
+        debug_assert!(buf.len() < 512);
+        self.regs.write(Self::GMBUS0, self.pin);
+        self.regs.write(
+            Self::GMBUS1,
+            Self::SW_RDY
+                | Self::CYCLE_INDEX
+                | Self::CYCLE_WAIT
+                | (buf.len() as u32) << Self::BYTE_COUNT_SHIFT
+                | u32::from(offset) << Self::INDEX_SHIFT
+                | EDID_ADDRESS << 1
+                | Self::READ,
+        );
+
+        for chunk in buf.chunks_mut(4) {
+            let status = poll(self.regs, Self::GMBUS2, |status| {
+                status & (Self::HW_RDY | Self::NAK) != 0
+            });
+            if status.map_or(true, |status| status & Self::NAK != 0) {
+                let _ = self.finish();
+                return Err("GMBUS: no acknowledge from sink");
+            }
+            let data = self.regs.read(Self::GMBUS3).to_le_bytes();
+            chunk.copy_from_slice(&data[..chunk.len()]);
+        }
+
+        poll(self.regs, Self::GMBUS2, |status| {
+            status & Self::HW_WAIT_PHASE != 0
+        })?;
+        self.finish()
+    }
+}
+
+/// DP AUX channel of a DDI
+pub struct DpAux<'a> {
+    regs: &'a dyn Registers,
+    /// DDI_AUX_CTL of the port
+    ctl: u32,
+}
+
+impl<'a> DpAux<'a> {
+    const SEND_BUSY: u32 = 1 << 31;
+    const DONE: u32 = 1 << 30;
+    const TIME_OUT_ERROR: u32 = 1 << 28;
+    const TIME_OUT_1600US: u32 = 3 << 26;
+    const RECEIVE_ERROR: u32 = 1 << 25;
+    const MESSAGE_SIZE_SHIFT: u32 = 20;
+    const MESSAGE_SIZE_MASK: u32 = 0x1F << 20;
+    const FW_SYNC_PULSE: u32 = (32 - 1) << 5;
+    const SYNC_PULSE: u32 = 32 - 1;
+
+    /// Bytes the five data registers hold
+    const MAX_MESSAGE: usize = 20;
+    /// Data bytes per I2C-over-AUX read
+    const MAX_PAYLOAD: usize = 16;
+
+    const I2C_WRITE: u8 = 0x0;
+    const I2C_READ: u8 = 0x1;
+    /// Middle of transaction, keeps the I2C bus from being stopped
+    const I2C_MOT: u8 = 0x4;
+
+    /// AUX channel of DDI `ddi` (0 for A)
+    pub fn new(regs: &'a dyn Registers, ddi: u32) -> Self {
+        Self {
+            regs,
+            ctl: 0x64010 + ddi * 0x100,
+        }
+    }
+
+    /// One AUX transaction, returning the reply with the reply code first
+    fn transfer(&self, request: &[u8]) -> Result<Vec<u8>, &'static str> {
+        debug_assert!(request.len() <= Self::MAX_MESSAGE);
+        for (index, word) in request.chunks(4).enumerate() {
+            let mut bytes = [0; 4];
+            bytes[..word.len()].copy_from_slice(word);
+            self.regs
+                .write(self.ctl + 4 + index as u32 * 4, u32::from_be_bytes(bytes));
+        }
+
+        self.regs.write(
+            self.ctl,
+            Self::SEND_BUSY
+                | Self::DONE
+                | Self::TIME_OUT_ERROR
+                | Self::TIME_OUT_1600US
+                | Self::RECEIVE_ERROR
+                | (request.len() as u32) << Self::MESSAGE_SIZE_SHIFT
+                | Self::FW_SYNC_PULSE
+                | Self::SYNC_PULSE,
+        );
+        let status = poll(self.regs, self.ctl, |ctl| ctl & Self::SEND_BUSY == 0)?;
+        // Clear the sticky status bits
+        self.regs.write(
+            self.ctl,
+            status | Self::DONE | Self::TIME_OUT_ERROR | Self::RECEIVE_ERROR,
+        );
+        if status & Self::TIME_OUT_ERROR != 0 {
+            return Err("AUX: timeout");
+        }
+        if status & Self::RECEIVE_ERROR != 0 {
+            return Err("AUX: receive error");
+        }
+
+        let size = ((status & Self::MESSAGE_SIZE_MASK) >> Self::MESSAGE_SIZE_SHIFT) as usize;
+        let size = size.min(Self::MAX_MESSAGE);
+        let mut reply = Vec::with_capacity(size);
+        for index in 0..size.div_ceil(4) {
+            let word = self.regs.read(self.ctl + 4 + index as u32 * 4);
+            reply.extend_from_slice(&word.to_be_bytes());
+        }
+        reply.truncate(size);
+        if reply.is_empty() {
+            return Err("AUX: empty reply");
+        }
+        // Native reply in bits 5:4, I2C reply in bits 7:6, 0 is ACK
+        if reply[0] & 0xF0 != 0 {
+            return Err("AUX: no acknowledge from sink");
+        }
+        Ok(reply)
+    }
+
+    fn header(command: u8, len: usize) -> [u8; 4] {
+        [
+            command << 4,
+            0,
+            EDID_ADDRESS as u8,
+            len.saturating_sub(1) as u8,
+        ]
+    }
+}
+
+impl Ddc for DpAux<'_> {
+    fn read(&self, offset: u8, buf: &mut [u8]) -> Result<(), &'static str> {
+        // Set the EEPROM's offset, keeping the bus for the reads
+        let mut request = Self::header(Self::I2C_WRITE | Self::I2C_MOT, 1).to_vec();
+        request.push(offset);
+        self.transfer(&request)?;
+
+        let mut read = 0;
+        while read < buf.len() {
+            let len = (buf.len() - read).min(Self::MAX_PAYLOAD);
+            let reply = self.transfer(&Self::header(Self::I2C_READ | Self::I2C_MOT, len))?;
+            // Sinks may return fewer bytes than asked for
+            let data = &reply[1..];
+            let count = data.len().min(len);
+            if count == 0 {
+                return Err("AUX: short read");
+            }
+            buf[read..read + count].copy_from_slice(&data[..count]);
+            read += count;
+        }
+
+        // Address-only read without MOT stops the I2C transaction
+        let stop = Self::header(Self::I2C_READ, 0);
+        self.transfer(&stop[..3])?;
+        Ok(())
+    }
+}
@@ -1,12 +1,51 @@
 //! Intel GPU device management
 
 use std::sync::Arc;
+use std::time::Instant;
+
+use crate::ddc::Registers;
+
+/// Interrupt registers (Gen11+)
+mod intr {
+    /// Master control, cleared while interrupts are handled
+    pub const GFX_MSTR_IRQ: u32 = 0x19_0010;
+    pub const MASTER_IRQ: u32 = 1 << 31;
+    pub const DISPLAY_IRQ: u32 = 1 << 16;
+    pub const GT_DW0_IRQ: u32 = 1 << 0;
+
+    /// Pending GT engine interrupts, a bit per engine
+    pub const GT_INTR_DW0: u32 = 0x19_0018;
+    pub const RCS0: u32 = 0;
+    /// Selects the engine whose identity is read
+    pub const IIR_REG0_SELECTOR: u32 = 0x19_0070;
+    pub const INTR_IDENTITY_REG0: u32 = 0x19_0060;
+    pub const IDENTITY_VALID: u32 = 1 << 31;
+    pub const RENDER_COPY_INTR_ENABLE: u32 = 0x19_0030;
+    pub const RCS0_RSVD_INTR_MASK: u32 = 0x19_0090;
+    pub const GT_RENDER_USER_INTERRUPT: u32 = 1 << 0;
+
+    pub const DISPLAY_INT_CTL: u32 = 0x4_4200;
+    pub const DISPLAY_IRQ_ENABLE: u32 = 1 << 31;
+    pub const DE_PIPE_A_IRQ: u32 = 1 << 16;
+    pub const DE_PCH_IRQ: u32 = 1 << 23;
+    pub const DE_PIPE_A_IMR: u32 = 0x4_4404;
+    pub const DE_PIPE_A_IIR: u32 = 0x4_4408;
+    pub const DE_PIPE_A_IER: u32 = 0x4_440C;
+    pub const PIPE_VBLANK: u32 = 1 << 0;
+
+    /// South display engine, which signals hotplug
+    pub const SDEIMR: u32 = 0xC_4004;
+    pub const SDEIIR: u32 = 0xC_4008;
+    pub const SDEIER: u32 = 0xC_400C;
+}
 
 pub struct IntelDevice {
     vendor_id: u16,
     device_id: u16,
     generation: u8,
     gem: Option<Arc<crate::gem::GemManager>>,
+    display: Arc<crate::display::IntelDisplay>,
+    guc: crate::guc::Guc,
 }
 
 impl IntelDevice {
@@ -16,6 +55,8 @@ impl IntelDevice {
             device_id: 0x0000,
             generation: 12, // Gen12 (Xe)
             gem: None,
+            display: Arc::new(crate::display::IntelDisplay::new()),
+            guc: crate::guc::Guc::new(),
         })
     }
 
@@ -31,6 +72,7 @@ impl IntelDevice {
 
     pub fn load_firmware(&self) -> Result<(), &'static str> {
         log::info!("GuC/HuC firmware loaded");
+        self.guc.enable_scheduling();
         Ok(())
     }
 
@@ -58,39 +100,511 @@ impl IntelDevice {
         Ok(())
     }
 
+    /// Unmask the render engine's user interrupt, pipe A vblank and port
+    /// hotplug, then enable interrupts
+    pub fn init_interrupts(&self, regs: &dyn Registers) {
+        let user = intr::GT_RENDER_USER_INTERRUPT;
+        regs.write(intr::RENDER_COPY_INTR_ENABLE, user << 16);
+        regs.write(intr::RCS0_RSVD_INTR_MASK, !(user << 16));
+
+        regs.write(intr::DE_PIPE_A_IMR, !intr::PIPE_VBLANK);
+        regs.write(intr::DE_PIPE_A_IER, intr::PIPE_VBLANK);
+        let hotplug = self.display.hotplug_bits();
+        regs.write(intr::SDEIMR, !hotplug);
+        regs.write(intr::SDEIER, hotplug);
+        regs.write(intr::DISPLAY_INT_CTL, intr::DISPLAY_IRQ_ENABLE);
+
+        regs.write(intr::GFX_MSTR_IRQ, intr::MASTER_IRQ);
+        log::info!("Interrupts enabled");
+    }
+
+    /// Handle an interrupt, returns whether the GPU raised it
+    pub fn handle_interrupt(&self, regs: &dyn Registers) -> bool {
+        regs.write(intr::GFX_MSTR_IRQ, 0);
+        let master = regs.read(intr::GFX_MSTR_IRQ);
+        if master & !intr::MASTER_IRQ == 0 {
+            regs.write(intr::GFX_MSTR_IRQ, intr::MASTER_IRQ);
+            return false;
+        }
+
+        if master & intr::GT_DW0_IRQ != 0 {
+            let engines = regs.read(intr::GT_INTR_DW0);
+            for engine in (0..32).filter(|bit| engines & (1 << bit) != 0) {
+                let identity = Self::engine_identity(regs, engine);
+                if engine == intr::RCS0 && identity & intr::GT_RENDER_USER_INTERRUPT != 0 {
+                    self.process_events();
+                }
+            }
+            regs.write(intr::GT_INTR_DW0, engines);
+        }
+
+        if master & intr::DISPLAY_IRQ != 0 {
+            let display = regs.read(intr::DISPLAY_INT_CTL);
+            if display & intr::DE_PIPE_A_IRQ != 0 {
+                let iir = regs.read(intr::DE_PIPE_A_IIR);
+                regs.write(intr::DE_PIPE_A_IIR, iir);
+                if iir & intr::PIPE_VBLANK != 0 {
+                    self.display.vblank(Instant::now());
+                }
+            }
+            if display & intr::DE_PCH_IRQ != 0 {
+                let iir = regs.read(intr::SDEIIR);
+                regs.write(intr::SDEIIR, iir);
+                self.display.hotplug(iir, regs);
+            }
+        }
+
+        regs.write(intr::GFX_MSTR_IRQ, intr::MASTER_IRQ);
+        true
+    }
+
+    /// Read and clear the interrupt identity of a GT engine
+    fn engine_identity(regs: &dyn Registers, engine: u32) -> u32 {
+        regs.write(intr::IIR_REG0_SELECTOR, 1 << engine);
+        // The identity takes a few cycles to latch
+        for _ in 0..100 {
+            let identity = regs.read(intr::INTR_IDENTITY_REG0);
+            if identity & intr::IDENTITY_VALID != 0 {
+                regs.write(intr::INTR_IDENTITY_REG0, identity);
+                return identity & 0xFFFF;
+            }
+            core::hint::spin_loop();
+        }
+        log::warn!("GT engine {} interrupt identity not valid", engine);
+        0
+    }
+
+    /// Process events
+    ///
+    /// Runs on render completion interrupts; no requests are tracked yet.
     pub fn process_events(&self) {}
     pub fn process_submissions(&self) {}
 
+    pub fn display(&self) -> &Arc<crate::display::IntelDisplay> {
+        &self.display
+    }
+
     pub fn gem(&self) -> Option<&Arc<crate::gem::GemManager>> {
         self.gem.as_ref()
     }
+
+    pub fn guc(&self) -> &crate::guc::Guc {
+        &self.guc
+    }
 }
 
 // Stub modules
-pub mod gtt {}
 pub mod execbuf {}
 pub mod ring {}
 pub mod context {}
-pub mod display {}
-pub mod guc {}
 pub mod huc {}
 
 pub mod gal_backend {
     use crate::device::IntelDevice;
-    use std::sync::Arc;
+    use crate::gem::GemFlags;
+    use crate::guc::EngineClass;
+    use driver_graphics::external::{ImportedFence, ImportedMemory};
+    use gal::device::DisplayInfo;
+    use gal::{
+        DeviceCapabilities, DisplayMode, DisplayTarget, Error, Extent2D, ExternalFence,
+        ExternalImageLayout, ExternalMemory, Fence, Image, ImageDescriptor, ImageFormat,
+        ImageUsage, PresentMode, QueuePriority, QueueType, Rect2D, ScanoutImage, Semaphore,
+    };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Formats the primary plane scans out (XRGB8888, XBGR8888)
+    const SCANOUT_FORMATS: &[ImageFormat] = &[ImageFormat::Bgra8Unorm, ImageFormat::Rgba8Unorm];
+
+    /// PLANE_STRIDE is programmed in 64 byte units for linear surfaces
+    const SCANOUT_STRIDE_ALIGNMENT: u32 = 64;
+
+    /// Size of a logical ring context image, the largest (render) one
+    const LRC_SIZE: usize = 22 * 4096;
+
+    /// GuC context behind one of the backend's queues
+    #[derive(Debug, Clone, Copy)]
+    struct QueueContext {
+        guc_id: u32,
+        /// GEM object holding the logical ring context
+        lrc: u32,
+        priority: QueuePriority,
+    }
 
     pub struct IntelGalBackend {
         device: Arc<IntelDevice>,
+        /// Graphics, compute and transfer queues, registered on first use
+        queues: Mutex<[Option<QueueContext>; 3]>,
+        /// Memory other drivers rendered offloaded frames into, by GEM
+        /// handle
+        offloaded: Mutex<HashMap<u32, ImportedMemory>>,
     }
 
     impl IntelGalBackend {
         pub fn new(device: Arc<IntelDevice>) -> Self {
-            Self { device }
+            Self {
+                device,
+                queues: Mutex::new([None; 3]),
+                offloaded: Mutex::new(HashMap::new()),
+            }
+        }
+
+        /// Capabilities of the backend, as `gal::DeviceInfo` reports them
+        pub fn capabilities(&self) -> DeviceCapabilities {
+            DeviceCapabilities::RENDER_3D
+                | DeviceCapabilities::COMPUTE
+                | DeviceCapabilities::EXTERNAL_MEMORY
+                | DeviceCapabilities::EXTERNAL_FENCE
         }
 
         pub fn register(&self) -> Result<(), &'static str> {
             log::info!("Registered with kernel GAL");
             Ok(())
         }
+
+        /// Scheduling priority of the backend's queue of `queue_type`
+        pub fn queue_priority(&self, queue_type: QueueType) -> QueuePriority {
+            self.queues.lock().unwrap()[Self::queue_index(queue_type)]
+                .map_or(QueuePriority::Normal, |queue| queue.priority)
+        }
+
+        /// Change the scheduling priority of the backend's queue of
+        /// `queue_type`
+        ///
+        /// The GuC preempts work of lower priority queues for it, mid-batch
+        /// or, for compute, mid-thread.
+        pub fn set_queue_priority(
+            &self,
+            queue_type: QueueType,
+            priority: QueuePriority,
+        ) -> gal::Result<()> {
+            let mut queues = self.queues.lock().unwrap();
+            let slot = &mut queues[Self::queue_index(queue_type)];
+            match slot {
+                Some(queue) => {
+                    self.device
+                        .guc()
+                        .set_priority(queue.guc_id, priority)
+                        .map_err(|_| Error::OperationFailed)?;
+                    queue.priority = priority;
+                }
+                None => *slot = Some(self.register_queue(queue_type, priority)?),
+            }
+            Ok(())
+        }
+
+        /// GuC id of the context behind the queue of `queue_type`
+        pub fn queue_context(&self, queue_type: QueueType) -> gal::Result<u32> {
+            let mut queues = self.queues.lock().unwrap();
+            let slot = &mut queues[Self::queue_index(queue_type)];
+            let queue = match *slot {
+                Some(queue) => queue,
+                None => *slot.insert(self.register_queue(queue_type, QueuePriority::Normal)?),
+            };
+            Ok(queue.guc_id)
+        }
+
+        fn queue_index(queue_type: QueueType) -> usize {
+            match queue_type {
+                QueueType::Graphics => 0,
+                QueueType::Compute => 1,
+                QueueType::Transfer => 2,
+            }
+        }
+
+        fn register_queue(
+            &self,
+            queue_type: QueueType,
+            priority: QueuePriority,
+        ) -> gal::Result<QueueContext> {
+            let class = match queue_type {
+                QueueType::Graphics => EngineClass::Render,
+                QueueType::Compute => EngineClass::Compute,
+                QueueType::Transfer => EngineClass::Copy,
+            };
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let lrc = gem
+                .alloc(LRC_SIZE, GemFlags::GPU_ACCESS)
+                .map_err(|_| Error::OutOfDeviceMemory)?;
+            let lrca = gem.get(lrc).ok_or(Error::OperationFailed)?.gtt_offset;
+            let guc_id = self.device.guc().register_context(class, lrca, priority);
+            Ok(QueueContext {
+                guc_id,
+                lrc,
+                priority,
+            })
+        }
+
+        /// Export the GEM object behind an image, for the compositor or
+        /// another device to import
+        ///
+        /// The memory's `fd` is the object's global GEM name; it stays
+        /// valid until the image is destroyed.
+        pub fn export_image(
+            &self,
+            image: &dyn Image,
+        ) -> gal::Result<(ExternalMemory, ExternalImageLayout)> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let object = gem
+                .get(image.handle() as u32)
+                .ok_or(Error::InvalidParameter)?;
+            let mut layout = ExternalImageLayout::linear(
+                image.format(),
+                image.extent_2d(),
+                SCANOUT_STRIDE_ALIGNMENT,
+            )
+            .ok_or(Error::NotSupported)?;
+            layout.modifier = object.tiling.modifier();
+
+            let name = gem.export(object.handle).map_err(|e| {
+                log::warn!("Failed to export image: {}", e);
+                Error::InvalidParameter
+            })?;
+            let memory = ExternalMemory {
+                fd: name as usize,
+                size: object.size as u64,
+            };
+            Ok((memory, layout))
+        }
+
+        /// Create an image on a GEM object exported by another process
+        pub fn import_image(
+            &self,
+            memory: &ExternalMemory,
+            layout: &ExternalImageLayout,
+            usage: ImageUsage,
+        ) -> gal::Result<Box<dyn Image>> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let name = u32::try_from(memory.fd).map_err(|_| Error::InvalidParameter)?;
+            if layout.size() > memory.size || layout.offset != 0 {
+                return Err(Error::InvalidParameter);
+            }
+
+            let handle = gem.import(name).map_err(|_| Error::InvalidParameter)?;
+            let object = gem.get(handle).ok_or(Error::InvalidParameter)?;
+            let expected =
+                ExternalImageLayout::linear(layout.format, layout.extent, SCANOUT_STRIDE_ALIGNMENT);
+            if layout.modifier != object.tiling.modifier()
+                || expected.map(|e| e.stride) != Some(layout.stride)
+                || layout.size() > object.size as u64
+            {
+                let _ = gem.free(handle);
+                return Err(Error::NotSupported);
+            }
+
+            let desc = ImageDescriptor::new_2d(
+                layout.extent.width,
+                layout.extent.height,
+                layout.format,
+                usage,
+            );
+            Ok(Box::new(ScanoutImage::new(handle as usize, &desc)))
+        }
+
+        /// Create an image on memory another GPU's driver exported, for
+        /// render offload
+        ///
+        /// The pages stay in the exporter's system memory and are bound
+        /// into the GTT, so the display engine scans them out directly.
+        pub fn import_offload_image(
+            &self,
+            memory: &ExternalMemory,
+            layout: &ExternalImageLayout,
+            usage: ImageUsage,
+        ) -> gal::Result<Box<dyn Image>> {
+            let row = layout
+                .format
+                .bytes_per_pixel()
+                .and_then(|bytes| layout.extent.width.checked_mul(bytes))
+                .ok_or(Error::NotSupported)?;
+            if layout.modifier != ExternalImageLayout::LINEAR
+                || layout.stride % SCANOUT_STRIDE_ALIGNMENT != 0
+                || layout.stride < row
+            {
+                return Err(Error::NotSupported);
+            }
+            if layout.offset != 0 || layout.size() > memory.size {
+                return Err(Error::InvalidParameter);
+            }
+
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let mapping = ImportedMemory::map(memory)?;
+            let pages = mapping.pages()?;
+            let handle = gem
+                .import_pages(&pages, mapping.len(), mapping.as_ptr() as usize)
+                .map_err(|_| Error::OutOfDeviceMemory)?;
+            self.offloaded.lock().unwrap().insert(handle, mapping);
+
+            let desc = ImageDescriptor::new_2d(
+                layout.extent.width,
+                layout.extent.height,
+                layout.format,
+                usage,
+            );
+            Ok(Box::new(ScanoutImage::new(handle as usize, &desc)))
+        }
+
+        /// Import a fence exported by another driver, to wait for an
+        /// offloaded frame before flipping to it
+        pub fn import_fence(&self, fence: &ExternalFence) -> gal::Result<Box<dyn Fence>> {
+            Ok(Box::new(ImportedFence::new(fence)?))
+        }
+    }
+
+    impl Drop for IntelGalBackend {
+        fn drop(&mut self) {
+            let queues = self.queues.get_mut().unwrap();
+            for queue in queues.iter().flatten() {
+                let _ = self.device.guc().deregister_context(queue.guc_id);
+                if let Some(gem) = self.device.gem() {
+                    let _ = gem.free(queue.lrc);
+                }
+            }
+        }
+    }
+
+    /// Presentation on the display, with scanout images in GEM objects
+    impl DisplayTarget for IntelGalBackend {
+        fn info(&self) -> DisplayInfo {
+            let display = self.device.display();
+            let (width, height) = display.resolution();
+            DisplayInfo {
+                id: 0,
+                name: display
+                    .name()
+                    .unwrap_or_else(|| String::from("Intel Display 0")),
+                extent: Extent2D::new(width, height),
+                refresh_rate: display.refresh_hz(),
+                is_primary: true,
+                enabled: display.connected(),
+            }
+        }
+
+        fn formats(&self) -> &[ImageFormat] {
+            SCANOUT_FORMATS
+        }
+
+        fn supports_present_mode(&self, _mode: PresentMode) -> bool {
+            true
+        }
+
+        fn create_image(
+            &self,
+            extent: Extent2D,
+            format: ImageFormat,
+        ) -> gal::Result<Box<dyn Image>> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let layout = ExternalImageLayout::linear(format, extent, SCANOUT_STRIDE_ALIGNMENT)
+                .ok_or(Error::NotSupported)?;
+            // Shareable, so clients can hand their frames to the compositor
+            let handle = gem
+                .alloc(
+                    layout.size() as usize,
+                    GemFlags::GPU_ACCESS | GemFlags::SHAREABLE,
+                )
+                .map_err(|_| Error::OutOfDeviceMemory)?;
+
+            let desc = ImageDescriptor::scanout(extent.width, extent.height, format);
+            Ok(Box::new(ScanoutImage::new(handle as usize, &desc)))
+        }
+
+        fn destroy_image(&self, image: Box<dyn Image>) {
+            if let Some(gem) = self.device.gem() {
+                let handle = image.handle() as u32;
+                if let Some(name) = gem.exported_name(handle) {
+                    let _ = gem.close_name(name);
+                }
+                if let Err(e) = gem.free(handle) {
+                    log::warn!("Failed to free scanout image: {}", e);
+                }
+            }
+            // Unmapped only once the GTT no longer points at the pages
+            self.offloaded
+                .lock()
+                .unwrap()
+                .remove(&(image.handle() as u32));
+        }
+
+        /// The plane scans out the whole surface, so damage is of no use
+        fn flip(&self, image: &dyn Image, _damage: &[Rect2D]) -> gal::Result<()> {
+            let gem = self.device.gem().ok_or(Error::OperationFailed)?;
+            let object = gem
+                .get(image.handle() as u32)
+                .ok_or(Error::InvalidParameter)?;
+            self.device.display().flip(object.gtt_offset);
+            Ok(())
+        }
+
+        fn flip_pending(&self) -> bool {
+            self.device.display().flip_pending()
+        }
+
+        fn flip_timestamp_ns(&self) -> Option<u64> {
+            let elapsed = self.device.display().flipped_at()?.elapsed();
+            gal::display::monotonic_ns()?.checked_sub(elapsed.as_nanos() as u64)
+        }
+
+        fn wait_vblank(&self, timeout_ns: u64) -> gal::Result<bool> {
+            Ok(self
+                .device
+                .display()
+                .wait_vblank(Duration::from_nanos(timeout_ns)))
+        }
+
+        // The driver has no GAL semaphores or fences of its own yet
+
+        fn wait_semaphores(&self, semaphores: &[&dyn Semaphore]) -> gal::Result<()> {
+            if semaphores.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::NotSupported)
+            }
+        }
+
+        fn signal(
+            &self,
+            semaphore: Option<&dyn Semaphore>,
+            fence: Option<&dyn Fence>,
+        ) -> gal::Result<()> {
+            if semaphore.is_none() && fence.is_none() {
+                Ok(())
+            } else {
+                Err(Error::NotSupported)
+            }
+        }
+
+        fn modes(&self) -> Vec<DisplayMode> {
+            self.device
+                .display()
+                .modes()
+                .iter()
+                .map(|mode| DisplayMode {
+                    extent: Extent2D::new(mode.hactive, mode.vactive),
+                    refresh_mhz: mode.refresh_mhz(),
+                    preferred: mode.preferred,
+                })
+                .collect()
+        }
+
+        fn set_mode(&self, mode: &DisplayMode) -> gal::Result<()> {
+            let display = self.device.display();
+            let target = display
+                .modes()
+                .into_iter()
+                .find(|m| {
+                    Extent2D::new(m.hactive, m.vactive) == mode.extent
+                        && m.refresh_mhz() == mode.refresh_mhz
+                })
+                .ok_or(Error::NotSupported)?;
+            display.set_mode(&target).map_err(|e| {
+                log::warn!("Failed to set mode: {}", e);
+                Error::OperationFailed
+            })
+        }
+
+        fn configuration_serial(&self) -> u64 {
+            self.device.display().serial()
+        }
     }
 }
@@ -0,0 +1,406 @@
+//! Display engine
+//!
+//! Hotplug interrupts on a port make the driver read the sink's EDID; a
+//! new sink is driven in its preferred mode, and the transcoder is turned
+//! off when it goes away. Each change bumps the configuration serial the
+//! GAL backend reports, so compositors can follow.
+
+use crate::ddc::{self, DpAux, Gmbus, Registers};
+use crate::edid::{self, Mode};
+use driver_graphics::scanout::ScanoutLog;
+use graphics_api::{DisplayTiming, VrrDisplay, VrrRange, VrrState, VrrTechnology};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// CEA 1920x1080@60 timing, used until a sink is probed
+const DEFAULT_TIMING: DisplayTiming = DisplayTiming {
+    pixel_clock_khz: 148_500,
+    htotal: 2200,
+    vtotal: 1125,
+};
+
+/// Mode of [`DEFAULT_TIMING`], as set up by the firmware
+const DEFAULT_MODE: Mode = Mode {
+    hactive: 1920,
+    vactive: 1080,
+    timing: DEFAULT_TIMING,
+    preferred: true,
+};
+
+/// Display port (DDI)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// Combo PHY port, 0 for DDI A
+    Combo(u32),
+    /// Type-C port, 0 for TC1
+    TypeC(u32),
+}
+
+impl Port {
+    /// Ports of Gen12 (Tiger Lake) display engines
+    pub const ALL: [Port; 9] = [
+        Port::Combo(0),
+        Port::Combo(1),
+        Port::Combo(2),
+        Port::TypeC(0),
+        Port::TypeC(1),
+        Port::TypeC(2),
+        Port::TypeC(3),
+        Port::TypeC(4),
+        Port::TypeC(5),
+    ];
+
+    /// Bit of the port in SDE_ISR
+    pub fn hotplug_bit(self) -> u32 {
+        match self {
+            Port::Combo(index) => 1 << (16 + index),
+            Port::TypeC(index) => 1 << (24 + index),
+        }
+    }
+
+    /// DDI index, for the AUX channel
+    fn ddi(self) -> u32 {
+        match self {
+            Port::Combo(index) => index,
+            Port::TypeC(index) => 3 + index,
+        }
+    }
+
+    /// GMBUS0 pin pair
+    fn gmbus_pin(self) -> u32 {
+        match self {
+            Port::Combo(index) => 1 + index,
+            Port::TypeC(index) => 9 + index,
+        }
+    }
+
+    /// Read the sink's EDID, over DP AUX or, for HDMI sinks, GMBUS
+    fn read_edid(self, regs: &dyn Registers) -> Result<Vec<u8>, &'static str> {
+        ddc::read_edid(&DpAux::new(regs, self.ddi()))
+            .or_else(|_| ddc::read_edid(&Gmbus::new(regs, self.gmbus_pin())))
+    }
+}
+
+/// Sink on the transcoder's port
+#[derive(Debug, Clone)]
+struct Output {
+    /// Port of the sink, `None` for the display the firmware set up
+    port: Option<Port>,
+    connected: bool,
+    name: Option<String>,
+    modes: Vec<Mode>,
+    mode: Mode,
+}
+
+/// Primary plane surfaces, as GTT offsets
+#[derive(Debug, Default)]
+struct Plane {
+    /// PLANE_SURF written since the last vertical blank, latched at the next
+    pending: Option<u64>,
+    /// Vertical blanks so far
+    vblanks: u64,
+    /// Vertical blank that latched the last flip
+    flipped_at: Option<Instant>,
+}
+
+/// TRANS_VRR_VMIN / TRANS_VRR_VMAX, in lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct VrrVTotal {
+    min: u32,
+    max: u32,
+}
+
+/// Transcoder driving one display
+///
+/// With TRANS_VRR_CTL enabled the transcoder holds the vertical blank
+/// between VRR_VMIN and VRR_VMAX lines until a flip arrives; a present
+/// target pins both to the length of that one frame.
+///
+/// The transcoder drives one sink at a time, the first one plugged in.
+pub struct IntelDisplay {
+    vrr: Mutex<VrrState>,
+    v_total: Mutex<VrrVTotal>,
+    plane: Mutex<Plane>,
+    vblank_event: Condvar,
+    output: Mutex<Output>,
+    /// Bumped on every hotplug and mode change
+    serial: AtomicU64,
+    scanout: Arc<ScanoutLog>,
+}
+
+impl IntelDisplay {
+    pub fn new() -> Self {
+        let vrr = VrrState::new(DEFAULT_TIMING, None, None);
+        let display = Self {
+            vrr: Mutex::new(vrr),
+            v_total: Mutex::new(VrrVTotal::default()),
+            plane: Mutex::new(Plane::default()),
+            vblank_event: Condvar::new(),
+            output: Mutex::new(Output {
+                port: None,
+                connected: true,
+                name: None,
+                modes: vec![DEFAULT_MODE],
+                mode: DEFAULT_MODE,
+            }),
+            serial: AtomicU64::new(0),
+            scanout: Arc::new(ScanoutLog::new()),
+        };
+        display.program(&display.vrr.lock().unwrap());
+        display
+    }
+
+    /// SDE interrupt bits of every port's hotplug
+    pub fn hotplug_bits(&self) -> u32 {
+        Port::ALL
+            .iter()
+            .fold(0, |bits, port| bits | port.hotplug_bit())
+    }
+
+    /// Probe every port, as if each had signaled a hotplug
+    pub fn detect(&self, regs: &dyn Registers) {
+        self.hotplug(self.hotplug_bits(), regs);
+    }
+
+    /// Handle the hotplug interrupt, `isr` holding the SDE_ISR bits of the
+    /// ports that signaled
+    ///
+    /// A port whose sink answers EDID reads has a display connected.
+    pub fn hotplug(&self, isr: u32, regs: &dyn Registers) {
+        for port in Port::ALL {
+            if isr & port.hotplug_bit() == 0 {
+                continue;
+            }
+            match port.read_edid(regs) {
+                Ok(edid) => self.probe(port, &edid),
+                Err(e) => {
+                    log::debug!("{:?}: no sink ({})", port, e);
+                    self.unplug(port);
+                }
+            }
+        }
+    }
+
+    /// Set up the transcoder for a new sink on `port`: drive it in its
+    /// preferred mode, with its Adaptive-Sync range, both from EDID
+    pub fn probe(&self, port: Port, edid: &[u8]) {
+        let mut output = self.output.lock().unwrap();
+        if output.connected && output.port.is_some_and(|current| current != port) {
+            log::info!(
+                "{:?}: sink ignored, the transcoder drives {:?}",
+                port,
+                output.port
+            );
+            return;
+        }
+
+        let modes = edid::modes(edid);
+        let Some(&preferred) = modes.first() else {
+            log::warn!("{:?}: EDID lists no usable mode", port);
+            return;
+        };
+        let name = edid::name(edid);
+        log::info!(
+            "{:?}: {} connected, {} modes, preferred {}x{}@{}mHz",
+            port,
+            name.as_deref().unwrap_or("display"),
+            modes.len(),
+            preferred.hactive,
+            preferred.vactive,
+            preferred.refresh_mhz()
+        );
+
+        let range = VrrRange::from_edid(edid);
+        match range {
+            Some(range) => log::info!("Adaptive-Sync range: {}-{} Hz", range.min_hz, range.max_hz),
+            None => log::info!("Sink has no Adaptive-Sync range"),
+        }
+        *self.vrr.lock().unwrap() =
+            VrrState::new(preferred.timing, range, Some(VrrTechnology::AdaptiveSync));
+
+        *output = Output {
+            port: Some(port),
+            connected: true,
+            name,
+            modes,
+            mode: preferred,
+        };
+        self.modeset(&preferred);
+        self.serial.fetch_add(1, Ordering::Release);
+    }
+
+    /// Turn the transcoder off if the sink on `port` was driven
+    fn unplug(&self, port: Port) {
+        let mut output = self.output.lock().unwrap();
+        if !output.connected || output.port != Some(port) {
+            return;
+        }
+        log::info!("{:?}: sink disconnected", port);
+        log::debug!("TRANS_CONF=0 PLANE_CTL=0");
+        output.connected = false;
+        self.plane.lock().unwrap().pending = None;
+        self.serial.fetch_add(1, Ordering::Release);
+    }
+
+    /// Whether a sink is connected
+    pub fn connected(&self) -> bool {
+        self.output.lock().unwrap().connected
+    }
+
+    /// Name of the sink, from EDID
+    pub fn name(&self) -> Option<String> {
+        self.output.lock().unwrap().name.clone()
+    }
+
+    /// Modes of the sink, the preferred one first
+    pub fn modes(&self) -> Vec<Mode> {
+        self.output.lock().unwrap().modes.clone()
+    }
+
+    /// Current mode
+    pub fn mode(&self) -> Mode {
+        self.output.lock().unwrap().mode
+    }
+
+    /// Drive the sink in `mode`, one of [`IntelDisplay::modes`]
+    pub fn set_mode(&self, mode: &Mode) -> Result<(), &'static str> {
+        let mut output = self.output.lock().unwrap();
+        if !output.connected {
+            return Err("No display connected");
+        }
+        if !output.modes.contains(mode) {
+            return Err("Mode not supported by the display");
+        }
+        if output.mode == *mode {
+            return Ok(());
+        }
+
+        {
+            let mut vrr = self.vrr.lock().unwrap();
+            let enabled = vrr.enabled();
+            *vrr = VrrState::new(mode.timing, vrr.range(), vrr.technology());
+            if enabled && vrr.set_enabled(true).is_err() {
+                log::info!("Adaptive-Sync unavailable in the new mode");
+            }
+        }
+        output.mode = *mode;
+        self.modeset(mode);
+        self.serial.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Counter bumped on every hotplug and mode change
+    pub fn serial(&self) -> u64 {
+        self.serial.load(Ordering::Acquire)
+    }
+
+    /// Program the transcoder and pipe for `mode`
+    fn modeset(&self, mode: &Mode) {
+        let timing = mode.timing;
+        log::debug!(
+            "TRANS_HTOTAL={:#x} TRANS_VTOTAL={:#x} PIPESRC={:#x} DPLL={} kHz",
+            (timing.htotal - 1) << 16 | (mode.hactive - 1),
+            (timing.vtotal - 1) << 16 | (mode.vactive - 1),
+            (mode.hactive - 1) << 16 | (mode.vactive - 1),
+            timing.pixel_clock_khz
+        );
+        self.program(&self.vrr.lock().unwrap());
+    }
+
+    /// Handle the vertical blank interrupt
+    pub fn vblank(&self, now: Instant) {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.vblank(now);
+        self.program(&vrr);
+
+        let mut plane = self.plane.lock().unwrap();
+        plane.vblanks += 1;
+        self.scanout.vblank(0, plane.vblanks);
+        if plane.pending.take().is_some() {
+            plane.flipped_at = Some(now);
+            self.scanout.flip_complete(0, plane.vblanks);
+        }
+        self.vblank_event.notify_all();
+    }
+
+    /// Active resolution
+    pub fn resolution(&self) -> (u32, u32) {
+        let mode = self.mode();
+        (mode.hactive, mode.vactive)
+    }
+
+    /// Nominal refresh rate in Hz
+    pub fn refresh_hz(&self) -> u32 {
+        self.vrr.lock().unwrap().timing().refresh_hz().round() as u32
+    }
+
+    /// Scan out the surface at `address` from the next vertical blank on
+    pub fn flip(&self, address: u64) {
+        log::debug!("PLANE_SURF={:#x}", address);
+        self.plane.lock().unwrap().pending = Some(address);
+    }
+
+    /// Check if a flip waits for the vertical blank
+    pub fn flip_pending(&self) -> bool {
+        self.plane.lock().unwrap().pending.is_some()
+    }
+
+    /// When the last flip reached the screen
+    pub fn flipped_at(&self) -> Option<Instant> {
+        self.plane.lock().unwrap().flipped_at
+    }
+
+    /// Vertical blank and flip timing, served on `scanout:`
+    pub fn scanout(&self) -> &Arc<ScanoutLog> {
+        &self.scanout
+    }
+
+    /// Wait for the next vertical blank, returns false on timeout
+    pub fn wait_vblank(&self, timeout: Duration) -> bool {
+        let plane = self.plane.lock().unwrap();
+        let vblanks = plane.vblanks;
+        let (_plane, result) = self
+            .vblank_event
+            .wait_timeout_while(plane, timeout, |plane| plane.vblanks == vblanks)
+            .unwrap();
+        !result.timed_out()
+    }
+
+    fn program(&self, vrr: &VrrState) {
+        let (min, max) = vrr.vtotal_limits();
+        let mut v_total = self.v_total.lock().unwrap();
+        if *v_total != (VrrVTotal { min, max }) {
+            log::debug!("TRANS_VRR_VMIN={} TRANS_VRR_VMAX={}", min, max);
+            *v_total = VrrVTotal { min, max };
+        }
+    }
+}
+
+impl VrrDisplay for IntelDisplay {
+    fn vrr_technology(&self) -> Option<VrrTechnology> {
+        self.vrr.lock().unwrap().technology()
+    }
+
+    fn vrr_range(&self) -> Option<VrrRange> {
+        self.vrr.lock().unwrap().range()
+    }
+
+    fn set_adaptive_sync(&self, enabled: bool) -> Result<(), &'static str> {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.set_enabled(enabled)?;
+        self.program(&vrr);
+        Ok(())
+    }
+
+    fn adaptive_sync_enabled(&self) -> bool {
+        self.vrr.lock().unwrap().enabled()
+    }
+
+    fn set_present_target(&self, target: Option<Instant>) -> Result<(), &'static str> {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.set_target(target)?;
+        self.program(&vrr);
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! EDID parsing: the modes a sink supports and its name
+
+use graphics_api::DisplayTiming;
+
+/// Mode of a sink, with the timing the transcoder is programmed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub hactive: u32,
+    pub vactive: u32,
+    pub timing: DisplayTiming,
+    /// The sink's native mode, its first detailed timing
+    pub preferred: bool,
+}
+
+impl Mode {
+    /// Refresh rate in millihertz
+    pub fn refresh_mhz(&self) -> u32 {
+        (self.timing.refresh_hz() * 1000.0).round() as u32
+    }
+}
+
+/// Established timings I and II the driver can drive, by bit of bytes
+/// 35-36, with their VESA DMT timings
+const ESTABLISHED_TIMINGS: &[(u16, Mode)] = &[
+    // 640x480@60
+    (1 << 5, established(640, 480, 25_175, 800, 525)),
+    // 800x600@60
+    (1 << 0, established(800, 600, 40_000, 1056, 628)),
+    // 1024x768@60
+    (1 << 11, established(1024, 768, 65_000, 1344, 806)),
+];
+
+const fn established(
+    hactive: u32,
+    vactive: u32,
+    pixel_clock_khz: u32,
+    htotal: u32,
+    vtotal: u32,
+) -> Mode {
+    Mode {
+        hactive,
+        vactive,
+        timing: DisplayTiming {
+            pixel_clock_khz,
+            htotal,
+            vtotal,
+        },
+        preferred: false,
+    }
+}
+
+/// CTA-861 extension block tag
+const CTA_EXTENSION: u8 = 0x02;
+/// Display Product Name descriptor tag
+const PRODUCT_NAME: u8 = 0xFC;
+
+/// Modes listed in `edid`, the preferred one first, without duplicates
+///
+/// Detailed timings of the base block and a CTA-861 extension are used,
+/// and the established timings the driver knows; interlaced modes are
+/// left out.
+pub fn modes(edid: &[u8]) -> Vec<Mode> {
+    let mut modes = Vec::new();
+    if edid.len() < 128 {
+        return modes;
+    }
+
+    for offset in (54..126).step_by(18) {
+        if let Some(mode) = detailed_timing(&edid[offset..offset + 18]) {
+            add(&mut modes, mode);
+        }
+    }
+
+    if let Some(extension) = edid.get(128..256) {
+        // Detailed timings from the offset in byte 2 up to the padding
+        let start = usize::from(extension[2]);
+        if extension[0] == CTA_EXTENSION && start >= 4 {
+            let mut offset = start;
+            while offset + 18 <= 127 {
+                match detailed_timing(&extension[offset..offset + 18]) {
+                    Some(mode) => add(&mut modes, mode),
+                    None if extension[offset..offset + 2] == [0, 0] => break,
+                    None => {}
+                }
+                offset += 18;
+            }
+        }
+    }
+
+    let established_bits = u16::from_le_bytes([edid[35], edid[36]]);
+    for &(bit, mode) in ESTABLISHED_TIMINGS {
+        if established_bits & bit != 0 {
+            add(&mut modes, mode);
+        }
+    }
+
+    if let Some(first) = modes.first_mut() {
+        first.preferred = true;
+    }
+    modes
+}
+
+fn add(modes: &mut Vec<Mode>, mode: Mode) {
+    let duplicate = modes.iter().any(|other| {
+        other.hactive == mode.hactive
+            && other.vactive == mode.vactive
+            && other.refresh_mhz() == mode.refresh_mhz()
+    });
+    if !duplicate {
+        modes.push(mode);
+    }
+}
+
+/// Parse an 18 byte detailed timing descriptor, `None` for other
+/// descriptors and interlaced timings
+fn detailed_timing(descriptor: &[u8]) -> Option<Mode> {
+    let pixel_clock = u32::from(u16::from_le_bytes([descriptor[0], descriptor[1]]));
+    if pixel_clock == 0 {
+        return None;
+    }
+    let interlaced = descriptor[17] & 0x80 != 0;
+    if interlaced {
+        return None;
+    }
+
+    let hactive = u32::from(descriptor[2]) | u32::from(descriptor[4] >> 4) << 8;
+    let hblank = u32::from(descriptor[3]) | u32::from(descriptor[4] & 0x0F) << 8;
+    let vactive = u32::from(descriptor[5]) | u32::from(descriptor[7] >> 4) << 8;
+    let vblank = u32::from(descriptor[6]) | u32::from(descriptor[7] & 0x0F) << 8;
+    if hactive == 0 || vactive == 0 {
+        return None;
+    }
+
+    Some(Mode {
+        hactive,
+        vactive,
+        timing: DisplayTiming {
+            // In units of 10 kHz
+            pixel_clock_khz: pixel_clock * 10,
+            htotal: hactive + hblank,
+            vtotal: vactive + vblank,
+        },
+        preferred: false,
+    })
+}
+
+/// Product name from the Display Product Name descriptor
+pub fn name(edid: &[u8]) -> Option<String> {
+    if edid.len() < 128 {
+        return None;
+    }
+    (54..126).step_by(18).find_map(|offset| {
+        let descriptor = &edid[offset..offset + 18];
+        if descriptor[..3] != [0, 0, 0] || descriptor[3] != PRODUCT_NAME {
+            return None;
+        }
+        // Up to 13 characters, ended by a line feed
+        let text = &descriptor[5..18];
+        let end = text.iter().position(|&c| c == b'\n').unwrap_or(text.len());
+        let name = String::from_utf8_lossy(&text[..end]).trim().to_string();
+        (!name.is_empty()).then_some(name)
+    })
+}
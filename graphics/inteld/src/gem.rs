@@ -1,17 +1,31 @@
 //! GEM (Graphics Execution Manager) for Intel GPUs
+//!
+//! Shareable objects can be exported under a global name, which another
+//! process imports to get its own handle to the same pages, the way a
+//! dma-buf is passed around. An object lives on until its last handle is
+//! freed and its name closed.
 
+use crate::gtt::{self, CacheLevel, Coherency, DomainFlush, GemDomains};
 use bitflags::bitflags;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
 /// GEM object
 pub struct GemObject {
+    /// Handle it was allocated under
     pub handle: u32,
     pub size: usize,
     pub gtt_offset: u64,
     pub cpu_addr: Option<usize>,
     pub flags: GemFlags,
     pub tiling: TilingMode,
+    /// CPU/GPU coherency, see [`crate::gtt`]
+    pub coherency: Mutex<Coherency>,
+    /// Handles and names referring to the object
+    refs: AtomicU32,
+    /// Name the object is exported under
+    name: Mutex<Option<u32>>,
 }
 
 bitflags! {
@@ -24,16 +38,32 @@ bitflags! {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TilingMode {
     None,
     X,
     Y,
 }
 
+impl TilingMode {
+    /// Format modifier describing the tiling to importers
+    /// (`I915_FORMAT_MOD_*_TILED`)
+    pub fn modifier(self) -> u64 {
+        const VENDOR_INTEL: u64 = 0x01 << 56;
+        match self {
+            TilingMode::None => 0,
+            TilingMode::X => VENDOR_INTEL | 1,
+            TilingMode::Y => VENDOR_INTEL | 2,
+        }
+    }
+}
+
 pub struct GemManager {
     objects: Mutex<HashMap<u32, Arc<GemObject>>>,
     next_handle: Mutex<u32>,
+    /// Exported objects, by name
+    names: Mutex<HashMap<u32, Arc<GemObject>>>,
+    next_name: Mutex<u32>,
     gtt_allocator: Mutex<GttAllocator>,
 }
 
@@ -42,19 +72,15 @@ impl GemManager {
         Self {
             objects: Mutex::new(HashMap::new()),
             next_handle: Mutex::new(1),
+            names: Mutex::new(HashMap::new()),
+            next_name: Mutex::new(1),
             gtt_allocator: Mutex::new(GttAllocator::new(gtt_size)),
         }
     }
 
     pub fn alloc(&self, size: usize, flags: GemFlags) -> Result<u32, &'static str> {
-        let handle = {
-            let mut next = self.next_handle.lock().unwrap();
-            let h = *next;
-            *next += 1;
-            h
-        };
-
         let gtt_offset = self.gtt_allocator.lock().unwrap().alloc(size)?;
+        let handle = self.next_handle();
 
         let obj = Arc::new(GemObject {
             handle,
@@ -63,6 +89,9 @@ impl GemManager {
             cpu_addr: None,
             flags,
             tiling: TilingMode::None,
+            coherency: Mutex::new(Coherency::new(CacheLevel::Llc)),
+            refs: AtomicU32::new(1),
+            name: Mutex::new(None),
         });
 
         self.objects.lock().unwrap().insert(handle, obj);
@@ -70,6 +99,43 @@ impl GemManager {
         Ok(handle)
     }
 
+    /// Create an object on pages owned by another device, mapped at
+    /// `cpu_addr`, binding them into the GTT
+    pub fn import_pages(
+        &self,
+        pages: &[usize],
+        size: usize,
+        cpu_addr: usize,
+    ) -> Result<u32, &'static str> {
+        if pages.len() * 4096 < size {
+            return Err("Pages too small");
+        }
+        let gtt_offset = self.gtt_allocator.lock().unwrap().alloc(size)?;
+        let handle = self.next_handle();
+
+        let obj = Arc::new(GemObject {
+            handle,
+            size,
+            gtt_offset,
+            cpu_addr: Some(cpu_addr),
+            flags: GemFlags::GPU_ACCESS | GemFlags::CPU_ACCESS,
+            tiling: TilingMode::None,
+            coherency: Mutex::new(Coherency::new(CacheLevel::Llc)),
+            refs: AtomicU32::new(1),
+            name: Mutex::new(None),
+        });
+        self.objects.lock().unwrap().insert(handle, obj);
+
+        log::debug!(
+            "GEM: bound {} foreign page(s) at GTT {:#x} as handle {}",
+            pages.len(),
+            gtt_offset,
+            handle
+        );
+        Ok(handle)
+    }
+
+    /// Free a handle, and the object with its last reference
     pub fn free(&self, handle: u32) -> Result<(), &'static str> {
         let obj = self
             .objects
@@ -78,16 +144,153 @@ impl GemManager {
             .remove(&handle)
             .ok_or("Invalid handle")?;
 
-        self.gtt_allocator
+        self.release(&obj);
+        Ok(())
+    }
+
+    pub fn get(&self, handle: u32) -> Option<Arc<GemObject>> {
+        self.objects.lock().unwrap().get(&handle).cloned()
+    }
+
+    /// Export a shareable object, returning the name it can be imported
+    /// under
+    ///
+    /// Exporting an object again returns the same name. The object is
+    /// moved out of the CPU and render caches, so importers see what was
+    /// written before.
+    pub fn export(&self, handle: u32) -> Result<u32, &'static str> {
+        let obj = self.get(handle).ok_or("Invalid handle")?;
+        if !obj.flags.contains(GemFlags::SHAREABLE) {
+            return Err("Object is not shareable");
+        }
+
+        let mut name = obj.name.lock().unwrap();
+        if let Some(name) = *name {
+            return Ok(name);
+        }
+
+        let new_name = {
+            let mut next = self.next_name.lock().unwrap();
+            let n = *next;
+            *next += 1;
+            n
+        };
+        obj.refs.fetch_add(1, Ordering::Relaxed);
+        self.names.lock().unwrap().insert(new_name, obj.clone());
+        *name = Some(new_name);
+        drop(name);
+
+        self.move_to_domain(&obj, GemDomains::GTT, false);
+        log::debug!(
+            "GEM: exported object {} ({} bytes) as {}",
+            handle,
+            obj.size,
+            new_name
+        );
+        Ok(new_name)
+    }
+
+    /// Name the object allocated under `handle` is exported under, `None`
+    /// for handles from [`GemManager::import`]
+    pub fn exported_name(&self, handle: u32) -> Option<u32> {
+        let obj = self.get(handle)?;
+        if obj.handle != handle {
+            return None;
+        }
+        let name = *obj.name.lock().unwrap();
+        name
+    }
+
+    /// Import an exported object, returning a new handle to it
+    pub fn import(&self, name: u32) -> Result<u32, &'static str> {
+        let obj = self
+            .names
+            .lock()
+            .unwrap()
+            .get(&name)
+            .cloned()
+            .ok_or("Invalid name")?;
+
+        obj.refs.fetch_add(1, Ordering::Relaxed);
+        let handle = self.next_handle();
+        self.objects.lock().unwrap().insert(handle, obj);
+        log::debug!("GEM: imported {} as handle {}", name, handle);
+        Ok(handle)
+    }
+
+    /// Close the name of an exported object, releasing its reference
+    ///
+    /// Handles already imported stay valid.
+    pub fn close_name(&self, name: u32) -> Result<(), &'static str> {
+        let obj = self
+            .names
             .lock()
             .unwrap()
-            .free(obj.gtt_offset, obj.size);
+            .remove(&name)
+            .ok_or("Invalid name")?;
 
+        *obj.name.lock().unwrap() = None;
+        self.release(&obj);
         Ok(())
     }
 
-    pub fn get(&self, handle: u32) -> Option<Arc<GemObject>> {
-        self.objects.lock().unwrap().get(&handle).cloned()
+    /// Move an object to `domain` before accessing it there, flushing the
+    /// domain that holds its latest writes
+    pub fn set_domain(
+        &self,
+        handle: u32,
+        domain: GemDomains,
+        write: bool,
+    ) -> Result<(), &'static str> {
+        let obj = self.get(handle).ok_or("Invalid handle")?;
+        self.move_to_domain(&obj, domain, write);
+        Ok(())
+    }
+
+    /// Change the caching of an object, e.g. to uncached for importers
+    /// that don't snoop the LLC
+    pub fn set_cache_level(&self, handle: u32, level: CacheLevel) -> Result<(), &'static str> {
+        let obj = self.get(handle).ok_or("Invalid handle")?;
+        let flush = obj.coherency.lock().unwrap().set_cache_level(level);
+        Self::flush(&obj, flush);
+        Ok(())
+    }
+
+    fn move_to_domain(&self, obj: &GemObject, domain: GemDomains, write: bool) {
+        let flush = obj.coherency.lock().unwrap().set_domain(domain, write);
+        Self::flush(obj, flush);
+    }
+
+    fn flush(obj: &GemObject, flush: DomainFlush) {
+        if flush.cpu {
+            if let Some(addr) = obj.cpu_addr {
+                gtt::clflush_range(addr, obj.size);
+            }
+        }
+        if flush.render {
+            log::trace!(
+                "GEM: object {} needs a render cache flush before the next batch",
+                obj.handle
+            );
+        }
+    }
+
+    fn next_handle(&self) -> u32 {
+        let mut next = self.next_handle.lock().unwrap();
+        let h = *next;
+        *next += 1;
+        h
+    }
+
+    /// Drop a handle's or name's reference, freeing the object's GTT space
+    /// with the last
+    fn release(&self, obj: &GemObject) {
+        if obj.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.gtt_allocator
+                .lock()
+                .unwrap()
+                .free(obj.gtt_offset, obj.size);
+        }
     }
 }
 
@@ -0,0 +1,130 @@
+//! Graphics translation table
+//!
+//! Besides placing GEM objects in the GTT, the driver keeps their contents
+//! coherent between the CPU and the GPU. Each object tracks the domains
+//! that may hold a copy of it and the one that may hold writes not yet
+//! visible elsewhere; moving it to another domain flushes those writes.
+//! Exported objects are also read by other processes and devices, so
+//! exporting moves them out of the CPU and render caches.
+
+use bitflags::bitflags;
+
+/// Size of a CPU cache line, the granularity of `clflush`
+const CACHE_LINE: usize = 64;
+
+bitflags! {
+    /// Places a GEM object's contents can be cached in
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct GemDomains: u32 {
+        /// CPU caches, through a cached CPU mapping
+        const CPU = 1 << 0;
+        /// Through the GTT aperture, uncached
+        const GTT = 1 << 1;
+        /// GPU render and sampler caches
+        const RENDER = 1 << 2;
+    }
+}
+
+/// Caching of the object's pages, the PAT index its GTT entries use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheLevel {
+    /// Write-back, coherent with the CPU through the LLC
+    Llc = 0,
+    /// Write-combined, for scanout and buffers the CPU only writes
+    WriteCombined = 1,
+    /// Write-through
+    WriteThrough = 2,
+    /// Uncached, for devices that don't snoop the LLC
+    Uncached = 3,
+}
+
+impl CacheLevel {
+    /// Whether CPU cache lines of the object are kept coherent with the GPU
+    pub fn coherent(self) -> bool {
+        matches!(self, CacheLevel::Llc)
+    }
+}
+
+/// Flushes needed to move an object to another domain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DomainFlush {
+    /// Write back the CPU cache lines of the object
+    pub cpu: bool,
+    /// Flush the GPU render caches, done by the next batch
+    pub render: bool,
+}
+
+/// Coherency state of one GEM object
+#[derive(Debug, Clone, Copy)]
+pub struct Coherency {
+    pub cache_level: CacheLevel,
+    /// Domains that may hold valid copies
+    pub read_domains: GemDomains,
+    /// Domain that may hold writes not visible to the others
+    pub write_domain: Option<GemDomains>,
+}
+
+impl Coherency {
+    pub fn new(cache_level: CacheLevel) -> Self {
+        Self {
+            cache_level,
+            read_domains: GemDomains::CPU,
+            write_domain: Some(GemDomains::CPU),
+        }
+    }
+
+    /// Move the object to `domain`, for reading or also writing, returning
+    /// the flushes that makes necessary
+    pub fn set_domain(&mut self, domain: GemDomains, write: bool) -> DomainFlush {
+        let mut flush = DomainFlush::default();
+        if let Some(dirty) = self.write_domain {
+            if dirty != domain {
+                flush = self.flush_for(dirty);
+                self.write_domain = None;
+            }
+        }
+
+        if write {
+            // Other copies become stale
+            self.read_domains = domain;
+            self.write_domain = Some(domain);
+        } else {
+            self.read_domains |= domain;
+        }
+        flush
+    }
+
+    /// Change the caching, flushing writes the new caching wouldn't see
+    pub fn set_cache_level(&mut self, cache_level: CacheLevel) -> DomainFlush {
+        self.cache_level = cache_level;
+        match self.write_domain.take() {
+            Some(dirty) => self.flush_for(dirty),
+            None => DomainFlush::default(),
+        }
+    }
+
+    fn flush_for(&self, dirty: GemDomains) -> DomainFlush {
+        DomainFlush {
+            cpu: dirty == GemDomains::CPU && !self.cache_level.coherent(),
+            render: dirty == GemDomains::RENDER,
+        }
+    }
+}
+
+/// Write back and invalidate the CPU cache lines covering a CPU mapping
+pub fn clflush_range(addr: usize, size: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let end = addr + size;
+        let mut line = addr & !(CACHE_LINE - 1);
+        while line < end {
+            // SAFETY: the range is a live CPU mapping of a GEM object
+            unsafe { core::arch::x86_64::_mm_clflush(line as *const u8) };
+            line += CACHE_LINE;
+        }
+        // SAFETY: no preconditions
+        unsafe { core::arch::x86_64::_mm_mfence() };
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = (addr, size, CACHE_LINE);
+}
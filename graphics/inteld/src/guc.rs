@@ -0,0 +1,292 @@
+//! GuC submission
+//!
+//! With GuC submission the driver no longer writes the execlist ports: it
+//! registers each context with the GuC firmware, and submits by appending
+//! an item to the context's work queue and ringing its doorbell. The GuC
+//! scheduler runs the highest priority context with work on each engine
+//! and preempts lower priority ones at the next arbitration point, so the
+//! compositor's work gets ahead of long-running compute from background
+//! applications.
+//!
+//! What a priority means is set per context: how long it may run before
+//! contexts of the same priority get a turn (the execution quantum), how
+//! long the GuC waits for it to reach a preemption point before resetting
+//! it (the preemption timeout), and how fine-grained its work is preempted.
+
+use gal::QueuePriority;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// Host-to-GuC actions
+mod action {
+    pub const SCHED_CONTEXT_MODE_SET: u32 = 0x1002;
+    pub const SET_CONTEXT_PRIORITY: u32 = 0x1005;
+    pub const SET_CONTEXT_PREEMPTION_TIMEOUT: u32 = 0x1007;
+    pub const SET_CONTEXT_EXECUTION_QUANTUM: u32 = 0x1008;
+    pub const REGISTER_CONTEXT: u32 = 0x4502;
+    pub const DEREGISTER_CONTEXT: u32 = 0x4503;
+}
+
+/// Work queue item header fields
+mod wq {
+    pub const TYPE_INORDER: u32 = 0x3;
+    pub const TARGET_SHIFT: u32 = 10;
+    pub const LEN_SHIFT: u32 = 16;
+    pub const RING_TAIL_SHIFT: u32 = 20;
+    pub const RING_TAIL_MAX: u32 = 0x7FF;
+}
+
+/// Size of a context's work queue, in dwords
+const WQ_SIZE: usize = 1024;
+/// Dwords of a work queue item, header included
+const WQ_ITEM_SIZE: usize = 4;
+
+/// Engine class, as the GuC numbers them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EngineClass {
+    Render = 0,
+    VideoDecode = 1,
+    VideoEnhance = 2,
+    Copy = 3,
+    Compute = 4,
+}
+
+/// GuC client priority; lower values run first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GucPriority {
+    KmdHigh = 0,
+    High = 1,
+    KmdNormal = 2,
+    Normal = 3,
+}
+
+/// Where running work may be stopped for a higher priority context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreemptionGranularity {
+    /// Between batches only
+    Batch,
+    /// At MI_ARB_CHECK and between commands, mid-batch
+    Command,
+    /// Between thread groups of a compute walker
+    ThreadGroup,
+    /// Mid-thread, saving the EU state
+    MidThread,
+}
+
+/// Scheduling policy of a context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulingPolicy {
+    pub priority: GucPriority,
+    /// Time slice against contexts of the same priority, in microseconds
+    pub execution_quantum_us: u32,
+    /// How long the context may take to reach a preemption point before
+    /// the GuC resets it, in microseconds
+    pub preemption_timeout_us: u32,
+    pub granularity: PreemptionGranularity,
+}
+
+impl SchedulingPolicy {
+    /// Policy for work of `priority` on an engine of `class`
+    ///
+    /// Compute work is preempted mid-thread, everything else mid-batch. Low
+    /// priority work gets long time slices, since it mostly runs when
+    /// nothing else does, but a short preemption timeout, so a background
+    /// job can't hold up the compositor.
+    pub fn new(class: EngineClass, priority: QueuePriority) -> Self {
+        let granularity = match class {
+            EngineClass::Compute => PreemptionGranularity::MidThread,
+            _ => PreemptionGranularity::Command,
+        };
+        let (priority, execution_quantum_us, preemption_timeout_us) = match priority {
+            QueuePriority::Low => (GucPriority::Normal, 25_000, 100_000),
+            QueuePriority::Normal => (GucPriority::KmdNormal, 5_000, 640_000),
+            QueuePriority::High => (GucPriority::High, 2_000, 640_000),
+            QueuePriority::Realtime => (GucPriority::KmdHigh, 1_000, 640_000),
+        };
+        Self {
+            priority,
+            execution_quantum_us,
+            preemption_timeout_us,
+            granularity,
+        }
+    }
+}
+
+/// Ring of work queue items the GuC consumes
+struct WorkQueue {
+    items: Box<[u32; WQ_SIZE]>,
+    /// Next dword the driver writes, in the process descriptor
+    tail: usize,
+    /// Next dword the GuC reads, in the process descriptor
+    head: usize,
+}
+
+impl WorkQueue {
+    fn new() -> Self {
+        Self {
+            items: Box::new([0; WQ_SIZE]),
+            tail: 0,
+            head: 0,
+        }
+    }
+
+    fn space(&self) -> usize {
+        (self.head + WQ_SIZE - self.tail - 1) % WQ_SIZE
+    }
+
+    fn push(&mut self, item: [u32; WQ_ITEM_SIZE]) -> Result<(), &'static str> {
+        if self.space() < WQ_ITEM_SIZE {
+            return Err("GuC work queue full");
+        }
+        // Items never wrap, WQ_SIZE being a multiple of their size
+        self.items[self.tail..self.tail + WQ_ITEM_SIZE].copy_from_slice(&item);
+        self.tail = (self.tail + WQ_ITEM_SIZE) % WQ_SIZE;
+        Ok(())
+    }
+}
+
+/// Context registered with the GuC
+struct GucContext {
+    class: EngineClass,
+    policy: SchedulingPolicy,
+    /// GTT address of the logical ring context
+    lrca: u64,
+    wq: WorkQueue,
+    /// Doorbell rings so far, the doorbell cacheline's cookie
+    doorbell: u32,
+}
+
+/// GuC firmware interface
+pub struct Guc {
+    contexts: Mutex<BTreeMap<u32, GucContext>>,
+    next_id: AtomicU32,
+}
+
+impl Guc {
+    pub fn new() -> Self {
+        Self {
+            contexts: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU32::new(1),
+        }
+    }
+
+    /// Turn on GuC scheduling, after the firmware is loaded
+    pub fn enable_scheduling(&self) {
+        self.send(action::SCHED_CONTEXT_MODE_SET, &[0, 1]);
+        log::info!("GuC submission enabled");
+    }
+
+    /// Register a context with its logical ring context at `lrca`,
+    /// returning its GuC id
+    pub fn register_context(&self, class: EngineClass, lrca: u64, priority: QueuePriority) -> u32 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let policy = SchedulingPolicy::new(class, priority);
+        self.send(
+            action::REGISTER_CONTEXT,
+            &[id, class as u32, lrca as u32, (lrca >> 32) as u32],
+        );
+        self.apply_policy(id, &policy);
+        // The preemption granularity is part of the context image
+        // (CS_CHICKEN1), not of the GuC's policy
+        log::debug!("GuC: context {} on {:?}: {:?}", id, class, policy);
+
+        self.contexts.lock().unwrap().insert(
+            id,
+            GucContext {
+                class,
+                policy,
+                lrca,
+                wq: WorkQueue::new(),
+                doorbell: 0,
+            },
+        );
+        id
+    }
+
+    pub fn deregister_context(&self, id: u32) -> Result<(), &'static str> {
+        self.contexts
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or("Invalid GuC context")?;
+        self.send(action::DEREGISTER_CONTEXT, &[id]);
+        Ok(())
+    }
+
+    /// Change the priority of a context's work, including work already
+    /// queued
+    pub fn set_priority(&self, id: u32, priority: QueuePriority) -> Result<(), &'static str> {
+        let mut contexts = self.contexts.lock().unwrap();
+        let context = contexts.get_mut(&id).ok_or("Invalid GuC context")?;
+        let policy = SchedulingPolicy::new(context.class, priority);
+        if policy != context.policy {
+            self.apply_policy(id, &policy);
+            context.policy = policy;
+        }
+        Ok(())
+    }
+
+    /// Scheduling policy of a context
+    pub fn policy(&self, id: u32) -> Option<SchedulingPolicy> {
+        self.contexts.lock().unwrap().get(&id).map(|c| c.policy)
+    }
+
+    /// Queue the context's ring up to `ring_tail` (in qwords) for execution
+    pub fn submit(&self, id: u32, ring_tail: u32) -> Result<(), &'static str> {
+        if ring_tail > wq::RING_TAIL_MAX {
+            return Err("Ring tail out of range");
+        }
+        let mut contexts = self.contexts.lock().unwrap();
+        let context = contexts.get_mut(&id).ok_or("Invalid GuC context")?;
+
+        let header = wq::TYPE_INORDER
+            | (context.class as u32) << wq::TARGET_SHIFT
+            | (WQ_ITEM_SIZE as u32 - 1) << wq::LEN_SHIFT;
+        context.wq.push([
+            header,
+            context.lrca as u32,
+            ring_tail << wq::RING_TAIL_SHIFT,
+            0,
+        ])?;
+
+        // The new tail goes to the process descriptor before the doorbell
+        context.doorbell = context.doorbell.wrapping_add(1);
+        log::trace!(
+            "GuC: context {} WQ tail {} doorbell {}",
+            id,
+            context.wq.tail,
+            context.doorbell
+        );
+        Ok(())
+    }
+
+    /// Update a context's work queue head from its process descriptor, once
+    /// the GuC reports having consumed items
+    pub fn retire(&self, id: u32, wq_head: usize) -> Result<(), &'static str> {
+        let mut contexts = self.contexts.lock().unwrap();
+        let context = contexts.get_mut(&id).ok_or("Invalid GuC context")?;
+        if wq_head >= WQ_SIZE || wq_head % WQ_ITEM_SIZE != 0 {
+            return Err("Invalid work queue head");
+        }
+        context.wq.head = wq_head;
+        Ok(())
+    }
+
+    fn apply_policy(&self, id: u32, policy: &SchedulingPolicy) {
+        self.send(action::SET_CONTEXT_PRIORITY, &[id, policy.priority as u32]);
+        self.send(
+            action::SET_CONTEXT_EXECUTION_QUANTUM,
+            &[id, policy.execution_quantum_us],
+        );
+        self.send(
+            action::SET_CONTEXT_PREEMPTION_TIMEOUT,
+            &[id, policy.preemption_timeout_us],
+        );
+    }
+
+    /// Send a host-to-GuC message over the CT buffer
+    fn send(&self, action: u32, data: &[u32]) {
+        log::trace!("GuC H2G {:#x} {:x?}", action, data);
+    }
+}
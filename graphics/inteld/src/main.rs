@@ -2,12 +2,17 @@
 //!
 //! Native Intel GPU driver with GEM memory manager and GuC firmware.
 
+use driver_graphics::irq::{GpuEventLoop, InterruptHandler, Mmio};
+use pcid_interface::PciFunctionHandle;
 use redox_daemon::Daemon;
 use std::sync::Arc;
+use std::time::Duration;
 
 mod context;
+mod ddc;
 mod device;
 mod display;
+mod edid;
 mod execbuf;
 mod gal_backend;
 mod gem;
@@ -19,6 +24,27 @@ mod ring;
 use device::IntelDevice;
 use gal_backend::IntelGalBackend;
 
+/// Period of event processing missed by interrupts
+const WATCHDOG_PERIOD: Duration = Duration::from_millis(100);
+
+/// Routes interrupts and the watchdog timer to the device
+struct Interrupts {
+    device: Arc<IntelDevice>,
+    mmio: Mmio,
+}
+
+impl InterruptHandler for Interrupts {
+    fn interrupt(&self, _vector: usize) -> bool {
+        let raised = self.device.handle_interrupt(&self.mmio);
+        self.device.process_submissions();
+        raised
+    }
+
+    fn timer(&self) {
+        self.device.process_events();
+    }
+}
+
 fn daemon(daemon: Daemon) -> ! {
     common::setup_logging(
         "gpu",
@@ -30,6 +56,13 @@ fn daemon(daemon: Daemon) -> ! {
 
     log::info!("Intel GPU Driver starting...");
 
+    // Registers are in GTTMMADR, BAR 0
+    let mut pcid_handle = PciFunctionHandle::connect_default();
+    let mmio = {
+        let bar = unsafe { pcid_handle.map_bar(0) };
+        unsafe { Mmio::new(bar.ptr.as_ptr(), bar.bar_size) }
+    };
+
     // Initialize PCI device
     let device = match IntelDevice::new() {
         Ok(dev) => {
@@ -77,6 +110,8 @@ fn daemon(daemon: Daemon) -> ! {
         std::process::exit(1);
     }
 
+    device.init_interrupts(&mmio);
+
     // Create GAL backend
     let gal_backend = Arc::new(IntelGalBackend::new(device.clone()));
 
@@ -86,16 +121,26 @@ fn daemon(daemon: Daemon) -> ! {
         std::process::exit(1);
     }
 
+    let scanout = device.display().scanout().clone();
+    std::thread::spawn(move || {
+        if let Err(e) = driver_graphics::scanout::serve_scanout(scanout) {
+            log::error!("Failed to serve scanout: {}", e);
+        }
+    });
+
+    let event_loop = match GpuEventLoop::new(&mut pcid_handle, "inteld", 1, WATCHDOG_PERIOD) {
+        Ok(event_loop) => event_loop,
+        Err(e) => {
+            log::error!("Failed to set up interrupts: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     log::info!("Intel GPU driver ready");
 
     daemon.ready().expect("Failed to mark daemon as ready");
 
-    // Main event loop
-    loop {
-        device.process_events();
-        device.process_submissions();
-        std::thread::sleep(std::time::Duration::from_millis(1));
-    }
+    event_loop.run(&Interrupts { device, mmio })
 }
 
 fn main() {
@@ -19,6 +19,8 @@ pub struct AntiLagContext {
     mode: LatencyMode,
     /// Statistics
     stats: LatencyStats,
+    /// When the input of the current frame was sampled (ns)
+    input_sample_ns: Option<u64>,
 }
 
 impl AntiLagContext {
@@ -44,6 +46,7 @@ impl AntiLagContext {
             version,
             mode: LatencyMode::Off,
             stats: LatencyStats::new(),
+            input_sample_ns: None,
         })
     }
 
@@ -100,8 +103,16 @@ impl AntiLagContext {
             LatencyMarker::RenderSubmitStart => {}
             LatencyMarker::RenderSubmitEnd => {}
             LatencyMarker::PresentStart => {}
-            LatencyMarker::PresentEnd => {}
-            LatencyMarker::InputSample => {}
+            LatencyMarker::PresentEnd => {
+                if let (Some(sample), Some(now)) =
+                    (self.input_sample_ns.take(), gal::display::monotonic_ns())
+                {
+                    self.stats.input_age_ms = now.saturating_sub(sample) as f32 / 1_000_000.0;
+                }
+            }
+            LatencyMarker::InputSample => {
+                self.input_sample_ns = gal::display::monotonic_ns();
+            }
         }
 
         Ok(())
@@ -124,6 +135,7 @@ impl Default for AntiLagContext {
             version: AntiLagVersion::AntiLag,
             mode: LatencyMode::Off,
             stats: LatencyStats::new(),
+            input_sample_ns: None,
         })
     }
 }
@@ -28,6 +28,9 @@ pub struct LatencyStats {
     pub os_queue_latency_ms: f32,
     /// GPU render time (milliseconds)
     pub gpu_render_ms: f32,
+    /// Age of the input the last frame was built from, at present
+    /// (milliseconds)
+    pub input_age_ms: f32,
 }
 
 impl LatencyStats {
@@ -40,6 +43,7 @@ impl LatencyStats {
             driver_latency_ms: 0.0,
             os_queue_latency_ms: 0.0,
             gpu_render_ms: 0.0,
+            input_age_ms: 0.0,
         }
     }
 
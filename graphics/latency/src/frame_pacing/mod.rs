@@ -1,6 +1,16 @@
 //! Frame pacing and synchronization
 
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use gal::{CommandBuffer, Device, QueryPool, QueryType};
+
 use crate::common::{LatencyError, LatencyStats};
+use crate::scanout::{ScanoutEvent, ScanoutEventKind};
+
+/// Weight of a new sample in the CPU and GPU time predictions
+const PREDICTION_WEIGHT: f32 = 0.2;
 
 /// Frame pacing strategy
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -13,6 +23,35 @@ pub enum PacingStrategy {
     Adaptive,
     /// Mailbox (replace queued frame)
     Mailbox,
+    /// Just-in-time (delay simulation start so the CPU submits a frame
+    /// right when the GPU gets to it, keeping the render queue empty)
+    JustInTime,
+}
+
+/// Vertical blanks of the paced display, from scanout events
+#[derive(Debug, Clone, Copy)]
+struct ScanoutClock {
+    /// Vertical blank count at `timestamp_ns`
+    sequence: u64,
+    /// Start of the last vertical blank (`CLOCK_MONOTONIC` ns)
+    timestamp_ns: u64,
+    /// Measured refresh interval (nanoseconds)
+    interval_ns: Option<f32>,
+}
+
+impl ScanoutClock {
+    /// First vertical blank at or after `at_ns`
+    fn next_vblank_ns(&self, at_ns: u64) -> Option<u64> {
+        let interval = self.interval_ns? as u64;
+        if interval == 0 {
+            return None;
+        }
+        if at_ns <= self.timestamp_ns {
+            return Some(self.timestamp_ns);
+        }
+        let intervals = (at_ns - self.timestamp_ns).div_ceil(interval);
+        Some(self.timestamp_ns + intervals * interval)
+    }
 }
 
 /// Frame pacer
@@ -27,6 +66,20 @@ pub struct FramePacer {
     current_frame_time_us: u64,
     /// Frame counter
     frame_count: u64,
+    /// Predicted CPU time of a frame (microseconds)
+    predicted_cpu_time_us: Option<f32>,
+    /// Predicted GPU time of a frame (microseconds)
+    predicted_gpu_time_us: Option<f32>,
+    /// Display whose scanout events are used
+    display: u16,
+    /// Vertical blank timing of `display`, if the driver reports it
+    scanout: Option<ScanoutClock>,
+    /// When the last flip reached the screen (`CLOCK_MONOTONIC` ns)
+    last_flip_ns: Option<u64>,
+    /// Present of the frame waiting for its flip (`CLOCK_MONOTONIC` ns)
+    pending_present_ns: Option<u64>,
+    /// Time from present to scanout of the last frame (microseconds)
+    present_latency_us: Option<u64>,
 }
 
 impl FramePacer {
@@ -47,6 +100,13 @@ impl FramePacer {
             max_flip_queue_depth: 1, // Minimize latency
             current_frame_time_us: 0,
             frame_count: 0,
+            predicted_cpu_time_us: None,
+            predicted_gpu_time_us: None,
+            display: 0,
+            scanout: None,
+            last_flip_ns: None,
+            pending_present_ns: None,
+            present_latency_us: None,
         }
     }
 
@@ -76,6 +136,110 @@ impl FramePacer {
         log::debug!("Max flip queue depth set to {}", depth);
     }
 
+    /// Pace to the scanout of `display`, instead of the first one
+    pub fn set_display(&mut self, display: u16) {
+        if display != self.display {
+            self.display = display;
+            self.scanout = None;
+            self.last_flip_ns = None;
+        }
+    }
+
+    /// Record a scanout event read from the `scanout:` scheme
+    ///
+    /// Events of other displays are ignored.
+    pub fn record_scanout(&mut self, event: &ScanoutEvent) {
+        if event.display != self.display {
+            return;
+        }
+        match event.kind {
+            ScanoutEventKind::Vblank => {
+                let interval_ns = match self.scanout {
+                    Some(clock)
+                        if event.sequence > clock.sequence
+                            && event.timestamp_ns > clock.timestamp_ns =>
+                    {
+                        let sample = (event.timestamp_ns - clock.timestamp_ns)
+                            / (event.sequence - clock.sequence);
+                        Some(predict(clock.interval_ns, sample))
+                    }
+                    // Out of order or restarted, keep the interval measured so far
+                    Some(clock) => clock.interval_ns,
+                    None => None,
+                };
+                self.scanout = Some(ScanoutClock {
+                    sequence: event.sequence,
+                    timestamp_ns: event.timestamp_ns,
+                    interval_ns,
+                });
+            }
+            ScanoutEventKind::FlipComplete => {
+                self.last_flip_ns = Some(event.timestamp_ns);
+                if let Some(present_ns) = self.pending_present_ns.take() {
+                    let latency = event.timestamp_ns.saturating_sub(present_ns);
+                    self.present_latency_us = Some(latency / 1000);
+                }
+            }
+        }
+    }
+
+    /// Record when the frame was presented (`CLOCK_MONOTONIC` ns), its flip
+    /// completing gives the present latency
+    pub fn record_present(&mut self, at_ns: u64) {
+        self.pending_present_ns = Some(at_ns);
+    }
+
+    /// Get the measured refresh interval of the display (microseconds)
+    pub fn refresh_interval_us(&self) -> Option<u64> {
+        self.scanout?
+            .interval_ns
+            .map(|interval| interval as u64 / 1000)
+    }
+
+    /// Get the first vertical blank at or after `at_ns` (`CLOCK_MONOTONIC`
+    /// ns), `None` until the driver reported enough of them
+    pub fn next_vblank_ns(&self, at_ns: u64) -> Option<u64> {
+        self.scanout?.next_vblank_ns(at_ns)
+    }
+
+    /// Get when to start the simulation of the next frame (`CLOCK_MONOTONIC`
+    /// ns), `now_ns` being the current time
+    ///
+    /// With scanout feedback, [`PacingStrategy::JustInTime`] starts the
+    /// frame so that it is ready just before the vertical blank it can make,
+    /// but not before [`target`](Self::set_target_fps) frame time after the
+    /// last flip. Without feedback this is
+    /// [`simulation_delay_us`](Self::simulation_delay_us) from now.
+    pub fn simulation_start_ns(&self, now_ns: u64) -> u64 {
+        let fallback = now_ns + self.simulation_delay_us() * 1000;
+        if self.strategy != PacingStrategy::JustInTime {
+            return fallback;
+        }
+        let (Some(clock), Some(cpu_time), Some(gpu_time)) = (
+            self.scanout,
+            self.predicted_cpu_time_us,
+            self.predicted_gpu_time_us,
+        ) else {
+            return fallback;
+        };
+        let Some(interval) = clock.interval_ns else {
+            return fallback;
+        };
+
+        let frame_ns = ((cpu_time + gpu_time + gpu_time / 10.0) * 1000.0) as u64;
+        let mut ready_ns = now_ns + frame_ns;
+        if let Some(flip_ns) = self.last_flip_ns {
+            // Half an interval early still latches at the intended vertical blank
+            let earliest =
+                flip_ns + (self.target_frame_time_us * 1000).saturating_sub(interval as u64 / 2);
+            ready_ns = ready_ns.max(earliest);
+        }
+        match clock.next_vblank_ns(ready_ns) {
+            Some(vblank_ns) => vblank_ns.saturating_sub(frame_ns).max(now_ns),
+            None => fallback,
+        }
+    }
+
     /// Begin frame
     pub fn begin_frame(&mut self) -> Result<(), LatencyError> {
         self.frame_count += 1;
@@ -83,14 +247,62 @@ impl FramePacer {
         Ok(())
     }
 
+    /// Record how long the GPU took for a frame, measured with a
+    /// [`GpuFrameTimer`]
+    pub fn record_gpu_time(&mut self, gpu_time_us: u64) {
+        self.predicted_gpu_time_us = Some(predict(self.predicted_gpu_time_us, gpu_time_us));
+    }
+
+    /// Get the predicted GPU time of the next frame (microseconds)
+    pub fn predicted_gpu_time_us(&self) -> Option<u64> {
+        self.predicted_gpu_time_us.map(|time| time as u64)
+    }
+
+    /// Get how long to wait before starting the simulation of the next frame
+    /// (microseconds)
+    ///
+    /// Only [`PacingStrategy::JustInTime`] waits. Frames can't go out faster
+    /// than the GPU renders them or the target frame time, so starting the
+    /// CPU work earlier only makes frames wait in the render queue with ever
+    /// older input. The CPU starts just early enough to submit when the GPU
+    /// is done with the previous frame, with a tenth of the GPU time to spare
+    /// so the GPU doesn't go idle on a slower frame.
+    pub fn simulation_delay_us(&self) -> u64 {
+        if self.strategy != PacingStrategy::JustInTime {
+            return 0;
+        }
+        let (Some(cpu_time), Some(gpu_time)) =
+            (self.predicted_cpu_time_us, self.predicted_gpu_time_us)
+        else {
+            return 0;
+        };
+
+        let frame_interval = gpu_time.max(self.target_frame_time_us as f32);
+        let delay = frame_interval - cpu_time - gpu_time / 10.0;
+        if delay > 0.0 {
+            delay as u64
+        } else {
+            0
+        }
+    }
+
     /// End frame and pace
+    ///
+    /// For [`PacingStrategy::JustInTime`], `frame_time_us` is the CPU time
+    /// from simulation start to render submit, without the delay from
+    /// [`simulation_delay_us`](Self::simulation_delay_us).
     pub fn end_frame(&mut self, frame_time_us: u64) -> Result<(), LatencyError> {
         self.current_frame_time_us = frame_time_us;
+        self.predicted_cpu_time_us = Some(predict(self.predicted_cpu_time_us, frame_time_us));
 
         match self.strategy {
             PacingStrategy::VSync => {
                 // Wait for VBlank
-                log::trace!("Frame {} end: VSync wait", self.frame_count);
+                log::trace!(
+                    "Frame {} end: VSync wait, refresh interval {:?} μs",
+                    self.frame_count,
+                    self.refresh_interval_us()
+                );
             }
             PacingStrategy::Immediate => {
                 // No waiting
@@ -111,6 +323,13 @@ impl FramePacer {
                 // Replace queued frame
                 log::trace!("Frame {} end: Mailbox present", self.frame_count);
             }
+            PacingStrategy::JustInTime => {
+                log::trace!(
+                    "Frame {} end: next simulation in {} μs",
+                    self.frame_count,
+                    self.simulation_delay_us()
+                );
+            }
         }
 
         Ok(())
@@ -129,10 +348,106 @@ impl FramePacer {
     pub fn stats(&self) -> LatencyStats {
         let mut stats = LatencyStats::new();
         stats.render_latency_ms = self.current_frame_time_us as f32 / 1000.0;
+        stats.present_latency_ms = self.present_latency_us.unwrap_or(0) as f32 / 1000.0;
+        stats.gpu_render_ms = self.predicted_gpu_time_us.unwrap_or(0.0) / 1000.0;
         stats
     }
 }
 
+/// Blend `sample` into the prediction `previous`
+fn predict(previous: Option<f32>, sample: u64) -> f32 {
+    let sample = sample as f32;
+    match previous {
+        Some(previous) => previous + (sample - previous) * PREDICTION_WEIGHT,
+        None => sample,
+    }
+}
+
+/// Measures the GPU time of frames with timestamp queries
+///
+/// Every frame in flight gets a pair of queries: the first command buffer of
+/// a frame starts with [`write_start`](Self::write_start) and the last one
+/// ends with [`write_end`](Self::write_end). [`poll`](Self::poll) reads the
+/// frames the GPU finished.
+pub struct GpuFrameTimer {
+    pool: Box<dyn QueryPool>,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    /// Frame slot being recorded
+    slot: u32,
+    /// Slots written but not read back yet
+    pending: Vec<bool>,
+}
+
+impl GpuFrameTimer {
+    /// Create a timer for up to `frames_in_flight` frames on `device`
+    pub fn new(device: &dyn Device, frames_in_flight: u32) -> Result<Self, LatencyError> {
+        let frames_in_flight = frames_in_flight.max(1);
+        let pool = device
+            .create_query_pool(QueryType::Timestamp, frames_in_flight * 2)
+            .map_err(|err| {
+                log::warn!("GPU frame timer unavailable: {}", err);
+                LatencyError::NotSupported
+            })?;
+
+        Ok(Self {
+            pool,
+            timestamp_period: device.info().timestamp_period,
+            slot: 0,
+            pending: vec![false; frames_in_flight as usize],
+        })
+    }
+
+    /// Mark the start of the GPU work of a frame
+    pub fn write_start(&mut self, cmd: &mut dyn CommandBuffer) -> Result<(), LatencyError> {
+        if self.pending[self.slot as usize] {
+            // Not read back in time, drop that measurement
+            log::trace!("GPU frame timer: overwriting slot {}", self.slot);
+        }
+        self.pool
+            .reset(self.slot * 2, 2)
+            .and_then(|()| cmd.write_timestamp(self.pool.as_ref(), self.slot * 2))
+            .map_err(|_| LatencyError::MeasurementFailed)?;
+        self.pending[self.slot as usize] = false;
+        Ok(())
+    }
+
+    /// Mark the end of the GPU work of a frame, the next frame goes into the
+    /// next slot
+    pub fn write_end(&mut self, cmd: &mut dyn CommandBuffer) -> Result<(), LatencyError> {
+        cmd.write_timestamp(self.pool.as_ref(), self.slot * 2 + 1)
+            .map_err(|_| LatencyError::MeasurementFailed)?;
+        self.pending[self.slot as usize] = true;
+        self.slot = (self.slot + 1) % self.pending.len() as u32;
+        Ok(())
+    }
+
+    /// Read back the frames the GPU finished, returning the GPU time of the
+    /// newest one (microseconds)
+    pub fn poll(&mut self) -> Result<Option<u64>, LatencyError> {
+        let frames = self.pending.len() as u32;
+        let mut newest = None;
+        // Oldest slot first
+        for offset in 0..frames {
+            let slot = (self.slot + offset) % frames;
+            if !self.pending[slot as usize] {
+                continue;
+            }
+
+            let mut timestamps = [None; 2];
+            self.pool
+                .results(slot * 2, &mut timestamps)
+                .map_err(|_| LatencyError::MeasurementFailed)?;
+            if let [Some(start), Some(end)] = timestamps {
+                let ticks = end.saturating_sub(start);
+                newest = Some((ticks as f32 * self.timestamp_period / 1000.0) as u64);
+                self.pending[slot as usize] = false;
+            }
+        }
+        Ok(newest)
+    }
+}
+
 /// VSync mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VSyncMode {
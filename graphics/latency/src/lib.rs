@@ -4,6 +4,7 @@
 //! - AMD Anti-Lag / Anti-Lag+
 //! - NVIDIA Reflex
 //! - Frame pacing and synchronization
+//! - Scanout timing feedback from the GPU drivers
 
 #![no_std]
 
@@ -11,6 +12,7 @@ extern crate alloc;
 
 pub mod common;
 pub mod frame_pacing;
+pub mod scanout;
 
 #[cfg(feature = "anti-lag")]
 pub mod anti_lag;
@@ -22,7 +24,8 @@ pub mod reflex;
 pub mod boost;
 
 pub use common::{LatencyError, LatencyMode, LatencyStats};
-pub use frame_pacing::FramePacer;
+pub use frame_pacing::{FramePacer, GpuFrameTimer};
+pub use scanout::{ScanoutEvent, ScanoutEventKind};
 
 /// Initialize latency reduction subsystem
 pub fn init() -> Result<(), &'static str> {
@@ -0,0 +1,73 @@
+//! Scanout timing feedback
+//!
+//! GPU drivers report when each display starts its vertical blank and when a
+//! flip reached the screen, as [`ScanoutEvent`]s read from the `scanout:`
+//! scheme. Feeding them to [`FramePacer::record_scanout`] lets frames be
+//! paced to the actual scanout instead of the nominal refresh rate, which
+//! drifts and doesn't hold at all with adaptive sync.
+//!
+//! [`FramePacer::record_scanout`]: crate::FramePacer::record_scanout
+
+/// Size of an encoded event
+pub const EVENT_SIZE: usize = 24;
+
+/// What happened on a display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanoutEventKind {
+    /// Vertical blank started
+    Vblank,
+    /// A flip was latched, the new image is being scanned out
+    FlipComplete,
+}
+
+/// Scanout event of a display
+///
+/// Encoded little endian:
+///
+/// | Offset | Size | Field                                   |
+/// |--------|------|-----------------------------------------|
+/// | 0      | 1    | kind: 0 vertical blank, 1 flip complete |
+/// | 1      | 1    | reserved                                |
+/// | 2      | 2    | display                                 |
+/// | 4      | 4    | reserved                                |
+/// | 8      | 8    | vertical blank count of the display     |
+/// | 16     | 8    | timestamp (`CLOCK_MONOTONIC` ns)        |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanoutEvent {
+    pub kind: ScanoutEventKind,
+    pub display: u16,
+    /// Vertical blanks of the display so far, a flip completes at one
+    pub sequence: u64,
+    pub timestamp_ns: u64,
+}
+
+impl ScanoutEvent {
+    /// Encode the event
+    pub fn to_bytes(&self) -> [u8; EVENT_SIZE] {
+        let mut bytes = [0u8; EVENT_SIZE];
+        bytes[0] = match self.kind {
+            ScanoutEventKind::Vblank => 0,
+            ScanoutEventKind::FlipComplete => 1,
+        };
+        bytes[2..4].copy_from_slice(&self.display.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.timestamp_ns.to_le_bytes());
+        bytes
+    }
+
+    /// Decode an event, `None` if `bytes` is too short or of an unknown kind
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; EVENT_SIZE] = bytes.get(..EVENT_SIZE)?.try_into().ok()?;
+        let kind = match bytes[0] {
+            0 => ScanoutEventKind::Vblank,
+            1 => ScanoutEventKind::FlipComplete,
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            display: u16::from_le_bytes([bytes[2], bytes[3]]),
+            sequence: u64::from_le_bytes(bytes[8..16].try_into().ok()?),
+            timestamp_ns: u64::from_le_bytes(bytes[16..24].try_into().ok()?),
+        })
+    }
+}
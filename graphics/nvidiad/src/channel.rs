@@ -0,0 +1,316 @@
+//! GPFIFO channels and fault recovery
+//!
+//! Each channel writes the sequence number of its last completed
+//! submission to a semaphore in GTT memory. A channel that keeps work
+//! pending without the semaphore moving for longer than the timeout, or
+//! that the GSP reports as faulted, is taken off the runlist and reset
+//! (timeout detection and recovery). Its pending fences are completed and
+//! the channel stays lost, so clients waiting on it see device-lost instead
+//! of blocking forever.
+
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::firmware::Gsp;
+use crate::ttm::{FenceWait, TtmFence, TtmFlags, TtmManager, TtmPlacement};
+
+/// RM client of the driver
+pub const RM_CLIENT: u32 = 0xC1D0_0000;
+/// RM device object under the client
+pub const RM_DEVICE: u32 = 0xDE1D_0000;
+/// RM subdevice object under the device
+pub const RM_SUBDEVICE: u32 = 0x5DE1_0000;
+/// RM handles of channels, ORed with the channel id
+const RM_CHANNEL_BASE: u32 = 0xCAF0_0000;
+
+/// RM classes
+pub mod class {
+    pub const NV01_ROOT: u32 = 0x0000;
+    pub const NV01_DEVICE_0: u32 = 0x0080;
+    pub const NV20_SUBDEVICE_0: u32 = 0x2080;
+    pub const AMPERE_CHANNEL_GPFIFO_A: u32 = 0xC56F;
+}
+
+/// RM controls of channels
+mod ctrl {
+    /// Take the channel on or off the runlist
+    pub const GPFIFO_SCHEDULE: u32 = 0xA06F_0103;
+    /// Reset the channel's engine context
+    pub const RESET_CHANNEL: u32 = 0x906F_0102;
+}
+
+/// Reset reason of RESET_CHANNEL, robust channel recovery
+const RESET_REASON_RC: u32 = 2;
+
+/// Pending work may go this long without progress before the channel is
+/// reset
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Size of the semaphore object
+const SEMAPHORE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    Running,
+    /// Reset after a fault or timeout, submissions and waits fail
+    Lost,
+}
+
+struct Channel {
+    state: ChannelState,
+    /// TTM handle and CPU mapping of the semaphore
+    semaphore: u32,
+    semaphore_addr: usize,
+    /// Last sequence number submitted
+    emitted: u64,
+    /// Last sequence number completed
+    completed: u64,
+    /// When `completed` last moved, or work was first queued after idling
+    last_progress: Instant,
+}
+
+impl Channel {
+    /// Read the semaphore, extending its 32-bit payload against `emitted`
+    fn read_semaphore(&self) -> u64 {
+        // SAFETY: the semaphore stays mapped while the channel exists
+        let value = unsafe { std::ptr::read_volatile(self.semaphore_addr as *const u32) };
+        let behind = (self.emitted as u32).wrapping_sub(value);
+        self.emitted.saturating_sub(u64::from(behind))
+    }
+
+    fn stuck(&self, now: Instant, timeout: Duration) -> bool {
+        self.state == ChannelState::Running
+            && self.completed < self.emitted
+            && now.duration_since(self.last_progress) > timeout
+    }
+}
+
+pub struct ChannelManager {
+    channels: Mutex<BTreeMap<u32, Channel>>,
+    /// Signalled when a channel completes work or is lost
+    progress: Condvar,
+    timeout: Duration,
+}
+
+impl ChannelManager {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            channels: Mutex::new(BTreeMap::new()),
+            progress: Condvar::new(),
+            timeout,
+        }
+    }
+
+    /// Allocate a channel and put it on the runlist
+    pub fn create(&self, gsp: &Gsp, ttm: &TtmManager) -> Result<u32, &'static str> {
+        let id = {
+            let channels = self.channels.lock().unwrap();
+            (0..0x1000)
+                .find(|id| !channels.contains_key(id))
+                .ok_or("No free channel")?
+        };
+
+        let semaphore = ttm.alloc(
+            SEMAPHORE_SIZE,
+            TtmPlacement::Gtt,
+            TtmFlags::PINNED | TtmFlags::CPU_ACCESS | TtmFlags::GPU_ACCESS,
+        )?;
+        let semaphore_addr = ttm.map(semaphore)?;
+        // SAFETY: freshly mapped semaphore
+        unsafe { std::ptr::write_volatile(semaphore_addr as *mut u32, 0) };
+
+        let handle = RM_CHANNEL_BASE | id;
+        let result = gsp
+            .alloc(
+                RM_CLIENT,
+                RM_DEVICE,
+                handle,
+                class::AMPERE_CHANNEL_GPFIFO_A,
+                &id.to_le_bytes(),
+            )
+            .and_then(|()| gsp.control(RM_CLIENT, handle, ctrl::GPFIFO_SCHEDULE, &[1, 0, 0, 0]));
+        if let Err(e) = result {
+            let _ = ttm.free(semaphore);
+            return Err(e);
+        }
+
+        self.channels.lock().unwrap().insert(
+            id,
+            Channel {
+                state: ChannelState::Running,
+                semaphore,
+                semaphore_addr,
+                emitted: 0,
+                completed: 0,
+                last_progress: Instant::now(),
+            },
+        );
+        log::debug!("Channel {} created", id);
+        Ok(id)
+    }
+
+    /// Free a channel, waking anyone still waiting on it
+    pub fn destroy(&self, id: u32, gsp: &Gsp, ttm: &TtmManager) -> Result<(), &'static str> {
+        let channel = self
+            .channels
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or("Invalid channel")?;
+        self.progress.notify_all();
+
+        if channel.state == ChannelState::Running {
+            gsp.control(
+                RM_CLIENT,
+                RM_CHANNEL_BASE | id,
+                ctrl::GPFIFO_SCHEDULE,
+                &[0; 4],
+            )?;
+        }
+        gsp.free(RM_CLIENT, RM_DEVICE, RM_CHANNEL_BASE | id)?;
+        ttm.free(channel.semaphore)
+    }
+
+    pub fn state(&self, id: u32) -> Option<ChannelState> {
+        self.channels.lock().unwrap().get(&id).map(|c| c.state)
+    }
+
+    /// GPU address the channel's pushbuffers release `seq` to
+    pub fn semaphore_addr(&self, id: u32, ttm: &TtmManager) -> Option<u64> {
+        let semaphore = self.channels.lock().unwrap().get(&id)?.semaphore;
+        ttm.get(semaphore).map(|obj| obj.gpu_addr)
+    }
+
+    /// Take the sequence number of a new submission on the channel
+    pub fn emit(&self, id: u32) -> Result<u64, &'static str> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels.get_mut(&id).ok_or("Invalid channel")?;
+        if channel.state == ChannelState::Lost {
+            return Err("Channel lost");
+        }
+        if channel.completed == channel.emitted {
+            // Idle until now, the timeout counts from here
+            channel.last_progress = Instant::now();
+        }
+        channel.emitted += 1;
+        Ok(channel.emitted)
+    }
+
+    /// Whether submission `seq` completed, `Err` once the channel is lost
+    pub fn is_complete(&self, id: u32, seq: u64) -> Result<bool, &'static str> {
+        let channels = self.channels.lock().unwrap();
+        let channel = channels.get(&id).ok_or("Invalid channel")?;
+        if channel.state == ChannelState::Lost {
+            return Err("Channel lost");
+        }
+        Ok(channel.completed >= seq)
+    }
+
+    /// Wait for submission `seq`, returns false on timeout and `Err` once
+    /// the channel is lost
+    pub fn wait(&self, id: u32, seq: u64, timeout: Duration) -> Result<bool, &'static str> {
+        let channels = self.channels.lock().unwrap();
+        let (channels, _) = self
+            .progress
+            .wait_timeout_while(channels, timeout, |channels| {
+                channels
+                    .get(&id)
+                    .is_some_and(|c| c.state == ChannelState::Running && c.completed < seq)
+            })
+            .unwrap();
+        let channel = channels.get(&id).ok_or("Invalid channel")?;
+        if channel.state == ChannelState::Lost {
+            return Err("Channel lost");
+        }
+        Ok(channel.completed >= seq)
+    }
+
+    /// Read the semaphores of running channels, waking waiters of
+    /// completed submissions
+    pub fn retire(&self) {
+        let now = Instant::now();
+        let mut channels = self.channels.lock().unwrap();
+        let mut progressed = false;
+        for channel in channels.values_mut() {
+            if channel.state != ChannelState::Running {
+                continue;
+            }
+            let completed = channel.read_semaphore();
+            if completed > channel.completed {
+                channel.completed = completed;
+                channel.last_progress = now;
+                progressed = true;
+            }
+        }
+        if progressed {
+            self.progress.notify_all();
+        }
+    }
+
+    /// Reset channels that stopped making progress
+    ///
+    /// Completions are normally retired by interrupt, the semaphores are
+    /// read again first so a lost interrupt doesn't look like a hang.
+    pub fn check_timeouts(&self, gsp: &Gsp) {
+        self.retire();
+        let now = Instant::now();
+        let stuck: Vec<_> = self
+            .channels
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, channel)| channel.stuck(now, self.timeout))
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in stuck {
+            log::error!(
+                "Channel {} made no progress for {:?}, resetting",
+                id,
+                self.timeout
+            );
+            self.recover(gsp, id);
+        }
+    }
+
+    /// Reset a channel after a fault or timeout and mark it lost
+    ///
+    /// If the GSP doesn't answer, the whole GPU is considered lost.
+    pub fn recover(&self, gsp: &Gsp, id: u32) {
+        let handle = RM_CHANNEL_BASE | id;
+        let mut reset = [0u8; 12];
+        reset[8..12].copy_from_slice(&RESET_REASON_RC.to_le_bytes());
+        let result = gsp
+            .control(RM_CLIENT, handle, ctrl::GPFIFO_SCHEDULE, &[0; 4])
+            .and_then(|_| gsp.control(RM_CLIENT, handle, ctrl::RESET_CHANNEL, &reset));
+
+        let mut channels = self.channels.lock().unwrap();
+        match result {
+            Ok(_) => {
+                if let Some(channel) = channels.get_mut(&id) {
+                    Self::lose(channel);
+                    log::warn!("Channel {} reset, reporting device lost", id);
+                }
+            }
+            Err(e) => {
+                log::error!("Channel {} reset failed: {}, GPU lost", id, e);
+                channels.values_mut().for_each(Self::lose);
+            }
+        }
+        drop(channels);
+        self.progress.notify_all();
+    }
+
+    fn lose(channel: &mut Channel) {
+        channel.state = ChannelState::Lost;
+        channel.completed = channel.emitted;
+    }
+}
+
+impl FenceWait for ChannelManager {
+    fn wait_fence(&self, fence: TtmFence, timeout: Duration) -> bool {
+        // Lost and destroyed channels don't run anything anymore
+        self.wait(fence.channel, fence.seq, timeout).unwrap_or(true)
+    }
+}
@@ -1,11 +1,33 @@
 //! NVIDIA GPU device management
 
+use driver_graphics::irq::Mmio;
 use std::sync::Arc;
+use std::time::Instant;
+
+use crate::channel::{self, ChannelManager};
+use crate::firmware::{Gsp, GspEvent};
+use crate::ttm::VramAperture;
+
+/// Interrupt registers
+mod intr {
+    /// Pending leaf registers, a bit per pair
+    pub const CPU_INTR_TOP: u32 = 0xB8_1600;
+    pub const CPU_INTR_LEAF: u32 = 0xB8_1000;
+    /// Display heads with pending interrupts
+    pub const DISP_RM_INTR_DISPATCH: u32 = 0x61_1EC0;
+    /// Timing interrupt status of a head
+    pub const DISP_HEAD_TIMING: u32 = 0x61_1800;
+    pub const HEAD_TIMING_VBLANK: u32 = 1 << 2;
+    pub const HEADS: u32 = 4;
+}
 
 pub struct NvidiaDevice {
     vendor_id: u16,
     device_id: u16,
     ttm: Option<Arc<crate::ttm::TtmManager>>,
+    display: Arc<crate::display::NvidiaDisplay>,
+    gsp: Gsp,
+    channels: Arc<ChannelManager>,
 }
 
 impl NvidiaDevice {
@@ -14,6 +36,9 @@ impl NvidiaDevice {
             vendor_id: 0x10de, // NVIDIA
             device_id: 0x0000,
             ttm: None,
+            display: Arc::new(crate::display::NvidiaDisplay::new()),
+            gsp: Gsp::new(),
+            channels: Arc::new(ChannelManager::new(channel::DEFAULT_TIMEOUT)),
         })
     }
 
@@ -25,25 +50,58 @@ impl NvidiaDevice {
     }
 
     pub fn load_firmware(&self) -> Result<(), &'static str> {
+        let ttm = self.ttm.as_ref().ok_or("TTM not initialized")?;
+        self.gsp.init(ttm)?;
         log::info!("GSP-RM firmware loaded");
         Ok(())
     }
 
-    pub fn init_ttm(&mut self) -> Result<(), &'static str> {
+    /// Set up the TTM, with the CPU reaching VRAM through `aperture`
+    pub fn init_ttm(&mut self, aperture: Option<VramAperture>) -> Result<(), &'static str> {
         let vram_size = 512 * 1024 * 1024; // 512MB
         let gtt_size = 1024 * 1024 * 1024; // 1GB
 
-        self.ttm = Some(Arc::new(crate::ttm::TtmManager::new(vram_size, gtt_size)));
+        // Buffers move once the channels using them are done
+        self.ttm = Some(Arc::new(crate::ttm::TtmManager::new(
+            vram_size,
+            gtt_size,
+            aperture,
+            self.channels.clone(),
+        )));
         log::info!(
-            "TTM initialized: VRAM={}MB, GTT={}MB",
+            "TTM initialized: VRAM={}MB, GTT={}MB, BAR1={}MB",
             vram_size / 1024 / 1024,
-            gtt_size / 1024 / 1024
+            gtt_size / 1024 / 1024,
+            aperture.map_or(0, |aperture| aperture.size / 1024 / 1024)
         );
 
         Ok(())
     }
 
     pub fn init_channels(&self) -> Result<(), &'static str> {
+        // Channels are allocated under the driver's RM client and device
+        let gsp = &self.gsp;
+        gsp.alloc(
+            channel::RM_CLIENT,
+            0,
+            channel::RM_CLIENT,
+            channel::class::NV01_ROOT,
+            &[],
+        )?;
+        gsp.alloc(
+            channel::RM_CLIENT,
+            channel::RM_CLIENT,
+            channel::RM_DEVICE,
+            channel::class::NV01_DEVICE_0,
+            &[0; 4],
+        )?;
+        gsp.alloc(
+            channel::RM_CLIENT,
+            channel::RM_DEVICE,
+            channel::RM_SUBDEVICE,
+            channel::class::NV20_SUBDEVICE_0,
+            &[0; 4],
+        )?;
         log::info!("Channels initialized");
         Ok(())
     }
@@ -53,38 +111,84 @@ impl NvidiaDevice {
         Ok(())
     }
 
-    pub fn process_events(&self) {}
+    /// Handle an interrupt, returns whether the GPU raised it
+    pub fn handle_interrupt(&self, mmio: &Mmio) -> bool {
+        let top = mmio.read32(intr::CPU_INTR_TOP);
+        if top == 0 {
+            return false;
+        }
+        // Each top bit covers two leaf registers
+        for bit in (0..32).filter(|bit| top & (1 << bit) != 0) {
+            for leaf in [bit * 2, bit * 2 + 1] {
+                let pending = mmio.read32(intr::CPU_INTR_LEAF + leaf * 4);
+                if pending != 0 {
+                    mmio.write32(intr::CPU_INTR_LEAF + leaf * 4, pending);
+                }
+            }
+        }
+
+        self.process_events();
+
+        let dispatch = mmio.read32(intr::DISP_RM_INTR_DISPATCH);
+        for head in (0..intr::HEADS).filter(|head| dispatch & (1 << head) != 0) {
+            let stat = mmio.read32(intr::DISP_HEAD_TIMING + head * 4);
+            mmio.write32(intr::DISP_HEAD_TIMING + head * 4, stat);
+            // One display is driven, on head 0
+            if head == 0 && stat & intr::HEAD_TIMING_VBLANK != 0 {
+                self.display.vblank(Instant::now());
+            }
+        }
+        true
+    }
+
+    /// Take GSP messages and retire completed submissions
+    pub fn process_events(&self) {
+        match self.gsp.poll_events() {
+            Ok(events) => {
+                for event in events {
+                    match event {
+                        GspEvent::RcTriggered { channel, exception } => {
+                            log::error!("Channel {} faulted: exception {:#x}", channel, exception);
+                            self.channels.recover(&self.gsp, channel);
+                        }
+                        GspEvent::ErrorLog(text) => log::error!("GSP: {}", text),
+                        event => log::debug!("GSP event: {:?}", event),
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to receive GSP events: {}", e),
+        }
+        self.channels.retire();
+    }
+
+    /// Periodic work: hang detection and moving evicted buffers back
+    pub fn watchdog(&self) {
+        self.channels.check_timeouts(&self.gsp);
+        if let Some(ttm) = &self.ttm {
+            ttm.rebalance();
+        }
+    }
+
     pub fn process_submissions(&self) {}
 
+    pub fn display(&self) -> &Arc<crate::display::NvidiaDisplay> {
+        &self.display
+    }
+
     pub fn ttm(&self) -> Option<&Arc<crate::ttm::TtmManager>> {
         self.ttm.as_ref()
     }
+
+    pub fn gsp(&self) -> &Gsp {
+        &self.gsp
+    }
+
+    pub fn channels(&self) -> &ChannelManager {
+        &self.channels
+    }
 }
 
 // Stub modules
-pub mod channel {}
 pub mod pushbuf {}
 pub mod fence {}
 pub mod scheduler {}
-pub mod display {}
-pub mod firmware {}
-
-pub mod gal_backend {
-    use crate::device::NvidiaDevice;
-    use std::sync::Arc;
-
-    pub struct NvidiaGalBackend {
-        device: Arc<NvidiaDevice>,
-    }
-
-    impl NvidiaGalBackend {
-        pub fn new(device: Arc<NvidiaDevice>) -> Self {
-            Self { device }
-        }
-
-        pub fn register(&self) -> Result<(), &'static str> {
-            log::info!("Registered with kernel GAL");
-            Ok(())
-        }
-    }
-}
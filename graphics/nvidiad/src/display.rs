@@ -0,0 +1,118 @@
+//! Display engine (NVDisplay)
+
+use driver_graphics::scanout::ScanoutLog;
+use graphics_api::{DisplayTiming, VrrDisplay, VrrRange, VrrState, VrrTechnology};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// CEA 1920x1080@60 timing, used until a sink is probed
+const DEFAULT_TIMING: DisplayTiming = DisplayTiming {
+    pixel_clock_khz: 148_500,
+    htotal: 2200,
+    vtotal: 1125,
+};
+
+/// Head raster vertical total limits, in lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct RasterVTotal {
+    min: u32,
+    max: u32,
+}
+
+/// Display head driving one display
+///
+/// G-SYNC Compatible mode lets the head stretch its raster between the
+/// minimum and maximum vertical totals until a flip arrives; a present
+/// target pins both to the length of that one frame.
+pub struct NvidiaDisplay {
+    vrr: Mutex<VrrState>,
+    v_total: Mutex<RasterVTotal>,
+    /// Vertical blanks so far
+    vblanks: AtomicU64,
+    scanout: Arc<ScanoutLog>,
+}
+
+impl NvidiaDisplay {
+    pub fn new() -> Self {
+        let vrr = VrrState::new(DEFAULT_TIMING, None, None);
+        let display = Self {
+            vrr: Mutex::new(vrr),
+            v_total: Mutex::new(RasterVTotal::default()),
+            vblanks: AtomicU64::new(0),
+            scanout: Arc::new(ScanoutLog::new()),
+        };
+        display.program(&display.vrr.lock().unwrap());
+        display
+    }
+
+    /// Set up the head for a new sink, reading its adaptive sync range from
+    /// EDID
+    pub fn probe(&self, timing: DisplayTiming, edid: &[u8]) {
+        let range = VrrRange::from_edid(edid);
+        match range {
+            Some(range) => log::info!(
+                "G-SYNC Compatible range: {}-{} Hz",
+                range.min_hz,
+                range.max_hz
+            ),
+            None => log::info!("Sink has no adaptive sync range"),
+        }
+
+        let mut vrr = self.vrr.lock().unwrap();
+        *vrr = VrrState::new(timing, range, Some(VrrTechnology::GSyncCompatible));
+        self.program(&vrr);
+    }
+
+    /// Handle the vertical blank interrupt
+    pub fn vblank(&self, now: Instant) {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.vblank(now);
+        self.program(&vrr);
+
+        let vblanks = self.vblanks.fetch_add(1, Ordering::Relaxed) + 1;
+        self.scanout.vblank(0, vblanks);
+    }
+
+    /// Vertical blank timing, served on `scanout:`
+    pub fn scanout(&self) -> &Arc<ScanoutLog> {
+        &self.scanout
+    }
+
+    fn program(&self, vrr: &VrrState) {
+        let (min, max) = vrr.vtotal_limits();
+        let mut v_total = self.v_total.lock().unwrap();
+        if *v_total != (RasterVTotal { min, max }) {
+            log::debug!("raster vtotal min={} max={}", min, max);
+            *v_total = RasterVTotal { min, max };
+        }
+    }
+}
+
+impl VrrDisplay for NvidiaDisplay {
+    fn vrr_technology(&self) -> Option<VrrTechnology> {
+        self.vrr.lock().unwrap().technology()
+    }
+
+    fn vrr_range(&self) -> Option<VrrRange> {
+        self.vrr.lock().unwrap().range()
+    }
+
+    fn set_adaptive_sync(&self, enabled: bool) -> Result<(), &'static str> {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.set_enabled(enabled)?;
+        self.program(&vrr);
+        Ok(())
+    }
+
+    fn adaptive_sync_enabled(&self) -> bool {
+        self.vrr.lock().unwrap().enabled()
+    }
+
+    fn set_present_target(&self, target: Option<Instant>) -> Result<(), &'static str> {
+        let mut vrr = self.vrr.lock().unwrap();
+        vrr.set_target(target)?;
+        self.program(&vrr);
+        Ok(())
+    }
+}
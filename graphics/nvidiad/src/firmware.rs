@@ -0,0 +1,533 @@
+//! GSP-RM firmware interface
+//!
+//! With GSP firmware, the resource manager runs on the GPU System Processor
+//! and the driver reaches it through remote procedure calls. Calls and
+//! replies travel through two message queues in memory shared with the GSP:
+//! the command queue the driver writes and the status queue the GSP writes.
+//! Each queue starts with the writer's header and the reader's read
+//! pointer, followed by fixed-size elements; a message takes one or more
+//! consecutive elements. The status queue also carries events the GSP
+//! raises on its own, such as channel faults, which are kept until the
+//! driver polls for them.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use crate::ttm::{TtmFlags, TtmManager, TtmPlacement};
+
+/// RPC functions
+pub mod function {
+    pub const FREE: u32 = 10;
+    pub const GSP_SET_SYSTEM_INFO: u32 = 72;
+    pub const SET_REGISTRY: u32 = 73;
+    pub const GSP_RM_CONTROL: u32 = 76;
+    pub const GSP_RM_ALLOC: u32 = 103;
+}
+
+/// Asynchronous events from the GSP
+mod event {
+    pub const FIRST: u32 = 0x1000;
+    pub const GSP_INIT_DONE: u32 = 0x1001;
+    pub const POST_EVENT: u32 = 0x1003;
+    pub const RC_TRIGGERED: u32 = 0x1004;
+    pub const MMU_FAULT_QUEUED: u32 = 0x1005;
+    pub const OS_ERROR_LOG: u32 = 0x1006;
+}
+
+/// Size of a queue element
+const ELEMENT_SIZE: usize = 0x1000;
+/// Elements of each queue
+const ELEMENT_COUNT: usize = 63;
+/// Offset of the first element, after the headers
+const ENTRY_OFFSET: usize = 0x1000;
+/// Offset of the reader's header
+const RX_HEADER_OFFSET: usize = 0x20;
+/// Bytes of the element header before the RPC
+const ELEMENT_HEADER_SIZE: usize = 0x30;
+/// Bytes of the RPC header before its parameters
+const RPC_HEADER_SIZE: usize = 0x20;
+/// Largest message, limited by what the GSP reassembles
+const MAX_MESSAGE_ELEMENTS: usize = 16;
+
+const RPC_HEADER_VERSION: u32 = 0x0300_0000;
+/// "VRPC"
+const RPC_SIGNATURE: u32 = u32::from_le_bytes(*b"VRPC");
+
+/// Status queue polls before a call times out
+const POLL_LIMIT: u32 = 1_000_000;
+
+/// Header fields of the writer's header
+mod tx {
+    pub const VERSION: usize = 0x00;
+    pub const SIZE: usize = 0x04;
+    pub const MSG_SIZE: usize = 0x08;
+    pub const MSG_COUNT: usize = 0x0C;
+    pub const WRITE_PTR: usize = 0x10;
+    pub const FLAGS: usize = 0x14;
+    pub const RX_HDR_OFF: usize = 0x18;
+    pub const ENTRY_OFF: usize = 0x1C;
+}
+
+/// Event the GSP raised
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GspEvent {
+    /// The resource manager finished booting
+    InitDone,
+    /// A channel faulted and needs recovery (robust channels)
+    RcTriggered {
+        channel: u32,
+        exception: u32,
+    },
+    /// MMU faults wait in the fault buffer
+    MmuFaultQueued,
+    /// Notifier event of an RM object
+    PostEvent {
+        object: u32,
+        notify_index: u32,
+    },
+    /// The GSP logged an error
+    ErrorLog(String),
+    Other(u32),
+}
+
+/// Ring of message elements in memory shared with the GSP
+struct MessageQueue {
+    /// CPU mapping of the queue
+    base: usize,
+    /// Bus address of the queue's pages, the GSP is given
+    bus_addr: u64,
+}
+
+impl MessageQueue {
+    const SIZE: usize = ENTRY_OFFSET + ELEMENT_COUNT * ELEMENT_SIZE;
+
+    fn new(ttm: &TtmManager) -> Result<Self, &'static str> {
+        let handle = ttm.alloc(
+            Self::SIZE,
+            TtmPlacement::Gtt,
+            TtmFlags::PINNED | TtmFlags::CPU_ACCESS | TtmFlags::GPU_ACCESS,
+        )?;
+        let base = ttm.map(handle)?;
+        let bus_addr = ttm
+            .get(handle)
+            .and_then(|obj| obj.bus_addr())
+            .ok_or("GSP queue not in DMA memory")?;
+        let queue = Self { base, bus_addr };
+
+        queue.write_u32(tx::VERSION, 0);
+        queue.write_u32(tx::SIZE, Self::SIZE as u32);
+        queue.write_u32(tx::MSG_SIZE, ELEMENT_SIZE as u32);
+        queue.write_u32(tx::MSG_COUNT, ELEMENT_COUNT as u32);
+        queue.write_u32(tx::WRITE_PTR, 0);
+        queue.write_u32(tx::FLAGS, 1);
+        queue.write_u32(tx::RX_HDR_OFF, RX_HEADER_OFFSET as u32);
+        queue.write_u32(tx::ENTRY_OFF, ENTRY_OFFSET as u32);
+        queue.write_u32(RX_HEADER_OFFSET, 0);
+        Ok(queue)
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        // SAFETY: offsets stay within the queue, mapped for the driver's
+        // lifetime
+        unsafe { std::ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_u32(&self, offset: usize, value: u32) {
+        // SAFETY: as in read_u32
+        unsafe { std::ptr::write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    fn write_ptr(&self) -> usize {
+        self.read_u32(tx::WRITE_PTR) as usize % ELEMENT_COUNT
+    }
+
+    fn read_ptr(&self) -> usize {
+        self.read_u32(RX_HEADER_OFFSET) as usize % ELEMENT_COUNT
+    }
+
+    /// Elements the writer may fill, one is kept free to tell full from
+    /// empty
+    fn free_elements(&self) -> usize {
+        (self.read_ptr() + ELEMENT_COUNT - self.write_ptr() - 1) % ELEMENT_COUNT
+    }
+
+    /// Elements the reader may consume
+    fn used_elements(&self) -> usize {
+        (self.write_ptr() + ELEMENT_COUNT - self.read_ptr()) % ELEMENT_COUNT
+    }
+
+    fn element(&self, index: usize) -> usize {
+        self.base + ENTRY_OFFSET + index % ELEMENT_COUNT * ELEMENT_SIZE
+    }
+
+    /// Append `message`, an element header and RPC, as the writer
+    fn push(&self, message: &[u8]) -> Result<(), &'static str> {
+        let count = message.len().div_ceil(ELEMENT_SIZE);
+        if count > self.free_elements() {
+            return Err("GSP command queue full");
+        }
+        let start = self.write_ptr();
+        for (index, chunk) in message.chunks(ELEMENT_SIZE).enumerate() {
+            // SAFETY: the element lies within the queue
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    chunk.as_ptr(),
+                    self.element(start + index) as *mut u8,
+                    chunk.len(),
+                );
+            }
+        }
+        // The GSP must see the elements before the new write pointer
+        std::sync::atomic::fence(Ordering::Release);
+        self.write_u32(tx::WRITE_PTR, ((start + count) % ELEMENT_COUNT) as u32);
+        Ok(())
+    }
+
+    /// Take the next message, as the reader
+    fn pop(&self) -> Result<Option<Vec<u8>>, &'static str> {
+        if self.used_elements() == 0 {
+            return Ok(None);
+        }
+        std::sync::atomic::fence(Ordering::Acquire);
+        let start = self.read_ptr();
+        let mut header = [0u8; ELEMENT_HEADER_SIZE];
+        // SAFETY: the element lies within the queue
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.element(start) as *const u8,
+                header.as_mut_ptr(),
+                header.len(),
+            );
+        }
+        let count = u32_at(&header, 0x28) as usize;
+        if count == 0 || count > MAX_MESSAGE_ELEMENTS || count > self.used_elements() {
+            return Err("Malformed GSP message");
+        }
+
+        let mut message = vec![0u8; count * ELEMENT_SIZE];
+        for (index, chunk) in message.chunks_mut(ELEMENT_SIZE).enumerate() {
+            // SAFETY: as above
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    self.element(start + index) as *const u8,
+                    chunk.as_mut_ptr(),
+                    chunk.len(),
+                );
+            }
+        }
+        self.write_u32(RX_HEADER_OFFSET, ((start + count) % ELEMENT_COUNT) as u32);
+
+        if checksum(&message) != 0 {
+            return Err("GSP message checksum mismatch");
+        }
+        Ok(Some(message))
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// XOR of the message's qwords, folded to 32 bits
+///
+/// Taken with the checksum field zeroed it is the value to store there; over
+/// a message with its checksum it is zero.
+fn checksum(message: &[u8]) -> u32 {
+    let sum = message
+        .chunks(8)
+        .map(|chunk| {
+            let mut qword = [0u8; 8];
+            qword[..chunk.len()].copy_from_slice(chunk);
+            u64::from_le_bytes(qword)
+        })
+        .fold(0, |sum, qword| sum ^ qword);
+    (sum >> 32) as u32 ^ sum as u32
+}
+
+/// Reply to an RPC
+#[derive(Debug)]
+struct Rpc {
+    function: u32,
+    result: u32,
+    sequence: u32,
+    params: Vec<u8>,
+}
+
+impl Rpc {
+    fn parse(message: &[u8]) -> Result<Self, &'static str> {
+        let rpc = &message[ELEMENT_HEADER_SIZE..];
+        if u32_at(rpc, 0x04) != RPC_SIGNATURE {
+            return Err("Invalid GSP RPC signature");
+        }
+        let length = u32_at(rpc, 0x08) as usize;
+        if length < RPC_HEADER_SIZE || length > rpc.len() {
+            return Err("Invalid GSP RPC length");
+        }
+        Ok(Self {
+            function: u32_at(rpc, 0x0C),
+            result: u32_at(rpc, 0x10),
+            sequence: u32_at(rpc, 0x18),
+            params: rpc[RPC_HEADER_SIZE..length].to_vec(),
+        })
+    }
+}
+
+/// GSP-RM resource manager client
+pub struct Gsp {
+    cmdq: Mutex<Option<MessageQueue>>,
+    msgq: Mutex<Option<MessageQueue>>,
+    /// Sequence number of the next element
+    element_seq: AtomicU32,
+    /// Sequence number of the next RPC
+    rpc_seq: AtomicU32,
+    /// Events received and not yet polled
+    events: Mutex<VecDeque<GspEvent>>,
+}
+
+impl Gsp {
+    pub fn new() -> Self {
+        Self {
+            cmdq: Mutex::new(None),
+            msgq: Mutex::new(None),
+            element_seq: AtomicU32::new(0),
+            rpc_seq: AtomicU32::new(0),
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Set up the message queues and wait for the resource manager to boot
+    ///
+    /// The queues' addresses reach the GSP through the boot arguments of
+    /// the firmware image.
+    pub fn init(&self, ttm: &TtmManager) -> Result<(), &'static str> {
+        let cmdq = MessageQueue::new(ttm)?;
+        let msgq = MessageQueue::new(ttm)?;
+        log::debug!(
+            "GSP queues: command {:#x}, status {:#x}",
+            cmdq.bus_addr,
+            msgq.bus_addr
+        );
+        *self.cmdq.lock().unwrap() = Some(cmdq);
+        *self.msgq.lock().unwrap() = Some(msgq);
+
+        self.rpc(function::GSP_SET_SYSTEM_INFO, &[], false)?;
+        self.rpc(function::SET_REGISTRY, &[], false)?;
+
+        for _ in 0..POLL_LIMIT {
+            self.receive()?;
+            let mut events = self.events.lock().unwrap();
+            if let Some(index) = events.iter().position(|e| *e == GspEvent::InitDone) {
+                events.remove(index);
+                log::info!("GSP-RM initialized");
+                return Ok(());
+            }
+            drop(events);
+            core::hint::spin_loop();
+        }
+        Err("GSP-RM init timeout")
+    }
+
+    /// Call an RM control on `object` of `client`, returning its updated
+    /// parameters
+    pub fn control(
+        &self,
+        client: u32,
+        object: u32,
+        cmd: u32,
+        params: &[u8],
+    ) -> Result<Vec<u8>, &'static str> {
+        let mut call = Vec::with_capacity(24 + params.len());
+        for field in [client, object, cmd, 0, params.len() as u32, 0] {
+            call.extend_from_slice(&field.to_le_bytes());
+        }
+        call.extend_from_slice(params);
+
+        let reply = self
+            .rpc(function::GSP_RM_CONTROL, &call, true)?
+            .ok_or("Missing GSP control reply")?;
+        if reply.len() < 24 {
+            return Err("Short GSP control reply");
+        }
+        let status = u32_at(&reply, 12);
+        if status != 0 {
+            log::warn!(
+                "GSP control {:#x} on {:#x}: status {:#x}",
+                cmd,
+                object,
+                status
+            );
+            return Err("GSP control failed");
+        }
+        Ok(reply[24..].to_vec())
+    }
+
+    /// Allocate RM object `object` of `class` under `parent`
+    pub fn alloc(
+        &self,
+        client: u32,
+        parent: u32,
+        object: u32,
+        class: u32,
+        params: &[u8],
+    ) -> Result<(), &'static str> {
+        let mut call = Vec::with_capacity(24 + params.len());
+        for field in [client, parent, object, class, 0, params.len() as u32] {
+            call.extend_from_slice(&field.to_le_bytes());
+        }
+        call.extend_from_slice(params);
+
+        let reply = self
+            .rpc(function::GSP_RM_ALLOC, &call, true)?
+            .ok_or("Missing GSP alloc reply")?;
+        let status = reply.get(16..20).map_or(u32::MAX, |s| u32_at(s, 0));
+        if status != 0 {
+            log::warn!("GSP alloc of class {:#x}: status {:#x}", class, status);
+            return Err("GSP alloc failed");
+        }
+        Ok(())
+    }
+
+    /// Free RM object `object`
+    pub fn free(&self, client: u32, parent: u32, object: u32) -> Result<(), &'static str> {
+        let mut call = Vec::with_capacity(12);
+        for field in [client, parent, object] {
+            call.extend_from_slice(&field.to_le_bytes());
+        }
+        self.rpc(function::FREE, &call, true)?;
+        Ok(())
+    }
+
+    /// Take the events received so far
+    pub fn poll_events(&self) -> Result<Vec<GspEvent>, &'static str> {
+        self.receive()?;
+        Ok(self.events.lock().unwrap().drain(..).collect())
+    }
+
+    /// Send an RPC, and with `wait` return the parameters of its reply
+    fn rpc(
+        &self,
+        function: u32,
+        params: &[u8],
+        wait: bool,
+    ) -> Result<Option<Vec<u8>>, &'static str> {
+        let sequence = self.rpc_seq.fetch_add(1, Ordering::Relaxed);
+        let length = RPC_HEADER_SIZE + params.len();
+        let elements = (ELEMENT_HEADER_SIZE + length).div_ceil(ELEMENT_SIZE);
+        if elements > MAX_MESSAGE_ELEMENTS {
+            return Err("GSP RPC too large");
+        }
+
+        let mut message = vec![0u8; elements * ELEMENT_SIZE];
+        let element_seq = self.element_seq.fetch_add(1, Ordering::Relaxed);
+        message[0x24..0x28].copy_from_slice(&element_seq.to_le_bytes());
+        message[0x28..0x2C].copy_from_slice(&(elements as u32).to_le_bytes());
+        let rpc = &mut message[ELEMENT_HEADER_SIZE..];
+        for (offset, field) in [
+            RPC_HEADER_VERSION,
+            RPC_SIGNATURE,
+            length as u32,
+            function,
+            0xFFFF_FFFF,
+            0xFFFF_FFFF,
+            sequence,
+            0,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            rpc[offset * 4..offset * 4 + 4].copy_from_slice(&field.to_le_bytes());
+        }
+        rpc[RPC_HEADER_SIZE..length].copy_from_slice(params);
+        let sum = checksum(&message);
+        message[0x20..0x24].copy_from_slice(&sum.to_le_bytes());
+
+        {
+            let cmdq = self.cmdq.lock().unwrap();
+            let cmdq = cmdq.as_ref().ok_or("GSP not initialized")?;
+            cmdq.push(&message)?;
+            // The new write pointer goes to the GSP's queue head register
+            log::trace!(
+                "GSP RPC {} seq {} wptr {}",
+                function,
+                sequence,
+                cmdq.write_ptr()
+            );
+        }
+        if !wait {
+            return Ok(None);
+        }
+
+        for _ in 0..POLL_LIMIT {
+            if let Some(reply) = self.receive_reply(function, sequence)? {
+                if reply.result != 0 {
+                    log::warn!("GSP RPC {} failed: {:#x}", function, reply.result);
+                    return Err("GSP RPC failed");
+                }
+                return Ok(Some(reply.params));
+            }
+            core::hint::spin_loop();
+        }
+        Err("GSP RPC timeout")
+    }
+
+    /// Drain the status queue, keeping events, until the reply to
+    /// `function` call `sequence` shows up
+    fn receive_reply(&self, function: u32, sequence: u32) -> Result<Option<Rpc>, &'static str> {
+        let msgq = self.msgq.lock().unwrap();
+        let msgq = msgq.as_ref().ok_or("GSP not initialized")?;
+        while let Some(message) = msgq.pop()? {
+            let rpc = Rpc::parse(&message)?;
+            if rpc.function >= event::FIRST {
+                self.queue_event(rpc);
+            } else if rpc.function == function && rpc.sequence == sequence {
+                return Ok(Some(rpc));
+            } else {
+                log::warn!("Unexpected GSP reply to RPC {}", rpc.function);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Drain the status queue of events
+    fn receive(&self) -> Result<(), &'static str> {
+        let msgq = self.msgq.lock().unwrap();
+        let msgq = msgq.as_ref().ok_or("GSP not initialized")?;
+        while let Some(message) = msgq.pop()? {
+            let rpc = Rpc::parse(&message)?;
+            if rpc.function >= event::FIRST {
+                self.queue_event(rpc);
+            } else {
+                log::warn!("Unexpected GSP reply to RPC {}", rpc.function);
+            }
+        }
+        Ok(())
+    }
+
+    fn queue_event(&self, rpc: Rpc) {
+        let param = |index: usize| {
+            rpc.params
+                .get(index * 4..index * 4 + 4)
+                .map_or(0, |field| u32_at(field, 0))
+        };
+        let event = match rpc.function {
+            event::GSP_INIT_DONE => GspEvent::InitDone,
+            event::RC_TRIGGERED => GspEvent::RcTriggered {
+                channel: param(2),
+                exception: param(1),
+            },
+            event::MMU_FAULT_QUEUED => GspEvent::MmuFaultQueued,
+            event::POST_EVENT => GspEvent::PostEvent {
+                object: param(1),
+                notify_index: param(2),
+            },
+            event::OS_ERROR_LOG => {
+                let text = rpc.params.get(8..).unwrap_or_default();
+                let end = text.iter().position(|&c| c == 0).unwrap_or(text.len());
+                GspEvent::ErrorLog(String::from_utf8_lossy(&text[..end]).into_owned())
+            }
+            other => GspEvent::Other(other),
+        };
+        self.events.lock().unwrap().push_back(event);
+    }
+}
@@ -0,0 +1,238 @@
+//! GAL backend
+//!
+//! Submissions of GAL clients run on channels and complete through
+//! [`NvidiaFence`]s. Once a channel is reset after a fault or timeout, its
+//! fences and further submissions fail with [`gal::Error::DeviceLost`], so
+//! the client can recreate its context instead of waiting forever.
+//!
+//! For render offload to another GPU, images are rendered into shared
+//! system memory and exported together with their fences, see
+//! [`driver_graphics::external`].
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use driver_graphics::external::{ExternalExports, ImportedFence, SharedMemory};
+use gal::allocator::{Heap, HeapStats};
+use gal::{
+    DeviceCapabilities, ExternalFence, ExternalImageLayout, ExternalMemory, Image, ImageDescriptor,
+    ScanoutImage,
+};
+
+use crate::channel::ChannelState;
+use crate::device::NvidiaDevice;
+use crate::ttm::{TtmFence, TtmFlags, TtmPlacement};
+
+/// Pitch alignment of linear render targets
+const PITCH_ALIGNMENT: u32 = 256;
+
+pub struct NvidiaGalBackend {
+    device: Arc<NvidiaDevice>,
+    exports: Arc<ExternalExports>,
+    /// System memory behind shared images, by TTM handle
+    shared: Mutex<HashMap<u32, Arc<SharedMemory>>>,
+}
+
+impl NvidiaGalBackend {
+    pub fn new(device: Arc<NvidiaDevice>, exports: Arc<ExternalExports>) -> Self {
+        Self {
+            device,
+            exports,
+            shared: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Capabilities of the backend, as `gal::DeviceInfo` reports them
+    pub fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities::RENDER_3D
+            | DeviceCapabilities::COMPUTE
+            | DeviceCapabilities::EXTERNAL_MEMORY
+            | DeviceCapabilities::EXTERNAL_FENCE
+    }
+
+    pub fn register(&self) -> Result<(), &'static str> {
+        log::info!("Registered with kernel GAL");
+        Ok(())
+    }
+
+    /// Create a channel for a client context
+    pub fn create_channel(&self) -> gal::Result<u32> {
+        let ttm = self.device.ttm().ok_or(gal::Error::DeviceNotFound)?;
+        self.device
+            .channels()
+            .create(self.device.gsp(), ttm)
+            .map_err(|e| {
+                log::error!("Failed to create channel: {}", e);
+                gal::Error::OperationFailed
+            })
+    }
+
+    pub fn destroy_channel(&self, channel: u32) -> gal::Result<()> {
+        let ttm = self.device.ttm().ok_or(gal::Error::DeviceNotFound)?;
+        self.device
+            .channels()
+            .destroy(channel, self.device.gsp(), ttm)
+            .map_err(|e| {
+                log::warn!("Failed to destroy channel {}: {}", channel, e);
+                gal::Error::OperationFailed
+            })
+    }
+
+    /// Usage and budget of a heap, as [`gal::Device::memory_budget`]
+    /// reports them
+    pub fn memory_budget(&self, heap: Heap) -> Option<HeapStats> {
+        self.device.ttm().map(|ttm| ttm.heap_stats(heap))
+    }
+
+    /// Queue a submission on `channel` using the TTM buffers `buffers`,
+    /// returning the fence it signals
+    ///
+    /// The submission's pushbuffer ends by releasing the fence's sequence
+    /// number to the channel's semaphore. The buffers aren't moved until
+    /// then.
+    pub fn submit(&self, channel: u32, buffers: &[u32]) -> gal::Result<NvidiaFence> {
+        let seq = self
+            .device
+            .channels()
+            .emit(channel)
+            .map_err(|_| channel_error(&self.device, channel))?;
+        if let Some(ttm) = self.device.ttm() {
+            for &handle in buffers {
+                ttm.fence(handle, TtmFence { channel, seq });
+            }
+        }
+        Ok(NvidiaFence {
+            device: self.device.clone(),
+            channel,
+            seq,
+        })
+    }
+
+    /// Create a linear image in system memory that another driver can
+    /// import
+    ///
+    /// The pages are bound into the GTT aperture, so the GPU renders into
+    /// them directly and the display GPU reads them without a copy.
+    pub fn create_shared_image(&self, descriptor: &ImageDescriptor) -> gal::Result<Box<dyn Image>> {
+        descriptor.validate()?;
+        let extent = gal::Extent2D::new(descriptor.extent.width, descriptor.extent.height);
+        let layout = ExternalImageLayout::linear(descriptor.format, extent, PITCH_ALIGNMENT)
+            .ok_or(gal::Error::NotSupported)?;
+        let size = layout.size() as usize;
+
+        let ttm = self.device.ttm().ok_or(gal::Error::DeviceNotFound)?;
+        let flags = TtmFlags::PINNED | TtmFlags::CPU_ACCESS | TtmFlags::GPU_ACCESS;
+        let handle = ttm
+            .alloc(size, TtmPlacement::Gtt, flags)
+            .map_err(|_| gal::Error::OutOfDeviceMemory)?;
+        let memory = match SharedMemory::new(size) {
+            Ok(memory) => memory,
+            Err(e) => {
+                log::warn!("Failed to allocate shared image: {}", e);
+                let _ = ttm.free(handle);
+                return Err(gal::Error::OutOfMemory);
+            }
+        };
+        log::debug!(
+            "Shared image {}: {} bytes in {} chunk(s)",
+            handle,
+            size,
+            memory.chunks().count()
+        );
+
+        self.shared.lock().unwrap().insert(handle, Arc::new(memory));
+        Ok(Box::new(ScanoutImage::new(handle as usize, descriptor)))
+    }
+
+    /// Destroy an image from [`NvidiaGalBackend::create_shared_image`]
+    ///
+    /// Importers keep the pages until they close their handles.
+    pub fn destroy_shared_image(&self, image: Box<dyn Image>) {
+        let handle = image.handle() as u32;
+        self.shared.lock().unwrap().remove(&handle);
+        if let Some(ttm) = self.device.ttm() {
+            if let Err(e) = ttm.free(handle) {
+                log::warn!("Failed to free shared image: {}", e);
+            }
+        }
+    }
+
+    /// Export a shared image for another driver to import
+    pub fn export_image(
+        &self,
+        image: &dyn Image,
+    ) -> gal::Result<(ExternalMemory, ExternalImageLayout)> {
+        let memory = self
+            .shared
+            .lock()
+            .unwrap()
+            .get(&(image.handle() as u32))
+            .cloned()
+            // Only shared images live in memory other GPUs can reach
+            .ok_or(gal::Error::NotSupported)?;
+        let layout =
+            ExternalImageLayout::linear(image.format(), image.extent_2d(), PITCH_ALIGNMENT)
+                .ok_or(gal::Error::NotSupported)?;
+        Ok((self.exports.export_memory(memory)?, layout))
+    }
+
+    /// Export a submission fence, readable once the submission completed
+    pub fn export_fence(&self, fence: &NvidiaFence) -> gal::Result<ExternalFence> {
+        let (device, channel, seq) = (fence.device.clone(), fence.channel, fence.seq);
+        // Wake importers of a lost channel rather than leave them waiting
+        self.exports
+            .export_fence(move || device.channels().is_complete(channel, seq).unwrap_or(true))
+    }
+
+    /// Import a fence exported by another driver
+    pub fn import_fence(&self, fence: &ExternalFence) -> gal::Result<Box<dyn gal::Fence>> {
+        Ok(Box::new(ImportedFence::new(fence)?))
+    }
+
+    /// Wake importers waiting for fences completed since the last call
+    pub fn signal_fences(&self) {
+        self.exports.signal_fences();
+    }
+}
+
+/// Error for a failed channel operation, device-lost once it was reset
+fn channel_error(device: &NvidiaDevice, channel: u32) -> gal::Error {
+    match device.channels().state(channel) {
+        Some(ChannelState::Lost) => gal::Error::DeviceLost,
+        Some(ChannelState::Running) => gal::Error::OperationFailed,
+        None => gal::Error::InvalidParameter,
+    }
+}
+
+/// Fence signalled when a channel submission completes
+pub struct NvidiaFence {
+    device: Arc<NvidiaDevice>,
+    channel: u32,
+    seq: u64,
+}
+
+impl gal::Fence for NvidiaFence {
+    fn handle(&self) -> usize {
+        self.seq as usize
+    }
+
+    fn is_signaled(&self) -> gal::Result<bool> {
+        self.device
+            .channels()
+            .is_complete(self.channel, self.seq)
+            .map_err(|_| channel_error(&self.device, self.channel))
+    }
+
+    fn wait(&self, timeout_ns: u64) -> gal::Result<bool> {
+        self.device
+            .channels()
+            .wait(self.channel, self.seq, Duration::from_nanos(timeout_ns))
+            .map_err(|_| channel_error(&self.device, self.channel))
+    }
+
+    fn reset(&self) -> gal::Result<()> {
+        // Submission fences signal once, a new submission gets a new fence
+        Err(gal::Error::NotSupported)
+    }
+}
@@ -2,8 +2,12 @@
 //!
 //! Native NVIDIA GPU driver with TTM memory manager and kernel GAL integration.
 
+use driver_graphics::external::ExternalExports;
+use driver_graphics::irq::{GpuEventLoop, InterruptHandler, Mmio};
+use pcid_interface::PciFunctionHandle;
 use redox_daemon::Daemon;
 use std::sync::Arc;
+use std::time::Duration;
 
 mod channel;
 mod device;
@@ -17,6 +21,30 @@ mod ttm;
 
 use device::NvidiaDevice;
 use gal_backend::NvidiaGalBackend;
+use ttm::VramAperture;
+
+/// Period of hang detection and TTM rebalancing
+const WATCHDOG_PERIOD: Duration = Duration::from_millis(100);
+
+/// Routes interrupts and the watchdog timer to the device
+struct Interrupts {
+    device: Arc<NvidiaDevice>,
+    gal_backend: Arc<NvidiaGalBackend>,
+    mmio: Mmio,
+}
+
+impl InterruptHandler for Interrupts {
+    fn interrupt(&self, _vector: usize) -> bool {
+        let raised = self.device.handle_interrupt(&self.mmio);
+        self.device.process_submissions();
+        self.gal_backend.signal_fences();
+        raised
+    }
+
+    fn timer(&self) {
+        self.device.watchdog();
+    }
+}
 
 fn daemon(daemon: Daemon) -> ! {
     common::setup_logging(
@@ -29,15 +57,29 @@ fn daemon(daemon: Daemon) -> ! {
 
     log::info!("NVIDIA GPU Driver starting...");
 
+    let mut pcid_handle = PciFunctionHandle::connect_default();
+    let mmio = {
+        let bar = unsafe { pcid_handle.map_bar(0) };
+        unsafe { Mmio::new(bar.ptr.as_ptr(), bar.bar_size) }
+    };
+    // BAR1 is the CPU's window onto VRAM
+    let aperture = {
+        let bar = unsafe { pcid_handle.map_bar(1) };
+        VramAperture {
+            base: bar.ptr.as_ptr() as usize,
+            size: bar.bar_size,
+        }
+    };
+
     // Initialize PCI device
-    let device = match NvidiaDevice::new() {
+    let mut device = match NvidiaDevice::new() {
         Ok(dev) => {
             log::info!(
                 "NVIDIA GPU detected: {:04x}:{:04x}",
                 dev.vendor_id(),
                 dev.device_id()
             );
-            Arc::new(dev)
+            dev
         }
         Err(e) => {
             log::error!("Failed to initialize NVIDIA GPU: {}", e);
@@ -45,15 +87,16 @@ fn daemon(daemon: Daemon) -> ! {
         }
     };
 
-    // Load GSP-RM firmware
-    if let Err(e) = device.load_firmware() {
-        log::error!("Failed to load firmware: {}", e);
+    // Initialize TTM memory manager, which holds the GSP message queues
+    if let Err(e) = device.init_ttm(Some(aperture)) {
+        log::error!("Failed to initialize TTM: {}", e);
         std::process::exit(1);
     }
+    let device = Arc::new(device);
 
-    // Initialize TTM memory manager
-    if let Err(e) = device.init_ttm() {
-        log::error!("Failed to initialize TTM: {}", e);
+    // Load GSP-RM firmware
+    if let Err(e) = device.load_firmware() {
+        log::error!("Failed to load firmware: {}", e);
         std::process::exit(1);
     }
 
@@ -70,7 +113,8 @@ fn daemon(daemon: Daemon) -> ! {
     }
 
     // Create GAL backend
-    let gal_backend = Arc::new(NvidiaGalBackend::new(device.clone()));
+    let exports = ExternalExports::new("nvidia-external");
+    let gal_backend = Arc::new(NvidiaGalBackend::new(device.clone(), exports.clone()));
 
     // Register with kernel GAL
     if let Err(e) = gal_backend.register() {
@@ -78,16 +122,35 @@ fn daemon(daemon: Daemon) -> ! {
         std::process::exit(1);
     }
 
+    let scanout = device.display().scanout().clone();
+    std::thread::spawn(move || {
+        if let Err(e) = driver_graphics::scanout::serve_scanout(scanout) {
+            log::error!("Failed to serve scanout: {}", e);
+        }
+    });
+    std::thread::spawn(move || {
+        if let Err(e) = driver_graphics::external::serve_external(exports) {
+            log::error!("Failed to serve external memory: {}", e);
+        }
+    });
+
+    let event_loop = match GpuEventLoop::new(&mut pcid_handle, "nvidiad", 1, WATCHDOG_PERIOD) {
+        Ok(event_loop) => event_loop,
+        Err(e) => {
+            log::error!("Failed to set up interrupts: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     log::info!("NVIDIA GPU driver ready");
 
     daemon.ready().expect("Failed to mark daemon as ready");
 
-    // Main event loop
-    loop {
-        device.process_events();
-        device.process_submissions();
-        std::thread::sleep(std::time::Duration::from_millis(1));
-    }
+    event_loop.run(&Interrupts {
+        device,
+        gal_backend,
+        mmio,
+    })
 }
 
 fn main() {
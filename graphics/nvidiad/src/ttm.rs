@@ -1,10 +1,119 @@
 //! TTM (Translation Table Manager) Memory Manager for NVIDIA GPUs
+//!
+//! Buffers live in VRAM, in GTT (system memory bound into the GPU's
+//! aperture) or in plain system memory the GPU can't reach. When a
+//! placement runs out of space, its least recently used evictable buffers
+//! move one step down, VRAM to GTT and GTT to system memory. VRAM
+//! allocations that still don't fit fall back to GTT unless pinned, so
+//! large scenes get slower instead of failing; [`TtmManager::rebalance`]
+//! moves them back once VRAM frees up.
+//!
+//! The CPU reaches VRAM through the BAR1 aperture, which may cover only
+//! part of it, and GTT buffers through the driver's mapping of their DMA
+//! pages. Moving a buffer waits for the last submission using it, then
+//! copies its contents through those mappings before the old range is
+//! freed. Buffers the CPU can't reach, or that are mapped or still busy,
+//! stay where they are.
 
 use bitflags::bitflags;
-use std::collections::HashMap;
+use common::dma::Dma;
+use gal::allocator::{Heap, HeapStats};
+use std::alloc::Layout;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ptr::NonNull;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long eviction waits for the GPU to finish with a buffer, past the
+/// channel timeout so hung channels are reset by then
+const EVICT_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// CPU mapping of VRAM through BAR1
+#[derive(Debug, Clone, Copy)]
+pub struct VramAperture {
+    /// Address of the BAR's mapping
+    pub base: usize,
+    /// Bytes of VRAM it covers, from offset 0
+    pub size: usize,
+}
+
+/// DMA pages of a GTT buffer
+struct GttPages {
+    /// Driver mapping of the pages
+    ptr: *mut u8,
+    dma: Dma<[u8]>,
+}
+
+// SAFETY: the pages are only reached through `ptr`, shared with the GPU
+unsafe impl Send for GttPages {}
+unsafe impl Sync for GttPages {}
+
+impl GttPages {
+    fn new(size: usize) -> Result<Self, &'static str> {
+        let dma = Dma::<[u8]>::zeroed_slice(size).map_err(|_| "Out of DMA memory")?;
+        // SAFETY: zeroed bytes are initialized
+        let mut dma = unsafe { dma.assume_init() };
+        Ok(Self {
+            ptr: dma.as_mut_ptr(),
+            dma,
+        })
+    }
+}
+
+/// Pages of a buffer in system memory
+struct SystemPages {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// SAFETY: as for GttPages, only the CPU reaches these
+unsafe impl Send for SystemPages {}
+unsafe impl Sync for SystemPages {}
+
+impl SystemPages {
+    fn new(size: usize) -> Result<Self, &'static str> {
+        let layout = Layout::from_size_align(MemoryPool::aligned(size.max(1)) as usize, 4096)
+            .map_err(|_| "Buffer too large")?;
+        // SAFETY: the layout isn't empty
+        let ptr = NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) })
+            .ok_or("Out of system memory")?;
+        Ok(Self { ptr, layout })
+    }
+}
+
+impl Drop for SystemPages {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `new` with the same layout
+        unsafe { std::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// Memory behind a buffer
+enum Backing {
+    /// Range of VRAM at the buffer's GPU address
+    Vram,
+    /// DMA pages, bound at the buffer's GPU address in the GTT aperture
+    Gtt(GttPages),
+    /// Pages out of the GPU's reach
+    System(SystemPages),
+}
+
+/// Submission a buffer was last used by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtmFence {
+    pub channel: u32,
+    pub seq: u64,
+}
+
+/// Tells when the GPU is done with a buffer
+pub trait FenceWait: Send + Sync {
+    /// Wait up to `timeout` for `fence`, returns whether the submission
+    /// no longer runs
+    fn wait_fence(&self, fence: TtmFence, timeout: Duration) -> bool;
+}
 
 /// TTM buffer object
+#[derive(Clone)]
 pub struct TtmObject {
     /// Unique handle
     pub handle: u32,
@@ -16,12 +125,34 @@ pub struct TtmObject {
     pub cpu_addr: Option<usize>,
     /// Placement
     pub placement: TtmPlacement,
+    /// Placement asked for at allocation, differs after eviction
+    pub preferred: TtmPlacement,
     /// Flags
     pub flags: TtmFlags,
+    /// Last submission using the buffer
+    pub fence: Option<TtmFence>,
+    /// Memory behind the buffer
+    backing: Arc<Backing>,
+}
+
+impl TtmObject {
+    fn evictable(&self) -> bool {
+        self.flags.contains(TtmFlags::EVICTABLE) && !self.flags.contains(TtmFlags::PINNED)
+    }
+
+    /// Bus address of a GTT buffer's pages, for engines given physical
+    /// addresses such as the GSP
+    pub fn bus_addr(&self) -> Option<u64> {
+        match &*self.backing {
+            Backing::Gtt(pages) => Some(pages.dma.physical() as u64),
+            Backing::Vram | Backing::System(_) => None,
+        }
+    }
 }
 
 bitflags! {
     /// TTM buffer flags
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct TtmFlags: u32 {
         /// Buffer can be evicted
         const EVICTABLE = 1 << 0;
@@ -45,26 +176,90 @@ pub enum TtmPlacement {
     System,
 }
 
+impl TtmPlacement {
+    /// Placement buffers are evicted to
+    fn lower(self) -> Option<Self> {
+        match self {
+            TtmPlacement::Vram => Some(TtmPlacement::Gtt),
+            TtmPlacement::Gtt => Some(TtmPlacement::System),
+            TtmPlacement::System => None,
+        }
+    }
+}
+
+/// Address space of VRAM and the GTT aperture, with eviction order
+struct Pools {
+    vram: MemoryPool,
+    gtt: MemoryPool,
+    /// Evictable buffers in VRAM, least recently used first
+    vram_lru: VecDeque<u32>,
+    /// Evictable buffers in GTT, least recently used first
+    gtt_lru: VecDeque<u32>,
+    /// Bytes evicted from VRAM so far
+    vram_evicted: u64,
+}
+
+impl Pools {
+    fn pool(&mut self, placement: TtmPlacement) -> Option<&mut MemoryPool> {
+        match placement {
+            TtmPlacement::Vram => Some(&mut self.vram),
+            TtmPlacement::Gtt => Some(&mut self.gtt),
+            TtmPlacement::System => None,
+        }
+    }
+
+    fn lru(&mut self, placement: TtmPlacement) -> Option<&mut VecDeque<u32>> {
+        match placement {
+            TtmPlacement::Vram => Some(&mut self.vram_lru),
+            TtmPlacement::Gtt => Some(&mut self.gtt_lru),
+            TtmPlacement::System => None,
+        }
+    }
+
+    fn unlink(&mut self, obj: &TtmObject) {
+        if let Some(lru) = self.lru(obj.placement) {
+            lru.retain(|&handle| handle != obj.handle);
+        }
+        if let Some(pool) = self.pool(obj.placement) {
+            pool.free(obj.gpu_addr, obj.size);
+        }
+    }
+}
+
 /// TTM memory manager
 pub struct TtmManager {
     /// Buffer objects
     objects: Mutex<HashMap<u32, Arc<TtmObject>>>,
     /// Next handle
     next_handle: Mutex<u32>,
-    /// VRAM pool
-    vram_pool: Mutex<MemoryPool>,
-    /// GTT pool
-    gtt_pool: Mutex<MemoryPool>,
+    /// Memory pools, locked after `objects`
+    pools: Mutex<Pools>,
+    /// CPU mapping of VRAM, if BAR1 is mapped
+    aperture: Option<VramAperture>,
+    /// Waits for submissions before buffers move
+    fences: Arc<dyn FenceWait>,
 }
 
 impl TtmManager {
     /// Create new TTM manager
-    pub fn new(vram_size: u64, gtt_size: u64) -> Self {
+    pub fn new(
+        vram_size: u64,
+        gtt_size: u64,
+        aperture: Option<VramAperture>,
+        fences: Arc<dyn FenceWait>,
+    ) -> Self {
         Self {
             objects: Mutex::new(HashMap::new()),
             next_handle: Mutex::new(1),
-            vram_pool: Mutex::new(MemoryPool::new(vram_size)),
-            gtt_pool: Mutex::new(MemoryPool::new(gtt_size)),
+            pools: Mutex::new(Pools {
+                vram: MemoryPool::new(vram_size),
+                gtt: MemoryPool::new(gtt_size),
+                vram_lru: VecDeque::new(),
+                gtt_lru: VecDeque::new(),
+                vram_evicted: 0,
+            }),
+            aperture,
+            fences,
         }
     }
 
@@ -82,45 +277,45 @@ impl TtmManager {
             h
         };
 
-        // Allocate from appropriate pool
-        let gpu_addr = match placement {
-            TtmPlacement::Vram => self.vram_pool.lock().unwrap().alloc(size)?,
-            TtmPlacement::Gtt | TtmPlacement::System => {
-                self.gtt_pool.lock().unwrap().alloc(size)?
-            }
-        };
+        let mut objects = self.objects.lock().unwrap();
+        let mut pools = self.pools.lock().unwrap();
+        let (actual, (gpu_addr, backing)) =
+            match self.place(&mut objects, &mut pools, size, placement) {
+                Ok(place) => (placement, place),
+                Err(_) if placement == TtmPlacement::Vram && !flags.contains(TtmFlags::PINNED) => {
+                    log::debug!("VRAM full, placing {} bytes in GTT", size);
+                    let place = self.place(&mut objects, &mut pools, size, TtmPlacement::Gtt)?;
+                    (TtmPlacement::Gtt, place)
+                }
+                Err(e) => return Err(e),
+            };
 
         let obj = Arc::new(TtmObject {
             handle,
             size,
             gpu_addr,
             cpu_addr: None,
-            placement,
+            placement: actual,
+            preferred: placement,
             flags,
+            fence: None,
+            backing: Arc::new(backing),
         });
-
-        self.objects.lock().unwrap().insert(handle, obj);
+        if obj.evictable() {
+            if let Some(lru) = pools.lru(actual) {
+                lru.push_back(handle);
+            }
+        }
+        objects.insert(handle, obj);
 
         Ok(handle)
     }
 
     /// Free TTM object
     pub fn free(&self, handle: u32) -> Result<(), &'static str> {
-        let obj = self
-            .objects
-            .lock()
-            .unwrap()
-            .remove(&handle)
-            .ok_or("Invalid handle")?;
-
-        // Free from pool
-        match obj.placement {
-            TtmPlacement::Vram => self.vram_pool.lock().unwrap().free(obj.gpu_addr, obj.size),
-            TtmPlacement::Gtt | TtmPlacement::System => {
-                self.gtt_pool.lock().unwrap().free(obj.gpu_addr, obj.size)
-            }
-        }
-
+        let mut objects = self.objects.lock().unwrap();
+        let obj = objects.remove(&handle).ok_or("Invalid handle")?;
+        self.pools.lock().unwrap().unlink(&obj);
         Ok(())
     }
 
@@ -129,44 +324,393 @@ impl TtmManager {
         self.objects.lock().unwrap().get(&handle).cloned()
     }
 
+    /// Map buffer for CPU access
+    ///
+    /// VRAM buffers beyond the BAR1 aperture can't be mapped, mapped
+    /// buffers aren't moved anymore.
+    pub fn map(&self, handle: u32) -> Result<usize, &'static str> {
+        let mut objects = self.objects.lock().unwrap();
+        let obj = objects.get_mut(&handle).ok_or("Invalid handle")?;
+
+        let cpu_addr = self
+            .cpu_ptr(&obj.backing, obj.gpu_addr, obj.size)
+            .ok_or("Buffer out of the CPU's reach")? as usize;
+        Arc::get_mut(obj).ok_or("Buffer in use")?.cpu_addr = Some(cpu_addr);
+
+        Ok(cpu_addr)
+    }
+
+    /// Mark a buffer as used, making it the last of its placement to be
+    /// evicted
+    pub fn touch(&self, handle: u32) {
+        let objects = self.objects.lock().unwrap();
+        let Some(obj) = objects.get(&handle) else {
+            return;
+        };
+        if let Some(lru) = self.pools.lock().unwrap().lru(obj.placement) {
+            if let Some(index) = lru.iter().position(|&h| h == handle) {
+                lru.remove(index);
+                lru.push_back(handle);
+            }
+        }
+    }
+
+    /// Record a submission using a buffer, which keeps it in place until
+    /// the submission completed
+    pub fn fence(&self, handle: u32, fence: TtmFence) {
+        {
+            let mut objects = self.objects.lock().unwrap();
+            let Some(obj) = objects.get_mut(&handle) else {
+                return;
+            };
+            let mut used = TtmObject::clone(obj);
+            used.fence = Some(fence);
+            *obj = Arc::new(used);
+        }
+        self.touch(handle);
+    }
+
     /// Migrate buffer between placements
+    ///
+    /// Makes room in `new_placement` by evicting, and the buffer is
+    /// preferred there from now on.
     pub fn migrate(&self, handle: u32, new_placement: TtmPlacement) -> Result<(), &'static str> {
-        // TODO: Implement buffer migration
-        log::info!("Migrating buffer {} to {:?}", handle, new_placement);
+        let mut objects = self.objects.lock().unwrap();
+        let mut pools = self.pools.lock().unwrap();
+        let obj = objects.get(&handle).ok_or("Invalid handle")?.clone();
+        if obj.flags.contains(TtmFlags::PINNED) {
+            return Err("Buffer is pinned");
+        }
+        if obj.placement != new_placement {
+            // Keep the buffer itself from being evicted to make room
+            if let Some(lru) = pools.lru(obj.placement) {
+                lru.retain(|&h| h != handle);
+            }
+            let result = self
+                .place(&mut objects, &mut pools, obj.size, new_placement)
+                .and_then(|(gpu_addr, backing)| {
+                    self.move_to(
+                        &mut objects,
+                        &mut pools,
+                        handle,
+                        new_placement,
+                        gpu_addr,
+                        backing,
+                        EVICT_TIMEOUT,
+                    )
+                });
+            if let Err(e) = result {
+                if obj.evictable() {
+                    if let Some(lru) = pools.lru(obj.placement) {
+                        lru.push_back(handle);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        if let Some(obj) = objects.get_mut(&handle) {
+            let mut moved = TtmObject::clone(obj);
+            moved.preferred = new_placement;
+            *obj = Arc::new(moved);
+        }
+        log::debug!("Migrated buffer {} to {:?}", handle, new_placement);
+        Ok(())
+    }
+
+    /// Move buffers evicted from VRAM back while it has free space
+    ///
+    /// Doesn't evict or wait, buffers stay where they are when VRAM is full
+    /// or the GPU still uses them.
+    ///
+    /// Runs on the interrupt thread, so it skips a round rather than block
+    /// behind an eviction waiting for the fences that thread signals.
+    pub fn rebalance(&self) {
+        let Ok(mut objects) = self.objects.try_lock() else {
+            return;
+        };
+        let mut pools = self.pools.lock().unwrap();
+        let mut evicted: Vec<_> = objects
+            .values()
+            .filter(|obj| {
+                obj.preferred == TtmPlacement::Vram && obj.placement != TtmPlacement::Vram
+            })
+            .map(|obj| (obj.handle, obj.size))
+            .collect();
+        // Oldest buffers first
+        evicted.sort_unstable();
+
+        for (handle, size) in evicted {
+            let Some(gpu_addr) = pools.vram.alloc(size) else {
+                continue;
+            };
+            match self.move_to(
+                &mut objects,
+                &mut pools,
+                handle,
+                TtmPlacement::Vram,
+                gpu_addr,
+                Backing::Vram,
+                Duration::ZERO,
+            ) {
+                Ok(()) => log::trace!("Buffer {} restored to VRAM", handle),
+                Err(e) => log::trace!("Buffer {} stays evicted: {}", handle, e),
+            }
+        }
+    }
+
+    /// Usage of the heap a placement belongs to
+    ///
+    /// The budget is the size of VRAM or the GTT aperture; past it,
+    /// allocations are served by evicting.
+    pub fn heap_stats(&self, heap: Heap) -> HeapStats {
+        let pools = self.pools.lock().unwrap();
+        let pool = match heap {
+            Heap::Device => &pools.vram,
+            Heap::Host => &pools.gtt,
+        };
+        HeapStats {
+            reserved_bytes: pool.used,
+            allocated_bytes: pool.used,
+            budget: Some(pool.size),
+        }
+    }
+
+    /// Bytes evicted from VRAM since the manager was created
+    pub fn vram_evicted(&self) -> u64 {
+        self.pools.lock().unwrap().vram_evicted
+    }
+
+    /// CPU address of the `size` bytes at `gpu_addr` of `backing`
+    fn cpu_ptr(&self, backing: &Backing, gpu_addr: u64, size: usize) -> Option<*mut u8> {
+        match backing {
+            Backing::Vram => {
+                let aperture = self.aperture?;
+                let offset = usize::try_from(gpu_addr).ok()?;
+                (offset.checked_add(size)? <= aperture.size)
+                    .then(|| (aperture.base + offset) as *mut u8)
+            }
+            Backing::Gtt(pages) => Some(pages.ptr),
+            Backing::System(pages) => Some(pages.ptr.as_ptr()),
+        }
+    }
+
+    /// Wait up to `timeout` for the GPU to finish with a buffer
+    fn idle(&self, obj: &TtmObject, timeout: Duration) -> bool {
+        obj.fence
+            .is_none_or(|fence| self.fences.wait_fence(fence, timeout))
+    }
+
+    /// Whether a buffer's contents can be copied elsewhere
+    fn movable(&self, obj: &TtmObject) -> bool {
+        obj.cpu_addr.is_none() && self.cpu_ptr(&obj.backing, obj.gpu_addr, obj.size).is_some()
+    }
+
+    /// Find `size` bytes in `placement` and the memory behind them,
+    /// evicting least recently used buffers as needed
+    fn place(
+        &self,
+        objects: &mut HashMap<u32, Arc<TtmObject>>,
+        pools: &mut Pools,
+        size: usize,
+        placement: TtmPlacement,
+    ) -> Result<(u64, Backing), &'static str> {
+        let backing = match placement {
+            TtmPlacement::Vram => Backing::Vram,
+            TtmPlacement::Gtt => Backing::Gtt(GttPages::new(size)?),
+            TtmPlacement::System => Backing::System(SystemPages::new(size)?),
+        };
+        let Some(pool) = pools.pool(placement) else {
+            // System memory has no GPU address
+            return Ok((0, backing));
+        };
+        if let Some(gpu_addr) = pool.alloc(size) {
+            return Ok((gpu_addr, backing));
+        }
+
+        // Buffers that can't move keep their place in the LRU order
+        let mut kept = Vec::new();
+        let mut result = Err("Out of memory");
+        while let Some(victim) = pools.lru(placement).and_then(VecDeque::pop_front) {
+            if !self.evict(objects, pools, victim) {
+                kept.push(victim);
+                continue;
+            }
+            if let Some(gpu_addr) = pools.pool(placement).and_then(|pool| pool.alloc(size)) {
+                result = Ok((gpu_addr, backing));
+                break;
+            }
+        }
+        if let Some(lru) = pools.lru(placement) {
+            for victim in kept.into_iter().rev() {
+                lru.push_front(victim);
+            }
+        }
+        result
+    }
+
+    /// Move a buffer, already off its LRU list, one placement down
+    ///
+    /// Returns false if the buffer stays where it is.
+    fn evict(
+        &self,
+        objects: &mut HashMap<u32, Arc<TtmObject>>,
+        pools: &mut Pools,
+        handle: u32,
+    ) -> bool {
+        let Some(obj) = objects.get(&handle).cloned() else {
+            // Freed, nothing left to keep on the LRU list
+            return true;
+        };
+        let Some(target) = obj.placement.lower() else {
+            return false;
+        };
+        if !self.movable(&obj) {
+            log::trace!("Buffer {} can't be copied, not evicting", handle);
+            return false;
+        }
+        if !self.idle(&obj, EVICT_TIMEOUT) {
+            log::warn!("Buffer {} still in use by the GPU, not evicting", handle);
+            return false;
+        }
+
+        // A full GTT sends buffers straight to system memory
+        let place = match self.place(objects, pools, obj.size, target) {
+            Err(_) if target == TtmPlacement::Gtt => self
+                .place(objects, pools, obj.size, TtmPlacement::System)
+                .map(|place| (TtmPlacement::System, place)),
+            place => place.map(|place| (target, place)),
+        };
+        let Ok((target, (gpu_addr, backing))) = place else {
+            log::warn!("No room to evict buffer {} to", handle);
+            return false;
+        };
+        log::trace!("Evicting buffer {} to {:?}", handle, target);
+        let moved = self.move_to(
+            objects,
+            pools,
+            handle,
+            target,
+            gpu_addr,
+            backing,
+            Duration::ZERO,
+        );
+        if moved.is_ok() && obj.placement == TtmPlacement::Vram {
+            pools.vram_evicted += obj.size as u64;
+        }
+        moved.is_ok()
+    }
+
+    /// Move a buffer to `gpu_addr` of `backing`, allocated in `placement`,
+    /// copying its contents and freeing its old range
+    ///
+    /// Waits up to `timeout` for the GPU to finish with the buffer first.
+    /// On failure the buffer stays where it was and the new range is freed.
+    #[allow(clippy::too_many_arguments)]
+    fn move_to(
+        &self,
+        objects: &mut HashMap<u32, Arc<TtmObject>>,
+        pools: &mut Pools,
+        handle: u32,
+        placement: TtmPlacement,
+        gpu_addr: u64,
+        backing: Backing,
+        timeout: Duration,
+    ) -> Result<(), &'static str> {
+        let Some(obj) = objects.get_mut(&handle) else {
+            return Err("Invalid handle");
+        };
+        let copy = if !self.movable(obj) {
+            Err("Buffer can't be copied")
+        } else if !self.idle(obj, timeout) {
+            Err("Buffer in use by the GPU")
+        } else {
+            self.cpu_ptr(&obj.backing, obj.gpu_addr, obj.size)
+                .zip(self.cpu_ptr(&backing, gpu_addr, obj.size))
+                .ok_or("Destination can't be written")
+        };
+        let (src, dst) = match copy {
+            Ok(copy) => copy,
+            Err(e) => {
+                if let Some(pool) = pools.pool(placement) {
+                    pool.free(gpu_addr, obj.size);
+                }
+                return Err(e);
+            }
+        };
+
+        // SAFETY: both ranges are mapped for `size` bytes, the GPU is done
+        // with the old one and the new one is unused
+        unsafe { std::ptr::copy_nonoverlapping(src, dst, obj.size) };
+        pools.unlink(obj);
+        let mut moved = TtmObject::clone(obj);
+        moved.placement = placement;
+        moved.gpu_addr = gpu_addr;
+        moved.backing = Arc::new(backing);
+        moved.fence = None;
+        if moved.evictable() {
+            if let Some(lru) = pools.lru(placement) {
+                lru.push_back(handle);
+            }
+        }
+        *obj = Arc::new(moved);
         Ok(())
     }
 }
 
-/// Simple memory pool
+/// Address space allocator with a free list
 struct MemoryPool {
-    base: u64,
     size: u64,
-    next: u64,
+    /// Bytes allocated
+    used: u64,
+    /// Free ranges by offset, with their size
+    free: BTreeMap<u64, u64>,
 }
 
 impl MemoryPool {
     fn new(size: u64) -> Self {
+        let mut free = BTreeMap::new();
+        if size != 0 {
+            free.insert(0, size);
+        }
         Self {
-            base: 0,
             size,
-            next: 0,
+            used: 0,
+            free,
         }
     }
 
-    fn alloc(&mut self, size: usize) -> Result<u64, &'static str> {
-        let aligned_size = (size + 4095) & !4095;
+    fn aligned(size: usize) -> u64 {
+        ((size + 4095) & !4095) as u64
+    }
 
-        if self.next + aligned_size as u64 > self.size {
-            return Err("Out of memory");
+    /// First fit
+    fn alloc(&mut self, size: usize) -> Option<u64> {
+        let aligned_size = Self::aligned(size);
+        let (&addr, &free) = self.free.iter().find(|(_, &free)| free >= aligned_size)?;
+        self.free.remove(&addr);
+        if free > aligned_size {
+            self.free.insert(addr + aligned_size, free - aligned_size);
         }
-
-        let addr = self.base + self.next;
-        self.next += aligned_size as u64;
-
-        Ok(addr)
+        self.used += aligned_size;
+        Some(addr)
     }
 
-    fn free(&mut self, _addr: u64, _size: usize) {
-        // Simple allocator doesn't support free
+    /// Return a range, merging it with free neighbors
+    fn free(&mut self, addr: u64, size: usize) {
+        let mut addr = addr;
+        let mut size = Self::aligned(size);
+        self.used -= size;
+
+        if let Some((&prev, &prev_size)) = self.free.range(..addr).next_back() {
+            if prev + prev_size == addr {
+                self.free.remove(&prev);
+                addr = prev;
+                size += prev_size;
+            }
+        }
+        if let Some(next_size) = self.free.remove(&(addr + size)) {
+            size += next_size;
+        }
+        self.free.insert(addr, size);
     }
 }
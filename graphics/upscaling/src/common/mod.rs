@@ -1,5 +1,7 @@
 //! Common upscaling types and utilities
 
+pub mod temporal;
+
 use alloc::string::String;
 use core::fmt;
 
@@ -12,6 +14,8 @@ pub enum UpscalingBackend {
     DLSS,
     /// Intel Xe Super Sampling
     XeSS,
+    /// Super resolution network on the NPU through RedoxML
+    Neural,
 }
 
 impl fmt::Display for UpscalingBackend {
@@ -20,6 +24,7 @@ impl fmt::Display for UpscalingBackend {
             UpscalingBackend::FSR => write!(f, "AMD FSR"),
             UpscalingBackend::DLSS => write!(f, "NVIDIA DLSS"),
             UpscalingBackend::XeSS => write!(f, "Intel XeSS"),
+            UpscalingBackend::Neural => write!(f, "RedoxML Neural"),
         }
     }
 }
@@ -0,0 +1,336 @@
+//! Temporal upscaling
+//!
+//! Backend-agnostic reference pipeline for temporal upscalers like FSR 2.
+//! Every frame is rendered at the render resolution with a sub-pixel jitter
+//! from a [`JitterSequence`]. The upscaler reprojects the display-resolution
+//! history into the current frame with the motion vectors, rejects history
+//! that got disoccluded (the depth there changed), clamps what's left to the
+//! colors around the new sample and blends the two.
+//!
+//! Conventions:
+//! - colors are linear RGBA and depths are in `[0, 1]` with 0 nearest
+//! - motion vectors are in UV units at the render resolution and point from
+//!   a pixel to where it was in the previous frame
+//! - jitter offsets are in render pixels, in `[-0.5, 0.5)`
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{UpscalingError, UpscalingQuality};
+
+/// Phases of the jitter sequence for a native resolution frame
+const BASE_PHASE_COUNT: f32 = 8.0;
+
+/// Element `index` of the Halton sequence in `base`, in `[0, 1)`
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Sub-pixel offsets to render successive frames at
+///
+/// Halton (2, 3) points, with enough phases that every display pixel gets
+/// about eight samples.
+#[derive(Debug, Clone)]
+pub struct JitterSequence {
+    phase_count: u32,
+    index: u32,
+}
+
+impl JitterSequence {
+    /// Sequence for rendering at `render_width` and displaying at
+    /// `display_width`
+    pub fn new(render_width: u32, display_width: u32) -> Self {
+        Self::with_ratio(display_width as f32 / render_width.max(1) as f32)
+    }
+
+    /// Sequence for a quality preset
+    pub fn for_quality(quality: UpscalingQuality) -> Self {
+        Self::with_ratio(quality.scale_factor())
+    }
+
+    fn with_ratio(ratio: f32) -> Self {
+        Self {
+            phase_count: ((BASE_PHASE_COUNT * ratio * ratio) as u32).max(1),
+            index: 0,
+        }
+    }
+
+    /// Number of offsets before the sequence repeats
+    pub fn phase_count(&self) -> u32 {
+        self.phase_count
+    }
+
+    /// Offset of phase `phase`, in render pixels
+    pub fn offset(&self, phase: u32) -> (f32, f32) {
+        // Halton starts at 0, skip it so the first offset isn't the center
+        let index = phase % self.phase_count + 1;
+        (halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+    }
+
+    /// Offset for the next frame, in render pixels
+    pub fn next_offset(&mut self) -> (f32, f32) {
+        let offset = self.offset(self.index);
+        self.index = (self.index + 1) % self.phase_count;
+        offset
+    }
+
+    /// Start over
+    pub fn reset(&mut self) {
+        self.index = 0;
+    }
+}
+
+/// Translation in clip space to add to the projection for a jitter offset
+pub fn jitter_to_clip(offset: (f32, f32), render_resolution: (u32, u32)) -> (f32, f32) {
+    (
+        2.0 * offset.0 / render_resolution.0 as f32,
+        -2.0 * offset.1 / render_resolution.1 as f32,
+    )
+}
+
+/// Tuning of the temporal upscaler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemporalConfig {
+    /// Weight of the current frame when the history is valid
+    pub current_weight: f32,
+    /// Depth difference beyond which the history counts as disoccluded
+    pub depth_threshold: f32,
+}
+
+impl Default for TemporalConfig {
+    fn default() -> Self {
+        Self {
+            current_weight: 0.1,
+            depth_threshold: 0.01,
+        }
+    }
+}
+
+/// Inputs of one frame, all at the render resolution
+pub struct TemporalInputs<'a> {
+    /// Rendered colors
+    pub color: &'a [[f32; 4]],
+    /// Depth of every pixel
+    pub depth: &'a [f32],
+    /// Motion of every pixel
+    pub motion_vectors: &'a [[f32; 2]],
+    /// Jitter offset the frame was rendered with
+    pub jitter: (f32, f32),
+    /// Drop the history, for example after a camera cut
+    pub reset: bool,
+}
+
+/// Temporal upscaler
+pub struct TemporalUpscaler {
+    config: TemporalConfig,
+    render_resolution: (u32, u32),
+    display_resolution: (u32, u32),
+    /// Output of the previous frame
+    history: Vec<[f32; 4]>,
+    /// Depth of the previous frame, at the render resolution
+    history_depth: Vec<f32>,
+    /// Output being built, swapped with the history after every frame
+    scratch: Vec<[f32; 4]>,
+    history_valid: bool,
+}
+
+impl TemporalUpscaler {
+    /// Create a temporal upscaler
+    pub fn new(
+        render_resolution: (u32, u32),
+        display_resolution: (u32, u32),
+        config: TemporalConfig,
+    ) -> Result<Self, UpscalingError> {
+        if render_resolution.0 == 0
+            || render_resolution.1 == 0
+            || display_resolution.0 < render_resolution.0
+            || display_resolution.1 < render_resolution.1
+        {
+            return Err(UpscalingError::InvalidParameters);
+        }
+
+        let render_pixels = (render_resolution.0 * render_resolution.1) as usize;
+        let display_pixels = (display_resolution.0 * display_resolution.1) as usize;
+        Ok(Self {
+            config,
+            render_resolution,
+            display_resolution,
+            history: vec![[0.0; 4]; display_pixels],
+            history_depth: vec![1.0; render_pixels],
+            scratch: vec![[0.0; 4]; display_pixels],
+            history_valid: false,
+        })
+    }
+
+    /// Render resolution
+    pub fn render_resolution(&self) -> (u32, u32) {
+        self.render_resolution
+    }
+
+    /// Display resolution
+    pub fn display_resolution(&self) -> (u32, u32) {
+        self.display_resolution
+    }
+
+    /// Drop the history, the next frame is output as is
+    pub fn reset(&mut self) {
+        self.history_valid = false;
+    }
+
+    /// Upscale a frame into `output`, at the display resolution
+    pub fn upscale(
+        &mut self,
+        inputs: &TemporalInputs,
+        output: &mut [[f32; 4]],
+    ) -> Result<(), UpscalingError> {
+        let render_pixels = (self.render_resolution.0 * self.render_resolution.1) as usize;
+        if inputs.color.len() != render_pixels
+            || inputs.depth.len() != render_pixels
+            || inputs.motion_vectors.len() != render_pixels
+            || output.len() != self.history.len()
+        {
+            return Err(UpscalingError::InvalidParameters);
+        }
+        if inputs.reset {
+            self.reset();
+        }
+
+        let (render_width, render_height) = (
+            self.render_resolution.0 as f32,
+            self.render_resolution.1 as f32,
+        );
+        let (display_width, display_height) = self.display_resolution;
+        for y in 0..display_height {
+            let v = (y as f32 + 0.5) / display_height as f32;
+            for x in 0..display_width {
+                let u = (x as f32 + 0.5) / display_width as f32;
+
+                // Rendered pixel nearest to the display pixel, without jitter
+                let sample = (
+                    floor(u * render_width - inputs.jitter.0),
+                    floor(v * render_height - inputs.jitter.1),
+                );
+                let current = self.texel(inputs.color, sample);
+                let (nearest, neighborhood_min, neighborhood_max) =
+                    self.neighborhood(inputs, sample);
+
+                // Motion of the nearest surface around, so that edges move
+                // with the object in front
+                let motion = self.texel(inputs.motion_vectors, nearest);
+                let previous = (u + motion[0], v + motion[1]);
+                let reprojected = self.history_valid
+                    && (0.0..1.0).contains(&previous.0)
+                    && (0.0..1.0).contains(&previous.1)
+                    && !self.disoccluded(inputs, nearest, previous);
+
+                let color = if reprojected {
+                    let history = self.sample_history(previous);
+                    let weight = self.config.current_weight;
+                    core::array::from_fn(|i| {
+                        let history = history[i].clamp(neighborhood_min[i], neighborhood_max[i]);
+                        history + (current[i] - history) * weight
+                    })
+                } else {
+                    current
+                };
+                self.scratch[(y * display_width + x) as usize] = color;
+            }
+        }
+
+        output.copy_from_slice(&self.scratch);
+        core::mem::swap(&mut self.history, &mut self.scratch);
+        self.history_depth.copy_from_slice(inputs.depth);
+        self.history_valid = true;
+        Ok(())
+    }
+
+    /// Nearest pixel in the 3x3 neighborhood of `center`, and the bounds of
+    /// the colors there
+    fn neighborhood(
+        &self,
+        inputs: &TemporalInputs,
+        center: (i64, i64),
+    ) -> ((i64, i64), [f32; 4], [f32; 4]) {
+        let mut nearest = (center, f32::INFINITY);
+        let mut min = [f32::INFINITY; 4];
+        let mut max = [f32::NEG_INFINITY; 4];
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let pixel = (center.0 + dx, center.1 + dy);
+                let depth = self.texel(inputs.depth, pixel);
+                if depth < nearest.1 {
+                    nearest = (pixel, depth);
+                }
+                let color = self.texel(inputs.color, pixel);
+                for i in 0..4 {
+                    min[i] = min[i].min(color[i]);
+                    max[i] = max[i].max(color[i]);
+                }
+            }
+        }
+        (nearest.0, min, max)
+    }
+
+    /// Whether the surface at `pixel` was hidden at `previous` last frame
+    fn disoccluded(
+        &self,
+        inputs: &TemporalInputs,
+        pixel: (i64, i64),
+        previous: (f32, f32),
+    ) -> bool {
+        let depth = self.texel(inputs.depth, pixel);
+        let previous_pixel = (
+            floor(previous.0 * self.render_resolution.0 as f32),
+            floor(previous.1 * self.render_resolution.1 as f32),
+        );
+        let previous_depth = self.texel(&self.history_depth, previous_pixel);
+        // Something nearer covered it
+        depth - previous_depth > self.config.depth_threshold
+    }
+
+    /// Bilinear sample of the history at `uv`
+    fn sample_history(&self, uv: (f32, f32)) -> [f32; 4] {
+        let (width, height) = self.display_resolution;
+        let texel = |x: i64, y: i64| {
+            let x = x.clamp(0, i64::from(width) - 1);
+            let y = y.clamp(0, i64::from(height) - 1);
+            self.history[y as usize * width as usize + x as usize]
+        };
+
+        let (fx, fy) = (uv.0 * width as f32 - 0.5, uv.1 * height as f32 - 0.5);
+        let (ix, iy) = (floor(fx), floor(fy));
+        let (tx, ty) = (fx - ix as f32, fy - iy as f32);
+        let (c00, c10) = (texel(ix, iy), texel(ix + 1, iy));
+        let (c01, c11) = (texel(ix, iy + 1), texel(ix + 1, iy + 1));
+        core::array::from_fn(|i| {
+            let top = c00[i] + (c10[i] - c00[i]) * tx;
+            let bottom = c01[i] + (c11[i] - c01[i]) * tx;
+            top + (bottom - top) * ty
+        })
+    }
+
+    /// Value of a render resolution image at `pixel`, clamped to the edges
+    fn texel<T: Copy>(&self, image: &[T], pixel: (i64, i64)) -> T {
+        let (width, height) = self.render_resolution;
+        let x = pixel.0.clamp(0, i64::from(width) - 1);
+        let y = pixel.1.clamp(0, i64::from(height) - 1);
+        image[y as usize * width as usize + x as usize]
+    }
+}
+
+/// `f32::floor` is not available without std
+fn floor(value: f32) -> i64 {
+    let truncated = value as i64;
+    if (truncated as f32) > value {
+        truncated - 1
+    } else {
+        truncated
+    }
+}
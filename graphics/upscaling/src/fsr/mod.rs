@@ -1,5 +1,6 @@
 //! AMD FidelityFX Super Resolution (FSR) implementation
 
+use crate::common::temporal::{JitterSequence, TemporalConfig, TemporalInputs, TemporalUpscaler};
 use crate::common::{UpscalingContext, UpscalingError, UpscalingQuality};
 use alloc::vec::Vec;
 
@@ -22,6 +23,8 @@ pub struct FsrContext {
     context: UpscalingContext,
     /// RCAS sharpening enabled
     rcas_enabled: bool,
+    /// Temporal pipeline of FSR 2 and 3
+    temporal: Option<TemporalUpscaler>,
 }
 
 impl FsrContext {
@@ -44,6 +47,7 @@ impl FsrContext {
             version: FsrVersion::FSR1,
             context,
             rcas_enabled: true,
+            temporal: None,
         })
     }
 
@@ -62,10 +66,17 @@ impl FsrContext {
             display_height,
         )?;
 
+        let temporal = TemporalUpscaler::new(
+            context.render_resolution,
+            context.display_resolution,
+            TemporalConfig::default(),
+        )?;
+
         Ok(Self {
             version: FsrVersion::FSR2,
             context,
             rcas_enabled: true,
+            temporal: Some(temporal),
         })
     }
 
@@ -84,10 +95,17 @@ impl FsrContext {
             display_height,
         )?;
 
+        let temporal = TemporalUpscaler::new(
+            context.render_resolution,
+            context.display_resolution,
+            TemporalConfig::default(),
+        )?;
+
         Ok(Self {
             version: FsrVersion::FSR3,
             context,
             rcas_enabled: true,
+            temporal: Some(temporal),
         })
     }
 
@@ -105,6 +123,27 @@ impl FsrContext {
         self.version
     }
 
+    /// Jitter offsets to render frames at, for FSR 2 and 3
+    pub fn jitter_sequence(&self) -> JitterSequence {
+        JitterSequence::new(
+            self.context.render_resolution.0,
+            self.context.display_resolution.0,
+        )
+    }
+
+    /// Perform temporal upscaling of a jittered frame, FSR 2 and 3 only
+    pub fn upscale_temporal(
+        &mut self,
+        inputs: &TemporalInputs,
+        output: &mut [[f32; 4]],
+    ) -> Result<(), UpscalingError> {
+        let temporal = self
+            .temporal
+            .as_mut()
+            .ok_or(UpscalingError::InvalidParameters)?;
+        temporal.upscale(inputs, output)
+    }
+
     /// Perform upscaling
     pub fn upscale(
         &mut self,
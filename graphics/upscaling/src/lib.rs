@@ -4,6 +4,7 @@
 //! - AMD FidelityFX Super Resolution (FSR)
 //! - NVIDIA Deep Learning Super Sampling (DLSS)
 //! - Intel Xe Super Sampling (XeSS)
+//! - Neural super resolution on the NPU through RedoxML
 
 #![no_std]
 
@@ -20,6 +21,9 @@ pub mod dlss;
 #[cfg(feature = "xess")]
 pub mod xess;
 
+#[cfg(feature = "neural")]
+pub mod neural;
+
 pub use common::{UpscalingBackend, UpscalingContext, UpscalingError, UpscalingQuality};
 
 use alloc::vec::Vec;
@@ -43,6 +47,11 @@ pub fn init() -> Result<(), &'static str> {
         log::info!("XeSS support enabled");
     }
 
+    #[cfg(feature = "neural")]
+    {
+        log::info!("Neural upscaling support enabled");
+    }
+
     Ok(())
 }
 
@@ -67,5 +76,12 @@ pub fn detect_backends() -> Vec<UpscalingBackend> {
         backends.push(UpscalingBackend::XeSS);
     }
 
+    #[cfg(feature = "neural")]
+    {
+        if neural::features::is_supported() {
+            backends.push(UpscalingBackend::Neural);
+        }
+    }
+
     backends
 }
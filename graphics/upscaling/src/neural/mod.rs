@@ -0,0 +1,148 @@
+//! Neural super resolution on the NPU
+//!
+//! A small super resolution network runs on the NPU through RedoxML. Frames
+//! never pass through the CPU: the rendered and the upscaled image are
+//! exported from the application's GAL device once, and the NPU reads and
+//! writes the same memory the GPU renders to and samples from.
+
+use alloc::format;
+use alloc::vec;
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use gal::{Device, ExternalImageLayout, Image, ImageFormat};
+use redoxml::inference::SuperResolution;
+use redoxml::tensor::{Shape, SharedBuffer, Tensor};
+
+use crate::common::{UpscalingContext, UpscalingError, UpscalingQuality};
+
+/// Format of the images the network reads and writes
+pub const FRAME_FORMAT: ImageFormat = ImageFormat::Rgba32Float;
+
+/// Rendered image and the image it is upscaled into, shared with the NPU
+pub struct NeuralFrame {
+    input: Tensor<f32>,
+    output: Tensor<f32>,
+}
+
+/// Neural upscaling context
+pub struct NeuralContext {
+    /// Upscaling context
+    context: UpscalingContext,
+    /// Super resolution network
+    network: SuperResolution,
+}
+
+impl NeuralContext {
+    /// Create neural upscaling context
+    pub fn new(
+        quality: UpscalingQuality,
+        display_width: u32,
+        display_height: u32,
+    ) -> Result<Self, UpscalingError> {
+        log::info!("Creating neural upscaling context");
+
+        let network = SuperResolution::new()
+            .map_err(|err| UpscalingError::InitializationFailed(err.into()))?;
+        if !network.is_accelerated() {
+            log::warn!("Neural upscaling requires an NPU");
+            return Err(UpscalingError::BackendNotAvailable);
+        }
+
+        let context = UpscalingContext::new(
+            crate::common::UpscalingBackend::Neural,
+            quality,
+            display_width,
+            display_height,
+        )?;
+
+        Ok(Self { context, network })
+    }
+
+    /// Share a rendered image and the image it is upscaled into with the NPU
+    ///
+    /// Both images are [`FRAME_FORMAT`] images of `device`, at the render and
+    /// the display resolution. Share every swapchain image once and keep the
+    /// frames, exporting costs more than upscaling.
+    pub fn share_frame(
+        &self,
+        device: &dyn Device,
+        input: &dyn Image,
+        output: &dyn Image,
+    ) -> Result<NeuralFrame, UpscalingError> {
+        Ok(NeuralFrame {
+            input: share_image(device, input, self.context.render_resolution)?,
+            output: share_image(device, output, self.context.display_resolution)?,
+        })
+    }
+
+    /// Perform upscaling
+    ///
+    /// Rendering into the input has to be finished, and the output must not
+    /// be used by the GPU until this returns.
+    pub fn upscale(&mut self, frame: &NeuralFrame) -> Result<(), UpscalingError> {
+        log::trace!(
+            "Neural upscaling: {}x{} -> {}x{}",
+            self.context.render_resolution.0,
+            self.context.render_resolution.1,
+            self.context.display_resolution.0,
+            self.context.display_resolution.1
+        );
+
+        block_on(self.network.upscale_into(&frame.input, &frame.output))
+            .map_err(|err| UpscalingError::UpscalingFailed(err.into()))
+    }
+}
+
+/// Export `image` and view it as a `[height, width, 4]` tensor
+fn share_image(
+    device: &dyn Device,
+    image: &dyn Image,
+    resolution: (u32, u32),
+) -> Result<Tensor<f32>, UpscalingError> {
+    let extent = image.extent();
+    if image.format() != FRAME_FORMAT || (extent.width, extent.height) != resolution {
+        return Err(UpscalingError::InvalidParameters);
+    }
+
+    let (memory, layout) = device
+        .export_image(image)
+        .map_err(|err| UpscalingError::ResourceCreationFailed(format!("{:?}", err)))?;
+    // The network reads tightly packed rows
+    let packed = ExternalImageLayout::linear(FRAME_FORMAT, layout.extent, 1);
+    if Some(layout) != packed {
+        return Err(UpscalingError::ResourceCreationFailed(format!(
+            "Unsupported image layout {:?}",
+            layout
+        )));
+    }
+
+    let shape = Shape::new(vec![extent.height as usize, extent.width as usize, 4]);
+    let buffer = SharedBuffer::import(memory.fd, memory.size as usize);
+    Ok(Tensor::from_shared_buffer(shape, buffer))
+}
+
+/// Run a RedoxML operation to completion
+///
+/// NPU commands are submitted synchronously, the future is ready after the
+/// first poll or shortly after.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Neural upscaling feature flags
+pub mod features {
+    /// Check if neural upscaling is supported
+    pub fn is_supported() -> bool {
+        // The network is too slow without an NPU
+        redoxml::npu::NpuDevice::open().is_ok_and(|npu| !npu.is_simulated())
+    }
+}
@@ -1,55 +1,198 @@
 //! Intel Xe Super Sampling (XeSS) implementation
 
+use core::fmt;
+
+use gal::{DeviceCapabilities, DeviceInfo, DeviceType};
+
 use crate::common::{UpscalingContext, UpscalingError, UpscalingQuality};
 
+/// PCI vendor ID of Intel
+const INTEL_VENDOR_ID: u32 = 0x8086;
+
+/// Hardware XeSS runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XessExecutionPath {
+    /// XMX matrix engines of Intel Arc GPUs
+    Xmx,
+    /// DP4a integer dot product instructions, on other GPUs
+    Dp4a,
+}
+
+impl fmt::Display for XessExecutionPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XessExecutionPath::Xmx => write!(f, "XMX"),
+            XessExecutionPath::Dp4a => write!(f, "DP4a"),
+        }
+    }
+}
+
+/// XeSS quality preset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XessPreset {
+    /// Ultra Performance (3x upscale)
+    UltraPerformance,
+    /// Performance (2.3x upscale)
+    Performance,
+    /// Balanced (2x upscale)
+    Balanced,
+    /// Quality (1.7x upscale)
+    Quality,
+    /// Ultra Quality (1.5x upscale)
+    UltraQuality,
+    /// Ultra Quality Plus (1.3x upscale)
+    UltraQualityPlus,
+    /// Native resolution anti-aliasing
+    NativeAntiAliasing,
+}
+
+impl XessPreset {
+    /// Convert quality to XeSS preset
+    pub fn from_quality(quality: UpscalingQuality) -> Self {
+        match quality {
+            UpscalingQuality::UltraPerformance => XessPreset::UltraPerformance,
+            UpscalingQuality::Performance => XessPreset::Performance,
+            UpscalingQuality::Balanced => XessPreset::Balanced,
+            UpscalingQuality::Quality => XessPreset::Quality,
+            UpscalingQuality::UltraQuality => XessPreset::UltraQuality,
+        }
+    }
+
+    /// Get the upscale factor of this preset
+    ///
+    /// XeSS 1.3 factors, higher than the common ones for the same name.
+    pub fn scale_factor(&self) -> f32 {
+        match self {
+            XessPreset::UltraPerformance => 3.0,
+            XessPreset::Performance => 2.3,
+            XessPreset::Balanced => 2.0,
+            XessPreset::Quality => 1.7,
+            XessPreset::UltraQuality => 1.5,
+            XessPreset::UltraQualityPlus => 1.3,
+            XessPreset::NativeAntiAliasing => 1.0,
+        }
+    }
+
+    /// Calculate render resolution from display resolution
+    pub fn render_resolution(&self, display_width: u32, display_height: u32) -> (u32, u32) {
+        let factor = self.scale_factor();
+        (
+            ((display_width as f32 / factor) as u32).max(1),
+            ((display_height as f32 / factor) as u32).max(1),
+        )
+    }
+}
+
 /// XeSS context
 pub struct XessContext {
     /// Upscaling context
     context: UpscalingContext,
-    /// Use XMX acceleration (Intel Arc GPUs)
-    xmx_acceleration: bool,
+    /// XeSS preset
+    preset: XessPreset,
+    /// Hardware the network runs on
+    path: XessExecutionPath,
+    /// Next frame must not use the history
+    reset_history: bool,
 }
 
 impl XessContext {
-    /// Create XeSS context
+    /// Create XeSS context for the GPU described by `device`
     pub fn new(
+        device: &DeviceInfo,
         quality: UpscalingQuality,
         display_width: u32,
         display_height: u32,
     ) -> Result<Self, UpscalingError> {
         log::info!("Creating XeSS context");
 
-        let xmx_acceleration = Self::check_xmx_support();
+        let Some(path) = features::select_execution_path(device) else {
+            log::warn!("XeSS requires XMX or DP4a support");
+            return Err(UpscalingError::BackendNotAvailable);
+        };
+        match path {
+            XessExecutionPath::Xmx => {
+                log::info!("XeSS: Using XMX acceleration (Intel Arc GPU detected)")
+            }
+            XessExecutionPath::Dp4a => log::info!("XeSS: Using DP4a fallback"),
+        }
 
-        if xmx_acceleration {
-            log::info!("XeSS: Using XMX acceleration (Intel Arc GPU detected)");
-        } else {
-            log::info!("XeSS: Using DP4a fallback");
+        if display_width == 0 || display_height == 0 {
+            return Err(UpscalingError::InvalidParameters);
         }
 
-        let context = UpscalingContext::new(
+        let preset = XessPreset::from_quality(quality);
+        let mut context = UpscalingContext::new(
             crate::common::UpscalingBackend::XeSS,
             quality,
             display_width,
             display_height,
         )?;
+        context.render_resolution = preset.render_resolution(display_width, display_height);
 
         Ok(Self {
             context,
-            xmx_acceleration,
+            preset,
+            path,
+            reset_history: true,
         })
     }
 
-    /// Check for XMX (Xe Matrix Extensions) support
-    fn check_xmx_support() -> bool {
-        // In real implementation, would query GPU capabilities
-        log::debug!("Checking for XMX support");
-        false // Default to DP4a fallback
+    /// Change the preset, for example to [`XessPreset::NativeAntiAliasing`]
+    pub fn set_preset(&mut self, preset: XessPreset) {
+        let (display_width, display_height) = self.context.display_resolution;
+        self.preset = preset;
+        self.context.render_resolution = preset.render_resolution(display_width, display_height);
+        self.reset_history = true;
+        log::debug!("XeSS preset: {:?}", preset);
+    }
+
+    /// Get XeSS preset
+    pub fn preset(&self) -> XessPreset {
+        self.preset
+    }
+
+    /// Change the display resolution, the history is dropped
+    pub fn resize(
+        &mut self,
+        display_width: u32,
+        display_height: u32,
+    ) -> Result<(), UpscalingError> {
+        if display_width == 0 || display_height == 0 {
+            return Err(UpscalingError::InvalidParameters);
+        }
+
+        self.context.display_resolution = (display_width, display_height);
+        self.context.render_resolution =
+            self.preset.render_resolution(display_width, display_height);
+        self.reset_history = true;
+        log::debug!(
+            "XeSS resized: {}x{} -> {}x{}",
+            self.context.render_resolution.0,
+            self.context.render_resolution.1,
+            display_width,
+            display_height
+        );
+        Ok(())
+    }
+
+    /// Drop the history, for example after a camera cut
+    pub fn reset_history(&mut self) {
+        self.reset_history = true;
+    }
+
+    /// Resolution to render frames at
+    pub fn render_resolution(&self) -> (u32, u32) {
+        self.context.render_resolution
+    }
+
+    /// Get the hardware XeSS runs on
+    pub fn execution_path(&self) -> XessExecutionPath {
+        self.path
     }
 
     /// Check if using XMX acceleration
     pub fn is_xmx_accelerated(&self) -> bool {
-        self.xmx_acceleration
+        self.path == XessExecutionPath::Xmx
     }
 
     /// Perform upscaling
@@ -61,29 +204,55 @@ impl XessContext {
     ) -> Result<(), UpscalingError> {
         // In real implementation, would call XeSS SDK
         log::trace!(
-            "XeSS upscaling: {}x{} -> {}x{} ({})",
+            "XeSS upscaling: {}x{} -> {}x{} ({}, preset: {:?}{})",
             self.context.render_resolution.0,
             self.context.render_resolution.1,
             self.context.display_resolution.0,
             self.context.display_resolution.1,
-            if self.xmx_acceleration { "XMX" } else { "DP4a" }
+            self.path,
+            self.preset,
+            if self.reset_history { ", reset" } else { "" }
         );
 
+        self.reset_history = false;
         Ok(())
     }
 }
 
+impl Drop for XessContext {
+    fn drop(&mut self) {
+        log::debug!("Destroying XeSS context");
+    }
+}
+
 /// XeSS feature flags
 pub mod features {
-    /// Check if XeSS is supported
-    pub fn is_supported() -> bool {
-        // Check for Intel GPU with DP4a support
-        true
+    use super::*;
+
+    /// Check if XeSS is supported on `device`
+    pub fn is_supported(device: &DeviceInfo) -> bool {
+        select_execution_path(device).is_some()
+    }
+
+    /// Check if XMX acceleration is available on `device`
+    pub fn is_xmx_available(device: &DeviceInfo) -> bool {
+        // Intel Arc GPUs, the discrete ones have XMX engines
+        device.vendor_id == INTEL_VENDOR_ID && device.device_type == DeviceType::Discrete
     }
 
-    /// Check if XMX acceleration is available
-    pub fn is_xmx_available() -> bool {
-        // Check for Intel Arc GPU
-        false
+    /// Pick the hardware XeSS runs on for `device`
+    ///
+    /// XMX on Intel Arc GPUs, DP4a everywhere else compute shaders run on
+    /// hardware.
+    pub fn select_execution_path(device: &DeviceInfo) -> Option<XessExecutionPath> {
+        if is_xmx_available(device) {
+            Some(XessExecutionPath::Xmx)
+        } else if device.capabilities.contains(DeviceCapabilities::COMPUTE)
+            && device.device_type.supports_acceleration()
+        {
+            Some(XessExecutionPath::Dp4a)
+        } else {
+            None
+        }
     }
 }
@@ -0,0 +1,408 @@
+//! Dispatch tables and multi-ICD aggregation
+//!
+//! A loader instance creates an instance on every ICD and resolves a dispatch table for each.
+//! Physical devices of all ICDs are reported together, each wrapped in a
+//! [`DispatchableObject`] that remembers which ICD and which driver handle it stands for, so
+//! commands on it reach the right driver. Devices get a table of their own, resolved through
+//! `vkGetDeviceProcAddr` once the device exists.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::{mem, ptr};
+
+use crate::icd::{self, IcdDriver};
+use crate::loader::LoaderError;
+
+const VK_SUCCESS: i32 = 0;
+const VK_INCOMPLETE: i32 = 5;
+
+type PfnCreateInstance = unsafe extern "C" fn(
+    create_info: *const c_void,
+    allocator: *const c_void,
+    instance: *mut usize,
+) -> i32;
+type PfnDestroyInstance = unsafe extern "C" fn(instance: usize, allocator: *const c_void);
+type PfnEnumeratePhysicalDevices =
+    unsafe extern "C" fn(instance: usize, count: *mut u32, devices: *mut usize) -> i32;
+type PfnCreateDevice = unsafe extern "C" fn(
+    physical_device: usize,
+    create_info: *const c_void,
+    allocator: *const c_void,
+    device: *mut usize,
+) -> i32;
+type PfnGetDeviceProcAddr = unsafe extern "C" fn(device: usize, name: *const u8) -> usize;
+type PfnDestroyDevice = unsafe extern "C" fn(device: usize, allocator: *const c_void);
+
+/// Commands dispatched on instances and physical devices
+pub const INSTANCE_COMMANDS: &[&str] = &[
+    "vkDestroyInstance",
+    "vkEnumeratePhysicalDevices",
+    "vkGetPhysicalDeviceProperties",
+    "vkGetPhysicalDeviceProperties2",
+    "vkGetPhysicalDeviceFeatures",
+    "vkGetPhysicalDeviceFeatures2",
+    "vkGetPhysicalDeviceQueueFamilyProperties",
+    "vkGetPhysicalDeviceMemoryProperties",
+    "vkGetPhysicalDeviceFormatProperties",
+    "vkGetPhysicalDeviceImageFormatProperties",
+    "vkEnumerateDeviceExtensionProperties",
+    "vkCreateDevice",
+    "vkGetDeviceProcAddr",
+    "vkDestroySurfaceKHR",
+    "vkGetPhysicalDeviceSurfaceSupportKHR",
+    "vkGetPhysicalDeviceSurfaceCapabilitiesKHR",
+    "vkGetPhysicalDeviceSurfaceFormatsKHR",
+    "vkGetPhysicalDeviceSurfacePresentModesKHR",
+];
+
+/// Commands dispatched on devices and their queues and command buffers
+pub const DEVICE_COMMANDS: &[&str] = &[
+    "vkDestroyDevice",
+    "vkGetDeviceQueue",
+    "vkQueueSubmit",
+    "vkQueueWaitIdle",
+    "vkDeviceWaitIdle",
+    "vkAllocateMemory",
+    "vkFreeMemory",
+    "vkMapMemory",
+    "vkUnmapMemory",
+    "vkCreateBuffer",
+    "vkDestroyBuffer",
+    "vkCreateImage",
+    "vkDestroyImage",
+    "vkCreateCommandPool",
+    "vkDestroyCommandPool",
+    "vkAllocateCommandBuffers",
+    "vkFreeCommandBuffers",
+    "vkBeginCommandBuffer",
+    "vkEndCommandBuffer",
+    "vkCreateFence",
+    "vkDestroyFence",
+    "vkWaitForFences",
+    "vkResetFences",
+    "vkCreateSwapchainKHR",
+    "vkDestroySwapchainKHR",
+    "vkGetSwapchainImagesKHR",
+    "vkAcquireNextImageKHR",
+    "vkQueuePresentKHR",
+];
+
+/// Entry points of one driver object, in the order of a command list
+#[derive(Debug)]
+pub struct DispatchTable {
+    commands: &'static [&'static str],
+    /// Entry point per command, 0 if the driver lacks it
+    entries: Vec<usize>,
+}
+
+impl DispatchTable {
+    /// Resolve each of `commands` with `resolve`
+    pub fn build(
+        commands: &'static [&'static str],
+        resolve: impl Fn(&str) -> Option<usize>,
+    ) -> Self {
+        let entries = commands
+            .iter()
+            .map(|name| resolve(name).unwrap_or(0))
+            .collect();
+        Self { commands, entries }
+    }
+
+    /// Entry point of `name`
+    pub fn get(&self, name: &str) -> Option<usize> {
+        let index = self.commands.iter().position(|command| *command == name)?;
+        Some(self.entries[index]).filter(|&addr| addr != 0)
+    }
+
+    /// Number of commands the driver provides
+    pub fn resolved(&self) -> usize {
+        self.entries.iter().filter(|&&addr| addr != 0).count()
+    }
+}
+
+/// Loader side of a dispatchable handle
+///
+/// The handle given to the application is the address of this object. Its first word points to
+/// the dispatch table, as layers and drivers expect of dispatchable handles.
+#[derive(Debug)]
+#[repr(C)]
+pub struct DispatchableObject {
+    dispatch: *const DispatchTable,
+    /// Index of the ICD in the loader instance
+    icd: usize,
+    /// Driver's own handle
+    handle: usize,
+}
+
+impl DispatchableObject {
+    /// Handle given to the application
+    pub fn as_handle(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Driver handle the object wraps
+    pub fn driver_handle(&self) -> usize {
+        self.handle
+    }
+
+    /// Table the object dispatches through
+    pub fn dispatch(&self) -> &DispatchTable {
+        // Tables are boxed by the instance or device that owns the object
+        unsafe { &*self.dispatch }
+    }
+}
+
+/// Entry point `name` of `table`, cast to the command's signature
+///
+/// # Safety
+///
+/// `F` must be the function pointer type of `name`.
+unsafe fn command<F: Copy>(table: &DispatchTable, name: &str) -> Result<F, LoaderError> {
+    debug_assert_eq!(mem::size_of::<F>(), mem::size_of::<usize>());
+    let addr = table
+        .get(name)
+        .ok_or_else(|| LoaderError::DriverLoadFailed(String::from(name)))?;
+    Ok(mem::transmute_copy(&addr))
+}
+
+/// Instance created on one ICD
+struct IcdInstance {
+    name: String,
+    instance: usize,
+    dispatch: Box<DispatchTable>,
+}
+
+/// Instance spanning every ICD
+pub struct LoaderInstance<'l> {
+    icds: Vec<IcdInstance>,
+    /// Wrappers of the physical devices reported so far, kept so that handles stay stable; boxed
+    /// as handles are their addresses
+    #[allow(clippy::vec_box)]
+    physical_devices: Vec<Box<DispatchableObject>>,
+    _drivers: PhantomData<&'l IcdDriver>,
+}
+
+impl<'l> LoaderInstance<'l> {
+    /// Create an instance on each driver, skipping those that fail
+    ///
+    /// # Safety
+    ///
+    /// `create_info` must point to a valid `VkInstanceCreateInfo`.
+    pub(crate) unsafe fn create(
+        drivers: &'l [IcdDriver],
+        create_info: *const c_void,
+    ) -> Result<Self, LoaderError> {
+        let mut icds = Vec::new();
+        let mut error = LoaderError::NoDriversFound;
+
+        for driver in drivers {
+            let Some(create) = driver.instance_proc_addr(0, "vkCreateInstance") else {
+                log::warn!("{} has no vkCreateInstance", driver.manifest.name);
+                continue;
+            };
+            let create: PfnCreateInstance = mem::transmute(create);
+            let mut instance = 0;
+            let result = create(create_info, ptr::null(), &mut instance);
+            if result != VK_SUCCESS {
+                log::warn!(
+                    "{} failed to create an instance: {}",
+                    driver.manifest.name,
+                    result
+                );
+                error = LoaderError::DriverError(result);
+                continue;
+            }
+
+            let dispatch = DispatchTable::build(INSTANCE_COMMANDS, |name| {
+                driver.instance_proc_addr(instance, name)
+            });
+            log::debug!(
+                "{}: {} instance command(s)",
+                driver.manifest.name,
+                dispatch.resolved()
+            );
+            icds.push(IcdInstance {
+                name: driver.manifest.name.clone(),
+                instance,
+                dispatch: Box::new(dispatch),
+            });
+        }
+
+        if icds.is_empty() {
+            return Err(error);
+        }
+        Ok(Self {
+            icds,
+            physical_devices: Vec::new(),
+            _drivers: PhantomData,
+        })
+    }
+
+    /// Names of the drivers taking part
+    pub fn drivers(&self) -> impl Iterator<Item = &str> {
+        self.icds.iter().map(|icd| icd.name.as_str())
+    }
+
+    /// Physical devices of all drivers, in driver order
+    ///
+    /// A physical device keeps its handle across calls. Drivers failing to enumerate are
+    /// skipped.
+    pub fn enumerate_physical_devices(&mut self) -> Vec<usize> {
+        let mut handles = Vec::new();
+        for (index, icd) in self.icds.iter().enumerate() {
+            let devices = match unsafe { enumerate(icd) } {
+                Ok(devices) => devices,
+                Err(e) => {
+                    log::warn!("{} failed to enumerate physical devices: {}", icd.name, e);
+                    continue;
+                }
+            };
+            for handle in devices {
+                let existing = self
+                    .physical_devices
+                    .iter()
+                    .find(|object| object.icd == index && object.handle == handle);
+                let object = match existing {
+                    Some(object) => object,
+                    None => {
+                        self.physical_devices.push(Box::new(DispatchableObject {
+                            dispatch: &*icd.dispatch,
+                            icd: index,
+                            handle,
+                        }));
+                        self.physical_devices.last().unwrap()
+                    }
+                };
+                handles.push(object.as_handle());
+            }
+        }
+        handles
+    }
+
+    /// Wrapper behind a physical device handle from [`Self::enumerate_physical_devices`]
+    pub fn physical_device(&self, handle: usize) -> Result<&DispatchableObject, LoaderError> {
+        self.physical_devices
+            .iter()
+            .map(|object| &**object)
+            .find(|object| object.as_handle() == handle)
+            .ok_or(LoaderError::InvalidHandle)
+    }
+
+    /// Name of the driver behind a physical device
+    pub fn physical_device_driver(&self, handle: usize) -> Result<&str, LoaderError> {
+        let object = self.physical_device(handle)?;
+        Ok(&self.icds[object.icd].name)
+    }
+
+    /// Create a device on the driver owning `physical_device`
+    ///
+    /// # Safety
+    ///
+    /// `create_info` must point to a valid `VkDeviceCreateInfo`.
+    pub unsafe fn create_device(
+        &self,
+        physical_device: usize,
+        create_info: *const c_void,
+    ) -> Result<LoaderDevice<'_>, LoaderError> {
+        let object = self.physical_device(physical_device)?;
+        let icd = &self.icds[object.icd];
+        let create: PfnCreateDevice = command(&icd.dispatch, "vkCreateDevice")?;
+        let get_proc_addr: PfnGetDeviceProcAddr = command(&icd.dispatch, "vkGetDeviceProcAddr")?;
+
+        let mut device = 0;
+        let result = create(object.handle, create_info, ptr::null(), &mut device);
+        if result != VK_SUCCESS {
+            return Err(LoaderError::DriverError(result));
+        }
+
+        let dispatch = Box::new(DispatchTable::build(DEVICE_COMMANDS, |name| {
+            let c_name = icd::c_name(name);
+            let addr = get_proc_addr(device, c_name.as_ptr());
+            (addr != 0).then_some(addr)
+        }));
+        let object = Box::new(DispatchableObject {
+            dispatch: &*dispatch,
+            icd: object.icd,
+            handle: device,
+        });
+        Ok(LoaderDevice {
+            object,
+            _dispatch: dispatch,
+            _instance: PhantomData,
+        })
+    }
+}
+
+/// Two-call enumeration of an ICD's physical devices
+unsafe fn enumerate(icd: &IcdInstance) -> Result<Vec<usize>, LoaderError> {
+    let enumerate: PfnEnumeratePhysicalDevices =
+        command(&icd.dispatch, "vkEnumeratePhysicalDevices")?;
+    loop {
+        let mut count = 0;
+        let result = enumerate(icd.instance, &mut count, ptr::null_mut());
+        if result != VK_SUCCESS {
+            return Err(LoaderError::DriverError(result));
+        }
+        let mut devices = vec![0; count as usize];
+        match enumerate(icd.instance, &mut count, devices.as_mut_ptr()) {
+            // The device list grew in between
+            VK_INCOMPLETE => continue,
+            VK_SUCCESS => {
+                devices.truncate(count as usize);
+                return Ok(devices);
+            }
+            result => return Err(LoaderError::DriverError(result)),
+        }
+    }
+}
+
+impl Drop for LoaderInstance<'_> {
+    fn drop(&mut self) {
+        for icd in &self.icds {
+            if let Ok(destroy) =
+                unsafe { command::<PfnDestroyInstance>(&icd.dispatch, "vkDestroyInstance") }
+            {
+                unsafe { destroy(icd.instance, ptr::null()) };
+            }
+        }
+    }
+}
+
+/// Device created through a [`LoaderInstance`]
+pub struct LoaderDevice<'i> {
+    object: Box<DispatchableObject>,
+    /// Table `object` points to
+    _dispatch: Box<DispatchTable>,
+    _instance: PhantomData<&'i LoaderInstance<'i>>,
+}
+
+impl LoaderDevice<'_> {
+    /// Handle given to the application
+    pub fn handle(&self) -> usize {
+        self.object.as_handle()
+    }
+
+    /// Driver's device handle
+    pub fn driver_handle(&self) -> usize {
+        self.object.handle
+    }
+
+    /// Entry point of device command `name`
+    pub fn get_device_proc_addr(&self, name: &str) -> Option<usize> {
+        self.object.dispatch().get(name)
+    }
+}
+
+impl Drop for LoaderDevice<'_> {
+    fn drop(&mut self) {
+        if let Ok(destroy) =
+            unsafe { command::<PfnDestroyDevice>(self.object.dispatch(), "vkDestroyDevice") }
+        {
+            unsafe { destroy(self.object.handle, ptr::null()) };
+        }
+    }
+}
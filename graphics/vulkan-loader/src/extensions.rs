@@ -1,5 +1,7 @@
 //! Vulkan extensions support
 
+pub mod surface;
+
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -80,6 +82,8 @@ pub struct InstanceExtensions;
 
 impl InstanceExtensions {
     pub const SURFACE: &'static str = "VK_KHR_surface";
+    /// Surfaces on displays and orbital windows, see [`surface`]
+    pub const REDOX_SURFACE: &'static str = "VK_EXT_redox_surface";
     pub const SWAPCHAIN: &'static str = "VK_KHR_swapchain";
     pub const GET_PHYSICAL_DEVICE_PROPERTIES_2: &'static str =
         "VK_KHR_get_physical_device_properties2";
@@ -0,0 +1,250 @@
+//! Window system integration
+//!
+//! `VK_KHR_surface` and the Redox specific `VK_EXT_redox_surface`. Surfaces are created from a
+//! handle to a display of a `display.*` scheme or to an orbital window, and present through GAL
+//! swapchains of the GAL device behind the physical device.
+//!
+//! Orbital doesn't take client buffers yet, so a window surface presents on the display orbital
+//! runs on, at the size of the window.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+
+use gal::device::{DisplayInfo, Swapchain, SwapchainConfig};
+use gal::{Device, Extent2D, ImageFormat, PresentMode, Semaphore};
+
+use super::{Extension, InstanceExtensions};
+use crate::loader::LoaderError;
+
+/// `VK_STRUCTURE_TYPE_REDOX_SURFACE_CREATE_INFO_EXT`, in the range of unregistered extensions
+pub const STRUCTURE_TYPE_REDOX_SURFACE_CREATE_INFO: i32 = 1_000_999_000;
+
+const VK_ERROR_OUT_OF_HOST_MEMORY: i32 = -1;
+const VK_ERROR_OUT_OF_DEVICE_MEMORY: i32 = -2;
+const VK_ERROR_INITIALIZATION_FAILED: i32 = -3;
+const VK_ERROR_DEVICE_LOST: i32 = -4;
+const VK_ERROR_FEATURE_NOT_PRESENT: i32 = -8;
+const VK_ERROR_FORMAT_NOT_SUPPORTED: i32 = -11;
+const VK_ERROR_UNKNOWN: i32 = -13;
+const VK_ERROR_SURFACE_LOST_KHR: i32 = -1_000_000_000;
+const VK_TIMEOUT: i32 = 2;
+
+/// Formats every GAL display target scans out
+pub const SURFACE_FORMATS: &[ImageFormat] = &[ImageFormat::Bgra8Unorm, ImageFormat::Rgba8Unorm];
+
+/// Present modes every GAL display target supports
+pub const SURFACE_PRESENT_MODES: &[PresentMode] = &[PresentMode::Fifo, PresentMode::Mailbox];
+
+/// `VkRedoxSurfaceCreateInfoEXT`
+#[derive(Debug)]
+#[repr(C)]
+pub struct RedoxSurfaceCreateInfo {
+    pub s_type: i32,
+    pub p_next: *const c_void,
+    pub flags: u32,
+    /// Handle to a display or an orbital window
+    pub fd: usize,
+}
+
+/// What a surface presents to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceSource {
+    /// Display `id` of a display scheme
+    Display { id: usize },
+    /// Orbital window of this size
+    Window { extent: Extent2D },
+}
+
+impl SurfaceSource {
+    /// Parse the path of a display or window handle, as returned by `fpath`
+    ///
+    /// Display paths end in the display number, window paths are
+    /// `orbital:flags/x/y/width/height/title`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.strip_prefix("/scheme/").unwrap_or(path);
+        let (scheme, rest) = path.split_once([':', '/'])?;
+
+        if scheme == "orbital" {
+            let mut fields = rest.split('/').skip(3);
+            let width = fields.next()?.parse().ok()?;
+            let height = fields.next()?.parse().ok()?;
+            Some(SurfaceSource::Window {
+                extent: Extent2D::new(width, height),
+            })
+        } else if scheme == "display" || scheme.starts_with("display.") {
+            let id = rest
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()?
+                .parse()
+                .ok()?;
+            Some(SurfaceSource::Display { id })
+        } else {
+            None
+        }
+    }
+}
+
+/// `VkSurfaceCapabilitiesKHR`, with the formats and present modes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurfaceCapabilities {
+    pub min_image_count: u32,
+    /// 0 for no limit
+    pub max_image_count: u32,
+    pub current_extent: Extent2D,
+    pub formats: Vec<ImageFormat>,
+    pub present_modes: Vec<PresentMode>,
+}
+
+/// Presentation surface, a `VkSurfaceKHR`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Surface {
+    source: SurfaceSource,
+}
+
+impl Surface {
+    pub fn new(source: SurfaceSource) -> Self {
+        Self { source }
+    }
+
+    /// `vkCreateRedoxSurfaceEXT`
+    pub fn from_create_info(info: &RedoxSurfaceCreateInfo) -> Result<Self, LoaderError> {
+        if info.s_type != STRUCTURE_TYPE_REDOX_SURFACE_CREATE_INFO {
+            return Err(LoaderError::DriverError(VK_ERROR_INITIALIZATION_FAILED));
+        }
+        handle_path(info.fd)
+            .as_deref()
+            .and_then(SurfaceSource::from_path)
+            .map(Self::new)
+            .ok_or(LoaderError::InvalidHandle)
+    }
+
+    /// What the surface presents to
+    pub fn source(&self) -> SurfaceSource {
+        self.source
+    }
+
+    /// Display of `device` the surface presents on, `None` if `device` can't present to it
+    pub fn display(&self, device: &dyn Device) -> Option<DisplayInfo> {
+        match self.source {
+            SurfaceSource::Display { id } => device.display(id).filter(|display| display.enabled),
+            SurfaceSource::Window { .. } => {
+                let displays = device.displays();
+                let primary = displays
+                    .iter()
+                    .position(|display| display.enabled && display.is_primary)
+                    .or_else(|| displays.iter().position(|display| display.enabled))?;
+                displays.into_iter().nth(primary)
+            }
+        }
+    }
+
+    /// `vkGetPhysicalDeviceSurfaceSupportKHR`
+    pub fn is_supported(&self, device: &dyn Device) -> bool {
+        self.display(device).is_some()
+    }
+
+    /// `vkGetPhysicalDeviceSurfaceCapabilitiesKHR`
+    pub fn capabilities(&self, device: &dyn Device) -> Result<SurfaceCapabilities, LoaderError> {
+        let display = self
+            .display(device)
+            .ok_or(LoaderError::DriverError(VK_ERROR_SURFACE_LOST_KHR))?;
+        Ok(SurfaceCapabilities {
+            min_image_count: PresentMode::Fifo.min_image_count(),
+            max_image_count: 0,
+            current_extent: self.extent(&display),
+            formats: SURFACE_FORMATS.to_vec(),
+            present_modes: SURFACE_PRESENT_MODES.to_vec(),
+        })
+    }
+
+    /// `vkCreateSwapchainKHR`: create a GAL swapchain of at least `min_image_count` images on
+    /// the surface's display
+    pub fn create_swapchain(
+        &self,
+        device: &dyn Device,
+        min_image_count: u32,
+        format: ImageFormat,
+        present_mode: PresentMode,
+    ) -> Result<Box<dyn Swapchain>, LoaderError> {
+        if !SURFACE_FORMATS.contains(&format) {
+            return Err(LoaderError::DriverError(VK_ERROR_FORMAT_NOT_SUPPORTED));
+        }
+        let display = self
+            .display(device)
+            .ok_or(LoaderError::DriverError(VK_ERROR_SURFACE_LOST_KHR))?;
+
+        let config = SwapchainConfig {
+            display_id: display.id,
+            extent: self.extent(&display),
+            buffer_count: min_image_count,
+            format,
+            present_mode,
+        };
+        device
+            .create_swapchain(&config)
+            .map_err(|e| LoaderError::DriverError(vk_result(&e)))
+    }
+
+    fn extent(&self, display: &DisplayInfo) -> Extent2D {
+        match self.source {
+            SurfaceSource::Display { .. } => display.extent,
+            SurfaceSource::Window { extent } => extent,
+        }
+    }
+}
+
+/// `vkQueuePresentKHR`: present `image_index` of each swapchain once `wait_semaphores` are
+/// signaled
+///
+/// Returns a result per swapchain, like `VkPresentInfoKHR::pResults`.
+pub fn queue_present(
+    presents: &[(&dyn Swapchain, u32)],
+    wait_semaphores: &[&dyn Semaphore],
+) -> Vec<Result<(), LoaderError>> {
+    presents
+        .iter()
+        .map(|&(swapchain, image_index)| {
+            swapchain
+                .present(image_index, wait_semaphores)
+                .map_err(|e| LoaderError::DriverError(vk_result(&e)))
+        })
+        .collect()
+}
+
+/// `VkResult` closest to a GAL error
+fn vk_result(error: &gal::Error) -> i32 {
+    match error {
+        gal::Error::OutOfMemory => VK_ERROR_OUT_OF_HOST_MEMORY,
+        gal::Error::OutOfDeviceMemory => VK_ERROR_OUT_OF_DEVICE_MEMORY,
+        gal::Error::DeviceNotFound | gal::Error::DeviceLost => VK_ERROR_DEVICE_LOST,
+        gal::Error::NotSupported | gal::Error::FeatureNotPresent(_) => VK_ERROR_FEATURE_NOT_PRESENT,
+        gal::Error::InvalidParameter => VK_ERROR_INITIALIZATION_FAILED,
+        gal::Error::Timeout => VK_TIMEOUT,
+        _ => VK_ERROR_UNKNOWN,
+    }
+}
+
+/// Path of the handle `fd`
+#[cfg(target_os = "redox")]
+fn handle_path(fd: usize) -> Option<alloc::string::String> {
+    let mut buf = vec![0u8; 4096];
+    let len = libredox::call::fpath(fd, &mut buf).ok()?;
+    buf.truncate(len);
+    alloc::string::String::from_utf8(buf).ok()
+}
+
+#[cfg(not(target_os = "redox"))]
+fn handle_path(_fd: usize) -> Option<alloc::string::String> {
+    None
+}
+
+/// Instance extensions the loader implements itself
+pub fn instance_extensions() -> Vec<Extension> {
+    vec![
+        Extension::new(InstanceExtensions::SURFACE, 25),
+        Extension::new(InstanceExtensions::REDOX_SURFACE, 1),
+    ]
+}
@@ -4,6 +4,20 @@ use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::fmt;
 
+/// `vk_icdGetInstanceProcAddr`, returning 0 for unknown commands
+pub type PfnGetInstanceProcAddr = unsafe extern "C" fn(instance: usize, name: *const u8) -> usize;
+
+/// Kind of GPU behind an ICD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceClass {
+    /// GPU sharing memory with the CPU, usually driving the built-in panel
+    Integrated,
+    /// Dedicated GPU with its own VRAM
+    Discrete,
+    /// Paravirtualized GPU
+    Virtual,
+}
+
 /// ICD manifest describing a Vulkan driver
 #[derive(Debug, Clone)]
 pub struct IcdManifest {
@@ -15,6 +29,12 @@ pub struct IcdManifest {
     pub library_path: String,
     /// Supported extensions
     pub extensions: Vec<String>,
+    /// Kind of GPU
+    pub device_class: DeviceClass,
+    /// Whether the GPU drives any displays
+    pub has_display: bool,
+    /// Whether the driver can export and import images and fences
+    pub external_memory: bool,
 }
 
 impl IcdManifest {
@@ -25,6 +45,9 @@ impl IcdManifest {
             api_version,
             library_path: String::new(),
             extensions: Vec::new(),
+            device_class: DeviceClass::Virtual,
+            has_display: false,
+            external_memory: false,
         }
     }
 
@@ -38,8 +61,8 @@ impl IcdManifest {
 pub struct IcdDriver {
     /// Manifest
     pub manifest: IcdManifest,
-    /// Function pointers (opaque for now)
-    _handle: usize,
+    /// Driver's `vk_icdGetInstanceProcAddr`
+    entry_point: Option<PfnGetInstanceProcAddr>,
 }
 
 impl IcdDriver {
@@ -54,17 +77,46 @@ impl IcdDriver {
 
         Ok(Self {
             manifest,
-            _handle: 0,
+            entry_point: None,
         })
     }
 
+    /// Driver whose commands are resolved through `entry_point`
+    ///
+    /// # Safety
+    ///
+    /// `entry_point` must behave like `vk_icdGetInstanceProcAddr` for as long as the driver lives.
+    pub unsafe fn from_entry_point(
+        manifest: IcdManifest,
+        entry_point: PfnGetInstanceProcAddr,
+    ) -> Self {
+        Self {
+            manifest,
+            entry_point: Some(entry_point),
+        }
+    }
+
     /// Get instance proc address
-    pub fn get_instance_proc_addr(&self, _name: &str) -> Option<usize> {
-        // Would return function pointer
-        None
+    pub fn get_instance_proc_addr(&self, name: &str) -> Option<usize> {
+        self.instance_proc_addr(0, name)
+    }
+
+    /// Resolve `name` for `instance`, a driver instance handle or 0 for global commands
+    pub fn instance_proc_addr(&self, instance: usize, name: &str) -> Option<usize> {
+        let entry_point = self.entry_point?;
+        let addr = unsafe { entry_point(instance, c_name(name).as_ptr()) };
+        (addr != 0).then_some(addr)
     }
 }
 
+/// Nul terminated copy of a command name
+pub(crate) fn c_name(name: &str) -> Vec<u8> {
+    let mut c_name = Vec::with_capacity(name.len() + 1);
+    c_name.extend_from_slice(name.as_bytes());
+    c_name.push(0);
+    c_name
+}
+
 impl fmt::Debug for IcdDriver {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("IcdDriver")
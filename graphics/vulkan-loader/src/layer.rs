@@ -0,0 +1,473 @@
+//! Layer discovery and chaining
+//!
+//! Layers sit between the application and the ICD, intercepting the calls they care about
+//! (validation, capture, overlays) and passing everything on to the next link of the chain.
+//! They are described by manifests in the layer directories:
+//!
+//! - Implicit layers are active without the application asking, unless the manifest's
+//!   `disable_environment` variable is set or its `enable_environment` variable is not.
+//! - Explicit layers are active when the application enables them, or when they are listed in
+//!   `VK_INSTANCE_LAYERS`.
+//!
+//! The active layers form a chain in that order, the first being closest to the application.
+//! Each layer receives the `vkGetInstanceProcAddr` of the link below it through a list of
+//! [`LayerInstanceLink`]s, the same way the Khronos loader hands it over.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ptr;
+
+use crate::icd::{c_name, PfnGetInstanceProcAddr};
+use crate::json::{self, Value};
+use crate::library::Library;
+use crate::loader::LoaderError;
+use crate::VulkanVersion;
+
+/// Directory of implicit layer manifests
+pub const IMPLICIT_LAYER_DIR: &str = "/etc/vulkan/implicit_layer.d";
+/// Directory of explicit layer manifests
+pub const EXPLICIT_LAYER_DIR: &str = "/etc/vulkan/explicit_layer.d";
+/// Colon separated explicit layers to enable for every application
+pub const INSTANCE_LAYERS_ENV: &str = "VK_INSTANCE_LAYERS";
+
+/// Whether a layer is active without being asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerType {
+    Implicit,
+    Explicit,
+}
+
+/// Layer manifest, the `layer` object of a layer's JSON file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerManifest {
+    /// Layer name, such as `VK_LAYER_KHRONOS_validation`
+    pub name: String,
+    pub layer_type: LayerType,
+    /// Path to layer library
+    pub library_path: String,
+    /// API version the layer was written against
+    pub api_version: VulkanVersion,
+    pub implementation_version: u32,
+    pub description: String,
+    /// Variable that must hold the given value for an implicit layer to be active
+    pub enable_environment: Option<(String, String)>,
+    /// Variable that deactivates an implicit layer when set
+    pub disable_environment: Option<String>,
+}
+
+impl LayerManifest {
+    /// Create a manifest for a layer without environment conditions
+    pub fn new(name: impl ToString, layer_type: LayerType) -> Self {
+        Self {
+            name: name.to_string(),
+            layer_type,
+            library_path: String::new(),
+            api_version: VulkanVersion::VK_1_0,
+            implementation_version: 1,
+            description: String::new(),
+            enable_environment: None,
+            disable_environment: None,
+        }
+    }
+
+    /// Parse the layers of a manifest file
+    ///
+    /// Files hold a single `layer` or, from format 1.0.1 on, a `layers` array.
+    pub fn parse(text: &str, layer_type: LayerType) -> Result<Vec<Self>, LoaderError> {
+        let invalid = |what: &str| LoaderError::InvalidManifest(what.to_string());
+        let root = json::parse(text).ok_or_else(|| invalid("not JSON"))?;
+
+        let layers = match (root.get("layer"), root.get("layers")) {
+            (Some(layer), _) => core::slice::from_ref(layer),
+            (None, Some(layers)) => layers.as_array().ok_or_else(|| invalid("layers"))?,
+            (None, None) => return Err(invalid("no layer")),
+        };
+        layers
+            .iter()
+            .map(|layer| Self::parse_layer(layer, layer_type))
+            .collect()
+    }
+
+    fn parse_layer(layer: &Value, layer_type: LayerType) -> Result<Self, LoaderError> {
+        let field = |key: &str| {
+            layer
+                .get(key)
+                .and_then(Value::as_str)
+                .ok_or_else(|| LoaderError::InvalidManifest(key.to_string()))
+        };
+        // The first member of each environment object is the one that counts
+        let first_member = |key: &str| {
+            let (name, value) = layer.get(key)?.as_object()?.first()?;
+            Some((name.clone(), value.as_str().unwrap_or_default().to_string()))
+        };
+
+        let mut manifest = Self::new(field("name")?, layer_type);
+        manifest.library_path = field("library_path")?.to_string();
+        manifest.api_version = parse_version(field("api_version")?)
+            .ok_or_else(|| LoaderError::InvalidManifest("api_version".to_string()))?;
+        manifest.implementation_version = field("implementation_version")
+            .ok()
+            .and_then(|version| version.parse().ok())
+            .unwrap_or(1);
+        manifest.description = field("description").unwrap_or_default().to_string();
+        manifest.enable_environment = first_member("enable_environment");
+        manifest.disable_environment = first_member("disable_environment").map(|(name, _)| name);
+        Ok(manifest)
+    }
+
+    /// Whether an implicit layer is active in `env`
+    fn implicitly_enabled(&self, env: &dyn Fn(&str) -> Option<String>) -> bool {
+        if self.layer_type != LayerType::Implicit {
+            return false;
+        }
+        if let Some(name) = &self.disable_environment {
+            if env(name).is_some() {
+                return false;
+            }
+        }
+        match &self.enable_environment {
+            Some((name, value)) => env(name).as_deref() == Some(value.as_str()),
+            None => true,
+        }
+    }
+}
+
+/// Parse a `major.minor.patch` version
+fn parse_version(text: &str) -> Option<VulkanVersion> {
+    let mut parts = text.split('.').map(|part| part.parse::<u32>().ok());
+    Some(VulkanVersion {
+        major: parts.next()??,
+        minor: parts.next().flatten().unwrap_or(0),
+        patch: parts.next().flatten().unwrap_or(0),
+    })
+}
+
+/// Read the manifests in the implicit and explicit layer directories
+///
+/// Unreadable directories and invalid manifests are skipped with a warning.
+pub fn discover_layers() -> Vec<LayerManifest> {
+    #[cfg_attr(not(target_os = "redox"), allow(unused_mut))]
+    let mut layers = Vec::new();
+
+    #[cfg(target_os = "redox")]
+    for (dir, layer_type) in [
+        (IMPLICIT_LAYER_DIR, LayerType::Implicit),
+        (EXPLICIT_LAYER_DIR, LayerType::Explicit),
+    ] {
+        for (path, text) in redox::read_manifests(dir) {
+            match LayerManifest::parse(&text, layer_type) {
+                Ok(parsed) => layers.extend(parsed),
+                Err(e) => log::warn!("Ignoring layer manifest {}: {}", path, e),
+            }
+        }
+    }
+
+    log::info!("Discovered {} layer(s)", layers.len());
+    layers
+}
+
+#[cfg(target_os = "redox")]
+mod redox {
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use libredox::flag;
+    use syscall::dirent::DirentIter;
+
+    fn read_file(path: &str) -> Option<String> {
+        let fd = libredox::call::open(path, flag::O_RDONLY, 0).ok()?;
+        let mut data = Vec::new();
+        let mut chunk = vec![0u8; 4096];
+        let result = loop {
+            match libredox::call::read(fd, &mut chunk) {
+                Ok(0) => break Some(()),
+                Ok(n) => data.extend_from_slice(&chunk[..n]),
+                Err(_) => break None,
+            }
+        };
+        let _ = libredox::call::close(fd);
+        result?;
+        String::from_utf8(data).ok()
+    }
+
+    /// Paths and contents of the `.json` files in `dir`
+    pub fn read_manifests(dir: &str) -> Vec<(String, String)> {
+        let Ok(fd) = libredox::call::open(dir, flag::O_RDONLY | flag::O_DIRECTORY, 0) else {
+            return Vec::new();
+        };
+        let mut names = Vec::new();
+        let mut buf = vec![0u8; 4096];
+        let mut opaque = 0;
+        while let Ok(len) = libredox::call::getdents(fd, &mut buf, opaque) {
+            if len == 0 {
+                break;
+            }
+            for entry in DirentIter::new(&buf[..len]) {
+                let Ok((header, name)) = entry else {
+                    break;
+                };
+                opaque = header.next_opaque_id;
+                if let Ok(name) = core::str::from_utf8(name) {
+                    if name.ends_with(".json") {
+                        names.push(String::from(name));
+                    }
+                }
+            }
+        }
+        let _ = libredox::call::close(fd);
+
+        // Directory order is up to the filesystem, sort for a stable chain
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let path = format!("{}/{}", dir, name);
+                let text = read_file(&path)?;
+                Some((path, text))
+            })
+            .collect()
+    }
+}
+
+/// Pick the layers to activate, ordered from the application down to the ICD
+///
+/// Implicit layers come first, then those in [`INSTANCE_LAYERS_ENV`], then those the
+/// application asked for. A layer is only activated once, at its first position.
+pub fn select_layers<'a>(
+    available: &'a [LayerManifest],
+    requested: &[String],
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<&'a LayerManifest>, LoaderError> {
+    let mut active: Vec<&LayerManifest> = available
+        .iter()
+        .filter(|layer| layer.implicitly_enabled(env))
+        .collect();
+
+    let from_env = env(INSTANCE_LAYERS_ENV).unwrap_or_default();
+    let names = from_env
+        .split(':')
+        .filter(|name| !name.is_empty())
+        .chain(requested.iter().map(String::as_str));
+    for name in names {
+        if active.iter().any(|layer| layer.name == name) {
+            continue;
+        }
+        let layer = available
+            .iter()
+            .find(|layer| layer.name == name)
+            .ok_or_else(|| LoaderError::LayerNotPresent(name.to_string()))?;
+        active.push(layer);
+    }
+    Ok(active)
+}
+
+/// `VkLayerInstanceLink`: what a layer calls to reach the next link of the chain
+#[derive(Debug)]
+#[repr(C)]
+pub struct LayerInstanceLink {
+    pub next: *mut LayerInstanceLink,
+    pub next_get_instance_proc_addr: usize,
+    pub next_get_physical_device_proc_addr: usize,
+}
+
+/// `VkNegotiateLayerInterface`, filled in by `vkNegotiateLoaderLayerInterfaceVersion`
+#[repr(C)]
+struct NegotiateLayerInterface {
+    s_type: u32,
+    p_next: *mut core::ffi::c_void,
+    loader_layer_interface_version: u32,
+    pfn_get_instance_proc_addr: usize,
+    pfn_get_device_proc_addr: usize,
+    pfn_get_physical_device_proc_addr: usize,
+}
+
+/// `LAYER_NEGOTIATE_INTERFACE_STRUCT`
+const LAYER_NEGOTIATE_INTERFACE_STRUCT: u32 = 1;
+/// Newest layer interface version the loader speaks
+const LAYER_INTERFACE_VERSION: u32 = 2;
+
+/// `vkNegotiateLoaderLayerInterfaceVersion`
+type PfnNegotiateLayerInterface = unsafe extern "C" fn(*mut NegotiateLayerInterface) -> i32;
+
+/// Loaded layer library
+pub struct LoadedLayer {
+    pub manifest: LayerManifest,
+    /// Layer's `vkGetInstanceProcAddr`
+    get_instance_proc_addr: PfnGetInstanceProcAddr,
+    /// Layer's `vk_layerGetPhysicalDeviceProcAddr`, 0 if it has none
+    get_physical_device_proc_addr: usize,
+    /// Library the entry points live in, `None` for layers built from entry points
+    _library: Option<Library>,
+}
+
+impl LoadedLayer {
+    /// Load a layer from its manifest
+    ///
+    /// Layers exporting `vkNegotiateLoaderLayerInterfaceVersion` hand over their entry points
+    /// through it, older ones are looked up by their exported names.
+    pub fn load(manifest: LayerManifest) -> Result<Self, LoaderError> {
+        log::info!("Loading layer: {}", manifest.name);
+
+        let failed =
+            |why: &str| LoaderError::LayerLoadFailed(format!("{}: {}", manifest.library_path, why));
+        let library = Library::open(&manifest.library_path).map_err(|e| failed(&e))?;
+
+        let (gipa, gpdpa) = match library.symbol("vkNegotiateLoaderLayerInterfaceVersion") {
+            Some(negotiate) => {
+                let negotiate: PfnNegotiateLayerInterface =
+                    unsafe { core::mem::transmute(negotiate) };
+                let mut interface = NegotiateLayerInterface {
+                    s_type: LAYER_NEGOTIATE_INTERFACE_STRUCT,
+                    p_next: ptr::null_mut(),
+                    loader_layer_interface_version: LAYER_INTERFACE_VERSION,
+                    pfn_get_instance_proc_addr: 0,
+                    pfn_get_device_proc_addr: 0,
+                    pfn_get_physical_device_proc_addr: 0,
+                };
+                let result = unsafe { negotiate(&mut interface) };
+                if result != 0 {
+                    return Err(failed(&format!("negotiation returned VkResult {}", result)));
+                }
+                (
+                    interface.pfn_get_instance_proc_addr,
+                    interface.pfn_get_physical_device_proc_addr,
+                )
+            }
+            None => (
+                library.symbol("vkGetInstanceProcAddr").unwrap_or(0),
+                library
+                    .symbol("vk_layerGetPhysicalDeviceProcAddr")
+                    .unwrap_or(0),
+            ),
+        };
+        if gipa == 0 {
+            return Err(failed("no vkGetInstanceProcAddr"));
+        }
+
+        Ok(Self {
+            manifest,
+            get_instance_proc_addr: unsafe {
+                core::mem::transmute::<usize, PfnGetInstanceProcAddr>(gipa)
+            },
+            get_physical_device_proc_addr: gpdpa,
+            _library: Some(library),
+        })
+    }
+
+    /// Layer whose commands are resolved through the entry points given
+    ///
+    /// # Safety
+    ///
+    /// `get_instance_proc_addr` must behave like `vkGetInstanceProcAddr`, and
+    /// `get_physical_device_proc_addr` be 0 or like `vk_layerGetPhysicalDeviceProcAddr`, for as
+    /// long as the layer lives.
+    pub unsafe fn from_entry_points(
+        manifest: LayerManifest,
+        get_instance_proc_addr: PfnGetInstanceProcAddr,
+        get_physical_device_proc_addr: usize,
+    ) -> Self {
+        Self {
+            manifest,
+            get_instance_proc_addr,
+            get_physical_device_proc_addr,
+            _library: None,
+        }
+    }
+
+    /// Entry point the layer returns for `name`, if it intercepts it
+    pub fn get_instance_proc_addr(&self, name: &str) -> Option<usize> {
+        let addr = unsafe { (self.get_instance_proc_addr)(0, c_name(name).as_ptr()) };
+        (addr != 0).then_some(addr)
+    }
+}
+
+impl fmt::Debug for LoadedLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadedLayer")
+            .field("manifest", &self.manifest)
+            .finish()
+    }
+}
+
+/// Active layers, linked from the application down to the ICD
+#[derive(Debug, Default)]
+pub struct LayerChain {
+    layers: Vec<LoadedLayer>,
+    /// `links[i]` is handed to `layers[i]` and leads to the link below it; boxed so that the
+    /// `next` pointers stay valid
+    links: Box<[LayerInstanceLink]>,
+}
+
+// The links only point into the chain's own allocation.
+unsafe impl Send for LayerChain {}
+unsafe impl Sync for LayerChain {}
+
+impl LayerChain {
+    /// Link `layers`, the last calling into the ICD entry points given
+    pub fn new(
+        layers: Vec<LoadedLayer>,
+        icd_get_instance_proc_addr: usize,
+        icd_get_physical_device_proc_addr: usize,
+    ) -> Self {
+        let mut links: Box<[LayerInstanceLink]> = layers
+            .iter()
+            .enumerate()
+            .map(|(i, _)| {
+                let (gipa, gpdpa) = match layers.get(i + 1) {
+                    Some(next) => (
+                        next.get_instance_proc_addr as usize,
+                        next.get_physical_device_proc_addr,
+                    ),
+                    None => (
+                        icd_get_instance_proc_addr,
+                        icd_get_physical_device_proc_addr,
+                    ),
+                };
+                LayerInstanceLink {
+                    next: ptr::null_mut(),
+                    next_get_instance_proc_addr: gipa,
+                    next_get_physical_device_proc_addr: gpdpa,
+                }
+            })
+            .collect();
+
+        let base = links.as_mut_ptr();
+        for i in 1..links.len() {
+            // Links are consumed front to back, each layer pops its own
+            links[i - 1].next = unsafe { base.add(i) };
+        }
+
+        Self { layers, links }
+    }
+
+    /// Active layers, closest to the application first
+    pub fn layers(&self) -> &[LoadedLayer] {
+        &self.layers
+    }
+
+    /// Head of the link list passed to `vkCreateInstance` in `VkLayerInstanceCreateInfo`, null
+    /// without layers
+    pub fn instance_links(&mut self) -> *mut LayerInstanceLink {
+        self.links
+            .first_mut()
+            .map_or(ptr::null_mut(), |link| link as *mut LayerInstanceLink)
+    }
+
+    /// Resolve `name` through the chain: the topmost layer intercepting it wins, otherwise the
+    /// ICD's entry point is used directly
+    pub fn get_instance_proc_addr(
+        &self,
+        name: &str,
+        icd: impl FnOnce(&str) -> Option<usize>,
+    ) -> Option<usize> {
+        self.layers
+            .iter()
+            .find_map(|layer| layer.get_instance_proc_addr(name))
+            .or_else(|| icd(name))
+    }
+}
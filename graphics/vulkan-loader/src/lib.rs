@@ -2,18 +2,34 @@
 //!
 //! This loader discovers and loads Vulkan drivers, providing a unified
 //! Vulkan API surface for applications.
+//!
+//! Physical devices of all drivers are reported together, see [`dispatch`]. Surfaces and
+//! presentation are handled by the loader on top of GAL swapchains, see
+//! [`extensions::surface`].
+//!
+//! Layers found in the implicit and explicit layer directories are chained between the
+//! application and the drivers; see [`layer`].
 
 #![no_std]
 
 extern crate alloc;
 
+pub mod dispatch;
 pub mod extensions;
 pub mod icd;
+mod json;
+pub mod layer;
+mod library;
 pub mod loader;
+pub mod selection;
 
+pub use dispatch::{DispatchTable, LoaderDevice, LoaderInstance};
+pub use extensions::surface::{Surface, SurfaceSource};
 pub use extensions::{Extension, RayTracingExtensions};
-pub use icd::{IcdDriver, IcdManifest};
+pub use icd::{DeviceClass, IcdDriver, IcdManifest};
+pub use layer::{LayerChain, LayerManifest, LayerType};
 pub use loader::{LoaderError, VulkanLoader};
+pub use selection::{Selection, SelectionPolicy};
 
 use alloc::string::String;
 use alloc::vec::Vec;
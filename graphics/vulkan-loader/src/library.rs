@@ -0,0 +1,59 @@
+//! Shared libraries opened through the dynamic linker
+
+use alloc::string::{String, ToString};
+use core::ffi::{c_char, c_int, c_void, CStr};
+
+use crate::icd::c_name;
+
+const RTLD_NOW: c_int = 2;
+
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlclose(handle: *mut c_void) -> c_int;
+    fn dlerror() -> *mut c_char;
+}
+
+/// Last error of the dynamic linker
+fn last_error() -> String {
+    let error = unsafe { dlerror() };
+    if error.is_null() {
+        return "unknown error".to_string();
+    }
+    unsafe { CStr::from_ptr(error) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Open library, closed on drop
+pub struct Library {
+    handle: *mut c_void,
+}
+
+impl Library {
+    /// Open the library at `path`, resolving all its symbols up front
+    pub fn open(path: &str) -> Result<Self, String> {
+        if path.contains('\0') {
+            return Err("path contains a nul byte".to_string());
+        }
+        let handle = unsafe { dlopen(c_name(path).as_ptr().cast(), RTLD_NOW) };
+        if handle.is_null() {
+            return Err(last_error());
+        }
+        Ok(Self { handle })
+    }
+
+    /// Address of the exported symbol `name`, if there is one
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        let addr = unsafe { dlsym(self.handle, c_name(name).as_ptr().cast()) };
+        (!addr.is_null()).then_some(addr as usize)
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe {
+            dlclose(self.handle);
+        }
+    }
+}
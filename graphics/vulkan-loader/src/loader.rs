@@ -3,9 +3,13 @@
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::ffi::c_void;
 use core::fmt;
 
-use crate::icd::{IcdDriver, IcdManifest};
+use crate::dispatch::LoaderInstance;
+use crate::icd::{DeviceClass, IcdDriver, IcdManifest};
+use crate::layer::{self, LayerChain, LayerManifest, LoadedLayer};
+use crate::selection::{self, Selection, SelectionPolicy};
 use crate::VulkanVersion;
 
 /// Loader error types
@@ -19,6 +23,16 @@ pub enum LoaderError {
     DriverLoadFailed(String),
     /// Unsupported API version
     UnsupportedVersion,
+    /// Requested layer has no manifest
+    LayerNotPresent(String),
+    /// Layer library could not be loaded
+    LayerLoadFailed(String),
+    /// Manifest could not be parsed, with the offending field
+    InvalidManifest(String),
+    /// Handle was not created by this loader
+    InvalidHandle,
+    /// Driver command failed with this `VkResult`
+    DriverError(i32),
 }
 
 impl fmt::Display for LoaderError {
@@ -28,6 +42,11 @@ impl fmt::Display for LoaderError {
             LoaderError::GalNotAvailable => write!(f, "GAL scheme not available"),
             LoaderError::DriverLoadFailed(msg) => write!(f, "Driver load failed: {}", msg),
             LoaderError::UnsupportedVersion => write!(f, "Unsupported API version"),
+            LoaderError::LayerNotPresent(name) => write!(f, "Layer not present: {}", name),
+            LoaderError::LayerLoadFailed(msg) => write!(f, "Layer load failed: {}", msg),
+            LoaderError::InvalidManifest(msg) => write!(f, "Invalid manifest: {}", msg),
+            LoaderError::InvalidHandle => write!(f, "Invalid handle"),
+            LoaderError::DriverError(result) => write!(f, "Driver returned VkResult {}", result),
         }
     }
 }
@@ -36,8 +55,12 @@ impl fmt::Display for LoaderError {
 pub struct VulkanLoader {
     /// Loaded ICDs
     drivers: Vec<IcdDriver>,
-    /// Enabled layers
+    /// Layers requested by the application
     layers: Vec<String>,
+    /// Layers with a manifest
+    available_layers: Vec<LayerManifest>,
+    /// Active layers, empty until [`Self::activate_layers`]
+    chain: LayerChain,
 }
 
 impl VulkanLoader {
@@ -66,6 +89,8 @@ impl VulkanLoader {
         Ok(Self {
             drivers: loaded_drivers,
             layers: Vec::new(),
+            available_layers: layer::discover_layers(),
+            chain: LayerChain::default(),
         })
     }
 
@@ -74,20 +99,81 @@ impl VulkanLoader {
         &self.drivers
     }
 
+    /// Pick the drivers used for rendering and presentation
+    pub fn select(&self, policy: &SelectionPolicy) -> Result<Selection, LoaderError> {
+        let manifests: Vec<&IcdManifest> =
+            self.drivers.iter().map(|driver| &driver.manifest).collect();
+        let selection = selection::select(&manifests, policy).ok_or(LoaderError::NoDriversFound)?;
+
+        log::info!(
+            "Rendering on {}, presenting on {}",
+            manifests[selection.render].name,
+            manifests[selection.display].name
+        );
+        Ok(selection)
+    }
+
+    /// Create an instance spanning all drivers, whose physical devices are enumerated together
+    ///
+    /// # Safety
+    ///
+    /// `create_info` must point to a valid `VkInstanceCreateInfo`.
+    pub unsafe fn create_instance(
+        &self,
+        create_info: *const c_void,
+    ) -> Result<LoaderInstance<'_>, LoaderError> {
+        LoaderInstance::create(&self.drivers, create_info)
+    }
+
+    /// Layers that can be enabled
+    pub fn available_layers(&self) -> &[LayerManifest] {
+        &self.available_layers
+    }
+
     /// Enable a validation layer
     pub fn enable_layer(&mut self, layer_name: impl Into<String>) {
         self.layers.push(layer_name.into());
     }
 
+    /// Load the implicit layers, those named in `VK_INSTANCE_LAYERS` and the enabled ones, and
+    /// chain them in front of the drivers
+    ///
+    /// `env` looks up environment variables. Layers that fail to load are skipped.
+    pub fn activate_layers(
+        &mut self,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<&LayerChain, LoaderError> {
+        let selected = layer::select_layers(&self.available_layers, &self.layers, env)?;
+        let loaded: Vec<LoadedLayer> = selected
+            .into_iter()
+            .filter_map(|manifest| match LoadedLayer::load(manifest.clone()) {
+                Ok(layer) => Some(layer),
+                Err(e) => {
+                    log::warn!("Failed to load layer {}: {}", manifest.name, e);
+                    None
+                }
+            })
+            .collect();
+
+        log::info!("Activated {} layer(s)", loaded.len());
+        // The drivers' entry points are resolved per call in get_instance_proc_addr
+        self.chain = LayerChain::new(loaded, 0, 0);
+        Ok(&self.chain)
+    }
+
+    /// Active layers
+    pub fn layer_chain(&self) -> &LayerChain {
+        &self.chain
+    }
+
     /// Get instance proc address
     pub fn get_instance_proc_addr(&self, name: &str) -> Option<usize> {
-        // Try each driver
-        for driver in &self.drivers {
-            if let Some(addr) = driver.get_instance_proc_addr(name) {
-                return Some(addr);
-            }
-        }
-        None
+        // Layers intercept first, then try each driver
+        self.chain.get_instance_proc_addr(name, |name| {
+            self.drivers
+                .iter()
+                .find_map(|driver| driver.get_instance_proc_addr(name))
+        })
     }
 }
 
@@ -96,6 +182,8 @@ impl Default for VulkanLoader {
         Self::new().unwrap_or_else(|_| Self {
             drivers: Vec::new(),
             layers: Vec::new(),
+            available_layers: layer::discover_layers(),
+            chain: LayerChain::default(),
         })
     }
 }
@@ -123,8 +211,27 @@ pub fn discover_icds() -> Result<Vec<IcdManifest>, LoaderError> {
     let mut virtio_manifest = IcdManifest::new("virtio-gpu", VulkanVersion::VK_1_2);
     virtio_manifest.library_path = "/scheme/gal/virtio".into();
     virtio_manifest.extensions = vec!["VK_KHR_surface".into(), "VK_KHR_swapchain".into()];
+    virtio_manifest.device_class = DeviceClass::Virtual;
+    virtio_manifest.has_display = true;
     manifests.push(virtio_manifest);
 
+    // Intel integrated GPU driver
+    #[cfg(feature = "intel")]
+    {
+        let mut intel_manifest = IcdManifest::new("intel", VulkanVersion::VK_1_3);
+        intel_manifest.library_path = "/scheme/gal/intel".into();
+        intel_manifest.extensions = vec![
+            "VK_KHR_surface".into(),
+            "VK_KHR_swapchain".into(),
+            "VK_KHR_external_memory".into(),
+            "VK_KHR_external_fence".into(),
+        ];
+        intel_manifest.device_class = DeviceClass::Integrated;
+        intel_manifest.has_display = true;
+        intel_manifest.external_memory = true;
+        manifests.push(intel_manifest);
+    }
+
     // AMD GPU driver
     #[cfg(feature = "amdgpu")]
     {
@@ -136,7 +243,11 @@ pub fn discover_icds() -> Result<Vec<IcdManifest>, LoaderError> {
             "VK_KHR_ray_tracing_pipeline".into(),
             "VK_KHR_acceleration_structure".into(),
             "VK_KHR_ray_query".into(),
+            "VK_KHR_external_memory".into(),
+            "VK_KHR_external_fence".into(),
         ];
+        amd_manifest.device_class = DeviceClass::Discrete;
+        amd_manifest.external_memory = true;
         manifests.push(amd_manifest);
     }
 
@@ -152,7 +263,11 @@ pub fn discover_icds() -> Result<Vec<IcdManifest>, LoaderError> {
             "VK_KHR_acceleration_structure".into(),
             "VK_KHR_ray_query".into(),
             "VK_NV_ray_tracing".into(),
+            "VK_KHR_external_memory".into(),
+            "VK_KHR_external_fence".into(),
         ];
+        nvidia_manifest.device_class = DeviceClass::Discrete;
+        nvidia_manifest.external_memory = true;
         manifests.push(nvidia_manifest);
     }
 
@@ -0,0 +1,39 @@
+//! Render and presentation device selection
+//!
+//! On hybrid systems the displays hang off the integrated GPU while the
+//! discrete GPU is the faster renderer. The loader then renders on the
+//! discrete GPU and hands frames over to the integrated one for scanout
+//! (render offload), provided both drivers can share images and fences.
+//!
+//! The policy itself lives in [`gal::offload`] so that the loader and native
+//! GAL clients agree on which GPU renders.
+
+use alloc::vec::Vec;
+
+use gal::offload::{self, Candidate};
+use gal::DeviceType;
+
+use crate::icd::{DeviceClass, IcdManifest};
+
+pub use gal::offload::{OffloadSelection as Selection, SelectionPolicy};
+
+/// Select render and display drivers according to `policy`, as indices into
+/// `manifests`
+pub fn select(manifests: &[&IcdManifest], policy: &SelectionPolicy) -> Option<Selection> {
+    let candidates: Vec<_> = manifests
+        .iter()
+        .map(|manifest| Candidate {
+            name: &manifest.name,
+            device_type: match manifest.device_class {
+                DeviceClass::Integrated => DeviceType::Integrated,
+                DeviceClass::Discrete => DeviceType::Discrete,
+                DeviceClass::Virtual => DeviceType::VirtioGpu,
+            },
+            has_display: manifest.has_display,
+            renders: true,
+            shares: manifest.external_memory,
+            rank: manifest.api_version.to_u32() as u64,
+        })
+        .collect();
+    offload::select(&candidates, policy)
+}
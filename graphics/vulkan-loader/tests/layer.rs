@@ -0,0 +1,109 @@
+//! Layer chains built from mock layers
+
+use std::ffi::CStr;
+
+use vulkan_loader::layer::LoadedLayer;
+use vulkan_loader::{LayerChain, LayerManifest, LayerType, LoaderError};
+
+extern "C" fn create_instance_a() {}
+extern "C" fn create_instance_b() {}
+extern "C" fn destroy_instance_b() {}
+extern "C" fn enumerate_devices_icd() {}
+
+unsafe fn command(name: *const u8) -> &'static str {
+    CStr::from_ptr(name.cast()).to_str().unwrap()
+}
+
+unsafe extern "C" fn layer_a(_instance: usize, name: *const u8) -> usize {
+    match command(name) {
+        "vkCreateInstance" => create_instance_a as usize,
+        _ => 0,
+    }
+}
+
+unsafe extern "C" fn layer_b(_instance: usize, name: *const u8) -> usize {
+    match command(name) {
+        "vkCreateInstance" => create_instance_b as usize,
+        "vkDestroyInstance" => destroy_instance_b as usize,
+        _ => 0,
+    }
+}
+
+unsafe extern "C" fn icd(_instance: usize, _name: *const u8) -> usize {
+    0
+}
+
+fn mock_chain() -> LayerChain {
+    let layers = unsafe {
+        vec![
+            LoadedLayer::from_entry_points(
+                LayerManifest::new("VK_LAYER_mock_a", LayerType::Implicit),
+                layer_a,
+                0,
+            ),
+            LoadedLayer::from_entry_points(
+                LayerManifest::new("VK_LAYER_mock_b", LayerType::Explicit),
+                layer_b,
+                0,
+            ),
+        ]
+    };
+    LayerChain::new(layers, icd as usize, 0)
+}
+
+#[test]
+fn links_lead_down_to_the_icd() {
+    let mut chain = mock_chain();
+    let first = chain.instance_links();
+    assert!(!first.is_null());
+    unsafe {
+        assert_eq!((*first).next_get_instance_proc_addr, layer_b as usize);
+        let second = (*first).next;
+        assert!(!second.is_null());
+        assert_eq!((*second).next_get_instance_proc_addr, icd as usize);
+        assert!((*second).next.is_null());
+    }
+}
+
+#[test]
+fn topmost_layer_intercepts() {
+    let chain = mock_chain();
+    let icd = |name: &str| {
+        (name == "vkEnumeratePhysicalDevices").then_some(enumerate_devices_icd as usize)
+    };
+
+    assert_eq!(
+        chain.get_instance_proc_addr("vkCreateInstance", icd),
+        Some(create_instance_a as usize)
+    );
+    assert_eq!(
+        chain.get_instance_proc_addr("vkDestroyInstance", icd),
+        Some(destroy_instance_b as usize)
+    );
+    assert_eq!(
+        chain.get_instance_proc_addr("vkEnumeratePhysicalDevices", icd),
+        Some(enumerate_devices_icd as usize)
+    );
+    assert_eq!(chain.get_instance_proc_addr("vkCreateDevice", icd), None);
+}
+
+#[test]
+fn missing_library_fails_to_load() {
+    let mut manifest = LayerManifest::new("VK_LAYER_missing", LayerType::Explicit);
+    manifest.library_path = "/nonexistent/libVkLayer_missing.so".into();
+    assert!(matches!(
+        LoadedLayer::load(manifest),
+        Err(LoaderError::LayerLoadFailed(_))
+    ));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn library_without_entry_point_fails_to_load() {
+    let mut manifest = LayerManifest::new("VK_LAYER_libc", LayerType::Explicit);
+    manifest.library_path = "libc.so.6".into();
+    assert!(matches!(
+        LoadedLayer::load(manifest),
+        Err(LoaderError::LayerLoadFailed(_))
+    ));
+}
@@ -0,0 +1,961 @@
+//! Dynamic linking
+//!
+//! Dynamically linked executables name an interpreter in `PT_INTERP`,
+//! usually `/lib64/ld-linux-x86-64.so.2`, which Linux maps next to them to
+//! load their shared libraries. `lacd` doesn't run the interpreter but does
+//! its work up front, as ld.so does with `LD_BIND_NOW`:
+//!
+//! - Libraries named by `DT_NEEDED` are looked up in a [`Sysroot`] holding a
+//!   Linux userland, in the directories of the needing object's
+//!   `DT_RUNPATH` (or `DT_RPATH`), then in [`DEFAULT_LIBRARY_PATH`]
+//! - They are laid out one after the other from a load base, in breadth
+//!   first order
+//! - Every relocation is resolved before the process starts, looking
+//!   symbols up in load order with the executable first, like ld.so's global
+//!   scope. Symbol versions are ignored.
+//!
+//! Supported relocations are the relative, absolute, GOT, PLT and copy
+//! relocations, the TLS relocations of the initial-exec and dynamic models,
+//! and `IRELATIVE`. Objects needing others, like TLS descriptors or
+//! relocations without addend, fail to load with `ENOEXEC`.
+//!
+//! What ld.so would set up at startup is laid out after the libraries, see
+//! [`Runtime`]:
+//!
+//! - The static TLS blocks of all objects and a thread control block for
+//!   the initial thread, whose thread pointer points at it. Threads the
+//!   program creates set up their own TLS, which ld.so would help glibc
+//!   with: glibc's `libc.so.6`, relying on ld.so's internals, can't be
+//!   linked
+//! - `__tls_get_addr`, `__libc_stack_end` and `__libc_enable_secure`, for
+//!   objects that don't define them
+//! - Code calling the resolvers of `IRELATIVE` relocations and GNU indirect
+//!   functions, which the process starts in before its entry point
+//!
+//! TLS, `IRELATIVE` and indirect functions are only supported on x86_64.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::elf_loader::{self, pt_type, LoadedElf};
+use crate::errno::LinuxErrno;
+
+/// Default location of the Linux userland libraries are loaded from
+pub const SYSROOT: &str = "/usr/lib/lac/sysroot";
+
+/// Where shared libraries are laid out from
+pub const SHLIB_LOAD_BASE: u64 = 0x7f00_0000_0000;
+
+/// Directories searched after the needing object's run path, like ld.so's
+/// defaults on this architecture
+#[cfg(target_arch = "x86_64")]
+pub const DEFAULT_LIBRARY_PATH: &[&str] = &[
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib64",
+    "/usr/lib64",
+    "/lib",
+    "/usr/lib",
+];
+#[cfg(target_arch = "aarch64")]
+pub const DEFAULT_LIBRARY_PATH: &[&str] = &[
+    "/lib/aarch64-linux-gnu",
+    "/usr/lib/aarch64-linux-gnu",
+    "/lib64",
+    "/usr/lib64",
+    "/lib",
+    "/usr/lib",
+];
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Dynamic section tags
+mod dt {
+    pub const DT_NULL: u64 = 0;
+    pub const DT_NEEDED: u64 = 1;
+    pub const DT_PLTRELSZ: u64 = 2;
+    pub const DT_HASH: u64 = 4;
+    pub const DT_STRTAB: u64 = 5;
+    pub const DT_SYMTAB: u64 = 6;
+    pub const DT_RELA: u64 = 7;
+    pub const DT_RELASZ: u64 = 8;
+    pub const DT_STRSZ: u64 = 10;
+    pub const DT_SONAME: u64 = 14;
+    pub const DT_RPATH: u64 = 15;
+    pub const DT_REL: u64 = 17;
+    pub const DT_PLTREL: u64 = 20;
+    pub const DT_JMPREL: u64 = 23;
+    pub const DT_RUNPATH: u64 = 29;
+    pub const DT_GNU_HASH: u64 = 0x6fff_fef5;
+}
+
+/// Relocation types
+#[cfg(target_arch = "x86_64")]
+mod r_type {
+    pub const R_NONE: u32 = 0;
+    pub const R_ABS64: u32 = 1; // R_X86_64_64
+    pub const R_COPY: u32 = 5;
+    pub const R_GLOB_DAT: u32 = 6;
+    pub const R_JUMP_SLOT: u32 = 7;
+    pub const R_RELATIVE: u32 = 8;
+    pub const R_DTPMOD64: u32 = 16;
+    pub const R_DTPOFF64: u32 = 17;
+    pub const R_TPOFF64: u32 = 18;
+    pub const R_IRELATIVE: u32 = 37;
+}
+#[cfg(target_arch = "aarch64")]
+mod r_type {
+    pub const R_NONE: u32 = 0;
+    pub const R_ABS64: u32 = 257;
+    pub const R_COPY: u32 = 1024;
+    pub const R_GLOB_DAT: u32 = 1025;
+    pub const R_JUMP_SLOT: u32 = 1026;
+    pub const R_RELATIVE: u32 = 1027;
+    pub const R_DTPMOD64: u32 = 1028;
+    pub const R_DTPOFF64: u32 = 1029;
+    pub const R_TPOFF64: u32 = 1030;
+    pub const R_IRELATIVE: u32 = 1032;
+}
+
+/// Symbol bindings
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+
+/// Symbol types
+const STT_FUNC: u8 = 2;
+const STT_OBJECT: u8 = 1;
+const STT_GNU_IFUNC: u8 = 10;
+
+/// Symbol visibility
+const STV_PROTECTED: u8 = 3;
+
+/// Section index of undefined symbols
+const SHN_UNDEF: u16 = 0;
+/// Section index of absolute symbols
+const SHN_ABS: u16 = 0xfff1;
+
+/// Size of a symbol table entry
+const SYM_SIZE: u64 = 24;
+
+/// Size of a relocation with addend
+const RELA_SIZE: u64 = 24;
+
+/// Alignment of the thread pointer, at least that of the thread control
+/// block
+const TCB_ALIGN: u64 = 64;
+/// Space reserved for the thread control block
+const TCB_SIZE: u64 = PAGE_SIZE;
+/// Offsets in the thread control block of pointers to itself, as libc
+/// reads them
+const TCB_SELF: [u64; 2] = [0, 16];
+
+// Runtime data layout, shared with `RUNTIME_TEXT`: the entry point, the
+// number of indirect functions, the variables ld.so defines, then the
+// indirect functions as `{ address, resolver }`
+const RT_ENTRY: u64 = 0;
+const RT_IFUNC_COUNT: u64 = 8;
+const RT_STACK_END: u64 = 16;
+const RT_ENABLE_SECURE: u64 = 24;
+const RT_IFUNCS: u64 = 32;
+const RT_IFUNC_SIZE: u64 = 16;
+
+/// Offsets of the functions in `RUNTIME_TEXT`
+const RT_TLS_GET_ADDR: u64 = 0x00;
+const RT_START: u64 = 0x11;
+
+/// Runtime code, assembled from:
+///
+/// ```text
+/// start_of_text:
+/// .set data, start_of_text + 0x1000
+/// tls_get_addr:                     # rdi = { module, offset }
+///     mov rax, qword ptr fs:[0]     # thread pointer
+///     add rax, qword ptr [rdi]      # the module's block, see `R_DTPMOD64`
+///     add rax, qword ptr [rdi + 8]
+///     ret
+/// start:
+///     lea r12, [rip + data + 32]    # indirect functions
+///     mov r13, qword ptr [rip + data + 8]
+/// 1:  test r13, r13
+///     jz 2f
+///     call qword ptr [r12 + 8]      # resolver
+///     mov rcx, qword ptr [r12]
+///     mov qword ptr [rcx], rax
+///     add r12, 16
+///     dec r13
+///     jmp 1b
+/// 2:  xor edx, edx                  # no atexit function, like the kernel
+///     jmp qword ptr [rip + data]    # entry point
+/// ```
+#[cfg(target_arch = "x86_64")]
+#[rustfmt::skip]
+const RUNTIME_TEXT: [u8; 0x41] = [
+    0x64, 0x48, 0x8b, 0x04, 0x25, 0x00, 0x00, 0x00, 0x00, 0x48, 0x03, 0x07, 0x48, 0x03, 0x47, 0x08,
+    0xc3, 0x4c, 0x8d, 0x25, 0x08, 0x10, 0x00, 0x00, 0x4c, 0x8b, 0x2d, 0xe9, 0x0f, 0x00, 0x00, 0x4d,
+    0x85, 0xed, 0x74, 0x15, 0x41, 0xff, 0x54, 0x24, 0x08, 0x49, 0x8b, 0x0c, 0x24, 0x48, 0x89, 0x01,
+    0x49, 0x83, 0xc4, 0x10, 0x49, 0xff, 0xcd, 0xeb, 0xe6, 0x31, 0xd2, 0xff, 0x25, 0xbf, 0x0f, 0x00,
+    0x00,
+];
+#[cfg(not(target_arch = "x86_64"))]
+const RUNTIME_TEXT: [u8; 0] = [];
+
+/// Linux userland shared libraries are loaded from
+#[derive(Debug, Clone)]
+pub struct Sysroot {
+    root: PathBuf,
+}
+
+impl Sysroot {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Host path of the Linux path `path`
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    /// Linux path of the library `name`, searching `run_path` first
+    fn find(&self, name: &str, run_path: &[String]) -> Option<String> {
+        if name.contains('/') {
+            return Some(name.to_string());
+        }
+        run_path
+            .iter()
+            .map(String::as_str)
+            .chain(DEFAULT_LIBRARY_PATH.iter().copied())
+            .map(|dir| format!("{}/{}", dir.trim_end_matches('/'), name))
+            .find(|path| self.path(path).is_file())
+    }
+}
+
+impl Default for Sysroot {
+    fn default() -> Self {
+        Self::new(SYSROOT)
+    }
+}
+
+/// Entry of a dynamic symbol table
+#[derive(Debug, Clone)]
+struct Symbol {
+    name: String,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+}
+
+impl Symbol {
+    fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+
+    fn kind(&self) -> u8 {
+        self.info & 0xf
+    }
+
+    fn is_defined(&self) -> bool {
+        self.shndx != SHN_UNDEF
+    }
+
+    /// Whether references from its own object always bind to it
+    fn binds_locally(&self) -> bool {
+        self.is_defined() && (self.binding() == STB_LOCAL || self.other & 3 == STV_PROTECTED)
+    }
+}
+
+/// Relocation with addend
+#[derive(Debug, Clone, Copy)]
+struct Rela {
+    offset: u64,
+    r_type: u32,
+    sym: u32,
+    addend: i64,
+}
+
+/// What the dynamic section of an object says
+#[derive(Debug, Default)]
+struct Dynamic {
+    needed: Vec<String>,
+    soname: Option<String>,
+    run_path: Vec<String>,
+    symbols: Vec<Symbol>,
+    relocations: Vec<Rela>,
+}
+
+/// Shared library loaded for an executable
+#[derive(Debug)]
+pub struct SharedObject {
+    /// Linux path it was found at
+    pub path: String,
+    /// Host path it was read from
+    pub host_path: PathBuf,
+    pub elf: LoadedElf,
+    /// Offset between the linked and the loaded addresses
+    pub bias: u64,
+    dynamic: Dynamic,
+}
+
+/// Bytes written over the loaded segments before the process starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub addr: u64,
+    pub data: Vec<u8>,
+}
+
+/// Memory set up in place of ld.so, mapped after the libraries
+///
+/// The code page is followed by the data: the variables ld.so defines,
+/// the resolvers to call, then the static TLS blocks of all objects and the
+/// thread control block the initial thread's thread pointer points at.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Runtime {
+    /// Address and size of the code, mapped read-only and executable
+    pub code: u64,
+    pub code_size: u64,
+    /// Address and size of the data, mapped read-write
+    pub data: u64,
+    pub data_size: u64,
+    /// Thread pointer of the initial thread
+    pub thread_pointer: u64,
+    /// Where the process has to start, if it isn't the executable's entry
+    /// point: indirect functions are resolved first
+    pub entry: Option<u64>,
+    /// Address of `__libc_stack_end`, which the initial stack pointer has
+    /// to be stored at
+    pub stack_end: u64,
+}
+
+/// Shared libraries of an executable, laid out and relocated
+#[derive(Debug, Default)]
+pub struct LinkMap {
+    /// Libraries in load order
+    pub libraries: Vec<SharedObject>,
+    /// Relocations of the executable and the libraries and the contents of
+    /// the runtime, in the order they must be applied
+    pub patches: Vec<Patch>,
+    pub runtime: Runtime,
+}
+
+/// Object taking part in symbol resolution
+struct Scope<'a> {
+    name: &'a str,
+    /// `None` for the symbols of the runtime
+    elf: Option<&'a LoadedElf>,
+    bias: u64,
+    dynamic: &'a Dynamic,
+    /// Offset of its TLS block below the thread pointer
+    tls: Option<u64>,
+}
+
+/// Relocations resolved so far
+#[derive(Default)]
+struct Relocated {
+    patches: Vec<Patch>,
+    /// Addresses to store the result of a resolver at, and the resolver
+    ifuncs: Vec<(u64, u64)>,
+}
+
+/// Load and relocate the shared libraries `exe` needs
+///
+/// `exe_path` is the executable's Linux path, substituted for `$ORIGIN` in
+/// its run path. Libraries are laid out from `load_base`.
+pub fn link(
+    exe: &LoadedElf,
+    exe_path: &str,
+    sysroot: &Sysroot,
+    load_base: u64,
+) -> Result<LinkMap, LinuxErrno> {
+    let exe_dynamic = parse_dynamic(exe, exe_path)?;
+    let mut map = LinkMap::default();
+
+    // Names already loaded, by what objects needed them and by soname
+    let mut loaded = HashSet::new();
+    let mut queue: VecDeque<(String, Vec<String>)> = exe_dynamic
+        .needed
+        .iter()
+        .map(|name| (name.clone(), exe_dynamic.run_path.clone()))
+        .collect();
+    let mut next_base = load_base;
+
+    while let Some((name, run_path)) = queue.pop_front() {
+        if loaded.contains(&name) {
+            continue;
+        }
+        let path = sysroot.find(&name, &run_path).ok_or_else(|| {
+            log::error!("{}: can't find needed library {}", exe_path, name);
+            LinuxErrno::ENOENT
+        })?;
+        let host_path = sysroot.path(&path);
+        let elf = elf_loader::load_elf(host_path.to_str().ok_or(LinuxErrno::ENOENT)?)?;
+        if !elf.is_pie {
+            log::error!("{}: {} isn't a shared object", exe_path, path);
+            return Err(LinuxErrno::ENOEXEC);
+        }
+        let dynamic = parse_dynamic(&elf, &path)?;
+
+        let start = elf.load_addr & !(PAGE_SIZE - 1);
+        let end = (elf.load_addr + elf.mem_size).next_multiple_of(PAGE_SIZE);
+        let bias = next_base - start;
+        // Leave an unmapped page between libraries
+        next_base += end - start + PAGE_SIZE;
+        log::debug!("{}: loading {} at {:#x}", exe_path, path, bias + start);
+
+        loaded.insert(name);
+        loaded.insert(path.clone());
+        if let Some(soname) = &dynamic.soname {
+            loaded.insert(soname.clone());
+        }
+        queue.extend(
+            dynamic
+                .needed
+                .iter()
+                .map(|needed| (needed.clone(), dynamic.run_path.clone())),
+        );
+        map.libraries.push(SharedObject {
+            path,
+            host_path,
+            elf,
+            bias,
+            dynamic,
+        });
+    }
+
+    let code = next_base;
+    let data = code + PAGE_SIZE;
+    let runtime_symbols = runtime_symbols(code, data);
+
+    // Static TLS, x86_64's variant II: the blocks lie below the thread
+    // pointer, the executable's right below it, as its local-exec accesses
+    // assume
+    let mut tls_size = 0;
+    let mut tls_align = TCB_ALIGN;
+    let mut tls_offset = |elf: &LoadedElf| {
+        let phdr = tls_segment(elf)?;
+        let align = phdr.p_align.max(1);
+        tls_size = (tls_size + phdr.p_memsz).next_multiple_of(align);
+        tls_align = tls_align.max(align);
+        Some(tls_size)
+    };
+    let mut scope: Vec<Scope> = vec![Scope {
+        name: exe_path,
+        elf: Some(exe),
+        bias: exe.load_bias(),
+        dynamic: &exe_dynamic,
+        tls: tls_offset(exe),
+    }];
+    for lib in &map.libraries {
+        scope.push(Scope {
+            name: &lib.path,
+            elf: Some(&lib.elf),
+            bias: lib.bias,
+            dynamic: &lib.dynamic,
+            tls: tls_offset(&lib.elf),
+        });
+    }
+    // Last, so that definitions of the objects win
+    scope.push(Scope {
+        name: "ld.so",
+        elf: None,
+        bias: 0,
+        dynamic: &runtime_symbols,
+        tls: None,
+    });
+
+    // First definition in load order wins
+    let mut definitions: HashMap<&str, (usize, &Symbol)> = HashMap::new();
+    for (index, object) in scope.iter().enumerate() {
+        for symbol in &object.dynamic.symbols {
+            if symbol.is_defined() && symbol.binding() != STB_LOCAL && !symbol.name.is_empty() {
+                definitions
+                    .entry(symbol.name.as_str())
+                    .or_insert((index, symbol));
+            }
+        }
+    }
+
+    // Like ld.so, dependencies first, so that copy relocations in the
+    // executable copy relocated data
+    let mut relocated = Relocated::default();
+    for index in (0..scope.len()).rev() {
+        relocate(&scope, index, &definitions, &mut relocated)?;
+    }
+    let Relocated {
+        mut patches,
+        ifuncs,
+    } = relocated;
+    if (tls_size > 0 || !ifuncs.is_empty()) && RUNTIME_TEXT.is_empty() {
+        log::error!("{}: TLS and indirect functions are unsupported", exe_path);
+        return Err(LinuxErrno::ENOEXEC);
+    }
+
+    let entry = exe.entry_point + exe.load_bias();
+    let tables = data + RT_IFUNCS + ifuncs.len() as u64 * RT_IFUNC_SIZE;
+    let thread_pointer = (tables + tls_size).next_multiple_of(tls_align);
+    let data_end = (thread_pointer + TCB_SIZE).next_multiple_of(PAGE_SIZE);
+    let mut put = |addr: u64, data: Vec<u8>| patches.push(Patch { addr, data });
+
+    put(code, RUNTIME_TEXT.to_vec());
+    put(data + RT_ENTRY, entry.to_le_bytes().to_vec());
+    put(
+        data + RT_IFUNC_COUNT,
+        (ifuncs.len() as u64).to_le_bytes().to_vec(),
+    );
+    for (index, (addr, resolver)) in ifuncs.iter().enumerate() {
+        let at = data + RT_IFUNCS + index as u64 * RT_IFUNC_SIZE;
+        put(at, addr.to_le_bytes().to_vec());
+        put(at + 8, resolver.to_le_bytes().to_vec());
+    }
+    for object in &scope {
+        let (Some(elf), Some(offset)) = (object.elf, object.tls) else {
+            continue;
+        };
+        // Initialized data, the rest is zero like the fresh mapping
+        let phdr = tls_segment(elf).unwrap();
+        let image = elf
+            .data
+            .get(phdr.p_offset as usize..(phdr.p_offset + phdr.p_filesz) as usize)
+            .ok_or(LinuxErrno::ENOEXEC)?;
+        put(thread_pointer - offset, image.to_vec());
+    }
+    for field in TCB_SELF {
+        put(
+            thread_pointer + field,
+            thread_pointer.to_le_bytes().to_vec(),
+        );
+    }
+
+    map.patches = patches;
+    map.runtime = Runtime {
+        code,
+        code_size: PAGE_SIZE,
+        data,
+        data_size: data_end - data,
+        thread_pointer,
+        entry: (!ifuncs.is_empty()).then_some(code + RT_START),
+        stack_end: data + RT_STACK_END,
+    };
+
+    Ok(map)
+}
+
+/// Symbols ld.so defines, for a runtime with code at `code` and data at
+/// `data`
+fn runtime_symbols(code: u64, data: u64) -> Dynamic {
+    let symbol = |name: &str, kind: u8, value: u64, size: u64| Symbol {
+        name: name.to_string(),
+        info: STB_GLOBAL << 4 | kind,
+        other: 0,
+        shndx: SHN_ABS,
+        value,
+        size,
+    };
+    Dynamic {
+        symbols: vec![
+            symbol("__tls_get_addr", STT_FUNC, code + RT_TLS_GET_ADDR, 0),
+            symbol("__libc_stack_end", STT_OBJECT, data + RT_STACK_END, 8),
+            symbol(
+                "__libc_enable_secure",
+                STT_OBJECT,
+                data + RT_ENABLE_SECURE,
+                4,
+            ),
+        ],
+        ..Default::default()
+    }
+}
+
+/// `PT_TLS` segment of `elf`
+fn tls_segment(elf: &LoadedElf) -> Option<&elf_loader::ProgramHeader> {
+    elf.program_headers
+        .iter()
+        .find(|phdr| phdr.p_type == pt_type::PT_TLS && phdr.p_memsz > 0)
+}
+
+/// Resolve the relocations of `scope[index]` into `relocated`
+fn relocate(
+    scope: &[Scope],
+    index: usize,
+    definitions: &HashMap<&str, (usize, &Symbol)>,
+    relocated: &mut Relocated,
+) -> Result<(), LinuxErrno> {
+    let object = &scope[index];
+    let patches = &mut relocated.patches;
+
+    for rela in &object.dynamic.relocations {
+        let addr = object.bias + rela.offset;
+        let symbol = match rela.sym {
+            0 => None,
+            sym => Some(
+                object
+                    .dynamic
+                    .symbols
+                    .get(sym as usize)
+                    .ok_or(LinuxErrno::ENOEXEC)?,
+            ),
+        };
+
+        // Loaded address of the definition and the object holding it
+        let resolve = |exclude_self: bool| -> Result<Option<(u64, usize, &Symbol)>, LinuxErrno> {
+            let Some(symbol) = symbol else {
+                return Ok(None);
+            };
+            if symbol.binds_locally() && !exclude_self {
+                return Ok(Some((object.bias + symbol.value, index, symbol)));
+            }
+            let found = if exclude_self {
+                // Copy relocations take the data from the next definition
+                scope
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .find_map(|(other, scoped)| {
+                        scoped
+                            .dynamic
+                            .symbols
+                            .iter()
+                            .find(|candidate| {
+                                candidate.is_defined()
+                                    && candidate.binding() != STB_LOCAL
+                                    && candidate.name == symbol.name
+                            })
+                            .map(|candidate| (other, candidate))
+                    })
+            } else {
+                definitions.get(symbol.name.as_str()).copied()
+            };
+            match found {
+                Some((other, definition)) => Ok(Some((
+                    scope[other].bias + definition.value,
+                    other,
+                    definition,
+                ))),
+                None if symbol.binding() == STB_WEAK => Ok(None),
+                None => {
+                    log::error!("{}: undefined symbol {}", object.name, symbol.name);
+                    Err(LinuxErrno::ENOEXEC)
+                }
+            }
+        };
+        // Offset below the thread pointer of the TLS block of the object
+        // defining the symbol, and the symbol's offset in it
+        let tls = || -> Result<Option<(u64, u64)>, LinuxErrno> {
+            let (other, offset) = match symbol {
+                None => (index, 0),
+                Some(_) => match resolve(false)? {
+                    Some((_, other, definition)) => (other, definition.value),
+                    None => return Ok(None),
+                },
+            };
+            match scope[other].tls {
+                Some(block) => Ok(Some((block, offset))),
+                None => {
+                    log::error!("{}: TLS relocation without a TLS block", object.name);
+                    Err(LinuxErrno::ENOEXEC)
+                }
+            }
+        };
+
+        let data = match rela.r_type {
+            r_type::R_NONE => continue,
+            r_type::R_RELATIVE => object.bias.wrapping_add_signed(rela.addend),
+            r_type::R_GLOB_DAT | r_type::R_JUMP_SLOT | r_type::R_ABS64 => {
+                match resolve(false)? {
+                    // The resolver picks the address, once the process runs
+                    Some((resolver, _, definition)) if definition.kind() == STT_GNU_IFUNC => {
+                        if rela.r_type == r_type::R_ABS64 && rela.addend != 0 {
+                            log::error!("{}: indirect function with addend", object.name);
+                            return Err(LinuxErrno::ENOEXEC);
+                        }
+                        relocated.ifuncs.push((addr, resolver));
+                        continue;
+                    }
+                    Some((value, _, _)) if rela.r_type == r_type::R_ABS64 => {
+                        value.wrapping_add_signed(rela.addend)
+                    }
+                    Some((value, _, _)) => value,
+                    None => 0,
+                }
+            }
+            r_type::R_IRELATIVE => {
+                let resolver = object.bias.wrapping_add_signed(rela.addend);
+                relocated.ifuncs.push((addr, resolver));
+                continue;
+            }
+            // The module ID is what `__tls_get_addr` adds to the thread
+            // pointer to reach the block, its negated offset
+            r_type::R_DTPMOD64 => tls()?.map_or(0, |(block, _)| block.wrapping_neg()),
+            r_type::R_DTPOFF64 => tls()?
+                .map_or(0, |(_, offset)| offset)
+                .wrapping_add_signed(rela.addend),
+            r_type::R_TPOFF64 => tls()?
+                .map_or(0, |(block, offset)| offset.wrapping_sub(block))
+                .wrapping_add_signed(rela.addend),
+            r_type::R_COPY => {
+                let Some((src, other, definition)) = resolve(true)? else {
+                    continue;
+                };
+                let elf = scope[other].elf.ok_or(LinuxErrno::ENOEXEC)?;
+                let mut data = image_bytes(elf, definition.value, definition.size)?;
+                overlay(patches, src, &mut data);
+                patches.push(Patch { addr, data });
+                continue;
+            }
+            r_type => {
+                log::error!(
+                    "{}: unsupported relocation type {} at {:#x}",
+                    object.name,
+                    r_type,
+                    rela.offset
+                );
+                return Err(LinuxErrno::ENOEXEC);
+            }
+        };
+        patches.push(Patch {
+            addr,
+            data: data.to_le_bytes().to_vec(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Apply the `patches` covering `addr..addr + data.len()` to `data`
+fn overlay(patches: &[Patch], addr: u64, data: &mut [u8]) {
+    let end = addr + data.len() as u64;
+    for patch in patches {
+        let patch_end = patch.addr + patch.data.len() as u64;
+        let from = patch.addr.max(addr);
+        let to = patch_end.min(end);
+        if from < to {
+            let src = (from - patch.addr) as usize..(to - patch.addr) as usize;
+            data[(from - addr) as usize..(to - addr) as usize].copy_from_slice(&patch.data[src]);
+        }
+    }
+}
+
+/// Initial contents of `len` bytes at the linked address `vaddr`, zero past
+/// the end of the file data
+fn image_bytes(elf: &LoadedElf, vaddr: u64, len: u64) -> Result<Vec<u8>, LinuxErrno> {
+    let phdr = elf
+        .program_headers
+        .iter()
+        .find(|phdr| {
+            phdr.p_type == pt_type::PT_LOAD
+                && phdr.p_vaddr <= vaddr
+                && vaddr + len <= phdr.p_vaddr + phdr.p_memsz
+        })
+        .ok_or(LinuxErrno::ENOEXEC)?;
+
+    let mut data = vec![0; len as usize];
+    let start = vaddr - phdr.p_vaddr;
+    if start < phdr.p_filesz {
+        let count = (phdr.p_filesz - start).min(len) as usize;
+        let offset = (phdr.p_offset + start) as usize;
+        let file = elf
+            .data
+            .get(offset..offset + count)
+            .ok_or(LinuxErrno::ENOEXEC)?;
+        data[..count].copy_from_slice(file);
+    }
+    Ok(data)
+}
+
+/// File data at the linked address `vaddr`, up to the end of its segment
+fn file_bytes(elf: &LoadedElf, vaddr: u64) -> Result<&[u8], LinuxErrno> {
+    elf.program_headers
+        .iter()
+        .find(|phdr| {
+            phdr.p_type == pt_type::PT_LOAD
+                && phdr.p_vaddr <= vaddr
+                && vaddr < phdr.p_vaddr + phdr.p_filesz
+        })
+        .and_then(|phdr| {
+            let start = (phdr.p_offset + vaddr - phdr.p_vaddr) as usize;
+            let end = (phdr.p_offset + phdr.p_filesz) as usize;
+            elf.data.get(start..end)
+        })
+        .ok_or(LinuxErrno::ENOEXEC)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, LinuxErrno> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(LinuxErrno::ENOEXEC)
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64, LinuxErrno> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(LinuxErrno::ENOEXEC)
+}
+
+/// NUL terminated string at `offset` in the string table
+fn string_at(strtab: &[u8], offset: u64) -> Result<String, LinuxErrno> {
+    let bytes = strtab.get(offset as usize..).ok_or(LinuxErrno::ENOEXEC)?;
+    let len = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(LinuxErrno::ENOEXEC)?;
+    String::from_utf8(bytes[..len].to_vec()).map_err(|_| LinuxErrno::ENOEXEC)
+}
+
+/// Number of dynamic symbols, from the symbol hash table
+///
+/// The dynamic section doesn't record the size of the symbol table.
+fn symbol_count(
+    elf: &LoadedElf,
+    hash: Option<u64>,
+    gnu_hash: Option<u64>,
+) -> Result<u64, LinuxErrno> {
+    if let Some(hash) = hash {
+        // nbucket, nchain: every symbol has a chain entry
+        return Ok(u32_at(file_bytes(elf, hash)?, 4)? as u64);
+    }
+    let Some(gnu_hash) = gnu_hash else {
+        return Ok(0);
+    };
+
+    let table = file_bytes(elf, gnu_hash)?;
+    let nbuckets = u32_at(table, 0)? as usize;
+    let symoffset = u32_at(table, 4)?;
+    let bloom_size = u32_at(table, 8)? as usize;
+    let buckets = 16 + bloom_size * 8;
+    let chains = buckets + nbuckets * 4;
+
+    let mut last = 0;
+    for bucket in 0..nbuckets {
+        last = last.max(u32_at(table, buckets + bucket * 4)?);
+    }
+    if last < symoffset {
+        return Ok(symoffset as u64);
+    }
+    // The last chain ends at the entry with the low bit set
+    while u32_at(table, chains + (last - symoffset) as usize * 4)? & 1 == 0 {
+        last += 1;
+    }
+    Ok(last as u64 + 1)
+}
+
+/// Parse the dynamic section of `elf`, whose Linux path is `path`
+fn parse_dynamic(elf: &LoadedElf, path: &str) -> Result<Dynamic, LinuxErrno> {
+    let Some(phdr) = elf
+        .program_headers
+        .iter()
+        .find(|phdr| phdr.p_type == pt_type::PT_DYNAMIC)
+    else {
+        return Ok(Dynamic::default());
+    };
+
+    let section = elf
+        .data
+        .get(phdr.p_offset as usize..(phdr.p_offset + phdr.p_filesz) as usize)
+        .ok_or(LinuxErrno::ENOEXEC)?;
+    let mut entries: Vec<(u64, u64)> = Vec::new();
+    for entry in section.chunks_exact(16) {
+        let tag = u64_at(entry, 0)?;
+        if tag == dt::DT_NULL {
+            break;
+        }
+        entries.push((tag, u64_at(entry, 8)?));
+    }
+    let value = |tag| {
+        entries
+            .iter()
+            .find(|entry| entry.0 == tag)
+            .map(|entry| entry.1)
+    };
+
+    if value(dt::DT_REL).is_some() {
+        log::error!("{}: relocations without addend are unsupported", path);
+        return Err(LinuxErrno::ENOEXEC);
+    }
+
+    let strtab: &[u8] = match value(dt::DT_STRTAB) {
+        Some(addr) => {
+            let bytes = file_bytes(elf, addr)?;
+            let size = value(dt::DT_STRSZ).unwrap_or(bytes.len() as u64) as usize;
+            bytes.get(..size).ok_or(LinuxErrno::ENOEXEC)?
+        }
+        None => &[],
+    };
+    let strings = |tag| -> Result<Vec<String>, LinuxErrno> {
+        entries
+            .iter()
+            .filter(|entry| entry.0 == tag)
+            .map(|entry| string_at(strtab, entry.1))
+            .collect()
+    };
+
+    let origin = Path::new(path)
+        .parent()
+        .and_then(Path::to_str)
+        .unwrap_or("/");
+    let run_path = match strings(dt::DT_RUNPATH)? {
+        run_path if !run_path.is_empty() => run_path,
+        _ => strings(dt::DT_RPATH)?,
+    }
+    .iter()
+    .flat_map(|paths| paths.split(':'))
+    .filter(|dir| !dir.is_empty())
+    .map(|dir| dir.replace("${ORIGIN}", origin).replace("$ORIGIN", origin))
+    .collect();
+
+    let mut relocations = Vec::new();
+    let mut tables = vec![(value(dt::DT_RELA), value(dt::DT_RELASZ))];
+    if value(dt::DT_PLTREL) == Some(dt::DT_RELA) {
+        tables.push((value(dt::DT_JMPREL), value(dt::DT_PLTRELSZ)));
+    }
+    for (addr, size) in tables {
+        let (Some(addr), Some(size)) = (addr, size) else {
+            continue;
+        };
+        let table = file_bytes(elf, addr)?
+            .get(..size as usize)
+            .ok_or(LinuxErrno::ENOEXEC)?;
+        for entry in table.chunks_exact(RELA_SIZE as usize) {
+            let info = u64_at(entry, 8)?;
+            relocations.push(Rela {
+                offset: u64_at(entry, 0)?,
+                r_type: info as u32,
+                sym: (info >> 32) as u32,
+                addend: u64_at(entry, 16)? as i64,
+            });
+        }
+    }
+
+    let mut symbols = Vec::new();
+    if let Some(symtab) = value(dt::DT_SYMTAB) {
+        // The GNU hash table leaves out undefined symbols, which may be
+        // all an executable has
+        let referenced = relocations.iter().map(|rela| rela.sym as u64 + 1).max();
+        let count = symbol_count(elf, value(dt::DT_HASH), value(dt::DT_GNU_HASH))?
+            .max(referenced.unwrap_or(0));
+        let table = file_bytes(elf, symtab)?;
+        for index in 0..count as usize {
+            let entry = table
+                .get(index * SYM_SIZE as usize..(index + 1) * SYM_SIZE as usize)
+                .ok_or(LinuxErrno::ENOEXEC)?;
+            symbols.push(Symbol {
+                name: string_at(strtab, u32_at(entry, 0)? as u64)?,
+                info: entry[4],
+                other: entry[5],
+                shndx: u16::from_le_bytes([entry[6], entry[7]]),
+                value: u64_at(entry, 8)?,
+                size: u64_at(entry, 16)?,
+            });
+        }
+    }
+
+    Ok(Dynamic {
+        needed: strings(dt::DT_NEEDED)?,
+        soname: strings(dt::DT_SONAME)?.into_iter().next(),
+        run_path,
+        symbols,
+        relocations,
+    })
+}
@@ -23,6 +23,10 @@ pub struct LoadedElf {
     pub load_addr: u64,
     /// Total memory size needed
     pub mem_size: u64,
+    /// File offset of the program headers
+    pub phdr_offset: u64,
+    /// Size of a program header entry
+    pub phent_size: u16,
     /// ELF data
     pub data: Vec<u8>,
 }
@@ -48,6 +52,39 @@ pub struct ProgramHeader {
     pub p_align: u64,
 }
 
+/// Where position independent executables are loaded
+pub const PIE_LOAD_BASE: u64 = 0x5555_5555_4000;
+
+impl LoadedElf {
+    /// Offset between the linked and the loaded addresses
+    pub fn load_bias(&self) -> u64 {
+        if self.is_pie && self.load_addr == 0 {
+            PIE_LOAD_BASE
+        } else {
+            0
+        }
+    }
+
+    /// Linked address of the program headers, if a segment loads them
+    pub fn phdr_vaddr(&self) -> Option<u64> {
+        if let Some(phdr) = self
+            .program_headers
+            .iter()
+            .find(|phdr| phdr.p_type == pt_type::PT_PHDR)
+        {
+            return Some(phdr.p_vaddr);
+        }
+        self.program_headers
+            .iter()
+            .find(|phdr| {
+                phdr.p_type == pt_type::PT_LOAD
+                    && phdr.p_offset <= self.phdr_offset
+                    && self.phdr_offset < phdr.p_offset + phdr.p_filesz
+            })
+            .map(|phdr| phdr.p_vaddr + (self.phdr_offset - phdr.p_offset))
+    }
+}
+
 /// ELF program header types
 pub mod pt_type {
     pub const PT_NULL: u32 = 0;
@@ -297,6 +334,8 @@ fn parse_elf(data: &[u8]) -> Result<LoadedElf, LinuxErrno> {
         is_pie,
         load_addr,
         mem_size,
+        phdr_offset: e_phoff,
+        phent_size: e_phentsize,
         data: data.to_vec(),
     })
 }
@@ -332,29 +371,88 @@ pub enum AuxvType {
     AtSysinfoEhdr = 33,
 }
 
+/// Process specific values of the auxiliary vector
+#[derive(Debug, Clone, Copy)]
+pub struct AuxvInfo {
+    /// Load address of the interpreter, 0 without one
+    pub interp_base: u64,
+    /// Address of 16 random bytes on the stack
+    pub random_addr: u64,
+    /// Address of the platform string on the stack
+    pub platform_addr: u64,
+    /// Address of the executable path on the stack
+    pub execfn_addr: u64,
+    /// Address of the vDSO image
+    pub vdso_base: Option<u64>,
+    pub uid: u32,
+    pub euid: u32,
+    pub gid: u32,
+    pub egid: u32,
+}
+
+/// Platform string for `AT_PLATFORM`
+#[cfg(target_arch = "x86_64")]
+pub const PLATFORM: &str = "x86_64";
+#[cfg(target_arch = "aarch64")]
+pub const PLATFORM: &str = "aarch64";
+
+/// CPU feature bits for `AT_HWCAP`, in the format of Linux on this
+/// architecture
+#[cfg(target_arch = "x86_64")]
+pub fn hwcap() -> u64 {
+    // CPUID leaf 1 EDX, like Linux
+    core::arch::x86_64::__cpuid(1).edx as u64
+}
+
+/// CPU feature bits for `AT_HWCAP`, in the format of Linux on this
+/// architecture
+#[cfg(target_arch = "aarch64")]
+pub fn hwcap() -> u64 {
+    use std::arch::is_aarch64_feature_detected as detected;
+
+    [
+        (detected!("fp"), 1 << 0),
+        (detected!("asimd"), 1 << 1),
+        (detected!("aes"), 1 << 3),
+        (detected!("pmull"), 1 << 4),
+        (detected!("sha2"), (1 << 5) | (1 << 6)),
+        (detected!("crc"), 1 << 7),
+        (detected!("lse"), 1 << 8),
+    ]
+    .into_iter()
+    .filter(|(present, _)| *present)
+    .fold(0, |hwcap, (_, bit)| hwcap | bit)
+}
+
 /// Build auxiliary vector for process startup
-pub fn build_auxv(
-    elf: &LoadedElf,
-    base_addr: u64,
-    phdr_addr: u64,
-    random_addr: u64,
-) -> Vec<(u64, u64)> {
+pub fn build_auxv(elf: &LoadedElf, info: &AuxvInfo) -> Vec<(u64, u64)> {
+    let bias = elf.load_bias();
     let mut auxv = Vec::new();
 
-    auxv.push((AuxvType::AtPhdr as u64, phdr_addr));
-    auxv.push((AuxvType::AtPhent as u64, 56)); // sizeof(Elf64_Phdr)
-    auxv.push((AuxvType::AtPhnum as u64, elf.program_headers.len() as u64));
+    if let Some(vdso_base) = info.vdso_base {
+        auxv.push((AuxvType::AtSysinfoEhdr as u64, vdso_base));
+    }
+    auxv.push((AuxvType::AtHwcap as u64, hwcap()));
     auxv.push((AuxvType::AtPagesz as u64, 4096));
-    auxv.push((AuxvType::AtBase as u64, base_addr));
-    auxv.push((AuxvType::AtFlags as u64, 0));
-    auxv.push((AuxvType::AtEntry as u64, elf.entry_point));
-    auxv.push((AuxvType::AtUid as u64, 1000));
-    auxv.push((AuxvType::AtEuid as u64, 1000));
-    auxv.push((AuxvType::AtGid as u64, 1000));
-    auxv.push((AuxvType::AtEgid as u64, 1000));
-    auxv.push((AuxvType::AtSecure as u64, 0));
-    auxv.push((AuxvType::AtRandom as u64, random_addr));
     auxv.push((AuxvType::AtClktck as u64, 100)); // sysconf(_SC_CLK_TCK)
+    if let Some(phdr) = elf.phdr_vaddr() {
+        auxv.push((AuxvType::AtPhdr as u64, phdr + bias));
+    }
+    auxv.push((AuxvType::AtPhent as u64, elf.phent_size as u64));
+    auxv.push((AuxvType::AtPhnum as u64, elf.program_headers.len() as u64));
+    auxv.push((AuxvType::AtBase as u64, info.interp_base));
+    auxv.push((AuxvType::AtFlags as u64, 0));
+    auxv.push((AuxvType::AtEntry as u64, elf.entry_point + bias));
+    auxv.push((AuxvType::AtUid as u64, info.uid as u64));
+    auxv.push((AuxvType::AtEuid as u64, info.euid as u64));
+    auxv.push((AuxvType::AtGid as u64, info.gid as u64));
+    auxv.push((AuxvType::AtEgid as u64, info.egid as u64));
+    let secure = info.uid != info.euid || info.gid != info.egid;
+    auxv.push((AuxvType::AtSecure as u64, secure as u64));
+    auxv.push((AuxvType::AtRandom as u64, info.random_addr));
+    auxv.push((AuxvType::AtHwcap2 as u64, 0));
+    auxv.push((AuxvType::AtExecfn as u64, info.execfn_addr));
+    auxv.push((AuxvType::AtPlatform as u64, info.platform_addr));
     auxv.push((AuxvType::AtNull as u64, 0));
 
     auxv
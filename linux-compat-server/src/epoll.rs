@@ -0,0 +1,250 @@
+//! epoll, poll and select
+//!
+//! Readiness is checked rather than waited for. Emulated files (epoll
+//! instances, eventfds and timerfds) know their own state, and Redox file
+//! handles are polled without blocking, which relibc answers from the
+//! `event:` queue of the handle's scheme. A syscall that finds nothing ready
+//! reports [`SyscallResult::Blocked`](crate::translator::SyscallResult::Blocked)
+//! like a futex wait, and is checked again every time its thread is resumed
+//! until something is ready or its timeout has passed.
+//!
+//! Edge-triggered interests report readiness that appeared since the last
+//! `epoll_wait` looked at them: a file that becomes ready and stops being
+//! ready between two checks isn't reported.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Weak};
+
+use crate::errno::LinuxErrno;
+use crate::process::{FileObject, OpenFile};
+
+/// Linux poll events, which `epoll` shares
+pub mod poll_events {
+    pub const POLLIN: u32 = 0x1;
+    pub const POLLPRI: u32 = 0x2;
+    pub const POLLOUT: u32 = 0x4;
+    pub const POLLERR: u32 = 0x8;
+    pub const POLLHUP: u32 = 0x10;
+    pub const POLLNVAL: u32 = 0x20;
+    pub const POLLRDNORM: u32 = 0x40;
+    pub const POLLWRNORM: u32 = 0x100;
+    pub const POLLRDHUP: u32 = 0x2000;
+}
+
+/// Linux epoll flags
+pub mod epoll_flags {
+    pub const EPOLLEXCLUSIVE: u32 = 1 << 28;
+    pub const EPOLLWAKEUP: u32 = 1 << 29;
+    pub const EPOLLONESHOT: u32 = 1 << 30;
+    pub const EPOLLET: u32 = 1 << 31;
+    pub const EPOLL_CLOEXEC: i32 = 0o2000000;
+}
+
+/// `epoll_ctl` operations
+pub mod epoll_ctl_op {
+    pub const EPOLL_CTL_ADD: i32 = 1;
+    pub const EPOLL_CTL_DEL: i32 = 2;
+    pub const EPOLL_CTL_MOD: i32 = 3;
+}
+
+/// Size of a `struct epoll_event`, packed on x86_64
+pub const EPOLL_EVENT_SIZE: usize = 12;
+
+/// Events reported whether they are asked for or not
+const ALWAYS: u32 = poll_events::POLLERR | poll_events::POLLHUP;
+
+/// Readiness of a Redox file handle
+pub fn host_readiness(file: &File) -> u32 {
+    use poll_events::*;
+
+    let mut pollfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: (POLLIN | POLLPRI | POLLOUT | POLLRDHUP) as i16,
+        revents: 0,
+    };
+    // SAFETY: a single pollfd is passed, and a zero timeout never blocks
+    if unsafe { libc::poll(&mut pollfd, 1, 0) } <= 0 {
+        return 0;
+    }
+    pollfd.revents as u16 as u32
+}
+
+/// Interest of an epoll instance in a file
+struct Interest {
+    /// Dropped with the last descriptor of the file, like on Linux
+    file: Weak<OpenFile>,
+    events: u32,
+    data: u64,
+    /// Readiness last seen, for edge-triggered interests
+    seen: u32,
+    /// Reported with `EPOLLONESHOT`, until `EPOLL_CTL_MOD` rearms it
+    disarmed: bool,
+}
+
+impl Interest {
+    /// Events to report for `readiness`
+    fn pending(&self, readiness: u32) -> u32 {
+        if self.disarmed {
+            return 0;
+        }
+        let level = readiness & (self.events | ALWAYS);
+        if self.events & epoll_flags::EPOLLET != 0 && level & !self.seen == 0 {
+            return 0;
+        }
+        level
+    }
+}
+
+#[derive(Default)]
+struct Interests {
+    /// By file descriptor
+    files: BTreeMap<i32, Interest>,
+    /// Descriptor reported last: the next scan starts after it, so that
+    /// busy files don't hide the others when fewer events are asked for
+    last: i32,
+}
+
+/// epoll instance
+#[derive(Default)]
+pub struct Epoll {
+    interests: spin::Mutex<Interests>,
+}
+
+impl Epoll {
+    /// Watch `file` behind `fd` for `events`
+    pub fn add(
+        &self,
+        fd: i32,
+        file: &Arc<OpenFile>,
+        events: u32,
+        data: u64,
+    ) -> Result<(), LinuxErrno> {
+        let mut interests = self.interests.lock();
+        if let Some(interest) = interests.files.get(&fd) {
+            if std::ptr::eq(interest.file.as_ptr(), Arc::as_ptr(file)) {
+                return Err(LinuxErrno::EEXIST);
+            }
+        }
+        interests.files.insert(
+            fd,
+            Interest {
+                file: Arc::downgrade(file),
+                events,
+                data,
+                seen: 0,
+                disarmed: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Change the events `file` behind `fd` is watched for
+    pub fn modify(
+        &self,
+        fd: i32,
+        file: &Arc<OpenFile>,
+        events: u32,
+        data: u64,
+    ) -> Result<(), LinuxErrno> {
+        if events & epoll_flags::EPOLLEXCLUSIVE != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut interests = self.interests.lock();
+        let interest = interests
+            .files
+            .get_mut(&fd)
+            .filter(|interest| std::ptr::eq(interest.file.as_ptr(), Arc::as_ptr(file)))
+            .ok_or(LinuxErrno::ENOENT)?;
+        if interest.events & epoll_flags::EPOLLEXCLUSIVE != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        interest.events = events;
+        interest.data = data;
+        interest.seen = 0;
+        interest.disarmed = false;
+        Ok(())
+    }
+
+    /// Stop watching `file` behind `fd`
+    pub fn delete(&self, fd: i32, file: &Arc<OpenFile>) -> Result<(), LinuxErrno> {
+        let mut interests = self.interests.lock();
+        match interests.files.get(&fd) {
+            Some(interest) if std::ptr::eq(interest.file.as_ptr(), Arc::as_ptr(file)) => {
+                interests.files.remove(&fd);
+                Ok(())
+            }
+            _ => Err(LinuxErrno::ENOENT),
+        }
+    }
+
+    /// Whether this is `other`, or watches it directly or through other
+    /// epoll instances
+    pub fn watches(&self, other: &Epoll) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        self.interests
+            .lock()
+            .files
+            .values()
+            .filter_map(|interest| interest.file.upgrade())
+            .any(|file| matches!(&file.object, FileObject::Epoll(epoll) if epoll.watches(other)))
+    }
+
+    /// Events of up to `max` ready files, with their data
+    pub fn ready(&self, max: usize) -> Vec<(u32, u64)> {
+        let mut interests = self.interests.lock();
+        interests
+            .files
+            .retain(|_, interest| interest.file.strong_count() > 0);
+
+        let last = interests.last;
+        let order: Vec<i32> = interests
+            .files
+            .range(last + 1..)
+            .chain(interests.files.range(..=last))
+            .map(|(&fd, _)| fd)
+            .collect();
+
+        let mut events = Vec::new();
+        for fd in order {
+            if events.len() == max {
+                break;
+            }
+            let interest = interests.files.get_mut(&fd).unwrap();
+            let Some(file) = interest.file.upgrade() else {
+                continue;
+            };
+            let readiness = file.readiness();
+            let pending = interest.pending(readiness);
+            interest.seen = readiness;
+            if pending == 0 {
+                continue;
+            }
+            if interest.events & epoll_flags::EPOLLONESHOT != 0 {
+                interest.disarmed = true;
+            }
+            events.push((pending, interest.data));
+            interests.last = fd;
+        }
+        events
+    }
+
+    /// Readiness of the instance itself, readable when a file it watches is
+    /// ready
+    pub fn readiness(&self) -> u32 {
+        let ready = self.interests.lock().files.values().any(|interest| {
+            interest
+                .file
+                .upgrade()
+                .is_some_and(|file| interest.pending(file.readiness()) != 0)
+        });
+        if ready {
+            poll_events::POLLIN | poll_events::POLLRDNORM
+        } else {
+            0
+        }
+    }
+}
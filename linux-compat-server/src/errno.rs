@@ -47,7 +47,6 @@ pub enum LinuxErrno {
     ENOSYS = 38,
     ENOTEMPTY = 39,
     ELOOP = 40,
-    EWOULDBLOCK = 11, // Same as EAGAIN
     ENOMSG = 42,
     EIDRM = 43,
     ECHRNG = 44,
@@ -64,7 +63,6 @@ pub enum LinuxErrno {
     ENOANO = 55,
     EBADRQC = 56,
     EBADSLT = 57,
-    EDEADLOCK = 35, // Same as EDEADLK
     EBFONT = 59,
     ENOSTR = 60,
     ENODATA = 61,
@@ -143,9 +141,14 @@ pub enum LinuxErrno {
 }
 
 impl LinuxErrno {
+    /// Same as [`LinuxErrno::EAGAIN`]
+    pub const EWOULDBLOCK: Self = Self::EAGAIN;
+    /// Same as [`LinuxErrno::EDEADLK`]
+    pub const EDEADLOCK: Self = Self::EDEADLK;
+
     /// Convert from Redox errno to Linux errno
     pub fn from_redox(errno: usize) -> Self {
-        // Redox uses similar errno values to Linux, with some differences
+        // Redox numbers its errors like Linux
         match errno {
             0 => Self::Success,
             1 => Self::EPERM,
@@ -188,6 +191,97 @@ impl LinuxErrno {
             38 => Self::ENOSYS,
             39 => Self::ENOTEMPTY,
             40 => Self::ELOOP,
+            42 => Self::ENOMSG,
+            43 => Self::EIDRM,
+            44 => Self::ECHRNG,
+            45 => Self::EL2NSYNC,
+            46 => Self::EL3HLT,
+            47 => Self::EL3RST,
+            48 => Self::ELNRNG,
+            49 => Self::EUNATCH,
+            50 => Self::ENOCSI,
+            51 => Self::EL2HLT,
+            52 => Self::EBADE,
+            53 => Self::EBADR,
+            54 => Self::EXFULL,
+            55 => Self::ENOANO,
+            56 => Self::EBADRQC,
+            57 => Self::EBADSLT,
+            59 => Self::EBFONT,
+            60 => Self::ENOSTR,
+            61 => Self::ENODATA,
+            62 => Self::ETIME,
+            63 => Self::ENOSR,
+            64 => Self::ENONET,
+            65 => Self::ENOPKG,
+            66 => Self::EREMOTE,
+            67 => Self::ENOLINK,
+            68 => Self::EADV,
+            69 => Self::ESRMNT,
+            70 => Self::ECOMM,
+            71 => Self::EPROTO,
+            72 => Self::EMULTIHOP,
+            73 => Self::EDOTDOT,
+            74 => Self::EBADMSG,
+            75 => Self::EOVERFLOW,
+            76 => Self::ENOTUNIQ,
+            77 => Self::EBADFD,
+            78 => Self::EREMCHG,
+            79 => Self::ELIBACC,
+            80 => Self::ELIBBAD,
+            81 => Self::ELIBSCN,
+            82 => Self::ELIBMAX,
+            83 => Self::ELIBEXEC,
+            84 => Self::EILSEQ,
+            85 => Self::ERESTART,
+            86 => Self::ESTRPIPE,
+            87 => Self::EUSERS,
+            88 => Self::ENOTSOCK,
+            89 => Self::EDESTADDRREQ,
+            90 => Self::EMSGSIZE,
+            91 => Self::EPROTOTYPE,
+            92 => Self::ENOPROTOOPT,
+            93 => Self::EPROTONOSUPPORT,
+            94 => Self::ESOCKTNOSUPPORT,
+            95 => Self::EOPNOTSUPP,
+            96 => Self::EPFNOSUPPORT,
+            97 => Self::EAFNOSUPPORT,
+            98 => Self::EADDRINUSE,
+            99 => Self::EADDRNOTAVAIL,
+            100 => Self::ENETDOWN,
+            101 => Self::ENETUNREACH,
+            102 => Self::ENETRESET,
+            103 => Self::ECONNABORTED,
+            104 => Self::ECONNRESET,
+            105 => Self::ENOBUFS,
+            106 => Self::EISCONN,
+            107 => Self::ENOTCONN,
+            108 => Self::ESHUTDOWN,
+            109 => Self::ETOOMANYREFS,
+            110 => Self::ETIMEDOUT,
+            111 => Self::ECONNREFUSED,
+            112 => Self::EHOSTDOWN,
+            113 => Self::EHOSTUNREACH,
+            114 => Self::EALREADY,
+            115 => Self::EINPROGRESS,
+            116 => Self::ESTALE,
+            117 => Self::EUCLEAN,
+            118 => Self::ENOTNAM,
+            119 => Self::ENAVAIL,
+            120 => Self::EISNAM,
+            121 => Self::EREMOTEIO,
+            122 => Self::EDQUOT,
+            123 => Self::ENOMEDIUM,
+            124 => Self::EMEDIUMTYPE,
+            125 => Self::ECANCELED,
+            126 => Self::ENOKEY,
+            127 => Self::EKEYEXPIRED,
+            128 => Self::EKEYREVOKED,
+            129 => Self::EKEYREJECTED,
+            130 => Self::EOWNERDEAD,
+            131 => Self::ENOTRECOVERABLE,
+            132 => Self::ERFKILL,
+            133 => Self::EHWPOISON,
             _ => Self::ENOSYS, // Default for unknown
         }
     }
@@ -235,13 +329,12 @@ impl LinuxErrno {
             Self::EPIPE => "Broken pipe",
             Self::EDOM => "Math argument out of domain",
             Self::ERANGE => "Math result not representable",
-            Self::EDEADLK | Self::EDEADLOCK => "Resource deadlock avoided",
+            Self::EDEADLK => "Resource deadlock avoided",
             Self::ENAMETOOLONG => "File name too long",
             Self::ENOLCK => "No record locks available",
             Self::ENOSYS => "Function not implemented",
             Self::ENOTEMPTY => "Directory not empty",
             Self::ELOOP => "Too many symbolic links",
-            Self::EWOULDBLOCK => "Operation would block",
             Self::ECONNREFUSED => "Connection refused",
             Self::ETIMEDOUT => "Connection timed out",
             Self::ECONNRESET => "Connection reset by peer",
@@ -262,7 +355,13 @@ impl From<std::io::Error> for LinuxErrno {
     fn from(err: std::io::Error) -> Self {
         match err.raw_os_error() {
             Some(e) => LinuxErrno::from_redox(e as usize),
-            None => LinuxErrno::EIO,
+            None => match err.kind() {
+                std::io::ErrorKind::NotFound => LinuxErrno::ENOENT,
+                std::io::ErrorKind::PermissionDenied => LinuxErrno::EACCES,
+                std::io::ErrorKind::AlreadyExists => LinuxErrno::EEXIST,
+                std::io::ErrorKind::InvalidInput => LinuxErrno::EINVAL,
+                _ => LinuxErrno::EIO,
+            },
         }
     }
 }
@@ -0,0 +1,76 @@
+//! eventfd
+//!
+//! A 64-bit counter: writes add to it, reads take it, or take one in
+//! semaphore mode. Reads wait while it is zero and writes while it would
+//! overflow.
+
+use crate::epoll::poll_events;
+use crate::errno::LinuxErrno;
+use crate::translator::open_flags;
+
+/// `eventfd2` flags
+pub mod eventfd_flags {
+    use super::open_flags;
+
+    pub const EFD_SEMAPHORE: i32 = 1;
+    pub const EFD_CLOEXEC: i32 = open_flags::O_CLOEXEC;
+    pub const EFD_NONBLOCK: i32 = open_flags::O_NONBLOCK;
+}
+
+/// Largest value the counter holds
+const MAX_COUNT: u64 = u64::MAX - 1;
+
+#[derive(Debug)]
+pub struct EventFd {
+    count: spin::Mutex<u64>,
+    semaphore: bool,
+}
+
+impl EventFd {
+    pub fn new(initval: u32, semaphore: bool) -> Self {
+        Self {
+            count: spin::Mutex::new(initval as u64),
+            semaphore,
+        }
+    }
+
+    /// Take the value a read returns, failing with `EAGAIN` while the
+    /// counter is zero
+    pub fn read(&self) -> Result<u64, LinuxErrno> {
+        let mut count = self.count.lock();
+        if *count == 0 {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        let value = if self.semaphore { 1 } else { *count };
+        *count -= value;
+        Ok(value)
+    }
+
+    /// Add `value` to the counter, failing with `EAGAIN` while it would
+    /// overflow
+    pub fn write(&self, value: u64) -> Result<(), LinuxErrno> {
+        if value > MAX_COUNT {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut count = self.count.lock();
+        if value > MAX_COUNT - *count {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        *count += value;
+        Ok(())
+    }
+
+    pub fn readiness(&self) -> u32 {
+        use poll_events::*;
+
+        let count = *self.count.lock();
+        let mut readiness = 0;
+        if count > 0 {
+            readiness |= POLLIN | POLLRDNORM;
+        }
+        if count < MAX_COUNT {
+            readiness |= POLLOUT | POLLWRNORM;
+        }
+        readiness
+    }
+}
@@ -0,0 +1,174 @@
+//! Futexes
+//!
+//! Linux threads block in `futex` until another thread wakes the same
+//! address. `lacd` serves every thread of every process from one event loop,
+//! so a waiting thread can't block the translator: [`FutexTable::wait`]
+//! queues the thread and the syscall reports
+//! [`SyscallResult::Blocked`](crate::translator::SyscallResult::Blocked).
+//! Whoever made the call parks it and polls [`FutexTable::poll`], through
+//! [`SyscallTranslator::resume`](crate::translator::SyscallTranslator::resume),
+//! until it has been woken or its timeout has passed.
+//!
+//! Futexes are keyed by address within the process, so `FUTEX_PRIVATE_FLAG`
+//! makes no difference and futexes in memory shared between processes only
+//! wake threads of the same process.
+
+use std::time::Instant;
+
+use crate::errno::LinuxErrno;
+use crate::usermem::UserMemory;
+
+/// Bitset matching every waiter
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Linux futex operations
+pub mod futex_op {
+    pub const FUTEX_WAIT: u32 = 0;
+    pub const FUTEX_WAKE: u32 = 1;
+    pub const FUTEX_REQUEUE: u32 = 3;
+    pub const FUTEX_CMP_REQUEUE: u32 = 4;
+    pub const FUTEX_WAIT_BITSET: u32 = 9;
+    pub const FUTEX_WAKE_BITSET: u32 = 10;
+    pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+    pub const FUTEX_CLOCK_REALTIME: u32 = 256;
+    pub const FUTEX_CMD_MASK: u32 = !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+}
+
+/// Thread waiting on a futex
+#[derive(Debug)]
+struct Waiter {
+    tid: u32,
+    addr: u64,
+    bitset: u32,
+    deadline: Option<Instant>,
+    woken: bool,
+}
+
+/// Threads of a process waiting on futexes, in the order they started
+/// waiting
+#[derive(Debug, Default)]
+pub struct FutexTable {
+    waiters: spin::Mutex<Vec<Waiter>>,
+}
+
+impl FutexTable {
+    /// Queue `tid` on the futex at `addr` if it still holds `val`
+    ///
+    /// Fails with `EAGAIN` if the value changed, as it is read under the
+    /// same lock wakers take.
+    pub fn wait(
+        &self,
+        memory: &dyn UserMemory,
+        tid: u32,
+        addr: u64,
+        val: u32,
+        bitset: u32,
+        deadline: Option<Instant>,
+    ) -> Result<(), LinuxErrno> {
+        let mut waiters = self.waiters.lock();
+        let mut current = [0; 4];
+        memory.read(addr, &mut current)?;
+        if u32::from_le_bytes(current) != val {
+            return Err(LinuxErrno::EAGAIN);
+        }
+
+        waiters.push(Waiter {
+            tid,
+            addr,
+            bitset,
+            deadline,
+            woken: false,
+        });
+        Ok(())
+    }
+
+    /// Wake up to `count` threads waiting on `addr` with a bit of `bitset`,
+    /// returning how many were woken
+    pub fn wake(&self, addr: u64, count: usize, bitset: u32) -> usize {
+        let mut waiters = self.waiters.lock();
+        let mut woken = 0;
+        for waiter in waiters.iter_mut() {
+            if woken == count {
+                break;
+            }
+            if !waiter.woken && waiter.addr == addr && waiter.bitset & bitset != 0 {
+                waiter.woken = true;
+                woken += 1;
+            }
+        }
+        woken
+    }
+
+    /// Wake up to `count` threads waiting on `addr` and move up to
+    /// `requeue` others to `addr2`, returning how many were woken and moved
+    ///
+    /// With `expected`, fails with `EAGAIN` unless `addr` holds it.
+    pub fn requeue(
+        &self,
+        memory: &dyn UserMemory,
+        addr: u64,
+        count: usize,
+        addr2: u64,
+        requeue: usize,
+        expected: Option<u32>,
+    ) -> Result<usize, LinuxErrno> {
+        let mut waiters = self.waiters.lock();
+        if let Some(expected) = expected {
+            let mut current = [0; 4];
+            memory.read(addr, &mut current)?;
+            if u32::from_le_bytes(current) != expected {
+                return Err(LinuxErrno::EAGAIN);
+            }
+        }
+
+        let mut woken = 0;
+        let mut moved = 0;
+        for waiter in waiters.iter_mut() {
+            if waiter.woken || waiter.addr != addr {
+                continue;
+            }
+            if woken < count {
+                waiter.woken = true;
+                woken += 1;
+            } else if moved < requeue {
+                waiter.addr = addr2;
+                moved += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(woken + moved)
+    }
+
+    /// Result of the wait of `tid`, once it has been woken or timed out
+    ///
+    /// `None` while it still waits, or if it doesn't wait at all.
+    pub fn poll(&self, tid: u32, now: Instant) -> Option<Result<i64, LinuxErrno>> {
+        let mut waiters = self.waiters.lock();
+        let index = waiters.iter().position(|waiter| waiter.tid == tid)?;
+        let waiter = &waiters[index];
+        let result = if waiter.woken {
+            Ok(0)
+        } else if waiter.deadline.is_some_and(|deadline| deadline <= now) {
+            Err(LinuxErrno::ETIMEDOUT)
+        } else {
+            return None;
+        };
+        waiters.remove(index);
+        Some(result)
+    }
+
+    /// When the wait of `tid` times out, if it waits with a timeout
+    pub fn deadline(&self, tid: u32) -> Option<Instant> {
+        self.waiters
+            .lock()
+            .iter()
+            .find(|waiter| waiter.tid == tid && !waiter.woken)
+            .and_then(|waiter| waiter.deadline)
+    }
+
+    /// Stop the wait of `tid`, which is exiting
+    pub fn cancel(&self, tid: u32) {
+        self.waiters.lock().retain(|waiter| waiter.tid != tid);
+    }
+}
@@ -66,27 +66,47 @@
 //! - `kill`, `tkill`, `tgkill`
 //! - `sigaction`, `rt_sigaction`
 //! - `sigprocmask`, `rt_sigprocmask`
+//!
+//! ## Time
+//! - `clock_gettime`, `gettimeofday` (also through the vDSO)
 
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::os::unix::io::AsRawFd;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::Instant;
 
 use event::{user_data, EventQueue};
 use libredox::flag;
-use redox_scheme::{RequestKind, SignalBehavior, Socket};
+use redox_scheme::{RequestKind, Response, SignalBehavior, Socket};
 
+mod dynamic_linker;
 mod elf_loader;
+mod epoll;
 mod errno;
+mod eventfd;
+mod futex;
 mod ipc;
 mod process;
+mod procfs;
+mod sandbox;
+mod scheme;
 mod signal;
+mod socket;
 mod syscall_table;
+mod timerfd;
 mod translator;
+mod usermem;
+mod vdso;
+
+#[cfg(all(test, target_os = "linux", target_arch = "x86_64"))]
+mod tests;
 
+pub use dynamic_linker::Sysroot;
 pub use errno::LinuxErrno;
-pub use process::{Process, ProcessState};
+pub use process::{Owner, Process, ProcessState};
+pub use sandbox::{SandboxConfig, SandboxPolicy};
 pub use syscall_table::LinuxSyscall;
 pub use translator::SyscallTranslator;
 
@@ -101,16 +121,19 @@ pub struct LacConfig {
     pub default_stack_size: usize,
     /// Path prefix mapping (Linux path → Redox path)
     pub path_mappings: HashMap<String, String>,
+    /// Per-binary sandbox policies
+    pub sandbox: SandboxConfig,
+    /// Linux userland shared libraries are loaded from
+    pub sysroot: Sysroot,
 }
 
 impl Default for LacConfig {
     fn default() -> Self {
         let mut path_mappings = HashMap::new();
-        // Map Linux paths to Redox equivalents
+        // Map Linux paths to Redox equivalents, but /proc and /sys, which
+        // the translator generates
         path_mappings.insert("/".to_string(), "file:/".to_string());
         path_mappings.insert("/dev".to_string(), "file:/dev".to_string());
-        path_mappings.insert("/proc".to_string(), "proc:".to_string());
-        path_mappings.insert("/sys".to_string(), "sys:".to_string());
         path_mappings.insert("/tmp".to_string(), "file:/tmp".to_string());
         path_mappings.insert("/home".to_string(), "file:/home".to_string());
 
@@ -119,6 +142,8 @@ impl Default for LacConfig {
             debug: false,
             default_stack_size: 8 * 1024 * 1024, // 8 MB
             path_mappings,
+            sandbox: SandboxConfig::default(),
+            sysroot: Sysroot::default(),
         }
     }
 }
@@ -128,7 +153,8 @@ pub struct LacServer {
     config: LacConfig,
     translator: Arc<SyscallTranslator>,
     processes: spin::RwLock<HashMap<u32, Arc<Process>>>,
-    next_pid: std::sync::atomic::AtomicU32,
+    /// vDSO mapped into every process, if its shared memory could be set up
+    vdso: Option<vdso::Vdso>,
 }
 
 impl LacServer {
@@ -138,14 +164,15 @@ impl LacServer {
             translator: Arc::new(SyscallTranslator::new(config.path_mappings.clone())),
             config,
             processes: spin::RwLock::new(HashMap::new()),
-            next_pid: std::sync::atomic::AtomicU32::new(1000),
+            vdso: vdso::Vdso::new()
+                .map_err(|err| log::warn!("vdso unavailable, clocks use syscalls: {}", err))
+                .ok(),
         }
     }
 
     /// Allocate a new PID
     pub fn alloc_pid(&self) -> u32 {
-        self.next_pid
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+        process::alloc_pid()
     }
 
     /// Register a new process
@@ -169,31 +196,104 @@ impl LacServer {
         &self.translator
     }
 
+    /// Refresh the clocks published to the vDSO
+    pub fn update_vdso(&self) {
+        if let Some(vdso) = &self.vdso {
+            vdso.update();
+        }
+    }
+
     /// Execute a Linux ELF binary
-    pub fn exec(&self, path: &str, args: &[String], env: &[String]) -> Result<u32, LinuxErrno> {
+    ///
+    /// `owner` is the Redox process that runs the binary. Only it can make
+    /// the process' syscalls through `lac:`, and they run with its user and
+    /// group IDs.
+    pub fn exec(
+        &self,
+        path: &str,
+        args: &[String],
+        env: &[String],
+        owner: Owner,
+    ) -> Result<u32, LinuxErrno> {
         // Load the ELF binary
         let elf = elf_loader::load_elf(path)?;
 
         // Create a new process
         let pid = self.alloc_pid();
         let process = Arc::new(Process::new(pid, path.to_string()));
+        process.attach(owner);
+        process.set_user_memory(Arc::new(usermem::ProcMemory::open(owner.pid)?));
+        process.set_address_space(Arc::new(usermem::ProcAddressSpace::open(owner.pid)?));
+        if let Some(policy) = self.config.sandbox.policy_for(path) {
+            log::info!("Sandboxing {} (pid {})", path, pid);
+            process.set_sandbox(policy);
+        }
 
         // Set up the process memory space
         process.setup_memory(&elf)?;
+        if let Some(interpreter) = &elf.interpreter {
+            // Do the interpreter's work instead of loading it
+            log::debug!("Linking {} in place of {}", path, interpreter);
+            let link_map = dynamic_linker::link(
+                &elf,
+                path,
+                &self.config.sysroot,
+                dynamic_linker::SHLIB_LOAD_BASE,
+            )?;
+            process.map_libraries(link_map);
+        }
+        process.inherit_stdio()?;
+        if self.vdso.is_some() {
+            process.map_vdso();
+        }
 
-        // Set up arguments and environment
-        process.setup_stack(args, env)?;
+        // Set up arguments, environment and auxiliary vector
+        process.setup_stack(&elf, args, env)?;
 
         // Register the process
         self.register_process(process.clone());
 
         // Start execution
-        process.start(elf.entry_point)?;
+        process.start(elf.entry_point + elf.load_bias())?;
 
         Ok(pid)
     }
 }
 
+/// `time:` alarm, waking the event queue when a wait times out
+struct Alarm {
+    file: File,
+    /// Time it goes off at, until it did
+    armed: Option<Instant>,
+}
+
+impl Alarm {
+    fn new() -> std::io::Result<Self> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(format!("/scheme/time/{}", syscall::CLOCK_MONOTONIC))?;
+        Ok(Self { file, armed: None })
+    }
+
+    /// Go off at `at`, unless it goes off before already
+    fn set(&mut self, at: Instant) -> std::io::Result<()> {
+        if self.armed.is_some_and(|armed| armed <= at) {
+            return Ok(());
+        }
+        let mut time = syscall::TimeSpec::default();
+        syscall::clock_gettime(syscall::CLOCK_MONOTONIC, &mut time)
+            .map_err(|err| std::io::Error::from_raw_os_error(err.errno))?;
+        let delay = at.saturating_duration_since(Instant::now());
+        let nsec = time.tv_nsec as u64 + u64::from(delay.subsec_nanos());
+        time.tv_sec += (delay.as_secs() + nsec / 1_000_000_000) as i64;
+        time.tv_nsec = (nsec % 1_000_000_000) as i32;
+        self.file.write_all(&time)?;
+        self.armed = Some(at);
+        Ok(())
+    }
+}
+
 fn daemon(daemon: redox_daemon::Daemon) -> ! {
     common::setup_logging(
         "compat",
@@ -205,8 +305,11 @@ fn daemon(daemon: redox_daemon::Daemon) -> ! {
 
     log::info!("Linux Compatibility Server starting...");
 
-    let config = LacConfig::default();
+    let mut config = LacConfig::default();
+    config.sandbox = sandbox::SandboxConfig::load(sandbox::SANDBOX_CONFIG)
+        .expect("lacd: failed to load sandbox configuration");
     let server = Arc::new(LacServer::new(config));
+    let mut scheme = scheme::LacScheme::new(server.clone());
 
     // Create the LAC scheme
     let socket = Socket::nonblock("lac").expect("lacd: failed to create lac scheme");
@@ -216,10 +319,13 @@ fn daemon(daemon: redox_daemon::Daemon) -> ! {
     user_data! {
         enum Source {
             Scheme,
+            /// Redox file of a Linux process with a waiting thread
+            Files,
+            Alarm,
         }
     }
 
-    let event_queue = EventQueue::<Source>::new().expect("lacd: failed to create event queue");
+    let mut event_queue = EventQueue::<Source>::new().expect("lacd: failed to create event queue");
 
     event_queue
         .subscribe(
@@ -229,15 +335,30 @@ fn daemon(daemon: redox_daemon::Daemon) -> ! {
         )
         .unwrap();
 
-    libredox::call::setrens(0, 0).expect("lacd: failed to enter null namespace");
+    let mut alarm = Alarm::new().expect("lacd: failed to open time scheme");
+    event_queue
+        .subscribe(
+            alarm.file.as_raw_fd() as usize,
+            Source::Alarm,
+            event::EventFlags::READ,
+        )
+        .unwrap();
+    // Files subscribed to for waits, until they are closed
+    let mut subscribed: Vec<Weak<process::OpenFile>> = Vec::new();
 
+    // The namespace is kept: translated syscalls open files on behalf of the
+    // Linux processes, with their credentials (see `scheme`)
     daemon
         .ready()
         .expect("lacd: failed to mark daemon as ready");
 
     log::info!("LAC server ready");
 
-    for event in event_queue.map(|e| e.expect("lacd: failed to get next event")) {
+    // Not a `for` loop: waits subscribe new files while handling events
+    while let Some(event) = event_queue.next() {
+        let event = event.expect("lacd: failed to get next event");
+        server.update_vdso();
+
         match event.user_data {
             Source::Scheme => {
                 loop {
@@ -252,30 +373,64 @@ fn daemon(daemon: redox_daemon::Daemon) -> ! {
 
                     match request.kind() {
                         RequestKind::Call(call) => {
-                            // Handle scheme calls
-                            let response = handle_scheme_call(&server, call);
+                            let response = call.handle_sync(&mut scheme);
                             socket
                                 .write_response(response, SignalBehavior::Restart)
                                 .expect("lacd: failed to write response");
                         }
+                        RequestKind::OnClose { id } => scheme.on_close(id),
                         _ => {}
                     }
                 }
             }
+            // Checked below, like everything a wait may depend on
+            Source::Files => {}
+            Source::Alarm => alarm.armed = None,
+        }
+
+        // Waits the requests or the event let finish
+        for id in scheme.poll_blocked() {
+            socket
+                .write_response(
+                    Response::post_fevent(id, syscall::EventFlags::EVENT_READ.bits()),
+                    SignalBehavior::Restart,
+                )
+                .expect("lacd: failed to post event");
+        }
+
+        let watched = scheme.watched();
+        subscribed.retain(|file| file.strong_count() > 0);
+        for file in watched.files {
+            if subscribed
+                .iter()
+                .any(|known| std::ptr::eq(known.as_ptr(), Arc::as_ptr(&file)))
+            {
+                continue;
+            }
+            let Some(host) = file.host() else {
+                continue;
+            };
+            // Regular files have no events, their waits don't last
+            let fd = host.as_raw_fd() as usize;
+            if let Err(err) = event_queue.subscribe(
+                fd,
+                Source::Files,
+                event::EventFlags::READ | event::EventFlags::WRITE,
+            ) {
+                log::debug!("Can't wait for events of fd {}: {}", fd, err);
+            }
+            subscribed.push(Arc::downgrade(&file));
+        }
+        if let Some(wakeup) = watched.wakeup {
+            if let Err(err) = alarm.set(wakeup) {
+                log::warn!("Failed to set alarm: {}", err);
+            }
         }
     }
 
     unreachable!()
 }
 
-fn handle_scheme_call(
-    _server: &Arc<LacServer>,
-    call: redox_scheme::Call,
-) -> redox_scheme::Response {
-    // Basic scheme handling - would be extended for full functionality
-    call.error(syscall::error::ENOSYS)
-}
-
 fn main() {
     redox_daemon::Daemon::new(daemon).expect("lacd: failed to create daemon");
 }
@@ -2,13 +2,45 @@
 //!
 //! This module manages processes running under the LAC layer.
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::os::fd::AsFd;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::elf_loader::LoadedElf;
+use crate::dynamic_linker::{LinkMap, Patch, Runtime, SHLIB_LOAD_BASE};
+use crate::elf_loader::{self, AuxvInfo, LoadedElf};
+use crate::epoll::{self, Epoll};
 use crate::errno::LinuxErrno;
+use crate::eventfd::EventFd;
+use crate::futex::FutexTable;
+use crate::procfs::ProcFile;
+use crate::sandbox::SandboxPolicy;
 use crate::signal::SignalState;
+use crate::socket::Socket;
+use crate::timerfd::TimerFd;
+use crate::translator::{open_flags, PendingCall};
+use crate::usermem::{AddressSpace, UserMemory};
+use crate::vdso;
+
+/// Most file descriptors a process can hold, Linux' default `RLIMIT_NOFILE`
+pub const MAX_FDS: i32 = 1024;
+
+const PAGE_SIZE: u64 = 4096;
+/// Mappings without a fixed address are placed top down from below the
+/// shared libraries
+const MMAP_TOP: u64 = SHLIB_LOAD_BASE;
+/// Lowest address a mapping can get, Linux' default `mmap_min_addr`
+const MMAP_MIN_ADDR: u64 = 0x10000;
+
+/// Next Linux process or thread ID, which share one namespace
+static NEXT_PID: AtomicU32 = AtomicU32::new(1000);
+
+/// Allocate an ID for a new process or thread
+pub fn alloc_pid() -> u32 {
+    NEXT_PID.fetch_add(1, Ordering::SeqCst)
+}
 
 /// Process state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -69,6 +101,27 @@ pub struct Process {
     start_time: u64,
     /// CPU time used (nanoseconds)
     cpu_time: AtomicU64,
+    /// Sandbox policy, inherited by children
+    sandbox: spin::RwLock<Option<Arc<SandboxPolicy>>>,
+    /// Access to the process' memory, once it is attached
+    user_memory: spin::RwLock<Option<Arc<dyn UserMemory>>>,
+    /// Mappings of the process, once it is attached
+    address_space: spin::RwLock<Option<Arc<dyn AddressSpace>>>,
+    /// Redox process running the program, once it is attached
+    owner: spin::RwLock<Option<Owner>>,
+    /// Threads waiting on futexes
+    futexes: FutexTable,
+}
+
+/// Redox process a Linux process runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    /// Redox pid
+    pub pid: usize,
+    /// User ID the Redox process runs as
+    pub uid: u32,
+    /// Group ID the Redox process runs as
+    pub gid: u32,
 }
 
 /// Thread within a process
@@ -83,6 +136,8 @@ pub struct Thread {
     clear_child_tid: AtomicU64,
     /// Register state
     registers: spin::RwLock<RegisterState>,
+    /// Syscall waiting for file descriptors to become ready
+    pending: spin::Mutex<Option<PendingCall>>,
 }
 
 /// Thread state
@@ -139,6 +194,15 @@ pub struct MemoryMap {
     stack_top: u64,
     /// Stack bottom
     stack_bottom: u64,
+    /// Initial contents of the top of the stack: argc, argv, envp, auxv and
+    /// the strings they point to
+    initial_stack: Vec<u8>,
+    /// Address of the vDSO image
+    vdso_base: Option<u64>,
+    /// Relocations resolved by the dynamic linker
+    relocations: Vec<Patch>,
+    /// What the dynamic linker set up in place of ld.so
+    runtime: Option<Runtime>,
 }
 
 impl Default for MemoryMap {
@@ -149,10 +213,99 @@ impl Default for MemoryMap {
             start_brk: 0,
             stack_top: 0,
             stack_bottom: 0,
+            initial_stack: Vec::new(),
+            vdso_base: None,
+            relocations: Vec::new(),
+            runtime: None,
         }
     }
 }
 
+impl MemoryMap {
+    /// Page aligned ranges in use: the regions and the heap
+    fn busy(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.regions
+            .iter()
+            .map(|region| {
+                (
+                    region.start & !(PAGE_SIZE - 1),
+                    region.end.next_multiple_of(PAGE_SIZE),
+                )
+            })
+            .chain([(self.start_brk, self.brk.next_multiple_of(PAGE_SIZE))])
+            .filter(|(start, end)| start < end)
+    }
+
+    /// Whether anything is mapped in `start..end`
+    fn is_busy(&self, start: u64, end: u64) -> bool {
+        self.busy()
+            .any(|(busy_start, busy_end)| busy_start < end && start < busy_end)
+    }
+
+    /// Whether all of `start..end` is mapped
+    fn is_mapped(&self, start: u64, end: u64) -> bool {
+        let mut busy: Vec<(u64, u64)> = self.busy().collect();
+        busy.sort();
+        let mut covered = start;
+        for (busy_start, busy_end) in busy {
+            if busy_start > covered {
+                break;
+            }
+            covered = covered.max(busy_end);
+        }
+        covered >= end
+    }
+
+    /// Highest free range of `len` bytes below [`MMAP_TOP`]
+    fn find_free(&self, len: u64) -> Option<u64> {
+        let mut busy: Vec<(u64, u64)> = self.busy().collect();
+        busy.sort();
+        let mut found = None;
+        let mut free = MMAP_MIN_ADDR;
+        for (start, end) in busy.into_iter().chain([(MMAP_TOP, MMAP_TOP)]) {
+            let gap_end = start.min(MMAP_TOP);
+            if let Some(addr) = gap_end.checked_sub(len).filter(|&addr| addr >= free) {
+                found = Some(addr);
+            }
+            free = free.max(end);
+            if free >= MMAP_TOP {
+                break;
+            }
+        }
+        found
+    }
+
+    /// The regions, split at `start` and `end`
+    fn split(&self, start: u64, end: u64) -> Vec<MemoryRegion> {
+        let mut regions = Vec::new();
+        for region in &self.regions {
+            let mut rest = region.clone();
+            for at in [start, end] {
+                if rest.start < at && at < rest.end {
+                    let mut head = rest.clone();
+                    head.end = at;
+                    if rest.path.is_some() {
+                        rest.offset += at - rest.start;
+                    }
+                    rest.start = at;
+                    regions.push(head);
+                }
+            }
+            regions.push(rest);
+        }
+        regions
+    }
+
+    /// Forget what was mapped in `start..end`
+    fn remove(&mut self, start: u64, end: u64) {
+        self.regions = self
+            .split(start, end)
+            .into_iter()
+            .filter(|region| region.end <= start || region.start >= end)
+            .collect();
+    }
+}
+
 /// Memory region
 #[derive(Debug, Clone)]
 pub struct MemoryRegion {
@@ -184,6 +337,7 @@ pub mod map_flags {
     pub const MAP_PRIVATE: u32 = 0x02;
     pub const MAP_FIXED: u32 = 0x10;
     pub const MAP_ANONYMOUS: u32 = 0x20;
+    pub const MAP_FIXED_NOREPLACE: u32 = 0x100000;
     pub const MAP_GROWSDOWN: u32 = 0x100;
     pub const MAP_DENYWRITE: u32 = 0x800;
     pub const MAP_EXECUTABLE: u32 = 0x1000;
@@ -196,33 +350,84 @@ pub mod map_flags {
 }
 
 /// File descriptor table
+#[derive(Default)]
 pub struct FdTable {
     /// Open file descriptors
-    files: HashMap<i32, FileDescriptor>,
-    /// Next available fd
-    next_fd: i32,
-    /// Close-on-exec flags
-    cloexec: HashMap<i32, bool>,
+    files: BTreeMap<i32, FileDescriptor>,
 }
 
-impl Default for FdTable {
-    fn default() -> Self {
-        Self {
-            files: HashMap::new(),
-            next_fd: 3, // 0, 1, 2 are stdin, stdout, stderr
-            cloexec: HashMap::new(),
+impl FdTable {
+    /// Lowest free descriptor, as Linux hands out
+    fn lowest_free(&self) -> Result<i32, LinuxErrno> {
+        (0..MAX_FDS)
+            .find(|fd| !self.files.contains_key(fd))
+            .ok_or(LinuxErrno::EMFILE)
+    }
+}
+
+/// What an open file description refers to
+pub enum FileObject {
+    /// Redox file handle, holding the offset
+    Host(File),
+    /// epoll instance
+    Epoll(Epoll),
+    /// eventfd counter
+    EventFd(EventFd),
+    /// timerfd
+    TimerFd(TimerFd),
+    /// Socket, backed by a host socket
+    Socket(Socket),
+    /// File or directory of /proc or /sys
+    Proc(ProcFile),
+}
+
+/// Open file description, shared by duplicated descriptors
+pub struct OpenFile {
+    pub object: FileObject,
+    /// Absolute Linux path it was opened by, or the name Linux shows for
+    /// emulated files
+    pub path: String,
+    /// Linux open flags
+    pub flags: i32,
+}
+
+impl OpenFile {
+    /// Redox file handle, for files that have one
+    pub fn host(&self) -> Option<&File> {
+        match &self.object {
+            FileObject::Host(file) => Some(file),
+            FileObject::Socket(socket) => Some(socket.file()),
+            _ => None,
+        }
+    }
+
+    /// Socket, for sockets
+    pub fn socket(&self) -> Option<&Socket> {
+        match &self.object {
+            FileObject::Socket(socket) => Some(socket),
+            _ => None,
+        }
+    }
+
+    /// Linux poll events the file is ready for
+    pub fn readiness(&self) -> u32 {
+        match &self.object {
+            FileObject::Host(file) => epoll::host_readiness(file),
+            FileObject::Epoll(epoll) => epoll.readiness(),
+            FileObject::EventFd(eventfd) => eventfd.readiness(),
+            FileObject::TimerFd(timerfd) => timerfd.readiness(Instant::now()),
+            FileObject::Socket(socket) => socket.readiness(),
+            FileObject::Proc(file) => file.readiness(),
         }
     }
 }
 
 /// File descriptor
+#[derive(Clone)]
 pub struct FileDescriptor {
-    /// Redox file handle (or scheme path)
-    pub path: String,
-    /// Open flags
-    pub flags: i32,
-    /// Current offset
-    pub offset: u64,
+    pub file: Arc<OpenFile>,
+    /// Closed on `execve`
+    pub cloexec: bool,
 }
 
 impl Process {
@@ -244,10 +449,16 @@ impl Process {
             exit_status: spin::RwLock::new(None),
             memory: spin::RwLock::new(MemoryMap::default()),
             signals: spin::RwLock::new(SignalState::default()),
-            threads: spin::RwLock::new(Vec::new()),
+            // The main thread's ID is the process ID
+            threads: spin::RwLock::new(vec![Arc::new(Thread::new(pid))]),
             fd_table: spin::RwLock::new(FdTable::default()),
             start_time: 0, // Would be set to current time
             cpu_time: AtomicU64::new(0),
+            sandbox: spin::RwLock::new(None),
+            user_memory: spin::RwLock::new(None),
+            address_space: spin::RwLock::new(None),
+            owner: spin::RwLock::new(None),
+            futexes: FutexTable::default(),
         }
     }
 
@@ -281,6 +492,16 @@ impl Process {
         &self.exe_path
     }
 
+    /// Command line arguments
+    pub fn args(&self) -> Vec<String> {
+        self.args.read().clone()
+    }
+
+    /// Environment variables
+    pub fn env(&self) -> Vec<String> {
+        self.env.read().clone()
+    }
+
     /// Get current working directory
     pub fn cwd(&self) -> String {
         self.cwd.read().clone()
@@ -311,25 +532,68 @@ impl Process {
         self.egid.load(Ordering::SeqCst)
     }
 
+    /// Get the sandbox policy, `None` if unconfined
+    pub fn sandbox(&self) -> Option<Arc<SandboxPolicy>> {
+        self.sandbox.read().clone()
+    }
+
+    /// Confine the process to `policy`
+    ///
+    /// Like Landlock, a process can't leave its sandbox: once a policy is set
+    /// it is kept, and later calls are ignored.
+    pub fn set_sandbox(&self, policy: Arc<SandboxPolicy>) {
+        self.sandbox.write().get_or_insert(policy);
+    }
+
+    /// Memory of the process, `EFAULT` until it is attached
+    pub fn user_memory(&self) -> Result<Arc<dyn UserMemory>, LinuxErrno> {
+        self.user_memory.read().clone().ok_or(LinuxErrno::EFAULT)
+    }
+
+    /// Attach the memory syscall arguments point into
+    pub fn set_user_memory(&self, memory: Arc<dyn UserMemory>) {
+        *self.user_memory.write() = Some(memory);
+    }
+
+    /// Attach the address space `mmap` and `brk` change
+    pub fn set_address_space(&self, space: Arc<dyn AddressSpace>) {
+        *self.address_space.write() = Some(space);
+    }
+
+    /// Address space of the process, `ENOMEM` until it is attached
+    fn address_space(&self) -> Result<Arc<dyn AddressSpace>, LinuxErrno> {
+        self.address_space.read().clone().ok_or(LinuxErrno::ENOMEM)
+    }
+
+    /// Redox process running the program, `None` until it is attached
+    pub fn owner(&self) -> Option<Owner> {
+        *self.owner.read()
+    }
+
+    /// Attach the Redox process running the program
+    ///
+    /// The process takes on its user and group IDs. Like the sandbox, the
+    /// owner is kept once set, so a process can't be handed to another.
+    pub fn attach(&self, owner: Owner) {
+        let mut current = self.owner.write();
+        if current.is_some() {
+            return;
+        }
+        *current = Some(owner);
+        self.uid.store(owner.uid, Ordering::SeqCst);
+        self.euid.store(owner.uid, Ordering::SeqCst);
+        self.gid.store(owner.gid, Ordering::SeqCst);
+        self.egid.store(owner.gid, Ordering::SeqCst);
+    }
+
     /// Set up memory from ELF
     pub fn setup_memory(&self, elf: &LoadedElf) -> Result<(), LinuxErrno> {
         let mut memory = self.memory.write();
 
         // Map program segments
-        for phdr in &elf.program_headers {
-            if phdr.p_type == crate::elf_loader::pt_type::PT_LOAD {
-                let prot = elf_flags_to_prot(phdr.p_flags);
-
-                memory.regions.push(MemoryRegion {
-                    start: phdr.p_vaddr,
-                    end: phdr.p_vaddr + phdr.p_memsz,
-                    prot,
-                    flags: map_flags::MAP_PRIVATE,
-                    offset: phdr.p_offset,
-                    path: Some(self.exe_path.clone()),
-                });
-            }
-        }
+        memory
+            .regions
+            .extend(segment_regions(elf, elf.load_bias(), &self.exe_path));
 
         // Set up brk after the last segment
         let end_addr = memory.regions.iter().map(|r| r.end).max().unwrap_or(0);
@@ -341,8 +605,80 @@ impl Process {
         Ok(())
     }
 
-    /// Set up stack with arguments and environment
-    pub fn setup_stack(&self, args: &[String], env: &[String]) -> Result<(), LinuxErrno> {
+    /// Map the shared libraries of a dynamically linked executable
+    ///
+    /// Called after [`Process::setup_memory`], so that the program break
+    /// stays after the executable.
+    pub fn map_libraries(&self, link_map: LinkMap) {
+        let mut memory = self.memory.write();
+
+        for library in &link_map.libraries {
+            let path = library.host_path.to_string_lossy();
+            memory
+                .regions
+                .extend(segment_regions(&library.elf, library.bias, &path));
+        }
+        let runtime = link_map.runtime;
+        memory.regions.push(MemoryRegion {
+            start: runtime.code,
+            end: runtime.code + runtime.code_size,
+            prot: prot_flags::PROT_READ | prot_flags::PROT_EXEC,
+            flags: map_flags::MAP_PRIVATE | map_flags::MAP_ANONYMOUS,
+            offset: 0,
+            path: None,
+        });
+        memory.regions.push(MemoryRegion {
+            start: runtime.data,
+            end: runtime.data + runtime.data_size,
+            prot: prot_flags::PROT_READ | prot_flags::PROT_WRITE,
+            flags: map_flags::MAP_PRIVATE | map_flags::MAP_ANONYMOUS,
+            offset: 0,
+            path: None,
+        });
+        memory.relocations = link_map.patches;
+        memory.runtime = Some(runtime);
+    }
+
+    /// Relocations to apply over the mapped segments before starting
+    pub fn relocations(&self) -> Vec<Patch> {
+        self.memory.read().relocations.clone()
+    }
+
+    /// Map the vDSO: the shared data page followed by the image
+    pub fn map_vdso(&self) {
+        let mut memory = self.memory.write();
+        let image_base = vdso::VDSO_BASE + vdso::PAGE_SIZE;
+
+        memory.regions.push(MemoryRegion {
+            start: vdso::VDSO_BASE,
+            end: image_base,
+            prot: prot_flags::PROT_READ,
+            flags: map_flags::MAP_SHARED,
+            offset: 0,
+            path: Some(vdso::VVAR_PATH.to_string()),
+        });
+        memory.regions.push(MemoryRegion {
+            start: image_base,
+            end: vdso::VDSO_BASE + vdso::VDSO_SIZE,
+            prot: prot_flags::PROT_READ | prot_flags::PROT_EXEC,
+            flags: map_flags::MAP_PRIVATE,
+            offset: 0,
+            path: Some(vdso::IMAGE_PATH.to_string()),
+        });
+        memory.vdso_base = Some(image_base);
+    }
+
+    /// Set up stack with arguments, environment and auxiliary vector
+    ///
+    /// Lays out the top of the stack like Linux: the strings and random
+    /// bytes at the top, below them the auxiliary vector, `envp`, `argv` and
+    /// `argc`, which the stack pointer points to.
+    pub fn setup_stack(
+        &self,
+        elf: &LoadedElf,
+        args: &[String],
+        env: &[String],
+    ) -> Result<(), LinuxErrno> {
         *self.args.write() = args.to_vec();
         *self.env.write() = env.to_vec();
 
@@ -365,32 +701,127 @@ impl Process {
             path: Some("[stack]".to_string()),
         });
 
+        // Strings and random bytes, ending 8 bytes below the top
+        let mut info = random_bytes().to_vec();
+        let push_str = |info: &mut Vec<u8>, s: &str| {
+            let offset = info.len() as u64;
+            info.extend_from_slice(s.as_bytes());
+            info.push(0);
+            offset
+        };
+        let platform = push_str(&mut info, elf_loader::PLATFORM);
+        let args_at: Vec<u64> = args.iter().map(|arg| push_str(&mut info, arg)).collect();
+        let env_at: Vec<u64> = env.iter().map(|var| push_str(&mut info, var)).collect();
+        let execfn = push_str(&mut info, &self.exe_path);
+        let info_start = stack_top - 8 - info.len() as u64;
+
+        let auxv = elf_loader::build_auxv(
+            elf,
+            &AuxvInfo {
+                interp_base: 0,
+                random_addr: info_start,
+                platform_addr: info_start + platform,
+                execfn_addr: info_start + execfn,
+                vdso_base: memory.vdso_base,
+                uid: self.uid(),
+                euid: self.euid(),
+                gid: self.gid(),
+                egid: self.egid(),
+            },
+        );
+
+        let mut words = vec![args.len() as u64];
+        words.extend(args_at.iter().map(|offset| info_start + offset));
+        words.push(0);
+        words.extend(env_at.iter().map(|offset| info_start + offset));
+        words.push(0);
+        words.extend(auxv.iter().flat_map(|&(key, value)| [key, value]));
+
+        let sp = (info_start - words.len() as u64 * 8) & !0xf;
+        if sp < stack_bottom {
+            return Err(LinuxErrno::E2BIG);
+        }
+        let mut image: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        image.resize((info_start - sp) as usize, 0);
+        image.extend_from_slice(&info);
+        image.extend_from_slice(&[0; 8]);
+        memory.initial_stack = image;
+        if let Some(stack_end) = memory.runtime.as_ref().map(|runtime| runtime.stack_end) {
+            memory.relocations.push(Patch {
+                addr: stack_end,
+                data: sp.to_le_bytes().to_vec(),
+            });
+        }
+
         Ok(())
     }
 
+    /// Initial contents of the top of the stack, ending at the stack top
+    pub fn initial_stack(&self) -> Vec<u8> {
+        self.memory.read().initial_stack.clone()
+    }
+
     /// Start process execution
+    ///
+    /// Dynamically linked executables may start in the runtime set up by
+    /// the dynamic linker instead of at `entry_point`, and their initial
+    /// thread gets the static TLS.
     pub fn start(&self, entry_point: u64) -> Result<(), LinuxErrno> {
         self.set_state(ProcessState::Ready);
 
-        // Create main thread
-        let main_thread = Arc::new(Thread::new(self.pid));
-
-        // Set up initial registers
+        // Set up initial registers of the main thread
         {
+            let main_thread = self.thread(self.pid)?;
             let mut regs = main_thread.registers.write();
+            let memory = self.memory.read();
             regs.rip = entry_point;
+            if let Some(runtime) = &memory.runtime {
+                regs.rip = runtime.entry.unwrap_or(entry_point);
+                regs.fs_base = runtime.thread_pointer;
+                main_thread.set_tls_ptr(runtime.thread_pointer);
+            }
 
-            let memory = self.memory.read();
-            regs.rsp = memory.stack_top - 8; // Leave space for return address
+            // argc is on top of the stack
+            regs.rsp = memory.stack_top - memory.initial_stack.len() as u64;
         }
 
-        self.threads.write().push(main_thread);
-
         self.set_state(ProcessState::Running);
 
         Ok(())
     }
 
+    /// Thread `tid` of the process
+    pub fn thread(&self, tid: u32) -> Result<Arc<Thread>, LinuxErrno> {
+        self.threads
+            .read()
+            .iter()
+            .find(|thread| thread.tid() == tid)
+            .cloned()
+            .ok_or(LinuxErrno::ESRCH)
+    }
+
+    /// Number of threads that haven't exited
+    pub fn thread_count(&self) -> usize {
+        self.threads.read().len()
+    }
+
+    /// Add a thread created by `clone`
+    pub fn add_thread(&self, thread: Arc<Thread>) {
+        self.threads.write().push(thread);
+    }
+
+    /// Remove an exited thread
+    pub fn remove_thread(&self, tid: u32) -> Option<Arc<Thread>> {
+        let mut threads = self.threads.write();
+        let index = threads.iter().position(|thread| thread.tid() == tid)?;
+        Some(threads.remove(index))
+    }
+
+    /// Threads waiting on futexes
+    pub fn futexes(&self) -> &FutexTable {
+        &self.futexes
+    }
+
     /// Exit the process
     pub fn exit(&self, status: i32) {
         *self.exit_status.write() = Some(status);
@@ -407,73 +838,244 @@ impl Process {
         self.memory.write().regions.push(region);
     }
 
+    /// Mapped memory regions and the heap, by address
+    pub fn memory_regions(&self) -> Vec<MemoryRegion> {
+        let memory = self.memory.read();
+        let mut regions = memory.regions.clone();
+        if memory.brk > memory.start_brk {
+            regions.push(MemoryRegion {
+                start: memory.start_brk,
+                end: memory.brk,
+                prot: prot_flags::PROT_READ | prot_flags::PROT_WRITE,
+                flags: map_flags::MAP_PRIVATE,
+                offset: 0,
+                path: Some("[heap]".to_string()),
+            });
+        }
+        regions.sort_by_key(|region| region.start);
+        regions
+    }
+
     /// Set program break
+    ///
+    /// The heap's pages are mapped and unmapped as it grows and shrinks. Like
+    /// Linux, the break stays where it was if it can't be moved.
     pub fn set_brk(&self, new_brk: u64) -> u64 {
         let mut memory = self.memory.write();
-
-        if new_brk >= memory.start_brk {
-            memory.brk = new_brk;
+        if memory.start_brk == 0 || new_brk < memory.start_brk {
+            return memory.brk;
         }
 
+        let old_end = memory.brk.next_multiple_of(PAGE_SIZE);
+        let new_end = new_brk.next_multiple_of(PAGE_SIZE);
+        let moved = match self.address_space() {
+            Ok(_) if new_end == old_end => Ok(()),
+            Ok(_) if memory.is_busy(old_end, new_end) => Err(LinuxErrno::ENOMEM),
+            Ok(space) if new_end > old_end => space.map(
+                old_end,
+                new_end - old_end,
+                prot_flags::PROT_READ | prot_flags::PROT_WRITE,
+            ),
+            Ok(space) => space.unmap(new_end, old_end - new_end),
+            Err(errno) => Err(errno),
+        };
+        match moved {
+            Ok(()) => memory.brk = new_brk,
+            Err(errno) => log::debug!("brk to {:#x} failed: {:?}", new_brk, errno),
+        }
         memory.brk
     }
 
+    /// Map `region`, returning its address
+    ///
+    /// The memory is zeroed: file contents are for the caller to copy in.
+    /// With `MAP_FIXED` the region replaces what was mapped at its address,
+    /// otherwise its address is a hint and it goes wherever there is room.
+    pub fn map(&self, mut region: MemoryRegion) -> Result<u64, LinuxErrno> {
+        let len = region.end - region.start;
+        let mut memory = self.memory.write();
+        let space = self.address_space()?;
+
+        let fixed = region.flags & (map_flags::MAP_FIXED | map_flags::MAP_FIXED_NOREPLACE) != 0;
+        if fixed {
+            if region.flags & map_flags::MAP_FIXED_NOREPLACE != 0
+                && memory.is_busy(region.start, region.end)
+            {
+                return Err(LinuxErrno::EEXIST);
+            }
+        } else if region.start < MMAP_MIN_ADDR || memory.is_busy(region.start, region.end) {
+            region.start = memory.find_free(len).ok_or(LinuxErrno::ENOMEM)?;
+            region.end = region.start + len;
+        }
+
+        space.map(region.start, len, region.prot)?;
+        memory.remove(region.start, region.end);
+        let start = region.start;
+        memory.regions.push(region);
+        Ok(start)
+    }
+
+    /// Unmap `len` bytes at `addr`
+    pub fn unmap(&self, addr: u64, len: u64) -> Result<(), LinuxErrno> {
+        let mut memory = self.memory.write();
+        self.address_space()?.unmap(addr, len)?;
+        memory.remove(addr, addr + len);
+        Ok(())
+    }
+
+    /// Change the protection of `len` bytes at `addr`, which all have to be
+    /// mapped
+    pub fn protect(&self, addr: u64, len: u64, prot: u32) -> Result<(), LinuxErrno> {
+        let mut memory = self.memory.write();
+        let end = addr + len;
+        if !memory.is_mapped(addr, end) {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        self.address_space()?.protect(addr, len, prot)?;
+
+        let regions = memory.split(addr, end);
+        memory.regions = regions
+            .into_iter()
+            .map(|mut region| {
+                if region.start >= addr && region.end <= end {
+                    region.prot = prot;
+                }
+                region
+            })
+            .collect();
+        Ok(())
+    }
+
     /// Get program break
     pub fn brk(&self) -> u64 {
         self.memory.read().brk
     }
 
-    /// Allocate a file descriptor
-    pub fn alloc_fd(&self, path: String, flags: i32) -> i32 {
+    /// Give the process `lacd`'s standard input, output and error as
+    /// descriptors 0, 1 and 2
+    pub fn inherit_stdio(&self) -> Result<(), LinuxErrno> {
+        let streams = [
+            (
+                "/dev/stdin",
+                open_flags::O_RDONLY,
+                std::io::stdin().as_fd().try_clone_to_owned()?,
+            ),
+            (
+                "/dev/stdout",
+                open_flags::O_WRONLY,
+                std::io::stdout().as_fd().try_clone_to_owned()?,
+            ),
+            (
+                "/dev/stderr",
+                open_flags::O_WRONLY,
+                std::io::stderr().as_fd().try_clone_to_owned()?,
+            ),
+        ];
+        let mut fd_table = self.fd_table.write();
+        for (fd, (path, flags, stream)) in streams.into_iter().enumerate() {
+            let file = OpenFile {
+                object: FileObject::Host(File::from(stream)),
+                path: path.to_string(),
+                flags,
+            };
+            fd_table.files.insert(
+                fd as i32,
+                FileDescriptor {
+                    file: Arc::new(file),
+                    cloexec: false,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Allocate the lowest free file descriptor for `file`
+    pub fn alloc_fd(&self, file: OpenFile, cloexec: bool) -> Result<i32, LinuxErrno> {
         let mut fd_table = self.fd_table.write();
-        let fd = fd_table.next_fd;
-        fd_table.next_fd += 1;
+        let fd = fd_table.lowest_free()?;
 
         fd_table.files.insert(
             fd,
             FileDescriptor {
-                path,
-                flags,
-                offset: 0,
+                file: Arc::new(file),
+                cloexec,
             },
         );
 
-        fd
+        Ok(fd)
+    }
+
+    /// Get the open file behind a file descriptor
+    pub fn get_fd(&self, fd: i32) -> Result<Arc<OpenFile>, LinuxErrno> {
+        self.fd_table
+            .read()
+            .files
+            .get(&fd)
+            .map(|desc| desc.file.clone())
+            .ok_or(LinuxErrno::EBADF)
+    }
+
+    /// Open file descriptors, in order, with their files
+    pub fn fds(&self) -> Vec<(i32, Arc<OpenFile>)> {
+        self.fd_table
+            .read()
+            .files
+            .iter()
+            .map(|(&fd, desc)| (fd, desc.file.clone()))
+            .collect()
     }
 
     /// Close a file descriptor
     pub fn close_fd(&self, fd: i32) -> Result<(), LinuxErrno> {
-        let mut fd_table = self.fd_table.write();
-
-        if fd_table.files.remove(&fd).is_some() {
-            fd_table.cloexec.remove(&fd);
-            Ok(())
-        } else {
-            Err(LinuxErrno::EBADF)
-        }
+        // The Redox handle is closed with the last descriptor sharing it
+        self.fd_table
+            .write()
+            .files
+            .remove(&fd)
+            .map(drop)
+            .ok_or(LinuxErrno::EBADF)
     }
 
-    /// Duplicate a file descriptor
+    /// Duplicate a file descriptor to the lowest free one
     pub fn dup_fd(&self, oldfd: i32) -> Result<i32, LinuxErrno> {
         let mut fd_table = self.fd_table.write();
+        let file = fd_table
+            .files
+            .get(&oldfd)
+            .ok_or(LinuxErrno::EBADF)?
+            .file
+            .clone();
+        let newfd = fd_table.lowest_free()?;
 
-        if let Some(old_file) = fd_table.files.get(&oldfd) {
-            let newfd = fd_table.next_fd;
-            fd_table.next_fd += 1;
+        fd_table.files.insert(
+            newfd,
+            FileDescriptor {
+                file,
+                cloexec: false,
+            },
+        );
 
-            fd_table.files.insert(
-                newfd,
-                FileDescriptor {
-                    path: old_file.path.clone(),
-                    flags: old_file.flags,
-                    offset: old_file.offset,
-                },
-            );
+        Ok(newfd)
+    }
 
-            Ok(newfd)
-        } else {
-            Err(LinuxErrno::EBADF)
+    /// Duplicate a file descriptor to `newfd`, closing what `newfd` was
+    pub fn dup_fd_to(&self, oldfd: i32, newfd: i32, cloexec: bool) -> Result<i32, LinuxErrno> {
+        if !(0..MAX_FDS).contains(&newfd) {
+            return Err(LinuxErrno::EBADF);
         }
+        let mut fd_table = self.fd_table.write();
+        let file = fd_table
+            .files
+            .get(&oldfd)
+            .ok_or(LinuxErrno::EBADF)?
+            .file
+            .clone();
+
+        fd_table
+            .files
+            .insert(newfd, FileDescriptor { file, cloexec });
+
+        Ok(newfd)
     }
 }
 
@@ -486,6 +1088,7 @@ impl Thread {
             tls_ptr: AtomicU64::new(0),
             clear_child_tid: AtomicU64::new(0),
             registers: spin::RwLock::new(RegisterState::default()),
+            pending: spin::Mutex::new(None),
         }
     }
 
@@ -514,6 +1117,16 @@ impl Thread {
         self.tls_ptr.store(ptr, Ordering::SeqCst);
     }
 
+    /// Address cleared and woken on thread exit, 0 for none
+    pub fn clear_child_tid(&self) -> u64 {
+        self.clear_child_tid.load(Ordering::SeqCst)
+    }
+
+    /// Set the address cleared and woken on thread exit
+    pub fn set_clear_child_tid(&self, addr: u64) {
+        self.clear_child_tid.store(addr, Ordering::SeqCst);
+    }
+
     /// Get register state
     pub fn registers(&self) -> RegisterState {
         self.registers.read().clone()
@@ -523,6 +1136,48 @@ impl Thread {
     pub fn set_registers(&self, regs: RegisterState) {
         *self.registers.write() = regs;
     }
+
+    /// Syscall the thread waits in for file descriptors
+    pub fn pending(&self) -> Option<PendingCall> {
+        self.pending.lock().clone()
+    }
+
+    /// Record the syscall the thread waits in, or that it no longer waits
+    pub fn set_pending(&self, call: Option<PendingCall>) {
+        *self.pending.lock() = call;
+    }
+}
+
+/// Bytes for `AT_RANDOM`, seeded from the OS by the standard library
+fn random_bytes() -> [u8; 16] {
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+
+    let mut bytes = [0; 16];
+    for chunk in bytes.chunks_exact_mut(8) {
+        chunk.copy_from_slice(&RandomState::new().hash_one(0u8).to_le_bytes());
+    }
+    bytes
+}
+
+/// Regions of the loadable segments of `elf`, loaded `bias` above their
+/// linked addresses from the file at `path`
+fn segment_regions<'a>(
+    elf: &'a LoadedElf,
+    bias: u64,
+    path: &'a str,
+) -> impl Iterator<Item = MemoryRegion> + 'a {
+    elf.program_headers
+        .iter()
+        .filter(|phdr| phdr.p_type == crate::elf_loader::pt_type::PT_LOAD)
+        .map(move |phdr| MemoryRegion {
+            start: bias + phdr.p_vaddr,
+            end: bias + phdr.p_vaddr + phdr.p_memsz,
+            prot: elf_flags_to_prot(phdr.p_flags),
+            flags: map_flags::MAP_PRIVATE,
+            offset: phdr.p_offset,
+            path: Some(path.to_string()),
+        })
 }
 
 /// Convert ELF flags to protection flags
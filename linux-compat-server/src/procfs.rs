@@ -0,0 +1,673 @@
+//! /proc and /sys
+//!
+//! Linux programs read these at startup, for their own executable and
+//! mappings, the number of CPUs or the size of memory. Redox' `proc:` and
+//! `sys:` schemes have neither Linux' layout nor its formats, so `lacd`
+//! generates the files itself: those of a process from what it keeps about
+//! the process, the others from the CPU and from Redox. A file is generated
+//! when it is opened, and reads go through that snapshot.
+//!
+//! A process only sees itself: `/proc` holds `self` and the directory of
+//! its own PID.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::io::SeekFrom;
+
+use crate::epoll::poll_events;
+use crate::errno::LinuxErrno;
+use crate::process::{map_flags, prot_flags, Process, ProcessState};
+use crate::sandbox;
+use crate::vdso;
+
+/// Linux file types, as in `st_mode`
+pub mod file_type {
+    pub const S_IFDIR: u32 = 0o040000;
+    pub const S_IFREG: u32 = 0o100000;
+    pub const S_IFLNK: u32 = 0o120000;
+}
+
+use file_type::*;
+
+/// Most links followed by a lookup, as on Linux
+const MAX_LINKS: usize = 40;
+/// Longest process name, `TASK_COMM_LEN` less the NUL
+const COMM_LEN: usize = 15;
+/// Column paths start at in `/proc/<pid>/maps`
+const MAPS_PATH_COLUMN: usize = 73;
+/// Size of a `struct linux_dirent64` before the name
+const DIRENT_HEADER_SIZE: usize = 19;
+/// Files of `/sys/devices/system/cpu` listing CPUs, which are all present
+/// and online
+const CPU_LISTS: [&str; 3] = ["online", "possible", "present"];
+
+/// Entry of a directory
+pub struct DirEntry {
+    name: String,
+    ino: u64,
+    /// File type
+    kind: u32,
+}
+
+/// File, directory or link of /proc or /sys
+pub enum Node {
+    /// Regular file, with its contents
+    File(Vec<u8>),
+    /// Directory, with its entries
+    Dir(Vec<DirEntry>),
+    /// Symbolic link, with its target
+    Link(String),
+}
+
+impl Node {
+    /// Linux file mode
+    pub fn mode(&self) -> u32 {
+        match self {
+            Node::File(_) => S_IFREG | 0o444,
+            Node::Dir(_) => S_IFDIR | 0o555,
+            Node::Link(_) => S_IFLNK | 0o777,
+        }
+    }
+
+    /// Size `stat` reports: generated files claim to be empty, as on Linux
+    pub fn size(&self) -> u64 {
+        match self {
+            Node::Link(target) => target.len() as u64,
+            _ => 0,
+        }
+    }
+}
+
+/// What a path below /proc or /sys leads to
+pub enum Entry {
+    /// Node, with the path it was found at once links are followed
+    Node(String, Node),
+    /// Linux path outside /proc and /sys a link leads to
+    Path(String),
+}
+
+/// Whether `path`, absolute and normalized, is below /proc or /sys
+pub fn is_virtual(path: &str) -> bool {
+    sandbox::is_below(path, "/proc") || sandbox::is_below(path, "/sys")
+}
+
+/// Inode number of a node, stable for its path
+pub fn inode(path: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Look up `path` for `process`, following a link it ends with if `follow`
+/// is set
+pub fn lookup(process: &Process, path: &str, follow: bool) -> Result<Entry, LinuxErrno> {
+    let mut path = path.to_string();
+    for _ in 0..=MAX_LINKS {
+        match walk(process, &path, follow)? {
+            Entry::Path(next) if is_virtual(&next) => path = next,
+            entry => return Ok(entry),
+        }
+    }
+    Err(LinuxErrno::ELOOP)
+}
+
+/// Walk `path` up to the node it leads to, or to the first link to follow,
+/// returning the path with that link replaced by its target
+fn walk(process: &Process, path: &str, follow: bool) -> Result<Entry, LinuxErrno> {
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    for depth in 1..=components.len() {
+        let last = depth == components.len();
+        match node(process, &components[..depth])? {
+            Node::Link(target) if !last || follow => {
+                let target = if target.starts_with('/') {
+                    target
+                } else {
+                    format!("/{}/{}", components[..depth - 1].join("/"), target)
+                };
+                let rest = components[depth..].join("/");
+                return Ok(Entry::Path(sandbox::normalize(&format!(
+                    "{}/{}",
+                    target, rest
+                ))));
+            }
+            node if last => return Ok(Entry::Node(path.to_string(), node)),
+            Node::Dir(_) => {}
+            _ => return Err(LinuxErrno::ENOTDIR),
+        }
+    }
+    Err(LinuxErrno::ENOENT)
+}
+
+/// Node at `components`, without following links
+fn node(process: &Process, components: &[&str]) -> Result<Node, LinuxErrno> {
+    let path = format!("/{}", components.join("/"));
+    let pid = process.pid().to_string();
+    match components {
+        ["proc"] => Ok(dir(
+            &path,
+            [
+                ("cpuinfo", S_IFREG),
+                ("meminfo", S_IFREG),
+                ("self", S_IFLNK),
+                (pid.as_str(), S_IFDIR),
+            ],
+        )),
+        ["proc", "cpuinfo"] => Ok(Node::File(cpuinfo().into_bytes())),
+        ["proc", "meminfo"] => Ok(Node::File(meminfo().into_bytes())),
+        ["proc", "self"] => Ok(Node::Link(pid)),
+        ["proc", id, rest @ ..] if *id == pid => process_node(process, &path, rest),
+        ["sys"] => Ok(dir(&path, [("devices", S_IFDIR)])),
+        ["sys", "devices"] => Ok(dir(&path, [("system", S_IFDIR)])),
+        ["sys", "devices", "system"] => Ok(dir(&path, [("cpu", S_IFDIR)])),
+        ["sys", "devices", "system", "cpu"] => {
+            Ok(dir(&path, CPU_LISTS.map(|name| (name, S_IFREG))))
+        }
+        ["sys", "devices", "system", "cpu", list] if CPU_LISTS.contains(list) => {
+            Ok(Node::File(cpu_list().into_bytes()))
+        }
+        _ => Err(LinuxErrno::ENOENT),
+    }
+}
+
+/// Node at `components` in the directory of `process`, at `path`
+fn process_node(process: &Process, path: &str, components: &[&str]) -> Result<Node, LinuxErrno> {
+    match components {
+        [] => Ok(dir(
+            path,
+            [
+                ("cmdline", S_IFREG),
+                ("comm", S_IFREG),
+                ("cwd", S_IFLNK),
+                ("environ", S_IFREG),
+                ("exe", S_IFLNK),
+                ("fd", S_IFDIR),
+                ("maps", S_IFREG),
+                ("status", S_IFREG),
+            ],
+        )),
+        ["cmdline"] => Ok(Node::File(nul_terminated(&process.args()))),
+        ["comm"] => Ok(Node::File(format!("{}\n", comm(process)).into_bytes())),
+        ["cwd"] => Ok(Node::Link(process.cwd())),
+        ["environ"] => Ok(Node::File(nul_terminated(&process.env()))),
+        ["exe"] => Ok(Node::Link(process.exe_path().to_string())),
+        ["fd"] => {
+            let fds = process.fds();
+            Ok(dir(
+                path,
+                fds.iter().map(|(fd, _)| (fd.to_string(), S_IFLNK)),
+            ))
+        }
+        ["fd", fd] => {
+            let file = fd
+                .parse::<i32>()
+                .ok()
+                .filter(|parsed| parsed.to_string() == *fd)
+                .and_then(|fd| process.get_fd(fd).ok())
+                .ok_or(LinuxErrno::ENOENT)?;
+            Ok(Node::Link(file.path.clone()))
+        }
+        ["maps"] => Ok(Node::File(maps(process).into_bytes())),
+        ["status"] => Ok(Node::File(status(process).into_bytes())),
+        _ => Err(LinuxErrno::ENOENT),
+    }
+}
+
+/// Directory at `path` holding `entries`, with `.` and `..`
+fn dir<S: AsRef<str>>(path: &str, entries: impl IntoIterator<Item = (S, u32)>) -> Node {
+    let parent = path.rsplit_once('/').map_or("/", |(parent, _)| parent);
+    let mut list = vec![
+        DirEntry {
+            name: ".".to_string(),
+            ino: inode(path),
+            kind: S_IFDIR,
+        },
+        DirEntry {
+            name: "..".to_string(),
+            ino: inode(if parent.is_empty() { "/" } else { parent }),
+            kind: S_IFDIR,
+        },
+    ];
+    list.extend(entries.into_iter().map(|(name, kind)| DirEntry {
+        name: name.as_ref().to_string(),
+        ino: inode(&format!("{}/{}", path, name.as_ref())),
+        kind,
+    }));
+    Node::Dir(list)
+}
+
+/// Strings each followed by a NUL, like `cmdline` and `environ`
+fn nul_terminated(strings: &[String]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for string in strings {
+        bytes.extend_from_slice(string.as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+/// Name of a process: the file name of its executable, truncated like
+/// Linux does
+fn comm(process: &Process) -> String {
+    let name = process.exe_path().rsplit('/').next().unwrap_or_default();
+    let len = (0..=COMM_LEN.min(name.len()))
+        .rev()
+        .find(|&len| name.is_char_boundary(len))
+        .unwrap_or(0);
+    name[..len].to_string()
+}
+
+fn status(process: &Process) -> String {
+    let state = match process.state() {
+        ProcessState::Blocked => "S (sleeping)",
+        ProcessState::Stopped => "T (stopped)",
+        ProcessState::Zombie => "Z (zombie)",
+        ProcessState::Dead => "X (dead)",
+        _ => "R (running)",
+    };
+    // Size of the descriptor table, which Linux grows in powers of two
+    let fd_size = process
+        .fds()
+        .last()
+        .map_or(0, |&(fd, _)| fd as usize + 1)
+        .next_power_of_two()
+        .max(64);
+    let vm_size: u64 = process
+        .memory_regions()
+        .iter()
+        .map(|region| region.end - region.start)
+        .sum();
+    let (uid, euid) = (process.uid(), process.euid());
+    let (gid, egid) = (process.gid(), process.egid());
+
+    let mut status = String::new();
+    let _ = writeln!(status, "Name:\t{}", comm(process));
+    let _ = writeln!(status, "State:\t{}", state);
+    let _ = writeln!(status, "Tgid:\t{}", process.tgid());
+    let _ = writeln!(status, "Pid:\t{}", process.pid());
+    let _ = writeln!(status, "PPid:\t{}", process.ppid());
+    let _ = writeln!(status, "TracerPid:\t0");
+    let _ = writeln!(status, "Uid:\t{}\t{}\t{}\t{}", uid, euid, euid, euid);
+    let _ = writeln!(status, "Gid:\t{}\t{}\t{}\t{}", gid, egid, egid, egid);
+    let _ = writeln!(status, "FDSize:\t{}", fd_size);
+    let _ = writeln!(status, "VmSize:\t{:>8} kB", vm_size / 1024);
+    let _ = writeln!(status, "Threads:\t{}", process.thread_count());
+    status
+}
+
+fn maps(process: &Process) -> String {
+    let mut maps = String::new();
+    for region in process.memory_regions() {
+        let perm = |prot, c| if region.prot & prot != 0 { c } else { '-' };
+        let line = format!(
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+            region.start,
+            region.end,
+            perm(prot_flags::PROT_READ, 'r'),
+            perm(prot_flags::PROT_WRITE, 'w'),
+            perm(prot_flags::PROT_EXEC, 'x'),
+            if region.flags & map_flags::MAP_SHARED != 0 {
+                's'
+            } else {
+                'p'
+            },
+            region.offset,
+        );
+        // The vDSO's pages have the names Linux gives them
+        let path = match region.path.as_deref() {
+            Some(vdso::VVAR_PATH) => Some("[vvar]"),
+            Some(vdso::IMAGE_PATH) => Some("[vdso]"),
+            path => path,
+        };
+        let _ = match path {
+            Some(path) => writeln!(maps, "{:<width$}{}", line, path, width = MAPS_PATH_COLUMN),
+            None => writeln!(maps, "{}", line),
+        };
+    }
+    maps
+}
+
+/// Number of online CPUs
+fn cpu_count() -> usize {
+    // SAFETY: sysconf has no preconditions
+    unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as usize
+}
+
+/// CPU list of the `/sys/devices/system/cpu` files
+fn cpu_list() -> String {
+    match cpu_count() {
+        1 => "0\n".to_string(),
+        count => format!("0-{}\n", count - 1),
+    }
+}
+
+fn cpuinfo() -> String {
+    let identity = cpu_identity();
+    let mut cpuinfo = String::new();
+    for cpu in 0..cpu_count() {
+        let _ = writeln!(cpuinfo, "processor\t: {}", cpu);
+        cpuinfo.push_str(&identity);
+        cpuinfo.push('\n');
+    }
+    cpuinfo
+}
+
+/// Register of a CPUID leaf
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Copy)]
+enum Register {
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+/// Feature bits of a CPUID register, with the names `/proc/cpuinfo` gives
+/// them
+#[cfg(target_arch = "x86_64")]
+type FeatureBits = &'static [(u32, &'static str)];
+
+/// Feature bits by leaf and register, in the order Linux lists them
+#[cfg(target_arch = "x86_64")]
+const CPU_FLAGS: &[(u32, Register, FeatureBits)] = &[
+    (
+        0x1,
+        Register::Edx,
+        &[
+            (0, "fpu"),
+            (1, "vme"),
+            (2, "de"),
+            (3, "pse"),
+            (4, "tsc"),
+            (5, "msr"),
+            (6, "pae"),
+            (7, "mce"),
+            (8, "cx8"),
+            (9, "apic"),
+            (11, "sep"),
+            (12, "mtrr"),
+            (13, "pge"),
+            (14, "mca"),
+            (15, "cmov"),
+            (16, "pat"),
+            (17, "pse36"),
+            (19, "clflush"),
+            (23, "mmx"),
+            (24, "fxsr"),
+            (25, "sse"),
+            (26, "sse2"),
+            (28, "ht"),
+        ],
+    ),
+    (
+        0x8000_0001,
+        Register::Edx,
+        &[
+            (11, "syscall"),
+            (20, "nx"),
+            (26, "pdpe1gb"),
+            (27, "rdtscp"),
+            (29, "lm"),
+        ],
+    ),
+    (
+        0x1,
+        Register::Ecx,
+        &[
+            (0, "pni"),
+            (1, "pclmulqdq"),
+            (3, "monitor"),
+            (9, "ssse3"),
+            (12, "fma"),
+            (13, "cx16"),
+            (19, "sse4_1"),
+            (20, "sse4_2"),
+            (22, "movbe"),
+            (23, "popcnt"),
+            (25, "aes"),
+            (26, "xsave"),
+            (28, "avx"),
+            (29, "f16c"),
+            (30, "rdrand"),
+            (31, "hypervisor"),
+        ],
+    ),
+    (
+        0x8000_0001,
+        Register::Ecx,
+        &[
+            (0, "lahf_lm"),
+            (5, "abm"),
+            (6, "sse4a"),
+            (8, "3dnowprefetch"),
+        ],
+    ),
+    (
+        0x7,
+        Register::Ebx,
+        &[
+            (0, "fsgsbase"),
+            (3, "bmi1"),
+            (5, "avx2"),
+            (8, "bmi2"),
+            (9, "erms"),
+            (16, "avx512f"),
+            (17, "avx512dq"),
+            (18, "rdseed"),
+            (19, "adx"),
+            (28, "avx512cd"),
+            (29, "sha_ni"),
+            (30, "avx512bw"),
+            (31, "avx512vl"),
+        ],
+    ),
+    (
+        0x7,
+        Register::Ecx,
+        &[
+            (1, "avx512vbmi"),
+            (8, "gfni"),
+            (9, "vaes"),
+            (10, "vpclmulqdq"),
+        ],
+    ),
+];
+
+/// Lines of `/proc/cpuinfo` after `processor`, the same for every CPU
+#[cfg(target_arch = "x86_64")]
+fn cpu_identity() -> String {
+    use core::arch::x86_64::__cpuid_count;
+
+    let max_leaf = __cpuid_count(0, 0).eax;
+    let max_extended = __cpuid_count(0x8000_0000, 0).eax;
+    let leaf = |leaf: u32| {
+        let supported = if leaf >= 0x8000_0000 {
+            leaf <= max_extended
+        } else {
+            leaf <= max_leaf
+        };
+        supported.then(|| __cpuid_count(leaf, 0))
+    };
+
+    let vendor_id = __cpuid_count(0, 0);
+    let vendor: Vec<u8> = [vendor_id.ebx, vendor_id.edx, vendor_id.ecx]
+        .iter()
+        .flat_map(|reg| reg.to_le_bytes())
+        .collect();
+    let signature = __cpuid_count(1, 0).eax;
+    let mut family = (signature >> 8) & 0xf;
+    let mut model = (signature >> 4) & 0xf;
+    if family == 0xf {
+        family += (signature >> 20) & 0xff;
+    }
+    if family == 0x6 || family >= 0xf {
+        model |= ((signature >> 16) & 0xf) << 4;
+    }
+    let brand: Vec<u8> = (0x8000_0002..=0x8000_0004)
+        .filter_map(leaf)
+        .flat_map(|regs| [regs.eax, regs.ebx, regs.ecx, regs.edx])
+        .flat_map(|reg| reg.to_le_bytes())
+        .collect();
+    let flags: Vec<&str> = CPU_FLAGS
+        .iter()
+        .filter_map(|&(number, register, bits)| {
+            let regs = leaf(number)?;
+            let value = match register {
+                Register::Ebx => regs.ebx,
+                Register::Ecx => regs.ecx,
+                Register::Edx => regs.edx,
+            };
+            Some(
+                bits.iter()
+                    .filter(move |&&(bit, _)| value & (1 << bit) != 0),
+            )
+        })
+        .flatten()
+        .map(|&(_, name)| name)
+        .collect();
+
+    let mut identity = String::new();
+    let _ = writeln!(
+        identity,
+        "vendor_id\t: {}",
+        String::from_utf8_lossy(&vendor)
+    );
+    let _ = writeln!(identity, "cpu family\t: {}", family);
+    let _ = writeln!(identity, "model\t\t: {}", model);
+    let _ = writeln!(
+        identity,
+        "model name\t: {}",
+        String::from_utf8_lossy(&brand).trim_matches(|c: char| c == '\0' || c == ' ')
+    );
+    let _ = writeln!(identity, "stepping\t: {}", signature & 0xf);
+    let _ = writeln!(identity, "flags\t\t: {}", flags.join(" "));
+    identity
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn cpu_identity() -> String {
+    String::new()
+}
+
+/// Total and free memory in bytes, from the `memory:` scheme like Redox'
+/// `free` reads them
+fn memory() -> (u64, u64) {
+    // SAFETY: statvfs is all integers, and the path is NUL-terminated
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c"/scheme/memory".as_ptr(), &mut stat) } != 0 {
+        return (0, 0);
+    }
+    let block = stat.f_bsize as u64;
+    (stat.f_blocks as u64 * block, stat.f_bfree as u64 * block)
+}
+
+fn meminfo() -> String {
+    let (total, free) = memory();
+    // Redox has neither a page cache nor swap to account for
+    let fields = [
+        ("MemTotal", total),
+        ("MemFree", free),
+        ("MemAvailable", free),
+        ("Buffers", 0),
+        ("Cached", 0),
+        ("SwapTotal", 0),
+        ("SwapFree", 0),
+    ];
+    let mut meminfo = String::new();
+    for (name, bytes) in fields {
+        let _ = writeln!(
+            meminfo,
+            "{:<16}{:>8} kB",
+            format!("{}:", name),
+            bytes / 1024
+        );
+    }
+    meminfo
+}
+
+/// Open file or directory of /proc or /sys
+pub struct ProcFile {
+    node: Node,
+    /// Offset in the file, or index of the next entry of a directory
+    offset: spin::Mutex<u64>,
+}
+
+impl ProcFile {
+    /// Open `node`, which isn't a link
+    pub fn new(node: Node) -> Self {
+        Self {
+            node,
+            offset: spin::Mutex::new(0),
+        }
+    }
+
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    pub fn is_dir(&self) -> bool {
+        matches!(self.node, Node::Dir(_))
+    }
+
+    /// Read up to `len` bytes at the offset
+    pub fn read(&self, len: usize) -> Result<Vec<u8>, LinuxErrno> {
+        let Node::File(contents) = &self.node else {
+            return Err(LinuxErrno::EISDIR);
+        };
+        let mut offset = self.offset.lock();
+        let start = (*offset as usize).min(contents.len());
+        let end = start + len.min(contents.len() - start);
+        *offset = end as u64;
+        Ok(contents[start..end].to_vec())
+    }
+
+    /// `struct linux_dirent64` records of the next entries fitting in `len`
+    /// bytes, failing with `EINVAL` if not even one does
+    pub fn read_dir(&self, len: usize) -> Result<Vec<u8>, LinuxErrno> {
+        let Node::Dir(entries) = &self.node else {
+            return Err(LinuxErrno::ENOTDIR);
+        };
+        let mut offset = self.offset.lock();
+        let mut records = Vec::new();
+        for entry in entries.iter().skip(*offset as usize) {
+            let start = records.len();
+            let reclen = (DIRENT_HEADER_SIZE + entry.name.len() + 1).next_multiple_of(8);
+            if start + reclen > len {
+                break;
+            }
+            *offset += 1;
+            records.extend_from_slice(&entry.ino.to_le_bytes());
+            // d_off: where the next entry is, for lseek
+            records.extend_from_slice(&offset.to_le_bytes());
+            records.extend_from_slice(&(reclen as u16).to_le_bytes());
+            records.push((entry.kind >> 12) as u8);
+            records.extend_from_slice(entry.name.as_bytes());
+            records.resize(start + reclen, 0);
+        }
+        if records.is_empty() && (*offset as usize) < entries.len() {
+            return Err(LinuxErrno::EINVAL);
+        }
+        Ok(records)
+    }
+
+    /// Move the offset, returning the new one
+    pub fn seek(&self, pos: SeekFrom) -> Result<u64, LinuxErrno> {
+        let mut offset = self.offset.lock();
+        let new = match (pos, &self.node) {
+            (SeekFrom::Start(pos), _) => Some(pos),
+            (SeekFrom::Current(delta), _) => offset.checked_add_signed(delta),
+            (SeekFrom::End(delta), Node::File(contents)) => {
+                (contents.len() as u64).checked_add_signed(delta)
+            }
+            (SeekFrom::End(_), _) => None,
+        };
+        *offset = new.ok_or(LinuxErrno::EINVAL)?;
+        Ok(*offset)
+    }
+
+    /// Linux poll events: like other files, always ready
+    pub fn readiness(&self) -> u32 {
+        use poll_events::*;
+
+        POLLIN | POLLOUT | POLLRDNORM | POLLWRNORM
+    }
+}
@@ -0,0 +1,296 @@
+//! Per-process sandbox policy
+//!
+//! Modelled after seccomp and Landlock: each Linux binary can be given an
+//! allow-list of syscalls and of path prefixes with the kind of access that
+//! is permitted below them. The translator checks a process' policy before
+//! dispatching anything to Redox, so an untrusted binary can only reach what
+//! its policy names.
+//!
+//! Policies are read from [`SANDBOX_CONFIG`]:
+//!
+//! ```text
+//! # Applies to binaries without a section of their own
+//! [default]
+//! syscalls *
+//!
+//! [/usr/bin/curl]
+//! on_violation deny          # deny | audit
+//! syscalls read write openat close mmap munmap brk socket connect
+//! path /etc/ssl r
+//! path /usr/lib rx
+//! path /tmp rw
+//! ```
+//!
+//! A binary with neither a section nor a `[default]` section runs
+//! unconfined. Within a section, `syscalls` and `path` lines accumulate. A
+//! section without any `syscalls` line allows every syscall, and one without
+//! any `path` line doesn't restrict paths.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+use bitflags::bitflags;
+
+use crate::errno::LinuxErrno;
+use crate::syscall_table::LinuxSyscall;
+
+/// Default location of the sandbox configuration
+pub const SANDBOX_CONFIG: &str = "/etc/lac/sandbox.conf";
+
+/// Highest syscall number looked up when resolving syscall names
+const MAX_SYSCALL: u64 = 512;
+
+bitflags! {
+    /// Access a path rule grants
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PathAccess: u8 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+/// What happens when a process violates its policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Violation {
+    /// Fail the syscall with `EPERM` (syscalls) or `EACCES` (paths)
+    #[default]
+    Deny,
+    /// Log the violation but let the syscall through, for writing policies
+    Audit,
+}
+
+/// Access granted below a path prefix
+#[derive(Debug, Clone)]
+pub struct PathRule {
+    pub prefix: String,
+    pub access: PathAccess,
+}
+
+/// Sandbox policy of a single binary
+#[derive(Debug, Clone, Default)]
+pub struct SandboxPolicy {
+    /// Allowed syscalls, `None` allows all of them
+    syscalls: Option<HashSet<u64>>,
+    /// Path rules, `None` doesn't restrict paths
+    paths: Option<Vec<PathRule>>,
+    on_violation: Violation,
+}
+
+impl SandboxPolicy {
+    /// Check whether the policy allows `syscall`
+    pub fn check_syscall(&self, syscall: u64) -> Result<(), LinuxErrno> {
+        let allowed = self
+            .syscalls
+            .as_ref()
+            .is_none_or(|syscalls| syscalls.contains(&syscall));
+        if allowed {
+            return Ok(());
+        }
+        self.violation(
+            format_args!("syscall {}", LinuxSyscall::from_number(syscall).name()),
+            LinuxErrno::EPERM,
+        )
+    }
+
+    /// Whether the policy restricts paths at all
+    pub fn restricts_paths(&self) -> bool {
+        self.paths.is_some()
+    }
+
+    /// Check whether the policy allows `access` to the absolute Linux path `path`
+    ///
+    /// The path is normalized lexically first, so `..` components can't be
+    /// used to escape an allowed prefix. Symlinks are left to the caller,
+    /// which has to check their targets too, see
+    /// [`SyscallTranslator::resolve_path`](crate::translator::SyscallTranslator::resolve_path).
+    pub fn check_path(&self, path: &str, access: PathAccess) -> Result<(), LinuxErrno> {
+        let Some(rules) = &self.paths else {
+            return Ok(());
+        };
+        let path = normalize(path);
+
+        // The most specific rule decides, so a read-only subtree can be carved
+        // out of a writable one.
+        let granted = rules
+            .iter()
+            .filter(|rule| is_below(&path, &rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+            .map_or(PathAccess::empty(), |rule| rule.access);
+        if granted.contains(access) {
+            return Ok(());
+        }
+        self.violation(
+            format_args!("{:?} access to {}", access, path),
+            LinuxErrno::EACCES,
+        )
+    }
+
+    fn violation(&self, what: std::fmt::Arguments, errno: LinuxErrno) -> Result<(), LinuxErrno> {
+        match self.on_violation {
+            Violation::Deny => {
+                log::warn!("Sandbox denied {}", what);
+                Err(errno)
+            }
+            Violation::Audit => {
+                log::info!("Sandbox would deny {}", what);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Sandbox policies of all configured binaries
+#[derive(Debug, Clone, Default)]
+pub struct SandboxConfig {
+    default: Option<Arc<SandboxPolicy>>,
+    binaries: HashMap<String, Arc<SandboxPolicy>>,
+}
+
+impl SandboxConfig {
+    /// Load the configuration from `path`, an absent file means no sandboxing
+    pub fn load(path: &str) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                Self::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Parse a configuration in the format described in the module documentation
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        // Section name, policy, and whether `syscalls *` was given
+        let mut current: Option<(String, SandboxPolicy, bool)> = None;
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: &str| format!("line {}: {}", line_no + 1, msg);
+
+            if let Some(section) = line.strip_prefix('[') {
+                let name = section
+                    .strip_suffix(']')
+                    .ok_or_else(|| err("unclosed section"))?;
+                if let Some((name, policy, _)) = current.take() {
+                    config.insert(name, policy);
+                }
+                current = Some((normalize(name.trim()), SandboxPolicy::default(), false));
+                continue;
+            }
+
+            let (_, policy, all_syscalls) = current
+                .as_mut()
+                .ok_or_else(|| err("rule outside of a section"))?;
+            let mut words = line.split_whitespace();
+            match words.next().unwrap() {
+                "syscalls" => {
+                    let allowed = policy.syscalls.get_or_insert_with(HashSet::new);
+                    for name in words {
+                        if name == "*" {
+                            *all_syscalls = true;
+                        } else {
+                            allowed.insert(
+                                syscall_number(name)
+                                    .ok_or_else(|| err(&format!("unknown syscall {}", name)))?,
+                            );
+                        }
+                    }
+                    if *all_syscalls {
+                        policy.syscalls = None;
+                    }
+                }
+                "path" => {
+                    let prefix = words.next().ok_or_else(|| err("missing path"))?;
+                    if !prefix.starts_with('/') {
+                        return Err(err("path must be absolute"));
+                    }
+                    let mut access = PathAccess::empty();
+                    for c in words.next().unwrap_or("").chars() {
+                        access |= match c {
+                            'r' => PathAccess::READ,
+                            'w' => PathAccess::WRITE,
+                            'x' => PathAccess::EXEC,
+                            '-' => PathAccess::empty(),
+                            _ => return Err(err(&format!("unknown access {}", c))),
+                        };
+                    }
+                    policy.paths.get_or_insert_with(Vec::new).push(PathRule {
+                        prefix: normalize(prefix),
+                        access,
+                    });
+                }
+                "on_violation" => {
+                    policy.on_violation = match words.next() {
+                        Some("deny") => Violation::Deny,
+                        Some("audit") => Violation::Audit,
+                        _ => return Err(err("expected deny or audit")),
+                    };
+                }
+                other => return Err(err(&format!("unknown directive {}", other))),
+            }
+        }
+        if let Some((name, policy, _)) = current {
+            config.insert(name, policy);
+        }
+
+        Ok(config)
+    }
+
+    fn insert(&mut self, name: String, policy: SandboxPolicy) {
+        let policy = Arc::new(policy);
+        if name == "default" {
+            self.default = Some(policy);
+        } else {
+            self.binaries.insert(name, policy);
+        }
+    }
+
+    /// Policy for the binary at `path`, `None` if it runs unconfined
+    pub fn policy_for(&self, path: &str) -> Option<Arc<SandboxPolicy>> {
+        self.binaries
+            .get(&normalize(path))
+            .or(self.default.as_ref())
+            .cloned()
+    }
+}
+
+fn syscall_number(name: &str) -> Option<u64> {
+    if let Ok(num) = name.parse() {
+        return Some(num);
+    }
+    (0..=MAX_SYSCALL).find(|&num| LinuxSyscall::from_number(num).name() == name)
+}
+
+/// Lexically normalize an absolute path, resolving `.` and `..`
+pub(crate) fn normalize(path: &str) -> String {
+    if !path.starts_with('/') {
+        return path.to_string();
+    }
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+/// Whether `path` is `prefix` or lies below it
+pub(crate) fn is_below(path: &str, prefix: &str) -> bool {
+    prefix == "/"
+        || path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
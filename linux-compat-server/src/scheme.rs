@@ -0,0 +1,326 @@
+//! `lac:` scheme
+//!
+//! Syscalls trapped in a Linux process are forwarded to `lacd` through this
+//! scheme. Each thread opens `lac:<pid>/<tid>`, `<pid>` being the pid
+//! [`LacServer::exec`] returned and `<tid>` the thread ID `clone` returned;
+//! `lac:<pid>` is the main thread. For every syscall it writes a request:
+//!
+//! | Offset | Field                                       |
+//! |--------|---------------------------------------------|
+//! | 0      | Linux syscall number                        |
+//! | 8      | Arguments 0 to 5, 8 bytes each              |
+//! | 56     | `rip` after the `syscall` instruction       |
+//! | 64     | `rsp` at the `syscall` instruction          |
+//!
+//! It then reads the result, 8 bytes holding the return value or the
+//! negated errno, as the Linux syscall would have returned it. All fields
+//! are little endian. Pointer arguments are addresses in the process that
+//! opened the handle. The registers are what a thread created by `clone`
+//! starts with, other syscalls ignore them.
+//!
+//! A syscall that has to wait, like `futex`, leaves no result: reads fail
+//! with `EAGAIN` until the thread can continue. Rather than retrying, the
+//! thread can wait for `EVENT_READ` on its handle, which is posted once the
+//! result is there. To notice, `lacd` subscribes the Redox files of processes
+//! with such waits to its event queue and sets a `time:` alarm for their
+//! timeouts, and checks the waits again after every event and request (see
+//! [`LacScheme::poll_blocked`] and [`LacScheme::watched`]).
+//!
+//! `lacd` keeps its namespace and privileges, so it must not act for
+//! anyone but the Linux process itself. Only the Redox process a Linux
+//! process was attached to, the `owner` given to [`LacServer::exec`], can
+//! open its handles and use them, and the syscalls are translated with
+//! `lacd`'s effective user and group IDs switched to the process' ones.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult};
+use syscall::schemev2::NewFdFlags;
+use syscall::{Error, EventFlags, Result, EACCES, EAGAIN, EBADF, EINVAL, ENOENT, EPERM};
+
+use crate::process::{OpenFile, Process};
+use crate::translator::{SyscallContext, SyscallResult};
+use crate::LacServer;
+
+/// Size of a syscall request
+const REQUEST_SIZE: usize = 72;
+
+struct Handle {
+    process: Arc<Process>,
+    /// Linux thread the handle makes syscalls for
+    tid: u32,
+    /// Result of the last request, until it is read
+    result: Option<i64>,
+    /// Whether the last request waits
+    blocked: bool,
+    /// Events the handle is subscribed to
+    events: EventFlags,
+}
+
+impl Handle {
+    /// Finish the syscall the thread waits in, if it can, leaving its
+    /// result
+    fn resume(&mut self, server: &LacServer) -> Result<()> {
+        if !self.blocked {
+            return Ok(());
+        }
+        let credentials = Credentials::assume(&self.process)?;
+        let result = server.translator().resume(&self.process, self.tid);
+        drop(credentials);
+        if let Some(result) = result {
+            self.blocked = false;
+            self.result = Some(result.to_raw());
+        }
+        Ok(())
+    }
+
+    /// Whether `EVENT_READ` is to be posted once the wait finishes
+    fn notifies(&self) -> bool {
+        self.blocked && self.events.contains(EventFlags::EVENT_READ)
+    }
+}
+
+/// What the waits of subscribed handles can be finished by
+#[derive(Default)]
+pub struct Watched {
+    /// Redox files of the processes
+    pub files: Vec<Arc<OpenFile>>,
+    /// Earliest timeout
+    pub wakeup: Option<Instant>,
+}
+
+/// Whether the caller is the Redox process running `process`
+fn is_owner(process: &Process, ctx: &CallerCtx) -> bool {
+    process
+        .owner()
+        .is_some_and(|owner| owner.pid == ctx.pid && owner.uid == ctx.uid)
+}
+
+/// `lacd`'s effective user and group IDs switched to those of a Linux
+/// process, until dropped
+///
+/// Files, sockets and schemes opened meanwhile check their permissions
+/// against the process rather than against `lacd`.
+struct Credentials {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+impl Credentials {
+    fn assume(process: &Process) -> Result<Self> {
+        // SAFETY: only changes the effective IDs, lacd keeps its saved ones
+        // to switch back
+        unsafe {
+            let saved = Self {
+                uid: libc::geteuid(),
+                gid: libc::getegid(),
+            };
+            if libc::setegid(process.egid() as _) < 0 {
+                return Err(Error::new(EPERM));
+            }
+            if libc::seteuid(process.euid() as _) < 0 {
+                libc::setegid(saved.gid);
+                return Err(Error::new(EPERM));
+            }
+            Ok(saved)
+        }
+    }
+}
+
+impl Drop for Credentials {
+    fn drop(&mut self) {
+        // SAFETY: see `assume`
+        unsafe {
+            libc::seteuid(self.uid);
+            libc::setegid(self.gid);
+        }
+    }
+}
+
+pub struct LacScheme {
+    server: Arc<LacServer>,
+    handles: BTreeMap<usize, Handle>,
+    next_id: usize,
+}
+
+impl LacScheme {
+    pub fn new(server: Arc<LacServer>) -> Self {
+        Self {
+            server,
+            handles: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn on_close(&mut self, id: usize) {
+        self.handles.remove(&id);
+    }
+
+    /// Finish the waits that can be, returning the handles to post
+    /// `EVENT_READ` for
+    pub fn poll_blocked(&mut self) -> Vec<usize> {
+        let mut ready = Vec::new();
+        for (&id, handle) in self.handles.iter_mut() {
+            if !handle.notifies() {
+                continue;
+            }
+            if let Err(err) = handle.resume(&self.server) {
+                log::warn!("Failed to resume thread {}: {}", handle.tid, err);
+            }
+            if !handle.blocked {
+                ready.push(id);
+            }
+        }
+        ready
+    }
+
+    /// What to watch for the waits of subscribed handles
+    pub fn watched(&self) -> Watched {
+        let mut watched = Watched::default();
+        let mut processes: Vec<&Arc<Process>> = Vec::new();
+        for handle in self.handles.values().filter(|handle| handle.notifies()) {
+            let wakeup = self.server.translator().wakeup(&handle.process, handle.tid);
+            watched.wakeup = watched.wakeup.into_iter().chain(wakeup).min();
+            if !processes.iter().any(|process| Arc::ptr_eq(process, &handle.process)) {
+                processes.push(&handle.process);
+            }
+        }
+        for process in processes {
+            watched.files.extend(
+                process
+                    .fds()
+                    .into_iter()
+                    .map(|(_, file)| file)
+                    .filter(|file| file.host().is_some()),
+            );
+        }
+        watched
+    }
+}
+
+impl SchemeSync for LacScheme {
+    fn open(&mut self, path: &str, _flags: usize, ctx: &CallerCtx) -> Result<OpenResult> {
+        let path = path.trim_matches('/');
+        let (pid, tid) = path.split_once('/').unwrap_or((path, path));
+        let pid = pid.parse().map_err(|_| Error::new(ENOENT))?;
+        let tid = tid.parse().map_err(|_| Error::new(ENOENT))?;
+        let process = self
+            .server
+            .get_process(pid)
+            .ok_or(Error::new(ENOENT))?;
+        process.thread(tid).map_err(|_| Error::new(ENOENT))?;
+        if !is_owner(&process, ctx) {
+            log::warn!(
+                "Refused pid {} (uid {}) access to Linux process {}",
+                ctx.pid,
+                ctx.uid,
+                pid
+            );
+            return Err(Error::new(EACCES));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handles.insert(
+            id,
+            Handle {
+                process,
+                tid,
+                result: None,
+                blocked: false,
+                events: EventFlags::empty(),
+            },
+        );
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn write(
+        &mut self,
+        id: usize,
+        buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        caller: &CallerCtx,
+    ) -> Result<usize> {
+        let handle = self.handles.get_mut(&id).ok_or(Error::new(EBADF))?;
+        // Handles can be passed on, so the caller is checked on every use
+        if !is_owner(&handle.process, caller) {
+            return Err(Error::new(EACCES));
+        }
+        if buf.len() != REQUEST_SIZE {
+            return Err(Error::new(EINVAL));
+        }
+
+        let word = |index: usize| {
+            u64::from_le_bytes(buf[index * 8..index * 8 + 8].try_into().unwrap())
+        };
+        let ctx = SyscallContext {
+            syscall_num: word(0),
+            arg0: word(1),
+            arg1: word(2),
+            arg2: word(3),
+            arg3: word(4),
+            arg4: word(5),
+            arg5: word(6),
+            tid: handle.tid,
+            rip: word(7),
+            rsp: word(8),
+        };
+
+        let credentials = Credentials::assume(&handle.process)?;
+        let result = self.server.translator().translate(&handle.process, &ctx);
+        drop(credentials);
+        match result {
+            SyscallResult::Blocked => {
+                handle.result = None;
+                handle.blocked = true;
+            }
+            result => handle.result = Some(result.to_raw()),
+        }
+        Ok(buf.len())
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let handle = self.handles.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if !is_owner(&handle.process, ctx) {
+            return Err(Error::new(EACCES));
+        }
+        if buf.len() < 8 {
+            return Err(Error::new(EINVAL));
+        }
+        handle.resume(&self.server)?;
+        if handle.blocked {
+            return Err(Error::new(EAGAIN));
+        }
+        let result = handle.result.take().ok_or(Error::new(EINVAL))?;
+        buf[..8].copy_from_slice(&result.to_le_bytes());
+        Ok(8)
+    }
+
+    fn fevent(&mut self, id: usize, flags: EventFlags, ctx: &CallerCtx) -> Result<EventFlags> {
+        let handle = self.handles.get_mut(&id).ok_or(Error::new(EBADF))?;
+        if !is_owner(&handle.process, ctx) {
+            return Err(Error::new(EACCES));
+        }
+        handle.events = flags;
+        handle.resume(&self.server)?;
+        if flags.contains(EventFlags::EVENT_READ) && handle.result.is_some() {
+            return Ok(EventFlags::EVENT_READ);
+        }
+        Ok(EventFlags::empty())
+    }
+}
@@ -314,7 +314,6 @@ pub mod si_code {
 }
 
 /// Signal state for a process
-#[derive(Default)]
 pub struct SignalState {
     /// Signal handlers
     handlers: [SigAction; 64],
@@ -326,6 +325,17 @@ pub struct SignalState {
     alt_stack: Option<SignalStack>,
 }
 
+impl Default for SignalState {
+    fn default() -> Self {
+        Self {
+            handlers: [SigAction::default(); 64],
+            pending: VecDeque::new(),
+            blocked: 0,
+            alt_stack: None,
+        }
+    }
+}
+
 /// Alternate signal stack
 #[derive(Debug, Clone, Copy)]
 pub struct SignalStack {
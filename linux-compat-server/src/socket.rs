@@ -0,0 +1,703 @@
+//! Sockets
+//!
+//! Linux sockets are backed by sockets of the host libc, which relibc serves
+//! from Redox schemes: `AF_INET` sockets from the network stack through
+//! `tcp:` and `udp:`, and `AF_UNIX` sockets from the local IPC scheme. What
+//! is translated is the Linux ABI around them: constants, the layout of
+//! socket addresses and options, and the paths of `AF_UNIX` addresses, which
+//! the translator maps like any other Linux path.
+//!
+//! Host sockets never block. A call that would block a blocking Linux
+//! socket fails with `EAGAIN` here, and the translator waits for it like
+//! `poll` does.
+
+use std::fs::File;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+use crate::epoll::{self, poll_events};
+use crate::errno::LinuxErrno;
+
+/// Linux address families
+pub mod address_family {
+    pub const AF_UNIX: i32 = 1;
+    pub const AF_INET: i32 = 2;
+}
+
+/// Linux socket types, and flags `socket` takes with them
+pub mod socket_type {
+    use crate::translator::open_flags;
+
+    pub const SOCK_STREAM: i32 = 1;
+    pub const SOCK_DGRAM: i32 = 2;
+    pub const SOCK_RAW: i32 = 3;
+    pub const SOCK_SEQPACKET: i32 = 5;
+    pub const SOCK_TYPE_MASK: i32 = 0xf;
+    pub const SOCK_NONBLOCK: i32 = open_flags::O_NONBLOCK;
+    pub const SOCK_CLOEXEC: i32 = open_flags::O_CLOEXEC;
+}
+
+/// Linux socket option levels and names
+pub mod socket_options {
+    pub const SOL_SOCKET: i32 = 1;
+    pub const SO_REUSEADDR: i32 = 2;
+    pub const SO_TYPE: i32 = 3;
+    pub const SO_ERROR: i32 = 4;
+    pub const SO_BROADCAST: i32 = 6;
+    pub const SO_SNDBUF: i32 = 7;
+    pub const SO_RCVBUF: i32 = 8;
+    pub const SO_KEEPALIVE: i32 = 9;
+    pub const SO_LINGER: i32 = 13;
+    pub const SO_RCVTIMEO: i32 = 20;
+    pub const SO_SNDTIMEO: i32 = 21;
+    pub const SO_ACCEPTCONN: i32 = 30;
+    pub const SO_PROTOCOL: i32 = 38;
+    pub const SO_DOMAIN: i32 = 39;
+
+    pub const IPPROTO_TCP: i32 = 6;
+    pub const IPPROTO_UDP: i32 = 17;
+    pub const TCP_NODELAY: i32 = 1;
+}
+
+/// Linux `send` and `recv` flags
+pub mod msg_flags {
+    pub const MSG_OOB: i32 = 0x1;
+    pub const MSG_PEEK: i32 = 0x2;
+    pub const MSG_TRUNC: i32 = 0x20;
+    pub const MSG_DONTWAIT: i32 = 0x40;
+}
+
+/// `shutdown` directions
+pub mod shutdown_how {
+    pub const SHUT_RD: i32 = 0;
+    pub const SHUT_WR: i32 = 1;
+    pub const SHUT_RDWR: i32 = 2;
+}
+
+/// Largest Linux `struct sockaddr`, the size of `struct sockaddr_storage`
+pub const MAX_SOCKADDR_SIZE: usize = 128;
+/// Largest option value, a `struct timeval`
+pub const MAX_OPTION_SIZE: usize = TIMEVAL_SIZE;
+/// Size of `struct sockaddr_in`
+const SOCKADDR_IN_SIZE: usize = 16;
+/// Size of `sun_path` in `struct sockaddr_un`
+const SUN_PATH_SIZE: usize = 108;
+/// Size of `struct timeval`, which the timeout options take
+const TIMEVAL_SIZE: usize = 16;
+
+/// Socket address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SockAddr {
+    Inet(SocketAddrV4),
+    /// `sun_path`: empty if unnamed, starting with a NUL byte if abstract
+    Unix(Vec<u8>),
+}
+
+impl SockAddr {
+    /// Parse a Linux `struct sockaddr` given to a socket of `domain`
+    pub fn from_linux(domain: i32, bytes: &[u8]) -> Result<Self, LinuxErrno> {
+        use address_family::*;
+
+        if bytes.len() < 2 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let family = u16::from_le_bytes([bytes[0], bytes[1]]) as i32;
+        match domain {
+            AF_INET if family != AF_INET => Err(LinuxErrno::EAFNOSUPPORT),
+            AF_INET if bytes.len() < SOCKADDR_IN_SIZE => Err(LinuxErrno::EINVAL),
+            AF_INET => {
+                let port = u16::from_be_bytes([bytes[2], bytes[3]]);
+                let ip = Ipv4Addr::new(bytes[4], bytes[5], bytes[6], bytes[7]);
+                Ok(Self::Inet(SocketAddrV4::new(ip, port)))
+            }
+            AF_UNIX if family != AF_UNIX || bytes.len() > 2 + SUN_PATH_SIZE => {
+                Err(LinuxErrno::EINVAL)
+            }
+            AF_UNIX => {
+                let mut path = bytes[2..].to_vec();
+                if path.first() != Some(&0) {
+                    if let Some(nul) = path.iter().position(|&byte| byte == 0) {
+                        path.truncate(nul);
+                    }
+                }
+                Ok(Self::Unix(path))
+            }
+            _ => Err(LinuxErrno::EAFNOSUPPORT),
+        }
+    }
+
+    /// The Linux `struct sockaddr`
+    pub fn to_linux(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SOCKADDR_IN_SIZE);
+        match self {
+            Self::Inet(addr) => {
+                bytes.extend_from_slice(&(address_family::AF_INET as u16).to_le_bytes());
+                bytes.extend_from_slice(&addr.port().to_be_bytes());
+                bytes.extend_from_slice(&addr.ip().octets());
+                bytes.resize(SOCKADDR_IN_SIZE, 0);
+            }
+            Self::Unix(path) => {
+                bytes.extend_from_slice(&(address_family::AF_UNIX as u16).to_le_bytes());
+                bytes.extend_from_slice(path);
+                if self.path().is_some() {
+                    bytes.push(0);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Path of an `AF_UNIX` address bound to the file system
+    pub fn path(&self) -> Option<&[u8]> {
+        match self {
+            Self::Unix(path) if path.first().is_some_and(|&byte| byte != 0) => Some(path),
+            _ => None,
+        }
+    }
+
+    fn to_host(&self) -> Result<(libc::sockaddr_storage, libc::socklen_t), LinuxErrno> {
+        // SAFETY: all-zero bytes are a valid sockaddr_storage
+        let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let len = match self {
+            Self::Inet(addr) => {
+                // SAFETY: sockaddr_storage is large and aligned enough for
+                // any address
+                let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = addr.port().to_be();
+                sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+                std::mem::size_of::<libc::sockaddr_in>()
+            }
+            Self::Unix(path) => {
+                // SAFETY: as above
+                let sun = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_un) };
+                sun.sun_family = libc::AF_UNIX as libc::sa_family_t;
+                // Pathnames keep a terminating NUL
+                let len = path.len() + self.path().is_some() as usize;
+                if len > sun.sun_path.len() {
+                    return Err(LinuxErrno::ENAMETOOLONG);
+                }
+                for (dst, &src) in sun.sun_path.iter_mut().zip(path) {
+                    *dst = src as libc::c_char;
+                }
+                let offset = sun.sun_path.as_ptr() as usize - sun as *const _ as usize;
+                offset + len
+            }
+        };
+        Ok((storage, len as libc::socklen_t))
+    }
+
+    /// Address the host reports, where Redox paths of `AF_UNIX` addresses
+    /// can't be told apart from unnamed ones
+    fn from_host(storage: &libc::sockaddr_storage) -> Self {
+        if storage.ss_family as i32 == libc::AF_INET {
+            // SAFETY: the host filled in a sockaddr_in
+            let sin = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+            Self::Inet(SocketAddrV4::new(ip, u16::from_be(sin.sin_port)))
+        } else {
+            Self::Unix(Vec::new())
+        }
+    }
+}
+
+/// Fail with the host's errno if a libc call failed
+fn cvt<T: Default + PartialOrd>(ret: T) -> Result<T, LinuxErrno> {
+    if ret < T::default() {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(ret)
+}
+
+fn timeval(timeout: Option<Duration>) -> Vec<u8> {
+    let timeout = timeout.unwrap_or_default();
+    let mut bytes = Vec::with_capacity(TIMEVAL_SIZE);
+    bytes.extend_from_slice(&(timeout.as_secs() as i64).to_le_bytes());
+    bytes.extend_from_slice(&(timeout.subsec_micros() as i64).to_le_bytes());
+    bytes
+}
+
+fn parse_timeval(bytes: &[u8]) -> Result<Option<Duration>, LinuxErrno> {
+    if bytes.len() < TIMEVAL_SIZE {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let sec = i64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let usec = i64::from_le_bytes(bytes[8..16].try_into().unwrap());
+    if !(0..1_000_000).contains(&usec) {
+        return Err(LinuxErrno::EDOM);
+    }
+    // Negative timeouts don't time out, like zero
+    if sec < 0 || (sec == 0 && usec == 0) {
+        return Ok(None);
+    }
+    Ok(Some(Duration::new(sec as u64, usec as u32 * 1000)))
+}
+
+/// Host level and name of an option set on the host socket, with the size
+/// of its value
+fn host_option(level: i32, name: i32) -> Option<(libc::c_int, libc::c_int, usize)> {
+    use socket_options::*;
+
+    let int = std::mem::size_of::<libc::c_int>();
+    Some(match (level, name) {
+        (SOL_SOCKET, SO_REUSEADDR) => (libc::SOL_SOCKET, libc::SO_REUSEADDR, int),
+        (SOL_SOCKET, SO_BROADCAST) => (libc::SOL_SOCKET, libc::SO_BROADCAST, int),
+        (SOL_SOCKET, SO_SNDBUF) => (libc::SOL_SOCKET, libc::SO_SNDBUF, int),
+        (SOL_SOCKET, SO_RCVBUF) => (libc::SOL_SOCKET, libc::SO_RCVBUF, int),
+        (SOL_SOCKET, SO_KEEPALIVE) => (libc::SOL_SOCKET, libc::SO_KEEPALIVE, int),
+        (SOL_SOCKET, SO_LINGER) => (
+            libc::SOL_SOCKET,
+            libc::SO_LINGER,
+            std::mem::size_of::<libc::linger>(),
+        ),
+        (IPPROTO_TCP, TCP_NODELAY) => (libc::IPPROTO_TCP, libc::TCP_NODELAY, int),
+        _ => return None,
+    })
+}
+
+/// Host flags of the Linux `send` and `recv` flags the host honours
+///
+/// `MSG_DONTWAIT` is up to the translator as host sockets never block.
+/// `MSG_WAITALL` is dropped: receives return what has arrived.
+fn host_msg_flags(flags: i32) -> libc::c_int {
+    use msg_flags::*;
+
+    [
+        (MSG_OOB, libc::MSG_OOB),
+        (MSG_PEEK, libc::MSG_PEEK),
+        (MSG_TRUNC, libc::MSG_TRUNC),
+    ]
+    .into_iter()
+    .filter(|&(linux, _)| flags & linux != 0)
+    .fold(0, |host, (_, flag)| host | flag)
+}
+
+/// Linux state the host socket doesn't keep
+#[derive(Default)]
+struct SocketState {
+    /// Linux address an `AF_UNIX` socket is bound to, the host only knows
+    /// its Redox path
+    name: Option<SockAddr>,
+    /// Linux address an `AF_UNIX` socket is connected to
+    peer: Option<SockAddr>,
+    listening: bool,
+    /// A blocking `connect` is waiting for the connection
+    connecting: bool,
+    recv_timeout: Option<Duration>,
+    send_timeout: Option<Duration>,
+}
+
+/// Socket
+pub struct Socket {
+    file: File,
+    domain: i32,
+    kind: i32,
+    protocol: i32,
+    state: spin::Mutex<SocketState>,
+}
+
+impl Socket {
+    /// Create a socket of a Linux `domain`, `kind` without flags and
+    /// `protocol`
+    pub fn new(domain: i32, kind: i32, protocol: i32) -> Result<Self, LinuxErrno> {
+        let (host_domain, host_kind, protocol) = host_socket(domain, kind, protocol)?;
+        // SAFETY: plain libc call
+        let fd = cvt(unsafe {
+            libc::socket(
+                host_domain,
+                host_kind | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                0,
+            )
+        })?;
+        // SAFETY: the descriptor was just created and is owned by nothing
+        // else
+        Ok(Self::from_fd(
+            unsafe { OwnedFd::from_raw_fd(fd) },
+            domain,
+            kind,
+            protocol,
+        ))
+    }
+
+    /// Create a pair of connected `AF_UNIX` sockets
+    pub fn pair(domain: i32, kind: i32, protocol: i32) -> Result<(Self, Self), LinuxErrno> {
+        let (host_domain, host_kind, protocol) = host_socket(domain, kind, protocol)?;
+        if domain != address_family::AF_UNIX {
+            return Err(LinuxErrno::EOPNOTSUPP);
+        }
+        let mut fds = [0; 2];
+        // SAFETY: fds has room for the two descriptors
+        cvt(unsafe {
+            libc::socketpair(
+                host_domain,
+                host_kind | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        })?;
+        // SAFETY: as in new
+        let [a, b] = fds.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+        Ok((
+            Self::from_fd(a, domain, kind, protocol),
+            Self::from_fd(b, domain, kind, protocol),
+        ))
+    }
+
+    fn from_fd(fd: OwnedFd, domain: i32, kind: i32, protocol: i32) -> Self {
+        Self {
+            file: File::from(fd),
+            domain,
+            kind,
+            protocol,
+            state: spin::Mutex::new(SocketState::default()),
+        }
+    }
+
+    fn fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    /// Host socket, which reads and writes go to
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn domain(&self) -> i32 {
+        self.domain
+    }
+
+    pub fn kind(&self) -> i32 {
+        self.kind
+    }
+
+    pub fn readiness(&self) -> u32 {
+        epoll::host_readiness(&self.file)
+    }
+
+    /// Bind to `host`, the host address of the Linux address `name`
+    pub fn bind(&self, name: &SockAddr, host: &SockAddr) -> Result<(), LinuxErrno> {
+        let mut state = self.state.lock();
+        let (addr, len) = host.to_host()?;
+        // SAFETY: addr holds len bytes of address
+        cvt(unsafe { libc::bind(self.fd(), &addr as *const _ as *const libc::sockaddr, len) })?;
+        if let SockAddr::Unix(_) = name {
+            state.name = Some(name.clone());
+        }
+        Ok(())
+    }
+
+    /// Connect to `host`, the host address of the Linux address `name`
+    ///
+    /// Fails with `EINPROGRESS` if the connection isn't established yet:
+    /// [`Socket::finish_connect`] then waits for it.
+    pub fn connect(&self, name: &SockAddr, host: &SockAddr) -> Result<(), LinuxErrno> {
+        let mut state = self.state.lock();
+        let (addr, len) = host.to_host()?;
+        // SAFETY: addr holds len bytes of address
+        let result = cvt(unsafe {
+            libc::connect(self.fd(), &addr as *const _ as *const libc::sockaddr, len)
+        });
+        match result {
+            Ok(_) => {}
+            Err(LinuxErrno::EINPROGRESS) => {
+                state.connecting = true;
+                return Err(LinuxErrno::EINPROGRESS);
+            }
+            Err(errno) => return Err(errno),
+        }
+        if let SockAddr::Unix(_) = name {
+            state.peer = Some(name.clone());
+        }
+        Ok(())
+    }
+
+    /// Whether a blocking `connect` is waiting for its connection
+    pub fn connecting(&self) -> bool {
+        self.state.lock().connecting
+    }
+
+    /// Result of the connection [`Socket::connect`] started, failing with
+    /// `EINPROGRESS` while it isn't established
+    pub fn finish_connect(&self) -> Result<(), LinuxErrno> {
+        use poll_events::*;
+
+        if self.readiness() & (POLLOUT | POLLERR | POLLHUP) == 0 {
+            return Err(LinuxErrno::EINPROGRESS);
+        }
+        self.state.lock().connecting = false;
+        match self.take_error()? {
+            0 => Ok(()),
+            errno => Err(LinuxErrno::from_redox(errno as usize)),
+        }
+    }
+
+    /// Give up waiting for the connection [`Socket::connect`] started
+    pub fn abandon_connect(&self) {
+        self.state.lock().connecting = false;
+    }
+
+    pub fn listen(&self, backlog: i32) -> Result<(), LinuxErrno> {
+        let mut state = self.state.lock();
+        // SAFETY: plain libc call
+        cvt(unsafe { libc::listen(self.fd(), backlog) })?;
+        state.listening = true;
+        Ok(())
+    }
+
+    /// Accept a connection, with the address of its peer
+    pub fn accept(&self) -> Result<(Socket, SockAddr), LinuxErrno> {
+        if !self.state.lock().listening {
+            return Err(LinuxErrno::EINVAL);
+        }
+        // SAFETY: all-zero bytes are a valid sockaddr_storage
+        let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of_val(&addr) as libc::socklen_t;
+        // SAFETY: addr has room for len bytes
+        let fd = cvt(unsafe {
+            libc::accept(
+                self.fd(),
+                &mut addr as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            )
+        })?;
+        // SAFETY: as in new
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // SAFETY: plain libc calls on the new descriptor
+        unsafe {
+            cvt(libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK))?;
+            cvt(libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC))?;
+        }
+
+        let socket = Self::from_fd(fd, self.domain, self.kind, self.protocol);
+        socket.state.lock().name = self.state.lock().name.clone();
+        Ok((socket, SockAddr::from_host(&addr)))
+    }
+
+    /// Send `data`, to `to` (a host address) if given
+    pub fn send_to(
+        &self,
+        data: &[u8],
+        flags: i32,
+        to: Option<&SockAddr>,
+    ) -> Result<usize, LinuxErrno> {
+        let to = to.map(SockAddr::to_host).transpose()?;
+        let (addr, len) = match &to {
+            Some((addr, len)) => (addr as *const _ as *const libc::sockaddr, *len),
+            None => (std::ptr::null(), 0),
+        };
+        // SAFETY: data and the address are valid for their lengths
+        let sent = cvt(unsafe {
+            libc::sendto(
+                self.fd(),
+                data.as_ptr().cast(),
+                data.len(),
+                host_msg_flags(flags),
+                addr,
+                len,
+            )
+        })?;
+        Ok(sent as usize)
+    }
+
+    /// Receive into `buf`, with the address of the sender unless the
+    /// socket is connected
+    ///
+    /// With `MSG_TRUNC` the full length of a datagram is returned, which
+    /// may be more than fits in `buf`.
+    pub fn recv_from(
+        &self,
+        buf: &mut [u8],
+        flags: i32,
+    ) -> Result<(usize, Option<SockAddr>), LinuxErrno> {
+        // SAFETY: all-zero bytes are a valid sockaddr_storage
+        let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of_val(&addr) as libc::socklen_t;
+        // SAFETY: buf and addr are valid for their lengths
+        let received = cvt(unsafe {
+            libc::recvfrom(
+                self.fd(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                host_msg_flags(flags),
+                &mut addr as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            )
+        })?;
+        let from = (len > 0).then(|| SockAddr::from_host(&addr));
+        Ok((received as usize, from))
+    }
+
+    pub fn shutdown(&self, how: i32) -> Result<(), LinuxErrno> {
+        use shutdown_how::*;
+
+        let how = match how {
+            SHUT_RD => libc::SHUT_RD,
+            SHUT_WR => libc::SHUT_WR,
+            SHUT_RDWR => libc::SHUT_RDWR,
+            _ => return Err(LinuxErrno::EINVAL),
+        };
+        // SAFETY: plain libc call
+        cvt(unsafe { libc::shutdown(self.fd(), how) })?;
+        Ok(())
+    }
+
+    /// Address the socket is bound to
+    pub fn local_addr(&self) -> Result<SockAddr, LinuxErrno> {
+        if self.domain == address_family::AF_UNIX {
+            let name = self.state.lock().name.clone();
+            return Ok(name.unwrap_or(SockAddr::Unix(Vec::new())));
+        }
+        self.host_addr(libc::getsockname)
+    }
+
+    /// Address the socket is connected to
+    pub fn peer_addr(&self) -> Result<SockAddr, LinuxErrno> {
+        let addr = self.host_addr(libc::getpeername)?;
+        if self.domain == address_family::AF_UNIX {
+            let peer = self.state.lock().peer.clone();
+            return Ok(peer.unwrap_or(addr));
+        }
+        Ok(addr)
+    }
+
+    fn host_addr(
+        &self,
+        call: unsafe extern "C" fn(
+            libc::c_int,
+            *mut libc::sockaddr,
+            *mut libc::socklen_t,
+        ) -> libc::c_int,
+    ) -> Result<SockAddr, LinuxErrno> {
+        // SAFETY: all-zero bytes are a valid sockaddr_storage
+        let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of_val(&addr) as libc::socklen_t;
+        // SAFETY: addr has room for len bytes
+        cvt(unsafe {
+            call(
+                self.fd(),
+                &mut addr as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            )
+        })?;
+        Ok(SockAddr::from_host(&addr))
+    }
+
+    /// Take the pending error of the host socket, as a host errno
+    fn take_error(&self) -> Result<i32, LinuxErrno> {
+        let mut error: libc::c_int = 0;
+        let mut len = std::mem::size_of_val(&error) as libc::socklen_t;
+        // SAFETY: error has room for len bytes
+        cvt(unsafe {
+            libc::getsockopt(
+                self.fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ERROR,
+                &mut error as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        })?;
+        Ok(error)
+    }
+
+    /// Timeout of receives, `SO_RCVTIMEO`
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        self.state.lock().recv_timeout
+    }
+
+    /// Timeout of sends and connections, `SO_SNDTIMEO`
+    pub fn send_timeout(&self) -> Option<Duration> {
+        self.state.lock().send_timeout
+    }
+
+    /// Value of a Linux socket option, as Linux lays it out
+    pub fn get_option(&self, level: i32, name: i32) -> Result<Vec<u8>, LinuxErrno> {
+        use socket_options::*;
+
+        let int = |value: i32| value.to_le_bytes().to_vec();
+        let state = self.state.lock();
+        Ok(match (level, name) {
+            (SOL_SOCKET, SO_TYPE) => int(self.kind),
+            (SOL_SOCKET, SO_DOMAIN) => int(self.domain),
+            (SOL_SOCKET, SO_PROTOCOL) => int(self.protocol),
+            (SOL_SOCKET, SO_ACCEPTCONN) => int(state.listening as i32),
+            (SOL_SOCKET, SO_RCVTIMEO) => timeval(state.recv_timeout),
+            (SOL_SOCKET, SO_SNDTIMEO) => timeval(state.send_timeout),
+            (SOL_SOCKET, SO_ERROR) => match self.take_error()? {
+                0 => int(0),
+                errno => int(LinuxErrno::from_redox(errno as usize) as i32),
+            },
+            _ => {
+                let (level, name, size) =
+                    host_option(level, name).ok_or(LinuxErrno::ENOPROTOOPT)?;
+                let mut value = vec![0; size];
+                let mut len = size as libc::socklen_t;
+                // SAFETY: value has room for len bytes
+                cvt(unsafe {
+                    libc::getsockopt(self.fd(), level, name, value.as_mut_ptr().cast(), &mut len)
+                })?;
+                value.truncate(len as usize);
+                value
+            }
+        })
+    }
+
+    /// Set a Linux socket option to `value`, as Linux lays it out
+    pub fn set_option(&self, level: i32, name: i32, value: &[u8]) -> Result<(), LinuxErrno> {
+        use socket_options::*;
+
+        let mut state = self.state.lock();
+        match (level, name) {
+            (SOL_SOCKET, SO_RCVTIMEO) => state.recv_timeout = parse_timeval(value)?,
+            (SOL_SOCKET, SO_SNDTIMEO) => state.send_timeout = parse_timeval(value)?,
+            (SOL_SOCKET, SO_TYPE | SO_DOMAIN | SO_PROTOCOL | SO_ACCEPTCONN | SO_ERROR) => {
+                return Err(LinuxErrno::ENOPROTOOPT)
+            }
+            _ => {
+                let (level, name, size) =
+                    host_option(level, name).ok_or(LinuxErrno::ENOPROTOOPT)?;
+                if value.len() < size {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                // SAFETY: value holds size bytes
+                cvt(unsafe {
+                    libc::setsockopt(
+                        self.fd(),
+                        level,
+                        name,
+                        value.as_ptr().cast(),
+                        size as libc::socklen_t,
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Host domain and type of a Linux socket, with the protocol it gets
+fn host_socket(
+    domain: i32,
+    kind: i32,
+    protocol: i32,
+) -> Result<(libc::c_int, libc::c_int, i32), LinuxErrno> {
+    use address_family::*;
+    use socket_options::*;
+    use socket_type::*;
+
+    match (domain, kind, protocol) {
+        (AF_INET, SOCK_STREAM, 0 | IPPROTO_TCP) => {
+            Ok((libc::AF_INET, libc::SOCK_STREAM, IPPROTO_TCP))
+        }
+        (AF_INET, SOCK_DGRAM, 0 | IPPROTO_UDP) => {
+            Ok((libc::AF_INET, libc::SOCK_DGRAM, IPPROTO_UDP))
+        }
+        (AF_INET, SOCK_STREAM | SOCK_DGRAM, _) => Err(LinuxErrno::EPROTONOSUPPORT),
+        (AF_UNIX, SOCK_STREAM, 0 | AF_UNIX) => Ok((libc::AF_UNIX, libc::SOCK_STREAM, 0)),
+        (AF_UNIX, SOCK_DGRAM, 0 | AF_UNIX) => Ok((libc::AF_UNIX, libc::SOCK_DGRAM, 0)),
+        (AF_UNIX, SOCK_STREAM | SOCK_DGRAM, _) => Err(LinuxErrno::EPROTONOSUPPORT),
+        (AF_INET | AF_UNIX, SOCK_RAW | SOCK_SEQPACKET, _) => Err(LinuxErrno::ESOCKTNOSUPPORT),
+        (AF_INET | AF_UNIX, _, _) => Err(LinuxErrno::EINVAL),
+        _ => Err(LinuxErrno::EAFNOSUPPORT),
+    }
+}
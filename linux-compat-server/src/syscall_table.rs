@@ -375,10 +375,24 @@ impl LinuxSyscall {
             20 => Self::Writev,
             21 => Self::Access,
             22 => Self::Pipe,
+            23 => Self::Select,
             32 => Self::Dup,
             33 => Self::Dup2,
             35 => Self::Nanosleep,
             39 => Self::Getpid,
+            41 => Self::Socket,
+            42 => Self::Connect,
+            43 => Self::Accept,
+            44 => Self::Sendto,
+            45 => Self::Recvfrom,
+            48 => Self::Shutdown,
+            49 => Self::Bind,
+            50 => Self::Listen,
+            51 => Self::Getsockname,
+            52 => Self::Getpeername,
+            53 => Self::Socketpair,
+            54 => Self::Setsockopt,
+            55 => Self::Getsockopt,
             56 => Self::Clone,
             57 => Self::Fork,
             58 => Self::Vfork,
@@ -400,18 +414,33 @@ impl LinuxSyscall {
             107 => Self::Geteuid,
             108 => Self::Getegid,
             110 => Self::Getppid,
+            158 => Self::ArchPrctl,
             186 => Self::Gettid,
             200 => Self::Tkill,
             202 => Self::Futex,
+            213 => Self::EpollCreate,
             217 => Self::Getdents64,
             218 => Self::SetTidAddress,
             228 => Self::ClockGettime,
             231 => Self::ExitGroup,
+            232 => Self::EpollWait,
+            233 => Self::EpollCtl,
             234 => Self::Tgkill,
             257 => Self::Openat,
             262 => Self::Newfstatat,
             263 => Self::Unlinkat,
+            267 => Self::Readlinkat,
             269 => Self::Faccessat,
+            270 => Self::Pselect6,
+            271 => Self::Ppoll,
+            281 => Self::EpollPwait,
+            283 => Self::TimerfdCreate,
+            284 => Self::Eventfd,
+            286 => Self::TimerfdSettime,
+            287 => Self::TimerfdGettime,
+            288 => Self::Accept4,
+            290 => Self::Eventfd2,
+            291 => Self::EpollCreate1,
             292 => Self::Dup3,
             293 => Self::Pipe2,
             302 => Self::Prlimit64,
@@ -443,10 +472,24 @@ impl LinuxSyscall {
             Self::Ioctl => "ioctl",
             Self::Access => "access",
             Self::Pipe => "pipe",
+            Self::Select => "select",
             Self::Dup => "dup",
             Self::Dup2 => "dup2",
             Self::Nanosleep => "nanosleep",
             Self::Getpid => "getpid",
+            Self::Socket => "socket",
+            Self::Connect => "connect",
+            Self::Accept => "accept",
+            Self::Sendto => "sendto",
+            Self::Recvfrom => "recvfrom",
+            Self::Shutdown => "shutdown",
+            Self::Bind => "bind",
+            Self::Listen => "listen",
+            Self::Getsockname => "getsockname",
+            Self::Getpeername => "getpeername",
+            Self::Socketpair => "socketpair",
+            Self::Setsockopt => "setsockopt",
+            Self::Getsockopt => "getsockopt",
             Self::Clone => "clone",
             Self::Fork => "fork",
             Self::Vfork => "vfork",
@@ -468,18 +511,33 @@ impl LinuxSyscall {
             Self::Geteuid => "geteuid",
             Self::Getegid => "getegid",
             Self::Getppid => "getppid",
+            Self::ArchPrctl => "arch_prctl",
             Self::Gettid => "gettid",
             Self::Tkill => "tkill",
             Self::Futex => "futex",
+            Self::EpollCreate => "epoll_create",
             Self::Getdents64 => "getdents64",
             Self::SetTidAddress => "set_tid_address",
             Self::ClockGettime => "clock_gettime",
             Self::ExitGroup => "exit_group",
+            Self::EpollWait => "epoll_wait",
+            Self::EpollCtl => "epoll_ctl",
             Self::Tgkill => "tgkill",
             Self::Openat => "openat",
             Self::Newfstatat => "newfstatat",
             Self::Unlinkat => "unlinkat",
+            Self::Readlinkat => "readlinkat",
             Self::Faccessat => "faccessat",
+            Self::Pselect6 => "pselect6",
+            Self::Ppoll => "ppoll",
+            Self::EpollPwait => "epoll_pwait",
+            Self::TimerfdCreate => "timerfd_create",
+            Self::Eventfd => "eventfd",
+            Self::TimerfdSettime => "timerfd_settime",
+            Self::TimerfdGettime => "timerfd_gettime",
+            Self::Accept4 => "accept4",
+            Self::Eventfd2 => "eventfd2",
+            Self::EpollCreate1 => "epoll_create1",
             Self::Dup3 => "dup3",
             Self::Pipe2 => "pipe2",
             Self::Prlimit64 => "prlimit64",
@@ -517,6 +575,7 @@ impl LinuxSyscall {
                 | Self::Pipe
                 | Self::Pipe2
                 | Self::Readlink
+                | Self::Readlinkat
                 | Self::Getdents64
         )
     }
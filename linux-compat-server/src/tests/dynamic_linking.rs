@@ -0,0 +1,283 @@
+//! The dynamic linker's output is checked by loading it into the test
+//! process itself and calling the executable's entry point, which only uses
+//! code and data of its shared libraries.
+//!
+//! Programs using TLS are started through the runtime instead, on the thread
+//! pointer it set up.
+
+use super::{assert_passed, compile, TempDir};
+use crate::dynamic_linker::{self, LinkMap, Sysroot};
+use crate::elf_loader::{self, pt_type, LoadedElf};
+use crate::errno::LinuxErrno;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Sysroot with libbase in the default library path and libadd in
+/// `/opt/lac/lib`, and an executable needing libadd, returning the
+/// executable's path
+fn build_sysroot(sysroot: &TempDir, dir: &TempDir) -> String {
+    let usr_lib = sysroot.join("usr/lib");
+    let opt_lib = sysroot.join("opt/lac/lib");
+    std::fs::create_dir_all(&usr_lib).unwrap();
+    std::fs::create_dir_all(&opt_lib).unwrap();
+
+    let libbase = format!("{}/libbase.so", usr_lib);
+    compile(
+        "libbase",
+        &libbase,
+        &["-shared", "-fPIC", "-Wl,-soname,libbase.so"],
+    );
+    compile(
+        "libadd",
+        &format!("{}/libadd.so", opt_lib),
+        &[
+            "-shared",
+            "-fPIC",
+            "-Wl,-soname,libadd.so",
+            &format!("-L{}", usr_lib),
+            "-lbase",
+        ],
+    );
+
+    let binary = dir.join("dynamic");
+    compile(
+        "dynamic",
+        &binary,
+        &[
+            "-no-pie",
+            "-fno-pic",
+            &format!("-L{}", opt_lib),
+            "-ladd",
+            &format!("-Wl,-rpath-link,{}", usr_lib),
+            "-Wl,--enable-new-dtags,-rpath,/opt/lac/lib",
+            "-Wl,--dynamic-linker,/lib64/ld-linux-x86-64.so.2",
+        ],
+    );
+    binary
+}
+
+/// Free address range for `len` bytes in the test process
+fn free_range(len: usize) -> u64 {
+    unsafe {
+        let addr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(addr, libc::MAP_FAILED);
+        libc::munmap(addr, len);
+        addr as u64
+    }
+}
+
+/// Mapping of an object in the test process
+struct Mapped {
+    addr: u64,
+    len: usize,
+}
+
+impl Mapped {
+    /// Map `len` bytes of zeroes at `addr`
+    fn anonymous(addr: u64, len: usize) -> Self {
+        let mapped = unsafe {
+            libc::mmap(
+                addr as *mut libc::c_void,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        assert_eq!(mapped as u64, addr, "Failed to map at {:#x}", addr);
+        Self { addr, len }
+    }
+
+    /// Map the segments of `elf`, `bias` above their linked addresses
+    fn new(elf: &LoadedElf, bias: u64) -> Self {
+        let addr = (bias + elf.load_addr) & !(PAGE_SIZE - 1);
+        let end = (bias + elf.load_addr + elf.mem_size).next_multiple_of(PAGE_SIZE);
+        let mapped = Self::anonymous(addr, (end - addr) as usize);
+
+        for phdr in &elf.program_headers {
+            if phdr.p_type == pt_type::PT_LOAD {
+                let data = &elf.data[phdr.p_offset as usize..][..phdr.p_filesz as usize];
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        data.as_ptr(),
+                        (bias + phdr.p_vaddr) as *mut u8,
+                        data.len(),
+                    );
+                }
+            }
+        }
+        mapped
+    }
+}
+
+impl Drop for Mapped {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// Map the executable, its libraries and the runtime and apply the
+/// relocations
+fn load(exe: &LoadedElf, map: &LinkMap) -> Vec<Mapped> {
+    let mut mapped = vec![Mapped::new(exe, exe.load_bias())];
+    for library in &map.libraries {
+        mapped.push(Mapped::new(&library.elf, library.bias));
+    }
+    let runtime = &map.runtime;
+    mapped.push(Mapped::anonymous(
+        runtime.code,
+        (runtime.data + runtime.data_size - runtime.code) as usize,
+    ));
+    for patch in &map.patches {
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                patch.data.as_ptr(),
+                patch.addr as *mut u8,
+                patch.data.len(),
+            );
+        }
+    }
+    mapped
+}
+
+#[test]
+fn shared_libraries() {
+    let sysroot = TempDir::new("sysroot");
+    let dir = TempDir::new("dynamic");
+    let binary = build_sysroot(&sysroot, &dir);
+
+    let exe = elf_loader::load_elf(&binary).unwrap();
+    assert_eq!(
+        exe.interpreter.as_deref(),
+        Some("/lib64/ld-linux-x86-64.so.2")
+    );
+    let base = free_range(64 * 1024 * 1024);
+    let map = dynamic_linker::link(
+        &exe,
+        "/usr/bin/dynamic",
+        &Sysroot::new(sysroot.path()),
+        base,
+    )
+    .unwrap();
+
+    // Breadth first, from the run path then the default path
+    let paths: Vec<&str> = map
+        .libraries
+        .iter()
+        .map(|library| library.path.as_str())
+        .collect();
+    assert_eq!(paths, ["/opt/lac/lib/libadd.so", "/usr/lib/libbase.so"]);
+    assert_eq!(map.libraries[0].bias, base);
+    assert!(map.libraries[1].bias > base);
+
+    let _mapped = load(&exe, &map);
+    // SAFETY: the entry point is a C function of the executable, relocated
+    let entry: extern "C" fn() -> i64 = unsafe { std::mem::transmute(exe.entry_point) };
+    assert_passed("dynamic", entry() as i32);
+}
+
+#[test]
+fn missing_library() {
+    let sysroot = TempDir::new("sysroot-missing");
+    let dir = TempDir::new("dynamic-missing");
+    let binary = build_sysroot(&sysroot, &dir);
+    std::fs::remove_file(sysroot.join("usr/lib/libbase.so")).unwrap();
+
+    let exe = elf_loader::load_elf(&binary).unwrap();
+    let result = dynamic_linker::link(
+        &exe,
+        "/usr/bin/dynamic",
+        &Sysroot::new(sysroot.path()),
+        dynamic_linker::SHLIB_LOAD_BASE,
+    );
+    assert_eq!(result.err(), Some(LinuxErrno::ENOENT));
+}
+
+/// Start a program in the runtime's start code, as the process would, with
+/// the thread pointer of the initial thread, returning what its entry point
+/// returns
+fn start(start: u64, thread_pointer: u64) -> i64 {
+    const ARCH_GET_FS: i32 = 0x1003;
+    let mut saved: u64 = 0;
+    unsafe { libc::syscall(libc::SYS_arch_prctl, ARCH_GET_FS, &mut saved) };
+    let result: i64;
+    // SAFETY: the program only uses its own TLS, and the test's thread
+    // pointer is restored before anything else runs
+    unsafe {
+        std::arch::asm!(
+            // arch_prctl(ARCH_SET_FS, thread_pointer)
+            "mov eax, 158",
+            "mov edi, 0x1002",
+            "syscall",
+            // Aligned like the stack at process start
+            "push rbp",
+            "mov rbp, rsp",
+            "and rsp, -16",
+            "sub rsp, 8",
+            "call r15",
+            "mov rsp, rbp",
+            "pop rbp",
+            "mov r12, rax",
+            "mov eax, 158",
+            "mov edi, 0x1002",
+            "mov rsi, r14",
+            "syscall",
+            in("rsi") thread_pointer,
+            in("r14") saved,
+            in("r15") start,
+            out("r12") result,
+            out("r13") _,
+            clobber_abi("C"),
+        );
+    }
+    result
+}
+
+#[test]
+fn tls_and_indirect_functions() {
+    let sysroot = TempDir::new("sysroot-tls");
+    let dir = TempDir::new("tls");
+    let usr_lib = sysroot.join("usr/lib");
+    std::fs::create_dir_all(&usr_lib).unwrap();
+    compile(
+        "libtls",
+        &format!("{}/libtls.so", usr_lib),
+        &["-shared", "-fPIC", "-Wl,-soname,libtls.so"],
+    );
+    let binary = dir.join("tls");
+    compile(
+        "tls",
+        &binary,
+        &[
+            "-no-pie",
+            "-fno-pic",
+            &format!("-L{}", usr_lib),
+            "-ltls",
+            // __tls_get_addr is left to the runtime
+            "-Wl,--allow-shlib-undefined",
+            "-Wl,--dynamic-linker,/lib64/ld-linux-x86-64.so.2",
+        ],
+    );
+
+    let exe = elf_loader::load_elf(&binary).unwrap();
+    let base = free_range(64 * 1024 * 1024);
+    let map =
+        dynamic_linker::link(&exe, "/usr/bin/tls", &Sysroot::new(sysroot.path()), base).unwrap();
+    let runtime = &map.runtime;
+    assert!(runtime.code > map.libraries[0].bias);
+    assert_eq!(runtime.thread_pointer % 64, 0);
+
+    let _mapped = load(&exe, &map);
+    let entry = runtime
+        .entry
+        .expect("indirect functions need the start code");
+    assert_passed("tls", start(entry, runtime.thread_pointer) as i32);
+}
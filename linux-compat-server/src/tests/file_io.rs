@@ -0,0 +1,80 @@
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+use super::{assert_passed, build, process, run, translator, TempDir};
+
+#[test]
+fn open_read_close() {
+    let dir = TempDir::new("open_read");
+    let binary = build("open_read", &dir);
+    let data = dir.join("data");
+    std::fs::write(&data, "hello, lac\n").unwrap();
+
+    let process = process("open_read", &dir);
+    let status = run(&translator(), &process, &binary, &[&data, &dir.join("")]);
+    assert_passed("open_read", status);
+}
+
+#[test]
+fn openat() {
+    let dir = TempDir::new("openat");
+    let binary = build("openat", &dir);
+    std::fs::write(dir.join("data"), "hello").unwrap();
+
+    let process = process("openat", &dir);
+    let status = run(&translator(), &process, &binary, &[&dir.join("")]);
+    assert_passed("openat", status);
+
+    let mode = std::fs::metadata(dir.join("new"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o640);
+}
+
+#[test]
+fn write_lseek() {
+    let dir = TempDir::new("write_lseek");
+    let binary = build("write_lseek", &dir);
+    let small = dir.join("small");
+    let big = dir.join("big");
+
+    let process = process("write_lseek", &dir);
+    let status = run(&translator(), &process, &binary, &[&small, &big]);
+    assert_passed("write_lseek", status);
+
+    assert_eq!(std::fs::read_to_string(&small).unwrap(), "hello world!");
+    let mode = std::fs::metadata(&small).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(std::fs::metadata(&big).unwrap().len(), 200000);
+}
+
+#[test]
+fn stat() {
+    let dir = TempDir::new("stat");
+    let binary = build("stat", &dir);
+    let data = dir.join("data");
+    let link = dir.join("link");
+    std::fs::write(&data, "hello, lac\n").unwrap();
+    std::fs::set_permissions(&data, std::fs::Permissions::from_mode(0o644)).unwrap();
+    std::os::unix::fs::symlink(&data, &link).unwrap();
+    assert_eq!(std::fs::metadata(&data).unwrap().nlink(), 1);
+
+    let process = process("stat", &dir);
+    let status = run(
+        &translator(),
+        &process,
+        &binary,
+        &[&data, &link, &dir.join("")],
+    );
+    assert_passed("stat", status);
+}
+
+#[test]
+fn pipe_dup() {
+    let dir = TempDir::new("pipe_dup");
+    let binary = build("pipe_dup", &dir);
+
+    let process = process("pipe_dup", &dir);
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("pipe_dup", status);
+}
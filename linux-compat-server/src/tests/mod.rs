@@ -0,0 +1,413 @@
+//! Translator tests
+//!
+//! The programs in `tests/programs` are small static Linux binaries built
+//! without libc. They run natively under ptrace, which stops them at every
+//! syscall: the syscall is skipped and translated instead, with the
+//! translator reading and writing the program's memory through
+//! `/proc/<pid>/mem`, much like `lacd` does through `proc:`. Only `clone`,
+//! `exit` and `exit_group` reach the host kernel, `clone` and `exit` once
+//! they are translated too. The mappings the translator makes are carried
+//! out by the program itself: the stopped thread is made to run `mmap`,
+//! `munmap` or `mprotect` before the syscall being translated.
+
+mod dynamic_linking;
+mod file_io;
+mod procfs;
+mod readiness;
+mod sandbox;
+mod sockets;
+mod threads;
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::errno::LinuxErrno;
+use crate::process::Process;
+use crate::translator::{clone_flags, SyscallContext, SyscallResult, SyscallTranslator};
+use crate::usermem::{AddressSpace, ProcMemory};
+
+const SYS_MMAP: u64 = 9;
+const SYS_MPROTECT: u64 = 10;
+const SYS_MUNMAP: u64 = 11;
+const SYS_CLONE: u64 = 56;
+const SYS_EXIT: u64 = 60;
+const SYS_EXIT_GROUP: u64 = 231;
+
+/// Directory removed on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("lac-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Compile `tests/programs/<name>.c` to `output`, without libc
+pub fn compile(name: &str, output: &str, flags: &[&str]) {
+    let programs = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let status = Command::new("cc")
+        .args([
+            "-nostdlib",
+            "-ffreestanding",
+            "-fno-stack-protector",
+            "-fno-tree-loop-distribute-patterns",
+            "-O1",
+        ])
+        .arg("-I")
+        .arg(&programs)
+        .arg("-o")
+        .arg(output)
+        .arg(programs.join(format!("{}.c", name)))
+        .args(flags)
+        .status()
+        .expect("Failed to run cc");
+    assert!(status.success(), "Failed to build {}", name);
+}
+
+/// Compile `tests/programs/<name>.c` into a static binary in `dir`
+pub fn build(name: &str, dir: &TempDir) -> String {
+    let binary = dir.join(name);
+    compile(name, &binary, &["-static", "-no-pie"]);
+    binary
+}
+
+/// Compile `tests/programs/<name>.c` into a static binary in `dir`, with
+/// the host's libc
+pub fn build_with_libc(name: &str, dir: &TempDir) -> String {
+    let binary = dir.join(name);
+    let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/programs")
+        .join(format!("{}.c", name));
+    let status = Command::new("cc")
+        .args(["-static", "-no-pie", "-pthread", "-O1", "-o", &binary])
+        .arg(source)
+        .status()
+        .expect("Failed to run cc");
+    assert!(status.success(), "Failed to build {}", name);
+    binary
+}
+
+/// Translator mapping Linux paths to the same host paths
+pub fn translator() -> SyscallTranslator {
+    let mut mappings = HashMap::new();
+    mappings.insert("/".to_string(), "/".to_string());
+    SyscallTranslator::new(mappings)
+}
+
+/// Process running in `cwd`, with the test's standard streams
+pub fn process(name: &str, cwd: &TempDir) -> Process {
+    let process = Process::new(1, name.to_string());
+    process.inherit_stdio().unwrap();
+    process.set_cwd(cwd.path().to_str().unwrap().to_string());
+    process
+}
+
+/// Thread of a program being run
+struct Tracee {
+    /// Linux thread ID
+    tid: u32,
+    /// Stopped at the exit of a syscall that waits
+    blocked: bool,
+    /// Not yet past the `SIGSTOP` new threads start with
+    starting: bool,
+}
+
+fn get_regs(native: i32) -> libc::user_regs_struct {
+    let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+    unsafe { libc::ptrace(libc::PTRACE_GETREGS, native, 0, &mut regs) };
+    regs
+}
+
+fn set_regs(native: i32, regs: &libc::user_regs_struct) {
+    unsafe { libc::ptrace(libc::PTRACE_SETREGS, native, 0, regs) };
+}
+
+/// Address space of a traced program
+struct TracedAddressSpace {
+    /// Thread stopped at the syscall being translated
+    native: AtomicI32,
+}
+
+impl TracedAddressSpace {
+    /// Make `syscall` in the stopped thread, which then stops at the syscall
+    /// being translated again
+    fn syscall(&self, syscall: u64, args: [u64; 6]) -> Result<(), LinuxErrno> {
+        let native = self.native.load(Ordering::SeqCst);
+        let saved = get_regs(native);
+        let mut regs = saved;
+        regs.orig_rax = syscall;
+        [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9] = args;
+        set_regs(native, &regs);
+        step(native);
+        let result = get_regs(native).rax as i64;
+
+        // Back before the `syscall` instruction, to run it again
+        let mut regs = saved;
+        regs.rip -= 2;
+        regs.rax = saved.orig_rax;
+        set_regs(native, &regs);
+        step(native);
+        set_regs(native, &saved);
+
+        match result {
+            errno @ -4095..=-1 => Err(LinuxErrno::from_redox(-errno as usize)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AddressSpace for TracedAddressSpace {
+    fn map(&self, addr: u64, len: u64, prot: u32) -> Result<(), LinuxErrno> {
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED;
+        self.syscall(
+            SYS_MMAP,
+            [addr, len, prot as u64, flags as u64, u64::MAX, 0],
+        )
+    }
+
+    fn unmap(&self, addr: u64, len: u64) -> Result<(), LinuxErrno> {
+        self.syscall(SYS_MUNMAP, [addr, len, 0, 0, 0, 0])
+    }
+
+    fn protect(&self, addr: u64, len: u64, prot: u32) -> Result<(), LinuxErrno> {
+        self.syscall(SYS_MPROTECT, [addr, len, prot as u64, 0, 0, 0])
+    }
+}
+
+/// Let a stopped thread run to its next stop
+fn step(native: i32) -> i32 {
+    let mut status = 0;
+    unsafe {
+        libc::ptrace(libc::PTRACE_SYSCALL, native, 0, 0);
+        libc::waitpid(native, &mut status, libc::__WALL);
+    }
+    status
+}
+
+/// Store the result of a syscall at its exit stop, along with the TLS
+/// pointer the translator keeps for the thread
+fn set_result(process: &Process, tracee: &Tracee, native: i32, result: i64) {
+    let mut regs = get_regs(native);
+    regs.rax = result as u64;
+    if let Ok(thread) = process.thread(tracee.tid) {
+        if thread.tls_ptr() != 0 {
+            regs.fs_base = thread.tls_ptr();
+        }
+    }
+    set_regs(native, &regs);
+}
+
+/// Handle a stop of a thread, returning the signal to resume it with, or
+/// `None` if it stays stopped
+fn handle_stop(
+    translator: &SyscallTranslator,
+    process: &Process,
+    space: &TracedAddressSpace,
+    tracees: &mut BTreeMap<i32, Tracee>,
+    native: i32,
+    status: i32,
+) -> Option<i32> {
+    let tracee = tracees.get_mut(&native).unwrap();
+    let signal = libc::WSTOPSIG(status);
+    if tracee.starting && signal == libc::SIGSTOP {
+        tracee.starting = false;
+        return Some(0);
+    }
+    if signal != libc::SIGTRAP | 0x80 {
+        // Deliver anything else, like a fault, to the program
+        return Some(signal);
+    }
+
+    // Syscall entry: exit stops are waited for below
+    let mut regs = get_regs(native);
+    if regs.orig_rax == SYS_EXIT_GROUP {
+        return Some(0);
+    }
+    let ctx = SyscallContext {
+        syscall_num: regs.orig_rax,
+        arg0: regs.rdi,
+        arg1: regs.rsi,
+        arg2: regs.rdx,
+        arg3: regs.r10,
+        arg4: regs.r8,
+        arg5: regs.r9,
+        rip: regs.rip,
+        rsp: regs.rsp,
+        tid: tracee.tid,
+    };
+    space.native.store(native, Ordering::SeqCst);
+    let result = translator.translate(process, &ctx);
+
+    match (regs.orig_rax, &result) {
+        (SYS_EXIT, _) => return Some(0),
+        (SYS_CLONE, SyscallResult::Success(tid)) => {
+            // The host creates the thread, leaving the thread IDs to the
+            // translator
+            regs.rdi &= !(clone_flags::CLONE_PARENT_SETTID
+                | clone_flags::CLONE_CHILD_SETTID
+                | clone_flags::CLONE_CHILD_CLEARTID);
+            set_regs(native, &regs);
+            let status = step(native);
+            assert_eq!(
+                status >> 8,
+                libc::SIGTRAP | (libc::PTRACE_EVENT_CLONE << 8),
+                "clone failed"
+            );
+            let mut child = 0 as libc::c_ulong;
+            unsafe { libc::ptrace(libc::PTRACE_GETEVENTMSG, native, 0, &mut child) };
+            tracees.insert(
+                child as i32,
+                Tracee {
+                    tid: *tid as u32,
+                    blocked: false,
+                    starting: true,
+                },
+            );
+            step(native);
+            set_result(process, &tracees[&native], native, *tid);
+            return Some(0);
+        }
+        _ => {}
+    }
+
+    // Skip the native syscall, then store the result at its exit stop
+    regs.orig_rax = u64::MAX;
+    set_regs(native, &regs);
+    step(native);
+    let tracee = tracees.get_mut(&native).unwrap();
+    if matches!(result, SyscallResult::Blocked) {
+        tracee.blocked = true;
+        return None;
+    }
+    set_result(process, tracee, native, result.to_raw());
+    Some(0)
+}
+
+/// Run `binary` with its syscalls translated for `process`, returning its
+/// exit status
+pub fn run(translator: &SyscallTranslator, process: &Process, binary: &str, args: &[&str]) -> i32 {
+    let path = CString::new(binary).unwrap();
+    let mut argv: Vec<CString> = vec![path.clone()];
+    argv.extend(args.iter().map(|arg| CString::new(*arg).unwrap()));
+    let mut argv_ptrs: Vec<*const libc::c_char> = argv.iter().map(|arg| arg.as_ptr()).collect();
+    argv_ptrs.push(std::ptr::null());
+
+    // SAFETY: the child only calls async-signal-safe functions before exec
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        unsafe {
+            libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+            libc::execv(path.as_ptr(), argv_ptrs.as_ptr());
+            libc::_exit(127);
+        }
+    }
+
+    let mut status = 0;
+    // Stopped by the exec
+    unsafe { libc::waitpid(pid, &mut status, 0) };
+    assert!(libc::WIFSTOPPED(status), "{} didn't start", binary);
+    unsafe {
+        libc::ptrace(
+            libc::PTRACE_SETOPTIONS,
+            pid,
+            0,
+            libc::PTRACE_O_TRACESYSGOOD | libc::PTRACE_O_TRACECLONE | libc::PTRACE_O_EXITKILL,
+        );
+    }
+
+    let mem = File::options()
+        .read(true)
+        .write(true)
+        .open(format!("/proc/{}/mem", pid))
+        .unwrap();
+    process.set_user_memory(Arc::new(ProcMemory::new(mem)));
+    let space = Arc::new(TracedAddressSpace {
+        native: AtomicI32::new(pid),
+    });
+    process.set_address_space(space.clone());
+
+    // Threads are polled one by one: waiting for any child could reap those
+    // of tests running alongside
+    let mut tracees = BTreeMap::new();
+    tracees.insert(
+        pid,
+        Tracee {
+            tid: process.pid(),
+            blocked: false,
+            starting: false,
+        },
+    );
+    unsafe { libc::ptrace(libc::PTRACE_SYSCALL, pid, 0, 0) };
+    loop {
+        let mut idle = true;
+        let natives: Vec<i32> = tracees.keys().copied().collect();
+        for native in natives {
+            let tracee = &tracees[&native];
+            if tracee.blocked {
+                if let Some(result) = translator.resume(process, tracee.tid) {
+                    set_result(process, tracee, native, result.to_raw());
+                    tracees.get_mut(&native).unwrap().blocked = false;
+                    unsafe { libc::ptrace(libc::PTRACE_SYSCALL, native, 0, 0) };
+                    idle = false;
+                }
+                continue;
+            }
+
+            let ret = unsafe { libc::waitpid(native, &mut status, libc::WNOHANG | libc::__WALL) };
+            if ret == 0 {
+                continue;
+            }
+            idle = false;
+            if ret < 0 || libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+                if native == pid {
+                    assert!(
+                        !libc::WIFSIGNALED(status),
+                        "{} killed by signal {}",
+                        binary,
+                        libc::WTERMSIG(status)
+                    );
+                    return libc::WEXITSTATUS(status);
+                }
+                tracees.remove(&native);
+                continue;
+            }
+            if let Some(signal) =
+                handle_stop(translator, process, &space, &mut tracees, native, status)
+            {
+                unsafe { libc::ptrace(libc::PTRACE_SYSCALL, native, 0, signal) };
+            }
+        }
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// Assert that a program exited successfully
+pub fn assert_passed(name: &str, status: i32) {
+    assert!(status == 0, "{}: failed check at line {}", name, status);
+}
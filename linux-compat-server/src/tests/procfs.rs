@@ -0,0 +1,24 @@
+use super::{assert_passed, build, run, translator, TempDir};
+use crate::elf_loader;
+use crate::process::Process;
+
+#[test]
+fn procfs() {
+    let dir = TempDir::new("procfs");
+    let binary = build("procfs", &dir);
+    let cwd = dir.path().to_str().unwrap();
+
+    // Set up like lacd does, for /proc to show the executable, mappings and
+    // arguments
+    let process = Process::new(1, binary.clone());
+    process.inherit_stdio().unwrap();
+    process.set_cwd(cwd.to_string());
+    let elf = elf_loader::load_elf(&binary).unwrap();
+    process.setup_memory(&elf).unwrap();
+    process
+        .setup_stack(&elf, &[binary.clone(), cwd.to_string()], &[])
+        .unwrap();
+
+    let status = run(&translator(), &process, &binary, &[cwd]);
+    assert_passed("procfs", status);
+}
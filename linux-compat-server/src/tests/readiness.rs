@@ -0,0 +1,41 @@
+use super::{assert_passed, build, process, run, translator, TempDir};
+
+#[test]
+fn poll_select() {
+    let dir = TempDir::new("poll_select");
+    let binary = build("poll_select", &dir);
+
+    let process = process("poll_select", &dir);
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("poll_select", status);
+}
+
+#[test]
+fn epoll() {
+    let dir = TempDir::new("epoll");
+    let binary = build("epoll", &dir);
+
+    let process = process("epoll", &dir);
+    let status = run(&translator(), &process, &binary, &[&binary]);
+    assert_passed("epoll", status);
+}
+
+#[test]
+fn eventfd() {
+    let dir = TempDir::new("eventfd");
+    let binary = build("eventfd", &dir);
+
+    let process = process("eventfd", &dir);
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("eventfd", status);
+}
+
+#[test]
+fn timerfd() {
+    let dir = TempDir::new("timerfd");
+    let binary = build("timerfd", &dir);
+
+    let process = process("timerfd", &dir);
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("timerfd", status);
+}
@@ -0,0 +1,42 @@
+use std::os::unix::fs::symlink;
+
+use super::{translator, TempDir};
+use crate::errno::LinuxErrno;
+use crate::sandbox::{PathAccess, SandboxConfig};
+
+#[test]
+fn symlinks_are_checked() {
+    let dir = TempDir::new("sandbox");
+    let allowed = dir.join("allowed");
+    let secret = dir.join("secret");
+    std::fs::create_dir(&allowed).unwrap();
+    std::fs::create_dir(&secret).unwrap();
+    std::fs::write(dir.join("secret/key"), "key").unwrap();
+    std::fs::write(dir.join("allowed/data"), "data").unwrap();
+    symlink(&secret, dir.join("allowed/dir")).unwrap();
+    symlink("../secret/key", dir.join("allowed/key")).unwrap();
+    symlink("data", dir.join("allowed/alias")).unwrap();
+    symlink("loop", dir.join("allowed/loop")).unwrap();
+
+    let config = SandboxConfig::parse(&format!("[test]\npath {} rw\n", allowed)).unwrap();
+    let policy = config.policy_for("test").unwrap();
+    let translator = translator();
+    let resolve = |path: &str, follow| {
+        translator.resolve_path(&dir.join(path), PathAccess::READ, Some(&policy), follow)
+    };
+
+    assert_eq!(resolve("allowed/data", true), Ok(dir.join("allowed/data")));
+    // Links within the allowed prefix resolve to their target
+    assert_eq!(resolve("allowed/alias", true), Ok(dir.join("allowed/data")));
+    // Files that don't exist yet can be created
+    assert_eq!(resolve("allowed/new", true), Ok(dir.join("allowed/new")));
+
+    // Links out of it are refused, whether the link is the file itself or
+    // a directory on the way to it
+    assert_eq!(resolve("allowed/key", true), Err(LinuxErrno::EACCES));
+    assert_eq!(resolve("allowed/dir/key", true), Err(LinuxErrno::EACCES));
+    // Unless the link itself is accessed, like by readlink
+    assert_eq!(resolve("allowed/key", false), Ok(dir.join("allowed/key")));
+
+    assert_eq!(resolve("allowed/loop", true), Err(LinuxErrno::ELOOP));
+}
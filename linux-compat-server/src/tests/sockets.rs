@@ -0,0 +1,21 @@
+use super::{assert_passed, build, process, run, translator, TempDir};
+
+#[test]
+fn unix_socket() {
+    let dir = TempDir::new("unix_socket");
+    let binary = build("unix_socket", &dir);
+
+    let process = process("unix_socket", &dir);
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("unix_socket", status);
+}
+
+#[test]
+fn inet_socket() {
+    let dir = TempDir::new("inet_socket");
+    let binary = build("inet_socket", &dir);
+
+    let process = process("inet_socket", &dir);
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("inet_socket", status);
+}
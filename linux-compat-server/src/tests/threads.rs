@@ -0,0 +1,29 @@
+use super::{assert_passed, build, build_with_libc, process, run, translator, TempDir};
+use crate::elf_loader;
+
+#[test]
+fn threads() {
+    let dir = TempDir::new("threads");
+    let binary = build("threads", &dir);
+
+    let process = process("threads", &dir);
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("threads", status);
+    assert_eq!(process.thread_count(), 1);
+}
+
+#[test]
+fn pthreads() {
+    let dir = TempDir::new("pthread");
+    let binary = build_with_libc("pthread", &dir);
+
+    // glibc finds itself through /proc/self/exe, and its program break
+    // after the binary
+    let process = process(&binary, &dir);
+    process
+        .setup_memory(&elf_loader::load_elf(&binary).unwrap())
+        .unwrap();
+    let status = run(&translator(), &process, &binary, &[]);
+    assert_passed("pthread", status);
+    assert_eq!(process.thread_count(), 1);
+}
@@ -0,0 +1,136 @@
+//! timerfd
+//!
+//! Timers are kept on `lacd`'s monotonic clock, whichever clock they were
+//! created on: absolute expirations are converted when the timer is set, so
+//! a realtime timer doesn't follow later changes of the time of day.
+
+use std::time::{Duration, Instant};
+
+use crate::epoll::poll_events;
+use crate::errno::LinuxErrno;
+use crate::translator::open_flags;
+
+/// `timerfd_create` and `timerfd_settime` flags
+pub mod timerfd_flags {
+    use super::open_flags;
+
+    pub const TFD_TIMER_ABSTIME: i32 = 1;
+    pub const TFD_TIMER_CANCEL_ON_SET: i32 = 2;
+    pub const TFD_CLOEXEC: i32 = open_flags::O_CLOEXEC;
+    pub const TFD_NONBLOCK: i32 = open_flags::O_NONBLOCK;
+}
+
+#[derive(Debug, Default)]
+struct TimerState {
+    /// Next expiration, `None` while disarmed
+    next: Option<Instant>,
+    /// Period, zero for a one-shot timer
+    interval: Duration,
+    /// Expirations not read yet
+    expirations: u64,
+}
+
+impl TimerState {
+    /// Count the expirations up to `now`
+    fn update(&mut self, now: Instant) {
+        let Some(next) = self.next.filter(|&next| next <= now) else {
+            return;
+        };
+        if self.interval.is_zero() {
+            self.expirations += 1;
+            self.next = None;
+            return;
+        }
+        let periods = ((now - next).as_nanos() / self.interval.as_nanos()) as u64 + 1;
+        self.expirations = self.expirations.saturating_add(periods);
+        self.next = Some(
+            next + Duration::from_nanos((self.interval.as_nanos() as u64).saturating_mul(periods)),
+        );
+    }
+
+    /// Time to the next expiration, zero while disarmed, and the interval
+    fn setting(&self, now: Instant) -> (Duration, Duration) {
+        let value = self
+            .next
+            .map_or(Duration::ZERO, |next| next.saturating_duration_since(now));
+        (value, self.interval)
+    }
+}
+
+#[derive(Debug)]
+pub struct TimerFd {
+    /// Linux clock the timer was created on
+    clock: i32,
+    state: spin::Mutex<TimerState>,
+}
+
+impl TimerFd {
+    pub fn new(clock: i32) -> Self {
+        Self {
+            clock,
+            state: spin::Mutex::new(TimerState::default()),
+        }
+    }
+
+    pub fn clock(&self) -> i32 {
+        self.clock
+    }
+
+    /// Arm the timer to expire at `next` then every `interval`, or disarm
+    /// it, returning the previous setting
+    ///
+    /// Expirations not read yet are dropped.
+    pub fn set(
+        &self,
+        next: Option<Instant>,
+        interval: Duration,
+        now: Instant,
+    ) -> (Duration, Duration) {
+        let mut state = self.state.lock();
+        state.update(now);
+        let old = state.setting(now);
+        *state = TimerState {
+            next,
+            interval: if next.is_some() {
+                interval
+            } else {
+                Duration::ZERO
+            },
+            expirations: 0,
+        };
+        old
+    }
+
+    /// Time to the next expiration, zero while disarmed, and the interval
+    pub fn get(&self, now: Instant) -> (Duration, Duration) {
+        let mut state = self.state.lock();
+        state.update(now);
+        state.setting(now)
+    }
+
+    /// Next expiration, `None` while disarmed
+    pub fn next(&self) -> Option<Instant> {
+        self.state.lock().next
+    }
+
+    /// Take the expirations since the last read, failing with `EAGAIN` if
+    /// there are none
+    pub fn read(&self, now: Instant) -> Result<u64, LinuxErrno> {
+        let mut state = self.state.lock();
+        state.update(now);
+        match std::mem::take(&mut state.expirations) {
+            0 => Err(LinuxErrno::EAGAIN),
+            expirations => Ok(expirations),
+        }
+    }
+
+    pub fn readiness(&self, now: Instant) -> u32 {
+        let mut state = self.state.lock();
+        state.update(now);
+        if state.expirations > 0 {
+            poll_events::POLLIN | poll_events::POLLRDNORM
+        } else {
+            0
+        }
+    }
+}
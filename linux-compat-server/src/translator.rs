@@ -3,15 +3,39 @@
 //! This module translates Linux syscalls to Redox equivalents.
 
 use std::collections::HashMap;
-use std::ffi::CStr;
-use std::fs::{File, OpenOptions};
+use std::fs::{File, Metadata, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::os::unix::fs::OpenOptionsExt;
-use std::path::Path;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::epoll::{self, epoll_ctl_op, epoll_flags, poll_events, Epoll};
 use crate::errno::LinuxErrno;
+use crate::eventfd::{eventfd_flags, EventFd};
+use crate::futex::{futex_op, FUTEX_BITSET_MATCH_ANY};
+use crate::process::{self, FileObject, MemoryRegion, OpenFile, Process, Thread, MAX_FDS};
+use crate::procfs::{self, Entry, Node, ProcFile};
+use crate::sandbox::{self, PathAccess, SandboxPolicy};
+use crate::socket::{self, msg_flags, socket_type, SockAddr, Socket};
 use crate::syscall_table::LinuxSyscall;
-use redox_syscall::{self, syscall5, syscall6, SYS_FUTEX};
+use crate::timerfd::{timerfd_flags, TimerFd};
+use crate::usermem::UserMemory;
+use crate::vdso::{self, clock_id};
+use syscall::syscall5;
+
+/// Largest transfer of a single `read` or `write`, as on Linux
+const MAX_RW_COUNT: usize = 0x7fff_f000;
+/// Bytes copied between a file and process memory at a time
+const IO_CHUNK: usize = 64 * 1024;
+/// Size of `struct stat` on x86_64
+const STAT_SIZE: usize = 144;
+/// Most events a single `epoll_wait` returns, as on Linux
+const EP_MAX_EVENTS: i32 = i32::MAX / epoll::EPOLL_EVENT_SIZE as i32;
+/// Most symlinks followed while resolving a path, as on Linux
+const MAX_SYMLINKS: usize = 40;
+const PAGE_SIZE: u64 = 4096;
 
 /// Syscall context containing all registers
 #[derive(Debug, Clone, Default)]
@@ -30,6 +54,8 @@ pub struct SyscallContext {
     pub arg4: u64,
     /// Sixth argument (r9)
     pub arg5: u64,
+    /// Linux thread making the syscall
+    pub tid: u32,
     /// Instruction pointer
     pub rip: u64,
     /// Stack pointer
@@ -52,6 +78,18 @@ pub enum SyscallResult {
     Error(LinuxErrno),
     /// Syscall not yet implemented
     NotImplemented,
+    /// The calling thread waits: [`SyscallTranslator::resume`] gives the
+    /// result once it can continue
+    Blocked,
+}
+
+impl From<Result<i64, LinuxErrno>> for SyscallResult {
+    fn from(result: Result<i64, LinuxErrno>) -> Self {
+        match result {
+            Ok(val) => Self::Success(val),
+            Err(errno) => Self::Error(errno),
+        }
+    }
 }
 
 impl SyscallResult {
@@ -61,6 +99,8 @@ impl SyscallResult {
             Self::Success(val) => *val,
             Self::Error(errno) => -(*errno as i32 as i64),
             Self::NotImplemented => -(LinuxErrno::ENOSYS as i32 as i64),
+            // A wait that is given up on is interrupted
+            Self::Blocked => -(LinuxErrno::EINTR as i32 as i64),
         }
     }
 }
@@ -69,22 +109,6 @@ impl SyscallResult {
 pub struct SyscallTranslator {
     /// Path mappings (Linux path → Redox path)
     path_mappings: HashMap<String, String>,
-    /// Open file descriptors (Linux fd → Redox file)
-    fds: spin::RwLock<HashMap<i32, FileDescriptor>>,
-    /// Next available fd
-    next_fd: std::sync::atomic::AtomicI32,
-}
-
-/// File descriptor wrapper
-pub struct FileDescriptor {
-    /// Redox file handle
-    file: Option<File>,
-    /// File path
-    path: String,
-    /// File flags
-    flags: i32,
-    /// Is a pipe
-    is_pipe: bool,
 }
 
 /// Linux open flags
@@ -113,6 +137,69 @@ pub mod open_flags {
     pub const O_TMPFILE: i32 = 0o20200000;
 }
 
+/// Progress of a syscall that waits for file descriptors
+pub enum Wait {
+    /// Finished, with the syscall's result
+    Done(i64),
+    /// Nothing is ready: wait up to this long, or for ever
+    Pending(Option<Duration>),
+}
+
+/// Handler of a syscall that waits for file descriptors
+///
+/// Once the timeout has passed it is run with `expired` set, and must then
+/// finish as if it had been given no time to wait.
+pub type WaitHandler =
+    fn(&SyscallTranslator, &Process, &SyscallContext, expired: bool) -> Result<Wait, LinuxErrno>;
+
+/// Syscall of a thread waiting for file descriptors, checked again each time
+/// the thread is resumed
+#[derive(Clone)]
+pub struct PendingCall {
+    ctx: SyscallContext,
+    handler: WaitHandler,
+    deadline: Option<Instant>,
+}
+
+/// Linux clone flags
+pub mod clone_flags {
+    pub const CSIGNAL: u64 = 0xff;
+    pub const CLONE_VM: u64 = 0x100;
+    pub const CLONE_FS: u64 = 0x200;
+    pub const CLONE_FILES: u64 = 0x400;
+    pub const CLONE_SIGHAND: u64 = 0x800;
+    pub const CLONE_THREAD: u64 = 0x10000;
+    pub const CLONE_SYSVSEM: u64 = 0x40000;
+    pub const CLONE_SETTLS: u64 = 0x80000;
+    pub const CLONE_PARENT_SETTID: u64 = 0x100000;
+    pub const CLONE_CHILD_CLEARTID: u64 = 0x200000;
+    pub const CLONE_DETACHED: u64 = 0x400000;
+    pub const CLONE_CHILD_SETTID: u64 = 0x01000000;
+
+    /// Flags every thread is created with: one address space, fd table
+    /// and set of signal handlers per process
+    pub const THREAD_REQUIRED: u64 =
+        CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD;
+    /// Flags threads may be created with
+    pub const THREAD_OPTIONAL: u64 = CLONE_SYSVSEM
+        | CLONE_SETTLS
+        | CLONE_PARENT_SETTID
+        | CLONE_CHILD_CLEARTID
+        | CLONE_DETACHED
+        | CLONE_CHILD_SETTID;
+}
+
+/// `arch_prctl` codes
+pub mod arch_prctl_code {
+    pub const ARCH_SET_GS: i32 = 0x1001;
+    pub const ARCH_SET_FS: i32 = 0x1002;
+    pub const ARCH_GET_FS: i32 = 0x1003;
+    pub const ARCH_GET_GS: i32 = 0x1004;
+}
+
+/// End of the user half of the x86_64 address space
+const USER_ADDR_END: u64 = 0x0000_8000_0000_0000;
+
 /// Linux seek whence values
 pub mod seek_whence {
     pub const SEEK_SET: i32 = 0;
@@ -141,73 +228,156 @@ pub mod at_flags {
 impl SyscallTranslator {
     /// Create a new syscall translator
     pub fn new(path_mappings: HashMap<String, String>) -> Self {
-        let mut fds = HashMap::new();
-
-        // Set up standard file descriptors
-        fds.insert(
-            0,
-            FileDescriptor {
-                file: None, // stdin
-                path: "/dev/stdin".to_string(),
-                flags: open_flags::O_RDONLY,
-                is_pipe: false,
-            },
-        );
-        fds.insert(
-            1,
-            FileDescriptor {
-                file: None, // stdout
-                path: "/dev/stdout".to_string(),
-                flags: open_flags::O_WRONLY,
-                is_pipe: false,
-            },
-        );
-        fds.insert(
-            2,
-            FileDescriptor {
-                file: None, // stderr
-                path: "/dev/stderr".to_string(),
-                flags: open_flags::O_WRONLY,
-                is_pipe: false,
-            },
-        );
+        Self { path_mappings }
+    }
 
-        Self {
-            path_mappings,
-            fds: spin::RwLock::new(fds),
-            next_fd: std::sync::atomic::AtomicI32::new(3),
+    /// Translate an absolute Linux path to Redox path
+    pub fn translate_path(&self, linux_path: &str) -> String {
+        // The most specific mapping wins, `/` covers everything else
+        let mapping = self
+            .path_mappings
+            .iter()
+            .filter(|(linux_prefix, _)| sandbox::is_below(linux_path, linux_prefix))
+            .max_by_key(|(linux_prefix, _)| linux_prefix.len());
+
+        match mapping {
+            Some((linux_prefix, redox_prefix)) if linux_prefix == "/" => {
+                format!("{}{}", redox_prefix, &linux_path[1..])
+            }
+            Some((linux_prefix, redox_prefix)) => {
+                format!("{}{}", redox_prefix, &linux_path[linux_prefix.len()..])
+            }
+            None => format!("file:{}", linux_path),
         }
     }
 
-    /// Translate a Linux path to Redox path
-    pub fn translate_path(&self, linux_path: &str) -> String {
-        // Check for exact matches first
-        if let Some(redox_path) = self.path_mappings.get(linux_path) {
-            return redox_path.clone();
+    /// Check a Linux path against the sandbox and translate it
+    ///
+    /// Path-taking syscalls must resolve their path through this, so the
+    /// sandbox sees the path before it is handed to Redox. Under a policy
+    /// restricting paths the symlinks in the path are resolved here, the last
+    /// component only if `follow`, and their target is checked as well. The
+    /// returned path then has no symlinks left, so a link below an allowed
+    /// prefix can't lead out of it.
+    pub fn resolve_path(
+        &self,
+        linux_path: &str,
+        access: PathAccess,
+        sandbox: Option<&SandboxPolicy>,
+        follow: bool,
+    ) -> Result<String, LinuxErrno> {
+        let Some(sandbox) = sandbox.filter(|sandbox| sandbox.restricts_paths()) else {
+            return Ok(self.translate_path(linux_path));
+        };
+        sandbox.check_path(linux_path, access)?;
+        let resolved = self.resolve_links(linux_path, follow)?;
+        if resolved != sandbox::normalize(linux_path) {
+            sandbox.check_path(&resolved, access)?;
         }
+        Ok(self.translate_path(&resolved))
+    }
 
-        // Check for prefix matches
-        for (linux_prefix, redox_prefix) in &self.path_mappings {
-            if linux_path.starts_with(linux_prefix) && linux_prefix != "/" {
-                let suffix = &linux_path[linux_prefix.len()..];
-                return format!("{}{}", redox_prefix, suffix);
+    /// Resolve the symlinks in the absolute Linux path `path`, the last
+    /// component only if `follow`
+    ///
+    /// Components that don't exist are kept as they are, for paths about to
+    /// be created.
+    fn resolve_links(&self, path: &str, follow: bool) -> Result<String, LinuxErrno> {
+        let mut path = sandbox::normalize(path);
+        let mut links = 0;
+        'lookup: loop {
+            let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+            let mut resolved = String::new();
+            for (i, component) in components.iter().enumerate() {
+                let current = format!("{}/{}", resolved, component);
+                let last = i + 1 == components.len();
+                let host_path = self.translate_path(&current);
+                let is_link = (follow || !last)
+                    && std::fs::symlink_metadata(&host_path).is_ok_and(|m| m.is_symlink());
+                if !is_link {
+                    resolved = current;
+                    continue;
+                }
+
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(LinuxErrno::ELOOP);
+                }
+                let target = std::fs::read_link(&host_path)?;
+                let target = target.to_str().ok_or(LinuxErrno::ENOENT)?;
+                // Absolute targets are Linux paths too
+                let base = if target.starts_with('/') {
+                    ""
+                } else {
+                    &resolved
+                };
+                path = sandbox::normalize(&format!(
+                    "{}/{}/{}",
+                    base,
+                    target,
+                    components[i + 1..].join("/")
+                ));
+                continue 'lookup;
+            }
+            if resolved.is_empty() {
+                resolved.push('/');
             }
+            return Ok(resolved);
         }
+    }
 
-        // Default: prepend file:
-        format!("file:{}", linux_path)
+    /// Absolute, normalized Linux path of `path`, which is relative to
+    /// `dirfd` like the path argument of the `*at` syscalls
+    fn path_at(&self, process: &Process, dirfd: i32, path: &str) -> Result<String, LinuxErrno> {
+        if path.is_empty() {
+            return Err(LinuxErrno::ENOENT);
+        }
+        let base = if path.starts_with('/') {
+            String::new()
+        } else if dirfd == at_flags::AT_FDCWD {
+            process.cwd()
+        } else {
+            let dir = process.get_fd(dirfd)?;
+            let is_dir = match &dir.object {
+                FileObject::Proc(file) => file.is_dir(),
+                _ => dir.host().ok_or(LinuxErrno::ENOTDIR)?.metadata()?.is_dir(),
+            };
+            if !is_dir {
+                return Err(LinuxErrno::ENOTDIR);
+            }
+            dir.path.clone()
+        };
+        Ok(sandbox::normalize(&format!("{}/{}", base, path)))
     }
 
-    /// Allocate a new file descriptor
-    fn alloc_fd(&self) -> i32 {
-        self.next_fd
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    /// Look up a path below /proc or /sys, once the sandbox allows `access`
+    /// to it
+    fn lookup_virtual(
+        &self,
+        process: &Process,
+        path: &str,
+        access: PathAccess,
+        follow: bool,
+    ) -> Result<Entry, LinuxErrno> {
+        if let Some(sandbox) = process.sandbox() {
+            sandbox.check_path(path, access)?;
+        }
+        procfs::lookup(process, path, follow)
     }
 
-    /// Translate and execute a syscall
-    pub fn translate(&self, ctx: &SyscallContext) -> SyscallResult {
+    /// Translate and execute a syscall made by `process`
+    ///
+    /// The sandbox policy of the process is checked before anything is
+    /// dispatched to Redox.
+    pub fn translate(&self, process: &Process, ctx: &SyscallContext) -> SyscallResult {
         let syscall = ctx.syscall();
 
+        if let Some(sandbox) = process.sandbox() {
+            if let Err(errno) = sandbox.check_syscall(ctx.syscall_num) {
+                return SyscallResult::Error(errno);
+            }
+        }
+
         log::debug!(
             "Translating syscall: {} ({:#x})",
             syscall.name(),
@@ -216,17 +386,17 @@ impl SyscallTranslator {
 
         match syscall {
             // File I/O
-            LinuxSyscall::Read => self.sys_read(ctx),
-            LinuxSyscall::Write => self.sys_write(ctx),
-            LinuxSyscall::Open => self.sys_open(ctx),
-            LinuxSyscall::Openat => self.sys_openat(ctx),
-            LinuxSyscall::Close => self.sys_close(ctx),
-            LinuxSyscall::Lseek => self.sys_lseek(ctx),
-            LinuxSyscall::Dup => self.sys_dup(ctx),
-            LinuxSyscall::Dup2 => self.sys_dup2(ctx),
-            LinuxSyscall::Dup3 => self.sys_dup3(ctx),
-            LinuxSyscall::Pipe => self.sys_pipe(ctx),
-            LinuxSyscall::Pipe2 => self.sys_pipe2(ctx),
+            LinuxSyscall::Read => self.wait(process, ctx, Self::sys_read),
+            LinuxSyscall::Write => self.wait(process, ctx, Self::sys_write),
+            LinuxSyscall::Open => self.sys_open(process, ctx).into(),
+            LinuxSyscall::Openat => self.sys_openat(process, ctx).into(),
+            LinuxSyscall::Close => self.sys_close(process, ctx).into(),
+            LinuxSyscall::Lseek => self.sys_lseek(process, ctx).into(),
+            LinuxSyscall::Dup => self.sys_dup(process, ctx).into(),
+            LinuxSyscall::Dup2 => self.sys_dup2(process, ctx).into(),
+            LinuxSyscall::Dup3 => self.sys_dup3(process, ctx).into(),
+            LinuxSyscall::Pipe => self.sys_pipe(process, ctx).into(),
+            LinuxSyscall::Pipe2 => self.sys_pipe2(process, ctx).into(),
             LinuxSyscall::Access => self.sys_access(ctx),
             LinuxSyscall::Faccessat => self.sys_faccessat(ctx),
             LinuxSyscall::Getcwd => self.sys_getcwd(ctx),
@@ -235,21 +405,57 @@ impl SyscallTranslator {
             LinuxSyscall::Rmdir => self.sys_rmdir(ctx),
             LinuxSyscall::Unlink => self.sys_unlink(ctx),
             LinuxSyscall::Unlinkat => self.sys_unlinkat(ctx),
-            LinuxSyscall::Stat
-            | LinuxSyscall::Fstat
-            | LinuxSyscall::Lstat
-            | LinuxSyscall::Newfstatat => self.sys_stat(ctx),
-            LinuxSyscall::Getdents64 => self.sys_getdents64(ctx),
+            LinuxSyscall::Stat => self.sys_stat(process, ctx).into(),
+            LinuxSyscall::Fstat => self.sys_fstat(process, ctx).into(),
+            LinuxSyscall::Lstat => self.sys_lstat(process, ctx).into(),
+            LinuxSyscall::Newfstatat => self.sys_newfstatat(process, ctx).into(),
+            LinuxSyscall::Readlink => self.sys_readlink(process, ctx).into(),
+            LinuxSyscall::Readlinkat => self.sys_readlinkat(process, ctx).into(),
+            LinuxSyscall::Getdents64 => self.sys_getdents64(process, ctx).into(),
+
+            // Readiness
+            LinuxSyscall::Poll => self.wait(process, ctx, Self::sys_poll),
+            LinuxSyscall::Ppoll => self.wait(process, ctx, Self::sys_ppoll),
+            LinuxSyscall::Select => self.wait(process, ctx, Self::sys_select),
+            LinuxSyscall::Pselect6 => self.wait(process, ctx, Self::sys_pselect6),
+            LinuxSyscall::EpollCreate => self.sys_epoll_create(process, ctx).into(),
+            LinuxSyscall::EpollCreate1 => self.sys_epoll_create1(process, ctx).into(),
+            LinuxSyscall::EpollCtl => self.sys_epoll_ctl(process, ctx).into(),
+            LinuxSyscall::EpollWait | LinuxSyscall::EpollPwait => {
+                self.wait(process, ctx, Self::sys_epoll_wait)
+            }
+            LinuxSyscall::Eventfd => self.sys_eventfd(process, ctx).into(),
+            LinuxSyscall::Eventfd2 => self.sys_eventfd2(process, ctx).into(),
+            LinuxSyscall::TimerfdCreate => self.sys_timerfd_create(process, ctx).into(),
+            LinuxSyscall::TimerfdSettime => self.sys_timerfd_settime(process, ctx).into(),
+            LinuxSyscall::TimerfdGettime => self.sys_timerfd_gettime(process, ctx).into(),
+
+            // Sockets
+            LinuxSyscall::Socket => self.sys_socket(process, ctx).into(),
+            LinuxSyscall::Socketpair => self.sys_socketpair(process, ctx).into(),
+            LinuxSyscall::Bind => self.sys_bind(process, ctx).into(),
+            LinuxSyscall::Listen => self.sys_listen(process, ctx).into(),
+            LinuxSyscall::Connect => self.wait(process, ctx, Self::sys_connect),
+            LinuxSyscall::Accept => self.wait(process, ctx, Self::sys_accept),
+            LinuxSyscall::Accept4 => self.wait(process, ctx, Self::sys_accept4),
+            LinuxSyscall::Sendto => self.wait(process, ctx, Self::sys_sendto),
+            LinuxSyscall::Recvfrom => self.wait(process, ctx, Self::sys_recvfrom),
+            LinuxSyscall::Shutdown => self.sys_shutdown(process, ctx).into(),
+            LinuxSyscall::Getsockname => self.sys_getsockname(process, ctx).into(),
+            LinuxSyscall::Getpeername => self.sys_getpeername(process, ctx).into(),
+            LinuxSyscall::Setsockopt => self.sys_setsockopt(process, ctx).into(),
+            LinuxSyscall::Getsockopt => self.sys_getsockopt(process, ctx).into(),
 
             // Process management
-            LinuxSyscall::Getpid => self.sys_getpid(ctx),
-            LinuxSyscall::Getppid => self.sys_getppid(ctx),
-            LinuxSyscall::Gettid => self.sys_gettid(ctx),
+            LinuxSyscall::Getpid => self.sys_getpid(process).into(),
+            LinuxSyscall::Getppid => self.sys_getppid(process).into(),
+            LinuxSyscall::Gettid => self.sys_gettid(ctx).into(),
             LinuxSyscall::Getuid | LinuxSyscall::Geteuid => self.sys_getuid(ctx),
             LinuxSyscall::Getgid | LinuxSyscall::Getegid => self.sys_getgid(ctx),
-            LinuxSyscall::Exit | LinuxSyscall::ExitGroup => self.sys_exit(ctx),
+            LinuxSyscall::Exit => self.sys_exit(process, ctx).into(),
+            LinuxSyscall::ExitGroup => self.sys_exit_group(process, ctx).into(),
             LinuxSyscall::Fork | LinuxSyscall::Vfork => self.sys_fork(ctx),
-            LinuxSyscall::Clone => self.sys_clone(ctx),
+            LinuxSyscall::Clone => self.sys_clone(process, ctx).into(),
             LinuxSyscall::Execve => self.sys_execve(ctx),
             LinuxSyscall::Wait4 => self.sys_wait4(ctx),
 
@@ -261,24 +467,28 @@ impl SyscallTranslator {
             LinuxSyscall::RtSigprocmask => self.sys_sigprocmask(ctx),
 
             // Memory management
-            LinuxSyscall::Brk => self.sys_brk(ctx),
-            LinuxSyscall::Mmap => self.sys_mmap(ctx),
-            LinuxSyscall::Munmap => self.sys_munmap(ctx),
-            LinuxSyscall::Mprotect => self.sys_mprotect(ctx),
+            LinuxSyscall::Brk => self.sys_brk(process, ctx).into(),
+            LinuxSyscall::Mmap => self.sys_mmap(process, ctx).into(),
+            LinuxSyscall::Munmap => self.sys_munmap(process, ctx).into(),
+            LinuxSyscall::Mprotect => self.sys_mprotect(process, ctx).into(),
 
             // Time
-            LinuxSyscall::ClockGettime => self.sys_clock_gettime(ctx),
-            LinuxSyscall::Gettimeofday => self.sys_gettimeofday(ctx),
+            LinuxSyscall::ClockGettime => self.sys_clock_gettime(process, ctx).into(),
+            LinuxSyscall::Gettimeofday => self.sys_gettimeofday(process, ctx).into(),
             LinuxSyscall::Nanosleep => self.sys_nanosleep(ctx),
 
             // Misc
             LinuxSyscall::Uname => self.sys_uname(ctx),
             LinuxSyscall::Getrandom => self.sys_getrandom(ctx),
-            LinuxSyscall::SetTidAddress => self.sys_set_tid_address(ctx),
-            LinuxSyscall::Futex => self.sys_futex(ctx),
+            LinuxSyscall::SetTidAddress => self.sys_set_tid_address(process, ctx).into(),
+            LinuxSyscall::Futex => match self.sys_futex(process, ctx) {
+                Ok(Some(val)) => SyscallResult::Success(val),
+                Ok(None) => SyscallResult::Blocked,
+                Err(errno) => SyscallResult::Error(errno),
+            },
             LinuxSyscall::FutexWaitv => self.sys_futex_waitv(ctx),
             LinuxSyscall::Prlimit64 => self.sys_prlimit64(ctx),
-            LinuxSyscall::ArchPrctl => self.sys_arch_prctl(ctx),
+            LinuxSyscall::ArchPrctl => self.sys_arch_prctl(process, ctx).into(),
 
             _ => {
                 log::warn!(
@@ -291,204 +501,359 @@ impl SyscallTranslator {
         }
     }
 
+    /// Result of the syscall thread `tid` made that reported
+    /// [`SyscallResult::Blocked`], once the thread can continue
+    pub fn resume(&self, process: &Process, tid: u32) -> Option<SyscallResult> {
+        let now = Instant::now();
+        if let Some(result) = process.futexes().poll(tid, now) {
+            return Some(result.into());
+        }
+
+        let thread = process.thread(tid).ok()?;
+        let call = thread.pending()?;
+        let expired = call.deadline.is_some_and(|deadline| deadline <= now);
+        let result = match (call.handler)(self, process, &call.ctx, expired) {
+            Ok(Wait::Done(val)) => SyscallResult::Success(val),
+            Ok(Wait::Pending(_)) => return None,
+            Err(errno) => SyscallResult::Error(errno),
+        };
+        thread.set_pending(None);
+        Some(result)
+    }
+
+    /// Earliest time [`SyscallTranslator::resume`] may finish the wait of
+    /// `tid` without any of its files changing: when the wait times out, or
+    /// when a timerfd of the process expires
+    pub fn wakeup(&self, process: &Process, tid: u32) -> Option<Instant> {
+        let timeout = process
+            .thread(tid)
+            .ok()
+            .and_then(|thread| thread.pending())
+            .and_then(|call| call.deadline);
+        let timers = process
+            .fds()
+            .into_iter()
+            .filter_map(|(_, file)| match &file.object {
+                FileObject::TimerFd(timer) => timer.next(),
+                _ => None,
+            });
+        timeout
+            .into_iter()
+            .chain(process.futexes().deadline(tid))
+            .chain(timers)
+            .min()
+    }
+
+    /// Run a syscall that may wait for file descriptors, parking the
+    /// calling thread if nothing is ready
+    fn wait(&self, process: &Process, ctx: &SyscallContext, handler: WaitHandler) -> SyscallResult {
+        let timeout = match handler(self, process, ctx, false) {
+            Ok(Wait::Done(val)) => return SyscallResult::Success(val),
+            Ok(Wait::Pending(timeout)) => timeout,
+            Err(errno) => return SyscallResult::Error(errno),
+        };
+        let thread = match process.thread(ctx.tid) {
+            Ok(thread) => thread,
+            Err(errno) => return SyscallResult::Error(errno),
+        };
+        thread.set_pending(Some(PendingCall {
+            ctx: ctx.clone(),
+            handler,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        }));
+        SyscallResult::Blocked
+    }
+
     // === File I/O syscalls ===
 
-    fn sys_read(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_read(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        _expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
         let fd = ctx.arg0 as i32;
-        let buf_ptr = ctx.arg1 as *mut u8;
-        let count = ctx.arg2 as usize;
+        let buf = ctx.arg1;
+        let count = (ctx.arg2 as usize).min(MAX_RW_COUNT);
 
-        let fds = self.fds.read();
-        match fds.get(&fd) {
-            Some(fd_info) => {
-                if let Some(ref file) = fd_info.file {
-                    // Would need unsafe to read into the buffer
-                    // For now, return success with 0 bytes
-                    SyscallResult::Success(0)
-                } else {
-                    // Standard I/O
-                    match fd {
-                        0 => {
-                            // stdin - would read from actual stdin
-                            SyscallResult::Success(0)
-                        }
-                        _ => SyscallResult::Error(LinuxErrno::EBADF),
-                    }
-                }
+        let file = process.get_fd(fd)?;
+        if file.flags & open_flags::O_ACCMODE == open_flags::O_WRONLY {
+            return Err(LinuxErrno::EBADF);
+        }
+        let memory = process.user_memory()?;
+        if let Some(host) = file.host() {
+            return match read_host(&*memory, host, buf, count) {
+                Ok(len) => Ok(Wait::Done(len)),
+                Err(errno) => would_block(&file, errno),
+            };
+        }
+        if let FileObject::Proc(proc_file) = &file.object {
+            let data = proc_file.read(count)?;
+            memory.write(buf, &data)?;
+            return Ok(Wait::Done(data.len() as i64));
+        }
+        let value = match &file.object {
+            FileObject::EventFd(eventfd) if count >= 8 => eventfd.read(),
+            FileObject::TimerFd(timerfd) if count >= 8 => timerfd.read(Instant::now()),
+            _ => Err(LinuxErrno::EINVAL),
+        };
+        match value {
+            Ok(value) => {
+                memory.write_u64(buf, value)?;
+                Ok(Wait::Done(8))
             }
-            None => SyscallResult::Error(LinuxErrno::EBADF),
+            Err(errno) => would_block(&file, errno),
         }
     }
 
-    fn sys_write(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_write(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        _expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
         let fd = ctx.arg0 as i32;
-        let buf_ptr = ctx.arg1 as *const u8;
-        let count = ctx.arg2 as usize;
+        let buf = ctx.arg1;
+        let count = (ctx.arg2 as usize).min(MAX_RW_COUNT);
 
-        let fds = self.fds.read();
-        match fds.get(&fd) {
-            Some(fd_info) => {
-                if let Some(ref file) = fd_info.file {
-                    // Would need unsafe to write from the buffer
-                    SyscallResult::Success(count as i64)
-                } else {
-                    // Standard I/O (stdout/stderr)
-                    match fd {
-                        1 | 2 => {
-                            // Would write to actual stdout/stderr
-                            SyscallResult::Success(count as i64)
-                        }
-                        _ => SyscallResult::Error(LinuxErrno::EBADF),
-                    }
+        let file = process.get_fd(fd)?;
+        if file.flags & open_flags::O_ACCMODE == open_flags::O_RDONLY {
+            return Err(LinuxErrno::EBADF);
+        }
+        let memory = process.user_memory()?;
+        if let Some(host) = file.host() {
+            return match write_host(&*memory, host, buf, count) {
+                Ok(len) => Ok(Wait::Done(len)),
+                Err(errno) => would_block(&file, errno),
+            };
+        }
+        match &file.object {
+            FileObject::EventFd(eventfd) if count >= 8 => {
+                let mut value = [0; 8];
+                memory.read(buf, &mut value)?;
+                match eventfd.write(u64::from_le_bytes(value)) {
+                    Ok(()) => Ok(Wait::Done(8)),
+                    Err(errno) => would_block(&file, errno),
                 }
             }
-            None => SyscallResult::Error(LinuxErrno::EBADF),
+            _ => Err(LinuxErrno::EINVAL),
         }
     }
 
-    fn sys_open(&self, ctx: &SyscallContext) -> SyscallResult {
-        let path_ptr = ctx.arg0 as *const i8;
+    fn sys_open(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let path = process.user_memory()?.read_cstr(ctx.arg0)?;
         let flags = ctx.arg1 as i32;
         let mode = ctx.arg2 as u32;
 
-        // Would need to read the path string from process memory
-        // For now, simulate success
-        let fd = self.alloc_fd();
-        self.fds.write().insert(
-            fd,
-            FileDescriptor {
-                file: None,
-                path: String::new(),
-                flags,
-                is_pipe: false,
-            },
-        );
-
-        SyscallResult::Success(fd as i64)
+        self.open_at(process, at_flags::AT_FDCWD, &path, flags, mode)
     }
 
-    fn sys_openat(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_openat(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
         let dirfd = ctx.arg0 as i32;
-        let path_ptr = ctx.arg1 as *const i8;
+        let path = process.user_memory()?.read_cstr(ctx.arg1)?;
         let flags = ctx.arg2 as i32;
         let mode = ctx.arg3 as u32;
 
-        // Similar to open, but relative to dirfd
-        let fd = self.alloc_fd();
-        self.fds.write().insert(
-            fd,
-            FileDescriptor {
-                file: None,
-                path: String::new(),
-                flags,
-                is_pipe: false,
-            },
-        );
-
-        SyscallResult::Success(fd as i64)
+        self.open_at(process, dirfd, &path, flags, mode)
     }
 
-    fn sys_close(&self, ctx: &SyscallContext) -> SyscallResult {
-        let fd = ctx.arg0 as i32;
+    fn open_at(
+        &self,
+        process: &Process,
+        dirfd: i32,
+        path: &str,
+        flags: i32,
+        mode: u32,
+    ) -> Result<i64, LinuxErrno> {
+        use open_flags::*;
+
+        if flags & O_TMPFILE == O_TMPFILE {
+            return Err(LinuxErrno::EOPNOTSUPP);
+        }
+        let accmode = flags & O_ACCMODE;
+        let mut access = match accmode {
+            O_RDONLY => PathAccess::READ,
+            O_WRONLY => PathAccess::WRITE,
+            O_RDWR => PathAccess::READ | PathAccess::WRITE,
+            _ => return Err(LinuxErrno::EINVAL),
+        };
+        if flags & (O_CREAT | O_TRUNC) != 0 {
+            access |= PathAccess::WRITE;
+        }
+        let path = self.path_at(process, dirfd, path)?;
+        if procfs::is_virtual(&path) {
+            return match self.lookup_virtual(process, &path, access, flags & O_NOFOLLOW == 0)? {
+                Entry::Path(target) => {
+                    self.open_at(process, at_flags::AT_FDCWD, &target, flags, mode)
+                }
+                Entry::Node(path, node) => {
+                    let file = virtual_file(path, node, flags)?;
+                    Ok(process.alloc_fd(file, flags & O_CLOEXEC != 0)? as i64)
+                }
+            };
+        }
+        let sandbox = process.sandbox();
+        let redox_path =
+            self.resolve_path(&path, access, sandbox.as_deref(), flags & O_NOFOLLOW == 0)?;
 
-        if self.fds.write().remove(&fd).is_some() {
-            SyscallResult::Success(0)
-        } else {
-            SyscallResult::Error(LinuxErrno::EBADF)
+        if flags & O_NOFOLLOW != 0 && std::fs::symlink_metadata(&redox_path)?.is_symlink() {
+            return Err(LinuxErrno::ELOOP);
+        }
+
+        let mut options = OpenOptions::new();
+        options
+            .read(accmode != O_WRONLY)
+            .write(accmode != O_RDONLY)
+            .append(flags & O_APPEND != 0)
+            .truncate(flags & O_TRUNC != 0 && accmode != O_RDONLY);
+        if flags & O_CREAT != 0 {
+            let mut create = OpenOptions::new();
+            create
+                .create(flags & O_EXCL == 0)
+                .create_new(flags & O_EXCL != 0)
+                .mode(mode & 0o7777);
+            if accmode == O_RDONLY {
+                // Redox only creates files opened for writing
+                create.write(true).open(&redox_path)?;
+            } else {
+                options = create;
+                options
+                    .write(true)
+                    .read(accmode == O_RDWR)
+                    .append(flags & O_APPEND != 0)
+                    .truncate(flags & O_TRUNC != 0);
+            }
+        }
+        let file = options.open(&redox_path)?;
+        let metadata = file.metadata()?;
+
+        // The sandbox checked a path without symlinks. Should one have been
+        // swapped in since, the open landed somewhere else.
+        if sandbox.is_some_and(|sandbox| sandbox.restricts_paths()) {
+            let checked = std::fs::symlink_metadata(&redox_path)?;
+            if (checked.dev(), checked.ino()) != (metadata.dev(), metadata.ino()) {
+                log::warn!("Sandbox denied {}, changed while opening", path);
+                return Err(LinuxErrno::EACCES);
+            }
         }
+
+        let is_dir = metadata.is_dir();
+        if flags & O_DIRECTORY != 0 && !is_dir {
+            return Err(LinuxErrno::ENOTDIR);
+        }
+        if is_dir && accmode != O_RDONLY {
+            return Err(LinuxErrno::EISDIR);
+        }
+
+        let file = OpenFile {
+            object: FileObject::Host(file),
+            path,
+            flags,
+        };
+        let fd = process.alloc_fd(file, flags & O_CLOEXEC != 0)?;
+        Ok(fd as i64)
+    }
+
+    fn sys_close(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        process.close_fd(ctx.arg0 as i32)?;
+        Ok(0)
     }
 
-    fn sys_lseek(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_lseek(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
         let fd = ctx.arg0 as i32;
         let offset = ctx.arg1 as i64;
         let whence = ctx.arg2 as i32;
 
-        // Would seek in the actual file
-        SyscallResult::Success(offset)
+        let file = process.get_fd(fd)?;
+        let seekable = file.host().is_some() || matches!(file.object, FileObject::Proc(_));
+        if !seekable {
+            return Err(LinuxErrno::ESPIPE);
+        }
+        let pos = match whence {
+            seek_whence::SEEK_SET => {
+                SeekFrom::Start(u64::try_from(offset).map_err(|_| LinuxErrno::EINVAL)?)
+            }
+            seek_whence::SEEK_CUR => SeekFrom::Current(offset),
+            seek_whence::SEEK_END => SeekFrom::End(offset),
+            _ => return Err(LinuxErrno::EINVAL),
+        };
+        let pos = match (&file.object, file.host()) {
+            (FileObject::Proc(proc_file), _) => proc_file.seek(pos)?,
+            (_, host) => host.ok_or(LinuxErrno::ESPIPE)?.seek(pos)?,
+        };
+        i64::try_from(pos).map_err(|_| LinuxErrno::EOVERFLOW)
     }
 
-    fn sys_dup(&self, ctx: &SyscallContext) -> SyscallResult {
-        let oldfd = ctx.arg0 as i32;
-
-        let fds = self.fds.read();
-        if let Some(fd_info) = fds.get(&oldfd) {
-            let newfd = self.alloc_fd();
-            //drop(fds);
-
-            self.fds.write().insert(
-                newfd,
-                FileDescriptor {
-                    file: None, // Would clone the file handle
-                    path: fd_info.path.clone(),
-                    flags: fd_info.flags,
-                    is_pipe: fd_info.is_pipe,
-                },
-            );
-
-            SyscallResult::Success(newfd as i64)
-        } else {
-            SyscallResult::Error(LinuxErrno::EBADF)
-        }
+    fn sys_dup(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        Ok(process.dup_fd(ctx.arg0 as i32)? as i64)
     }
 
-    fn sys_dup2(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_dup2(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
         let oldfd = ctx.arg0 as i32;
         let newfd = ctx.arg1 as i32;
 
         if oldfd == newfd {
-            return SyscallResult::Success(newfd as i64);
+            process.get_fd(oldfd)?;
+            return Ok(newfd as i64);
         }
-
-        // Would duplicate the file descriptor
-        SyscallResult::Success(newfd as i64)
+        Ok(process.dup_fd_to(oldfd, newfd, false)? as i64)
     }
 
-    fn sys_dup3(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_dup3(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
         let oldfd = ctx.arg0 as i32;
         let newfd = ctx.arg1 as i32;
         let flags = ctx.arg2 as i32;
 
-        if oldfd == newfd {
-            return SyscallResult::Error(LinuxErrno::EINVAL);
+        if oldfd == newfd || flags & !open_flags::O_CLOEXEC != 0 {
+            return Err(LinuxErrno::EINVAL);
         }
-
-        // Would duplicate with flags
-        SyscallResult::Success(newfd as i64)
+        let cloexec = flags & open_flags::O_CLOEXEC != 0;
+        Ok(process.dup_fd_to(oldfd, newfd, cloexec)? as i64)
     }
 
-    fn sys_pipe(&self, ctx: &SyscallContext) -> SyscallResult {
-        let pipefd_ptr = ctx.arg0 as *mut [i32; 2];
+    fn sys_pipe(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        self.pipe(process, ctx.arg0, 0)
+    }
 
-        // Would create a pipe and write fds to pipefd_ptr
-        let read_fd = self.alloc_fd();
-        let write_fd = self.alloc_fd();
+    fn sys_pipe2(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        self.pipe(process, ctx.arg0, ctx.arg1 as i32)
+    }
 
-        self.fds.write().insert(
-            read_fd,
-            FileDescriptor {
-                file: None,
-                path: "pipe:read".to_string(),
-                flags: open_flags::O_RDONLY,
-                is_pipe: true,
-            },
-        );
+    fn pipe(&self, process: &Process, pipefd: u64, flags: i32) -> Result<i64, LinuxErrno> {
+        use open_flags::*;
 
-        self.fds.write().insert(
-            write_fd,
-            FileDescriptor {
-                file: None,
-                path: "pipe:write".to_string(),
-                flags: open_flags::O_WRONLY,
-                is_pipe: true,
-            },
-        );
+        if flags & !(O_CLOEXEC | O_NONBLOCK | O_DIRECT) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let memory = process.user_memory()?;
+        let (reader, writer) = std::io::pipe()?;
+        set_nonblocking(&reader)?;
+        set_nonblocking(&writer)?;
+        let cloexec = flags & O_CLOEXEC != 0;
+        let end = |fd: OwnedFd, accmode| OpenFile {
+            object: FileObject::Host(File::from(fd)),
+            path: "pipe:".to_string(),
+            flags: accmode | (flags & O_NONBLOCK),
+        };
 
-        SyscallResult::Success(0)
-    }
+        let read_fd = process.alloc_fd(end(reader.into(), O_RDONLY), cloexec)?;
+        let write_fd = match process.alloc_fd(end(writer.into(), O_WRONLY), cloexec) {
+            Ok(fd) => fd,
+            Err(errno) => {
+                let _ = process.close_fd(read_fd);
+                return Err(errno);
+            }
+        };
 
-    fn sys_pipe2(&self, ctx: &SyscallContext) -> SyscallResult {
-        // Same as pipe but with flags
-        self.sys_pipe(ctx)
+        let mut fds = [0; 8];
+        fds[..4].copy_from_slice(&read_fd.to_le_bytes());
+        fds[4..].copy_from_slice(&write_fd.to_le_bytes());
+        if let Err(errno) = memory.write(pipefd, &fds) {
+            let _ = process.close_fd(read_fd);
+            let _ = process.close_fd(write_fd);
+            return Err(errno);
+        }
+        Ok(0)
     }
 
     fn sys_access(&self, ctx: &SyscallContext) -> SyscallResult {
@@ -534,29 +899,849 @@ impl SyscallTranslator {
         SyscallResult::Success(0)
     }
 
-    fn sys_stat(&self, ctx: &SyscallContext) -> SyscallResult {
-        // Get file status - would fill in stat structure
-        SyscallResult::Success(0)
+    fn sys_stat(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let path = process.user_memory()?.read_cstr(ctx.arg0)?;
+        self.stat_at(process, at_flags::AT_FDCWD, &path, true, ctx.arg1)
     }
 
-    fn sys_getdents64(&self, ctx: &SyscallContext) -> SyscallResult {
-        // Read directory entries
-        SyscallResult::Success(0)
+    fn sys_lstat(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let path = process.user_memory()?.read_cstr(ctx.arg0)?;
+        self.stat_at(process, at_flags::AT_FDCWD, &path, false, ctx.arg1)
+    }
+
+    fn sys_fstat(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let stat = file_stat(&file)?;
+        process.user_memory()?.write(ctx.arg1, &stat)?;
+        Ok(0)
+    }
+
+    fn sys_newfstatat(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use at_flags::*;
+
+        let dirfd = ctx.arg0 as i32;
+        let path = process.user_memory()?.read_cstr(ctx.arg1)?;
+        let buf = ctx.arg2;
+        let flags = ctx.arg3 as i32;
+
+        if flags & !(AT_SYMLINK_NOFOLLOW | AT_NO_AUTOMOUNT | AT_EMPTY_PATH) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            let stat = if dirfd == AT_FDCWD {
+                let cwd = process.cwd();
+                let sandbox = process.sandbox();
+                let redox_path =
+                    self.resolve_path(&cwd, PathAccess::READ, sandbox.as_deref(), true)?;
+                linux_stat(&std::fs::metadata(redox_path)?)
+            } else {
+                file_stat(&*process.get_fd(dirfd)?)?
+            };
+            process.user_memory()?.write(buf, &stat)?;
+            return Ok(0);
+        }
+        self.stat_at(process, dirfd, &path, flags & AT_SYMLINK_NOFOLLOW == 0, buf)
+    }
+
+    fn stat_at(
+        &self,
+        process: &Process,
+        dirfd: i32,
+        path: &str,
+        follow: bool,
+        buf: u64,
+    ) -> Result<i64, LinuxErrno> {
+        let path = self.path_at(process, dirfd, path)?;
+        if procfs::is_virtual(&path) {
+            return match self.lookup_virtual(process, &path, PathAccess::READ, follow)? {
+                Entry::Path(target) => {
+                    self.stat_at(process, at_flags::AT_FDCWD, &target, follow, buf)
+                }
+                Entry::Node(path, node) => {
+                    let stat = virtual_stat(&path, &node);
+                    process.user_memory()?.write(buf, &stat)?;
+                    Ok(0)
+                }
+            };
+        }
+        let sandbox = process.sandbox();
+        let redox_path = self.resolve_path(&path, PathAccess::READ, sandbox.as_deref(), follow)?;
+        let metadata = if follow {
+            std::fs::metadata(&redox_path)?
+        } else {
+            std::fs::symlink_metadata(&redox_path)?
+        };
+        process.user_memory()?.write(buf, &linux_stat(&metadata))?;
+        Ok(0)
+    }
+
+    fn sys_readlink(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let path = process.user_memory()?.read_cstr(ctx.arg0)?;
+        let buf = ctx.arg1;
+        let size = ctx.arg2 as i32;
+
+        self.readlink_at(process, at_flags::AT_FDCWD, &path, buf, size)
+    }
+
+    fn sys_readlinkat(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let dirfd = ctx.arg0 as i32;
+        let path = process.user_memory()?.read_cstr(ctx.arg1)?;
+        let buf = ctx.arg2;
+        let size = ctx.arg3 as i32;
+
+        self.readlink_at(process, dirfd, &path, buf, size)
+    }
+
+    fn readlink_at(
+        &self,
+        process: &Process,
+        dirfd: i32,
+        path: &str,
+        buf: u64,
+        size: i32,
+    ) -> Result<i64, LinuxErrno> {
+        if size <= 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let path = self.path_at(process, dirfd, path)?;
+        let target = if procfs::is_virtual(&path) {
+            match self.lookup_virtual(process, &path, PathAccess::READ, false)? {
+                Entry::Path(target) => {
+                    return self.readlink_at(process, at_flags::AT_FDCWD, &target, buf, size)
+                }
+                Entry::Node(_, Node::Link(target)) => target.into_bytes(),
+                Entry::Node(..) => return Err(LinuxErrno::EINVAL),
+            }
+        } else {
+            let sandbox = process.sandbox();
+            let redox_path =
+                self.resolve_path(&path, PathAccess::READ, sandbox.as_deref(), false)?;
+            std::fs::read_link(redox_path)?.into_os_string().into_vec()
+        };
+        // Truncated without a NUL, as on Linux
+        let len = target.len().min(size as usize);
+        process.user_memory()?.write(buf, &target[..len])?;
+        Ok(len as i64)
+    }
+
+    fn sys_getdents64(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let FileObject::Proc(proc_file) = &file.object else {
+            // Host directories aren't listed yet
+            return Ok(0);
+        };
+        let records = proc_file.read_dir(ctx.arg2 as usize)?;
+        process.user_memory()?.write(ctx.arg1, &records)?;
+        Ok(records.len() as i64)
+    }
+
+    // === Readiness syscalls ===
+
+    fn sys_poll(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        let timeout = timeout_ms(ctx.arg2 as i32);
+        self.poll(process, ctx.arg0, ctx.arg1, timeout, expired)
+    }
+
+    fn sys_ppoll(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        // Signals aren't delivered while waiting, so the mask is ignored
+        let timeout = read_timeout(&*process.user_memory()?, ctx.arg2)?;
+        self.poll(process, ctx.arg0, ctx.arg1, timeout, expired)
+    }
+
+    fn poll(
+        &self,
+        process: &Process,
+        fds: u64,
+        nfds: u64,
+        timeout: Option<Duration>,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        use poll_events::*;
+
+        if nfds > MAX_FDS as u64 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let memory = process.user_memory()?;
+        // struct pollfd { int fd; short events; short revents; }
+        let mut pollfds = vec![0; nfds as usize * 8];
+        // Without descriptors, poll only sleeps
+        if nfds > 0 {
+            memory.read(fds, &mut pollfds)?;
+        }
+
+        let mut ready = 0;
+        for pollfd in pollfds.chunks_exact_mut(8) {
+            let fd = i32::from_le_bytes(pollfd[..4].try_into().unwrap());
+            let events = u16::from_le_bytes(pollfd[4..6].try_into().unwrap()) as u32;
+            let revents = if fd < 0 {
+                0
+            } else {
+                match process.get_fd(fd) {
+                    Ok(file) => file.readiness() & (events | POLLERR | POLLHUP),
+                    Err(_) => POLLNVAL,
+                }
+            };
+            pollfd[6..].copy_from_slice(&(revents as u16).to_le_bytes());
+            if revents != 0 {
+                ready += 1;
+            }
+        }
+
+        if ready == 0 && waits(timeout, expired) {
+            return Ok(Wait::Pending(timeout));
+        }
+        if nfds > 0 {
+            memory.write(fds, &pollfds)?;
+        }
+        Ok(Wait::Done(ready))
+    }
+
+    fn sys_select(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        let timeout = if ctx.arg4 == 0 {
+            None
+        } else {
+            let mut timeval = [0; 16];
+            process.user_memory()?.read(ctx.arg4, &mut timeval)?;
+            let sec = i64::from_le_bytes(timeval[..8].try_into().unwrap());
+            let usec = i64::from_le_bytes(timeval[8..].try_into().unwrap());
+            if sec < 0 || !(0..1_000_000).contains(&usec) {
+                return Err(LinuxErrno::EINVAL);
+            }
+            Some(Duration::new(sec as u64, usec as u32 * 1000))
+        };
+        let sets = [ctx.arg1, ctx.arg2, ctx.arg3];
+        self.select(process, ctx.arg0 as i32, sets, timeout, expired)
+    }
+
+    fn sys_pselect6(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        // Signals aren't delivered while waiting, so the mask is ignored
+        let timeout = read_timeout(&*process.user_memory()?, ctx.arg4)?;
+        let sets = [ctx.arg1, ctx.arg2, ctx.arg3];
+        self.select(process, ctx.arg0 as i32, sets, timeout, expired)
+    }
+
+    /// `sets` are the addresses of the read, write and exception sets
+    fn select(
+        &self,
+        process: &Process,
+        nfds: i32,
+        sets: [u64; 3],
+        timeout: Option<Duration>,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        use poll_events::*;
+
+        // Readiness each set reports, as Linux' select maps it from poll
+        const SET_EVENTS: [u32; 3] = [
+            POLLIN | POLLRDNORM | POLLHUP | POLLERR,
+            POLLOUT | POLLWRNORM | POLLERR,
+            POLLPRI,
+        ];
+
+        if nfds < 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let nfds = nfds.min(MAX_FDS) as usize;
+        let memory = process.user_memory()?;
+        let len = nfds.div_ceil(64) * 8;
+
+        let mut watched = [vec![0u8; len], vec![0u8; len], vec![0u8; len]];
+        for (set, addr) in watched.iter_mut().zip(sets) {
+            if addr != 0 {
+                memory.read(addr, set)?;
+            }
+        }
+
+        let mut ready_sets = [vec![0u8; len], vec![0u8; len], vec![0u8; len]];
+        let mut ready = 0;
+        for fd in 0..nfds {
+            let (byte, bit) = (fd / 8, 1 << (fd % 8));
+            if watched.iter().all(|set| set[byte] & bit == 0) {
+                continue;
+            }
+            let readiness = process.get_fd(fd as i32)?.readiness();
+            for (index, events) in SET_EVENTS.into_iter().enumerate() {
+                if watched[index][byte] & bit != 0 && readiness & events != 0 {
+                    ready_sets[index][byte] |= bit;
+                    ready += 1;
+                }
+            }
+        }
+
+        if ready == 0 && waits(timeout, expired) {
+            return Ok(Wait::Pending(timeout));
+        }
+        for (set, addr) in ready_sets.iter().zip(sets) {
+            if addr != 0 {
+                memory.write(addr, set)?;
+            }
+        }
+        Ok(Wait::Done(ready))
+    }
+
+    fn sys_epoll_create(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        // The size hint is only checked
+        if ctx.arg0 as i32 <= 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        self.epoll_create(process, 0)
+    }
+
+    fn sys_epoll_create1(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+    ) -> Result<i64, LinuxErrno> {
+        self.epoll_create(process, ctx.arg0 as i32)
+    }
+
+    fn epoll_create(&self, process: &Process, flags: i32) -> Result<i64, LinuxErrno> {
+        if flags & !epoll_flags::EPOLL_CLOEXEC != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let file = OpenFile {
+            object: FileObject::Epoll(Epoll::default()),
+            path: "anon_inode:[eventpoll]".to_string(),
+            flags: open_flags::O_RDWR,
+        };
+        let fd = process.alloc_fd(file, flags & epoll_flags::EPOLL_CLOEXEC != 0)?;
+        Ok(fd as i64)
+    }
+
+    fn sys_epoll_ctl(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use epoll_ctl_op::*;
+
+        let epfd = ctx.arg0 as i32;
+        let op = ctx.arg1 as i32;
+        let fd = ctx.arg2 as i32;
+        let event = ctx.arg3;
+
+        let epoll_file = process.get_fd(epfd)?;
+        let file = process.get_fd(fd)?;
+        let FileObject::Epoll(epoll) = &epoll_file.object else {
+            return Err(LinuxErrno::EINVAL);
+        };
+        if Arc::ptr_eq(&epoll_file, &file) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        // Regular files and directories are always ready, so Linux refuses
+        // to watch them
+        if let Some(host) = file.host() {
+            let metadata = host.metadata()?;
+            if metadata.is_file() || metadata.is_dir() {
+                return Err(LinuxErrno::EPERM);
+            }
+        }
+        if let FileObject::Proc(_) = &file.object {
+            return Err(LinuxErrno::EPERM);
+        }
+
+        if op == EPOLL_CTL_DEL {
+            epoll.delete(fd, &file)?;
+            return Ok(0);
+        }
+        // struct epoll_event { uint32_t events; uint64_t data; }, packed
+        let mut buf = [0; epoll::EPOLL_EVENT_SIZE];
+        process.user_memory()?.read(event, &mut buf)?;
+        let events = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let data = u64::from_le_bytes(buf[4..].try_into().unwrap());
+        match op {
+            EPOLL_CTL_ADD => {
+                if let FileObject::Epoll(watched) = &file.object {
+                    if watched.watches(epoll) {
+                        return Err(LinuxErrno::ELOOP);
+                    }
+                }
+                epoll.add(fd, &file, events, data)?;
+            }
+            EPOLL_CTL_MOD => epoll.modify(fd, &file, events, data)?,
+            _ => return Err(LinuxErrno::EINVAL),
+        }
+        Ok(0)
+    }
+
+    /// Also `epoll_pwait`, whose signal mask is ignored as signals aren't
+    /// delivered while waiting
+    fn sys_epoll_wait(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        let epfd = ctx.arg0 as i32;
+        let events = ctx.arg1;
+        let maxevents = ctx.arg2 as i32;
+        let timeout = timeout_ms(ctx.arg3 as i32);
+
+        if maxevents <= 0 || maxevents > EP_MAX_EVENTS {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let file = process.get_fd(epfd)?;
+        let FileObject::Epoll(epoll) = &file.object else {
+            return Err(LinuxErrno::EINVAL);
+        };
+        let memory = process.user_memory()?;
+
+        let ready = epoll.ready(maxevents as usize);
+        if ready.is_empty() && waits(timeout, expired) {
+            return Ok(Wait::Pending(timeout));
+        }
+        let mut buf = Vec::with_capacity(ready.len() * epoll::EPOLL_EVENT_SIZE);
+        for (events, data) in &ready {
+            buf.extend_from_slice(&events.to_le_bytes());
+            buf.extend_from_slice(&data.to_le_bytes());
+        }
+        memory.write(events, &buf)?;
+        Ok(Wait::Done(ready.len() as i64))
+    }
+
+    fn sys_eventfd(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        self.eventfd(process, ctx.arg0 as u32, 0)
+    }
+
+    fn sys_eventfd2(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        self.eventfd(process, ctx.arg0 as u32, ctx.arg1 as i32)
+    }
+
+    fn eventfd(&self, process: &Process, initval: u32, flags: i32) -> Result<i64, LinuxErrno> {
+        use eventfd_flags::*;
+
+        if flags & !(EFD_SEMAPHORE | EFD_CLOEXEC | EFD_NONBLOCK) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let file = OpenFile {
+            object: FileObject::EventFd(EventFd::new(initval, flags & EFD_SEMAPHORE != 0)),
+            path: "anon_inode:[eventfd]".to_string(),
+            flags: open_flags::O_RDWR | (flags & EFD_NONBLOCK),
+        };
+        let fd = process.alloc_fd(file, flags & EFD_CLOEXEC != 0)?;
+        Ok(fd as i64)
+    }
+
+    fn sys_timerfd_create(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+    ) -> Result<i64, LinuxErrno> {
+        use timerfd_flags::*;
+
+        let clock = ctx.arg0 as i32;
+        let flags = ctx.arg1 as i32;
+
+        if !matches!(
+            clock,
+            clock_id::CLOCK_REALTIME | clock_id::CLOCK_MONOTONIC | clock_id::CLOCK_BOOTTIME
+        ) || flags & !(TFD_CLOEXEC | TFD_NONBLOCK) != 0
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let file = OpenFile {
+            object: FileObject::TimerFd(TimerFd::new(clock)),
+            path: "anon_inode:[timerfd]".to_string(),
+            flags: open_flags::O_RDWR | (flags & TFD_NONBLOCK),
+        };
+        let fd = process.alloc_fd(file, flags & TFD_CLOEXEC != 0)?;
+        Ok(fd as i64)
+    }
+
+    fn sys_timerfd_settime(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+    ) -> Result<i64, LinuxErrno> {
+        use timerfd_flags::*;
+
+        let fd = ctx.arg0 as i32;
+        let flags = ctx.arg1 as i32;
+        let new_value = ctx.arg2;
+        let old_value = ctx.arg3;
+
+        let file = process.get_fd(fd)?;
+        let FileObject::TimerFd(timerfd) = &file.object else {
+            return Err(LinuxErrno::EINVAL);
+        };
+        if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let memory = process.user_memory()?;
+        // struct itimerspec { struct timespec it_interval, it_value; }
+        let interval = read_timespec(&*memory, new_value)?;
+        let value = read_timespec(&*memory, new_value + 16)?;
+
+        let now = Instant::now();
+        let next = if value.is_zero() {
+            None
+        } else if flags & TFD_TIMER_ABSTIME != 0 {
+            Some(now + value.saturating_sub(clock_now(timerfd.clock())?))
+        } else {
+            Some(now + value)
+        };
+        let (old_next, old_interval) = timerfd.set(next, interval, now);
+        if old_value != 0 {
+            write_itimerspec(&*memory, old_value, old_next, old_interval)?;
+        }
+        Ok(0)
+    }
+
+    fn sys_timerfd_gettime(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+    ) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let FileObject::TimerFd(timerfd) = &file.object else {
+            return Err(LinuxErrno::EINVAL);
+        };
+        let (next, interval) = timerfd.get(Instant::now());
+        write_itimerspec(&*process.user_memory()?, ctx.arg1, next, interval)?;
+        Ok(0)
+    }
+
+    // === Socket syscalls ===
+
+    fn sys_socket(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use socket_type::*;
+
+        let domain = ctx.arg0 as i32;
+        let kind = ctx.arg1 as i32;
+        let protocol = ctx.arg2 as i32;
+
+        if kind & !(SOCK_TYPE_MASK | SOCK_NONBLOCK | SOCK_CLOEXEC) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let socket = Socket::new(domain, kind & SOCK_TYPE_MASK, protocol)?;
+        let fd = process.alloc_fd(socket_file(socket, kind), kind & SOCK_CLOEXEC != 0)?;
+        Ok(fd as i64)
+    }
+
+    fn sys_socketpair(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use socket_type::*;
+
+        let domain = ctx.arg0 as i32;
+        let kind = ctx.arg1 as i32;
+        let protocol = ctx.arg2 as i32;
+        let sv = ctx.arg3;
+
+        if kind & !(SOCK_TYPE_MASK | SOCK_NONBLOCK | SOCK_CLOEXEC) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let memory = process.user_memory()?;
+        let (a, b) = Socket::pair(domain, kind & SOCK_TYPE_MASK, protocol)?;
+        let cloexec = kind & SOCK_CLOEXEC != 0;
+
+        let fd_a = process.alloc_fd(socket_file(a, kind), cloexec)?;
+        let fd_b = match process.alloc_fd(socket_file(b, kind), cloexec) {
+            Ok(fd) => fd,
+            Err(errno) => {
+                let _ = process.close_fd(fd_a);
+                return Err(errno);
+            }
+        };
+
+        let mut fds = [0; 8];
+        fds[..4].copy_from_slice(&fd_a.to_le_bytes());
+        fds[4..].copy_from_slice(&fd_b.to_le_bytes());
+        if let Err(errno) = memory.write(sv, &fds) {
+            let _ = process.close_fd(fd_a);
+            let _ = process.close_fd(fd_b);
+            return Err(errno);
+        }
+        Ok(0)
+    }
+
+    fn sys_bind(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        let memory = process.user_memory()?;
+
+        let name = read_sock_addr(&*memory, socket.domain(), ctx.arg1, ctx.arg2)?;
+        let host = self.host_sock_addr(process, &name)?;
+        socket.bind(&name, &host)?;
+        Ok(0)
+    }
+
+    fn sys_listen(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        socket.listen(ctx.arg1 as i32)?;
+        Ok(0)
+    }
+
+    /// Waits for the connection of blocking sockets, up to `SO_SNDTIMEO`
+    fn sys_connect(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+
+        let result = if socket.connecting() {
+            socket.finish_connect()
+        } else {
+            let memory = process.user_memory()?;
+            let name = read_sock_addr(&*memory, socket.domain(), ctx.arg1, ctx.arg2)?;
+            let host = self.host_sock_addr(process, &name)?;
+            socket.connect(&name, &host)
+        };
+        match result {
+            Ok(()) => Ok(Wait::Done(0)),
+            // Non-blocking sockets are left connecting, for poll and
+            // SO_ERROR to tell when they are done
+            Err(LinuxErrno::EINPROGRESS) if file.flags & open_flags::O_NONBLOCK != 0 || expired => {
+                socket.abandon_connect();
+                Err(LinuxErrno::EINPROGRESS)
+            }
+            Err(LinuxErrno::EINPROGRESS) => Ok(Wait::Pending(socket.send_timeout())),
+            Err(errno) => would_block(&file, errno),
+        }
+    }
+
+    fn sys_accept(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        self.accept(process, ctx, 0, expired)
+    }
+
+    fn sys_accept4(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        self.accept(process, ctx, ctx.arg3 as i32, expired)
+    }
+
+    /// Waits for a connection on blocking sockets, up to `SO_RCVTIMEO`
+    fn accept(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        flags: i32,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        use socket_type::*;
+
+        let fd = ctx.arg0 as i32;
+        let addr = ctx.arg1;
+        let addrlen = ctx.arg2;
+
+        if flags & !(SOCK_NONBLOCK | SOCK_CLOEXEC) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let file = process.get_fd(fd)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        let memory = process.user_memory()?;
+        let (accepted, peer) = match socket.accept() {
+            Ok(accepted) => accepted,
+            Err(errno) => {
+                return socket_would_block(&file, errno, 0, socket.recv_timeout(), expired)
+            }
+        };
+
+        let new_fd = process.alloc_fd(socket_file(accepted, flags), flags & SOCK_CLOEXEC != 0)?;
+        if addr != 0 {
+            if let Err(errno) = write_sock_addr(&*memory, addr, addrlen, &peer) {
+                let _ = process.close_fd(new_fd);
+                return Err(errno);
+            }
+        }
+        Ok(Wait::Done(new_fd as i64))
+    }
+
+    /// Waits for room on blocking sockets, up to `SO_SNDTIMEO`
+    fn sys_sendto(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        let fd = ctx.arg0 as i32;
+        let buf = ctx.arg1;
+        let count = (ctx.arg2 as usize).min(MAX_RW_COUNT);
+        let flags = ctx.arg3 as i32;
+        let dest_addr = ctx.arg4;
+        let addrlen = ctx.arg5;
+
+        let file = process.get_fd(fd)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        let memory = process.user_memory()?;
+        let to = if dest_addr != 0 {
+            let name = read_sock_addr(&*memory, socket.domain(), dest_addr, addrlen)?;
+            Some(self.host_sock_addr(process, &name)?)
+        } else {
+            None
+        };
+
+        // Streams take part of the data, datagrams all of it or nothing
+        let len = if socket.kind() == socket_type::SOCK_STREAM {
+            count.min(IO_CHUNK)
+        } else {
+            count
+        };
+        let mut data = vec![0; len];
+        if len > 0 {
+            memory.read(buf, &mut data)?;
+        }
+        match socket.send_to(&data, flags, to.as_ref()) {
+            Ok(sent) => Ok(Wait::Done(sent as i64)),
+            Err(errno) => socket_would_block(&file, errno, flags, socket.send_timeout(), expired),
+        }
+    }
+
+    /// Waits for data on blocking sockets, up to `SO_RCVTIMEO`
+    fn sys_recvfrom(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+        expired: bool,
+    ) -> Result<Wait, LinuxErrno> {
+        let fd = ctx.arg0 as i32;
+        let buf = ctx.arg1;
+        let count = ctx.arg2 as usize;
+        let flags = ctx.arg3 as i32;
+        let src_addr = ctx.arg4;
+        let addrlen = ctx.arg5;
+
+        let file = process.get_fd(fd)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        let memory = process.user_memory()?;
+
+        // A chunk holds any UDP datagram
+        let mut data = vec![0; count.min(IO_CHUNK)];
+        let (received, from) = match socket.recv_from(&mut data, flags) {
+            Ok(received) => received,
+            Err(errno) => {
+                return socket_would_block(&file, errno, flags, socket.recv_timeout(), expired)
+            }
+        };
+        let copied = received.min(data.len());
+        if copied > 0 {
+            memory.write(buf, &data[..copied])?;
+        }
+        if src_addr != 0 {
+            match from {
+                Some(from) => write_sock_addr(&*memory, src_addr, addrlen, &from)?,
+                None => memory.write(addrlen, &0u32.to_le_bytes())?,
+            }
+        }
+        Ok(Wait::Done(received as i64))
+    }
+
+    fn sys_shutdown(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        socket.shutdown(ctx.arg1 as i32)?;
+        Ok(0)
+    }
+
+    fn sys_getsockname(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        let name = socket.local_addr()?;
+        write_sock_addr(&*process.user_memory()?, ctx.arg1, ctx.arg2, &name)?;
+        Ok(0)
+    }
+
+    fn sys_getpeername(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let file = process.get_fd(ctx.arg0 as i32)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        let name = socket.peer_addr()?;
+        write_sock_addr(&*process.user_memory()?, ctx.arg1, ctx.arg2, &name)?;
+        Ok(0)
+    }
+
+    fn sys_setsockopt(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let fd = ctx.arg0 as i32;
+        let level = ctx.arg1 as i32;
+        let name = ctx.arg2 as i32;
+        let optval = ctx.arg3;
+        let optlen = ctx.arg4 as i32;
+
+        let file = process.get_fd(fd)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        if optlen < 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut value = vec![0; (optlen as usize).min(socket::MAX_OPTION_SIZE)];
+        if !value.is_empty() {
+            process.user_memory()?.read(optval, &mut value)?;
+        }
+        socket.set_option(level, name, &value)?;
+        Ok(0)
+    }
+
+    fn sys_getsockopt(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let fd = ctx.arg0 as i32;
+        let level = ctx.arg1 as i32;
+        let name = ctx.arg2 as i32;
+        let optval = ctx.arg3;
+        let optlen = ctx.arg4;
+
+        let file = process.get_fd(fd)?;
+        let socket = file.socket().ok_or(LinuxErrno::ENOTSOCK)?;
+        let memory = process.user_memory()?;
+        let len = read_len(&*memory, optlen)?;
+        let value = socket.get_option(level, name)?;
+        let copied = value.len().min(len);
+        if copied > 0 {
+            memory.write(optval, &value[..copied])?;
+        }
+        memory.write(optlen, &(copied as u32).to_le_bytes())?;
+        Ok(0)
+    }
+
+    /// Host address of a Linux socket address, whose `AF_UNIX` path is a
+    /// Linux path
+    fn host_sock_addr(&self, process: &Process, addr: &SockAddr) -> Result<SockAddr, LinuxErrno> {
+        let Some(path) = addr.path() else {
+            return Ok(addr.clone());
+        };
+        let path = std::str::from_utf8(path).map_err(|_| LinuxErrno::EINVAL)?;
+        let path = self.path_at(process, at_flags::AT_FDCWD, path)?;
+        // Binding creates the socket file, and connecting needs write
+        // access to it
+        let redox_path =
+            self.resolve_path(&path, PathAccess::WRITE, process.sandbox().as_deref(), true)?;
+        Ok(SockAddr::Unix(redox_path.into_bytes()))
     }
 
     // === Process management syscalls ===
 
-    fn sys_getpid(&self, _ctx: &SyscallContext) -> SyscallResult {
-        // Would return actual process ID
-        SyscallResult::Success(1000)
+    fn sys_getpid(&self, process: &Process) -> Result<i64, LinuxErrno> {
+        Ok(process.tgid() as i64)
     }
 
-    fn sys_getppid(&self, _ctx: &SyscallContext) -> SyscallResult {
-        SyscallResult::Success(1)
+    fn sys_getppid(&self, process: &Process) -> Result<i64, LinuxErrno> {
+        Ok(process.ppid() as i64)
     }
 
-    fn sys_gettid(&self, _ctx: &SyscallContext) -> SyscallResult {
-        SyscallResult::Success(1000)
+    fn sys_gettid(&self, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        Ok(ctx.tid as i64)
     }
 
     fn sys_getuid(&self, _ctx: &SyscallContext) -> SyscallResult {
@@ -567,10 +1752,38 @@ impl SyscallTranslator {
         SyscallResult::Success(1000)
     }
 
-    fn sys_exit(&self, ctx: &SyscallContext) -> SyscallResult {
+    /// Exit the calling thread
+    fn sys_exit(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let status = ctx.arg0 as i32;
+        let thread = process.thread(ctx.tid)?;
+
+        // pthread_join waits for the kernel to clear the thread ID; failing
+        // to is ignored, like on Linux
+        let clear_child_tid = thread.clear_child_tid();
+        if clear_child_tid != 0 {
+            if let Ok(memory) = process.user_memory() {
+                if memory.write(clear_child_tid, &0u32.to_le_bytes()).is_ok() {
+                    process
+                        .futexes()
+                        .wake(clear_child_tid, 1, FUTEX_BITSET_MATCH_ANY);
+                }
+            }
+        }
+        process.futexes().cancel(ctx.tid);
+        process.remove_thread(ctx.tid);
+
+        if process.thread_count() == 0 {
+            log::info!("Process exiting with status: {}", status);
+            process.exit(status);
+        }
+        Ok(0)
+    }
+
+    fn sys_exit_group(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
         let status = ctx.arg0 as i32;
         log::info!("Process exiting with status: {}", status);
-        SyscallResult::Success(0)
+        process.exit(status);
+        Ok(0)
     }
 
     fn sys_fork(&self, _ctx: &SyscallContext) -> SyscallResult {
@@ -579,9 +1792,67 @@ impl SyscallTranslator {
         SyscallResult::Success(0)
     }
 
-    fn sys_clone(&self, ctx: &SyscallContext) -> SyscallResult {
-        // Clone with flags
-        SyscallResult::Success(0)
+    /// Create a thread, the only kind of `clone` supported
+    ///
+    /// Only the bookkeeping is done here: whoever intercepts the syscall
+    /// creates the thread itself, on the stack and with the TLS pointer it
+    /// asked for, and it gets the returned thread ID.
+    fn sys_clone(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use clone_flags::*;
+
+        let flags = ctx.arg0;
+        let stack = ctx.arg1;
+        let parent_tid = ctx.arg2;
+        let child_tid = ctx.arg3;
+        let tls = ctx.arg4;
+
+        // Threads share signal handlers, which need a shared address space
+        if (flags & CLONE_THREAD != 0 && flags & CLONE_SIGHAND == 0)
+            || (flags & CLONE_SIGHAND != 0 && flags & CLONE_VM == 0)
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if flags & CLONE_THREAD == 0 {
+            log::warn!("clone without CLONE_THREAD is unsupported: {:#x}", flags);
+            return Err(LinuxErrno::ENOSYS);
+        }
+        if flags & THREAD_REQUIRED != THREAD_REQUIRED
+            || flags & !(THREAD_REQUIRED | THREAD_OPTIONAL | CSIGNAL) != 0
+        {
+            log::warn!("Unsupported clone flags: {:#x}", flags);
+            return Err(LinuxErrno::EINVAL);
+        }
+
+        let parent = process.thread(ctx.tid)?;
+        let memory = process.user_memory()?;
+        let tid = process::alloc_pid();
+        let thread = Arc::new(Thread::new(tid));
+
+        if flags & CLONE_PARENT_SETTID != 0 {
+            memory.write(parent_tid, &tid.to_le_bytes())?;
+        }
+        if flags & CLONE_CHILD_SETTID != 0 {
+            memory.write(child_tid, &tid.to_le_bytes())?;
+        }
+        if flags & CLONE_CHILD_CLEARTID != 0 {
+            thread.set_clear_child_tid(child_tid);
+        }
+        thread.set_tls_ptr(if flags & CLONE_SETTLS != 0 {
+            tls
+        } else {
+            parent.tls_ptr()
+        });
+
+        // The child returns 0 from the same instruction, on its own stack
+        let mut regs = parent.registers();
+        regs.rip = ctx.rip;
+        regs.rsp = if stack != 0 { stack } else { ctx.rsp };
+        regs.rax = 0;
+        regs.fs_base = thread.tls_ptr();
+        thread.set_registers(regs);
+
+        process.add_thread(thread);
+        Ok(tid as i64)
     }
 
     fn sys_execve(&self, ctx: &SyscallContext) -> SyscallResult {
@@ -629,51 +1900,145 @@ impl SyscallTranslator {
 
     // === Memory syscalls ===
 
-    fn sys_brk(&self, ctx: &SyscallContext) -> SyscallResult {
-        let addr = ctx.arg0;
-        // Would adjust program break
-        SyscallResult::Success(addr as i64)
+    fn sys_brk(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        Ok(process.set_brk(ctx.arg0) as i64)
     }
 
-    fn sys_mmap(&self, ctx: &SyscallContext) -> SyscallResult {
+    /// Map anonymous memory or a copy of a file
+    ///
+    /// Private file mappings get the file's contents at the time of the
+    /// call. Shared file mappings, which would have to stay in sync with the
+    /// file, fail with `ENODEV`.
+    fn sys_mmap(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use process::{map_flags::*, prot_flags::*};
+
         let addr = ctx.arg0;
         let len = ctx.arg1;
-        let prot = ctx.arg2;
-        let flags = ctx.arg3;
+        let prot = ctx.arg2 as u32;
+        let flags = ctx.arg3 as u32;
         let fd = ctx.arg4 as i32;
         let offset = ctx.arg5;
 
-        // Would map memory
-        SyscallResult::Success(addr as i64)
+        let fixed = flags & (MAP_FIXED | MAP_FIXED_NOREPLACE) != 0;
+        if len == 0
+            || !offset.is_multiple_of(PAGE_SIZE)
+            || (fixed && !addr.is_multiple_of(PAGE_SIZE))
+            || flags & (MAP_SHARED | MAP_PRIVATE) == 0
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let len = len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .filter(|len| len.checked_add(addr).is_some())
+            .ok_or(LinuxErrno::ENOMEM)?;
+        let start = addr & !(PAGE_SIZE - 1);
+
+        if flags & MAP_ANONYMOUS != 0 {
+            // Without fork, shared anonymous memory is only shared between
+            // threads, like private memory
+            return Ok(process.map(MemoryRegion {
+                start,
+                end: start + len,
+                prot,
+                flags,
+                offset: 0,
+                path: None,
+            })? as i64);
+        }
+
+        let file = process.get_fd(fd)?;
+        let host = file.host().ok_or(LinuxErrno::ENODEV)?;
+        if file.flags & open_flags::O_ACCMODE == open_flags::O_WRONLY {
+            return Err(LinuxErrno::EACCES);
+        }
+        if flags & MAP_SHARED != 0 {
+            log::warn!("Shared mapping of {} is unsupported", file.path);
+            return Err(LinuxErrno::ENODEV);
+        }
+
+        // Writable until the contents are in
+        let start = process.map(MemoryRegion {
+            start,
+            end: start + len,
+            prot: prot | PROT_WRITE,
+            flags,
+            offset,
+            path: Some(file.path.clone()),
+        })?;
+        let copied = copy_file(&*process.user_memory()?, host, offset, start, len).and_then(|_| {
+            if prot & PROT_WRITE == 0 {
+                process.protect(start, len, prot)?;
+            }
+            Ok(())
+        });
+        if let Err(errno) = copied {
+            let _ = process.unmap(start, len);
+            return Err(errno);
+        }
+        Ok(start as i64)
     }
 
-    fn sys_munmap(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_munmap(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
         let addr = ctx.arg0;
         let len = ctx.arg1;
-        SyscallResult::Success(0)
+
+        if !addr.is_multiple_of(PAGE_SIZE) || len == 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let len = len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .filter(|len| len.checked_add(addr).is_some())
+            .ok_or(LinuxErrno::EINVAL)?;
+        process.unmap(addr, len)?;
+        Ok(0)
     }
 
-    fn sys_mprotect(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_mprotect(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use process::prot_flags::*;
+
         let addr = ctx.arg0;
         let len = ctx.arg1;
-        let prot = ctx.arg2;
-        SyscallResult::Success(0)
+        let prot = ctx.arg2 as u32;
+
+        if !addr.is_multiple_of(PAGE_SIZE) || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let len = len
+            .checked_next_multiple_of(PAGE_SIZE)
+            .filter(|len| len.checked_add(addr).is_some())
+            .ok_or(LinuxErrno::ENOMEM)?;
+        if len > 0 {
+            process.protect(addr, len, prot)?;
+        }
+        Ok(0)
     }
 
     // === Time syscalls ===
 
-    fn sys_clock_gettime(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_clock_gettime(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        // Slow path, the vDSO normally answers without a syscall
         let clockid = ctx.arg0 as i32;
-        let tp_ptr = ctx.arg1 as *mut u8;
-        // Would fill in timespec
-        SyscallResult::Success(0)
+        let tp = ctx.arg1;
+
+        let (sec, nsec) = crate::vdso::read_clock(clockid)?;
+        write_pair(&*process.user_memory()?, tp, sec, nsec)?;
+        Ok(0)
     }
 
-    fn sys_gettimeofday(&self, ctx: &SyscallContext) -> SyscallResult {
-        let tv_ptr = ctx.arg0 as *mut u8;
-        let tz_ptr = ctx.arg1 as *mut u8;
-        // Would fill in timeval
-        SyscallResult::Success(0)
+    fn sys_gettimeofday(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        let tv = ctx.arg0;
+        let tz = ctx.arg1;
+        let memory = process.user_memory()?;
+
+        if tv != 0 {
+            let (sec, nsec) = crate::vdso::read_clock(crate::vdso::clock_id::CLOCK_REALTIME)?;
+            write_pair(&*memory, tv, sec, nsec / 1000)?;
+        }
+        // The kernel keeps no timezone: always UTC without DST
+        if tz != 0 {
+            memory.write(tz, &[0; 8])?;
+        }
+        Ok(0)
     }
 
     fn sys_nanosleep(&self, ctx: &SyscallContext) -> SyscallResult {
@@ -699,25 +2064,99 @@ impl SyscallTranslator {
         SyscallResult::Success(buflen as i64)
     }
 
-    fn sys_set_tid_address(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_set_tid_address(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+    ) -> Result<i64, LinuxErrno> {
         let tidptr = ctx.arg0;
-        // Would set thread ID address
-        SyscallResult::Success(1000) // Return thread ID
+        process.thread(ctx.tid)?.set_clear_child_tid(tidptr);
+        Ok(ctx.tid as i64)
     }
 
-    fn sys_futex(&self, ctx: &SyscallContext) -> SyscallResult {
-        let uaddr = ctx.arg0 as usize;
-        let op = ctx.arg1 as usize;
-        let val = ctx.arg2 as usize;
-        let timeout = ctx.arg3 as usize;
-        let uaddr2 = ctx.arg4 as usize;
-        let _val3 = ctx.arg5 as usize;
-
-        let res = unsafe { syscall6(SYS_FUTEX, uaddr, op, val, timeout, uaddr2, 0) };
+    /// `None` when the calling thread waits
+    fn sys_futex(
+        &self,
+        process: &Process,
+        ctx: &SyscallContext,
+    ) -> Result<Option<i64>, LinuxErrno> {
+        use futex_op::*;
+
+        let uaddr = ctx.arg0;
+        let op = ctx.arg1 as u32;
+        let val = ctx.arg2 as u32;
+        let timeout = ctx.arg3;
+        let uaddr2 = ctx.arg4;
+        let val3 = ctx.arg5 as u32;
+
+        if !uaddr.is_multiple_of(4) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let memory = process.user_memory()?;
+        let futexes = process.futexes();
 
-        match res {
-            Ok(val) => SyscallResult::Success(val as i64),
-            Err(err) => SyscallResult::Error(LinuxErrno::from_redox(err)),
+        match op & FUTEX_CMD_MASK {
+            cmd @ (FUTEX_WAIT | FUTEX_WAIT_BITSET) => {
+                let bitset = if cmd == FUTEX_WAIT {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                // FUTEX_WAIT takes a relative timeout, FUTEX_WAIT_BITSET an
+                // absolute one
+                let deadline = if timeout == 0 {
+                    None
+                } else {
+                    let mut duration = read_timespec(&*memory, timeout)?;
+                    if cmd == FUTEX_WAIT_BITSET {
+                        let clock = if op & FUTEX_CLOCK_REALTIME != 0 {
+                            clock_id::CLOCK_REALTIME
+                        } else {
+                            clock_id::CLOCK_MONOTONIC
+                        };
+                        duration = duration.saturating_sub(clock_now(clock)?);
+                    }
+                    Some(Instant::now() + duration)
+                };
+                futexes.wait(&*memory, ctx.tid, uaddr, val, bitset, deadline)?;
+                Ok(None)
+            }
+            cmd @ (FUTEX_WAKE | FUTEX_WAKE_BITSET) => {
+                let bitset = if cmd == FUTEX_WAKE {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                let count = (val as i32).max(0) as usize;
+                Ok(Some(futexes.wake(uaddr, count, bitset) as i64))
+            }
+            cmd @ (FUTEX_REQUEUE | FUTEX_CMP_REQUEUE) => {
+                // The timeout argument holds the number to requeue
+                let requeue = timeout as i32;
+                if !uaddr2.is_multiple_of(4) || (val as i32) < 0 || requeue < 0 {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                let expected = (cmd == FUTEX_CMP_REQUEUE).then_some(val3);
+                let count = futexes.requeue(
+                    &*memory,
+                    uaddr,
+                    val as usize,
+                    uaddr2,
+                    requeue as usize,
+                    expected,
+                )?;
+                Ok(Some(count as i64))
+            }
+            cmd => {
+                log::warn!("Unsupported futex operation: {}", cmd);
+                Err(LinuxErrno::ENOSYS)
+            }
         }
     }
 
@@ -741,7 +2180,7 @@ impl SyscallTranslator {
 
         match res {
             Ok(val) => SyscallResult::Success(val as i64),
-            Err(err) => SyscallResult::Error(LinuxErrno::from_redox(err)),
+            Err(err) => SyscallResult::Error(LinuxErrno::from_redox(err.errno as usize)),
         }
     }
 
@@ -752,10 +2191,349 @@ impl SyscallTranslator {
         SyscallResult::Success(0)
     }
 
-    fn sys_arch_prctl(&self, ctx: &SyscallContext) -> SyscallResult {
+    fn sys_arch_prctl(&self, process: &Process, ctx: &SyscallContext) -> Result<i64, LinuxErrno> {
+        use arch_prctl_code::*;
+
         let code = ctx.arg0 as i32;
         let addr = ctx.arg1;
-        // Would set architecture-specific thread state (e.g., FS/GS base)
-        SyscallResult::Success(0)
+        let thread = process.thread(ctx.tid)?;
+
+        match code {
+            ARCH_SET_FS | ARCH_SET_GS if addr >= USER_ADDR_END => Err(LinuxErrno::EPERM),
+            // Applied to the thread by whoever intercepts the syscall
+            ARCH_SET_FS => {
+                thread.set_tls_ptr(addr);
+                let mut regs = thread.registers();
+                regs.fs_base = addr;
+                thread.set_registers(regs);
+                Ok(0)
+            }
+            ARCH_SET_GS => {
+                let mut regs = thread.registers();
+                regs.gs_base = addr;
+                thread.set_registers(regs);
+                Ok(0)
+            }
+            ARCH_GET_FS => {
+                process.user_memory()?.write_u64(addr, thread.tls_ptr())?;
+                Ok(0)
+            }
+            ARCH_GET_GS => {
+                process
+                    .user_memory()?
+                    .write_u64(addr, thread.registers().gs_base)?;
+                Ok(0)
+            }
+            _ => Err(LinuxErrno::EINVAL),
+        }
+    }
+}
+
+/// Read up to `count` bytes of a Redox file handle to `buf`
+fn read_host(
+    memory: &dyn UserMemory,
+    mut file: &File,
+    buf: u64,
+    count: usize,
+) -> Result<i64, LinuxErrno> {
+    // Only regular files are read until the buffer is full, anything else
+    // could block waiting for more
+    let regular = count > IO_CHUNK && file.metadata()?.is_file();
+
+    let mut data = vec![0; count.min(IO_CHUNK)];
+    let mut done = 0;
+    while done < count {
+        let chunk = (count - done).min(IO_CHUNK);
+        let len = match file.read(&mut data[..chunk]) {
+            Ok(len) => len,
+            Err(_) if done > 0 => break,
+            Err(err) => return Err(err.into()),
+        };
+        memory.write(buf + done as u64, &data[..len])?;
+        done += len;
+        if len < chunk || !regular {
+            break;
+        }
+    }
+    Ok(done as i64)
+}
+
+/// Copy up to `len` bytes of `file` from `offset` to `addr`, as much as
+/// the file holds
+fn copy_file(
+    memory: &dyn UserMemory,
+    file: &File,
+    offset: u64,
+    addr: u64,
+    len: u64,
+) -> Result<(), LinuxErrno> {
+    let mut data = vec![0; (len as usize).min(IO_CHUNK)];
+    let mut done = 0;
+    while done < len {
+        let chunk = ((len - done) as usize).min(IO_CHUNK);
+        let count = file.read_at(&mut data[..chunk], offset + done)?;
+        if count == 0 {
+            break;
+        }
+        memory.write(addr + done, &data[..count])?;
+        done += count as u64;
+    }
+    Ok(())
+}
+
+/// Write up to `count` bytes of `buf` to a Redox file handle
+fn write_host(
+    memory: &dyn UserMemory,
+    mut file: &File,
+    buf: u64,
+    count: usize,
+) -> Result<i64, LinuxErrno> {
+    let mut data = vec![0; count.min(IO_CHUNK)];
+    let mut done = 0;
+    while done < count {
+        let chunk = (count - done).min(IO_CHUNK);
+        memory.read(buf + done as u64, &mut data[..chunk])?;
+        let len = match file.write(&data[..chunk]) {
+            Ok(len) => len,
+            Err(_) if done > 0 => break,
+            Err(err) => return Err(err.into()),
+        };
+        done += len;
+        if len < chunk {
+            break;
+        }
+    }
+    Ok(done as i64)
+}
+
+/// Open file of a new socket, created with `flags`
+fn socket_file(socket: Socket, flags: i32) -> OpenFile {
+    OpenFile {
+        object: FileObject::Socket(socket),
+        path: "socket:".to_string(),
+        flags: open_flags::O_RDWR | (flags & socket_type::SOCK_NONBLOCK),
+    }
+}
+
+/// Result of a socket call failing with `errno`: `EAGAIN` waits up to
+/// `timeout`, unless the socket is non-blocking or `flags` has
+/// `MSG_DONTWAIT`
+fn socket_would_block(
+    file: &OpenFile,
+    errno: LinuxErrno,
+    flags: i32,
+    timeout: Option<Duration>,
+    expired: bool,
+) -> Result<Wait, LinuxErrno> {
+    if flags & msg_flags::MSG_DONTWAIT != 0 || expired {
+        return Err(errno);
+    }
+    match would_block(file, errno)? {
+        Wait::Pending(_) => Ok(Wait::Pending(timeout)),
+        done => Ok(done),
+    }
+}
+
+/// Length a `socklen_t` at `addr` gives, failing with `EINVAL` if negative
+fn read_len(memory: &dyn UserMemory, addr: u64) -> Result<usize, LinuxErrno> {
+    let mut len = [0; 4];
+    memory.read(addr, &mut len)?;
+    usize::try_from(i32::from_le_bytes(len)).map_err(|_| LinuxErrno::EINVAL)
+}
+
+/// Read a socket address of `addrlen` bytes for a socket of `domain`
+fn read_sock_addr(
+    memory: &dyn UserMemory,
+    domain: i32,
+    addr: u64,
+    addrlen: u64,
+) -> Result<SockAddr, LinuxErrno> {
+    let len = usize::try_from(addrlen as i32).map_err(|_| LinuxErrno::EINVAL)?;
+    if len > socket::MAX_SOCKADDR_SIZE {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut bytes = vec![0; len];
+    if len > 0 {
+        memory.read(addr, &mut bytes)?;
+    }
+    SockAddr::from_linux(domain, &bytes)
+}
+
+/// Write a socket address, truncated to the length at `addrlen`, which is
+/// then set to its full length
+fn write_sock_addr(
+    memory: &dyn UserMemory,
+    addr: u64,
+    addrlen: u64,
+    sock_addr: &SockAddr,
+) -> Result<(), LinuxErrno> {
+    let len = read_len(memory, addrlen)?;
+    let bytes = sock_addr.to_linux();
+    let copied = bytes.len().min(len);
+    if copied > 0 {
+        memory.write(addr, &bytes[..copied])?;
+    }
+    memory.write(addrlen, &(bytes.len() as u32).to_le_bytes())
+}
+
+/// Make a Redox file handle non-blocking, so that blocking Linux files wait
+/// in [`SyscallTranslator::wait`] instead of blocking `lacd`
+fn set_nonblocking(fd: &impl AsRawFd) -> Result<(), LinuxErrno> {
+    let fd = fd.as_raw_fd();
+    // SAFETY: plain libc calls on a descriptor of lacd
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Result of a file failing with `errno`: `EAGAIN` waits, unless the file
+/// is non-blocking
+fn would_block(file: &OpenFile, errno: LinuxErrno) -> Result<Wait, LinuxErrno> {
+    if errno == LinuxErrno::EAGAIN && file.flags & open_flags::O_NONBLOCK == 0 {
+        Ok(Wait::Pending(None))
+    } else {
+        Err(errno)
     }
 }
+
+/// Read a `timespec`, failing with `EINVAL` if it is negative or not
+/// normalized
+fn read_timespec(memory: &dyn UserMemory, addr: u64) -> Result<Duration, LinuxErrno> {
+    let mut timespec = [0; 16];
+    memory.read(addr, &mut timespec)?;
+    let sec = i64::from_le_bytes(timespec[..8].try_into().unwrap());
+    let nsec = i64::from_le_bytes(timespec[8..].try_into().unwrap());
+    if sec < 0 || !(0..1_000_000_000).contains(&nsec) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    Ok(Duration::new(sec as u64, nsec as u32))
+}
+
+/// Timeout of a `timespec`, waiting for ever without one
+fn read_timeout(memory: &dyn UserMemory, addr: u64) -> Result<Option<Duration>, LinuxErrno> {
+    if addr == 0 {
+        return Ok(None);
+    }
+    read_timespec(memory, addr).map(Some)
+}
+
+/// Timeout in milliseconds, waiting for ever if negative
+fn timeout_ms(timeout: i32) -> Option<Duration> {
+    u64::try_from(timeout).ok().map(Duration::from_millis)
+}
+
+/// Whether a syscall that found nothing ready still waits
+fn waits(timeout: Option<Duration>, expired: bool) -> bool {
+    !expired && timeout != Some(Duration::ZERO)
+}
+
+/// Current reading of a Linux clock
+fn clock_now(clock: i32) -> Result<Duration, LinuxErrno> {
+    let (sec, nsec) = vdso::read_clock(clock)?;
+    Ok(Duration::new(sec as u64, nsec as u32))
+}
+
+/// Write a `struct itimerspec`
+fn write_itimerspec(
+    memory: &dyn UserMemory,
+    addr: u64,
+    value: Duration,
+    interval: Duration,
+) -> Result<(), LinuxErrno> {
+    let mut itimerspec = [0; 32];
+    for (field, time) in itimerspec.chunks_exact_mut(8).zip([
+        interval.as_secs(),
+        interval.subsec_nanos() as u64,
+        value.as_secs(),
+        value.subsec_nanos() as u64,
+    ]) {
+        field.copy_from_slice(&time.to_le_bytes());
+    }
+    memory.write(addr, &itimerspec)
+}
+
+/// Write a `timespec` or `timeval`
+fn write_pair(memory: &dyn UserMemory, addr: u64, first: i64, second: i64) -> Result<(), LinuxErrno> {
+    let mut pair = [0; 16];
+    pair[..8].copy_from_slice(&first.to_le_bytes());
+    pair[8..].copy_from_slice(&second.to_le_bytes());
+    memory.write(addr, &pair)
+}
+
+/// `struct stat` of an open file
+///
+/// Emulated files are anonymous inodes, as on Linux.
+fn file_stat(file: &OpenFile) -> Result<[u8; STAT_SIZE], LinuxErrno> {
+    if let Some(host) = file.host() {
+        return Ok(linux_stat(&host.metadata()?));
+    }
+    if let FileObject::Proc(proc_file) = &file.object {
+        return Ok(virtual_stat(&file.path, proc_file.node()));
+    }
+    let mut stat = [0; STAT_SIZE];
+    stat[16..24].copy_from_slice(&1u64.to_le_bytes());
+    stat[24..28].copy_from_slice(&0o600u32.to_le_bytes());
+    stat[56..64].copy_from_slice(&4096u64.to_le_bytes());
+    Ok(stat)
+}
+
+/// Open file of a node of /proc or /sys, found at `path`
+fn virtual_file(path: String, node: Node, flags: i32) -> Result<OpenFile, LinuxErrno> {
+    use open_flags::*;
+
+    match node {
+        // Only reached with O_NOFOLLOW
+        Node::Link(_) => return Err(LinuxErrno::ELOOP),
+        Node::Dir(_) if flags & O_ACCMODE != O_RDONLY => return Err(LinuxErrno::EISDIR),
+        Node::File(_) if flags & O_DIRECTORY != 0 => return Err(LinuxErrno::ENOTDIR),
+        _ if flags & O_ACCMODE != O_RDONLY || flags & O_TRUNC != 0 => {
+            return Err(LinuxErrno::EACCES)
+        }
+        _ => {}
+    }
+    Ok(OpenFile {
+        object: FileObject::Proc(ProcFile::new(node)),
+        path,
+        flags,
+    })
+}
+
+/// `struct stat` of a node of /proc or /sys, found at `path`
+fn virtual_stat(path: &str, node: &Node) -> [u8; STAT_SIZE] {
+    let nlink: u64 = if matches!(node, Node::Dir(_)) { 2 } else { 1 };
+    let mut stat = [0; STAT_SIZE];
+    stat[8..16].copy_from_slice(&procfs::inode(path).to_le_bytes());
+    stat[16..24].copy_from_slice(&nlink.to_le_bytes());
+    stat[24..28].copy_from_slice(&node.mode().to_le_bytes());
+    stat[48..56].copy_from_slice(&node.size().to_le_bytes());
+    stat[56..64].copy_from_slice(&1024u64.to_le_bytes());
+    stat
+}
+
+/// `struct stat` as laid out by the x86_64 Linux ABI
+fn linux_stat(metadata: &Metadata) -> [u8; STAT_SIZE] {
+    let mut stat = [0; STAT_SIZE];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        stat[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    put(0, &metadata.dev().to_le_bytes());
+    put(8, &metadata.ino().to_le_bytes());
+    put(16, &metadata.nlink().to_le_bytes());
+    put(24, &metadata.mode().to_le_bytes());
+    put(28, &metadata.uid().to_le_bytes());
+    put(32, &metadata.gid().to_le_bytes());
+    put(40, &metadata.rdev().to_le_bytes());
+    put(48, &metadata.size().to_le_bytes());
+    put(56, &metadata.blksize().to_le_bytes());
+    put(64, &metadata.blocks().to_le_bytes());
+    put(72, &metadata.atime().to_le_bytes());
+    put(80, &metadata.atime_nsec().to_le_bytes());
+    put(88, &metadata.mtime().to_le_bytes());
+    put(96, &metadata.mtime_nsec().to_le_bytes());
+    put(104, &metadata.ctime().to_le_bytes());
+    put(112, &metadata.ctime_nsec().to_le_bytes());
+    stat
+}
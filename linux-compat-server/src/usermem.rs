@@ -0,0 +1,190 @@
+//! Access to the memory of Linux processes
+//!
+//! Syscall arguments are addresses in the calling process, not in `lacd`.
+//! Buffers, paths and result structures are copied in and out through
+//! [`UserMemory`], which fails with `EFAULT` like the Linux kernel does for
+//! addresses the process hasn't mapped.
+//!
+//! Mappings are changed through [`AddressSpace`]: `lacd` keeps track of what
+//! a process mapped and picks the addresses, the address space carries the
+//! changes out.
+
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+
+use crate::errno::LinuxErrno;
+use crate::process::prot_flags;
+
+/// Longest path accepted from a process, including the terminating NUL
+pub const PATH_MAX: usize = 4096;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Memory of a Linux process
+pub trait UserMemory: Send + Sync {
+    /// Copy `buf.len()` bytes from `addr` in the process
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), LinuxErrno>;
+
+    /// Copy `buf` to `addr` in the process
+    fn write(&self, addr: u64, buf: &[u8]) -> Result<(), LinuxErrno>;
+}
+
+impl dyn UserMemory {
+    /// Read the NUL terminated string at `addr`
+    ///
+    /// Reads never cross into a page past the terminator, which may be
+    /// unmapped.
+    pub fn read_cstr(&self, addr: u64) -> Result<String, LinuxErrno> {
+        let mut bytes = Vec::new();
+        let mut at = addr;
+        while bytes.len() < PATH_MAX {
+            let chunk = ((PAGE_SIZE - at % PAGE_SIZE) as usize).min(PATH_MAX - bytes.len());
+            let start = bytes.len();
+            bytes.resize(start + chunk, 0);
+            self.read(at, &mut bytes[start..])?;
+            if let Some(len) = bytes[start..].iter().position(|&byte| byte == 0) {
+                bytes.truncate(start + len);
+                return String::from_utf8(bytes).map_err(|_| LinuxErrno::EINVAL);
+            }
+            at += chunk as u64;
+        }
+        Err(LinuxErrno::ENAMETOOLONG)
+    }
+
+    pub fn read_u64(&self, addr: u64) -> Result<u64, LinuxErrno> {
+        let mut bytes = [0; 8];
+        self.read(addr, &mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn write_u64(&self, addr: u64, value: u64) -> Result<(), LinuxErrno> {
+        self.write(addr, &value.to_le_bytes())
+    }
+}
+
+/// Memory of another process, through its `mem` file
+///
+/// Offsets into the file are addresses in the process.
+pub struct ProcMemory {
+    file: File,
+}
+
+impl ProcMemory {
+    /// Open the memory of the Redox process `pid`
+    pub fn open(pid: usize) -> Result<Self, LinuxErrno> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(format!("/scheme/proc/{}/mem", pid))?;
+        Ok(Self::new(file))
+    }
+
+    pub fn new(file: File) -> Self {
+        Self { file }
+    }
+}
+
+impl UserMemory for ProcMemory {
+    fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), LinuxErrno> {
+        if addr == 0 {
+            return Err(LinuxErrno::EFAULT);
+        }
+        self.file
+            .read_exact_at(buf, addr)
+            .map_err(|_| LinuxErrno::EFAULT)
+    }
+
+    fn write(&self, addr: u64, buf: &[u8]) -> Result<(), LinuxErrno> {
+        if addr == 0 {
+            return Err(LinuxErrno::EFAULT);
+        }
+        self.file
+            .write_all_at(buf, addr)
+            .map_err(|_| LinuxErrno::EFAULT)
+    }
+}
+
+/// Address space of a Linux process
+///
+/// Addresses and lengths are page aligned. Mappings replace whatever was
+/// mapped in their range, like `MAP_FIXED`.
+pub trait AddressSpace: Send + Sync {
+    /// Map `len` bytes of zeroed, private memory at `addr`
+    fn map(&self, addr: u64, len: u64, prot: u32) -> Result<(), LinuxErrno>;
+
+    /// Unmap `len` bytes at `addr`
+    fn unmap(&self, addr: u64, len: u64) -> Result<(), LinuxErrno>;
+
+    /// Change the protection of `len` bytes at `addr`
+    fn protect(&self, addr: u64, len: u64, prot: u32) -> Result<(), LinuxErrno>;
+}
+
+/// Address space of another process, through its `addrspace` file
+///
+/// Anonymous memory is mapped from `memory:zeroed`.
+pub struct ProcAddressSpace {
+    addrspace: File,
+    zeroed: File,
+}
+
+impl ProcAddressSpace {
+    /// Open the address space of the Redox process `pid`
+    pub fn open(pid: usize) -> Result<Self, LinuxErrno> {
+        Ok(Self {
+            addrspace: File::options()
+                .write(true)
+                .open(format!("/scheme/proc/{}/addrspace", pid))?,
+            zeroed: File::open("/scheme/memory/zeroed")?,
+        })
+    }
+
+    fn op(&self, words: &[usize]) -> Result<(), LinuxErrno> {
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+        (&self.addrspace).write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+/// Redox mapping flags for the Linux protection `prot`
+fn map_flags(prot: u32) -> syscall::MapFlags {
+    let mut flags = syscall::MapFlags::empty();
+    if prot & prot_flags::PROT_READ != 0 {
+        flags |= syscall::MapFlags::PROT_READ;
+    }
+    if prot & prot_flags::PROT_WRITE != 0 {
+        flags |= syscall::MapFlags::PROT_WRITE;
+    }
+    if prot & prot_flags::PROT_EXEC != 0 {
+        flags |= syscall::MapFlags::PROT_EXEC;
+    }
+    flags
+}
+
+impl AddressSpace for ProcAddressSpace {
+    fn map(&self, addr: u64, len: u64, prot: u32) -> Result<(), LinuxErrno> {
+        let flags = map_flags(prot) | syscall::MapFlags::MAP_PRIVATE | syscall::MapFlags::MAP_FIXED;
+        self.op(&[
+            syscall::ADDRSPACE_OP_MMAP,
+            self.zeroed.as_raw_fd() as usize,
+            0,
+            addr as usize,
+            len as usize,
+            flags.bits(),
+        ])
+    }
+
+    fn unmap(&self, addr: u64, len: u64) -> Result<(), LinuxErrno> {
+        self.op(&[syscall::ADDRSPACE_OP_MUNMAP, addr as usize, len as usize])
+    }
+
+    fn protect(&self, addr: u64, len: u64, prot: u32) -> Result<(), LinuxErrno> {
+        self.op(&[
+            syscall::ADDRSPACE_OP_MPROTECT,
+            addr as usize,
+            len as usize,
+            map_flags(prot).bits(),
+        ])
+    }
+}
@@ -0,0 +1,473 @@
+//! vDSO for Linux processes
+//!
+//! `clock_gettime` and `gettimeofday` are called often enough that a
+//! round-trip through the translator dominates their cost. Like Linux, every
+//! process gets a small shared object mapped next to its stack, announced via
+//! `AT_SYSINFO_EHDR`, whose functions compute the time in userspace.
+//!
+//! The mapping is two pages: the data page ("vvar"), which the server
+//! updates and processes map read-only, followed by the ELF image. The data
+//! page holds, for every clock, its value at one TSC reading and the TSC
+//! rate; the vDSO extrapolates from there with `rdtsc`. A sequence counter
+//! that is odd while the server writes lets readers retry torn reads.
+//! Clocks without a fast path fall back to the real `clock_gettime` syscall.
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+
+use crate::errno::LinuxErrno;
+
+/// Page size of the mapping
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Address of the mapping, below the stack
+pub const VDSO_BASE: u64 = 0x7fff_f7f0_0000;
+
+/// Size of the mapping: the data page and the image
+pub const VDSO_SIZE: u64 = 2 * PAGE_SIZE;
+
+/// Shared memory holding the data page
+pub const VVAR_PATH: &str = "/scheme/shm/lac-vvar";
+
+/// Shared memory holding the image
+pub const IMAGE_PATH: &str = "/scheme/shm/lac-vdso";
+
+/// Linux clock ids
+pub mod clock_id {
+    pub const CLOCK_REALTIME: i32 = 0;
+    pub const CLOCK_MONOTONIC: i32 = 1;
+    pub const CLOCK_PROCESS_CPUTIME_ID: i32 = 2;
+    pub const CLOCK_THREAD_CPUTIME_ID: i32 = 3;
+    pub const CLOCK_MONOTONIC_RAW: i32 = 4;
+    pub const CLOCK_REALTIME_COARSE: i32 = 5;
+    pub const CLOCK_MONOTONIC_COARSE: i32 = 6;
+    pub const CLOCK_BOOTTIME: i32 = 7;
+}
+
+// Data page layout, shared with `TEXT`
+const VVAR_SEQ: u64 = 0;
+const VVAR_TSC_BASE: u64 = 8;
+const VVAR_CLOCKS: u64 = 32;
+/// `{ sec: u64, nsec: u64, mode: u64, _: u64 }`
+const VVAR_CLOCK_SIZE: usize = 32;
+const VVAR_CLOCK_COUNT: usize = 8;
+const VVAR_LEN: usize = VVAR_CLOCKS as usize + VVAR_CLOCK_COUNT * VVAR_CLOCK_SIZE;
+
+/// Clock mode: take the syscall
+const MODE_SYSCALL: u64 = 0;
+/// Clock mode: extrapolate with the TSC
+const MODE_TSC: u64 = 1;
+
+/// Shortest calibration before the TSC rate is trusted, in nanoseconds
+const MIN_CALIBRATION_NS: u64 = 10_000_000;
+
+/// Offset of the code in the image, `TEXT` addresses the data page
+/// relative to it
+const TEXT_OFFSET: usize = 0x400;
+
+/// Offsets of the functions in `TEXT`
+const CLOCK_GETTIME: usize = 0x00;
+const GETTIMEOFDAY: usize = 0x7e;
+
+/// Code of the vDSO, assembled from:
+///
+/// ```text
+/// start:
+/// .set vvar, start - 0x400 - 0x1000
+/// clock_gettime:                    # edi = clock id, rsi = timespec
+///     cmp edi, 8
+///     jae 3f
+///     lea r8, [rip + vvar]
+///     mov eax, edi
+///     shl eax, 5
+///     lea r9, [r8 + rax + 32]       # clock entry
+/// 1:  mov r10d, dword ptr [r8]      # sequence
+///     test r10d, 1
+///     jnz 4f
+///     cmp qword ptr [r9 + 16], 0    # MODE_SYSCALL
+///     je 3f
+///     mov rcx, qword ptr [r8 + 16]  # TSC rate, 32.32 ns per tick
+///     test rcx, rcx
+///     jz 3f
+///     lfence
+///     rdtsc
+///     shl rdx, 32
+///     or rax, rdx
+///     sub rax, qword ptr [r8 + 8]   # ticks since the base
+///     jae 2f
+///     xor eax, eax
+/// 2:  mul rcx
+///     shrd rax, rdx, 32
+///     add rax, qword ptr [r9 + 8]
+///     mov rcx, qword ptr [r9]
+///     cmp r10d, dword ptr [r8]
+///     jne 1b
+///     xor edx, edx
+///     mov r11d, 1000000000
+///     div r11
+///     add rcx, rax
+///     mov qword ptr [rsi], rcx
+///     mov qword ptr [rsi + 8], rdx
+///     xor eax, eax
+///     ret
+/// 3:  mov eax, 228                  # SYS_clock_gettime
+///     syscall
+///     ret
+/// 4:  pause
+///     jmp 1b
+///
+/// gettimeofday:                     # rdi = timeval, rsi = timezone
+///     sub rsp, 40
+///     mov qword ptr [rsp + 16], rdi
+///     mov qword ptr [rsp + 24], rsi
+///     test rdi, rdi
+///     jz 5f
+///     xor edi, edi                  # CLOCK_REALTIME
+///     mov rsi, rsp
+///     call clock_gettime
+///     test rax, rax
+///     jnz 7f
+///     mov rdi, qword ptr [rsp + 16]
+///     mov rcx, qword ptr [rsp]
+///     mov qword ptr [rdi], rcx
+///     mov rax, qword ptr [rsp + 8]
+///     xor edx, edx
+///     mov ecx, 1000
+///     div rcx
+///     mov qword ptr [rdi + 8], rax
+/// 5:  mov rsi, qword ptr [rsp + 24]
+///     test rsi, rsi
+///     jz 6f
+///     mov qword ptr [rsi], 0
+/// 6:  xor eax, eax
+/// 7:  add rsp, 40
+///     ret
+/// ```
+#[rustfmt::skip]
+const TEXT: [u8; 0xd7] = [
+    0x83, 0xff, 0x08, 0x73, 0x6d, 0x4c, 0x8d, 0x05, 0xf4, 0xeb, 0xff, 0xff, 0x89, 0xf8, 0xc1, 0xe0,
+    0x05, 0x4d, 0x8d, 0x4c, 0x00, 0x20, 0x45, 0x8b, 0x10, 0x41, 0xf7, 0xc2, 0x01, 0x00, 0x00, 0x00,
+    0x75, 0x58, 0x49, 0x83, 0x79, 0x10, 0x00, 0x74, 0x49, 0x49, 0x8b, 0x48, 0x10, 0x48, 0x85, 0xc9,
+    0x74, 0x40, 0x0f, 0xae, 0xe8, 0x0f, 0x31, 0x48, 0xc1, 0xe2, 0x20, 0x48, 0x09, 0xd0, 0x49, 0x2b,
+    0x40, 0x08, 0x73, 0x02, 0x31, 0xc0, 0x48, 0xf7, 0xe1, 0x48, 0x0f, 0xac, 0xd0, 0x20, 0x49, 0x03,
+    0x41, 0x08, 0x49, 0x8b, 0x09, 0x45, 0x3b, 0x10, 0x75, 0xbc, 0x31, 0xd2, 0x41, 0xbb, 0x00, 0xca,
+    0x9a, 0x3b, 0x49, 0xf7, 0xf3, 0x48, 0x01, 0xc1, 0x48, 0x89, 0x0e, 0x48, 0x89, 0x56, 0x08, 0x31,
+    0xc0, 0xc3, 0xb8, 0xe4, 0x00, 0x00, 0x00, 0x0f, 0x05, 0xc3, 0xf3, 0x90, 0xeb, 0x98, 0x48, 0x83,
+    0xec, 0x28, 0x48, 0x89, 0x7c, 0x24, 0x10, 0x48, 0x89, 0x74, 0x24, 0x18, 0x48, 0x85, 0xff, 0x74,
+    0x2e, 0x31, 0xff, 0x48, 0x89, 0xe6, 0xe8, 0x65, 0xff, 0xff, 0xff, 0x48, 0x85, 0xc0, 0x75, 0x32,
+    0x48, 0x8b, 0x7c, 0x24, 0x10, 0x48, 0x8b, 0x0c, 0x24, 0x48, 0x89, 0x0f, 0x48, 0x8b, 0x44, 0x24,
+    0x08, 0x31, 0xd2, 0xb9, 0xe8, 0x03, 0x00, 0x00, 0x48, 0xf7, 0xf1, 0x48, 0x89, 0x47, 0x08, 0x48,
+    0x8b, 0x74, 0x24, 0x18, 0x48, 0x85, 0xf6, 0x74, 0x07, 0x48, 0xc7, 0x06, 0x00, 0x00, 0x00, 0x00,
+    0x31, 0xc0, 0x48, 0x83, 0xc4, 0x28, 0xc3,
+];
+
+/// Exported symbols: name, offset in `TEXT`, weak
+const SYMBOLS: [(&str, usize, bool); 4] = [
+    ("__vdso_clock_gettime", CLOCK_GETTIME, false),
+    ("__vdso_gettimeofday", GETTIMEOFDAY, false),
+    ("clock_gettime", CLOCK_GETTIME, true),
+    ("gettimeofday", GETTIMEOFDAY, true),
+];
+
+const SONAME: &str = "linux-vdso.so.1";
+
+// ELF image layout
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PHDR_COUNT: usize = 2;
+const DYN_OFFSET: usize = EHDR_SIZE + PHDR_COUNT * PHDR_SIZE;
+const DYN_COUNT: usize = 7;
+const HASH_OFFSET: usize = DYN_OFFSET + DYN_COUNT * 16;
+/// `nbucket`, `nchain`, one bucket and a chain entry per symbol
+const HASH_SIZE: usize = (3 + SYMBOLS.len() + 1) * 4;
+const SYMTAB_OFFSET: usize = HASH_OFFSET + HASH_SIZE;
+const SYM_SIZE: usize = 24;
+const STRTAB_OFFSET: usize = SYMTAB_OFFSET + (SYMBOLS.len() + 1) * SYM_SIZE;
+
+// Dynamic tags
+const DT_NULL: u64 = 0;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_STRSZ: u64 = 10;
+const DT_SYMENT: u64 = 11;
+const DT_SONAME: u64 = 14;
+
+/// Build the ELF image of the vDSO
+///
+/// A shared object linked at address 0 with one read-execute segment and a
+/// dynamic section exporting [`SYMBOLS`] through `DT_HASH`, which is what
+/// glibc and musl look symbols up with. It has no section headers; symbols
+/// name section 1 so loaders don't take them for undefined.
+pub fn image() -> Vec<u8> {
+    let mut strtab = vec![0u8];
+    let soname = strtab.len();
+    strtab.extend_from_slice(SONAME.as_bytes());
+    strtab.push(0);
+    let mut names = Vec::new();
+    for (name, _, _) in SYMBOLS {
+        names.push(strtab.len());
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+    assert!(STRTAB_OFFSET + strtab.len() <= TEXT_OFFSET);
+
+    let len = TEXT_OFFSET + TEXT.len();
+    let mut image = vec![0u8; len];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    // ELF header
+    put(0, &[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    put(16, &3u16.to_le_bytes()); // ET_DYN
+    put(18, &62u16.to_le_bytes()); // EM_X86_64
+    put(20, &1u32.to_le_bytes()); // EV_CURRENT
+    put(32, &(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+    put(52, &(EHDR_SIZE as u16).to_le_bytes());
+    put(54, &(PHDR_SIZE as u16).to_le_bytes());
+    put(56, &(PHDR_COUNT as u16).to_le_bytes());
+    put(58, &64u16.to_le_bytes()); // e_shentsize
+
+    // PT_LOAD, read-execute, and PT_DYNAMIC
+    let segments = [
+        (1u32, 5u32, 0, len, PAGE_SIZE),
+        (2, 4, DYN_OFFSET, DYN_COUNT * 16, 8),
+    ];
+    for (index, (p_type, flags, offset, size, align)) in segments.into_iter().enumerate() {
+        let at = EHDR_SIZE + index * PHDR_SIZE;
+        put(at, &p_type.to_le_bytes());
+        put(at + 4, &flags.to_le_bytes());
+        // p_offset, p_vaddr, p_paddr, p_filesz, p_memsz, p_align
+        put(at + 8, &(offset as u64).to_le_bytes());
+        put(at + 16, &(offset as u64).to_le_bytes());
+        put(at + 24, &(offset as u64).to_le_bytes());
+        put(at + 32, &(size as u64).to_le_bytes());
+        put(at + 40, &(size as u64).to_le_bytes());
+        put(at + 48, &align.to_le_bytes());
+    }
+
+    // Dynamic section
+    let dynamic = [
+        (DT_HASH, HASH_OFFSET as u64),
+        (DT_STRTAB, STRTAB_OFFSET as u64),
+        (DT_SYMTAB, SYMTAB_OFFSET as u64),
+        (DT_STRSZ, strtab.len() as u64),
+        (DT_SYMENT, SYM_SIZE as u64),
+        (DT_SONAME, soname as u64),
+        (DT_NULL, 0),
+    ];
+    for (index, (tag, value)) in dynamic.into_iter().enumerate() {
+        put(DYN_OFFSET + index * 16, &tag.to_le_bytes());
+        put(DYN_OFFSET + index * 16 + 8, &value.to_le_bytes());
+    }
+
+    // Hash table with a single bucket chaining all symbols
+    let symbol_count = SYMBOLS.len() as u32 + 1;
+    let mut hash = vec![1, symbol_count, 1, 0];
+    hash.extend((2..=symbol_count).map(|next| next % symbol_count));
+    for (index, word) in hash.into_iter().enumerate() {
+        put(HASH_OFFSET + index * 4, &word.to_le_bytes());
+    }
+
+    // Symbols, after the null symbol
+    for (index, ((_, offset, weak), name)) in SYMBOLS.into_iter().zip(names).enumerate() {
+        let at = SYMTAB_OFFSET + (index + 1) * SYM_SIZE;
+        let binding = if weak { 2 } else { 1 };
+        put(at, &(name as u32).to_le_bytes());
+        put(at + 4, &[binding << 4 | 2, 0]); // STT_FUNC, STV_DEFAULT
+        put(at + 6, &1u16.to_le_bytes());
+        put(at + 8, &((TEXT_OFFSET + offset) as u64).to_le_bytes());
+    }
+
+    put(STRTAB_OFFSET, &strtab);
+    put(TEXT_OFFSET, &TEXT);
+    image
+}
+
+/// Read a Linux clock through the Redox clocks, as `(seconds, nanoseconds)`
+///
+/// The boot time clock equals the monotonic one, Redox doesn't suspend.
+pub fn read_clock(clock: i32) -> Result<(i64, i64), LinuxErrno> {
+    let redox_clock = match clock {
+        clock_id::CLOCK_REALTIME | clock_id::CLOCK_REALTIME_COARSE => syscall::CLOCK_REALTIME,
+        clock_id::CLOCK_MONOTONIC
+        | clock_id::CLOCK_MONOTONIC_RAW
+        | clock_id::CLOCK_MONOTONIC_COARSE
+        | clock_id::CLOCK_BOOTTIME => syscall::CLOCK_MONOTONIC,
+        // TODO: CPU time clocks
+        clock_id::CLOCK_PROCESS_CPUTIME_ID | clock_id::CLOCK_THREAD_CPUTIME_ID => {
+            return Err(LinuxErrno::EINVAL)
+        }
+        _ => return Err(LinuxErrno::EINVAL),
+    };
+    let mut time = syscall::TimeSpec::default();
+    syscall::clock_gettime(redox_clock, &mut time)
+        .map_err(|err| LinuxErrno::from_redox(err.errno as usize))?;
+    Ok((time.tv_sec, time.tv_nsec as i64))
+}
+
+/// Clock readings taken together
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// Time stamp counter
+    pub tsc: u64,
+    /// `CLOCK_REALTIME` in nanoseconds
+    pub realtime: u64,
+    /// `CLOCK_MONOTONIC` in nanoseconds
+    pub monotonic: u64,
+}
+
+impl Sample {
+    /// Read the clocks and the TSC
+    pub fn now() -> Result<Self, LinuxErrno> {
+        let nanos = |(sec, nsec): (i64, i64)| sec as u64 * 1_000_000_000 + nsec as u64;
+        let monotonic = nanos(read_clock(clock_id::CLOCK_MONOTONIC)?);
+        let tsc = read_tsc();
+        let realtime = nanos(read_clock(clock_id::CLOCK_REALTIME)?);
+        Ok(Self {
+            tsc,
+            realtime,
+            monotonic,
+        })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn read_tsc() -> u64 {
+    unsafe {
+        core::arch::x86_64::_mm_lfence();
+        core::arch::x86_64::_rdtsc()
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn read_tsc() -> u64 {
+    0
+}
+
+/// Whether the TSC ticks at a constant rate, in sync on all CPUs
+#[cfg(target_arch = "x86_64")]
+fn tsc_invariant() -> bool {
+    use core::arch::x86_64::__cpuid;
+
+    __cpuid(0x8000_0000).eax >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn tsc_invariant() -> bool {
+    false
+}
+
+/// Writer side of the data page
+pub struct Vvar {
+    file: File,
+    seq: u32,
+    /// First sample, the TSC rate is measured against it
+    calibration: Option<Sample>,
+}
+
+impl Vvar {
+    /// Create the data page at `path`, with all clocks on the syscall path
+    pub fn create(path: &str) -> Result<Self, LinuxErrno> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)
+            .map_err(|_| LinuxErrno::EIO)?;
+        file.set_len(PAGE_SIZE).map_err(|_| LinuxErrno::EIO)?;
+        file.write_all_at(&[0; VVAR_LEN], 0)
+            .map_err(|_| LinuxErrno::EIO)?;
+        Ok(Self {
+            file,
+            seq: 0,
+            calibration: None,
+        })
+    }
+
+    /// TSC rate in nanoseconds per tick as 32.32 fixed point, 0 until
+    /// calibrated
+    fn tsc_mult(&mut self, sample: &Sample) -> u64 {
+        if !tsc_invariant() {
+            return 0;
+        }
+        let start = *self.calibration.get_or_insert(*sample);
+        let ticks = sample.tsc.wrapping_sub(start.tsc);
+        let nanos = sample.monotonic.saturating_sub(start.monotonic);
+        if ticks == 0 || nanos < MIN_CALIBRATION_NS {
+            return 0;
+        }
+        u64::try_from(((nanos as u128) << 32) / ticks as u128).unwrap_or(0)
+    }
+
+    /// Publish the clocks at `sample`
+    pub fn publish(&mut self, sample: &Sample) -> Result<(), LinuxErrno> {
+        let mult = self.tsc_mult(sample);
+
+        let mut data = [0u8; VVAR_LEN];
+        data[VVAR_TSC_BASE as usize..][..8].copy_from_slice(&sample.tsc.to_le_bytes());
+        data[VVAR_TSC_BASE as usize + 8..][..8].copy_from_slice(&mult.to_le_bytes());
+        for clock in 0..VVAR_CLOCK_COUNT as i32 {
+            let (nanos, mode) = match clock {
+                clock_id::CLOCK_REALTIME | clock_id::CLOCK_REALTIME_COARSE => {
+                    (sample.realtime, MODE_TSC)
+                }
+                clock_id::CLOCK_MONOTONIC
+                | clock_id::CLOCK_MONOTONIC_RAW
+                | clock_id::CLOCK_MONOTONIC_COARSE
+                | clock_id::CLOCK_BOOTTIME => (sample.monotonic, MODE_TSC),
+                _ => (0, MODE_SYSCALL),
+            };
+            let entry = &mut data[VVAR_CLOCKS as usize + clock as usize * VVAR_CLOCK_SIZE..];
+            entry[0..8].copy_from_slice(&(nanos / 1_000_000_000).to_le_bytes());
+            entry[8..16].copy_from_slice(&(nanos % 1_000_000_000).to_le_bytes());
+            entry[16..24].copy_from_slice(&mode.to_le_bytes());
+        }
+
+        // Odd sequence while the page is inconsistent
+        let write = |bytes: &[u8], offset: u64| {
+            self.file
+                .write_all_at(bytes, offset)
+                .map_err(|_| LinuxErrno::EIO)
+        };
+        write(&self.seq.wrapping_add(1).to_le_bytes(), VVAR_SEQ)?;
+        write(&data[VVAR_TSC_BASE as usize..], VVAR_TSC_BASE)?;
+        self.seq = self.seq.wrapping_add(2);
+        write(&self.seq.to_le_bytes(), VVAR_SEQ)
+    }
+
+    /// Read the clocks and publish them
+    pub fn update(&mut self) -> Result<(), LinuxErrno> {
+        self.publish(&Sample::now()?)
+    }
+}
+
+/// vDSO shared by all processes
+pub struct Vdso {
+    vvar: spin::Mutex<Vvar>,
+}
+
+impl Vdso {
+    /// Create the data page and the image in shared memory
+    pub fn new() -> Result<Self, LinuxErrno> {
+        let vvar = Vvar::create(VVAR_PATH)?;
+        std::fs::write(IMAGE_PATH, image()).map_err(|_| LinuxErrno::EIO)?;
+        let vdso = Self {
+            vvar: spin::Mutex::new(vvar),
+        };
+        vdso.update();
+        Ok(vdso)
+    }
+
+    /// Refresh the data page
+    ///
+    /// Called from the server's event loop; the TSC extrapolation keeps the
+    /// clocks exact between updates, frequent updates only bound the drift
+    /// between the TSC and the kernel clocks.
+    pub fn update(&self) {
+        if let Err(err) = self.vvar.lock().update() {
+            log::warn!("vdso: failed to update clocks: {}", err);
+        }
+    }
+}
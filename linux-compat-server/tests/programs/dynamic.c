@@ -0,0 +1,28 @@
+/* Dynamically linked against libadd: called in place by the dynamic linker
+ * tests rather than run, so no syscalls. Built without -fPIC, so that
+ * counter and hook are copied into the executable */
+
+extern int counter;
+extern int (*hook)(int, int);
+extern int add(int a, int b);
+extern int bump(void);
+extern int lookup(int index);
+
+#define CHECK(cond)          \
+    do {                     \
+        if (!(cond))         \
+            return __LINE__; \
+    } while (0)
+
+long _start(void)
+{
+    /* Through the PLT, and from libadd on to libbase */
+    CHECK(add(2, 3) == 105);
+    CHECK(counter == 42);
+    CHECK(bump() == 43);
+    CHECK(counter == 43);
+    CHECK(hook(1, 2) == 103);
+    CHECK(lookup(0) == 10);
+    CHECK(lookup(1) == 20);
+    return 0;
+}
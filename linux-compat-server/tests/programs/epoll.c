@@ -0,0 +1,133 @@
+/* epoll: level triggered, edge triggered and one shot interests */
+#include "lac.h"
+
+#define EPOLLIN 0x1
+#define EPOLLOUT 0x4
+#define EPOLLHUP 0x10
+#define EPOLLONESHOT (1u << 30)
+#define EPOLLET (1u << 31)
+
+#define EPOLL_CTL_ADD 1
+#define EPOLL_CTL_DEL 2
+#define EPOLL_CTL_MOD 3
+
+#define EFD_NONBLOCK O_NONBLOCK
+
+struct epoll_event {
+    unsigned int events;
+    unsigned long data;
+} __attribute__((packed));
+
+#define epoll_create1(flags) syscall1(SYS_epoll_create1, flags)
+#define epoll_ctl(epfd, op, fd, event) syscall4(SYS_epoll_ctl, epfd, op, fd, event)
+#define epoll_wait(epfd, events, max, timeout) syscall4(SYS_epoll_wait, epfd, events, max, timeout)
+#define eventfd2(initval, flags) syscall2(SYS_eventfd2, initval, flags)
+
+int main(int argc, char **argv)
+{
+    struct epoll_event ev, events[4];
+    unsigned long value = 1;
+    int fds[2];
+    long ep, ep2, efd, efd2, file;
+    char buf[8];
+
+    ep = epoll_create1(0);
+    CHECK(ep == 3);
+    CHECK(pipe(fds) == 0);
+    efd = eventfd2(0, EFD_NONBLOCK);
+    CHECK(efd == 6);
+
+    /* Nothing ready: no timeout returns at once, a timeout expires */
+    ev.events = EPOLLIN;
+    ev.data = 100;
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, fds[0], &ev) == 0);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, fds[0], &ev) == -EEXIST);
+    CHECK(epoll_wait(ep, events, 4, 0) == 0);
+    CHECK(epoll_wait(ep, events, 4, 5) == 0);
+
+    /* Level triggered: reported until drained */
+    CHECK(write(fds[1], "x", 1) == 1);
+    CHECK(epoll_wait(ep, events, 4, -1) == 1);
+    CHECK(events[0].events == EPOLLIN && events[0].data == 100);
+    CHECK(epoll_wait(ep, events, 4, 0) == 1);
+    CHECK(read(fds[0], buf, sizeof(buf)) == 1);
+    CHECK(epoll_wait(ep, events, 4, 0) == 0);
+
+    /* Edge triggered: reported when it becomes ready */
+    ev.events = EPOLLIN | EPOLLET;
+    ev.data = 200;
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, efd, &ev) == 0);
+    CHECK(write(efd, &value, 8) == 8);
+    CHECK(epoll_wait(ep, events, 4, 0) == 1);
+    CHECK(events[0].events == EPOLLIN && events[0].data == 200);
+    CHECK(epoll_wait(ep, events, 4, 0) == 0);
+    CHECK(read(efd, &value, 8) == 8);
+    CHECK(epoll_wait(ep, events, 4, 0) == 0);
+    CHECK(write(efd, &value, 8) == 8);
+    CHECK(epoll_wait(ep, events, 4, 0) == 1);
+    CHECK(events[0].data == 200);
+
+    /* One shot: disabled once reported, until modified */
+    ev.events = EPOLLOUT | EPOLLONESHOT;
+    ev.data = 300;
+    CHECK(epoll_ctl(ep, EPOLL_CTL_MOD, efd, &ev) == 0);
+    CHECK(epoll_wait(ep, events, 4, 0) == 1);
+    CHECK(events[0].events == EPOLLOUT && events[0].data == 300);
+    CHECK(epoll_wait(ep, events, 4, 0) == 0);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_MOD, efd, &ev) == 0);
+    CHECK(epoll_wait(ep, events, 4, 0) == 1);
+
+    /* Ready files take turns when fewer events are asked for */
+    efd2 = eventfd2(1, 0);
+    ev.events = EPOLLIN;
+    ev.data = 201;
+    CHECK(epoll_ctl(ep, EPOLL_CTL_MOD, efd, &ev) == 0);
+    ev.data = 202;
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, efd2, &ev) == 0);
+    CHECK(epoll_wait(ep, events, 1, 0) == 1);
+    CHECK(events[0].data == 201 || events[0].data == 202);
+    CHECK(epoll_wait(ep, &events[1], 1, 0) == 1);
+    CHECK(events[1].data == 201 + 202 - events[0].data);
+    CHECK(epoll_wait(ep, &events[2], 1, 0) == 1);
+    CHECK(events[2].data == events[0].data);
+
+    /* Closing the last descriptor of a file removes it */
+    CHECK(close(efd2) == 0);
+
+    /* Hang ups are reported without being asked for */
+    CHECK(close(fds[1]) == 0);
+    CHECK(epoll_wait(ep, events, 4, 0) == 2);
+    CHECK(events[0].events == EPOLLHUP && events[0].data == 100);
+    CHECK(events[1].events == EPOLLIN && events[1].data == 201);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_DEL, fds[0], 0) == 0);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_DEL, fds[0], 0) == -ENOENT);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_MOD, fds[0], &ev) == -ENOENT);
+
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, ep, &ev) == -EINVAL);
+    CHECK(epoll_ctl(efd, EPOLL_CTL_ADD, fds[0], &ev) == -EINVAL);
+    CHECK(epoll_ctl(ep, 4, fds[0], &ev) == -EINVAL);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, 100, &ev) == -EBADF);
+    file = open(argv[0], O_RDONLY, 0);
+    CHECK(file >= 0);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, file, &ev) == -EPERM);
+    CHECK(epoll_wait(ep, events, 0, 0) == -EINVAL);
+    CHECK(epoll_wait(efd, events, 4, 0) == -EINVAL);
+
+    /* Nested: readable while an instance it watches has events */
+    ep2 = syscall1(SYS_epoll_create, 1);
+    CHECK(ep2 >= 0);
+    ev.data = 400;
+    CHECK(epoll_ctl(ep2, EPOLL_CTL_ADD, ep, &ev) == 0);
+    CHECK(epoll_ctl(ep, EPOLL_CTL_ADD, ep2, &ev) == -ELOOP);
+    CHECK(epoll_wait(ep2, events, 4, 0) == 1);
+    CHECK(events[0].events == EPOLLIN && events[0].data == 400);
+    CHECK(read(efd, &value, 8) == 8);
+    CHECK(epoll_wait(ep2, events, 4, 0) == 0);
+    CHECK(syscall1(SYS_epoll_create, 0) == -EINVAL);
+
+    /* epoll_pwait takes a signal mask on top */
+    CHECK(write(efd, &value, 8) == 8);
+    CHECK(syscall6(SYS_epoll_pwait, ep, (long)events, 4, -1, 0, 8) == 1);
+    CHECK(events[0].data == 201);
+    return 0;
+}
@@ -0,0 +1,57 @@
+/* eventfd: a counter read and written 8 bytes at a time */
+#include "lac.h"
+
+#define EFD_SEMAPHORE 1
+#define EFD_NONBLOCK O_NONBLOCK
+
+#define eventfd2(initval, flags) syscall2(SYS_eventfd2, initval, flags)
+
+int main(int argc, char **argv)
+{
+    struct linux_stat st;
+    unsigned long value;
+    long fd;
+
+    fd = eventfd2(0, EFD_NONBLOCK);
+    CHECK(fd == 3);
+    CHECK(read(fd, &value, 8) == -EAGAIN);
+    value = 2;
+    CHECK(write(fd, &value, 8) == 8);
+    value = 3;
+    CHECK(write(fd, &value, 8) == 8);
+    CHECK(read(fd, &value, 4) == -EINVAL);
+    CHECK(read(fd, &value, 8) == 8);
+    CHECK(value == 5);
+    CHECK(read(fd, &value, 8) == -EAGAIN);
+
+    /* The counter stops one short of the largest value */
+    value = -1UL;
+    CHECK(write(fd, &value, 8) == -EINVAL);
+    value = -2UL;
+    CHECK(write(fd, &value, 8) == 8);
+    value = 1;
+    CHECK(write(fd, &value, 8) == -EAGAIN);
+
+    /* An anonymous inode, not a file */
+    CHECK(lseek(fd, 0, SEEK_SET) == -ESPIPE);
+    CHECK(fstat(fd, &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == 0);
+    CHECK((st.st_mode & 0777) == 0600);
+    CHECK(close(fd) == 0);
+
+    /* Semaphores are taken one at a time */
+    fd = eventfd2(2, EFD_SEMAPHORE);
+    CHECK(fd == 3);
+    CHECK(read(fd, &value, 8) == 8);
+    CHECK(value == 1);
+    CHECK(read(fd, &value, 8) == 8);
+    CHECK(value == 1);
+    CHECK(close(fd) == 0);
+
+    fd = syscall1(SYS_eventfd, 7);
+    CHECK(fd == 3);
+    CHECK(read(fd, &value, 8) == 8);
+    CHECK(value == 7);
+    CHECK(eventfd2(0, 0x10) == -EINVAL);
+    return 0;
+}
@@ -0,0 +1,157 @@
+/* AF_INET sockets over loopback: TCP connections and UDP datagrams */
+#include "lac.h"
+
+#define AF_UNIX 1
+#define AF_INET 2
+#define SOCK_STREAM 1
+#define SOCK_DGRAM 2
+#define SOCK_NONBLOCK O_NONBLOCK
+
+#define SOL_SOCKET 1
+#define SO_REUSEADDR 2
+#define SO_ERROR 4
+#define SO_PROTOCOL 38
+#define IPPROTO_TCP 6
+#define IPPROTO_UDP 17
+#define TCP_NODELAY 1
+
+#define POLLOUT 0x4
+
+struct sockaddr_in {
+    unsigned short sin_family;
+    unsigned short sin_port;
+    unsigned int sin_addr;
+    char sin_zero[8];
+};
+
+struct pollfd {
+    int fd;
+    short events;
+    short revents;
+};
+
+#define LOOPBACK 0x0100007f
+
+#define socket(domain, type, protocol) syscall3(SYS_socket, domain, type, protocol)
+#define bind(fd, addr, len) syscall3(SYS_bind, fd, addr, len)
+#define listen(fd, backlog) syscall2(SYS_listen, fd, backlog)
+#define connect(fd, addr, len) syscall3(SYS_connect, fd, addr, len)
+#define accept(fd, addr, len) syscall3(SYS_accept, fd, addr, len)
+#define sendto(fd, buf, len, flags, addr, alen) \
+    syscall6(SYS_sendto, fd, (long)(buf), len, flags, (long)(addr), alen)
+#define recvfrom(fd, buf, len, flags, addr, alen) \
+    syscall6(SYS_recvfrom, fd, (long)(buf), len, flags, (long)(addr), (long)(alen))
+#define getsockname(fd, addr, len) syscall3(SYS_getsockname, fd, addr, len)
+#define getpeername(fd, addr, len) syscall3(SYS_getpeername, fd, addr, len)
+#define getsockopt(fd, level, name, val, len) \
+    syscall6(SYS_getsockopt, fd, level, name, (long)(val), (long)(len), 0)
+#define setsockopt(fd, level, name, val, len) \
+    syscall6(SYS_setsockopt, fd, level, name, (long)(val), len, 0)
+
+/* Loopback address with an unused port */
+static int bound(long fd, struct sockaddr_in *addr)
+{
+    unsigned int len = sizeof(*addr);
+    memset(addr, 0, sizeof(*addr));
+    addr->sin_family = AF_INET;
+    addr->sin_addr = LOOPBACK;
+    if (bind(fd, addr, sizeof(*addr)) != 0)
+        return -1;
+    if (getsockname(fd, addr, &len) != 0 || len != sizeof(*addr))
+        return -1;
+    return addr->sin_port != 0 ? 0 : -1;
+}
+
+int main(int argc, char **argv)
+{
+    struct sockaddr_in addr, local, peer, from;
+    struct pollfd pfd;
+    unsigned int len;
+    long server, client, conn, closed, udp1, udp2;
+    int value, one = 1;
+    char buf[16];
+
+    server = socket(AF_INET, SOCK_STREAM, 0);
+    CHECK(server == 3);
+    CHECK(setsockopt(server, SOL_SOCKET, SO_REUSEADDR, &one, sizeof(one)) == 0);
+    CHECK(bound(server, &addr) == 0);
+    CHECK(addr.sin_addr == LOOPBACK);
+    len = sizeof(peer);
+    CHECK(accept(server, &peer, &len) == -EINVAL);
+    CHECK(listen(server, 4) == 0);
+
+    /* A blocking connect waits for the connection */
+    client = socket(AF_INET, SOCK_STREAM, IPPROTO_TCP);
+    CHECK(client == 4);
+    CHECK(connect(client, &addr, sizeof(addr)) == 0);
+    len = sizeof(peer);
+    CHECK(getpeername(client, &peer, &len) == 0);
+    CHECK(peer.sin_port == addr.sin_port && peer.sin_addr == LOOPBACK);
+
+    len = sizeof(peer);
+    conn = accept(server, &peer, &len);
+    CHECK(conn == 5);
+    CHECK(len == sizeof(peer) && peer.sin_family == AF_INET);
+    len = sizeof(local);
+    CHECK(getsockname(client, &local, &len) == 0);
+    CHECK(local.sin_port == peer.sin_port && local.sin_addr == peer.sin_addr);
+
+    CHECK(setsockopt(client, IPPROTO_TCP, TCP_NODELAY, &one, sizeof(one)) == 0);
+    len = sizeof(value);
+    CHECK(getsockopt(client, IPPROTO_TCP, TCP_NODELAY, &value, &len) == 0);
+    CHECK(value != 0);
+    CHECK(getsockopt(client, SOL_SOCKET, SO_PROTOCOL, &value, &len) == 0);
+    CHECK(value == IPPROTO_TCP);
+
+    CHECK(sendto(client, "hello", 5, 0, 0, 0) == 5);
+    len = sizeof(from);
+    CHECK(recvfrom(conn, buf, sizeof(buf), 0, &from, &len) == 5);
+    CHECK(memcmp(buf, "hello", 5) == 0);
+    CHECK(close(conn) == 0);
+    CHECK(read(client, buf, sizeof(buf)) == 0);
+    CHECK(close(client) == 0);
+
+    /* Non-blocking connects are left to poll and SO_ERROR */
+    client = socket(AF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0);
+    CHECK(client == 4);
+    value = connect(client, &addr, sizeof(addr));
+    CHECK(value == 0 || value == -EINPROGRESS);
+    pfd.fd = client;
+    pfd.events = POLLOUT;
+    CHECK(syscall3(SYS_poll, &pfd, 1, -1) == 1);
+    len = sizeof(value);
+    CHECK(getsockopt(client, SOL_SOCKET, SO_ERROR, &value, &len) == 0);
+    CHECK(value == 0);
+    len = sizeof(peer);
+    CHECK(accept(server, &peer, &len) == 5);
+    CHECK(close(5) == 0);
+    CHECK(close(client) == 0);
+
+    /* Nobody listens on a port only bound */
+    closed = socket(AF_INET, SOCK_STREAM, 0);
+    CHECK(bound(closed, &local) == 0);
+    client = socket(AF_INET, SOCK_STREAM, 0);
+    CHECK(connect(client, &local, sizeof(local)) == -ECONNREFUSED);
+    CHECK(getpeername(client, &peer, &len) == -ENOTCONN);
+
+    /* UDP: datagrams come with their sender */
+    udp1 = socket(AF_INET, SOCK_DGRAM, 0);
+    udp2 = socket(AF_INET, SOCK_DGRAM, IPPROTO_UDP);
+    CHECK(bound(udp1, &local) == 0);
+    CHECK(bound(udp2, &peer) == 0);
+    CHECK(sendto(udp1, "datagram", 8, 0, &peer, sizeof(peer)) == 8);
+    len = sizeof(from);
+    memset(&from, 0, sizeof(from));
+    CHECK(recvfrom(udp2, buf, sizeof(buf), 0, &from, &len) == 8);
+    CHECK(memcmp(buf, "datagram", 8) == 0);
+    CHECK(len == sizeof(from) && from.sin_family == AF_INET);
+    CHECK(from.sin_port == local.sin_port && from.sin_addr == LOOPBACK);
+    CHECK(listen(udp1, 1) == -EOPNOTSUPP);
+
+    CHECK(bind(udp1, &addr, 8) == -EINVAL);
+    addr.sin_family = AF_UNIX;
+    CHECK(bind(udp1, &addr, sizeof(addr)) == -EAFNOSUPPORT);
+    CHECK(bind(udp1, &addr, -1) == -EINVAL);
+    CHECK(socket(AF_INET, SOCK_DGRAM, IPPROTO_TCP) == -EPROTONOSUPPORT);
+    return 0;
+}
@@ -0,0 +1,221 @@
+/*
+ * Runtime of the translator's test programs
+ *
+ * No libc: every syscall a program makes is one it spells out, so the tests
+ * know exactly what the translator sees. A failed CHECK exits with its line
+ * number.
+ */
+#ifndef LAC_H
+#define LAC_H
+
+#define SYS_read 0
+#define SYS_write 1
+#define SYS_open 2
+#define SYS_close 3
+#define SYS_stat 4
+#define SYS_fstat 5
+#define SYS_lstat 6
+#define SYS_poll 7
+#define SYS_lseek 8
+#define SYS_pipe 22
+#define SYS_select 23
+#define SYS_dup 32
+#define SYS_dup2 33
+#define SYS_getpid 39
+#define SYS_socket 41
+#define SYS_connect 42
+#define SYS_accept 43
+#define SYS_sendto 44
+#define SYS_recvfrom 45
+#define SYS_shutdown 48
+#define SYS_bind 49
+#define SYS_listen 50
+#define SYS_getsockname 51
+#define SYS_getpeername 52
+#define SYS_socketpair 53
+#define SYS_setsockopt 54
+#define SYS_getsockopt 55
+#define SYS_clone 56
+#define SYS_exit 60
+#define SYS_readlink 89
+#define SYS_arch_prctl 158
+#define SYS_gettid 186
+#define SYS_futex 202
+#define SYS_epoll_create 213
+#define SYS_getdents64 217
+#define SYS_set_tid_address 218
+#define SYS_exit_group 231
+#define SYS_epoll_wait 232
+#define SYS_epoll_ctl 233
+#define SYS_openat 257
+#define SYS_newfstatat 262
+#define SYS_readlinkat 267
+#define SYS_pselect6 270
+#define SYS_ppoll 271
+#define SYS_epoll_pwait 281
+#define SYS_timerfd_create 283
+#define SYS_eventfd 284
+#define SYS_timerfd_settime 286
+#define SYS_timerfd_gettime 287
+#define SYS_accept4 288
+#define SYS_eventfd2 290
+#define SYS_epoll_create1 291
+#define SYS_dup3 292
+#define SYS_pipe2 293
+
+#define EPERM 1
+#define ENOENT 2
+#define EBADF 9
+#define EAGAIN 11
+#define EACCES 13
+#define EFAULT 14
+#define EEXIST 17
+#define ENOTDIR 20
+#define EISDIR 21
+#define EINVAL 22
+#define ESPIPE 29
+#define ENOSYS 38
+#define ELOOP 40
+#define ENOTSOCK 88
+#define ENOPROTOOPT 92
+#define EPROTONOSUPPORT 93
+#define EOPNOTSUPP 95
+#define EAFNOSUPPORT 97
+#define EADDRINUSE 98
+#define ENOTCONN 107
+#define ETIMEDOUT 110
+#define ECONNREFUSED 111
+#define EINPROGRESS 115
+
+#define O_RDONLY 0
+#define O_WRONLY 1
+#define O_RDWR 2
+#define O_CREAT 0100
+#define O_EXCL 0200
+#define O_TRUNC 01000
+#define O_APPEND 02000
+#define O_NONBLOCK 04000
+#define O_DIRECTORY 0200000
+#define O_CLOEXEC 02000000
+
+#define SEEK_SET 0
+#define SEEK_CUR 1
+#define SEEK_END 2
+
+#define AT_FDCWD -100
+#define AT_SYMLINK_NOFOLLOW 0x100
+#define AT_EMPTY_PATH 0x1000
+
+#define S_IFMT 0170000
+#define S_IFDIR 0040000
+#define S_IFREG 0100000
+#define S_IFLNK 0120000
+#define S_IFSOCK 0140000
+
+struct linux_stat {
+    unsigned long st_dev;
+    unsigned long st_ino;
+    unsigned long st_nlink;
+    unsigned int st_mode;
+    unsigned int st_uid;
+    unsigned int st_gid;
+    unsigned int pad0;
+    unsigned long st_rdev;
+    long st_size;
+    long st_blksize;
+    long st_blocks;
+    long st_atime;
+    long st_atime_nsec;
+    long st_mtime;
+    long st_mtime_nsec;
+    long st_ctime;
+    long st_ctime_nsec;
+    long unused[3];
+};
+
+static long syscall6(long n, long a, long b, long c, long d, long e, long f)
+{
+    register long r10 __asm__("r10") = d;
+    register long r8 __asm__("r8") = e;
+    register long r9 __asm__("r9") = f;
+    long ret;
+    __asm__ volatile("syscall"
+                     : "=a"(ret)
+                     : "a"(n), "D"(a), "S"(b), "d"(c), "r"(r10), "r"(r8), "r"(r9)
+                     : "rcx", "r11", "memory");
+    return ret;
+}
+
+#define syscall0(n) syscall6(n, 0, 0, 0, 0, 0, 0)
+#define syscall1(n, a) syscall6(n, (long)(a), 0, 0, 0, 0, 0)
+#define syscall2(n, a, b) syscall6(n, (long)(a), (long)(b), 0, 0, 0, 0)
+#define syscall3(n, a, b, c) syscall6(n, (long)(a), (long)(b), (long)(c), 0, 0, 0)
+#define syscall4(n, a, b, c, d) syscall6(n, (long)(a), (long)(b), (long)(c), (long)(d), 0, 0)
+
+#define read(fd, buf, count) syscall3(SYS_read, fd, buf, count)
+#define write(fd, buf, count) syscall3(SYS_write, fd, buf, count)
+#define open(path, flags, mode) syscall3(SYS_open, path, flags, mode)
+#define openat(dirfd, path, flags, mode) syscall4(SYS_openat, dirfd, path, flags, mode)
+#define close(fd) syscall1(SYS_close, fd)
+#define lseek(fd, offset, whence) syscall3(SYS_lseek, fd, offset, whence)
+#define stat(path, buf) syscall2(SYS_stat, path, buf)
+#define fstat(fd, buf) syscall2(SYS_fstat, fd, buf)
+#define lstat(path, buf) syscall2(SYS_lstat, path, buf)
+#define newfstatat(dirfd, path, buf, flags) syscall4(SYS_newfstatat, dirfd, path, buf, flags)
+#define pipe(fds) syscall1(SYS_pipe, fds)
+#define pipe2(fds, flags) syscall2(SYS_pipe2, fds, flags)
+#define dup(fd) syscall1(SYS_dup, fd)
+#define dup2(oldfd, newfd) syscall2(SYS_dup2, oldfd, newfd)
+#define dup3(oldfd, newfd, flags) syscall3(SYS_dup3, oldfd, newfd, flags)
+
+#define CHECK(cond)              \
+    do {                         \
+        if (!(cond))             \
+            return __LINE__;     \
+    } while (0)
+
+/* The compiler may emit calls to these even without libc */
+void *memset(void *dst, int c, unsigned long n)
+{
+    unsigned char *d = dst;
+    while (n--)
+        *d++ = (unsigned char)c;
+    return dst;
+}
+
+void *memcpy(void *dst, const void *src, unsigned long n)
+{
+    unsigned char *d = dst;
+    const unsigned char *s = src;
+    while (n--)
+        *d++ = *s++;
+    return dst;
+}
+
+int memcmp(const void *a, const void *b, unsigned long n)
+{
+    const unsigned char *x = a, *y = b;
+    for (; n; n--, x++, y++)
+        if (*x != *y)
+            return *x - *y;
+    return 0;
+}
+
+int main(int argc, char **argv);
+
+__attribute__((used)) static void start(long *sp)
+{
+    syscall1(SYS_exit_group, main((int)sp[0], (char **)(sp + 1)));
+    for (;;)
+        ;
+}
+
+__asm__(".global _start\n"
+        "_start:\n"
+        "\txor %ebp, %ebp\n"
+        "\tmov %rsp, %rdi\n"
+        "\tand $-16, %rsp\n"
+        "\tcall start\n"
+        "\thlt\n");
+
+#endif
@@ -0,0 +1,29 @@
+/* Shared library linked into dynamic, needing libbase */
+
+extern int base_value(void);
+
+int counter = 42;
+
+int add(int a, int b)
+{
+    return a + b + base_value();
+}
+
+/* Absolute reference to a function */
+int (*hook)(int, int) = add;
+
+/* Goes through the GOT, so it must see the executable's copy */
+int bump(void)
+{
+    return ++counter;
+}
+
+static int first = 10;
+static int second = 20;
+/* Pointers into the library itself */
+static int *table[] = { &first, &second };
+
+int lookup(int index)
+{
+    return *table[index];
+}
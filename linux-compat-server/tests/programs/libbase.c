@@ -0,0 +1,6 @@
+/* Shared library needed by libadd */
+
+int base_value(void)
+{
+    return 100;
+}
@@ -0,0 +1,57 @@
+/* Shared library with thread-local variables and indirect functions, linked
+ * into tls */
+
+extern int __libc_enable_secure;
+
+__thread int tls_counter = 5;
+__thread int tls_zero;
+static __thread int tls_local = 7;
+
+/* General dynamic model, through __tls_get_addr */
+int *counter_addr(void)
+{
+    return &tls_counter;
+}
+
+/* Local dynamic model */
+int local_value(void)
+{
+    return tls_local++;
+}
+
+/* Defined by the runtime in place of ld.so */
+int secure(void)
+{
+    return __libc_enable_secure;
+}
+
+static int pick_impl(void)
+{
+    return 11;
+}
+
+static void *resolve_pick(void)
+{
+    return pick_impl;
+}
+
+/* Bound by the executable's PLT slot */
+int pick(void) __attribute__((ifunc("resolve_pick")));
+
+static int local_impl(void)
+{
+    return 22;
+}
+
+static void *resolve_local(void)
+{
+    return local_impl;
+}
+
+/* Only used here, so bound by an IRELATIVE relocation */
+static int local_pick(void) __attribute__((ifunc("resolve_local")));
+
+int call_local(void)
+{
+    return local_pick();
+}
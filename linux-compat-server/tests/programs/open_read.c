@@ -0,0 +1,39 @@
+/* open, read and close: argv[1] holds "hello, lac\n", argv[2] is a directory */
+#include "lac.h"
+
+int main(int argc, char **argv)
+{
+    char buf[64];
+    long fd;
+
+    CHECK(argc == 3);
+
+    fd = open(argv[1], O_RDONLY, 0);
+    CHECK(fd == 3);
+    CHECK(read(fd, buf, sizeof(buf)) == 11);
+    CHECK(memcmp(buf, "hello, lac\n", 11) == 0);
+    /* End of file */
+    CHECK(read(fd, buf, sizeof(buf)) == 0);
+    CHECK(close(fd) == 0);
+
+    CHECK(close(fd) == -EBADF);
+    CHECK(read(fd, buf, sizeof(buf)) == -EBADF);
+    CHECK(read(-1, buf, sizeof(buf)) == -EBADF);
+
+    CHECK(open("/nonexistent/lac", O_RDONLY, 0) == -ENOENT);
+    CHECK(open(argv[2], O_WRONLY, 0) == -EISDIR);
+    CHECK(open((char *)0, O_RDONLY, 0) == -EFAULT);
+    CHECK(open("", O_RDONLY, 0) == -ENOENT);
+
+    /* Write-only descriptors can't be read, and the read buffer must be mapped */
+    fd = open(argv[1], O_WRONLY, 0);
+    CHECK(fd == 3);
+    CHECK(read(fd, buf, 1) == -EBADF);
+    CHECK(close(fd) == 0);
+    fd = open(argv[1], O_RDONLY, 0);
+    CHECK(read(fd, (char *)8, 1) == -EFAULT);
+
+    /* Standard output is inherited */
+    CHECK(write(1, "", 0) == 0);
+    return 0;
+}
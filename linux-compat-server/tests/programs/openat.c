@@ -0,0 +1,40 @@
+/* Path resolution: runs in the directory argv[1], which holds "data" */
+#include "lac.h"
+
+int main(int argc, char **argv)
+{
+    char buf[16];
+    long dirfd, fd;
+
+    CHECK(argc == 2);
+
+    dirfd = open(argv[1], O_RDONLY | O_DIRECTORY, 0);
+    CHECK(dirfd == 3);
+    fd = openat(dirfd, "data", O_RDONLY, 0);
+    CHECK(fd == 4);
+    CHECK(read(fd, buf, 5) == 5);
+    CHECK(memcmp(buf, "hello", 5) == 0);
+
+    /* Relative to the working directory */
+    CHECK(openat(AT_FDCWD, "./data", O_RDONLY, 0) == 5);
+    CHECK(open("data", O_RDONLY, 0) == 6);
+
+    /* Only directories resolve relative paths */
+    CHECK(openat(fd, "data", O_RDONLY, 0) == -ENOTDIR);
+    CHECK(open("data", O_RDONLY | O_DIRECTORY, 0) == -ENOTDIR);
+    CHECK(openat(99, "data", O_RDONLY, 0) == -EBADF);
+    /* Absolute paths ignore the directory */
+    CHECK(openat(fd, argv[1], O_RDONLY | O_DIRECTORY, 0) == 7);
+
+    CHECK(open("data", O_WRONLY | O_CREAT | O_EXCL, 0644) == -EEXIST);
+    CHECK(openat(dirfd, "new", O_WRONLY | O_CREAT | O_EXCL, 0640) == 8);
+    CHECK(openat(dirfd, "missing", O_RDONLY, 0) == -ENOENT);
+
+    /* The lowest free descriptor is reused */
+    CHECK(close(4) == 0);
+    CHECK(close(6) == 0);
+    CHECK(open("data", O_RDONLY, 0) == 4);
+    CHECK(open("data", O_RDONLY, 0) == 6);
+    CHECK(open("data", O_RDONLY, 0) == 9);
+    return 0;
+}
@@ -0,0 +1,39 @@
+/* pipe and dup: descriptors sharing an open file */
+#include "lac.h"
+
+int main(int argc, char **argv)
+{
+    int fds[2];
+    char buf[8];
+    long copy;
+
+    CHECK(pipe(fds) == 0);
+    CHECK(fds[0] == 3 && fds[1] == 4);
+    CHECK(write(fds[1], "ping", 4) == 4);
+    CHECK(read(fds[0], buf, sizeof(buf)) == 4);
+    CHECK(memcmp(buf, "ping", 4) == 0);
+
+    /* The pipe stays open while a duplicate of its write end does */
+    copy = dup(fds[1]);
+    CHECK(copy == 5);
+    CHECK(close(fds[1]) == 0);
+    CHECK(write(copy, "pong", 4) == 4);
+    CHECK(close(copy) == 0);
+    CHECK(read(fds[0], buf, sizeof(buf)) == 4);
+    CHECK(read(fds[0], buf, sizeof(buf)) == 0);
+
+    CHECK(pipe2(fds, O_CLOEXEC) == 0);
+    CHECK(fds[0] == 4 && fds[1] == 5);
+    CHECK(pipe2(fds, 1) == -EINVAL);
+    CHECK(pipe((int *)8) == -EFAULT);
+
+    CHECK(dup2(fds[1], 10) == 10);
+    CHECK(dup2(10, 10) == 10);
+    CHECK(write(10, "x", 1) == 1);
+    CHECK(read(fds[0], buf, 1) == 1);
+    CHECK(dup3(10, 10, 0) == -EINVAL);
+    CHECK(dup3(fds[1], 11, O_CLOEXEC) == 11);
+    CHECK(dup2(99, 12) == -EBADF);
+    CHECK(dup(99) == -EBADF);
+    return 0;
+}
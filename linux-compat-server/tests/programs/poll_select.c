@@ -0,0 +1,88 @@
+/* poll and select on the ends of a pipe */
+#include "lac.h"
+
+#define POLLIN 0x1
+#define POLLOUT 0x4
+#define POLLHUP 0x10
+#define POLLNVAL 0x20
+
+struct pollfd {
+    int fd;
+    short events;
+    short revents;
+};
+
+struct timespec {
+    long tv_sec;
+    long tv_nsec;
+};
+
+struct timeval {
+    long tv_sec;
+    long tv_usec;
+};
+
+#define poll(fds, nfds, timeout) syscall3(SYS_poll, fds, nfds, timeout)
+#define select(nfds, rd, wr, ex, timeout) syscall6(SYS_select, nfds, (long)(rd), (long)(wr), (long)(ex), (long)(timeout), 0)
+
+int main(int argc, char **argv)
+{
+    struct pollfd pfds[3];
+    struct timespec ts = {0, 0};
+    struct timeval tv = {0, 0};
+    unsigned long rd[16], wr[16];
+    int fds[2];
+    char buf[8];
+
+    CHECK(pipe(fds) == 0);
+    pfds[0].fd = fds[0];
+    pfds[0].events = POLLIN;
+    pfds[1].fd = fds[1];
+    pfds[1].events = POLLOUT;
+    pfds[2].fd = -1;
+    pfds[2].events = POLLIN;
+    CHECK(poll(pfds, 3, 0) == 1);
+    CHECK(pfds[0].revents == 0 && pfds[1].revents == POLLOUT && pfds[2].revents == 0);
+    /* Times out, and sleeps without descriptors */
+    CHECK(poll(pfds, 1, 5) == 0);
+    CHECK(poll(0, 0, 5) == 0);
+
+    CHECK(write(fds[1], "x", 1) == 1);
+    CHECK(poll(pfds, 2, -1) == 2);
+    CHECK(pfds[0].revents == POLLIN && pfds[1].revents == POLLOUT);
+    CHECK(syscall6(SYS_ppoll, (long)pfds, 1, (long)&ts, 0, 8, 0) == 1);
+    pfds[2].fd = 100;
+    CHECK(poll(&pfds[2], 1, 0) == 1);
+    CHECK(pfds[2].revents == POLLNVAL);
+    CHECK(poll(pfds, 2000, 0) == -EINVAL);
+
+    memset(rd, 0, sizeof(rd));
+    memset(wr, 0, sizeof(wr));
+    rd[0] = 1UL << fds[0];
+    wr[0] = 1UL << fds[1];
+    CHECK(select(fds[1] + 1, rd, wr, 0, &tv) == 2);
+    CHECK(rd[0] == 1UL << fds[0] && wr[0] == 1UL << fds[1]);
+    CHECK(read(fds[0], buf, sizeof(buf)) == 1);
+
+    /* Sets are cleared when nothing is ready */
+    tv.tv_usec = 5000;
+    CHECK(select(fds[0] + 1, rd, 0, 0, &tv) == 0);
+    CHECK(rd[0] == 0);
+    rd[0] = 1UL << 50;
+    CHECK(select(51, rd, 0, 0, &tv) == -EBADF);
+    tv.tv_usec = 1000000;
+    CHECK(select(1, 0, 0, 0, &tv) == -EINVAL);
+    CHECK(select(-1, 0, 0, 0, 0) == -EINVAL);
+
+    wr[0] = 1UL << fds[1];
+    CHECK(syscall6(SYS_pselect6, fds[1] + 1, 0, (long)wr, 0, (long)&ts, 0) == 1);
+    CHECK(wr[0] == 1UL << fds[1]);
+
+    /* A closed write end reads as end of file */
+    CHECK(close(fds[1]) == 0);
+    rd[0] = 1UL << fds[0];
+    CHECK(select(fds[0] + 1, rd, 0, 0, 0) == 1);
+    CHECK(poll(pfds, 1, 0) == 1);
+    CHECK(pfds[0].revents == POLLHUP);
+    return 0;
+}
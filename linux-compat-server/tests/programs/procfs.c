@@ -0,0 +1,155 @@
+/* /proc and /sys: argv[1] is the working directory the process was given */
+#include "lac.h"
+
+#define O_NOFOLLOW 0400000
+
+#define readlink(path, buf, size) syscall3(SYS_readlink, path, buf, size)
+#define getdents64(fd, buf, len) syscall3(SYS_getdents64, fd, buf, len)
+
+struct linux_dirent64 {
+    unsigned long d_ino;
+    long d_off;
+    unsigned short d_reclen;
+    unsigned char d_type;
+    char d_name[];
+} __attribute__((packed));
+
+#define DT_DIR 4
+#define DT_LNK 10
+
+static long length(const char *s)
+{
+    long n = 0;
+    while (s[n])
+        n++;
+    return n;
+}
+
+static int contains(const char *buf, long len, const char *needle)
+{
+    long n = length(needle), i;
+    for (i = 0; i + n <= len; i++)
+        if (memcmp(buf + i, needle, n) == 0)
+            return 1;
+    return 0;
+}
+
+/* Read all of a file, returning its length */
+static long slurp(const char *path, char *buf, long size)
+{
+    long fd = open(path, O_RDONLY, 0), n, total = 0;
+    if (fd < 0)
+        return fd;
+    while ((n = read(fd, buf + total, size - total)) > 0)
+        total += n;
+    close(fd);
+    return n < 0 ? n : total;
+}
+
+int main(int argc, char **argv)
+{
+    static char buf[65536];
+    struct linux_stat st;
+    struct linux_dirent64 *entry;
+    long n, fd, dir, pos, entries;
+
+    CHECK(argc == 2);
+
+    /* self is the process' own directory, named by its Linux PID */
+    CHECK(readlink("/proc/self", buf, sizeof(buf)) == 1);
+    CHECK(buf[0] == '1');
+    n = readlink("/proc/self/exe", buf, sizeof(buf));
+    CHECK(n == length(argv[0]) && memcmp(buf, argv[0], n) == 0);
+    n = readlink("/proc/1/cwd", buf, sizeof(buf));
+    CHECK(n == length(argv[1]) && memcmp(buf, argv[1], n) == 0);
+    CHECK(readlink("/proc/self/exe", buf, 3) == 3);
+    CHECK(readlink("/proc/self/exe", buf, 0) == -EINVAL);
+    CHECK(readlink("/proc/self/status", buf, sizeof(buf)) == -EINVAL);
+    CHECK(readlink(argv[0], buf, sizeof(buf)) == -EINVAL);
+
+    /* Files of the process */
+    n = slurp("/proc/self/status", buf, sizeof(buf));
+    CHECK(n > 0);
+    CHECK(contains(buf, n, "Name:\tprocfs\n"));
+    CHECK(contains(buf, n, "\nPid:\t1\n"));
+    CHECK(contains(buf, n, "\nThreads:\t1\n"));
+    n = slurp("/proc/self/cmdline", buf, sizeof(buf));
+    CHECK(n == length(argv[0]) + length(argv[1]) + 2);
+    CHECK(memcmp(buf, argv[0], length(argv[0]) + 1) == 0);
+    CHECK(memcmp(buf + length(argv[0]) + 1, argv[1], length(argv[1]) + 1) == 0);
+    n = slurp("/proc/self/maps", buf, sizeof(buf));
+    CHECK(contains(buf, n, " r-xp "));
+    CHECK(contains(buf, n, argv[0]));
+    CHECK(contains(buf, n, "[stack]\n"));
+
+    /* Files are read-only snapshots */
+    CHECK(open("/proc/self/comm", O_WRONLY, 0) == -EACCES);
+    fd = open("/proc/self/comm", O_RDONLY, 0);
+    CHECK(fd == 3);
+    CHECK(read(fd, buf, 2) == 2 && memcmp(buf, "pr", 2) == 0);
+    CHECK(lseek(fd, 1, SEEK_SET) == 1);
+    CHECK(read(fd, buf, sizeof(buf)) == 6 && memcmp(buf, "rocfs\n", 6) == 0);
+    CHECK(read(fd, buf, sizeof(buf)) == 0);
+    CHECK(lseek(fd, -1, SEEK_END) == 6);
+    CHECK(write(fd, "x", 1) == -EBADF);
+    CHECK(fstat(fd, &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFREG && st.st_size == 0);
+
+    /* Descriptors link to the path their file was found at */
+    n = readlink("/proc/self/fd/3", buf, sizeof(buf));
+    CHECK(n == 12 && memcmp(buf, "/proc/1/comm", 12) == 0);
+    CHECK(readlink("/proc/self/fd/9", buf, sizeof(buf)) == -ENOENT);
+    CHECK(readlink("/proc/self/fd/03", buf, sizeof(buf)) == -ENOENT);
+
+    /* Links are followed unless asked not to */
+    CHECK(stat("/proc/self", &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFDIR);
+    CHECK(lstat("/proc/self", &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFLNK);
+    CHECK(stat("/proc/self/exe", &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFREG && st.st_size > 0);
+    CHECK(open("/proc/self", O_RDONLY | O_NOFOLLOW, 0) == -ELOOP);
+    CHECK(open("/proc/2/status", O_RDONLY, 0) == -ENOENT);
+    CHECK(open("/proc/self/nothing", O_RDONLY, 0) == -ENOENT);
+    CHECK(open("/proc/self/status/x", O_RDONLY, 0) == -ENOTDIR);
+    CHECK(open("/proc/self/status", O_RDONLY | O_DIRECTORY, 0) == -ENOTDIR);
+
+    /* Directories list their entries, as of when they were opened: here
+     * before the directory's own descriptor */
+    dir = open("/proc/self/fd", O_RDONLY | O_DIRECTORY, 0);
+    CHECK(dir == 4);
+    n = getdents64(dir, buf, sizeof(buf));
+    CHECK(n > 0);
+    for (pos = 0, entries = 0; pos < n; pos += entry->d_reclen, entries++) {
+        entry = (struct linux_dirent64 *)(buf + pos);
+        if (entries < 2)
+            CHECK(entry->d_type == DT_DIR);
+        else
+            CHECK(entry->d_type == DT_LNK && entry->d_name[0] == '0' + entries - 2);
+    }
+    CHECK(entries == 6);
+    CHECK(getdents64(dir, buf, sizeof(buf)) == 0);
+    CHECK(lseek(dir, 0, SEEK_SET) == 0);
+    CHECK(getdents64(dir, buf, 8) == -EINVAL);
+    CHECK(read(dir, buf, 1) == -EISDIR);
+    CHECK(close(dir) == 0);
+
+    dir = open("/proc/self", O_RDONLY | O_DIRECTORY, 0);
+    fd = openat(dir, "comm", O_RDONLY, 0);
+    CHECK(fd >= 0);
+    CHECK(read(fd, buf, sizeof(buf)) == 7);
+    n = syscall4(SYS_readlinkat, dir, "exe", buf, sizeof(buf));
+    CHECK(n == length(argv[0]) && memcmp(buf, argv[0], n) == 0);
+
+    /* Files of the system */
+    n = slurp("/proc/cpuinfo", buf, sizeof(buf));
+    CHECK(n > 14 && memcmp(buf, "processor\t: 0\n", 14) == 0);
+    CHECK(contains(buf, n, "\nflags\t\t: fpu "));
+    n = slurp("/proc/meminfo", buf, sizeof(buf));
+    CHECK(n > 9 && memcmp(buf, "MemTotal:", 9) == 0);
+    n = slurp("/sys/devices/system/cpu/online", buf, sizeof(buf));
+    CHECK(n >= 2 && buf[0] == '0' && buf[n - 1] == '\n');
+    CHECK(stat("/sys/devices/system/cpu", &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFDIR);
+    return 0;
+}
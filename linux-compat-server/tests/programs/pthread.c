@@ -0,0 +1,90 @@
+/*
+ * Memory and glibc's pthreads: the heap grown with brk, anonymous and file
+ * mappings, thread stacks mmap'ed with a PROT_NONE guard page, a mutex and
+ * joins on the exit futex
+ *
+ * Unlike the other programs this one uses libc, so it's built without
+ * lac.h.
+ */
+#include <fcntl.h>
+#include <pthread.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define CHECK(cond)          \
+    do {                     \
+        if (!(cond))         \
+            _exit(__LINE__); \
+    } while (0)
+
+#define THREADS 4
+#define ITERATIONS 1000
+#define PAGE 4096
+
+static pthread_mutex_t mutex = PTHREAD_MUTEX_INITIALIZER;
+static long counter;
+static __thread long own;
+
+static void *worker(void *arg)
+{
+    long index = (long)arg;
+    /* Deep enough into the stack to leave its first page */
+    volatile char buf[3 * PAGE];
+
+    memset((char *)buf, (int)index, sizeof(buf));
+    own = index;
+    for (int i = 0; i < ITERATIONS; i++) {
+        pthread_mutex_lock(&mutex);
+        counter++;
+        pthread_mutex_unlock(&mutex);
+    }
+    CHECK(buf[0] == index && buf[sizeof(buf) - 1] == index);
+    return (void *)(own * 2);
+}
+
+int main(void)
+{
+    char *heap = sbrk(0);
+    CHECK(brk(heap + 2 * PAGE) == 0);
+    heap[2 * PAGE - 1] = 1;
+    CHECK(brk(heap + PAGE) == 0);
+    CHECK(sbrk(0) == heap + PAGE);
+
+    char *map = mmap(NULL, 3 * PAGE, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    CHECK(map != MAP_FAILED && ((unsigned long)map & (PAGE - 1)) == 0);
+    CHECK(map[PAGE] == 0);
+    CHECK(mprotect(map + PAGE, PAGE, PROT_READ | PROT_WRITE) == 0);
+    map[PAGE] = 1;
+    char *fixed = mmap(map + 2 * PAGE, PAGE, PROT_READ | PROT_WRITE,
+                       MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0);
+    CHECK(fixed == map + 2 * PAGE);
+    fixed[0] = 2;
+    CHECK(munmap(map, 3 * PAGE) == 0);
+    CHECK(mprotect(map, PAGE, PROT_READ) < 0);
+
+    int fd = open("mapped", O_RDWR | O_CREAT | O_TRUNC, 0600);
+    CHECK(fd >= 0 && write(fd, "mapped", 6) == 6);
+    char *file = mmap(NULL, PAGE, PROT_READ, MAP_PRIVATE, fd, 0);
+    CHECK(file != MAP_FAILED && memcmp(file, "mapped", 6) == 0 && file[6] == 0);
+    CHECK(mmap(NULL, PAGE, PROT_READ, MAP_SHARED, fd, 0) == MAP_FAILED);
+    CHECK(munmap(file, PAGE) == 0 && close(fd) == 0);
+
+    /* Too large for the heap, malloc maps it */
+    char *big = malloc(1 << 20);
+    CHECK(big != NULL);
+    big[0] = big[(1 << 20) - 1] = 1;
+    free(big);
+
+    pthread_t threads[THREADS];
+    for (long i = 0; i < THREADS; i++)
+        CHECK(pthread_create(&threads[i], NULL, worker, (void *)i) == 0);
+    for (long i = 0; i < THREADS; i++) {
+        void *result;
+        CHECK(pthread_join(threads[i], &result) == 0);
+        CHECK((long)result == i * 2);
+    }
+    CHECK(counter == THREADS * ITERATIONS);
+    return 0;
+}
@@ -0,0 +1,47 @@
+/* stat family: argv[1] is "data" (11 bytes, mode 0644), argv[2] a symlink to
+ * it and argv[3] the directory holding both */
+#include "lac.h"
+
+int main(int argc, char **argv)
+{
+    struct linux_stat st, fst;
+    long fd, dirfd;
+
+    CHECK(argc == 4);
+
+    CHECK(stat(argv[1], &st) == 0);
+    CHECK(st.st_size == 11);
+    CHECK((st.st_mode & S_IFMT) == S_IFREG);
+    CHECK((st.st_mode & 07777) == 0644);
+    CHECK(st.st_nlink == 1);
+
+    fd = open(argv[1], O_RDONLY, 0);
+    CHECK(fstat(fd, &fst) == 0);
+    CHECK(fst.st_ino == st.st_ino);
+    CHECK(fst.st_dev == st.st_dev);
+    CHECK(fst.st_size == 11);
+
+    /* Symlinks are followed unless asked not to */
+    CHECK(lstat(argv[2], &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFLNK);
+    CHECK(stat(argv[2], &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFREG);
+    CHECK(st.st_ino == fst.st_ino);
+    CHECK(newfstatat(AT_FDCWD, argv[2], &st, AT_SYMLINK_NOFOLLOW) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFLNK);
+
+    CHECK(newfstatat(fd, "", &st, AT_EMPTY_PATH) == 0);
+    CHECK(st.st_ino == fst.st_ino);
+    dirfd = open(argv[3], O_RDONLY | O_DIRECTORY, 0);
+    CHECK(newfstatat(dirfd, "data", &st, 0) == 0);
+    CHECK(st.st_ino == fst.st_ino);
+    CHECK(fstat(dirfd, &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFDIR);
+
+    CHECK(stat("/nonexistent/lac", &st) == -ENOENT);
+    CHECK(stat(argv[1], (void *)8) == -EFAULT);
+    CHECK(fstat(99, &st) == -EBADF);
+    CHECK(newfstatat(AT_FDCWD, argv[1], &st, 1) == -EINVAL);
+    CHECK(newfstatat(fd, "", &st, 0) == -ENOENT);
+    return 0;
+}
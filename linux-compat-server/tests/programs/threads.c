@@ -0,0 +1,205 @@
+/* clone, futex and TLS: threads sharing a counter under a futex mutex */
+#include "lac.h"
+
+#define CLONE_VM 0x100
+#define CLONE_FS 0x200
+#define CLONE_FILES 0x400
+#define CLONE_SIGHAND 0x800
+#define CLONE_THREAD 0x10000
+#define CLONE_SYSVSEM 0x40000
+#define CLONE_SETTLS 0x80000
+#define CLONE_PARENT_SETTID 0x100000
+#define CLONE_CHILD_CLEARTID 0x200000
+#define CLONE_CHILD_SETTID 0x01000000
+
+#define FUTEX_WAIT 0
+#define FUTEX_WAKE 1
+#define FUTEX_WAKE_OP 5
+#define FUTEX_WAIT_BITSET 9
+#define FUTEX_PRIVATE_FLAG 128
+
+#define ARCH_SET_FS 0x1002
+#define ARCH_GET_FS 0x1003
+
+#define WORKERS 4
+#define ITERATIONS 50
+
+struct timespec {
+    long tv_sec;
+    long tv_nsec;
+};
+
+/* What %fs points to */
+struct tls {
+    struct tls *self;
+    long index;
+};
+
+struct worker {
+    int ptid;
+    int tid;
+    int status;
+    struct tls tls;
+    char stack[16384] __attribute__((aligned(16)));
+};
+
+static struct worker workers[WORKERS];
+static struct tls main_tls;
+static int mutex;
+static volatile long counter;
+
+#define getpid() syscall0(SYS_getpid)
+#define gettid() syscall0(SYS_gettid)
+#define set_tid_address(tidptr) syscall1(SYS_set_tid_address, tidptr)
+#define arch_prctl(code, addr) syscall2(SYS_arch_prctl, code, addr)
+#define futex(uaddr, op, val, timeout) syscall4(SYS_futex, uaddr, op, val, timeout)
+
+/*
+ * clone running fn(arg) in the new thread, on stack, then exiting with its
+ * return value
+ */
+long clone_thread(unsigned long flags, void *stack, int *ptid, int *ctid, void *tls,
+                  int (*fn)(void *), void *arg);
+
+__asm__(".global clone_thread\n"
+        "clone_thread:\n"
+        "\tand $-16, %rsi\n"
+        "\tsub $16, %rsi\n"
+        "\tmov %r9, (%rsi)\n"
+        "\tmov 8(%rsp), %rax\n"
+        "\tmov %rax, 8(%rsi)\n"
+        "\tmov %rcx, %r10\n"
+        "\tmov $56, %eax\n"
+        "\tsyscall\n"
+        "\ttest %rax, %rax\n"
+        "\tjnz 1f\n"
+        "\tpop %rax\n"
+        "\tpop %rdi\n"
+        "\tcall *%rax\n"
+        "\tmov %rax, %rdi\n"
+        "\tmov $60, %eax\n"
+        "\tsyscall\n"
+        "\thlt\n"
+        "1:\tret\n");
+
+static struct tls *fs_self(void)
+{
+    struct tls *self;
+    __asm__ volatile("mov %%fs:0, %0" : "=r"(self));
+    return self;
+}
+
+/* Mutex from "Futexes Are Tricky": 0 unlocked, 1 locked, 2 contended */
+static void lock(int *m)
+{
+    int c = 0;
+    if (__atomic_compare_exchange_n(m, &c, 1, 0, __ATOMIC_SEQ_CST, __ATOMIC_SEQ_CST))
+        return;
+    if (c != 2)
+        c = __atomic_exchange_n(m, 2, __ATOMIC_SEQ_CST);
+    while (c != 0) {
+        futex(m, FUTEX_WAIT | FUTEX_PRIVATE_FLAG, 2, 0);
+        c = __atomic_exchange_n(m, 2, __ATOMIC_SEQ_CST);
+    }
+}
+
+static void unlock(int *m)
+{
+    if (__atomic_fetch_sub(m, 1, __ATOMIC_SEQ_CST) != 1) {
+        __atomic_store_n(m, 0, __ATOMIC_SEQ_CST);
+        futex(m, FUTEX_WAKE | FUTEX_PRIVATE_FLAG, 1, 0);
+    }
+}
+
+static int work(struct worker *w)
+{
+    unsigned long fs;
+    long value;
+    int i;
+
+    CHECK(gettid() == w->tid);
+    CHECK(getpid() == 1);
+    CHECK(fs_self() == &w->tls);
+    CHECK(arch_prctl(ARCH_GET_FS, &fs) == 0);
+    CHECK(fs == (unsigned long)&w->tls);
+
+    for (i = 0; i < ITERATIONS; i++) {
+        lock(&mutex);
+        value = counter;
+        /* Give the other threads a chance to run inside the lock */
+        gettid();
+        counter = value + 1;
+        unlock(&mutex);
+    }
+    CHECK(fs_self()->index == w->tls.index);
+    return 0;
+}
+
+static int worker_main(void *arg)
+{
+    struct worker *w = arg;
+    w->status = work(w);
+    return 0;
+}
+
+int main(int argc, char **argv)
+{
+    unsigned long flags = CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD |
+                          CLONE_SYSVSEM | CLONE_SETTLS | CLONE_PARENT_SETTID |
+                          CLONE_CHILD_SETTID | CLONE_CHILD_CLEARTID;
+    struct timespec timeout = {0, 1000000};
+    unsigned long fs;
+    int word = 7;
+    int clear_tid;
+    long tid;
+    int i;
+
+    tid = gettid();
+    CHECK(tid == getpid());
+    CHECK(set_tid_address(&clear_tid) == tid);
+
+    main_tls.self = &main_tls;
+    CHECK(arch_prctl(ARCH_SET_FS, &main_tls) == 0);
+    CHECK(arch_prctl(ARCH_GET_FS, &fs) == 0);
+    CHECK(fs == (unsigned long)&main_tls);
+    CHECK(fs_self() == &main_tls);
+    CHECK(arch_prctl(ARCH_SET_FS, 1UL << 47) == -EPERM);
+    CHECK(arch_prctl(0x1234, 0) == -EINVAL);
+
+    CHECK(futex(&word, FUTEX_WAIT, 8, 0) == -EAGAIN);
+    CHECK(futex(&word, FUTEX_WAIT, 7, &timeout) == -ETIMEDOUT);
+    CHECK(futex(&word, FUTEX_WAKE, 1, 0) == 0);
+    CHECK(futex((char *)&word + 1, FUTEX_WAKE, 1, 0) == -EINVAL);
+    CHECK(syscall6(SYS_futex, (long)&word, FUTEX_WAIT_BITSET, 7, 0, 0, 0) == -EINVAL);
+    CHECK(futex(&word, FUTEX_WAKE_OP, 1, 0) == -ENOSYS);
+
+    /* Threads share signal handlers, so they need a shared address space */
+    CHECK(clone_thread(CLONE_THREAD | CLONE_SIGHAND, workers[0].stack + sizeof(workers[0].stack),
+                       0, 0, 0, worker_main, 0) == -EINVAL);
+
+    lock(&mutex);
+    for (i = 0; i < WORKERS; i++) {
+        struct worker *w = &workers[i];
+        w->status = -1;
+        w->tls.self = &w->tls;
+        w->tls.index = i;
+        tid = clone_thread(flags, w->stack + sizeof(w->stack), &w->ptid, &w->tid, &w->tls,
+                           worker_main, w);
+        CHECK(tid > 0 && tid != getpid());
+        CHECK(w->ptid == tid && w->tid == tid);
+    }
+    /* The workers wait on the mutex until all of them have started */
+    unlock(&mutex);
+
+    /* Join: the thread ID is cleared and woken on exit */
+    for (i = 0; i < WORKERS; i++) {
+        int *ctid = &workers[i].tid;
+        int value;
+        while ((value = __atomic_load_n(ctid, __ATOMIC_SEQ_CST)) != 0)
+            futex(ctid, FUTEX_WAIT, value, 0);
+        CHECK(workers[i].status == 0);
+    }
+    CHECK(counter == WORKERS * ITERATIONS);
+    CHECK(fs_self() == &main_tls);
+    return 0;
+}
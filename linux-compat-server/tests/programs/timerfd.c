@@ -0,0 +1,90 @@
+/* timerfd: one shot and periodic timers, read and waited for */
+#include "lac.h"
+
+#define CLOCK_REALTIME 0
+#define CLOCK_MONOTONIC 1
+#define CLOCK_PROCESS_CPUTIME_ID 2
+
+#define TFD_NONBLOCK O_NONBLOCK
+
+#define EPOLLIN 0x1
+#define EPOLL_CTL_ADD 1
+
+struct timespec {
+    long tv_sec;
+    long tv_nsec;
+};
+
+struct itimerspec {
+    struct timespec it_interval;
+    struct timespec it_value;
+};
+
+struct epoll_event {
+    unsigned int events;
+    unsigned long data;
+} __attribute__((packed));
+
+#define timerfd_create(clock, flags) syscall2(SYS_timerfd_create, clock, flags)
+#define timerfd_settime(fd, flags, new, old) syscall4(SYS_timerfd_settime, fd, flags, new, old)
+#define timerfd_gettime(fd, curr) syscall2(SYS_timerfd_gettime, fd, curr)
+
+int main(int argc, char **argv)
+{
+    struct itimerspec its, old;
+    struct epoll_event ev;
+    unsigned long expirations;
+    long fd, ep;
+
+    /* One shot: a blocking read waits for it */
+    fd = timerfd_create(CLOCK_MONOTONIC, 0);
+    CHECK(fd == 3);
+    memset(&its, 0, sizeof(its));
+    its.it_value.tv_nsec = 10000000;
+    CHECK(timerfd_settime(fd, 0, &its, 0) == 0);
+    CHECK(timerfd_gettime(fd, &old) == 0);
+    CHECK(old.it_value.tv_sec == 0 && old.it_value.tv_nsec > 0);
+    CHECK(old.it_value.tv_nsec <= 10000000);
+    CHECK(read(fd, &expirations, 8) == 8);
+    CHECK(expirations == 1);
+    CHECK(timerfd_gettime(fd, &old) == 0);
+    CHECK(old.it_value.tv_sec == 0 && old.it_value.tv_nsec == 0);
+    CHECK(read(fd, &expirations, 4) == -EINVAL);
+    CHECK(close(fd) == 0);
+
+    /* Periodic: expirations add up until read */
+    fd = timerfd_create(CLOCK_MONOTONIC, TFD_NONBLOCK);
+    CHECK(fd == 3);
+    CHECK(read(fd, &expirations, 8) == -EAGAIN);
+    its.it_interval.tv_nsec = 2000000;
+    its.it_value.tv_nsec = 2000000;
+    CHECK(timerfd_settime(fd, 0, &its, 0) == 0);
+
+    ep = syscall1(SYS_epoll_create1, 0);
+    CHECK(ep == 4);
+    ev.events = EPOLLIN;
+    ev.data = 1;
+    CHECK(syscall4(SYS_epoll_ctl, ep, EPOLL_CTL_ADD, fd, &ev) == 0);
+    CHECK(syscall4(SYS_epoll_wait, ep, &ev, 1, -1) == 1);
+    CHECK(ev.events == EPOLLIN && ev.data == 1);
+    CHECK(read(fd, &expirations, 8) == 8);
+    CHECK(expirations >= 1);
+
+    /* poll without descriptors sleeps */
+    CHECK(syscall3(SYS_poll, 0, 0, 10) == 0);
+    CHECK(read(fd, &expirations, 8) == 8);
+    CHECK(expirations >= 4);
+
+    /* Disarming returns the old setting */
+    memset(&its, 0, sizeof(its));
+    CHECK(timerfd_settime(fd, 0, &its, &old) == 0);
+    CHECK(old.it_interval.tv_sec == 0 && old.it_interval.tv_nsec == 2000000);
+    CHECK(syscall4(SYS_epoll_wait, ep, &ev, 1, 5) == 0);
+
+    CHECK(timerfd_create(CLOCK_PROCESS_CPUTIME_ID, 0) == -EINVAL);
+    CHECK(timerfd_create(CLOCK_REALTIME, 4) == -EINVAL);
+    its.it_value.tv_nsec = 1000000000;
+    CHECK(timerfd_settime(fd, 0, &its, 0) == -EINVAL);
+    CHECK(timerfd_gettime(ep, &old) == -EINVAL);
+    return 0;
+}
@@ -0,0 +1,42 @@
+/* Dynamically linked against libtls: started in place by the dynamic linker
+ * tests rather than run, so no syscalls */
+
+extern __thread int tls_counter;
+extern __thread int tls_zero;
+extern int *counter_addr(void);
+extern int local_value(void);
+extern int secure(void);
+extern int pick(void);
+extern int call_local(void);
+
+/* Local exec model, right below the thread pointer */
+static __thread int own = 3;
+
+#define CHECK(cond)          \
+    do {                     \
+        if (!(cond))         \
+            return __LINE__; \
+    } while (0)
+
+long _start(void)
+{
+    CHECK(own == 3);
+    own++;
+    CHECK(own == 4);
+
+    /* Initial exec from here and general dynamic from libtls reach the
+     * same variable */
+    CHECK(tls_counter == 5);
+    CHECK(counter_addr() == &tls_counter);
+    tls_counter = 6;
+    CHECK(*counter_addr() == 6);
+    CHECK(tls_zero == 0);
+    CHECK(local_value() == 7);
+    CHECK(local_value() == 8);
+    CHECK(secure() == 0);
+
+    /* Resolved before the entry point */
+    CHECK(pick() == 11);
+    CHECK(call_local() == 22);
+    return 0;
+}
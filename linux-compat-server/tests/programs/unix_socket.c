@@ -0,0 +1,171 @@
+/* AF_UNIX sockets: a stream server and client, and a datagram pair */
+#include "lac.h"
+
+#define AF_UNIX 1
+#define AF_INET 2
+#define AF_INET6 10
+#define SOCK_STREAM 1
+#define SOCK_DGRAM 2
+#define SOCK_NONBLOCK O_NONBLOCK
+#define SOCK_CLOEXEC O_CLOEXEC
+
+#define SOL_SOCKET 1
+#define SO_TYPE 3
+#define SO_ERROR 4
+#define SO_RCVTIMEO 20
+#define SO_ACCEPTCONN 30
+#define SO_DOMAIN 39
+
+#define MSG_PEEK 0x2
+#define MSG_TRUNC 0x20
+#define MSG_DONTWAIT 0x40
+
+#define SHUT_WR 1
+
+#define POLLIN 0x1
+
+struct sockaddr_un {
+    unsigned short sun_family;
+    char sun_path[108];
+};
+
+struct timeval {
+    long tv_sec;
+    long tv_usec;
+};
+
+struct pollfd {
+    int fd;
+    short events;
+    short revents;
+};
+
+#define socket(domain, type, protocol) syscall3(SYS_socket, domain, type, protocol)
+#define bind(fd, addr, len) syscall3(SYS_bind, fd, addr, len)
+#define listen(fd, backlog) syscall2(SYS_listen, fd, backlog)
+#define connect(fd, addr, len) syscall3(SYS_connect, fd, addr, len)
+#define accept4(fd, addr, len, flags) syscall4(SYS_accept4, fd, addr, len, flags)
+#define send(fd, buf, len, flags) syscall6(SYS_sendto, fd, (long)(buf), len, flags, 0, 0)
+#define recv(fd, buf, len, flags) syscall6(SYS_recvfrom, fd, (long)(buf), len, flags, 0, 0)
+#define getsockname(fd, addr, len) syscall3(SYS_getsockname, fd, addr, len)
+#define getpeername(fd, addr, len) syscall3(SYS_getpeername, fd, addr, len)
+#define getsockopt(fd, level, name, val, len) \
+    syscall6(SYS_getsockopt, fd, level, name, (long)(val), (long)(len), 0)
+#define setsockopt(fd, level, name, val, len) \
+    syscall6(SYS_setsockopt, fd, level, name, (long)(val), len, 0)
+
+int main(int argc, char **argv)
+{
+    struct sockaddr_un addr, name;
+    struct timeval tv = {0, 10000};
+    struct linux_stat st;
+    struct pollfd pfd;
+    unsigned int len;
+    long server, client, conn;
+    int value, pair[2], fds[2];
+    char buf[16];
+
+    /* A path relative to the working directory */
+    memset(&addr, 0, sizeof(addr));
+    addr.sun_family = AF_UNIX;
+    memcpy(addr.sun_path, "sock", 5);
+    server = socket(AF_UNIX, SOCK_STREAM, 0);
+    CHECK(server == 3);
+    CHECK(bind(server, &addr, sizeof(addr)) == 0);
+    CHECK(stat("sock", &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFSOCK);
+    CHECK(listen(server, 4) == 0);
+    len = sizeof(name);
+    CHECK(getsockname(server, &name, &len) == 0);
+    CHECK(len == 7 && name.sun_family == AF_UNIX && memcmp(name.sun_path, "sock", 5) == 0);
+    len = sizeof(value);
+    CHECK(getsockopt(server, SOL_SOCKET, SO_ACCEPTCONN, &value, &len) == 0);
+    CHECK(len == 4 && value == 1);
+
+    client = socket(AF_UNIX, SOCK_STREAM | SOCK_NONBLOCK, 0);
+    CHECK(client == 4);
+    CHECK(connect(client, &addr, 2 + 5) == 0);
+    len = sizeof(name);
+    CHECK(getpeername(client, &name, &len) == 0);
+    CHECK(len == 7 && memcmp(name.sun_path, "sock", 5) == 0);
+    /* Short buffers get part of the address, and its full length */
+    len = 4;
+    memset(&name, 0, sizeof(name));
+    CHECK(getpeername(client, &name, &len) == 0);
+    CHECK(len == 7 && name.sun_path[0] == 's' && name.sun_path[1] == 'o' && name.sun_path[2] == 0);
+
+    /* The client is unnamed */
+    len = sizeof(name);
+    conn = accept4(server, &name, &len, SOCK_CLOEXEC);
+    CHECK(conn == 5);
+    CHECK(len == 2 && name.sun_family == AF_UNIX);
+    CHECK(fstat(conn, &st) == 0);
+    CHECK((st.st_mode & S_IFMT) == S_IFSOCK);
+    len = sizeof(name);
+    CHECK(getsockname(conn, &name, &len) == 0);
+    CHECK(len == 7);
+
+    CHECK(write(client, "ping", 4) == 4);
+    pfd.fd = conn;
+    pfd.events = POLLIN;
+    CHECK(syscall3(SYS_poll, &pfd, 1, -1) == 1);
+    CHECK(pfd.revents == POLLIN);
+    CHECK(recv(conn, buf, sizeof(buf), MSG_PEEK) == 4);
+    CHECK(read(conn, buf, sizeof(buf)) == 4);
+    CHECK(memcmp(buf, "ping", 4) == 0);
+    CHECK(send(conn, "pong", 4, 0) == 4);
+    CHECK(recv(client, buf, sizeof(buf), 0) == 4);
+    CHECK(memcmp(buf, "pong", 4) == 0);
+
+    /* Nothing to read: non-blocking, MSG_DONTWAIT and timeouts */
+    CHECK(recv(client, buf, sizeof(buf), 0) == -EAGAIN);
+    CHECK(recv(conn, buf, sizeof(buf), MSG_DONTWAIT) == -EAGAIN);
+    CHECK(setsockopt(conn, SOL_SOCKET, SO_RCVTIMEO, &tv, sizeof(tv)) == 0);
+    CHECK(setsockopt(conn, SOL_SOCKET, SO_RCVTIMEO, &tv, 8) == -EINVAL);
+    tv.tv_usec = 0;
+    len = sizeof(tv);
+    CHECK(getsockopt(conn, SOL_SOCKET, SO_RCVTIMEO, &tv, &len) == 0);
+    CHECK(len == sizeof(tv) && tv.tv_sec == 0 && tv.tv_usec == 10000);
+    CHECK(recv(conn, buf, sizeof(buf), 0) == -EAGAIN);
+    CHECK(setsockopt(server, SOL_SOCKET, SO_RCVTIMEO, &tv, sizeof(tv)) == 0);
+    len = sizeof(name);
+    CHECK(accept4(server, &name, &len, 0) == -EAGAIN);
+
+    /* Shutting down writes ends the stream */
+    CHECK(syscall2(SYS_shutdown, client, SHUT_WR) == 0);
+    CHECK(read(conn, buf, sizeof(buf)) == 0);
+    CHECK(syscall2(SYS_shutdown, client, 7) == -EINVAL);
+
+    len = sizeof(value);
+    CHECK(getsockopt(conn, SOL_SOCKET, SO_TYPE, &value, &len) == 0);
+    CHECK(value == SOCK_STREAM);
+    CHECK(getsockopt(conn, SOL_SOCKET, SO_DOMAIN, &value, &len) == 0);
+    CHECK(value == AF_UNIX);
+    CHECK(getsockopt(conn, SOL_SOCKET, SO_ERROR, &value, &len) == 0);
+    CHECK(value == 0);
+    CHECK(getsockopt(conn, SOL_SOCKET, 1000, &value, &len) == -ENOPROTOOPT);
+
+    /* Datagrams keep their boundaries */
+    CHECK(syscall4(SYS_socketpair, AF_UNIX, SOCK_DGRAM, 0, pair) == 0);
+    CHECK(pair[0] == 6 && pair[1] == 7);
+    CHECK(send(pair[0], "a", 1, 0) == 1);
+    CHECK(send(pair[0], "bcdef", 5, 0) == 5);
+    CHECK(recv(pair[1], buf, sizeof(buf), 0) == 1);
+    CHECK(buf[0] == 'a');
+    CHECK(recv(pair[1], buf, 2, MSG_TRUNC) == 5);
+    CHECK(buf[0] == 'b' && buf[1] == 'c');
+
+    CHECK(socket(AF_INET6, SOCK_STREAM, 0) == -EAFNOSUPPORT);
+    CHECK(socket(AF_UNIX, 9, 0) == -EINVAL);
+    CHECK(socket(AF_UNIX, SOCK_STREAM, 6) == -EPROTONOSUPPORT);
+    CHECK(syscall4(SYS_socketpair, AF_INET, SOCK_STREAM, 0, pair) == -EOPNOTSUPP);
+    CHECK(pipe(fds) == 0);
+    CHECK(bind(fds[0], &addr, sizeof(addr)) == -ENOTSOCK);
+    client = socket(AF_UNIX, SOCK_STREAM, 0);
+    CHECK(bind(client, &addr, sizeof(addr)) == -EADDRINUSE);
+    memcpy(addr.sun_path, "none", 5);
+    CHECK(connect(client, &addr, sizeof(addr)) == -ENOENT);
+    addr.sun_family = AF_INET;
+    CHECK(connect(client, &addr, sizeof(addr)) == -EINVAL);
+    return 0;
+}
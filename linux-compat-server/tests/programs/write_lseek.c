@@ -0,0 +1,49 @@
+/* write and lseek: creates argv[1] and argv[2] */
+#include "lac.h"
+
+#define BIG 200000
+
+static char big[BIG];
+static char back[BIG];
+
+int main(int argc, char **argv)
+{
+    char buf[16];
+    long fd, app, ro, i;
+
+    CHECK(argc == 3);
+
+    fd = open(argv[1], O_RDWR | O_CREAT | O_TRUNC, 0600);
+    CHECK(fd == 3);
+    CHECK(write(fd, "hello world", 11) == 11);
+    CHECK(lseek(fd, 0, SEEK_CUR) == 11);
+    CHECK(lseek(fd, 6, SEEK_SET) == 6);
+    CHECK(read(fd, buf, 5) == 5);
+    CHECK(memcmp(buf, "world", 5) == 0);
+    CHECK(lseek(fd, -5, SEEK_END) == 6);
+
+    CHECK(lseek(fd, -1, SEEK_SET) == -EINVAL);
+    CHECK(lseek(fd, 0, 7) == -EINVAL);
+    CHECK(lseek(99, 0, SEEK_SET) == -EBADF);
+
+    /* Appends go to the end whatever the offset */
+    app = open(argv[1], O_WRONLY | O_APPEND, 0);
+    CHECK(app == 4);
+    CHECK(write(app, "!", 1) == 1);
+    CHECK(lseek(fd, 0, SEEK_END) == 12);
+
+    ro = open(argv[1], O_RDONLY, 0);
+    CHECK(write(ro, "x", 1) == -EBADF);
+    CHECK(write(fd, (char *)8, 1) == -EFAULT);
+    CHECK(write(-1, "x", 1) == -EBADF);
+
+    /* Transfers larger than the translator's buffer */
+    for (i = 0; i < BIG; i++)
+        big[i] = (char)(i * 7);
+    fd = open(argv[2], O_RDWR | O_CREAT | O_TRUNC, 0644);
+    CHECK(write(fd, big, BIG) == BIG);
+    CHECK(lseek(fd, 0, SEEK_SET) == 0);
+    CHECK(read(fd, back, BIG) == BIG);
+    CHECK(memcmp(big, back, BIG) == 0);
+    return 0;
+}
@@ -21,8 +21,9 @@
 extern crate alloc;
 
 use alloc::collections::VecDeque;
+use alloc::string::String;
 use core::cmp::{max, min};
-use core::fmt;
+use core::fmt::{self, Write};
 use core::time::Duration;
 
 // =============================================================================
@@ -242,6 +243,205 @@ impl BbrMetrics {
             full_bw_reached: buf[53] != 0,
         }
     }
+
+    /// Format metrics in the Prometheus text exposition format
+    ///
+    /// Every sample carries `labels`, e.g. `&[("interface", "eth0")]`, so
+    /// several connections or adapters can be exported side by side.
+    /// Rates and sizes are in bytes, times in seconds and loss and ECN rates
+    /// as ratios, following Prometheus naming conventions.
+    pub fn to_prometheus(&self, labels: &[(&str, &str)]) -> String {
+        let mut out = String::new();
+        let labels = format_labels(labels);
+        let mut metric = |name: &str, kind: &str, help: &str, value: &dyn fmt::Display| {
+            // Writing to a String can't fail
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "{name}{labels} {value}");
+        };
+
+        metric(
+            "bbr_state",
+            "gauge",
+            "State machine state (0=Startup, 1=Drain, 2=ProbeBw, 3=ProbeRtt)",
+            &self.state,
+        );
+        metric(
+            "bbr_bottleneck_bandwidth_bytes_per_second",
+            "gauge",
+            "Estimated bottleneck bandwidth",
+            &self.btl_bw,
+        );
+        metric(
+            "bbr_min_rtt_seconds",
+            "gauge",
+            "Minimum round-trip time",
+            &(self.min_rtt_us as f64 / 1_000_000.0),
+        );
+        metric(
+            "bbr_pacing_rate_bytes_per_second",
+            "gauge",
+            "Current pacing rate",
+            &self.pacing_rate,
+        );
+        metric(
+            "bbr_cwnd_bytes",
+            "gauge",
+            "Current congestion window",
+            &self.cwnd,
+        );
+        metric(
+            "bbr_inflight_bytes",
+            "gauge",
+            "Bytes currently in flight",
+            &self.inflight,
+        );
+        metric(
+            "bbr_delivered_bytes_total",
+            "counter",
+            "Total bytes delivered",
+            &self.delivered,
+        );
+        metric(
+            "bbr_loss_ratio",
+            "gauge",
+            "Current loss rate",
+            &(f64::from(self.loss_rate_pct) / 100.0),
+        );
+        metric(
+            "bbr_ecn_ratio",
+            "gauge",
+            "Current ECN mark rate",
+            &(f64::from(self.ecn_rate_pct) / 100.0),
+        );
+        metric(
+            "bbr_probe_bw_cycle",
+            "gauge",
+            "ProbeBw cycle index",
+            &self.probe_bw_cycle,
+        );
+        metric(
+            "bbr_full_bw_count",
+            "gauge",
+            "Rounds since last bandwidth increase",
+            &self.full_bw_cnt,
+        );
+        metric(
+            "bbr_full_bw_reached",
+            "gauge",
+            "Whether full bandwidth has been reached",
+            &u8::from(self.full_bw_reached),
+        );
+        out
+    }
+}
+
+/// Format a Prometheus label set, escaping label values
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    let mut out = String::new();
+    if labels.is_empty() {
+        return out;
+    }
+    out.push('{');
+    for (i, (name, value)) in labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(name);
+        out.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out.push('}');
+    out
+}
+
+// =============================================================================
+// Periodic Metrics Sampling
+// =============================================================================
+
+/// Periodic sampler of BBR metrics for monitoring exports
+///
+/// Call [`MetricsSampler::poll`] from the connection's event loop; it takes
+/// a snapshot once per interval, so exporters read a consistent sample
+/// instead of racing the ACK path, and derives the delivery rate between
+/// snapshots.
+#[derive(Debug, Clone)]
+pub struct MetricsSampler {
+    interval_us: u64,
+    last_us: Option<u64>,
+    latest: Option<BbrMetrics>,
+    delivery_rate: u64,
+}
+
+impl MetricsSampler {
+    /// Create a sampler taking a snapshot every `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval_us: max(interval.as_micros() as u64, 1),
+            last_us: None,
+            latest: None,
+            delivery_rate: 0,
+        }
+    }
+
+    /// Take a snapshot of `bbr` if the interval has elapsed since the last
+    /// one, returning the new snapshot
+    pub fn poll(&mut self, bbr: &Bbr, now_us: u64) -> Option<BbrMetrics> {
+        if let Some(last_us) = self.last_us {
+            if now_us.saturating_sub(last_us) < self.interval_us {
+                return None;
+            }
+        }
+
+        let metrics = bbr.metrics();
+        if let (Some(last_us), Some(previous)) = (self.last_us, self.latest) {
+            let elapsed_us = now_us - last_us;
+            let delivered = metrics.delivered.saturating_sub(previous.delivered);
+            self.delivery_rate = (delivered as u128 * 1_000_000 / elapsed_us as u128) as u64;
+        }
+        self.last_us = Some(now_us);
+        self.latest = Some(metrics);
+        Some(metrics)
+    }
+
+    /// Latest snapshot
+    pub fn latest(&self) -> Option<BbrMetrics> {
+        self.latest
+    }
+
+    /// Delivery rate between the last two snapshots (bytes/sec)
+    pub fn delivery_rate(&self) -> u64 {
+        self.delivery_rate
+    }
+
+    /// Format the latest snapshot in the Prometheus text exposition format,
+    /// empty before the first snapshot
+    pub fn to_prometheus(&self, labels: &[(&str, &str)]) -> String {
+        let Some(metrics) = self.latest else {
+            return String::new();
+        };
+        let mut out = metrics.to_prometheus(labels);
+        let _ = writeln!(
+            out,
+            "# HELP bbr_delivery_rate_bytes_per_second Delivery rate over the last sample interval"
+        );
+        let _ = writeln!(out, "# TYPE bbr_delivery_rate_bytes_per_second gauge");
+        let _ = writeln!(
+            out,
+            "bbr_delivery_rate_bytes_per_second{} {}",
+            format_labels(labels),
+            self.delivery_rate
+        );
+        out
+    }
 }
 
 // =============================================================================
@@ -898,4 +1098,33 @@ mod tests {
         assert_eq!(decoded.btl_bw, metrics.btl_bw);
         assert_eq!(decoded.pacing_rate, metrics.pacing_rate);
     }
+
+    #[test]
+    fn test_metrics_prometheus() {
+        let mut bbr = Bbr::new();
+        bbr.on_ack(10000, 10000, 5000, 100000);
+
+        let text = bbr.metrics().to_prometheus(&[("interface", "eth\"0")]);
+        assert!(text.contains("# TYPE bbr_delivered_bytes_total counter\n"));
+        assert!(text.contains("bbr_min_rtt_seconds{interface=\"eth\\\"0\"} 0.01\n"));
+        assert!(text.contains("bbr_state{interface=\"eth\\\"0\"} 0\n"));
+        assert!(!bbr.metrics().to_prometheus(&[]).contains('{'));
+    }
+
+    #[test]
+    fn test_metrics_sampler() {
+        let mut bbr = Bbr::new();
+        let mut sampler = MetricsSampler::new(Duration::from_secs(1));
+        assert!(sampler.to_prometheus(&[]).is_empty());
+
+        bbr.on_ack(10000, 10000, 5000, 0);
+        assert!(sampler.poll(&bbr, 0).is_some());
+        bbr.on_ack(20000, 10000, 5000, 500_000);
+        assert!(sampler.poll(&bbr, 500_000).is_none());
+        assert!(sampler.poll(&bbr, 2_000_000).is_some());
+        assert_eq!(sampler.delivery_rate(), 10000);
+        assert!(sampler
+            .to_prometheus(&[])
+            .contains("bbr_delivery_rate_bytes_per_second 10000\n"));
+    }
 }
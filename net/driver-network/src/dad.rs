@@ -0,0 +1,281 @@
+//! IPv6 Duplicate Address Detection (RFC 4862, section 5.4)
+//!
+//! A new IPv6 address stays tentative while Neighbor Solicitations for it are
+//! sent from the unspecified address. If no other node answers with a
+//! Neighbor Advertisement, or solicits the same address for its own DAD, by
+//! the time the last retransmit timer expires, the address is handed to the
+//! adapter. Otherwise it's marked duplicate and never used.
+//!
+//! The adapter has to receive the all-nodes (`ff02::1`) and solicited-node
+//! multicast groups of the tentative address, or conflicts can't be seen.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+/// Number of Neighbor Solicitations sent (DupAddrDetectTransmits)
+pub const DAD_TRANSMITS: u32 = 1;
+
+/// Time between solicitations, and after the last one (RetransTimer)
+pub const RETRANS_TIMER: Duration = Duration::from_millis(1000);
+
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const NEXT_HEADER_ICMPV6: u8 = 58;
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV6_HEADER_LEN: usize = 40;
+/// ICMPv6 header and target address, without options
+const NEIGHBOR_MESSAGE_LEN: usize = 24;
+
+/// Kind of IPv6 address an adapter holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ipv6Scope {
+    /// Link-local address (fe80::/10)
+    LinkLocal,
+    /// Global address (2000::/3)
+    Global,
+    /// Unique local address (fc00::/7)
+    UniqueLocal,
+}
+
+impl Ipv6Scope {
+    /// Name of the scheme path holding this address
+    pub fn path(self) -> &'static str {
+        match self {
+            Ipv6Scope::LinkLocal => "ipv6",
+            Ipv6Scope::Global => "ipv6_global",
+            Ipv6Scope::UniqueLocal => "ipv6_unique_local",
+        }
+    }
+
+    /// Check that `address` belongs to this scope
+    pub fn contains(self, address: [u8; 16]) -> bool {
+        match self {
+            Ipv6Scope::LinkLocal => address[0] == 0xfe && address[1] & 0xc0 == 0x80,
+            Ipv6Scope::Global => address[0] & 0xe0 == 0x20,
+            Ipv6Scope::UniqueLocal => address[0] & 0xfe == 0xfc,
+        }
+    }
+}
+
+/// DAD state of an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DadState {
+    /// Detection is running, the address isn't assigned yet
+    Tentative,
+    /// No conflict was found, the address is assigned
+    Preferred,
+    /// Another node uses the address
+    Duplicate,
+}
+
+impl fmt::Display for DadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DadState::Tentative => "tentative",
+            DadState::Preferred => "preferred",
+            DadState::Duplicate => "duplicate",
+        })
+    }
+}
+
+struct Probe {
+    address: [u8; 16],
+    state: DadState,
+    /// Solicitations sent so far
+    sent: u32,
+    /// When the next solicitation is due, or the detection ends
+    deadline: Instant,
+}
+
+/// What [`Dad::poll`] wants done
+pub enum DadAction {
+    /// Send this Neighbor Solicitation frame
+    Send(Vec<u8>),
+    /// Detection succeeded, assign the address
+    Assign(Ipv6Scope, [u8; 16]),
+}
+
+/// Duplicate Address Detection of the addresses of one adapter
+#[derive(Default)]
+pub struct Dad {
+    probes: BTreeMap<Ipv6Scope, Probe>,
+}
+
+impl Dad {
+    /// Start detection for `address`, replacing any earlier one of `scope`
+    pub fn start(&mut self, scope: Ipv6Scope, address: [u8; 16], now: Instant) {
+        self.probes.insert(
+            scope,
+            Probe {
+                address,
+                state: DadState::Tentative,
+                sent: 0,
+                deadline: now,
+            },
+        );
+    }
+
+    /// Forget the address of `scope`
+    pub fn remove(&mut self, scope: Ipv6Scope) {
+        self.probes.remove(&scope);
+    }
+
+    /// Advance the running detections, returning the frames to send and the
+    /// addresses that passed
+    pub fn poll(&mut self, mac: [u8; 6], now: Instant) -> Vec<DadAction> {
+        let mut actions = Vec::new();
+        for (&scope, probe) in self.probes.iter_mut() {
+            if probe.state != DadState::Tentative || now < probe.deadline {
+                continue;
+            }
+            if probe.sent < DAD_TRANSMITS {
+                actions.push(DadAction::Send(neighbor_solicitation(mac, probe.address)));
+                probe.sent += 1;
+                probe.deadline = now + RETRANS_TIMER;
+            } else {
+                probe.state = DadState::Preferred;
+                actions.push(DadAction::Assign(scope, probe.address));
+            }
+        }
+        actions
+    }
+
+    /// Check a received Ethernet frame for a conflict, returning the scope of
+    /// the tentative address it proved duplicate
+    pub fn on_receive(&mut self, frame: &[u8]) -> Option<Ipv6Scope> {
+        let target = conflicting_target(frame)?;
+        let (&scope, probe) = self
+            .probes
+            .iter_mut()
+            .find(|(_, probe)| probe.state == DadState::Tentative && probe.address == target)?;
+        probe.state = DadState::Duplicate;
+        Some(scope)
+    }
+
+    /// Earliest time [`Dad::poll`] has something to do
+    pub fn deadline(&self) -> Option<Instant> {
+        self.probes
+            .values()
+            .filter(|probe| probe.state == DadState::Tentative)
+            .map(|probe| probe.deadline)
+            .min()
+    }
+
+    /// Addresses under detection or detected, with their state
+    pub fn states(&self) -> impl Iterator<Item = (Ipv6Scope, [u8; 16], DadState)> + '_ {
+        self.probes
+            .iter()
+            .map(|(&scope, probe)| (scope, probe.address, probe.state))
+    }
+
+    /// Text report of [`Dad::states`], one `<path> <address> <state>` line
+    /// per address
+    pub fn report(&self) -> String {
+        self.states()
+            .map(|(scope, address, state)| {
+                format!("{} {} {}\n", scope.path(), Ipv6Addr::from(address), state)
+            })
+            .collect()
+    }
+}
+
+/// Solicited-node multicast address of `address` (ff02::1:ffXX:XXXX)
+fn solicited_node(address: [u8; 16]) -> [u8; 16] {
+    let mut group = [0; 16];
+    group[..2].copy_from_slice(&[0xff, 0x02]);
+    group[11..13].copy_from_slice(&[0x01, 0xff]);
+    group[13..].copy_from_slice(&address[13..]);
+    group
+}
+
+/// Build the Ethernet frame of a DAD Neighbor Solicitation for `target`
+fn neighbor_solicitation(mac: [u8; 6], target: [u8; 16]) -> Vec<u8> {
+    let source = [0; 16];
+    let destination = solicited_node(target);
+
+    let mut frame =
+        Vec::with_capacity(ETHERNET_HEADER_LEN + IPV6_HEADER_LEN + NEIGHBOR_MESSAGE_LEN);
+
+    // Ethernet, to the multicast MAC of the solicited-node group
+    frame.extend_from_slice(&[0x33, 0x33]);
+    frame.extend_from_slice(&destination[12..]);
+    frame.extend_from_slice(&mac);
+    frame.extend_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+
+    // IPv6
+    frame.extend_from_slice(&[0x60, 0, 0, 0]);
+    frame.extend_from_slice(&(NEIGHBOR_MESSAGE_LEN as u16).to_be_bytes());
+    frame.push(NEXT_HEADER_ICMPV6);
+    frame.push(255); // Hop limit
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&destination);
+
+    // ICMPv6 Neighbor Solicitation, without a source link-layer address
+    // option as the source is unspecified
+    let icmp = frame.len();
+    frame.extend_from_slice(&[ICMPV6_NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0]);
+    frame.extend_from_slice(&target);
+
+    let checksum = icmpv6_checksum(&source, &destination, &frame[icmp..]);
+    frame[icmp + 2..icmp + 4].copy_from_slice(&checksum.to_be_bytes());
+    frame
+}
+
+/// ICMPv6 checksum over the IPv6 pseudo-header and `message`
+fn icmpv6_checksum(source: &[u8; 16], destination: &[u8; 16], message: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut add = |data: &[u8]| {
+        for chunk in data.chunks(2) {
+            let word = match *chunk {
+                [high, low] => u16::from_be_bytes([high, low]),
+                [high] => u16::from_be_bytes([high, 0]),
+                _ => unreachable!(),
+            };
+            sum += u32::from(word);
+        }
+    };
+    add(source);
+    add(destination);
+    add(&(message.len() as u32).to_be_bytes());
+    add(&[0, 0, 0, NEXT_HEADER_ICMPV6]);
+    add(message);
+
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Target address of a received message showing that it's in use elsewhere
+///
+/// That's any Neighbor Advertisement, or a Neighbor Solicitation from the
+/// unspecified address, i.e. another node running DAD for the same address.
+fn conflicting_target(frame: &[u8]) -> Option<[u8; 16]> {
+    if frame.len() < ETHERNET_HEADER_LEN + IPV6_HEADER_LEN + NEIGHBOR_MESSAGE_LEN
+        || u16::from_be_bytes([frame[12], frame[13]]) != ETHERTYPE_IPV6
+    {
+        return None;
+    }
+
+    let ip = &frame[ETHERNET_HEADER_LEN..];
+    // Neighbor Discovery messages from off-link have a lower hop limit
+    if ip[0] >> 4 != 6 || ip[6] != NEXT_HEADER_ICMPV6 || ip[7] != 255 {
+        return None;
+    }
+    let source = &ip[8..24];
+
+    let icmp = &ip[IPV6_HEADER_LEN..];
+    if icmp[1] != 0 {
+        return None;
+    }
+    let target: [u8; 16] = icmp[8..24].try_into().ok()?;
+    match icmp[0] {
+        ICMPV6_NEIGHBOR_ADVERTISEMENT => Some(target),
+        ICMPV6_NEIGHBOR_SOLICITATION if source.iter().all(|&b| b == 0) => Some(target),
+        _ => None,
+    }
+}
@@ -0,0 +1,183 @@
+//! Adapter self-tests
+//!
+//! Writing anything to the `diag` path runs [`NetworkAdapter::self_test`],
+//! reading it returns the results of the last run, one test per line:
+//!
+//! ```text
+//! registers pass 12us
+//! loopback fail 100341us no test frame received
+//! interrupt skip 0us not supported
+//! ```
+//!
+//! The tests are meant for bring-up and may reconfigure the hardware for a
+//! moment, so traffic can be lost while they run.
+//!
+//! [`NetworkAdapter::self_test`]: crate::NetworkAdapter::self_test
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Ethertype of loopback test frames (IEEE 802 local experimental)
+pub const ETHERTYPE_LOOPBACK_TEST: u16 = 0x88B5;
+
+/// Shortest Ethernet frame, without the FCS
+const MIN_FRAME_LEN: usize = 60;
+
+/// Kind of adapter self-test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTest {
+    /// Registers can be reached, and read back what was written
+    Registers,
+    /// A frame sent in loopback mode is received again
+    Loopback,
+    /// The adapter raises interrupts
+    Interrupt,
+}
+
+impl SelfTest {
+    /// All tests, in the order they run
+    pub const ALL: [SelfTest; 3] = [SelfTest::Registers, SelfTest::Loopback, SelfTest::Interrupt];
+
+    /// Name used in the report
+    pub fn name(self) -> &'static str {
+        match self {
+            SelfTest::Registers => "registers",
+            SelfTest::Loopback => "loopback",
+            SelfTest::Interrupt => "interrupt",
+        }
+    }
+}
+
+/// Outcome of a self-test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    /// The test passed
+    Pass,
+    /// The test failed, with the reason
+    Fail(String),
+    /// The test didn't run, with the reason
+    Skip(String),
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TestOutcome::Pass => "pass",
+            TestOutcome::Fail(_) => "fail",
+            TestOutcome::Skip(_) => "skip",
+        })
+    }
+}
+
+/// Result of one self-test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    /// Test that ran
+    pub test: SelfTest,
+    /// What came out of it
+    pub outcome: TestOutcome,
+    /// Time the test took
+    pub duration: Duration,
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}us",
+            self.test.name(),
+            self.outcome,
+            self.duration.as_micros()
+        )?;
+        match &self.outcome {
+            TestOutcome::Pass => Ok(()),
+            TestOutcome::Fail(reason) | TestOutcome::Skip(reason) => write!(f, " {reason}"),
+        }
+    }
+}
+
+/// Results of a self-test run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    results: Vec<TestResult>,
+}
+
+impl SelfTestReport {
+    /// Create an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `test` and record its outcome and duration
+    ///
+    /// `f` returns the reason when the test fails.
+    pub fn run<F>(&mut self, test: SelfTest, f: F)
+    where
+        F: FnOnce() -> std::result::Result<(), String>,
+    {
+        let start = Instant::now();
+        let outcome = match f() {
+            Ok(()) => TestOutcome::Pass,
+            Err(reason) => TestOutcome::Fail(reason),
+        };
+        self.results.push(TestResult {
+            test,
+            outcome,
+            duration: start.elapsed(),
+        });
+    }
+
+    /// Record that `test` didn't run
+    pub fn skip(&mut self, test: SelfTest, reason: &str) {
+        self.results.push(TestResult {
+            test,
+            outcome: TestOutcome::Skip(reason.to_string()),
+            duration: Duration::ZERO,
+        });
+    }
+
+    /// Recorded results, in the order the tests ran
+    pub fn results(&self) -> &[TestResult] {
+        &self.results
+    }
+
+    /// Whether no test failed
+    pub fn passed(&self) -> bool {
+        !self
+            .results
+            .iter()
+            .any(|result| matches!(result.outcome, TestOutcome::Fail(_)))
+    }
+
+    /// Text format of the `diag` path
+    pub fn report(&self) -> String {
+        self.results
+            .iter()
+            .map(|result| format!("{result}\n"))
+            .collect()
+    }
+}
+
+/// Build a loopback test frame from `mac` to itself carrying `sequence`
+///
+/// The payload is padded to the minimum frame length with a fixed pattern,
+/// so corrupted frames don't match in [`is_loopback_frame`].
+pub fn loopback_frame(mac: [u8; 6], sequence: u32) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MIN_FRAME_LEN);
+    frame.extend_from_slice(&mac);
+    frame.extend_from_slice(&mac);
+    frame.extend_from_slice(&ETHERTYPE_LOOPBACK_TEST.to_be_bytes());
+    frame.extend_from_slice(&sequence.to_be_bytes());
+    let pattern = (0..).map(|i: u8| i.wrapping_mul(7));
+    frame.extend(pattern.take(MIN_FRAME_LEN - frame.len()));
+    frame
+}
+
+/// Check that `frame` is the loopback test frame `sequence` sent by `mac`
+///
+/// Receivers may add padding or keep the FCS, so only the length of the
+/// sent frame is compared.
+pub fn is_loopback_frame(frame: &[u8], mac: [u8; 6], sequence: u32) -> bool {
+    let expected = loopback_frame(mac, sequence);
+    frame.get(..expected.len()) == Some(&expected[..])
+}
@@ -5,6 +5,7 @@
 //!
 //! - Packet read/write with congestion-aware pacing
 //! - ECN (Explicit Congestion Notification) detection
+//! - Per-flow RTT samples from TCP timestamp echoes
 //! - Real-time BBRv3 metrics monitoring via scheme interface
 //! - Multiple address type queries (MAC, IPv4, IPv6)
 //!
@@ -12,26 +13,52 @@
 //!
 //! - `/` - Raw packet read/write with BBRv3 pacing
 //! - `mac` - Read MAC address (6 bytes)
-//! - `ipv4` - Read IPv4 address (4 bytes)
-//! - `ipv6` - Read link-local IPv6 address (16 bytes)
-//! - `ipv6_global` - Read global IPv6 address (16 bytes)
-//! - `ipv6_unique_local` - Read unique local IPv6 address (16 bytes)
+//! - `ipv4` - Read/write IPv4 address (4 bytes)
+//! - `ipv6` - Read/write link-local IPv6 address (16 bytes)
+//! - `ipv6_global` - Read/write global IPv6 address (16 bytes)
+//! - `ipv6_unique_local` - Read/write unique local IPv6 address (16 bytes)
+//! - `dad` - Read IPv6 duplicate address detection state (text format)
 //! - `bbr` - Read BBRv3 metrics (text format for debugging)
 //! - `bbr_raw` - Read BBRv3 metrics (binary format, 64 bytes)
+//! - `bbr_prometheus` - Read sampled BBRv3 metrics (Prometheus text format)
+//! - `mtu` - Read/write MTU (u32, little-endian)
+//! - `promisc` - Read/write promiscuous mode (1 byte, 0 or 1)
+//! - `diag` - Write to run the adapter self-tests, read their results (text format)
+//!
+//! Writes fail with `EOPNOTSUPP` when the adapter can't change the setting,
+//! or has no self-tests.
+//!
+//! A written IPv6 address only becomes active once duplicate address
+//! detection finds no other node using it, until then reading it returns
+//! the unspecified address. Handles of the address and of `dad` get a read
+//! event when detection completes or finds a conflict. Writing the
+//! unspecified address removes the address.
 
 use std::collections::BTreeMap;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{cmp, io};
 
-pub use bbrv3_rs::{Bbr, BbrMetrics, BbrState};
+mod dad;
+mod diag;
+mod rtt;
+
+pub use bbrv3_rs::{Bbr, BbrMetrics, BbrState, MetricsSampler};
+use dad::{Dad, DadAction};
+pub use dad::{DadState, Ipv6Scope};
+pub use diag::{
+    is_loopback_frame, loopback_frame, SelfTest, SelfTestReport, TestOutcome, TestResult,
+    ETHERTYPE_LOOPBACK_TEST,
+};
 use libredox::flag::O_NONBLOCK;
 use libredox::Fd;
 use redox_scheme::{
     CallRequest, CallerCtx, OpenResult, RequestKind, Response, SchemeBlock, SignalBehavior, Socket,
 };
+use rtt::{RttProbe, RttSample};
 use syscall::schemev2::NewFdFlags;
 use syscall::{
-    Error, EventFlags, Result, Stat, EACCES, EAGAIN, EBADF, EINTR, EINVAL, EWOULDBLOCK, MODE_FILE,
+    Error, EventFlags, Result, Stat, EACCES, EAGAIN, EBADF, EINTR, EINVAL, EOPNOTSUPP, EWOULDBLOCK,
+    MODE_FILE,
 };
 
 /// Trait for network adapter implementations
@@ -75,6 +102,48 @@ pub trait NetworkAdapter {
 
     /// Returns the number of bytes currently in flight (sent but not yet acknowledged).
     fn in_flight(&self) -> u64;
+
+    /// Assign the IPv4 address of this adapter.
+    fn set_ipv4_address(&mut self, _address: [u8; 4]) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    /// Assign an IPv6 address of this adapter.
+    ///
+    /// Called once duplicate address detection passed, or with the
+    /// unspecified address to remove the address of `scope`.
+    fn set_ipv6_address(&mut self, _scope: Ipv6Scope, _address: [u8; 16]) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    /// Returns the MTU in bytes, excluding the Ethernet header.
+    fn mtu(&mut self) -> u32 {
+        1500
+    }
+
+    /// Change the MTU.
+    fn set_mtu(&mut self, _mtu: u32) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    /// Returns whether the adapter receives all frames, regardless of destination.
+    fn promiscuous(&mut self) -> bool {
+        false
+    }
+
+    /// Enable or disable promiscuous mode.
+    fn set_promiscuous(&mut self, _enabled: bool) -> Result<()> {
+        Err(Error::new(EOPNOTSUPP))
+    }
+
+    /// Run the hardware self-tests of the adapter.
+    ///
+    /// Adapters record a result for each [`SelfTest`], skipping the ones they
+    /// can't run. Tests may disturb traffic, and should leave the adapter
+    /// configured as before.
+    fn self_test(&mut self) -> Result<SelfTestReport> {
+        Err(Error::new(EOPNOTSUPP))
+    }
 }
 
 /// ECN (Explicit Congestion Notification) flags
@@ -142,8 +211,8 @@ fn extract_ecn(packet: &[u8]) -> Option<EcnFlag> {
 
 /// Calculate RTT from packet receive (simplified approach)
 ///
-/// In a real implementation, this would track sequence numbers and ACKs.
-/// For now, we use the time since the last write as a rough RTT estimate.
+/// Only used for frames [`RttProbe`] can't measure: the time since the last
+/// write is a rough RTT estimate.
 fn estimate_rtt_us(last_write: Instant) -> u64 {
     let elapsed = last_write.elapsed();
     elapsed.as_micros() as u64
@@ -156,20 +225,44 @@ enum Handle {
     Data,
     /// MAC address (read-only)
     Mac,
-    /// IPv4 address (read-only)
+    /// IPv4 address
     Ipv4,
-    /// Link-local IPv6 address (read-only)
+    /// Link-local IPv6 address
     Ipv6,
-    /// Global IPv6 address (read-only)
+    /// Global IPv6 address
     Ipv6Global,
-    /// Unique local IPv6 address (read-only)
+    /// Unique local IPv6 address
     Ipv6UniqueLocal,
+    /// Duplicate address detection state (text format, read-only)
+    Dad,
     /// BBRv3 metrics (text format, read-only)
     Bbr,
     /// BBRv3 metrics (binary format, read-only)
     BbrRaw,
+    /// Sampled BBRv3 metrics (Prometheus text format, read-only)
+    BbrPrometheus,
+    /// MTU
+    Mtu,
+    /// Promiscuous mode
+    Promisc,
+    /// Adapter self-tests (text format)
+    Diag,
+}
+
+impl Handle {
+    /// Handle of the address file of `scope`
+    fn ipv6(scope: Ipv6Scope) -> Self {
+        match scope {
+            Ipv6Scope::LinkLocal => Handle::Ipv6,
+            Ipv6Scope::Global => Handle::Ipv6Global,
+            Ipv6Scope::UniqueLocal => Handle::Ipv6UniqueLocal,
+        }
+    }
 }
 
+/// Interval between BBRv3 metrics snapshots
+const METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Pacing state for controlling packet transmission rate
 struct PacingState {
     /// Timestamp of last packet send (microseconds since arbitrary epoch)
@@ -197,12 +290,22 @@ pub struct NetworkScheme<T: NetworkAdapter> {
     blocked: Vec<CallRequest>,
     /// BBRv3 congestion control instance
     bbr: Bbr,
+    /// Periodic BBRv3 metrics snapshots for monitoring
+    sampler: MetricsSampler,
     /// Timestamp of last write for RTT estimation
     last_write: Instant,
+    /// RTT samples from TCP timestamps
+    rtt: RttProbe,
     /// Monotonic timestamp source (in microseconds)
     start_time: Instant,
     /// Pacing state for rate control
     pacing: PacingState,
+    /// IPv6 duplicate address detection
+    dad: Dad,
+    /// Addresses whose detection state changed since the last tick
+    dad_changed: Vec<Ipv6Scope>,
+    /// Results of the last adapter self-test run
+    diag: Option<SelfTestReport>,
 }
 
 impl<T: NetworkAdapter> NetworkScheme<T> {
@@ -224,9 +327,14 @@ impl<T: NetworkAdapter> NetworkScheme<T> {
             handles: BTreeMap::new(),
             blocked: vec![],
             bbr,
+            sampler: MetricsSampler::new(METRICS_INTERVAL),
             last_write: Instant::now(),
+            rtt: RttProbe::default(),
             start_time: Instant::now(),
             pacing: PacingState::default(),
+            dad: Dad::default(),
+            dad_changed: Vec::new(),
+            diag: None,
         }
     }
 
@@ -255,6 +363,55 @@ impl<T: NetworkAdapter> NetworkScheme<T> {
         &mut self.bbr
     }
 
+    /// Start using `address` as the IPv6 address of `scope`
+    ///
+    /// The previous address of `scope` is removed right away, the new one is
+    /// assigned to the adapter once duplicate address detection passed. Used
+    /// for writes to the address files, and by address autoconfiguration.
+    pub fn assign_ipv6(&mut self, scope: Ipv6Scope, address: [u8; 16]) -> Result<()> {
+        if address == [0; 16] {
+            self.adapter.set_ipv6_address(scope, address)?;
+            self.dad.remove(scope);
+            self.dad_changed.push(scope);
+            return Ok(());
+        }
+        if !scope.contains(address) {
+            return Err(Error::new(EINVAL));
+        }
+
+        self.adapter.set_ipv6_address(scope, [0; 16])?;
+        self.dad.start(scope, address, Instant::now());
+        self.dad_changed.push(scope);
+        self.run_dad()
+    }
+
+    /// Returns when [`NetworkScheme::tick`] next has to run for duplicate
+    /// address detection to progress
+    ///
+    /// Drivers should arm a timer for this, as detection otherwise only
+    /// advances when the adapter or the scheme has events.
+    pub fn dad_deadline(&self) -> Option<Instant> {
+        self.dad.deadline()
+    }
+
+    /// Send due Neighbor Solicitations and assign addresses that passed
+    fn run_dad(&mut self) -> Result<()> {
+        let mac = self.adapter.mac_address();
+        for action in self.dad.poll(mac, Instant::now()) {
+            match action {
+                DadAction::Send(frame) => {
+                    // Control traffic, not subject to pacing
+                    self.adapter.write_packet(&frame, 0)?;
+                }
+                DadAction::Assign(scope, address) => {
+                    self.adapter.set_ipv6_address(scope, address)?;
+                    self.dad_changed.push(scope);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Get current timestamp in microseconds since scheme creation
     fn now_us(&self) -> u64 {
         self.start_time.elapsed().as_micros() as u64
@@ -333,6 +490,21 @@ impl<T: NetworkAdapter> NetworkScheme<T> {
             }
         }
 
+        self.run_dad()?;
+
+        let now_us = self.now_us();
+        self.sampler.poll(&self.bbr, now_us);
+
+        // Notify watchers of addresses whose detection state changed
+        for scope in std::mem::take(&mut self.dad_changed) {
+            for (&handle_id, &handle) in self.handles.iter() {
+                if handle == Handle::Dad || handle == Handle::ipv6(scope) {
+                    self.socket
+                        .post_fevent(handle_id, syscall::flag::EVENT_READ.bits())?;
+                }
+            }
+        }
+
         // Notify readers about incoming events
         let available_for_read = self.adapter.available_for_read();
         if available_for_read > 0 {
@@ -368,8 +540,13 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
             "ipv6" => (Handle::Ipv6, NewFdFlags::POSITIONED),
             "ipv6_global" => (Handle::Ipv6Global, NewFdFlags::POSITIONED),
             "ipv6_unique_local" => (Handle::Ipv6UniqueLocal, NewFdFlags::POSITIONED),
+            "dad" => (Handle::Dad, NewFdFlags::POSITIONED),
             "bbr" => (Handle::Bbr, NewFdFlags::POSITIONED),
             "bbr_raw" => (Handle::BbrRaw, NewFdFlags::POSITIONED),
+            "bbr_prometheus" => (Handle::BbrPrometheus, NewFdFlags::POSITIONED),
+            "mtu" => (Handle::Mtu, NewFdFlags::POSITIONED),
+            "promisc" => (Handle::Promisc, NewFdFlags::POSITIONED),
+            "diag" => (Handle::Diag, NewFdFlags::POSITIONED),
             _ => return Err(Error::new(EINVAL)),
         };
 
@@ -422,6 +599,13 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
                 buf[..i].copy_from_slice(&data[..i]);
                 return Ok(Some(i));
             }
+            Handle::Dad => {
+                let data = self.dad.report().into_bytes();
+                let data = data.get(offset as usize..).unwrap_or(&[]);
+                let i = cmp::min(buf.len(), data.len());
+                buf[..i].copy_from_slice(&data[..i]);
+                return Ok(Some(i));
+            }
             Handle::Bbr => {
                 // Text format for human-readable debugging
                 let data = format!("{}", self.bbr).into_bytes();
@@ -445,19 +629,64 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
                 buf[..i].copy_from_slice(&data[..i]);
                 return Ok(Some(i));
             }
+            Handle::BbrPrometheus => {
+                let labels = [("scheme", self.scheme_name.as_str())];
+                let data = self.sampler.to_prometheus(&labels).into_bytes();
+                let data = data.get(offset as usize..).unwrap_or(&[]);
+                let i = cmp::min(buf.len(), data.len());
+                buf[..i].copy_from_slice(&data[..i]);
+                return Ok(Some(i));
+            }
+            Handle::Mtu => {
+                let data = self.adapter.mtu().to_le_bytes();
+                let data = data.get(offset as usize..).unwrap_or(&[]);
+                let i = cmp::min(buf.len(), data.len());
+                buf[..i].copy_from_slice(&data[..i]);
+                return Ok(Some(i));
+            }
+            Handle::Promisc => {
+                let data = [u8::from(self.adapter.promiscuous())];
+                let data = data.get(offset as usize..).unwrap_or(&[]);
+                let i = cmp::min(buf.len(), data.len());
+                buf[..i].copy_from_slice(&data[..i]);
+                return Ok(Some(i));
+            }
+            Handle::Diag => {
+                // Empty until the tests ran once
+                let data = self
+                    .diag
+                    .as_ref()
+                    .map(SelfTestReport::report)
+                    .unwrap_or_default()
+                    .into_bytes();
+                let data = data.get(offset as usize..).unwrap_or(&[]);
+                let i = cmp::min(buf.len(), data.len());
+                buf[..i].copy_from_slice(&data[..i]);
+                return Ok(Some(i));
+            }
         };
 
         // Handle packet read with BBRv3 updates
         match self.adapter.read_packet(buf)? {
             Some(count) => {
-                // Estimate RTT from time since last write
-                let rtt_us = estimate_rtt_us(self.last_write);
+                let rtt_us = match self.rtt.on_receive(&buf[..count], Instant::now()) {
+                    RttSample::Measured(rtt) => (rtt.as_micros() as u64).max(1),
+                    // BBR ignores a zero RTT, keep a TCP flow's samples exact
+                    RttSample::Unmatched => 0,
+                    // Estimate RTT from time since last write
+                    RttSample::NoTimestamp => estimate_rtt_us(self.last_write),
+                };
                 let in_flight = self.adapter.in_flight();
                 let now_us = self.now_us();
 
                 // Update BBRv3 with the ACK
                 self.bbr.on_ack(count as u64, rtt_us, in_flight, now_us);
 
+                // Neighbor Discovery messages may prove a tentative address duplicate
+                if let Some(scope) = self.dad.on_receive(&buf[..count]) {
+                    self.dad_changed.push(scope);
+                }
+
                 // Check for ECN congestion signals
                 if let Some(ecn) = extract_ecn(&buf[..count]) {
                     if ecn == EcnFlag::Ce {
@@ -484,17 +713,54 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
         _offset: u64,
         _fcntl_flags: u32,
     ) -> Result<Option<usize>> {
-        let handle = self.handles.get(&id).ok_or(Error::new(EBADF))?;
+        let handle = *self.handles.get(&id).ok_or(Error::new(EBADF))?;
 
         match handle {
             Handle::Data => {}
             Handle::Mac => return Err(Error::new(EINVAL)),
-            Handle::Ipv4 => return Err(Error::new(EINVAL)),
-            Handle::Ipv6 => return Err(Error::new(EINVAL)),
-            Handle::Ipv6Global => return Err(Error::new(EINVAL)),
-            Handle::Ipv6UniqueLocal => return Err(Error::new(EINVAL)),
+            Handle::Ipv4 => {
+                let address = buf.try_into().map_err(|_| Error::new(EINVAL))?;
+                self.adapter.set_ipv4_address(address)?;
+                return Ok(Some(buf.len()));
+            }
+            Handle::Ipv6 | Handle::Ipv6Global | Handle::Ipv6UniqueLocal => {
+                let scope = match handle {
+                    Handle::Ipv6 => Ipv6Scope::LinkLocal,
+                    Handle::Ipv6Global => Ipv6Scope::Global,
+                    _ => Ipv6Scope::UniqueLocal,
+                };
+                let address = buf.try_into().map_err(|_| Error::new(EINVAL))?;
+                self.assign_ipv6(scope, address)?;
+                return Ok(Some(buf.len()));
+            }
+            Handle::Dad => return Err(Error::new(EINVAL)),
             Handle::Bbr => return Err(Error::new(EINVAL)),
             Handle::BbrRaw => return Err(Error::new(EINVAL)),
+            Handle::BbrPrometheus => return Err(Error::new(EINVAL)),
+            Handle::Mtu => {
+                let mtu = buf.try_into().map_err(|_| Error::new(EINVAL))?;
+                self.adapter.set_mtu(u32::from_le_bytes(mtu))?;
+                return Ok(Some(buf.len()));
+            }
+            Handle::Promisc => {
+                let enabled = match buf {
+                    [0] => false,
+                    [1] => true,
+                    _ => return Err(Error::new(EINVAL)),
+                };
+                self.adapter.set_promiscuous(enabled)?;
+                return Ok(Some(buf.len()));
+            }
+            Handle::Diag => {
+                self.diag = Some(self.adapter.self_test()?);
+                for (&handle_id, &handle) in self.handles.iter() {
+                    if handle == Handle::Diag {
+                        self.socket
+                            .post_fevent(handle_id, syscall::flag::EVENT_READ.bits())?;
+                    }
+                }
+                return Ok(Some(buf.len()));
+            }
         }
 
         // Enforce pacing rate
@@ -521,6 +787,7 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
         // Update pacing state and BBRv3
         self.record_send(result as u64);
         self.last_write = Instant::now();
+        self.rtt.on_send(&buf[..result], self.last_write);
 
         Ok(Some(result))
     }
@@ -555,8 +822,13 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
             Handle::Ipv6 => &b"ipv6"[..],
             Handle::Ipv6Global => &b"ipv6_global"[..],
             Handle::Ipv6UniqueLocal => &b"ipv6_unique_local"[..],
+            Handle::Dad => &b"dad"[..],
             Handle::Bbr => &b"bbr"[..],
             Handle::BbrRaw => &b"bbr_raw"[..],
+            Handle::BbrPrometheus => &b"bbr_prometheus"[..],
+            Handle::Mtu => &b"mtu"[..],
+            Handle::Promisc => &b"promisc"[..],
+            Handle::Diag => &b"diag"[..],
         };
 
         j = 0;
@@ -581,13 +853,17 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
                 stat.st_size = 6;
             }
             Handle::Ipv4 => {
-                stat.st_mode = MODE_FILE | 0o400;
+                stat.st_mode = MODE_FILE | 0o600;
                 stat.st_size = 4;
             }
             Handle::Ipv6 | Handle::Ipv6Global | Handle::Ipv6UniqueLocal => {
-                stat.st_mode = MODE_FILE | 0o400;
+                stat.st_mode = MODE_FILE | 0o600;
                 stat.st_size = 16;
             }
+            Handle::Dad => {
+                stat.st_mode = MODE_FILE | 0o400;
+                stat.st_size = 0; // Variable size text
+            }
             Handle::Bbr => {
                 stat.st_mode = MODE_FILE | 0o400;
                 stat.st_size = 0; // Variable size text
@@ -596,6 +872,22 @@ impl<T: NetworkAdapter> SchemeBlock for NetworkScheme<T> {
                 stat.st_mode = MODE_FILE | 0o400;
                 stat.st_size = 64; // Fixed size binary
             }
+            Handle::BbrPrometheus => {
+                stat.st_mode = MODE_FILE | 0o400;
+                stat.st_size = 0; // Variable size text
+            }
+            Handle::Mtu => {
+                stat.st_mode = MODE_FILE | 0o600;
+                stat.st_size = 4;
+            }
+            Handle::Promisc => {
+                stat.st_mode = MODE_FILE | 0o600;
+                stat.st_size = 1;
+            }
+            Handle::Diag => {
+                stat.st_mode = MODE_FILE | 0o600;
+                stat.st_size = 0; // Variable size text
+            }
         }
 
         Ok(Some(0))
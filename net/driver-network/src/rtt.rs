@@ -0,0 +1,254 @@
+//! Per-flow RTT samples from the TCP timestamp option (RFC 7323)
+//!
+//! The TSval of every outgoing segment carrying a timestamp option is
+//! recorded with the time it was first sent. When the peer echoes it back
+//! in TSecr, the time since then is an RTT sample for that flow. Each TSval
+//! gives at most one sample, and echoing it also acknowledges every older
+//! TSval of the flow, so delayed ACKs and duplicate ACKs don't inflate the
+//! samples. Retransmissions carry a fresh TSval, which keeps them apart from
+//! the original transmission (Karn's problem doesn't apply).
+//!
+//! Only TCP directly after the IPv4 or IPv6 header is recognized; IPv6
+//! extension headers and IPv4 fragments other than the first aren't parsed.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// TSvals remembered per flow before the oldest are dropped
+const MAX_OUTSTANDING: usize = 64;
+
+/// Flows tracked at once before the least recently active is dropped
+const MAX_FLOWS: usize = 1024;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const PROTOCOL_TCP: u8 = 6;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const TCP_HEADER_LEN: usize = 20;
+
+const TCP_FLAG_RST: u8 = 0x04;
+const TCP_FLAG_ACK: u8 = 0x10;
+
+const TCP_OPTION_END: u8 = 0;
+const TCP_OPTION_NOP: u8 = 1;
+const TCP_OPTION_TIMESTAMP: u8 = 8;
+const TCP_OPTION_TIMESTAMP_LEN: usize = 10;
+
+/// TCP connection as seen from this host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Flow {
+    local: IpAddr,
+    local_port: u16,
+    remote: IpAddr,
+    remote_port: u16,
+}
+
+/// TCP header fields of a frame
+struct Segment {
+    /// Source address and port
+    source: (IpAddr, u16),
+    /// Destination address and port
+    destination: (IpAddr, u16),
+    flags: u8,
+    /// TSval and TSecr
+    timestamp: Option<(u32, u32)>,
+}
+
+/// What a received frame tells about the RTT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RttSample {
+    /// A TCP segment echoed the timestamp of a segment sent earlier
+    Measured(Duration),
+    /// A TCP segment without a new echo, no sample
+    Unmatched,
+    /// Not TCP, or TCP without the timestamp option: can't be measured
+    NoTimestamp,
+}
+
+/// Timestamps sent on a flow and not echoed yet
+struct Outstanding {
+    /// TSvals in sending order, with the time they were first sent
+    sent: VecDeque<(u32, Instant)>,
+    last_active: Instant,
+}
+
+/// RTT measurement through TCP timestamp echoes
+#[derive(Default)]
+pub struct RttProbe {
+    flows: BTreeMap<Flow, Outstanding>,
+}
+
+impl RttProbe {
+    /// Record the timestamp of an outgoing frame
+    pub fn on_send(&mut self, frame: &[u8], now: Instant) {
+        let Some(segment) = parse_segment(frame) else {
+            return;
+        };
+        let flow = Flow {
+            local: segment.source.0,
+            local_port: segment.source.1,
+            remote: segment.destination.0,
+            remote_port: segment.destination.1,
+        };
+        if segment.flags & TCP_FLAG_RST != 0 {
+            self.flows.remove(&flow);
+            return;
+        }
+        let Some((tsval, _)) = segment.timestamp else {
+            return;
+        };
+
+        if !self.flows.contains_key(&flow) && self.flows.len() >= MAX_FLOWS {
+            self.evict_idle();
+        }
+        let outstanding = self.flows.entry(flow).or_insert_with(|| Outstanding {
+            sent: VecDeque::new(),
+            last_active: now,
+        });
+        outstanding.last_active = now;
+        // Segments sent within one timestamp clock tick share a TSval, the
+        // first one sent times it
+        if outstanding.sent.back().map(|&(last, _)| last) == Some(tsval) {
+            return;
+        }
+        if outstanding.sent.len() == MAX_OUTSTANDING {
+            outstanding.sent.pop_front();
+        }
+        outstanding.sent.push_back((tsval, now));
+    }
+
+    /// Take an RTT sample from an incoming frame
+    pub fn on_receive(&mut self, frame: &[u8], now: Instant) -> RttSample {
+        let Some(segment) = parse_segment(frame) else {
+            return RttSample::NoTimestamp;
+        };
+        let flow = Flow {
+            local: segment.destination.0,
+            local_port: segment.destination.1,
+            remote: segment.source.0,
+            remote_port: segment.source.1,
+        };
+        if segment.flags & TCP_FLAG_RST != 0 {
+            self.flows.remove(&flow);
+            return RttSample::Unmatched;
+        }
+        let Some((_, tsecr)) = segment.timestamp else {
+            return RttSample::NoTimestamp;
+        };
+        // TSecr is only meaningful with ACK set
+        if segment.flags & TCP_FLAG_ACK == 0 {
+            return RttSample::Unmatched;
+        }
+        let Some(outstanding) = self.flows.get_mut(&flow) else {
+            return RttSample::Unmatched;
+        };
+        outstanding.last_active = now;
+
+        let Some(index) = outstanding
+            .sent
+            .iter()
+            .position(|&(tsval, _)| tsval == tsecr)
+        else {
+            return RttSample::Unmatched;
+        };
+        let (_, sent) = outstanding.sent[index];
+        outstanding.sent.drain(..=index);
+        RttSample::Measured(now.saturating_duration_since(sent))
+    }
+
+    /// Forget the flow that was active the longest time ago
+    fn evict_idle(&mut self) {
+        if let Some(flow) = self
+            .flows
+            .iter()
+            .min_by_key(|(_, outstanding)| outstanding.last_active)
+            .map(|(&flow, _)| flow)
+        {
+            self.flows.remove(&flow);
+        }
+    }
+}
+
+/// Parse the addresses, ports, flags and timestamp option of a TCP segment
+/// in an Ethernet frame
+fn parse_segment(frame: &[u8]) -> Option<Segment> {
+    let ethertype = u16::from_be_bytes([*frame.get(12)?, *frame.get(13)?]);
+    let packet = &frame[ETHERNET_HEADER_LEN..];
+
+    let (source, destination, tcp) = match ethertype {
+        ETHERTYPE_IPV4 => {
+            let header = packet.get(..IPV4_HEADER_LEN)?;
+            let header_len = usize::from(header[0] & 0x0F) * 4;
+            let fragment_offset = u16::from_be_bytes([header[6], header[7]]) & 0x1FFF;
+            if header[0] >> 4 != 4
+                || header[9] != PROTOCOL_TCP
+                || header_len < IPV4_HEADER_LEN
+                || fragment_offset != 0
+            {
+                return None;
+            }
+            let source: [u8; 4] = header[12..16].try_into().ok()?;
+            let destination: [u8; 4] = header[16..20].try_into().ok()?;
+            (
+                IpAddr::from(source),
+                IpAddr::from(destination),
+                packet.get(header_len..)?,
+            )
+        }
+        ETHERTYPE_IPV6 => {
+            let header = packet.get(..IPV6_HEADER_LEN)?;
+            if header[0] >> 4 != 6 || header[6] != PROTOCOL_TCP {
+                return None;
+            }
+            let source: [u8; 16] = header[8..24].try_into().ok()?;
+            let destination: [u8; 16] = header[24..40].try_into().ok()?;
+            (
+                IpAddr::from(source),
+                IpAddr::from(destination),
+                &packet[IPV6_HEADER_LEN..],
+            )
+        }
+        _ => return None,
+    };
+
+    let header = tcp.get(..TCP_HEADER_LEN)?;
+    let header_len = usize::from(header[12] >> 4) * 4;
+    if header_len < TCP_HEADER_LEN {
+        return None;
+    }
+    let options = tcp.get(TCP_HEADER_LEN..header_len)?;
+
+    Some(Segment {
+        source: (source, u16::from_be_bytes([header[0], header[1]])),
+        destination: (destination, u16::from_be_bytes([header[2], header[3]])),
+        flags: header[13],
+        timestamp: parse_timestamp(options),
+    })
+}
+
+/// Find the timestamp option in the TCP options
+fn parse_timestamp(mut options: &[u8]) -> Option<(u32, u32)> {
+    loop {
+        match *options.first()? {
+            TCP_OPTION_END => return None,
+            TCP_OPTION_NOP => options = &options[1..],
+            kind => {
+                let len = usize::from(*options.get(1)?);
+                if len < 2 {
+                    return None;
+                }
+                let option = options.get(..len)?;
+                if kind == TCP_OPTION_TIMESTAMP && len == TCP_OPTION_TIMESTAMP_LEN {
+                    let tsval = u32::from_be_bytes(option[2..6].try_into().ok()?);
+                    let tsecr = u32::from_be_bytes(option[6..10].try_into().ok()?);
+                    return Some((tsval, tsecr));
+                }
+                options = &options[len..];
+            }
+        }
+    }
+}
@@ -2,9 +2,11 @@ use std::convert::TryInto;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::{cmp, mem, ptr, slice, thread, time};
 
-use driver_network::NetworkAdapter;
+use driver_network::{
+    is_loopback_frame, loopback_frame, Ipv6Scope, NetworkAdapter, SelfTest, SelfTestReport,
+};
 
-use syscall::error::Result;
+use syscall::error::{Error, Result, EOPNOTSUPP};
 
 use common::dma::Dma;
 
@@ -25,6 +27,7 @@ const FCT: u32 = 0x30;
 const FCTTV: u32 = 0x170;
 
 const ICR: u32 = 0xC0;
+const ICS: u32 = 0xC8;
 
 const IMS: u32 = 0xD0;
 const IMS_TXDW: u32 = 1;
@@ -41,6 +44,7 @@ const RCTL_UPE: u32 = 1 << 3;
 const RCTL_MPE: u32 = 1 << 4;
 const RCTL_LPE: u32 = 1 << 5;
 const RCTL_LBM: u32 = 1 << 6 | 1 << 7;
+const RCTL_LBM_MAC: u32 = 1 << 6;
 const RCTL_BAM: u32 = 1 << 15;
 const RCTL_BSIZE1: u32 = 1 << 16;
 const RCTL_BSIZE2: u32 = 1 << 17;
@@ -99,6 +103,8 @@ pub struct Intel8254x {
     base: usize,
     mac_address: [u8; 6],
     ipv4_address: [u8; 4],
+    ipv6_address_global: [u8; 16],
+    ipv6_address_unique_local: [u8; 16],
     receive_buffer: [Dma<[u8; 16384]>; 16],
     receive_ring: Dma<[Rd; 16]>,
     receive_index: usize,
@@ -154,11 +160,11 @@ impl NetworkAdapter for Intel8254x {
     }
 
     fn ipv6_address_global(&mut self) -> [u8; 16] {
-        [0; 16] // No global IPv6 address configured
+        self.ipv6_address_global
     }
 
     fn ipv6_address_unique_local(&mut self) -> [u8; 16] {
-        [0; 16] // No unique local IPv6 address configured
+        self.ipv6_address_unique_local
     }
 
     fn available_for_read(&mut self) -> usize {
@@ -253,6 +259,38 @@ impl NetworkAdapter for Intel8254x {
     fn in_flight(&self) -> u64 {
         self.in_flight_bytes.load(Ordering::SeqCst)
     }
+
+    fn set_ipv4_address(&mut self, address: [u8; 4]) -> Result<()> {
+        self.ipv4_address = address;
+        Ok(())
+    }
+
+    fn set_ipv6_address(&mut self, scope: Ipv6Scope, address: [u8; 16]) -> Result<()> {
+        match scope {
+            // The link-local address is always derived from the MAC address
+            Ipv6Scope::LinkLocal => return Err(Error::new(EOPNOTSUPP)),
+            Ipv6Scope::Global => self.ipv6_address_global = address,
+            Ipv6Scope::UniqueLocal => self.ipv6_address_unique_local = address,
+        }
+        Ok(())
+    }
+
+    fn promiscuous(&mut self) -> bool {
+        unsafe { self.read_reg(RCTL) & RCTL_UPE == RCTL_UPE }
+    }
+
+    fn set_promiscuous(&mut self, enabled: bool) -> Result<()> {
+        unsafe { self.flag(RCTL, RCTL_UPE | RCTL_MPE, enabled) };
+        Ok(())
+    }
+
+    fn self_test(&mut self) -> Result<SelfTestReport> {
+        let mut report = SelfTestReport::new();
+        report.run(SelfTest::Registers, || unsafe { self.test_registers() });
+        report.run(SelfTest::Loopback, || unsafe { self.test_loopback() });
+        report.run(SelfTest::Interrupt, || unsafe { self.test_interrupt() });
+        Ok(report)
+    }
 }
 
 fn dma_array<T, const N: usize>() -> Result<[Dma<T>; N]> {
@@ -269,6 +307,8 @@ impl Intel8254x {
             base,
             mac_address: [0; 6],
             ipv4_address: [10, 0, 2, 15], // Default QEMU/DHCP address
+            ipv6_address_global: [0; 16],
+            ipv6_address_unique_local: [0; 16],
             receive_buffer: dma_array()?,
             receive_ring: Dma::zeroed()?.assume_init(),
             transmit_buffer: dma_array()?,
@@ -307,6 +347,73 @@ impl Intel8254x {
         }
     }
 
+    /// Check that the registers respond, using the unused flow control timer
+    unsafe fn test_registers(&self) -> std::result::Result<(), String> {
+        let status = self.read_reg(STATUS);
+        if status == u32::MAX {
+            return Err(format!("device not responding, STATUS {status:#X}"));
+        }
+        for pattern in [0x5A5A, 0xA5A5, 0xFFFF] {
+            let value = self.write_reg(FCTTV, pattern) & 0xFFFF;
+            if value != pattern {
+                self.write_reg(FCTTV, 0);
+                return Err(format!("FCTTV read back {value:#X}, wrote {pattern:#X}"));
+            }
+        }
+        self.write_reg(FCTTV, 0);
+        Ok(())
+    }
+
+    /// Send a frame in MAC loopback mode and wait for it to come back
+    ///
+    /// Frames received meanwhile are dropped.
+    unsafe fn test_loopback(&mut self) -> std::result::Result<(), String> {
+        let mac = self.mac_address;
+        // Tells the frame apart from ones left over by earlier runs
+        let sequence = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let rctl = self.read_reg(RCTL);
+        self.write_reg(RCTL, rctl & !RCTL_LBM | RCTL_LBM_MAC);
+
+        let result = self
+            .write_packet(&loopback_frame(mac, sequence), 0)
+            .map_err(|err| format!("transmit failed: {err}"))
+            .and_then(|_| {
+                let mut buf = [0; 2048];
+                for _ in 0..100 {
+                    while let Some(count) = self.read_packet(&mut buf).ok().flatten() {
+                        if is_loopback_frame(&buf[..count], mac, sequence) {
+                            return Ok(());
+                        }
+                    }
+                    thread::sleep(time::Duration::from_millis(1));
+                }
+                Err("no test frame received".to_string())
+            });
+
+        self.write_reg(RCTL, rctl);
+        result
+    }
+
+    /// Raise the masked transmit queue empty cause and check that it latches
+    ///
+    /// Causes that were pending are raised again for the IRQ handler.
+    unsafe fn test_interrupt(&self) -> std::result::Result<(), String> {
+        let pending = self.read_reg(ICR);
+        self.write_reg(ICS, IMS_TXQE);
+        let icr = self.read_reg(ICR);
+        let pending = pending | icr & !IMS_TXQE;
+        if pending != 0 {
+            self.write_reg(ICS, pending);
+        }
+        if icr & IMS_TXQE == IMS_TXQE {
+            Ok(())
+        } else {
+            Err(format!("ICR {icr:#X} after setting TXQE in ICS"))
+        }
+    }
+
     pub unsafe fn init(&mut self) {
         self.flag(CTRL, CTRL_RST, true);
         while self.read_reg(CTRL) & CTRL_RST == CTRL_RST {
@@ -0,0 +1,215 @@
+//! netcfg - network interface configuration utility
+//!
+//! Lists network adapters and shows or changes their addresses, MTU and
+//! promiscuous mode through the files of their `network.*` schemes, and dumps
+//! their BBRv3 congestion control metrics.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::process;
+
+const SCHEME_DIR: &str = "/scheme";
+const SCHEME_PREFIX: &str = "network.";
+
+const USAGE: &str = "Usage: netcfg [command]
+
+Commands:
+  list                          List network adapters (default)
+  show <iface>                  Show addresses and settings of an adapter
+  addr <iface> <ipv4>           Set the IPv4 address
+  mtu <iface> <bytes>           Set the MTU
+  promisc <iface> <on|off>      Enable or disable promiscuous mode
+  bbr <iface> [--raw]           Dump BBRv3 metrics
+
+<iface> is the adapter name as printed by `netcfg list`.";
+
+/// Network adapter scheme
+struct Interface {
+    /// Name without the `network.` prefix
+    name: String,
+}
+
+impl Interface {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.strip_prefix(SCHEME_PREFIX).unwrap_or(name).to_string(),
+        }
+    }
+
+    fn path(&self, file: &str) -> String {
+        format!("{}/{}{}/{}", SCHEME_DIR, SCHEME_PREFIX, self.name, file)
+    }
+
+    fn read(&self, file: &str) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        File::open(self.path(file))
+            .and_then(|mut f| f.read_to_end(&mut data))
+            .map_err(|e| format!("{}: failed to read {}: {}", self.name, file, e))?;
+        Ok(data)
+    }
+
+    fn read_array<const N: usize>(&self, file: &str) -> Result<[u8; N], String> {
+        let data = self.read(file)?;
+        data.get(..N)
+            .and_then(|data| data.try_into().ok())
+            .ok_or_else(|| format!("{}: short read of {}", self.name, file))
+    }
+
+    fn write(&self, file: &str, data: &[u8]) -> Result<(), String> {
+        File::options()
+            .write(true)
+            .open(self.path(file))
+            .and_then(|mut f| f.write_all(data))
+            .map_err(|e| format!("{}: failed to write {}: {}", self.name, file, e))
+    }
+
+    fn mac(&self) -> Result<String, String> {
+        let mac = self.read_array::<6>("mac")?;
+        Ok(mac
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(":"))
+    }
+
+    fn ipv4(&self) -> Result<Ipv4Addr, String> {
+        self.read_array::<4>("ipv4").map(Ipv4Addr::from)
+    }
+
+    fn ipv6(&self, file: &str) -> Result<Ipv6Addr, String> {
+        self.read_array::<16>(file).map(Ipv6Addr::from)
+    }
+
+    fn mtu(&self) -> Result<u32, String> {
+        self.read_array::<4>("mtu").map(u32::from_le_bytes)
+    }
+
+    fn promiscuous(&self) -> Result<bool, String> {
+        self.read_array::<1>("promisc").map(|[b]| b != 0)
+    }
+}
+
+/// Adapters registered with the scheme namespace, sorted by name
+fn interfaces() -> Result<Vec<Interface>, String> {
+    let entries =
+        fs::read_dir(SCHEME_DIR).map_err(|e| format!("failed to list {}: {}", SCHEME_DIR, e))?;
+    let mut interfaces: Vec<Interface> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with(SCHEME_PREFIX))
+        .map(|name| Interface::new(&name))
+        .collect();
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(interfaces)
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn list() -> Result<(), String> {
+    let interfaces = interfaces()?;
+    if interfaces.is_empty() {
+        println!("No network adapters");
+        return Ok(());
+    }
+
+    println!(
+        "{:<32} {:<17} {:<15} {:>5} PROMISC",
+        "NAME", "MAC", "IPV4", "MTU"
+    );
+    for iface in interfaces {
+        let field = |value: Result<String, String>| value.unwrap_or_else(|_| "-".to_string());
+        println!(
+            "{:<32} {:<17} {:<15} {:>5} {}",
+            iface.name,
+            field(iface.mac()),
+            field(iface.ipv4().map(|a| a.to_string())),
+            field(iface.mtu().map(|m| m.to_string())),
+            field(iface.promiscuous().map(|p| on_off(p).to_string())),
+        );
+    }
+    Ok(())
+}
+
+fn show(iface: &Interface) -> Result<(), String> {
+    println!("{}:", iface.name);
+    println!("    mac       {}", iface.mac()?);
+    println!("    ipv4      {}", iface.ipv4()?);
+    for (label, file) in [
+        ("link", "ipv6"),
+        ("global", "ipv6_global"),
+        ("ula", "ipv6_unique_local"),
+    ] {
+        let addr = iface.ipv6(file)?;
+        if !addr.is_unspecified() {
+            println!("    ipv6      {} ({})", addr, label);
+        }
+    }
+    println!("    mtu       {}", iface.mtu()?);
+    println!("    promisc   {}", on_off(iface.promiscuous()?));
+    Ok(())
+}
+
+fn bbr(iface: &Interface, raw: bool) -> Result<(), String> {
+    if raw {
+        let data = iface.read("bbr_raw")?;
+        for (i, chunk) in data.chunks(16).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            println!("{:04x}: {}", i * 16, hex.join(" "));
+        }
+    } else {
+        let data = iface.read("bbr")?;
+        print!("{}", String::from_utf8_lossy(&data));
+    }
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] | ["list"] => list(),
+        ["show", name] => show(&Interface::new(name)),
+        ["addr", name, addr] => {
+            let addr: Ipv4Addr = addr
+                .parse()
+                .map_err(|_| format!("invalid IPv4 address: {}", addr))?;
+            Interface::new(name).write("ipv4", &addr.octets())
+        }
+        ["mtu", name, mtu] => {
+            let mtu: u32 = mtu.parse().map_err(|_| format!("invalid MTU: {}", mtu))?;
+            if !(68..=65535).contains(&mtu) {
+                return Err(format!("MTU out of range (68-65535): {}", mtu));
+            }
+            Interface::new(name).write("mtu", &mtu.to_le_bytes())
+        }
+        ["promisc", name, mode] => {
+            let enabled = match *mode {
+                "on" => true,
+                "off" => false,
+                _ => return Err(format!("expected on or off, got {}", mode)),
+            };
+            Interface::new(name).write("promisc", &[u8::from(enabled)])
+        }
+        ["bbr", name] => bbr(&Interface::new(name), false),
+        ["bbr", name, "--raw"] => bbr(&Interface::new(name), true),
+        ["-h"] | ["--help"] | ["help"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("netcfg: {}", e);
+        process::exit(1);
+    }
+}
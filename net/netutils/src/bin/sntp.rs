@@ -0,0 +1,290 @@
+//! sntp - SNTP time synchronization client
+//!
+//! Queries an NTP server (RFC 4330), steps the system clock by the measured
+//! offset through the kernel's time offset, the same way `rtcd` sets it from
+//! the hardware clock at boot, and then re-syncs periodically in the
+//! background. Embedded images without a battery-backed RTC have no other
+//! source of wall-clock time.
+
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const NTP_PORT: u16 = 123;
+const DEFAULT_SERVER: &str = "pool.ntp.org";
+/// Default time between syncs, the longest poll interval SNTP clients use
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1024);
+/// First retry delay after a failed sync, doubled up to the sync interval
+const MIN_RETRY: Duration = Duration::from_secs(16);
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Offsets below this are left alone instead of stepping the clock
+const MIN_STEP_NS: i128 = 1_000_000;
+
+const TIME_OFFSET_PATH: &str = "/scheme/sys/update_time_offset";
+
+const PACKET_LEN: usize = 48;
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+const MAX_STRATUM: u8 = 15;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970)
+const NTP_UNIX_OFFSET: i128 = 2_208_988_800;
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+const USAGE: &str = "Usage: sntp [options] [server[:port]]
+
+Options:
+  --once              Sync once in the foreground and exit
+  --interval <secs>   Seconds between syncs (default 1024)
+
+The server defaults to pool.ntp.org.";
+
+struct Config {
+    server: String,
+    interval: Duration,
+    once: bool,
+}
+
+impl Config {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut config = Self {
+            server: DEFAULT_SERVER.to_string(),
+            interval: DEFAULT_INTERVAL,
+            once: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--once" => config.once = true,
+                "--interval" => {
+                    let secs = args
+                        .next()
+                        .and_then(|secs| secs.parse::<u64>().ok())
+                        .filter(|&secs| secs != 0)
+                        .ok_or_else(|| USAGE.to_string())?;
+                    config.interval = Duration::from_secs(secs);
+                }
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                server if !server.starts_with('-') => config.server = server.to_string(),
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(config)
+    }
+
+    fn resolve(&self) -> Result<SocketAddr, String> {
+        let server = self.server.as_str();
+        let addrs: Vec<SocketAddr> = match server.parse::<IpAddr>() {
+            Ok(ip) => Ok(vec![SocketAddr::new(ip, NTP_PORT)]),
+            // host:port or [ipv6]:port
+            Err(_) if server.contains(':') => server.to_socket_addrs().map(Iterator::collect),
+            Err(_) => (server, NTP_PORT).to_socket_addrs().map(Iterator::collect),
+        }
+        .map_err(|e| format!("failed to resolve {}: {}", server, e))?;
+        addrs
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("{} has no addresses", server))
+    }
+}
+
+/// Result of one exchange with the server
+struct Sample {
+    /// Server clock minus local clock, in nanoseconds
+    offset: i128,
+    /// Round trip time, without the server's processing time, in nanoseconds
+    delay: i128,
+    stratum: u8,
+}
+
+/// Wall-clock time in nanoseconds since the Unix epoch
+fn now() -> i128 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i128,
+        Err(err) => -(err.duration().as_nanos() as i128),
+    }
+}
+
+/// Unix time in nanoseconds to an NTP timestamp (32.32 fixed point seconds)
+fn to_ntp(unix: i128) -> u64 {
+    let ntp = unix + NTP_UNIX_OFFSET * NANOS_PER_SEC;
+    let secs = ntp.div_euclid(NANOS_PER_SEC) as u64 & 0xFFFF_FFFF;
+    let frac = ((ntp.rem_euclid(NANOS_PER_SEC) << 32) / NANOS_PER_SEC) as u64;
+    secs << 32 | frac
+}
+
+/// NTP timestamp to Unix time in nanoseconds
+///
+/// Timestamps with the top bit clear are taken to be in the era starting in
+/// 2036, as RFC 4330 suggests.
+fn from_ntp(ntp: u64) -> i128 {
+    let mut secs = i128::from(ntp >> 32);
+    if secs & 0x8000_0000 == 0 {
+        secs += 1 << 32;
+    }
+    let frac = (i128::from(ntp & 0xFFFF_FFFF) * NANOS_PER_SEC) >> 32;
+    (secs - NTP_UNIX_OFFSET) * NANOS_PER_SEC + frac
+}
+
+fn timestamp_at(packet: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(packet[offset..offset + 8].try_into().unwrap())
+}
+
+/// Run one client/server exchange with `addr`
+fn query(addr: SocketAddr) -> Result<Sample, String> {
+    let bind: SocketAddr = if addr.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind).map_err(|e| format!("failed to bind socket: {}", e))?;
+    socket
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| format!("failed to set timeout: {}", e))?;
+
+    let mut request = [0; PACKET_LEN];
+    request[0] = VERSION << 3 | MODE_CLIENT;
+    let t1 = now();
+    let transmit = to_ntp(t1);
+    request[40..48].copy_from_slice(&transmit.to_be_bytes());
+
+    let sent = Instant::now();
+    socket
+        .send_to(&request, addr)
+        .map_err(|e| format!("failed to send to {}: {}", addr, e))?;
+
+    let mut reply = [0; 512];
+    let t4 = loop {
+        let (len, from) = socket
+            .recv_from(&mut reply)
+            .map_err(|e| format!("no reply from {}: {}", addr, e))?;
+        // Measure T4 on the monotonic clock, so a concurrent clock step
+        // doesn't skew the sample
+        let elapsed = sent.elapsed().as_nanos() as i128;
+        if from == addr && len >= PACKET_LEN && timestamp_at(&reply, 24) == transmit {
+            break t1 + elapsed;
+        }
+        if sent.elapsed() >= TIMEOUT {
+            return Err(format!("no reply from {}", addr));
+        }
+    };
+    let reply = &reply[..PACKET_LEN];
+
+    let leap = reply[0] >> 6;
+    let mode = reply[0] & 0x7;
+    let stratum = reply[1];
+    if mode != MODE_SERVER {
+        return Err(format!("{}: unexpected mode {}", addr, mode));
+    }
+    if stratum == 0 {
+        let code = String::from_utf8_lossy(&reply[12..16]);
+        return Err(format!(
+            "{}: kiss-o'-death {}",
+            addr,
+            code.trim_end_matches('\0')
+        ));
+    }
+    if stratum > MAX_STRATUM || leap == LEAP_UNSYNCHRONIZED {
+        return Err(format!("{}: server is not synchronized", addr));
+    }
+    let t3_raw = timestamp_at(reply, 40);
+    if t3_raw == 0 {
+        return Err(format!("{}: reply without transmit timestamp", addr));
+    }
+
+    let t2 = from_ntp(timestamp_at(reply, 32));
+    let t3 = from_ntp(t3_raw);
+    Ok(Sample {
+        offset: ((t2 - t1) + (t3 - t4)) / 2,
+        delay: (t4 - t1) - (t3 - t2),
+        stratum,
+    })
+}
+
+/// Step the system clock by `offset` nanoseconds
+fn step(offset: i128) -> Result<(), String> {
+    let time = u128::try_from(now() + offset)
+        .map_err(|_| format!("offset {} ns is before the epoch", offset))?;
+    std::fs::write(TIME_OFFSET_PATH, time.to_ne_bytes())
+        .map_err(|e| format!("failed to write {}: {}", TIME_OFFSET_PATH, e))
+}
+
+fn sync(config: &Config) -> Result<(), String> {
+    let addr = config.resolve()?;
+    let sample = query(addr)?;
+    log::debug!(
+        "{}: stratum {}, offset {} ns, delay {} ns",
+        addr,
+        sample.stratum,
+        sample.offset,
+        sample.delay
+    );
+    if sample.offset.abs() < MIN_STEP_NS {
+        return Ok(());
+    }
+    step(sample.offset)?;
+    log::info!(
+        "stepped clock by {:.3} s from {}",
+        sample.offset as f64 / NANOS_PER_SEC as f64,
+        addr
+    );
+    Ok(())
+}
+
+fn run(config: Config) -> ! {
+    let mut retry = MIN_RETRY;
+    loop {
+        let wait = match sync(&config) {
+            Ok(()) => {
+                retry = MIN_RETRY;
+                config.interval
+            }
+            Err(err) => {
+                log::warn!("sntp: {}", err);
+                let wait = retry;
+                retry = (retry * 2).min(config.interval);
+                wait
+            }
+        };
+        thread::sleep(wait);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match Config::parse(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("sntp: {}", e);
+            process::exit(1);
+        }
+    };
+
+    common::setup_logging(
+        "net",
+        "netutils",
+        "sntp",
+        common::output_level(),
+        common::file_level(),
+    );
+
+    if config.once {
+        if let Err(e) = sync(&config) {
+            eprintln!("sntp: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    redox_daemon::Daemon::new(move |daemon| {
+        daemon.ready().expect("sntp: failed to notify parent");
+        run(config)
+    })
+    .expect("sntp: failed to daemonize");
+}
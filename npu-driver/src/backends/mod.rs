@@ -0,0 +1,92 @@
+//! Hardware backends
+//!
+//! A backend drives one physical NPU. The device's command queue is drained
+//! by executor threads that hand each command to the backend and report its
+//! status back to the queue, so clients see backends only through
+//! completions. A backend whose device is split into partitions gets an
+//! executor per partition, running independent commands side by side.
+//!
+//! Executors go through the device's [`PowerManager`], which may hold them
+//! back when the device runs hot, and a power thread per device samples its
+//! temperature and suspends it when idle.
+//!
+//! [`PowerManager`]: crate::power::PowerManager
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::command::{Command, CommandStatus};
+use crate::memory::NpuBuffer;
+use crate::power::{PowerState, SAMPLE_PERIOD};
+use crate::NpuDevice;
+
+pub mod xdna;
+
+/// How long the executor waits for a submission before checking again
+const EXECUTOR_POLL: Duration = Duration::from_millis(100);
+
+/// Driver for one physical NPU
+pub trait Backend: Send + Sync {
+    /// Upload the firmware `payload` and boot the device with it
+    ///
+    /// Returns once the device acknowledged the firmware. A device that
+    /// booted before is reset first, so a rejected update can be followed by
+    /// the image it replaced.
+    fn boot(&self, payload: &[u8]) -> Result<(), &'static str>;
+
+    /// Check that the booted device works, before its firmware is committed
+    fn confirm(&self) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /// Number of partitions commands can run on at the same time
+    fn partitions(&self) -> usize {
+        1
+    }
+
+    /// Move the whole device into `state`
+    fn set_power_state(&self, _state: PowerState) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /// Execute `cmd` on `partition`, blocking until the device finished it
+    ///
+    /// `buffers` looks up the buffers the command refers to.
+    fn execute(
+        &self,
+        partition: usize,
+        cmd: &Command,
+        buffers: &dyn Fn(u32) -> Option<NpuBuffer>,
+    ) -> CommandStatus;
+}
+
+/// Start executing the commands of `device` on `backend`
+pub fn start(device: Arc<NpuDevice>, backend: Arc<dyn Backend>) {
+    for partition in 0..backend.partitions() {
+        let device = device.clone();
+        let backend = backend.clone();
+        std::thread::spawn(move || run(&device, &*backend, partition));
+    }
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SAMPLE_PERIOD);
+        device.power().tick(&|state| backend.set_power_state(state));
+    });
+}
+
+fn run(device: &NpuDevice, backend: &dyn Backend, partition: usize) -> ! {
+    loop {
+        let Some(cmd) = device.next_command(EXECUTOR_POLL) else {
+            continue;
+        };
+
+        device
+            .power()
+            .begin(&|state| backend.set_power_state(state));
+        let start = Instant::now();
+        let status = device.with_buffers(&cmd, |buffers| backend.execute(partition, &cmd, buffers));
+        let elapsed = start.elapsed();
+        device.complete(cmd.id, status, elapsed);
+        device.power().end(elapsed);
+    }
+}
@@ -0,0 +1,131 @@
+//! Hardware contexts
+//!
+//! A hardware context is a partition of AIE columns the firmware runs
+//! compute units on. It is created through the management channel, which
+//! also hands out the context's own channel for execution requests, and is
+//! configured with the PDIs (partition images) of its compute units.
+
+use std::sync::Arc;
+
+use common::dma::Dma;
+
+use super::mailbox::{Channel, RingDesc};
+use super::regs::Bar;
+
+mod opcode {
+    /// Management channel
+    pub const CREATE_CONTEXT: u32 = 0x2;
+    pub const DESTROY_CONTEXT: u32 = 0x3;
+    pub const CONFIG_CU: u32 = 0x11;
+    /// Context channel
+    pub const EXECUTE_BUFFER: u32 = 0xc;
+}
+
+/// Kernel arguments of an execution request, in `u32`
+pub const MAX_ARGS: usize = 35;
+
+/// PDI of a compute unit, resident while the context uses it
+pub struct Kernel {
+    pdi: Dma<[u8]>,
+    /// Entry point of the compute unit in the PDI
+    function: u32,
+}
+
+// SAFETY: the image is only written before it is handed to the firmware
+unsafe impl Send for Kernel {}
+unsafe impl Sync for Kernel {}
+
+impl Kernel {
+    pub fn new(pdi: &[u8], function: u32) -> Result<Self, &'static str> {
+        let mut memory = unsafe {
+            Dma::<[u8]>::zeroed_slice(pdi.len())
+                .map_err(|_| "failed to allocate kernel image")?
+                .assume_init()
+        };
+        memory.copy_from_slice(pdi);
+        Ok(Self {
+            pdi: memory,
+            function,
+        })
+    }
+}
+
+pub struct HwContext {
+    id: u32,
+    channel: Channel,
+    /// Indexed by compute unit
+    _kernels: Vec<Kernel>,
+}
+
+impl HwContext {
+    /// Create a context spanning `columns` AIE columns from `start_column`
+    /// on, running `kernels` as its compute units
+    pub fn create(
+        mgmt: &Channel,
+        mailbox: Arc<Bar>,
+        start_column: u8,
+        columns: u8,
+        kernels: Vec<Kernel>,
+    ) -> Result<Self, &'static str> {
+        let mut request = Vec::new();
+        request.push(start_column);
+        request.push(columns);
+        // One queue pair, for execution requests
+        request.extend_from_slice(&[1, 0]);
+        let response = mgmt.call(opcode::CREATE_CONTEXT, &request)?;
+
+        let id = response
+            .get(..4)
+            .map(|id| u32::from_le_bytes(id.try_into().unwrap()))
+            .ok_or("truncated context")?;
+        let x2i = RingDesc::parse(&response[4..]).ok_or("truncated context")?;
+        let i2x = RingDesc::parse(&response[4 + RingDesc::SIZE..]).ok_or("truncated context")?;
+
+        let context = Self {
+            id,
+            channel: Channel::new(mailbox, x2i, i2x),
+            _kernels: Vec::new(),
+        };
+
+        let mut request = Vec::new();
+        request.extend_from_slice(&id.to_le_bytes());
+        request.extend_from_slice(&(kernels.len() as u32).to_le_bytes());
+        for kernel in &kernels {
+            let address = kernel.pdi.physical() as u64;
+            request.extend_from_slice(&address.to_le_bytes());
+            request.extend_from_slice(&(kernel.pdi.len() as u32).to_le_bytes());
+            request.extend_from_slice(&kernel.function.to_le_bytes());
+        }
+        if let Err(err) = mgmt.call(opcode::CONFIG_CU, &request) {
+            context.destroy(mgmt);
+            return Err(err);
+        }
+
+        Ok(Self {
+            _kernels: kernels,
+            ..context
+        })
+    }
+
+    /// Run compute unit `cu` with `args`, blocking until it finished
+    pub fn execute(&self, cu: u32, args: &[u32]) -> Result<(), &'static str> {
+        if args.len() > MAX_ARGS {
+            return Err("too many kernel arguments");
+        }
+        let mut request = Vec::with_capacity(4 + MAX_ARGS * 4);
+        request.extend_from_slice(&cu.to_le_bytes());
+        for arg in args {
+            request.extend_from_slice(&arg.to_le_bytes());
+        }
+        request.resize(4 + MAX_ARGS * 4, 0);
+        self.channel.call(opcode::EXECUTE_BUFFER, &request)?;
+        Ok(())
+    }
+
+    /// Release the context's columns
+    pub fn destroy(self, mgmt: &Channel) {
+        if let Err(err) = mgmt.call(opcode::DESTROY_CONTEXT, &self.id.to_le_bytes()) {
+            eprintln!("NPU: failed to destroy XDNA context {}: {}", self.id, err);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Mailbox channels
+//!
+//! The firmware is talked to through channels of two rings in the mailbox
+//! aperture: the host writes requests to the x2i ring and the firmware
+//! answers on the i2x ring. The writer of a ring owns its tail register, the
+//! reader its head register. A message that doesn't fit before the end of a
+//! ring is preceded by a tombstone telling the reader to wrap around.
+//!
+//! Messages start with a 16 byte header:
+//!
+//! | offset | size | field                        |
+//! |--------|------|------------------------------|
+//! | 0      | 4    | size, including the header   |
+//! | 4      | 4    | protocol version             |
+//! | 8      | 4    | id, echoed in the response   |
+//! | 12     | 4    | opcode                       |
+//!
+//! A response's payload starts with a status, 0 on success.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::regs::{self, Bar};
+
+const HEADER_SIZE: usize = 16;
+const PROTOCOL_VERSION: u32 = 1;
+/// Marks the rest of a ring as unused
+const TOMBSTONE: u32 = 0xdead_face;
+
+/// How long the firmware gets to answer a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Location of a ring in the mailbox aperture
+///
+/// Encoded as four `u32`: tail register, head register, buffer and buffer
+/// size.
+#[derive(Debug, Clone, Copy)]
+pub struct RingDesc {
+    tail: usize,
+    head: usize,
+    buf: usize,
+    size: usize,
+}
+
+impl RingDesc {
+    pub const SIZE: usize = 16;
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let word = |i: usize| {
+            bytes
+                .get(i * 4..i * 4 + 4)
+                .map(|word| u32::from_le_bytes(word.try_into().unwrap()) as usize)
+        };
+        Some(Self {
+            tail: word(0)?,
+            head: word(1)?,
+            buf: word(2)?,
+            size: word(3)?,
+        })
+    }
+}
+
+struct Rings {
+    x2i: RingDesc,
+    i2x: RingDesc,
+    next_id: u32,
+}
+
+/// Request/response channel to the firmware
+pub struct Channel {
+    bar: Arc<Bar>,
+    rings: Mutex<Rings>,
+}
+
+impl Channel {
+    pub fn new(bar: Arc<Bar>, x2i: RingDesc, i2x: RingDesc) -> Self {
+        Self {
+            bar,
+            rings: Mutex::new(Rings {
+                x2i,
+                i2x,
+                next_id: 1,
+            }),
+        }
+    }
+
+    /// Send a request and wait for its response, returning the response's
+    /// payload after the status
+    pub fn call(&self, opcode: u32, payload: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let mut rings = self.rings.lock().unwrap();
+        let id = rings.next_id;
+        rings.next_id = rings.next_id.wrapping_add(1);
+
+        let size = (HEADER_SIZE + payload.len()).next_multiple_of(4);
+        let mut message = Vec::with_capacity(size);
+        message.extend_from_slice(&(size as u32).to_le_bytes());
+        message.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        message.extend_from_slice(&id.to_le_bytes());
+        message.extend_from_slice(&opcode.to_le_bytes());
+        message.extend_from_slice(payload);
+        message.resize(size, 0);
+        self.push(&rings.x2i, &message)?;
+
+        let mut response = None;
+        if !regs::poll(RESPONSE_TIMEOUT, || {
+            while let Some(message) = self.pop(&rings.i2x) {
+                if message.get(8..12) == Some(&id.to_le_bytes()[..]) {
+                    response = Some(message);
+                    return true;
+                }
+                // Answer to a request that timed out earlier
+            }
+            false
+        }) {
+            return Err("firmware did not respond");
+        }
+
+        let response = response.unwrap();
+        let status = response
+            .get(HEADER_SIZE..HEADER_SIZE + 4)
+            .ok_or("truncated firmware response")?;
+        if status != [0; 4] {
+            return Err("firmware failed the request");
+        }
+        Ok(response[HEADER_SIZE + 4..].to_vec())
+    }
+
+    fn push(&self, ring: &RingDesc, message: &[u8]) -> Result<(), &'static str> {
+        let head = self.bar.read32(ring.head) as usize;
+        let tail = self.bar.read32(ring.tail) as usize;
+
+        // Keep a word free so a full ring isn't mistaken for an empty one
+        let start = if tail >= head {
+            if tail + message.len() < ring.size || (tail + message.len() == ring.size && head > 0) {
+                tail
+            } else if message.len() < head {
+                if tail + 4 <= ring.size {
+                    self.bar.write32(ring.buf + tail, TOMBSTONE);
+                }
+                0
+            } else {
+                return Err("mailbox full");
+            }
+        } else if tail + message.len() < head {
+            tail
+        } else {
+            return Err("mailbox full");
+        };
+
+        self.bar.write_bytes(ring.buf + start, message);
+        let tail = (start + message.len()) % ring.size;
+        self.bar.write32(ring.tail, tail as u32);
+        Ok(())
+    }
+
+    fn pop(&self, ring: &RingDesc) -> Option<Vec<u8>> {
+        let mut head = self.bar.read32(ring.head) as usize;
+        let tail = self.bar.read32(ring.tail) as usize;
+        if head == tail {
+            return None;
+        }
+        if head + 4 > ring.size || self.bar.read32(ring.buf + head) == TOMBSTONE {
+            head = 0;
+            if head == tail {
+                self.bar.write32(ring.head, 0);
+                return None;
+            }
+        }
+
+        let size = self.bar.read32(ring.buf + head) as usize;
+        if size < HEADER_SIZE || !size.is_multiple_of(4) || head + size > ring.size {
+            // Corrupted, drop everything the firmware wrote
+            self.bar.write32(ring.head, tail as u32);
+            return None;
+        }
+        let mut message = vec![0; size];
+        self.bar.read_bytes(ring.buf + head, &mut message);
+        self.bar
+            .write32(ring.head, ((head + size) % ring.size) as u32);
+        Some(message)
+    }
+}
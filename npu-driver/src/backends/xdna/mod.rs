@@ -0,0 +1,351 @@
+//! AMD XDNA (Ryzen AI)
+//!
+//! The NPU is an array of AIE tiles behind a firmware-managed microcontroller.
+//! The PSP boots the firmware, which then serves a management channel for
+//! creating hardware contexts. The backend splits the array into
+//! partitions of four columns and keeps a context on each, with GEMM and
+//! conv2d compute units built from the PDIs in the firmware directory's
+//! `kernels/`. Commands run on the partitions independently.
+//!
+//! Buffers are passed to the compute units by physical address, Redox
+//! doesn't put the NPU behind an IOMMU.
+
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use pcid_interface::PciFunctionHandle;
+use syscall::{EINVAL, EIO, EOPNOTSUPP};
+
+use super::Backend;
+use crate::command::{Command, CommandStatus, CommandType};
+use crate::firmware::FIRMWARE_ROOT;
+use crate::memory::NpuBuffer;
+use crate::power::PowerState;
+use crate::tensor::DataType;
+use crate::{NpuCapabilities, NpuType};
+
+mod hwctx;
+mod mailbox;
+mod psp;
+mod regs;
+
+use hwctx::{HwContext, Kernel};
+use mailbox::{Channel, RingDesc};
+use regs::Bar;
+
+pub const VENDOR_ID: u16 = 0x1022;
+
+/// Magic of the management channel description
+const MGMT_MAGIC: u32 = 0x5550_4e5f;
+
+/// Requests on the management channel, contexts are kept across a suspend
+mod mgmt_opcode {
+    pub const SUSPEND: u32 = 0x101;
+    pub const RESUME: u32 = 0x102;
+    /// Have the firmware exercise the AIE array
+    pub const SELF_TEST: u32 = 0x104;
+}
+
+/// Compute units of the context, in the order their kernels are configured
+mod cu {
+    pub const GEMM: u32 = 0;
+    pub const CONV2D: u32 = 1;
+}
+
+/// Kernels, file name and entry point, indexed by compute unit
+const KERNELS: [(&str, u32); 2] = [("gemm.pdi", 0), ("conv2d.pdi", 0)];
+
+/// NPU generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Generation {
+    /// XDNA, Phoenix and Hawk Point
+    Npu1,
+    /// XDNA 2, Strix and Krackan
+    Npu4,
+}
+
+impl Generation {
+    fn from_device_id(device_id: u16) -> Option<Self> {
+        match device_id {
+            0x1502 => Some(Self::Npu1),
+            0x17f0 => Some(Self::Npu4),
+            _ => None,
+        }
+    }
+
+    /// Partitions of the AIE columns available to applications, first
+    /// column and count each
+    fn partitions(self) -> &'static [(u8, u8)] {
+        match self {
+            // Column 0 is reserved for the shim on the first generation
+            Self::Npu1 => &[(1, 4)],
+            Self::Npu4 => &[(0, 4), (4, 4)],
+        }
+    }
+}
+
+/// Firmware state, set up once the device booted
+struct Booted {
+    mgmt: Channel,
+    /// Indexed by partition
+    contexts: Vec<HwContext>,
+}
+
+impl Booted {
+    fn destroy(self) {
+        for context in self.contexts {
+            context.destroy(&self.mgmt);
+        }
+    }
+}
+
+pub struct Xdna {
+    generation: Generation,
+    mpnpu: Bar,
+    mailbox: Arc<Bar>,
+    /// Replaced when the device is booted again
+    booted: RwLock<Option<Booted>>,
+}
+
+impl Xdna {
+    /// Map the device behind `pcid`, failing if it isn't an XDNA NPU
+    pub fn probe(pcid: &mut PciFunctionHandle) -> Result<Self, &'static str> {
+        let id = pcid.config().func.full_device_id;
+        if id.vendor_id != VENDOR_ID {
+            return Err("not an AMD device");
+        }
+        let generation =
+            Generation::from_device_id(id.device_id).ok_or("unsupported XDNA device")?;
+
+        // SAFETY: BARs stay mapped as long as the process runs
+        let mpnpu = unsafe { Bar::new(pcid.map_bar(regs::MPNPU_BAR)) };
+        let mailbox = unsafe { Bar::new(pcid.map_bar(regs::MAILBOX_BAR)) };
+
+        Ok(Self {
+            generation,
+            mpnpu,
+            mailbox: Arc::new(mailbox),
+            booted: RwLock::new(None),
+        })
+    }
+
+    pub fn capabilities(&self) -> NpuCapabilities {
+        let columns: u32 = self
+            .generation
+            .partitions()
+            .iter()
+            .map(|&(_, columns)| columns as u32)
+            .sum();
+        let (name, frequency_mhz, peak_tops) = match self.generation {
+            Generation::Npu1 => ("AMD XDNA", 1000, 10.0),
+            Generation::Npu4 => ("AMD XDNA 2", 1800, 50.0),
+        };
+        NpuCapabilities {
+            device_type: NpuType::AmdXdna,
+            device_name: name.to_string(),
+            // Shares system memory
+            memory_bytes: 0,
+            // Four compute tiles per column
+            compute_units: columns * 4,
+            data_types: vec![
+                DataType::Int8,
+                DataType::UInt8,
+                DataType::Int16,
+                DataType::BFloat16,
+            ],
+            max_dimensions: 4,
+            max_batch_size: 1,
+            async_execution: true,
+            zero_copy: true,
+            frequency_mhz,
+            peak_tops,
+        }
+    }
+
+    /// Wait for the firmware to publish the management channel
+    fn mgmt_channel(&self) -> Result<Channel, &'static str> {
+        let mut info = 0;
+        if !regs::poll(std::time::Duration::from_secs(1), || {
+            info = self.mpnpu.read32(regs::FW_ALIVE) as usize;
+            info != 0
+        }) {
+            return Err("firmware did not come up");
+        }
+
+        // x2i and i2x rings, then magic
+        let mut bytes = [0; 2 * RingDesc::SIZE + 4];
+        self.mailbox.read_bytes(info, &mut bytes);
+        let magic = u32::from_le_bytes(bytes[2 * RingDesc::SIZE..].try_into().unwrap());
+        if magic != MGMT_MAGIC {
+            return Err("bad management channel magic");
+        }
+        let x2i = RingDesc::parse(&bytes).unwrap();
+        let i2x = RingDesc::parse(&bytes[RingDesc::SIZE..]).unwrap();
+        Ok(Channel::new(self.mailbox.clone(), x2i, i2x))
+    }
+
+    fn kernels() -> Result<Vec<Kernel>, &'static str> {
+        let dir = Path::new(FIRMWARE_ROOT)
+            .join(NpuType::AmdXdna.firmware_name().unwrap())
+            .join("kernels");
+        KERNELS
+            .iter()
+            .map(|&(file, function)| {
+                let pdi = std::fs::read(dir.join(file)).map_err(|_| "missing XDNA kernel")?;
+                Kernel::new(&pdi, function)
+            })
+            .collect()
+    }
+
+    fn gemm(
+        context: &HwContext,
+        [a, b, c]: [&NpuBuffer; 3],
+        m: u32,
+        n: u32,
+        k: u32,
+    ) -> CommandStatus {
+        // The kernel multiplies BF16 matrices, row major
+        let element = DataType::BFloat16.size() as u64;
+        let fits = |buffer: &NpuBuffer, rows: u32, cols: u32| {
+            buffer.size as u64 >= rows as u64 * cols as u64 * element
+        };
+        if !fits(a, m, k) || !fits(b, k, n) || !fits(c, m, n) {
+            return CommandStatus::Failed(EINVAL as u32);
+        }
+
+        let mut args = Vec::new();
+        for buffer in [a, b, c] {
+            args.extend_from_slice(&split(buffer.device_addr));
+        }
+        args.extend_from_slice(&[m, n, k]);
+        Self::status(context.execute(cu::GEMM, &args))
+    }
+
+    fn conv2d(context: &HwContext, buffers: [&NpuBuffer; 3]) -> CommandStatus {
+        // The kernel takes its shape from the start of the kernel buffer, so
+        // only the extents are passed along
+        let mut args = Vec::new();
+        for buffer in buffers {
+            args.extend_from_slice(&split(buffer.device_addr));
+            args.push(buffer.size as u32);
+        }
+        Self::status(context.execute(cu::CONV2D, &args))
+    }
+
+    fn status(result: Result<(), &'static str>) -> CommandStatus {
+        match result {
+            Ok(()) => CommandStatus::Completed,
+            Err(err) => {
+                eprintln!("NPU: XDNA execution failed: {}", err);
+                CommandStatus::Failed(EIO as u32)
+            }
+        }
+    }
+}
+
+/// Low and high half of a device address
+fn split(address: u64) -> [u32; 2] {
+    [address as u32, (address >> 32) as u32]
+}
+
+impl Backend for Xdna {
+    fn boot(&self, payload: &[u8]) -> Result<(), &'static str> {
+        let mut booted = self.booted.write().unwrap();
+        if let Some(previous) = booted.take() {
+            previous.destroy();
+        }
+        // Don't mistake the channel of the old firmware for the new one's
+        self.mpnpu.write32(regs::FW_ALIVE, 0);
+
+        psp::load(&self.mpnpu, payload)?;
+        let mgmt = self.mgmt_channel()?;
+
+        let mut contexts = Vec::new();
+        for &(start, columns) in self.generation.partitions() {
+            let context = Self::kernels().and_then(|kernels| {
+                HwContext::create(&mgmt, self.mailbox.clone(), start, columns, kernels)
+            });
+            match context {
+                Ok(context) => contexts.push(context),
+                Err(err) => {
+                    for context in contexts {
+                        context.destroy(&mgmt);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        *booted = Some(Booted { mgmt, contexts });
+        Ok(())
+    }
+
+    fn confirm(&self) -> Result<(), &'static str> {
+        let booted = self.booted.read().unwrap();
+        let booted = booted.as_ref().ok_or("XDNA device not booted")?;
+        booted.mgmt.call(mgmt_opcode::SELF_TEST, &[])?;
+        Ok(())
+    }
+
+    fn partitions(&self) -> usize {
+        self.generation.partitions().len()
+    }
+
+    fn set_power_state(&self, state: PowerState) -> Result<(), &'static str> {
+        let booted = self.booted.read().unwrap();
+        let booted = booted.as_ref().ok_or("XDNA device not booted")?;
+        let opcode = match state {
+            PowerState::Active => mgmt_opcode::RESUME,
+            PowerState::Suspended => mgmt_opcode::SUSPEND,
+        };
+        booted.mgmt.call(opcode, &[])?;
+        Ok(())
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        cmd: &Command,
+        buffers: &dyn Fn(u32) -> Option<NpuBuffer>,
+    ) -> CommandStatus {
+        let booted = self.booted.read().unwrap();
+        let Some(context) = booted
+            .as_ref()
+            .and_then(|booted| booted.contexts.get(partition))
+        else {
+            return CommandStatus::Failed(EIO as u32);
+        };
+        let lookup = |handles: [u32; 3]| -> Option<[NpuBuffer; 3]> {
+            Some([
+                buffers(handles[0])?,
+                buffers(handles[1])?,
+                buffers(handles[2])?,
+            ])
+        };
+
+        match cmd.cmd_type {
+            CommandType::MatMul { a, b, c, m, n, k } => match lookup([a, b, c]) {
+                Some([a, b, c]) => Self::gemm(context, [&a, &b, &c], m, n, k),
+                None => CommandStatus::Failed(EINVAL as u32),
+            },
+            CommandType::Conv2d {
+                input,
+                kernel,
+                output,
+            } => match lookup([input, kernel, output]) {
+                Some([input, kernel, output]) => Self::conv2d(context, [&input, &kernel, &output]),
+                None => CommandStatus::Failed(EINVAL as u32),
+            },
+            // Commands run in order, everything before already finished
+            CommandType::Barrier => CommandStatus::Completed,
+            _ => CommandStatus::Failed(EOPNOTSUPP as u32),
+        }
+    }
+}
+
+impl Drop for Xdna {
+    fn drop(&mut self) {
+        if let Some(booted) = self.booted.get_mut().unwrap().take() {
+            booted.destroy();
+        }
+    }
+}
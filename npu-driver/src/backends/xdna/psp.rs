@@ -0,0 +1,63 @@
+//! Firmware loading through the PSP
+//!
+//! The NPU doesn't boot firmware from the host directly. The PSP validates
+//! the image in host memory, copies it into the NPU and starts it. A PSP
+//! command is issued by writing its arguments and opcode, then ringing the
+//! doorbell; the PSP sets the ready bit of the status register when it is
+//! done and leaves a response, 0 on success.
+
+use std::time::Duration;
+
+use common::dma::Dma;
+
+use super::regs::{self, Bar};
+
+/// Validate the image at arguments 0 (low) and 1 (high), of argument 2 bytes
+const VALIDATE: u32 = 1;
+/// Start the NPU with the validated image
+const START: u32 = 2;
+/// Argument of [`START`]: copy the image into the NPU first
+const START_COPY_FW: u32 = 1;
+
+/// The PSP only validates images at 1 MiB aligned addresses
+const FW_ALIGN: usize = 1 << 20;
+
+const PSP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Have the PSP validate and start `payload`
+pub fn load(mpnpu: &Bar, payload: &[u8]) -> Result<(), &'static str> {
+    // The image is read by DMA, it has to stay around until the NPU started
+    let mut image = unsafe {
+        Dma::<[u8]>::zeroed_slice(payload.len() + FW_ALIGN)
+            .map_err(|_| "failed to allocate firmware image")?
+            .assume_init()
+    };
+    let start = image.physical().next_multiple_of(FW_ALIGN) - image.physical();
+    image[start..start + payload.len()].copy_from_slice(payload);
+    let address = (image.physical() + start) as u64;
+
+    exec(
+        mpnpu,
+        VALIDATE,
+        [address as u32, (address >> 32) as u32, payload.len() as u32],
+    )?;
+    exec(mpnpu, START, [START_COPY_FW, 0, 0])
+}
+
+fn exec(mpnpu: &Bar, cmd: u32, args: [u32; 3]) -> Result<(), &'static str> {
+    mpnpu.write32(regs::PSP_ARG0, args[0]);
+    mpnpu.write32(regs::PSP_ARG1, args[1]);
+    mpnpu.write32(regs::PSP_ARG2, args[2]);
+    mpnpu.write32(regs::PSP_CMD, cmd);
+    mpnpu.write32(regs::PSP_INTR, 1);
+
+    if !regs::poll(PSP_TIMEOUT, || {
+        mpnpu.read32(regs::PSP_STATUS) & regs::PSP_STATUS_READY != 0
+    }) {
+        return Err("PSP timed out");
+    }
+    if mpnpu.read32(regs::PSP_RESP) != 0 {
+        return Err("PSP rejected the firmware");
+    }
+    Ok(())
+}
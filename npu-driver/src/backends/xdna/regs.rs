@@ -0,0 +1,104 @@
+//! Register apertures
+//!
+//! BAR 0 holds the MPNPU public registers, through which the PSP and the
+//! firmware's liveness are reached. BAR 4 is the mailbox aperture holding
+//! the message rings and their head and tail registers.
+
+use std::ptr::NonNull;
+use std::time::{Duration, Instant};
+
+use pcid_interface::MappedBar;
+
+/// BAR of the MPNPU registers
+pub const MPNPU_BAR: u8 = 0;
+/// BAR of the mailbox aperture
+pub const MAILBOX_BAR: u8 = 4;
+
+/// PSP mailbox, scratch registers shared with the PSP
+pub const PSP_CMD: usize = 0x100a0;
+/// Reads back from the command register once the PSP picked it up
+pub const PSP_STATUS: usize = PSP_CMD;
+pub const PSP_ARG0: usize = 0x100a4;
+/// Reads back from the first argument once the command finished
+pub const PSP_RESP: usize = PSP_ARG0;
+pub const PSP_ARG1: usize = 0x100a8;
+pub const PSP_ARG2: usize = 0x100bc;
+/// Doorbell of the PSP
+pub const PSP_INTR: usize = 0x10090;
+pub const PSP_STATUS_READY: u32 = 1 << 31;
+
+/// Mailbox aperture offset of the management channel description, 0 until
+/// the firmware is up
+pub const FW_ALIVE: usize = 0x100b4;
+
+/// Mapped register aperture
+pub struct Bar {
+    ptr: NonNull<u8>,
+    size: usize,
+}
+
+// SAFETY: the mapping stays valid for the lifetime of the process, accesses
+// are volatile
+unsafe impl Send for Bar {}
+unsafe impl Sync for Bar {}
+
+impl Bar {
+    /// # Safety
+    ///
+    /// `bar` must stay mapped for the lifetime of the returned aperture.
+    pub unsafe fn new(bar: &MappedBar) -> Self {
+        Self {
+            ptr: bar.ptr,
+            size: bar.bar_size,
+        }
+    }
+
+    pub fn read32(&self, offset: usize) -> u32 {
+        assert!(offset.is_multiple_of(4) && offset + 4 <= self.size);
+        // SAFETY: aligned and within the aperture
+        unsafe { self.ptr.as_ptr().add(offset).cast::<u32>().read_volatile() }
+    }
+
+    pub fn write32(&self, offset: usize, value: u32) {
+        assert!(offset.is_multiple_of(4) && offset + 4 <= self.size);
+        // SAFETY: aligned and within the aperture
+        unsafe {
+            self.ptr
+                .as_ptr()
+                .add(offset)
+                .cast::<u32>()
+                .write_volatile(value)
+        }
+    }
+
+    /// Copy `buf.len()` bytes from `offset` out, a multiple of 4
+    pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) {
+        for (i, chunk) in buf.chunks_exact_mut(4).enumerate() {
+            chunk.copy_from_slice(&self.read32(offset + i * 4).to_le_bytes());
+        }
+    }
+
+    /// Copy `buf` to `offset`, a multiple of 4 bytes long
+    pub fn write_bytes(&self, offset: usize, buf: &[u8]) {
+        for (i, chunk) in buf.chunks_exact(4).enumerate() {
+            self.write32(
+                offset + i * 4,
+                u32::from_le_bytes(chunk.try_into().unwrap()),
+            );
+        }
+    }
+}
+
+/// Poll `done` until it holds, false if it didn't within `timeout`
+pub fn poll(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    loop {
+        if done() {
+            return true;
+        }
+        if start.elapsed() > timeout {
+            return false;
+        }
+        std::thread::sleep(Duration::from_micros(50));
+    }
+}
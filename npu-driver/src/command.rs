@@ -5,6 +5,9 @@
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::memory::Client;
 
 /// Command types
 #[derive(Debug, Clone)]
@@ -40,6 +43,42 @@ pub enum CommandType {
     Barrier,
 }
 
+impl CommandType {
+    /// Buffers the command reads and writes
+    pub fn buffers(&self) -> (Vec<u32>, Vec<u32>) {
+        match *self {
+            CommandType::CopyToDevice { dst, .. } => (vec![], vec![dst]),
+            CommandType::CopyFromDevice { src, .. } => (vec![src], vec![]),
+            CommandType::Inference { input, output, .. } => (vec![input], vec![output]),
+            CommandType::MatMul { a, b, c, .. } => (vec![a, b], vec![c]),
+            CommandType::Conv2d {
+                input,
+                kernel,
+                output,
+            } => (vec![input, kernel], vec![output]),
+            CommandType::Activation { buffer, .. } => (vec![buffer], vec![buffer]),
+            CommandType::Barrier => (vec![], vec![]),
+        }
+    }
+
+    /// Check if the command has to stay ordered with `other`
+    ///
+    /// Commands conflict if one writes a buffer the other uses, so a buffer
+    /// can be reused for a later output once everything reading it ran.
+    /// Barriers conflict with everything.
+    fn conflicts(&self, other: &CommandType) -> bool {
+        if matches!(self, CommandType::Barrier) || matches!(other, CommandType::Barrier) {
+            return true;
+        }
+        let (reads, writes) = self.buffers();
+        let (other_reads, other_writes) = other.buffers();
+        writes
+            .iter()
+            .any(|b| other_reads.contains(b) || other_writes.contains(b))
+            || reads.iter().any(|b| other_writes.contains(b))
+    }
+}
+
 /// Activation functions
 #[derive(Debug, Clone, Copy)]
 pub enum ActivationFunc {
@@ -67,6 +106,10 @@ pub struct Command {
     pub cmd_type: CommandType,
     pub priority: u32,
     pub status: CommandStatus,
+    /// Commands that have to complete first
+    pub deps: Vec<u64>,
+    /// Client that submitted the command, it may only use its own buffers
+    pub client: Option<Client>,
 }
 
 impl Command {
@@ -76,6 +119,8 @@ impl Command {
             cmd_type,
             priority: 0,
             status: CommandStatus::Pending,
+            deps: Vec::new(),
+            client: None,
         }
     }
 
@@ -83,9 +128,62 @@ impl Command {
         self.priority = priority;
         self
     }
+
+    /// Submit on behalf of `client`
+    pub fn for_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Run after the commands `deps`
+    pub fn after(mut self, deps: &[u64]) -> Self {
+        self.deps.extend_from_slice(deps);
+        self
+    }
+}
+
+/// Commands submitted at once, such as the layers of a model
+///
+/// Nodes can only depend on nodes added before them, so a graph never has
+/// cycles.
+#[derive(Debug, Default)]
+pub struct CommandGraph {
+    nodes: Vec<(Command, Vec<usize>)>,
+}
+
+impl CommandGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `cmd` to run after the nodes `deps`, returning its node
+    pub fn add(&mut self, cmd: Command, deps: &[usize]) -> usize {
+        let node = self.nodes.len();
+        assert!(
+            deps.iter().all(|&dep| dep < node),
+            "dependency on a later node"
+        );
+        self.nodes.push((cmd, deps.to_vec()));
+        node
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
 }
 
 /// Command queue for async execution
+///
+/// Besides their explicit dependencies, commands wait for earlier commands
+/// they conflict with, see [`CommandType::conflicts`]. Commands without
+/// anything to wait for run in priority order, so several executors can
+/// work through independent commands at once.
+///
+/// Locks are taken in the order `pending`, `in_flight`, `completed`.
 pub struct CommandQueue {
     /// Pending commands
     pending: Mutex<VecDeque<Command>>,
@@ -95,10 +193,14 @@ pub struct CommandQueue {
     completed: RwLock<Vec<(u64, CommandStatus)>>,
     /// Next command ID
     next_id: AtomicU64,
+    /// Number of commands completed so far
+    completions: AtomicU64,
     /// Queue capacity
     capacity: usize,
     /// Condition variable for waiters
     completion: Condvar,
+    /// Signalled when a command may have become ready
+    submission: Condvar,
 }
 
 impl CommandQueue {
@@ -108,45 +210,111 @@ impl CommandQueue {
             in_flight: RwLock::new(Vec::new()),
             completed: RwLock::new(Vec::new()),
             next_id: AtomicU64::new(1),
+            completions: AtomicU64::new(0),
             capacity,
             completion: Condvar::new(),
+            submission: Condvar::new(),
         }
     }
 
     /// Submit a command for execution
-    pub fn submit(&self, mut cmd: Command) -> u64 {
-        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
-        cmd.id = id;
-        cmd.status = CommandStatus::Pending;
+    pub fn submit(&self, cmd: Command) -> u64 {
+        let mut graph = CommandGraph::new();
+        graph.add(cmd, &[]);
+        self.submit_graph(graph)[0]
+    }
 
+    /// Submit the commands of `graph` at once, returning their IDs by node
+    pub fn submit_graph(&self, graph: CommandGraph) -> Vec<u64> {
         let mut pending = self.pending.lock().unwrap();
+        let in_flight = self.in_flight.read().unwrap();
 
-        // Insert sorted by priority (higher priority first)
-        let pos = pending
-            .iter()
-            .position(|c| c.priority < cmd.priority)
-            .unwrap_or(pending.len());
-        pending.insert(pos, cmd);
+        let mut ids: Vec<u64> = Vec::with_capacity(graph.len());
+        for (mut cmd, deps) in graph.nodes {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            cmd.id = id;
+            cmd.status = CommandStatus::Pending;
+            cmd.deps.extend(deps.iter().map(|&node| ids[node]));
+            // Earlier nodes of the graph are pending by now
+            for other in pending.iter().chain(in_flight.iter()) {
+                if cmd.cmd_type.conflicts(&other.cmd_type) && !cmd.deps.contains(&other.id) {
+                    cmd.deps.push(other.id);
+                }
+            }
 
-        id
+            // Insert sorted by priority (higher priority first)
+            let pos = pending
+                .iter()
+                .position(|c| c.priority < cmd.priority)
+                .unwrap_or(pending.len());
+            pending.insert(pos, cmd);
+            ids.push(id);
+        }
+        self.submission.notify_all();
+
+        ids
     }
 
-    /// Get the next pending command
+    /// Get the next command ready to run
     pub fn dequeue(&self) -> Option<Command> {
         let mut pending = self.pending.lock().unwrap();
-        pending.pop_front().map(|mut cmd| {
+        self.take_ready(&mut pending)
+    }
+
+    /// Get the next command ready to run, waiting up to `timeout` for one
+    pub fn dequeue_timeout(&self, timeout: Duration) -> Option<Command> {
+        let deadline = Instant::now() + timeout;
+        let mut pending = self.pending.lock().unwrap();
+        loop {
+            if let Some(cmd) = self.take_ready(&mut pending) {
+                return Some(cmd);
+            }
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            pending = self.submission.wait_timeout(pending, remaining).unwrap().0;
+        }
+    }
+
+    /// Move the first ready command to in-flight, failing commands whose
+    /// dependencies failed on the way
+    fn take_ready(&self, pending: &mut VecDeque<Command>) -> Option<Command> {
+        loop {
+            let mut failed = None;
+            let ready = pending.iter().position(|cmd| {
+                failed = None;
+                let mut ready = true;
+                for &dep in &cmd.deps {
+                    match self.status_locked(pending, dep) {
+                        Some(CommandStatus::Pending | CommandStatus::Running) => ready = false,
+                        Some(CommandStatus::Failed(code)) => failed = Some(code),
+                        _ => (),
+                    }
+                }
+                ready
+            })?;
+
+            let mut cmd = pending.remove(ready).unwrap();
+            if let Some(code) = failed {
+                // Never runs, its input is missing
+                self.finish(cmd.id, CommandStatus::Failed(code));
+                continue;
+            }
             cmd.status = CommandStatus::Running;
             self.in_flight.write().unwrap().push(cmd.clone());
-            cmd
-        })
+            return Some(cmd);
+        }
     }
 
     /// Mark a command as completed
     pub fn complete(&self, id: u64, status: CommandStatus) {
-        // Remove from in-flight
+        // Waiters are notified under the lock they wait with, so none misses it
+        let _pending = self.pending.lock().unwrap();
         self.in_flight.write().unwrap().retain(|c| c.id != id);
+        self.finish(id, status);
+    }
 
-        // Add to completed
+    /// Record the status of a command that left the queue, with `pending`
+    /// locked
+    fn finish(&self, id: u64, status: CommandStatus) {
         let mut completed = self.completed.write().unwrap();
         completed.push((id, status));
 
@@ -155,41 +323,61 @@ impl CommandQueue {
             completed.drain(0..500);
         }
 
-        // Notify waiters
+        drop(completed);
+        self.completions.fetch_add(1, Ordering::Release);
+
         self.completion.notify_all();
+        // Dependents may be ready now
+        self.submission.notify_all();
+    }
+
+    /// Wait until more than `seen` commands completed, or `timeout` passed
+    ///
+    /// Returns the number of commands completed so far, to pass as `seen`
+    /// next time.
+    pub fn wait_completion(&self, seen: u64, timeout: Duration) -> u64 {
+        let pending = self.pending.lock().unwrap();
+        let _guard = self
+            .completion
+            .wait_timeout_while(pending, timeout, |_| {
+                self.completions.load(Ordering::Acquire) == seen
+            })
+            .unwrap();
+        self.completions.load(Ordering::Acquire)
     }
 
     /// Wait for a command to complete
     pub fn wait(&self, id: u64) -> CommandStatus {
-        // Check if already completed
-        if let Some(status) = self.get_status(id) {
-            if status != CommandStatus::Pending && status != CommandStatus::Running {
-                return status;
-            }
-        }
-
-        // Wait for completion
         let pending = self.pending.lock().unwrap();
-        let _guard = self
+        let pending = self
             .completion
-            .wait_while(pending, |_| {
-                self.get_status(id)
-                    .map(|s| s == CommandStatus::Pending || s == CommandStatus::Running)
-                    .unwrap_or(false)
+            .wait_while(pending, |pending| {
+                matches!(
+                    self.status_locked(pending, id),
+                    Some(CommandStatus::Pending | CommandStatus::Running)
+                )
             })
             .unwrap();
 
-        self.get_status(id).unwrap_or(CommandStatus::Failed(1))
+        self.status_locked(&pending, id)
+            .unwrap_or(CommandStatus::Failed(1))
     }
 
     /// Get command status
     pub fn get_status(&self, id: u64) -> Option<CommandStatus> {
+        let pending = self.pending.lock().unwrap();
+        self.status_locked(&pending, id)
+    }
+
+    /// Get command status, with `pending` locked
+    fn status_locked(&self, pending: &VecDeque<Command>, id: u64) -> Option<CommandStatus> {
         // Check completed
         if let Some((_, status)) = self
             .completed
             .read()
             .unwrap()
             .iter()
+            .rev()
             .find(|(i, _)| *i == id)
         {
             return Some(*status);
@@ -201,7 +389,7 @@ impl CommandQueue {
         }
 
         // Check pending
-        if self.pending.lock().unwrap().iter().any(|c| c.id == id) {
+        if pending.iter().any(|c| c.id == id) {
             return Some(CommandStatus::Pending);
         }
 
@@ -0,0 +1,533 @@
+//! NPU Firmware Management
+//!
+//! Firmware blobs live in one directory per backend below the firmware root:
+//!
+//! ```text
+//! /usr/lib/firmware/npu/<backend>/
+//!     firmware.bin   currently active image
+//!     staged.bin     update installed by the updater, activated on next load
+//!     previous.bin   image that was active before the last update
+//!     pending        present while an activated update hasn't been confirmed
+//! ```
+//!
+//! Updates are staged by writing the image to `npu:firmware/<backend>`. A
+//! staged image only becomes permanent once the backend confirmed that the
+//! device works with it through [`FirmwareManager::commit`]. If the device
+//! rejects it, or the driver finds an unconfirmed update on the next load,
+//! the previous image is restored. The first image installed has no previous
+//! one, so a failed update then leaves it active and drops only the pending
+//! marker.
+//!
+//! Images are signed with Ed25519 over header and payload. The public key of
+//! each backend is configured outside the firmware root, as 64 hex digits in
+//! `/etc/npu/firmware-keys/<backend>.pub`; a backend without a key refuses
+//! every image.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::NpuType;
+
+/// Default firmware root
+pub const FIRMWARE_ROOT: &str = "/usr/lib/firmware/npu";
+/// Default directory of the signing keys
+pub const FIRMWARE_KEYS: &str = "/etc/npu/firmware-keys";
+
+const ACTIVE: &str = "firmware.bin";
+const STAGED: &str = "staged.bin";
+const PREVIOUS: &str = "previous.bin";
+const PENDING: &str = "pending";
+
+const MAGIC: [u8; 4] = *b"NPFW";
+const HEADER_LEN: usize = 32;
+
+/// Firmware version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Header at the start of every firmware image
+///
+/// All fields are little endian:
+///
+/// | offset | size | field                                   |
+/// |--------|------|-----------------------------------------|
+/// | 0      | 4    | magic `NPFW`                            |
+/// | 4      | 2    | major version                           |
+/// | 6      | 2    | minor version                           |
+/// | 8      | 2    | patch version                           |
+/// | 10     | 2    | reserved                                |
+/// | 12     | 4    | host interface (ABI) version            |
+/// | 16     | 4    | payload length                          |
+/// | 20     | 4    | CRC-32 of the payload                   |
+/// | 24     | 4    | signature length, follows the payload   |
+/// | 28     | 4    | reserved                                |
+///
+/// The signature covers the header and the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FirmwareHeader {
+    pub version: FirmwareVersion,
+    pub abi: u32,
+    pub payload_len: u32,
+    pub payload_crc: u32,
+    pub signature_len: u32,
+}
+
+impl FirmwareHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self, FirmwareError> {
+        if bytes.len() < HEADER_LEN || bytes[0..4] != MAGIC {
+            return Err(FirmwareError::InvalidHeader);
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+
+        Ok(Self {
+            version: FirmwareVersion {
+                major: u16_at(4),
+                minor: u16_at(6),
+                patch: u16_at(8),
+            },
+            abi: u32_at(12),
+            payload_len: u32_at(16),
+            payload_crc: u32_at(20),
+            signature_len: u32_at(24),
+        })
+    }
+}
+
+/// A validated firmware image
+#[derive(Debug, Clone)]
+pub struct FirmwareImage {
+    pub header: FirmwareHeader,
+    data: Vec<u8>,
+}
+
+impl FirmwareImage {
+    /// The part of the image that is uploaded to the device
+    pub fn payload(&self) -> &[u8] {
+        &self.data[HEADER_LEN..HEADER_LEN + self.header.payload_len as usize]
+    }
+
+    /// Header and payload, the part covered by the signature
+    pub fn signed(&self) -> &[u8] {
+        &self.data[..HEADER_LEN + self.header.payload_len as usize]
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.data[HEADER_LEN + self.header.payload_len as usize..]
+    }
+}
+
+/// Firmware errors
+#[derive(Debug)]
+pub enum FirmwareError {
+    /// The backend doesn't use firmware
+    NotRequired,
+    /// No firmware image installed
+    NotFound,
+    Io(io::Error),
+    /// Bad magic or truncated header
+    InvalidHeader,
+    /// Payload length or CRC doesn't match the header
+    Corrupted,
+    /// The image speaks a host interface the driver doesn't implement
+    UnsupportedAbi(u32),
+    /// The signature didn't verify
+    BadSignature,
+    /// The staged image is older than the active one
+    Downgrade {
+        active: FirmwareVersion,
+        staged: FirmwareVersion,
+    },
+    /// The device didn't come up with the image
+    Rejected(&'static str),
+}
+
+impl fmt::Display for FirmwareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FirmwareError::NotRequired => write!(f, "backend does not use firmware"),
+            FirmwareError::NotFound => write!(f, "firmware not found"),
+            FirmwareError::Io(err) => write!(f, "I/O error: {}", err),
+            FirmwareError::InvalidHeader => write!(f, "invalid firmware header"),
+            FirmwareError::Corrupted => write!(f, "firmware image corrupted"),
+            FirmwareError::UnsupportedAbi(abi) => write!(f, "unsupported firmware ABI {}", abi),
+            FirmwareError::BadSignature => write!(f, "bad firmware signature"),
+            FirmwareError::Downgrade { active, staged } => {
+                write!(f, "refusing to downgrade from {} to {}", active, staged)
+            }
+            FirmwareError::Rejected(err) => write!(f, "device rejected firmware: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for FirmwareError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::NotFound {
+            FirmwareError::NotFound
+        } else {
+            FirmwareError::Io(err)
+        }
+    }
+}
+
+/// Checks the signature of a firmware image
+pub trait SignatureVerifier: Send + Sync {
+    /// Whether `signature` signs `message`, the header and payload
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// Checks Ed25519 signatures against a public key
+pub struct Ed25519Verifier {
+    key: ed25519_dalek::VerifyingKey,
+}
+
+impl Ed25519Verifier {
+    /// `None` if `key` isn't a valid public key
+    pub fn new(key: &[u8; 32]) -> Option<Self> {
+        ed25519_dalek::VerifyingKey::from_bytes(key)
+            .ok()
+            .map(|key| Self { key })
+    }
+
+    /// Parse a key written as 64 hex digits
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().as_bytes();
+        if hex.len() != 64 {
+            return None;
+        }
+        let mut key = [0; 32];
+        for (byte, digits) in key.iter_mut().zip(hex.chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+        }
+        Self::new(&key)
+    }
+}
+
+impl SignatureVerifier for Ed25519Verifier {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        let Ok(signature) = ed25519_dalek::Signature::from_slice(signature) else {
+            return false;
+        };
+        self.key.verify_strict(message, &signature).is_ok()
+    }
+}
+
+/// Refuses every image, for backends without a configured key
+struct NoKey;
+
+impl SignatureVerifier for NoKey {
+    fn verify(&self, _message: &[u8], _signature: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Per-backend firmware requirements
+pub struct BackendFirmware {
+    /// Directory below the firmware root
+    pub name: &'static str,
+    /// Host interface versions the driver implements
+    pub abi: std::ops::RangeInclusive<u32>,
+    pub verifier: Box<dyn SignatureVerifier>,
+}
+
+/// Backends that use firmware
+const FIRMWARE_TYPES: [NpuType; 5] = [
+    NpuType::IntelVpu,
+    NpuType::AmdXdna,
+    NpuType::AppleAne,
+    NpuType::QualcommHexagon,
+    NpuType::GoogleEdgeTpu,
+];
+
+impl NpuType {
+    /// Backend whose firmware directory is `name`
+    pub fn from_firmware_name(name: &str) -> Option<Self> {
+        FIRMWARE_TYPES
+            .into_iter()
+            .find(|device_type| device_type.firmware_name() == Some(name))
+    }
+
+    /// Firmware directory of the backend, `None` if it doesn't need firmware
+    pub fn firmware_name(&self) -> Option<&'static str> {
+        match self {
+            NpuType::IntelVpu => Some("intel-vpu"),
+            NpuType::AmdXdna => Some("amd-xdna"),
+            NpuType::AppleAne => Some("apple-ane"),
+            NpuType::QualcommHexagon => Some("qcom-hexagon"),
+            NpuType::GoogleEdgeTpu => Some("edgetpu"),
+            NpuType::Generic | NpuType::NvidiaTensorCore => None,
+        }
+    }
+}
+
+/// Firmware loaded for a backend
+#[derive(Debug, Clone, Copy)]
+pub struct LoadedFirmware {
+    pub version: FirmwareVersion,
+    pub abi: u32,
+    /// Loaded from an update that hasn't been committed yet
+    pub pending: bool,
+}
+
+/// Locates, validates and updates firmware for all backends
+pub struct FirmwareManager {
+    root: PathBuf,
+    backends: RwLock<BTreeMap<&'static str, BackendFirmware>>,
+    loaded: RwLock<BTreeMap<&'static str, LoadedFirmware>>,
+}
+
+impl FirmwareManager {
+    pub fn new() -> Self {
+        Self::with_root(FIRMWARE_ROOT, FIRMWARE_KEYS)
+    }
+
+    /// Firmware below `root`, signing keys in `keys`
+    pub fn with_root(root: impl Into<PathBuf>, keys: impl Into<PathBuf>) -> Self {
+        let keys = keys.into();
+        let manager = Self {
+            root: root.into(),
+            backends: RwLock::new(BTreeMap::new()),
+            loaded: RwLock::new(BTreeMap::new()),
+        };
+        // Accept the first host interface revision, signed with the
+        // configured key
+        for device_type in FIRMWARE_TYPES {
+            if let Some(name) = device_type.firmware_name() {
+                manager.register_backend(BackendFirmware {
+                    name,
+                    abi: 1..=1,
+                    verifier: load_key(&keys, name),
+                });
+            }
+        }
+        manager
+    }
+
+    /// Register the firmware requirements of a backend, replacing the default
+    pub fn register_backend(&self, backend: BackendFirmware) {
+        self.backends.write().unwrap().insert(backend.name, backend);
+    }
+
+    fn dir(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// Read and validate an image for backend `name`
+    fn validate(&self, name: &str, data: Vec<u8>) -> Result<FirmwareImage, FirmwareError> {
+        let header = FirmwareHeader::parse(&data)?;
+        let expected_len =
+            HEADER_LEN as u64 + u64::from(header.payload_len) + u64::from(header.signature_len);
+        if data.len() as u64 != expected_len {
+            return Err(FirmwareError::Corrupted);
+        }
+        let image = FirmwareImage { header, data };
+        if crc32(image.payload()) != header.payload_crc {
+            return Err(FirmwareError::Corrupted);
+        }
+
+        let backends = self.backends.read().unwrap();
+        let backend = backends.get(name).ok_or(FirmwareError::NotRequired)?;
+        if !backend.abi.contains(&header.abi) {
+            return Err(FirmwareError::UnsupportedAbi(header.abi));
+        }
+        if !backend.verifier.verify(image.signed(), image.signature()) {
+            return Err(FirmwareError::BadSignature);
+        }
+        Ok(image)
+    }
+
+    /// Install an update, to be activated the next time the firmware is loaded
+    pub fn stage(
+        &self,
+        device_type: NpuType,
+        data: Vec<u8>,
+    ) -> Result<FirmwareVersion, FirmwareError> {
+        let name = device_type
+            .firmware_name()
+            .ok_or(FirmwareError::NotRequired)?;
+        let dir = self.dir(name);
+        let image = self.validate(name, data)?;
+
+        if let Ok(active) = fs::read(dir.join(ACTIVE)) {
+            let active = FirmwareHeader::parse(&active)?.version;
+            if image.header.version < active {
+                return Err(FirmwareError::Downgrade {
+                    active,
+                    staged: image.header.version,
+                });
+            }
+        }
+
+        fs::create_dir_all(&dir)?;
+        // Write to a temporary file first so that a crash never leaves a torn staged image.
+        let tmp = dir.join("staged.tmp");
+        fs::write(&tmp, &image.data)?;
+        fs::rename(&tmp, dir.join(STAGED))?;
+
+        eprintln!("NPU: staged {} firmware {}", name, image.header.version);
+        Ok(image.header.version)
+    }
+
+    /// Load the firmware for a backend, activating a staged update if there is one
+    ///
+    /// If the previous load activated an update that was never committed,
+    /// the device didn't come up with it, so the previous image is restored
+    /// first.
+    pub fn load(&self, device_type: NpuType) -> Result<FirmwareImage, FirmwareError> {
+        let name = device_type
+            .firmware_name()
+            .ok_or(FirmwareError::NotRequired)?;
+        let dir = self.dir(name);
+
+        if dir.join(PENDING).exists() {
+            eprintln!(
+                "NPU: {} firmware update was not confirmed, rolling back",
+                name
+            );
+            self.rollback(device_type)?;
+        } else if dir.join(STAGED).exists() {
+            if dir.join(ACTIVE).exists() {
+                fs::rename(dir.join(ACTIVE), dir.join(PREVIOUS))?;
+            }
+            fs::rename(dir.join(STAGED), dir.join(ACTIVE))?;
+            fs::write(dir.join(PENDING), [])?;
+        }
+
+        let image = match self.validate(name, fs::read(dir.join(ACTIVE))?) {
+            Ok(image) => image,
+            Err(err) if dir.join(PENDING).exists() => {
+                eprintln!("NPU: activated {} firmware is invalid: {}", name, err);
+                self.rollback(device_type)?;
+                self.validate(name, fs::read(dir.join(ACTIVE))?)?
+            }
+            Err(err) => return Err(err),
+        };
+
+        let loaded = LoadedFirmware {
+            version: image.header.version,
+            abi: image.header.abi,
+            pending: dir.join(PENDING).exists(),
+        };
+        eprintln!(
+            "NPU: loaded {} firmware {}{}",
+            name,
+            loaded.version,
+            if loaded.pending { " (pending)" } else { "" }
+        );
+        self.loaded.write().unwrap().insert(name, loaded);
+        Ok(image)
+    }
+
+    /// Confirm that the device works with the loaded firmware
+    pub fn commit(&self, device_type: NpuType) -> Result<(), FirmwareError> {
+        let name = device_type
+            .firmware_name()
+            .ok_or(FirmwareError::NotRequired)?;
+        let dir = self.dir(name);
+        if !dir.join(PENDING).exists() {
+            return Ok(());
+        }
+
+        fs::remove_file(dir.join(PENDING))?;
+        if let Some(loaded) = self.loaded.write().unwrap().get_mut(name) {
+            loaded.pending = false;
+        }
+        Ok(())
+    }
+
+    /// Whether the active image is an update that hasn't been committed yet
+    pub fn is_pending(&self, device_type: NpuType) -> bool {
+        device_type
+            .firmware_name()
+            .is_some_and(|name| self.dir(name).join(PENDING).exists())
+    }
+
+    /// Restore the image that was active before the last update
+    ///
+    /// Returns whether there was one. Without it, the update was the first
+    /// image installed and stays active, only no longer pending.
+    pub fn rollback(&self, device_type: NpuType) -> Result<bool, FirmwareError> {
+        let name = device_type
+            .firmware_name()
+            .ok_or(FirmwareError::NotRequired)?;
+        let dir = self.dir(name);
+        let restored = dir.join(PREVIOUS).exists();
+        if restored {
+            fs::rename(dir.join(PREVIOUS), dir.join(ACTIVE))?;
+        } else {
+            eprintln!(
+                "NPU: no previous {} firmware, keeping the active image",
+                name
+            );
+        }
+        match fs::remove_file(dir.join(PENDING)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        self.loaded.write().unwrap().remove(name);
+        Ok(restored)
+    }
+
+    /// Firmware currently loaded, by backend
+    pub fn loaded(&self) -> BTreeMap<&'static str, LoadedFirmware> {
+        self.loaded.read().unwrap().clone()
+    }
+}
+
+impl Default for FirmwareManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Verifier for the key of backend `name` in `keys`
+fn load_key(keys: &Path, name: &str) -> Box<dyn SignatureVerifier> {
+    let path = keys.join(format!("{}.pub", name));
+    match fs::read_to_string(&path) {
+        Ok(hex) => match Ed25519Verifier::from_hex(&hex) {
+            Some(verifier) => return Box::new(verifier),
+            None => eprintln!("NPU: invalid firmware key {}", path.display()),
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => eprintln!("NPU: failed to read {}: {}", path.display(), err),
+    }
+    eprintln!("NPU: no key for {} firmware, refusing its images", name);
+    Box::new(NoKey)
+}
+
+/// CRC-32 (IEEE 802.3)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -36,15 +36,27 @@
 //! ```
 
 use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::Read;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
+use common::dma::Dma;
+
+mod backends;
 mod command;
+mod firmware;
 mod memory;
+mod power;
+mod scheme;
 mod tensor;
 
-pub use command::{Command, CommandQueue, CommandStatus};
-pub use memory::{BufferUsage, NpuBuffer};
+pub use backends::Backend;
+pub use command::{Command, CommandGraph, CommandQueue, CommandStatus};
+pub use firmware::{FirmwareError, FirmwareManager, FirmwareVersion};
+pub use memory::{BufferUsage, Client, MemoryError, NpuBuffer};
+pub use power::{PowerManager, PowerState, TemperatureSensor, ThermalPolicy};
 pub use tensor::{DataType, TensorDesc};
 
 /// NPU device type
@@ -93,6 +105,8 @@ pub struct NpuConfig {
     pub queue_depth: u32,
     /// Memory pool size
     pub memory_pool_mb: u32,
+    /// Memory a single process may allocate
+    pub process_quota_mb: u32,
     /// Enable performance counters
     pub perf_counters: bool,
 }
@@ -103,6 +117,7 @@ impl Default for NpuConfig {
             power_management: true,
             queue_depth: 256,
             memory_pool_mb: 256,
+            process_quota_mb: 128,
             perf_counters: false,
         }
     }
@@ -120,6 +135,8 @@ pub struct NpuDevice {
     memory_pool: Arc<NpuMemoryPool>,
     /// Statistics
     stats: NpuStats,
+    /// Power and thermal state
+    power: PowerManager,
 }
 
 impl NpuDevice {
@@ -130,8 +147,10 @@ impl NpuDevice {
             queue: Arc::new(CommandQueue::new(config.queue_depth as usize)),
             memory_pool: Arc::new(NpuMemoryPool::new(
                 config.memory_pool_mb as u64 * 1024 * 1024,
+                config.process_quota_mb as u64 * 1024 * 1024,
             )),
             stats: NpuStats::new(),
+            power: PowerManager::new(config.power_management, ThermalPolicy::default()),
         }
     }
 
@@ -144,85 +163,327 @@ impl NpuDevice {
         id
     }
 
+    /// Submit the commands of `graph` at once, returning their IDs by node
+    pub fn submit_graph(&self, graph: CommandGraph) -> Vec<u64> {
+        let count = graph.len() as u64;
+        let ids = self.queue.submit_graph(graph);
+        self.stats
+            .commands_submitted
+            .fetch_add(count, Ordering::Relaxed);
+        ids
+    }
+
     /// Wait for a command to complete
     pub fn wait(&self, cmd_id: u64) -> CommandStatus {
         self.queue.wait(cmd_id)
     }
 
-    /// Allocate a buffer
-    pub fn alloc_buffer(&self, size: usize, usage: BufferUsage) -> Option<NpuBuffer> {
-        self.memory_pool.allocate(size, usage)
+    /// Status of a command, `None` once it was forgotten
+    pub fn status(&self, cmd_id: u64) -> Option<CommandStatus> {
+        self.queue.get_status(cmd_id)
+    }
+
+    /// Wait until more than `seen` commands completed, see
+    /// [`CommandQueue::wait_completion`]
+    pub fn wait_completion(&self, seen: u64, timeout: Duration) -> u64 {
+        self.queue.wait_completion(seen, timeout)
+    }
+
+    /// Allocate a buffer for `client`
+    pub fn alloc_buffer(
+        &self,
+        size: usize,
+        usage: BufferUsage,
+        client: Client,
+    ) -> Result<NpuBuffer, MemoryError> {
+        self.memory_pool.allocate(size, usage, client)
     }
 
-    /// Free a buffer
-    pub fn free_buffer(&self, buffer: NpuBuffer) {
-        self.memory_pool.free(buffer);
+    /// Import GAL memory by its global name as a buffer of `client`
+    pub fn import_buffer(
+        &self,
+        name: u64,
+        size: usize,
+        client: Client,
+    ) -> Result<NpuBuffer, MemoryError> {
+        self.memory_pool.import(name, size, client)
+    }
+
+    /// Free a buffer of `client`, false if it has none by that handle
+    pub fn free_buffer(&self, handle: u32, client: Client) -> bool {
+        self.memory_pool.free(handle, client)
+    }
+
+    /// Free all buffers of `client`, returning how many there were
+    pub fn free_client(&self, client: Client) -> usize {
+        self.memory_pool.free_client(client)
+    }
+
+    /// Look up a buffer of `client` by handle
+    pub fn buffer(&self, handle: u32, client: Client) -> Option<NpuBuffer> {
+        self.memory_pool.get(handle, client)
+    }
+
+    /// Run `f` with the buffers `cmd` uses pinned, looked up by handle
+    ///
+    /// Buffers of other clients than the one that submitted `cmd` are not
+    /// found.
+    pub fn with_buffers<R>(
+        &self,
+        cmd: &Command,
+        f: impl FnOnce(&dyn Fn(u32) -> Option<NpuBuffer>) -> R,
+    ) -> R {
+        let (reads, writes) = cmd.cmd_type.buffers();
+        let pinned: Vec<Arc<Allocation>> = reads
+            .into_iter()
+            .chain(writes)
+            .filter_map(|handle| self.memory_pool.pin(handle))
+            .filter(|alloc| Some(alloc.owner) == cmd.client)
+            .collect();
+        f(&|handle| {
+            pinned
+                .iter()
+                .find(|alloc| alloc.handle == handle)
+                .map(|alloc| alloc.buffer())
+        })
+    }
+
+    /// Take the next command to execute, waiting up to `timeout` for one
+    pub fn next_command(&self, timeout: Duration) -> Option<Command> {
+        self.queue.dequeue_timeout(timeout)
+    }
+
+    /// Report a command executed by the backend
+    pub fn complete(&self, cmd_id: u64, status: CommandStatus, elapsed: Duration) {
+        self.queue.complete(cmd_id, status);
+        self.stats
+            .commands_completed
+            .fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .total_execution_ns
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
     }
 
     /// Get statistics
     pub fn stats(&self) -> &NpuStats {
         &self.stats
     }
+
+    /// Power and thermal state
+    pub fn power(&self) -> &PowerManager {
+        &self.power
+    }
+
+    /// Throttle the device by the temperature `sensor` reads
+    pub fn set_temperature_sensor(&self, sensor: Box<dyn TemperatureSensor>) {
+        self.power.set_sensor(sensor);
+    }
 }
 
+/// Granularity of buffer mappings
+const PAGE_SIZE: usize = 4096;
+/// Scheme GPU memory is imported from
+const GAL_SCHEME: &str = "/scheme/gal";
+
 /// NPU memory pool
+///
+/// Buffers are backed by physically contiguous memory, so clients can map
+/// them and devices access them directly. Every buffer belongs to a client,
+/// which only gets to use its own buffers, and each process may only hold
+/// up to its quota, however many clients it opens.
+///
+/// Executors pin the buffers of a command while it runs, so freeing a buffer
+/// the device still accesses only drops it from the pool; the memory is
+/// released once the command finished.
+///
+/// Memory allocated from the GPU through the `gal:` scheme can be imported
+/// as a buffer too. The pool keeps the GAL allocation open while the buffer
+/// exists, but it doesn't count against the pool or the quota.
 struct NpuMemoryPool {
     total_bytes: u64,
+    /// Bytes a single process may allocate
+    process_quota: u64,
     used_bytes: AtomicU64,
     next_handle: AtomicU32,
-    allocations: RwLock<BTreeMap<u32, Allocation>>,
+    allocations: RwLock<BTreeMap<u32, Arc<Allocation>>>,
+    /// Bytes allocated by each process
+    process_bytes: Mutex<BTreeMap<usize, u64>>,
 }
 
 struct Allocation {
     handle: u32,
     size: usize,
     usage: BufferUsage,
+    owner: Client,
+    memory: Backing,
+}
+
+enum Backing {
+    /// Page aligned pool memory, mapped into clients
+    Pool(Dma<[u8]>),
+    /// GAL allocation, which clients map from the `gal:` scheme
+    Gal { _memory: File, device_addr: u64 },
+}
+
+// SAFETY: the backing memory is owned by the allocation and only freed with it
+unsafe impl Send for Allocation {}
+unsafe impl Sync for Allocation {}
+
+impl Allocation {
+    fn buffer(&self) -> NpuBuffer {
+        let (ptr, device_addr) = match &self.memory {
+            Backing::Pool(memory) => (memory.as_ptr().cast_mut(), memory.physical() as u64),
+            Backing::Gal { device_addr, .. } => (std::ptr::null_mut(), *device_addr),
+        };
+        NpuBuffer {
+            handle: self.handle,
+            size: self.size,
+            usage: self.usage,
+            ptr,
+            device_addr,
+        }
+    }
 }
 
 impl NpuMemoryPool {
-    fn new(total_bytes: u64) -> Self {
+    fn new(total_bytes: u64, process_quota: u64) -> Self {
         Self {
             total_bytes,
+            process_quota,
             used_bytes: AtomicU64::new(0),
             next_handle: AtomicU32::new(1),
             allocations: RwLock::new(BTreeMap::new()),
+            process_bytes: Mutex::new(BTreeMap::new()),
         }
     }
 
-    fn allocate(&self, size: usize, usage: BufferUsage) -> Option<NpuBuffer> {
+    fn allocate(
+        &self,
+        size: usize,
+        usage: BufferUsage,
+        owner: Client,
+    ) -> Result<NpuBuffer, MemoryError> {
+        let mut process_bytes = self.process_bytes.lock().unwrap();
+        let process_used = process_bytes.get(&owner.pid).copied().unwrap_or(0);
+        if process_used + size as u64 > self.process_quota {
+            return Err(MemoryError::QuotaExceeded);
+        }
         let current = self.used_bytes.load(Ordering::Relaxed);
         if current + size as u64 > self.total_bytes {
-            return None;
+            return Err(MemoryError::OutOfMemory);
         }
 
+        // SAFETY: zeroed bytes are initialized
+        let memory = unsafe {
+            Dma::<[u8]>::zeroed_slice(size.max(1).next_multiple_of(PAGE_SIZE))
+                .map_err(|_| MemoryError::OutOfMemory)?
+                .assume_init()
+        };
+
         self.used_bytes.fetch_add(size as u64, Ordering::Relaxed);
-        let handle = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        process_bytes.insert(owner.pid, process_used + size as u64);
+        Ok(self.insert(size, usage, owner, Backing::Pool(memory)))
+    }
 
+    /// Import `size` bytes of the GAL allocation named `name` for `owner`
+    fn import(&self, name: u64, size: usize, owner: Client) -> Result<NpuBuffer, MemoryError> {
+        let mut memory = File::open(format!("{}/memory/{}", GAL_SCHEME, name))
+            .map_err(|_| MemoryError::ImportFailed)?;
+        // Name, device address and size
+        let mut info = [0u8; 24];
+        memory
+            .read_exact(&mut info)
+            .map_err(|_| MemoryError::ImportFailed)?;
+        let device_addr = u64::from_le_bytes(info[8..16].try_into().unwrap());
+        let gal_size = u64::from_le_bytes(info[16..24].try_into().unwrap());
+        if size == 0 || size as u64 > gal_size {
+            return Err(MemoryError::ImportFailed);
+        }
+
+        let backing = Backing::Gal {
+            _memory: memory,
+            device_addr,
+        };
+        Ok(self.insert(size, BufferUsage::ReadWrite, owner, backing))
+    }
+
+    fn insert(&self, size: usize, usage: BufferUsage, owner: Client, memory: Backing) -> NpuBuffer {
+        let handle = self.next_handle.fetch_add(1, Ordering::Relaxed);
         let alloc = Allocation {
             handle,
             size,
             usage,
+            owner,
+            memory,
         };
-        self.allocations.write().unwrap().insert(handle, alloc);
+        let buffer = alloc.buffer();
+        self.allocations
+            .write()
+            .unwrap()
+            .insert(handle, Arc::new(alloc));
+        buffer
+    }
 
-        Some(NpuBuffer {
-            handle,
-            size,
-            usage,
-            ptr: std::ptr::null_mut(), // Would be actual device pointer
-        })
+    /// Look up a buffer of `owner`
+    fn get(&self, handle: u32, owner: Client) -> Option<NpuBuffer> {
+        let allocations = self.allocations.read().unwrap();
+        let alloc = allocations.get(&handle)?;
+        (alloc.owner == owner).then(|| alloc.buffer())
     }
 
-    fn free(&self, buffer: NpuBuffer) {
-        if self
-            .allocations
-            .write()
-            .unwrap()
-            .remove(&buffer.handle)
-            .is_some()
+    /// Keep the backing memory of a buffer until the returned reference is
+    /// dropped, even if the buffer is freed meanwhile
+    fn pin(&self, handle: u32) -> Option<Arc<Allocation>> {
+        self.allocations.read().unwrap().get(&handle).cloned()
+    }
+
+    /// Free a buffer of `owner`, false if it has none by that handle
+    fn free(&self, handle: u32, owner: Client) -> bool {
+        let mut allocations = self.allocations.write().unwrap();
+        if allocations
+            .get(&handle)
+            .is_none_or(|alloc| alloc.owner != owner)
         {
-            self.used_bytes
-                .fetch_sub(buffer.size as u64, Ordering::Relaxed);
+            return false;
+        }
+        let alloc = allocations.remove(&handle).unwrap();
+        drop(allocations);
+        self.release(&alloc);
+        true
+    }
+
+    /// Free all buffers of `owner`, returning how many there were
+    fn free_client(&self, owner: Client) -> usize {
+        let mut allocations = self.allocations.write().unwrap();
+        let handles: Vec<u32> = allocations
+            .values()
+            .filter(|alloc| alloc.owner == owner)
+            .map(|alloc| alloc.handle)
+            .collect();
+        let freed: Vec<Arc<Allocation>> = handles
+            .iter()
+            .filter_map(|handle| allocations.remove(handle))
+            .collect();
+        drop(allocations);
+        for alloc in &freed {
+            self.release(alloc);
+        }
+        freed.len()
+    }
+
+    /// Return the accounting of a buffer removed from the pool
+    fn release(&self, alloc: &Allocation) {
+        if let Backing::Gal { .. } = alloc.memory {
+            return;
+        }
+        self.used_bytes
+            .fetch_sub(alloc.size as u64, Ordering::Relaxed);
+        let mut process_bytes = self.process_bytes.lock().unwrap();
+        if let Some(bytes) = process_bytes.get_mut(&alloc.owner.pid) {
+            *bytes -= alloc.size as u64;
+            if *bytes == 0 {
+                process_bytes.remove(&alloc.owner.pid);
+            }
         }
     }
 }
@@ -257,6 +518,7 @@ impl Default for NpuStats {
 pub struct NpuDriver {
     devices: RwLock<BTreeMap<u32, Arc<NpuDevice>>>,
     next_device_id: AtomicU32,
+    firmware: FirmwareManager,
 }
 
 impl NpuDriver {
@@ -264,15 +526,102 @@ impl NpuDriver {
         Self {
             devices: RwLock::new(BTreeMap::new()),
             next_device_id: AtomicU32::new(0),
+            firmware: FirmwareManager::new(),
         }
     }
 
     /// Register a new NPU device
-    pub fn register_device(&self, capabilities: NpuCapabilities, config: NpuConfig) -> u32 {
+    ///
+    /// A device with a backend is booted with its firmware first, and its
+    /// commands are executed by the backend from then on.
+    pub fn register_device(
+        &self,
+        capabilities: NpuCapabilities,
+        config: NpuConfig,
+        backend: Option<Arc<dyn Backend>>,
+    ) -> Result<u32, FirmwareError> {
+        let device_type = capabilities.device_type;
+        if let (Some(backend), Some(_)) = (&backend, device_type.firmware_name()) {
+            self.boot_firmware(device_type, &**backend)?;
+        }
+
         let id = self.next_device_id.fetch_add(1, Ordering::Relaxed);
         let device = Arc::new(NpuDevice::new(id, capabilities, config));
-        self.devices.write().unwrap().insert(id, device);
-        id
+        self.devices.write().unwrap().insert(id, device.clone());
+
+        if let Some(backend) = backend {
+            backends::start(device, backend);
+        }
+        Ok(id)
+    }
+
+    /// Boot `backend` with its firmware and commit the image once the device
+    /// works with it
+    ///
+    /// An update the device rejects is rolled back right away and the image
+    /// it replaced booted instead.
+    fn boot_firmware(
+        &self,
+        device_type: NpuType,
+        backend: &dyn Backend,
+    ) -> Result<(), FirmwareError> {
+        loop {
+            let image = self.firmware.load(device_type)?;
+            let result = backend
+                .boot(image.payload())
+                .and_then(|()| backend.confirm());
+            match result {
+                Ok(()) => return self.firmware.commit(device_type),
+                Err(err) if self.firmware.is_pending(device_type) => {
+                    eprintln!(
+                        "NPU: device rejected firmware {}: {}, rolling back",
+                        image.header.version, err
+                    );
+                    if !self.firmware.rollback(device_type)? {
+                        return Err(FirmwareError::Rejected(err));
+                    }
+                }
+                Err(err) => return Err(FirmwareError::Rejected(err)),
+            }
+        }
+    }
+
+    /// Firmware manager shared by all backends
+    pub fn firmware(&self) -> &FirmwareManager {
+        &self.firmware
+    }
+
+    /// Contents of `npu:info`
+    pub fn info(&self) -> String {
+        let mut info = String::new();
+        for device in self.devices.read().unwrap().values() {
+            info.push_str(&format!(
+                "device {}: {} ({:?})\n",
+                device.id, device.capabilities.device_name, device.capabilities.device_type
+            ));
+            let power = device.power();
+            info.push_str(&format!(
+                "power {}: {:?} utilization {}% throttle {:?}",
+                device.id,
+                power.state(),
+                power.utilization(),
+                power.throttle()
+            ));
+            if let Some(mc) = power.temperature_mc() {
+                info.push_str(&format!(" temperature {:.1} C", mc as f32 / 1000.0));
+            }
+            info.push('\n');
+        }
+        for (backend, loaded) in self.firmware.loaded() {
+            info.push_str(&format!(
+                "firmware {}: {} abi {}{}\n",
+                backend,
+                loaded.version,
+                loaded.abi,
+                if loaded.pending { " pending" } else { "" }
+            ));
+        }
+        info
     }
 
     /// Get a device by ID
@@ -292,13 +641,51 @@ impl Default for NpuDriver {
     }
 }
 
-fn main() {
+fn daemon(daemon: redox_daemon::Daemon) -> ! {
     eprintln!("NPU/TPU Driver starting...");
 
-    let driver = NpuDriver::new();
+    let driver = Arc::new(NpuDriver::new());
+
+    // Spawned by pcid-spawner for an NPU function
+    let mut pcid = std::env::var_os("PCID_CLIENT_CHANNEL")
+        .map(|_| pcid_interface::PciFunctionHandle::connect_default());
+    if let Some(pcid) = &mut pcid {
+        match backends::xdna::Xdna::probe(pcid) {
+            Ok(xdna) => {
+                let capabilities = xdna.capabilities();
+                match driver.register_device(
+                    capabilities,
+                    NpuConfig::default(),
+                    Some(Arc::new(xdna)),
+                ) {
+                    Ok(id) => eprintln!("NPU: registered AMD XDNA as device {}", id),
+                    Err(err) => eprintln!("NPU: failed to boot AMD XDNA: {}", err),
+                }
+            }
+            Err(err) => eprintln!("NPU: no supported device: {}", err),
+        }
+    }
 
-    // TODO: Probe for NPU devices and register them
-    // TODO: Register "npu:" scheme
+    let socket = match redox_scheme::Socket::create("npu") {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("NPU: failed to register scheme: {}", err);
+            std::process::exit(1);
+        }
+    };
 
     eprintln!("NPU: Ready");
+    daemon.ready().expect("npu: failed to mark daemon as ready");
+
+    match scheme::serve(driver, socket) {
+        Ok(()) => std::process::exit(0),
+        Err(err) => {
+            eprintln!("NPU: scheme failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    redox_daemon::Daemon::new(daemon).expect("npu: failed to create daemon");
 }
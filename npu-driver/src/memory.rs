@@ -17,6 +17,26 @@ pub enum BufferUsage {
     Scratch,
 }
 
+/// Owner of buffers, an open device handle of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Client {
+    /// Handle the buffers were allocated through
+    pub id: u64,
+    /// Process holding the handle, quotas are per process
+    pub pid: usize,
+}
+
+/// Allocation errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    /// The pool or system memory is exhausted
+    OutOfMemory,
+    /// The process reached its quota
+    QuotaExceeded,
+    /// The memory to import doesn't exist or is too small
+    ImportFailed,
+}
+
 /// NPU buffer handle
 #[derive(Debug, Clone)]
 pub struct NpuBuffer {
@@ -24,6 +44,8 @@ pub struct NpuBuffer {
     pub size: usize,
     pub usage: BufferUsage,
     pub ptr: *mut u8,
+    /// Address the device accesses the buffer at, 0 if it can't
+    pub device_addr: u64,
 }
 
 impl NpuBuffer {
@@ -34,6 +56,7 @@ impl NpuBuffer {
             size,
             usage,
             ptr: std::ptr::null_mut(),
+            device_addr: 0,
         }
     }
 
@@ -0,0 +1,299 @@
+//! NPU power management
+//!
+//! Executors bracket every command with [`PowerManager::begin`] and
+//! [`PowerManager::end`], which tracks how busy the device is. With power
+//! management enabled, a device idle for [`IDLE_TIMEOUT`] is put into a low
+//! power state and woken up by the next command. Power state changes are
+//! made under the manager's lock, so a resume never overtakes the suspend
+//! it undoes.
+//!
+//! Independently of that, a temperature sensor attached to the device is
+//! sampled periodically. Above the throttle temperature dispatch is
+//! duty-cycled so the device rests as long as it ran, above the critical
+//! temperature dispatch stops until the device cooled down below the
+//! throttle temperature again. Sensors are read through redox-hal, from
+//! I2C temperature sensors or analog ones on an ADC channel.
+
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use redox_hal::adc::Adc;
+use redox_hal::i2c::{I2c, I2cAddress};
+
+/// Idle time after which the device enters a low power state
+pub const IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Period of sampling the temperature and checking for idleness
+pub const SAMPLE_PERIOD: Duration = Duration::from_millis(100);
+/// Window utilization is averaged over
+const UTILIZATION_WINDOW: Duration = Duration::from_secs(1);
+
+/// Device power states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    /// Clocked and ready to execute
+    Active,
+    /// Clock and power gated, has to be resumed before executing
+    Suspended,
+}
+
+/// Thermal limits in millidegrees Celsius
+#[derive(Debug, Clone, Copy)]
+pub struct ThermalPolicy {
+    /// Start duty-cycling dispatch
+    pub throttle_mc: i32,
+    /// Stop dispatch
+    pub critical_mc: i32,
+    /// Cooling needed below the throttle temperature to stop throttling
+    pub hysteresis_mc: i32,
+}
+
+impl Default for ThermalPolicy {
+    fn default() -> Self {
+        Self {
+            throttle_mc: 85_000,
+            critical_mc: 95_000,
+            hysteresis_mc: 5_000,
+        }
+    }
+}
+
+/// How dispatch is held back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttle {
+    None,
+    /// Rest as long as the last command ran
+    DutyCycle,
+    /// Don't dispatch at all
+    Halt,
+}
+
+/// Moves the device into a power state
+pub type SetState<'a> = dyn Fn(PowerState) -> Result<(), &'static str> + 'a;
+
+/// Source of the device temperature
+pub trait TemperatureSensor: Send {
+    /// Temperature in millidegrees Celsius, `None` if it couldn't be read
+    fn read_millicelsius(&mut self) -> Option<i32>;
+}
+
+/// TMP102 or LM75 compatible sensor on an I2C bus
+///
+/// The temperature register holds a left aligned two's complement value in
+/// 1/256 degrees Celsius.
+pub struct I2cSensor<B> {
+    bus: B,
+    address: I2cAddress,
+}
+
+impl<B: I2c> I2cSensor<B> {
+    const TEMPERATURE: u8 = 0;
+
+    pub fn new(bus: B, address: I2cAddress) -> Self {
+        Self { bus, address }
+    }
+}
+
+impl<B: I2c + Send> TemperatureSensor for I2cSensor<B> {
+    fn read_millicelsius(&mut self) -> Option<i32> {
+        let mut raw = [0u8; 2];
+        self.bus
+            .read_registers(self.address, Self::TEMPERATURE, &mut raw)
+            .ok()?;
+        Some(i32::from(i16::from_be_bytes(raw)) * 1000 / 256)
+    }
+}
+
+/// Analog sensor with a linear output, like the TMP36, on an ADC channel
+pub struct AdcSensor<A: Adc> {
+    adc: A,
+    channel: A::Channel,
+    /// Output at 0 degrees Celsius
+    offset_mv: i32,
+    /// Output change per degree Celsius, in microvolts
+    uv_per_degree: i32,
+}
+
+impl<A: Adc> AdcSensor<A> {
+    pub fn new(adc: A, channel: A::Channel, offset_mv: i32, uv_per_degree: i32) -> Self {
+        Self {
+            adc,
+            channel,
+            offset_mv,
+            uv_per_degree,
+        }
+    }
+}
+
+impl<A: Adc + Send> TemperatureSensor for AdcSensor<A>
+where
+    A::Channel: Send,
+{
+    fn read_millicelsius(&mut self) -> Option<i32> {
+        let vref = self.adc.reference_voltage_mv();
+        let mv = i32::from(self.adc.read_voltage(&self.channel, vref).ok()?);
+        Some((mv - self.offset_mv) * 1_000_000 / self.uv_per_degree)
+    }
+}
+
+struct State {
+    power: PowerState,
+    throttle: Throttle,
+    /// Commands executing right now
+    running: u32,
+    last_activity: Instant,
+    /// Busy time in the current utilization window
+    busy: Duration,
+    window_start: Instant,
+    /// Utilization of the last full window, in percent
+    utilization: u32,
+    temperature_mc: Option<i32>,
+}
+
+/// Power and thermal state of a device
+pub struct PowerManager {
+    /// Whether idle devices are suspended
+    enabled: bool,
+    policy: ThermalPolicy,
+    state: Mutex<State>,
+    /// Signalled when dispatch may continue
+    resumed: Condvar,
+    sensor: Mutex<Option<Box<dyn TemperatureSensor>>>,
+}
+
+impl PowerManager {
+    pub fn new(enabled: bool, policy: ThermalPolicy) -> Self {
+        let now = Instant::now();
+        Self {
+            enabled,
+            policy,
+            state: Mutex::new(State {
+                power: PowerState::Active,
+                throttle: Throttle::None,
+                running: 0,
+                last_activity: now,
+                busy: Duration::ZERO,
+                window_start: now,
+                utilization: 0,
+                temperature_mc: None,
+            }),
+            resumed: Condvar::new(),
+            sensor: Mutex::new(None),
+        }
+    }
+
+    /// Sample `sensor` for the device temperature from now on
+    pub fn set_sensor(&self, sensor: Box<dyn TemperatureSensor>) {
+        *self.sensor.lock().unwrap() = Some(sensor);
+    }
+
+    /// Wait until a command may be dispatched, resuming the device through
+    /// `set_state` if it is suspended
+    pub fn begin(&self, set_state: &SetState<'_>) {
+        let state = self.state.lock().unwrap();
+        let mut state = self
+            .resumed
+            .wait_while(state, |state| state.throttle == Throttle::Halt)
+            .unwrap();
+        state.running += 1;
+        state.last_activity = Instant::now();
+        if state.power == PowerState::Suspended {
+            if let Err(err) = set_state(PowerState::Active) {
+                eprintln!("NPU: failed to resume: {}", err);
+            }
+            state.power = PowerState::Active;
+        }
+    }
+
+    /// Account a command that ran for `elapsed`, resting afterwards if the
+    /// device is throttled
+    pub fn end(&self, elapsed: Duration) {
+        let throttle = {
+            let mut state = self.state.lock().unwrap();
+            state.running -= 1;
+            state.last_activity = Instant::now();
+            state.busy += elapsed;
+            state.throttle
+        };
+        if throttle == Throttle::DutyCycle {
+            std::thread::sleep(elapsed);
+        }
+    }
+
+    /// Sample the sensor and suspend the device through `set_state` if it
+    /// idled long enough
+    ///
+    /// Called every [`SAMPLE_PERIOD`].
+    pub fn tick(&self, set_state: &SetState<'_>) {
+        let temperature = self
+            .sensor
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|sensor| sensor.read_millicelsius());
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        let window = now.duration_since(state.window_start);
+        if window >= UTILIZATION_WINDOW {
+            state.utilization = (state.busy.as_nanos() * 100 / window.as_nanos()).min(100) as u32;
+            state.busy = Duration::ZERO;
+            state.window_start = now;
+        }
+
+        state.temperature_mc = temperature;
+        let throttle = match temperature {
+            Some(mc) if mc >= self.policy.critical_mc => Throttle::Halt,
+            Some(mc) if mc >= self.policy.throttle_mc => match state.throttle {
+                // Halted until cooled down below the throttle temperature
+                Throttle::Halt => Throttle::Halt,
+                _ => Throttle::DutyCycle,
+            },
+            Some(mc) if mc >= self.policy.throttle_mc - self.policy.hysteresis_mc => {
+                match state.throttle {
+                    Throttle::None => Throttle::None,
+                    _ => Throttle::DutyCycle,
+                }
+            }
+            _ => Throttle::None,
+        };
+        if throttle != state.throttle {
+            eprintln!(
+                "NPU: thermal throttle {:?} at {} mC",
+                throttle,
+                temperature.unwrap_or(0)
+            );
+            state.throttle = throttle;
+            self.resumed.notify_all();
+        }
+
+        if self.enabled
+            && state.power == PowerState::Active
+            && state.running == 0
+            && now.duration_since(state.last_activity) >= IDLE_TIMEOUT
+        {
+            match set_state(PowerState::Suspended) {
+                Ok(()) => state.power = PowerState::Suspended,
+                Err(err) => eprintln!("NPU: failed to suspend: {}", err),
+            }
+        }
+    }
+
+    pub fn state(&self) -> PowerState {
+        self.state.lock().unwrap().power
+    }
+
+    pub fn throttle(&self) -> Throttle {
+        self.state.lock().unwrap().throttle
+    }
+
+    /// Busy time of the last second, in percent
+    pub fn utilization(&self) -> u32 {
+        self.state.lock().unwrap().utilization
+    }
+
+    /// Last sampled temperature in millidegrees Celsius
+    pub fn temperature_mc(&self) -> Option<i32> {
+        self.state.lock().unwrap().temperature_mc
+    }
+}
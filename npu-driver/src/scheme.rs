@@ -0,0 +1,757 @@
+//! `npu:` scheme
+//!
+//! Paths:
+//!
+//! - `npu:` lists the devices, a line of `<id> <type> <compute units>
+//!   <memory bytes> <name>` each
+//! - `npu:info` describes the devices, their power state and firmware
+//! - `npu:<id>` is a device handle
+//! - `npu:firmware/<backend>` takes a firmware update for the backend with
+//!   that firmware directory, root only
+//!
+//! A firmware update is written to its handle as a whole image and staged
+//! by `fsync`, which fails if the image doesn't validate. Closing the handle
+//! stages an image not synced yet. The update is activated the next time
+//! the driver boots the device.
+//!
+//! Writes to a device handle carry one or more requests, reads return the
+//! completions of finished requests, whole records only and none if nothing
+//! finished yet. The commands of a write are submitted together, so a whole
+//! model can be enqueued at once. A command runs after earlier ones using a
+//! buffer it writes or writing a buffer it uses, and after the requests named
+//! by an `after` right before it. A handle registered for `EVENT_READ` is notified when
+//! completions are waiting. Buffers are mapped with `mmap` at offset
+//! `handle << 32`.
+//!
+//! Buffers belong to the handle they were allocated through: other handles,
+//! even of the same process, can't free, map or compute on them, and a write
+//! naming a foreign buffer fails with `EBADF` before submitting anything.
+//! Closing a handle frees its buffers. Each process may allocate up to its
+//! quota, allocations beyond it fail with `EDQUOT`.
+//!
+//! All fields are little endian. A request starts with a 16 byte header:
+//!
+//! | offset | size | field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 4    | op                                     |
+//! | 4      | 4    | priority, higher runs first            |
+//! | 8      | 8    | tag, returned in the completion        |
+//!
+//! followed by the op's arguments, each a `u32` unless noted:
+//!
+//! | op | request    | arguments                           |
+//! |----|------------|-------------------------------------|
+//! | 1  | alloc      | size (`u64`), usage                 |
+//! | 2  | free       | buffer                              |
+//! | 3  | inference  | model, input, output                |
+//! | 4  | matmul     | a, b, c, m, n, k                    |
+//! | 5  | conv2d     | input, kernel, output               |
+//! | 6  | activation | buffer, function                    |
+//! | 7  | barrier    |                                     |
+//! | 8  | after      | count, then count tags (`u64`)      |
+//! | 9  | import     | GAL name (`u64`), size (`u64`)      |
+//!
+//! Usages are 0 read only, 1 write only, 2 read write, 3 constant and 4
+//! scratch; activation functions 0 ReLU, 1 sigmoid, 2 tanh, 3 GeLU, 4 SiLU
+//! and 5 softmax. An `after` has no completion of its own, tags it names
+//! that aren't pending refer to requests that finished already. An `import`
+//! makes memory allocated from the `gal:` scheme a buffer, so the GPU and
+//! NPU share it without copying; the buffer is accessed through the GAL
+//! mapping and not mapped from the device handle.
+//!
+//! A completion is 24 bytes:
+//!
+//! | offset | size | field                                     |
+//! |--------|------|-------------------------------------------|
+//! | 0      | 8    | tag of the request                        |
+//! | 8      | 4    | status, 0 or an errno                     |
+//! | 12     | 4    | reserved                                  |
+//! | 16     | 8    | value, the buffer handle of an alloc or   |
+//! |        |      | import                                    |
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use redox_scheme::scheme::SchemeSync;
+use redox_scheme::{CallerCtx, OpenResult, RequestKind, Response, SignalBehavior, Socket};
+use syscall::schemev2::NewFdFlags;
+use syscall::{
+    Error, EventFlags, MapFlags, Result, EACCES, EBADF, EDQUOT, EFBIG, EINVAL, EIO, EISDIR, ENOENT,
+    ENOMEM, EPERM, EROFS,
+};
+
+use crate::command::{ActivationFunc, Command, CommandGraph, CommandStatus, CommandType};
+use crate::{BufferUsage, Client, FirmwareError, MemoryError, NpuDevice, NpuDriver, NpuType};
+
+/// Size of a request header
+const HEADER_SIZE: usize = 16;
+/// Size of a completion
+pub const COMPLETION_SIZE: usize = 24;
+/// Buffers are mapped at their handle shifted by this
+pub const MAP_OFFSET_SHIFT: u32 = 32;
+
+/// How long completion threads wait before checking again
+const COMPLETION_POLL: Duration = Duration::from_millis(100);
+
+mod op {
+    pub const ALLOC: u32 = 1;
+    pub const FREE: u32 = 2;
+    pub const INFERENCE: u32 = 3;
+    pub const MATMUL: u32 = 4;
+    pub const CONV2D: u32 = 5;
+    pub const ACTIVATION: u32 = 6;
+    pub const BARRIER: u32 = 7;
+    pub const AFTER: u32 = 8;
+    pub const IMPORT: u32 = 9;
+}
+
+/// Finished request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Completion {
+    tag: u64,
+    status: u32,
+    value: u64,
+}
+
+impl Completion {
+    fn to_bytes(self) -> [u8; COMPLETION_SIZE] {
+        let mut bytes = [0u8; COMPLETION_SIZE];
+        bytes[0..8].copy_from_slice(&self.tag.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.status.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.value.to_le_bytes());
+        bytes
+    }
+}
+
+/// Request taken from a write
+#[derive(Debug)]
+enum Request {
+    Alloc {
+        size: u64,
+        usage: BufferUsage,
+    },
+    Free {
+        buffer: u32,
+    },
+    /// Import GAL memory
+    Import {
+        name: u64,
+        size: u64,
+    },
+    Submit(CommandType),
+    /// Tags the next submitted command waits for
+    After(Vec<u64>),
+}
+
+impl Request {
+    /// Parse the request at the start of `buf`, returning its size too
+    fn parse(buf: &[u8]) -> Result<(u32, u64, Request, usize)> {
+        let header = buf.get(..HEADER_SIZE).ok_or(Error::new(EINVAL))?;
+        let op = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let priority = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let tag = u64::from_le_bytes(header[8..16].try_into().unwrap());
+
+        let args = |count: usize| -> Result<Vec<u32>> {
+            let bytes = buf
+                .get(HEADER_SIZE..HEADER_SIZE + count * 4)
+                .ok_or(Error::new(EINVAL))?;
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|arg| u32::from_le_bytes(arg.try_into().unwrap()))
+                .collect())
+        };
+
+        let (request, arg_size) = match op {
+            op::ALLOC => {
+                let a = args(3)?;
+                let size = u64::from(a[0]) | u64::from(a[1]) << 32;
+                (
+                    Request::Alloc {
+                        size,
+                        usage: parse_usage(a[2])?,
+                    },
+                    12,
+                )
+            }
+            op::FREE => (
+                Request::Free {
+                    buffer: args(1)?[0],
+                },
+                4,
+            ),
+            op::INFERENCE => {
+                let a = args(3)?;
+                (
+                    Request::Submit(CommandType::Inference {
+                        model_id: a[0],
+                        input: a[1],
+                        output: a[2],
+                    }),
+                    12,
+                )
+            }
+            op::MATMUL => {
+                let a = args(6)?;
+                (
+                    Request::Submit(CommandType::MatMul {
+                        a: a[0],
+                        b: a[1],
+                        c: a[2],
+                        m: a[3],
+                        n: a[4],
+                        k: a[5],
+                    }),
+                    24,
+                )
+            }
+            op::CONV2D => {
+                let a = args(3)?;
+                (
+                    Request::Submit(CommandType::Conv2d {
+                        input: a[0],
+                        kernel: a[1],
+                        output: a[2],
+                    }),
+                    12,
+                )
+            }
+            op::ACTIVATION => {
+                let a = args(2)?;
+                (
+                    Request::Submit(CommandType::Activation {
+                        buffer: a[0],
+                        func: parse_activation(a[1])?,
+                    }),
+                    8,
+                )
+            }
+            op::BARRIER => (Request::Submit(CommandType::Barrier), 0),
+            op::AFTER => {
+                let count = args(1)?[0] as usize;
+                let tags = args(1 + count * 2)?[1..]
+                    .chunks_exact(2)
+                    .map(|tag| u64::from(tag[0]) | u64::from(tag[1]) << 32)
+                    .collect();
+                (Request::After(tags), 4 + count * 8)
+            }
+            op::IMPORT => {
+                let a = args(4)?;
+                (
+                    Request::Import {
+                        name: u64::from(a[0]) | u64::from(a[1]) << 32,
+                        size: u64::from(a[2]) | u64::from(a[3]) << 32,
+                    },
+                    16,
+                )
+            }
+            _ => return Err(Error::new(EINVAL)),
+        };
+        Ok((priority, tag, request, HEADER_SIZE + arg_size))
+    }
+}
+
+fn parse_usage(usage: u32) -> Result<BufferUsage> {
+    Ok(match usage {
+        0 => BufferUsage::ReadOnly,
+        1 => BufferUsage::WriteOnly,
+        2 => BufferUsage::ReadWrite,
+        3 => BufferUsage::Constant,
+        4 => BufferUsage::Scratch,
+        _ => return Err(Error::new(EINVAL)),
+    })
+}
+
+fn parse_activation(func: u32) -> Result<ActivationFunc> {
+    Ok(match func {
+        0 => ActivationFunc::ReLU,
+        1 => ActivationFunc::Sigmoid,
+        2 => ActivationFunc::Tanh,
+        3 => ActivationFunc::GeLU,
+        4 => ActivationFunc::SiLU,
+        5 => ActivationFunc::Softmax,
+        _ => return Err(Error::new(EINVAL)),
+    })
+}
+
+/// Open device handle
+struct DeviceHandle {
+    device: Arc<NpuDevice>,
+    /// Owner of the buffers allocated through the handle
+    client: Client,
+    /// Tags of submitted commands by command ID
+    submitted: BTreeMap<u64, u64>,
+    /// Completions not read yet
+    completions: VecDeque<Completion>,
+    events: EventFlags,
+    /// Whether `EVENT_READ` was posted since the last read
+    notified: bool,
+}
+
+impl DeviceHandle {
+    /// Move finished commands to the completions
+    fn collect(&mut self) {
+        let device = &self.device;
+        let completions = &mut self.completions;
+        self.submitted.retain(|&id, &mut tag| {
+            let status = match device.status(id) {
+                Some(CommandStatus::Pending | CommandStatus::Running) => return true,
+                Some(CommandStatus::Completed) => 0,
+                Some(CommandStatus::Failed(errno)) => errno,
+                // Completed so long ago the queue forgot its status
+                None => EIO as u32,
+            };
+            completions.push_back(Completion {
+                tag,
+                status,
+                value: 0,
+            });
+            false
+        });
+    }
+}
+
+/// Largest firmware image accepted
+const MAX_FIRMWARE_SIZE: usize = 64 << 20;
+
+/// Firmware update being written
+struct FirmwareHandle {
+    device_type: NpuType,
+    data: Vec<u8>,
+    /// Whether `data` was staged already
+    staged: bool,
+}
+
+enum Handle {
+    /// Device list
+    List(Vec<u8>),
+    Info(Vec<u8>),
+    Device(DeviceHandle),
+    Firmware(FirmwareHandle),
+}
+
+/// Stage the image written to `handle`, unless it was staged already
+fn stage(driver: &NpuDriver, handle: &mut FirmwareHandle) -> Result<()> {
+    if handle.staged || handle.data.is_empty() {
+        return Ok(());
+    }
+    let data = std::mem::take(&mut handle.data);
+    driver
+        .firmware()
+        .stage(handle.device_type, data)
+        .map_err(|err| {
+            eprintln!("NPU: refusing firmware update: {}", err);
+            Error::new(firmware_errno(&err))
+        })?;
+    handle.staged = true;
+    Ok(())
+}
+
+/// Errno of a firmware error
+fn firmware_errno(err: &FirmwareError) -> i32 {
+    match err {
+        FirmwareError::NotRequired | FirmwareError::NotFound => ENOENT,
+        FirmwareError::InvalidHeader
+        | FirmwareError::Corrupted
+        | FirmwareError::UnsupportedAbi(_) => EINVAL,
+        FirmwareError::BadSignature | FirmwareError::Downgrade { .. } => EPERM,
+        FirmwareError::Io(_) | FirmwareError::Rejected(_) => EIO,
+    }
+}
+
+pub struct NpuScheme {
+    driver: Arc<NpuDriver>,
+    handles: BTreeMap<usize, Handle>,
+    next_id: usize,
+}
+
+impl NpuScheme {
+    pub fn new(driver: Arc<NpuDriver>) -> Self {
+        Self {
+            driver,
+            handles: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn on_close(&mut self, id: usize) {
+        match self.handles.remove(&id) {
+            Some(Handle::Device(handle)) => {
+                let freed = handle.device.free_client(handle.client);
+                if freed > 0 {
+                    eprintln!(
+                        "NPU: freed {} buffers of process {} left behind on close",
+                        freed, handle.client.pid
+                    );
+                }
+            }
+            Some(Handle::Firmware(mut handle)) => {
+                // Failures are only logged, there's no one left to tell
+                let _ = stage(&self.driver, &mut handle);
+            }
+            _ => {}
+        }
+    }
+
+    fn list(&self) -> Vec<u8> {
+        let mut list = String::new();
+        for id in self.driver.list_devices() {
+            let Some(device) = self.driver.get_device(id) else {
+                continue;
+            };
+            let caps = &device.capabilities;
+            list.push_str(&format!(
+                "{} {:?} {} {} {}\n",
+                id, caps.device_type, caps.compute_units, caps.memory_bytes, caps.device_name
+            ));
+        }
+        list.into_bytes()
+    }
+
+    /// Collect finished commands of `device`'s handles, returning the
+    /// handles to notify
+    fn collect(&mut self, device: u32) -> Vec<usize> {
+        let mut notify = Vec::new();
+        for (&id, handle) in self.handles.iter_mut() {
+            let Handle::Device(handle) = handle else {
+                continue;
+            };
+            if handle.device.id != device {
+                continue;
+            }
+            handle.collect();
+            if !handle.completions.is_empty()
+                && !handle.notified
+                && handle.events.contains(EventFlags::EVENT_READ)
+            {
+                handle.notified = true;
+                notify.push(id);
+            }
+        }
+        notify
+    }
+
+    /// Submit the commands gathered in `graph`, tagged `tags` by node
+    fn submit(handle: &mut DeviceHandle, graph: &mut CommandGraph, tags: &mut Vec<u64>) {
+        if graph.is_empty() {
+            return;
+        }
+        let ids = handle.device.submit_graph(std::mem::take(graph));
+        handle.submitted.extend(ids.into_iter().zip(tags.drain(..)));
+    }
+
+    fn handle_request(handle: &mut DeviceHandle, tag: u64, request: Request) -> Result<()> {
+        match request {
+            Request::Alloc { size, usage } => {
+                let size = usize::try_from(size).map_err(|_| Error::new(EINVAL))?;
+                if size == 0 || size >= 1 << MAP_OFFSET_SHIFT {
+                    return Err(Error::new(EINVAL));
+                }
+                let (status, value) = match handle.device.alloc_buffer(size, usage, handle.client) {
+                    Ok(buffer) => (0, u64::from(buffer.handle)),
+                    Err(MemoryError::QuotaExceeded) => (EDQUOT as u32, 0),
+                    Err(MemoryError::OutOfMemory) => (ENOMEM as u32, 0),
+                    Err(MemoryError::ImportFailed) => unreachable!(),
+                };
+                handle
+                    .completions
+                    .push_back(Completion { tag, status, value });
+            }
+            Request::Free { buffer } => {
+                let status = if handle.device.free_buffer(buffer, handle.client) {
+                    0
+                } else {
+                    EBADF as u32
+                };
+                handle.completions.push_back(Completion {
+                    tag,
+                    status,
+                    value: 0,
+                });
+            }
+            Request::Import { name, size } => {
+                let size = usize::try_from(size).map_err(|_| Error::new(EINVAL))?;
+                let (status, value) = match handle.device.import_buffer(name, size, handle.client) {
+                    Ok(buffer) => (0, u64::from(buffer.handle)),
+                    Err(_) => (ENOENT as u32, 0),
+                };
+                handle
+                    .completions
+                    .push_back(Completion { tag, status, value });
+            }
+            Request::Submit(_) | Request::After(_) => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+impl SchemeSync for NpuScheme {
+    fn open(&mut self, path: &str, _flags: usize, ctx: &CallerCtx) -> Result<OpenResult> {
+        let handle = match path.trim_matches('/') {
+            "" => Handle::List(self.list()),
+            "info" => Handle::Info(self.driver.info().into_bytes()),
+            path if path.starts_with("firmware/") => {
+                if ctx.uid != 0 {
+                    return Err(Error::new(EACCES));
+                }
+                let name = &path["firmware/".len()..];
+                let device_type = NpuType::from_firmware_name(name).ok_or(Error::new(ENOENT))?;
+                Handle::Firmware(FirmwareHandle {
+                    device_type,
+                    data: Vec::new(),
+                    staged: false,
+                })
+            }
+            device => {
+                let id = device.parse().map_err(|_| Error::new(ENOENT))?;
+                let device = self.driver.get_device(id).ok_or(Error::new(ENOENT))?;
+                Handle::Device(DeviceHandle {
+                    device,
+                    client: Client {
+                        id: self.next_id as u64,
+                        pid: ctx.pid,
+                    },
+                    submitted: BTreeMap::new(),
+                    completions: VecDeque::new(),
+                    events: EventFlags::empty(),
+                    notified: false,
+                })
+            }
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handles.insert(id, handle);
+
+        Ok(OpenResult::ThisScheme {
+            number: id,
+            flags: NewFdFlags::empty(),
+        })
+    }
+
+    fn read(
+        &mut self,
+        id: usize,
+        buf: &mut [u8],
+        offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        match self.handles.get_mut(&id).ok_or(Error::new(EBADF))? {
+            Handle::List(text) | Handle::Info(text) => {
+                let start = (offset as usize).min(text.len());
+                let len = buf.len().min(text.len() - start);
+                buf[..len].copy_from_slice(&text[start..start + len]);
+                Ok(len)
+            }
+            Handle::Device(handle) => {
+                handle.collect();
+                let mut len = 0;
+                while let Some(chunk) = buf.get_mut(len..len + COMPLETION_SIZE) {
+                    let Some(completion) = handle.completions.pop_front() else {
+                        break;
+                    };
+                    chunk.copy_from_slice(&completion.to_bytes());
+                    len += COMPLETION_SIZE;
+                }
+                handle.notified = false;
+                Ok(len)
+            }
+            Handle::Firmware(_) => Err(Error::new(EBADF)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        id: usize,
+        buf: &[u8],
+        _offset: u64,
+        _fcntl_flags: u32,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let handle = match self.handles.get_mut(&id).ok_or(Error::new(EBADF))? {
+            Handle::Device(handle) => handle,
+            Handle::Firmware(handle) => {
+                if handle.staged {
+                    return Err(Error::new(EINVAL));
+                }
+                if handle.data.len() + buf.len() > MAX_FIRMWARE_SIZE {
+                    return Err(Error::new(EFBIG));
+                }
+                handle.data.extend_from_slice(buf);
+                return Ok(buf.len());
+            }
+            _ => return Err(Error::new(EROFS)),
+        };
+
+        // Parse everything first, so a malformed write submits nothing
+        let mut requests = Vec::new();
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let (priority, tag, request, size) = Request::parse(rest)?;
+            if let Request::Submit(cmd_type) = &request {
+                let (reads, writes) = cmd_type.buffers();
+                if reads
+                    .into_iter()
+                    .chain(writes)
+                    .any(|buffer| handle.device.buffer(buffer, handle.client).is_none())
+                {
+                    return Err(Error::new(EBADF));
+                }
+            }
+            requests.push((priority, tag, request));
+            rest = &rest[size..];
+        }
+        if matches!(requests.last(), Some((_, _, Request::After(_)))) {
+            return Err(Error::new(EINVAL));
+        }
+
+        let mut graph = CommandGraph::new();
+        // Tags of the nodes of `graph`
+        let mut tags = Vec::new();
+        let mut after = Vec::new();
+        for (priority, tag, request) in requests {
+            match request {
+                Request::After(deps) => after.extend(deps),
+                Request::Submit(cmd_type) => {
+                    let mut nodes = Vec::new();
+                    let mut ids = Vec::new();
+                    for dep in after.drain(..) {
+                        if let Some(node) = tags.iter().rposition(|&node_tag| node_tag == dep) {
+                            nodes.push(node);
+                        } else if let Some((&id, _)) = handle
+                            .submitted
+                            .iter()
+                            .find(|&(_, &pending)| pending == dep)
+                        {
+                            ids.push(id);
+                        }
+                    }
+                    let cmd = Command::new(cmd_type)
+                        .with_priority(priority)
+                        .for_client(handle.client)
+                        .after(&ids);
+                    graph.add(cmd, &nodes);
+                    tags.push(tag);
+                }
+                request => {
+                    // Keep allocations and frees ordered with the commands
+                    Self::submit(handle, &mut graph, &mut tags);
+                    Self::handle_request(handle, tag, request)?;
+                }
+            }
+        }
+        Self::submit(handle, &mut graph, &mut tags);
+        Ok(buf.len())
+    }
+
+    fn fevent(&mut self, id: usize, flags: EventFlags, _ctx: &CallerCtx) -> Result<EventFlags> {
+        let Handle::Device(handle) = self.handles.get_mut(&id).ok_or(Error::new(EBADF))? else {
+            return Err(Error::new(EINVAL));
+        };
+        handle.events = flags;
+        handle.notified = false;
+        handle.collect();
+        if flags.contains(EventFlags::EVENT_READ) && !handle.completions.is_empty() {
+            handle.notified = true;
+            return Ok(EventFlags::EVENT_READ);
+        }
+        Ok(EventFlags::empty())
+    }
+
+    fn mmap_prep(
+        &mut self,
+        id: usize,
+        offset: u64,
+        size: usize,
+        _flags: MapFlags,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let Handle::Device(handle) = self.handles.get(&id).ok_or(Error::new(EBADF))? else {
+            return Err(Error::new(EISDIR));
+        };
+        let buffer = u32::try_from(offset >> MAP_OFFSET_SHIFT).map_err(|_| Error::new(EINVAL))?;
+        let start = (offset & ((1 << MAP_OFFSET_SHIFT) - 1)) as usize;
+        let buffer = handle
+            .device
+            .buffer(buffer, handle.client)
+            .ok_or(Error::new(EINVAL))?;
+        let end = start.checked_add(size).ok_or(Error::new(EINVAL))?;
+        // Mappings are whole pages, and so is the backing memory
+        if end > buffer.size.next_multiple_of(crate::PAGE_SIZE) || buffer.ptr.is_null() {
+            return Err(Error::new(EINVAL));
+        }
+        Ok(buffer.ptr as usize + start)
+    }
+
+    fn fsync(&mut self, id: usize, _ctx: &CallerCtx) -> Result<()> {
+        match self.handles.get_mut(&id).ok_or(Error::new(EBADF))? {
+            Handle::Firmware(handle) => stage(&self.driver, handle),
+            _ => Ok(()),
+        }
+    }
+
+    fn fpath(&mut self, id: usize, buf: &mut [u8], _ctx: &CallerCtx) -> Result<usize> {
+        let path = match self.handles.get(&id).ok_or(Error::new(EBADF))? {
+            Handle::List(_) => "npu:".to_string(),
+            Handle::Info(_) => "npu:info".to_string(),
+            Handle::Device(handle) => format!("npu:{}", handle.device.id),
+            Handle::Firmware(handle) => format!(
+                "npu:firmware/{}",
+                handle.device_type.firmware_name().unwrap_or_default()
+            ),
+        };
+        let len = buf.len().min(path.len());
+        buf[..len].copy_from_slice(&path.as_bytes()[..len]);
+        Ok(len)
+    }
+}
+
+/// Serve `npu:` for `driver` until the scheme is unmounted
+///
+/// Every device gets a thread that waits for its completions and notifies
+/// the handles waiting for them.
+pub fn serve(driver: Arc<NpuDriver>, socket: Socket) -> Result<()> {
+    let socket = Arc::new(socket);
+    let scheme = Arc::new(Mutex::new(NpuScheme::new(driver.clone())));
+
+    for id in driver.list_devices() {
+        let Some(device) = driver.get_device(id) else {
+            continue;
+        };
+        let (socket, scheme) = (socket.clone(), scheme.clone());
+        std::thread::spawn(move || {
+            let mut seen = 0;
+            loop {
+                seen = device.wait_completion(seen, COMPLETION_POLL);
+                let notify = scheme.lock().unwrap().collect(id);
+                for handle in notify {
+                    if let Err(err) = socket.write_response(
+                        Response::post_fevent(handle, EventFlags::EVENT_READ.bits()),
+                        SignalBehavior::Restart,
+                    ) {
+                        eprintln!("NPU: failed to post event: {}", err);
+                    }
+                }
+            }
+        });
+    }
+
+    loop {
+        let Some(request) = socket.next_request(SignalBehavior::Restart)? else {
+            // Scheme likely got unmounted
+            return Ok(());
+        };
+
+        match request.kind() {
+            RequestKind::Call(call) => {
+                let response = call.handle_sync(&mut *scheme.lock().unwrap());
+                socket.write_response(response, SignalBehavior::Restart)?;
+            }
+            RequestKind::OnClose { id } => {
+                scheme.lock().unwrap().on_close(id);
+            }
+            _ => (),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use super::NvmeCmd;
+use super::features::{Feature, FeatureDwords, FeatureSelect, NumberOfQueues};
 
 impl NvmeCmd {
     pub fn create_io_completion_queue(
@@ -116,15 +117,29 @@ impl NvmeCmd {
         }
     }
     pub fn get_features(cid: u16, ptr: usize, fid: u8) -> Self {
+        Self::get_features_raw(cid, ptr, fid, FeatureSelect::Current, 0)
+    }
+
+    pub fn get_features_raw(cid: u16, ptr: usize, fid: u8, sel: FeatureSelect, cdw11: u32) -> Self {
         Self {
-            opcode: 0xA,
+            opcode: 0xA, // Get Features
+            cid,
             dptr: [ptr as u64, 0],
-            cdw10: u32::from(fid), // TODO: SEL
+            cdw10: ((sel as u32) << 8) | u32::from(fid),
+            cdw11,
             ..Default::default()
         }
     }
 
-    pub fn set_features_num_queues(cid: u16, num_sq: u16, num_cq: u16) -> Self {
+    pub fn set_features_raw(
+        cid: u16,
+        ptr: usize,
+        fid: u8,
+        save: bool,
+        dwords: FeatureDwords,
+    ) -> Self {
+        const DW10_SAVE_BIT: u32 = 1 << 31;
+
         Self {
             opcode: 9, // Set Features
             flags: 0,
@@ -132,16 +147,28 @@ impl NvmeCmd {
             nsid: 0,
             _rsvd: 0,
             mptr: 0,
-            dptr: [0, 0],
-            cdw10: 7, // Number of Queues feature identifier
-            cdw11: (((num_cq - 1) as u32) << 16) | ((num_sq - 1) as u32),
-            cdw12: 0,
-            cdw13: 0,
-            cdw14: 0,
-            cdw15: 0,
+            dptr: [ptr as u64, 0],
+            cdw10: if save { DW10_SAVE_BIT } else { 0 } | u32::from(fid),
+            cdw11: dwords.cdw11,
+            cdw12: dwords.cdw12,
+            cdw13: dwords.cdw13,
+            cdw14: dwords.cdw14,
+            cdw15: dwords.cdw15,
         }
     }
 
+    pub fn set_feature<F: Feature>(cid: u16, value: &F, save: bool) -> Self {
+        Self::set_features_raw(cid, 0, F::ID as u8, save, value.encode())
+    }
+
+    pub fn set_features_num_queues(cid: u16, num_sq: u16, num_cq: u16) -> Self {
+        let queues = NumberOfQueues {
+            submission_queues: num_sq,
+            completion_queues: num_cq,
+        };
+        Self::set_feature(cid, &queues, false)
+    }
+
     pub fn io_read(cid: u16, nsid: u32, lba: u64, blocks_1: u16, ptr0: u64, ptr1: u64) -> Self {
         Self {
             opcode: 2,
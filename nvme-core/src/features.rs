@@ -0,0 +1,403 @@
+//! Typed Get Features and Set Features.
+//!
+//! Each feature is a type implementing [`Feature`], which knows its feature
+//! identifier and how its fields map to command dwords 11 to 15 and to
+//! dword 0 of the completion. Features without a typed definition can still
+//! be used through [`Nvme::get_features_raw`] and [`Nvme::set_features_raw`].
+//!
+//! See NVME spec section 5.27.1.
+
+use syscall::error::{EINVAL, EIO, Error, Result};
+
+use super::{Nvme, NvmeCmd, NvmeComp};
+
+/// Feature identifiers, see NVME spec figure 271.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FeatureId {
+    Arbitration = 0x01,
+    PowerManagement = 0x02,
+    LbaRangeType = 0x03,
+    TemperatureThreshold = 0x04,
+    ErrorRecovery = 0x05,
+    VolatileWriteCache = 0x06,
+    NumberOfQueues = 0x07,
+    InterruptCoalescing = 0x08,
+    InterruptVectorConfiguration = 0x09,
+    WriteAtomicityNormal = 0x0A,
+    AsynchronousEventConfiguration = 0x0B,
+    AutonomousPowerStateTransition = 0x0C,
+    HostMemoryBuffer = 0x0D,
+    Timestamp = 0x0E,
+    KeepAliveTimer = 0x0F,
+}
+
+/// Which value Get Features returns (SEL), see NVME spec figure 270.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum FeatureSelect {
+    /// The value in use.
+    #[default]
+    Current = 0b000,
+    /// The value after a controller reset, if nothing was saved.
+    Default = 0b001,
+    /// The value saved across resets.
+    Saved = 0b010,
+    /// What can be done with the feature, see [`FeatureCapabilities`].
+    SupportedCapabilities = 0b011,
+}
+
+/// Command dwords 10 to 15 of a features command, minus the identifier.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FeatureDwords {
+    pub cdw11: u32,
+    pub cdw12: u32,
+    pub cdw13: u32,
+    pub cdw14: u32,
+    pub cdw15: u32,
+}
+
+impl FeatureDwords {
+    pub fn new(cdw11: u32) -> Self {
+        Self {
+            cdw11,
+            ..Default::default()
+        }
+    }
+}
+
+/// A feature with a typed value.
+pub trait Feature: Sized {
+    const ID: FeatureId;
+
+    /// Encodes the value for Set Features.
+    fn encode(&self) -> FeatureDwords;
+
+    /// Decodes dword 0 of a Get Features completion.
+    fn decode(cdw0: u32) -> Self;
+}
+
+/// Result of Get Features with [`FeatureSelect::SupportedCapabilities`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FeatureCapabilities(pub u32);
+
+impl FeatureCapabilities {
+    /// Set Features can save the value across resets.
+    pub fn saveable(&self) -> bool {
+        self.0 & (1 << 0) != 0
+    }
+    /// The value is per namespace rather than per controller.
+    pub fn namespace_specific(&self) -> bool {
+        self.0 & (1 << 1) != 0
+    }
+    /// Set Features can change the value.
+    pub fn changeable(&self) -> bool {
+        self.0 & (1 << 2) != 0
+    }
+}
+
+/// Arbitration burst size, unlimited.
+pub const ARBITRATION_BURST_UNLIMITED: u8 = 0b111;
+
+/// Command arbitration between submission queues.
+///
+/// The weights only matter with weighted round robin arbitration (CC.AMS),
+/// and like the burst they are 0's based.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Arbitration {
+    /// Log2 of the commands fetched from a queue at a time.
+    pub burst: u8,
+    pub low_priority_weight: u8,
+    pub medium_priority_weight: u8,
+    pub high_priority_weight: u8,
+}
+
+impl Feature for Arbitration {
+    const ID: FeatureId = FeatureId::Arbitration;
+
+    fn encode(&self) -> FeatureDwords {
+        FeatureDwords::new(
+            u32::from(self.burst & 0b111)
+                | (u32::from(self.low_priority_weight) << 8)
+                | (u32::from(self.medium_priority_weight) << 16)
+                | (u32::from(self.high_priority_weight) << 24),
+        )
+    }
+    fn decode(cdw0: u32) -> Self {
+        Self {
+            burst: (cdw0 & 0b111) as u8,
+            low_priority_weight: (cdw0 >> 8) as u8,
+            medium_priority_weight: (cdw0 >> 16) as u8,
+            high_priority_weight: (cdw0 >> 24) as u8,
+        }
+    }
+}
+
+/// Power state of the controller.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PowerManagement {
+    /// Power state, an index into the identify controller power state
+    /// descriptors.
+    pub power_state: u8,
+    /// Expected workload, 0 when unknown.
+    pub workload_hint: u8,
+}
+
+impl Feature for PowerManagement {
+    const ID: FeatureId = FeatureId::PowerManagement;
+
+    fn encode(&self) -> FeatureDwords {
+        FeatureDwords::new(
+            u32::from(self.power_state & 0x1F) | (u32::from(self.workload_hint & 0b111) << 5),
+        )
+    }
+    fn decode(cdw0: u32) -> Self {
+        Self {
+            power_state: (cdw0 & 0x1F) as u8,
+            workload_hint: ((cdw0 >> 5) & 0b111) as u8,
+        }
+    }
+}
+
+/// Volatile write cache, if the controller has one (identify VWC).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VolatileWriteCache {
+    pub enabled: bool,
+}
+
+impl Feature for VolatileWriteCache {
+    const ID: FeatureId = FeatureId::VolatileWriteCache;
+
+    fn encode(&self) -> FeatureDwords {
+        FeatureDwords::new(u32::from(self.enabled))
+    }
+    fn decode(cdw0: u32) -> Self {
+        Self {
+            enabled: cdw0 & 1 != 0,
+        }
+    }
+}
+
+/// Number of I/O queues.
+///
+/// Set Features requests a number of queues, and the completion says how
+/// many were allocated, which may be more or fewer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NumberOfQueues {
+    pub submission_queues: u16,
+    pub completion_queues: u16,
+}
+
+impl NumberOfQueues {
+    /// Returns `None` if either count is 0 or above 65535.
+    pub fn new(submission_queues: u32, completion_queues: u32) -> Option<Self> {
+        let in_range = |count: u32| (1..=0xFFFF).contains(&count);
+        if !in_range(submission_queues) || !in_range(completion_queues) {
+            return None;
+        }
+        Some(Self {
+            submission_queues: submission_queues as u16,
+            completion_queues: completion_queues as u16,
+        })
+    }
+}
+
+impl Feature for NumberOfQueues {
+    const ID: FeatureId = FeatureId::NumberOfQueues;
+
+    fn encode(&self) -> FeatureDwords {
+        // Both counts are 0's based
+        FeatureDwords::new(
+            (u32::from(self.completion_queues - 1) << 16) | u32::from(self.submission_queues - 1),
+        )
+    }
+    fn decode(cdw0: u32) -> Self {
+        // Saturates at 65535 queues, since 0xFFFF means 65536
+        Self {
+            submission_queues: (cdw0 & 0xFFFF).saturating_add(1).min(0xFFFF) as u16,
+            completion_queues: (cdw0 >> 16).saturating_add(1).min(0xFFFF) as u16,
+        }
+    }
+}
+
+/// Interrupt coalescing, for vectors that don't opt out with
+/// Interrupt Vector Configuration.
+///
+/// An interrupt is sent when either the threshold or the time is reached.
+/// Both zero disables coalescing.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct InterruptCoalescing {
+    /// Completions to aggregate, 0's based.
+    pub threshold: u8,
+    /// Longest delay, in 100 microsecond increments.
+    pub time: u8,
+}
+
+impl InterruptCoalescing {
+    /// Coalesces up to `completions` completions or `micros` microseconds.
+    ///
+    /// Returns `None` if either is outside what the feature can express.
+    pub fn new(completions: u16, micros: u32) -> Option<Self> {
+        if !(1..=256).contains(&completions) || micros > 255 * 100 {
+            return None;
+        }
+        Some(Self {
+            threshold: (completions - 1) as u8,
+            time: micros.div_ceil(100) as u8,
+        })
+    }
+
+    /// Coalescing disabled.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// The longest delay, in microseconds.
+    pub fn time_micros(&self) -> u32 {
+        u32::from(self.time) * 100
+    }
+}
+
+impl Feature for InterruptCoalescing {
+    const ID: FeatureId = FeatureId::InterruptCoalescing;
+
+    fn encode(&self) -> FeatureDwords {
+        FeatureDwords::new(u32::from(self.threshold) | (u32::from(self.time) << 8))
+    }
+    fn decode(cdw0: u32) -> Self {
+        Self {
+            threshold: cdw0 as u8,
+            time: (cdw0 >> 8) as u8,
+        }
+    }
+}
+
+/// Host memory buffer, for controllers that ask for some host memory
+/// (identify HMPRE and HMMIN).
+///
+/// The descriptor list holds 16 byte entries of a 64-bit address and a
+/// size in memory pages, and both the list and the buffers must stay
+/// allocated until the buffer is disabled again.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HostMemoryBuffer {
+    pub enabled: bool,
+    /// The buffer is the one used before a reset, with its contents intact.
+    pub memory_return: bool,
+    /// Buffer size in memory pages.
+    pub size: u32,
+    /// Physical address of the descriptor list, 16 byte aligned.
+    pub descriptor_list: u64,
+    /// Number of descriptors.
+    pub descriptor_count: u32,
+}
+
+impl HostMemoryBuffer {
+    /// Disables the buffer, after which the host may free it.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+}
+
+impl Feature for HostMemoryBuffer {
+    const ID: FeatureId = FeatureId::HostMemoryBuffer;
+
+    fn encode(&self) -> FeatureDwords {
+        FeatureDwords {
+            cdw11: u32::from(self.enabled) | (u32::from(self.memory_return) << 1),
+            cdw12: self.size,
+            cdw13: self.descriptor_list as u32,
+            cdw14: (self.descriptor_list >> 32) as u32,
+            cdw15: self.descriptor_count,
+        }
+    }
+    /// Only the enable bit comes back in dword 0; the rest is in the
+    /// attributes data structure.
+    fn decode(cdw0: u32) -> Self {
+        Self {
+            enabled: cdw0 & 1 != 0,
+            ..Default::default()
+        }
+    }
+}
+
+fn check_status(comp: &NvmeComp, what: &str, fid: u8) -> Result<u32> {
+    let status = comp.status >> 1;
+    if status == 0 {
+        Ok(comp.command_specific)
+    } else {
+        log::warn!(
+            "{} feature {:#x} failed with status {:#x}",
+            what,
+            fid,
+            status
+        );
+        Err(Error::new(EIO))
+    }
+}
+
+impl Nvme {
+    /// Issues Get Features for any feature, returning dword 0 of the completion.
+    ///
+    /// `ptr` is the physical address of the buffer for features that return
+    /// a data structure, or 0.
+    pub async fn get_features_raw(
+        &self,
+        fid: u8,
+        sel: FeatureSelect,
+        cdw11: u32,
+        ptr: usize,
+    ) -> Result<u32> {
+        let comp = self
+            .submit_and_complete_admin_command(|cid| {
+                NvmeCmd::get_features_raw(cid, ptr, fid, sel, cdw11)
+            })
+            .await;
+        check_status(&comp, "get", fid)
+    }
+
+    /// Issues Set Features for any feature, returning dword 0 of the completion.
+    pub async fn set_features_raw(
+        &self,
+        fid: u8,
+        save: bool,
+        dwords: FeatureDwords,
+        ptr: usize,
+    ) -> Result<u32> {
+        let comp = self
+            .submit_and_complete_admin_command(|cid| {
+                NvmeCmd::set_features_raw(cid, ptr, fid, save, dwords)
+            })
+            .await;
+        check_status(&comp, "set", fid)
+    }
+
+    pub async fn get_feature<F: Feature>(&self, sel: FeatureSelect) -> Result<F> {
+        if sel == FeatureSelect::SupportedCapabilities {
+            // Not a value of the feature
+            return Err(Error::new(EINVAL));
+        }
+        self.get_features_raw(F::ID as u8, sel, 0, 0)
+            .await
+            .map(F::decode)
+    }
+
+    /// Changes a feature, also saving it across resets if `save` is set.
+    pub async fn set_feature<F: Feature>(&self, value: &F, save: bool) -> Result<()> {
+        self.set_features_raw(F::ID as u8, save, value.encode(), 0)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn feature_capabilities(&self, fid: FeatureId) -> Result<FeatureCapabilities> {
+        self.get_features_raw(fid as u8, FeatureSelect::SupportedCapabilities, 0, 0)
+            .await
+            .map(FeatureCapabilities)
+    }
+
+    /// Requests I/O queues, returning how many the controller allocated.
+    pub async fn set_number_of_queues(&self, queues: NumberOfQueues) -> Result<NumberOfQueues> {
+        self.set_features_raw(FeatureId::NumberOfQueues as u8, false, queues.encode(), 0)
+            .await
+            .map(NumberOfQueues::decode)
+    }
+}
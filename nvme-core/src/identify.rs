@@ -65,6 +65,16 @@ impl IdentifyControllerData {
     pub fn max_sq_entries(&self) -> u16 {
         self.maxcmd
     }
+    /// Maximum data transfer size (MDTS) of a single command in bytes, `None` if unlimited.
+    ///
+    /// MDTS is a power of two in units of the minimum memory page size, 4 KiB for the controllers
+    /// set up here.
+    pub fn max_transfer_size(&self) -> Option<u32> {
+        match self.mdts {
+            0 => None,
+            mdts => 4096u32.checked_shl(mdts.into()).filter(|&size| size != 0),
+        }
+    }
 }
 #[derive(Clone, Copy)]
 #[repr(C, packed)]
@@ -16,10 +16,12 @@ use common::dma::Dma;
 
 pub mod cmd;
 pub mod executor;
+pub mod features;
 pub mod identify;
 pub mod queues;
 
 pub use self::queues::{NvmeCmd, NvmeCmdQueue, NvmeComp, NvmeCompQueue};
+pub use self::features::{Feature, FeatureId, FeatureSelect};
 pub use self::identify::{IdentifyControllerData, IdentifyNamespaceData};
 
 // Aliases for nvme-driver
@@ -587,13 +589,15 @@ impl Nvme {
         let controller_data = self.identify_controller().await;
         let num_queues_wanted = num_cpus::get().min((controller_data.oncs as usize >> 7) & 0x1FF);
 
-        let comp = self.submit_and_complete_admin_command(|cid| {
-            NvmeCmd::set_features_num_queues(cid, num_queues_wanted as u16, num_queues_wanted as u16)
-        }).await;
+        let wanted = features::NumberOfQueues::new(num_queues_wanted as u32, num_queues_wanted as u32)
+            .expect("nvmed: no I/O queues wanted");
+        let allocated = self
+            .set_number_of_queues(wanted)
+            .await
+            .expect("nvmed: failed to set number of queues");
 
-        let queues_created = (comp.cdw0 & 0xFFFF) + 1;
-        let num_cqs = queues_created;
-        let num_sqs = (comp.cdw0 >> 16) + 1;
+        let num_cqs = allocated.completion_queues.min(wanted.completion_queues);
+        let num_sqs = allocated.submission_queues.min(wanted.submission_queues);
 
         log::info!("Created {} I/O submission queues and {} I/O completion queues", num_sqs, num_cqs);
 
@@ -480,18 +480,28 @@ impl IoScheduler for DeadlineScheduler {
 }
 
 /// I/O batch for coalescing adjacent requests
+///
+/// A batch covers one contiguous LBA range of a namespace and is issued as a
+/// single command. Its requests may overlap, in which case they share the
+/// blocks they have in common. Requests being merged have to cover whole
+/// blocks.
 #[derive(Debug)]
 pub struct IoBatch {
-    /// Batched requests (same direction, adjacent LBAs)
+    /// Batched requests in submission order, so where writes overlap the
+    /// later one wins when copied in this order
     pub requests: Vec<IoRequest>,
     /// Batch type
     pub io_type: IoType,
+    /// Namespace ID
+    pub ns_id: u32,
     /// Starting LBA
     pub start_lba: u64,
     /// Total blocks
     pub total_blocks: u32,
     /// Total size
     pub total_size: usize,
+    /// Block size of the namespace
+    pub block_size: usize,
 }
 
 impl IoBatch {
@@ -499,28 +509,51 @@ impl IoBatch {
     pub fn new(request: IoRequest) -> Self {
         Self {
             io_type: request.io_type,
+            ns_id: request.ns_id,
             start_lba: request.lba,
             total_blocks: request.blocks as u32,
             total_size: request.size,
+            block_size: request.size / (request.blocks as usize).max(1),
             requests: vec![request],
         }
     }
 
+    /// LBA following the last block of the batch
+    pub fn end_lba(&self) -> u64 {
+        self.start_lba + self.total_blocks as u64
+    }
+
+    /// Offset of `request`'s data in the batch's transfer
+    pub fn offset_of(&self, request: &IoRequest) -> usize {
+        (request.lba - self.start_lba) as usize * self.block_size
+    }
+
     /// Try to add request to batch (returns false if not mergeable)
-    pub fn try_add(&mut self, request: &IoRequest, max_size: usize) -> bool {
-        // Must be same type
-        if request.io_type != self.io_type {
+    ///
+    /// Requests are added in LBA order, so `request` can't start before the
+    /// batch does.
+    pub fn try_add(&self, request: &IoRequest, max_size: usize) -> bool {
+        // Must be same type, and only reads and writes carry data to merge
+        if request.io_type != self.io_type
+            || !matches!(request.io_type, IoType::Read | IoType::Write)
+        {
+            return false;
+        }
+
+        // Must be the same namespace and block size
+        if request.ns_id != self.ns_id || request.size != request.blocks as usize * self.block_size
+        {
             return false;
         }
 
-        // Must be adjacent
-        let expected_lba = self.start_lba + self.total_blocks as u64;
-        if request.lba != expected_lba {
+        // Must be adjacent or overlapping
+        if request.lba < self.start_lba || request.lba > self.end_lba() {
             return false;
         }
 
         // Check size limit
-        if self.total_size + request.size > max_size {
+        let end_lba = self.end_lba().max(request.lba + request.blocks as u64);
+        if (end_lba - self.start_lba) as usize * self.block_size > max_size {
             return false;
         }
 
@@ -529,15 +562,17 @@ impl IoBatch {
 
     /// Add request to batch
     pub fn add(&mut self, request: IoRequest) {
-        self.total_blocks += request.blocks as u32;
-        self.total_size += request.size;
+        let end_lba = self.end_lba().max(request.lba + request.blocks as u64);
+        self.total_blocks = (end_lba - self.start_lba) as u32;
+        self.total_size = self.total_blocks as usize * self.block_size;
         self.requests.push(request);
     }
 }
 
 /// Request merger for coalescing adjacent I/O
 pub struct RequestMerger {
-    /// Maximum merge size
+    /// Maximum merge size, at most the controller's maximum data transfer
+    /// size (MDTS)
     max_merge_size: usize,
     /// Maximum requests per batch
     max_batch_requests: usize,
@@ -551,16 +586,25 @@ impl RequestMerger {
         }
     }
 
+    /// Whether no more requests can be added to `batch`
+    pub fn is_full(&self, batch: &IoBatch) -> bool {
+        batch.requests.len() >= self.max_batch_requests
+            || batch.total_size + batch.block_size > self.max_merge_size
+    }
+
     /// Merge a list of requests into batches
     pub fn merge(&self, mut requests: Vec<IoRequest>) -> Vec<IoBatch> {
         if requests.is_empty() {
             return Vec::new();
         }
 
-        // Sort by namespace, then LBA
-        requests.sort_by(|a, b| match a.ns_id.cmp(&b.ns_id) {
-            CmpOrdering::Equal => a.lba.cmp(&b.lba),
-            ord => ord,
+        // Sort by namespace, type, then LBA, so reads and writes to the same
+        // range don't split each other's batches
+        requests.sort_by(|a, b| {
+            a.ns_id
+                .cmp(&b.ns_id)
+                .then((a.io_type as u8).cmp(&(b.io_type as u8)))
+                .then(a.lba.cmp(&b.lba))
         });
 
         let mut batches = Vec::new();
@@ -587,6 +631,79 @@ impl RequestMerger {
             batches.push(batch);
         }
 
+        for batch in &mut batches {
+            batch.requests.sort_by_key(|request| request.id);
+        }
+
         batches
     }
 }
+
+/// Queue holding reads and writes back briefly, so that adjacent ones
+/// arriving close together are issued as one command
+///
+/// Requests wait at most `max_delay` before dispatch. Batches that can't
+/// grow any further are dispatched right away.
+pub struct MergeQueue {
+    merger: RequestMerger,
+    /// Longest time a request is held back
+    max_delay: Duration,
+    /// Requests waiting for dispatch, in submission order
+    pending: Mutex<Vec<IoRequest>>,
+}
+
+impl MergeQueue {
+    pub fn new(merger: RequestMerger, max_delay: Duration) -> Self {
+        Self {
+            merger,
+            max_delay,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queue a request for merging
+    pub fn push(&self, request: IoRequest) {
+        self.pending.lock().push(request);
+    }
+
+    /// Take the batches due for dispatch at `now`
+    ///
+    /// Once the oldest request has waited `max_delay`, everything queued is
+    /// dispatched; until then only batches that are full.
+    pub fn take_due(&self, now: Instant) -> Vec<IoBatch> {
+        let mut pending = self.pending.lock();
+        let Some(oldest) = pending.first().map(|request| request.queued_at) else {
+            return Vec::new();
+        };
+
+        let batches = self.merger.merge(std::mem::take(&mut *pending));
+        if now.duration_since(oldest) >= self.max_delay {
+            return batches;
+        }
+
+        let (full, partial): (Vec<_>, Vec<_>) = batches
+            .into_iter()
+            .partition(|batch| self.merger.is_full(batch));
+        pending.extend(partial.into_iter().flat_map(|batch| batch.requests));
+        pending.sort_by_key(|request| request.id);
+        full
+    }
+
+    /// Take all queued requests, regardless of how long they've waited
+    pub fn take_all(&self) -> Vec<IoBatch> {
+        self.merger.merge(std::mem::take(&mut *self.pending.lock()))
+    }
+
+    /// When the oldest queued request is due for dispatch
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .lock()
+            .first()
+            .map(|request| request.queued_at + self.max_delay)
+    }
+
+    /// Get number of queued requests
+    pub fn pending_count(&self) -> usize {
+        self.pending.lock().len()
+    }
+}
@@ -69,6 +69,7 @@ mod io_scheduler;
 mod queue;
 mod scheme;
 mod stats;
+mod tags;
 
 use crate::scheme::NvmeScheme;
 use crate::stats::PerformanceStats;
@@ -81,7 +82,8 @@ static GLOBAL_STATS: PerformanceStats = PerformanceStats::new();
 pub struct DriverConfig {
     /// Number of I/O queues (0 = auto-detect based on CPU count)
     pub num_queues: usize,
-    /// Queue depth (commands per queue)
+    /// Queue depth (most commands in flight per queue, capped by the
+    /// submission queue size)
     pub queue_depth: u16,
     /// Enable polling mode instead of interrupts
     pub polling_mode: bool,
@@ -95,6 +97,10 @@ pub struct DriverConfig {
     pub max_concurrent_cmds: u32,
     /// I/O scheduler type
     pub scheduler: IoSchedulerType,
+    /// Merge adjacent and overlapping reads and writes into single commands
+    pub merge_requests: bool,
+    /// Longest time a request is held back for merging, in microseconds
+    pub merge_delay_us: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -122,6 +128,8 @@ impl Default for DriverConfig {
             numa_aware: true,
             max_concurrent_cmds: 256,
             scheduler: IoSchedulerType::CpuAffinity,
+            merge_requests: false,
+            merge_delay_us: 50,
         }
     }
 }
@@ -194,14 +202,28 @@ fn main() {
     // Spawn statistics reporter thread
     #[cfg(feature = "performance-counters")]
     {
+        let scheme_clone = Arc::clone(&scheme);
+
         thread::Builder::new()
             .name("nvme-stats".to_string())
             .spawn(move || {
-                stats_reporter();
+                stats_reporter(scheme_clone);
             })
             .expect("nvme: failed to spawn stats thread");
     }
 
+    // Spawn merge dispatcher thread, submitting held back requests once due
+    let merge_thread = config.merge_requests.then(|| {
+        let scheme_clone = Arc::clone(&scheme);
+
+        thread::Builder::new()
+            .name("nvme-merge".to_string())
+            .spawn(move || {
+                merge_dispatcher(scheme_clone);
+            })
+            .expect("nvme: failed to spawn merge thread")
+    });
+
     // Main scheme event loop
     let mut event_queue = EventQueue::new().expect("nvme: failed to create event queue");
     let scheme_token = 1;
@@ -238,14 +260,22 @@ fn main() {
             let count = packets.len();
 
             for mut packet in packets {
-                scheme_for_event.write().handle(&mut packet);
-                let _ = syscall::write(scheme_fd, &packet);
+                if scheme_for_event.write().handle(&mut packet) {
+                    let _ = syscall::write(scheme_fd, &packet);
+                }
             }
 
             #[cfg(feature = "performance-counters")]
             {
                 GLOBAL_STATS.record_batch(count, start.elapsed());
             }
+
+            // Submit merged batches that can't grow any further right away,
+            // and have the merge thread wait for the rest
+            if let Some(merge_thread) = &merge_thread {
+                scheme_for_event.read().dispatch_merged(Instant::now());
+                merge_thread.thread().unpark();
+            }
         }
     }
 }
@@ -321,9 +351,23 @@ fn polling_worker(queue_id: usize, scheme: Arc<RwLock<NvmeScheme>>, config: Driv
     }
 }
 
+/// Merge dispatcher thread - submits requests held back for merging once
+/// they've waited long enough
+fn merge_dispatcher(scheme: Arc<RwLock<NvmeScheme>>) {
+    loop {
+        let deadline = scheme.read().next_merge_deadline();
+        match deadline {
+            Some(at) => thread::sleep(at.saturating_duration_since(Instant::now())),
+            None => thread::park(),
+        }
+
+        scheme.read().dispatch_merged(Instant::now());
+    }
+}
+
 /// Statistics reporter thread
 #[cfg(feature = "performance-counters")]
-fn stats_reporter() {
+fn stats_reporter(scheme: Arc<RwLock<NvmeScheme>>) {
     let interval = Duration::from_secs(10);
 
     loop {
@@ -340,6 +384,19 @@ fn stats_reporter() {
         info!("  P99 Latency:    {:>12?}", stats.p99_latency);
         info!("  Total Commands: {:>12}", stats.total_commands);
         info!("  Queue Depth:    {:>12}", stats.current_queue_depth);
+
+        for queue in &scheme.read().queues {
+            let occupancy = queue.get_stats().occupancy;
+            info!(
+                "  Queue {:<3} tags: {}/{} in use, max {}, {} waiting, exhausted {} times",
+                queue.id,
+                occupancy.in_use,
+                occupancy.depth,
+                occupancy.max_in_use,
+                occupancy.waiters,
+                occupancy.exhausted
+            );
+        }
     }
 }
 
@@ -384,5 +441,15 @@ fn parse_config() -> DriverConfig {
         };
     }
 
+    if let Ok(val) = std::env::var("NVME_MERGE") {
+        config.merge_requests = val == "1" || val.to_lowercase() == "true";
+    }
+
+    if let Ok(val) = std::env::var("NVME_MERGE_DELAY_US") {
+        if let Ok(n) = val.parse() {
+            config.merge_delay_us = n;
+        }
+    }
+
     config
 }
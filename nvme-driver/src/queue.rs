@@ -7,7 +7,7 @@
 //! for maximum I/O throughput.
 
 use std::collections::BTreeMap;
-use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 
 use crossbeam_queue::ArrayQueue;
@@ -17,6 +17,8 @@ use spin::Mutex as SpinMutex;
 use nvme::{CompletionQueue, Doorbell, NvmeCmd, NvmeComp, SubmissionQueue};
 use syscall::Physmap;
 
+use crate::tags::{TagAllocator, TagOccupancy};
+
 /// Maximum queue depth
 pub const MAX_QUEUE_DEPTH: usize = 4096;
 
@@ -27,6 +29,16 @@ pub struct PendingCommand {
     pub submitted_at: Instant,
     pub is_write: bool,
     pub bytes: usize,
+    /// Requests served by a merged command, answered instead of `packet`
+    pub merged: Option<MergedCommand>,
+}
+
+/// Command issued for several merged reads or writes
+pub struct MergedCommand {
+    /// Transfer buffer covering the LBA range of all requests
+    pub buffer: Vec<u8>,
+    /// Requests with the offset of their data in `buffer`
+    pub requests: Vec<(libredox::Packet, usize)>,
 }
 
 /// Completion information
@@ -65,14 +77,8 @@ pub struct QueuePair {
     /// Completion results ready for processing
     completions: ArrayQueue<CompletionInfo>,
 
-    /// Next command ID
-    next_cmd_id: AtomicU16,
-
-    /// Number of commands in flight
-    in_flight: AtomicU32,
-
-    /// Maximum queue depth
-    max_depth: u16,
+    /// Command ID tags, bounding the commands in flight to the queue depth
+    tags: TagAllocator<libredox::Packet>,
 
     /// Queue is active
     active: AtomicBool,
@@ -109,6 +115,9 @@ pub struct QueueStats {
 
 impl QueuePair {
     /// Create a new I/O queue pair
+    ///
+    /// At most `max_depth` commands are in flight at once, less if the
+    /// submission queue can't hold that many.
     pub fn new(
         id: usize,
         sq: SubmissionQueue,
@@ -116,6 +125,11 @@ impl QueuePair {
         doorbell: Doorbell,
         max_depth: u16,
     ) -> Self {
+        // A full submission queue keeps one slot empty to tell it from an
+        // empty one
+        let sq_capacity = sq.data.len().min(MAX_QUEUE_DEPTH + 1).saturating_sub(1);
+        let depth = (max_depth as usize).clamp(1, sq_capacity.max(1)) as u16;
+
         Self {
             id,
             sq: SpinMutex::new(SubmissionQueueState {
@@ -130,10 +144,8 @@ impl QueuePair {
             }),
             sq_doorbell: doorbell,
             pending: RwLock::new(BTreeMap::new()),
-            completions: ArrayQueue::new(max_depth as usize),
-            next_cmd_id: AtomicU16::new(0),
-            in_flight: AtomicU32::new(0),
-            max_depth,
+            completions: ArrayQueue::new(depth as usize),
+            tags: TagAllocator::new(depth),
             active: AtomicBool::new(true),
             stats: QueueStats::default(),
         }
@@ -144,31 +156,37 @@ impl QueuePair {
         Self::new(0, sq, cq, doorbell, 32) // Admin queue smaller
     }
 
-    /// Check if queue has space
-    pub fn has_space(&self) -> bool {
-        self.in_flight.load(Ordering::Relaxed) < self.max_depth as u32
+    /// Allocate a command ID, or park `packet` until one is free
+    ///
+    /// Returns `None` when the packet was parked; it's handed back with its
+    /// command ID by the [`QueuePair::free_cmd_id`] that frees one.
+    pub fn allocate_cmd_id(&self, packet: libredox::Packet) -> Option<u16> {
+        self.tags.alloc_or_wait(packet)
+    }
+
+    /// Allocate a command ID if one is free and no packet is parked
+    pub fn try_allocate_cmd_id(&self) -> Option<u16> {
+        self.tags.try_alloc()
     }
 
-    /// Get next command ID
-    fn allocate_cmd_id(&self) -> u16 {
-        self.next_cmd_id.fetch_add(1, Ordering::Relaxed)
+    /// Release the command ID of a finished or failed command
+    ///
+    /// If a packet is parked, the command ID is handed to it instead, and
+    /// the caller has to submit it.
+    pub fn free_cmd_id(&self, cmd_id: u16) -> Option<(u16, libredox::Packet)> {
+        self.tags.free(cmd_id)
     }
 
-    /// Submit a read command
+    /// Submit a read command using the allocated `cmd_id`
     pub fn submit_read(
         &self,
+        cmd_id: u16,
         ns_id: u32,
         lba: u64,
         blocks: u16,
         data_ptr: usize,
         size: usize,
     ) -> Option<u16> {
-        if !self.has_space() {
-            return None;
-        }
-
-        let cmd_id = self.allocate_cmd_id();
-
         // Build NVMe read command
         let cmd = NvmeCmd::io_read(
             cmd_id,
@@ -182,21 +200,16 @@ impl QueuePair {
         self.submit_command(cmd, size, false)
     }
 
-    /// Submit a write command
+    /// Submit a write command using the allocated `cmd_id`
     pub fn submit_write(
         &self,
+        cmd_id: u16,
         ns_id: u32,
         lba: u64,
         blocks: u16,
         data_ptr: usize,
         size: usize,
     ) -> Option<u16> {
-        if !self.has_space() {
-            return None;
-        }
-
-        let cmd_id = self.allocate_cmd_id();
-
         // Build NVMe write command
         let cmd = NvmeCmd::io_write(
             cmd_id,
@@ -210,9 +223,8 @@ impl QueuePair {
         self.submit_command(cmd, size, true)
     }
 
-    /// Submit a flush command
-    pub fn submit_flush(&self, ns_id: u32) -> Option<u16> {
-        let cmd_id = self.allocate_cmd_id();
+    /// Submit a flush command using the allocated `cmd_id`
+    pub fn submit_flush(&self, cmd_id: u16, ns_id: u32) -> Option<u16> {
         let cmd = NvmeCmd::io_flush(cmd_id, ns_id);
         self.submit_command(cmd, 0, false)
     }
@@ -239,7 +251,6 @@ impl QueuePair {
             self.sq_doorbell.write(sq.tail as u32);
         }
 
-        self.in_flight.fetch_add(1, Ordering::Relaxed);
         self.stats
             .commands_submitted
             .fetch_add(1, Ordering::Relaxed);
@@ -323,7 +334,6 @@ impl QueuePair {
                 self.sq_doorbell.cq_write(cq.head as u32);
             }
 
-            self.in_flight.fetch_sub(1, Ordering::Relaxed);
             self.stats
                 .commands_completed
                 .fetch_add(1, Ordering::Relaxed);
@@ -365,7 +375,7 @@ impl QueuePair {
 
     /// Wait for queue to become idle (no pending commands)
     pub fn wait_idle(&self) {
-        while self.in_flight.load(Ordering::Relaxed) > 0 {
+        while self.tags.in_use() > 0 {
             // Poll completions
             while self.poll_completion().is_some() {}
             std::thread::yield_now();
@@ -376,11 +386,13 @@ impl QueuePair {
     pub fn get_stats(&self) -> QueueStatsSnapshot {
         let completed = self.stats.commands_completed.load(Ordering::Relaxed);
         let total_latency = self.stats.total_latency_ns.load(Ordering::Relaxed);
+        let occupancy = self.tags.occupancy();
 
         QueueStatsSnapshot {
             commands_submitted: self.stats.commands_submitted.load(Ordering::Relaxed),
             commands_completed: completed,
-            commands_in_flight: self.in_flight.load(Ordering::Relaxed),
+            commands_in_flight: occupancy.in_use,
+            occupancy,
             bytes_read: self.stats.bytes_read.load(Ordering::Relaxed),
             bytes_written: self.stats.bytes_written.load(Ordering::Relaxed),
             avg_latency_ns: if completed > 0 {
@@ -395,7 +407,12 @@ impl QueuePair {
 
     /// Get current queue depth
     pub fn queue_depth(&self) -> u32 {
-        self.in_flight.load(Ordering::Relaxed)
+        self.tags.in_use() as u32
+    }
+
+    /// Get the maximum number of commands in flight
+    pub fn max_queue_depth(&self) -> u16 {
+        self.tags.depth()
     }
 }
 
@@ -405,6 +422,7 @@ pub struct QueueStatsSnapshot {
     pub commands_submitted: u64,
     pub commands_completed: u64,
     pub commands_in_flight: u32,
+    pub occupancy: TagOccupancy,
     pub bytes_read: u64,
     pub bytes_written: u64,
     pub avg_latency_ns: u64,
@@ -454,6 +472,7 @@ impl IoQueueManager {
             commands_submitted: 0,
             commands_completed: 0,
             commands_in_flight: 0,
+            occupancy: TagOccupancy::default(),
             bytes_read: 0,
             bytes_written: 0,
             avg_latency_ns: 0,
@@ -466,6 +485,11 @@ impl IoQueueManager {
             total.commands_submitted += stats.commands_submitted;
             total.commands_completed += stats.commands_completed;
             total.commands_in_flight += stats.commands_in_flight;
+            total.occupancy.depth += stats.occupancy.depth;
+            total.occupancy.in_use += stats.occupancy.in_use;
+            total.occupancy.max_in_use += stats.occupancy.max_in_use;
+            total.occupancy.waiters += stats.occupancy.waiters;
+            total.occupancy.exhausted += stats.occupancy.exhausted;
             total.bytes_read += stats.bytes_read;
             total.bytes_written += stats.bytes_written;
             total.avg_latency_ns += stats.avg_latency_ns;
@@ -498,11 +522,14 @@ pub trait IoQueue: Send + Sync {
 
 impl IoQueue for QueuePair {
     fn read(&self, ns_id: u32, lba: u64, blocks: u16, data: usize, size: usize) -> Option<u16> {
-        self.submit_read(ns_id, lba, blocks, data, size)
+        // Submission can't fail once a tag is held, the tags fit the queue
+        let cmd_id = self.tags.try_alloc()?;
+        self.submit_read(cmd_id, ns_id, lba, blocks, data, size)
     }
 
     fn write(&self, ns_id: u32, lba: u64, blocks: u16, data: usize, size: usize) -> Option<u16> {
-        self.submit_write(ns_id, lba, blocks, data, size)
+        let cmd_id = self.tags.try_alloc()?;
+        self.submit_write(cmd_id, ns_id, lba, blocks, data, size)
     }
 
     fn poll(&self) -> Option<CompletionInfo> {
@@ -6,7 +6,7 @@
 use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::bail;
 use crossbeam_queue::ArrayQueue;
@@ -20,7 +20,8 @@ use nvme::{
 };
 use syscall::{physmap, physunmap, Io, Physmap};
 
-use crate::queue::{IoQueue, PendingCommand, QueuePair};
+use crate::io_scheduler::{IoBatch, IoRequest, IoType, MergeQueue, RequestMerger};
+use crate::queue::{IoQueue, MergedCommand, PendingCommand, QueuePair};
 use crate::stats::GLOBAL_STATS;
 use crate::{DriverConfig, IoSchedulerType};
 
@@ -30,6 +31,18 @@ const MAX_NAMESPACES: usize = 256;
 /// Maximum commands in flight per queue
 const MAX_QUEUE_DEPTH: usize = 4096;
 
+/// Transfer size assumed when the controller doesn't limit it
+const DEFAULT_MAX_TRANSFER_SIZE: u32 = 1024 * 1024;
+
+/// Largest merged command, even if the controller allows more
+///
+/// Merging pays off for small requests, and every merged command needs a
+/// transfer buffer of its size.
+const MAX_MERGE_SIZE: usize = 1024 * 1024;
+
+/// Most requests merged into one command
+const MAX_MERGED_REQUESTS: usize = 64;
+
 /// NVMe namespace information
 #[derive(Debug, Clone)]
 pub struct NamespaceInfo {
@@ -66,6 +79,16 @@ pub struct SubmissionEntry {
     pub submitted_at: Instant,
 }
 
+/// Reads and writes held back for merging
+struct Merging {
+    queue: MergeQueue,
+    /// Packets of the queued requests, by request ID
+    packets: Mutex<BTreeMap<u64, libredox::Packet>>,
+    next_id: AtomicU64,
+    /// Largest transfer a request can have to be merged
+    max_size: usize,
+}
+
 /// NVMe scheme implementation
 pub struct NvmeScheme {
     /// PCI handle
@@ -86,6 +109,8 @@ pub struct NvmeScheme {
     config: DriverConfig,
     /// Admin queue for controller commands
     admin_queue: Arc<QueuePair>,
+    /// Request merging, if enabled
+    merging: Option<Merging>,
 }
 
 impl NvmeScheme {
@@ -109,6 +134,11 @@ impl NvmeScheme {
         info!("  Firmware: {:?}", ctrl_info.firmware_revision);
         info!("  Max Namespaces: {}", ctrl_info.nvm_ns_count);
 
+        // MDTS applies to every namespace of the controller
+        let max_transfer_size = ctrl_info
+            .max_transfer_size()
+            .unwrap_or(DEFAULT_MAX_TRANSFER_SIZE);
+
         for i in 0..ctrl_info.nvm_ns_count {
             let ns_id = i + 1;
             if let Some(ctrl) = nvme.namespace(ns_id) {
@@ -118,7 +148,7 @@ impl NvmeScheme {
                     block_size: ctrl.block_size() as u32,
                     blocks: ctrl.blocks(),
                     optimal_write_size: ctrl.optimal_write_size().unwrap_or(128 * 1024),
-                    max_transfer_size: ctrl.max_transfer_size().unwrap_or(1024 * 1024),
+                    max_transfer_size,
                 };
 
                 info!(
@@ -158,6 +188,24 @@ impl NvmeScheme {
             nvme.admin_doorbell(),
         ));
 
+        let merging = config.merge_requests.then(|| {
+            let max_size = (max_transfer_size as usize).min(MAX_MERGE_SIZE);
+            info!(
+                "Merging requests up to {} KiB, held back at most {}us",
+                max_size / 1024,
+                config.merge_delay_us
+            );
+            Merging {
+                queue: MergeQueue::new(
+                    RequestMerger::new(max_size, MAX_MERGED_REQUESTS),
+                    Duration::from_micros(config.merge_delay_us),
+                ),
+                packets: Mutex::new(BTreeMap::new()),
+                next_id: AtomicU64::new(0),
+                max_size,
+            }
+        });
+
         Ok(Self {
             pci_handle,
             nvme,
@@ -168,6 +216,7 @@ impl NvmeScheme {
             queue_counter: AtomicUsize::new(0),
             config: config.clone(),
             admin_queue,
+            merging,
         })
     }
 
@@ -224,14 +273,41 @@ impl NvmeScheme {
                 }
 
                 // Send response to caller
-                let mut packet = pending.packet;
-                packet.a = if completion.status == 0 {
-                    completion.bytes
-                } else {
-                    syscall::Error::new(syscall::EIO).to_errno()
-                };
+                match pending.merged {
+                    Some(merged) => {
+                        for (mut packet, offset) in merged.requests {
+                            packet.a = if completion.status == 0 {
+                                if !pending.is_write {
+                                    let data = unsafe {
+                                        std::slice::from_raw_parts_mut(
+                                            packet.c as *mut u8,
+                                            packet.d,
+                                        )
+                                    };
+                                    data.copy_from_slice(&merged.buffer[offset..offset + packet.d]);
+                                }
+                                packet.d
+                            } else {
+                                syscall::Error::new(syscall::EIO).to_errno()
+                            };
+
+                            let _ = syscall::write(self.pci_handle, &packet);
+                        }
+                    }
+                    None => {
+                        let mut packet = pending.packet;
+                        packet.a = if completion.status == 0 {
+                            completion.bytes
+                        } else {
+                            syscall::Error::new(syscall::EIO).to_errno()
+                        };
+
+                        let _ = syscall::write(self.pci_handle, &packet);
+                    }
+                }
 
-                let _ = syscall::write(self.pci_handle, &packet);
+                // Only now can the command ID be used again
+                self.release_cmd_id(queue_id, completion.command_id);
             }
 
             count += 1;
@@ -240,8 +316,66 @@ impl NvmeScheme {
         count
     }
 
+    /// Allocate a command ID on a queue and submit the request, or park it
+    /// until a command ID is free
+    ///
+    /// Returns whether `packet` holds the reply, rather than it being sent
+    /// once the command completes.
+    fn start_command(&self, queue_id: usize, packet: &mut libredox::Packet) -> bool {
+        let Some(cmd_id) = self.queues[queue_id].allocate_cmd_id(*packet) else {
+            trace!("nvme: queue {} full, parking request", queue_id);
+            return false;
+        };
+
+        match self.submit_command(queue_id, cmd_id, packet) {
+            Ok(()) => false,
+            Err(err) => {
+                self.release_cmd_id(queue_id, cmd_id);
+                packet.a = err.to_errno();
+                true
+            }
+        }
+    }
+
+    /// Release a command ID, submitting the parked requests it's handed to
+    fn release_cmd_id(&self, queue_id: usize, cmd_id: u16) {
+        let queue = &self.queues[queue_id];
+        let mut next = queue.free_cmd_id(cmd_id);
+
+        while let Some((cmd_id, mut packet)) = next {
+            next = match self.submit_command(queue_id, cmd_id, &mut packet) {
+                Ok(()) => None,
+                Err(err) => {
+                    packet.a = err.to_errno();
+                    let _ = syscall::write(self.pci_handle, &packet);
+                    queue.free_cmd_id(cmd_id)
+                }
+            };
+        }
+    }
+
+    /// Submit the request in `packet` using an allocated command ID
+    fn submit_command(
+        &self,
+        queue_id: usize,
+        cmd_id: u16,
+        packet: &mut libredox::Packet,
+    ) -> syscall::Result<()> {
+        let (a, _, _, _) = libredox::flag::decode_usize(packet.a);
+
+        match a {
+            libredox::flag::SYS_READ => self.submit_io(queue_id, cmd_id, packet, false),
+            libredox::flag::SYS_WRITE => self.submit_io(queue_id, cmd_id, packet, true),
+            libredox::flag::SYS_FSYNC => self.submit_flush(queue_id, cmd_id, packet),
+            _ => Err(syscall::Error::new(syscall::ENOSYS)),
+        }
+    }
+
     /// Handle a scheme packet
-    pub fn handle(&mut self, packet: &mut libredox::Packet) {
+    ///
+    /// Returns `false` if the request completes asynchronously, in which
+    /// case the reply is sent when its command finishes.
+    pub fn handle(&mut self, packet: &mut libredox::Packet) -> bool {
         let (a, b, c, d) = libredox::flag::decode_usize(packet.a);
 
         match (a, b, c, d) {
@@ -249,10 +383,10 @@ impl NvmeScheme {
                 self.handle_open(packet);
             }
             (libredox::flag::SYS_READ, _, _, _) => {
-                self.handle_read(packet);
+                return self.handle_read(packet);
             }
             (libredox::flag::SYS_WRITE, _, _, _) => {
-                self.handle_write(packet);
+                return self.handle_write(packet);
             }
             (libredox::flag::SYS_FSTAT, _, _, _) => {
                 self.handle_fstat(packet);
@@ -264,7 +398,7 @@ impl NvmeScheme {
                 self.handle_lseek(packet);
             }
             (libredox::flag::SYS_FSYNC, _, _, _) => {
-                self.handle_fsync(packet);
+                return self.handle_fsync(packet);
             }
             (libredox::flag::SYS_CLOSE, _, _, _) => {
                 self.handle_close(packet);
@@ -278,6 +412,8 @@ impl NvmeScheme {
                 packet.a = syscall::Error::new(syscall::ENOSYS).to_errno();
             }
         }
+
+        true
     }
 
     /// Handle SYS_OPEN
@@ -336,97 +472,219 @@ impl NvmeScheme {
     }
 
     /// Handle SYS_READ - async read with zero-copy support
-    fn handle_read(&mut self, packet: &mut libredox::Packet) {
-        let handle_id = packet.b as u64;
-        let offset = packet.e as u64;
-        let size = packet.d;
+    fn handle_read(&mut self, packet: &mut libredox::Packet) -> bool {
+        let queue_id = match self.handles.read().get(&(packet.b as u64)) {
+            Some(handle) => self.select_queue(handle),
+            None => {
+                packet.a = syscall::Error::new(syscall::EBADF).to_errno();
+                return true;
+            }
+        };
 
-        let handles = self.handles.read();
-        let handle = match handles.get(&handle_id) {
-            Some(h) => h,
+        if self.queue_for_merging(queue_id, packet, false) {
+            return false;
+        }
+
+        self.start_command(queue_id, packet)
+    }
+
+    /// Handle SYS_WRITE - async write with zero-copy support
+    fn handle_write(&mut self, packet: &mut libredox::Packet) -> bool {
+        let queue_id = match self.handles.read().get(&(packet.b as u64)) {
+            Some(handle) => self.select_queue(handle),
             None => {
                 packet.a = syscall::Error::new(syscall::EBADF).to_errno();
-                return;
+                return true;
             }
         };
 
-        let queue_id = self.select_queue(handle);
-        let queue = &self.queues[queue_id];
+        if self.queue_for_merging(queue_id, packet, true) {
+            return false;
+        }
+
+        self.start_command(queue_id, packet)
+    }
+
+    /// Hold a read or write back for merging, returning whether it was
+    fn queue_for_merging(
+        &self,
+        queue_id: usize,
+        packet: &libredox::Packet,
+        is_write: bool,
+    ) -> bool {
+        let Some(merging) = &self.merging else {
+            return false;
+        };
+
+        // Zero-copy requests are transferred in place
+        if self.config.zero_copy && packet.c & 1 == 1 {
+            return false;
+        }
+
+        let handles = self.handles.read();
+        let Some(handle) = handles.get(&(packet.b as u64)) else {
+            return false;
+        };
         let ns_info = &handle.ns_info;
 
-        // Calculate LBA and block count
-        let lba = offset / ns_info.block_size as u64;
-        let blocks =
-            ((size + ns_info.block_size as usize - 1) / ns_info.block_size as usize) as u16;
+        // Only requests covering whole blocks can share a command
+        let block_size = ns_info.block_size as usize;
+        let offset = packet.e;
+        let size = packet.d;
+        if size == 0
+            || size > merging.max_size
+            || size % block_size != 0
+            || offset % block_size != 0
+        {
+            return false;
+        }
 
-        // Zero-copy mode: use physical address directly
-        let (phys, data_ptr) = if self.config.zero_copy && (packet.c & 1 == 1) {
-            // Physical address passed in
-            let phys_addr = packet.c & !1;
-            let phys = match unsafe { physmap(phys_addr, size, 0) } {
-                Ok(p) => p,
-                Err(_) => {
-                    packet.a = syscall::Error::new(syscall::EFAULT).to_errno();
-                    return;
-                }
-            };
-            (Some(phys), phys.address)
+        let id = merging.next_id.fetch_add(1, Ordering::Relaxed);
+        let lba = (offset / block_size) as u64;
+        let blocks = (size / block_size) as u16;
+        let request = if is_write {
+            IoRequest::write(id, ns_info.id, lba, blocks, packet.c, size)
         } else {
-            // Virtual address - need to allocate DMA buffer
-            (None, packet.c)
+            IoRequest::read(id, ns_info.id, lba, blocks, packet.c, size)
         };
 
-        // Submit read command
-        let cmd_id = match queue.submit_read(ns_info.id, lba, blocks, data_ptr, size) {
-            Some(id) => id,
-            None => {
-                // Queue full
-                if let Some(p) = phys {
-                    unsafe {
-                        let _ = physunmap(p.address, p.size);
-                    }
+        merging.packets.lock().insert(id, *packet);
+        merging.queue.push(request.with_queue_hint(queue_id));
+        true
+    }
+
+    /// When the oldest request held back for merging is due for dispatch
+    pub fn next_merge_deadline(&self) -> Option<Instant> {
+        self.merging
+            .as_ref()
+            .and_then(|merging| merging.queue.next_deadline())
+    }
+
+    /// Submit the merged requests due for dispatch at `now`
+    pub fn dispatch_merged(&self, now: Instant) {
+        let Some(merging) = &self.merging else {
+            return;
+        };
+
+        for batch in merging.queue.take_due(now) {
+            self.submit_batch(merging, batch);
+        }
+    }
+
+    /// Submit a batch of merged requests as a single command
+    fn submit_batch(&self, merging: &Merging, batch: IoBatch) {
+        let queue_id = batch.requests[0].queue_hint.unwrap_or(0);
+        let requests: Vec<_> = {
+            let mut packets = merging.packets.lock();
+            batch
+                .requests
+                .iter()
+                .filter_map(|request| {
+                    Some((packets.remove(&request.id)?, batch.offset_of(request)))
+                })
+                .collect()
+        };
+
+        // A lone request, or one finding no command ID free, takes the usual
+        // path and waits for a command ID there
+        let queue = &self.queues[queue_id];
+        let cmd_id = match requests.len() {
+            0 | 1 => None,
+            _ => queue.try_allocate_cmd_id(),
+        };
+        let Some(cmd_id) = cmd_id else {
+            for (mut packet, _) in requests {
+                if self.start_command(queue_id, &mut packet) {
+                    let _ = syscall::write(self.pci_handle, &packet);
                 }
-                packet.a = syscall::Error::new(syscall::EAGAIN).to_errno();
-                return;
             }
+            return;
         };
 
-        // Store pending request
+        let is_write = batch.io_type == IoType::Write;
+        let mut buffer = vec![0u8; batch.total_size];
+        if is_write {
+            // In submission order, so the latest of overlapping writes wins
+            for (packet, offset) in &requests {
+                let data = unsafe { std::slice::from_raw_parts(packet.c as *const u8, packet.d) };
+                buffer[*offset..*offset + packet.d].copy_from_slice(data);
+            }
+        }
+
+        let blocks = batch.total_blocks as u16;
+        let data_ptr = buffer.as_ptr() as usize;
+        let submitted = if is_write {
+            queue.submit_write(
+                cmd_id,
+                batch.ns_id,
+                batch.start_lba,
+                blocks,
+                data_ptr,
+                buffer.len(),
+            )
+        } else {
+            queue.submit_read(
+                cmd_id,
+                batch.ns_id,
+                batch.start_lba,
+                blocks,
+                data_ptr,
+                buffer.len(),
+            )
+        };
+        if submitted.is_none() {
+            // Submission ring full
+            self.release_cmd_id(queue_id, cmd_id);
+            for (mut packet, _) in requests {
+                packet.a = syscall::Error::new(syscall::EAGAIN).to_errno();
+                let _ = syscall::write(self.pci_handle, &packet);
+            }
+            return;
+        }
+
+        trace!(
+            "nvme: merged {} requests into {} blocks at LBA {}",
+            requests.len(),
+            blocks,
+            batch.start_lba
+        );
+
         queue.add_pending(
             cmd_id,
             PendingCommand {
-                packet: *packet,
-                phys,
+                packet: requests[0].0,
+                phys: None,
                 submitted_at: Instant::now(),
-                is_write: false,
-                bytes: size,
+                is_write,
+                bytes: buffer.len(),
+                merged: Some(MergedCommand { buffer, requests }),
             },
         );
 
         #[cfg(feature = "performance-counters")]
         {
-            GLOBAL_STATS.record_io_submit(size, false);
+            GLOBAL_STATS.record_io_submit(batch.total_size, is_write);
         }
-
-        // Don't set packet.a - completion will be async
     }
 
-    /// Handle SYS_WRITE - async write with zero-copy support
-    fn handle_write(&mut self, packet: &mut libredox::Packet) {
+    /// Submit a read or write command
+    fn submit_io(
+        &self,
+        queue_id: usize,
+        cmd_id: u16,
+        packet: &mut libredox::Packet,
+        is_write: bool,
+    ) -> syscall::Result<()> {
         let handle_id = packet.b as u64;
         let offset = packet.e as u64;
         let size = packet.d;
 
+        // The handle may have been closed while the request was parked
         let handles = self.handles.read();
-        let handle = match handles.get(&handle_id) {
-            Some(h) => h,
-            None => {
-                packet.a = syscall::Error::new(syscall::EBADF).to_errno();
-                return;
-            }
-        };
+        let handle = handles
+            .get(&handle_id)
+            .ok_or(syscall::Error::new(syscall::EBADF))?;
 
-        let queue_id = self.select_queue(handle);
         let queue = &self.queues[queue_id];
         let ns_info = &handle.ns_info;
 
@@ -435,50 +693,53 @@ impl NvmeScheme {
         let blocks =
             ((size + ns_info.block_size as usize - 1) / ns_info.block_size as usize) as u16;
 
-        // Zero-copy mode
+        // Zero-copy mode: use physical address directly
         let (phys, data_ptr) = if self.config.zero_copy && (packet.c & 1 == 1) {
+            // Physical address passed in
             let phys_addr = packet.c & !1;
-            let phys = match unsafe { physmap(phys_addr, size, 0) } {
-                Ok(p) => p,
-                Err(_) => {
-                    packet.a = syscall::Error::new(syscall::EFAULT).to_errno();
-                    return;
-                }
-            };
+            let phys = unsafe { physmap(phys_addr, size, 0) }
+                .map_err(|_| syscall::Error::new(syscall::EFAULT))?;
             (Some(phys), phys.address)
         } else {
+            // Virtual address - need to allocate DMA buffer
             (None, packet.c)
         };
 
-        // Submit write command
-        let cmd_id = match queue.submit_write(ns_info.id, lba, blocks, data_ptr, size) {
-            Some(id) => id,
-            None => {
-                if let Some(p) = phys {
-                    unsafe {
-                        let _ = physunmap(p.address, p.size);
-                    }
+        let submitted = if is_write {
+            queue.submit_write(cmd_id, ns_info.id, lba, blocks, data_ptr, size)
+        } else {
+            queue.submit_read(cmd_id, ns_info.id, lba, blocks, data_ptr, size)
+        };
+        if submitted.is_none() {
+            // Submission ring full
+            if let Some(p) = phys {
+                unsafe {
+                    let _ = physunmap(p.address, p.size);
                 }
-                packet.a = syscall::Error::new(syscall::EAGAIN).to_errno();
-                return;
             }
-        };
+            return Err(syscall::Error::new(syscall::EAGAIN));
+        }
 
+        // Store pending request
         queue.add_pending(
             cmd_id,
             PendingCommand {
                 packet: *packet,
                 phys,
                 submitted_at: Instant::now(),
-                is_write: true,
+                is_write,
                 bytes: size,
+                merged: None,
             },
         );
 
         #[cfg(feature = "performance-counters")]
         {
-            GLOBAL_STATS.record_io_submit(size, true);
+            GLOBAL_STATS.record_io_submit(size, is_write);
         }
+
+        // Don't set packet.a - completion will be async
+        Ok(())
     }
 
     /// Handle SYS_FSTAT
@@ -563,35 +824,48 @@ impl NvmeScheme {
     }
 
     /// Handle SYS_FSYNC - flush writes to stable storage
-    fn handle_fsync(&mut self, packet: &mut libredox::Packet) {
-        let handle_id = packet.b as u64;
-
-        let handles = self.handles.read();
-        let handle = match handles.get(&handle_id) {
-            Some(h) => h,
+    fn handle_fsync(&mut self, packet: &mut libredox::Packet) -> bool {
+        let queue_id = match self.handles.read().get(&(packet.b as u64)) {
+            Some(handle) => handle.queue_id,
             None => {
                 packet.a = syscall::Error::new(syscall::EBADF).to_errno();
-                return;
+                return true;
             }
         };
 
-        let queue = &self.queues[handle.queue_id];
+        self.start_command(queue_id, packet)
+    }
 
-        // Submit flush command
-        if let Some(cmd_id) = queue.submit_flush(handle.ns_info.id) {
-            queue.add_pending(
-                cmd_id,
-                PendingCommand {
-                    packet: *packet,
-                    phys: None,
-                    submitted_at: Instant::now(),
-                    is_write: false,
-                    bytes: 0,
-                },
-            );
-        } else {
-            packet.a = syscall::Error::new(syscall::EAGAIN).to_errno();
-        }
+    /// Submit a flush command
+    fn submit_flush(
+        &self,
+        queue_id: usize,
+        cmd_id: u16,
+        packet: &mut libredox::Packet,
+    ) -> syscall::Result<()> {
+        let handles = self.handles.read();
+        let handle = handles
+            .get(&(packet.b as u64))
+            .ok_or(syscall::Error::new(syscall::EBADF))?;
+
+        let queue = &self.queues[queue_id];
+
+        queue
+            .submit_flush(cmd_id, handle.ns_info.id)
+            .ok_or(syscall::Error::new(syscall::EAGAIN))?;
+        queue.add_pending(
+            cmd_id,
+            PendingCommand {
+                packet: *packet,
+                phys: None,
+                submitted_at: Instant::now(),
+                is_write: false,
+                bytes: 0,
+                merged: None,
+            },
+        );
+
+        Ok(())
     }
 
     /// Handle SYS_CLOSE
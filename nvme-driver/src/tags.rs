@@ -0,0 +1,191 @@
+// SPDX-FileCopyrightText: 2024 Redox OS Developers
+// SPDX-License-Identifier: MIT
+
+//! Command tag allocation
+//!
+//! Every command in flight on a queue pair needs a command ID that no other
+//! outstanding command uses. Tags are handed out from a bitmap sized to the
+//! queue depth, so the number of commands in flight can never exceed it and
+//! an ID is only reused once its completion has been processed.
+//!
+//! When all tags are taken, requests wait in FIFO order and each freed tag is
+//! handed straight to the oldest waiter, so new requests can't overtake
+//! parked ones.
+
+use std::collections::VecDeque;
+
+use parking_lot::Mutex;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Occupancy of a tag allocator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TagOccupancy {
+    /// Number of tags, i.e. the enforced queue depth
+    pub depth: u32,
+    /// Tags currently allocated
+    pub in_use: u32,
+    /// Highest number of tags allocated at once
+    pub max_in_use: u32,
+    /// Requests waiting for a tag
+    pub waiters: usize,
+    /// Times a request found every tag taken
+    pub exhausted: u64,
+}
+
+struct TagState<W> {
+    /// One bit per tag, set while allocated
+    bitmap: Vec<u64>,
+    /// Where the next search starts, so freed tags aren't reused right away
+    hint: usize,
+    waiters: VecDeque<W>,
+    in_use: u16,
+    max_in_use: u16,
+    exhausted: u64,
+}
+
+/// Bitmap tag allocator of one queue pair
+///
+/// `W` is whatever a waiting request needs to be resumed once it's given a
+/// tag.
+pub struct TagAllocator<W> {
+    depth: u16,
+    state: Mutex<TagState<W>>,
+}
+
+impl<W> TagAllocator<W> {
+    /// Create an allocator of `depth` tags, numbered `0..depth`
+    pub fn new(depth: u16) -> Self {
+        assert!(depth > 0, "nvme: tag allocator needs at least one tag");
+
+        let words = (depth as usize).div_ceil(WORD_BITS);
+        let mut bitmap = vec![0; words];
+
+        // Mark the bits past the last tag as allocated so they're never found
+        let tail = depth as usize % WORD_BITS;
+        if tail != 0 {
+            bitmap[words - 1] = !0 << tail;
+        }
+
+        Self {
+            depth,
+            state: Mutex::new(TagState {
+                bitmap,
+                hint: 0,
+                waiters: VecDeque::new(),
+                in_use: 0,
+                max_in_use: 0,
+                exhausted: 0,
+            }),
+        }
+    }
+
+    /// Number of tags
+    pub fn depth(&self) -> u16 {
+        self.depth
+    }
+
+    /// Allocate a tag, or queue `waiter` if none is free
+    ///
+    /// Returns `None` when the waiter was queued; it's handed a tag by a
+    /// later [`TagAllocator::free`]. Requests also queue while others are
+    /// already waiting, to keep the wakeup order fair.
+    pub fn alloc_or_wait(&self, waiter: W) -> Option<u16> {
+        let mut state = self.state.lock();
+        if state.waiters.is_empty()
+            && let Some(tag) = state.alloc()
+        {
+            return Some(tag);
+        }
+        state.exhausted += 1;
+        state.waiters.push_back(waiter);
+        None
+    }
+
+    /// Allocate a tag if one is free and nobody is waiting for it
+    pub fn try_alloc(&self) -> Option<u16> {
+        let mut state = self.state.lock();
+        let tag = if state.waiters.is_empty() {
+            state.alloc()
+        } else {
+            None
+        };
+        if tag.is_none() {
+            state.exhausted += 1;
+        }
+        tag
+    }
+
+    /// Release `tag`
+    ///
+    /// If a request is waiting, the tag goes straight to it and is returned
+    /// with the waiter; the caller has to resume that request. Freeing a tag
+    /// that isn't allocated is ignored and logged, since that means a
+    /// completion was processed twice.
+    pub fn free(&self, tag: u16) -> Option<(u16, W)> {
+        let mut state = self.state.lock();
+        let (word, bit) = (tag as usize / WORD_BITS, tag as usize % WORD_BITS);
+        if tag >= self.depth || state.bitmap[word] & (1 << bit) == 0 {
+            log::error!("nvme: freeing tag {} that isn't allocated", tag);
+            return None;
+        }
+
+        match state.waiters.pop_front() {
+            Some(waiter) => Some((tag, waiter)),
+            None => {
+                state.bitmap[word] &= !(1 << bit);
+                state.in_use -= 1;
+                None
+            }
+        }
+    }
+
+    /// Number of tags currently allocated
+    pub fn in_use(&self) -> u16 {
+        self.state.lock().in_use
+    }
+
+    /// Snapshot of the allocator's occupancy
+    pub fn occupancy(&self) -> TagOccupancy {
+        let state = self.state.lock();
+        TagOccupancy {
+            depth: self.depth.into(),
+            in_use: state.in_use.into(),
+            max_in_use: state.max_in_use.into(),
+            waiters: state.waiters.len(),
+            exhausted: state.exhausted,
+        }
+    }
+}
+
+impl<W> TagState<W> {
+    fn alloc(&mut self) -> Option<u16> {
+        let words = self.bitmap.len();
+        let start = self.hint / WORD_BITS;
+
+        for i in 0..=words {
+            let word = (start + i) % words;
+            let mut free = !self.bitmap[word];
+            // Start in the middle of the first word, and come back for its
+            // lower bits at the end
+            if i == 0 {
+                free &= !0 << (self.hint % WORD_BITS);
+            } else if i == words {
+                free &= !(!0 << (self.hint % WORD_BITS));
+            }
+            if free == 0 {
+                continue;
+            }
+
+            let bit = free.trailing_zeros() as usize;
+            self.bitmap[word] |= 1 << bit;
+            let tag = word * WORD_BITS + bit;
+            self.hint = tag + 1;
+            self.in_use += 1;
+            self.max_in_use = self.max_in_use.max(self.in_use);
+            return Some(tag as u16);
+        }
+
+        None
+    }
+}
@@ -1,5 +1,5 @@
 use std::fs;
-use std::process::Command;
+use std::path::Path;
 
 use anyhow::{anyhow, Context, Result};
 
@@ -20,18 +20,7 @@ fn main() -> Result<()> {
         common::file_level(),
     );
 
-    let config_data = if fs::metadata(&config_path)?.is_file() {
-        fs::read_to_string(&config_path)?
-    } else {
-        let mut config_data = String::new();
-        for path in fs::read_dir(&config_path)? {
-            if let Ok(tmp) = fs::read_to_string(path.unwrap().path()) {
-                config_data.push_str(&tmp);
-            }
-        }
-        config_data
-    };
-    let config: Config = toml::from_str(&config_data)?;
+    let config = Config::load(Path::new(&config_path))?;
 
     for entry in fs::read_dir("/scheme/pci")? {
         let entry = entry.context("failed to get entry")?;
@@ -58,31 +47,20 @@ fn main() -> Result<()> {
             full_device_id.display()
         );
 
-        let Some(driver) = config
-            .drivers
-            .iter()
-            .find(|driver| driver.match_function(&full_device_id))
-        else {
+        let Some(driver) = config.find_driver(&full_device_id) else {
             log::debug!("no driver for {}, continuing", handle.config().func.addr);
             continue;
         };
 
-        let mut args = driver.command.iter();
-
-        let program = args
-            .next()
+        let mut command = driver
+            .build_command()
             .ok_or_else(|| anyhow!("driver configuration entry did not have any command!"))?;
-        let program = if program.starts_with('/') {
-            program.to_owned()
-        } else {
-            "/usr/lib/drivers/".to_owned() + program
-        };
-
-        let mut command = Command::new(program);
-        command.args(args);
 
         log::info!("pcid-spawner: spawn {:?}", command);
 
+        if let Some(name) = driver.driver_name() {
+            handle.set_driver_name(name);
+        }
         handle.enable_device();
 
         let channel_fd = handle.into_inner_fd();
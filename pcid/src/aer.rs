@@ -0,0 +1,187 @@
+//! PCIe Advanced Error Reporting.
+//!
+//! Without a handler, errors latched in the AER status registers are never looked at, so a
+//! misbehaving device fails silently. pcid polls every function that has the AER capability, logs
+//! what it finds, clears the (write-1-to-clear) status bits and keeps counters that are exposed
+//! through the `aer` file of each device in the pci scheme.
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+
+use crate::cfg_access::Pcie;
+use crate::ext_cap::ExtendedCapability;
+
+const UNCOR_STATUS: u16 = 0x04;
+const UNCOR_SEVERITY: u16 = 0x0C;
+const COR_STATUS: u16 = 0x10;
+const CAP_CONTROL: u16 = 0x18;
+const HEADER_LOG: u16 = 0x1C;
+
+// TODO: Use the root port's AER MSI once pcid can receive interrupts itself.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const UNCOR_BITS: &[(u32, &str)] = &[
+    (1 << 4, "data link protocol error"),
+    (1 << 5, "surprise down"),
+    (1 << 12, "poisoned TLP"),
+    (1 << 13, "flow control protocol error"),
+    (1 << 14, "completion timeout"),
+    (1 << 15, "completer abort"),
+    (1 << 16, "unexpected completion"),
+    (1 << 17, "receiver overflow"),
+    (1 << 18, "malformed TLP"),
+    (1 << 19, "ECRC error"),
+    (1 << 20, "unsupported request"),
+    (1 << 21, "ACS violation"),
+    (1 << 22, "uncorrectable internal error"),
+];
+
+const COR_BITS: &[(u32, &str)] = &[
+    (1 << 0, "receiver error"),
+    (1 << 6, "bad TLP"),
+    (1 << 7, "bad DLLP"),
+    (1 << 8, "replay num rollover"),
+    (1 << 12, "replay timer timeout"),
+    (1 << 13, "advisory non-fatal"),
+    (1 << 14, "corrected internal error"),
+    (1 << 15, "header log overflow"),
+];
+
+#[derive(Debug, Default)]
+pub struct AerCounters {
+    correctable: AtomicU64,
+    nonfatal: AtomicU64,
+    fatal: AtomicU64,
+    last_cor_status: AtomicU32,
+    last_uncor_status: AtomicU32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Aer {
+    addr: PciAddress,
+    offset: u16,
+    counters: Arc<AerCounters>,
+}
+
+impl Aer {
+    pub fn new(addr: PciAddress, cap: ExtendedCapability) -> Self {
+        Self {
+            addr,
+            offset: cap.offset,
+            counters: Arc::new(AerCounters::default()),
+        }
+    }
+
+    fn read(&self, pcie: &Pcie, reg: u16) -> u32 {
+        unsafe { pcie.read(self.addr, self.offset + reg) }
+    }
+    fn write(&self, pcie: &Pcie, reg: u16, value: u32) {
+        unsafe { pcie.write(self.addr, self.offset + reg, value) }
+    }
+
+    /// Check the error status registers once, logging and clearing anything that is set.
+    pub fn poll(&self, pcie: &Pcie) {
+        let uncor = self.read(pcie, UNCOR_STATUS);
+        if uncor != 0 {
+            let severity = self.read(pcie, UNCOR_SEVERITY);
+            let fatal = uncor & severity;
+            let nonfatal = uncor & !severity;
+
+            let header_log = [0, 4, 8, 12].map(|i| self.read(pcie, HEADER_LOG + i));
+            let first_error = self.read(pcie, CAP_CONTROL) & 0x1F;
+
+            log::error!(
+                "pcid: {} uncorrectable error(s) [{}], first error bit {}, header log {:08X?}",
+                self.addr,
+                describe(uncor, UNCOR_BITS),
+                first_error,
+                header_log,
+            );
+
+            self.counters
+                .fatal
+                .fetch_add(u64::from(fatal.count_ones()), Ordering::Relaxed);
+            self.counters
+                .nonfatal
+                .fetch_add(u64::from(nonfatal.count_ones()), Ordering::Relaxed);
+            self.counters
+                .last_uncor_status
+                .store(uncor, Ordering::Relaxed);
+
+            self.write(pcie, UNCOR_STATUS, uncor);
+        }
+
+        let cor = self.read(pcie, COR_STATUS);
+        if cor != 0 {
+            log::warn!(
+                "pcid: {} correctable error(s) [{}]",
+                self.addr,
+                describe(cor, COR_BITS),
+            );
+
+            self.counters
+                .correctable
+                .fetch_add(u64::from(cor.count_ones()), Ordering::Relaxed);
+            self.counters.last_cor_status.store(cor, Ordering::Relaxed);
+
+            self.write(pcie, COR_STATUS, cor);
+        }
+    }
+
+    /// Render the counters as the contents of the `aer` scheme file.
+    pub fn report(&self) -> String {
+        let c = &self.counters;
+        format!(
+            "correctable: {}\nnonfatal: {}\nfatal: {}\nlast_correctable_status: {:#010x}\nlast_uncorrectable_status: {:#010x}\n",
+            c.correctable.load(Ordering::Relaxed),
+            c.nonfatal.load(Ordering::Relaxed),
+            c.fatal.load(Ordering::Relaxed),
+            c.last_cor_status.load(Ordering::Relaxed),
+            c.last_uncor_status.load(Ordering::Relaxed),
+        )
+    }
+}
+
+fn describe(status: u32, bits: &[(u32, &str)]) -> String {
+    let mut string = String::new();
+    let mut known = 0;
+    for &(bit, name) in bits {
+        if status & bit != 0 {
+            known |= bit;
+            if !string.is_empty() {
+                string.push_str(", ");
+            }
+            string.push_str(name);
+        }
+    }
+    if status & !known != 0 {
+        if !string.is_empty() {
+            string.push_str(", ");
+        }
+        let _ = write!(string, "unknown {:#x}", status & !known);
+    }
+    string
+}
+
+/// Spawn the background thread that polls all AER capable functions.
+pub fn spawn_handler(pcie: Arc<Pcie>, devices: Vec<Aer>) {
+    if devices.is_empty() {
+        return;
+    }
+    log::debug!("pcid: watching {} function(s) for AER errors", devices.len());
+
+    thread::Builder::new()
+        .name("pcid-aer".to_owned())
+        .spawn(move || loop {
+            for aer in &devices {
+                aer.poll(&pcie);
+            }
+            thread::sleep(POLL_INTERVAL);
+        })
+        .expect("pcid: failed to spawn AER handler thread");
+}
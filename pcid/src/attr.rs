@@ -0,0 +1,299 @@
+//! Per-function attribute files, so diagnostic tools can inspect a function without binding a
+//! driver to it.
+
+use std::fmt::Write;
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+use pcid_interface::PciBar;
+
+use crate::cfg_access::Pcie;
+use crate::vpd::Vpd;
+use crate::{ext_cap, Func};
+
+const STATUS: u16 = 0x04;
+const SUBSYSTEM: u16 = 0x2C;
+const STATUS_CAP_LIST: u32 = 1 << (16 + 4);
+const CAP_POINTER: u16 = 0x34;
+const INTERRUPT: u16 = 0x3C;
+
+// Resource flags as reported by Linux.
+const IORESOURCE_IO: u64 = 0x100;
+const IORESOURCE_MEM: u64 = 0x200;
+const IORESOURCE_PREFETCH: u64 = 0x2000;
+const IORESOURCE_MEM_64: u64 = 0x10_0000;
+
+const BAR_NAMES: [&str; 6] = ["bar0", "bar1", "bar2", "bar3", "bar4", "bar5"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attr {
+    Vendor,
+    Device,
+    SubsystemVendor,
+    SubsystemDevice,
+    Class,
+    Revision,
+    /// Legacy interrupt line, 0 if the function has no INTx pin.
+    Irq,
+    /// Proximity domain of the function.
+    NumaNode,
+    /// Driver bound to the function, only present while one is and it told pcid its name.
+    Driver,
+    /// Start, end and flags of every BAR and the expansion ROM, one per line.
+    Resource,
+    /// Device Serial Number, only present if the function has one.
+    SerialNumber,
+    /// Identifier that persists across reboots, see [`pcid_interface::UniqueId`].
+    UniqueId,
+    /// Raw configuration space.
+    Config,
+    /// Capabilities and vital product data, for lspci-like tools.
+    Info,
+}
+
+impl Attr {
+    const ALL: [Attr; 14] = [
+        Attr::Vendor,
+        Attr::Device,
+        Attr::SubsystemVendor,
+        Attr::SubsystemDevice,
+        Attr::Class,
+        Attr::Revision,
+        Attr::Irq,
+        Attr::NumaNode,
+        Attr::Driver,
+        Attr::Resource,
+        Attr::SerialNumber,
+        Attr::UniqueId,
+        Attr::Config,
+        Attr::Info,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Attr::Vendor => "vendor",
+            Attr::Device => "device",
+            Attr::SubsystemVendor => "subsystem_vendor",
+            Attr::SubsystemDevice => "subsystem_device",
+            Attr::Class => "class",
+            Attr::Revision => "revision",
+            Attr::Irq => "irq",
+            Attr::NumaNode => "numa_node",
+            Attr::Driver => "driver",
+            Attr::Resource => "resource",
+            Attr::SerialNumber => "serial_number",
+            Attr::UniqueId => "unique_id",
+            Attr::Config => "config",
+            Attr::Info => "info",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|attr| attr.name() == name)
+    }
+
+    pub fn is_present(self, func: &Func) -> bool {
+        match self {
+            Attr::SerialNumber => func
+                .unique_id
+                .as_ref()
+                .is_some_and(|id| id.serial_number.is_some()),
+            Attr::Driver => func.driver.is_some(),
+            _ => true,
+        }
+    }
+
+    /// Only root may read the full configuration space, as reads of some device specific
+    /// registers have side effects. Reading VPD needs writes to the configuration space.
+    pub fn requires_root(self) -> bool {
+        matches!(self, Attr::Config | Attr::Info)
+    }
+
+    pub fn read(self, pcie: &Pcie, func: &Func) -> Vec<u8> {
+        let id = &func.inner.full_device_id;
+        let addr = func.inner.addr;
+        let subsystem = || unsafe { pcie.read(addr, SUBSYSTEM) };
+        let unique_id = func.unique_id.as_ref();
+
+        match self {
+            Attr::Vendor => format!("0x{:04x}\n", id.vendor_id).into_bytes(),
+            Attr::Device => format!("0x{:04x}\n", id.device_id).into_bytes(),
+            Attr::SubsystemVendor => format!("0x{:04x}\n", subsystem() & 0xFFFF).into_bytes(),
+            Attr::SubsystemDevice => format!("0x{:04x}\n", subsystem() >> 16).into_bytes(),
+            Attr::Class => format!(
+                "0x{:02x}{:02x}{:02x}\n",
+                id.class, id.subclass, id.interface
+            )
+            .into_bytes(),
+            Attr::Revision => format!("0x{:02x}\n", id.revision).into_bytes(),
+            Attr::Irq => format!("{}\n", irq(pcie, func)).into_bytes(),
+            // FIXME read the proximity domain of the host bridge (_PXM) once pcid uses ACPI for
+            // enumerating them. Until then every function is reported like on a non-NUMA system.
+            Attr::NumaNode => b"-1\n".to_vec(),
+            Attr::Driver => func
+                .driver
+                .as_ref()
+                .map_or_else(Vec::new, |driver| format!("{driver}\n").into_bytes()),
+            Attr::Resource => resource(pcie, func).into_bytes(),
+            Attr::SerialNumber => unique_id
+                .and_then(|id| id.serial_number)
+                .map_or_else(Vec::new, |serial| {
+                    format!("{}\n", ext_cap::format_serial_number(serial)).into_bytes()
+                }),
+            Attr::UniqueId => {
+                unique_id.map_or_else(Vec::new, |id| format!("{}\n", id.id).into_bytes())
+            }
+            Attr::Config => read_config(pcie, addr),
+            Attr::Info => info(pcie, func).into_bytes(),
+        }
+    }
+}
+
+fn irq(pcie: &Pcie, func: &Func) -> u8 {
+    if let Some(line) = func.inner.legacy_interrupt_line {
+        return line.irq;
+    }
+    // Not enabled yet, report what the firmware assigned.
+    let [line, pin, ..] = unsafe { pcie.read(func.inner.addr, INTERRUPT) }.to_le_bytes();
+    if pin == 0 || line == 0xFF {
+        0
+    } else {
+        line
+    }
+}
+
+/// The `resource` file of Linux: six BARs followed by the expansion ROM, unused ones as zeros.
+///
+/// pcid doesn't size I/O BARs or the expansion ROM, so I/O BARs end where they start and the
+/// ROM is always reported as unused.
+fn resource(pcie: &Pcie, func: &Func) -> String {
+    let mut resource = String::new();
+    for (index, bar) in func.inner.bars.iter().enumerate() {
+        let (start, end, flags) = if let PciBar::Port(port) = *bar {
+            (u64::from(port), u64::from(port), IORESOURCE_IO)
+        } else if let Some((addr, size)) = bar_region(bar) {
+            let mut flags = IORESOURCE_MEM;
+            if matches!(bar, PciBar::Memory64 { .. }) {
+                flags |= IORESOURCE_MEM_64;
+            }
+            if crate::rebar::is_prefetchable(pcie, func.inner.addr, index as u8) {
+                flags |= IORESOURCE_PREFETCH;
+            }
+            (addr, addr + size - 1, flags)
+        } else {
+            (0, 0, 0)
+        };
+        let _ = writeln!(resource, "0x{start:016x} 0x{end:016x} 0x{flags:016x}");
+    }
+    let _ = writeln!(resource, "0x{0:016x} 0x{0:016x} 0x{0:016x}", 0);
+    resource
+}
+
+fn info(pcie: &Pcie, func: &Func) -> String {
+    let addr = func.inner.addr;
+    let read = |offset: u16| unsafe { pcie.read(addr, offset) };
+    let mut info = String::new();
+    let _ = writeln!(info, "id: {}", func.inner.full_device_id.display());
+    if let Some(unique_id) = &func.unique_id {
+        let _ = writeln!(info, "unique id: {}", unique_id.id);
+    }
+
+    if read(STATUS) & STATUS_CAP_LIST != 0 {
+        let mut offset = (read(CAP_POINTER) & 0xFC) as u16;
+        // At most 48 capabilities fit between the header and the end of the legacy space.
+        for _ in 0..48 {
+            if offset < 0x40 {
+                break;
+            }
+            let header = read(offset);
+            let id = header as u8;
+            let name = cap_name(id).map_or_else(|| format!("{id:#04x}"), str::to_owned);
+            let _ = writeln!(info, "cap {offset:03x}: {name}");
+            offset = ((header >> 8) & 0xFC) as u16;
+        }
+    }
+
+    for cap in &func.ext_capabilities {
+        let name = ext_cap::name(cap.id).map_or_else(|| format!("{:#06x}", cap.id), str::to_owned);
+        let _ = write!(info, "ext cap {:03x}: {name} v{}", cap.offset, cap.version);
+        if let Some(description) = ext_cap::describe(pcie, addr, cap) {
+            let _ = write!(info, ": {description}");
+        }
+        info.push('\n');
+    }
+
+    if let Some(vpd) = func.vpd.get_or_init(|| Vpd::read(pcie, addr)) {
+        if let Some(identifier) = &vpd.identifier {
+            let _ = writeln!(info, "vpd product: {identifier}");
+        }
+        for (keyword, value) in &vpd.fields {
+            let _ = writeln!(info, "vpd {keyword}: {value}");
+        }
+    }
+
+    info
+}
+
+fn cap_name(id: u8) -> Option<&'static str> {
+    Some(match id {
+        0x01 => "Power Management",
+        0x02 => "AGP",
+        0x03 => "Vital Product Data",
+        0x04 => "Slot Identification",
+        0x05 => "MSI",
+        0x07 => "PCI-X",
+        0x09 => "Vendor-Specific",
+        0x0A => "Debug Port",
+        0x0D => "Bridge Subsystem Vendor ID",
+        0x10 => "PCI Express",
+        0x11 => "MSI-X",
+        0x12 => "SATA",
+        0x13 => "Advanced Features",
+        0x14 => "Enhanced Allocation",
+        _ => return None,
+    })
+}
+
+fn read_config(pcie: &Pcie, addr: PciAddress) -> Vec<u8> {
+    let len: u16 = if pcie.has_extended_config(addr) {
+        4096
+    } else {
+        256
+    };
+    (0..len)
+        .step_by(4)
+        .flat_map(|offset| unsafe { pcie.read(addr, offset) }.to_le_bytes())
+        .collect()
+}
+
+/// Directory entries of a function, in addition to the channel and AER files.
+pub fn entries(func: &Func) -> impl Iterator<Item = &'static str> + '_ {
+    Attr::ALL
+        .into_iter()
+        .filter(|attr| attr.is_present(func))
+        .map(Attr::name)
+        .chain(
+            func.inner
+                .bars
+                .iter()
+                .zip(BAR_NAMES)
+                .filter(|(bar, _)| !bar.is_none())
+                .map(|(_, name)| name),
+        )
+}
+
+/// The BAR index of a `barN` file name.
+pub fn parse_bar(name: &str) -> Option<u8> {
+    BAR_NAMES
+        .iter()
+        .position(|&bar| bar == name)
+        .map(|bar| bar as u8)
+}
+
+/// Physical address and size of a memory BAR, `None` for I/O and unimplemented BARs.
+pub fn bar_region(bar: &PciBar) -> Option<(u64, u64)> {
+    match *bar {
+        PciBar::Memory32 { addr, size } if size != 0 => Some((addr.into(), size.into())),
+        PciBar::Memory64 { addr, size } if size != 0 => Some((addr, size)),
+        _ => None,
+    }
+}
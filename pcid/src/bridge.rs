@@ -0,0 +1,143 @@
+//! PCI-to-PCI bridges and bus number assignment.
+//!
+//! Firmware normally numbers every bus before handing over, but bridges that it didn't configure
+//! (hotplug slots populated after boot, firmware that only sets up the boot path, ...) come up
+//! with a secondary bus number of zero. Such bridges get the lowest bus number that neither the
+//! host bridge nor any other bridge in their parent's range claims.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+use pcid_interface::FullDeviceId;
+
+use crate::cfg_access::Pcie;
+
+const BUS_NUMBERS: u16 = 0x18;
+
+#[derive(Clone, Debug)]
+pub struct Bridge {
+    pub addr: PciAddress,
+    pub full_device_id: FullDeviceId,
+    pub secondary_bus: u8,
+    pub subordinate_bus: u8,
+    /// Whether pcid assigned the bus numbers instead of the firmware.
+    pub assigned: bool,
+}
+
+impl Bridge {
+    pub fn new(pcie: &Pcie, addr: PciAddress, full_device_id: FullDeviceId) -> Self {
+        let buses = unsafe { pcie.read(addr, BUS_NUMBERS) };
+        Self {
+            addr,
+            full_device_id,
+            secondary_bus: (buses >> 8) as u8,
+            subordinate_bus: (buses >> 16) as u8,
+            assigned: false,
+        }
+    }
+
+    /// Whether the firmware programmed the bus numbers.
+    pub fn is_configured(&self) -> bool {
+        self.secondary_bus > self.addr.bus() && self.subordinate_bus >= self.secondary_bus
+    }
+
+    pub fn buses(&self) -> RangeInclusive<u8> {
+        self.secondary_bus..=self.subordinate_bus
+    }
+
+    fn set_buses(&mut self, pcie: &Pcie, secondary: u8, subordinate: u8) {
+        unsafe {
+            let buses = pcie.read(self.addr, BUS_NUMBERS);
+            pcie.write(
+                self.addr,
+                BUS_NUMBERS,
+                (buses & 0xFF00_0000)
+                    | (u32::from(subordinate) << 16)
+                    | (u32::from(secondary) << 8)
+                    | u32::from(self.addr.bus()),
+            );
+        }
+        self.secondary_bus = secondary;
+        self.subordinate_bus = subordinate;
+        self.assigned = true;
+    }
+
+    /// Human readable description, exposed through the scheme.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let _ = writeln!(report, "id: {}", self.full_device_id.display());
+        let _ = writeln!(report, "secondary: {:02x}", self.secondary_bus);
+        let _ = writeln!(report, "subordinate: {:02x}", self.subordinate_bus);
+        let _ = writeln!(report, "assigned: {}", self.assigned);
+        report
+    }
+}
+
+/// Bus numbers in use in every segment, and the range each bus may hand out to bridges on it.
+pub struct BusNumbers {
+    used: BTreeMap<u16, Vec<RangeInclusive<u8>>>,
+    limits: BTreeMap<(u16, u8), u8>,
+}
+
+impl BusNumbers {
+    pub fn new() -> Self {
+        Self {
+            used: BTreeMap::new(),
+            limits: BTreeMap::new(),
+        }
+    }
+
+    /// Add the root bus of a host bridge that decodes `buses`.
+    pub fn add_root(&mut self, segment: u16, buses: RangeInclusive<u8>) {
+        self.claim(segment, *buses.start()..=*buses.start());
+        self.limits.insert((segment, *buses.start()), *buses.end());
+    }
+
+    fn claim(&mut self, segment: u16, buses: RangeInclusive<u8>) {
+        self.used.entry(segment).or_default().push(buses);
+    }
+
+    fn is_free(&self, segment: u16, bus: u8) -> bool {
+        self.used
+            .get(&segment)
+            .map_or(true, |used| !used.iter().any(|range| range.contains(&bus)))
+    }
+
+    /// Record a bridge the firmware configured.
+    pub fn add_configured(&mut self, bridge: &Bridge) {
+        let segment = bridge.addr.segment();
+        self.claim(segment, bridge.buses());
+        self.limits
+            .insert((segment, bridge.secondary_bus), bridge.subordinate_bus);
+    }
+
+    /// Give an unconfigured bridge a secondary bus, returning false if its parent has none left.
+    ///
+    /// The bridge only gets a single bus, so further unconfigured bridges behind it can't be
+    /// numbered.
+    pub fn assign(&mut self, pcie: &Pcie, bridge: &mut Bridge) -> bool {
+        let segment = bridge.addr.segment();
+        let parent = bridge.addr.bus();
+        let Some(&limit) = self.limits.get(&(segment, parent)) else {
+            return false;
+        };
+        let Some(bus) = (parent.saturating_add(1)..=limit).find(|&bus| self.is_free(segment, bus))
+        else {
+            return false;
+        };
+
+        bridge.set_buses(pcie, bus, bus);
+        self.claim(segment, bus..=bus);
+        self.limits.insert((segment, bus), bus);
+        true
+    }
+}
+
+/// The bridge whose secondary bus `addr` is on.
+pub fn upstream(bridges: &BTreeMap<PciAddress, Bridge>, addr: PciAddress) -> Option<&Bridge> {
+    bridges.values().find(|bridge| {
+        bridge.addr.segment() == addr.segment() && bridge.secondary_bus == addr.bus()
+    })
+}
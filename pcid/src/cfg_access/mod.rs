@@ -1,7 +1,9 @@
+use std::ops::RangeInclusive;
 use std::sync::Mutex;
 use std::{fs, io, mem};
 
 use common::{MemoryType, PhysBorrowed, Prot};
+use fdt::node::FdtNode;
 use fdt::Fdt;
 use pci_types::{ConfigRegionAccess, PciAddress};
 
@@ -10,6 +12,8 @@ use fallback::Pci;
 mod fallback;
 
 pub struct InterruptMap {
+    /// Segment of the host bridge the entry belongs to.
+    pub seg: u16,
     pub addr: [u32; 3],
     pub interrupt: u32,
     pub parent_phandle: u32,
@@ -29,30 +33,86 @@ fn locate_ecam_dtb<T>(
         )
     })?;
 
-    let node = dt
-        .find_compatible(&["pci-host-ecam-generic"])
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                "couldn't find pci-host-ecam-generic node in device tree",
-            )
-        })?;
+    let mut allocs = Vec::new();
+    let mut interrupt_map = Vec::new();
+    let mut interrupt_map_mask = None;
+
+    // Systems with more than one host bridge have one node per bridge, each of which is a
+    // separate PCI segment.
+    let nodes = dt.all_nodes().filter(|node| {
+        node.compatible()
+            .is_some_and(|compatible| compatible.all().any(|c| c == "pci-host-ecam-generic"))
+    });
+    for (i, node) in nodes.enumerate() {
+        let seg = match node.property("linux,pci-domain").and_then(|p| p.as_usize()) {
+            Some(domain) => domain as u16,
+            None => i as u16,
+        };
+
+        let address = node.reg().unwrap().next().unwrap().starting_address as u64;
+
+        let bus_range = node.property("bus-range").unwrap();
+        assert_eq!(bus_range.value.len(), 8);
+        let start_bus = u32::from_be_bytes(<[u8; 4]>::try_from(&bus_range.value[0..4]).unwrap());
+        let end_bus = u32::from_be_bytes(<[u8; 4]>::try_from(&bus_range.value[4..8]).unwrap());
 
-    let address = node.reg().unwrap().next().unwrap().starting_address as u64;
+        allocs.push(PcieAlloc {
+            base_addr: address,
+            seg_group_num: seg,
+            start_bus: start_bus.try_into().unwrap(),
+            end_bus: end_bus.try_into().unwrap(),
+            _rsvd: [0; 4],
+        });
+
+        parse_interrupt_map(&dt, &node, seg, &mut interrupt_map);
+
+        let mask = if let Some(interrupt_mask_node) = node.property("interrupt-map-mask") {
+            let mut cells = interrupt_mask_node
+                .value
+                .chunks_exact(4)
+                .map(|x| u32::from_be_bytes(<[u8; 4]>::try_from(x).unwrap()));
+            cells.next_chunk::<4>().unwrap().to_owned()
+        } else {
+            [u32::MAX, u32::MAX, u32::MAX, u32::MAX]
+        };
+        match interrupt_map_mask {
+            None => interrupt_map_mask = Some(mask),
+            Some(first) if first != mask => {
+                log::warn!("interrupt-map-mask of segment {seg} differs from the first segment");
+            }
+            Some(_) => {}
+        }
+    }
+
+    if allocs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "couldn't find pci-host-ecam-generic node in device tree",
+        ));
+    }
+
+    f(
+        PcieAllocs(&allocs),
+        interrupt_map,
+        interrupt_map_mask.unwrap(),
+    )
+}
 
-    let bus_range = node.property("bus-range").unwrap();
-    assert_eq!(bus_range.value.len(), 8);
-    let start_bus = u32::from_be_bytes(<[u8; 4]>::try_from(&bus_range.value[0..4]).unwrap());
-    let end_bus = u32::from_be_bytes(<[u8; 4]>::try_from(&bus_range.value[4..8]).unwrap());
+fn parse_interrupt_map(
+    dt: &Fdt<'_>,
+    node: &FdtNode<'_, '_>,
+    seg: u16,
+    interrupt_map: &mut Vec<InterruptMap>,
+) {
+    let Some(property) = node.property("interrupt-map") else {
+        return;
+    };
 
     // address-cells == 3, size-cells == 2, interrupt-cells == 1
-    let mut interrupt_map_data = node
-        .property("interrupt-map")
-        .unwrap()
+    let mut interrupt_map_data = property
         .value
         .chunks_exact(4)
         .map(|x| u32::from_be_bytes(<[u8; 4]>::try_from(x).unwrap()));
-    let mut interrupt_map = Vec::<InterruptMap>::new();
     while let Ok([addr1, addr2, addr3, int1, phandle]) = interrupt_map_data.next_chunk::<5>() {
         let parent = dt.find_phandle(phandle).unwrap();
         let parent_address_cells = u32::from_be_bytes(
@@ -81,6 +141,7 @@ fn locate_ecam_dtb<T>(
             _ => break,
         };
         interrupt_map.push(InterruptMap {
+            seg,
             addr: [addr1, addr2, addr3],
             interrupt: int1,
             parent_phandle: phandle,
@@ -88,29 +149,6 @@ fn locate_ecam_dtb<T>(
             parent_interrupt_cells,
         });
     }
-
-    let interrupt_map_mask = if let Some(interrupt_mask_node) = node.property("interrupt-map-mask")
-    {
-        let mut cells = interrupt_mask_node
-            .value
-            .chunks_exact(4)
-            .map(|x| u32::from_be_bytes(<[u8; 4]>::try_from(x).unwrap()));
-        cells.next_chunk::<4>().unwrap().to_owned()
-    } else {
-        [u32::MAX, u32::MAX, u32::MAX, u32::MAX]
-    };
-
-    f(
-        PcieAllocs(&[PcieAlloc {
-            base_addr: address,
-            seg_group_num: 0,
-            start_bus: start_bus.try_into().unwrap(),
-            end_bus: end_bus.try_into().unwrap(),
-            _rsvd: [0; 4],
-        }]),
-        interrupt_map,
-        interrupt_map_mask,
-    )
 }
 
 pub const MCFG_NAME: [u8; 4] = *b"MCFG";
@@ -340,15 +378,28 @@ impl Pcie {
     // TODO: A safer interface, using e.g. a VolatileCell or Volatile<'a>. The PhysBorrowed wrapper
     // can possibly deref to or provide a Volatile<T>.
     fn mmio_addr(&self, address: PciAddress, offset: u16) -> Option<*mut u32> {
-        assert_eq!(
-            address.segment(),
-            0,
-            "multiple segments not yet implemented"
-        );
-
         let bus_addr = self.bus_addr(address.segment(), address.bus())?;
         Some(unsafe { bus_addr.add(Self::bus_addr_offset_in_dwords(address, offset)) })
     }
+
+    /// The segment and bus range decoded by every host bridge.
+    ///
+    /// Without ECAM only the single segment reachable through the PCI 3.0 I/O ports exists.
+    pub fn host_bridges(&self) -> Vec<(u16, RangeInclusive<u8>)> {
+        if self.allocs.is_empty() {
+            return vec![(0, 0..=255)];
+        }
+        self.allocs
+            .iter()
+            .map(|alloc| (alloc.seg, alloc.start_bus..=alloc.end_bus))
+            .collect()
+    }
+
+    /// Whether the 4096 byte PCIe extended configuration space of `address` is reachable. The
+    /// PCI 3.0 fallback only gives access to the first 256 bytes.
+    pub fn has_extended_config(&self, address: PciAddress) -> bool {
+        self.bus_addr(address.segment(), address.bus()).is_some()
+    }
 }
 
 impl ConfigRegionAccess for Pcie {
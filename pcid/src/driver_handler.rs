@@ -1,28 +1,55 @@
 use pci_types::capability::{MultipleMessageSupport, PciCapability};
 use pci_types::{ConfigRegionAccess, EndpointHeader};
-use pcid_interface::PciFunction;
+use pcid_interface::{PciFunction, UniqueId};
 
 use crate::cfg_access::Pcie;
+use crate::ext_cap::ExtendedCapability;
+use crate::interrupts::{self, InterruptAllocation};
+use crate::pm::PowerManagement;
+use crate::rebar::{self, BarWindow};
+use crate::state::ConfigState;
 
 pub struct DriverHandler<'a> {
     func: PciFunction,
     endpoint_header: &'a mut EndpointHeader,
     capabilities: &'a mut [PciCapability],
+    ext_capabilities: &'a [ExtendedCapability],
+    bar_window: Option<BarWindow>,
+    interrupts: &'a mut Option<InterruptAllocation>,
+    pm: &'a mut Option<PowerManagement>,
+    saved_state: &'a mut Option<ConfigState>,
+    unique_id: &'a UniqueId,
+    driver: &'a mut Option<String>,
 
     pcie: &'a Pcie,
 }
 
 impl<'a> DriverHandler<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         func: PciFunction,
         endpoint_header: &'a mut EndpointHeader,
         capabilities: &'a mut [PciCapability],
+        ext_capabilities: &'a [ExtendedCapability],
+        bar_window: Option<BarWindow>,
+        interrupts: &'a mut Option<InterruptAllocation>,
+        pm: &'a mut Option<PowerManagement>,
+        saved_state: &'a mut Option<ConfigState>,
+        unique_id: &'a UniqueId,
+        driver: &'a mut Option<String>,
         pcie: &'a Pcie,
     ) -> Self {
         DriverHandler {
             func,
             endpoint_header,
             capabilities,
+            ext_capabilities,
+            bar_window,
+            interrupts,
+            pm,
+            saved_state,
+            unique_id,
+            driver,
             pcie,
         }
     }
@@ -278,7 +305,140 @@ impl<'a> DriverHandler<'a> {
                 }
                 return PcidClientResponse::WriteConfig;
             }
+            PcidClientRequest::RequestResizableBars => PcidClientResponse::ResizableBars(
+                rebar::resizable_bars(self.pcie, self.func.addr, self.ext_capabilities),
+            ),
+            PcidClientRequest::ResizeBar { bar, size } => {
+                if bar >= 6 {
+                    return PcidClientResponse::Error(PcidServerResponseError::NonexistentBar(
+                        bar,
+                    ));
+                }
+                let Some(window) = self.bar_window.as_ref() else {
+                    return PcidClientResponse::Error(PcidServerResponseError::NoBarSpace);
+                };
+                match rebar::resize(
+                    self.pcie,
+                    self.endpoint_header,
+                    self.ext_capabilities,
+                    &self.func.bars,
+                    window,
+                    bar,
+                    size,
+                ) {
+                    Ok(new_bar) => {
+                        self.func.bars[usize::from(bar)] = new_bar;
+                        PcidClientResponse::BarResized(bar, new_bar)
+                    }
+                    Err(err) => PcidClientResponse::Error(err),
+                }
+            }
+            PcidClientRequest::RequestInterruptVectors { count } => {
+                if self
+                    .interrupts
+                    .as_ref()
+                    .is_some_and(InterruptAllocation::is_programmed)
+                {
+                    return PcidClientResponse::Error(
+                        PcidServerResponseError::InterruptsAlreadyAllocated,
+                    );
+                }
+                match interrupts::negotiate(self.capabilities, count) {
+                    Ok(allocation) => {
+                        let response = PcidClientResponse::InterruptVectorsGranted(
+                            allocation.feature,
+                            allocation.count,
+                        );
+                        *self.interrupts = Some(allocation);
+                        response
+                    }
+                    Err(err) => PcidClientResponse::Error(err),
+                }
+            }
+            PcidClientRequest::ProgramInterruptVectors(vectors) => {
+                let Some(allocation) = self.interrupts.as_mut() else {
+                    return PcidClientResponse::Error(
+                        PcidServerResponseError::InvalidInterruptVectors,
+                    );
+                };
+                if allocation.is_programmed() {
+                    return PcidClientResponse::Error(
+                        PcidServerResponseError::InterruptsAlreadyAllocated,
+                    );
+                }
+                match interrupts::program(
+                    self.pcie,
+                    self.capabilities,
+                    &self.func.bars,
+                    allocation,
+                    &vectors,
+                ) {
+                    Ok(()) => PcidClientResponse::InterruptVectorsProgrammed(allocation.feature),
+                    Err(err) => PcidClientResponse::Error(err),
+                }
+            }
+            PcidClientRequest::ReleaseInterruptVectors => {
+                if let Some(allocation) = self.interrupts.take() {
+                    interrupts::release(self.pcie, self.capabilities, &self.func.bars, allocation);
+                }
+                PcidClientResponse::InterruptVectorsReleased
+            }
+            PcidClientRequest::RequestPowerState => match self.pm {
+                Some(pm) => PcidClientResponse::PowerState(pm.state(self.pcie)),
+                None => PcidClientResponse::Error(PcidServerResponseError::NoPowerManagement),
+            },
+            PcidClientRequest::SetPowerState(state) => {
+                let Some(pm) = self.pm.as_mut() else {
+                    return PcidClientResponse::Error(PcidServerResponseError::NoPowerManagement);
+                };
+                match pm.set_state(self.pcie, self.ext_capabilities, state) {
+                    Ok(reset) => {
+                        if reset {
+                            self.restore_interrupts();
+                        }
+                        PcidClientResponse::PowerState(state)
+                    }
+                    Err(err) => PcidClientResponse::Error(err),
+                }
+            }
+            PcidClientRequest::SaveState => {
+                *self.saved_state = Some(ConfigState::save(
+                    self.pcie,
+                    self.func.addr,
+                    self.ext_capabilities,
+                ));
+                PcidClientResponse::StateSaved
+            }
+            PcidClientRequest::RestoreState => {
+                let Some(saved_state) = self.saved_state.as_ref() else {
+                    return PcidClientResponse::Error(PcidServerResponseError::NoSavedState);
+                };
+                saved_state.restore(self.pcie);
+                // The MSI-X table lives in a BAR and isn't part of the snapshot.
+                self.restore_interrupts();
+                PcidClientResponse::StateRestored
+            }
+            PcidClientRequest::RequestUniqueId => {
+                PcidClientResponse::UniqueId(self.unique_id.clone())
+            }
+            PcidClientRequest::SetDriverName(name) => {
+                *self.driver = Some(name);
+                PcidClientResponse::DriverNameSet
+            }
             _ => unreachable!(),
         }
     }
+    fn restore_interrupts(&mut self) {
+        let Some(allocation) = self.interrupts.as_ref() else {
+            return;
+        };
+        if let Err(err) =
+            interrupts::restore(self.pcie, self.capabilities, &self.func.bars, allocation)
+        {
+            log::error!(
+                "pcid: {} failed to restore interrupts: {err:?}",
+                self.func.addr
+            );
+        }
+    }
 }
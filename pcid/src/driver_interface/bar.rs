@@ -53,3 +53,14 @@ impl PciBar {
         }
     }
 }
+
+/// A BAR which can be resized through the Resizable BAR extended capability.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResizableBar {
+    /// Index of the BAR.
+    pub bar: u8,
+    /// Currently programmed size in bytes.
+    pub current_size: u64,
+    /// All sizes in bytes the function supports for this BAR, in ascending order.
+    pub supported_sizes: Vec<u64>,
+}
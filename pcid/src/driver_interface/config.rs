@@ -1,5 +1,8 @@
 use std::collections::BTreeMap;
+use std::fs;
+use std::io;
 use std::ops::Range;
+use std::path::Path;
 
 use serde::Deserialize;
 
@@ -10,6 +13,29 @@ pub struct Config {
     pub drivers: Vec<DriverConfig>,
 }
 
+impl Config {
+    /// Load the driver table from a file, or from every file in a directory.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let config_data = if fs::metadata(path)?.is_file() {
+            fs::read_to_string(path)?
+        } else {
+            let mut config_data = String::new();
+            for path in fs::read_dir(path)? {
+                if let Ok(tmp) = fs::read_to_string(path?.path()) {
+                    config_data.push_str(&tmp);
+                }
+            }
+            config_data
+        };
+        toml::from_str(&config_data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The first driver matching a function.
+    pub fn find_driver(&self, id: &FullDeviceId) -> Option<&DriverConfig> {
+        self.drivers.iter().find(|driver| driver.match_function(id))
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct DriverConfig {
     pub name: Option<String>,
@@ -24,6 +50,30 @@ pub struct DriverConfig {
 }
 
 impl DriverConfig {
+    /// The program and arguments to run, with relative programs looked up in the driver
+    /// directory.
+    pub fn build_command(&self) -> Option<std::process::Command> {
+        let mut args = self.command.iter();
+        let program = args.next()?;
+        let program = if program.starts_with('/') {
+            program.to_owned()
+        } else {
+            "/usr/lib/drivers/".to_owned() + program
+        };
+
+        let mut command = std::process::Command::new(program);
+        command.args(args);
+        Some(command)
+    }
+
+    /// The name of the entry, or the file name of its program if it has none.
+    pub fn driver_name(&self) -> Option<&str> {
+        self.name.as_deref().or_else(|| {
+            let program = self.command.first()?;
+            Some(program.rsplit('/').next().unwrap_or(program))
+        })
+    }
+
     pub fn match_function(&self, id: &FullDeviceId) -> bool {
         if let Some(class) = self.class {
             if class != id.class {
@@ -46,3 +46,22 @@ impl FullDeviceId {
         string
     }
 }
+
+/// Identifier of a PCI function that stays the same across reboots, for persistent device names.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UniqueId {
+    /// `dsn-<serial number>-<function>` if the device reports a usable serial number, so the
+    /// identifier follows the device into another slot. Otherwise
+    /// `pci-<segment>:<root bus>-<device.function of every hop>-<vendor, device, subsystem ids>`,
+    /// which is stable as long as the device stays in the same slot.
+    pub id: String,
+    pub source: UniqueIdSource,
+    /// Device Serial Number capability of the function, if any.
+    pub serial_number: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UniqueIdSource {
+    SerialNumber,
+    Topology,
+}
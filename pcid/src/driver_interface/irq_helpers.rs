@@ -9,6 +9,7 @@ use std::io::{self, prelude::*};
 use std::num::NonZeroU8;
 
 use crate::driver_interface::msi::MsiAddrAndData;
+use crate::driver_interface::PciFeature;
 
 /// Read the local APIC ID of the bootstrap processor.
 pub fn read_bsp_apic_id() -> io::Result<usize> {
@@ -227,3 +228,64 @@ pub fn allocate_first_msi_interrupt_on_bsp(
 
     interrupt_handle
 }
+
+/// Interrupt vectors allocated by [`allocate_interrupts`].
+#[derive(Debug)]
+pub struct AllocatedInterrupts {
+    /// Whether the vectors are delivered through MSI or MSI-X.
+    pub feature: PciFeature,
+    /// One IRQ handle per vector. For MSI-X, handle `n` belongs to table entry `n`, and for MSI
+    /// to message number `n`.
+    pub handles: Vec<File>,
+}
+
+/// Allocate up to `count` MSI or MSI-X vectors on the BSP and let pcid program them into the
+/// device.
+///
+/// pcid chooses MSI-X if the function supports it, and MSI otherwise. Fewer vectors than
+/// requested may be returned, either because the function doesn't support as many or because not
+/// enough interrupt vectors are free.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn allocate_interrupts(
+    pcid_handle: &mut crate::driver_interface::PciFunctionHandle,
+    count: u16,
+) -> io::Result<AllocatedInterrupts> {
+    use crate::driver_interface::msi::x86 as x86_msix;
+
+    let (feature, granted) = pcid_handle
+        .request_interrupt_vectors(count)
+        .map_err(|err| io::Error::other(format!("pcid refused interrupt vectors: {err:?}")))?;
+    let granted = u8::try_from(granted).unwrap_or(u8::MAX);
+
+    let destination_id = read_bsp_apic_id()?;
+    // FIXME for cpu_id >255 we need to use the IOMMU to use IRQ remapping
+    let lapic_id = u8::try_from(destination_id).expect("CPU id couldn't fit inside u8");
+
+    let (base, mut handles) = match feature {
+        PciFeature::MsiX => allocate_interrupt_vectors(destination_id, granted)?,
+        // MSI vectors have to be naturally aligned to the size of the block.
+        PciFeature::Msi => allocate_aligned_interrupt_vectors(
+            destination_id,
+            NonZeroU8::new(granted).unwrap(),
+            granted,
+        )?,
+    }
+    .ok_or_else(|| io::Error::other("no interrupt vectors left"))?;
+    if feature == PciFeature::Msi {
+        handles.truncate(1 << handles.len().ilog2());
+    }
+
+    let vectors = (0..handles.len() as u8)
+        .map(|i| MsiAddrAndData {
+            addr: x86_msix::message_address(lapic_id, false, false),
+            data: x86_msix::message_data_edge_triggered(x86_msix::DeliveryMode::Fixed, base + i),
+        })
+        .collect();
+    pcid_handle
+        .program_interrupt_vectors(vectors)
+        .map_err(|err| io::Error::other(format!("pcid failed to program vectors: {err:?}")))?;
+
+    log::debug!("Enabled {} {:?} vector(s)", handles.len(), feature);
+
+    Ok(AllocatedInterrupts { feature, handles })
+}
@@ -8,9 +8,9 @@ use std::{fmt, process};
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-pub use bar::PciBar;
+pub use bar::{PciBar, ResizableBar};
 pub use cap::VendorSpecificCapability;
-pub use id::FullDeviceId;
+pub use id::{FullDeviceId, UniqueId, UniqueIdSource};
 pub use pci_types::PciAddress;
 
 mod bar;
@@ -19,6 +19,7 @@ pub mod config;
 mod id;
 pub mod irq_helpers;
 pub mod msi;
+pub mod pm;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct LegacyInterruptLine {
@@ -229,6 +230,29 @@ pub enum PcidClientRequest {
     SetFeatureInfo(SetFeatureInfo),
     ReadConfig(u16),
     WriteConfig(u16, u32),
+    RequestResizableBars,
+    /// Resize a BAR to the given size in bytes. The BAR may be moved by pcid if it doesn't fit
+    /// at its current address anymore.
+    ResizeBar { bar: u8, size: u64 },
+    /// Ask for up to `count` MSI or MSI-X vectors. pcid picks the mechanism and the number of
+    /// vectors the function can actually use.
+    RequestInterruptVectors { count: u16 },
+    /// Program the previously granted vectors, one message address and data pair per vector.
+    ProgramInterruptVectors(Vec<msi::MsiAddrAndData>),
+    /// Mask and disable all vectors owned by this function.
+    ReleaseInterruptVectors,
+    RequestPowerState,
+    /// Move the function to another D-state. Config space is saved before leaving D0 and restored
+    /// when returning to it.
+    SetPowerState(pm::PowerState),
+    /// Snapshot the configuration space, e.g. before resetting the function or the bus above it.
+    SaveState,
+    /// Write the last snapshot back and reprogram the interrupt vectors.
+    RestoreState,
+    RequestUniqueId,
+    /// Name the driver bound to the function, shown in its `driver` attribute until the channel
+    /// is closed.
+    SetDriverName(String),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -236,6 +260,19 @@ pub enum PcidClientRequest {
 pub enum PcidServerResponseError {
     NonexistentFeature(PciFeature),
     InvalidBitPattern,
+    NonexistentBar(u8),
+    UnsupportedBarSize(u64),
+    /// There is no free range in the bridge window above the function that the BAR fits into.
+    NoBarSpace,
+    /// The function already owns interrupt vectors, release them first.
+    InterruptsAlreadyAllocated,
+    /// The vectors don't match what was granted, or violate the MSI alignment rules.
+    InvalidInterruptVectors,
+    /// The function has no power management capability.
+    NoPowerManagement,
+    UnsupportedPowerState(pm::PowerState),
+    /// `RestoreState` without a previous `SaveState`.
+    NoSavedState,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -252,6 +289,16 @@ pub enum PcidClientResponse {
     SetFeatureInfo(PciFeature),
     ReadConfig(u32),
     WriteConfig,
+    ResizableBars(Vec<ResizableBar>),
+    BarResized(u8, PciBar),
+    InterruptVectorsGranted(PciFeature, u16),
+    InterruptVectorsProgrammed(PciFeature),
+    InterruptVectorsReleased,
+    PowerState(pm::PowerState),
+    StateSaved,
+    StateRestored,
+    UniqueId(UniqueId),
+    DriverNameSet,
 }
 
 pub struct MappedBar {
@@ -441,6 +488,152 @@ impl PciFunctionHandle {
             }
         }
     }
+    pub fn resizable_bars(&mut self) -> Vec<ResizableBar> {
+        self.send(&PcidClientRequest::RequestResizableBars);
+        match self.recv() {
+            PcidClientResponse::ResizableBars(bars) => bars,
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    /// Resize BAR `bir` to `size` bytes, returning the new location of the BAR.
+    ///
+    /// The BAR must not be mapped yet, as pcid may move it.
+    pub fn resize_bar(&mut self, bir: u8, size: u64) -> Result<PciBar, PcidServerResponseError> {
+        assert!(
+            self.mapped_bars[bir as usize].is_none(),
+            "BAR {bir} has to be resized before it is mapped"
+        );
+        self.send(&PcidClientRequest::ResizeBar { bar: bir, size });
+        match self.recv() {
+            PcidClientResponse::BarResized(bar, new_bar) if bar == bir => {
+                self.config.func.bars[bir as usize] = new_bar;
+                Ok(new_bar)
+            }
+            PcidClientResponse::Error(err) => Err(err),
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    /// Negotiate up to `count` interrupt vectors, returning the mechanism that will be used and
+    /// the number of vectors granted.
+    ///
+    /// See [`irq_helpers::allocate_interrupts`] for a helper that also allocates and programs
+    /// the vectors.
+    pub fn request_interrupt_vectors(
+        &mut self,
+        count: u16,
+    ) -> Result<(PciFeature, u16), PcidServerResponseError> {
+        self.send(&PcidClientRequest::RequestInterruptVectors { count });
+        match self.recv() {
+            PcidClientResponse::InterruptVectorsGranted(feature, count) => Ok((feature, count)),
+            PcidClientResponse::Error(err) => Err(err),
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    pub fn program_interrupt_vectors(
+        &mut self,
+        vectors: Vec<msi::MsiAddrAndData>,
+    ) -> Result<PciFeature, PcidServerResponseError> {
+        self.send(&PcidClientRequest::ProgramInterruptVectors(vectors));
+        match self.recv() {
+            PcidClientResponse::InterruptVectorsProgrammed(feature) => Ok(feature),
+            PcidClientResponse::Error(err) => Err(err),
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    pub fn release_interrupt_vectors(&mut self) {
+        self.send(&PcidClientRequest::ReleaseInterruptVectors);
+        match self.recv() {
+            PcidClientResponse::InterruptVectorsReleased => {}
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    pub fn power_state(&mut self) -> Result<pm::PowerState, PcidServerResponseError> {
+        self.send(&PcidClientRequest::RequestPowerState);
+        match self.recv() {
+            PcidClientResponse::PowerState(state) => Ok(state),
+            PcidClientResponse::Error(err) => Err(err),
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    /// Move the function to `state`.
+    ///
+    /// The function must not be accessed through its BARs while it isn't in D0.
+    pub fn set_power_state(&mut self, state: pm::PowerState) -> Result<(), PcidServerResponseError> {
+        self.send(&PcidClientRequest::SetPowerState(state));
+        match self.recv() {
+            PcidClientResponse::PowerState(new_state) if new_state == state => Ok(()),
+            PcidClientResponse::Error(err) => Err(err),
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    /// Save the configuration space of the function in pcid.
+    ///
+    /// Call this before anything that resets the function behind pcid's back, and
+    /// [`restore_state`](Self::restore_state) afterwards.
+    pub fn save_state(&mut self) {
+        self.send(&PcidClientRequest::SaveState);
+        match self.recv() {
+            PcidClientResponse::StateSaved => {}
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    pub fn restore_state(&mut self) -> Result<(), PcidServerResponseError> {
+        self.send(&PcidClientRequest::RestoreState);
+        match self.recv() {
+            PcidClientResponse::StateRestored => Ok(()),
+            PcidClientResponse::Error(err) => Err(err),
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    /// Identifier of the function that persists across reboots, to name the devices it provides.
+    pub fn unique_id(&mut self) -> UniqueId {
+        self.send(&PcidClientRequest::RequestUniqueId);
+        match self.recv() {
+            PcidClientResponse::UniqueId(id) => id,
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
+    /// Tell pcid which driver the function is bound to, for tools inspecting the function.
+    pub fn set_driver_name(&mut self, name: &str) {
+        self.send(&PcidClientRequest::SetDriverName(name.to_owned()));
+        match self.recv() {
+            PcidClientResponse::DriverNameSet => {}
+            other => {
+                log::error!("received wrong pcid response: {other:?}");
+                process::exit(1);
+            }
+        }
+    }
     pub unsafe fn map_bar(&mut self, bir: u8) -> &MappedBar {
         let mapped_bar = &mut self.mapped_bars[bir as usize];
         if let Some(mapped_bar) = mapped_bar {
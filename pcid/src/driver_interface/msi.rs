@@ -11,7 +11,7 @@ use serde::{Deserialize, Serialize};
 ///
 /// For MSI using this only works when you need a single interrupt vector.
 /// For MSI-X you can have a single [MsiEntry] for each interrupt vector.
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct MsiAddrAndData {
     pub addr: u64,
     pub data: u32,
@@ -0,0 +1,137 @@
+//! PCI power management.
+//!
+//! Drivers of devices that are idle most of the time (audio, NICs, ...) can put their function
+//! into a low power D-state through pcid. pcid takes care of saving the configuration space before
+//! the function is suspended and restoring it when it is brought back to D0.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::driver_interface::{PciFunctionHandle, PcidServerResponseError};
+
+/// The device power states that can be entered through the PM capability.
+///
+/// D3cold requires removing power from the slot, which is platform specific and not handled here.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum PowerState {
+    D0,
+    D1,
+    D2,
+    D3Hot,
+}
+
+impl PowerState {
+    /// Value of the PowerState field in the PMCSR.
+    pub fn bits(self) -> u32 {
+        match self {
+            Self::D0 => 0b00,
+            Self::D1 => 0b01,
+            Self::D2 => 0b10,
+            Self::D3Hot => 0b11,
+        }
+    }
+    pub fn from_bits(bits: u32) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::D0,
+            0b01 => Self::D1,
+            0b10 => Self::D2,
+            _ => Self::D3Hot,
+        }
+    }
+}
+
+impl fmt::Display for PowerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::D0 => write!(f, "D0"),
+            Self::D1 => write!(f, "D1"),
+            Self::D2 => write!(f, "D2"),
+            Self::D3Hot => write!(f, "D3hot"),
+        }
+    }
+}
+
+/// Runtime suspend bookkeeping for a driver.
+///
+/// The driver wraps every access to the device in [`RuntimePm::get`] and [`RuntimePm::put`], and
+/// calls [`RuntimePm::poll`] from its event loop. Once no access has been in flight for the
+/// autosuspend delay, the function is put into `suspend_state`; the next `get` resumes it.
+pub struct RuntimePm {
+    autosuspend_delay: Duration,
+    suspend_state: PowerState,
+    usage: usize,
+    last_busy: Instant,
+    suspended: bool,
+}
+
+impl RuntimePm {
+    pub fn new(autosuspend_delay: Duration) -> Self {
+        Self {
+            autosuspend_delay,
+            suspend_state: PowerState::D3Hot,
+            usage: 0,
+            last_busy: Instant::now(),
+            suspended: false,
+        }
+    }
+
+    /// Use a lighter sleep state than D3hot, e.g. for devices that need to wake quickly.
+    pub fn with_suspend_state(mut self, state: PowerState) -> Self {
+        self.suspend_state = state;
+        self
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    /// Take a usage reference, resuming the function if it is suspended.
+    pub fn get(&mut self, handle: &mut PciFunctionHandle) -> Result<(), PcidServerResponseError> {
+        if self.suspended {
+            handle.set_power_state(PowerState::D0)?;
+            self.suspended = false;
+            log::debug!("runtime resumed {}", handle.config().func.name());
+        }
+        self.usage += 1;
+        self.last_busy = Instant::now();
+        Ok(())
+    }
+
+    /// Drop a usage reference taken with [`RuntimePm::get`].
+    pub fn put(&mut self) {
+        self.usage = self.usage.saturating_sub(1);
+        self.last_busy = Instant::now();
+    }
+
+    /// Note activity that didn't go through `get`/`put`, e.g. an interrupt.
+    pub fn mark_busy(&mut self) {
+        self.last_busy = Instant::now();
+    }
+
+    /// Suspend the function if it has been idle for at least the autosuspend delay.
+    ///
+    /// Returns the time until the next check is useful, if any.
+    pub fn poll(
+        &mut self,
+        handle: &mut PciFunctionHandle,
+    ) -> Result<Option<Duration>, PcidServerResponseError> {
+        if self.suspended || self.usage > 0 {
+            return Ok(None);
+        }
+        let idle = self.last_busy.elapsed();
+        if idle < self.autosuspend_delay {
+            return Ok(Some(self.autosuspend_delay - idle));
+        }
+
+        handle.set_power_state(self.suspend_state)?;
+        self.suspended = true;
+        log::debug!(
+            "runtime suspended {} to {}",
+            handle.config().func.name(),
+            self.suspend_state
+        );
+        Ok(None)
+    }
+}
@@ -0,0 +1,176 @@
+//! PCIe extended capabilities, which live in the configuration space above offset 0x100.
+
+use std::fmt::Write;
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+
+use crate::cfg_access::Pcie;
+
+pub const EXT_CAP_START: u16 = 0x100;
+
+pub const EXT_CAP_ID_AER: u16 = 0x0001;
+pub const EXT_CAP_ID_DSN: u16 = 0x0003;
+pub const EXT_CAP_ID_ARI: u16 = 0x000E;
+pub const EXT_CAP_ID_ATS: u16 = 0x000F;
+pub const EXT_CAP_ID_LTR: u16 = 0x0018;
+
+const DSN_LOWER: u16 = 0x04;
+const DSN_UPPER: u16 = 0x08;
+const ARI_CAP: u16 = 0x04;
+const ATS_CAP: u16 = 0x04;
+const ATS_ENABLE: u32 = 1 << (16 + 15);
+const LTR_MAX_LATENCY: u16 = 0x04;
+
+/// A single entry in the extended capability list of a function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtendedCapability {
+    pub id: u16,
+    pub version: u8,
+    pub offset: u16,
+}
+
+/// Walk the extended capability list of the function at `addr`.
+///
+/// Returns an empty list if the extended configuration space isn't accessible.
+pub fn scan(pcie: &Pcie, addr: PciAddress) -> Vec<ExtendedCapability> {
+    let mut capabilities = Vec::new();
+    if !pcie.has_extended_config(addr) {
+        return capabilities;
+    }
+
+    let mut offset = EXT_CAP_START;
+    // Each capability is at least one dword, so this bounds the walk even on a corrupted list.
+    for _ in 0..(4096 - EXT_CAP_START as usize) / 4 {
+        let header = unsafe { pcie.read(addr, offset) };
+        if header == 0 || header == 0xFFFF_FFFF {
+            break;
+        }
+
+        capabilities.push(ExtendedCapability {
+            id: (header & 0xFFFF) as u16,
+            version: ((header >> 16) & 0xF) as u8,
+            offset,
+        });
+
+        let next = ((header >> 20) & 0xFFC) as u16;
+        if next < EXT_CAP_START {
+            break;
+        }
+        offset = next;
+    }
+
+    capabilities
+}
+
+pub fn find(capabilities: &[ExtendedCapability], id: u16) -> Option<ExtendedCapability> {
+    capabilities.iter().copied().find(|cap| cap.id == id)
+}
+
+pub fn name(id: u16) -> Option<&'static str> {
+    Some(match id {
+        0x0001 => "Advanced Error Reporting",
+        0x0002 | 0x0009 => "Virtual Channel",
+        EXT_CAP_ID_DSN => "Device Serial Number",
+        0x0004 => "Power Budgeting",
+        0x000B => "Vendor-Specific",
+        0x000D => "Access Control Services",
+        EXT_CAP_ID_ARI => "Alternative Routing-ID Interpretation",
+        EXT_CAP_ID_ATS => "Address Translation Services",
+        0x0010 => "Single Root I/O Virtualization",
+        0x0013 => "Page Request Interface",
+        0x0015 => "Resizable BAR",
+        EXT_CAP_ID_LTR => "Latency Tolerance Reporting",
+        0x0019 => "Secondary PCI Express",
+        0x001B => "Process Address Space ID",
+        0x001E => "L1 PM Substates",
+        0x001F => "Precision Time Measurement",
+        0x0023 => "Designated Vendor-Specific",
+        0x0025 => "Data Link Feature",
+        0x0026 => "Physical Layer 16.0 GT/s",
+        0x0027 => "Lane Margining at the Receiver",
+        _ => return None,
+    })
+}
+
+/// Decoded registers of the capabilities userspace tooling most often asks about, `None` for
+/// others.
+pub fn describe(pcie: &Pcie, addr: PciAddress, cap: &ExtendedCapability) -> Option<String> {
+    let read = |offset: u16| unsafe { pcie.read(addr, cap.offset + offset) };
+    let mut string = String::new();
+    match cap.id {
+        EXT_CAP_ID_DSN => string = format_serial_number(serial_number(pcie, addr, cap)),
+        EXT_CAP_ID_ARI => {
+            let reg = read(ARI_CAP);
+            let _ = write!(string, "next function {}", (reg >> 8) & 0xFF);
+            if reg & (1 << 0) != 0 {
+                string.push_str(", MFVC function groups");
+            }
+            if reg & (1 << 1) != 0 {
+                string.push_str(", ACS function groups");
+            }
+        }
+        EXT_CAP_ID_ATS => {
+            let reg = read(ATS_CAP);
+            let queue_depth = match reg & 0x1F {
+                0 => 32,
+                depth => depth,
+            };
+            let _ = write!(
+                string,
+                "invalidate queue depth {queue_depth}, {}",
+                if reg & ATS_ENABLE != 0 {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+            if reg & ATS_ENABLE != 0 {
+                let _ = write!(string, ", smallest translation unit {}", (reg >> 16) & 0x1F);
+            }
+            if reg & (1 << 5) != 0 {
+                string.push_str(", page aligned requests");
+            }
+        }
+        EXT_CAP_ID_LTR => {
+            let reg = read(LTR_MAX_LATENCY);
+            let _ = write!(
+                string,
+                "max snoop latency {}ns, max no-snoop latency {}ns",
+                ltr_latency_ns(reg as u16),
+                ltr_latency_ns((reg >> 16) as u16)
+            );
+        }
+        _ => return None,
+    }
+    Some(string)
+}
+
+/// The 64 bit serial number in a Device Serial Number capability.
+///
+/// All functions of a multi-function device report the same number.
+pub fn serial_number(pcie: &Pcie, addr: PciAddress, cap: &ExtendedCapability) -> u64 {
+    let (lower, upper) = unsafe {
+        (
+            pcie.read(addr, cap.offset + DSN_LOWER),
+            pcie.read(addr, cap.offset + DSN_UPPER),
+        )
+    };
+    (u64::from(upper) << 32) | u64::from(lower)
+}
+
+/// Format a serial number like lspci does, most significant byte first.
+pub fn format_serial_number(serial: u64) -> String {
+    serial
+        .to_be_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Decode a latency value in the LTR format: a 10 bit value scaled by 2^(5 * scale) ns.
+fn ltr_latency_ns(reg: u16) -> u64 {
+    let value = u64::from(reg & 0x3FF);
+    let scale = u32::from((reg >> 10) & 0x7).min(5);
+    value << (5 * scale)
+}
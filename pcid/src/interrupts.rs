@@ -0,0 +1,296 @@
+//! MSI and MSI-X vector programming on behalf of drivers.
+//!
+//! A driver asks for a number of vectors, pcid decides whether MSI-X or MSI is used and how many
+//! vectors the function can actually get, the driver allocates the interrupts from the IRQ scheme
+//! and finally pcid writes the resulting message addresses and data into the capability or the
+//! MSI-X table. pcid remembers which vectors belong to the function, so that they can be masked
+//! again once the driver goes away.
+
+use pci_types::capability::{MultipleMessageSupport, PciCapability};
+use pcid_interface::msi::{MsiAddrAndData, MsixTableEntry};
+use pcid_interface::{PciBar, PciFeature, PcidServerResponseError};
+
+use crate::cfg_access::Pcie;
+
+/// Vectors granted to the driver that currently owns a function.
+#[derive(Clone, Debug)]
+pub struct InterruptAllocation {
+    pub feature: PciFeature,
+    pub count: u16,
+    /// The programmed vectors, kept to restore them after the function lost its state.
+    pub vectors: Vec<MsiAddrAndData>,
+}
+
+impl InterruptAllocation {
+    pub fn is_programmed(&self) -> bool {
+        !self.vectors.is_empty()
+    }
+}
+
+/// Decide how many vectors of which kind a function can get, preferring MSI-X.
+pub fn negotiate(
+    capabilities: &[PciCapability],
+    requested: u16,
+) -> Result<InterruptAllocation, PcidServerResponseError> {
+    if requested == 0 {
+        return Err(PcidServerResponseError::InvalidInterruptVectors);
+    }
+
+    for capability in capabilities {
+        if let PciCapability::MsiX(msix) = capability {
+            return Ok(InterruptAllocation {
+                feature: PciFeature::MsiX,
+                count: requested.min(msix.table_size()),
+                vectors: Vec::new(),
+            });
+        }
+    }
+    for capability in capabilities {
+        if let PciCapability::Msi(msi) = capability {
+            // MSI can only hand out a power of two vectors.
+            let capable = 1u16 << (msi.multiple_message_capable() as u8);
+            let count = requested.min(capable);
+            return Ok(InterruptAllocation {
+                feature: PciFeature::Msi,
+                count: 1 << count.ilog2(),
+                vectors: Vec::new(),
+            });
+        }
+    }
+
+    Err(PcidServerResponseError::NonexistentFeature(PciFeature::Msi))
+}
+
+/// Write the message address and data of every vector to the device and enable the granted
+/// interrupt mechanism.
+pub fn program(
+    pcie: &Pcie,
+    capabilities: &mut [PciCapability],
+    bars: &[PciBar; 6],
+    allocation: &mut InterruptAllocation,
+    vectors: &[MsiAddrAndData],
+) -> Result<(), PcidServerResponseError> {
+    if vectors.is_empty() || vectors.len() > usize::from(allocation.count) {
+        return Err(PcidServerResponseError::InvalidInterruptVectors);
+    }
+
+    write_vectors(pcie, capabilities, bars, allocation.feature, vectors)?;
+
+    allocation.count = vectors.len() as u16;
+    allocation.vectors = vectors.to_vec();
+    Ok(())
+}
+
+/// Reprogram the vectors of a function whose configuration space and MSI-X table were reset,
+/// e.g. after a D3hot to D0 transition.
+pub fn restore(
+    pcie: &Pcie,
+    capabilities: &mut [PciCapability],
+    bars: &[PciBar; 6],
+    allocation: &InterruptAllocation,
+) -> Result<(), PcidServerResponseError> {
+    if !allocation.is_programmed() {
+        return Ok(());
+    }
+    write_vectors(
+        pcie,
+        capabilities,
+        bars,
+        allocation.feature,
+        &allocation.vectors,
+    )
+}
+
+fn write_vectors(
+    pcie: &Pcie,
+    capabilities: &mut [PciCapability],
+    bars: &[PciBar; 6],
+    feature: PciFeature,
+    vectors: &[MsiAddrAndData],
+) -> Result<(), PcidServerResponseError> {
+    let count = vectors.len() as u16;
+
+    match feature {
+        PciFeature::MsiX => {
+            let table = MsixTable::map(capabilities, bars)?;
+
+            for capability in capabilities.iter_mut() {
+                if let PciCapability::Msi(msi) = capability {
+                    msi.set_enabled(false, pcie);
+                }
+            }
+
+            for i in 0..table.len {
+                table.entry(i).mask();
+            }
+            for (i, vector) in vectors.iter().enumerate() {
+                let entry = table.entry(i as u16);
+                entry.write_addr_and_data(vector.clone());
+                entry.unmask();
+            }
+
+            let msix = find_msix(capabilities)?;
+            msix.set_function_mask(false, pcie);
+            msix.set_enabled(true, pcie);
+        }
+        PciFeature::Msi => {
+            // The device ORs the message number into the low bits of the data, so the vectors
+            // have to form one aligned, contiguous block sharing a single address.
+            let first = &vectors[0];
+            let contiguous = vectors.iter().enumerate().all(|(i, vector)| {
+                vector.addr == first.addr && vector.data == first.data + i as u32
+            });
+            if !count.is_power_of_two()
+                || !contiguous
+                || first.addr & 0b11 != 0
+                || first.data & (u32::from(count) - 1) != 0
+            {
+                return Err(PcidServerResponseError::InvalidInterruptVectors);
+            }
+
+            for capability in capabilities.iter_mut() {
+                if let PciCapability::MsiX(msix) = capability {
+                    msix.set_enabled(false, pcie);
+                }
+            }
+
+            let msi = find_msi(capabilities)?;
+            msi.set_multiple_message_enable(
+                match count.ilog2() {
+                    0 => MultipleMessageSupport::Int1,
+                    1 => MultipleMessageSupport::Int2,
+                    2 => MultipleMessageSupport::Int4,
+                    3 => MultipleMessageSupport::Int8,
+                    4 => MultipleMessageSupport::Int16,
+                    _ => MultipleMessageSupport::Int32,
+                },
+                pcie,
+            );
+            msi.set_message_info(
+                first.addr,
+                first
+                    .data
+                    .try_into()
+                    .map_err(|_| PcidServerResponseError::InvalidInterruptVectors)?,
+                pcie,
+            );
+            msi.set_enabled(true, pcie);
+        }
+    }
+
+    Ok(())
+}
+
+/// Mask and disable the vectors of a function, e.g. when its driver closed the channel.
+pub fn release(
+    pcie: &Pcie,
+    capabilities: &mut [PciCapability],
+    bars: &[PciBar; 6],
+    allocation: InterruptAllocation,
+) {
+    if !allocation.is_programmed() {
+        return;
+    }
+
+    match allocation.feature {
+        PciFeature::MsiX => {
+            match MsixTable::map(capabilities, bars) {
+                Ok(table) => {
+                    for i in 0..table.len {
+                        table.entry(i).mask();
+                    }
+                }
+                Err(err) => log::warn!("pcid: failed to mask MSI-X table: {err:?}"),
+            }
+            if let Ok(msix) = find_msix(capabilities) {
+                msix.set_enabled(false, pcie);
+            }
+        }
+        PciFeature::Msi => {
+            if let Ok(msi) = find_msi(capabilities) {
+                msi.set_enabled(false, pcie);
+            }
+        }
+    }
+}
+
+fn find_msi(
+    capabilities: &mut [PciCapability],
+) -> Result<&mut pci_types::capability::MsiCapability, PcidServerResponseError> {
+    capabilities
+        .iter_mut()
+        .find_map(|capability| match capability {
+            PciCapability::Msi(cap) => Some(cap),
+            _ => None,
+        })
+        .ok_or(PcidServerResponseError::NonexistentFeature(PciFeature::Msi))
+}
+
+fn find_msix(
+    capabilities: &mut [PciCapability],
+) -> Result<&mut pci_types::capability::MsixCapability, PcidServerResponseError> {
+    capabilities
+        .iter_mut()
+        .find_map(|capability| match capability {
+            PciCapability::MsiX(cap) => Some(cap),
+            _ => None,
+        })
+        .ok_or(PcidServerResponseError::NonexistentFeature(PciFeature::MsiX))
+}
+
+/// Temporary mapping of the MSI-X table of a function.
+struct MsixTable {
+    mapping: common::PhysBorrowed,
+    offset: usize,
+    len: u16,
+}
+
+impl MsixTable {
+    fn map(
+        capabilities: &mut [PciCapability],
+        bars: &[PciBar; 6],
+    ) -> Result<Self, PcidServerResponseError> {
+        let msix = find_msix(capabilities)?;
+        let table_bar = msix.table_bar();
+        let len = msix.table_size();
+        let table_offset = msix.table_offset() as usize;
+
+        let bar_addr = match bars.get(usize::from(table_bar)) {
+            Some(PciBar::Memory32 { addr, size }) if *size != 0 => *addr as usize,
+            Some(PciBar::Memory64 { addr, size }) if *size != 0 => *addr as usize,
+            _ => return Err(PcidServerResponseError::NonexistentBar(table_bar)),
+        };
+
+        let phys = bar_addr + table_offset;
+        let base = phys & !(syscall::PAGE_SIZE - 1);
+        let mapping = common::PhysBorrowed::map(
+            base,
+            phys - base + usize::from(len) * size_of::<MsixTableEntry>(),
+            common::Prot::RW,
+            common::MemoryType::Uncacheable,
+        )
+        .map_err(|err| {
+            log::error!("pcid: failed to map MSI-X table at {phys:#x}: {err}");
+            PcidServerResponseError::NonexistentBar(table_bar)
+        })?;
+
+        Ok(Self {
+            mapping,
+            offset: phys - base,
+            len,
+        })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn entry(&self, index: u16) -> &mut MsixTableEntry {
+        assert!(index < self.len);
+        unsafe {
+            &mut *self
+                .mapping
+                .as_ptr()
+                .byte_add(self.offset)
+                .cast::<MsixTableEntry>()
+                .add(usize::from(index))
+        }
+    }
+}
@@ -2,29 +2,52 @@
 #![feature(iter_next_chunk)]
 #![feature(if_let_guard)]
 
+use std::cell::OnceCell;
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use log::{debug, info, trace, warn};
 use pci_types::capability::PciCapability;
 use pci_types::{
-    Bar as TyBar, CommandRegister, EndpointHeader, HeaderType, PciAddress,
-    PciHeader as TyPciHeader, PciPciBridgeHeader,
+    Bar as TyBar, CommandRegister, EndpointHeader, HeaderType, PciAddress, PciHeader as TyPciHeader,
 };
 use redox_scheme::{RequestKind, SignalBehavior};
 
 use crate::cfg_access::Pcie;
 use pcid_interface::{FullDeviceId, LegacyInterruptLine, PciBar, PciFunction};
 
+mod aer;
+mod attr;
+mod bridge;
 mod cfg_access;
 mod driver_handler;
+mod ext_cap;
+mod interrupts;
+mod pm;
+mod rebar;
 mod scheme;
+mod state;
+mod supervisor;
+mod uid;
+mod vpd;
 
 pub struct Func {
     inner: PciFunction,
 
     capabilities: Vec<PciCapability>,
+    ext_capabilities: Vec<ext_cap::ExtendedCapability>,
+    aer: Option<aer::Aer>,
+    interrupts: Option<interrupts::InterruptAllocation>,
+    pm: Option<pm::PowerManagement>,
+    saved_state: Option<state::ConfigState>,
     endpoint_header: EndpointHeader,
     enabled: bool,
+    /// Assigned once enumeration has found every bridge.
+    unique_id: Option<pcid_interface::UniqueId>,
+    /// Name of the driver holding the channel, if it told us.
+    driver: Option<String>,
+    /// Read on first use, as reading it takes configuration space writes and polling.
+    vpd: OnceCell<Option<vpd::Vpd>>,
 }
 
 fn handle_parsed_header(
@@ -89,6 +112,24 @@ fn handle_parsed_header(
         capabilities
     );
 
+    let ext_capabilities = ext_cap::scan(pcie, endpoint_header.header().address());
+    if !ext_capabilities.is_empty() {
+        debug!(
+            "PCI DEVICE EXTENDED CAPABILITIES for {}: {:?}",
+            endpoint_header.header().address(),
+            ext_capabilities
+        );
+    }
+    let aer = ext_cap::find(&ext_capabilities, ext_cap::EXT_CAP_ID_AER)
+        .map(|cap| aer::Aer::new(endpoint_header.header().address(), cap));
+    let pm = capabilities
+        .iter()
+        .find_map(|capability| match capability {
+            PciCapability::PowerManagement(addr) => Some(addr.offset),
+            _ => None,
+        })
+        .map(|offset| pm::PowerManagement::new(endpoint_header.header().address(), offset));
+
     let func = Func {
         inner: pcid_interface::PciFunction {
             bars,
@@ -98,8 +139,16 @@ fn handle_parsed_header(
         },
 
         capabilities,
+        ext_capabilities,
+        aer,
         endpoint_header,
         enabled: false,
+        interrupts: None,
+        pm,
+        saved_state: None,
+        unique_id: None,
+        driver: None,
+        vpd: OnceCell::new(),
     };
 
     tree.insert(func.inner.addr, func);
@@ -164,10 +213,9 @@ fn enable_function(
             0u32,
             interrupt_pin as u32 & pcie.interrupt_map_mask[3],
         ];
-        let mapping = pcie
-            .interrupt_map
-            .iter()
-            .find(|x| x.addr == addr[0..3] && x.interrupt == addr[3]);
+        let mapping = pcie.interrupt_map.iter().find(|x| {
+            x.seg == pci_address.segment() && x.addr == addr[0..3] && x.interrupt == addr[3]
+        });
         let phandled = if let Some(mapping) = mapping {
             Some((
                 mapping.parent_phandle,
@@ -190,6 +238,9 @@ fn enable_function(
 fn main() {
     let mut args = pico_args::Arguments::from_env();
     let verbosity = (0..).find(|_| !args.contains("-v")).unwrap_or(0);
+    let drivers: Option<std::path::PathBuf> = args
+        .opt_value_from_str("--drivers")
+        .expect("pcid: invalid --drivers argument");
     let log_level = match verbosity {
         0 => log::LevelFilter::Info,
         1 => log::LevelFilter::Debug,
@@ -198,27 +249,42 @@ fn main() {
 
     common::setup_logging("bus", "pci", "pcid", log_level, log::LevelFilter::Info);
 
-    redox_daemon::Daemon::new(move |daemon| main_inner(daemon)).unwrap();
+    // Load the driver table before forking, so that a broken one is reported to whoever started
+    // pcid.
+    let drivers = drivers.map(|path| {
+        pcid_interface::config::Config::load(&path)
+            .unwrap_or_else(|err| panic!("pcid: failed to load {}: {err}", path.display()))
+    });
+
+    redox_daemon::Daemon::new(move |daemon| main_inner(daemon, drivers)).unwrap();
 }
 
-fn main_inner(daemon: redox_daemon::Daemon) -> ! {
-    let pcie = Pcie::new();
+fn main_inner(daemon: redox_daemon::Daemon, drivers: Option<pcid_interface::config::Config>) -> ! {
+    let pcie = Arc::new(Pcie::new());
     let mut tree = BTreeMap::new();
+    let mut bridges = BTreeMap::new();
+    let mut bus_numbers = bridge::BusNumbers::new();
 
     info!("PCI SG-BS:DV.F VEND:DEVI CL.SC.IN.RV");
 
-    // FIXME Use full ACPI for enumerating the host bridges. MCFG only describes the first
-    // host bridge, while multi-processor systems likely have a host bridge for each CPU.
-    // See also https://www.kernel.org/doc/html/latest/PCI/acpi-info.html
-    // FIXME Use full ACPI for enumerating the host bridges. MCFG only describes the first
-    // host bridge, while multi-processor systems likely have a host bridge for each CPU.
-    // See also https://www.kernel.org/doc/html/latest/PCI/acpi-info.html
-    let mut bus_nums = vec![0];
+    // FIXME Use full ACPI for enumerating the host bridges. MCFG and the device tree describe
+    // where the configuration space of each segment is, but not which resources the host bridges
+    // decode. See also https://www.kernel.org/doc/html/latest/PCI/acpi-info.html
+    let mut bus_nums = Vec::new();
+    for (segment, buses) in pcie.host_bridges() {
+        debug!(
+            "PCI segment {segment:04x} buses {:02x}-{:02x}",
+            buses.start(),
+            buses.end()
+        );
+        bus_nums.push((segment, *buses.start()));
+        bus_numbers.add_root(segment, buses);
+    }
     let mut bus_i = 0;
 
     // Scan buses
     while bus_i < bus_nums.len() {
-        let bus_num = bus_nums[bus_i];
+        let (segment, bus_num) = bus_nums[bus_i];
         bus_i += 1;
 
         let (tx, rx) = std::sync::mpsc::channel();
@@ -230,7 +296,7 @@ fn main_inner(daemon: redox_daemon::Daemon) -> ! {
                 let pcie = &pcie;
                 s.spawn(move || {
                     for dev_num in chunk_start..std::cmp::min(chunk_start + 4, 32) {
-                        if let Some(results) = scan_device_pure(pcie, bus_num, dev_num) {
+                        if let Some(results) = scan_device_pure(pcie, segment, bus_num, dev_num) {
                             for res in results {
                                 let _ = tx.send(res);
                             }
@@ -241,17 +307,62 @@ fn main_inner(daemon: redox_daemon::Daemon) -> ! {
         });
         drop(tx); // Close main thread sender
 
-        for (func, new_buses) in rx {
-            tree.insert(func.inner.addr, func);
-            bus_nums.extend(new_buses);
+        let mut unconfigured = Vec::new();
+        for (func, bridge) in rx {
+            if let Some(func) = func {
+                tree.insert(func.inner.addr, func);
+            }
+            if let Some(bridge) = bridge {
+                if bridge.is_configured() {
+                    bus_numbers.add_configured(&bridge);
+                    bus_nums.push((segment, bridge.secondary_bus));
+                    bridges.insert(bridge.addr, bridge);
+                } else {
+                    unconfigured.push(bridge);
+                }
+            }
+        }
+
+        // Only number the unconfigured bridges once every configured sibling has claimed its
+        // buses, and in a stable order.
+        unconfigured.sort_by_key(|bridge: &bridge::Bridge| bridge.addr);
+        for mut bridge in unconfigured {
+            if bus_numbers.assign(&pcie, &mut bridge) {
+                info!(
+                    "PCI {} assigned bus {:02x}",
+                    bridge.addr, bridge.secondary_bus
+                );
+                bus_nums.push((segment, bridge.secondary_bus));
+            } else {
+                warn!("pcid: no free bus number for bridge {}", bridge.addr);
+            }
+            bridges.insert(bridge.addr, bridge);
         }
     }
 
     debug!("Enumeration complete, now starting pci scheme");
 
-    let mut scheme = scheme::PciScheme::new(pcie, tree);
+    uid::assign(&pcie, &mut tree, &bridges);
+
+    aer::spawn_handler(
+        Arc::clone(&pcie),
+        tree.values()
+            .filter_map(|func: &Func| func.aer.clone())
+            .collect(),
+    );
+
+    let functions = tree
+        .values()
+        .map(|func: &Func| (func.inner.addr, func.inner.full_device_id))
+        .collect();
+
+    let mut scheme = scheme::PciScheme::new(pcie, tree, bridges);
     let socket = redox_scheme::Socket::create("pci").expect("failed to open pci scheme socket");
 
+    if let Some(drivers) = drivers {
+        scheme.set_supervisor(supervisor::spawn(drivers, functions));
+    }
+
     let _ = daemon.ready();
 
     loop {
@@ -280,95 +391,6 @@ fn main_inner(daemon: redox_daemon::Daemon) -> ! {
     std::process::exit(0);
 }
 
-fn scan_device_pure(pcie: &Pcie, bus_num: u8, dev_num: u8) -> Option<Vec<(Func, Vec<u8>)>> {
-    let mut results = Vec::new();
-
-    for func_num in 0..8 {
-        let header = TyPciHeader::new(PciAddress::new(0, bus_num, dev_num, func_num));
-
-        let (vendor_id, device_id) = header.id(pcie);
-        if vendor_id == 0xffff && device_id == 0xffff {
-            if func_num == 0 {
-                // trace!("PCI {:>02X}:{:>02X}: no dev", bus_num, dev_num);
-                return None;
-            }
-            continue;
-        }
-
-        let (revision, class, subclass, interface) = header.revision_and_class(pcie);
-        let full_device_id = pcid_interface::FullDeviceId {
-            vendor_id,
-            device_id,
-            class,
-            subclass,
-            interface,
-            revision,
-        };
-
-        info!("PCI {} {}", header.address(), full_device_id.display());
-
-        let has_multiple_functions = header.has_multiple_functions(pcie);
-
-        let mut new_buses = Vec::new();
-
-        let endpoint_header = match header.header_type(pcie) {
-            HeaderType::Endpoint => Some(EndpointHeader::from_header(header, pcie).unwrap()),
-            HeaderType::PciPciBridge => {
-                let bridge_header = PciPciBridgeHeader::from_header(header, pcie).unwrap();
-                new_buses.push(bridge_header.secondary_bus_number(pcie));
-                // Bridges might also have capabilities? Usually EndpointHeader is for Endpoints.
-                // But PCID structure `Func` expects `EndpointHeader`.
-                // Existing code passed `EndpointHeader` via `handle_parsed_header` ONLY for `HeaderType::Endpoint`.
-                // Bridges were just adding bus nums.
-                // Does `pcid` not expose bridges as functions?
-                // The original code:
-                /*
-                match header.header_type(pcie) {
-                    HeaderType::Endpoint => { handle_parsed_header(...) }
-                    HeaderType::PciPciBridge => { bus_nums.push(...) }
-                }
-                */
-                // So bridges are NOT added to the `tree` in the original code.
-                None
-            }
-            ty => {
-                warn!("pcid: unknown header type: {ty:?}");
-                None
-            }
-        };
-
-        if let Some(endpoint_header) = endpoint_header {
-            let func = build_func(pcie, endpoint_header, full_device_id);
-            results.push((func, new_buses));
-        } else if !new_buses.is_empty() {
-            // Bridge case: we don't add to tree, but we return new buses
-            // We need to return the new buses even if no func is added.
-            // But my return type is `Vec<(Func, Vec<u8>)>`.
-            // If I return dummy func? No.
-            // I should chanage return type or logic.
-            // Actually, the original code DID NOT insert Func for bridges.
-            // So I only need to return buses.
-
-            // Let's change return type to `Vec<ScanResult>` enum?
-            // Or `(Vec<Func>, Vec<u8>)`.
-            // `results` accumulator.
-            // `scan_device_pure` -> `(Vec<Func>, Vec<u8>)`.
-        }
-
-        if func_num == 0 && !has_multiple_functions {
-            break;
-        }
-    }
-
-    // Refined logic for return
-    // I cannot easily restart this replace block logic without defining `build_func` helper.
-    // I will extract `handle_parsed_header` logic into `build_func`.
-    // And updated `scan_device_pure` to return `(Vec<Func>, Vec<u8>)`.
-
-    // I'll rewrite the function below correctly.
-    None // Placeholder to break replace block compilation check for now? No, I must write valid code.
-}
-
 fn build_func(
     pcie: &Pcie,
     endpoint_header: EndpointHeader,
@@ -432,6 +454,24 @@ fn build_func(
         capabilities
     );
 
+    let ext_capabilities = ext_cap::scan(pcie, endpoint_header.header().address());
+    if !ext_capabilities.is_empty() {
+        debug!(
+            "PCI DEVICE EXTENDED CAPABILITIES for {}: {:?}",
+            endpoint_header.header().address(),
+            ext_capabilities
+        );
+    }
+    let aer = ext_cap::find(&ext_capabilities, ext_cap::EXT_CAP_ID_AER)
+        .map(|cap| aer::Aer::new(endpoint_header.header().address(), cap));
+    let pm = capabilities
+        .iter()
+        .find_map(|capability| match capability {
+            PciCapability::PowerManagement(addr) => Some(addr.offset),
+            _ => None,
+        })
+        .map(|offset| pm::PowerManagement::new(endpoint_header.header().address(), offset));
+
     Func {
         inner: pcid_interface::PciFunction {
             bars,
@@ -441,17 +481,29 @@ fn build_func(
         },
 
         capabilities,
+        ext_capabilities,
+        aer,
         endpoint_header,
         enabled: false,
+        interrupts: None,
+        pm,
+        saved_state: None,
+        unique_id: None,
+        driver: None,
+        vpd: OnceCell::new(),
     }
 }
 
-// Redefined scan_device_pure properly
-fn scan_device_pure(pcie: &Pcie, bus_num: u8, dev_num: u8) -> Option<Vec<(Option<Func>, Vec<u8>)>> {
+fn scan_device_pure(
+    pcie: &Pcie,
+    segment: u16,
+    bus_num: u8,
+    dev_num: u8,
+) -> Option<Vec<(Option<Func>, Option<bridge::Bridge>)>> {
     let mut results = Vec::new();
 
     for func_num in 0..8 {
-        let header = TyPciHeader::new(PciAddress::new(0, bus_num, dev_num, func_num));
+        let header = TyPciHeader::new(PciAddress::new(segment, bus_num, dev_num, func_num));
 
         let (vendor_id, device_id) = header.id(pcie);
         if vendor_id == 0xffff && device_id == 0xffff {
@@ -475,7 +527,7 @@ fn scan_device_pure(pcie: &Pcie, bus_num: u8, dev_num: u8) -> Option<Vec<(Option
 
         let has_multiple_functions = header.has_multiple_functions(pcie);
 
-        let mut new_buses = Vec::new();
+        let mut found_bridge = None;
         let mut found_func = None;
 
         match header.header_type(pcie) {
@@ -487,15 +539,14 @@ fn scan_device_pure(pcie: &Pcie, bus_num: u8, dev_num: u8) -> Option<Vec<(Option
                 ));
             }
             HeaderType::PciPciBridge => {
-                let bridge_header = PciPciBridgeHeader::from_header(header, pcie).unwrap();
-                new_buses.push(bridge_header.secondary_bus_number(pcie));
+                found_bridge = Some(bridge::Bridge::new(pcie, header.address(), full_device_id));
             }
             ty => {
                 warn!("pcid: unknown header type: {ty:?}");
             }
         }
 
-        results.push((found_func, new_buses));
+        results.push((found_func, found_bridge));
 
         if func_num == 0 && !has_multiple_functions {
             break;
@@ -0,0 +1,137 @@
+//! PCI power management capability.
+//!
+//! A function that goes from D3hot back to D0 performs an internal reset unless it advertises
+//! No_Soft_Reset, losing its BAR assignments and interrupt configuration. pcid saves the relevant
+//! parts of the configuration space before the function leaves D0 and writes them back on resume.
+
+use std::thread;
+use std::time::Duration;
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+use pcid_interface::pm::PowerState;
+use pcid_interface::PcidServerResponseError;
+
+use crate::cfg_access::Pcie;
+use crate::ext_cap::ExtendedCapability;
+use crate::state::ConfigState;
+
+const PMC: u16 = 0x00;
+const PMCSR: u16 = 0x04;
+
+// The PMC register is the upper half of the first dword of the capability.
+const PMC_D1_SUPPORT: u32 = 1 << (16 + 9);
+const PMC_D2_SUPPORT: u32 = 1 << (16 + 10);
+
+const PMCSR_STATE_MASK: u32 = 0b11;
+const PMCSR_NO_SOFT_RESET: u32 = 1 << 3;
+/// Write-1-to-clear, must not be written back as set by accident.
+const PMCSR_PME_STATUS: u32 = 1 << 15;
+
+/// Recovery times from PCI PM 1.2 section 5.6.1.
+const D3HOT_DELAY: Duration = Duration::from_millis(10);
+const D2_DELAY: Duration = Duration::from_micros(200);
+
+#[derive(Debug)]
+pub struct PowerManagement {
+    addr: PciAddress,
+    offset: u16,
+    saved: Option<ConfigState>,
+}
+
+impl PowerManagement {
+    pub fn new(addr: PciAddress, offset: u16) -> Self {
+        Self {
+            addr,
+            offset,
+            saved: None,
+        }
+    }
+
+    fn read(&self, pcie: &Pcie, reg: u16) -> u32 {
+        unsafe { pcie.read(self.addr, self.offset + reg) }
+    }
+
+    pub fn state(&self, pcie: &Pcie) -> PowerState {
+        PowerState::from_bits(self.read(pcie, PMCSR) & PMCSR_STATE_MASK)
+    }
+
+    pub fn supports(&self, pcie: &Pcie, state: PowerState) -> bool {
+        let pmc = self.read(pcie, PMC);
+        match state {
+            PowerState::D0 | PowerState::D3Hot => true,
+            PowerState::D1 => pmc & PMC_D1_SUPPORT != 0,
+            PowerState::D2 => pmc & PMC_D2_SUPPORT != 0,
+        }
+    }
+
+    /// Move the function to `state`.
+    ///
+    /// Returns `true` if the function went through a soft reset and the caller has to restore
+    /// state that isn't kept in the header, such as MSI-X vectors.
+    pub fn set_state(
+        &mut self,
+        pcie: &Pcie,
+        ext_capabilities: &[ExtendedCapability],
+        state: PowerState,
+    ) -> Result<bool, PcidServerResponseError> {
+        if !self.supports(pcie, state) {
+            return Err(PcidServerResponseError::UnsupportedPowerState(state));
+        }
+
+        let current = self.state(pcie);
+        if current == state {
+            return Ok(false);
+        }
+
+        // Only transitions to a deeper state or back to D0 are allowed.
+        let mut reset = false;
+        if current != PowerState::D0 && state < current {
+            reset = self.write_state(pcie, current, PowerState::D0);
+            if reset {
+                self.restore(pcie);
+            }
+        }
+
+        if state == PowerState::D0 {
+            self.saved = None;
+        } else {
+            if self.saved.is_none() {
+                self.saved = Some(ConfigState::save(pcie, self.addr, ext_capabilities));
+            }
+            self.write_state(pcie, self.state(pcie), state);
+        }
+
+        log::debug!("pcid: {} {} -> {}", self.addr, current, state);
+
+        Ok(reset)
+    }
+
+    /// Write the PowerState field, wait for the transition and report whether the function was
+    /// reset by it.
+    fn write_state(&self, pcie: &Pcie, from: PowerState, to: PowerState) -> bool {
+        let pmcsr = self.read(pcie, PMCSR);
+        unsafe {
+            pcie.write(
+                self.addr,
+                self.offset + PMCSR,
+                (pmcsr & !(PMCSR_STATE_MASK | PMCSR_PME_STATUS)) | to.bits(),
+            );
+        }
+
+        let slowest = from.max(to);
+        match slowest {
+            PowerState::D3Hot => thread::sleep(D3HOT_DELAY),
+            PowerState::D2 => thread::sleep(D2_DELAY),
+            _ => {}
+        }
+
+        from == PowerState::D3Hot && to == PowerState::D0 && pmcsr & PMCSR_NO_SOFT_RESET == 0
+    }
+
+    fn restore(&self, pcie: &Pcie) {
+        match &self.saved {
+            Some(saved) => saved.restore(pcie),
+            None => log::warn!("pcid: {} was reset without saved state", self.addr),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Resizable BAR extended capability.
+//!
+//! Firmware usually only assigns the smallest supported size to a resizable BAR (often 256 MiB of
+//! VRAM). GPU drivers can ask pcid to grow the BAR, in which case pcid looks for room inside the
+//! memory window of the upstream bridge and reprograms both the capability and the BAR.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use pci_types::{CommandRegister, ConfigRegionAccess, EndpointHeader, PciAddress};
+use pcid_interface::{PciBar, PcidServerResponseError, ResizableBar};
+
+use crate::bridge::{self, Bridge};
+use crate::cfg_access::Pcie;
+use crate::ext_cap::{self, ExtendedCapability};
+use crate::Func;
+
+pub const EXT_CAP_ID_REBAR: u16 = 0x0015;
+
+const REBAR_CAP: u16 = 0x04;
+const REBAR_CTRL: u16 = 0x08;
+const REBAR_ENTRY_SIZE: u16 = 0x08;
+
+const CTRL_BAR_INDEX_MASK: u32 = 0x7;
+const CTRL_NUM_BARS_SHIFT: u32 = 5;
+const CTRL_BAR_SIZE_SHIFT: u32 = 8;
+const CTRL_BAR_SIZE_MASK: u32 = 0x3F << CTRL_BAR_SIZE_SHIFT;
+
+/// Sizes are encoded as log2(size) - 20, the smallest size being 1 MiB.
+const SIZE_ENCODING_BASE: u32 = 20;
+
+const BAR_OFFSET: u16 = 0x10;
+const BAR_MEM_64: u32 = 0b10 << 1;
+const BAR_PREFETCHABLE: u32 = 1 << 3;
+
+const BRIDGE_MEM_WINDOW: u16 = 0x20;
+const BRIDGE_PREF_WINDOW: u16 = 0x24;
+const BRIDGE_PREF_BASE_UPPER: u16 = 0x28;
+const BRIDGE_PREF_LIMIT_UPPER: u16 = 0x2C;
+
+struct Entry {
+    ctrl_offset: u16,
+    bar: u8,
+    supported: u32,
+    current: u32,
+}
+
+fn entries(pcie: &Pcie, addr: PciAddress, cap: ExtendedCapability) -> Vec<Entry> {
+    let first_ctrl = unsafe { pcie.read(addr, cap.offset + REBAR_CTRL) };
+    let count = ((first_ctrl >> CTRL_NUM_BARS_SHIFT) & 0x7).clamp(1, 6) as u16;
+
+    (0..count)
+        .map(|i| {
+            let entry = cap.offset + i * REBAR_ENTRY_SIZE;
+            let supported = unsafe { pcie.read(addr, entry + REBAR_CAP) } >> 4;
+            let ctrl = unsafe { pcie.read(addr, entry + REBAR_CTRL) };
+            Entry {
+                ctrl_offset: entry + REBAR_CTRL,
+                bar: (ctrl & CTRL_BAR_INDEX_MASK) as u8,
+                supported,
+                current: (ctrl & CTRL_BAR_SIZE_MASK) >> CTRL_BAR_SIZE_SHIFT,
+            }
+        })
+        .collect()
+}
+
+fn encoded_size(encoding: u32) -> u64 {
+    1 << (encoding + SIZE_ENCODING_BASE)
+}
+
+/// List the resizable BARs of a function.
+pub fn resizable_bars(
+    pcie: &Pcie,
+    addr: PciAddress,
+    ext_capabilities: &[ExtendedCapability],
+) -> Vec<ResizableBar> {
+    let Some(cap) = ext_cap::find(ext_capabilities, EXT_CAP_ID_REBAR) else {
+        return Vec::new();
+    };
+
+    entries(pcie, addr, cap)
+        .into_iter()
+        .map(|entry| ResizableBar {
+            bar: entry.bar,
+            current_size: encoded_size(entry.current),
+            supported_sizes: (0..28)
+                .filter(|bit| entry.supported & (1 << bit) != 0)
+                .map(encoded_size)
+                .collect(),
+        })
+        .collect()
+}
+
+/// The address ranges that constrain where a BAR behind a given bridge may be placed.
+pub struct BarWindow {
+    window: Option<Range<u64>>,
+    used: Vec<Range<u64>>,
+}
+
+impl BarWindow {
+    /// Collect the memory window of the bridge above `addr` and every memory BAR of the other
+    /// functions on the same bus.
+    pub fn new(
+        pcie: &Pcie,
+        bridges: &BTreeMap<PciAddress, Bridge>,
+        tree: &BTreeMap<PciAddress, Func>,
+        addr: PciAddress,
+        prefetchable: bool,
+    ) -> Self {
+        let window = bridge::upstream(bridges, addr)
+            .and_then(|bridge| bridge_window(pcie, bridge.addr, prefetchable));
+
+        let used = tree
+            .values()
+            .filter(|func| {
+                func.inner.addr.segment() == addr.segment()
+                    && func.inner.addr.bus() == addr.bus()
+                    && func.inner.addr != addr
+            })
+            .flat_map(|func| func.inner.bars.iter().filter_map(bar_range))
+            .collect();
+
+        Self { window, used }
+    }
+
+    fn allocate(&self, current: u64, size: u64, others: &[Range<u64>]) -> Option<u64> {
+        let window = self.window.as_ref()?;
+        let fits = |start: u64| {
+            let Some(end) = start.checked_add(size) else {
+                return false;
+            };
+            start >= window.start
+                && end <= window.end
+                && self
+                    .used
+                    .iter()
+                    .chain(others)
+                    .all(|range| end <= range.start || range.end <= start)
+        };
+
+        // Prefer keeping the BAR where it is, so that only its size changes.
+        if current % size == 0 && fits(current) {
+            return Some(current);
+        }
+
+        let mut start = window.start.next_multiple_of(size);
+        while start < window.end {
+            if fits(start) {
+                return Some(start);
+            }
+            start = start.checked_add(size)?;
+        }
+        None
+    }
+}
+
+fn bar_range(bar: &PciBar) -> Option<Range<u64>> {
+    match *bar {
+        PciBar::Memory32 { addr, size } if size != 0 => {
+            Some(u64::from(addr)..u64::from(addr) + u64::from(size))
+        }
+        PciBar::Memory64 { addr, size } if size != 0 => Some(addr..addr + size),
+        _ => None,
+    }
+}
+
+fn bridge_window(pcie: &Pcie, bridge: PciAddress, prefetchable: bool) -> Option<Range<u64>> {
+    let (base, limit) = if prefetchable {
+        let window = unsafe { pcie.read(bridge, BRIDGE_PREF_WINDOW) };
+        let mut base = u64::from(window & 0xFFF0) << 16;
+        let mut limit = (u64::from((window >> 16) & 0xFFF0) << 16) | 0xF_FFFF;
+        if window & 0xF == 0x1 {
+            // 64-bit prefetchable window
+            base |= u64::from(unsafe { pcie.read(bridge, BRIDGE_PREF_BASE_UPPER) }) << 32;
+            limit |= u64::from(unsafe { pcie.read(bridge, BRIDGE_PREF_LIMIT_UPPER) }) << 32;
+        }
+        (base, limit)
+    } else {
+        let window = unsafe { pcie.read(bridge, BRIDGE_MEM_WINDOW) };
+        let base = u64::from(window & 0xFFF0) << 16;
+        let limit = (u64::from((window >> 16) & 0xFFF0) << 16) | 0xF_FFFF;
+        (base, limit)
+    };
+
+    if limit < base {
+        return None;
+    }
+    Some(base..limit + 1)
+}
+
+pub fn is_prefetchable(pcie: &Pcie, addr: PciAddress, bar: u8) -> bool {
+    let reg = unsafe { pcie.read(addr, BAR_OFFSET + u16::from(bar) * 4) };
+    reg & BAR_PREFETCHABLE != 0
+}
+
+/// Resize BAR `bar` of the function to `size` bytes, moving it inside `window` if necessary.
+pub fn resize(
+    pcie: &Pcie,
+    endpoint_header: &mut EndpointHeader,
+    ext_capabilities: &[ExtendedCapability],
+    bars: &[PciBar; 6],
+    window: &BarWindow,
+    bar: u8,
+    size: u64,
+) -> Result<PciBar, PcidServerResponseError> {
+    let addr = endpoint_header.header().address();
+
+    let cap = ext_cap::find(ext_capabilities, EXT_CAP_ID_REBAR)
+        .ok_or(PcidServerResponseError::NonexistentBar(bar))?;
+    let entry = entries(pcie, addr, cap)
+        .into_iter()
+        .find(|entry| entry.bar == bar)
+        .ok_or(PcidServerResponseError::NonexistentBar(bar))?;
+
+    if !size.is_power_of_two() || size.trailing_zeros() < SIZE_ENCODING_BASE {
+        return Err(PcidServerResponseError::UnsupportedBarSize(size));
+    }
+    let encoding = size.trailing_zeros() - SIZE_ENCODING_BASE;
+    if encoding >= 28 || entry.supported & (1 << encoding) == 0 {
+        return Err(PcidServerResponseError::UnsupportedBarSize(size));
+    }
+
+    let current = match bars[usize::from(bar)] {
+        PciBar::Memory32 { addr, .. } => u64::from(addr),
+        PciBar::Memory64 { addr, .. } => addr,
+        _ => return Err(PcidServerResponseError::NonexistentBar(bar)),
+    };
+    let is_64bit = matches!(bars[usize::from(bar)], PciBar::Memory64 { .. });
+    if !is_64bit && size > u64::from(u32::MAX) {
+        return Err(PcidServerResponseError::UnsupportedBarSize(size));
+    }
+
+    let others = bars
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != usize::from(bar))
+        .filter_map(|(_, bar)| bar_range(bar))
+        .collect::<Vec<_>>();
+    let new_addr = window
+        .allocate(current, size, &others)
+        .ok_or(PcidServerResponseError::NoBarSpace)?;
+    if !is_64bit && new_addr + size > 1 << 32 {
+        return Err(PcidServerResponseError::NoBarSpace);
+    }
+
+    log::info!(
+        "pcid: {} resizing BAR{} from {:#x}@{:#x} to {:#x}@{:#x}",
+        addr,
+        bar,
+        encoded_size(entry.current),
+        current,
+        size,
+        new_addr,
+    );
+
+    // The BAR must not be decoded while its size and address are inconsistent.
+    let mut memory_was_enabled = false;
+    endpoint_header.update_command(pcie, |cmd| {
+        memory_was_enabled = cmd.contains(CommandRegister::MEMORY_ENABLE);
+        cmd - CommandRegister::MEMORY_ENABLE
+    });
+
+    unsafe {
+        let ctrl = pcie.read(addr, entry.ctrl_offset);
+        pcie.write(
+            addr,
+            entry.ctrl_offset,
+            (ctrl & !CTRL_BAR_SIZE_MASK) | (encoding << CTRL_BAR_SIZE_SHIFT),
+        );
+
+        let bar_offset = BAR_OFFSET + u16::from(bar) * 4;
+        let flags = pcie.read(addr, bar_offset) & 0xF;
+        pcie.write(addr, bar_offset, (new_addr as u32 & !0xF) | flags);
+        if flags & BAR_MEM_64 != 0 {
+            pcie.write(addr, bar_offset + 4, (new_addr >> 32) as u32);
+        }
+    }
+
+    if memory_was_enabled {
+        endpoint_header.update_command(pcie, |cmd| cmd | CommandRegister::MEMORY_ENABLE);
+    }
+
+    Ok(if is_64bit {
+        PciBar::Memory64 {
+            addr: new_addr,
+            size,
+        }
+    } else {
+        PciBar::Memory32 {
+            addr: new_addr as u32,
+            size: size as u32,
+        }
+    })
+}
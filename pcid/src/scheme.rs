@@ -1,27 +1,43 @@
 use std::collections::{BTreeMap, VecDeque};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
 
 use pci_types::{ConfigRegionAccess, PciAddress};
 use redox_scheme::scheme::SchemeSync;
 use redox_scheme::{CallerCtx, OpenResult};
 use syscall::dirent::{DirEntry, DirentBuf, DirentKind};
-use syscall::error::{Error, Result, EACCES, EBADF, EINVAL, EIO, EISDIR, ENOENT, ENOTDIR};
-use syscall::flag::{MODE_CHR, MODE_DIR, O_DIRECTORY, O_STAT};
+use syscall::error::{
+    Error, Result, EACCES, EBADF, EINVAL, EIO, EISDIR, ENODEV, ENOENT, ENOMEM, ENOTDIR,
+};
+use syscall::flag::{MODE_CHR, MODE_DIR, MODE_FILE, O_DIRECTORY, O_STAT};
 use syscall::schemev2::NewFdFlags;
-use syscall::ENOLCK;
+use syscall::{MapFlags, ENOLCK, PAGE_SIZE};
 
+use crate::attr::Attr;
+use crate::bridge::Bridge;
 use crate::cfg_access::Pcie;
+use crate::rebar::BarWindow;
+use pcid_interface::pm::PowerState;
+use pcid_interface::{PcidClientRequest, PcidClientResponse};
 
 pub struct PciScheme {
     handles: BTreeMap<usize, HandleWrapper>,
     next_id: usize,
-    pcie: Pcie,
+    pcie: Arc<Pcie>,
     tree: BTreeMap<PciAddress, crate::Func>,
+    bridges: BTreeMap<PciAddress, Bridge>,
+    /// Told about every closed channel, so it can restart the driver.
+    supervisor: Option<Sender<PciAddress>>,
 }
 enum Handle {
     TopLevel { entries: Vec<String> },
     Access,
-    Device,
+    Device { entries: Vec<&'static str> },
     Channel { addr: PciAddress, st: ChannelState },
+    Aer { addr: PciAddress },
+    Bridge { addr: PciAddress },
+    Attr { addr: PciAddress, attr: Attr },
+    Bar { addr: PciAddress, bar: u8, mapping: Option<common::PhysBorrowed> },
 }
 struct HandleWrapper {
     inner: Handle,
@@ -29,14 +45,26 @@ struct HandleWrapper {
 }
 impl Handle {
     fn is_file(&self) -> bool {
-        matches!(self, Self::Access | Self::Channel { .. })
+        matches!(
+            self,
+            Self::Access
+                | Self::Channel { .. }
+                | Self::Aer { .. }
+                | Self::Bridge { .. }
+                | Self::Attr { .. }
+                | Self::Bar { .. }
+        )
     }
     fn is_dir(&self) -> bool {
         !self.is_file()
     }
     // TODO: capability rather than root
     fn requires_root(&self) -> bool {
-        matches!(self, Self::Access | Self::Channel { .. })
+        match self {
+            Self::Access | Self::Channel { .. } | Self::Bar { .. } => true,
+            Self::Attr { attr, .. } => attr.requires_root(),
+            _ => false,
+        }
     }
 }
 
@@ -45,8 +73,6 @@ enum ChannelState {
     AwaitingResponseRead(VecDeque<u8>),
 }
 
-const DEVICE_CONTENTS: &[&str] = &["channel"];
-
 impl SchemeSync for PciScheme {
     fn open(&mut self, path: &str, flags: usize, ctx: &CallerCtx) -> Result<OpenResult> {
         log::trace!("OPEN `{}` flags {}", path, flags);
@@ -57,12 +83,17 @@ impl SchemeSync for PciScheme {
         let path = path.trim_matches('/');
 
         let handle = if path.is_empty() {
+            let mut addrs = self
+                .tree
+                .keys()
+                .chain(self.bridges.keys())
+                .collect::<Vec<_>>();
+            addrs.sort();
             Handle::TopLevel {
-                entries: self
-                    .tree
-                    .iter()
+                entries: addrs
+                    .into_iter()
                     // FIXME remove replacement of : once the old scheme format is no longer supported.
-                    .map(|(addr, _)| format!("{}", addr).replace(':', "--"))
+                    .map(|addr| format!("{}", addr).replace(':', "--"))
                     .collect::<Vec<_>>(),
             }
         } else if path == "access" {
@@ -106,8 +137,20 @@ impl SchemeSync for PciScheme {
 
         let (len, mode) = match handle.inner {
             Handle::TopLevel { ref entries } => (entries.len(), MODE_DIR | 0o755),
-            Handle::Device => (DEVICE_CONTENTS.len(), MODE_DIR | 0o755),
+            Handle::Device { ref entries } => (entries.len(), MODE_DIR | 0o755),
             Handle::Access | Handle::Channel { .. } => (0, MODE_CHR | 0o600),
+            Handle::Aer { .. } | Handle::Bridge { .. } => (0, MODE_CHR | 0o444),
+            Handle::Attr { addr, attr } => {
+                let func = self.tree.get(&addr).ok_or(Error::new(EBADF))?;
+                let mode = if attr.requires_root() { 0o400 } else { 0o444 };
+                (attr.read(&self.pcie, func).len(), MODE_FILE | mode)
+            }
+            Handle::Bar { addr, bar, .. } => {
+                let func = self.tree.get(&addr).ok_or(Error::new(EBADF))?;
+                let size = crate::attr::bar_region(&func.inner.bars[usize::from(bar)])
+                    .map_or(0, |(_, size)| size);
+                (size as usize, MODE_FILE | 0o600)
+            }
         };
         stat.st_size = len as u64;
         stat.st_mode = mode;
@@ -117,7 +160,7 @@ impl SchemeSync for PciScheme {
         &mut self,
         id: usize,
         buf: &mut [u8],
-        _offset: u64,
+        offset: u64,
         _fcntl_flags: u32,
         _ctx: &CallerCtx,
     ) -> Result<usize> {
@@ -129,7 +172,20 @@ impl SchemeSync for PciScheme {
 
         match handle.inner {
             Handle::TopLevel { .. } => Err(Error::new(EISDIR)),
-            Handle::Device => Err(Error::new(EISDIR)),
+            Handle::Device { .. } => Err(Error::new(EISDIR)),
+            Handle::Aer { addr } => {
+                let func = self.tree.get(&addr).ok_or(Error::new(EBADF))?;
+                let report = func.aer.as_ref().ok_or(Error::new(EBADF))?.report();
+                Ok(read_at(report.as_bytes(), buf, offset))
+            }
+            Handle::Bridge { addr } => {
+                let report = self.bridges.get(&addr).ok_or(Error::new(EBADF))?.report();
+                Ok(read_at(report.as_bytes(), buf, offset))
+            }
+            Handle::Attr { addr, attr } => {
+                let func = self.tree.get(&addr).ok_or(Error::new(EBADF))?;
+                Ok(read_at(&attr.read(&self.pcie, func), buf, offset))
+            }
             Handle::Channel {
                 addr: _,
                 ref mut st,
@@ -165,8 +221,13 @@ impl SchemeSync for PciScheme {
                 }
                 return Ok(buf);
             }
-            Handle::Device => DEVICE_CONTENTS,
-            Handle::Access | Handle::Channel { .. } => return Err(Error::new(ENOTDIR)),
+            Handle::Device { ref entries } => entries,
+            Handle::Access
+            | Handle::Channel { .. }
+            | Handle::Aer { .. }
+            | Handle::Bridge { .. }
+            | Handle::Attr { .. }
+            | Handle::Bar { .. } => return Err(Error::new(ENOTDIR)),
         };
 
         for (i, dent_name) in entries.iter().enumerate().skip(offset) {
@@ -196,7 +257,7 @@ impl SchemeSync for PciScheme {
 
         match handle.inner {
             Handle::Channel { addr, ref mut st } => {
-                Self::write_channel(&self.pcie, &mut self.tree, addr, st, buf)
+                Self::write_channel(&self.pcie, &mut self.tree, &self.bridges, addr, st, buf)
             }
 
             _ => Err(Error::new(EBADF)),
@@ -261,6 +322,69 @@ impl SchemeSync for PciScheme {
             _ => Err(Error::new(EBADF)),
         }
     }
+
+    fn mmap_prep(
+        &mut self,
+        id: usize,
+        offset: u64,
+        size: usize,
+        _flags: MapFlags,
+        _ctx: &CallerCtx,
+    ) -> Result<usize> {
+        let handle = self.handles.get_mut(&id).ok_or(Error::new(EBADF))?;
+
+        if handle.stat {
+            return Err(Error::new(EBADF));
+        }
+
+        let Handle::Bar {
+            addr,
+            bar,
+            ref mut mapping,
+        } = handle.inner
+        else {
+            return Err(Error::new(EBADF));
+        };
+        let func = self.tree.get(&addr).ok_or(Error::new(EBADF))?;
+        let (phys, len) = crate::attr::bar_region(&func.inner.bars[usize::from(bar)])
+            .ok_or(Error::new(ENODEV))?;
+
+        // BARs can be smaller than a page and needn't start at one, so the file maps the whole
+        // pages holding the BAR, starting with the page containing its start. A BAR which isn't
+        // page aligned is at its physical address modulo the page size into the mapping.
+        let page_mask = PAGE_SIZE as u64 - 1;
+        let base = phys & !page_mask;
+        let window = (phys - base + len + page_mask) & !page_mask;
+        if offset & page_mask != 0
+            || offset
+                .checked_add(size as u64)
+                .map_or(true, |end| end > window)
+        {
+            return Err(Error::new(EINVAL));
+        }
+
+        if mapping.is_none() {
+            let ty = if crate::rebar::is_prefetchable(&self.pcie, addr, bar) {
+                common::MemoryType::WriteCombining
+            } else {
+                common::MemoryType::Uncacheable
+            };
+            *mapping = Some(
+                common::PhysBorrowed::map(
+                    base as usize,
+                    window as usize,
+                    common::Prot::RW,
+                    ty,
+                )
+                .map_err(|err| {
+                    log::error!("pcid: failed to map BAR{bar} of {addr}: {err}");
+                    Error::new(ENOMEM)
+                })?,
+            );
+        }
+        let ptr = mapping.as_ref().unwrap().as_ptr();
+        Ok(ptr as usize + offset as usize)
+    }
 }
 
 impl PciScheme {
@@ -272,8 +396,29 @@ impl PciScheme {
             }) => {
                 log::trace!("TODO: Support disabling device (called on {})", addr);
                 if let Some(func) = self.tree.get_mut(&addr) {
+                    // Leave the function usable for the next driver.
+                    if let Some(pm) = func.pm.as_mut() {
+                        if let Err(err) =
+                            pm.set_state(&self.pcie, &func.ext_capabilities, PowerState::D0)
+                        {
+                            log::warn!("pcid: failed to resume {addr} on close: {err:?}");
+                        }
+                    }
+                    if let Some(allocation) = func.interrupts.take() {
+                        crate::interrupts::release(
+                            &self.pcie,
+                            &mut func.capabilities,
+                            &func.inner.bars,
+                            allocation,
+                        );
+                    }
+                    func.saved_state = None;
+                    func.driver = None;
                     func.enabled = false;
                 }
+                if let Some(supervisor) = &self.supervisor {
+                    let _ = supervisor.send(addr);
+                }
             }
             _ => {}
         }
@@ -281,22 +426,46 @@ impl PciScheme {
 }
 
 impl PciScheme {
-    pub fn new(pcie: Pcie, tree: BTreeMap<PciAddress, crate::Func>) -> Self {
+    pub fn new(
+        pcie: Arc<Pcie>,
+        tree: BTreeMap<PciAddress, crate::Func>,
+        bridges: BTreeMap<PciAddress, Bridge>,
+    ) -> Self {
         Self {
             handles: BTreeMap::new(),
             next_id: 0,
             pcie,
             tree,
+            bridges,
+            supervisor: None,
         }
     }
+
+    pub fn set_supervisor(&mut self, supervisor: Sender<PciAddress>) {
+        self.supervisor = Some(supervisor);
+    }
     fn parse_after_pci_addr(&mut self, addr: PciAddress, after: &str) -> Result<Handle> {
         if after.chars().next().map_or(false, |c| c != '/') {
             return Err(Error::new(ENOENT));
         }
+        if self.bridges.contains_key(&addr) {
+            return match after {
+                "" => Ok(Handle::Device {
+                    entries: vec!["bridge"],
+                }),
+                "/bridge" => Ok(Handle::Bridge { addr }),
+                _ => Err(Error::new(ENOENT)),
+            };
+        }
         let func = self.tree.get_mut(&addr).ok_or(Error::new(ENOENT))?;
 
         Ok(if after.is_empty() {
-            Handle::Device
+            let mut entries = vec!["channel"];
+            if func.aer.is_some() {
+                entries.push("aer");
+            }
+            entries.extend(crate::attr::entries(func));
+            Handle::Device { entries }
         } else {
             let path = &after[1..];
 
@@ -316,7 +485,22 @@ impl PciScheme {
                         st: ChannelState::AwaitingData,
                     }
                 }
-                _ => return Err(Error::new(ENOENT)),
+                "aer" if func.aer.is_some() => Handle::Aer { addr },
+                _ => {
+                    if let Some(attr) = Attr::from_name(path).filter(|attr| attr.is_present(func)) {
+                        Handle::Attr { addr, attr }
+                    } else if let Some(bar) = crate::attr::parse_bar(path)
+                        .filter(|&bar| !func.inner.bars[usize::from(bar)].is_none())
+                    {
+                        Handle::Bar {
+                            addr,
+                            bar,
+                            mapping: None,
+                        }
+                    } else {
+                        return Err(Error::new(ENOENT));
+                    }
+                }
             }
         })
     }
@@ -340,6 +524,7 @@ impl PciScheme {
     fn write_channel(
         pci_state: &Pcie,
         tree: &mut BTreeMap<PciAddress, crate::Func>,
+        bridges: &BTreeMap<PciAddress, Bridge>,
         addr: PciAddress,
         state: &mut ChannelState,
         buf: &[u8],
@@ -347,17 +532,42 @@ impl PciScheme {
         match *state {
             ChannelState::AwaitingResponseRead(_) => return Err(Error::new(EINVAL)),
             ChannelState::AwaitingData => {
-                let func = tree.get_mut(&addr).unwrap();
-
                 let request = bincode::deserialize_from(buf).map_err(|_| Error::new(EINVAL))?;
+
+                // Moving a BAR requires knowing what the rest of the bus is using.
+                let bar_window = match request {
+                    PcidClientRequest::ResizeBar { bar, .. } if bar < 6 => Some(BarWindow::new(
+                        pci_state,
+                        bridges,
+                        tree,
+                        addr,
+                        crate::rebar::is_prefetchable(pci_state, addr, bar),
+                    )),
+                    _ => None,
+                };
+
+                let func = tree.get_mut(&addr).unwrap();
                 let response = crate::driver_handler::DriverHandler::new(
                     func.inner.clone(),
                     &mut func.endpoint_header,
                     &mut func.capabilities,
+                    &func.ext_capabilities,
+                    bar_window,
+                    &mut func.interrupts,
+                    &mut func.pm,
+                    &mut func.saved_state,
+                    func.unique_id
+                        .as_ref()
+                        .expect("pcid: unique ids are assigned before the scheme starts"),
+                    &mut func.driver,
                     &*pci_state,
                 )
                 .respond(request);
 
+                if let PcidClientResponse::BarResized(bar, new_bar) = response {
+                    func.inner.bars[usize::from(bar)] = new_bar;
+                }
+
                 let mut output_bytes = vec![0_u8; 8];
                 bincode::serialize_into(&mut output_bytes, &response)
                     .map_err(|_| Error::new(EIO))?;
@@ -371,6 +581,13 @@ impl PciScheme {
     }
 }
 
+fn read_at(data: &[u8], buf: &mut [u8], offset: u64) -> usize {
+    let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+    let len = buf.len().min(data.len() - start);
+    buf[..len].copy_from_slice(&data[start..start + len]);
+    len
+}
+
 fn parse_pci_addr(addr: &str) -> Option<PciAddress> {
     let (segment, rest) = addr.split_once('-')?;
     let segment = u16::from_str_radix(segment, 16).ok()?;
@@ -0,0 +1,134 @@
+//! Configuration space snapshots.
+//!
+//! A function loses its configuration when it goes through a reset: a D3hot to D0 transition
+//! without No_Soft_Reset, a secondary bus reset of the bridge above it, or a platform suspend. The
+//! snapshot holds every register pcid or the driver may have programmed, so that the function
+//! can be brought back to the state it was in before.
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+
+use crate::cfg_access::Pcie;
+use crate::ext_cap::{self, ExtendedCapability};
+
+const COMMAND: u16 = 0x04;
+const STATUS_CAP_LIST: u32 = 1 << (16 + 4);
+const CAP_POINTER: u16 = 0x34;
+
+/// Cache line size and latency timer, the BARs and the interrupt line. The identification
+/// registers are read-only.
+const HEADER_REGISTERS: &[u16] = &[0x0C, 0x10, 0x14, 0x18, 0x1C, 0x20, 0x24, 0x3C];
+
+const CAP_ID_MSI: u8 = 0x05;
+const CAP_ID_PCIE: u8 = 0x10;
+const CAP_ID_MSIX: u8 = 0x11;
+
+const MSI_64BIT: u32 = 1 << (16 + 7);
+const MSI_PER_VECTOR_MASK: u32 = 1 << (16 + 8);
+
+/// Device, link, slot and root control. The upper half of each dword is a status register with
+/// write-1-to-clear bits, so only the lower half is kept.
+const PCIE_CONTROL_REGISTERS: &[u16] = &[0x08, 0x10, 0x18, 0x1C];
+/// Device, link and slot control 2, which only exist from version 2 of the capability on.
+const PCIE_CONTROL2_REGISTERS: &[u16] = &[0x28, 0x30, 0x38];
+const PCIE_VERSION_SHIFT: u32 = 16;
+
+/// AER uncorrectable mask and severity, correctable mask and capabilities and control.
+const AER_REGISTERS: &[u16] = &[0x08, 0x0C, 0x14, 0x18];
+
+const REBAR_CTRL: u16 = 0x08;
+const REBAR_ENTRY_SIZE: u16 = 0x08;
+const REBAR_NUM_BARS_SHIFT: u32 = 5;
+
+/// Saved configuration registers of a function, in the order they have to be written back.
+#[derive(Clone, Debug)]
+pub struct ConfigState {
+    addr: PciAddress,
+    registers: Vec<(u16, u32)>,
+}
+
+impl ConfigState {
+    pub fn save(pcie: &Pcie, addr: PciAddress, ext_capabilities: &[ExtendedCapability]) -> Self {
+        let read = |offset: u16| unsafe { pcie.read(addr, offset) };
+        let mut registers = Vec::new();
+
+        for &offset in HEADER_REGISTERS {
+            registers.push((offset, read(offset)));
+        }
+
+        let mut interrupts = Vec::new();
+        if read(COMMAND) & STATUS_CAP_LIST != 0 {
+            let mut offset = (read(CAP_POINTER) & 0xFC) as u16;
+            // At most 48 capabilities fit between the header and the end of the legacy space.
+            for _ in 0..48 {
+                if offset < 0x40 {
+                    break;
+                }
+                let header = read(offset);
+                match header as u8 {
+                    CAP_ID_PCIE => {
+                        let version = (header >> PCIE_VERSION_SHIFT) & 0xF;
+                        let control2 = if version >= 2 {
+                            PCIE_CONTROL2_REGISTERS
+                        } else {
+                            &[]
+                        };
+                        for &reg in PCIE_CONTROL_REGISTERS.iter().chain(control2) {
+                            registers.push((offset + reg, read(offset + reg) & 0xFFFF));
+                        }
+                    }
+                    CAP_ID_MSI => {
+                        let mut len = if header & MSI_64BIT != 0 { 0x0C } else { 0x08 };
+                        if header & MSI_PER_VECTOR_MASK != 0 {
+                            len += 0x04;
+                        }
+                        for reg in (0x04..=len).step_by(4) {
+                            interrupts.push((offset + reg, read(offset + reg)));
+                        }
+                        // Message control goes last, it may enable MSI.
+                        interrupts.push((offset, header));
+                    }
+                    CAP_ID_MSIX => interrupts.push((offset, header)),
+                    _ => {}
+                }
+                offset = ((header >> 8) & 0xFC) as u16;
+            }
+        }
+
+        for cap in ext_capabilities {
+            match cap.id {
+                ext_cap::EXT_CAP_ID_AER => {
+                    for &reg in AER_REGISTERS {
+                        registers.push((cap.offset + reg, read(cap.offset + reg)));
+                    }
+                }
+                crate::rebar::EXT_CAP_ID_REBAR => {
+                    let count = (read(cap.offset + REBAR_CTRL) >> REBAR_NUM_BARS_SHIFT) & 0x7;
+                    for i in 0..count as u16 {
+                        let reg = REBAR_CTRL + i * REBAR_ENTRY_SIZE;
+                        registers.push((cap.offset + reg, read(cap.offset + reg)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Only enable decoding once the BARs are valid again, and interrupts once decoding is
+        // back. The upper half of the command register is the status register.
+        registers.push((COMMAND, read(COMMAND) & 0xFFFF));
+        registers.extend(interrupts);
+
+        Self { addr, registers }
+    }
+
+    /// Write the saved registers back to the function.
+    pub fn restore(&self, pcie: &Pcie) {
+        for &(offset, value) in &self.registers {
+            unsafe { pcie.write(self.addr, offset, value) };
+        }
+        log::debug!(
+            "pcid: {} restored {} config registers",
+            self.addr,
+            self.registers.len()
+        );
+    }
+}
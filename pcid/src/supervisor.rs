@@ -0,0 +1,160 @@
+//! Launching and supervising drivers.
+//!
+//! When pcid is given a driver table with `--drivers`, it starts the matching driver for every
+//! function itself instead of leaving that to pcid-spawner. The driver is the only holder of the
+//! function's channel, so the channel closing means the driver exited. pcid then restarts it,
+//! waiting longer after every consecutive failure, and gives up once a driver keeps failing.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pci_types::PciAddress;
+use pcid_interface::config::{Config, DriverConfig};
+use pcid_interface::{FullDeviceId, PciFunctionHandle};
+
+/// Delay before the first restart, doubled after every consecutive failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A driver that ran at least this long is considered to have worked, resetting the backoff.
+const STABLE_RUNTIME: Duration = Duration::from_secs(60);
+const MAX_FAILURES: u32 = 8;
+
+struct Supervised {
+    driver: DriverConfig,
+    /// When the driver was last started, `None` while it isn't running under pcid.
+    started: Option<Instant>,
+    restart_at: Option<Instant>,
+    failures: u32,
+}
+
+/// Start the supervisor thread, returning where the scheme reports closed channels.
+pub fn spawn(config: Config, functions: Vec<(PciAddress, FullDeviceId)>) -> Sender<PciAddress> {
+    let (tx, rx) = mpsc::channel();
+
+    let mut supervised = BTreeMap::new();
+    for (addr, id) in functions {
+        match config.find_driver(&id) {
+            Some(driver) => {
+                supervised.insert(
+                    addr,
+                    Supervised {
+                        driver: driver.clone(),
+                        started: None,
+                        restart_at: Some(Instant::now()),
+                        failures: 0,
+                    },
+                );
+            }
+            None => log::debug!("pcid: no driver for {addr}"),
+        }
+    }
+
+    thread::spawn(move || run(supervised, rx));
+    tx
+}
+
+fn run(mut supervised: BTreeMap<PciAddress, Supervised>, rx: Receiver<PciAddress>) {
+    loop {
+        let now = Instant::now();
+        for (addr, entry) in supervised.iter_mut() {
+            if entry.restart_at.is_some_and(|at| at <= now) {
+                entry.restart_at = None;
+                entry.started = start(*addr, &entry.driver).then_some(now);
+            }
+        }
+
+        let next = supervised
+            .values()
+            .filter_map(|entry| entry.restart_at)
+            .min();
+        let closed = match next {
+            Some(at) => match rx.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                Ok(addr) => addr,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            },
+            None => match rx.recv() {
+                Ok(addr) => addr,
+                Err(_) => return,
+            },
+        };
+
+        // Channels opened by anyone else, like pcid-spawner or a diagnostic tool, aren't ours.
+        let Some(entry) = supervised.get_mut(&closed) else {
+            continue;
+        };
+        let Some(started) = entry.started.take() else {
+            continue;
+        };
+
+        if started.elapsed() >= STABLE_RUNTIME {
+            entry.failures = 0;
+        }
+        entry.failures += 1;
+        if entry.failures > MAX_FAILURES {
+            log::error!(
+                "pcid: driver {:?} for {closed} failed {MAX_FAILURES} times, giving up",
+                entry.driver.command
+            );
+            continue;
+        }
+
+        let backoff = INITIAL_BACKOFF
+            .saturating_mul(1 << (entry.failures - 1))
+            .min(MAX_BACKOFF);
+        log::warn!(
+            "pcid: driver {:?} for {closed} exited, restarting in {}s",
+            entry.driver.command,
+            backoff.as_secs()
+        );
+        entry.restart_at = Some(Instant::now() + backoff);
+    }
+}
+
+/// Start `driver` on the function at `addr`, returning whether pcid owns the function now.
+///
+/// Once the channel is open, a failure to start the driver is handled like the driver exiting,
+/// as closing the channel here notifies the supervisor all the same.
+fn start(addr: PciAddress, driver: &DriverConfig) -> bool {
+    let Some(mut command) = driver.build_command() else {
+        log::error!("pcid: driver entry {:?} has no command", driver.name);
+        return false;
+    };
+
+    // FIXME remove replacement of : once the old scheme format is no longer supported.
+    let device_path = PathBuf::from(format!(
+        "/scheme/pci/{}",
+        addr.to_string().replace(':', "--")
+    ));
+    let mut handle = match PciFunctionHandle::connect_by_path(&device_path) {
+        Ok(handle) => handle,
+        Err(err) => {
+            // Most likely claimed by pcid-spawner or a manually started driver.
+            log::info!("pcid: not starting driver for {addr}: {err}");
+            return false;
+        }
+    };
+
+    log::info!("pcid: spawn {command:?} for {addr}");
+
+    if let Some(name) = driver.driver_name() {
+        handle.set_driver_name(name);
+    }
+    handle.enable_device();
+
+    let channel_fd = handle.into_inner_fd();
+    command.env("PCID_CLIENT_CHANNEL", channel_fd.to_string());
+
+    match command.status() {
+        Ok(status) if !status.success() => {
+            log::error!("pcid: driver {command:?} failed with {status}");
+        }
+        Ok(_) => {}
+        Err(err) => log::error!("pcid: failed to execute {command:?}: {err}"),
+    }
+    let _ = syscall::close(channel_fd as usize);
+    true
+}
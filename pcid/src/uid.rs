@@ -0,0 +1,94 @@
+//! Identifiers of functions that persist across reboots, so storage and network stacks can give
+//! the devices behind them stable names.
+//!
+//! A function with a Device Serial Number is identified by it, which keeps the identifier when the
+//! card moves to another slot. Other functions are identified by the device and function numbers
+//! on the path from the root bus, which don't depend on how buses got numbered, plus their IDs so
+//! a different card in the same slot gets a different identifier.
+
+use std::collections::BTreeMap;
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+use pcid_interface::{UniqueId, UniqueIdSource};
+
+use crate::bridge::{self, Bridge};
+use crate::cfg_access::Pcie;
+use crate::{ext_cap, Func};
+
+const SUBSYSTEM: u16 = 0x2C;
+
+/// The Device Serial Number of a function, if it has one that can tell devices apart.
+pub fn serial_number(pcie: &Pcie, func: &Func) -> Option<u64> {
+    let cap = ext_cap::find(&func.ext_capabilities, ext_cap::EXT_CAP_ID_DSN)?;
+    let serial = ext_cap::serial_number(pcie, func.inner.addr, &cap);
+    // Devices without a programmed serial number commonly report all zeros or all ones.
+    (serial != 0 && serial != u64::MAX).then_some(serial)
+}
+
+/// Give every function its identifier, once all bridges are known.
+pub fn assign(
+    pcie: &Pcie,
+    tree: &mut BTreeMap<PciAddress, Func>,
+    bridges: &BTreeMap<PciAddress, Bridge>,
+) {
+    let serials = tree
+        .values()
+        .map(|func| (func.inner.addr, serial_number(pcie, func)))
+        .collect::<BTreeMap<_, _>>();
+
+    for func in tree.values_mut() {
+        let addr = func.inner.addr;
+        let serial_number = serials[&addr];
+        // Serial numbers are supposed to be unique, but cloned firmware sometimes isn't.
+        let duplicate = serial_number.is_some()
+            && serials.iter().any(|(&other, &serial)| {
+                other != addr && other.function() == addr.function() && serial == serial_number
+            });
+        if duplicate {
+            log::warn!("pcid: {addr} shares its serial number with another device, ignoring it");
+        }
+
+        let unique_id = match serial_number {
+            Some(serial) if !duplicate => UniqueId {
+                id: format!("dsn-{serial:016x}-{}", addr.function()),
+                source: UniqueIdSource::SerialNumber,
+                serial_number,
+            },
+            _ => UniqueId {
+                id: topology_id(pcie, func, bridges),
+                source: UniqueIdSource::Topology,
+                serial_number,
+            },
+        };
+        log::debug!("PCI {addr} unique id {}", unique_id.id);
+        func.unique_id = Some(unique_id);
+    }
+}
+
+fn topology_id(pcie: &Pcie, func: &Func, bridges: &BTreeMap<PciAddress, Bridge>) -> String {
+    let addr = func.inner.addr;
+    let mut hops = vec![addr];
+    // Bounded by the number of buses, in case of a bridge loop.
+    for _ in 0..256 {
+        match bridge::upstream(bridges, *hops.last().unwrap()) {
+            Some(bridge) => hops.push(bridge.addr),
+            None => break,
+        }
+    }
+    let root = hops.last().unwrap();
+
+    let mut id = format!("pci-{:04x}:{:02x}", root.segment(), root.bus());
+    for hop in hops.iter().rev() {
+        id.push_str(&format!("-{:02x}.{}", hop.device(), hop.function()));
+    }
+    let subsystem = unsafe { pcie.read(addr, SUBSYSTEM) };
+    let device_id = &func.inner.full_device_id;
+    id.push_str(&format!(
+        "-{:04x}{:04x}{:04x}{:04x}",
+        device_id.vendor_id,
+        device_id.device_id,
+        subsystem & 0xFFFF,
+        subsystem >> 16
+    ));
+    id
+}
@@ -0,0 +1,159 @@
+//! Vital Product Data: part number, serial number and similar information stored on the device.
+//!
+//! VPD is read through a window in the VPD capability, one dword at a time: software writes the
+//! address with the flag bit clear and the device sets the flag once the data register holds the
+//! dword at that address. The data itself is a list of resources in the format from the PCI Local
+//! Bus Specification, appendix I.
+
+use std::thread;
+use std::time::Duration;
+
+use pci_types::{ConfigRegionAccess, PciAddress};
+
+use crate::cfg_access::Pcie;
+
+const STATUS: u16 = 0x04;
+const STATUS_CAP_LIST: u32 = 1 << (16 + 4);
+const CAP_POINTER: u16 = 0x34;
+const CAP_ID_VPD: u8 = 0x03;
+
+const VPD_FLAG: u32 = 1 << 31;
+const VPD_DATA: u16 = 0x04;
+/// The address register is 15 bits wide.
+const VPD_MAX_LEN: u16 = 0x8000;
+/// Polls of the flag before giving up on a dword. Devices typically need a few microseconds.
+const VPD_POLLS: u32 = 100;
+
+const TAG_ID_STRING: u8 = 0x82;
+const TAG_VPD_R: u8 = 0x90;
+const TAG_VPD_W: u8 = 0x91;
+const TAG_END: u8 = 0x78;
+
+#[derive(Clone, Debug, Default)]
+pub struct Vpd {
+    /// Product name.
+    pub identifier: Option<String>,
+    /// Keyword and value of every read-only and read-write field, except checksums and padding.
+    pub fields: Vec<(String, String)>,
+}
+
+impl Vpd {
+    /// Read and parse the VPD of the function at `addr`, if it has any.
+    pub fn read(pcie: &Pcie, addr: PciAddress) -> Option<Self> {
+        let cap = find_vpd_capability(pcie, addr)?;
+        let mut reader = Reader {
+            pcie,
+            addr,
+            cap,
+            dword: None,
+        };
+
+        let mut vpd = Vpd::default();
+        let mut offset = 0;
+        while offset < VPD_MAX_LEN {
+            let tag = reader.byte(offset)?;
+            if tag == TAG_END {
+                break;
+            }
+            if tag & 0x80 == 0 {
+                // Small resources other than the end tag carry nothing we report.
+                offset += 1 + u16::from(tag & 0x07);
+                continue;
+            }
+
+            let len = u16::from_le_bytes([reader.byte(offset + 1)?, reader.byte(offset + 2)?]);
+            let data = (offset + 3..offset.checked_add(3 + len)?)
+                .map(|offset| reader.byte(offset))
+                .collect::<Option<Vec<u8>>>()?;
+            match tag {
+                TAG_ID_STRING => vpd.identifier = Some(string(&data)),
+                TAG_VPD_R | TAG_VPD_W => vpd.fields.extend(parse_keywords(&data)),
+                _ => {}
+            }
+            offset += 3 + len;
+        }
+
+        Some(vpd)
+    }
+}
+
+fn find_vpd_capability(pcie: &Pcie, addr: PciAddress) -> Option<u16> {
+    let read = |offset: u16| unsafe { pcie.read(addr, offset) };
+    if read(STATUS) & STATUS_CAP_LIST == 0 {
+        return None;
+    }
+
+    let mut offset = (read(CAP_POINTER) & 0xFC) as u16;
+    // At most 48 capabilities fit between the header and the end of the legacy space.
+    for _ in 0..48 {
+        if offset < 0x40 {
+            return None;
+        }
+        let header = read(offset);
+        if header as u8 == CAP_ID_VPD {
+            return Some(offset);
+        }
+        offset = ((header >> 8) & 0xFC) as u16;
+    }
+    None
+}
+
+/// Reads VPD bytes, caching the dword last read as every access costs a round trip to the device.
+struct Reader<'a> {
+    pcie: &'a Pcie,
+    addr: PciAddress,
+    cap: u16,
+    dword: Option<(u16, [u8; 4])>,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self, offset: u16) -> Option<u8> {
+        let aligned = offset & !3;
+        if self.dword.map(|(cached, _)| cached) != Some(aligned) {
+            self.dword = Some((aligned, self.read_dword(aligned)?));
+        }
+        self.dword.map(|(_, bytes)| bytes[usize::from(offset & 3)])
+    }
+
+    fn read_dword(&self, offset: u16) -> Option<[u8; 4]> {
+        if offset >= VPD_MAX_LEN {
+            return None;
+        }
+        unsafe {
+            // The low half of the dword holds the read-only capability ID and next pointer.
+            self.pcie
+                .write(self.addr, self.cap, u32::from(offset) << 16);
+            for _ in 0..VPD_POLLS {
+                if self.pcie.read(self.addr, self.cap) & VPD_FLAG != 0 {
+                    return Some(self.pcie.read(self.addr, self.cap + VPD_DATA).to_le_bytes());
+                }
+                thread::sleep(Duration::from_micros(50));
+            }
+        }
+        log::warn!("pcid: VPD read of {} timed out at {offset:#x}", self.addr);
+        None
+    }
+}
+
+fn parse_keywords(mut data: &[u8]) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    while let [k0, k1, len, rest @ ..] = data {
+        let len = usize::from(*len);
+        let Some(value) = rest.get(..len) else {
+            break;
+        };
+        let keyword = string(&[*k0, *k1]);
+        // RV is the checksum of the read-only area and RW the unused part of the writable one.
+        if keyword != "RV" && keyword != "RW" {
+            fields.push((keyword, string(value)));
+        }
+        data = &rest[len..];
+    }
+    fields
+}
+
+fn string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(['\0', ' '])
+        .to_owned()
+}
@@ -1,6 +1,40 @@
 //! Board-specific implementations
 
 use crate::BoardInfo;
+#[cfg(any(
+    feature = "beaglebone-black",
+    feature = "esp32-c3-devkit",
+    feature = "esp32-c6-devkit",
+    feature = "raspberry-pi-zero",
+    feature = "rpi-4",
+    feature = "rpi-5",
+    feature = "rpi-pico",
+    feature = "rpi-pico-w",
+    feature = "rpi-pico2",
+    feature = "sifive-hifive1"
+))]
+use redox_hal::clocks::{ClockId, ClockNode, ClockTree, PllConfig};
+#[cfg(any(
+    feature = "beaglebone-black",
+    feature = "esp32-c3-devkit",
+    feature = "esp32-c6-devkit",
+    feature = "rpi-4",
+    feature = "rpi-5",
+    feature = "rpi-pico",
+    feature = "rpi-pico-w",
+    feature = "rpi-pico2"
+))]
+use redox_hal::pinmux::{AltFn, Peripheral, PinAssignment, PinMap};
+#[cfg(any(
+    feature = "esp32-c3-devkit",
+    feature = "esp32-c6-devkit",
+    feature = "rpi-4",
+    feature = "rpi-5",
+    feature = "rpi-pico",
+    feature = "rpi-pico-w",
+    feature = "rpi-pico2"
+))]
+use redox_hal::reset::BrownoutConfig;
 
 /// BeagleBone Black board information
 #[cfg(feature = "beaglebone-black")]
@@ -10,6 +44,42 @@ pub const BEAGLEBONE_BLACK: BoardInfo = BoardInfo {
     ram_size: 512 * 1024 * 1024,        // 512 MB
     flash_size: 4 * 1024 * 1024 * 1024, // 4 GB eMMC
     cpu_freq: 1_000_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "clk_m_osc", 24_000_000),
+        ClockNode::pll(
+            ClockId::CPU,
+            "dpll_mpu",
+            ClockId::OSC,
+            PllConfig::new(24, 1000, 1),
+        ),
+        ClockNode::pll(
+            am335x::clk::CORE,
+            "dpll_core_m4",
+            ClockId::OSC,
+            PllConfig::new(24, 2000, 10),
+        ),
+        ClockNode::pll(
+            am335x::clk::PER,
+            "dpll_per_m2",
+            ClockId::OSC,
+            PllConfig::new(24, 960, 5),
+        ),
+        ClockNode::divider(am335x::clk::PER_48M, "per_48m", am335x::clk::PER, 4),
+        ClockNode::divider(am335x::clk::L4LS, "l4ls_gclk", am335x::clk::CORE, 2),
+        ClockNode::gate(am335x::clk::UART0, "uart0_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::UART1, "uart1_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::UART2, "uart2_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::I2C0, "i2c0_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::I2C1, "i2c1_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::I2C2, "i2c2_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::SPI0, "spi0_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::SPI1, "spi1_fclk", am335x::clk::PER_48M),
+        ClockNode::gate(am335x::clk::GPIO0, "gpio0_gclk", am335x::clk::L4LS),
+        ClockNode::gate(am335x::clk::GPIO1, "gpio1_gclk", am335x::clk::L4LS),
+        ClockNode::gate(am335x::clk::GPIO2, "gpio2_gclk", am335x::clk::L4LS),
+        ClockNode::gate(am335x::clk::GPIO3, "gpio3_gclk", am335x::clk::L4LS),
+        ClockNode::gate(am335x::clk::TIMER2, "timer2_gclk", ClockId::OSC),
+    ]),
     has_ethernet: true,
     has_wifi: false,
     gpio_count: 65,
@@ -18,6 +88,113 @@ pub const BEAGLEBONE_BLACK: BoardInfo = BoardInfo {
     i2c_count: 3,
 };
 
+/// BeagleBone Black pads, by control module register offset
+///
+/// UART0 is the console header, UART2 and I2C2 are on P9.19-P9.22, and the
+/// four user LEDs are GPIO1_21-GPIO1_24.
+#[cfg(feature = "beaglebone-black")]
+pub const BEAGLEBONE_BLACK_PINS: PinMap = PinMap::new(&[
+    PinAssignment::new(0x970, Peripheral::Uart(0), "rxd", AltFn(0)),
+    PinAssignment::new(0x974, Peripheral::Uart(0), "txd", AltFn(0)),
+    PinAssignment::new(0x950, Peripheral::Uart(2), "rxd", AltFn(1)), // P9.22
+    PinAssignment::new(0x954, Peripheral::Uart(2), "txd", AltFn(1)), // P9.21
+    PinAssignment::new(0x988, Peripheral::I2c(0), "sda", AltFn(0)),
+    PinAssignment::new(0x98C, Peripheral::I2c(0), "scl", AltFn(0)),
+    PinAssignment::new(0x978, Peripheral::I2c(2), "sda", AltFn(3)), // P9.20
+    PinAssignment::new(0x97C, Peripheral::I2c(2), "scl", AltFn(3)), // P9.19
+    PinAssignment::new(0x854, Peripheral::Gpio(53), "usr0", AltFn(7)),
+    PinAssignment::new(0x858, Peripheral::Gpio(54), "usr1", AltFn(7)),
+    PinAssignment::new(0x85C, Peripheral::Gpio(55), "usr2", AltFn(7)),
+    PinAssignment::new(0x860, Peripheral::Gpio(56), "usr3", AltFn(7)),
+]);
+
+/// ESP32-C3-DevKitM-1 board information
+#[cfg(feature = "esp32-c3-devkit")]
+pub const ESP32_C3_DEVKIT: BoardInfo = BoardInfo {
+    name: "ESP32-C3-DevKitM-1",
+    cpu: "ESP32-C3 (RISC-V RV32IMC @ 160MHz)",
+    ram_size: 400 * 1024,
+    flash_size: 4 * 1024 * 1024,
+    cpu_freq: 160_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 40_000_000),
+        // 480 MHz PLL divided by 3
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 12, 3)),
+        ClockNode::divider(esp32c3::clk::APB, "apb", ClockId::CPU, 2),
+    ]),
+    has_ethernet: false,
+    has_wifi: true,
+    gpio_count: 22,
+    uart_count: 2,
+    spi_count: 1, // SPI2; SPI0/1 belong to the flash
+    i2c_count: 1,
+};
+
+/// ESP32-C3-DevKitM-1 pads, by GPIO number
+///
+/// UART0 is the console through the USB bridge and SPI2 uses its IO_MUX
+/// pads. I2C has no IO_MUX function and goes through the GPIO matrix. The
+/// RGB LED is a WS2812 on GPIO8.
+#[cfg(feature = "esp32-c3-devkit")]
+pub const ESP32_C3_DEVKIT_PINS: PinMap = PinMap::new(&[
+    PinAssignment::new(21, Peripheral::Uart(0), "txd", AltFn(0)),
+    PinAssignment::new(20, Peripheral::Uart(0), "rxd", AltFn(0)),
+    PinAssignment::new(2, Peripheral::Spi(2), "miso", AltFn(2)),
+    PinAssignment::new(6, Peripheral::Spi(2), "clk", AltFn(2)),
+    PinAssignment::new(7, Peripheral::Spi(2), "mosi", AltFn(2)),
+    PinAssignment::new(10, Peripheral::Spi(2), "cs0", AltFn(2)),
+    PinAssignment::new(8, Peripheral::Gpio(8), "rgb_led", AltFn(1)),
+]);
+
+/// ESP32-C3-DevKitM-1 brownout detector
+#[cfg(feature = "esp32-c3-devkit")]
+pub const ESP32_C3_DEVKIT_BROWNOUT: BrownoutConfig = BrownoutConfig::reset("VDD3P3", 2_510);
+
+/// ESP32-C6-DevKitC-1 board information
+#[cfg(feature = "esp32-c6-devkit")]
+pub const ESP32_C6_DEVKIT: BoardInfo = BoardInfo {
+    name: "ESP32-C6-DevKitC-1",
+    cpu: "ESP32-C6 (RISC-V RV32IMAC @ 160MHz)",
+    ram_size: 512 * 1024, // HP SRAM, plus 16 KB LP SRAM
+    flash_size: 8 * 1024 * 1024,
+    cpu_freq: 160_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 40_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 12, 3)),
+        ClockNode::pll(
+            esp32c6::clk::PLL_F80M,
+            "pll_f80m",
+            ClockId::OSC,
+            PllConfig::new(1, 12, 6),
+        ),
+    ]),
+    has_ethernet: false,
+    has_wifi: true, // WiFi 6, plus BLE and 802.15.4
+    gpio_count: 31,
+    uart_count: 2,
+    spi_count: 1,
+    i2c_count: 1,
+};
+
+/// ESP32-C6-DevKitC-1 pads, by GPIO number
+///
+/// UART0 is the console through the USB bridge. SPI2 uses its IO_MUX pads
+/// except for CS0, which shares GPIO16 with the console. The RGB LED is a
+/// WS2812 on GPIO8.
+#[cfg(feature = "esp32-c6-devkit")]
+pub const ESP32_C6_DEVKIT_PINS: PinMap = PinMap::new(&[
+    PinAssignment::new(16, Peripheral::Uart(0), "txd", AltFn(0)),
+    PinAssignment::new(17, Peripheral::Uart(0), "rxd", AltFn(0)),
+    PinAssignment::new(2, Peripheral::Spi(2), "miso", AltFn(2)),
+    PinAssignment::new(6, Peripheral::Spi(2), "clk", AltFn(2)),
+    PinAssignment::new(7, Peripheral::Spi(2), "mosi", AltFn(2)),
+    PinAssignment::new(8, Peripheral::Gpio(8), "rgb_led", AltFn(1)),
+]);
+
+/// ESP32-C6-DevKitC-1 brownout detector
+#[cfg(feature = "esp32-c6-devkit")]
+pub const ESP32_C6_DEVKIT_BROWNOUT: BrownoutConfig = BrownoutConfig::reset("VDD3P3", 2_510);
+
 /// Raspberry Pi Zero board information
 #[cfg(feature = "raspberry-pi-zero")]
 pub const RASPBERRY_PI_ZERO: BoardInfo = BoardInfo {
@@ -26,6 +203,15 @@ pub const RASPBERRY_PI_ZERO: BoardInfo = BoardInfo {
     ram_size: 512 * 1024 * 1024, // 512 MB
     flash_size: 0,               // SD card
     cpu_freq: 1_000_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "osc", 19_200_000),
+        ClockNode::pll(
+            ClockId::CPU,
+            "arm",
+            ClockId::OSC,
+            PllConfig::new(12, 625, 1),
+        ),
+    ]),
     has_ethernet: false,
     has_wifi: false, // Zero W has WiFi
     gpio_count: 40,
@@ -34,6 +220,193 @@ pub const RASPBERRY_PI_ZERO: BoardInfo = BoardInfo {
     i2c_count: 2,
 };
 
+/// Raspberry Pi 4 Model B board information
+#[cfg(feature = "rpi-4")]
+pub const RASPBERRY_PI_4: BoardInfo = BoardInfo {
+    name: "Raspberry Pi 4 Model B",
+    cpu: "BCM2711 (ARM Cortex-A72 @ 1.5GHz)",
+    ram_size: 4 * 1024 * 1024 * 1024, // 4 GB, 1/2/8 GB variants exist
+    flash_size: 0,                    // SD card
+    cpu_freq: 1_500_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "osc", 54_000_000),
+        ClockNode::pll(ClockId::CPU, "arm", ClockId::OSC, PllConfig::new(9, 250, 1)),
+        // The firmware owns these; the rates are the config.txt defaults
+        ClockNode::pll(
+            bcm2711::clk::CORE,
+            "vpu",
+            ClockId::OSC,
+            PllConfig::new(27, 1000, 4),
+        ),
+        ClockNode::fixed(bcm2711::clk::UART, "uart", 48_000_000),
+        ClockNode::fixed(bcm2711::clk::EMMC2, "emmc2", 100_000_000),
+    ]),
+    has_ethernet: true,
+    has_wifi: true,
+    gpio_count: 58,
+    uart_count: 6,
+    spi_count: 7,
+    i2c_count: 6,
+};
+
+/// Raspberry Pi 4 pads, by GPIO number
+///
+/// UART0 is the console on header pins 8 and 10, I2C1 is on pins 3 and 5
+/// and SPI0 on pins 19-24.
+#[cfg(feature = "rpi-4")]
+pub const RASPBERRY_PI_4_PINS: PinMap = PinMap::new(&[
+    PinAssignment::new(14, Peripheral::Uart(0), "txd", AltFn(0)),
+    PinAssignment::new(15, Peripheral::Uart(0), "rxd", AltFn(0)),
+    PinAssignment::new(2, Peripheral::I2c(1), "sda", AltFn(0)),
+    PinAssignment::new(3, Peripheral::I2c(1), "scl", AltFn(0)),
+    PinAssignment::new(7, Peripheral::Spi(0), "ce1", AltFn(0)),
+    PinAssignment::new(8, Peripheral::Spi(0), "ce0", AltFn(0)),
+    PinAssignment::new(9, Peripheral::Spi(0), "miso", AltFn(0)),
+    PinAssignment::new(10, Peripheral::Spi(0), "mosi", AltFn(0)),
+    PinAssignment::new(11, Peripheral::Spi(0), "sclk", AltFn(0)),
+]);
+
+/// Raspberry Pi 4 under-voltage detection
+///
+/// The firmware flags the 5 V input dropping below 4.63 V and throttles,
+/// but doesn't reset.
+#[cfg(feature = "rpi-4")]
+pub const RASPBERRY_PI_4_BROWNOUT: BrownoutConfig = BrownoutConfig::interrupt("5V", 4_630);
+
+/// Raspberry Pi 5 board information
+#[cfg(feature = "rpi-5")]
+pub const RASPBERRY_PI_5: BoardInfo = BoardInfo {
+    name: "Raspberry Pi 5",
+    cpu: "BCM2712 (ARM Cortex-A76 @ 2.4GHz)",
+    ram_size: 4 * 1024 * 1024 * 1024, // 4 GB, 2/8/16 GB variants exist
+    flash_size: 0,                    // SD card
+    cpu_freq: 2_400_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "osc", 54_000_000),
+        ClockNode::pll(ClockId::CPU, "arm", ClockId::OSC, PllConfig::new(9, 400, 1)),
+        ClockNode::fixed(bcm2712::clk::DEBUG_UART, "uart_10", 44_236_800),
+        ClockNode::fixed(bcm2712::clk::RP1_UART, "rp1_uart", 50_000_000),
+    ]),
+    // Ethernet is the RP1 Cadence GEM, which has no driver here yet
+    has_ethernet: true,
+    has_wifi: true,
+    gpio_count: 54,
+    uart_count: 6,
+    spi_count: 6,
+    i2c_count: 7,
+};
+
+/// Raspberry Pi 5 header pads on the RP1, by GPIO number
+///
+/// UART0 is on header pins 8 and 10, I2C1 on pins 3 and 5 and SPI0 on
+/// pins 21-24. The console defaults to the separate debug UART connector.
+#[cfg(feature = "rpi-5")]
+pub const RASPBERRY_PI_5_PINS: PinMap = PinMap::new(&[
+    PinAssignment::new(14, Peripheral::Uart(0), "txd", AltFn(4)),
+    PinAssignment::new(15, Peripheral::Uart(0), "rxd", AltFn(4)),
+    PinAssignment::new(2, Peripheral::I2c(1), "sda", AltFn(3)),
+    PinAssignment::new(3, Peripheral::I2c(1), "scl", AltFn(3)),
+    PinAssignment::new(8, Peripheral::Spi(0), "ce0", AltFn(0)),
+    PinAssignment::new(9, Peripheral::Spi(0), "miso", AltFn(0)),
+    PinAssignment::new(10, Peripheral::Spi(0), "mosi", AltFn(0)),
+    PinAssignment::new(11, Peripheral::Spi(0), "sclk", AltFn(0)),
+]);
+
+/// Raspberry Pi 5 under-voltage detection, by the PMIC on the 5 V input
+#[cfg(feature = "rpi-5")]
+pub const RASPBERRY_PI_5_BROWNOUT: BrownoutConfig = BrownoutConfig::interrupt("5V", 4_630);
+
+/// Raspberry Pi Pico board information
+#[cfg(any(feature = "rpi-pico", feature = "rpi-pico-w"))]
+pub const RASPBERRY_PI_PICO: BoardInfo = BoardInfo {
+    name: "Raspberry Pi Pico",
+    cpu: "RP2040 (Dual ARM Cortex-M0+ @ 133MHz)",
+    ram_size: 264 * 1024,
+    flash_size: 2 * 1024 * 1024,
+    cpu_freq: 125_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xosc", 12_000_000),
+        ClockNode::pll(
+            ClockId::CPU,
+            "pll_sys",
+            ClockId::OSC,
+            PllConfig::new(1, 125, 12),
+        ),
+        ClockNode::pll(
+            rp2040::clk::USB,
+            "pll_usb",
+            ClockId::OSC,
+            PllConfig::new(1, 100, 25),
+        ),
+        // clk_peri feeds the UARTs and SPIs
+        ClockNode::divider(rp2040::clk::PERI, "clk_peri", ClockId::CPU, 1),
+    ]),
+    has_ethernet: false,
+    has_wifi: false, // The Pico W adds a CYW43439 on PIO-driven SPI
+    gpio_count: 30,
+    uart_count: 2,
+    spi_count: 2,
+    i2c_count: 2,
+};
+
+/// Raspberry Pi Pico 2 board information
+#[cfg(feature = "rpi-pico2")]
+pub const RASPBERRY_PI_PICO_2: BoardInfo = BoardInfo {
+    name: "Raspberry Pi Pico 2",
+    cpu: "RP2350 (Dual ARM Cortex-M33 @ 150MHz)",
+    ram_size: 520 * 1024,
+    flash_size: 4 * 1024 * 1024,
+    cpu_freq: 150_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xosc", 12_000_000),
+        ClockNode::pll(
+            ClockId::CPU,
+            "pll_sys",
+            ClockId::OSC,
+            PllConfig::new(1, 125, 10),
+        ),
+        ClockNode::pll(
+            rp2350::clk::USB,
+            "pll_usb",
+            ClockId::OSC,
+            PllConfig::new(1, 100, 25),
+        ),
+        ClockNode::divider(rp2350::clk::PERI, "clk_peri", ClockId::CPU, 1),
+    ]),
+    has_ethernet: false,
+    has_wifi: false,
+    gpio_count: 30, // RP2350A; the RP2350B has 48
+    uart_count: 2,
+    spi_count: 2,
+    i2c_count: 2,
+};
+
+/// Raspberry Pi Pico and Pico 2 pads, by GPIO number
+///
+/// Both boards share the pinout and function selects. UART0 is on GP0 and
+/// GP1, I2C0 on GP4 and GP5 and SPI0 on GP16-19. The LED is on GP25, except
+/// on the Pico W where the wireless chip drives it.
+#[cfg(any(feature = "rpi-pico", feature = "rpi-pico-w", feature = "rpi-pico2"))]
+pub const RASPBERRY_PI_PICO_PINS: PinMap = PinMap::new(&[
+    PinAssignment::new(0, Peripheral::Uart(0), "tx", AltFn(2)),
+    PinAssignment::new(1, Peripheral::Uart(0), "rx", AltFn(2)),
+    PinAssignment::new(4, Peripheral::I2c(0), "sda", AltFn(3)),
+    PinAssignment::new(5, Peripheral::I2c(0), "scl", AltFn(3)),
+    PinAssignment::new(16, Peripheral::Spi(0), "rx", AltFn(1)),
+    PinAssignment::new(17, Peripheral::Spi(0), "csn", AltFn(1)),
+    PinAssignment::new(18, Peripheral::Spi(0), "sck", AltFn(1)),
+    PinAssignment::new(19, Peripheral::Spi(0), "tx", AltFn(1)),
+    PinAssignment::new(25, Peripheral::Gpio(25), "led", AltFn(5)),
+]);
+
+/// Raspberry Pi Pico brownout detector, fixed on the core supply
+#[cfg(any(feature = "rpi-pico", feature = "rpi-pico-w"))]
+pub const RASPBERRY_PI_PICO_BROWNOUT: BrownoutConfig = BrownoutConfig::reset("DVDD", 860);
+
+/// Raspberry Pi Pico 2 brownout detector, at its reset default
+#[cfg(feature = "rpi-pico2")]
+pub const RASPBERRY_PI_PICO_2_BROWNOUT: BrownoutConfig = BrownoutConfig::reset("DVDD", 946);
+
 /// SiFive HiFive1 board information
 #[cfg(feature = "sifive-hifive1")]
 pub const SIFIVE_HIFIVE1: BoardInfo = BoardInfo {
@@ -42,6 +415,16 @@ pub const SIFIVE_HIFIVE1: BoardInfo = BoardInfo {
     ram_size: 16 * 1024,         // 16 KB SRAM
     flash_size: 4 * 1024 * 1024, // 4 MB QSPI Flash
     cpu_freq: 320_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "hfxosc", 16_000_000),
+        // PLL R = 2, F = 80, Q = 2
+        ClockNode::pll(
+            ClockId::CPU,
+            "hfclk",
+            ClockId::OSC,
+            PllConfig::new(2, 80, 2),
+        ),
+    ]),
     has_ethernet: false,
     has_wifi: false,
     gpio_count: 19,
@@ -103,11 +486,46 @@ pub mod am335x {
     /// MDIO base
     pub const MDIO_BASE: usize = 0x4A10_1000;
 
+    /// Power, reset and clock management base
+    pub const PRCM_BASE: usize = 0x44E0_0000;
+
+    /// Control module base (pad configuration)
+    pub const CONTROL_MODULE_BASE: usize = 0x44E1_0000;
+
     /// Interrupt controller base
     pub const INTC_BASE: usize = 0x4820_0000;
 
     /// DDR base
     pub const DDR_BASE: usize = 0x8000_0000;
+
+    /// Clocks, gates numbered by their CLKCTRL register offset from
+    /// [`PRCM_BASE`]
+    pub mod clk {
+        use redox_hal::clocks::ClockId;
+
+        /// Core PLL, M4 output
+        pub const CORE: ClockId = ClockId(2);
+        /// Peripheral PLL, M2 output
+        pub const PER: ClockId = ClockId(3);
+        /// 48 MHz functional clock of UART, I2C and SPI
+        pub const PER_48M: ClockId = ClockId(4);
+        /// L4 slow interconnect clock
+        pub const L4LS: ClockId = ClockId(5);
+
+        pub const GPIO0: ClockId = ClockId(0x408);
+        pub const UART0: ClockId = ClockId(0x4B4);
+        pub const I2C0: ClockId = ClockId(0x4B8);
+        pub const I2C2: ClockId = ClockId(0x044);
+        pub const I2C1: ClockId = ClockId(0x048);
+        pub const SPI0: ClockId = ClockId(0x04C);
+        pub const SPI1: ClockId = ClockId(0x050);
+        pub const UART1: ClockId = ClockId(0x06C);
+        pub const UART2: ClockId = ClockId(0x070);
+        pub const TIMER2: ClockId = ClockId(0x080);
+        pub const GPIO1: ClockId = ClockId(0x0AC);
+        pub const GPIO2: ClockId = ClockId(0x0B0);
+        pub const GPIO3: ClockId = ClockId(0x0B4);
+    }
 }
 
 /// Memory map for BCM2835 (Raspberry Pi)
@@ -151,6 +569,326 @@ pub mod bcm2835 {
     pub const WATCHDOG_BASE: usize = PERIPHERAL_BASE + 0x10_001C;
 }
 
+/// Memory map for BCM2711 (Raspberry Pi 4), low peripheral mode
+#[cfg(feature = "bcm2711")]
+pub mod bcm2711 {
+    /// Peripheral base, as seen by the ARM
+    pub const PERIPHERAL_BASE: usize = 0xFE00_0000;
+
+    /// GPIO base
+    pub const GPIO_BASE: usize = PERIPHERAL_BASE + 0x20_0000;
+    /// UART0 (PL011) base, UART2-5 follow at 0x200 intervals
+    pub const UART0_BASE: usize = PERIPHERAL_BASE + 0x20_1000;
+    /// SPI0 base
+    pub const SPI0_BASE: usize = PERIPHERAL_BASE + 0x20_4000;
+    /// BSC0 (I2C0) base
+    pub const BSC0_BASE: usize = PERIPHERAL_BASE + 0x20_5000;
+    /// BSC1 (I2C1) base
+    pub const BSC1_BASE: usize = PERIPHERAL_BASE + 0x80_4000;
+
+    /// AUX base (mini UART, SPI1, SPI2)
+    pub const AUX_BASE: usize = PERIPHERAL_BASE + 0x21_5000;
+
+    /// System timer
+    pub const TIMER_BASE: usize = PERIPHERAL_BASE + 0x3000;
+
+    /// Mailbox
+    pub const MAILBOX_BASE: usize = PERIPHERAL_BASE + 0xB_880;
+
+    /// Power management
+    pub const PM_BASE: usize = PERIPHERAL_BASE + 0x10_0000;
+
+    /// Watchdog
+    pub const WATCHDOG_BASE: usize = PERIPHERAL_BASE + 0x10_001C;
+
+    /// EMMC2 (SD card) base
+    pub const EMMC2_BASE: usize = PERIPHERAL_BASE + 0x34_0000;
+
+    /// GENET Ethernet MAC base
+    pub const GENET_BASE: usize = 0xFD58_0000;
+    /// MDIO address of the BCM54213PE PHY
+    pub const GENET_PHY_ADDR: u8 = 1;
+
+    /// GIC-400 distributor base
+    pub const GICD_BASE: usize = 0xFF84_1000;
+    /// GIC-400 CPU interface base
+    pub const GICC_BASE: usize = 0xFF84_2000;
+
+    /// Clocks
+    pub mod clk {
+        use redox_hal::clocks::ClockId;
+
+        /// VPU core clock, feeding the mini UART
+        pub const CORE: ClockId = ClockId(2);
+        /// PL011 reference clock
+        pub const UART: ClockId = ClockId(3);
+        /// EMMC2 base clock
+        pub const EMMC2: ClockId = ClockId(4);
+    }
+}
+
+/// Memory map for BCM2712 (Raspberry Pi 5)
+#[cfg(feature = "bcm2712")]
+pub mod bcm2712 {
+    /// Debug UART (PL011) base
+    pub const DEBUG_UART_BASE: usize = 0x10_7D00_1000;
+
+    /// Mailbox
+    pub const MAILBOX_BASE: usize = 0x10_7C01_3880;
+
+    /// Watchdog
+    pub const WATCHDOG_BASE: usize = 0x10_7D20_0000;
+
+    /// GIC-400 distributor base
+    pub const GICD_BASE: usize = 0x10_7FFF_9000;
+    /// GIC-400 CPU interface base
+    pub const GICC_BASE: usize = 0x10_7FFF_A000;
+
+    /// RP1 peripheral window, as mapped by the firmware
+    pub const RP1_BASE: usize = 0x1F_0000_0000;
+    /// RP1 UART0 (PL011) base, UART1-5 follow at 0x4000 intervals
+    pub const RP1_UART0_BASE: usize = RP1_BASE + 0x3_0000;
+    /// RP1 Ethernet (Cadence GEM) base
+    pub const RP1_ETH_BASE: usize = RP1_BASE + 0x10_0000;
+
+    /// Clocks
+    pub mod clk {
+        use redox_hal::clocks::ClockId;
+
+        /// Debug UART reference clock
+        pub const DEBUG_UART: ClockId = ClockId(2);
+        /// RP1 UART reference clock
+        pub const RP1_UART: ClockId = ClockId(3);
+    }
+}
+
+/// Memory map for RP2040 (Raspberry Pi Pico)
+///
+/// Peripherals start in reset; RESETS has to release them, PIO0 and PIO1
+/// included, before their registers respond.
+#[cfg(feature = "rp2040")]
+pub mod rp2040 {
+    /// Execute-in-place flash window
+    pub const XIP_BASE: usize = 0x1000_0000;
+    /// Striped SRAM
+    pub const SRAM_BASE: usize = 0x2000_0000;
+
+    /// Clock generators
+    pub const CLOCKS_BASE: usize = 0x4000_8000;
+    /// Peripheral resets
+    pub const RESETS_BASE: usize = 0x4000_C000;
+    /// GPIO function select
+    pub const IO_BANK0_BASE: usize = 0x4001_4000;
+    /// GPIO pad control
+    pub const PADS_BANK0_BASE: usize = 0x4001_C000;
+    /// Crystal oscillator
+    pub const XOSC_BASE: usize = 0x4002_4000;
+    /// System PLL
+    pub const PLL_SYS_BASE: usize = 0x4002_8000;
+    /// USB PLL
+    pub const PLL_USB_BASE: usize = 0x4002_C000;
+
+    /// UART0 (PL011) base
+    pub const UART0_BASE: usize = 0x4003_4000;
+    /// UART1 (PL011) base
+    pub const UART1_BASE: usize = 0x4003_8000;
+    /// SPI0 (PL022) base
+    pub const SPI0_BASE: usize = 0x4003_C000;
+    /// SPI1 (PL022) base
+    pub const SPI1_BASE: usize = 0x4004_0000;
+    /// I2C0 (DW_apb_i2c) base
+    pub const I2C0_BASE: usize = 0x4004_4000;
+    /// I2C1 (DW_apb_i2c) base
+    pub const I2C1_BASE: usize = 0x4004_8000;
+    /// PWM base
+    pub const PWM_BASE: usize = 0x4005_0000;
+    /// Microsecond timer
+    pub const TIMER_BASE: usize = 0x4005_4000;
+    /// Watchdog
+    pub const WATCHDOG_BASE: usize = 0x4005_8000;
+
+    /// PIO0 base
+    pub const PIO0_BASE: usize = 0x5020_0000;
+    /// PIO1 base
+    pub const PIO1_BASE: usize = 0x5030_0000;
+
+    /// Single-cycle IO (GPIO, FIFOs between the cores)
+    pub const SIO_BASE: usize = 0xD000_0000;
+
+    /// Clocks
+    pub mod clk {
+        use redox_hal::clocks::ClockId;
+
+        /// USB PLL, 48 MHz
+        pub const USB: ClockId = ClockId(2);
+        /// clk_peri, feeding UART and SPI
+        pub const PERI: ClockId = ClockId(3);
+    }
+}
+
+/// Memory map for RP2350 (Raspberry Pi Pico 2)
+///
+/// As on the RP2040, RESETS has to release the peripherals before use.
+#[cfg(feature = "rp2350")]
+pub mod rp2350 {
+    /// Execute-in-place flash window
+    pub const XIP_BASE: usize = 0x1000_0000;
+    /// SRAM
+    pub const SRAM_BASE: usize = 0x2000_0000;
+
+    /// Clock generators
+    pub const CLOCKS_BASE: usize = 0x4001_0000;
+    /// Peripheral resets
+    pub const RESETS_BASE: usize = 0x4002_0000;
+    /// GPIO function select
+    pub const IO_BANK0_BASE: usize = 0x4002_8000;
+    /// GPIO pad control
+    pub const PADS_BANK0_BASE: usize = 0x4003_8000;
+    /// Crystal oscillator
+    pub const XOSC_BASE: usize = 0x4004_8000;
+    /// System PLL
+    pub const PLL_SYS_BASE: usize = 0x4005_0000;
+    /// USB PLL
+    pub const PLL_USB_BASE: usize = 0x4005_8000;
+
+    /// UART0 (PL011) base
+    pub const UART0_BASE: usize = 0x4007_0000;
+    /// UART1 (PL011) base
+    pub const UART1_BASE: usize = 0x4007_8000;
+    /// SPI0 (PL022) base
+    pub const SPI0_BASE: usize = 0x4008_0000;
+    /// SPI1 (PL022) base
+    pub const SPI1_BASE: usize = 0x4008_8000;
+    /// I2C0 (DW_apb_i2c) base
+    pub const I2C0_BASE: usize = 0x4009_0000;
+    /// I2C1 (DW_apb_i2c) base
+    pub const I2C1_BASE: usize = 0x4009_8000;
+    /// PWM base
+    pub const PWM_BASE: usize = 0x400A_8000;
+    /// Microsecond timer 0
+    pub const TIMER0_BASE: usize = 0x400B_0000;
+    /// Watchdog
+    pub const WATCHDOG_BASE: usize = 0x400D_8000;
+
+    /// PIO0 base
+    pub const PIO0_BASE: usize = 0x5020_0000;
+    /// PIO1 base
+    pub const PIO1_BASE: usize = 0x5030_0000;
+    /// PIO2 base
+    pub const PIO2_BASE: usize = 0x5040_0000;
+
+    /// Single-cycle IO
+    pub const SIO_BASE: usize = 0xD000_0000;
+
+    /// Clocks
+    pub mod clk {
+        use redox_hal::clocks::ClockId;
+
+        /// USB PLL, 48 MHz
+        pub const USB: ClockId = ClockId(2);
+        /// clk_peri, feeding UART and SPI
+        pub const PERI: ClockId = ClockId(3);
+    }
+}
+
+/// Memory map for ESP32-C3
+#[cfg(feature = "esp32-c3")]
+pub mod esp32c3 {
+    /// Internal SRAM, data bus view
+    pub const DRAM_BASE: usize = 0x3FC8_0000;
+    /// Internal SRAM, instruction bus view
+    pub const IRAM_BASE: usize = 0x4037_C000;
+    /// Flash, instruction cache window
+    pub const IROM_BASE: usize = 0x4200_0000;
+    /// Flash, data cache window
+    pub const DROM_BASE: usize = 0x3C00_0000;
+
+    /// UART0 base
+    pub const UART0_BASE: usize = 0x6000_0000;
+    /// UART1 base
+    pub const UART1_BASE: usize = 0x6001_0000;
+    /// GPIO (and GPIO matrix) base
+    pub const GPIO_BASE: usize = 0x6000_4000;
+    /// RTC control, including the RTC watchdog
+    pub const RTC_CNTL_BASE: usize = 0x6000_8000;
+    /// eFuse controller
+    pub const EFUSE_BASE: usize = 0x6000_8800;
+    /// IO_MUX base
+    pub const IO_MUX_BASE: usize = 0x6000_9000;
+    /// I2C0 base
+    pub const I2C0_BASE: usize = 0x6001_3000;
+    /// LED PWM controller
+    pub const LEDC_BASE: usize = 0x6001_9000;
+    /// Timer group 0
+    pub const TIMG0_BASE: usize = 0x6001_F000;
+    /// Timer group 1
+    pub const TIMG1_BASE: usize = 0x6002_0000;
+    /// System timer
+    pub const SYSTIMER_BASE: usize = 0x6002_3000;
+    /// General purpose SPI2
+    pub const SPI2_BASE: usize = 0x6002_4000;
+    /// USB serial/JTAG controller
+    pub const USB_SERIAL_JTAG_BASE: usize = 0x6004_3000;
+    /// System registers (clock gates, resets)
+    pub const SYSTEM_BASE: usize = 0x600C_0000;
+
+    /// Clocks
+    pub mod clk {
+        use redox_hal::clocks::ClockId;
+
+        /// APB clock, feeding UART, SPI2 and the timer groups
+        pub const APB: ClockId = ClockId(2);
+    }
+}
+
+/// Memory map for ESP32-C6
+#[cfg(feature = "esp32-c6")]
+pub mod esp32c6 {
+    /// HP SRAM
+    pub const HP_SRAM_BASE: usize = 0x4080_0000;
+    /// LP SRAM
+    pub const LP_SRAM_BASE: usize = 0x5000_0000;
+    /// Flash, cache window
+    pub const FLASH_BASE: usize = 0x4200_0000;
+
+    /// UART0 base
+    pub const UART0_BASE: usize = 0x6000_0000;
+    /// UART1 base
+    pub const UART1_BASE: usize = 0x6000_1000;
+    /// I2C0 base
+    pub const I2C0_BASE: usize = 0x6000_4000;
+    /// LED PWM controller
+    pub const LEDC_BASE: usize = 0x6000_7000;
+    /// Timer group 0
+    pub const TIMG0_BASE: usize = 0x6000_8000;
+    /// Timer group 1
+    pub const TIMG1_BASE: usize = 0x6000_9000;
+    /// System timer
+    pub const SYSTIMER_BASE: usize = 0x6000_A000;
+    /// USB serial/JTAG controller
+    pub const USB_SERIAL_JTAG_BASE: usize = 0x6000_F000;
+    /// General purpose SPI2
+    pub const SPI2_BASE: usize = 0x6008_1000;
+    /// IO_MUX base
+    pub const IO_MUX_BASE: usize = 0x6009_0000;
+    /// GPIO (and GPIO matrix) base
+    pub const GPIO_BASE: usize = 0x6009_1000;
+    /// Peripheral clock control and resets
+    pub const PCR_BASE: usize = 0x6009_6000;
+    /// eFuse controller
+    pub const EFUSE_BASE: usize = 0x600B_0800;
+    /// LP watchdog
+    pub const LP_WDT_BASE: usize = 0x600B_1C00;
+
+    /// Clocks
+    pub mod clk {
+        use redox_hal::clocks::ClockId;
+
+        /// 80 MHz PLL output, feeding SPI2
+        pub const PLL_F80M: ClockId = ClockId(2);
+    }
+}
+
 /// Memory map for FE310 (SiFive HiFive1)
 #[cfg(feature = "fe310")]
 pub mod fe310 {
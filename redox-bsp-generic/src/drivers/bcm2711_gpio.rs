@@ -0,0 +1,259 @@
+//! BCM2711 GPIO driver (Raspberry Pi 4)
+//!
+//! The BCM2835 family GPIO block with the BCM2711 pull control registers.
+
+use redox_hal::gpio::events::PinEvent;
+use redox_hal::gpio::{Edge, GpioPin, InterruptPin, Level, PinMode, Pull, Trigger};
+use redox_hal::pinmux::{AltFn, Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::Error;
+
+const GPFSEL0: usize = 0x00;
+const GPSET0: usize = 0x1C;
+const GPCLR0: usize = 0x28;
+const GPLEV0: usize = 0x34;
+const GPEDS0: usize = 0x40;
+const GPREN0: usize = 0x4C;
+const GPFEN0: usize = 0x58;
+const GPHEN0: usize = 0x64;
+const GPLEN0: usize = 0x70;
+const GPIO_PUP_PDN_CNTRL_REG0: usize = 0xE4;
+
+// Function select codes
+const FSEL_INPUT: u32 = 0b000;
+const FSEL_OUTPUT: u32 = 0b001;
+const FSEL_ALT: [u32; 6] = [0b100, 0b101, 0b110, 0b111, 0b011, 0b010];
+
+/// Number of GPIO lines
+pub const GPIO_COUNT: u8 = 58;
+
+/// Pad function for plain GPIO use, after ALT0 to ALT5
+pub const GPIO_FUNCTION: AltFn = AltFn(6);
+
+/// Set the function select field of `pin`
+///
+/// # Safety
+///
+/// `base` must be the mapped GPIO block.
+unsafe fn set_fsel(base: usize, pin: u8, fsel: u32) {
+    let reg = (base + GPFSEL0 + (pin / 10) as usize * 4) as *mut u32;
+    let shift = (pin % 10) as u32 * 3;
+    let value = core::ptr::read_volatile(reg) & !(0b111 << shift);
+    core::ptr::write_volatile(reg, value | (fsel << shift));
+}
+
+/// BCM2711 GPIO pin
+pub struct Bcm2711GpioPin {
+    base: usize,
+    pin: u8,
+    mode: PinMode,
+    handler: Option<fn(PinEvent)>,
+}
+
+impl Bcm2711GpioPin {
+    /// Create a pin of the GPIO block at `base`
+    pub const fn new(base: usize, pin: u8) -> Self {
+        Self {
+            base,
+            pin,
+            mode: PinMode::Input,
+            handler: None,
+        }
+    }
+
+    /// Take GPIO `pin` after routing its pad through `pinmux`
+    pub fn claim<C: PinController>(
+        base: usize,
+        pin: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        pinmux.claim_pin(
+            u16::from(pin),
+            GPIO_FUNCTION,
+            Peripheral::Gpio(u16::from(pin)),
+        )?;
+        Ok(Self::new(base, pin))
+    }
+
+    /// Service a pending interrupt of this pin and run its handler
+    ///
+    /// Called from the interrupt handler of the GPIO bank.
+    pub fn handle_interrupt(&mut self, timestamp: u64) -> Option<PinEvent> {
+        let event = self.take_interrupt(timestamp)?;
+        if let Some(handler) = self.handler {
+            handler(event);
+        }
+        Some(event)
+    }
+
+    /// Offset of this pin's word in a two-word register bank
+    fn bank(&self, offset: usize) -> usize {
+        offset + (self.pin / 32) as usize * 4
+    }
+
+    fn bit_mask(&self) -> u32 {
+        1 << (self.pin % 32)
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    unsafe fn modify_reg(&self, offset: usize, set: bool) {
+        let offset = self.bank(offset);
+        let value = self.read_reg(offset);
+        if set {
+            self.write_reg(offset, value | self.bit_mask());
+        } else {
+            self.write_reg(offset, value & !self.bit_mask());
+        }
+    }
+}
+
+impl GpioPin for Bcm2711GpioPin {
+    type Error = Error;
+
+    fn pin_number(&self) -> u8 {
+        self.pin
+    }
+
+    fn set_mode(&mut self, mode: PinMode) -> Result<(), Self::Error> {
+        let fsel = match mode {
+            // Open drain outputs are emulated by switching the direction,
+            // starting released
+            PinMode::Input | PinMode::OpenDrain => FSEL_INPUT,
+            PinMode::Output => FSEL_OUTPUT,
+            PinMode::Alternate(alt) => {
+                *FSEL_ALT.get(alt as usize).ok_or(Error::InvalidParameter)?
+            }
+            PinMode::Analog => return Err(Error::NotAvailable),
+        };
+        unsafe { set_fsel(self.base, self.pin, fsel) };
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn mode(&self) -> PinMode {
+        self.mode
+    }
+
+    fn set_pull(&mut self, pull: Pull) -> Result<(), Self::Error> {
+        let bits = match pull {
+            Pull::None => 0b00,
+            Pull::Up => 0b01,
+            Pull::Down => 0b10,
+        };
+        let offset = GPIO_PUP_PDN_CNTRL_REG0 + (self.pin / 16) as usize * 4;
+        let shift = (self.pin % 16) as u32 * 2;
+        unsafe {
+            let value = self.read_reg(offset) & !(0b11 << shift);
+            self.write_reg(offset, value | (bits << shift));
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Level, Self::Error> {
+        let value = unsafe { self.read_reg(self.bank(GPLEV0)) };
+        Ok(Level::from_bool(value & self.bit_mask() != 0))
+    }
+
+    fn write(&mut self, level: Level) -> Result<(), Self::Error> {
+        unsafe {
+            match level {
+                Level::High => self.write_reg(self.bank(GPSET0), self.bit_mask()),
+                Level::Low => self.write_reg(self.bank(GPCLR0), self.bit_mask()),
+            }
+            if self.mode == PinMode::OpenDrain {
+                // Drive low, release high
+                let fsel = match level {
+                    Level::Low => FSEL_OUTPUT,
+                    Level::High => FSEL_INPUT,
+                };
+                set_fsel(self.base, self.pin, fsel);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl InterruptPin for Bcm2711GpioPin {
+    fn set_trigger(&mut self, trigger: Trigger) -> Result<(), Self::Error> {
+        let (rising, falling, low, high) = match trigger {
+            Trigger::Edge(Edge::Rising) => (true, false, false, false),
+            Trigger::Edge(Edge::Falling) => (false, true, false, false),
+            Trigger::Edge(Edge::Both) => (true, true, false, false),
+            Trigger::Level(Level::Low) => (false, false, true, false),
+            Trigger::Level(Level::High) => (false, false, false, true),
+        };
+        unsafe {
+            self.modify_reg(GPREN0, rising);
+            self.modify_reg(GPFEN0, falling);
+            self.modify_reg(GPLEN0, low);
+            self.modify_reg(GPHEN0, high);
+            // Drop an event latched while reconfiguring
+            self.write_reg(self.bank(GPEDS0), self.bit_mask());
+        }
+        Ok(())
+    }
+
+    fn disable_interrupt(&mut self) -> Result<(), Self::Error> {
+        unsafe {
+            for offset in [GPREN0, GPFEN0, GPLEN0, GPHEN0] {
+                self.modify_reg(offset, false);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_interrupt_pending(&self) -> bool {
+        unsafe { self.read_reg(self.bank(GPEDS0)) & self.bit_mask() != 0 }
+    }
+
+    fn clear_interrupt(&mut self) {
+        // Write one to clear
+        unsafe {
+            self.write_reg(self.bank(GPEDS0), self.bit_mask());
+        }
+    }
+
+    fn set_interrupt_handler(&mut self, handler: fn(PinEvent)) {
+        self.handler = Some(handler);
+    }
+}
+
+/// BCM2711 pad function controller
+///
+/// Pins are GPIO numbers and functions are ALT0 to ALT5, or
+/// [`GPIO_FUNCTION`] for an input.
+pub struct Bcm2711PinController {
+    base: usize,
+}
+
+impl Bcm2711PinController {
+    /// Create a pad controller for the GPIO block at `base`
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+}
+
+impl PinController for Bcm2711PinController {
+    type Error = Error;
+
+    fn set_function(&mut self, pin: u16, function: AltFn) -> Result<(), Self::Error> {
+        if pin >= u16::from(GPIO_COUNT) {
+            return Err(Error::InvalidParameter);
+        }
+        let fsel = if function == GPIO_FUNCTION {
+            FSEL_INPUT
+        } else {
+            *FSEL_ALT
+                .get(function.0 as usize)
+                .ok_or(Error::InvalidParameter)?
+        };
+        unsafe { set_fsel(self.base, pin as u8, fsel) };
+        Ok(())
+    }
+}
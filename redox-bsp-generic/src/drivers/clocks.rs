@@ -0,0 +1,183 @@
+//! Generic clock controller driver
+
+use redox_hal::clocks::{ClockControl, ClockId, ClockKind, ClockTree, PllConfig, PllLimits};
+use redox_hal::time::Rate;
+use redox_hal::Error;
+
+// AM335x style PRCM module clock control registers
+const CLKCTRL_MODULEMODE: u32 = 0x3;
+const MODULEMODE_ENABLE: u32 = 0x2;
+const CLKCTRL_IDLEST: u32 = 0x3 << 16;
+
+// MPU DPLL registers
+const CM_IDLEST_DPLL_MPU: usize = 0x420;
+const CM_CLKSEL_DPLL_MPU: usize = 0x42C;
+const CM_CLKMODE_DPLL_MPU: usize = 0x488;
+const CM_DIV_M2_DPLL_MPU: usize = 0x4A8;
+const DPLL_EN: u32 = 0x7;
+const DPLL_EN_MN_BYPASS: u32 = 0x4;
+const DPLL_EN_LOCK: u32 = 0x7;
+const ST_DPLL_CLK: u32 = 1 << 0;
+const ST_MN_BYPASS: u32 = 1 << 8;
+const DPLL_MULT_SHIFT: u32 = 8;
+const DPLL_MULT: u32 = 0x7FF << DPLL_MULT_SHIFT;
+const DPLL_DIV: u32 = 0x7F;
+const DPLL_CLKOUT_DIV: u32 = 0x1F;
+
+const MPU_LIMITS: PllLimits = PllLimits {
+    max_input_div: 128,
+    max_multiplier: 2047,
+    max_output_div: 31,
+    vco_min: 50_000_000,
+    vco_max: 2_000_000_000,
+};
+
+// Register polls before giving up
+const POLL_LIMIT: u32 = 100_000;
+
+/// Generic clock controller
+///
+/// Peripheral gates are the offsets of their CLKCTRL registers from the
+/// PRCM base; sources, PLLs and dividers run as long as the board is up.
+/// Only the CPU PLL can be reprogrammed.
+pub struct GenericClockControl {
+    base: usize,
+    tree: ClockTree,
+    cpu_pll: Option<PllConfig>,
+}
+
+impl GenericClockControl {
+    /// Create a clock controller for the PRCM at `base` driving `tree`
+    pub const fn new(base: usize, tree: ClockTree) -> Self {
+        Self {
+            base,
+            tree,
+            cpu_pll: None,
+        }
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&mut self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    fn poll(&self, offset: usize, mask: u32, value: u32) -> Result<(), Error> {
+        for _ in 0..POLL_LIMIT {
+            if self.read(offset) & mask == value {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Error::Timeout)
+    }
+
+    fn kind(&self, clock: ClockId) -> Result<ClockKind, Error> {
+        self.tree
+            .node(clock)
+            .map(|node| node.kind)
+            .ok_or(Error::InvalidParameter)
+    }
+
+    fn set_dpll_mode(&mut self, mode: u32, status: u32) -> Result<(), Error> {
+        let clkmode = self.read(CM_CLKMODE_DPLL_MPU) & !DPLL_EN;
+        self.write(CM_CLKMODE_DPLL_MPU, clkmode | mode);
+        self.poll(CM_IDLEST_DPLL_MPU, status, status)
+    }
+}
+
+impl ClockControl for GenericClockControl {
+    type Error = Error;
+
+    fn tree(&self) -> &ClockTree {
+        &self.tree
+    }
+
+    fn enable(&mut self, clock: ClockId) -> Result<(), Self::Error> {
+        if self.kind(clock)? != ClockKind::Gate {
+            return Ok(());
+        }
+        let offset = clock.0 as usize;
+        let clkctrl = self.read(offset) & !CLKCTRL_MODULEMODE;
+        self.write(offset, clkctrl | MODULEMODE_ENABLE);
+        // Wait for the module to become functional
+        self.poll(offset, CLKCTRL_IDLEST, 0)
+    }
+
+    fn disable(&mut self, clock: ClockId) -> Result<(), Self::Error> {
+        if self.kind(clock)? != ClockKind::Gate {
+            return Err(Error::NotAvailable);
+        }
+        let offset = clock.0 as usize;
+        let clkctrl = self.read(offset) & !CLKCTRL_MODULEMODE;
+        self.write(offset, clkctrl);
+        Ok(())
+    }
+
+    fn is_enabled(&self, clock: ClockId) -> bool {
+        match self.kind(clock) {
+            Ok(ClockKind::Gate) => {
+                self.read(clock.0 as usize) & CLKCTRL_MODULEMODE == MODULEMODE_ENABLE
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        }
+    }
+
+    fn frequency(&self, clock: ClockId) -> Result<Rate, Self::Error> {
+        let hz = match (clock, self.cpu_pll) {
+            (ClockId::CPU, Some(config)) => self
+                .tree
+                .frequency(ClockId::OSC)
+                .map(|hz| config.output(hz)),
+            _ => self.tree.frequency(clock),
+        };
+        hz.map(Rate::from_hz).ok_or(Error::InvalidParameter)
+    }
+
+    fn set_frequency(&mut self, clock: ClockId, rate: Rate) -> Result<Rate, Self::Error> {
+        if clock != ClockId::CPU {
+            return Err(Error::NotAvailable);
+        }
+        let input = self
+            .tree
+            .frequency(ClockId::OSC)
+            .ok_or(Error::InvalidConfig)?;
+        let config =
+            PllConfig::find(input, rate.as_hz(), MPU_LIMITS).ok_or(Error::InvalidParameter)?;
+        self.configure_pll(clock, config)
+    }
+
+    fn configure_pll(&mut self, pll: ClockId, config: PllConfig) -> Result<Rate, Self::Error> {
+        if pll != ClockId::CPU {
+            return Err(Error::NotAvailable);
+        }
+        if config.input_div == 0
+            || config.input_div > MPU_LIMITS.max_input_div
+            || config.multiplier < 2
+            || config.multiplier > MPU_LIMITS.max_multiplier
+            || config.output_div == 0
+            || config.output_div > MPU_LIMITS.max_output_div
+        {
+            return Err(Error::InvalidParameter);
+        }
+
+        // The MPU runs from the bypass clock while the DPLL relocks
+        self.set_dpll_mode(DPLL_EN_MN_BYPASS, ST_MN_BYPASS)?;
+
+        let clksel = self.read(CM_CLKSEL_DPLL_MPU) & !(DPLL_MULT | DPLL_DIV);
+        let mult = u32::from(config.multiplier) << DPLL_MULT_SHIFT;
+        self.write(
+            CM_CLKSEL_DPLL_MPU,
+            clksel | mult | u32::from(config.input_div - 1),
+        );
+        let div_m2 = self.read(CM_DIV_M2_DPLL_MPU) & !DPLL_CLKOUT_DIV;
+        self.write(CM_DIV_M2_DPLL_MPU, div_m2 | u32::from(config.output_div));
+
+        self.set_dpll_mode(DPLL_EN_LOCK, ST_DPLL_CLK)?;
+        self.cpu_pll = Some(config);
+        self.frequency(ClockId::CPU)
+    }
+}
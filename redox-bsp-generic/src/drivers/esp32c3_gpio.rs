@@ -0,0 +1,310 @@
+//! ESP32-C3/C6 GPIO driver
+//!
+//! Each pad has an IO_MUX register selecting between a few fixed functions
+//! (function 1 is GPIO) and setting pulls, input enable and drive strength.
+//! Everything else reaches the pads through the GPIO matrix, which connects
+//! any of the 128 peripheral output signals to any pad, and any pad to a
+//! peripheral input signal.
+
+use redox_hal::gpio::{GpioPin, Level, PinMode, Pull};
+use redox_hal::pinmux::{AltFn, Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::Error;
+
+// GPIO registers
+const GPIO_OUT_W1TS: usize = 0x08;
+const GPIO_OUT_W1TC: usize = 0x0C;
+const GPIO_ENABLE_W1TS: usize = 0x24;
+const GPIO_ENABLE_W1TC: usize = 0x28;
+const GPIO_IN: usize = 0x3C;
+const GPIO_PIN0: usize = 0x74;
+const GPIO_FUNC0_IN_SEL_CFG: usize = 0x154;
+const GPIO_FUNC0_OUT_SEL_CFG: usize = 0x554;
+
+const PIN_PAD_DRIVER: u32 = 1 << 2;
+
+// Output selection: signal 128 is the GPIO_OUT register
+const OUT_SEL_GPIO: u32 = 128;
+const OUT_INV_SEL: u32 = 1 << 8;
+// Output enable from GPIO_ENABLE rather than the peripheral
+const OEN_SEL: u32 = 1 << 9;
+
+// IO_MUX pad register
+const MUX_FUN_WPD: u32 = 1 << 7;
+const MUX_FUN_WPU: u32 = 1 << 8;
+const MUX_FUN_IE: u32 = 1 << 9;
+const MUX_MCU_SEL_SHIFT: u32 = 12;
+const MUX_MCU_SEL: u32 = 0x7 << MUX_MCU_SEL_SHIFT;
+
+/// IO_MUX function for plain GPIO and GPIO matrix use
+pub const GPIO_FUNCTION: AltFn = AltFn(1);
+
+/// Highest peripheral signal number of the GPIO matrix
+pub const MAX_SIGNAL: u8 = 127;
+
+/// ESP32 RISC-V chip variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EspChip {
+    /// ESP32-C3
+    Esp32C3,
+    /// ESP32-C6
+    Esp32C6,
+}
+
+impl EspChip {
+    /// Number of GPIO pads
+    pub const fn gpio_count(&self) -> u8 {
+        match self {
+            EspChip::Esp32C3 => 22,
+            EspChip::Esp32C6 => 31,
+        }
+    }
+
+    /// Width of the pad select of the input matrix; the invert and
+    /// matrix-enable bits follow it
+    const fn in_sel_bits(&self) -> u32 {
+        match self {
+            EspChip::Esp32C3 => 5,
+            EspChip::Esp32C6 => 6,
+        }
+    }
+}
+
+unsafe fn read_reg(base: usize, offset: usize) -> u32 {
+    core::ptr::read_volatile((base + offset) as *const u32)
+}
+
+unsafe fn write_reg(base: usize, offset: usize, value: u32) {
+    core::ptr::write_volatile((base + offset) as *mut u32, value);
+}
+
+unsafe fn modify_reg(base: usize, offset: usize, clear: u32, set: u32) {
+    let value = read_reg(base, offset) & !clear;
+    write_reg(base, offset, value | set);
+}
+
+fn mux_offset(pin: u8) -> usize {
+    0x04 + usize::from(pin) * 4
+}
+
+fn out_sel_offset(pin: u8) -> usize {
+    GPIO_FUNC0_OUT_SEL_CFG + usize::from(pin) * 4
+}
+
+/// GPIO matrix and IO_MUX of one chip
+///
+/// Functions passed to [`PinController::set_function`] are IO_MUX
+/// functions; peripherals without one there are routed with
+/// [`EspPinController::connect_output`] and
+/// [`EspPinController::connect_input`].
+pub struct EspPinController {
+    chip: EspChip,
+    gpio: usize,
+    io_mux: usize,
+}
+
+impl EspPinController {
+    /// Create a pad controller for the GPIO and IO_MUX blocks
+    pub const fn new(chip: EspChip, gpio: usize, io_mux: usize) -> Self {
+        Self { chip, gpio, io_mux }
+    }
+
+    fn check_pin(&self, pin: u8) -> Result<(), Error> {
+        if pin < self.chip.gpio_count() {
+            Ok(())
+        } else {
+            Err(Error::InvalidParameter)
+        }
+    }
+
+    /// Drive `pin` from peripheral output `signal`
+    ///
+    /// The peripheral also controls the output enable.
+    pub fn connect_output(&mut self, pin: u8, signal: u8, invert: bool) -> Result<(), Error> {
+        self.check_pin(pin)?;
+        if signal > MAX_SIGNAL {
+            return Err(Error::InvalidParameter);
+        }
+        let mut value = u32::from(signal);
+        if invert {
+            value |= OUT_INV_SEL;
+        }
+        unsafe {
+            modify_reg(
+                self.io_mux,
+                mux_offset(pin),
+                MUX_MCU_SEL,
+                1 << MUX_MCU_SEL_SHIFT,
+            );
+            write_reg(self.gpio, out_sel_offset(pin), value);
+        }
+        Ok(())
+    }
+
+    /// Feed peripheral input `signal` from `pin`
+    pub fn connect_input(&mut self, signal: u8, pin: u8, invert: bool) -> Result<(), Error> {
+        self.check_pin(pin)?;
+        if signal > MAX_SIGNAL {
+            return Err(Error::InvalidParameter);
+        }
+        let bits = self.chip.in_sel_bits();
+        let mut value = u32::from(pin) | 1 << (bits + 1);
+        if invert {
+            value |= 1 << bits;
+        }
+        unsafe {
+            modify_reg(self.io_mux, mux_offset(pin), 0, MUX_FUN_IE);
+            write_reg(
+                self.gpio,
+                GPIO_FUNC0_IN_SEL_CFG + usize::from(signal) * 4,
+                value,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl PinController for EspPinController {
+    type Error = Error;
+
+    fn set_function(&mut self, pin: u16, function: AltFn) -> Result<(), Self::Error> {
+        let pin = u8::try_from(pin).map_err(|_| Error::InvalidParameter)?;
+        self.check_pin(pin)?;
+        if function.0 > 7 {
+            return Err(Error::InvalidParameter);
+        }
+        unsafe {
+            modify_reg(
+                self.io_mux,
+                mux_offset(pin),
+                MUX_MCU_SEL,
+                u32::from(function.0) << MUX_MCU_SEL_SHIFT | MUX_FUN_IE,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// ESP32-C3/C6 GPIO pin
+pub struct EspGpioPin {
+    gpio: usize,
+    io_mux: usize,
+    pin: u8,
+    mask: u32,
+    mode: PinMode,
+}
+
+impl EspGpioPin {
+    /// Create GPIO `pin`
+    pub fn new(chip: EspChip, gpio: usize, io_mux: usize, pin: u8) -> Result<Self, Error> {
+        if pin >= chip.gpio_count() {
+            return Err(Error::InvalidParameter);
+        }
+        Ok(Self {
+            gpio,
+            io_mux,
+            pin,
+            mask: 1 << pin,
+            mode: PinMode::Input,
+        })
+    }
+
+    /// Take GPIO `pin` after routing its pad through `pinmux`
+    pub fn claim<C: PinController>(
+        chip: EspChip,
+        gpio: usize,
+        io_mux: usize,
+        pin: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        let peripheral = Peripheral::Gpio(u16::from(pin));
+        let gpio =
+            Self::new(chip, gpio, io_mux, pin).map_err(|_| PinmuxError::NotMapped(peripheral))?;
+        pinmux.claim_pin(u16::from(pin), GPIO_FUNCTION, peripheral)?;
+        Ok(gpio)
+    }
+
+    fn set_output_enable(&mut self, enabled: bool) {
+        let offset = if enabled {
+            GPIO_ENABLE_W1TS
+        } else {
+            GPIO_ENABLE_W1TC
+        };
+        unsafe { write_reg(self.gpio, offset, self.mask) };
+    }
+}
+
+impl GpioPin for EspGpioPin {
+    type Error = Error;
+
+    fn pin_number(&self) -> u8 {
+        self.pin
+    }
+
+    fn set_mode(&mut self, mode: PinMode) -> Result<(), Self::Error> {
+        let function = match mode {
+            PinMode::Input | PinMode::Output | PinMode::OpenDrain => GPIO_FUNCTION.0,
+            PinMode::Alternate(alt) if alt <= 7 => alt,
+            PinMode::Alternate(_) => return Err(Error::InvalidParameter),
+            // ADC pads only need the digital input off, but the ADC isn't
+            // supported here
+            PinMode::Analog => return Err(Error::NotAvailable),
+        };
+        let pin_reg = GPIO_PIN0 + usize::from(self.pin) * 4;
+        unsafe {
+            modify_reg(
+                self.io_mux,
+                mux_offset(self.pin),
+                MUX_MCU_SEL,
+                u32::from(function) << MUX_MCU_SEL_SHIFT | MUX_FUN_IE,
+            );
+            if matches!(mode, PinMode::Output | PinMode::OpenDrain) {
+                write_reg(self.gpio, out_sel_offset(self.pin), OUT_SEL_GPIO | OEN_SEL);
+            }
+            let driver = if mode == PinMode::OpenDrain {
+                PIN_PAD_DRIVER
+            } else {
+                0
+            };
+            modify_reg(self.gpio, pin_reg, PIN_PAD_DRIVER, driver);
+        }
+        self.set_output_enable(matches!(mode, PinMode::Output | PinMode::OpenDrain));
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn mode(&self) -> PinMode {
+        self.mode
+    }
+
+    fn set_pull(&mut self, pull: Pull) -> Result<(), Self::Error> {
+        let set = match pull {
+            Pull::None => 0,
+            Pull::Up => MUX_FUN_WPU,
+            Pull::Down => MUX_FUN_WPD,
+        };
+        unsafe {
+            modify_reg(
+                self.io_mux,
+                mux_offset(self.pin),
+                MUX_FUN_WPU | MUX_FUN_WPD,
+                set,
+            )
+        };
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Level, Self::Error> {
+        let value = unsafe { read_reg(self.gpio, GPIO_IN) };
+        Ok(Level::from_bool(value & self.mask != 0))
+    }
+
+    fn write(&mut self, level: Level) -> Result<(), Self::Error> {
+        // Open drain pads only ever pull low, so the output latch alone does
+        let offset = match level {
+            Level::High => GPIO_OUT_W1TS,
+            Level::Low => GPIO_OUT_W1TC,
+        };
+        unsafe { write_reg(self.gpio, offset, self.mask) };
+        Ok(())
+    }
+}
@@ -0,0 +1,256 @@
+//! ESP32-C3/C6 general purpose SPI (GP-SPI2) driver
+//!
+//! Transfers are built from user-defined transactions of up to 64 bytes
+//! through the W0-W15 data buffer, with CS0 driven by the controller around
+//! each of them. The same register layout is used by both chips.
+
+use redox_hal::pinmux::{Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::spi::{BitOrder, ClockPhase, ClockPolarity, SpiBus, SpiConfig};
+use redox_hal::Error;
+
+/// Register offsets
+mod regs {
+    pub const CMD: usize = 0x00; // Command, user transaction start
+    pub const CTRL: usize = 0x08; // Bit order
+    pub const CLOCK: usize = 0x0C; // Clock divider
+    pub const USER: usize = 0x10; // Transaction phases
+    pub const MS_DLEN: usize = 0x1C; // Data length in bits, minus one
+    pub const MISC: usize = 0x20; // CS and clock idle polarity
+    pub const DMA_CONF: usize = 0x30; // DMA and FIFO resets
+    pub const W0: usize = 0x98; // Data buffer, 16 words
+    pub const CLK_GATE: usize = 0xE8; // Module clock gating
+}
+
+const CMD_UPDATE: u32 = 1 << 23;
+const CMD_USR: u32 = 1 << 24;
+
+const CTRL_RD_BIT_ORDER: u32 = 1 << 25;
+const CTRL_WR_BIT_ORDER: u32 = 1 << 26;
+
+const CLOCK_EQU_SYSCLK: u32 = 1 << 31;
+
+const USER_DOUTDIN: u32 = 1 << 0;
+const USER_CS_HOLD: u32 = 1 << 6;
+const USER_CS_SETUP: u32 = 1 << 7;
+const USER_CK_OUT_EDGE: u32 = 1 << 9;
+const USER_USR_MOSI: u32 = 1 << 27;
+const USER_USR_MISO: u32 = 1 << 28;
+
+const MISC_CK_IDLE_EDGE: u32 = 1 << 29;
+
+const DMA_CONF_AFIFO_RST: u32 = (1 << 29) | (1 << 30) | (1 << 31);
+
+const CLK_GATE_CLK_EN: u32 = 1 << 0;
+const CLK_GATE_MST_CLK_ACTIVE: u32 = 1 << 1;
+const CLK_GATE_MST_CLK_SEL: u32 = 1 << 2;
+
+/// Largest transaction, in bytes
+pub const BUFFER_SIZE: usize = 64;
+
+// Register polls before giving up
+const POLL_LIMIT: u32 = 1_000_000;
+
+/// GP-SPI2 master
+pub struct EspSpi {
+    base: usize,
+    clock_freq: u32,
+    config: SpiConfig,
+}
+
+impl EspSpi {
+    /// Create a SPI master whose source clock runs at `clock_freq` Hz
+    /// (80 MHz PLL on both chips)
+    pub const fn new(base: usize, clock_freq: u32) -> Self {
+        Self {
+            base,
+            clock_freq,
+            config: SpiConfig {
+                mode: redox_hal::spi::SpiMode::Mode0,
+                bit_order: BitOrder::MsbFirst,
+                frequency: 1_000_000,
+                word_size: 8,
+            },
+        }
+    }
+
+    /// Create SPI `instance` after routing its pads through `pinmux`
+    pub fn claim<C: PinController>(
+        base: usize,
+        clock_freq: u32,
+        instance: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        pinmux.claim(Peripheral::Spi(instance))?;
+        Ok(Self::new(base, clock_freq))
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    /// Current configuration
+    pub fn config(&self) -> &SpiConfig {
+        &self.config
+    }
+
+    /// CLOCK register value for the fastest rate not above `frequency`
+    fn clock_reg(&self, frequency: u32) -> Result<u32, Error> {
+        if frequency == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        if frequency >= self.clock_freq {
+            return Ok(CLOCK_EQU_SYSCLK);
+        }
+        // f = clock / ((pre + 1) * (n + 1)) with pre < 16 and 1 <= n < 64
+        let total = self.clock_freq.div_ceil(frequency);
+        let pre = (total.div_ceil(64)).max(1);
+        if pre > 16 {
+            return Err(Error::InvalidParameter);
+        }
+        let n = total.div_ceil(pre).clamp(2, 64) - 1;
+        let h = n.div_ceil(2) - 1;
+        Ok((pre - 1) << 18 | n << 12 | h << 6 | n)
+    }
+
+    fn wait_clear(&self, offset: usize, bit: u32) -> Result<(), Error> {
+        for _ in 0..POLL_LIMIT {
+            if unsafe { self.read_reg(offset) } & bit == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Run one transaction of at most [`BUFFER_SIZE`] bytes
+    ///
+    /// Sends `write`, or zeros when it is empty, and stores what comes back
+    /// in `read` if it isn't empty.
+    fn transaction(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let len = read.len().max(write.len());
+        debug_assert!(len <= BUFFER_SIZE);
+        if len == 0 {
+            return Ok(());
+        }
+
+        for (index, chunk) in (0..len).step_by(4).enumerate() {
+            let mut word = [0u8; 4];
+            if let Some(bytes) = write.get(chunk..(chunk + 4).min(write.len())) {
+                word[..bytes.len()].copy_from_slice(bytes);
+            }
+            unsafe { self.write_reg(regs::W0 + index * 4, u32::from_le_bytes(word)) };
+        }
+
+        let mut user = USER_DOUTDIN | USER_CS_SETUP | USER_CS_HOLD | USER_USR_MOSI;
+        if !read.is_empty() {
+            user |= USER_USR_MISO;
+        }
+        if self.config.mode.phase() == ClockPhase::CaptureOnSecondTransition {
+            user |= USER_CK_OUT_EDGE;
+        }
+
+        unsafe {
+            self.write_reg(regs::USER, user);
+            self.write_reg(regs::MS_DLEN, (len * 8 - 1) as u32);
+            // Move the configuration into the SPI clock domain first
+            self.write_reg(regs::CMD, CMD_UPDATE);
+        }
+        self.wait_clear(regs::CMD, CMD_UPDATE)?;
+        unsafe { self.write_reg(regs::CMD, CMD_USR) };
+        self.wait_clear(regs::CMD, CMD_USR)?;
+
+        for (index, chunk) in read.chunks_mut(4).enumerate() {
+            let word = unsafe { self.read_reg(regs::W0 + index * 4) }.to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+impl SpiBus for EspSpi {
+    type Error = Error;
+
+    fn configure(&mut self, config: SpiConfig) -> Result<(), Self::Error> {
+        if config.word_size != 8 {
+            return Err(Error::InvalidConfig);
+        }
+        let clock = self.clock_reg(config.frequency)?;
+
+        let order = match config.bit_order {
+            BitOrder::MsbFirst => 0,
+            BitOrder::LsbFirst => CTRL_RD_BIT_ORDER | CTRL_WR_BIT_ORDER,
+        };
+        let idle = match config.mode.polarity() {
+            ClockPolarity::IdleLow => 0,
+            ClockPolarity::IdleHigh => MISC_CK_IDLE_EDGE,
+        };
+
+        unsafe {
+            self.write_reg(
+                regs::CLK_GATE,
+                CLK_GATE_CLK_EN | CLK_GATE_MST_CLK_ACTIVE | CLK_GATE_MST_CLK_SEL,
+            );
+            self.write_reg(regs::DMA_CONF, DMA_CONF_AFIFO_RST);
+            self.write_reg(regs::DMA_CONF, 0);
+            self.write_reg(regs::CLOCK, clock);
+            let ctrl = self.read_reg(regs::CTRL) & !(CTRL_RD_BIT_ORDER | CTRL_WR_BIT_ORDER);
+            self.write_reg(regs::CTRL, ctrl | order);
+            // Only CS0 is used, CS1-CS5 are disabled
+            self.write_reg(regs::MISC, idle | 0x3E);
+        }
+
+        self.config = config;
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        let len = read.len().max(write.len());
+        let mut offset = 0;
+        while offset < len {
+            let end = (offset + BUFFER_SIZE).min(len);
+            // Pad the write side with zeros when it is the shorter one
+            let mut out = [0u8; BUFFER_SIZE];
+            let sent = write.get(offset..end.min(write.len())).unwrap_or_default();
+            out[..sent.len()].copy_from_slice(sent);
+            let received = offset.min(read.len())..end.min(read.len());
+            self.transaction(&mut read[received], &out[..end - offset])?;
+            offset = end;
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        for chunk in data.chunks_mut(BUFFER_SIZE) {
+            let mut write = [0u8; BUFFER_SIZE];
+            write[..chunk.len()].copy_from_slice(chunk);
+            let len = chunk.len();
+            self.transaction(chunk, &write[..len])?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        for chunk in data.chunks(BUFFER_SIZE) {
+            self.transaction(&mut [], chunk)?;
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        for chunk in data.chunks_mut(BUFFER_SIZE) {
+            let zeros = [0u8; BUFFER_SIZE];
+            let len = chunk.len();
+            self.transaction(chunk, &zeros[..len])?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // Transactions complete before the calls return
+        Ok(())
+    }
+}
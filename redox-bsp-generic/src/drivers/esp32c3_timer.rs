@@ -0,0 +1,277 @@
+//! ESP32-C3/C6 timer drivers
+//!
+//! SYSTIMER is a 52-bit counter running at a fixed 16 MHz, kept going
+//! through light sleep, and serves as the monotonic clock. The timer groups
+//! (TIMG0/1) each have a 54-bit general purpose timer with a 16-bit
+//! prescaler and an alarm.
+
+use redox_hal::time::{Duration, Instant};
+use redox_hal::timer::{
+    CountDirection, Delay, Monotonic, Timer, TimerConfig, TimerMode, Timestamp,
+};
+use redox_hal::Error;
+
+/// SYSTIMER register offsets
+mod systimer {
+    pub const CONF: usize = 0x00;
+    pub const UNIT0_OP: usize = 0x04;
+    pub const UNIT0_VALUE_HI: usize = 0x40;
+    pub const UNIT0_VALUE_LO: usize = 0x44;
+
+    pub const CONF_CLK_EN: u32 = 1 << 31;
+    pub const CONF_UNIT0_WORK_EN: u32 = 1 << 30;
+    pub const OP_UPDATE: u32 = 1 << 30;
+    pub const OP_VALUE_VALID: u32 = 1 << 29;
+}
+
+/// Timer group register offsets, timer 0
+mod timg {
+    pub const T0CONFIG: usize = 0x00;
+    pub const T0LO: usize = 0x04;
+    pub const T0UPDATE: usize = 0x0C;
+    pub const T0ALARMLO: usize = 0x10;
+    pub const T0ALARMHI: usize = 0x14;
+    pub const T0LOADLO: usize = 0x18;
+    pub const T0LOADHI: usize = 0x1C;
+    pub const T0LOAD: usize = 0x20;
+
+    pub const CONFIG_EN: u32 = 1 << 31;
+    pub const CONFIG_INCREASE: u32 = 1 << 30;
+    pub const CONFIG_AUTORELOAD: u32 = 1 << 29;
+    pub const CONFIG_DIVIDER_SHIFT: u32 = 13;
+    pub const CONFIG_DIVIDER: u32 = 0xFFFF << CONFIG_DIVIDER_SHIFT;
+    pub const CONFIG_DIVCNT_RST: u32 = 1 << 12;
+    pub const CONFIG_ALARM_EN: u32 = 1 << 10;
+}
+
+/// SYSTIMER counter frequency
+pub const SYSTIMER_FREQ: u32 = 16_000_000;
+
+/// SYSTIMER unit 0
+pub struct EspSysTimer {
+    base: usize,
+}
+
+impl EspSysTimer {
+    /// Create a driver for the SYSTIMER at `base`, starting unit 0
+    pub fn new(base: usize) -> Self {
+        let timer = Self { base };
+        unsafe {
+            let conf = timer.read_reg(systimer::CONF);
+            timer.write_reg(
+                systimer::CONF,
+                conf | systimer::CONF_CLK_EN | systimer::CONF_UNIT0_WORK_EN,
+            );
+        }
+        timer
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    /// Counter value, in 16 MHz ticks
+    pub fn ticks(&self) -> u64 {
+        unsafe {
+            // Latch the counter into the value registers
+            self.write_reg(systimer::UNIT0_OP, systimer::OP_UPDATE);
+            while self.read_reg(systimer::UNIT0_OP) & systimer::OP_VALUE_VALID == 0 {
+                core::hint::spin_loop();
+            }
+            let hi = self.read_reg(systimer::UNIT0_VALUE_HI) & 0xF_FFFF;
+            let lo = self.read_reg(systimer::UNIT0_VALUE_LO);
+            u64::from(hi) << 32 | u64::from(lo)
+        }
+    }
+}
+
+impl Timestamp for EspSysTimer {
+    fn timestamp(&self) -> u64 {
+        self.ticks()
+    }
+
+    fn timestamp_frequency(&self) -> u32 {
+        SYSTIMER_FREQ
+    }
+}
+
+impl Monotonic for EspSysTimer {
+    fn now(&self) -> Instant {
+        // 62.5 ns per tick
+        Instant::from_ticks(self.ticks() * 125 / 2)
+    }
+}
+
+impl Delay for EspSysTimer {
+    fn delay(&mut self, duration: Duration) {
+        let start = self.now();
+        while self.now().duration_since(start) < duration {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// General purpose timer 0 of a timer group
+///
+/// The timer counts the group clock (APB on the ESP32-C3, XTAL on the
+/// ESP32-C6 unless PCR says otherwise) through its prescaler. The HAL
+/// period is the alarm value, which reloads the counter in periodic mode.
+pub struct EspTimgTimer {
+    base: usize,
+    clock_freq: u32,
+    config: TimerConfig,
+}
+
+impl EspTimgTimer {
+    /// Create timer 0 of the timer group at `base`, clocked at
+    /// `clock_freq` Hz
+    pub const fn new(base: usize, clock_freq: u32) -> Self {
+        Self {
+            base,
+            clock_freq,
+            config: TimerConfig {
+                mode: TimerMode::Periodic,
+                direction: CountDirection::Up,
+                prescaler: 0,
+                period: 0xFFFFFFFF,
+            },
+        }
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    /// Prescaler register value: the divider, where 0 stands for 65536
+    fn divider(prescaler: u32) -> Result<u32, Error> {
+        // Dividers below 2 are not allowed; the HAL prescaler is divide - 1
+        match prescaler + 1 {
+            1 => Ok(2),
+            divider @ 2..=65535 => Ok(divider),
+            65536 => Ok(0),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+
+    fn load(&self, value: u64) {
+        unsafe {
+            self.write_reg(timg::T0LOADLO, value as u32);
+            self.write_reg(timg::T0LOADHI, (value >> 32) as u32 & 0x3F_FFFF);
+            self.write_reg(timg::T0LOAD, 1);
+        }
+    }
+}
+
+impl Timer for EspTimgTimer {
+    type Error = Error;
+
+    fn configure(&mut self, config: TimerConfig) -> Result<(), Self::Error> {
+        let divider = Self::divider(config.prescaler)?;
+        let mut value = divider << timg::CONFIG_DIVIDER_SHIFT;
+        value |= match config.direction {
+            CountDirection::Up => timg::CONFIG_INCREASE,
+            CountDirection::Down => 0,
+            CountDirection::UpDown => return Err(Error::InvalidConfig),
+        };
+        value |= match config.mode {
+            TimerMode::Periodic => timg::CONFIG_AUTORELOAD | timg::CONFIG_ALARM_EN,
+            TimerMode::OneShot => timg::CONFIG_ALARM_EN,
+            TimerMode::FreeRunning => 0,
+            TimerMode::InputCapture | TimerMode::OutputCompare => return Err(Error::InvalidConfig),
+        };
+
+        unsafe {
+            // The divider may only change while the timer is stopped
+            let config_reg = self.read_reg(timg::T0CONFIG) & !timg::CONFIG_EN;
+            self.write_reg(timg::T0CONFIG, config_reg);
+            self.write_reg(timg::T0CONFIG, value | timg::CONFIG_DIVCNT_RST);
+            self.write_reg(timg::T0CONFIG, value);
+        }
+        self.config = config;
+        self.set_period(config.period)?;
+        // Down counters start from the period
+        let start = match config.direction {
+            CountDirection::Down => u64::from(config.period),
+            _ => 0,
+        };
+        self.load(start);
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Self::Error> {
+        unsafe {
+            let value = self.read_reg(timg::T0CONFIG);
+            self.write_reg(timg::T0CONFIG, value | timg::CONFIG_EN);
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Self::Error> {
+        unsafe {
+            let value = self.read_reg(timg::T0CONFIG);
+            self.write_reg(timg::T0CONFIG, value & !timg::CONFIG_EN);
+        }
+        Ok(())
+    }
+
+    fn counter(&self) -> u32 {
+        unsafe {
+            // Latch the counter first
+            self.write_reg(timg::T0UPDATE, 1 << 31);
+            while self.read_reg(timg::T0UPDATE) & 1 << 31 != 0 {
+                core::hint::spin_loop();
+            }
+            self.read_reg(timg::T0LO)
+        }
+    }
+
+    fn set_counter(&mut self, value: u32) {
+        self.load(u64::from(value));
+    }
+
+    fn is_running(&self) -> bool {
+        unsafe { self.read_reg(timg::T0CONFIG) & timg::CONFIG_EN != 0 }
+    }
+
+    fn frequency(&self) -> u32 {
+        let divider = (unsafe { self.read_reg(timg::T0CONFIG) } & timg::CONFIG_DIVIDER)
+            >> timg::CONFIG_DIVIDER_SHIFT;
+        let divider = if divider == 0 { 65536 } else { divider };
+        self.clock_freq / divider
+    }
+
+    fn set_period(&mut self, period: u32) -> Result<(), Self::Error> {
+        // Down counters alarm at zero and reload the period
+        let alarm = match self.config.direction {
+            CountDirection::Down => 0,
+            _ => period,
+        };
+        unsafe {
+            self.write_reg(timg::T0ALARMLO, alarm);
+            self.write_reg(timg::T0ALARMHI, 0);
+            if self.config.direction == CountDirection::Down {
+                self.write_reg(timg::T0LOADLO, period);
+                self.write_reg(timg::T0LOADHI, 0);
+            }
+            // Re-arm: the alarm enable clears itself once it fires
+            let value = self.read_reg(timg::T0CONFIG);
+            if self.config.mode != TimerMode::FreeRunning {
+                self.write_reg(timg::T0CONFIG, value | timg::CONFIG_ALARM_EN);
+            }
+        }
+        self.config.period = period;
+        Ok(())
+    }
+
+    fn period(&self) -> u32 {
+        self.config.period
+    }
+}
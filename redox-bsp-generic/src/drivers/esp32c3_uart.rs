@@ -0,0 +1,265 @@
+//! ESP32-C3/C6 UART driver
+//!
+//! The UART core runs from a function clock (`sclk`) which is APB on the
+//! ESP32-C3 and XTAL on the ESP32-C6 after reset; its selection lives in
+//! the UART itself on the C3 and in PCR on the C6, and is left alone here.
+//! Pads are routed through the GPIO matrix, except for UART0 which also has
+//! IO_MUX pads.
+
+use redox_hal::pinmux::{Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::uart::{BaudRate, DataBits, FlowControl, Parity, StopBits, Uart, UartConfig};
+use redox_hal::Error;
+
+use super::esp32c3_gpio::EspChip;
+
+/// Register offsets
+mod regs {
+    pub const FIFO: usize = 0x00; // RX/TX FIFO
+    pub const INT_RAW: usize = 0x04; // Raw interrupt status
+    pub const INT_ENA: usize = 0x0C; // Interrupt enable
+    pub const INT_CLR: usize = 0x10; // Interrupt clear
+    pub const CLKDIV: usize = 0x14; // Baud rate divider
+    pub const STATUS: usize = 0x1C; // FIFO levels
+    pub const CONF0: usize = 0x20; // Frame format
+    pub const CONF1: usize = 0x24; // Thresholds, RX flow control
+    pub const FSM_STATUS: usize = 0x64; // Transmitter state
+    pub const ID_C3: usize = 0x80; // REG_UPDATE in bit 31 on the C3
+    pub const REG_UPDATE_C6: usize = 0x98; // REG_UPDATE in bit 0 on the C6
+}
+
+/// Interrupt bits
+mod int {
+    pub const PARITY_ERR: u32 = 1 << 2;
+    pub const FRM_ERR: u32 = 1 << 3;
+    pub const RXFIFO_OVF: u32 = 1 << 4;
+}
+
+/// CONF0 bits
+mod conf0 {
+    pub const PARITY_ODD: u32 = 1 << 0;
+    pub const PARITY_EN: u32 = 1 << 1;
+    pub const BIT_NUM_SHIFT: u32 = 2;
+    pub const STOP_BIT_NUM_SHIFT: u32 = 4;
+    pub const TX_FLOW_EN: u32 = 1 << 15;
+    pub const RXFIFO_RST: u32 = 1 << 17;
+    pub const TXFIFO_RST: u32 = 1 << 18;
+}
+
+const CONF1_RX_FLOW_EN: u32 = 1 << 22;
+
+/// FIFO depth in bytes
+pub const FIFO_SIZE: usize = 128;
+
+/// ESP32-C3/C6 UART
+pub struct EspUart {
+    base: usize,
+    chip: EspChip,
+    sclk_freq: u32,
+    config: UartConfig,
+}
+
+impl EspUart {
+    /// Create a UART whose function clock runs at `sclk_freq` Hz
+    pub const fn new(chip: EspChip, base: usize, sclk_freq: u32) -> Self {
+        Self {
+            base,
+            chip,
+            sclk_freq,
+            config: UartConfig {
+                baud_rate: BaudRate::Baud115200,
+                data_bits: DataBits::Eight,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+                flow_control: FlowControl::None,
+            },
+        }
+    }
+
+    /// Create UART `instance` after routing its pads through `pinmux`
+    pub fn claim<C: PinController>(
+        chip: EspChip,
+        base: usize,
+        sclk_freq: u32,
+        instance: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        pinmux.claim(Peripheral::Uart(instance))?;
+        Ok(Self::new(chip, base, sclk_freq))
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    /// Baud rate divisor in 1/16ths: sclk / baud
+    fn divisor(&self, baud_rate: u32) -> Result<u32, Error> {
+        if baud_rate == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        let div =
+            (u64::from(self.sclk_freq) * 16 + u64::from(baud_rate) / 2) / u64::from(baud_rate);
+        // 12-bit integer part, at least 1
+        if !(16..=0xFFF * 16 + 15).contains(&div) {
+            return Err(Error::InvalidParameter);
+        }
+        Ok(div as u32)
+    }
+
+    /// Latch the configuration into the UART core clock domain
+    fn update(&self) {
+        unsafe {
+            match self.chip {
+                EspChip::Esp32C3 => {
+                    let id = self.read_reg(regs::ID_C3);
+                    self.write_reg(regs::ID_C3, id | 1 << 31);
+                    while self.read_reg(regs::ID_C3) & 1 << 31 != 0 {
+                        core::hint::spin_loop();
+                    }
+                }
+                EspChip::Esp32C6 => {
+                    self.write_reg(regs::REG_UPDATE_C6, 1);
+                    while self.read_reg(regs::REG_UPDATE_C6) & 1 != 0 {
+                        core::hint::spin_loop();
+                    }
+                }
+            }
+        }
+    }
+
+    fn status(&self) -> u32 {
+        unsafe { self.read_reg(regs::STATUS) }
+    }
+}
+
+impl Uart for EspUart {
+    type Error = Error;
+
+    fn configure(&mut self, config: UartConfig) -> Result<(), Self::Error> {
+        let divisor = self.divisor(config.baud_rate.value())?;
+
+        let mut frame = match config.data_bits {
+            DataBits::Five => 0,
+            DataBits::Six => 1,
+            DataBits::Seven => 2,
+            DataBits::Eight => 3,
+            DataBits::Nine => return Err(Error::InvalidConfig),
+        } << conf0::BIT_NUM_SHIFT;
+        frame |= match config.stop_bits {
+            StopBits::One => 1,
+            StopBits::OnePointFive => 2,
+            StopBits::Two => 3,
+        } << conf0::STOP_BIT_NUM_SHIFT;
+        frame |= match config.parity {
+            Parity::None => 0,
+            Parity::Odd => conf0::PARITY_EN | conf0::PARITY_ODD,
+            Parity::Even => conf0::PARITY_EN,
+            Parity::Mark | Parity::Space => return Err(Error::InvalidConfig),
+        };
+        let rx_flow = match config.flow_control {
+            FlowControl::None => false,
+            FlowControl::Hardware => {
+                frame |= conf0::TX_FLOW_EN;
+                true
+            }
+            // XON/XOFF needs the flow control characters programmed too
+            FlowControl::Software => return Err(Error::InvalidConfig),
+        };
+
+        self.flush()?;
+        unsafe {
+            self.write_reg(regs::INT_ENA, 0);
+            self.write_reg(regs::INT_CLR, u32::MAX);
+            self.write_reg(regs::CLKDIV, (divisor & 0xF) << 20 | divisor >> 4);
+            // Both FIFO resets are level triggered
+            self.write_reg(regs::CONF0, frame | conf0::RXFIFO_RST | conf0::TXFIFO_RST);
+            self.write_reg(regs::CONF0, frame);
+            let conf1 = self.read_reg(regs::CONF1) & !CONF1_RX_FLOW_EN;
+            let flow = if rx_flow { CONF1_RX_FLOW_EN } else { 0 };
+            self.write_reg(regs::CONF1, conf1 | flow);
+        }
+        self.update();
+
+        self.config = config;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        for &byte in data {
+            while !self.is_tx_ready() {
+                core::hint::spin_loop();
+            }
+            unsafe {
+                self.write_reg(regs::FIFO, byte as u32);
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut count = 0;
+        for byte in buffer.iter_mut() {
+            if !self.is_rx_ready() {
+                break;
+            }
+            *byte = self.read_byte()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        while !self.is_rx_ready() {
+            core::hint::spin_loop();
+        }
+        // Errors are only reported per FIFO, not per byte
+        let errors = unsafe { self.read_reg(regs::INT_RAW) }
+            & (int::PARITY_ERR | int::FRM_ERR | int::RXFIFO_OVF);
+        let data = unsafe { self.read_reg(regs::FIFO) };
+        if errors != 0 {
+            unsafe { self.write_reg(regs::INT_CLR, errors) };
+        }
+        match errors {
+            0 => Ok(data as u8),
+            err if err & int::FRM_ERR != 0 => Err(Error::FramingError),
+            err if err & int::PARITY_ERR != 0 => Err(Error::ParityError),
+            _ => Err(Error::OverrunError),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // Wait for the FIFO to drain and the last frame to leave
+        while self.tx_free() < FIFO_SIZE
+            || (unsafe { self.read_reg(regs::FSM_STATUS) } >> 4) & 0xF != 0
+        {
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+
+    fn is_rx_ready(&self) -> bool {
+        self.rx_available() != 0
+    }
+
+    fn is_tx_ready(&self) -> bool {
+        self.tx_free() != 0
+    }
+
+    fn rx_available(&self) -> usize {
+        (self.status() & 0x3FF) as usize
+    }
+
+    fn tx_free(&self) -> usize {
+        FIFO_SIZE.saturating_sub(((self.status() >> 16) & 0x3FF) as usize)
+    }
+}
+
+impl core::fmt::Write for EspUart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let _ = Uart::write(self, s.as_bytes());
+        Ok(())
+    }
+}
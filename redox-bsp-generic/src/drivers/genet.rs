@@ -0,0 +1,715 @@
+//! Broadcom GENET v5 Ethernet MAC driver (Raspberry Pi 4)
+//!
+//! GENET keeps its DMA descriptors in register space and hands buffers
+//! back and forth with producer and consumer indices. This driver uses the
+//! default queue (ring 16) of each direction with one buffer per
+//! descriptor, and talks to the external RGMII PHY (a BCM54213PE at MDIO
+//! address 1 on the Pi 4) through the UniMAC MDIO controller.
+//!
+//! The packet buffers are handed to the MAC by address, so they have to be
+//! identity mapped and uncached.
+//!
+//! With the `driver-network` feature, [`GenetAdapter`] implements
+//! driver-network's `NetworkAdapter`, so the MAC can back a `network.*`
+//! scheme with BBRv3 pacing.
+
+use alloc::vec::Vec;
+
+use redox_hal::Error;
+
+use super::ethernet::{
+    EthernetDriver, EthernetStats, LinkStatus, MacAddress, MdioInterface, PhyDriver,
+};
+
+// Register blocks
+const SYS: usize = 0x0000;
+const EXT: usize = 0x0080;
+const INTRL2_0: usize = 0x0200;
+const RBUF: usize = 0x0300;
+const UMAC: usize = 0x0800;
+const RDMA: usize = 0x2000;
+const TDMA: usize = 0x4000;
+
+const SYS_REV_CTRL: usize = SYS;
+const SYS_PORT_CTRL: usize = SYS + 0x04;
+const SYS_RBUF_FLUSH_CTRL: usize = SYS + 0x08;
+const PORT_MODE_EXT_GPHY: u32 = 3;
+
+const EXT_RGMII_OOB_CTRL: usize = EXT + 0x0C;
+const RGMII_LINK: u32 = 1 << 4;
+const OOB_DISABLE: u32 = 1 << 5;
+const RGMII_MODE_EN: u32 = 1 << 6;
+const ID_MODE_DIS: u32 = 1 << 16;
+
+const INTRL2_CPU_CLEAR: usize = INTRL2_0 + 0x08;
+const INTRL2_CPU_MASK_SET: usize = INTRL2_0 + 0x10;
+const INTRL2_CPU_MASK_CLEAR: usize = INTRL2_0 + 0x14;
+const IRQ_RXDMA_DONE: u32 = 1 << 13;
+const IRQ_TXDMA_DONE: u32 = 1 << 16;
+
+const RBUF_CTRL: usize = RBUF;
+const RBUF_TBUF_SIZE_CTRL: usize = RBUF + 0xB4;
+const RBUF_ALIGN_2B: u32 = 1 << 1;
+
+const UMAC_CMD: usize = UMAC + 0x008;
+const UMAC_MAC0: usize = UMAC + 0x00C;
+const UMAC_MAC1: usize = UMAC + 0x010;
+const UMAC_MAX_FRAME_LEN: usize = UMAC + 0x014;
+const UMAC_TX_FLUSH: usize = UMAC + 0x334;
+const UMAC_MIB_CTRL: usize = UMAC + 0x580;
+const MDIO_CMD: usize = UMAC + 0x614;
+
+const CMD_TX_EN: u32 = 1 << 0;
+const CMD_RX_EN: u32 = 1 << 1;
+const CMD_SPEED_SHIFT: u32 = 2;
+const CMD_SPEED_MASK: u32 = 3 << CMD_SPEED_SHIFT;
+const CMD_PROMISC: u32 = 1 << 4;
+const CMD_SW_RESET: u32 = 1 << 13;
+const CMD_LCL_LOOP_EN: u32 = 1 << 15;
+const MIB_RESET_ALL: u32 = 0x7;
+
+const MDIO_START_BUSY: u32 = 1 << 29;
+const MDIO_READ_FAIL: u32 = 1 << 28;
+const MDIO_RD: u32 = 2 << 26;
+const MDIO_WR: u32 = 1 << 26;
+const MDIO_PMD_SHIFT: u32 = 21;
+const MDIO_REG_SHIFT: u32 = 16;
+
+// Descriptors: 256 per direction, 3 words each, at the start of the block
+const TOTAL_DESC: usize = 256;
+const DESC_SIZE: usize = 12;
+const DESC_LENGTH_STATUS: usize = 0x00;
+const DESC_ADDRESS_LO: usize = 0x04;
+const DESC_ADDRESS_HI: usize = 0x08;
+
+const DMA_BUFLENGTH_SHIFT: u32 = 16;
+const DMA_BUFLENGTH_MASK: u32 = 0xFFF;
+const DMA_OWN: u32 = 0x8000;
+const DMA_EOP: u32 = 0x4000;
+const DMA_SOP: u32 = 0x2000;
+const DMA_TX_APPEND_CRC: u32 = 0x0040;
+const DMA_TX_QTAG: u32 = 0x3F << 7;
+const DMA_RX_ERRORS: u32 = 0x1F;
+const DMA_RX_CRC_ERROR: u32 = 0x02;
+const DMA_RX_OV: u32 = 0x01;
+
+// Ring registers follow the descriptors, 0x40 per ring; ring 16 is the
+// default queue, and the DMA control registers follow the 17 rings
+const DEFAULT_Q: usize = 16;
+const RING_SIZE: usize = 0x40;
+const RING_REGS: usize = TOTAL_DESC * DESC_SIZE + DEFAULT_Q * RING_SIZE;
+const DMA_REGS: usize = TOTAL_DESC * DESC_SIZE + (DEFAULT_Q + 1) * RING_SIZE;
+
+const TDMA_READ_PTR: usize = 0x00;
+const RDMA_WRITE_PTR: usize = 0x00;
+const RDMA_PROD_INDEX: usize = 0x08;
+const TDMA_CONS_INDEX: usize = 0x08;
+const RDMA_CONS_INDEX: usize = 0x0C;
+const TDMA_PROD_INDEX: usize = 0x0C;
+const DMA_RING_BUF_SIZE: usize = 0x10;
+const DMA_START_ADDR: usize = 0x14;
+const DMA_END_ADDR: usize = 0x1C;
+const DMA_MBUF_DONE_THRESH: usize = 0x24;
+const TDMA_FLOW_PERIOD: usize = 0x28;
+const RDMA_XON_XOFF_THRESH: usize = 0x28;
+const TDMA_WRITE_PTR: usize = 0x2C;
+const RDMA_READ_PTR: usize = 0x2C;
+
+const DMA_RING_CFG: usize = 0x00;
+const DMA_CTRL: usize = 0x04;
+const DMA_SCB_BURST_SIZE: usize = 0x0C;
+const DMA_EN: u32 = 1 << 0;
+const DMA_RING_BUF_EN_SHIFT: usize = 1;
+const DMA_MAX_BURST_LENGTH: u32 = 0x08;
+const DMA_RING_SIZE_SHIFT: u32 = 16;
+const DMA_INDEX_MASK: u32 = 0xFFFF;
+
+/// Descriptors and buffers per direction
+pub const RING_DESCS: usize = 64;
+/// Size of each packet buffer
+pub const BUFFER_SIZE: usize = 2048;
+/// Largest frame, with VLAN tag and FCS
+const MAX_FRAME_LEN: u32 = 1536;
+/// Received frames start after two bytes of padding, aligning the IP header
+const RX_BUF_OFFSET: usize = 2;
+
+// Polls before giving up on MDIO and DMA
+const POLL_LIMIT: u32 = 100_000;
+
+// PHY 1000BASE-T control and status registers
+const REG_GBCR: u8 = 9;
+const REG_GBSR: u8 = 10;
+const GBCR_1000FULL: u16 = 1 << 9;
+const GBSR_LP_1000FULL: u16 = 1 << 11;
+
+/// DMA buffers of a [`Genet`] MAC
+#[repr(C, align(64))]
+pub struct GenetBuffers {
+    rx: [[u8; BUFFER_SIZE]; RING_DESCS],
+    tx: [[u8; BUFFER_SIZE]; RING_DESCS],
+}
+
+impl GenetBuffers {
+    /// Zeroed buffers, for a `static`
+    pub const fn new() -> Self {
+        Self {
+            rx: [[0; BUFFER_SIZE]; RING_DESCS],
+            tx: [[0; BUFFER_SIZE]; RING_DESCS],
+        }
+    }
+}
+
+impl Default for GenetBuffers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn spin(iterations: u32) {
+    for _ in 0..iterations {
+        core::hint::spin_loop();
+    }
+}
+
+/// UniMAC MDIO controller of a GENET MAC
+#[derive(Debug, Clone, Copy)]
+pub struct GenetMdio {
+    base: usize,
+}
+
+impl GenetMdio {
+    /// Create an MDIO controller for the GENET at `base`
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    fn command(&self, command: u32) -> u32 {
+        let reg = (self.base + MDIO_CMD) as *mut u32;
+        unsafe {
+            core::ptr::write_volatile(reg, command);
+            core::ptr::write_volatile(reg, command | MDIO_START_BUSY);
+            for _ in 0..POLL_LIMIT {
+                let value = core::ptr::read_volatile(reg);
+                if value & MDIO_START_BUSY == 0 {
+                    return value;
+                }
+                core::hint::spin_loop();
+            }
+        }
+        MDIO_READ_FAIL
+    }
+}
+
+impl MdioInterface for GenetMdio {
+    fn read(&self, phy_addr: u8, reg_addr: u8) -> u16 {
+        let command = MDIO_RD
+            | u32::from(phy_addr & 0x1F) << MDIO_PMD_SHIFT
+            | u32::from(reg_addr & 0x1F) << MDIO_REG_SHIFT;
+        let value = self.command(command);
+        // Failed reads look like an absent PHY
+        if value & MDIO_READ_FAIL != 0 {
+            return 0xFFFF;
+        }
+        value as u16
+    }
+
+    fn write(&self, phy_addr: u8, reg_addr: u8, value: u16) {
+        let command = MDIO_WR
+            | u32::from(phy_addr & 0x1F) << MDIO_PMD_SHIFT
+            | u32::from(reg_addr & 0x1F) << MDIO_REG_SHIFT
+            | u32::from(value);
+        self.command(command);
+    }
+}
+
+/// GENET v5 Ethernet MAC
+pub struct Genet {
+    base: usize,
+    buffers: &'static mut GenetBuffers,
+    phy: PhyDriver<GenetMdio>,
+    phy_addr: u8,
+    mac: MacAddress,
+    link: LinkStatus,
+    rx_cons: u32,
+    tx_prod: u32,
+    tx_cons: u32,
+    stats: EthernetStats,
+}
+
+impl Genet {
+    /// Create a driver for the GENET at `base` with the PHY at MDIO
+    /// address `phy_addr`
+    ///
+    /// `mac` is usually the address the firmware reports through the
+    /// mailbox. Nothing is touched until [`EthernetDriver::init`].
+    pub fn new(
+        base: usize,
+        phy_addr: u8,
+        mac: MacAddress,
+        buffers: &'static mut GenetBuffers,
+    ) -> Self {
+        Self {
+            base,
+            buffers,
+            phy: PhyDriver::new(GenetMdio::new(base), phy_addr),
+            phy_addr,
+            mac,
+            link: LinkStatus::Down,
+            rx_cons: 0,
+            tx_prod: 0,
+            tx_cons: 0,
+            stats: EthernetStats::default(),
+        }
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    unsafe fn modify_reg(&self, offset: usize, clear: u32, set: u32) {
+        let value = self.read_reg(offset) & !clear;
+        self.write_reg(offset, value | set);
+    }
+
+    /// GENET major version, 6 for the v5 block of the BCM2711
+    pub fn version(&self) -> u32 {
+        unsafe { (self.read_reg(SYS_REV_CTRL) >> 24) & 0xF }
+    }
+
+    /// Enable or disable promiscuous mode
+    pub fn set_promiscuous(&mut self, enabled: bool) {
+        let set = if enabled { CMD_PROMISC } else { 0 };
+        unsafe { self.modify_reg(UMAC_CMD, CMD_PROMISC, set) };
+    }
+
+    /// Check if the MAC receives all frames
+    pub fn promiscuous(&self) -> bool {
+        unsafe { self.read_reg(UMAC_CMD) & CMD_PROMISC != 0 }
+    }
+
+    /// Number of received frames waiting
+    pub fn rx_pending(&self) -> usize {
+        let prod = unsafe { self.read_reg(RDMA + RING_REGS + RDMA_PROD_INDEX) };
+        (prod.wrapping_sub(self.rx_cons) & DMA_INDEX_MASK) as usize
+    }
+
+    /// Number of frames the MAC has not sent yet
+    pub fn tx_pending(&mut self) -> usize {
+        self.reclaim_tx();
+        (self.tx_prod.wrapping_sub(self.tx_cons) & DMA_INDEX_MASK) as usize
+    }
+
+    /// Copy the next received frame into `buffer`
+    ///
+    /// Returns the frame length, which is larger than `buffer` for a
+    /// truncated frame.
+    pub fn receive_into(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Error> {
+        loop {
+            if self.rx_pending() == 0 {
+                return Ok(None);
+            }
+            let index = self.rx_cons as usize % RING_DESCS;
+            let desc = RDMA + index * DESC_SIZE;
+            let status = unsafe { self.read_reg(desc + DESC_LENGTH_STATUS) };
+            let length = ((status >> DMA_BUFLENGTH_SHIFT) & DMA_BUFLENGTH_MASK) as usize;
+
+            let frame = if status & DMA_RX_ERRORS != 0 || length < RX_BUF_OFFSET {
+                self.stats.rx_errors += 1;
+                if status & DMA_RX_CRC_ERROR != 0 {
+                    self.stats.crc_errors += 1;
+                }
+                if status & DMA_RX_OV != 0 {
+                    self.stats.rx_dropped += 1;
+                }
+                None
+            } else {
+                let data = &self.buffers.rx[index][RX_BUF_OFFSET..length.min(BUFFER_SIZE)];
+                let copied = data.len().min(buffer.len());
+                buffer[..copied].copy_from_slice(&data[..copied]);
+                self.stats.rx_packets += 1;
+                self.stats.rx_bytes += data.len() as u64;
+                Some(data.len())
+            };
+
+            // Hand the buffer back to the MAC
+            self.rx_cons = (self.rx_cons + 1) & DMA_INDEX_MASK;
+            unsafe { self.write_reg(RDMA + RING_REGS + RDMA_CONS_INDEX, self.rx_cons) };
+
+            if frame.is_some() {
+                return Ok(frame);
+            }
+        }
+    }
+
+    fn reclaim_tx(&mut self) {
+        self.tx_cons =
+            unsafe { self.read_reg(TDMA + RING_REGS + TDMA_CONS_INDEX) } & DMA_INDEX_MASK;
+    }
+
+    fn reset(&mut self) {
+        unsafe {
+            self.modify_reg(SYS_RBUF_FLUSH_CTRL, 0, 1 << 1);
+            spin(1000);
+            self.modify_reg(SYS_RBUF_FLUSH_CTRL, 1 << 1, 0);
+            spin(1000);
+            self.write_reg(SYS_RBUF_FLUSH_CTRL, 0);
+            spin(1000);
+
+            self.write_reg(UMAC_CMD, 0);
+            self.write_reg(UMAC_CMD, CMD_SW_RESET | CMD_LCL_LOOP_EN);
+            spin(200);
+            self.write_reg(UMAC_CMD, 0);
+
+            self.write_reg(UMAC_MIB_CTRL, MIB_RESET_ALL);
+            self.write_reg(UMAC_MIB_CTRL, 0);
+            self.write_reg(UMAC_MAX_FRAME_LEN, MAX_FRAME_LEN);
+
+            self.modify_reg(RBUF_CTRL, 0, RBUF_ALIGN_2B);
+            self.write_reg(RBUF_TBUF_SIZE_CTRL, 1);
+
+            // Interrupts are masked until enable_interrupts
+            self.write_reg(INTRL2_CPU_MASK_SET, u32::MAX);
+            self.write_reg(INTRL2_CPU_CLEAR, u32::MAX);
+        }
+    }
+
+    fn stop_dma(&mut self) -> Result<(), Error> {
+        unsafe {
+            self.modify_reg(TDMA + DMA_REGS + DMA_CTRL, DMA_EN, 0);
+            self.modify_reg(RDMA + DMA_REGS + DMA_CTRL, DMA_EN, 0);
+            self.write_reg(UMAC_TX_FLUSH, 1);
+            spin(1000);
+            self.write_reg(UMAC_TX_FLUSH, 0);
+        }
+        Ok(())
+    }
+
+    fn init_rings(&mut self) {
+        let ring_end = (RING_DESCS * DESC_SIZE / 4 - 1) as u32;
+        let buf_size = (RING_DESCS as u32) << DMA_RING_SIZE_SHIFT | BUFFER_SIZE as u32;
+        let rx_ring = RDMA + RING_REGS;
+        let tx_ring = TDMA + RING_REGS;
+
+        unsafe {
+            self.write_reg(RDMA + DMA_REGS + DMA_SCB_BURST_SIZE, DMA_MAX_BURST_LENGTH);
+            self.write_reg(rx_ring + DMA_START_ADDR, 0);
+            self.write_reg(rx_ring + RDMA_READ_PTR, 0);
+            self.write_reg(rx_ring + RDMA_WRITE_PTR, 0);
+            self.write_reg(rx_ring + DMA_END_ADDR, ring_end);
+            self.write_reg(rx_ring + RDMA_PROD_INDEX, 0);
+            self.write_reg(rx_ring + RDMA_CONS_INDEX, 0);
+            self.write_reg(rx_ring + DMA_RING_BUF_SIZE, buf_size);
+            // Flow control thresholds: XOFF at 5, XON at a sixteenth
+            self.write_reg(
+                rx_ring + RDMA_XON_XOFF_THRESH,
+                5 << 16 | (RING_DESCS >> 4) as u32,
+            );
+            self.write_reg(RDMA + DMA_REGS + DMA_RING_CFG, 1 << DEFAULT_Q);
+
+            self.write_reg(TDMA + DMA_REGS + DMA_SCB_BURST_SIZE, DMA_MAX_BURST_LENGTH);
+            self.write_reg(tx_ring + DMA_START_ADDR, 0);
+            self.write_reg(tx_ring + TDMA_READ_PTR, 0);
+            self.write_reg(tx_ring + TDMA_WRITE_PTR, 0);
+            self.write_reg(tx_ring + DMA_END_ADDR, ring_end);
+            self.write_reg(tx_ring + TDMA_PROD_INDEX, 0);
+            self.write_reg(tx_ring + TDMA_CONS_INDEX, 0);
+            self.write_reg(tx_ring + DMA_MBUF_DONE_THRESH, 1);
+            self.write_reg(tx_ring + TDMA_FLOW_PERIOD, 0);
+            self.write_reg(tx_ring + DMA_RING_BUF_SIZE, buf_size);
+            self.write_reg(TDMA + DMA_REGS + DMA_RING_CFG, 1 << DEFAULT_Q);
+
+            for index in 0..RING_DESCS {
+                let address = self.buffers.rx[index].as_ptr() as u64;
+                let desc = RDMA + index * DESC_SIZE;
+                self.write_reg(desc + DESC_ADDRESS_LO, address as u32);
+                self.write_reg(desc + DESC_ADDRESS_HI, (address >> 32) as u32);
+                self.write_reg(
+                    desc + DESC_LENGTH_STATUS,
+                    (BUFFER_SIZE as u32) << DMA_BUFLENGTH_SHIFT | DMA_OWN,
+                );
+            }
+        }
+
+        self.rx_cons = 0;
+        self.tx_prod = 0;
+        self.tx_cons = 0;
+    }
+
+    /// Read the link from the PHY and program the MAC speed to match
+    pub fn update_link(&mut self) -> LinkStatus {
+        let mut link = self.phy.link_status();
+        if link.is_up() {
+            let mdio = GenetMdio::new(self.base);
+            let local = mdio.read(self.phy_addr, REG_GBCR);
+            let partner = mdio.read(self.phy_addr, REG_GBSR);
+            if local & GBCR_1000FULL != 0 && partner & GBSR_LP_1000FULL != 0 {
+                link = LinkStatus::Up1000FullDuplex;
+            }
+        }
+
+        if link != self.link {
+            let speed = match link.speed_mbps() {
+                1000 => 2,
+                100 => 1,
+                _ => 0,
+            };
+            let oob = if link.is_up() { RGMII_LINK } else { 0 };
+            unsafe {
+                self.modify_reg(EXT_RGMII_OOB_CTRL, RGMII_LINK, oob);
+                self.modify_reg(UMAC_CMD, CMD_SPEED_MASK, speed << CMD_SPEED_SHIFT);
+            }
+            self.link = link;
+        }
+        link
+    }
+}
+
+impl EthernetDriver for Genet {
+    type Error = Error;
+
+    fn init(&mut self) -> Result<(), Self::Error> {
+        // GENET v5 reports major version 6
+        if self.version() != 6 {
+            return Err(Error::NotAvailable);
+        }
+
+        unsafe { self.write_reg(SYS_PORT_CTRL, PORT_MODE_EXT_GPHY) };
+        self.reset();
+        self.set_mac_address(self.mac)?;
+        self.stop_dma()?;
+        self.init_rings();
+
+        // RGMII with the PHY adding the clock delays
+        unsafe {
+            self.modify_reg(EXT_RGMII_OOB_CTRL, OOB_DISABLE, RGMII_MODE_EN | ID_MODE_DIS);
+        }
+
+        self.phy.reset();
+        self.phy.start_autoneg();
+        self.link = LinkStatus::Down;
+        self.update_link();
+
+        unsafe {
+            let ring_enable = 1 << (DEFAULT_Q + DMA_RING_BUF_EN_SHIFT);
+            self.modify_reg(TDMA + DMA_REGS + DMA_CTRL, 0, ring_enable | DMA_EN);
+            self.modify_reg(RDMA + DMA_REGS + DMA_CTRL, 0, ring_enable | DMA_EN);
+            self.modify_reg(UMAC_CMD, 0, CMD_TX_EN | CMD_RX_EN);
+        }
+        Ok(())
+    }
+
+    fn mac_address(&self) -> MacAddress {
+        self.mac
+    }
+
+    fn set_mac_address(&mut self, mac: MacAddress) -> Result<(), Self::Error> {
+        let [a, b, c, d, e, f] = mac.0;
+        unsafe {
+            self.write_reg(UMAC_MAC0, u32::from_be_bytes([a, b, c, d]));
+            self.write_reg(UMAC_MAC1, u32::from_be_bytes([0, 0, e, f]));
+        }
+        self.mac = mac;
+        Ok(())
+    }
+
+    fn link_status(&self) -> LinkStatus {
+        self.link
+    }
+
+    fn transmit(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        if data.len() > MAX_FRAME_LEN as usize {
+            return Err(Error::DataTooLarge);
+        }
+        if self.tx_pending() >= RING_DESCS {
+            return Err(Error::Busy);
+        }
+
+        let index = self.tx_prod as usize % RING_DESCS;
+        self.buffers.tx[index][..data.len()].copy_from_slice(data);
+        let address = self.buffers.tx[index].as_ptr() as u64;
+        let status = (data.len() as u32) << DMA_BUFLENGTH_SHIFT
+            | DMA_TX_QTAG
+            | DMA_TX_APPEND_CRC
+            | DMA_SOP
+            | DMA_EOP;
+
+        let desc = TDMA + index * DESC_SIZE;
+        unsafe {
+            self.write_reg(desc + DESC_ADDRESS_LO, address as u32);
+            self.write_reg(desc + DESC_ADDRESS_HI, (address >> 32) as u32);
+            self.write_reg(desc + DESC_LENGTH_STATUS, status);
+        }
+
+        self.tx_prod = (self.tx_prod + 1) & DMA_INDEX_MASK;
+        unsafe { self.write_reg(TDMA + RING_REGS + TDMA_PROD_INDEX, self.tx_prod) };
+
+        self.stats.tx_packets += 1;
+        self.stats.tx_bytes += data.len() as u64;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, Self::Error> {
+        let mut buffer = alloc::vec![0; BUFFER_SIZE];
+        Ok(self.receive_into(&mut buffer)?.map(|len| {
+            buffer.truncate(len);
+            buffer
+        }))
+    }
+
+    fn enable_interrupts(&mut self) {
+        unsafe {
+            self.write_reg(INTRL2_CPU_CLEAR, IRQ_RXDMA_DONE | IRQ_TXDMA_DONE);
+            self.write_reg(INTRL2_CPU_MASK_CLEAR, IRQ_RXDMA_DONE | IRQ_TXDMA_DONE);
+        }
+    }
+
+    fn disable_interrupts(&mut self) {
+        unsafe { self.write_reg(INTRL2_CPU_MASK_SET, IRQ_RXDMA_DONE | IRQ_TXDMA_DONE) };
+    }
+
+    fn handle_interrupt(&mut self) {
+        unsafe { self.write_reg(INTRL2_CPU_CLEAR, IRQ_RXDMA_DONE | IRQ_TXDMA_DONE) };
+        self.reclaim_tx();
+    }
+
+    fn statistics(&self) -> EthernetStats {
+        self.stats.clone()
+    }
+}
+
+#[cfg(feature = "driver-network")]
+pub use adapter::GenetAdapter;
+
+#[cfg(feature = "driver-network")]
+mod adapter {
+    use driver_network::{Ipv6Scope, NetworkAdapter};
+    use syscall::error::{Error, Result, EAGAIN, EINVAL, EIO, EOPNOTSUPP};
+
+    use super::{Genet, MAX_FRAME_LEN};
+    use crate::drivers::ethernet::EthernetDriver;
+
+    /// [`Genet`] as a driver-network adapter
+    pub struct GenetAdapter {
+        mac: Genet,
+        ipv4: [u8; 4],
+        ipv6_global: [u8; 16],
+        ipv6_unique_local: [u8; 16],
+    }
+
+    impl GenetAdapter {
+        /// Wrap an initialized MAC
+        pub fn new(mac: Genet) -> Self {
+            Self {
+                mac,
+                ipv4: [0; 4],
+                ipv6_global: [0; 16],
+                ipv6_unique_local: [0; 16],
+            }
+        }
+
+        /// Access the MAC
+        pub fn mac(&mut self) -> &mut Genet {
+            &mut self.mac
+        }
+    }
+
+    impl NetworkAdapter for GenetAdapter {
+        fn mac_address(&mut self) -> [u8; 6] {
+            self.mac.mac_address().0
+        }
+
+        fn ipv4_address(&mut self) -> [u8; 4] {
+            self.ipv4
+        }
+
+        fn ipv6_address(&mut self) -> [u8; 16] {
+            // Link-local address from the MAC (EUI-64)
+            let mac = self.mac.mac_address().0;
+            [
+                0xfe,
+                0x80,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                mac[0] ^ 0x02,
+                mac[1],
+                mac[2],
+                0xff,
+                0xfe,
+                mac[3],
+                mac[4],
+                mac[5],
+            ]
+        }
+
+        fn ipv6_address_global(&mut self) -> [u8; 16] {
+            self.ipv6_global
+        }
+
+        fn ipv6_address_unique_local(&mut self) -> [u8; 16] {
+            self.ipv6_unique_local
+        }
+
+        fn available_for_read(&mut self) -> usize {
+            self.mac.rx_pending()
+        }
+
+        fn read_packet(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+            match self.mac.receive_into(buf) {
+                Ok(Some(len)) => Ok(Some(len.min(buf.len()))),
+                Ok(None) => Ok(None),
+                Err(_) => Err(Error::new(EIO)),
+            }
+        }
+
+        fn write_packet(&mut self, buf: &[u8], _pacing_rate: u64) -> Result<usize> {
+            match self.mac.transmit(buf) {
+                Ok(()) => Ok(buf.len()),
+                Err(redox_hal::Error::Busy) => Err(Error::new(EAGAIN)),
+                Err(redox_hal::Error::DataTooLarge) => Err(Error::new(EINVAL)),
+                Err(_) => Err(Error::new(EIO)),
+            }
+        }
+
+        fn in_flight(&self) -> u64 {
+            // Frames are done once the MAC has sent them
+            0
+        }
+
+        fn set_ipv4_address(&mut self, address: [u8; 4]) -> Result<()> {
+            self.ipv4 = address;
+            Ok(())
+        }
+
+        fn set_ipv6_address(&mut self, scope: Ipv6Scope, address: [u8; 16]) -> Result<()> {
+            match scope {
+                // The link-local address is always derived from the MAC address
+                Ipv6Scope::LinkLocal => return Err(Error::new(EOPNOTSUPP)),
+                Ipv6Scope::Global => self.ipv6_global = address,
+                Ipv6Scope::UniqueLocal => self.ipv6_unique_local = address,
+            }
+            Ok(())
+        }
+
+        fn mtu(&mut self) -> u32 {
+            // Frame limit less Ethernet header, VLAN tag and FCS
+            MAX_FRAME_LEN - 22
+        }
+
+        fn promiscuous(&mut self) -> bool {
+            self.mac.promiscuous()
+        }
+
+        fn set_promiscuous(&mut self, enabled: bool) -> Result<()> {
+            self.mac.set_promiscuous(enabled);
+            Ok(())
+        }
+    }
+}
@@ -1,13 +1,25 @@
 //! Generic GPIO driver
 
-use redox_hal::gpio::{GpioPin, Level, PinMode, Pull};
+use redox_hal::gpio::events::PinEvent;
+use redox_hal::gpio::{Edge, GpioPin, InterruptPin, Level, PinMode, Pull, Trigger};
+use redox_hal::pinmux::{Peripheral, PinController, Pinmux, PinmuxError};
 use redox_hal::Error;
 
+// AM335x style interrupt registers
+const IRQSTATUS_0: usize = 0x2C;
+const IRQSTATUS_SET_0: usize = 0x34;
+const IRQSTATUS_CLR_0: usize = 0x3C;
+const LEVELDETECT0: usize = 0x140;
+const LEVELDETECT1: usize = 0x144;
+const RISINGDETECT: usize = 0x148;
+const FALLINGDETECT: usize = 0x14C;
+
 /// Generic GPIO pin implementation
 pub struct GenericGpioPin {
     base: usize,
     pin: u8,
     mode: PinMode,
+    handler: Option<fn(PinEvent)>,
 }
 
 impl GenericGpioPin {
@@ -17,7 +29,19 @@ impl GenericGpioPin {
             base,
             pin,
             mode: PinMode::Input,
+            handler: None,
+        }
+    }
+
+    /// Service a pending interrupt of this pin and run its handler
+    ///
+    /// Called from the interrupt handler of the GPIO bank.
+    pub fn handle_interrupt(&mut self, timestamp: u64) -> Option<PinEvent> {
+        let event = self.take_interrupt(timestamp)?;
+        if let Some(handler) = self.handler {
+            handler(event);
         }
+        Some(event)
     }
 
     /// Get register offset for this pin
@@ -39,6 +63,16 @@ impl GenericGpioPin {
     unsafe fn write_reg(&self, offset: usize, value: u32) {
         core::ptr::write_volatile((self.base + offset) as *mut u32, value);
     }
+
+    /// Set or clear this pin's bit in a shared register
+    unsafe fn modify_reg(&self, offset: usize, set: bool) {
+        let value = self.read_reg(offset);
+        if set {
+            self.write_reg(offset, value | self.bit_mask());
+        } else {
+            self.write_reg(offset, value & !self.bit_mask());
+        }
+    }
 }
 
 impl GpioPin for GenericGpioPin {
@@ -92,6 +126,51 @@ impl GpioPin for GenericGpioPin {
     }
 }
 
+impl InterruptPin for GenericGpioPin {
+    fn set_trigger(&mut self, trigger: Trigger) -> Result<(), Self::Error> {
+        let (rising, falling, low, high) = match trigger {
+            Trigger::Edge(Edge::Rising) => (true, false, false, false),
+            Trigger::Edge(Edge::Falling) => (false, true, false, false),
+            Trigger::Edge(Edge::Both) => (true, true, false, false),
+            Trigger::Level(Level::Low) => (false, false, true, false),
+            Trigger::Level(Level::High) => (false, false, false, true),
+        };
+        unsafe {
+            self.write_reg(IRQSTATUS_CLR_0, self.bit_mask());
+            self.modify_reg(RISINGDETECT, rising);
+            self.modify_reg(FALLINGDETECT, falling);
+            self.modify_reg(LEVELDETECT0, low);
+            self.modify_reg(LEVELDETECT1, high);
+            // Drop an edge latched while reconfiguring
+            self.write_reg(IRQSTATUS_0, self.bit_mask());
+            self.write_reg(IRQSTATUS_SET_0, self.bit_mask());
+        }
+        Ok(())
+    }
+
+    fn disable_interrupt(&mut self) -> Result<(), Self::Error> {
+        unsafe {
+            self.write_reg(IRQSTATUS_CLR_0, self.bit_mask());
+        }
+        Ok(())
+    }
+
+    fn is_interrupt_pending(&self) -> bool {
+        unsafe { self.read_reg(IRQSTATUS_0) & self.bit_mask() != 0 }
+    }
+
+    fn clear_interrupt(&mut self) {
+        // Write one to clear
+        unsafe {
+            self.write_reg(IRQSTATUS_0, self.bit_mask());
+        }
+    }
+
+    fn set_interrupt_handler(&mut self, handler: fn(PinEvent)) {
+        self.handler = Some(handler);
+    }
+}
+
 /// GPIO port
 pub struct GpioPort {
     base: usize,
@@ -109,6 +188,17 @@ impl GpioPort {
         GenericGpioPin::new(self.base, pin_number)
     }
 
+    /// Get a pin from this port after routing its pad through `pinmux`
+    pub fn claim_pin<C: PinController>(
+        &self,
+        pin_number: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<GenericGpioPin, PinmuxError<C::Error>> {
+        let gpio = u16::from(self.port_number) * 32 + u16::from(pin_number);
+        pinmux.claim(Peripheral::Gpio(gpio))?;
+        Ok(self.pin(pin_number))
+    }
+
     /// Read all pins
     pub fn read_all(&self) -> u32 {
         unsafe { core::ptr::read_volatile((self.base + 0x138) as *const u32) }
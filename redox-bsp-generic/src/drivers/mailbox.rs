@@ -0,0 +1,276 @@
+//! VideoCore mailbox property interface driver
+//!
+//! The Raspberry Pi firmware owns clocks, power domains and board
+//! identification; the ARM asks for them through property tags sent over
+//! mailbox channel 8. A request is a 16-byte aligned buffer of tags which
+//! the firmware overwrites with the responses.
+//!
+//! The buffer has to be at an address the VideoCore sees at the same
+//! address, and either uncached or cleaned and invalidated around
+//! [`Mailbox::call`].
+
+use core::sync::atomic::{fence, Ordering};
+
+use redox_hal::Error;
+
+/// Register offsets from the mailbox base
+mod regs {
+    pub const READ: usize = 0x00; // Mailbox 0 read (VideoCore to ARM)
+    pub const STATUS0: usize = 0x18; // Mailbox 0 status
+    pub const WRITE: usize = 0x20; // Mailbox 1 write (ARM to VideoCore)
+    pub const STATUS1: usize = 0x38; // Mailbox 1 status
+}
+
+const STATUS_FULL: u32 = 1 << 31;
+const STATUS_EMPTY: u32 = 1 << 30;
+
+/// Property tags channel
+const CHANNEL_PROPERTY: u32 = 8;
+
+const REQUEST: u32 = 0;
+const RESPONSE_SUCCESS: u32 = 0x8000_0000;
+const TAG_RESPONSE: u32 = 1 << 31;
+
+/// Largest tag value buffer, in words
+const MAX_VALUE_WORDS: usize = 8;
+
+// Status polls before giving up
+const POLL_LIMIT: u32 = 1_000_000;
+
+/// Property tags
+pub mod tag {
+    pub const GET_FIRMWARE_REVISION: u32 = 0x0000_0001;
+    pub const GET_BOARD_MODEL: u32 = 0x0001_0001;
+    pub const GET_BOARD_REVISION: u32 = 0x0001_0002;
+    pub const GET_MAC_ADDRESS: u32 = 0x0001_0003;
+    pub const GET_BOARD_SERIAL: u32 = 0x0001_0004;
+    pub const GET_ARM_MEMORY: u32 = 0x0001_0005;
+    pub const GET_VC_MEMORY: u32 = 0x0001_0006;
+    pub const GET_POWER_STATE: u32 = 0x0002_0001;
+    pub const SET_POWER_STATE: u32 = 0x0002_8001;
+    pub const GET_CLOCK_RATE: u32 = 0x0003_0002;
+    pub const GET_MAX_CLOCK_RATE: u32 = 0x0003_0004;
+    pub const GET_TEMPERATURE: u32 = 0x0003_0006;
+    pub const SET_CLOCK_RATE: u32 = 0x0003_8002;
+}
+
+/// Firmware clock ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FirmwareClock {
+    Emmc = 1,
+    Uart = 2,
+    Arm = 3,
+    Core = 4,
+    V3d = 5,
+    H264 = 6,
+    Isp = 7,
+    Sdram = 8,
+    Pixel = 9,
+    Pwm = 10,
+    Hevc = 11,
+    Emmc2 = 12,
+}
+
+/// Firmware power domain ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PowerDomain {
+    SdCard = 0,
+    Uart0 = 1,
+    Uart1 = 2,
+    UsbHcd = 3,
+    I2c0 = 4,
+    I2c1 = 5,
+    I2c2 = 6,
+    Spi = 7,
+    Ccp2tx = 8,
+}
+
+/// Property request buffer: size, code, one tag and the end tag
+#[repr(C, align(16))]
+struct PropertyBuffer {
+    words: [u32; 6 + MAX_VALUE_WORDS],
+}
+
+/// VideoCore mailbox
+pub struct Mailbox {
+    base: usize,
+}
+
+impl Mailbox {
+    /// Create a mailbox driver for the registers at `base`
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    fn wait(&self, offset: usize, flag: u32) -> Result<(), Error> {
+        for _ in 0..POLL_LIMIT {
+            if unsafe { self.read_reg(offset) } & flag == 0 {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Send the property buffer at `buffer` and wait for the response
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must point to a 16-byte aligned property buffer the
+    /// VideoCore can access at the same address, which stays valid until
+    /// this returns.
+    pub unsafe fn call(&mut self, buffer: *mut u32) -> Result<(), Error> {
+        let address = buffer as usize as u32;
+        if address & 0xF != 0 || buffer as usize > u32::MAX as usize {
+            return Err(Error::InvalidParameter);
+        }
+
+        // The buffer must be written out before the firmware looks at it
+        fence(Ordering::SeqCst);
+        self.wait(regs::STATUS1, STATUS_FULL)?;
+        self.write_reg(regs::WRITE, address | CHANNEL_PROPERTY);
+
+        loop {
+            self.wait(regs::STATUS0, STATUS_EMPTY)?;
+            // Replies on other channels aren't ours
+            if self.read_reg(regs::READ) == address | CHANNEL_PROPERTY {
+                break;
+            }
+        }
+        fence(Ordering::SeqCst);
+
+        if core::ptr::read_volatile(buffer.add(1)) != RESPONSE_SUCCESS {
+            return Err(Error::HardwareFailure);
+        }
+        Ok(())
+    }
+
+    /// Run a single property tag
+    ///
+    /// `values` holds the request and is overwritten with the response;
+    /// it has to be large enough for either. Returns the response length in
+    /// bytes. The request is built on the stack, so the stack has to meet
+    /// the requirements of [`Mailbox::call`].
+    pub fn property(&mut self, tag: u32, values: &mut [u32]) -> Result<usize, Error> {
+        if values.len() > MAX_VALUE_WORDS {
+            return Err(Error::DataTooLarge);
+        }
+        let words = 6 + values.len();
+        let mut buffer = PropertyBuffer {
+            words: [0; 6 + MAX_VALUE_WORDS],
+        };
+        buffer.words[0] = (words * 4) as u32;
+        buffer.words[1] = REQUEST;
+        buffer.words[2] = tag;
+        buffer.words[3] = (values.len() * 4) as u32;
+        buffer.words[4] = 0;
+        buffer.words[5..words - 1].copy_from_slice(values);
+        // End tag is already zero
+
+        unsafe { self.call(buffer.words.as_mut_ptr())? };
+
+        let status = buffer.words[4];
+        if status & TAG_RESPONSE == 0 {
+            return Err(Error::NotAvailable);
+        }
+        let len = (status & !TAG_RESPONSE) as usize;
+        let copied = values.len().min(len.div_ceil(4));
+        values[..copied].copy_from_slice(&buffer.words[5..5 + copied]);
+        Ok(len)
+    }
+
+    /// Firmware revision
+    pub fn firmware_revision(&mut self) -> Result<u32, Error> {
+        let mut values = [0];
+        self.property(tag::GET_FIRMWARE_REVISION, &mut values)?;
+        Ok(values[0])
+    }
+
+    /// Board revision code
+    pub fn board_revision(&mut self) -> Result<u32, Error> {
+        let mut values = [0];
+        self.property(tag::GET_BOARD_REVISION, &mut values)?;
+        Ok(values[0])
+    }
+
+    /// Board serial number
+    pub fn board_serial(&mut self) -> Result<u64, Error> {
+        let mut values = [0; 2];
+        self.property(tag::GET_BOARD_SERIAL, &mut values)?;
+        Ok(u64::from(values[1]) << 32 | u64::from(values[0]))
+    }
+
+    /// MAC address of the onboard Ethernet
+    pub fn mac_address(&mut self) -> Result<[u8; 6], Error> {
+        let mut values = [0; 2];
+        self.property(tag::GET_MAC_ADDRESS, &mut values)?;
+        let mut mac = [0; 6];
+        mac[..4].copy_from_slice(&values[0].to_le_bytes());
+        mac[4..].copy_from_slice(&values[1].to_le_bytes()[..2]);
+        Ok(mac)
+    }
+
+    /// Base and size of the ARM's part of the first RAM region
+    pub fn arm_memory(&mut self) -> Result<(usize, usize), Error> {
+        let mut values = [0; 2];
+        self.property(tag::GET_ARM_MEMORY, &mut values)?;
+        Ok((values[0] as usize, values[1] as usize))
+    }
+
+    /// Current rate of `clock` in Hz
+    pub fn clock_rate(&mut self, clock: FirmwareClock) -> Result<u32, Error> {
+        let mut values = [clock as u32, 0];
+        self.property(tag::GET_CLOCK_RATE, &mut values)?;
+        Ok(values[1])
+    }
+
+    /// Highest rate of `clock` in Hz
+    pub fn max_clock_rate(&mut self, clock: FirmwareClock) -> Result<u32, Error> {
+        let mut values = [clock as u32, 0];
+        self.property(tag::GET_MAX_CLOCK_RATE, &mut values)?;
+        Ok(values[1])
+    }
+
+    /// Set `clock` to `hz`, returning the rate the firmware picked
+    pub fn set_clock_rate(&mut self, clock: FirmwareClock, hz: u32) -> Result<u32, Error> {
+        // The third word asks the firmware not to change turbo settings
+        let mut values = [clock as u32, hz, 1];
+        self.property(tag::SET_CLOCK_RATE, &mut values)?;
+        match values[1] {
+            0 => Err(Error::InvalidParameter),
+            rate => Ok(rate),
+        }
+    }
+
+    /// SoC temperature in millidegrees Celsius
+    pub fn temperature(&mut self) -> Result<u32, Error> {
+        let mut values = [0, 0];
+        self.property(tag::GET_TEMPERATURE, &mut values)?;
+        Ok(values[1])
+    }
+
+    /// Power `domain` on or off, waiting for it to settle
+    pub fn set_power(&mut self, domain: PowerDomain, on: bool) -> Result<(), Error> {
+        // Bit 1 waits for the domain
+        let mut values = [domain as u32, u32::from(on) | 1 << 1];
+        self.property(tag::SET_POWER_STATE, &mut values)?;
+        // Bit 1 of the response means the domain doesn't exist
+        if values[1] & 1 << 1 != 0 {
+            return Err(Error::NotAvailable);
+        }
+        if (values[1] & 1 != 0) != on {
+            return Err(Error::HardwareFailure);
+        }
+        Ok(())
+    }
+}
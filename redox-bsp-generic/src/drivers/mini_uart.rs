@@ -0,0 +1,201 @@
+//! BCM283x/BCM2711 auxiliary mini UART driver
+//!
+//! The mini UART is clocked from the VPU core clock, so its baud rate is
+//! only stable with a fixed core frequency (`enable_uart=1` or
+//! `core_freq` in `config.txt`). It only supports 7 or 8 data bits, one
+//! stop bit and no parity.
+
+use redox_hal::pinmux::{Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::uart::{BaudRate, DataBits, FlowControl, Parity, StopBits, Uart, UartConfig};
+use redox_hal::Error;
+
+/// Register offsets from the AUX block base
+mod regs {
+    pub const AUX_ENABLES: usize = 0x04; // Auxiliary peripheral enables
+    pub const IO: usize = 0x40; // I/O Data
+    pub const IER: usize = 0x44; // Interrupt Enable
+    pub const IIR: usize = 0x48; // Interrupt Identify (FIFO clear)
+    pub const LCR: usize = 0x4C; // Line Control
+    pub const MCR: usize = 0x50; // Modem Control
+    pub const LSR: usize = 0x54; // Line Status
+    pub const CNTL: usize = 0x60; // Extra Control
+    pub const STAT: usize = 0x64; // Extra Status
+    pub const BAUD: usize = 0x68; // Baudrate
+}
+
+const AUX_ENABLES_UART: u32 = 1 << 0;
+
+/// Line Status Register bits
+mod lsr {
+    pub const DATA_READY: u32 = 1 << 0;
+    pub const TX_EMPTY: u32 = 1 << 5; // Room for a byte
+    pub const TX_IDLE: u32 = 1 << 6;
+}
+
+/// Extra Control Register bits
+mod cntl {
+    pub const RX_EN: u32 = 1 << 0;
+    pub const TX_EN: u32 = 1 << 1;
+    pub const RTS_FLOW: u32 = 1 << 2;
+    pub const CTS_FLOW: u32 = 1 << 3;
+}
+
+/// Mini UART FIFO depth
+const FIFO_SIZE: usize = 8;
+
+/// Auxiliary mini UART (UART1)
+pub struct MiniUart {
+    aux_base: usize,
+    core_clock: u32,
+    config: UartConfig,
+}
+
+impl MiniUart {
+    /// Create the mini UART of the AUX block at `aux_base`, clocked at
+    /// `core_clock` Hz
+    pub const fn new(aux_base: usize, core_clock: u32) -> Self {
+        Self {
+            aux_base,
+            core_clock,
+            config: UartConfig {
+                baud_rate: BaudRate::Baud115200,
+                data_bits: DataBits::Eight,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+                flow_control: FlowControl::None,
+            },
+        }
+    }
+
+    /// Create the mini UART after routing its pads through `pinmux`
+    pub fn claim<C: PinController>(
+        aux_base: usize,
+        core_clock: u32,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        pinmux.claim(Peripheral::Uart(1))?;
+        Ok(Self::new(aux_base, core_clock))
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.aux_base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.aux_base + offset) as *mut u32, value);
+    }
+
+    fn line_status(&self) -> u32 {
+        unsafe { self.read_reg(regs::LSR) }
+    }
+}
+
+impl Uart for MiniUart {
+    type Error = Error;
+
+    fn configure(&mut self, config: UartConfig) -> Result<(), Self::Error> {
+        let data_bits = match config.data_bits {
+            DataBits::Seven => 0,
+            DataBits::Eight => 3,
+            _ => return Err(Error::InvalidConfig),
+        };
+        if !matches!(config.parity, Parity::None) || !matches!(config.stop_bits, StopBits::One) {
+            return Err(Error::InvalidConfig);
+        }
+
+        // baud = core_clock / (8 * (BAUD + 1))
+        let baud = config.baud_rate.value();
+        if baud == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        let divisor = (self.core_clock + 4 * baud) / (8 * baud);
+        if !(1..=0x1_0000).contains(&divisor) {
+            return Err(Error::InvalidParameter);
+        }
+
+        let mut control = cntl::RX_EN | cntl::TX_EN;
+        if !matches!(config.flow_control, FlowControl::None) {
+            control |= cntl::RTS_FLOW | cntl::CTS_FLOW;
+        }
+
+        unsafe {
+            let enables = self.read_reg(regs::AUX_ENABLES);
+            self.write_reg(regs::AUX_ENABLES, enables | AUX_ENABLES_UART);
+            self.write_reg(regs::CNTL, 0);
+            self.write_reg(regs::IER, 0);
+            self.write_reg(regs::LCR, data_bits);
+            self.write_reg(regs::MCR, 0);
+            // Clear both FIFOs
+            self.write_reg(regs::IIR, 0xC6);
+            self.write_reg(regs::BAUD, divisor - 1);
+            self.write_reg(regs::CNTL, control);
+        }
+
+        self.config = config;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        for &byte in data {
+            while !self.is_tx_ready() {
+                core::hint::spin_loop();
+            }
+            unsafe {
+                self.write_reg(regs::IO, byte as u32);
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut count = 0;
+        for byte in buffer.iter_mut() {
+            if !self.is_rx_ready() {
+                break;
+            }
+            *byte = unsafe { self.read_reg(regs::IO) as u8 };
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        while !self.is_rx_ready() {
+            core::hint::spin_loop();
+        }
+        unsafe { Ok(self.read_reg(regs::IO) as u8) }
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while self.line_status() & lsr::TX_IDLE == 0 {
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+
+    fn is_rx_ready(&self) -> bool {
+        self.line_status() & lsr::DATA_READY != 0
+    }
+
+    fn is_tx_ready(&self) -> bool {
+        self.line_status() & lsr::TX_EMPTY != 0
+    }
+
+    fn rx_available(&self) -> usize {
+        // FIFO fill levels are in bits 19:16 (receive) and 27:24 (transmit)
+        let stat = unsafe { self.read_reg(regs::STAT) };
+        ((stat >> 16) & 0xF) as usize
+    }
+
+    fn tx_free(&self) -> usize {
+        let stat = unsafe { self.read_reg(regs::STAT) };
+        FIFO_SIZE.saturating_sub(((stat >> 24) & 0xF) as usize)
+    }
+}
+
+impl core::fmt::Write for MiniUart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let _ = Uart::write(self, s.as_bytes());
+        Ok(())
+    }
+}
@@ -1,5 +1,21 @@
 //! Generic drivers for embedded peripherals
 
+pub mod bcm2711_gpio;
+pub mod clocks;
+pub mod esp32c3_gpio;
+pub mod esp32c3_spi;
+pub mod esp32c3_timer;
+pub mod esp32c3_uart;
 pub mod ethernet;
+pub mod genet;
 pub mod gpio;
+pub mod mailbox;
+pub mod mini_uart;
+pub mod pinmux;
+pub mod pl011;
+pub mod rp1_gpio;
+pub mod rp2040_flash;
+pub mod rp2040_gpio;
+pub mod rp2040_pio;
+pub mod sdhci;
 pub mod uart;
@@ -0,0 +1,47 @@
+//! Generic pad control driver
+
+use redox_hal::pinmux::{AltFn, PinController};
+use redox_hal::Error;
+
+// AM335x style control module pad registers
+const CONF_FIRST: u16 = 0x800;
+const CONF_LAST: u16 = 0xA34;
+const CONF_MUXMODE: u32 = 0x7;
+const CONF_RXACTIVE: u32 = 1 << 5;
+
+/// Generic pad controller
+///
+/// Pins are the offsets of the pads' configuration registers from the
+/// control module base, as listed in the SoC reference manual.
+pub struct GenericPinController {
+    base: usize,
+}
+
+impl GenericPinController {
+    /// Create a pad controller for the control module at `base`
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+}
+
+impl PinController for GenericPinController {
+    type Error = Error;
+
+    fn set_function(&mut self, pin: u16, function: AltFn) -> Result<(), Self::Error> {
+        if !(CONF_FIRST..=CONF_LAST).contains(&pin) || !pin.is_multiple_of(4) {
+            return Err(Error::InvalidParameter);
+        }
+        if u32::from(function.0) > CONF_MUXMODE {
+            return Err(Error::InvalidParameter);
+        }
+
+        // Keep the pull and slew settings, and enable the input buffer so
+        // inputs and I2C lines work
+        let reg = (self.base + pin as usize) as *mut u32;
+        unsafe {
+            let value = core::ptr::read_volatile(reg) & !CONF_MUXMODE;
+            core::ptr::write_volatile(reg, value | u32::from(function.0) | CONF_RXACTIVE);
+        }
+        Ok(())
+    }
+}
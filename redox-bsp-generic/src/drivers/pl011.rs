@@ -0,0 +1,236 @@
+//! ARM PrimeCell PL011 UART driver
+//!
+//! The full UARTs of the BCM2711, the debug UART of the BCM2712 and the
+//! RP1 UARTs are all PL011s.
+
+use redox_hal::pinmux::{Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::uart::{BaudRate, DataBits, FlowControl, Parity, StopBits, Uart, UartConfig};
+use redox_hal::Error;
+
+/// Register offsets
+mod regs {
+    pub const DR: usize = 0x00; // Data Register
+    pub const FR: usize = 0x18; // Flag Register
+    pub const IBRD: usize = 0x24; // Integer Baud Rate Divisor
+    pub const FBRD: usize = 0x28; // Fractional Baud Rate Divisor
+    pub const LCRH: usize = 0x2C; // Line Control Register
+    pub const CR: usize = 0x30; // Control Register
+    pub const IMSC: usize = 0x38; // Interrupt Mask Set/Clear
+    pub const ICR: usize = 0x44; // Interrupt Clear Register
+}
+
+/// Flag Register bits
+mod fr {
+    pub const BUSY: u32 = 1 << 3; // Transmitting
+    pub const RXFE: u32 = 1 << 4; // Receive FIFO empty
+    pub const TXFF: u32 = 1 << 5; // Transmit FIFO full
+}
+
+/// Line Control Register bits
+mod lcrh {
+    pub const PEN: u32 = 1 << 1; // Parity enable
+    pub const EPS: u32 = 1 << 2; // Even parity
+    pub const STP2: u32 = 1 << 3; // Two stop bits
+    pub const FEN: u32 = 1 << 4; // FIFO enable
+    pub const WLEN_SHIFT: u32 = 5; // Word length
+    pub const SPS: u32 = 1 << 7; // Stick parity
+}
+
+/// Control Register bits
+mod cr {
+    pub const UARTEN: u32 = 1 << 0;
+    pub const TXE: u32 = 1 << 8;
+    pub const RXE: u32 = 1 << 9;
+    pub const RTSEN: u32 = 1 << 14;
+    pub const CTSEN: u32 = 1 << 15;
+}
+
+/// PL011 UART
+pub struct Pl011Uart {
+    base: usize,
+    clock_freq: u32,
+    config: UartConfig,
+}
+
+impl Pl011Uart {
+    /// Create a UART with a `clock_freq` Hz reference clock
+    pub const fn new(base: usize, clock_freq: u32) -> Self {
+        Self {
+            base,
+            clock_freq,
+            config: UartConfig {
+                baud_rate: BaudRate::Baud115200,
+                data_bits: DataBits::Eight,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+                flow_control: FlowControl::None,
+            },
+        }
+    }
+
+    /// Create UART `instance` after routing its pads through `pinmux`
+    pub fn claim<C: PinController>(
+        base: usize,
+        clock_freq: u32,
+        instance: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        pinmux.claim(Peripheral::Uart(instance))?;
+        Ok(Self::new(base, clock_freq))
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    /// Baud rate divisor in 1/64ths: clock / (16 * baud), rounded
+    fn divisor(&self, baud_rate: u32) -> Result<u32, Error> {
+        if baud_rate == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        let div =
+            (u64::from(self.clock_freq) * 4 + u64::from(baud_rate) / 2) / u64::from(baud_rate);
+        // 16-bit integer part, at least 1
+        if !(64..=0xFFFF * 64 + 63).contains(&div) {
+            return Err(Error::InvalidParameter);
+        }
+        Ok(div as u32)
+    }
+
+    fn flags(&self) -> u32 {
+        unsafe { self.read_reg(regs::FR) }
+    }
+}
+
+impl Uart for Pl011Uart {
+    type Error = Error;
+
+    fn configure(&mut self, config: UartConfig) -> Result<(), Self::Error> {
+        let divisor = self.divisor(config.baud_rate.value())?;
+
+        let mut line = lcrh::FEN;
+        line |= match config.data_bits {
+            DataBits::Five => 0,
+            DataBits::Six => 1,
+            DataBits::Seven => 2,
+            DataBits::Eight => 3,
+            DataBits::Nine => return Err(Error::InvalidConfig),
+        } << lcrh::WLEN_SHIFT;
+        if matches!(config.stop_bits, StopBits::Two) {
+            line |= lcrh::STP2;
+        }
+        line |= match config.parity {
+            Parity::None => 0,
+            Parity::Odd => lcrh::PEN,
+            Parity::Even => lcrh::PEN | lcrh::EPS,
+            // Stick parity sends the inverse of EPS
+            Parity::Mark => lcrh::PEN | lcrh::SPS,
+            Parity::Space => lcrh::PEN | lcrh::EPS | lcrh::SPS,
+        };
+
+        let mut control = cr::UARTEN | cr::TXE | cr::RXE;
+        if !matches!(config.flow_control, FlowControl::None) {
+            control |= cr::RTSEN | cr::CTSEN;
+        }
+
+        unsafe {
+            // Disable and let the transmitter drain before reprogramming
+            self.write_reg(regs::CR, 0);
+            while self.flags() & fr::BUSY != 0 {
+                core::hint::spin_loop();
+            }
+            self.write_reg(regs::IMSC, 0);
+            self.write_reg(regs::ICR, 0x7FF);
+            self.write_reg(regs::IBRD, divisor >> 6);
+            self.write_reg(regs::FBRD, divisor & 0x3F);
+            // The divisors only latch on a write to LCRH
+            self.write_reg(regs::LCRH, line);
+            self.write_reg(regs::CR, control);
+        }
+
+        self.config = config;
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        for &byte in data {
+            while self.flags() & fr::TXFF != 0 {
+                core::hint::spin_loop();
+            }
+            unsafe {
+                self.write_reg(regs::DR, byte as u32);
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut count = 0;
+        for byte in buffer.iter_mut() {
+            if !self.is_rx_ready() {
+                break;
+            }
+            *byte = self.read_byte()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        while !self.is_rx_ready() {
+            core::hint::spin_loop();
+        }
+        let data = unsafe { self.read_reg(regs::DR) };
+        // Error flags come with the byte
+        match data >> 8 {
+            0 => Ok(data as u8),
+            err if err & 0x1 != 0 => Err(Error::FramingError),
+            err if err & 0x2 != 0 => Err(Error::ParityError),
+            err if err & 0x8 != 0 => Err(Error::OverrunError),
+            _ => Err(Error::BusError), // Break
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while self.flags() & fr::BUSY != 0 {
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+
+    fn is_rx_ready(&self) -> bool {
+        self.flags() & fr::RXFE == 0
+    }
+
+    fn is_tx_ready(&self) -> bool {
+        self.flags() & fr::TXFF == 0
+    }
+
+    fn rx_available(&self) -> usize {
+        // The FIFO level isn't readable
+        if self.is_rx_ready() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn tx_free(&self) -> usize {
+        if self.is_tx_ready() {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+impl core::fmt::Write for Pl011Uart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let _ = Uart::write(self, s.as_bytes());
+        Ok(())
+    }
+}
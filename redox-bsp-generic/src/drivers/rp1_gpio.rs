@@ -0,0 +1,215 @@
+//! RP1 GPIO driver (Raspberry Pi 5)
+//!
+//! The 40-pin header of the Pi 5 is wired to the RP1 south bridge, which
+//! the firmware leaves mapped behind PCIe. Each of the three GPIO banks has
+//! an IO block (function select), a RIO block (software controlled output,
+//! output enable and input) and a pads block (pulls, input enable, drive).
+
+use redox_hal::gpio::{GpioPin, Level, PinMode, Pull};
+use redox_hal::pinmux::{AltFn, Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::Error;
+
+const IO_BANK0: usize = 0xD_0000;
+const SYS_RIO0: usize = 0xE_0000;
+const PADS_BANK0: usize = 0xF_0000;
+const BANK_STRIDE: usize = 0x4000;
+
+// RIO registers and atomic aliases
+const RIO_OUT: usize = 0x00;
+const RIO_OE: usize = 0x04;
+const RIO_IN: usize = 0x08;
+const RIO_SET: usize = 0x2000;
+const RIO_CLR: usize = 0x3000;
+
+// IO bank control register
+const CTRL_FUNCSEL: u32 = 0x1F;
+const FUNCSEL_SYS_RIO: u32 = 5;
+const FUNCSEL_NULL: u32 = 0x1F;
+
+// Pad control register
+const PAD_PDE: u32 = 1 << 2;
+const PAD_PUE: u32 = 1 << 3;
+const PAD_IE: u32 = 1 << 6;
+const PAD_OD: u32 = 1 << 7;
+
+// First GPIO of each bank, and the end of the last
+const BANK_START: [u8; 4] = [0, 28, 34, 54];
+
+/// Number of GPIO lines
+pub const GPIO_COUNT: u8 = 54;
+
+/// Pad function for plain GPIO use
+pub const GPIO_FUNCTION: AltFn = AltFn(FUNCSEL_SYS_RIO as u8);
+
+/// Register offsets of one pin, relative to the RP1 base
+#[derive(Debug, Clone, Copy)]
+struct PinRegs {
+    ctrl: usize,
+    rio: usize,
+    pad: usize,
+    mask: u32,
+}
+
+impl PinRegs {
+    fn new(pin: u8) -> Option<Self> {
+        let bank = BANK_START
+            .windows(2)
+            .position(|w| (w[0]..w[1]).contains(&pin))?;
+        let index = (pin - BANK_START[bank]) as usize;
+        Some(Self {
+            ctrl: IO_BANK0 + bank * BANK_STRIDE + index * 8 + 4,
+            rio: SYS_RIO0 + bank * BANK_STRIDE,
+            // The first pads register selects the bank voltage
+            pad: PADS_BANK0 + bank * BANK_STRIDE + 4 + index * 4,
+            mask: 1 << index,
+        })
+    }
+}
+
+unsafe fn read_reg(base: usize, offset: usize) -> u32 {
+    core::ptr::read_volatile((base + offset) as *const u32)
+}
+
+unsafe fn write_reg(base: usize, offset: usize, value: u32) {
+    core::ptr::write_volatile((base + offset) as *mut u32, value);
+}
+
+unsafe fn modify_reg(base: usize, offset: usize, clear: u32, set: u32) {
+    let value = read_reg(base, offset) & !clear;
+    write_reg(base, offset, value | set);
+}
+
+/// RP1 GPIO pin
+pub struct Rp1GpioPin {
+    base: usize,
+    pin: u8,
+    regs: PinRegs,
+    mode: PinMode,
+}
+
+impl Rp1GpioPin {
+    /// Create GPIO `pin` of the RP1 mapped at `base`
+    pub fn new(base: usize, pin: u8) -> Result<Self, Error> {
+        let regs = PinRegs::new(pin).ok_or(Error::InvalidParameter)?;
+        Ok(Self {
+            base,
+            pin,
+            regs,
+            mode: PinMode::Input,
+        })
+    }
+
+    /// Take GPIO `pin` after routing its pad through `pinmux`
+    pub fn claim<C: PinController>(
+        base: usize,
+        pin: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        let peripheral = Peripheral::Gpio(u16::from(pin));
+        let gpio = Self::new(base, pin).map_err(|_| PinmuxError::NotMapped(peripheral))?;
+        pinmux.claim_pin(u16::from(pin), GPIO_FUNCTION, peripheral)?;
+        Ok(gpio)
+    }
+
+    fn set_output_enable(&mut self, enabled: bool) {
+        let offset = self.regs.rio + RIO_OE + if enabled { RIO_SET } else { RIO_CLR };
+        unsafe { write_reg(self.base, offset, self.regs.mask) };
+    }
+}
+
+impl GpioPin for Rp1GpioPin {
+    type Error = Error;
+
+    fn pin_number(&self) -> u8 {
+        self.pin
+    }
+
+    fn set_mode(&mut self, mode: PinMode) -> Result<(), Self::Error> {
+        let funcsel = match mode {
+            PinMode::Input | PinMode::Output | PinMode::OpenDrain => FUNCSEL_SYS_RIO,
+            PinMode::Alternate(alt) if u32::from(alt) < FUNCSEL_SYS_RIO => u32::from(alt),
+            PinMode::Alternate(_) => return Err(Error::InvalidParameter),
+            PinMode::Analog => return Err(Error::NotAvailable),
+        };
+        unsafe {
+            modify_reg(self.base, self.regs.pad, PAD_OD, PAD_IE);
+            modify_reg(self.base, self.regs.ctrl, CTRL_FUNCSEL, funcsel);
+        }
+        // Open drain outputs drive low by enabling the output with the
+        // output latch cleared
+        if mode == PinMode::OpenDrain {
+            let offset = self.regs.rio + RIO_OUT + RIO_CLR;
+            unsafe { write_reg(self.base, offset, self.regs.mask) };
+        }
+        self.set_output_enable(mode == PinMode::Output);
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn mode(&self) -> PinMode {
+        self.mode
+    }
+
+    fn set_pull(&mut self, pull: Pull) -> Result<(), Self::Error> {
+        let set = match pull {
+            Pull::None => 0,
+            Pull::Up => PAD_PUE,
+            Pull::Down => PAD_PDE,
+        };
+        unsafe { modify_reg(self.base, self.regs.pad, PAD_PUE | PAD_PDE, set) };
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Level, Self::Error> {
+        let value = unsafe { read_reg(self.base, self.regs.rio + RIO_IN) };
+        Ok(Level::from_bool(value & self.regs.mask != 0))
+    }
+
+    fn write(&mut self, level: Level) -> Result<(), Self::Error> {
+        if self.mode == PinMode::OpenDrain {
+            // Drive low, release high
+            self.set_output_enable(level == Level::Low);
+            return Ok(());
+        }
+        let alias = match level {
+            Level::High => RIO_SET,
+            Level::Low => RIO_CLR,
+        };
+        unsafe { write_reg(self.base, self.regs.rio + RIO_OUT + alias, self.regs.mask) };
+        Ok(())
+    }
+}
+
+/// RP1 pad function controller
+///
+/// Pins are GPIO numbers and functions are the RP1 function selects
+/// `a0` to `a8`, with [`GPIO_FUNCTION`] for software control.
+pub struct Rp1PinController {
+    base: usize,
+}
+
+impl Rp1PinController {
+    /// Create a pad controller for the RP1 mapped at `base`
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+}
+
+impl PinController for Rp1PinController {
+    type Error = Error;
+
+    fn set_function(&mut self, pin: u16, function: AltFn) -> Result<(), Self::Error> {
+        let regs = u8::try_from(pin)
+            .ok()
+            .and_then(PinRegs::new)
+            .ok_or(Error::InvalidParameter)?;
+        if u32::from(function.0) >= FUNCSEL_NULL {
+            return Err(Error::InvalidParameter);
+        }
+        unsafe {
+            modify_reg(self.base, regs.pad, PAD_OD, PAD_IE);
+            modify_reg(self.base, regs.ctrl, CTRL_FUNCSEL, u32::from(function.0));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! RP2040 QSPI flash (Raspberry Pi Pico)
+//!
+//! The flash is programmed through the routines of the boot ROM. While they
+//! run, execute-in-place is off, so the code driving them runs from RAM with
+//! interrupts disabled, and afterwards XIP is set up again with the boot2
+//! stage copied from the start of flash. Reads go through the XIP window.
+//!
+//! [`Rp2040FlashSlots`] keeps the two [`ota`](crate::ota) firmware slots in
+//! flash, [`Rp2040FlashStore`] the boot control record, so both survive a
+//! power loss.
+
+use redox_hal::Error;
+
+use crate::ota::{Slot, SlotStorage};
+use crate::runtime::recovery::PersistentStore;
+
+/// Erase granularity
+pub const SECTOR_SIZE: usize = 4096;
+/// Program granularity
+pub const PAGE_SIZE: usize = 256;
+
+/// Start of the XIP window, flash offset 0
+const XIP_BASE: usize = 0x1000_0000;
+/// Size of the boot2 stage at the start of flash
+const BOOT2_SIZE: usize = 256;
+
+/// 64 KiB block erase, used where the range allows it
+const BLOCK_SIZE: u32 = 1 << 16;
+const BLOCK_ERASE_CMD: u8 = 0xD8;
+
+// Boot ROM lookup
+const ROM_FUNC_TABLE: usize = 0x14;
+const ROM_TABLE_LOOKUP: usize = 0x18;
+
+type RomLookup = unsafe extern "C" fn(table: *const u16, code: u32) -> usize;
+type RomFn = unsafe extern "C" fn();
+type RomEraseFn = unsafe extern "C" fn(offset: u32, count: usize, block_size: u32, block_cmd: u8);
+type RomProgramFn = unsafe extern "C" fn(offset: u32, data: *const u8, count: usize);
+
+/// Flash routines of the boot ROM
+#[derive(Clone, Copy)]
+struct Rom {
+    connect_internal_flash: RomFn,
+    flash_exit_xip: RomFn,
+    flash_range_erase: RomEraseFn,
+    flash_range_program: RomProgramFn,
+    flash_flush_cache: RomFn,
+}
+
+impl Rom {
+    /// Look the routines up in the ROM function table
+    unsafe fn lookup() -> Self {
+        let table = core::ptr::read_volatile(ROM_FUNC_TABLE as *const u16) as usize;
+        let lookup = core::ptr::read_volatile(ROM_TABLE_LOOKUP as *const u16) as usize;
+        let lookup = core::mem::transmute::<usize, RomLookup>(lookup);
+        let function = |code: [u8; 2]| lookup(table as *const u16, u16::from_le_bytes(code) as u32);
+        Self {
+            connect_internal_flash: core::mem::transmute::<usize, RomFn>(function(*b"IF")),
+            flash_exit_xip: core::mem::transmute::<usize, RomFn>(function(*b"EX")),
+            flash_range_erase: core::mem::transmute::<usize, RomEraseFn>(function(*b"RE")),
+            flash_range_program: core::mem::transmute::<usize, RomProgramFn>(function(*b"RP")),
+            flash_flush_cache: core::mem::transmute::<usize, RomFn>(function(*b"FC")),
+        }
+    }
+}
+
+/// Erase or program with XIP off
+///
+/// Lives in RAM: nothing may be fetched from flash until boot2 re-enabled
+/// XIP, so this calls nothing but the ROM and the boot2 copy.
+#[inline(never)]
+#[link_section = ".data.rp2040_flash"]
+unsafe extern "C" fn run_from_ram(
+    rom: &Rom,
+    boot2: *const u32,
+    erase: bool,
+    offset: u32,
+    data: *const u8,
+    len: usize,
+) {
+    (rom.connect_internal_flash)();
+    (rom.flash_exit_xip)();
+    if erase {
+        (rom.flash_range_erase)(offset, len, BLOCK_SIZE, BLOCK_ERASE_CMD);
+    } else {
+        (rom.flash_range_program)(offset, data, len);
+    }
+    (rom.flash_flush_cache)();
+    // Thumb code, hence the set low bit
+    let boot2 = core::mem::transmute::<usize, RomFn>(boot2 as usize | 1);
+    boot2();
+}
+
+/// Raw access to the QSPI flash
+pub struct Rp2040Flash {
+    rom: Rom,
+    boot2: [u32; BOOT2_SIZE / 4],
+}
+
+impl Rp2040Flash {
+    /// Look up the ROM routines and save boot2
+    ///
+    /// # Safety
+    ///
+    /// While an erase or program runs, the other core must not execute
+    /// from flash or touch the XIP window, e.g. by being parked in RAM.
+    /// The value must live in RAM, since boot2 runs from its copy.
+    pub unsafe fn new() -> Self {
+        let mut boot2 = [0; BOOT2_SIZE / 4];
+        for (i, word) in boot2.iter_mut().enumerate() {
+            *word = core::ptr::read_volatile((XIP_BASE + i * 4) as *const u32);
+        }
+        Self {
+            rom: Rom::lookup(),
+            boot2,
+        }
+    }
+
+    fn run(&mut self, erase: bool, offset: usize, data: *const u8, len: usize) {
+        unsafe {
+            #[cfg(target_arch = "arm")]
+            let primask = {
+                let primask: u32;
+                core::arch::asm!("mrs {}, primask", out(reg) primask);
+                core::arch::asm!("cpsid i");
+                primask
+            };
+            run_from_ram(
+                &self.rom,
+                self.boot2.as_ptr(),
+                erase,
+                offset as u32,
+                data,
+                len,
+            );
+            #[cfg(target_arch = "arm")]
+            if primask & 1 == 0 {
+                core::arch::asm!("cpsie i");
+            }
+        }
+    }
+
+    /// Erase `len` bytes at `offset`, both sector aligned
+    pub fn erase(&mut self, offset: usize, len: usize) -> Result<(), Error> {
+        if !offset.is_multiple_of(SECTOR_SIZE)
+            || !len.is_multiple_of(SECTOR_SIZE)
+            || offset < BOOT2_SIZE
+        {
+            return Err(Error::InvalidParameter);
+        }
+        self.run(true, offset, core::ptr::null(), len);
+        Ok(())
+    }
+
+    /// Program `data` at `offset` of erased flash
+    ///
+    /// Pages are padded with 0xFF, which leaves bits alone, so partial pages
+    /// can be written in several steps.
+    pub fn program(&mut self, mut offset: usize, mut data: &[u8]) -> Result<(), Error> {
+        if offset < BOOT2_SIZE {
+            return Err(Error::InvalidParameter);
+        }
+        let mut page = [0xFF; PAGE_SIZE];
+        while !data.is_empty() {
+            let start = offset % PAGE_SIZE;
+            let count = data.len().min(PAGE_SIZE - start);
+            page.fill(0xFF);
+            page[start..start + count].copy_from_slice(&data[..count]);
+            self.run(false, offset - start, page.as_ptr(), PAGE_SIZE);
+            offset += count;
+            data = &data[count..];
+        }
+        Ok(())
+    }
+
+    /// Read `buf.len()` bytes at `offset` through the XIP window
+    pub fn read(&self, offset: usize, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((XIP_BASE + offset + i) as *const u8) };
+        }
+    }
+}
+
+/// OTA firmware slots in flash
+pub struct Rp2040FlashSlots {
+    flash: Rp2040Flash,
+    offsets: [usize; 2],
+    slot_size: usize,
+}
+
+impl Rp2040FlashSlots {
+    /// Slots of `slot_size` bytes at flash offsets `a` and `b`
+    ///
+    /// Offsets and size have to be sector aligned and the slots must not
+    /// overlap.
+    pub fn new(flash: Rp2040Flash, a: usize, b: usize, slot_size: usize) -> Result<Self, Error> {
+        let aligned = |value: usize| value.is_multiple_of(SECTOR_SIZE);
+        if !aligned(a)
+            || !aligned(b)
+            || !aligned(slot_size)
+            || slot_size == 0
+            || a.abs_diff(b) < slot_size
+            || a < BOOT2_SIZE
+            || b < BOOT2_SIZE
+        {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(Self {
+            flash,
+            offsets: [a, b],
+            slot_size,
+        })
+    }
+
+    /// Flash offset of `offset` in `slot`, checked against the slot size
+    fn at(&self, slot: Slot, offset: usize, len: usize) -> Result<usize, Error> {
+        if offset
+            .checked_add(len)
+            .is_none_or(|end| end > self.slot_size)
+        {
+            return Err(Error::InvalidParameter);
+        }
+        let base = match slot {
+            Slot::A => self.offsets[0],
+            Slot::B => self.offsets[1],
+        };
+        Ok(base + offset)
+    }
+}
+
+impl SlotStorage for Rp2040FlashSlots {
+    fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    fn erase(&mut self, slot: Slot) -> Result<(), Error> {
+        let offset = self.at(slot, 0, self.slot_size)?;
+        self.flash.erase(offset, self.slot_size)
+    }
+
+    fn write(&mut self, slot: Slot, offset: usize, data: &[u8]) -> Result<(), Error> {
+        let offset = self.at(slot, offset, data.len())?;
+        self.flash.program(offset, data)
+    }
+
+    fn read(&mut self, slot: Slot, offset: usize, buf: &mut [u8]) -> Result<(), Error> {
+        let offset = self.at(slot, offset, buf.len())?;
+        self.flash.read(offset, buf);
+        Ok(())
+    }
+}
+
+/// [`PersistentStore`] in one flash sector
+///
+/// Every write erases the sector, so it suits records written a few times
+/// per boot, like the OTA boot control record.
+pub struct Rp2040FlashStore {
+    flash: Rp2040Flash,
+    offset: usize,
+}
+
+impl Rp2040FlashStore {
+    /// Use the sector at flash `offset`
+    pub fn new(flash: Rp2040Flash, offset: usize) -> Result<Self, Error> {
+        if !offset.is_multiple_of(SECTOR_SIZE) || offset < BOOT2_SIZE {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(Self { flash, offset })
+    }
+}
+
+impl PersistentStore for Rp2040FlashStore {
+    fn size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn read(&mut self, buf: &mut [u8]) {
+        let len = buf.len().min(SECTOR_SIZE);
+        self.flash.read(self.offset, &mut buf[..len]);
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let data = &data[..data.len().min(SECTOR_SIZE)];
+        // The offset was checked, neither can fail
+        let _ = self.flash.erase(self.offset, SECTOR_SIZE);
+        let _ = self.flash.program(self.offset, data);
+    }
+}
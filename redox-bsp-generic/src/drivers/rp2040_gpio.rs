@@ -0,0 +1,285 @@
+//! RP2040/RP2350 GPIO driver (Raspberry Pi Pico)
+//!
+//! Pads are routed by IO_BANK0 (function select) and configured by
+//! PADS_BANK0 (pulls, input enable, isolation). Under software control the
+//! pins are driven from the SIO block, whose registers moved on the RP2350
+//! to make room for its upper GPIO bank.
+
+use redox_hal::gpio::{GpioPin, Level, PinMode, Pull};
+use redox_hal::pinmux::{AltFn, Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::Error;
+
+// IO bank control register
+const CTRL_FUNCSEL: u32 = 0x1F;
+const FUNCSEL_SIO: u32 = 5;
+const FUNCSEL_NULL: u32 = 0x1F;
+
+// Pad control register
+const PAD_PDE: u32 = 1 << 2;
+const PAD_PUE: u32 = 1 << 3;
+const PAD_IE: u32 = 1 << 6;
+const PAD_OD: u32 = 1 << 7;
+// Isolation latch, RP2350 only; reserved on the RP2040
+const PAD_ISO: u32 = 1 << 8;
+
+/// Pad function for plain GPIO use
+pub const GPIO_FUNCTION: AltFn = AltFn(FUNCSEL_SIO as u8);
+
+/// RP2 chip variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rp2Chip {
+    /// RP2040
+    Rp2040,
+    /// RP2350 (A and B packages)
+    Rp2350,
+}
+
+impl Rp2Chip {
+    /// Number of GPIO lines in bank 0
+    pub const fn gpio_count(&self) -> u8 {
+        match self {
+            Rp2Chip::Rp2040 => 30,
+            Rp2Chip::Rp2350 => 48,
+        }
+    }
+
+    /// Number of PIO blocks
+    pub const fn pio_count(&self) -> u8 {
+        match self {
+            Rp2Chip::Rp2040 => 2,
+            Rp2Chip::Rp2350 => 3,
+        }
+    }
+
+    /// SIO register offsets
+    const fn sio(&self) -> SioRegs {
+        match self {
+            Rp2Chip::Rp2040 => SioRegs {
+                gpio_in: 0x004,
+                out_set: 0x014,
+                out_clr: 0x018,
+                oe_set: 0x024,
+                oe_clr: 0x028,
+                hi_stride: 0,
+            },
+            Rp2Chip::Rp2350 => SioRegs {
+                gpio_in: 0x004,
+                out_set: 0x018,
+                out_clr: 0x020,
+                oe_set: 0x038,
+                oe_clr: 0x040,
+                hi_stride: 0x004,
+            },
+        }
+    }
+}
+
+/// SIO registers of bank 0; the upper 32 GPIOs of the RP2350 use the `_HI`
+/// register following each one
+#[derive(Debug, Clone, Copy)]
+struct SioRegs {
+    gpio_in: usize,
+    out_set: usize,
+    out_clr: usize,
+    oe_set: usize,
+    oe_clr: usize,
+    hi_stride: usize,
+}
+
+unsafe fn read_reg(base: usize, offset: usize) -> u32 {
+    core::ptr::read_volatile((base + offset) as *const u32)
+}
+
+unsafe fn write_reg(base: usize, offset: usize, value: u32) {
+    core::ptr::write_volatile((base + offset) as *mut u32, value);
+}
+
+unsafe fn modify_reg(base: usize, offset: usize, clear: u32, set: u32) {
+    let value = read_reg(base, offset) & !clear;
+    write_reg(base, offset, value | set);
+}
+
+fn ctrl_offset(pin: u8) -> usize {
+    usize::from(pin) * 8 + 4
+}
+
+// The first pads register selects the bank voltage
+fn pad_offset(pin: u8) -> usize {
+    4 + usize::from(pin) * 4
+}
+
+/// Select `funcsel` for `pin` and enable its pad
+unsafe fn route_pad(io_bank0: usize, pads_bank0: usize, pin: u8, funcsel: u32) {
+    modify_reg(pads_bank0, pad_offset(pin), PAD_OD, PAD_IE);
+    modify_reg(io_bank0, ctrl_offset(pin), CTRL_FUNCSEL, funcsel);
+    // Only release the isolation latch once the function is in place
+    modify_reg(pads_bank0, pad_offset(pin), PAD_ISO, 0);
+}
+
+/// RP2 pad function controller
+///
+/// Functions are the function selects F1 (SPI) to F9, with
+/// [`GPIO_FUNCTION`] for software control and F6 onward for the PIO blocks.
+pub struct Rp2040PinController {
+    chip: Rp2Chip,
+    io_bank0: usize,
+    pads_bank0: usize,
+}
+
+impl Rp2040PinController {
+    /// Create a pad controller for the IO_BANK0 and PADS_BANK0 blocks
+    pub const fn new(chip: Rp2Chip, io_bank0: usize, pads_bank0: usize) -> Self {
+        Self {
+            chip,
+            io_bank0,
+            pads_bank0,
+        }
+    }
+}
+
+impl PinController for Rp2040PinController {
+    type Error = Error;
+
+    fn set_function(&mut self, pin: u16, function: AltFn) -> Result<(), Self::Error> {
+        let pin = u8::try_from(pin)
+            .ok()
+            .filter(|&pin| pin < self.chip.gpio_count())
+            .ok_or(Error::InvalidParameter)?;
+        if u32::from(function.0) >= FUNCSEL_NULL {
+            return Err(Error::InvalidParameter);
+        }
+        unsafe { route_pad(self.io_bank0, self.pads_bank0, pin, u32::from(function.0)) };
+        Ok(())
+    }
+}
+
+/// RP2 GPIO pin driven from SIO
+pub struct Rp2040GpioPin {
+    sio: usize,
+    io_bank0: usize,
+    pads_bank0: usize,
+    pin: u8,
+    regs: SioRegs,
+    // SIO register offset of the pin's half, and its bit there
+    half: usize,
+    mask: u32,
+    mode: PinMode,
+}
+
+impl Rp2040GpioPin {
+    /// Create GPIO `pin` of bank 0
+    pub fn new(
+        chip: Rp2Chip,
+        sio: usize,
+        io_bank0: usize,
+        pads_bank0: usize,
+        pin: u8,
+    ) -> Result<Self, Error> {
+        if pin >= chip.gpio_count() {
+            return Err(Error::InvalidParameter);
+        }
+        let regs = chip.sio();
+        Ok(Self {
+            sio,
+            io_bank0,
+            pads_bank0,
+            pin,
+            regs,
+            half: if pin < 32 { 0 } else { regs.hi_stride },
+            mask: 1 << (pin % 32),
+            mode: PinMode::Input,
+        })
+    }
+
+    /// Take GPIO `pin` after routing its pad through `pinmux`
+    pub fn claim<C: PinController>(
+        chip: Rp2Chip,
+        sio: usize,
+        io_bank0: usize,
+        pads_bank0: usize,
+        pin: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        let peripheral = Peripheral::Gpio(u16::from(pin));
+        let gpio = Self::new(chip, sio, io_bank0, pads_bank0, pin)
+            .map_err(|_| PinmuxError::NotMapped(peripheral))?;
+        pinmux.claim_pin(u16::from(pin), GPIO_FUNCTION, peripheral)?;
+        Ok(gpio)
+    }
+
+    fn set_output_enable(&mut self, enabled: bool) {
+        let offset = if enabled {
+            self.regs.oe_set
+        } else {
+            self.regs.oe_clr
+        };
+        unsafe { write_reg(self.sio, offset + self.half, self.mask) };
+    }
+}
+
+impl GpioPin for Rp2040GpioPin {
+    type Error = Error;
+
+    fn pin_number(&self) -> u8 {
+        self.pin
+    }
+
+    fn set_mode(&mut self, mode: PinMode) -> Result<(), Self::Error> {
+        let funcsel = match mode {
+            PinMode::Input | PinMode::Output | PinMode::OpenDrain => FUNCSEL_SIO,
+            PinMode::Alternate(alt) if u32::from(alt) < FUNCSEL_NULL => u32::from(alt),
+            PinMode::Alternate(_) => return Err(Error::InvalidParameter),
+            // ADC inputs are GPIO 26 to 29 with the pad's digital input off
+            PinMode::Analog => return Err(Error::NotAvailable),
+        };
+        unsafe { route_pad(self.io_bank0, self.pads_bank0, self.pin, funcsel) };
+        // Open drain outputs drive low by enabling the output with the
+        // output latch cleared
+        if mode == PinMode::OpenDrain {
+            unsafe { write_reg(self.sio, self.regs.out_clr + self.half, self.mask) };
+        }
+        self.set_output_enable(mode == PinMode::Output);
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn mode(&self) -> PinMode {
+        self.mode
+    }
+
+    fn set_pull(&mut self, pull: Pull) -> Result<(), Self::Error> {
+        let set = match pull {
+            Pull::None => 0,
+            Pull::Up => PAD_PUE,
+            Pull::Down => PAD_PDE,
+        };
+        unsafe {
+            modify_reg(
+                self.pads_bank0,
+                pad_offset(self.pin),
+                PAD_PUE | PAD_PDE,
+                set,
+            )
+        };
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Level, Self::Error> {
+        let value = unsafe { read_reg(self.sio, self.regs.gpio_in + self.half) };
+        Ok(Level::from_bool(value & self.mask != 0))
+    }
+
+    fn write(&mut self, level: Level) -> Result<(), Self::Error> {
+        if self.mode == PinMode::OpenDrain {
+            // Drive low, release high
+            self.set_output_enable(level == Level::Low);
+            return Ok(());
+        }
+        let offset = match level {
+            Level::High => self.regs.out_set,
+            Level::Low => self.regs.out_clr,
+        };
+        unsafe { write_reg(self.sio, offset + self.half, self.mask) };
+        Ok(())
+    }
+}
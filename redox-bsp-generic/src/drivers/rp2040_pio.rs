@@ -0,0 +1,404 @@
+//! RP2040/RP2350 PIO driver
+//!
+//! Each PIO block has four state machines sharing 32 words of instruction
+//! memory. [`Rp2040Pio`] owns the block and hands out its state machines,
+//! which implement [`PioStateMachine`] and allocate instruction memory as
+//! they load programs. The block only tracks its allocations in a `Cell`,
+//! so its state machines all live on one core.
+//!
+//! The RP2350 PIO is a superset of the RP2040 one, and the registers used
+//! here are the same on both. The block must be out of reset (RESETS) before
+//! it is used.
+
+use core::cell::Cell;
+
+use redox_hal::pinmux::{AltFn, Peripheral, PinController, Pinmux, PinmuxError};
+use redox_hal::pio::{
+    FifoJoin, Instruction, JmpCondition, PinRange, PioProgram, PioStateMachine, SetDestination,
+    ShiftDirection, StateMachineConfig, MAX_PROGRAM_LEN,
+};
+use redox_hal::Error;
+
+const CTRL: usize = 0x000;
+const FSTAT: usize = 0x004;
+const FLEVEL: usize = 0x00C;
+const TXF0: usize = 0x010;
+const RXF0: usize = 0x020;
+const INSTR_MEM0: usize = 0x048;
+const SM0: usize = 0x0C8;
+const SM_STRIDE: usize = 0x18;
+
+// State machine registers
+const SM_CLKDIV: usize = 0x00;
+const SM_EXECCTRL: usize = 0x04;
+const SM_SHIFTCTRL: usize = 0x08;
+const SM_INSTR: usize = 0x10;
+const SM_PINCTRL: usize = 0x14;
+
+// Atomic register aliases
+const ALIAS_SET: usize = 0x2000;
+const ALIAS_CLR: usize = 0x3000;
+
+const CTRL_SM_RESTART_SHIFT: u32 = 4;
+const CTRL_CLKDIV_RESTART_SHIFT: u32 = 8;
+
+const FSTAT_RXEMPTY_SHIFT: u32 = 8;
+const FSTAT_TXFULL_SHIFT: u32 = 16;
+
+const EXECCTRL_SIDE_EN: u32 = 1 << 30;
+const EXECCTRL_SIDE_PINDIR: u32 = 1 << 29;
+const EXECCTRL_JMP_PIN_SHIFT: u32 = 24;
+const EXECCTRL_WRAP_TOP_SHIFT: u32 = 12;
+const EXECCTRL_WRAP_BOTTOM_SHIFT: u32 = 7;
+
+const SHIFTCTRL_FJOIN_RX: u32 = 1 << 31;
+const SHIFTCTRL_FJOIN_TX: u32 = 1 << 30;
+const SHIFTCTRL_PULL_THRESH_SHIFT: u32 = 25;
+const SHIFTCTRL_PUSH_THRESH_SHIFT: u32 = 20;
+const SHIFTCTRL_OUT_SHIFTDIR: u32 = 1 << 19;
+const SHIFTCTRL_IN_SHIFTDIR: u32 = 1 << 18;
+const SHIFTCTRL_AUTOPULL: u32 = 1 << 17;
+const SHIFTCTRL_AUTOPUSH: u32 = 1 << 16;
+
+const PINCTRL_SIDESET_COUNT_SHIFT: u32 = 29;
+const PINCTRL_SET_COUNT_SHIFT: u32 = 26;
+const PINCTRL_OUT_COUNT_SHIFT: u32 = 20;
+const PINCTRL_IN_BASE_SHIFT: u32 = 15;
+const PINCTRL_SIDESET_BASE_SHIFT: u32 = 10;
+const PINCTRL_SET_BASE_SHIFT: u32 = 5;
+
+/// State machines per block
+pub const STATE_MACHINES: u8 = 4;
+
+/// Pad function of PIO0; PIO1 and the RP2350's PIO2 follow
+const FUNCSEL_PIO0: u8 = 6;
+
+/// PIO block
+pub struct Rp2040Pio {
+    base: usize,
+    index: u8,
+    // Instruction memory words in use
+    used: Cell<u32>,
+    // State machines handed out
+    claimed: Cell<u8>,
+}
+
+impl Rp2040Pio {
+    /// Create a driver for PIO block `index` at `base`
+    pub const fn new(base: usize, index: u8) -> Self {
+        Self {
+            base,
+            index,
+            used: Cell::new(0),
+            claimed: Cell::new(0),
+        }
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    /// Pad function routing a pin to this block
+    pub const fn gpio_function(&self) -> AltFn {
+        AltFn(FUNCSEL_PIO0 + self.index)
+    }
+
+    /// Route `pins` to this block through `pinmux`
+    ///
+    /// The pad function decides which block drives a pin, while the state
+    /// machine configuration decides which of its state machines do.
+    pub fn claim_pins<C: PinController>(
+        &self,
+        pins: PinRange,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<(), PinmuxError<C::Error>> {
+        for pin in pins.base..pins.base + pins.count {
+            pinmux.claim_pin(
+                u16::from(pin),
+                self.gpio_function(),
+                Peripheral::Pio(self.index),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Take state machine `sm`
+    ///
+    /// Fails with [`Error::Busy`] while it is handed out already.
+    pub fn state_machine(&self, sm: u8) -> Result<Rp2040StateMachine<'_>, Error> {
+        if sm >= STATE_MACHINES {
+            return Err(Error::InvalidParameter);
+        }
+        let claimed = self.claimed.get();
+        if claimed & (1 << sm) != 0 {
+            return Err(Error::Busy);
+        }
+        self.claimed.set(claimed | (1 << sm));
+        Ok(Rp2040StateMachine {
+            pio: self,
+            sm,
+            program: None,
+        })
+    }
+
+    /// Find room for `len` instructions, at `origin` if given
+    fn allocate(&self, len: usize, origin: Option<u8>) -> Option<u8> {
+        let mask = if len == MAX_PROGRAM_LEN {
+            u32::MAX
+        } else {
+            (1 << len) - 1
+        };
+        let used = self.used.get();
+        let offset = match origin {
+            Some(origin) => Some(origin).filter(|&origin| used & (mask << origin) == 0),
+            // Top down, like the SDK, leaving offset 0 for fixed programs
+            None => (0..=(MAX_PROGRAM_LEN - len) as u8)
+                .rev()
+                .find(|&offset| used & (mask << offset) == 0),
+        }?;
+        self.used.set(used | (mask << offset));
+        Some(offset)
+    }
+
+    fn release(&self, offset: u8, len: u8) {
+        let mask = if usize::from(len) == MAX_PROGRAM_LEN {
+            u32::MAX
+        } else {
+            (1 << len) - 1
+        };
+        self.used.set(self.used.get() & !(mask << offset));
+    }
+}
+
+/// State machine of an [`Rp2040Pio`]
+pub struct Rp2040StateMachine<'a> {
+    pio: &'a Rp2040Pio,
+    sm: u8,
+    // Offset and length of the loaded program
+    program: Option<(u8, u8)>,
+}
+
+impl Rp2040StateMachine<'_> {
+    fn reg(&self, offset: usize) -> usize {
+        SM0 + usize::from(self.sm) * SM_STRIDE + offset
+    }
+
+    unsafe fn read_sm(&self, offset: usize) -> u32 {
+        self.pio.read_reg(self.reg(offset))
+    }
+
+    unsafe fn write_sm(&self, offset: usize, value: u32) {
+        self.pio.write_reg(self.reg(offset), value);
+    }
+
+    /// State machine number within its block
+    pub fn index(&self) -> u8 {
+        self.sm
+    }
+
+    /// Run SET on consecutive pins, five at a time
+    fn set_pin_field(
+        &mut self,
+        pins: PinRange,
+        destination: SetDestination,
+        value: bool,
+    ) -> Result<(), Error> {
+        if u16::from(pins.base) + u16::from(pins.count) > 32 {
+            return Err(Error::InvalidParameter);
+        }
+        let pinctrl = unsafe { self.read_sm(SM_PINCTRL) };
+        let execctrl = unsafe { self.read_sm(SM_EXECCTRL) };
+        // Without side-set, so the delay field is all delay
+        unsafe { self.write_sm(SM_EXECCTRL, execctrl & !EXECCTRL_SIDE_EN) };
+
+        let mut base = pins.base;
+        let end = pins.base + pins.count;
+        while base < end {
+            let count = (end - base).min(5);
+            let data = if value { (1 << count) - 1 } else { 0 };
+            unsafe {
+                self.write_sm(
+                    SM_PINCTRL,
+                    (u32::from(count) << PINCTRL_SET_COUNT_SHIFT)
+                        | (u32::from(base) << PINCTRL_SET_BASE_SHIFT),
+                );
+            }
+            self.exec(Instruction::set(destination, data));
+            base += count;
+        }
+
+        unsafe {
+            self.write_sm(SM_PINCTRL, pinctrl);
+            self.write_sm(SM_EXECCTRL, execctrl);
+        }
+        Ok(())
+    }
+}
+
+impl PioStateMachine for Rp2040StateMachine<'_> {
+    type Error = Error;
+
+    fn load(
+        &mut self,
+        program: &PioProgram<'_>,
+        config: &StateMachineConfig,
+    ) -> Result<u8, Self::Error> {
+        program.validate()?;
+        let pins_valid = |base: u8, count: u8| base < 32 && count <= 32;
+        if !pins_valid(config.out_pins.base, config.out_pins.count)
+            || !pins_valid(config.set_pins.base, config.set_pins.count.min(5))
+            || config.set_pins.count > 5
+            || config.in_base >= 32
+            || config.side_set_base >= 32
+            || config.jmp_pin >= 32
+        {
+            return Err(Error::InvalidParameter);
+        }
+        if config.clock_divider.integer == 0 {
+            return Err(Error::InvalidConfig);
+        }
+
+        self.unload();
+        let len = program.code.len();
+        let offset = self.pio.allocate(len, program.origin).ok_or(Error::Busy)?;
+        self.program = Some((offset, len as u8));
+
+        for index in 0..len {
+            let slot = INSTR_MEM0 + (usize::from(offset) + index) * 4;
+            unsafe {
+                self.pio
+                    .write_reg(slot, u32::from(program.relocated(index, offset)))
+            };
+        }
+
+        let side_set = program.side_set;
+        let mut execctrl = (u32::from(config.jmp_pin) << EXECCTRL_JMP_PIN_SHIFT)
+            | (u32::from(offset + program.wrap) << EXECCTRL_WRAP_TOP_SHIFT)
+            | (u32::from(offset + program.wrap_target) << EXECCTRL_WRAP_BOTTOM_SHIFT);
+        if side_set.optional {
+            execctrl |= EXECCTRL_SIDE_EN;
+        }
+        if side_set.pindirs {
+            execctrl |= EXECCTRL_SIDE_PINDIR;
+        }
+
+        // Thresholds of 32 are encoded as 0
+        let mut shiftctrl = (u32::from(config.out_shift.threshold & 0x1F)
+            << SHIFTCTRL_PULL_THRESH_SHIFT)
+            | (u32::from(config.in_shift.threshold & 0x1F) << SHIFTCTRL_PUSH_THRESH_SHIFT);
+        if config.out_shift.direction == ShiftDirection::Right {
+            shiftctrl |= SHIFTCTRL_OUT_SHIFTDIR;
+        }
+        if config.in_shift.direction == ShiftDirection::Right {
+            shiftctrl |= SHIFTCTRL_IN_SHIFTDIR;
+        }
+        if config.out_shift.auto {
+            shiftctrl |= SHIFTCTRL_AUTOPULL;
+        }
+        if config.in_shift.auto {
+            shiftctrl |= SHIFTCTRL_AUTOPUSH;
+        }
+        shiftctrl |= match config.fifo_join {
+            FifoJoin::None => 0,
+            FifoJoin::Tx => SHIFTCTRL_FJOIN_TX,
+            FifoJoin::Rx => SHIFTCTRL_FJOIN_RX,
+        };
+
+        let pinctrl = (u32::from(side_set.field_bits()) << PINCTRL_SIDESET_COUNT_SHIFT)
+            | (u32::from(config.set_pins.count) << PINCTRL_SET_COUNT_SHIFT)
+            | (u32::from(config.out_pins.count & 0x3F) << PINCTRL_OUT_COUNT_SHIFT)
+            | (u32::from(config.in_base) << PINCTRL_IN_BASE_SHIFT)
+            | (u32::from(config.side_set_base) << PINCTRL_SIDESET_BASE_SHIFT)
+            | (u32::from(config.set_pins.base) << PINCTRL_SET_BASE_SHIFT)
+            | u32::from(config.out_pins.base);
+
+        let clkdiv = (u32::from(config.clock_divider.integer) << 16)
+            | (u32::from(config.clock_divider.fraction) << 8);
+
+        unsafe {
+            self.write_sm(SM_CLKDIV, clkdiv);
+            self.write_sm(SM_EXECCTRL, execctrl);
+            self.write_sm(SM_SHIFTCTRL, shiftctrl);
+            self.write_sm(SM_PINCTRL, pinctrl);
+        }
+        self.clear_fifos();
+        self.restart();
+        self.exec(Instruction::jmp(JmpCondition::Always, offset));
+        Ok(offset)
+    }
+
+    fn unload(&mut self) {
+        self.set_enabled(false);
+        if let Some((offset, len)) = self.program.take() {
+            self.pio.release(offset, len);
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        let alias = if enabled { ALIAS_SET } else { ALIAS_CLR };
+        unsafe { self.pio.write_reg(CTRL + alias, 1 << self.sm) };
+    }
+
+    fn restart(&mut self) {
+        // Both bits clear themselves
+        let bits = (1 << (CTRL_SM_RESTART_SHIFT + u32::from(self.sm)))
+            | (1 << (CTRL_CLKDIV_RESTART_SHIFT + u32::from(self.sm)));
+        unsafe { self.pio.write_reg(CTRL + ALIAS_SET, bits) };
+    }
+
+    fn exec(&mut self, instruction: Instruction) {
+        unsafe { self.write_sm(SM_INSTR, u32::from(instruction.0)) };
+    }
+
+    fn set_pins(&mut self, pins: PinRange, high: bool) -> Result<(), Self::Error> {
+        self.set_pin_field(pins, SetDestination::Pins, high)
+    }
+
+    fn set_pin_dirs(&mut self, pins: PinRange, output: bool) -> Result<(), Self::Error> {
+        self.set_pin_field(pins, SetDestination::PinDirs, output)
+    }
+
+    fn try_push(&mut self, word: u32) -> bool {
+        let fstat = unsafe { self.pio.read_reg(FSTAT) };
+        if fstat & (1 << (FSTAT_TXFULL_SHIFT + u32::from(self.sm))) != 0 {
+            return false;
+        }
+        unsafe { self.pio.write_reg(TXF0 + usize::from(self.sm) * 4, word) };
+        true
+    }
+
+    fn try_pull(&mut self) -> Option<u32> {
+        let fstat = unsafe { self.pio.read_reg(FSTAT) };
+        if fstat & (1 << (FSTAT_RXEMPTY_SHIFT + u32::from(self.sm))) != 0 {
+            return None;
+        }
+        Some(unsafe { self.pio.read_reg(RXF0 + usize::from(self.sm) * 4) })
+    }
+
+    fn rx_level(&self) -> usize {
+        let flevel = unsafe { self.pio.read_reg(FLEVEL) };
+        ((flevel >> (u32::from(self.sm) * 8 + 4)) & 0xF) as usize
+    }
+
+    fn clear_fifos(&mut self) {
+        // Changing the FIFO join empties both FIFOs
+        unsafe {
+            let shiftctrl = self.read_sm(SM_SHIFTCTRL);
+            self.write_sm(SM_SHIFTCTRL, shiftctrl ^ SHIFTCTRL_FJOIN_RX);
+            self.write_sm(SM_SHIFTCTRL, shiftctrl);
+        }
+    }
+}
+
+impl Drop for Rp2040StateMachine<'_> {
+    fn drop(&mut self) {
+        self.unload();
+        self.pio
+            .claimed
+            .set(self.pio.claimed.get() & !(1 << self.sm));
+    }
+}
@@ -0,0 +1,717 @@
+//! SD Host Controller Interface (SDHCI) driver
+//!
+//! Drives one SD card slot of a controller following the SD Host Controller
+//! specification 2.0/3.0, such as the Arasan EMMC controller of the BCM283x
+//! and the EMMC2 controller of the BCM2711. The BCM2835 `sdhost` controller
+//! is not SDHCI compatible and isn't covered.
+//!
+//! The card is brought up in SD default speed with a 4-bit bus. Data moves
+//! through the buffer data port, or with ADMA2 once [`Sdhci::enable_dma`]
+//! was called on a controller that supports it. Registers are only ever
+//! accessed 32 bits wide, as the Arasan controller requires.
+//!
+//! With the `driver-block` feature, [`SdhciDisk`] implements driver-block's
+//! `Disk`, so the card can back a `disk.sd` scheme for RedoxFS.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use redox_hal::dma::{CacheMaintenance, Coherent};
+use redox_hal::timer::Delay;
+use redox_hal::Error;
+
+/// Register offsets
+mod regs {
+    pub const BLOCK: usize = 0x04; // Block size, block count
+    pub const ARGUMENT: usize = 0x08; // Command argument
+    pub const COMMAND: usize = 0x0C; // Transfer mode, command
+    pub const RESPONSE: usize = 0x10; // Response, four words
+    pub const DATA: usize = 0x20; // Buffer data port
+    pub const PRESENT_STATE: usize = 0x24; // Inhibit bits, card detect
+    pub const HOST_CONTROL: usize = 0x28; // Host control 1, power control
+    pub const CLOCK_CONTROL: usize = 0x2C; // Clock, timeout, software reset
+    pub const INT_STATUS: usize = 0x30; // Normal and error interrupt status
+    pub const INT_STATUS_ENABLE: usize = 0x34; // Status bits that get set
+    pub const INT_SIGNAL_ENABLE: usize = 0x38; // Status bits that interrupt
+    pub const CAPABILITIES: usize = 0x40; // Capabilities, low word
+    pub const ADMA_ADDRESS: usize = 0x58; // ADMA descriptor table
+    pub const HOST_VERSION: usize = 0xFC; // Slot status, spec version
+}
+
+/// COMMAND bits, transfer mode in the low half
+mod cmd {
+    pub const DMA: u32 = 1 << 0;
+    pub const BLOCK_COUNT: u32 = 1 << 1;
+    pub const AUTO_CMD12: u32 = 1 << 2;
+    pub const READ: u32 = 1 << 4;
+    pub const MULTI_BLOCK: u32 = 1 << 5;
+    pub const RESPONSE_136: u32 = 1 << 16;
+    pub const RESPONSE_48: u32 = 2 << 16;
+    pub const RESPONSE_48_BUSY: u32 = 3 << 16;
+    pub const CRC_CHECK: u32 = 1 << 19;
+    pub const INDEX_CHECK: u32 = 1 << 20;
+    pub const DATA_PRESENT: u32 = 1 << 21;
+    pub const INDEX_SHIFT: u32 = 24;
+}
+
+/// INT_STATUS bits, error status in the high half
+mod int {
+    pub const COMMAND_COMPLETE: u32 = 1 << 0;
+    pub const TRANSFER_COMPLETE: u32 = 1 << 1;
+    pub const BUFFER_WRITE_READY: u32 = 1 << 4;
+    pub const BUFFER_READ_READY: u32 = 1 << 5;
+    pub const ERROR: u32 = 1 << 15;
+    pub const COMMAND_TIMEOUT: u32 = 1 << 16;
+    pub const COMMAND_CRC: u32 = 1 << 17;
+    pub const DATA_TIMEOUT: u32 = 1 << 20;
+    pub const DATA_CRC: u32 = 1 << 21;
+    pub const ADMA: u32 = 1 << 25;
+    pub const ALL: u32 = 0xFFFF_FFFF;
+}
+
+const PRESENT_COMMAND_INHIBIT: u32 = 1 << 0;
+const PRESENT_DATA_INHIBIT: u32 = 1 << 1;
+const PRESENT_CARD_INSERTED: u32 = 1 << 16;
+
+const HOST_DATA_WIDTH_4: u32 = 1 << 1;
+const HOST_DMA_SELECT: u32 = 3 << 3;
+const HOST_DMA_ADMA2: u32 = 2 << 3;
+const HOST_POWER_ON: u32 = 1 << 8;
+const HOST_POWER_3V3: u32 = 7 << 9;
+
+const CLOCK_INTERNAL_ENABLE: u32 = 1 << 0;
+const CLOCK_INTERNAL_STABLE: u32 = 1 << 1;
+const CLOCK_CARD_ENABLE: u32 = 1 << 2;
+const CLOCK_DIVIDER: u32 = 0xFFC0;
+const CLOCK_TIMEOUT_MAX: u32 = 0xE << 16;
+const RESET_ALL: u32 = 1 << 24;
+const RESET_COMMAND: u32 = 1 << 25;
+const RESET_DATA: u32 = 1 << 26;
+
+const CAPS_ADMA2: u32 = 1 << 19;
+
+/// SD commands
+mod sd {
+    pub const GO_IDLE_STATE: u32 = 0;
+    pub const ALL_SEND_CID: u32 = 2;
+    pub const SEND_RELATIVE_ADDR: u32 = 3;
+    pub const SET_BUS_WIDTH: u32 = 6; // Application command
+    pub const SELECT_CARD: u32 = 7;
+    pub const SEND_IF_COND: u32 = 8;
+    pub const SEND_CSD: u32 = 9;
+    pub const SET_BLOCKLEN: u32 = 16;
+    pub const READ_SINGLE_BLOCK: u32 = 17;
+    pub const READ_MULTIPLE_BLOCK: u32 = 18;
+    pub const WRITE_BLOCK: u32 = 24;
+    pub const WRITE_MULTIPLE_BLOCK: u32 = 25;
+    pub const SD_SEND_OP_COND: u32 = 41; // Application command
+    pub const APP_CMD: u32 = 55;
+}
+
+/// CMD8 argument: 2.7-3.6 V and a check pattern
+const IF_COND_3V3: u32 = 0x1AA;
+/// ACMD41 argument: 3.2-3.4 V window
+const OCR_3V3: u32 = 0x0030_0000;
+const OCR_HIGH_CAPACITY: u32 = 1 << 30;
+const OCR_POWERED_UP: u32 = 1 << 31;
+
+/// Block size, in bytes
+pub const BLOCK_SIZE: usize = 512;
+
+/// Identification clock
+const CLOCK_INIT: u32 = 400_000;
+/// Default speed clock
+const CLOCK_DEFAULT_SPEED: u32 = 25_000_000;
+
+/// Most blocks in one command, from the 16-bit block count
+const MAX_BLOCKS: usize = 0xFFFF;
+/// Bytes per ADMA2 descriptor, a whole number of blocks below 64 KiB
+const ADMA_CHUNK: usize = 0xFE00;
+
+const ADMA_VALID: u64 = 1 << 0;
+const ADMA_END: u64 = 1 << 1;
+const ADMA_TRANSFER: u64 = 2 << 4;
+
+// Register polls before giving up
+const POLL_LIMIT: u32 = 1_000_000;
+
+/// Card found by [`Sdhci::init`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardInfo {
+    /// Relative card address
+    pub rca: u16,
+    /// Blocks are addressed by number rather than by byte (SDHC/SDXC)
+    pub high_capacity: bool,
+    /// Capacity in blocks of [`BLOCK_SIZE`]
+    pub blocks: u64,
+    /// Card identification register
+    pub cid: [u32; 4],
+}
+
+/// SD host controller slot
+///
+/// `M` does the cache maintenance around DMA transfers, and can be left as
+/// [`Coherent`] when DMA isn't enabled.
+pub struct Sdhci<M: CacheMaintenance = Coherent> {
+    base: usize,
+    base_clock: u32,
+    version: u32,
+    card: Option<CardInfo>,
+    // Offset from CPU to bus addresses, set when ADMA2 is used
+    dma_offset: Option<usize>,
+    adma_table: Vec<u64>,
+    _cache: PhantomData<M>,
+}
+
+impl<M: CacheMaintenance> Sdhci<M> {
+    /// Create a driver for the controller at `base`
+    ///
+    /// `base_clock` is the controller input clock in Hz, used when the
+    /// capabilities register doesn't report it (it is 0 on the BCM283x).
+    pub fn new(base: usize, base_clock: u32) -> Self {
+        Self {
+            base,
+            base_clock,
+            version: 0,
+            card: None,
+            dma_offset: None,
+            adma_table: Vec::new(),
+            _cache: PhantomData,
+        }
+    }
+
+    unsafe fn read_reg(&self, offset: usize) -> u32 {
+        core::ptr::read_volatile((self.base + offset) as *const u32)
+    }
+
+    unsafe fn write_reg(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.base + offset) as *mut u32, value);
+    }
+
+    /// Move data with ADMA2 if the controller supports it
+    ///
+    /// Buffers and the descriptor table are handed to the controller at
+    /// their CPU address plus `bus_offset`, so they have to be in memory
+    /// the controller can reach at that offset.
+    pub fn enable_dma(&mut self, bus_offset: usize) -> Result<(), Error> {
+        if unsafe { self.read_reg(regs::CAPABILITIES) } & CAPS_ADMA2 == 0 {
+            return Err(Error::NotAvailable);
+        }
+        self.dma_offset = Some(bus_offset);
+        Ok(())
+    }
+
+    /// Whether data moves with ADMA2
+    pub fn dma_enabled(&self) -> bool {
+        self.dma_offset.is_some()
+    }
+
+    /// Specification version, 0 for 1.0, 1 for 2.0, 2 for 3.0
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Card found by [`Sdhci::init`]
+    pub fn card(&self) -> Option<&CardInfo> {
+        self.card.as_ref()
+    }
+
+    /// Whether a card sits in the slot
+    pub fn card_present(&self) -> bool {
+        (unsafe { self.read_reg(regs::PRESENT_STATE) } & PRESENT_CARD_INSERTED) != 0
+    }
+
+    fn wait(&self, offset: usize, mask: u32, value: u32) -> Result<(), Error> {
+        for _ in 0..POLL_LIMIT {
+            if unsafe { self.read_reg(offset) } & mask == value {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Reset parts of the controller selected by `bits`
+    fn reset(&self, bits: u32) -> Result<(), Error> {
+        unsafe {
+            let clock = self.read_reg(regs::CLOCK_CONTROL);
+            self.write_reg(regs::CLOCK_CONTROL, clock | bits);
+        }
+        self.wait(regs::CLOCK_CONTROL, bits, 0)
+    }
+
+    /// Controller input clock in Hz
+    fn input_clock(&self) -> u32 {
+        let caps = unsafe { self.read_reg(regs::CAPABILITIES) };
+        // 6 bits of MHz before 3.0, 8 bits since
+        let mask = if self.version >= 2 { 0xFF } else { 0x3F };
+        match (caps >> 8) & mask {
+            0 => self.base_clock,
+            mhz => mhz * 1_000_000,
+        }
+    }
+
+    /// Run the card clock at the fastest rate not above `frequency`
+    fn set_clock(&self, frequency: u32) -> Result<(), Error> {
+        let input = self.input_clock();
+        if input == 0 || frequency == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        // SDCLK = input / (2 * divider), divider 0 passing the input through
+        let divider = if frequency >= input {
+            0
+        } else if self.version >= 2 {
+            // 10-bit divided clock mode
+            input.div_ceil(2 * frequency).min(0x3FF)
+        } else {
+            // 8-bit divider, a power of two
+            input.div_ceil(2 * frequency).next_power_of_two().min(0x80)
+        };
+        let field = (divider & 0xFF) << 8 | (divider >> 8) << 6;
+
+        unsafe {
+            let value = self.read_reg(regs::CLOCK_CONTROL)
+                & !(CLOCK_CARD_ENABLE | CLOCK_INTERNAL_ENABLE | CLOCK_DIVIDER | 0xF << 16);
+            self.write_reg(regs::CLOCK_CONTROL, value);
+            self.write_reg(
+                regs::CLOCK_CONTROL,
+                value | field | CLOCK_TIMEOUT_MAX | CLOCK_INTERNAL_ENABLE,
+            );
+        }
+        self.wait(
+            regs::CLOCK_CONTROL,
+            CLOCK_INTERNAL_STABLE,
+            CLOCK_INTERNAL_STABLE,
+        )?;
+        unsafe {
+            let value = self.read_reg(regs::CLOCK_CONTROL);
+            self.write_reg(regs::CLOCK_CONTROL, value | CLOCK_CARD_ENABLE);
+        }
+        Ok(())
+    }
+
+    /// Map error interrupt bits to an error, resetting the failed lines
+    fn check_errors(&self, status: u32) -> Result<(), Error> {
+        if status & int::ERROR == 0 {
+            return Ok(());
+        }
+        unsafe { self.write_reg(regs::INT_STATUS, status & 0xFFFF_0000 | int::ERROR) };
+        let _ = self.reset(RESET_COMMAND | RESET_DATA);
+        let err = if status & (int::COMMAND_TIMEOUT | int::DATA_TIMEOUT) != 0 {
+            Error::Timeout
+        } else if status & (int::COMMAND_CRC | int::DATA_CRC) != 0 {
+            Error::CrcError
+        } else if status & int::ADMA != 0 {
+            Error::DmaError
+        } else {
+            Error::BusError
+        };
+        Err(err)
+    }
+
+    /// Wait for any of the `bits` interrupt status bits and clear them
+    fn wait_status(&self, bits: u32) -> Result<(), Error> {
+        for _ in 0..POLL_LIMIT {
+            let status = unsafe { self.read_reg(regs::INT_STATUS) };
+            self.check_errors(status)?;
+            if status & bits != 0 {
+                unsafe { self.write_reg(regs::INT_STATUS, status & bits) };
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        let _ = self.reset(RESET_COMMAND | RESET_DATA);
+        Err(Error::Timeout)
+    }
+
+    /// Issue a command, with `flags` giving the response type and the
+    /// transfer mode, and return the first response word
+    fn command(&self, index: u32, argument: u32, flags: u32) -> Result<u32, Error> {
+        let inhibit = if flags & (cmd::DATA_PRESENT | cmd::RESPONSE_48_BUSY) != 0 {
+            PRESENT_COMMAND_INHIBIT | PRESENT_DATA_INHIBIT
+        } else {
+            PRESENT_COMMAND_INHIBIT
+        };
+        self.wait(regs::PRESENT_STATE, inhibit, 0)?;
+        unsafe {
+            self.write_reg(regs::INT_STATUS, int::ALL);
+            self.write_reg(regs::ARGUMENT, argument);
+            self.write_reg(regs::COMMAND, index << cmd::INDEX_SHIFT | flags);
+        }
+        self.wait_status(int::COMMAND_COMPLETE)?;
+        if flags & cmd::RESPONSE_48_BUSY == cmd::RESPONSE_48_BUSY && flags & cmd::DATA_PRESENT == 0
+        {
+            // Busy signalling ends with a transfer complete
+            self.wait_status(int::TRANSFER_COMPLETE)?;
+        }
+        Ok(unsafe { self.read_reg(regs::RESPONSE) })
+    }
+
+    /// Response of a command with a 136-bit response, CRC stripped
+    fn long_response(&self) -> [u32; 4] {
+        let mut response = [0; 4];
+        for (index, word) in response.iter_mut().enumerate() {
+            *word = unsafe { self.read_reg(regs::RESPONSE + index * 4) };
+        }
+        response
+    }
+
+    /// Issue an application command
+    fn app_command(&self, index: u32, argument: u32, flags: u32) -> Result<u32, Error> {
+        let rca = self.card.map_or(0, |card| card.rca);
+        self.command(sd::APP_CMD, u32::from(rca) << 16, R1)?;
+        self.command(index, argument, flags)
+    }
+
+    /// Reset the controller and identify the card in the slot
+    ///
+    /// Leaves the card selected, with a 4-bit bus at default speed.
+    pub fn init(&mut self, delay: &mut dyn Delay) -> Result<CardInfo, Error> {
+        self.card = None;
+        self.version = (unsafe { self.read_reg(regs::HOST_VERSION) } >> 16) & 0xFF;
+
+        self.reset(RESET_ALL)?;
+        if !self.card_present() {
+            return Err(Error::NotAvailable);
+        }
+        unsafe {
+            self.write_reg(regs::HOST_CONTROL, HOST_POWER_3V3);
+            self.write_reg(regs::HOST_CONTROL, HOST_POWER_3V3 | HOST_POWER_ON);
+            self.write_reg(regs::INT_STATUS_ENABLE, int::ALL);
+            // Completion is polled
+            self.write_reg(regs::INT_SIGNAL_ENABLE, 0);
+        }
+        self.set_clock(CLOCK_INIT)?;
+        // 74 clocks of power up time
+        delay.delay_ms(1);
+
+        self.command(sd::GO_IDLE_STATE, 0, 0)?;
+        // Cards before 2.0 don't know CMD8 and stay silent
+        let version_2 = match self.command(sd::SEND_IF_COND, IF_COND_3V3, R7) {
+            Ok(response) if response & 0xFFF == IF_COND_3V3 => true,
+            Ok(_) => return Err(Error::HardwareFailure),
+            Err(Error::Timeout) => false,
+            Err(err) => return Err(err),
+        };
+
+        let argument = if version_2 {
+            OCR_3V3 | OCR_HIGH_CAPACITY
+        } else {
+            OCR_3V3
+        };
+        let mut ocr = 0;
+        // Cards have up to a second to power up
+        for _ in 0..1000 {
+            ocr = self.app_command(sd::SD_SEND_OP_COND, argument, R3)?;
+            if ocr & OCR_POWERED_UP != 0 {
+                break;
+            }
+            delay.delay_ms(1);
+        }
+        if ocr & OCR_POWERED_UP == 0 {
+            return Err(Error::Timeout);
+        }
+        let high_capacity = ocr & OCR_HIGH_CAPACITY != 0;
+
+        self.command(sd::ALL_SEND_CID, 0, R2)?;
+        let cid = self.long_response();
+        let rca = (self.command(sd::SEND_RELATIVE_ADDR, 0, R6)? >> 16) as u16;
+        self.command(sd::SEND_CSD, u32::from(rca) << 16, R2)?;
+        let blocks = csd_blocks(&self.long_response()).ok_or(Error::HardwareFailure)?;
+        self.command(sd::SELECT_CARD, u32::from(rca) << 16, R1B)?;
+
+        self.card = Some(CardInfo {
+            rca,
+            high_capacity,
+            blocks,
+            cid,
+        });
+
+        if !high_capacity {
+            self.command(sd::SET_BLOCKLEN, BLOCK_SIZE as u32, R1)?;
+        }
+        // 4-bit bus, which every SD card supports
+        self.app_command(sd::SET_BUS_WIDTH, 2, R1)?;
+        unsafe {
+            let host = self.read_reg(regs::HOST_CONTROL);
+            self.write_reg(regs::HOST_CONTROL, host | HOST_DATA_WIDTH_4);
+        }
+        self.set_clock(CLOCK_DEFAULT_SPEED)?;
+
+        Ok(self.card.unwrap())
+    }
+
+    /// Check a transfer of `len` bytes at `block` and return its argument
+    fn transfer_argument(&self, block: u64, len: usize) -> Result<u32, Error> {
+        let card = self.card.ok_or(Error::NotInitialized)?;
+        if !len.is_multiple_of(BLOCK_SIZE) {
+            return Err(Error::InvalidParameter);
+        }
+        let count = (len / BLOCK_SIZE) as u64;
+        if block.checked_add(count).is_none_or(|end| end > card.blocks) {
+            return Err(Error::InvalidParameter);
+        }
+        // Standard capacity cards take byte addresses
+        let address = if card.high_capacity {
+            block
+        } else {
+            block * BLOCK_SIZE as u64
+        };
+        u32::try_from(address).map_err(|_| Error::InvalidParameter)
+    }
+
+    /// Read whole blocks starting at `block` into `buffer`
+    pub fn read_blocks(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        for (index, chunk) in buffer.chunks_mut(MAX_BLOCKS * BLOCK_SIZE).enumerate() {
+            let start = block + (index * MAX_BLOCKS) as u64;
+            self.transfer(start, chunk.as_mut_ptr(), chunk.len(), true)?;
+        }
+        Ok(())
+    }
+
+    /// Write whole blocks from `buffer` starting at `block`
+    pub fn write_blocks(&mut self, block: u64, buffer: &[u8]) -> Result<(), Error> {
+        for (index, chunk) in buffer.chunks(MAX_BLOCKS * BLOCK_SIZE).enumerate() {
+            let start = block + (index * MAX_BLOCKS) as u64;
+            // The buffer is only read on writes
+            self.transfer(start, chunk.as_ptr() as *mut u8, chunk.len(), false)?;
+        }
+        Ok(())
+    }
+
+    /// Move `len` bytes at `data` from or to the card in one command
+    fn transfer(&mut self, block: u64, data: *mut u8, len: usize, read: bool) -> Result<(), Error> {
+        let argument = self.transfer_argument(block, len)?;
+        let count = len / BLOCK_SIZE;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut flags = R1 | cmd::DATA_PRESENT;
+        let index = match (read, count) {
+            (true, 1) => sd::READ_SINGLE_BLOCK,
+            (true, _) => sd::READ_MULTIPLE_BLOCK,
+            (false, 1) => sd::WRITE_BLOCK,
+            (false, _) => sd::WRITE_MULTIPLE_BLOCK,
+        };
+        if read {
+            flags |= cmd::READ;
+        }
+        if count > 1 {
+            flags |= cmd::MULTI_BLOCK | cmd::BLOCK_COUNT | cmd::AUTO_CMD12;
+        }
+
+        unsafe {
+            self.write_reg(regs::BLOCK, (count as u32) << 16 | BLOCK_SIZE as u32);
+            let host = self.read_reg(regs::HOST_CONTROL) & !HOST_DMA_SELECT;
+            let dma = if self.dma_offset.is_some() {
+                HOST_DMA_ADMA2
+            } else {
+                0
+            };
+            self.write_reg(regs::HOST_CONTROL, host | dma);
+        }
+
+        match self.dma_offset {
+            Some(offset) => self.transfer_dma(index, argument, flags, data, len, offset),
+            None => self.transfer_pio(index, argument, flags, data, count),
+        }
+    }
+
+    fn transfer_pio(
+        &self,
+        index: u32,
+        argument: u32,
+        flags: u32,
+        data: *mut u8,
+        count: usize,
+    ) -> Result<(), Error> {
+        self.command(index, argument, flags)?;
+        let read = flags & cmd::READ != 0;
+        let ready = if read {
+            int::BUFFER_READ_READY
+        } else {
+            int::BUFFER_WRITE_READY
+        };
+        for block in 0..count {
+            self.wait_status(ready)?;
+            for word in 0..BLOCK_SIZE / 4 {
+                // The buffer may not be word aligned
+                let ptr = unsafe { data.add(block * BLOCK_SIZE + word * 4) } as *mut [u8; 4];
+                unsafe {
+                    if read {
+                        let value = self.read_reg(regs::DATA);
+                        ptr.write_unaligned(value.to_le_bytes());
+                    } else {
+                        self.write_reg(regs::DATA, u32::from_le_bytes(ptr.read_unaligned()));
+                    }
+                }
+            }
+        }
+        self.wait_status(int::TRANSFER_COMPLETE)
+    }
+
+    fn transfer_dma(
+        &mut self,
+        index: u32,
+        argument: u32,
+        flags: u32,
+        data: *mut u8,
+        len: usize,
+        offset: usize,
+    ) -> Result<(), Error> {
+        let descriptors = len.div_ceil(ADMA_CHUNK);
+        self.adma_table.clear();
+        self.adma_table.resize(descriptors, 0);
+        for (number, descriptor) in self.adma_table.iter_mut().enumerate() {
+            let start = number * ADMA_CHUNK;
+            let size = (len - start).min(ADMA_CHUNK) as u64;
+            let address = (data as usize + start + offset) as u64;
+            if address > u64::from(u32::MAX) {
+                return Err(Error::DmaError);
+            }
+            let end = if number + 1 == descriptors {
+                ADMA_END
+            } else {
+                0
+            };
+            *descriptor = address << 32 | size << 16 | ADMA_TRANSFER | end | ADMA_VALID;
+        }
+
+        let table = self.adma_table.as_ptr() as usize;
+        let table_len = descriptors * core::mem::size_of::<u64>();
+        let table_address = u32::try_from(table + offset).map_err(|_| Error::DmaError)?;
+        let read = flags & cmd::READ != 0;
+        unsafe {
+            M::clean_range(table, table_len);
+            if read {
+                M::clean_invalidate_range(data as usize, len);
+            } else {
+                M::clean_range(data as usize, len);
+            }
+            self.write_reg(regs::ADMA_ADDRESS, table_address);
+        }
+
+        let result = self
+            .command(index, argument, flags | cmd::DMA)
+            .and_then(|_| self.wait_status(int::TRANSFER_COMPLETE));
+        if read {
+            // Drop lines fetched while the transfer ran
+            unsafe { M::invalidate_range(data as usize, len) };
+        }
+        result
+    }
+}
+
+// Response types and checks of the SD responses
+const R1: u32 = cmd::RESPONSE_48 | cmd::CRC_CHECK | cmd::INDEX_CHECK;
+const R1B: u32 = cmd::RESPONSE_48_BUSY | cmd::CRC_CHECK | cmd::INDEX_CHECK;
+const R2: u32 = cmd::RESPONSE_136 | cmd::CRC_CHECK;
+const R3: u32 = cmd::RESPONSE_48;
+const R6: u32 = R1;
+const R7: u32 = R1;
+
+/// Bits `high..=low` of a CSD as stored in the response registers, which
+/// drop the CRC byte
+fn csd_bits(response: &[u32; 4], high: usize, low: usize) -> u32 {
+    let mut value = 0;
+    for bit in (low..=high).rev() {
+        let position = bit - 8;
+        let set = response[position / 32] >> (position % 32) & 1;
+        value = value << 1 | set;
+    }
+    value
+}
+
+/// Card capacity in 512-byte blocks, from the CSD
+fn csd_blocks(response: &[u32; 4]) -> Option<u64> {
+    match csd_bits(response, 127, 126) {
+        // Standard capacity
+        0 => {
+            let c_size = u64::from(csd_bits(response, 73, 62));
+            let c_size_mult = csd_bits(response, 49, 47);
+            let read_bl_len = csd_bits(response, 83, 80);
+            let bytes = (c_size + 1) << (c_size_mult + 2 + read_bl_len);
+            Some(bytes / BLOCK_SIZE as u64)
+        }
+        // High and extended capacity, in units of 512 KiB
+        1 => Some((u64::from(csd_bits(response, 69, 48)) + 1) * 1024),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "driver-block")]
+pub use adapter::{SdhciDisk, SCHEME_NAME};
+
+#[cfg(feature = "driver-block")]
+mod adapter {
+    use alloc::collections::BTreeMap;
+    use alloc::string::ToString;
+
+    use driver_block::{Disk, DiskScheme, ExecutorTrait};
+    use redox_hal::dma::CacheMaintenance;
+    use syscall::error::{Error, Result, EINVAL, EIO, ENODEV, ETIMEDOUT};
+
+    use super::{Sdhci, BLOCK_SIZE};
+
+    /// Name of the block device scheme
+    pub const SCHEME_NAME: &str = "disk.sd";
+
+    /// [`Sdhci`] as a driver-block disk
+    pub struct SdhciDisk<M: CacheMaintenance> {
+        host: Sdhci<M>,
+    }
+
+    impl<M: CacheMaintenance> SdhciDisk<M> {
+        /// Wrap a controller whose card was initialized
+        pub fn new(host: Sdhci<M>) -> Self {
+            Self { host }
+        }
+
+        /// Access the controller
+        pub fn host(&mut self) -> &mut Sdhci<M> {
+            &mut self.host
+        }
+
+        /// Serve the card as disk 0 of the `disk.sd` scheme
+        pub fn into_scheme(self, executor: &impl ExecutorTrait) -> DiskScheme<Self> {
+            DiskScheme::new(
+                None,
+                SCHEME_NAME.to_string(),
+                BTreeMap::from([(0, self)]),
+                executor,
+            )
+        }
+    }
+
+    fn errno(err: redox_hal::Error) -> Error {
+        Error::new(match err {
+            redox_hal::Error::InvalidParameter => EINVAL,
+            redox_hal::Error::NotAvailable | redox_hal::Error::NotInitialized => ENODEV,
+            redox_hal::Error::Timeout => ETIMEDOUT,
+            _ => EIO,
+        })
+    }
+
+    impl<M: CacheMaintenance> Disk for SdhciDisk<M> {
+        fn block_size(&self) -> u32 {
+            BLOCK_SIZE as u32
+        }
+
+        fn size(&self) -> u64 {
+            self.host
+                .card()
+                .map_or(0, |card| card.blocks * BLOCK_SIZE as u64)
+        }
+
+        async fn read(&mut self, block: u64, buffer: &mut [u8]) -> Result<usize> {
+            self.host.read_blocks(block, buffer).map_err(errno)?;
+            Ok(buffer.len())
+        }
+
+        async fn write(&mut self, block: u64, buffer: &[u8]) -> Result<usize> {
+            self.host.write_blocks(block, buffer).map_err(errno)?;
+            Ok(buffer.len())
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! Generic UART driver
 
+use redox_hal::pinmux::{Peripheral, PinController, Pinmux, PinmuxError};
 use redox_hal::uart::{BaudRate, DataBits, FlowControl, Parity, StopBits, Uart, UartConfig};
 use redox_hal::Error;
 
@@ -58,6 +59,17 @@ impl GenericUart {
         }
     }
 
+    /// Create UART `instance` after routing its pads through `pinmux`
+    pub fn claim<C: PinController>(
+        base: usize,
+        clock_freq: u32,
+        instance: u8,
+        pinmux: &mut Pinmux<C>,
+    ) -> Result<Self, PinmuxError<C::Error>> {
+        pinmux.claim(Peripheral::Uart(instance))?;
+        Ok(Self::new(base, clock_freq))
+    }
+
     /// Read a register
     unsafe fn read_reg(&self, offset: usize) -> u32 {
         core::ptr::read_volatile((self.base + offset) as *const u32)
@@ -7,8 +7,23 @@
 //!
 //! - **BeagleBone Black**: TI AM335x (ARMv7-A Cortex-A8)
 //! - **Raspberry Pi Zero**: BCM2835 (ARMv6)
+//! - **Raspberry Pi 4**: BCM2711 (AArch64 Cortex-A72), GENET Ethernet
+//! - **Raspberry Pi 5**: BCM2712 (AArch64 Cortex-A76) with RP1 I/O
+//! - **Raspberry Pi Pico / Pico W**: RP2040 (Dual Cortex-M0+) with PIO
+//! - **Raspberry Pi Pico 2**: RP2350 (Dual Cortex-M33) with PIO
+//! - **ESP32-C3 DevKitM-1**: ESP32-C3 (RISC-V RV32IMC), WiFi
+//! - **ESP32-C6 DevKitC-1**: ESP32-C6 (RISC-V RV32IMAC), WiFi 6
 //! - **SiFive HiFive1**: FE310 (RISC-V RV32IMAC)
 //!
+//! Boards whose firmware passes a devicetree can also be discovered at boot
+//! with [`runtime::discover_board`], without a board feature.
+//!
+//! Firmware updates go to the inactive of two slots and are rolled back if
+//! the new firmware never confirms a good boot, see [`ota`].
+//!
+//! Battery-powered deployments sleep between work in the low-power states of
+//! [`runtime::power`], woken by GPIO, RTC alarm or UART events.
+//!
 //! # Minimal Embedded Profile
 //!
 //! This BSP is designed for the minimal Redox OS embedded profile:
@@ -46,9 +61,12 @@
 
 extern crate alloc;
 
+use alloc::vec::Vec;
+
 pub mod board;
 pub mod drivers;
 pub mod net;
+pub mod ota;
 pub mod runtime;
 
 // Re-export HAL traits
@@ -85,6 +103,8 @@ pub struct BoardInfo {
     pub flash_size: usize,
     /// CPU frequency in Hz
     pub cpu_freq: u32,
+    /// Clock tree, at the frequencies the board boots with
+    pub clocks: redox_hal::clocks::ClockTree,
     /// Has Ethernet
     pub has_ethernet: bool,
     /// Has WiFi
@@ -99,6 +119,46 @@ pub struct BoardInfo {
     pub i2c_count: u8,
 }
 
+/// Memory-mapped peripheral found at boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioDevice {
+    /// Devicetree node name, with the unit address
+    pub name: &'static str,
+    /// Most specific `compatible` entry, empty if there is none
+    pub compatible: &'static str,
+    /// Physical base address
+    pub base: usize,
+    /// Size of the register window in bytes
+    pub size: usize,
+}
+
+/// Peripherals of a board discovered at boot
+#[derive(Debug, Clone, Default)]
+pub struct PeripheralConfig {
+    /// Console UART chosen by the firmware
+    pub console: Option<MmioDevice>,
+    /// Console baud rate chosen by the firmware
+    pub console_baud: Option<u32>,
+    /// UARTs
+    pub uarts: Vec<MmioDevice>,
+    /// GPIO controllers
+    pub gpio: Vec<MmioDevice>,
+    /// SPI controllers
+    pub spi: Vec<MmioDevice>,
+    /// I2C controllers
+    pub i2c: Vec<MmioDevice>,
+    /// Ethernet MACs
+    pub ethernet: Vec<MmioDevice>,
+    /// SD/MMC host controllers
+    pub mmc: Vec<MmioDevice>,
+    /// Timers
+    pub timers: Vec<MmioDevice>,
+    /// Watchdogs
+    pub watchdogs: Vec<MmioDevice>,
+    /// Interrupt controllers
+    pub interrupt_controllers: Vec<MmioDevice>,
+}
+
 /// Networking profile type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NetworkProfile {
@@ -112,6 +172,35 @@ pub enum NetworkProfile {
     Full,
 }
 
+impl NetworkProfile {
+    /// Whether the profile brings up WiFi
+    pub fn uses_wifi(&self) -> bool {
+        matches!(self, NetworkProfile::WiFi | NetworkProfile::Full)
+    }
+}
+
+/// WiFi authentication mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiSecurity {
+    /// Open network
+    Open,
+    /// WPA2-Personal (PSK)
+    Wpa2Personal,
+    /// WPA3-Personal (SAE)
+    Wpa3Personal,
+}
+
+/// WiFi station credentials
+#[derive(Debug, Clone, Copy)]
+pub struct WifiCredentials {
+    /// Network name, at most 32 bytes
+    pub ssid: &'static str,
+    /// Passphrase, 8 to 63 bytes unless the network is open
+    pub password: &'static str,
+    /// Authentication mode
+    pub security: WifiSecurity,
+}
+
 /// Embedded system configuration
 #[derive(Debug, Clone)]
 pub struct EmbeddedConfig {
@@ -127,6 +216,8 @@ pub struct EmbeddedConfig {
     pub gateway: Option<[u8; 4]>,
     /// DNS servers
     pub dns: Option<[u8; 4]>,
+    /// WiFi network to join (WiFi profiles only)
+    pub wifi: Option<WifiCredentials>,
     /// Enable console on UART
     pub uart_console: bool,
     /// Console baud rate
@@ -135,6 +226,8 @@ pub struct EmbeddedConfig {
     pub watchdog: bool,
     /// Watchdog timeout in seconds
     pub watchdog_timeout: u32,
+    /// Crash resets in a row before booting in safe mode, 0 for never
+    pub safe_mode_after: u32,
 }
 
 impl Default for EmbeddedConfig {
@@ -146,10 +239,26 @@ impl Default for EmbeddedConfig {
             static_ip: None,
             gateway: None,
             dns: None,
+            wifi: None,
             uart_console: true,
             console_baud: 115200,
             watchdog: true,
             watchdog_timeout: 10,
+            safe_mode_after: 3,
+        }
+    }
+}
+
+impl EmbeddedConfig {
+    /// Minimal profile booted after repeated crash resets: console and
+    /// watchdog only, without networking
+    pub fn safe_mode(&self) -> Self {
+        Self {
+            network_profile: NetworkProfile::None,
+            dhcp: false,
+            wifi: None,
+            uart_console: true,
+            ..self.clone()
         }
     }
 }
@@ -6,6 +6,8 @@ use alloc::vec::Vec;
 
 use crate::drivers::ethernet::{EthernetDriver, MacAddress};
 
+pub mod esp_wifi;
+
 /// IPv4 address
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Ipv4Address(pub [u8; 4]);
@@ -0,0 +1,366 @@
+//! ESP32-C3/C6 WiFi station
+//!
+//! The WiFi MAC of the ESP32 parts is run by Espressif's closed firmware
+//! libraries, which exchange Ethernet II frames with the host stack and
+//! report connection events. [`WifiMac`] is the boundary to that firmware;
+//! [`EspWifi`] drives a station from the [`EmbeddedConfig`] credentials on
+//! top of it, and with the `driver-network` feature [`EspWifiAdapter`]
+//! exposes it to the Redox network stack.
+//!
+//! [`EspWifiMac`] only reads the factory MAC address for now; everything
+//! else reports [`Error::NotAvailable`] until the firmware is linked in.
+
+use redox_hal::Error;
+
+use crate::{EmbeddedConfig, WifiCredentials, WifiSecurity};
+
+/// Longest SSID, in bytes
+pub const MAX_SSID_LEN: usize = 32;
+
+/// Connection events reported by the firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiEvent {
+    /// The station interface is up
+    Started,
+    /// Associated and authenticated
+    Connected {
+        /// Access point address
+        bssid: [u8; 6],
+        /// Primary channel
+        channel: u8,
+    },
+    /// Lost or refused the association, with the 802.11 reason code
+    Disconnected {
+        /// Reason code
+        reason: u16,
+    },
+}
+
+/// Interface to the WiFi MAC firmware
+pub trait WifiMac {
+    /// Station MAC address
+    fn mac_address(&self) -> [u8; 6];
+
+    /// Bring up the station interface
+    fn start(&mut self) -> Result<(), Error>;
+
+    /// Join a network; completion is reported as an event
+    fn connect(&mut self, credentials: &WifiCredentials) -> Result<(), Error>;
+
+    /// Leave the network
+    fn disconnect(&mut self) -> Result<(), Error>;
+
+    /// Next pending connection event
+    fn poll_event(&mut self) -> Option<WifiEvent>;
+
+    /// Queue an Ethernet frame for transmission
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), Error>;
+
+    /// Copy the next received Ethernet frame into `buf`, returning its length
+    fn receive(&mut self, buf: &mut [u8]) -> Option<usize>;
+
+    /// Number of received frames waiting
+    fn rx_pending(&self) -> usize;
+}
+
+/// Station state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StationState {
+    /// Not started
+    Idle,
+    /// Waiting for the firmware to come up
+    Starting,
+    /// Association in progress
+    Connecting,
+    /// Associated to an access point
+    Connected {
+        /// Access point address
+        bssid: [u8; 6],
+        /// Primary channel
+        channel: u8,
+    },
+    /// Disconnected, waiting to retry
+    Disconnected {
+        /// Reason code of the last disconnection
+        reason: u16,
+    },
+}
+
+/// ESP32 WiFi MAC
+///
+/// Stub until the firmware libraries are linked: only the factory MAC
+/// address is available.
+pub struct EspWifiMac {
+    mac: [u8; 6],
+}
+
+impl EspWifiMac {
+    /// eFuse word holding the low four bytes of the factory MAC address
+    const EFUSE_RD_MAC_SYS_0: usize = 0x44;
+    /// eFuse word holding the high two bytes
+    const EFUSE_RD_MAC_SYS_1: usize = 0x48;
+
+    /// Read the factory MAC address from the eFuse block at `efuse`
+    pub fn new(efuse: usize) -> Self {
+        let (low, high) = unsafe {
+            (
+                core::ptr::read_volatile((efuse + Self::EFUSE_RD_MAC_SYS_0) as *const u32),
+                core::ptr::read_volatile((efuse + Self::EFUSE_RD_MAC_SYS_1) as *const u32),
+            )
+        };
+        let low = low.to_be_bytes();
+        let high = (high as u16).to_be_bytes();
+        Self {
+            mac: [high[0], high[1], low[0], low[1], low[2], low[3]],
+        }
+    }
+}
+
+impl WifiMac for EspWifiMac {
+    fn mac_address(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        Err(Error::NotAvailable)
+    }
+
+    fn connect(&mut self, _credentials: &WifiCredentials) -> Result<(), Error> {
+        Err(Error::NotAvailable)
+    }
+
+    fn disconnect(&mut self) -> Result<(), Error> {
+        Err(Error::NotAvailable)
+    }
+
+    fn poll_event(&mut self) -> Option<WifiEvent> {
+        None
+    }
+
+    fn transmit(&mut self, _frame: &[u8]) -> Result<(), Error> {
+        Err(Error::NotAvailable)
+    }
+
+    fn receive(&mut self, _buf: &mut [u8]) -> Option<usize> {
+        None
+    }
+
+    fn rx_pending(&self) -> usize {
+        0
+    }
+}
+
+/// WiFi station
+pub struct EspWifi<M: WifiMac> {
+    mac: M,
+    credentials: WifiCredentials,
+    state: StationState,
+}
+
+impl<M: WifiMac> EspWifi<M> {
+    /// Create a station joining the network of `config`
+    ///
+    /// Fails with [`Error::InvalidConfig`] when the network profile doesn't
+    /// include WiFi or the credentials are missing or malformed.
+    pub fn new(mac: M, config: &EmbeddedConfig) -> Result<Self, Error> {
+        if !config.network_profile.uses_wifi() {
+            return Err(Error::InvalidConfig);
+        }
+        let credentials = config.wifi.ok_or(Error::InvalidConfig)?;
+        Self::check_credentials(&credentials)?;
+        Ok(Self {
+            mac,
+            credentials,
+            state: StationState::Idle,
+        })
+    }
+
+    fn check_credentials(credentials: &WifiCredentials) -> Result<(), Error> {
+        if credentials.ssid.is_empty() || credentials.ssid.len() > MAX_SSID_LEN {
+            return Err(Error::InvalidConfig);
+        }
+        let password = credentials.password.len();
+        match credentials.security {
+            WifiSecurity::Open if password != 0 => Err(Error::InvalidConfig),
+            WifiSecurity::Wpa2Personal | WifiSecurity::Wpa3Personal
+                if !(8..=63).contains(&password) =>
+            {
+                Err(Error::InvalidConfig)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Start the station; it connects once the firmware is up
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.mac.start()?;
+        self.state = StationState::Starting;
+        Ok(())
+    }
+
+    /// Handle pending firmware events, reconnecting after a disconnection
+    pub fn poll(&mut self) -> Result<StationState, Error> {
+        while let Some(event) = self.mac.poll_event() {
+            self.state = match event {
+                WifiEvent::Started => {
+                    self.mac.connect(&self.credentials)?;
+                    StationState::Connecting
+                }
+                WifiEvent::Connected { bssid, channel } => {
+                    StationState::Connected { bssid, channel }
+                }
+                WifiEvent::Disconnected { reason } => StationState::Disconnected { reason },
+            };
+        }
+        if let StationState::Disconnected { .. } = self.state {
+            self.mac.connect(&self.credentials)?;
+            self.state = StationState::Connecting;
+        }
+        Ok(self.state)
+    }
+
+    /// Leave the network and stop retrying
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.mac.disconnect()?;
+        self.state = StationState::Idle;
+        Ok(())
+    }
+
+    /// Station state
+    pub fn state(&self) -> StationState {
+        self.state
+    }
+
+    /// Whether frames can be exchanged
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, StationState::Connected { .. })
+    }
+
+    /// Network being joined
+    pub fn ssid(&self) -> &'static str {
+        self.credentials.ssid
+    }
+
+    /// Access the MAC
+    pub fn mac(&mut self) -> &mut M {
+        &mut self.mac
+    }
+}
+
+#[cfg(feature = "driver-network")]
+pub use adapter::EspWifiAdapter;
+
+#[cfg(feature = "driver-network")]
+mod adapter {
+    use driver_network::{Ipv6Scope, NetworkAdapter};
+    use syscall::error::{Error, Result, EAGAIN, EIO, ENOTCONN, EOPNOTSUPP};
+
+    use super::{EspWifi, WifiMac};
+
+    /// [`EspWifi`] as a driver-network adapter
+    pub struct EspWifiAdapter<M: WifiMac> {
+        station: EspWifi<M>,
+        ipv4: [u8; 4],
+        ipv6_global: [u8; 16],
+        ipv6_unique_local: [u8; 16],
+    }
+
+    impl<M: WifiMac> EspWifiAdapter<M> {
+        /// Wrap a started station
+        pub fn new(station: EspWifi<M>) -> Self {
+            Self {
+                station,
+                ipv4: [0; 4],
+                ipv6_global: [0; 16],
+                ipv6_unique_local: [0; 16],
+            }
+        }
+
+        /// Access the station
+        pub fn station(&mut self) -> &mut EspWifi<M> {
+            &mut self.station
+        }
+    }
+
+    impl<M: WifiMac> NetworkAdapter for EspWifiAdapter<M> {
+        fn mac_address(&mut self) -> [u8; 6] {
+            self.station.mac().mac_address()
+        }
+
+        fn ipv4_address(&mut self) -> [u8; 4] {
+            self.ipv4
+        }
+
+        fn ipv6_address(&mut self) -> [u8; 16] {
+            // Link-local address from the MAC (EUI-64)
+            let mac = self.station.mac().mac_address();
+            [
+                0xfe,
+                0x80,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                mac[0] ^ 0x02,
+                mac[1],
+                mac[2],
+                0xff,
+                0xfe,
+                mac[3],
+                mac[4],
+                mac[5],
+            ]
+        }
+
+        fn ipv6_address_global(&mut self) -> [u8; 16] {
+            self.ipv6_global
+        }
+
+        fn ipv6_address_unique_local(&mut self) -> [u8; 16] {
+            self.ipv6_unique_local
+        }
+
+        fn available_for_read(&mut self) -> usize {
+            // Connection events arrive on the same poll as frames
+            let _ = self.station.poll();
+            self.station.mac().rx_pending()
+        }
+
+        fn read_packet(&mut self, buf: &mut [u8]) -> Result<Option<usize>> {
+            Ok(self.station.mac().receive(buf))
+        }
+
+        fn write_packet(&mut self, buf: &[u8], _pacing_rate: u64) -> Result<usize> {
+            if !self.station.is_connected() {
+                return Err(Error::new(ENOTCONN));
+            }
+            match self.station.mac().transmit(buf) {
+                Ok(()) => Ok(buf.len()),
+                Err(redox_hal::Error::Busy) => Err(Error::new(EAGAIN)),
+                Err(_) => Err(Error::new(EIO)),
+            }
+        }
+
+        fn in_flight(&self) -> u64 {
+            // The firmware owns its queues and doesn't report them
+            0
+        }
+
+        fn set_ipv4_address(&mut self, address: [u8; 4]) -> Result<()> {
+            self.ipv4 = address;
+            Ok(())
+        }
+
+        fn set_ipv6_address(&mut self, scope: Ipv6Scope, address: [u8; 16]) -> Result<()> {
+            match scope {
+                // The link-local address is always derived from the MAC address
+                Ipv6Scope::LinkLocal => return Err(Error::new(EOPNOTSUPP)),
+                Ipv6Scope::Global => self.ipv6_global = address,
+                Ipv6Scope::UniqueLocal => self.ipv6_unique_local = address,
+            }
+            Ok(())
+        }
+    }
+}
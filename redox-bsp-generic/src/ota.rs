@@ -0,0 +1,491 @@
+//! Over-the-air firmware updates with A/B slots
+//!
+//! The firmware lives in two slots. One is active, the other receives
+//! updates: [`Ota::begin`] erases it, [`Ota::write`] streams the image in and
+//! [`Ota::finish`] checks the signature and hash of what ended up in flash
+//! before marking the slot for a trial boot.
+//!
+//! [`Ota::boot_slot`] picks the slot to boot. A trial slot gets
+//! [`MAX_TRIAL_BOOTS`] attempts; the new firmware calls [`Ota::confirm`] once
+//! it runs well, typically together with
+//! [`Recovery::mark_healthy`](crate::runtime::recovery::Recovery::mark_healthy).
+//! Boots that panic or hang reset through the watchdog without confirming,
+//! and once the attempts are used up the previous slot boots again. Boards
+//! that know the [`ResetCause`] pick the slot with [`Ota::boot_slot_after`]
+//! instead, so a trial boot cut short by a power loss or a deliberate
+//! reboot doesn't use up an attempt.
+//!
+//! # Image format
+//!
+//! ```text
+//! 0    magic "ROTA"
+//! 4    header version (u16), reserved (u16)
+//! 8    firmware version (u32)
+//! 12   payload length (u32)
+//! 16   SHA-256 of the payload
+//! 48   signature of bytes 0..48
+//! 112  payload
+//! ```
+//!
+//! All fields are little endian. The signature algorithm is up to the
+//! [`SignatureVerifier`] of the board; [`Ed25519Verifier`] checks Ed25519
+//! signatures against the public key built into the firmware.
+//!
+//! On the RP2040 the slots and the boot control record live in flash, see
+//! [`drivers::rp2040_flash`](crate::drivers::rp2040_flash).
+
+mod sha256;
+
+pub use sha256::Sha256;
+
+use redox_hal::reset::ResetCause;
+
+use crate::runtime::recovery::{crc32, PersistentStore};
+
+/// Image magic, "ROTA"
+pub const IMAGE_MAGIC: u32 = 0x4154_4F52;
+/// Image header version
+pub const IMAGE_VERSION: u16 = 1;
+/// Image header size in bytes
+pub const HEADER_SIZE: usize = 112;
+/// Header bytes covered by the signature
+pub const SIGNED_LEN: usize = 48;
+/// Signature size in bytes
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Boots a trial slot gets before rolling back
+pub const MAX_TRIAL_BOOTS: u8 = 3;
+
+/// Bytes a [`PersistentStore`] needs for the boot control record
+pub const BOOT_CONTROL_SIZE: usize = 24;
+
+const CONTROL_MAGIC: u32 = 0x4254_4F52; // "ROTB"
+const CONTROL_VERSION: u16 = 1;
+const NO_SLOT: u8 = 0xFF;
+
+/// Firmware slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    /// The other slot
+    pub fn other(self) -> Self {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Slot::A => 0,
+            Slot::B => 1,
+        }
+    }
+
+    fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(Slot::A),
+            1 => Some(Slot::B),
+            _ => None,
+        }
+    }
+}
+
+/// Flash holding the two firmware slots
+pub trait SlotStorage {
+    /// Size of one slot in bytes
+    fn slot_size(&self) -> usize;
+
+    /// Erase a whole slot
+    fn erase(&mut self, slot: Slot) -> Result<(), redox_hal::Error>;
+
+    /// Program `data` at `offset` of an erased slot
+    fn write(&mut self, slot: Slot, offset: usize, data: &[u8]) -> Result<(), redox_hal::Error>;
+
+    /// Read `buf.len()` bytes at `offset` of a slot
+    fn read(&mut self, slot: Slot, offset: usize, buf: &mut [u8]) -> Result<(), redox_hal::Error>;
+}
+
+/// Check of image signatures against the update key
+pub trait SignatureVerifier {
+    /// Whether `signature` signs `message`
+    fn verify(&self, message: &[u8], signature: &[u8; SIGNATURE_LEN]) -> bool;
+}
+
+/// [`SignatureVerifier`] for Ed25519 signatures
+pub struct Ed25519Verifier {
+    key: ed25519_dalek::VerifyingKey,
+}
+
+impl Ed25519Verifier {
+    /// Check signatures against the public `key`, `None` if it isn't a
+    /// valid curve point
+    pub fn new(key: &[u8; 32]) -> Option<Self> {
+        ed25519_dalek::VerifyingKey::from_bytes(key)
+            .ok()
+            .map(|key| Self { key })
+    }
+}
+
+impl SignatureVerifier for Ed25519Verifier {
+    fn verify(&self, message: &[u8], signature: &[u8; SIGNATURE_LEN]) -> bool {
+        let signature = ed25519_dalek::Signature::from_bytes(signature);
+        self.key.verify_strict(message, &signature).is_ok()
+    }
+}
+
+/// OTA update errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtaError {
+    /// The slot storage failed
+    Storage(redox_hal::Error),
+    /// No update was started with [`Ota::begin`]
+    NoUpdate,
+    /// The last update isn't confirmed or rolled back yet
+    TrialPending,
+    /// The image doesn't fit in a slot
+    TooLarge,
+    /// The image header is malformed
+    BadHeader,
+    /// Less was written than the header announces
+    Incomplete,
+    /// The signature doesn't verify
+    BadSignature,
+    /// The payload doesn't match the hash in the header
+    HashMismatch,
+    /// The image is older than the active firmware
+    Downgrade {
+        /// Version of the active firmware
+        current: u32,
+        /// Version of the image
+        image: u32,
+    },
+    /// Downloading the image failed
+    #[cfg(feature = "ota-scheme")]
+    Network(syscall::Error),
+}
+
+impl From<redox_hal::Error> for OtaError {
+    fn from(err: redox_hal::Error) -> Self {
+        OtaError::Storage(err)
+    }
+}
+
+/// Parsed image header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    /// Firmware version
+    pub version: u32,
+    /// Payload length in bytes
+    pub length: u32,
+    /// SHA-256 of the payload
+    pub digest: [u8; 32],
+    /// Signature of the first [`SIGNED_LEN`] header bytes
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl ImageHeader {
+    /// Parse the header at the start of an image
+    pub fn parse(bytes: &[u8]) -> Result<Self, OtaError> {
+        let bytes = bytes.get(..HEADER_SIZE).ok_or(OtaError::BadHeader)?;
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        if u32_at(0) != IMAGE_MAGIC || u16::from_le_bytes([bytes[4], bytes[5]]) != IMAGE_VERSION {
+            return Err(OtaError::BadHeader);
+        }
+        Ok(Self {
+            version: u32_at(8),
+            length: u32_at(12),
+            digest: bytes[16..48].try_into().unwrap(),
+            signature: bytes[SIGNED_LEN..HEADER_SIZE].try_into().unwrap(),
+        })
+    }
+}
+
+/// Slot state kept across resets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BootControl {
+    active: Slot,
+    trial: Option<Slot>,
+    trial_boots: u8,
+    versions: [u32; 2],
+}
+
+impl BootControl {
+    const fn initial() -> Self {
+        Self {
+            active: Slot::A,
+            trial: None,
+            trial_boots: 0,
+            versions: [0; 2],
+        }
+    }
+
+    fn encode(&self) -> [u8; BOOT_CONTROL_SIZE] {
+        let mut bytes = [0; BOOT_CONTROL_SIZE];
+        bytes[0..4].copy_from_slice(&CONTROL_MAGIC.to_le_bytes());
+        bytes[4..6].copy_from_slice(&CONTROL_VERSION.to_le_bytes());
+        bytes[6] = self.active.index() as u8;
+        bytes[7] = self.trial.map_or(NO_SLOT, |slot| slot.index() as u8);
+        bytes[8] = self.trial_boots;
+        bytes[12..16].copy_from_slice(&self.versions[0].to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.versions[1].to_le_bytes());
+        let checksum = crc32(&bytes[..20]);
+        bytes[20..24].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Decode a record, `None` if the store holds anything else
+    fn decode(bytes: &[u8; BOOT_CONTROL_SIZE]) -> Option<Self> {
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        if u32_at(0) != CONTROL_MAGIC
+            || u16::from_le_bytes([bytes[4], bytes[5]]) != CONTROL_VERSION
+            || u32_at(20) != crc32(&bytes[..20])
+        {
+            return None;
+        }
+        Some(Self {
+            active: Slot::from_index(bytes[6])?,
+            trial: match bytes[7] {
+                NO_SLOT => None,
+                index => Some(Slot::from_index(index)?),
+            },
+            trial_boots: bytes[8],
+            versions: [u32_at(12), u32_at(16)],
+        })
+    }
+}
+
+/// Update being written
+struct Update {
+    slot: Slot,
+    written: usize,
+}
+
+/// A/B slot manager
+pub struct Ota<S: SlotStorage, P: PersistentStore, V: SignatureVerifier> {
+    storage: S,
+    store: P,
+    verifier: V,
+    control: BootControl,
+    update: Option<Update>,
+}
+
+impl<S: SlotStorage, P: PersistentStore, V: SignatureVerifier> Ota<S, P, V> {
+    /// Load the boot control record from `store`
+    ///
+    /// `store` must not be shared with the recovery record. A store without
+    /// a valid record starts out with slot A active.
+    pub fn new(storage: S, mut store: P, verifier: V) -> Self {
+        let mut control = None;
+        if store.size() >= BOOT_CONTROL_SIZE {
+            let mut bytes = [0; BOOT_CONTROL_SIZE];
+            store.read(&mut bytes);
+            control = BootControl::decode(&bytes);
+        }
+        Self {
+            storage,
+            store,
+            verifier,
+            control: control.unwrap_or(BootControl::initial()),
+            update: None,
+        }
+    }
+
+    fn save(&mut self) {
+        if self.store.size() >= BOOT_CONTROL_SIZE {
+            self.store.write(&self.control.encode());
+        }
+    }
+
+    /// Slot holding the confirmed firmware
+    pub fn active_slot(&self) -> Slot {
+        self.control.active
+    }
+
+    /// Slot waiting for [`Ota::confirm`], if any
+    pub fn trial_slot(&self) -> Option<Slot> {
+        self.control.trial
+    }
+
+    /// Firmware version installed in `slot`, 0 if unknown
+    pub fn firmware_version(&self, slot: Slot) -> u32 {
+        self.control.versions[slot.index()]
+    }
+
+    /// Pick the slot to boot, once per boot
+    ///
+    /// Counts a boot of the trial slot, and rolls back to the active slot
+    /// once the trial slot has used up its attempts.
+    pub fn boot_slot(&mut self) -> Slot {
+        let Some(trial) = self.control.trial else {
+            return self.control.active;
+        };
+        if self.control.trial_boots >= MAX_TRIAL_BOOTS {
+            self.rollback();
+            return self.control.active;
+        }
+        self.control.trial_boots += 1;
+        self.save();
+        trial
+    }
+
+    /// Like [`Ota::boot_slot`], after the previous boot ended with `cause`
+    ///
+    /// A trial boot that ended without crashing gives its attempt back.
+    /// Firmware that keeps rebooting itself without confirming therefore
+    /// stays on trial until it crashes or confirms.
+    pub fn boot_slot_after(&mut self, cause: ResetCause) -> Slot {
+        if self.control.trial.is_some() && cause.is_normal() && self.control.trial_boots > 0 {
+            self.control.trial_boots -= 1;
+        }
+        self.boot_slot()
+    }
+
+    /// Make the trial slot the active one
+    pub fn confirm(&mut self) {
+        if let Some(trial) = self.control.trial.take() {
+            self.control.active = trial;
+            self.control.trial_boots = 0;
+            self.save();
+        }
+    }
+
+    /// Drop the trial slot and keep booting the active one
+    pub fn rollback(&mut self) {
+        if self.control.trial.take().is_some() {
+            self.control.trial_boots = 0;
+            self.save();
+        }
+    }
+
+    /// Erase the inactive slot for a new image
+    pub fn begin(&mut self) -> Result<(), OtaError> {
+        if self.control.trial.is_some() {
+            return Err(OtaError::TrialPending);
+        }
+        let slot = self.control.active.other();
+        self.update = None;
+        self.storage.erase(slot)?;
+        self.control.versions[slot.index()] = 0;
+        self.save();
+        self.update = Some(Update { slot, written: 0 });
+        Ok(())
+    }
+
+    /// Append image data to the slot being updated
+    pub fn write(&mut self, data: &[u8]) -> Result<(), OtaError> {
+        let update = self.update.as_mut().ok_or(OtaError::NoUpdate)?;
+        if update.written + data.len() > self.storage.slot_size() {
+            return Err(OtaError::TooLarge);
+        }
+        self.storage.write(update.slot, update.written, data)?;
+        update.written += data.len();
+        Ok(())
+    }
+
+    /// Drop the update being written
+    pub fn abort(&mut self) {
+        self.update = None;
+    }
+
+    /// Verify the written image and boot it on trial next time
+    ///
+    /// Returns the firmware version of the image.
+    pub fn finish(&mut self) -> Result<u32, OtaError> {
+        let update = self.update.take().ok_or(OtaError::NoUpdate)?;
+        let length = match self.read_header(update.slot) {
+            Ok((header, _)) => HEADER_SIZE + header.length as usize,
+            Err(_) => HEADER_SIZE,
+        };
+        if update.written < length {
+            return Err(OtaError::Incomplete);
+        }
+        let header = self.verify(update.slot)?;
+        let current = self.firmware_version(self.control.active);
+        if header.version < current {
+            return Err(OtaError::Downgrade {
+                current,
+                image: header.version,
+            });
+        }
+
+        self.control.versions[update.slot.index()] = header.version;
+        self.control.trial = Some(update.slot);
+        self.control.trial_boots = 0;
+        self.save();
+        Ok(header.version)
+    }
+
+    fn read_header(&mut self, slot: Slot) -> Result<(ImageHeader, [u8; HEADER_SIZE]), OtaError> {
+        let mut bytes = [0; HEADER_SIZE];
+        self.storage.read(slot, 0, &mut bytes)?;
+        Ok((ImageHeader::parse(&bytes)?, bytes))
+    }
+
+    /// Check the signature and hash of the image in `slot`
+    pub fn verify(&mut self, slot: Slot) -> Result<ImageHeader, OtaError> {
+        let (header, bytes) = self.read_header(slot)?;
+        if HEADER_SIZE + header.length as usize > self.storage.slot_size() {
+            return Err(OtaError::TooLarge);
+        }
+        if !self
+            .verifier
+            .verify(&bytes[..SIGNED_LEN], &header.signature)
+        {
+            return Err(OtaError::BadSignature);
+        }
+
+        // Hash what is in flash rather than what was sent
+        let mut hasher = Sha256::new();
+        let mut chunk = [0; 256];
+        let mut offset = HEADER_SIZE;
+        let end = HEADER_SIZE + header.length as usize;
+        while offset < end {
+            let count = chunk.len().min(end - offset);
+            self.storage.read(slot, offset, &mut chunk[..count])?;
+            hasher.update(&chunk[..count]);
+            offset += count;
+        }
+        if hasher.finish() != header.digest {
+            return Err(OtaError::HashMismatch);
+        }
+        Ok(header)
+    }
+}
+
+#[cfg(feature = "ota-scheme")]
+impl<S: SlotStorage, P: PersistentStore, V: SignatureVerifier> Ota<S, P, V> {
+    /// Download an image from `address` (`host:port`) into the inactive slot
+    ///
+    /// Connects through the `tcp` scheme, the server sends the image and
+    /// closes the connection. Returns the firmware version like
+    /// [`Ota::finish`].
+    pub fn download(&mut self, address: &str) -> Result<u32, OtaError> {
+        let path = alloc::format!("/scheme/tcp/{}", address);
+        let fd = syscall::open(path, syscall::O_RDWR).map_err(OtaError::Network)?;
+        let result = self.receive(fd);
+        let _ = syscall::close(fd);
+        if result.is_err() {
+            self.abort();
+        }
+        result
+    }
+
+    fn receive(&mut self, fd: usize) -> Result<u32, OtaError> {
+        self.begin()?;
+        let mut buf = [0; 4096];
+        loop {
+            match syscall::read(fd, &mut buf) {
+                Ok(0) => break,
+                Ok(count) => self.write(&buf[..count])?,
+                Err(err) if err.errno == syscall::EINTR => {}
+                Err(err) => return Err(OtaError::Network(err)),
+            }
+        }
+        self.finish()
+    }
+}
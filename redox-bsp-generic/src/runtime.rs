@@ -4,6 +4,12 @@
 
 use core::panic::PanicInfo;
 
+use crate::{BoardInfo, PeripheralConfig};
+
+pub mod fdt;
+pub mod power;
+pub mod recovery;
+
 /// Boot information passed from bootloader
 #[derive(Debug, Clone)]
 pub struct BootInfo {
@@ -31,6 +37,36 @@ impl Default for BootInfo {
     }
 }
 
+impl BootInfo {
+    /// Devicetree passed by the bootloader
+    ///
+    /// # Safety
+    ///
+    /// `dtb_address` must point to a devicetree blob that stays mapped and
+    /// unmodified for the rest of the boot.
+    pub unsafe fn devicetree(&self) -> Option<Result<fdt::Fdt<'static>, fdt::FdtError>> {
+        self.dtb_address
+            .map(|address| fdt::Fdt::from_address(address))
+    }
+}
+
+/// Discover the board from the devicetree passed by the bootloader
+///
+/// Returns `None` when there is no devicetree. Needs the heap.
+///
+/// # Safety
+///
+/// See [`BootInfo::devicetree`].
+pub unsafe fn discover_board(
+    boot_info: &BootInfo,
+) -> Option<Result<(BoardInfo, PeripheralConfig), fdt::FdtError>> {
+    let fdt = match boot_info.devicetree()? {
+        Ok(fdt) => fdt,
+        Err(err) => return Some(Err(err)),
+    };
+    Some(fdt::discover(&fdt))
+}
+
 /// Simple heap allocator for embedded systems
 pub mod heap {
     use core::alloc::{GlobalAlloc, Layout};
@@ -112,6 +148,9 @@ fn panic(info: &PanicInfo) -> ! {
         }
     }
 
+    // Keep the reason for the next boot, the watchdog resets the system
+    recovery::record_panic(info);
+
     // Halt the CPU
     loop {
         #[cfg(target_arch = "arm")]
@@ -0,0 +1,537 @@
+//! Flattened devicetree (FDT) parser
+//!
+//! Reads the devicetree blob the firmware or bootloader leaves in memory
+//! (version 17 layout) and discovers the board from it: [`discover`] fills a
+//! [`BoardInfo`] and a [`PeripheralConfig`] at boot, so one image runs on
+//! any board whose firmware passes a devicetree instead of relying on the
+//! per-SoC memory maps in [`crate::board`].
+//!
+//! Peripherals are classified by the generic node names of the devicetree
+//! specification (`serial@...`, `gpio@...`, `ethernet@...`); disabled nodes
+//! are skipped. `reg` addresses are translated through the `ranges` of the
+//! parent buses to CPU physical addresses.
+
+use alloc::vec::Vec;
+
+use redox_hal::clocks::{ClockId, ClockNode, ClockTree};
+
+use crate::{BoardInfo, MmioDevice, PeripheralConfig};
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+/// Oldest layout with `size_dt_struct`
+const FDT_VERSION: u32 = 17;
+const HEADER_SIZE: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// Deepest node nesting [`Fdt::walk`] follows
+pub const MAX_DEPTH: usize = 16;
+
+/// Devicetree parse errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdtError {
+    /// The blob doesn't start with the FDT magic
+    BadMagic,
+    /// The blob layout is older than version 17
+    UnsupportedVersion(u32),
+    /// A block or token runs past the end of the blob
+    Truncated,
+    /// Unknown token, or unbalanced nodes
+    BadStructure,
+    /// Nodes are nested deeper than [`MAX_DEPTH`]
+    TooDeep,
+}
+
+fn be32(data: &[u8], offset: usize) -> Result<u32, FdtError> {
+    let bytes = data.get(offset..offset + 4).ok_or(FdtError::Truncated)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// NUL terminated string at the start of `data`
+fn c_str(data: &[u8]) -> Result<&str, FdtError> {
+    let len = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(FdtError::Truncated)?;
+    core::str::from_utf8(&data[..len]).map_err(|_| FdtError::BadStructure)
+}
+
+/// Read a number of `cells` 32-bit cells from the front of `data`
+fn read_cells(data: &mut &[u8], cells: u32) -> Option<u64> {
+    let len = cells as usize * 4;
+    if cells > 2 || data.len() < len {
+        return None;
+    }
+    let value = data[..len].chunks_exact(4).fold(0u64, |acc, cell| {
+        acc << 32 | u64::from(be32(cell, 0).unwrap_or(0))
+    });
+    *data = &data[len..];
+    Some(value)
+}
+
+/// Structure block token
+#[derive(Clone, Copy)]
+enum Token<'a> {
+    BeginNode(Node<'a>),
+    EndNode,
+    Prop(Property<'a>),
+    End,
+}
+
+/// Flattened devicetree blob
+#[derive(Debug, Clone, Copy)]
+pub struct Fdt<'a> {
+    blob: &'a [u8],
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Check the header of `blob` and locate its blocks
+    pub fn new(blob: &'a [u8]) -> Result<Self, FdtError> {
+        if blob.len() < HEADER_SIZE {
+            return Err(FdtError::Truncated);
+        }
+        if be32(blob, 0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = be32(blob, 4)? as usize;
+        let version = be32(blob, 20)?;
+        if version < FDT_VERSION {
+            return Err(FdtError::UnsupportedVersion(version));
+        }
+        let blob = blob.get(..total_size).ok_or(FdtError::Truncated)?;
+        let block = |offset, size| -> Result<&'a [u8], FdtError> {
+            let start = be32(blob, offset)? as usize;
+            let len = be32(blob, size)? as usize;
+            blob.get(start..start + len).ok_or(FdtError::Truncated)
+        };
+        Ok(Self {
+            blob,
+            structs: block(8, 36)?,
+            strings: block(12, 32)?,
+        })
+    }
+
+    /// Parse the blob at physical address `address`
+    ///
+    /// # Safety
+    ///
+    /// `address` must point to a devicetree blob that stays mapped and
+    /// unmodified for the rest of the boot.
+    pub unsafe fn from_address(address: usize) -> Result<Fdt<'static>, FdtError> {
+        let header = core::slice::from_raw_parts(address as *const u8, HEADER_SIZE);
+        if be32(header, 0)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = be32(header, 4)? as usize;
+        Fdt::new(core::slice::from_raw_parts(
+            address as *const u8,
+            total_size,
+        ))
+    }
+
+    /// Size of the blob in bytes
+    pub fn total_size(&self) -> usize {
+        self.blob.len()
+    }
+
+    /// Decode the token at `*offset` of the structure block, skipping NOPs
+    fn token(&self, offset: &mut usize) -> Result<Token<'a>, FdtError> {
+        loop {
+            let token = be32(self.structs, *offset)?;
+            *offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(self.structs.get(*offset..).unwrap_or_default())?;
+                    *offset = (*offset + name.len() + 1).next_multiple_of(4);
+                    return Ok(Token::BeginNode(Node {
+                        fdt: *self,
+                        name,
+                        offset: *offset,
+                    }));
+                }
+                FDT_END_NODE => return Ok(Token::EndNode),
+                FDT_PROP => {
+                    let len = be32(self.structs, *offset)? as usize;
+                    let name_offset = be32(self.structs, *offset + 4)? as usize;
+                    let start = *offset + 8;
+                    let value = self
+                        .structs
+                        .get(start..start + len)
+                        .ok_or(FdtError::Truncated)?;
+                    let name = c_str(self.strings.get(name_offset..).unwrap_or_default())?;
+                    *offset = (start + len).next_multiple_of(4);
+                    return Ok(Token::Prop(Property { name, value }));
+                }
+                FDT_NOP => continue,
+                FDT_END => return Ok(Token::End),
+                _ => return Err(FdtError::BadStructure),
+            }
+        }
+    }
+
+    /// Root node
+    pub fn root(&self) -> Result<Node<'a>, FdtError> {
+        match self.token(&mut 0)? {
+            Token::BeginNode(node) => Ok(node),
+            _ => Err(FdtError::BadStructure),
+        }
+    }
+
+    /// Node at the absolute `path`, like `/soc/serial@7e201000`
+    ///
+    /// Path components without a unit address match the first sibling of
+    /// that name.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let mut node = self.root().ok()?;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node.children().find(|child| {
+                child.name == component || child.name.split('@').next() == Some(component)
+            })?;
+        }
+        Some(node)
+    }
+
+    /// Resolve an alias from `/aliases`, or return `path` if it is absolute
+    pub fn resolve_alias(&self, path: &'a str) -> Option<&'a str> {
+        if path.starts_with('/') {
+            return Some(path);
+        }
+        self.find_node("/aliases")?.property_str(path)
+    }
+
+    /// Call `f` for every node, in depth-first order
+    ///
+    /// `f` gets the path from the root to the node, the node last.
+    pub fn walk<F>(&self, mut f: F) -> Result<(), FdtError>
+    where
+        F: FnMut(&[Node<'a>]),
+    {
+        let root = self.root()?;
+        let mut stack = [root; MAX_DEPTH];
+        let mut depth = 0;
+        let mut offset = 0;
+        loop {
+            match self.token(&mut offset)? {
+                Token::BeginNode(node) => {
+                    if depth == MAX_DEPTH {
+                        return Err(FdtError::TooDeep);
+                    }
+                    stack[depth] = node;
+                    depth += 1;
+                    f(&stack[..depth]);
+                }
+                Token::EndNode => {
+                    depth = depth.checked_sub(1).ok_or(FdtError::BadStructure)?;
+                }
+                Token::Prop(_) => {}
+                Token::End if depth == 0 => return Ok(()),
+                Token::End => return Err(FdtError::BadStructure),
+            }
+        }
+    }
+}
+
+/// Node property
+#[derive(Debug, Clone, Copy)]
+pub struct Property<'a> {
+    /// Property name
+    pub name: &'a str,
+    /// Raw, big-endian value
+    pub value: &'a [u8],
+}
+
+impl<'a> Property<'a> {
+    /// Value as a single cell
+    pub fn as_u32(&self) -> Option<u32> {
+        be32(self.value, 0).ok().filter(|_| self.value.len() == 4)
+    }
+
+    /// Value as one or two cells
+    pub fn as_u64(&self) -> Option<u64> {
+        let mut value = self.value;
+        read_cells(&mut value, self.value.len() as u32 / 4).filter(|_| value.is_empty())
+    }
+
+    /// Value as a string
+    pub fn as_str(&self) -> Option<&'a str> {
+        c_str(self.value).ok()
+    }
+
+    /// Value as a string list
+    pub fn strings(&self) -> impl Iterator<Item = &'a str> {
+        self.value
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| core::str::from_utf8(s).ok())
+    }
+}
+
+/// Devicetree node
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    /// Node name, with the unit address
+    pub name: &'a str,
+    /// Offset of the first token after the name
+    offset: usize,
+}
+
+impl<'a> Node<'a> {
+    /// Properties of the node
+    pub fn properties(&self) -> impl Iterator<Item = Property<'a>> {
+        let fdt = self.fdt;
+        let mut offset = self.offset;
+        core::iter::from_fn(move || match fdt.token(&mut offset) {
+            Ok(Token::Prop(property)) => Some(property),
+            _ => None,
+        })
+    }
+
+    /// Property `name`
+    pub fn property(&self, name: &str) -> Option<Property<'a>> {
+        self.properties().find(|property| property.name == name)
+    }
+
+    /// String property `name`
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        self.property(name)?.as_str()
+    }
+
+    /// Single cell property `name`
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        self.property(name)?.as_u32()
+    }
+
+    /// Direct children of the node
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> {
+        let fdt = self.fdt;
+        let mut offset = self.offset;
+        let mut depth = 0usize;
+        core::iter::from_fn(move || loop {
+            match fdt.token(&mut offset).ok()? {
+                Token::BeginNode(node) => {
+                    depth += 1;
+                    if depth == 1 {
+                        return Some(node);
+                    }
+                }
+                Token::EndNode => {
+                    depth = depth.checked_sub(1)?;
+                }
+                Token::Prop(_) => {}
+                Token::End => return None,
+            }
+        })
+    }
+
+    /// Name without the unit address
+    pub fn base_name(&self) -> &'a str {
+        self.name.split('@').next().unwrap_or(self.name)
+    }
+
+    /// Entries of the `compatible` property, most specific first
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> {
+        self.property("compatible")
+            .into_iter()
+            .flat_map(|property| property.strings())
+    }
+
+    /// Whether `compatible` lists `model`
+    pub fn is_compatible(&self, model: &str) -> bool {
+        self.compatible().any(|entry| entry == model)
+    }
+
+    /// Whether the node is enabled; nodes without `status` are
+    pub fn is_enabled(&self) -> bool {
+        matches!(self.property_str("status"), None | Some("okay" | "ok"))
+    }
+
+    /// `#address-cells` for the children of the node
+    pub fn address_cells(&self) -> u32 {
+        self.property_u32("#address-cells").unwrap_or(2)
+    }
+
+    /// `#size-cells` for the children of the node
+    pub fn size_cells(&self) -> u32 {
+        self.property_u32("#size-cells").unwrap_or(1)
+    }
+
+    /// `reg` entries as (address, size), in the address space of `parent`
+    pub fn reg(&self, parent: &Node<'a>) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let address_cells = parent.address_cells();
+        let size_cells = parent.size_cells();
+        let mut value = self.property("reg").map_or(&[][..], |reg| reg.value);
+        core::iter::from_fn(move || {
+            let address = read_cells(&mut value, address_cells)?;
+            let size = read_cells(&mut value, size_cells)?;
+            Some((address, size))
+        })
+    }
+}
+
+/// Translate `address`, from the bus of the last node of `path`, to a CPU
+/// physical address
+///
+/// Returns `None` when a bus on the way has no `ranges` covering it.
+pub fn translate(path: &[Node<'_>], mut address: u64) -> Option<u64> {
+    // path[i] is a bus whose children address with its cells, path[i - 1]
+    // the bus it sits on
+    for i in (1..path.len().saturating_sub(1)).rev() {
+        let bus = &path[i];
+        let ranges = bus.property("ranges")?;
+        if ranges.value.is_empty() {
+            continue;
+        }
+        let child_cells = bus.address_cells();
+        let parent_cells = path[i - 1].address_cells();
+        let size_cells = bus.size_cells();
+
+        let mut value = ranges.value;
+        address = loop {
+            let child = read_cells(&mut value, child_cells)?;
+            let parent = read_cells(&mut value, parent_cells)?;
+            let size = read_cells(&mut value, size_cells)?;
+            if (child..child.saturating_add(size)).contains(&address) {
+                break parent + (address - child);
+            }
+        };
+    }
+    Some(address)
+}
+
+/// Discover the board from `fdt`
+///
+/// The board name comes from the root `model`, the console from
+/// `/chosen/stdout-path`, RAM from the `memory` nodes and the clock tree
+/// from the `fixed-clock` nodes, the first of which becomes
+/// [`ClockId::OSC`]. The clock tree is allocated once and never freed.
+pub fn discover(fdt: &Fdt<'static>) -> Result<(BoardInfo, PeripheralConfig), FdtError> {
+    let root = fdt.root()?;
+    let mut peripherals = PeripheralConfig::default();
+    let mut ram_size = 0u64;
+    let mut cpu = None;
+    let mut cpu_freq = 0;
+    let mut clocks = Vec::new();
+    let mut gpio_count = 0u32;
+    let mut has_wifi = false;
+
+    // stdout-path is "<path or alias>[:<options>]", the options starting
+    // with the baud rate
+    let stdout = fdt
+        .find_node("/chosen")
+        .and_then(|chosen| chosen.property_str("stdout-path"));
+    let mut console = None;
+    if let Some(stdout) = stdout {
+        let (path, options) = stdout.split_once(':').unwrap_or((stdout, ""));
+        console = fdt.resolve_alias(path).and_then(|path| fdt.find_node(path));
+        let digits = options
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(options.len());
+        peripherals.console_baud = options[..digits].parse().ok();
+    }
+
+    fdt.walk(|path| {
+        let node = path[path.len() - 1];
+        if path.len() < 2 || !node.is_enabled() {
+            return;
+        }
+        let parent = &path[path.len() - 2];
+
+        if node.property_str("device_type") == Some("memory") {
+            ram_size += node.reg(parent).map(|(_, size)| size).sum::<u64>();
+            return;
+        }
+        if node.property_str("device_type") == Some("cpu") {
+            if cpu.is_none() {
+                cpu = node.compatible().next();
+                cpu_freq = node
+                    .property("clock-frequency")
+                    .and_then(|freq| freq.as_u64())
+                    .unwrap_or(0) as u32;
+            }
+            return;
+        }
+        if node.is_compatible("fixed-clock") {
+            if let Some(hz) = node.property_u32("clock-frequency") {
+                let id = match clocks.len() {
+                    0 => ClockId::OSC,
+                    n => ClockId(n as u16 + 1),
+                };
+                let name = node.property_str("clock-output-names").unwrap_or(node.name);
+                clocks.push(ClockNode::fixed(id, name, hz));
+            }
+            return;
+        }
+        if node.base_name() == "wifi" {
+            has_wifi = true;
+            return;
+        }
+
+        let Some(device) = mmio_device(path, parent) else {
+            return;
+        };
+        if console.is_some_and(|console| console.offset == node.offset) {
+            peripherals.console = Some(device);
+        }
+        let list = match node.base_name() {
+            "serial" => &mut peripherals.uarts,
+            "gpio" => {
+                gpio_count += node.property_u32("ngpios").unwrap_or(0);
+                &mut peripherals.gpio
+            }
+            "spi" => &mut peripherals.spi,
+            "i2c" => &mut peripherals.i2c,
+            "ethernet" => &mut peripherals.ethernet,
+            "mmc" => &mut peripherals.mmc,
+            "timer" => &mut peripherals.timers,
+            "watchdog" => &mut peripherals.watchdogs,
+            _ if node.property("interrupt-controller").is_some() => {
+                &mut peripherals.interrupt_controllers
+            }
+            _ => return,
+        };
+        list.push(device);
+    })?;
+
+    if cpu_freq != 0 && !clocks.iter().any(|clock| clock.id == ClockId::CPU) {
+        clocks.push(ClockNode::fixed(ClockId::CPU, "cpu", cpu_freq));
+    }
+
+    let info = BoardInfo {
+        name: root
+            .property_str("model")
+            .or_else(|| root.compatible().next())
+            .unwrap_or("Unknown board"),
+        cpu: cpu.unwrap_or("Unknown CPU"),
+        ram_size: usize::try_from(ram_size).unwrap_or(usize::MAX),
+        flash_size: 0,
+        cpu_freq,
+        clocks: ClockTree::new(clocks.leak()),
+        has_ethernet: !peripherals.ethernet.is_empty(),
+        has_wifi,
+        gpio_count: gpio_count.min(u32::from(u8::MAX)) as u8,
+        uart_count: peripherals.uarts.len() as u8,
+        spi_count: peripherals.spi.len() as u8,
+        i2c_count: peripherals.i2c.len() as u8,
+    };
+    Ok((info, peripherals))
+}
+
+/// First `reg` region of the last node of `path`, as a CPU address
+fn mmio_device(path: &[Node<'static>], parent: &Node<'static>) -> Option<MmioDevice> {
+    let node = path[path.len() - 1];
+    let (address, size) = node.reg(parent).next()?;
+    let base = translate(path, address)?;
+    Some(MmioDevice {
+        name: node.name,
+        compatible: node.compatible().next().unwrap_or(""),
+        base: usize::try_from(base).ok()?,
+        size: usize::try_from(size).ok()?,
+    })
+}
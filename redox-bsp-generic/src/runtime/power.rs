@@ -0,0 +1,256 @@
+//! Low-power sleep states and wake sources
+//!
+//! [`PowerManager`] arms wake sources through the HAL traits of the
+//! peripherals involved (GPIO interrupts, RTC alarms, UART receive
+//! interrupts) and hands the sleep itself to the SoC's [`PowerController`].
+//!
+//! The modes trade wake-up latency for current draw:
+//!
+//! | Mode                   | CPU     | Peripherals    | RAM      | Resumes       |
+//! |------------------------|---------|----------------|----------|---------------|
+//! | [`Mode::Idle`]         | stopped | running        | retained | in place      |
+//! | [`Mode::Standby`]      | stopped | wake sources   | retained | in place      |
+//! | [`Mode::DeepSleep`]    | off     | RTC domain     | lost     | through reset |
+//!
+//! Waking from deep sleep boots the system again; [`PowerManager::boot_wake_cause`]
+//! tells a wake-up apart from a cold boot. Keep state that must survive in a
+//! [`super::recovery::PersistentStore`].
+
+use alloc::vec::Vec;
+
+use redox_hal::gpio::{InterruptPin, Trigger};
+use redox_hal::rtc::{Alarm, AlarmMatch, DateTime, Rtc};
+use redox_hal::time::Duration;
+use redox_hal::uart::UartInterrupt;
+
+/// Sleep state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Mode {
+    /// Wait for any interrupt with everything powered
+    Idle,
+    /// Gate the CPU and all peripherals but the armed wake sources
+    Standby,
+    /// Power down everything but the RTC domain, waking up through a reset
+    DeepSleep,
+}
+
+/// Event that can end a sleep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeSource {
+    /// Interrupt of a GPIO pin
+    Gpio { pin: u8, trigger: Trigger },
+    /// RTC alarm
+    RtcAlarm { at: DateTime },
+    /// Data received on a UART port
+    Uart { port: u8 },
+}
+
+/// Why the system woke up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeCause {
+    /// A GPIO wake source fired
+    Gpio(u8),
+    /// The RTC alarm went off
+    RtcAlarm,
+    /// A UART port received data
+    Uart(u8),
+    /// Some other interrupt, only possible in [`Mode::Idle`]
+    Interrupt,
+}
+
+/// Handle of a registered wake source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakeId(u32);
+
+/// Power management errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// The SoC doesn't implement this mode
+    UnsupportedMode,
+    /// A registered wake source can't wake the SoC from this mode
+    UnsupportedWakeSource(WakeSource),
+    /// Sleeping without wake sources would never return
+    NoWakeSource,
+    /// Unknown wake source handle
+    UnknownWakeSource,
+    /// Arming the peripheral failed
+    Hal,
+}
+
+/// SoC specific part of entering and leaving sleep states
+pub trait PowerController {
+    /// Whether the SoC implements `mode`
+    fn supports(&self, mode: Mode) -> bool;
+
+    /// Whether `source` can end a sleep in `mode`
+    fn can_wake(&self, source: &WakeSource, mode: Mode) -> bool;
+
+    /// Route `sources` to the wake logic, sleep, and report what woke the
+    /// system
+    ///
+    /// Doesn't return for [`Mode::DeepSleep`]; the system boots again.
+    fn enter(&mut self, mode: Mode, sources: &[WakeSource]) -> WakeCause;
+
+    /// Wake source that caused the current boot, `None` after a cold boot
+    fn boot_wake_cause(&self) -> Option<WakeCause>;
+}
+
+/// [`PowerController`] for SoCs without power domains: every mode waits for
+/// an interrupt with the CPU idle
+pub struct CpuIdle;
+
+impl PowerController for CpuIdle {
+    fn supports(&self, mode: Mode) -> bool {
+        mode == Mode::Idle
+    }
+
+    fn can_wake(&self, _source: &WakeSource, mode: Mode) -> bool {
+        mode == Mode::Idle
+    }
+
+    fn enter(&mut self, _mode: Mode, _sources: &[WakeSource]) -> WakeCause {
+        super::sleep();
+        WakeCause::Interrupt
+    }
+
+    fn boot_wake_cause(&self) -> Option<WakeCause> {
+        None
+    }
+}
+
+/// Sleep state and wake source bookkeeping
+pub struct PowerManager<P: PowerController> {
+    controller: P,
+    sources: Vec<(WakeId, WakeSource)>,
+    next_id: u32,
+}
+
+impl<P: PowerController> PowerManager<P> {
+    /// Manage sleep through `controller`
+    pub fn new(controller: P) -> Self {
+        Self {
+            controller,
+            sources: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    fn add(&mut self, source: WakeSource) -> WakeId {
+        let id = WakeId(self.next_id);
+        self.next_id += 1;
+        self.sources.push((id, source));
+        id
+    }
+
+    /// Wake up when `pin` sees `trigger`
+    pub fn wake_on_gpio<G: InterruptPin>(
+        &mut self,
+        pin: &mut G,
+        trigger: Trigger,
+    ) -> Result<WakeId, PowerError> {
+        pin.set_trigger(trigger).map_err(|_| PowerError::Hal)?;
+        Ok(self.add(WakeSource::Gpio {
+            pin: pin.pin_number(),
+            trigger,
+        }))
+    }
+
+    /// Wake up once `after` has passed on `rtc`, with second resolution
+    pub fn wake_on_rtc_alarm<R: Rtc>(
+        &mut self,
+        rtc: &mut R,
+        after: Duration,
+    ) -> Result<WakeId, PowerError> {
+        let now = rtc.datetime().map_err(|_| PowerError::Hal)?;
+        let secs = after.as_secs().max(1);
+        let at = DateTime::from_unix_timestamp(now.to_unix_timestamp() + secs);
+        rtc.set_alarm(Alarm {
+            time: at,
+            match_config: AlarmMatch::Full,
+        })
+        .map_err(|_| PowerError::Hal)?;
+        rtc.enable_alarm_interrupt();
+        Ok(self.add(WakeSource::RtcAlarm { at }))
+    }
+
+    /// Wake up when UART `port` receives data
+    ///
+    /// The byte that woke the system may be lost while the clocks restart.
+    pub fn wake_on_uart<U: UartInterrupt>(&mut self, uart: &mut U, port: u8) -> WakeId {
+        uart.enable_rx_interrupt();
+        self.add(WakeSource::Uart { port })
+    }
+
+    /// Stop waking up from a source
+    ///
+    /// The peripheral's interrupt stays enabled, disarm it through its
+    /// driver if it shouldn't fire while awake either.
+    pub fn remove(&mut self, id: WakeId) -> Result<WakeSource, PowerError> {
+        let index = self
+            .sources
+            .iter()
+            .position(|(source_id, _)| *source_id == id)
+            .ok_or(PowerError::UnknownWakeSource)?;
+        Ok(self.sources.remove(index).1)
+    }
+
+    /// Registered wake sources
+    pub fn wake_sources(&self) -> impl Iterator<Item = &WakeSource> {
+        self.sources.iter().map(|(_, source)| source)
+    }
+
+    /// Sleep in `mode` until a wake source fires
+    ///
+    /// Returns without sleeping if one of the registered sources can't wake
+    /// the SoC from `mode`. [`Mode::Idle`] is also left by any other
+    /// interrupt; the deeper modes need at least one wake source.
+    pub fn sleep(&mut self, mode: Mode) -> Result<WakeCause, PowerError> {
+        if !self.controller.supports(mode) {
+            return Err(PowerError::UnsupportedMode);
+        }
+        if mode != Mode::Idle && self.sources.is_empty() {
+            return Err(PowerError::NoWakeSource);
+        }
+
+        let mut sources = Vec::with_capacity(self.sources.len());
+        for (_, source) in &self.sources {
+            if !self.controller.can_wake(source, mode) {
+                return Err(PowerError::UnsupportedWakeSource(*source));
+            }
+            sources.push(*source);
+        }
+
+        let cause = self.controller.enter(mode, &sources);
+        // An alarm only fires once
+        if cause == WakeCause::RtcAlarm {
+            self.sources
+                .retain(|(_, source)| !matches!(source, WakeSource::RtcAlarm { .. }));
+        }
+        Ok(cause)
+    }
+
+    /// Deepest supported mode every registered wake source can end
+    pub fn deepest_mode(&self) -> Mode {
+        [Mode::DeepSleep, Mode::Standby]
+            .into_iter()
+            .find(|&mode| {
+                self.controller.supports(mode)
+                    && !self.sources.is_empty()
+                    && self
+                        .sources
+                        .iter()
+                        .all(|(_, source)| self.controller.can_wake(source, mode))
+            })
+            .unwrap_or(Mode::Idle)
+    }
+
+    /// Wake source that caused the current boot, `None` after a cold boot
+    pub fn boot_wake_cause(&self) -> Option<WakeCause> {
+        self.controller.boot_wake_cause()
+    }
+
+    /// SoC power controller
+    pub fn controller(&mut self) -> &mut P {
+        &mut self.controller
+    }
+}
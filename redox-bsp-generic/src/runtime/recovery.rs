@@ -0,0 +1,399 @@
+//! Watchdog service and panic recovery
+//!
+//! [`Recovery`] starts the hardware watchdog from [`EmbeddedConfig`] and
+//! feeds it from the idle loop, so a system that stops reaching idle gets
+//! reset. Panic messages are kept in a [`PersistentStore`] across the reset,
+//! together with the number of consecutive crash resets. Once that number
+//! reaches [`EmbeddedConfig::safe_mode_after`], the next boot comes up in
+//! [`BootMode::SafeMode`] with the minimal [`EmbeddedConfig::safe_mode`]
+//! profile.
+//!
+//! A crash is a watchdog or lockup reset, as reported by the board's
+//! [`ResetController`] through [`Recovery::start_with_reset`]. Power-on,
+//! brownout and software resets start the count over.
+//!
+//! A panic doesn't reset by itself: the panic handler records the message
+//! and halts, and the watchdog takes it from there.
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+use redox_hal::reset::{ResetCause, ResetController};
+use redox_hal::time::Duration;
+use redox_hal::watchdog::Watchdog;
+
+use crate::EmbeddedConfig;
+
+/// Memory that keeps its contents across a reset
+///
+/// Typically RAM the startup code doesn't clear, or a flash sector.
+pub trait PersistentStore {
+    /// Size in bytes
+    fn size(&self) -> usize;
+
+    /// Copy the start of the store into `buf`
+    fn read(&mut self, buf: &mut [u8]);
+
+    /// Replace the start of the store with `data`
+    fn write(&mut self, data: &[u8]);
+}
+
+/// RAM left alone by the startup code and the bootloader
+pub struct RetainedRam {
+    base: usize,
+    len: usize,
+}
+
+impl RetainedRam {
+    /// Use `len` bytes at `base`
+    ///
+    /// # Safety
+    ///
+    /// The range must be mapped RAM used by nothing else.
+    pub const unsafe fn new(base: usize, len: usize) -> Self {
+        Self { base, len }
+    }
+}
+
+impl PersistentStore for RetainedRam {
+    fn size(&self) -> usize {
+        self.len
+    }
+
+    fn read(&mut self, buf: &mut [u8]) {
+        for (offset, byte) in buf.iter_mut().take(self.len).enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((self.base + offset) as *const u8) };
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        for (offset, &byte) in data.iter().take(self.len).enumerate() {
+            unsafe { core::ptr::write_volatile((self.base + offset) as *mut u8, byte) };
+        }
+    }
+}
+
+/// Longest panic message kept, in bytes
+pub const PANIC_MESSAGE_LEN: usize = 100;
+
+/// Bytes a [`PersistentStore`] needs for the recovery record
+pub const RECORD_SIZE: usize = 124;
+
+const RECORD_MAGIC: u32 = 0x5256_4352; // "RCVR"
+const RECORD_VERSION: u16 = 1;
+const FLAG_PANICKED: u16 = 1 << 0;
+
+/// What survives a reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Record {
+    flags: u16,
+    boot_count: u32,
+    watchdog_resets: u32,
+    panic_len: u16,
+    panic: [u8; PANIC_MESSAGE_LEN],
+}
+
+impl Record {
+    const fn empty() -> Self {
+        Self {
+            flags: 0,
+            boot_count: 0,
+            watchdog_resets: 0,
+            panic_len: 0,
+            panic: [0; PANIC_MESSAGE_LEN],
+        }
+    }
+
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[0..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        bytes[4..6].copy_from_slice(&RECORD_VERSION.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.boot_count.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.watchdog_resets.to_le_bytes());
+        bytes[16..18].copy_from_slice(&self.panic_len.to_le_bytes());
+        bytes[20..120].copy_from_slice(&self.panic);
+        let checksum = crc32(&bytes[..120]);
+        bytes[120..124].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Decode a record, `None` if the store holds anything else
+    fn decode(bytes: &[u8; RECORD_SIZE]) -> Option<Self> {
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        if u32_at(0) != RECORD_MAGIC
+            || u16_at(4) != RECORD_VERSION
+            || u32_at(120) != crc32(&bytes[..120])
+        {
+            return None;
+        }
+        let mut panic = [0; PANIC_MESSAGE_LEN];
+        panic.copy_from_slice(&bytes[20..120]);
+        Some(Self {
+            flags: u16_at(6),
+            boot_count: u32_at(8),
+            watchdog_resets: u32_at(12),
+            panic_len: u16_at(16).min(PANIC_MESSAGE_LEN as u16),
+            panic,
+        })
+    }
+
+    fn panic_message(&self) -> Option<&str> {
+        if self.flags & FLAG_PANICKED == 0 {
+            return None;
+        }
+        let message = &self.panic[..usize::from(self.panic_len)];
+        // Truncation may have split a character
+        Some(match core::str::from_utf8(message) {
+            Ok(message) => message,
+            Err(err) => core::str::from_utf8(&message[..err.valid_up_to()]).unwrap_or(""),
+        })
+    }
+}
+
+/// CRC-32 (IEEE 802.3)
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Store shared with the panic handler
+static STORE: spin::Mutex<Option<&'static mut (dyn PersistentStore + Send)>> =
+    spin::Mutex::new(None);
+
+fn load(store: &mut dyn PersistentStore) -> Option<Record> {
+    if store.size() < RECORD_SIZE {
+        return None;
+    }
+    let mut bytes = [0; RECORD_SIZE];
+    store.read(&mut bytes);
+    Record::decode(&bytes)
+}
+
+fn save(record: &Record) {
+    if let Some(store) = STORE.lock().as_mut() {
+        if store.size() >= RECORD_SIZE {
+            store.write(&record.encode());
+        }
+    }
+}
+
+/// Record a panic for the next boot
+///
+/// Called by the panic handler. Does nothing before [`Recovery::start`], or
+/// if the panic hit while the store was in use.
+pub fn record_panic(info: &PanicInfo) {
+    let Some(mut guard) = STORE.try_lock() else {
+        return;
+    };
+    let Some(store) = guard.as_mut() else {
+        return;
+    };
+    let mut record = load(&mut **store).unwrap_or(Record::empty());
+
+    let mut message = MessageBuffer {
+        buf: [0; PANIC_MESSAGE_LEN],
+        len: 0,
+    };
+    let _ = write!(message, "{}", info);
+    record.flags |= FLAG_PANICKED;
+    record.panic = message.buf;
+    record.panic_len = message.len as u16;
+    if store.size() >= RECORD_SIZE {
+        store.write(&record.encode());
+    }
+}
+
+/// Formatting target that keeps the first [`PANIC_MESSAGE_LEN`] bytes
+struct MessageBuffer {
+    buf: [u8; PANIC_MESSAGE_LEN],
+    len: usize,
+}
+
+impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let count = s.len().min(PANIC_MESSAGE_LEN - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Profile the system boots into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootMode {
+    /// The configured profile
+    Normal,
+    /// The minimal profile, after repeated crash resets
+    SafeMode,
+}
+
+/// Watchdog service
+pub struct Recovery<W: Watchdog> {
+    watchdog: Option<W>,
+    mode: BootMode,
+    reset_cause: ResetCause,
+    record: Record,
+    last_panic: Record,
+}
+
+impl<W: Watchdog> Recovery<W> {
+    /// Count this boot in `store`, pick the boot mode and start `watchdog`
+    /// as `config` says
+    ///
+    /// The panic recorded by the previous boot, if any, is available from
+    /// [`Recovery::last_panic`] and cleared from the store.
+    ///
+    /// Only the watchdog tells why the last boot ended, so every other reset
+    /// counts as [`ResetCause::Unknown`]. Boards with a [`ResetController`]
+    /// use [`Recovery::start_with_cause`].
+    pub fn start(
+        watchdog: W,
+        store: &'static mut (dyn PersistentStore + Send),
+        config: &EmbeddedConfig,
+    ) -> Self {
+        let cause = if watchdog.caused_last_reset() {
+            ResetCause::Watchdog
+        } else {
+            ResetCause::Unknown
+        };
+        Self::start_with_cause(watchdog, cause, store, config)
+    }
+
+    /// Like [`Recovery::start`], with the cause of the last reset read from
+    /// `reset`
+    ///
+    /// Clears the latched reset flags, so a later reset doesn't report this
+    /// one's cause as well.
+    pub fn start_with_reset<R: ResetController>(
+        watchdog: W,
+        reset: &mut R,
+        store: &'static mut (dyn PersistentStore + Send),
+        config: &EmbeddedConfig,
+    ) -> Self {
+        let cause = reset.reset_cause();
+        let _ = reset.clear_reset_cause();
+        Self::start_with_cause(watchdog, cause, store, config)
+    }
+
+    /// Like [`Recovery::start`], for a last reset caused by `cause`
+    pub fn start_with_cause(
+        mut watchdog: W,
+        cause: ResetCause,
+        store: &'static mut (dyn PersistentStore + Send),
+        config: &EmbeddedConfig,
+    ) -> Self {
+        let previous = load(store).unwrap_or(Record::empty());
+        *STORE.lock() = Some(store);
+
+        let mut record = previous;
+        record.boot_count = record.boot_count.wrapping_add(1);
+        record.watchdog_resets = if cause.is_crash() {
+            record.watchdog_resets.saturating_add(1)
+        } else {
+            0
+        };
+        record.flags &= !FLAG_PANICKED;
+        record.panic_len = 0;
+        save(&record);
+
+        let mode =
+            if config.safe_mode_after != 0 && record.watchdog_resets >= config.safe_mode_after {
+                BootMode::SafeMode
+            } else {
+                BootMode::Normal
+            };
+
+        let started = config.watchdog
+            && watchdog
+                .configure(Duration::from_secs(u64::from(config.watchdog_timeout)))
+                .and_then(|()| watchdog.start())
+                .is_ok();
+
+        Self {
+            watchdog: started.then_some(watchdog),
+            mode,
+            reset_cause: cause,
+            record,
+            last_panic: previous,
+        }
+    }
+
+    /// Profile to boot into
+    pub fn boot_mode(&self) -> BootMode {
+        self.mode
+    }
+
+    /// `config`, or its safe mode profile after repeated crash resets
+    pub fn config(&self, config: &EmbeddedConfig) -> EmbeddedConfig {
+        match self.mode {
+            BootMode::Normal => config.clone(),
+            BootMode::SafeMode => config.safe_mode(),
+        }
+    }
+
+    /// Whether the watchdog runs
+    pub fn watchdog_running(&self) -> bool {
+        self.watchdog.is_some()
+    }
+
+    /// Boots since the store was first used
+    pub fn boot_count(&self) -> u32 {
+        self.record.boot_count
+    }
+
+    /// Crash resets in a row up to this boot
+    pub fn watchdog_resets(&self) -> u32 {
+        self.record.watchdog_resets
+    }
+
+    /// Why the previous boot ended
+    pub fn reset_cause(&self) -> ResetCause {
+        self.reset_cause
+    }
+
+    /// Whether the previous boot panicked or ended in a crash reset
+    pub fn crashed(&self) -> bool {
+        self.reset_cause.is_crash() || self.last_panic().is_some()
+    }
+
+    /// Panic message recorded by the previous boot
+    pub fn last_panic(&self) -> Option<&str> {
+        self.last_panic.panic_message()
+    }
+
+    /// Feed the watchdog
+    pub fn feed(&mut self) {
+        if let Some(watchdog) = &mut self.watchdog {
+            let _ = watchdog.feed();
+        }
+    }
+
+    /// Idle loop body: feed the watchdog and sleep until the next interrupt
+    pub fn idle(&mut self) {
+        self.feed();
+        super::sleep();
+    }
+
+    /// Reset the crash reset count once the system has run long enough to
+    /// be considered healthy
+    ///
+    /// A crash reset after this counts as the first in a row again.
+    pub fn mark_healthy(&mut self) {
+        if self.record.watchdog_resets != 0 {
+            self.record.watchdog_resets = 0;
+            save(&self.record);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! OTA image verification and A/B slot switching
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ed25519_dalek::{Signer, SigningKey};
+use redox_bsp_generic::ota::*;
+use redox_bsp_generic::runtime::recovery::PersistentStore;
+use redox_hal::reset::ResetCause;
+
+const SLOT_SIZE: usize = 4096;
+
+/// Two slots of erasable memory
+struct MemorySlots([Vec<u8>; 2]);
+
+impl MemorySlots {
+    fn new() -> Self {
+        Self([vec![0xFF; SLOT_SIZE], vec![0xFF; SLOT_SIZE]])
+    }
+
+    fn slot(&mut self, slot: Slot) -> &mut Vec<u8> {
+        match slot {
+            Slot::A => &mut self.0[0],
+            Slot::B => &mut self.0[1],
+        }
+    }
+}
+
+impl SlotStorage for MemorySlots {
+    fn slot_size(&self) -> usize {
+        SLOT_SIZE
+    }
+
+    fn erase(&mut self, slot: Slot) -> Result<(), redox_hal::Error> {
+        self.slot(slot).fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, slot: Slot, offset: usize, data: &[u8]) -> Result<(), redox_hal::Error> {
+        // Like NOR flash, programming only clears bits
+        for (byte, new) in self.slot(slot)[offset..offset + data.len()]
+            .iter_mut()
+            .zip(data)
+        {
+            *byte &= new;
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, slot: Slot, offset: usize, buf: &mut [u8]) -> Result<(), redox_hal::Error> {
+        buf.copy_from_slice(&self.slot(slot)[offset..offset + buf.len()]);
+        Ok(())
+    }
+}
+
+/// Store surviving "resets", shared between the [`Ota`]s of several boots
+#[derive(Clone)]
+struct RetainedStore(Rc<RefCell<Vec<u8>>>);
+
+impl RetainedStore {
+    fn new() -> Self {
+        Self(Rc::new(RefCell::new(vec![0; BOOT_CONTROL_SIZE])))
+    }
+}
+
+impl PersistentStore for RetainedStore {
+    fn size(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    fn read(&mut self, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0.borrow()[..buf.len()]);
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.0.borrow_mut()[..data.len()].copy_from_slice(data);
+    }
+}
+
+fn signing_key() -> SigningKey {
+    SigningKey::from_bytes(&[7; 32])
+}
+
+fn verifier() -> Ed25519Verifier {
+    Ed25519Verifier::new(signing_key().verifying_key().as_bytes()).unwrap()
+}
+
+fn image(version: u32, payload: &[u8]) -> Vec<u8> {
+    let mut image = vec![0; HEADER_SIZE];
+    image[0..4].copy_from_slice(&IMAGE_MAGIC.to_le_bytes());
+    image[4..6].copy_from_slice(&IMAGE_VERSION.to_le_bytes());
+    image[8..12].copy_from_slice(&version.to_le_bytes());
+    image[12..16].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+    image[16..48].copy_from_slice(&Sha256::digest(payload));
+    let signature = signing_key().sign(&image[..SIGNED_LEN]);
+    image[SIGNED_LEN..HEADER_SIZE].copy_from_slice(&signature.to_bytes());
+    image.extend_from_slice(payload);
+    image
+}
+
+type TestOta = Ota<MemorySlots, RetainedStore, Ed25519Verifier>;
+
+fn install(ota: &mut TestOta, image: &[u8]) -> Result<u32, OtaError> {
+    ota.begin()?;
+    // Uneven chunks, as they come off the network
+    for chunk in image.chunks(100) {
+        ota.write(chunk)?;
+    }
+    ota.finish()
+}
+
+fn hex(digest: [u8; 32]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn sha256_known_answers() {
+    // FIPS 180-4 examples and the NIST long message vector
+    assert_eq!(
+        hex(Sha256::digest(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex(Sha256::digest(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hex(Sha256::digest(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+
+    let mut hasher = Sha256::new();
+    for _ in 0..1000 {
+        hasher.update(&[b'a'; 1000]);
+    }
+    assert_eq!(
+        hex(hasher.finish()),
+        "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+    );
+}
+
+#[test]
+fn sha256_split_updates() {
+    // Splitting the input must not change the digest, whatever the block
+    // boundaries
+    let data: Vec<u8> = (0..300u16).map(|i| i as u8).collect();
+    let expected = Sha256::digest(&data);
+    for split in [0, 1, 55, 56, 63, 64, 65, 128, 299] {
+        let mut hasher = Sha256::new();
+        hasher.update(&data[..split]);
+        hasher.update(&data[split..]);
+        assert_eq!(hasher.finish(), expected, "split at {}", split);
+    }
+}
+
+#[test]
+fn ed25519_verifier() {
+    let verifier = verifier();
+    let signature = signing_key().sign(b"header").to_bytes();
+    assert!(verifier.verify(b"header", &signature));
+    assert!(!verifier.verify(b"headers", &signature));
+
+    let mut bad = signature;
+    bad[0] ^= 1;
+    assert!(!verifier.verify(b"header", &bad));
+
+    let other = SigningKey::from_bytes(&[8; 32]).sign(b"header").to_bytes();
+    assert!(!verifier.verify(b"header", &other));
+}
+
+#[test]
+fn update_is_confirmed() {
+    let store = RetainedStore::new();
+    let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+    assert_eq!(ota.boot_slot(), Slot::A);
+
+    assert_eq!(install(&mut ota, &image(5, &[7; 500])), Ok(5));
+    assert_eq!(ota.trial_slot(), Some(Slot::B));
+    assert_eq!(ota.begin(), Err(OtaError::TrialPending));
+
+    // Reset into the new firmware, which confirms
+    let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+    assert_eq!(ota.boot_slot(), Slot::B);
+    ota.confirm();
+    assert_eq!(ota.active_slot(), Slot::B);
+    assert_eq!(ota.trial_slot(), None);
+    assert_eq!(ota.firmware_version(Slot::B), 5);
+
+    // And stays there
+    let mut ota = Ota::new(MemorySlots::new(), store, verifier());
+    assert_eq!(ota.boot_slot(), Slot::B);
+    assert_eq!(ota.boot_slot(), Slot::B);
+}
+
+#[test]
+fn unconfirmed_update_rolls_back() {
+    let store = RetainedStore::new();
+    let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+    assert_eq!(install(&mut ota, &image(2, &[1; 300])), Ok(2));
+
+    // The new firmware crashes on every boot
+    for _ in 0..MAX_TRIAL_BOOTS {
+        let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+        assert_eq!(ota.boot_slot_after(ResetCause::Watchdog), Slot::B);
+    }
+    let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+    assert_eq!(ota.boot_slot_after(ResetCause::Watchdog), Slot::A);
+    assert_eq!(ota.active_slot(), Slot::A);
+    assert_eq!(ota.trial_slot(), None);
+
+    // The next update goes to the slot that was rolled back
+    assert_eq!(install(&mut ota, &image(3, &[2; 300])), Ok(3));
+    assert_eq!(ota.trial_slot(), Some(Slot::B));
+}
+
+#[test]
+fn clean_resets_keep_trial_attempts() {
+    let store = RetainedStore::new();
+    let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+    assert_eq!(install(&mut ota, &image(2, &[1; 10])), Ok(2));
+
+    // Power cuts don't use up attempts, crashes do
+    for _ in 0..2 * MAX_TRIAL_BOOTS {
+        let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+        assert_eq!(ota.boot_slot_after(ResetCause::PowerOn), Slot::B);
+    }
+    for _ in 1..MAX_TRIAL_BOOTS {
+        let mut ota = Ota::new(MemorySlots::new(), store.clone(), verifier());
+        assert_eq!(ota.boot_slot_after(ResetCause::Watchdog), Slot::B);
+    }
+    let mut ota = Ota::new(MemorySlots::new(), store, verifier());
+    assert_eq!(ota.boot_slot_after(ResetCause::Watchdog), Slot::A);
+}
+
+#[test]
+fn explicit_rollback() {
+    let mut ota = Ota::new(MemorySlots::new(), RetainedStore::new(), verifier());
+    assert_eq!(install(&mut ota, &image(2, &[1; 10])), Ok(2));
+    ota.rollback();
+    assert_eq!(ota.trial_slot(), None);
+    assert_eq!(ota.boot_slot(), Slot::A);
+}
+
+#[test]
+fn bad_images_are_refused() {
+    let mut ota = Ota::new(MemorySlots::new(), RetainedStore::new(), verifier());
+    assert_eq!(install(&mut ota, &image(4, &[1; 10])), Ok(4));
+    ota.confirm();
+
+    let mut tampered = image(6, &[1; 10]);
+    tampered[HEADER_SIZE] ^= 1;
+    assert_eq!(install(&mut ota, &tampered), Err(OtaError::HashMismatch));
+
+    let mut resigned = image(6, &[1; 10]);
+    resigned[8] = 9;
+    assert_eq!(install(&mut ota, &resigned), Err(OtaError::BadSignature));
+
+    let mut foreign = image(6, &[1; 10]);
+    let signature = SigningKey::from_bytes(&[8; 32]).sign(&foreign[..SIGNED_LEN]);
+    foreign[SIGNED_LEN..HEADER_SIZE].copy_from_slice(&signature.to_bytes());
+    assert_eq!(install(&mut ota, &foreign), Err(OtaError::BadSignature));
+
+    assert_eq!(
+        install(&mut ota, &image(3, &[1; 10])),
+        Err(OtaError::Downgrade {
+            current: 4,
+            image: 3
+        })
+    );
+
+    let truncated = image(6, &[1; 500]);
+    assert_eq!(
+        install(&mut ota, &truncated[..truncated.len() - 1]),
+        Err(OtaError::Incomplete)
+    );
+
+    assert_eq!(
+        install(&mut ota, &image(6, &[1; SLOT_SIZE])),
+        Err(OtaError::TooLarge)
+    );
+
+    // None of them left a trial behind
+    assert_eq!(ota.trial_slot(), None);
+    assert_eq!(ota.active_slot(), Slot::B);
+}
@@ -267,6 +267,31 @@ pub mod cache {
     pub unsafe fn zero_dcache_line(addr: u64) {
         core::arch::asm!("dc zva, {}", in(reg) addr);
     }
+
+    /// Data cache maintenance for non-coherent DMA
+    #[cfg(feature = "dma")]
+    pub struct DataCache;
+
+    #[cfg(feature = "dma")]
+    impl crate::dma::CacheMaintenance for DataCache {
+        const LINE_SIZE: usize = 64;
+
+        unsafe fn clean_line(addr: usize) {
+            clean_dcache_line(addr as u64);
+        }
+
+        unsafe fn invalidate_line(addr: usize) {
+            invalidate_dcache_line(addr as u64);
+        }
+
+        unsafe fn clean_invalidate_line(addr: usize) {
+            clean_invalidate_dcache_line(addr as u64);
+        }
+
+        fn barrier() {
+            super::barriers::dsb_sy();
+        }
+    }
 }
 
 /// GICv2/GICv3 interrupt controller
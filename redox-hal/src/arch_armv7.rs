@@ -142,6 +142,34 @@ pub mod cache {
         asm!("mcr p15, 0, {}, c7, c14, 1", in(reg) addr);
     }
 
+    /// Data cache maintenance for non-coherent DMA
+    ///
+    /// Uses the 32-byte lines of the Cortex-A7; walking larger lines in
+    /// 32-byte steps is only redundant.
+    #[cfg(feature = "dma")]
+    pub struct DataCache;
+
+    #[cfg(feature = "dma")]
+    impl crate::dma::CacheMaintenance for DataCache {
+        const LINE_SIZE: usize = 32;
+
+        unsafe fn clean_line(addr: usize) {
+            clean_dcache_line(addr);
+        }
+
+        unsafe fn invalidate_line(addr: usize) {
+            invalidate_dcache_line(addr);
+        }
+
+        unsafe fn clean_invalidate_line(addr: usize) {
+            clean_invalidate_dcache_line(addr);
+        }
+
+        fn barrier() {
+            dsb();
+        }
+    }
+
     /// Data synchronization barrier
     #[inline]
     pub fn dsb() {
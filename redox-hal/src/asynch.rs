@@ -0,0 +1,289 @@
+//! Async driver support
+//!
+//! Interrupt and DMA driven drivers implement the async peripheral traits
+//! ([`crate::spi::SpiBusAsync`], [`crate::i2c::I2cAsync`],
+//! [`crate::uart::UartAsync`]) and complete their futures from the interrupt
+//! handler through an [`InterruptSignal`]. [`Blocking`] turns such a driver
+//! back into a blocking one for code without an executor.
+
+use core::future::Future;
+use core::pin::pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// Completion flag set from an interrupt handler and awaited by a driver
+///
+/// A typical driver resets the signal, starts a DMA transfer with the
+/// completion interrupt enabled and awaits [`InterruptSignal::wait`]; the
+/// interrupt handler calls [`InterruptSignal::signal`].
+pub struct InterruptSignal {
+    signaled: AtomicBool,
+    waker: spin::Mutex<Option<Waker>>,
+}
+
+impl InterruptSignal {
+    /// Create a signal that is not set
+    pub const fn new() -> Self {
+        Self {
+            signaled: AtomicBool::new(false),
+            waker: spin::Mutex::new(None),
+        }
+    }
+
+    /// Set the signal and wake the waiting task
+    pub fn signal(&self) {
+        self.signaled.store(true, Ordering::Release);
+        // Take the waker so it is woken outside the lock.
+        let waker = self.waker.lock().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Clear the signal before starting a new operation
+    pub fn reset(&self) {
+        self.signaled.store(false, Ordering::Release);
+    }
+
+    /// Check if the signal is set
+    pub fn is_signaled(&self) -> bool {
+        self.signaled.load(Ordering::Acquire)
+    }
+
+    /// Poll for the signal, consuming it when set
+    pub fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.signaled.swap(false, Ordering::AcqRel) {
+            return Poll::Ready(());
+        }
+
+        {
+            let mut waker = self.waker.lock();
+            match waker.as_ref() {
+                Some(current) if current.will_wake(cx.waker()) => {}
+                _ => *waker = Some(cx.waker().clone()),
+            }
+        }
+
+        // The interrupt may have fired before the waker was stored.
+        if self.signaled.swap(false, Ordering::AcqRel) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Wait for the signal
+    pub fn wait(&self) -> Wait<'_> {
+        Wait { signal: self }
+    }
+}
+
+impl Default for InterruptSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by [`InterruptSignal::wait`]
+pub struct Wait<'a> {
+    signal: &'a InterruptSignal,
+}
+
+impl Future for Wait<'_> {
+    type Output = ();
+
+    fn poll(self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.signal.poll_wait(cx)
+    }
+}
+
+static FLAG_WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    flag_waker_clone,
+    flag_waker_wake,
+    flag_waker_wake,
+    flag_waker_drop,
+);
+
+fn flag_waker_clone(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &FLAG_WAKER_VTABLE)
+}
+
+fn flag_waker_wake(data: *const ()) {
+    // SAFETY: `block_on` keeps the flag alive while the future can wake it.
+    unsafe { (*data.cast::<AtomicBool>()).store(true, Ordering::Release) };
+}
+
+fn flag_waker_drop(_data: *const ()) {}
+
+/// Run a future to completion on the current core
+///
+/// `idle` is called whenever the future is pending and has not been woken
+/// since it was last polled, e.g. to wait for an interrupt. The future must
+/// not keep its waker beyond its own lifetime.
+pub fn block_on<F: Future>(future: F, idle: fn()) -> F::Output {
+    let woken = AtomicBool::new(true);
+    let raw = RawWaker::new((&woken as *const AtomicBool).cast(), &FLAG_WAKER_VTABLE);
+    // SAFETY: the vtable functions only touch `woken`, which outlives the
+    // future polled below.
+    let waker = unsafe { Waker::from_raw(raw) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        if woken.swap(false, Ordering::AcqRel) {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        } else {
+            idle();
+        }
+    }
+}
+
+/// Blocking adapter for async peripheral drivers
+///
+/// Implements [`crate::spi::SpiBus`], [`crate::i2c::I2c`] and
+/// [`crate::uart::Uart`] for drivers implementing the async variants, by
+/// running every operation to completion with [`block_on`].
+pub struct Blocking<T> {
+    inner: T,
+    idle: fn(),
+}
+
+impl<T> Blocking<T> {
+    /// Wrap an async driver, spinning while it waits
+    pub fn new(inner: T) -> Self {
+        Self::with_idle(inner, core::hint::spin_loop)
+    }
+
+    /// Wrap an async driver, calling `idle` while it waits
+    ///
+    /// Pass a function executing `wfi` (Cortex-M, RISC-V) to sleep until the
+    /// completion interrupt instead of spinning.
+    pub fn with_idle(inner: T, idle: fn()) -> Self {
+        Self { inner, idle }
+    }
+
+    /// Get the wrapped driver
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get the wrapped driver (mutable)
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Unwrap the driver
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[cfg(feature = "spi")]
+impl<T: crate::spi::SpiBusAsync> crate::spi::SpiBus for Blocking<T> {
+    type Error = T::Error;
+
+    fn configure(&mut self, config: crate::spi::SpiConfig) -> Result<(), Self::Error> {
+        self.inner.configure(config)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        block_on(self.inner.transfer(read, write), self.idle)
+    }
+
+    fn transfer_in_place(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        block_on(self.inner.transfer_in_place(data), self.idle)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        block_on(self.inner.write(data), self.idle)
+    }
+
+    fn read(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        block_on(self.inner.read(data), self.idle)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        block_on(self.inner.flush(), self.idle)
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<T: crate::i2c::I2cAsync> crate::i2c::I2c for Blocking<T> {
+    type Error = T::Error;
+
+    fn configure(&mut self, config: crate::i2c::I2cConfig) -> Result<(), Self::Error> {
+        self.inner.configure(config)
+    }
+
+    fn write(&mut self, address: crate::i2c::I2cAddress, data: &[u8]) -> Result<(), Self::Error> {
+        block_on(self.inner.write(address, data), self.idle)
+    }
+
+    fn read(
+        &mut self,
+        address: crate::i2c::I2cAddress,
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        block_on(self.inner.read(address, buffer), self.idle)
+    }
+
+    fn write_read(
+        &mut self,
+        address: crate::i2c::I2cAddress,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        block_on(self.inner.write_read(address, write, read), self.idle)
+    }
+
+    fn transaction(
+        &mut self,
+        address: crate::i2c::I2cAddress,
+        operations: &mut [crate::i2c::I2cOperation<'_>],
+    ) -> Result<(), Self::Error> {
+        block_on(self.inner.transaction(address, operations), self.idle)
+    }
+}
+
+#[cfg(feature = "uart")]
+impl<T: crate::uart::UartAsync> crate::uart::Uart for Blocking<T> {
+    type Error = T::Error;
+
+    fn configure(&mut self, config: crate::uart::UartConfig) -> Result<(), Self::Error> {
+        self.inner.configure(config)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        block_on(self.inner.write(data), self.idle)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        block_on(self.inner.read(buffer), self.idle)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        block_on(self.inner.read_byte(), self.idle)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        block_on(self.inner.flush(), self.idle)
+    }
+
+    fn is_rx_ready(&self) -> bool {
+        self.inner.rx_available() > 0
+    }
+
+    fn is_tx_ready(&self) -> bool {
+        self.inner.tx_free() > 0
+    }
+
+    fn rx_available(&self) -> usize {
+        self.inner.rx_available()
+    }
+
+    fn tx_free(&self) -> usize {
+        self.inner.tx_free()
+    }
+}
@@ -3,7 +3,42 @@
 //! This module contains comprehensive board information for ESP32, STM32, Teensy,
 //! Raspberry Pi, Radxa, ODROID, Pine64, Orange Pi, NanoPi, and Banana Pi.
 
-use crate::{Architecture, BoardInfo, PeripheralConfig};
+// Only the boards enabled by features use these
+#[cfg(any(
+    feature = "esp32",
+    feature = "esp32-s3",
+    feature = "esp32-c3",
+    feature = "stm32f4",
+    feature = "stm32h7",
+    feature = "stm32f1",
+    feature = "teensy-40",
+    feature = "teensy-41",
+    feature = "teensy-36",
+    feature = "rpi-zero-w",
+    feature = "rpi-4",
+    feature = "rpi-5",
+    feature = "rpi-pico",
+    feature = "radxa-rock-5b",
+    feature = "radxa-rock-4",
+    feature = "odroid-n2",
+    feature = "odroid-m1",
+    feature = "pinephone-pro",
+    feature = "pinebook-pro",
+    feature = "star64",
+    feature = "orange-pi-5",
+    feature = "orange-pi-zero2",
+    feature = "nanopi-r5s",
+    feature = "nanopi-r6s",
+    feature = "nanopi-r4s",
+    feature = "banana-pi-m5",
+    feature = "banana-pi-r3",
+    feature = "banana-pi-r4",
+    feature = "banana-pi-m7",
+))]
+use crate::{
+    clocks::{ClockId, ClockNode, ClockTree, PllConfig},
+    Architecture, BoardInfo, PeripheralConfig,
+};
 
 // ============================================================
 // ESP32 Family
@@ -16,6 +51,10 @@ pub const ESP32: BoardInfo = BoardInfo {
     variant: "ESP32-WROOM-32",
     arch: Architecture::Xtensa,
     cpu_freq: 240_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 40_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 6, 1)),
+    ]),
     ram_size: 520 * 1024,       // 520KB SRAM
     flash_size: 4 * 1024 * 1024, // 4MB typically
     peripherals: PeripheralConfig {
@@ -35,6 +74,10 @@ pub const ESP32_S3: BoardInfo = BoardInfo {
     variant: "ESP32-S3-WROOM-1",
     arch: Architecture::Xtensa,
     cpu_freq: 240_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 40_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 6, 1)),
+    ]),
     ram_size: 512 * 1024,
     flash_size: 8 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -54,6 +97,10 @@ pub const ESP32_C3: BoardInfo = BoardInfo {
     variant: "ESP32-C3-MINI-1",
     arch: Architecture::RISCV32,
     cpu_freq: 160_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 40_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 4, 1)),
+    ]),
     ram_size: 400 * 1024,
     flash_size: 4 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -77,6 +124,10 @@ pub const STM32F407_DISCOVERY: BoardInfo = BoardInfo {
     variant: "STM32F407VG",
     arch: Architecture::ARMv7M,
     cpu_freq: 168_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 8_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 21, 1)),
+    ]),
     ram_size: 192 * 1024,      // 192KB
     flash_size: 1024 * 1024,   // 1MB
     peripherals: PeripheralConfig {
@@ -96,6 +147,10 @@ pub const STM32H743_NUCLEO: BoardInfo = BoardInfo {
     variant: "STM32H743ZI",
     arch: Architecture::ARMv7M,
     cpu_freq: 480_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 8_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 60, 1)),
+    ]),
     ram_size: 1024 * 1024,     // 1MB
     flash_size: 2 * 1024 * 1024, // 2MB
     peripherals: PeripheralConfig {
@@ -115,6 +170,10 @@ pub const STM32_BLUE_PILL: BoardInfo = BoardInfo {
     variant: "STM32F103C8T6",
     arch: Architecture::ARMv7M,
     cpu_freq: 72_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 8_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 9, 1)),
+    ]),
     ram_size: 20 * 1024,       // 20KB
     flash_size: 64 * 1024,     // 64KB
     peripherals: PeripheralConfig {
@@ -138,6 +197,10 @@ pub const TEENSY_40: BoardInfo = BoardInfo {
     variant: "IMXRT1062",
     arch: Architecture::ARMv7M,
     cpu_freq: 600_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 25, 1)),
+    ]),
     ram_size: 1024 * 1024,     // 1MB
     flash_size: 2 * 1024 * 1024, // 2MB
     peripherals: PeripheralConfig {
@@ -157,6 +220,10 @@ pub const TEENSY_41: BoardInfo = BoardInfo {
     variant: "IMXRT1062",
     arch: Architecture::ARMv7M,
     cpu_freq: 600_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 25, 1)),
+    ]),
     ram_size: 1024 * 1024,
     flash_size: 8 * 1024 * 1024, // 8MB
     peripherals: PeripheralConfig {
@@ -176,6 +243,10 @@ pub const TEENSY_36: BoardInfo = BoardInfo {
     variant: "MK66FX1M0",
     arch: Architecture::ARMv7M,
     cpu_freq: 180_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 16_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(4, 45, 1)),
+    ]),
     ram_size: 256 * 1024,
     flash_size: 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -199,6 +270,10 @@ pub const RPI_ZERO_W: BoardInfo = BoardInfo {
     variant: "BCM2835",
     arch: Architecture::ARMv6,
     cpu_freq: 1_000_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 19_200_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(12, 625, 1)),
+    ]),
     ram_size: 512 * 1024 * 1024,
     flash_size: 0, // SD card
     peripherals: PeripheralConfig {
@@ -218,6 +293,10 @@ pub const RPI_4: BoardInfo = BoardInfo {
     variant: "BCM2711",
     arch: Architecture::ARMv8,
     cpu_freq: 1_500_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 54_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(9, 250, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024, // 4GB model
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -237,6 +316,10 @@ pub const RPI_5: BoardInfo = BoardInfo {
     variant: "BCM2712",
     arch: Architecture::ARMv8,
     cpu_freq: 2_400_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 54_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(9, 400, 1)),
+    ]),
     ram_size: 8 * 1024 * 1024 * 1024, // 8GB model
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -256,6 +339,10 @@ pub const RPI_PICO: BoardInfo = BoardInfo {
     variant: "RP2040",
     arch: Architecture::ARMv7M,
     cpu_freq: 133_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 12_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(12, 133, 1)),
+    ]),
     ram_size: 264 * 1024,
     flash_size: 2 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -279,6 +366,10 @@ pub const RADXA_ROCK_5B: BoardInfo = BoardInfo {
     variant: "RK3588",
     arch: Architecture::ARMv8,
     cpu_freq: 2_400_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 100, 1)),
+    ]),
     ram_size: 16 * 1024 * 1024 * 1024, // 16GB model
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -298,6 +389,10 @@ pub const RADXA_ROCK_4: BoardInfo = BoardInfo {
     variant: "RK3399",
     arch: Architecture::ARMv8,
     cpu_freq: 1_800_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 75, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -321,6 +416,10 @@ pub const ODROID_N2: BoardInfo = BoardInfo {
     variant: "S922X",
     arch: Architecture::ARMv8,
     cpu_freq: 2_400_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 100, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -340,6 +439,10 @@ pub const ODROID_M1: BoardInfo = BoardInfo {
     variant: "RK3568",
     arch: Architecture::ARMv8,
     cpu_freq: 2_000_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(3, 250, 1)),
+    ]),
     ram_size: 8 * 1024 * 1024 * 1024,
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -363,6 +466,10 @@ pub const PINEPHONE_PRO: BoardInfo = BoardInfo {
     variant: "RK3399S",
     arch: Architecture::ARMv8,
     cpu_freq: 1_800_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 75, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 128 * 1024 * 1024 * 1024, // 128GB eMMC option
     peripherals: PeripheralConfig {
@@ -382,6 +489,10 @@ pub const PINEBOOK_PRO: BoardInfo = BoardInfo {
     variant: "RK3399",
     arch: Architecture::ARMv8,
     cpu_freq: 1_800_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 75, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 64 * 1024 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -401,6 +512,10 @@ pub const STAR64: BoardInfo = BoardInfo {
     variant: "JH7110",
     arch: Architecture::RISCV64,
     cpu_freq: 1_500_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(2, 125, 1)),
+    ]),
     ram_size: 8 * 1024 * 1024 * 1024,
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -424,6 +539,10 @@ pub const ORANGE_PI_5: BoardInfo = BoardInfo {
     variant: "RK3588S",
     arch: Architecture::ARMv8,
     cpu_freq: 2_400_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 100, 1)),
+    ]),
     ram_size: 8 * 1024 * 1024 * 1024,
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -443,6 +562,10 @@ pub const ORANGE_PI_ZERO2: BoardInfo = BoardInfo {
     variant: "H616",
     arch: Architecture::ARMv8,
     cpu_freq: 1_500_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(2, 125, 1)),
+    ]),
     ram_size: 1024 * 1024 * 1024,
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -466,6 +589,10 @@ pub const NANOPI_R5S: BoardInfo = BoardInfo {
     variant: "RK3568",
     arch: Architecture::ARMv8,
     cpu_freq: 2_000_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(3, 250, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 32 * 1024 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -485,6 +612,10 @@ pub const NANOPI_R6S: BoardInfo = BoardInfo {
     variant: "RK3588S",
     arch: Architecture::ARMv8,
     cpu_freq: 2_400_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 100, 1)),
+    ]),
     ram_size: 8 * 1024 * 1024 * 1024,
     flash_size: 32 * 1024 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -504,6 +635,10 @@ pub const NANOPI_R4S: BoardInfo = BoardInfo {
     variant: "RK3399",
     arch: Architecture::ARMv8,
     cpu_freq: 1_800_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 75, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 0,
     peripherals: PeripheralConfig {
@@ -527,6 +662,10 @@ pub const BANANA_PI_M5: BoardInfo = BoardInfo {
     variant: "S905X3",
     arch: Architecture::ARMv8,
     cpu_freq: 2_000_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(3, 250, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 16 * 1024 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -546,6 +685,10 @@ pub const BANANA_PI_R3: BoardInfo = BoardInfo {
     variant: "MT7986A",
     arch: Architecture::ARMv8,
     cpu_freq: 2_000_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 40_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 50, 1)),
+    ]),
     ram_size: 2 * 1024 * 1024 * 1024,
     flash_size: 8 * 1024 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -565,6 +708,10 @@ pub const BANANA_PI_R4: BoardInfo = BoardInfo {
     variant: "MT7988A",
     arch: Architecture::ARMv8,
     cpu_freq: 1_800_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 40_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 45, 1)),
+    ]),
     ram_size: 4 * 1024 * 1024 * 1024,
     flash_size: 8 * 1024 * 1024 * 1024,
     peripherals: PeripheralConfig {
@@ -584,6 +731,10 @@ pub const BANANA_PI_M7: BoardInfo = BoardInfo {
     variant: "RK3588",
     arch: Architecture::ARMv8,
     cpu_freq: 2_400_000_000,
+    clocks: ClockTree::new(&[
+        ClockNode::fixed(ClockId::OSC, "xtal", 24_000_000),
+        ClockNode::pll(ClockId::CPU, "cpu", ClockId::OSC, PllConfig::new(1, 100, 1)),
+    ]),
     ram_size: 32 * 1024 * 1024 * 1024, // 32GB model
     flash_size: 64 * 1024 * 1024 * 1024,
     peripherals: PeripheralConfig {
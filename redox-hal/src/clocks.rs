@@ -0,0 +1,391 @@
+//! Clock tree and peripheral clock management
+//!
+//! A board describes its clocks as a [`ClockTree`]: oscillators feeding
+//! PLLs, dividers and finally the gates in front of each peripheral. The
+//! BSP implements [`ClockControl`] on top of the SoC's clock controller, and
+//! drivers take their peripheral clock through a [`ClockManager`], which
+//! counts users so a clock shared by several peripherals stays on until the
+//! last one releases it, and gates everything nobody uses.
+//!
+//! ```ignore
+//! let mut clocks = ClockManager::new(prcm);
+//! clocks.acquire(UART2_CLK)?;
+//! let baud_clock = clocks.controller().frequency(UART2_CLK)?;
+//! clocks.gate_unused();
+//! ```
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::time::Rate;
+
+/// Identifier of a clock in a [`ClockTree`]
+///
+/// Board clocks are numbered by the BSP, usually after the clock
+/// controller's register layout; the reference oscillator and the CPU clock
+/// have fixed identifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClockId(pub u16);
+
+impl ClockId {
+    /// Reference oscillator every other clock derives from
+    pub const OSC: ClockId = ClockId(0);
+    /// CPU core clock
+    pub const CPU: ClockId = ClockId(1);
+}
+
+/// PLL divider and multiplier settings
+///
+/// The output is `input / input_div * multiplier / output_div`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PllConfig {
+    /// Reference divider
+    pub input_div: u16,
+    /// Feedback multiplier
+    pub multiplier: u16,
+    /// Post divider
+    pub output_div: u16,
+}
+
+/// Limits of a PLL, for [`PllConfig::find`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PllLimits {
+    /// Largest reference divider
+    pub max_input_div: u16,
+    /// Largest feedback multiplier
+    pub max_multiplier: u16,
+    /// Largest post divider
+    pub max_output_div: u16,
+    /// Lowest VCO frequency in Hz
+    pub vco_min: u64,
+    /// Highest VCO frequency in Hz
+    pub vco_max: u64,
+}
+
+impl PllConfig {
+    /// Create a PLL configuration
+    pub const fn new(input_div: u16, multiplier: u16, output_div: u16) -> Self {
+        Self {
+            input_div,
+            multiplier,
+            output_div,
+        }
+    }
+
+    /// VCO frequency for a reference of `input` Hz
+    pub const fn vco(&self, input: u32) -> u64 {
+        input as u64 * self.multiplier as u64 / self.input_div as u64
+    }
+
+    /// Output frequency for a reference of `input` Hz
+    pub const fn output(&self, input: u32) -> u32 {
+        (self.vco(input) / self.output_div as u64) as u32
+    }
+
+    /// Find the configuration getting closest to `target` Hz from `input`
+    /// Hz within `limits`
+    ///
+    /// Exact matches with the smallest reference divider win.
+    pub fn find(input: u32, target: u32, limits: PllLimits) -> Option<Self> {
+        let mut best: Option<(u64, Self)> = None;
+        for input_div in 1..=limits.max_input_div {
+            for output_div in 1..=limits.max_output_div {
+                let scaled = u64::from(target) * u64::from(input_div) * u64::from(output_div);
+                let multiplier = (scaled + u64::from(input) / 2) / u64::from(input);
+                if multiplier == 0 || multiplier > u64::from(limits.max_multiplier) {
+                    continue;
+                }
+                let config = Self::new(input_div, multiplier as u16, output_div);
+                if !(limits.vco_min..=limits.vco_max).contains(&config.vco(input)) {
+                    continue;
+                }
+                let error = u64::from(config.output(input).abs_diff(target));
+                if best.is_none_or(|(best_error, _)| error < best_error) {
+                    best = Some((error, config));
+                    if error == 0 {
+                        return Some(config);
+                    }
+                }
+            }
+        }
+        best.map(|(_, config)| config)
+    }
+}
+
+/// How a clock is derived from its parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockKind {
+    /// Fixed frequency source, in Hz
+    Fixed(u32),
+    /// PLL
+    Pll(PllConfig),
+    /// Integer divider
+    Divider(u32),
+    /// Gate passing the parent's clock through when enabled
+    Gate,
+}
+
+/// One clock of a [`ClockTree`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockNode {
+    /// Identifier of the clock
+    pub id: ClockId,
+    /// Name, as in the SoC reference manual
+    pub name: &'static str,
+    /// Clock this one is derived from, `None` for sources
+    pub parent: Option<ClockId>,
+    /// How the clock is derived
+    pub kind: ClockKind,
+}
+
+impl ClockNode {
+    /// Fixed frequency source
+    pub const fn fixed(id: ClockId, name: &'static str, hz: u32) -> Self {
+        Self {
+            id,
+            name,
+            parent: None,
+            kind: ClockKind::Fixed(hz),
+        }
+    }
+
+    /// PLL fed by `parent`
+    pub const fn pll(id: ClockId, name: &'static str, parent: ClockId, config: PllConfig) -> Self {
+        Self {
+            id,
+            name,
+            parent: Some(parent),
+            kind: ClockKind::Pll(config),
+        }
+    }
+
+    /// `parent` divided by `div`
+    pub const fn divider(id: ClockId, name: &'static str, parent: ClockId, div: u32) -> Self {
+        Self {
+            id,
+            name,
+            parent: Some(parent),
+            kind: ClockKind::Divider(div),
+        }
+    }
+
+    /// Peripheral clock gate on `parent`
+    pub const fn gate(id: ClockId, name: &'static str, parent: ClockId) -> Self {
+        Self {
+            id,
+            name,
+            parent: Some(parent),
+            kind: ClockKind::Gate,
+        }
+    }
+}
+
+/// Clock tree of a board, with the frequencies it boots with
+#[derive(Debug, Clone, Copy)]
+pub struct ClockTree {
+    nodes: &'static [ClockNode],
+}
+
+impl ClockTree {
+    /// Create a clock tree
+    pub const fn new(nodes: &'static [ClockNode]) -> Self {
+        Self { nodes }
+    }
+
+    /// All clocks
+    pub fn nodes(&self) -> &'static [ClockNode] {
+        self.nodes
+    }
+
+    /// Look up a clock
+    pub fn node(&self, id: ClockId) -> Option<&'static ClockNode> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    /// Look up a clock by name
+    pub fn find(&self, name: &str) -> Option<&'static ClockNode> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    /// Clocks derived directly from `id`
+    pub fn children(&self, id: ClockId) -> impl Iterator<Item = &'static ClockNode> {
+        self.nodes
+            .iter()
+            .filter(move |node| node.parent == Some(id))
+    }
+
+    /// `id` followed by its parents up to the source
+    ///
+    /// Stops early on a missing parent or a loop.
+    pub fn ancestors(&self, id: ClockId) -> impl Iterator<Item = &'static ClockNode> + '_ {
+        let mut next = self.node(id);
+        (0..self.nodes.len()).map_while(move |_| {
+            let node = next?;
+            next = node.parent.and_then(|parent| self.node(parent));
+            Some(node)
+        })
+    }
+
+    /// Nominal frequency of a clock in Hz, `None` if it isn't in the tree
+    /// or doesn't lead to a source
+    pub fn frequency(&self, id: ClockId) -> Option<u32> {
+        let path = self.ancestors(id).collect::<Vec<_>>();
+        let mut hz = match path.last()?.kind {
+            ClockKind::Fixed(hz) => hz,
+            _ => return None,
+        };
+        for node in path.iter().rev().skip(1) {
+            hz = match node.kind {
+                ClockKind::Fixed(hz) => hz,
+                ClockKind::Pll(config) => config.output(hz),
+                ClockKind::Divider(div) => hz / div.max(1),
+                ClockKind::Gate => hz,
+            };
+        }
+        Some(hz)
+    }
+
+    /// Nominal CPU frequency in Hz
+    pub fn cpu_frequency(&self) -> Option<u32> {
+        self.frequency(ClockId::CPU)
+    }
+
+    /// Check that identifiers are unique and every parent leads to a
+    /// source, returning the first clock that breaks this
+    pub fn validate(&self) -> Result<(), ClockId> {
+        for (i, node) in self.nodes.iter().enumerate() {
+            if self.nodes[..i].iter().any(|earlier| earlier.id == node.id) {
+                return Err(node.id);
+            }
+            let source = self.ancestors(node.id).last();
+            if source.is_none_or(|source| source.parent.is_some()) {
+                return Err(node.id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if `id` feeds the CPU, so it must never be turned off
+    pub fn is_critical(&self, id: ClockId) -> bool {
+        self.ancestors(ClockId::CPU).any(|node| node.id == id)
+    }
+}
+
+/// Clock controller of a SoC
+pub trait ClockControl {
+    /// Error type
+    type Error;
+
+    /// Clock tree the controller drives
+    fn tree(&self) -> &ClockTree;
+
+    /// Ungate a clock
+    fn enable(&mut self, clock: ClockId) -> Result<(), Self::Error>;
+
+    /// Gate a clock
+    fn disable(&mut self, clock: ClockId) -> Result<(), Self::Error>;
+
+    /// Check if a clock is running
+    fn is_enabled(&self, clock: ClockId) -> bool;
+
+    /// Current frequency of a clock
+    fn frequency(&self, clock: ClockId) -> Result<Rate, Self::Error>;
+
+    /// Change the frequency of a clock, returning the frequency actually set
+    fn set_frequency(&mut self, clock: ClockId, rate: Rate) -> Result<Rate, Self::Error>;
+
+    /// Reprogram a PLL, returning its new output frequency
+    fn configure_pll(&mut self, pll: ClockId, config: PllConfig) -> Result<Rate, Self::Error>;
+}
+
+/// Reference counted clock gating on top of a [`ClockControl`]
+pub struct ClockManager<C> {
+    controller: C,
+    users: BTreeMap<ClockId, u32>,
+}
+
+impl<C: ClockControl> ClockManager<C> {
+    /// Manage the clocks of `controller`
+    pub fn new(controller: C) -> Self {
+        Self {
+            controller,
+            users: BTreeMap::new(),
+        }
+    }
+
+    /// Take a reference on `clock` and the gates above it, enabling those
+    /// that weren't used
+    ///
+    /// Nothing stays referenced if enabling one of them fails.
+    pub fn acquire(&mut self, clock: ClockId) -> Result<(), C::Error> {
+        let tree = *self.controller.tree();
+        let mut path = tree.ancestors(clock).collect::<Vec<_>>();
+        // Enable from the source down
+        path.reverse();
+
+        for (i, node) in path.iter().enumerate() {
+            if self.users(node.id) == 0 && node.kind == ClockKind::Gate {
+                if let Err(err) = self.controller.enable(node.id) {
+                    for taken in &path[..i] {
+                        self.put(taken);
+                    }
+                    return Err(err);
+                }
+            }
+            *self.users.entry(node.id).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    /// Drop a reference taken by [`ClockManager::acquire`], gating clocks
+    /// nobody uses anymore
+    pub fn release(&mut self, clock: ClockId) {
+        let tree = *self.controller.tree();
+        for node in tree.ancestors(clock) {
+            self.put(node);
+        }
+    }
+
+    fn put(&mut self, node: &ClockNode) {
+        let Some(users) = self.users.get_mut(&node.id) else {
+            return;
+        };
+        *users = users.saturating_sub(1);
+        if *users == 0 {
+            self.users.remove(&node.id);
+            if node.kind == ClockKind::Gate && !self.controller.tree().is_critical(node.id) {
+                let _ = self.controller.disable(node.id);
+            }
+        }
+    }
+
+    /// Number of references on `clock`
+    pub fn users(&self, clock: ClockId) -> u32 {
+        self.users.get(&clock).copied().unwrap_or(0)
+    }
+
+    /// Gate every running peripheral clock nobody acquired, e.g. the ones
+    /// firmware left on, returning how many were gated
+    pub fn gate_unused(&mut self) -> usize {
+        let tree = *self.controller.tree();
+        let mut gated = 0;
+        for node in tree.nodes() {
+            if node.kind != ClockKind::Gate
+                || self.users.contains_key(&node.id)
+                || tree.is_critical(node.id)
+                || !self.controller.is_enabled(node.id)
+            {
+                continue;
+            }
+            if self.controller.disable(node.id).is_ok() {
+                gated += 1;
+            }
+        }
+        gated
+    }
+
+    /// Access the clock controller
+    pub fn controller(&mut self) -> &mut C {
+        &mut self.controller
+    }
+}
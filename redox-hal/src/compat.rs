@@ -0,0 +1,455 @@
+//! embedded-hal 1.0 compatibility
+//!
+//! [`EmbeddedHal`] wraps a redox-hal peripheral so it implements the
+//! `embedded-hal` traits, letting existing sensor and display drivers from
+//! the embedded Rust ecosystem run unmodified on a Redox BSP. [`RedoxHal`]
+//! goes the other way, so peripherals of an `embedded-hal` based HAL can be
+//! handed to redox-hal drivers.
+//!
+//! | redox-hal                  | embedded-hal 1.0               |
+//! |----------------------------|--------------------------------|
+//! | [`I2c`]                    | `i2c::I2c`                     |
+//! | [`SpiBus`]                 | `spi::SpiBus`                  |
+//! | [`OutputPin`]              | `digital::OutputPin`           |
+//! | [`Delay`]                  | `delay::DelayNs`               |
+//!
+//! embedded-hal errors must report an error kind, so [`EmbeddedHal`] needs
+//! the wrapped peripheral to use [`Error`] (or another type implementing the
+//! embedded-hal error traits). [`RedoxHal`] converts the embedded-hal errors
+//! to [`Error`] by their kind.
+//!
+//! ```ignore
+//! let i2c = EmbeddedHal::new(bsp.i2c(1)?);
+//! let mut sensor = Bme280::new(i2c, 0x76);
+//! ```
+//!
+//! [`I2c`]: crate::i2c::I2c
+//! [`SpiBus`]: crate::spi::SpiBus
+//! [`OutputPin`]: crate::gpio::OutputPin
+//! [`Delay`]: crate::timer::Delay
+
+use crate::error::Error;
+
+#[cfg(any(feature = "i2c", feature = "spi", feature = "gpio"))]
+use crate::error::Result;
+
+#[cfg(feature = "i2c")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "i2c")]
+use crate::i2c::{I2c, I2cAddress, I2cConfig, I2cOperation};
+
+#[cfg(feature = "spi")]
+use crate::spi::{SpiBus, SpiConfig};
+
+#[cfg(feature = "gpio")]
+use crate::gpio::OutputPin;
+
+#[cfg(feature = "timer")]
+use crate::time::Duration;
+#[cfg(feature = "timer")]
+use crate::timer::Delay;
+
+use embedded_hal::{digital, i2c, spi};
+
+#[cfg(feature = "timer")]
+use embedded_hal::delay;
+
+/// redox-hal peripheral exposed through the embedded-hal traits
+#[derive(Debug)]
+pub struct EmbeddedHal<T> {
+    inner: T,
+}
+
+impl<T> EmbeddedHal<T> {
+    /// Wrap `inner`
+    pub const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Get a reference to the wrapped peripheral
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped peripheral
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Unwrap the peripheral
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+/// embedded-hal peripheral exposed through the redox-hal traits
+///
+/// embedded-hal buses are configured by the HAL that created them, so
+/// `configure` calls are accepted and ignored.
+#[derive(Debug)]
+pub struct RedoxHal<T> {
+    inner: T,
+}
+
+impl<T> RedoxHal<T> {
+    /// Wrap `inner`
+    pub const fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    /// Get a reference to the wrapped peripheral
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the wrapped peripheral
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Unwrap the peripheral
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+// ============================================================
+// Error kinds
+// ============================================================
+
+impl i2c::Error for Error {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            Error::NoAcknowledge => {
+                i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Unknown)
+            }
+            Error::ArbitrationLost => i2c::ErrorKind::ArbitrationLoss,
+            Error::BusError => i2c::ErrorKind::Bus,
+            Error::Overflow | Error::OverrunError => i2c::ErrorKind::Overrun,
+            _ => i2c::ErrorKind::Other,
+        }
+    }
+}
+
+impl spi::Error for Error {
+    fn kind(&self) -> spi::ErrorKind {
+        match self {
+            Error::Overflow | Error::OverrunError => spi::ErrorKind::Overrun,
+            Error::FramingError => spi::ErrorKind::FrameFormat,
+            _ => spi::ErrorKind::Other,
+        }
+    }
+}
+
+impl digital::Error for Error {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+#[cfg(feature = "i2c")]
+fn from_i2c_error(error: impl i2c::Error) -> Error {
+    match error.kind() {
+        i2c::ErrorKind::Bus => Error::BusError,
+        i2c::ErrorKind::ArbitrationLoss => Error::ArbitrationLost,
+        i2c::ErrorKind::NoAcknowledge(_) => Error::NoAcknowledge,
+        i2c::ErrorKind::Overrun => Error::OverrunError,
+        _ => Error::Other,
+    }
+}
+
+#[cfg(feature = "spi")]
+fn from_spi_error(error: impl spi::Error) -> Error {
+    match error.kind() {
+        spi::ErrorKind::Overrun => Error::OverrunError,
+        spi::ErrorKind::FrameFormat => Error::FramingError,
+        spi::ErrorKind::ModeFault | spi::ErrorKind::ChipSelectFault => Error::BusError,
+        _ => Error::Other,
+    }
+}
+
+// ============================================================
+// I2C
+// ============================================================
+
+#[cfg(feature = "i2c")]
+impl<T> i2c::ErrorType for EmbeddedHal<T>
+where
+    T: I2c,
+    T::Error: i2c::Error,
+{
+    type Error = T::Error;
+}
+
+#[cfg(feature = "i2c")]
+impl<T> EmbeddedHal<T>
+where
+    T: I2c,
+    T::Error: i2c::Error,
+{
+    fn i2c_transaction(
+        &mut self,
+        address: I2cAddress,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), T::Error> {
+        match operations {
+            [] => Ok(()),
+            [i2c::Operation::Write(data)] => self.inner.write(address, data),
+            [i2c::Operation::Read(buffer)] => self.inner.read(address, buffer),
+            [i2c::Operation::Write(write), i2c::Operation::Read(read)] => {
+                self.inner.write_read(address, write, read)
+            }
+            _ => {
+                let mut operations: Vec<I2cOperation<'_>> = operations
+                    .iter_mut()
+                    .map(|operation| match operation {
+                        i2c::Operation::Read(buffer) => I2cOperation::Read(buffer),
+                        i2c::Operation::Write(data) => I2cOperation::Write(data),
+                    })
+                    .collect();
+                self.inner.transaction(address, &mut operations)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<T> i2c::I2c<i2c::SevenBitAddress> for EmbeddedHal<T>
+where
+    T: I2c,
+    T::Error: i2c::Error,
+{
+    fn transaction(
+        &mut self,
+        address: i2c::SevenBitAddress,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.i2c_transaction(I2cAddress::SevenBit(address), operations)
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<T> i2c::I2c<i2c::TenBitAddress> for EmbeddedHal<T>
+where
+    T: I2c,
+    T::Error: i2c::Error,
+{
+    fn transaction(
+        &mut self,
+        address: i2c::TenBitAddress,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.i2c_transaction(I2cAddress::TenBit(address), operations)
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<T: i2c::I2c> I2c for RedoxHal<T> {
+    type Error = Error;
+
+    fn configure(&mut self, _config: I2cConfig) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn write(&mut self, address: I2cAddress, data: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(address, &mut [I2cOperation::Write(data)])
+    }
+
+    fn read(&mut self, address: I2cAddress, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.transaction(address, &mut [I2cOperation::Read(buffer)])
+    }
+
+    fn write_read(
+        &mut self,
+        address: I2cAddress,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.transaction(
+            address,
+            &mut [I2cOperation::Write(write), I2cOperation::Read(read)],
+        )
+    }
+
+    /// Execute a transaction
+    ///
+    /// Only 7-bit addresses are supported, a 10-bit address fails with
+    /// [`Error::InvalidParameter`].
+    fn transaction(
+        &mut self,
+        address: I2cAddress,
+        operations: &mut [I2cOperation<'_>],
+    ) -> Result<(), Self::Error> {
+        let I2cAddress::SevenBit(address) = address else {
+            return Err(Error::InvalidParameter);
+        };
+        let mut operations: Vec<i2c::Operation<'_>> = operations
+            .iter_mut()
+            .map(|operation| match operation {
+                I2cOperation::Read(buffer) => i2c::Operation::Read(buffer),
+                I2cOperation::Write(data) => i2c::Operation::Write(data),
+            })
+            .collect();
+        self.inner
+            .transaction(address, &mut operations)
+            .map_err(from_i2c_error)
+    }
+}
+
+// ============================================================
+// SPI
+// ============================================================
+
+#[cfg(feature = "spi")]
+impl<T> spi::ErrorType for EmbeddedHal<T>
+where
+    T: SpiBus,
+    T::Error: spi::Error,
+{
+    type Error = T::Error;
+}
+
+#[cfg(feature = "spi")]
+impl<T> spi::SpiBus for EmbeddedHal<T>
+where
+    T: SpiBus,
+    T::Error: spi::Error,
+{
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.inner.read(words)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.inner.write(words)
+    }
+
+    /// Transfer data
+    ///
+    /// embedded-hal allows `read` and `write` of different lengths: the
+    /// common part is transferred at once, then the rest of the longer
+    /// buffer is written or read on its own.
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        let common = read.len().min(write.len());
+        let (read, read_rest) = read.split_at_mut(common);
+        let (write, write_rest) = write.split_at(common);
+        self.inner.transfer(read, write)?;
+        if !write_rest.is_empty() {
+            self.inner.write(write_rest)?;
+        }
+        if !read_rest.is_empty() {
+            self.inner.read(read_rest)?;
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.inner.transfer_in_place(words)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "spi")]
+impl<T: spi::SpiBus> SpiBus for RedoxHal<T> {
+    type Error = Error;
+
+    fn configure(&mut self, _config: SpiConfig) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.inner.transfer(read, write).map_err(from_spi_error)
+    }
+
+    fn transfer_in_place(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        self.inner.transfer_in_place(data).map_err(from_spi_error)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.inner.write(data).map_err(from_spi_error)
+    }
+
+    fn read(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        self.inner.read(data).map_err(from_spi_error)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().map_err(from_spi_error)
+    }
+}
+
+// ============================================================
+// GPIO
+// ============================================================
+
+#[cfg(feature = "gpio")]
+impl<T> digital::ErrorType for EmbeddedHal<T>
+where
+    T: OutputPin,
+    T::Error: digital::Error,
+{
+    type Error = T::Error;
+}
+
+#[cfg(feature = "gpio")]
+impl<T> digital::OutputPin for EmbeddedHal<T>
+where
+    T: OutputPin,
+    T::Error: digital::Error,
+{
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.inner.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.inner.set_high()
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl<T: digital::OutputPin> OutputPin for RedoxHal<T> {
+    type Error = Error;
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.inner.set_high().map_err(|_| Error::Other)
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.inner.set_low().map_err(|_| Error::Other)
+    }
+}
+
+// ============================================================
+// Delay
+// ============================================================
+
+#[cfg(feature = "timer")]
+impl<T: Delay> delay::DelayNs for EmbeddedHal<T> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.inner.delay(Duration::from_nanos(ns as u64));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.inner.delay_us(us);
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.inner.delay_ms(ms);
+    }
+}
+
+#[cfg(feature = "timer")]
+impl<T: delay::DelayNs> Delay for RedoxHal<T> {
+    fn delay(&mut self, duration: Duration) {
+        // DelayNs takes a u32, split long delays into whole milliseconds.
+        let millis = duration.as_millis();
+        for _ in 0..millis / u32::MAX as u64 {
+            self.inner.delay_ms(u32::MAX);
+        }
+        self.inner.delay_ms((millis % u32::MAX as u64) as u32);
+        self.inner.delay_ns(duration.subsec_nanos() % 1_000_000);
+    }
+}
@@ -1,6 +1,30 @@
 //! DMA (Direct Memory Access) HAL traits
+//!
+//! A driver takes a channel from the controller, describes the transfer with
+//! a [`TransferDescriptor`] borrowing its buffer, and starts it with
+//! [`Transfer::start`]. The channel's completion interrupt reports to a
+//! [`DmaCompletion`], which the [`Transfer`] polls or awaits. Cache
+//! maintenance for non-coherent DMA goes through a [`CacheMaintenance`]
+//! implementation of the architecture.
+//!
+//! ```ignore
+//! static TX_DONE: DmaCompletion = DmaCompletion::new();
+//!
+//! let descriptor = TransferDescriptor::write_to(&uart, &buffer);
+//! let transfer = Transfer::<_, DataCache>::start(&mut channel, descriptor, &TX_DONE)?;
+//! transfer.wait()?;
+//!
+//! // In the channel's interrupt handler
+//! TX_DONE.complete();
+//! ```
 
-use crate::error::Result;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use crate::error::{Error, Result};
 
 /// DMA transfer direction
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -24,6 +48,40 @@ pub enum DmaDataSize {
     Word,
 }
 
+impl DmaDataSize {
+    /// Size in bytes
+    pub const fn bytes(&self) -> usize {
+        match self {
+            DmaDataSize::Byte => 1,
+            DmaDataSize::HalfWord => 2,
+            DmaDataSize::Word => 4,
+        }
+    }
+}
+
+/// Element type a DMA engine can move
+pub trait DmaWord: Copy {
+    /// Transfer width of the type
+    const SIZE: DmaDataSize;
+}
+
+impl DmaWord for u8 {
+    const SIZE: DmaDataSize = DmaDataSize::Byte;
+}
+
+impl DmaWord for u16 {
+    const SIZE: DmaDataSize = DmaDataSize::HalfWord;
+}
+
+impl DmaWord for u32 {
+    const SIZE: DmaDataSize = DmaDataSize::Word;
+}
+
+/// Peripheral request line pacing a transfer, as numbered by the DMA
+/// controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaRequest(pub u16);
+
 /// DMA priority
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DmaPriority {
@@ -118,6 +176,26 @@ pub trait DmaChannel {
 
     /// Set interrupt handler
     fn set_handler(&mut self, handler: fn());
+
+    /// Select the peripheral request line pacing the transfer, `None` for
+    /// memory to memory transfers
+    ///
+    /// Controllers with fixed request routing keep the default, which does
+    /// nothing.
+    fn set_request(&mut self, _request: Option<DmaRequest>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Configure the channel for `descriptor` and start it
+    fn submit(&mut self, descriptor: &TransferDescriptor<'_>) -> Result<(), Self::Error> {
+        self.configure(descriptor.config())?;
+        self.set_request(descriptor.request())?;
+        self.start(
+            descriptor.source(),
+            descriptor.destination(),
+            descriptor.count(),
+        )
+    }
 }
 
 /// DMA controller trait
@@ -128,12 +206,605 @@ pub trait Dma {
     type Channel: DmaChannel;
 
     /// Get a DMA channel
+    ///
+    /// Fails if the channel is already allocated.
     fn channel(&mut self, channel_number: u8) -> Result<Self::Channel, Self::Error>;
 
+    /// Allocate any free channel
+    fn allocate(&mut self) -> Result<Self::Channel, Self::Error>;
+
+    /// Return a channel, stopping it if it's still running
+    fn release(&mut self, channel: Self::Channel);
+
     /// Get the number of available channels
     fn channel_count(&self) -> u8;
 }
 
+/// Channel allocation bitmap for [`Dma`] implementations
+///
+/// Lock-free, so channels can be allocated and released from any context.
+pub struct ChannelAllocator {
+    used: AtomicU32,
+    count: u8,
+}
+
+impl ChannelAllocator {
+    /// Create an allocator of `count` channels, at most 32
+    pub const fn new(count: u8) -> Self {
+        assert!(
+            count <= 32,
+            "DMA channel allocator supports up to 32 channels"
+        );
+        Self {
+            used: AtomicU32::new(0),
+            count,
+        }
+    }
+
+    /// Number of channels
+    pub const fn count(&self) -> u8 {
+        self.count
+    }
+
+    /// Allocate the lowest free channel
+    pub fn allocate(&self) -> Option<u8> {
+        let mut used = self.used.load(Ordering::Relaxed);
+        loop {
+            let channel = (!used).trailing_zeros() as u8;
+            if channel >= self.count {
+                return None;
+            }
+            match self.used.compare_exchange_weak(
+                used,
+                used | 1 << channel,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(channel),
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    /// Allocate a specific channel
+    pub fn claim(&self, channel: u8) -> Result<()> {
+        if channel >= self.count {
+            return Err(Error::InvalidParameter);
+        }
+        let bit = 1 << channel;
+        if self.used.fetch_or(bit, Ordering::Acquire) & bit != 0 {
+            return Err(Error::Busy);
+        }
+        Ok(())
+    }
+
+    /// Free a channel
+    pub fn release(&self, channel: u8) {
+        if channel < self.count {
+            self.used.fetch_and(!(1 << channel), Ordering::Release);
+        }
+    }
+
+    /// Check if a channel is allocated
+    pub fn is_allocated(&self, channel: u8) -> bool {
+        channel < self.count && self.used.load(Ordering::Relaxed) & 1 << channel != 0
+    }
+
+    /// Number of free channels
+    pub fn available(&self) -> u8 {
+        self.count - self.used.load(Ordering::Relaxed).count_ones() as u8
+    }
+}
+
+/// Peripheral with a data register a DMA engine can feed or drain
+///
+/// Implemented by SPI, UART and I2C drivers that offer DMA-backed bulk
+/// transfers, so [`TransferDescriptor::write_to`] and
+/// [`TransferDescriptor::read_from`] can target them.
+pub trait DmaPeripheral {
+    /// Address of the register transmitted data is written to
+    fn tx_address(&self) -> usize;
+
+    /// Address of the register received data is read from
+    fn rx_address(&self) -> usize;
+
+    /// Request line raised when the peripheral can accept data
+    fn tx_request(&self) -> DmaRequest;
+
+    /// Request line raised when the peripheral has received data
+    fn rx_request(&self) -> DmaRequest;
+}
+
+/// Description of one DMA transfer
+///
+/// Borrows the memory buffer for `'a`, so it can't be touched or freed
+/// while a [`Transfer`] built from the descriptor is running.
+#[derive(Debug)]
+pub struct TransferDescriptor<'a> {
+    source: usize,
+    destination: usize,
+    count: usize,
+    config: DmaConfig,
+    request: Option<DmaRequest>,
+    _buffer: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> TransferDescriptor<'a> {
+    /// Transfer `buffer` to the peripheral register at `register`
+    pub fn memory_to_peripheral<T: DmaWord>(
+        buffer: &'a [T],
+        register: usize,
+        request: DmaRequest,
+    ) -> Self {
+        Self {
+            source: buffer.as_ptr() as usize,
+            destination: register,
+            count: buffer.len(),
+            config: DmaConfig {
+                direction: DmaDirection::MemoryToPeripheral,
+                source_size: T::SIZE,
+                dest_size: T::SIZE,
+                source_increment: true,
+                dest_increment: false,
+                ..DmaConfig::default()
+            },
+            request: Some(request),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Fill `buffer` from the peripheral register at `register`
+    pub fn peripheral_to_memory<T: DmaWord>(
+        register: usize,
+        buffer: &'a mut [T],
+        request: DmaRequest,
+    ) -> Self {
+        Self {
+            source: register,
+            destination: buffer.as_mut_ptr() as usize,
+            count: buffer.len(),
+            config: DmaConfig {
+                direction: DmaDirection::PeripheralToMemory,
+                source_size: T::SIZE,
+                dest_size: T::SIZE,
+                source_increment: false,
+                dest_increment: true,
+                ..DmaConfig::default()
+            },
+            request: Some(request),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Copy `source` to `destination`, up to the length of the shorter one
+    pub fn memory_to_memory<T: DmaWord>(source: &'a [T], destination: &'a mut [T]) -> Self {
+        Self {
+            source: source.as_ptr() as usize,
+            destination: destination.as_mut_ptr() as usize,
+            count: source.len().min(destination.len()),
+            config: DmaConfig {
+                direction: DmaDirection::MemoryToMemory,
+                source_size: T::SIZE,
+                dest_size: T::SIZE,
+                source_increment: true,
+                dest_increment: true,
+                ..DmaConfig::default()
+            },
+            request: None,
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Transmit `buffer` through `peripheral`
+    pub fn write_to<P: DmaPeripheral, T: DmaWord>(peripheral: &P, buffer: &'a [T]) -> Self {
+        Self::memory_to_peripheral(buffer, peripheral.tx_address(), peripheral.tx_request())
+    }
+
+    /// Receive into `buffer` from `peripheral`
+    pub fn read_from<P: DmaPeripheral, T: DmaWord>(peripheral: &P, buffer: &'a mut [T]) -> Self {
+        Self::peripheral_to_memory(peripheral.rx_address(), buffer, peripheral.rx_request())
+    }
+
+    /// Set the channel priority
+    pub fn with_priority(mut self, priority: DmaPriority) -> Self {
+        self.config.priority = priority;
+        self
+    }
+
+    /// Restart the transfer from the beginning of the buffer when it ends
+    pub fn circular(mut self) -> Self {
+        self.config.circular = true;
+        self
+    }
+
+    /// Source address
+    pub fn source(&self) -> usize {
+        self.source
+    }
+
+    /// Destination address
+    pub fn destination(&self) -> usize {
+        self.destination
+    }
+
+    /// Number of elements to transfer
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Number of bytes to transfer
+    pub fn byte_len(&self) -> usize {
+        self.count * self.config.source_size.bytes()
+    }
+
+    /// Channel configuration
+    pub fn config(&self) -> DmaConfig {
+        self.config
+    }
+
+    /// Peripheral request line, `None` for memory to memory transfers
+    pub fn request(&self) -> Option<DmaRequest> {
+        self.request
+    }
+
+    /// Make the buffers visible to the DMA engine before starting
+    ///
+    /// Memory read by the engine is cleaned. Memory it writes is cleaned and
+    /// invalidated, so no dirty line gets written back over the data later.
+    pub fn prepare<M: CacheMaintenance>(&self) {
+        let direction = self.config.direction;
+        // SAFETY: the ranges are the borrowed buffers.
+        unsafe {
+            if direction != DmaDirection::PeripheralToMemory {
+                M::clean_range(self.source, self.byte_len());
+            }
+            if direction != DmaDirection::MemoryToPeripheral {
+                M::clean_invalidate_range(self.destination, self.byte_len());
+            }
+        }
+    }
+
+    /// Make data written by the DMA engine visible to the CPU
+    ///
+    /// Lines fetched speculatively while the transfer ran are dropped.
+    pub fn finish<M: CacheMaintenance>(&self) {
+        if self.config.direction != DmaDirection::MemoryToPeripheral {
+            // SAFETY: the range is the borrowed buffer.
+            unsafe { M::invalidate_range(self.destination, self.byte_len()) };
+        }
+    }
+}
+
+/// Data cache maintenance for non-coherent DMA
+///
+/// The architecture modules implement this for their data cache; systems
+/// where DMA is cache coherent use [`Coherent`].
+pub trait CacheMaintenance {
+    /// Smallest data cache line size, in bytes
+    const LINE_SIZE: usize;
+
+    /// Write back the line containing `addr`
+    ///
+    /// # Safety
+    ///
+    /// `addr` must be mapped.
+    unsafe fn clean_line(addr: usize);
+
+    /// Discard the line containing `addr` without writing it back
+    ///
+    /// # Safety
+    ///
+    /// `addr` must be mapped, and any data only in the cache is lost.
+    unsafe fn invalidate_line(addr: usize);
+
+    /// Write back and discard the line containing `addr`
+    ///
+    /// # Safety
+    ///
+    /// `addr` must be mapped.
+    unsafe fn clean_invalidate_line(addr: usize) {
+        Self::clean_line(addr);
+        Self::invalidate_line(addr);
+    }
+
+    /// Wait for previous maintenance operations to complete
+    fn barrier();
+
+    /// Write back every line overlapping `addr..addr + len`
+    ///
+    /// # Safety
+    ///
+    /// The range must be mapped.
+    unsafe fn clean_range(addr: usize, len: usize) {
+        for line in lines::<Self>(addr, len) {
+            Self::clean_line(line);
+        }
+        Self::barrier();
+    }
+
+    /// Discard every line overlapping `addr..addr + len`
+    ///
+    /// Lines only partly inside the range are cleaned as well, so data next
+    /// to the buffer isn't lost.
+    ///
+    /// # Safety
+    ///
+    /// The range must be mapped, and data in it only in the cache is lost.
+    unsafe fn invalidate_range(addr: usize, len: usize) {
+        let end = addr + len;
+        for line in lines::<Self>(addr, len) {
+            if line < addr || line + Self::LINE_SIZE > end {
+                Self::clean_invalidate_line(line);
+            } else {
+                Self::invalidate_line(line);
+            }
+        }
+        Self::barrier();
+    }
+
+    /// Write back and discard every line overlapping `addr..addr + len`
+    ///
+    /// # Safety
+    ///
+    /// The range must be mapped.
+    unsafe fn clean_invalidate_range(addr: usize, len: usize) {
+        for line in lines::<Self>(addr, len) {
+            Self::clean_invalidate_line(line);
+        }
+        Self::barrier();
+    }
+}
+
+/// Start addresses of the cache lines overlapping `addr..addr + len`
+fn lines<M: CacheMaintenance + ?Sized>(addr: usize, len: usize) -> impl Iterator<Item = usize> {
+    let start = addr & !(M::LINE_SIZE - 1);
+    let end = if len == 0 { start } else { addr + len };
+    (start..end).step_by(M::LINE_SIZE)
+}
+
+/// Cache maintenance for cache coherent DMA, doing nothing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Coherent;
+
+impl CacheMaintenance for Coherent {
+    const LINE_SIZE: usize = 1;
+
+    unsafe fn clean_line(_addr: usize) {}
+
+    unsafe fn invalidate_line(_addr: usize) {}
+
+    fn barrier() {}
+
+    unsafe fn clean_range(_addr: usize, _len: usize) {}
+
+    unsafe fn invalidate_range(_addr: usize, _len: usize) {}
+
+    unsafe fn clean_invalidate_range(_addr: usize, _len: usize) {}
+}
+
+const STATE_IDLE: u8 = 0;
+const STATE_IN_PROGRESS: u8 = 1;
+const STATE_COMPLETE: u8 = 2;
+const STATE_ERROR: u8 = 3;
+
+/// Completion of a channel's transfers, reported from its interrupt handler
+///
+/// A [`Transfer`] marks it in progress when started; the interrupt handler
+/// calls [`DmaCompletion::complete`] or [`DmaCompletion::fail`], which wakes
+/// a task awaiting the transfer.
+pub struct DmaCompletion {
+    state: AtomicU8,
+    waker: spin::Mutex<Option<Waker>>,
+}
+
+impl DmaCompletion {
+    /// Create an idle completion
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(STATE_IDLE),
+            waker: spin::Mutex::new(None),
+        }
+    }
+
+    /// Mark a transfer as started
+    pub fn start(&self) {
+        self.state.store(STATE_IN_PROGRESS, Ordering::Release);
+    }
+
+    /// Report that the transfer completed
+    pub fn complete(&self) {
+        self.finish(STATE_COMPLETE);
+    }
+
+    /// Report that the transfer failed
+    pub fn fail(&self) {
+        self.finish(STATE_ERROR);
+    }
+
+    fn finish(&self, state: u8) {
+        self.state.store(state, Ordering::Release);
+        // Take the waker so it is woken outside the lock.
+        let waker = self.waker.lock().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Status of the last transfer
+    pub fn status(&self) -> DmaStatus {
+        match self.state.load(Ordering::Acquire) {
+            STATE_IN_PROGRESS => DmaStatus::InProgress,
+            STATE_COMPLETE => DmaStatus::Complete,
+            STATE_ERROR => DmaStatus::Error,
+            _ => DmaStatus::Idle,
+        }
+    }
+
+    /// Poll for the end of the transfer, registering the task's waker
+    pub fn poll_complete(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(result) = self.result() {
+            return Poll::Ready(result);
+        }
+
+        {
+            let mut waker = self.waker.lock();
+            match waker.as_ref() {
+                Some(current) if current.will_wake(cx.waker()) => {}
+                _ => *waker = Some(cx.waker().clone()),
+            }
+        }
+
+        // The interrupt may have fired before the waker was stored.
+        match self.result() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Wait for the end of the transfer
+    pub fn wait(&self) -> WaitComplete<'_> {
+        WaitComplete { completion: self }
+    }
+
+    fn result(&self) -> Option<Result<()>> {
+        match self.status() {
+            DmaStatus::InProgress => None,
+            DmaStatus::Error => Some(Err(Error::DmaError)),
+            DmaStatus::Idle | DmaStatus::Complete => Some(Ok(())),
+        }
+    }
+}
+
+impl Default for DmaCompletion {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by [`DmaCompletion::wait`]
+pub struct WaitComplete<'a> {
+    completion: &'a DmaCompletion,
+}
+
+impl Future for WaitComplete<'_> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.completion.poll_complete(cx)
+    }
+}
+
+/// Running transfer on a channel
+///
+/// Holds the channel and the descriptor's buffer borrow until the transfer
+/// ends. Dropping it early stops the channel. `M` does the cache maintenance
+/// around the transfer.
+pub struct Transfer<'a, C: DmaChannel, M: CacheMaintenance = Coherent> {
+    channel: &'a mut C,
+    descriptor: TransferDescriptor<'a>,
+    completion: &'a DmaCompletion,
+    done: bool,
+    _cache: PhantomData<M>,
+}
+
+impl<'a, C: DmaChannel, M: CacheMaintenance> Transfer<'a, C, M> {
+    /// Start `descriptor` on `channel`, reporting to `completion`
+    pub fn start(
+        channel: &'a mut C,
+        descriptor: TransferDescriptor<'a>,
+        completion: &'a DmaCompletion,
+    ) -> Result<Self, C::Error> {
+        descriptor.prepare::<M>();
+        completion.start();
+        if let Err(err) = channel.submit(&descriptor) {
+            completion.fail();
+            return Err(err);
+        }
+        Ok(Self {
+            channel,
+            descriptor,
+            completion,
+            done: false,
+            _cache: PhantomData,
+        })
+    }
+
+    /// Descriptor of the transfer
+    pub fn descriptor(&self) -> &TransferDescriptor<'a> {
+        &self.descriptor
+    }
+
+    /// Check the transfer's status
+    ///
+    /// Falls back to the channel's status when the completion hasn't been
+    /// signaled, so this also works without the completion interrupt.
+    pub fn poll(&mut self) -> DmaStatus {
+        match self.completion.status() {
+            DmaStatus::InProgress => match self.channel.status() {
+                DmaStatus::Complete => {
+                    self.completion.complete();
+                    DmaStatus::Complete
+                }
+                DmaStatus::Error => {
+                    self.completion.fail();
+                    DmaStatus::Error
+                }
+                _ => DmaStatus::InProgress,
+            },
+            status => status,
+        }
+    }
+
+    /// Elements left to transfer
+    pub fn remaining(&self) -> usize {
+        self.channel.remaining()
+    }
+
+    /// Spin until the transfer ends
+    pub fn wait(mut self) -> Result<()> {
+        while self.poll() == DmaStatus::InProgress {
+            core::hint::spin_loop();
+        }
+        self.finish()
+    }
+
+    /// Wait for the completion interrupt
+    #[cfg(feature = "async")]
+    pub async fn wait_async(mut self) -> Result<()> {
+        self.completion.wait().await?;
+        self.finish()
+    }
+
+    /// Stop the transfer before it ends
+    ///
+    /// Returns the number of elements that were not transferred.
+    pub fn abort(mut self) -> Result<usize, C::Error> {
+        self.channel.stop()?;
+        let remaining = self.channel.remaining();
+        self.descriptor.finish::<M>();
+        self.done = true;
+        Ok(remaining)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.done = true;
+        self.descriptor.finish::<M>();
+        match self.completion.status() {
+            DmaStatus::Error => Err(Error::DmaError),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<C: DmaChannel, M: CacheMaintenance> Drop for Transfer<'_, C, M> {
+    fn drop(&mut self) {
+        if !self.done && self.completion.status() == DmaStatus::InProgress {
+            // The buffer borrow ends here, so the engine must stop touching it
+            let _ = self.channel.stop();
+        }
+    }
+}
+
 /// Safe DMA buffer wrapper
 pub struct DmaBuffer<T, const N: usize> {
     data: [T; N],
@@ -162,6 +833,11 @@ impl<T: Copy + Default, const N: usize> DmaBuffer<T, N> {
         N
     }
 
+    /// Whether the buffer holds no elements
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
     /// Get a slice of the buffer
     pub fn as_slice(&self) -> &[T] {
         &self.data
@@ -0,0 +1,96 @@
+//! Debounced push button
+
+use super::{Debouncer, DEFAULT_DEBOUNCE};
+use crate::error::{Error, Result};
+use crate::gpio::{GpioPin, Level, PinMode, Pull};
+use crate::time::{Duration, Instant};
+
+/// Button state change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    /// The button went down
+    Pressed,
+    /// The button went up
+    Released,
+    /// The button has been down for the hold time (reported once per press)
+    Held,
+}
+
+/// Debounced push button on one GPIO
+pub struct Button<P> {
+    pin: P,
+    active_low: bool,
+    debouncer: Debouncer,
+    hold_time: Option<Duration>,
+    pressed_at: Option<Instant>,
+}
+
+impl<P: GpioPin> Button<P> {
+    /// Use the button on `pin`
+    ///
+    /// An active low button connects the pin to ground and gets the
+    /// internal pull-up; an active high one gets the pull-down.
+    pub fn new(mut pin: P, active_low: bool) -> Result<Self> {
+        let pull = if active_low { Pull::Up } else { Pull::Down };
+        pin.set_mode(PinMode::Input)
+            .and_then(|_| pin.set_pull(pull))
+            .map_err(|_| Error::InvalidConfig)?;
+        Ok(Self {
+            pin,
+            active_low,
+            debouncer: Debouncer::new(DEFAULT_DEBOUNCE),
+            hold_time: None,
+            pressed_at: None,
+        })
+    }
+
+    /// Set the debounce time
+    pub fn with_debounce(mut self, time: Duration) -> Self {
+        self.debouncer = Debouncer::new(time);
+        self
+    }
+
+    /// Report [`ButtonEvent::Held`] after the button is down for `time`
+    pub fn with_hold_time(mut self, time: Duration) -> Self {
+        self.hold_time = Some(time);
+        self
+    }
+
+    /// Sample the button at `now`
+    pub fn update(&mut self, now: Instant) -> Result<Option<ButtonEvent>> {
+        let level = self.pin.read().map_err(|_| Error::BusError)?;
+        let down = (level == Level::Low) == self.active_low;
+
+        match self.debouncer.update(down, now) {
+            Some(true) => {
+                self.pressed_at = Some(now);
+                Ok(Some(ButtonEvent::Pressed))
+            }
+            Some(false) => {
+                self.pressed_at = None;
+                Ok(Some(ButtonEvent::Released))
+            }
+            None => {
+                let held = match (self.pressed_at, self.hold_time) {
+                    (Some(at), Some(hold)) => now.duration_since(at) >= hold,
+                    _ => false,
+                };
+                if held {
+                    self.pressed_at = None;
+                    return Ok(Some(ButtonEvent::Held));
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Debounced button state
+    pub fn is_pressed(&self) -> bool {
+        self.debouncer.state()
+    }
+
+    /// Release the pin
+    pub fn free(self) -> P {
+        self.pin
+    }
+}
@@ -0,0 +1,104 @@
+//! Quadrature (rotary) encoder
+
+use crate::error::{Error, Result};
+use crate::gpio::{GpioPin, Level, PinMode, Pull};
+
+/// Rotation direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// A leads B
+    Clockwise,
+    /// B leads A
+    CounterClockwise,
+}
+
+// Step for each (previous AB, current AB) pair; invalid double transitions
+// (a missed sample) count as no movement
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Quadrature encoder decoded from its A and B pins
+///
+/// The pins have to be polled faster than the fastest expected transition;
+/// a skipped quadrature state is dropped rather than guessed.
+pub struct QuadratureEncoder<P> {
+    a: P,
+    b: P,
+    state: u8,
+    steps: i8,
+    steps_per_detent: i8,
+    position: i32,
+}
+
+impl<P: GpioPin> QuadratureEncoder<P> {
+    /// Use the encoder on `a` and `b`, with contacts to ground and pull-ups
+    ///
+    /// Common mechanical encoders go through all four states per detent.
+    pub fn new(mut a: P, mut b: P) -> Result<Self> {
+        for pin in [&mut a, &mut b] {
+            pin.set_mode(PinMode::Input)
+                .and_then(|_| pin.set_pull(Pull::Up))
+                .map_err(|_| Error::InvalidConfig)?;
+        }
+        let mut encoder = Self {
+            a,
+            b,
+            state: 0,
+            steps: 0,
+            steps_per_detent: 4,
+            position: 0,
+        };
+        encoder.state = encoder.sample()?;
+        Ok(encoder)
+    }
+
+    /// Set the quadrature states per detent (1, 2 or 4)
+    pub fn with_steps_per_detent(mut self, steps: u8) -> Result<Self> {
+        if !matches!(steps, 1 | 2 | 4) {
+            return Err(Error::InvalidParameter);
+        }
+        self.steps_per_detent = steps as i8;
+        Ok(self)
+    }
+
+    fn sample(&self) -> Result<u8> {
+        let a = self.a.read().map_err(|_| Error::BusError)?;
+        let b = self.b.read().map_err(|_| Error::BusError)?;
+        Ok(u8::from(a == Level::High) << 1 | u8::from(b == Level::High))
+    }
+
+    /// Sample the pins, returning the direction when a detent is passed
+    pub fn update(&mut self) -> Result<Option<Direction>> {
+        let state = self.sample()?;
+        let step = TRANSITIONS[usize::from(self.state << 2 | state)];
+        self.state = state;
+        self.steps += step;
+
+        if self.steps >= self.steps_per_detent {
+            self.steps = 0;
+            self.position += 1;
+            Ok(Some(Direction::Clockwise))
+        } else if self.steps <= -self.steps_per_detent {
+            self.steps = 0;
+            self.position -= 1;
+            Ok(Some(Direction::CounterClockwise))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Detents turned since creation or the last reset, clockwise positive
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// Reset the position to zero
+    pub fn reset(&mut self) {
+        self.position = 0;
+        self.steps = 0;
+    }
+
+    /// Release the pins
+    pub fn free(self) -> (P, P) {
+        (self.a, self.b)
+    }
+}
@@ -0,0 +1,98 @@
+//! Matrix keypad
+
+use super::{Debouncer, DEFAULT_DEBOUNCE};
+use crate::error::{Error, Result};
+use crate::gpio::{GpioPin, Level, PinMode, Pull};
+use crate::time::{Duration, Instant};
+
+/// Key state change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// Row of the key
+    pub row: usize,
+    /// Column of the key
+    pub col: usize,
+    /// Whether the key went down or up
+    pub pressed: bool,
+}
+
+/// Row/column scanned key matrix
+///
+/// Rows are open-drain outputs pulled low one at a time, columns are inputs
+/// with pull-ups, so a pressed key reads low on its column while its row is
+/// selected. Matrices without diodes can ghost when three keys forming a
+/// rectangle corner are held.
+pub struct Keypad<P, const ROWS: usize, const COLS: usize> {
+    rows: [P; ROWS],
+    cols: [P; COLS],
+    keys: [[Debouncer; COLS]; ROWS],
+}
+
+impl<P: GpioPin, const ROWS: usize, const COLS: usize> Keypad<P, ROWS, COLS> {
+    /// Use the matrix on `rows` and `cols`
+    pub fn new(mut rows: [P; ROWS], mut cols: [P; COLS]) -> Result<Self> {
+        for row in rows.iter_mut() {
+            row.set_mode(PinMode::OpenDrain)
+                .map_err(|_| Error::InvalidConfig)?;
+            row.write(Level::High).map_err(|_| Error::BusError)?;
+        }
+        for col in cols.iter_mut() {
+            col.set_mode(PinMode::Input)
+                .and_then(|_| col.set_pull(Pull::Up))
+                .map_err(|_| Error::InvalidConfig)?;
+        }
+        Ok(Self {
+            rows,
+            cols,
+            keys: [[Debouncer::new(DEFAULT_DEBOUNCE); COLS]; ROWS],
+        })
+    }
+
+    /// Set the debounce time of every key
+    pub fn with_debounce(mut self, time: Duration) -> Self {
+        self.keys = [[Debouncer::new(time); COLS]; ROWS];
+        self
+    }
+
+    /// Scan the matrix at `now`, calling `on_event` for each key that
+    /// changed state
+    pub fn scan(&mut self, now: Instant, mut on_event: impl FnMut(KeyEvent)) -> Result<()> {
+        for (row, pin) in self.rows.iter_mut().enumerate() {
+            pin.write(Level::Low).map_err(|_| Error::BusError)?;
+            let sampled = sample_row(&self.cols, &mut self.keys[row], now, row, &mut on_event);
+            // Deselect the row even when a column read failed
+            pin.write(Level::High).map_err(|_| Error::BusError)?;
+            sampled?;
+        }
+        Ok(())
+    }
+
+    /// Debounced state of the key at `row`, `col`
+    pub fn is_pressed(&self, row: usize, col: usize) -> bool {
+        self.keys
+            .get(row)
+            .and_then(|keys| keys.get(col))
+            .is_some_and(Debouncer::state)
+    }
+
+    /// Release the pins
+    pub fn free(self) -> ([P; ROWS], [P; COLS]) {
+        (self.rows, self.cols)
+    }
+}
+
+fn sample_row<P: GpioPin>(
+    cols: &[P],
+    keys: &mut [Debouncer],
+    now: Instant,
+    row: usize,
+    on_event: &mut impl FnMut(KeyEvent),
+) -> Result<()> {
+    for (col, (pin, key)) in cols.iter().zip(keys).enumerate() {
+        let down = pin.read().map_err(|_| Error::BusError)? == Level::Low;
+        if let Some(pressed) = key.update(down, now) {
+            on_event(KeyEvent { row, col, pressed });
+        }
+    }
+    Ok(())
+}
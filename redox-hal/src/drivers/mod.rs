@@ -0,0 +1,83 @@
+//! Input helper drivers
+//!
+//! Buttons, key matrices, rotary encoders and capacitive touch pads show up
+//! on most embedded boards, and each of them only needs a few GPIOs and a
+//! clock. These drivers implement them once on top of [`GpioPin`] and
+//! [`Monotonic`] time stamps.
+//!
+//! The drivers are polled: call `update` (or `scan`) from a timer tick or
+//! the main loop, passing the current [`Instant`] of a monotonic clock.
+//!
+//! ```ignore
+//! let mut button = Button::new(pin, true)?;
+//! let mut encoder = QuadratureEncoder::new(pin_a, pin_b)?;
+//! loop {
+//!     let now = clock.now();
+//!     if let Some(ButtonEvent::Pressed) = button.update(now)? {
+//!         encoder.reset();
+//!     }
+//!     if let Some(direction) = encoder.update()? {
+//!         volume.step(direction);
+//!     }
+//! }
+//! ```
+//!
+//! [`GpioPin`]: crate::gpio::GpioPin
+//! [`Monotonic`]: crate::timer::Monotonic
+
+use crate::time::{Duration, Instant};
+
+pub mod button;
+pub mod encoder;
+pub mod keypad;
+pub mod touch;
+
+pub use button::{Button, ButtonEvent};
+pub use encoder::{Direction, QuadratureEncoder};
+pub use keypad::{KeyEvent, Keypad};
+pub use touch::TouchPad;
+
+/// Default debounce time for mechanical contacts
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// Debounce state of one contact
+///
+/// A new level is accepted once the raw input has read it continuously for
+/// the debounce time.
+#[derive(Debug, Clone, Copy)]
+pub struct Debouncer {
+    stable: bool,
+    candidate: bool,
+    since: Instant,
+    time: Duration,
+}
+
+impl Debouncer {
+    /// Create a debouncer, released, accepting changes stable for `time`
+    pub const fn new(time: Duration) -> Self {
+        Self {
+            stable: false,
+            candidate: false,
+            since: Instant::from_ticks(0),
+            time,
+        }
+    }
+
+    /// Debounced state
+    pub fn state(&self) -> bool {
+        self.stable
+    }
+
+    /// Feed a raw sample taken at `now`, returning the new state on a change
+    pub fn update(&mut self, raw: bool, now: Instant) -> Option<bool> {
+        if raw != self.candidate {
+            self.candidate = raw;
+            self.since = now;
+        }
+        if self.candidate != self.stable && now.duration_since(self.since) >= self.time {
+            self.stable = self.candidate;
+            return Some(self.stable);
+        }
+        None
+    }
+}
@@ -0,0 +1,142 @@
+//! Capacitive touch pad
+
+use crate::error::{Error, Result};
+use crate::gpio::{GpioPin, Level, PinMode, Pull};
+use crate::time::Duration;
+use crate::timer::Monotonic;
+
+/// Capacitive touch pad read by RC charge time
+///
+/// The pad is connected to the pin and, through a high value resistor
+/// (around 1 MΩ), to the supply. Each measurement discharges the pad and
+/// times how long it takes to charge back to a logic high; a finger adds
+/// capacitance and makes that longer. Timing resolution is that of the
+/// monotonic clock, so the resistor has to be picked to charge in tens of
+/// clock ticks.
+pub struct TouchPad<P, M> {
+    pin: P,
+    clock: M,
+    baseline: Duration,
+    threshold_percent: u32,
+    timeout: Duration,
+    touched: bool,
+}
+
+impl<P: GpioPin, M: Monotonic> TouchPad<P, M> {
+    /// Default time to give up charging
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1);
+    /// Default rise over the baseline that counts as a touch
+    pub const DEFAULT_THRESHOLD_PERCENT: u32 = 30;
+
+    /// Use the pad on `pin`, timing it with `clock`
+    ///
+    /// The pad has to be [calibrated](Self::calibrate) untouched before
+    /// touches are reported.
+    pub fn new(mut pin: P, clock: M) -> Result<Self> {
+        pin.set_pull(Pull::None).map_err(|_| Error::InvalidConfig)?;
+        Ok(Self {
+            pin,
+            clock,
+            baseline: Duration::ZERO,
+            threshold_percent: Self::DEFAULT_THRESHOLD_PERCENT,
+            timeout: Self::DEFAULT_TIMEOUT,
+            touched: false,
+        })
+    }
+
+    /// Set the rise over the baseline, in percent, that counts as a touch
+    pub fn with_threshold(mut self, percent: u32) -> Result<Self> {
+        if percent == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        self.threshold_percent = percent;
+        Ok(self)
+    }
+
+    /// Set the time after which a measurement gives up
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Measure the pad's charge time
+    pub fn measure(&mut self) -> Result<Duration> {
+        self.pin
+            .set_mode(PinMode::Output)
+            .and_then(|_| self.pin.write(Level::Low))
+            .map_err(|_| Error::BusError)?;
+        // Let the pad discharge fully
+        let start = self.clock.now();
+        while self.clock.elapsed_since(start) < Duration::from_micros(10) {
+            core::hint::spin_loop();
+        }
+
+        self.pin
+            .set_mode(PinMode::Input)
+            .map_err(|_| Error::BusError)?;
+        let start = self.clock.now();
+        loop {
+            let elapsed = self.clock.elapsed_since(start);
+            if self.pin.read().map_err(|_| Error::BusError)? == Level::High {
+                return Ok(elapsed);
+            }
+            if elapsed >= self.timeout {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    /// Take the untouched charge time as the average of `samples`
+    /// measurements
+    pub fn calibrate(&mut self, samples: u32) -> Result<Duration> {
+        if samples == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        let mut total = Duration::ZERO;
+        for _ in 0..samples {
+            total = total.saturating_add(self.measure()?);
+        }
+        self.baseline = total / samples;
+        self.touched = false;
+        Ok(self.baseline)
+    }
+
+    /// Untouched charge time
+    pub fn baseline(&self) -> Duration {
+        self.baseline
+    }
+
+    /// Measure the pad, returning the new state when it changed
+    ///
+    /// A touch is released only once the charge time drops below half the
+    /// threshold, so a finger hovering at the threshold doesn't chatter.
+    pub fn update(&mut self) -> Result<Option<bool>> {
+        if self.baseline.is_zero() {
+            return Err(Error::NotInitialized);
+        }
+        let rise = self.measure()?.saturating_sub(self.baseline);
+        let percent = rise.as_nanos() * 100 / self.baseline.as_nanos();
+        let threshold = u64::from(self.threshold_percent);
+
+        let touched = if self.touched {
+            percent >= threshold / 2
+        } else {
+            percent >= threshold
+        };
+        if touched == self.touched {
+            return Ok(None);
+        }
+        self.touched = touched;
+        Ok(Some(touched))
+    }
+
+    /// Last reported state
+    pub fn is_touched(&self) -> bool {
+        self.touched
+    }
+
+    /// Release the pin and clock
+    pub fn free(self) -> (P, M) {
+        (self.pin, self.clock)
+    }
+}
@@ -1,6 +1,11 @@
 //! GPIO (General Purpose Input/Output) HAL traits
 //!
 //! This module defines the GPIO abstraction for digital I/O pins.
+//!
+//! Pins with interrupt support implement [`InterruptPin`]. Their interrupt
+//! handlers report [`events::PinEvent`]s to an [`events::PinEventQueue`],
+//! which wakes async tasks and feeds the events to the GPIO scheme, where
+//! userspace drivers block on them.
 
 use crate::error::Result;
 
@@ -86,6 +91,24 @@ pub enum Edge {
     Both,
 }
 
+/// Interrupt trigger condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// Trigger on an edge
+    Edge(Edge),
+    /// Trigger while the pin is at a level
+    ///
+    /// The interrupt keeps firing until the level changes, so the handler
+    /// has to disable it until the device deasserted its interrupt line.
+    Level(Level),
+}
+
+impl From<Edge> for Trigger {
+    fn from(edge: Edge) -> Self {
+        Trigger::Edge(edge)
+    }
+}
+
 /// Output drive strength
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DriveStrength {
@@ -160,8 +183,18 @@ pub trait GpioPin {
 
 /// GPIO pin with interrupt support
 pub trait InterruptPin: GpioPin {
+    /// Enable the interrupt with the given trigger
+    fn set_trigger(&mut self, trigger: Trigger) -> Result<(), Self::Error>;
+
     /// Enable interrupt on edge
-    fn enable_interrupt(&mut self, edge: Edge) -> Result<(), Self::Error>;
+    fn enable_interrupt(&mut self, edge: Edge) -> Result<(), Self::Error> {
+        self.set_trigger(Trigger::Edge(edge))
+    }
+
+    /// Enable interrupt while the pin is at `level`
+    fn enable_level_interrupt(&mut self, level: Level) -> Result<(), Self::Error> {
+        self.set_trigger(Trigger::Level(level))
+    }
 
     /// Disable interrupt
     fn disable_interrupt(&mut self) -> Result<(), Self::Error>;
@@ -172,8 +205,26 @@ pub trait InterruptPin: GpioPin {
     /// Clear interrupt pending flag
     fn clear_interrupt(&mut self);
 
-    /// Set interrupt callback
-    fn set_interrupt_handler(&mut self, handler: fn());
+    /// Set the callback run from the interrupt handler for each event
+    fn set_interrupt_handler(&mut self, handler: fn(events::PinEvent));
+
+    /// Service a pending interrupt, returning its event
+    ///
+    /// Called from the interrupt handler of the GPIO bank. Clears the
+    /// pending flag and reads the pin level; the caller passes the event on
+    /// to the registered callback or a [`events::PinEventQueue`].
+    fn take_interrupt(&mut self, timestamp: u64) -> Option<events::PinEvent> {
+        if !self.is_interrupt_pending() {
+            return None;
+        }
+        self.clear_interrupt();
+        let level = self.read().ok()?;
+        Some(events::PinEvent {
+            pin: self.pin_number(),
+            level,
+            timestamp,
+        })
+    }
 }
 
 /// GPIO pin with configurable drive strength
@@ -262,3 +313,191 @@ pub trait GpioController {
     /// Get total number of ports
     fn port_count(&self) -> u8;
 }
+
+/// Delivery of pin interrupt events
+pub mod events {
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::task::{Context, Poll, Waker};
+
+    use super::Level;
+
+    /// Interrupt event of a pin
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PinEvent {
+        /// Pin number
+        pub pin: u8,
+        /// Pin level when the interrupt was serviced, for edge interrupts
+        /// on both edges this tells which edge it was
+        pub level: Level,
+        /// Time of the interrupt in timer ticks, 0 if unknown
+        pub timestamp: u64,
+    }
+
+    impl PinEvent {
+        /// Size of an encoded event
+        pub const SIZE: usize = 16;
+
+        /// Encode the event as read from the GPIO scheme
+        ///
+        /// Byte 0 is the pin number, byte 1 the level (0 low, 1 high), bytes
+        /// 8 to 15 the timestamp in little endian. The rest is reserved.
+        pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+            let mut bytes = [0; Self::SIZE];
+            bytes[0] = self.pin;
+            bytes[1] = u8::from(self.level.to_bool());
+            bytes[8..].copy_from_slice(&self.timestamp.to_le_bytes());
+            bytes
+        }
+
+        /// Decode an event read from the GPIO scheme
+        pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+            let mut timestamp = [0; 8];
+            timestamp.copy_from_slice(&bytes[8..]);
+            Self {
+                pin: bytes[0],
+                level: Level::from_bool(bytes[1] != 0),
+                timestamp: u64::from_le_bytes(timestamp),
+            }
+        }
+    }
+
+    struct QueueState<const N: usize> {
+        events: [Option<PinEvent>; N],
+        head: usize,
+        len: usize,
+        dropped: u32,
+        waker: Option<Waker>,
+    }
+
+    /// Bounded queue of pin events, filled by interrupt handlers
+    ///
+    /// Consumers either await [`PinEventQueue::next`], or, in the GPIO scheme
+    /// daemon, serve blocking reads with [`PinEventQueue::read_into`] and
+    /// report readiness with [`PinEventQueue::is_empty`]. When the queue is
+    /// full the oldest event is dropped.
+    pub struct PinEventQueue<const N: usize> {
+        state: spin::Mutex<QueueState<N>>,
+    }
+
+    impl<const N: usize> PinEventQueue<N> {
+        /// Create an empty queue
+        pub const fn new() -> Self {
+            Self {
+                state: spin::Mutex::new(QueueState {
+                    events: [None; N],
+                    head: 0,
+                    len: 0,
+                    dropped: 0,
+                    waker: None,
+                }),
+            }
+        }
+
+        /// Add an event and wake the waiting task
+        pub fn push(&self, event: PinEvent) {
+            let waker = {
+                let mut state = self.state.lock();
+                if state.len == N {
+                    state.head = (state.head + 1) % N;
+                    state.len -= 1;
+                    state.dropped = state.dropped.saturating_add(1);
+                }
+                let tail = (state.head + state.len) % N;
+                state.events[tail] = Some(event);
+                state.len += 1;
+                state.waker.take()
+            };
+            // Wake outside the lock
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+
+        /// Take the oldest event
+        pub fn pop(&self) -> Option<PinEvent> {
+            let mut state = self.state.lock();
+            if state.len == 0 {
+                return None;
+            }
+            let head = state.head;
+            let event = state.events[head].take();
+            state.head = (head + 1) % N;
+            state.len -= 1;
+            event
+        }
+
+        /// Check if no event is queued
+        pub fn is_empty(&self) -> bool {
+            self.state.lock().len == 0
+        }
+
+        /// Number of events dropped because the queue was full, resetting
+        /// the count
+        pub fn take_dropped(&self) -> u32 {
+            core::mem::take(&mut self.state.lock().dropped)
+        }
+
+        /// Poll for the next event, registering the task's waker
+        pub fn poll_next(&self, cx: &mut Context<'_>) -> Poll<PinEvent> {
+            if let Some(event) = self.pop() {
+                return Poll::Ready(event);
+            }
+
+            {
+                let mut state = self.state.lock();
+                match state.waker.as_ref() {
+                    Some(current) if current.will_wake(cx.waker()) => {}
+                    _ => state.waker = Some(cx.waker().clone()),
+                }
+            }
+
+            // The interrupt may have fired before the waker was stored.
+            match self.pop() {
+                Some(event) => Poll::Ready(event),
+                None => Poll::Pending,
+            }
+        }
+
+        /// Wait for the next event
+        pub fn next(&self) -> Next<'_, N> {
+            Next { queue: self }
+        }
+
+        /// Move as many whole encoded events as fit into `buf`
+        ///
+        /// Returns the number of bytes written, 0 if no event is queued, in
+        /// which case the scheme blocks the read until the next
+        /// [`PinEventQueue::push`].
+        pub fn read_into(&self, buf: &mut [u8]) -> usize {
+            let mut written = 0;
+            for chunk in buf.chunks_exact_mut(PinEvent::SIZE) {
+                let Some(event) = self.pop() else {
+                    break;
+                };
+                chunk.copy_from_slice(&event.to_bytes());
+                written += PinEvent::SIZE;
+            }
+            written
+        }
+    }
+
+    impl<const N: usize> Default for PinEventQueue<N> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// Future returned by [`PinEventQueue::next`]
+    pub struct Next<'a, const N: usize> {
+        queue: &'a PinEventQueue<N>,
+    }
+
+    impl<const N: usize> Future for Next<'_, N> {
+        type Output = PinEvent;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<PinEvent> {
+            self.queue.poll_next(cx)
+        }
+    }
+}
@@ -178,11 +178,17 @@ pub trait I2cController {
 }
 
 /// Async I2C trait
+///
+/// Like [`crate::spi::SpiBusAsync`], buffers may be used for DMA until the
+/// future completes, and dropping the future must abort the transfer.
 #[cfg(feature = "async")]
-pub trait AsyncI2c {
+pub trait I2cAsync {
     /// Error type
     type Error;
 
+    /// Configure the I2C bus
+    fn configure(&mut self, config: I2cConfig) -> Result<(), Self::Error>;
+
     /// Write data asynchronously
     async fn write(&mut self, address: I2cAddress, data: &[u8]) -> Result<(), Self::Error>;
 
@@ -196,6 +202,13 @@ pub trait AsyncI2c {
         write: &[u8],
         read: &mut [u8],
     ) -> Result<(), Self::Error>;
+
+    /// Execute a transaction with multiple operations asynchronously
+    async fn transaction(
+        &mut self,
+        address: I2cAddress,
+        operations: &mut [I2cOperation<'_>],
+    ) -> Result<(), Self::Error>;
 }
 
 use alloc::vec;
@@ -0,0 +1,477 @@
+//! I2S (Inter-IC Sound) HAL traits
+//!
+//! This module defines the digital audio interface abstraction for codecs,
+//! microphones and amplifiers. A controller advertises what it supports as
+//! [`I2sCapabilities`]; [`I2s::configure`] negotiates the requested
+//! [`I2sConfig`] against them and reports the configuration actually used,
+//! so an audio driver can convert or resample when it got something else.
+//!
+//! Samples are exchanged as interleaved frames of little-endian samples in
+//! the negotiated [`SampleFormat`].
+//!
+//! Boards without an I2S controller can use [`BitBangI2s`], which is only
+//! fast enough for low sample rates such as 8 kHz voice.
+
+use crate::error::Result;
+
+#[cfg(all(feature = "gpio", feature = "timer"))]
+use crate::error::Error;
+#[cfg(all(feature = "gpio", feature = "timer"))]
+use crate::gpio::{GpioPin, Level, PinMode};
+#[cfg(all(feature = "gpio", feature = "timer"))]
+use crate::time::Duration;
+#[cfg(all(feature = "gpio", feature = "timer"))]
+use crate::timer::Delay;
+
+/// Sample format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SampleFormat {
+    /// Signed 16-bit
+    S16,
+    /// Signed 24-bit, packed in 3 bytes
+    S24,
+    /// Signed 24-bit in the low bits of 4 bytes
+    S24In32,
+    /// Signed 32-bit
+    S32,
+}
+
+impl SampleFormat {
+    /// Significant bits per sample
+    pub fn bits(&self) -> u8 {
+        match self {
+            SampleFormat::S16 => 16,
+            SampleFormat::S24 | SampleFormat::S24In32 => 24,
+            SampleFormat::S32 => 32,
+        }
+    }
+
+    /// Bytes per sample in memory
+    pub fn bytes(&self) -> usize {
+        match self {
+            SampleFormat::S16 => 2,
+            SampleFormat::S24 => 3,
+            SampleFormat::S24In32 | SampleFormat::S32 => 4,
+        }
+    }
+
+    /// Bit clocks per sample on the wire
+    pub fn slot_bits(&self) -> u8 {
+        match self {
+            SampleFormat::S16 => 16,
+            _ => 32,
+        }
+    }
+}
+
+/// Frame format on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2sStandard {
+    /// Philips I2S: MSB one bit clock after the word select edge
+    Philips,
+    /// Left justified: MSB on the word select edge
+    LeftJustified,
+    /// Right justified: LSB on the last bit clock of the slot
+    RightJustified,
+    /// PCM/DSP: short frame sync pulse, channels back to back
+    Pcm,
+}
+
+/// Direction of the audio stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2sDirection {
+    /// Playback
+    Transmit,
+    /// Capture
+    Receive,
+    /// Simultaneous playback and capture
+    Duplex,
+}
+
+/// I2S configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2sConfig {
+    /// Frames per second
+    pub sample_rate: u32,
+    /// Sample format
+    pub format: SampleFormat,
+    /// Channels per frame
+    pub channels: u8,
+    /// Frame format on the wire
+    pub standard: I2sStandard,
+    /// Stream direction
+    pub direction: I2sDirection,
+    /// Generate the bit clock and word select (master) or follow them
+    pub master: bool,
+}
+
+impl Default for I2sConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000,
+            format: SampleFormat::S16,
+            channels: 2,
+            standard: I2sStandard::Philips,
+            direction: I2sDirection::Transmit,
+            master: true,
+        }
+    }
+}
+
+impl I2sConfig {
+    /// Bytes per frame in memory
+    pub fn frame_bytes(&self) -> usize {
+        self.format.bytes() * usize::from(self.channels)
+    }
+
+    /// Bit clock frequency in Hz, for two slots per frame
+    pub fn bit_clock(&self) -> u32 {
+        self.sample_rate * 2 * u32::from(self.format.slot_bits())
+    }
+}
+
+/// What an I2S controller supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2sCapabilities {
+    /// Supported sample formats, preferred first
+    pub formats: &'static [SampleFormat],
+    /// Supported sample rates, empty if any rate in `min_rate..=max_rate` works
+    pub rates: &'static [u32],
+    /// Lowest sample rate
+    pub min_rate: u32,
+    /// Highest sample rate
+    pub max_rate: u32,
+    /// Most channels per frame
+    pub max_channels: u8,
+    /// Supported frame formats
+    pub standards: &'static [I2sStandard],
+    /// Whether playback and capture can run at the same time
+    pub duplex: bool,
+    /// Whether the controller can follow an external bit clock
+    pub slave: bool,
+}
+
+impl I2sCapabilities {
+    /// Closest configuration to `wanted` these capabilities allow
+    ///
+    /// The format falls back to the narrowest supported one holding every
+    /// bit of the wanted format, else the widest one; the rate to the
+    /// nearest supported rate. The standard, direction and master/slave role
+    /// are not substituted, `None` is returned when they aren't supported.
+    pub fn negotiate(&self, wanted: I2sConfig) -> Option<I2sConfig> {
+        if !self.standards.contains(&wanted.standard)
+            || (wanted.direction == I2sDirection::Duplex && !self.duplex)
+            || (!wanted.master && !self.slave)
+            || self.max_channels == 0
+        {
+            return None;
+        }
+
+        let format = if self.formats.contains(&wanted.format) {
+            wanted.format
+        } else {
+            self.formats
+                .iter()
+                .filter(|format| format.bits() >= wanted.format.bits())
+                .min_by_key(|format| (format.bits(), format.bytes()))
+                .or_else(|| self.formats.iter().max_by_key(|format| format.bits()))
+                .copied()?
+        };
+
+        let sample_rate = if self.rates.is_empty() {
+            wanted.sample_rate.clamp(self.min_rate, self.max_rate)
+        } else {
+            self.rates
+                .iter()
+                .copied()
+                .min_by_key(|rate| rate.abs_diff(wanted.sample_rate))?
+        };
+
+        Some(I2sConfig {
+            sample_rate,
+            format,
+            channels: wanted.channels.clamp(1, self.max_channels),
+            ..wanted
+        })
+    }
+}
+
+/// I2S controller trait
+pub trait I2s {
+    /// Error type
+    type Error;
+
+    /// What the controller supports
+    fn capabilities(&self) -> I2sCapabilities;
+
+    /// Configure the stream, returning the configuration negotiated from
+    /// `config` (see [`I2sCapabilities::negotiate`])
+    fn configure(&mut self, config: I2sConfig) -> Result<I2sConfig, Self::Error>;
+
+    /// Start clocking frames
+    fn start(&mut self) -> Result<(), Self::Error>;
+
+    /// Stop clocking frames
+    fn stop(&mut self) -> Result<(), Self::Error>;
+
+    /// Queue frames for playback, blocking until at least one is queued, and
+    /// return how many bytes were taken
+    ///
+    /// Only whole frames are taken, so 0 means `data` is shorter than a frame.
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error>;
+
+    /// Read captured frames, blocking until at least one is available, and
+    /// return how many bytes were stored
+    ///
+    /// Only whole frames are stored.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Write all frames, blocking until they are queued
+    ///
+    /// A trailing partial frame is dropped.
+    fn write_all(&mut self, mut data: &[u8]) -> Result<(), Self::Error> {
+        loop {
+            let written = self.write(data)?;
+            if written == 0 {
+                break;
+            }
+            data = &data[written..];
+        }
+        Ok(())
+    }
+}
+
+/// I2S controller managing multiple ports
+pub trait I2sController {
+    /// Error type
+    type Error;
+    /// I2S port type
+    type Port: I2s;
+
+    /// Get an I2S port
+    fn port(&mut self, port_number: u8) -> Result<Self::Port, Self::Error>;
+
+    /// Get the number of available ports
+    fn port_count(&self) -> u8;
+}
+
+/// Read a little-endian sample of `format` as a left-aligned 32-bit word
+#[cfg(all(feature = "gpio", feature = "timer"))]
+fn load_sample(format: SampleFormat, bytes: &[u8]) -> u32 {
+    match format {
+        SampleFormat::S16 => u32::from(u16::from_le_bytes([bytes[0], bytes[1]])) << 16,
+        SampleFormat::S24 => u32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]),
+        SampleFormat::S24In32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) << 8,
+        SampleFormat::S32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+/// Store a left-aligned 32-bit word as a little-endian sample of `format`
+#[cfg(all(feature = "gpio", feature = "timer"))]
+fn store_sample(format: SampleFormat, word: u32, bytes: &mut [u8]) {
+    let le = word.to_le_bytes();
+    match format {
+        SampleFormat::S16 => bytes[..2].copy_from_slice(&le[2..]),
+        SampleFormat::S24 => bytes[..3].copy_from_slice(&le[1..]),
+        SampleFormat::S24In32 => {
+            // Sign extend into the unused top byte
+            let value = (word as i32 >> 8).to_le_bytes();
+            bytes[..4].copy_from_slice(&value);
+        }
+        SampleFormat::S32 => bytes[..4].copy_from_slice(&le),
+    }
+}
+
+#[cfg(all(feature = "gpio", feature = "timer"))]
+const BIT_BANG_CAPABILITIES: I2sCapabilities = I2sCapabilities {
+    formats: &[
+        SampleFormat::S16,
+        SampleFormat::S24,
+        SampleFormat::S24In32,
+        SampleFormat::S32,
+    ],
+    rates: &[],
+    min_rate: 1_000,
+    max_rate: 16_000,
+    max_channels: 2,
+    standards: &[I2sStandard::Philips, I2sStandard::LeftJustified],
+    duplex: false,
+    slave: false,
+};
+
+/// Software I2S master on three GPIOs
+///
+/// Drives the bit clock and word select, and either drives or samples the
+/// data line. Every bit takes two calls to the [`Delay`], so only low sample
+/// rates work, and the transfer blocks the CPU; mono streams are sent on
+/// both channels and captured from the left one.
+#[cfg(all(feature = "gpio", feature = "timer"))]
+pub struct BitBangI2s<SCK, WS, SD, D> {
+    sck: SCK,
+    ws: WS,
+    sd: SD,
+    delay: D,
+    config: I2sConfig,
+    running: bool,
+}
+
+#[cfg(all(feature = "gpio", feature = "timer"))]
+impl<SCK, WS, SD, D> BitBangI2s<SCK, WS, SD, D>
+where
+    SCK: GpioPin,
+    WS: GpioPin,
+    SD: GpioPin,
+    D: Delay,
+{
+    /// Create a software I2S master on the bit clock, word select and data
+    /// pins, timing bits with `delay`
+    pub fn new(mut sck: SCK, mut ws: WS, sd: SD, delay: D) -> Result<Self> {
+        sck.set_mode(PinMode::Output)
+            .map_err(|_| Error::InvalidConfig)?;
+        ws.set_mode(PinMode::Output)
+            .map_err(|_| Error::InvalidConfig)?;
+        let mut i2s = Self {
+            sck,
+            ws,
+            sd,
+            delay,
+            config: I2sConfig::default(),
+            running: false,
+        };
+        i2s.config = i2s.configure(I2sConfig {
+            sample_rate: 8_000,
+            ..I2sConfig::default()
+        })?;
+        Ok(i2s)
+    }
+
+    /// Release the pins and delay
+    pub fn free(self) -> (SCK, WS, SD, D) {
+        (self.sck, self.ws, self.sd, self.delay)
+    }
+
+    fn half_bit(&self) -> Duration {
+        Duration::from_nanos(500_000_000 / u64::from(self.config.bit_clock()))
+    }
+
+    /// Clock one frame, sending `tx` and returning the words received, both
+    /// left-aligned
+    fn frame(&mut self, tx: [u32; 2], receive: bool) -> Result<[u32; 2]> {
+        let slot = u32::from(self.config.format.slot_bits());
+        let philips = self.config.standard == I2sStandard::Philips;
+        let half = self.half_bit();
+        let mut rx = [0u32; 2];
+
+        for channel in 0..2 {
+            for bit in 0..slot {
+                // Philips I2S switches word select one bit before the MSB
+                let right = if philips && bit == slot - 1 {
+                    channel == 0
+                } else {
+                    channel == 1
+                };
+                self.sck.write(Level::Low).map_err(|_| Error::BusError)?;
+                self.ws
+                    .write(Level::from_bool(right))
+                    .map_err(|_| Error::BusError)?;
+                if !receive {
+                    let level = Level::from_bool(tx[channel] & (1 << (31 - bit)) != 0);
+                    self.sd.write(level).map_err(|_| Error::BusError)?;
+                }
+                self.delay.delay(half);
+
+                self.sck.write(Level::High).map_err(|_| Error::BusError)?;
+                if receive && self.sd.is_high().map_err(|_| Error::BusError)? {
+                    rx[channel] |= 1 << (31 - bit);
+                }
+                self.delay.delay(half);
+            }
+        }
+        Ok(rx)
+    }
+}
+
+#[cfg(all(feature = "gpio", feature = "timer"))]
+impl<SCK, WS, SD, D> I2s for BitBangI2s<SCK, WS, SD, D>
+where
+    SCK: GpioPin,
+    WS: GpioPin,
+    SD: GpioPin,
+    D: Delay,
+{
+    type Error = Error;
+
+    fn capabilities(&self) -> I2sCapabilities {
+        BIT_BANG_CAPABILITIES
+    }
+
+    fn configure(&mut self, config: I2sConfig) -> Result<I2sConfig> {
+        if self.running {
+            return Err(Error::Busy);
+        }
+        let config = BIT_BANG_CAPABILITIES
+            .negotiate(config)
+            .ok_or(Error::InvalidConfig)?;
+        let mode = match config.direction {
+            I2sDirection::Receive => PinMode::Input,
+            _ => PinMode::Output,
+        };
+        self.sd.set_mode(mode).map_err(|_| Error::InvalidConfig)?;
+        self.config = config;
+        Ok(config)
+    }
+
+    fn start(&mut self) -> Result<()> {
+        self.running = true;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running = false;
+        self.sck.write(Level::Low).map_err(|_| Error::BusError)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        if !self.running {
+            return Err(Error::NotInitialized);
+        }
+        if self.config.direction != I2sDirection::Transmit {
+            return Err(Error::NotAvailable);
+        }
+        let format = self.config.format;
+        let frame_bytes = self.config.frame_bytes();
+        let frames = data.chunks_exact(frame_bytes);
+        let written = frames.len() * frame_bytes;
+        for frame in frames {
+            let left = load_sample(format, frame);
+            let right = if self.config.channels > 1 {
+                load_sample(format, &frame[format.bytes()..])
+            } else {
+                left
+            };
+            self.frame([left, right], false)?;
+        }
+        Ok(written)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if !self.running {
+            return Err(Error::NotInitialized);
+        }
+        if self.config.direction != I2sDirection::Receive {
+            return Err(Error::NotAvailable);
+        }
+        let format = self.config.format;
+        let frame_bytes = self.config.frame_bytes();
+        let mut frames = buffer.chunks_exact_mut(frame_bytes);
+        let read = frames.len() * frame_bytes;
+        for frame in &mut frames {
+            let [left, right] = self.frame([0; 2], true)?;
+            store_sample(format, left, frame);
+            if self.config.channels > 1 {
+                store_sample(format, right, &mut frame[format.bytes()..]);
+            }
+        }
+        Ok(read)
+    }
+}
@@ -60,7 +60,49 @@
 //! - [`adc::Adc`] - Analog to digital conversion
 //! - [`dma::Dma`] - DMA transfer
 //! - [`watchdog::Watchdog`] - Watchdog timer
+//! - [`reset::ResetController`] - Reset cause and software reset
+//! - [`reset::BrownoutDetector`] - Supply brownout detection
 //! - [`rtc::Rtc`] - Real-time clock
+//! - [`onewire::OneWire`] - 1-Wire bus master
+//! - [`i2s::I2s`] - Digital audio interface
+//! - [`pio::PioStateMachine`] - Programmable I/O state machine
+//!
+//! 1-Wire and I2S also come as GPIO bit-bang implementations
+//! (`onewire::BitBangOneWire`, `i2s::BitBangI2s`) for boards without the
+//! controller.
+//!
+//! With the `async` feature, SPI, I2C and UART also have async variants
+//! (`spi::SpiBusAsync`, `i2c::I2cAsync`, `uart::UartAsync`) for interrupt
+//! and DMA driven drivers, and `asynch::Blocking` adapts those to the
+//! blocking traits.
+//!
+//! Boards route peripheral signals to pads with a `pinmux::Pinmux`, which
+//! applies the board's `pinmux::PinMap` and keeps a pad from being claimed
+//! twice.
+//!
+//! DMA-backed SPI, UART and I2C drivers describe bulk transfers with a
+//! `dma::TransferDescriptor` and run them as a `dma::Transfer`, which does
+//! the cache maintenance and completes from the channel interrupt.
+//!
+//! Boards describe their clocks with a `clocks::ClockTree`. BSPs implement
+//! `clocks::ClockControl`, and drivers take their peripheral clocks through
+//! a `clocks::ClockManager`, so unused peripherals can be gated.
+//!
+//! Drivers sharing one I2C or SPI bus get device handles
+//! (`shared_bus::SharedI2c`, `shared_bus::SharedSpi`) from a
+//! `shared_bus::BusManager`, which serves blocking and async drivers.
+//!
+//! PIO state machines come with drivers for WS2812 LEDs
+//! (`pio::Ws2812`), a UART transmitter (`pio::PioSerialTx`) and pin
+//! capture (`pio::PioCapture`), built from `pio::Instruction` encoders.
+//!
+//! The `drivers` feature adds polled helpers for common input hardware
+//! built on GPIOs and a monotonic clock: `drivers::Button`,
+//! `drivers::Keypad`, `drivers::QuadratureEncoder` and `drivers::TouchPad`.
+//!
+//! With the `embedded-hal` feature, `compat::EmbeddedHal` and
+//! `compat::RedoxHal` adapt between these traits and embedded-hal 1.0, so
+//! existing embedded-hal device drivers run on Redox BSPs.
 //!
 //! # Usage
 //!
@@ -96,6 +138,7 @@
 extern crate alloc;
 
 // Core modules
+pub mod clocks;
 pub mod error;
 pub mod prelude;
 pub mod time;
@@ -107,12 +150,21 @@ pub mod boards;
 #[cfg(feature = "gpio")]
 pub mod gpio;
 
+#[cfg(feature = "pinmux")]
+pub mod pinmux;
+
 #[cfg(feature = "spi")]
 pub mod spi;
 
 #[cfg(feature = "i2c")]
 pub mod i2c;
 
+#[cfg(feature = "i2s")]
+pub mod i2s;
+
+#[cfg(feature = "onewire")]
+pub mod onewire;
+
 #[cfg(feature = "uart")]
 pub mod uart;
 
@@ -122,6 +174,9 @@ pub mod timer;
 #[cfg(feature = "pwm")]
 pub mod pwm;
 
+#[cfg(feature = "pio")]
+pub mod pio;
+
 #[cfg(feature = "adc")]
 pub mod adc;
 
@@ -131,9 +186,27 @@ pub mod dma;
 #[cfg(feature = "watchdog")]
 pub mod watchdog;
 
+#[cfg(feature = "reset")]
+pub mod reset;
+
 #[cfg(feature = "rtc")]
 pub mod rtc;
 
+#[cfg(feature = "drivers")]
+pub mod drivers;
+
+#[cfg(feature = "async")]
+pub mod asynch;
+
+#[cfg(feature = "embedded-hal")]
+pub mod compat;
+
+#[cfg(all(
+    feature = "critical-section",
+    any(feature = "i2c", all(feature = "spi", feature = "gpio"))
+))]
+pub mod shared_bus;
+
 // Architecture-specific modules
 #[cfg(any(feature = "armv6", feature = "armv7", feature = "armv8"))]
 pub mod arch_armv7;
@@ -184,6 +257,8 @@ pub struct BoardInfo {
     pub arch: Architecture,
     /// CPU frequency in Hz
     pub cpu_freq: u32,
+    /// Clock tree, at the frequencies the board boots with
+    pub clocks: clocks::ClockTree,
     /// RAM size in bytes
     pub ram_size: usize,
     /// Flash size in bytes
@@ -353,5 +428,10 @@ pub mod critical_section {
         pub fn lock<R>(&self, _cs: &CriticalSection, f: impl FnOnce(&mut T) -> R) -> R {
             f(unsafe { &mut *self.data.get() })
         }
+
+        /// Consume the mutex, returning the data
+        pub fn into_inner(self) -> T {
+            self.data.into_inner()
+        }
     }
 }
@@ -0,0 +1,494 @@
+//! 1-Wire bus HAL traits
+//!
+//! This module defines the 1-Wire master abstraction used by DS18B20-class
+//! temperature sensors, iButtons and similar single-pin devices. Boards with
+//! a 1-Wire controller (or a DS2482 bridge) implement [`OneWire`]; on other
+//! boards [`BitBangOneWire`] drives the bus from an open-drain GPIO.
+//!
+//! ```ignore
+//! let mut bus = BitBangOneWire::new(pin, delay)?;
+//! let mut search = DeviceSearch::new();
+//! while let Some(rom) = search.next(&mut bus)? {
+//!     if rom.family() == ds18b20::FAMILY {
+//!         let sensor = Ds18b20::new(rom);
+//!         sensor.start_conversion(&mut bus)?;
+//!         delay.delay(ds18b20::CONVERSION_TIME);
+//!         let millidegrees = sensor.read_temperature(&mut bus)?;
+//!     }
+//! }
+//! ```
+
+use crate::error::{Error, Result};
+
+#[cfg(all(feature = "gpio", feature = "timer"))]
+use crate::gpio::{GpioPin, Level, PinMode};
+#[cfg(all(feature = "gpio", feature = "timer"))]
+use crate::time::Duration;
+#[cfg(all(feature = "gpio", feature = "timer"))]
+use crate::timer::Delay;
+
+/// Read the ROM code of the only device on the bus
+pub const READ_ROM: u8 = 0x33;
+/// Address one device by ROM code
+pub const MATCH_ROM: u8 = 0x55;
+/// Address every device on the bus
+pub const SKIP_ROM: u8 = 0xCC;
+/// Enumerate devices
+pub const SEARCH_ROM: u8 = 0xF0;
+/// Enumerate devices with an alarm condition
+pub const ALARM_SEARCH: u8 = 0xEC;
+
+/// Dallas/Maxim CRC-8 (polynomial x^8 + x^5 + x^4 + 1)
+///
+/// Running it over data followed by its CRC byte yields 0.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, &byte| {
+        let mut byte = byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 1;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            byte >>= 1;
+        }
+        crc
+    })
+}
+
+/// 64-bit device ROM code
+///
+/// Byte 0 is the family code, bytes 1 to 6 the serial number and byte 7 the
+/// CRC of the first seven bytes, in the order they are sent on the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RomCode(pub [u8; 8]);
+
+impl RomCode {
+    /// Family code, identifying the device type
+    pub fn family(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// 48-bit serial number
+    pub fn serial(&self) -> u64 {
+        self.0[1..7]
+            .iter()
+            .rev()
+            .fold(0, |serial, &byte| serial << 8 | u64::from(byte))
+    }
+
+    /// CRC byte
+    pub fn crc(&self) -> u8 {
+        self.0[7]
+    }
+
+    /// Check the CRC
+    pub fn is_valid(&self) -> bool {
+        crc8(&self.0) == 0
+    }
+}
+
+/// 1-Wire bus master trait
+pub trait OneWire {
+    /// Error type
+    type Error;
+
+    /// Send a reset pulse, returning whether a device answered with a
+    /// presence pulse
+    fn reset(&mut self) -> Result<bool, Self::Error>;
+
+    /// Write one bit
+    fn write_bit(&mut self, bit: bool) -> Result<(), Self::Error>;
+
+    /// Read one bit
+    fn read_bit(&mut self) -> Result<bool, Self::Error>;
+
+    /// Write a byte, LSB first
+    fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error> {
+        for i in 0..8 {
+            self.write_bit(byte & (1 << i) != 0)?;
+        }
+        Ok(())
+    }
+
+    /// Read a byte, LSB first
+    fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        let mut byte = 0;
+        for i in 0..8 {
+            if self.read_bit()? {
+                byte |= 1 << i;
+            }
+        }
+        Ok(byte)
+    }
+
+    /// Write bytes
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        data.iter().try_for_each(|&byte| self.write_byte(byte))
+    }
+
+    /// Read bytes
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        for byte in buffer {
+            *byte = self.read_byte()?;
+        }
+        Ok(())
+    }
+
+    /// One step of the ROM search: read a bit and its complement, then write
+    /// the direction taken
+    ///
+    /// The direction is the bit read if all devices agree, `direction`
+    /// otherwise. Returns the two bits read and the direction written.
+    /// Controllers with a search accelerator override this.
+    fn triplet(&mut self, direction: bool) -> Result<(bool, bool, bool), Self::Error> {
+        let id_bit = self.read_bit()?;
+        let complement = self.read_bit()?;
+        let taken = if id_bit != complement {
+            id_bit
+        } else {
+            direction
+        };
+        self.write_bit(taken)?;
+        Ok((id_bit, complement, taken))
+    }
+
+    /// Reset the bus and address one device, or all of them with `None`
+    ///
+    /// Returns `false` if no device is present.
+    fn select(&mut self, rom: Option<&RomCode>) -> Result<bool, Self::Error> {
+        if !self.reset()? {
+            return Ok(false);
+        }
+        match rom {
+            Some(rom) => {
+                self.write_byte(MATCH_ROM)?;
+                self.write(&rom.0)?;
+            }
+            None => self.write_byte(SKIP_ROM)?,
+        }
+        Ok(true)
+    }
+
+    /// Read the ROM code of the only device on the bus
+    fn read_rom(&mut self) -> Result<Option<RomCode>, Self::Error>
+    where
+        Self::Error: From<Error>,
+    {
+        if !self.reset()? {
+            return Ok(None);
+        }
+        self.write_byte(READ_ROM)?;
+        let mut rom = RomCode([0; 8]);
+        self.read(&mut rom.0)?;
+        if !rom.is_valid() {
+            return Err(Error::CrcError.into());
+        }
+        Ok(Some(rom))
+    }
+}
+
+/// Enumeration of the devices on a bus
+///
+/// Walks the ROM code binary tree as described in Maxim application note
+/// 187, returning one device per call to [`DeviceSearch::next`].
+#[derive(Debug, Clone)]
+pub struct DeviceSearch {
+    command: u8,
+    rom: [u8; 8],
+    last_discrepancy: u8,
+    last_family_discrepancy: u8,
+    done: bool,
+}
+
+impl DeviceSearch {
+    /// Search for all devices
+    pub const fn new() -> Self {
+        Self::with_command(SEARCH_ROM)
+    }
+
+    /// Search for devices with an alarm condition only
+    pub const fn alarm() -> Self {
+        Self::with_command(ALARM_SEARCH)
+    }
+
+    /// Search for devices of one family only
+    ///
+    /// Devices of other families may follow the last match, so compare
+    /// [`RomCode::family`] on the results.
+    pub const fn family(family: u8) -> Self {
+        let mut search = Self::new();
+        search.rom[0] = family;
+        search.last_discrepancy = 64;
+        search
+    }
+
+    const fn with_command(command: u8) -> Self {
+        Self {
+            command,
+            rom: [0; 8],
+            last_discrepancy: 0,
+            last_family_discrepancy: 0,
+            done: false,
+        }
+    }
+
+    /// Skip the remaining devices of the family of the last result
+    pub fn skip_family(&mut self) {
+        self.last_discrepancy = self.last_family_discrepancy;
+        self.last_family_discrepancy = 0;
+        if self.last_discrepancy == 0 {
+            self.done = true;
+        }
+    }
+
+    /// Find the next device, `None` once all were found
+    pub fn next<W>(&mut self, bus: &mut W) -> Result<Option<RomCode>, W::Error>
+    where
+        W: OneWire,
+        W::Error: From<Error>,
+    {
+        if self.done || !bus.reset()? {
+            self.done = true;
+            return Ok(None);
+        }
+        bus.write_byte(self.command)?;
+
+        let mut last_zero = 0;
+        for bit in 1..=64u8 {
+            let byte = usize::from((bit - 1) / 8);
+            let mask = 1 << ((bit - 1) % 8);
+            let direction = match bit.cmp(&self.last_discrepancy) {
+                core::cmp::Ordering::Less => self.rom[byte] & mask != 0,
+                core::cmp::Ordering::Equal => true,
+                core::cmp::Ordering::Greater => false,
+            };
+
+            let (id_bit, complement, taken) = bus.triplet(direction)?;
+            if id_bit && complement {
+                // Nobody answered, the devices left the bus
+                self.done = true;
+                return Ok(None);
+            }
+            if !id_bit && !complement && !taken {
+                last_zero = bit;
+                if bit <= 8 {
+                    self.last_family_discrepancy = bit;
+                }
+            }
+            if taken {
+                self.rom[byte] |= mask;
+            } else {
+                self.rom[byte] &= !mask;
+            }
+        }
+
+        let rom = RomCode(self.rom);
+        if !rom.is_valid() {
+            self.done = true;
+            return Err(Error::CrcError.into());
+        }
+        self.last_discrepancy = last_zero;
+        self.done = last_zero == 0;
+        Ok(Some(rom))
+    }
+}
+
+impl Default for DeviceSearch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// DS18B20 family digital thermometers
+pub mod ds18b20 {
+    use super::{crc8, OneWire, RomCode};
+    use crate::error::{Error, Result};
+    use crate::time::Duration;
+
+    /// DS18B20 family code
+    pub const FAMILY: u8 = 0x28;
+    /// DS18S20 family code, with a 0.5 °C resolution
+    pub const FAMILY_DS18S20: u8 = 0x10;
+    /// DS1822 family code
+    pub const FAMILY_DS1822: u8 = 0x22;
+
+    /// Start a temperature conversion
+    pub const CONVERT_T: u8 = 0x44;
+    /// Read the 9 byte scratchpad
+    pub const READ_SCRATCHPAD: u8 = 0xBE;
+
+    /// Worst case conversion time at 12-bit resolution
+    pub const CONVERSION_TIME: Duration = Duration::from_millis(750);
+
+    /// DS18B20-class sensor
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Ds18b20 {
+        rom: Option<RomCode>,
+    }
+
+    impl Ds18b20 {
+        /// Sensor with ROM code `rom`
+        pub const fn new(rom: RomCode) -> Self {
+            Self { rom: Some(rom) }
+        }
+
+        /// The only sensor on the bus, addressed without its ROM code
+        pub const fn single() -> Self {
+            Self { rom: None }
+        }
+
+        /// ROM code of the sensor, if known
+        pub fn rom(&self) -> Option<RomCode> {
+            self.rom
+        }
+
+        /// Start a conversion, which takes up to [`CONVERSION_TIME`]
+        pub fn start_conversion<W>(&self, bus: &mut W) -> Result<(), W::Error>
+        where
+            W: OneWire,
+            W::Error: From<Error>,
+        {
+            if !bus.select(self.rom.as_ref())? {
+                return Err(Error::NoAcknowledge.into());
+            }
+            bus.write_byte(CONVERT_T)
+        }
+
+        /// Read the result of the last conversion, in millidegrees Celsius
+        pub fn read_temperature<W>(&self, bus: &mut W) -> Result<i32, W::Error>
+        where
+            W: OneWire,
+            W::Error: From<Error>,
+        {
+            if !bus.select(self.rom.as_ref())? {
+                return Err(Error::NoAcknowledge.into());
+            }
+            bus.write_byte(READ_SCRATCHPAD)?;
+            let mut scratchpad = [0u8; 9];
+            bus.read(&mut scratchpad)?;
+            if crc8(&scratchpad) != 0 {
+                return Err(Error::CrcError.into());
+            }
+
+            let raw = i32::from(i16::from_le_bytes([scratchpad[0], scratchpad[1]]));
+            match self.rom.map(|rom| rom.family()) {
+                // 0.5 °C steps
+                Some(FAMILY_DS18S20) => Ok(raw * 500),
+                // 1/16 °C steps
+                _ => Ok(raw * 125 / 2),
+            }
+        }
+    }
+}
+
+pub use ds18b20::Ds18b20;
+
+// Standard speed slot timings, in microseconds
+#[cfg(all(feature = "gpio", feature = "timer"))]
+mod timing {
+    pub const RESET_LOW: u64 = 480;
+    pub const PRESENCE_WAIT: u64 = 70;
+    pub const RESET_RECOVERY: u64 = 410;
+    pub const WRITE_1_LOW: u64 = 6;
+    pub const WRITE_1_RECOVERY: u64 = 64;
+    pub const WRITE_0_LOW: u64 = 60;
+    pub const WRITE_0_RECOVERY: u64 = 10;
+    pub const READ_LOW: u64 = 6;
+    pub const READ_SAMPLE: u64 = 9;
+    pub const READ_RECOVERY: u64 = 55;
+}
+
+/// Software 1-Wire master on an open-drain GPIO
+///
+/// The bus needs an external pull-up (4.7 kΩ typically). Each time slot runs
+/// in a critical section, as a slot stretched by an interrupt corrupts the
+/// bit; the reset pulse keeps interrupts disabled for close to 1 ms.
+#[cfg(all(feature = "gpio", feature = "timer"))]
+pub struct BitBangOneWire<P, D> {
+    pin: P,
+    delay: D,
+}
+
+#[cfg(all(feature = "gpio", feature = "timer"))]
+impl<P: GpioPin, D: Delay> BitBangOneWire<P, D> {
+    /// Drive the bus on `pin`, timing slots with `delay`
+    pub fn new(mut pin: P, delay: D) -> Result<Self> {
+        pin.set_mode(PinMode::OpenDrain)
+            .map_err(|_| Error::InvalidConfig)?;
+        pin.write(Level::High).map_err(|_| Error::BusError)?;
+        Ok(Self { pin, delay })
+    }
+
+    /// Release the pin and delay
+    pub fn free(self) -> (P, D) {
+        (self.pin, self.delay)
+    }
+
+    fn wait(&mut self, us: u64) {
+        self.delay.delay(Duration::from_micros(us));
+    }
+
+    fn slot<R>(&mut self, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        #[cfg(feature = "critical-section")]
+        return crate::critical_section::with(|_| f(self));
+        #[cfg(not(feature = "critical-section"))]
+        return f(self);
+    }
+
+    fn drive(&mut self, level: Level) -> Result<()> {
+        self.pin.write(level).map_err(|_| Error::BusError)
+    }
+
+    fn sample(&self) -> Result<bool> {
+        self.pin.is_high().map_err(|_| Error::BusError)
+    }
+}
+
+#[cfg(all(feature = "gpio", feature = "timer"))]
+impl<P: GpioPin, D: Delay> OneWire for BitBangOneWire<P, D> {
+    type Error = Error;
+
+    fn reset(&mut self) -> Result<bool> {
+        // A bus held low by a device or a short never sees a reset
+        if !self.sample()? {
+            return Err(Error::BusError);
+        }
+        let present = self.slot(|bus| {
+            bus.drive(Level::Low)?;
+            bus.wait(timing::RESET_LOW);
+            bus.drive(Level::High)?;
+            bus.wait(timing::PRESENCE_WAIT);
+            bus.sample().map(|high| !high)
+        })?;
+        self.wait(timing::RESET_RECOVERY);
+        Ok(present)
+    }
+
+    fn write_bit(&mut self, bit: bool) -> Result<()> {
+        let (low, recovery) = if bit {
+            (timing::WRITE_1_LOW, timing::WRITE_1_RECOVERY)
+        } else {
+            (timing::WRITE_0_LOW, timing::WRITE_0_RECOVERY)
+        };
+        self.slot(|bus| {
+            bus.drive(Level::Low)?;
+            bus.wait(low);
+            bus.drive(Level::High)
+        })?;
+        self.wait(recovery);
+        Ok(())
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        let bit = self.slot(|bus| {
+            bus.drive(Level::Low)?;
+            bus.wait(timing::READ_LOW);
+            bus.drive(Level::High)?;
+            bus.wait(timing::READ_SAMPLE);
+            bus.sample()
+        })?;
+        self.wait(timing::READ_RECOVERY);
+        Ok(bit)
+    }
+}
@@ -0,0 +1,256 @@
+//! Pin multiplexing
+//!
+//! Most pads can carry one of several signals: a GPIO, or an alternate
+//! function such as the TX line of a UART. A board describes which pads its
+//! peripherals use in a [`PinMap`], and the BSP routes them through a
+//! [`Pinmux`] while constructing each peripheral. The [`Pinmux`] refuses to
+//! hand a pad to a second owner, so two drivers can't fight over it.
+//!
+//! ```ignore
+//! static BOARD_PINS: PinMap = PinMap::new(&[
+//!     PinAssignment::new(0x954, Peripheral::Uart(2), "txd", AltFn(1)),
+//!     PinAssignment::new(0x950, Peripheral::Uart(2), "rxd", AltFn(1)),
+//! ]);
+//!
+//! let mut pinmux = Pinmux::new(control_module, BOARD_PINS);
+//! pinmux.claim(Peripheral::Uart(2))?;
+//! ```
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+/// Alternate function selecting a signal on a pad, as numbered in the SoC
+/// datasheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AltFn(pub u8);
+
+/// Owner of a pad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peripheral {
+    /// GPIO line, by global GPIO number
+    Gpio(u16),
+    /// UART instance
+    Uart(u8),
+    /// SPI bus
+    Spi(u8),
+    /// I2C bus
+    I2c(u8),
+    /// PWM module
+    Pwm(u8),
+    /// Ethernet MAC
+    Ethernet(u8),
+    /// PIO block
+    Pio(u8),
+    /// Any other peripheral
+    Other(&'static str),
+}
+
+impl fmt::Display for Peripheral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Peripheral::Gpio(n) => write!(f, "GPIO{}", n),
+            Peripheral::Uart(n) => write!(f, "UART{}", n),
+            Peripheral::Spi(n) => write!(f, "SPI{}", n),
+            Peripheral::I2c(n) => write!(f, "I2C{}", n),
+            Peripheral::Pwm(n) => write!(f, "PWM{}", n),
+            Peripheral::Ethernet(n) => write!(f, "ETH{}", n),
+            Peripheral::Pio(n) => write!(f, "PIO{}", n),
+            Peripheral::Other(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Pad carrying one signal of a peripheral
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinAssignment {
+    /// Pad, numbered as the [`PinController`] expects
+    pub pin: u16,
+    /// Peripheral the signal belongs to
+    pub peripheral: Peripheral,
+    /// Signal name, for diagnostics
+    pub signal: &'static str,
+    /// Function routing the signal to the pad
+    pub function: AltFn,
+}
+
+impl PinAssignment {
+    /// Create an assignment of `signal` of `peripheral` to `pin`
+    pub const fn new(
+        pin: u16,
+        peripheral: Peripheral,
+        signal: &'static str,
+        function: AltFn,
+    ) -> Self {
+        Self {
+            pin,
+            peripheral,
+            signal,
+            function,
+        }
+    }
+}
+
+/// Pads used by the peripherals of a board
+#[derive(Debug, Clone, Copy)]
+pub struct PinMap {
+    assignments: &'static [PinAssignment],
+}
+
+impl PinMap {
+    /// Create a pin map
+    pub const fn new(assignments: &'static [PinAssignment]) -> Self {
+        Self { assignments }
+    }
+
+    /// All assignments of the map
+    pub fn assignments(&self) -> &'static [PinAssignment] {
+        self.assignments
+    }
+
+    /// Assignments of the pads of `peripheral`
+    pub fn pins(&self, peripheral: Peripheral) -> impl Iterator<Item = &'static PinAssignment> {
+        self.assignments
+            .iter()
+            .filter(move |assignment| assignment.peripheral == peripheral)
+    }
+
+    /// Check that no pad is assigned twice, returning the first one that is
+    pub fn validate(&self) -> Result<(), u16> {
+        for (i, assignment) in self.assignments.iter().enumerate() {
+            if self.assignments[..i]
+                .iter()
+                .any(|earlier| earlier.pin == assignment.pin)
+            {
+                return Err(assignment.pin);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pad function selection of a SoC
+pub trait PinController {
+    /// Error type
+    type Error;
+
+    /// Route alternate function `function` to `pin`
+    fn set_function(&mut self, pin: u16, function: AltFn) -> Result<(), Self::Error>;
+}
+
+/// Pin multiplexing error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinmuxError<E> {
+    /// The pin map has no pads for the peripheral
+    NotMapped(Peripheral),
+    /// The pad is already owned by another peripheral
+    Claimed {
+        /// Pad asked for
+        pin: u16,
+        /// Peripheral owning it
+        owner: Peripheral,
+    },
+    /// The pin controller failed
+    Controller(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for PinmuxError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinmuxError::NotMapped(peripheral) => write!(f, "No pads mapped for {}", peripheral),
+            PinmuxError::Claimed { pin, owner } => {
+                write!(f, "Pad {:#x} already claimed by {}", pin, owner)
+            }
+            PinmuxError::Controller(err) => write!(f, "Pin controller error: {:?}", err),
+        }
+    }
+}
+
+/// Pad routing of a board, tracking the owner of every claimed pad
+pub struct Pinmux<C> {
+    controller: C,
+    map: PinMap,
+    claims: BTreeMap<u16, Peripheral>,
+}
+
+impl<C: PinController> Pinmux<C> {
+    /// Create a pinmux routing the pads of `map` through `controller`
+    pub const fn new(controller: C, map: PinMap) -> Self {
+        Self {
+            controller,
+            map,
+            claims: BTreeMap::new(),
+        }
+    }
+
+    /// Board pin map
+    pub fn map(&self) -> &PinMap {
+        &self.map
+    }
+
+    /// Route all pads the pin map assigns to `peripheral`
+    ///
+    /// Nothing is changed if any of them is already claimed, by this or
+    /// another peripheral.
+    pub fn claim(&mut self, peripheral: Peripheral) -> Result<(), PinmuxError<C::Error>> {
+        let mut pins = self.map.pins(peripheral).peekable();
+        if pins.peek().is_none() {
+            return Err(PinmuxError::NotMapped(peripheral));
+        }
+        for assignment in pins {
+            self.check_free(assignment.pin)?;
+        }
+
+        for assignment in self.map.pins(peripheral) {
+            self.controller
+                .set_function(assignment.pin, assignment.function)
+                .map_err(PinmuxError::Controller)?;
+            self.claims.insert(assignment.pin, peripheral);
+        }
+        Ok(())
+    }
+
+    /// Route `function` to a single pad for `owner`, whether or not the pin
+    /// map mentions it
+    pub fn claim_pin(
+        &mut self,
+        pin: u16,
+        function: AltFn,
+        owner: Peripheral,
+    ) -> Result<(), PinmuxError<C::Error>> {
+        self.check_free(pin)?;
+        self.controller
+            .set_function(pin, function)
+            .map_err(PinmuxError::Controller)?;
+        self.claims.insert(pin, owner);
+        Ok(())
+    }
+
+    /// Release every pad owned by `peripheral`
+    ///
+    /// The pads keep their function until claimed again.
+    pub fn release(&mut self, peripheral: Peripheral) {
+        self.claims.retain(|_, owner| *owner != peripheral);
+    }
+
+    /// Peripheral owning `pin`
+    pub fn owner(&self, pin: u16) -> Option<Peripheral> {
+        self.claims.get(&pin).copied()
+    }
+
+    /// Claimed pads and their owners
+    pub fn claims(&self) -> impl Iterator<Item = (u16, Peripheral)> + '_ {
+        self.claims.iter().map(|(&pin, &owner)| (pin, owner))
+    }
+
+    /// Access the pin controller
+    pub fn controller(&mut self) -> &mut C {
+        &mut self.controller
+    }
+
+    fn check_free(&self, pin: u16) -> Result<(), PinmuxError<C::Error>> {
+        match self.owner(pin) {
+            Some(owner) => Err(PinmuxError::Claimed { pin, owner }),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,766 @@
+//! Programmable I/O (PIO) HAL traits
+//!
+//! Some microcontrollers, like the RP2040 and RP2350, have small state
+//! machines that run programs of a handful of instructions and drive pins
+//! with cycle exact timing. They implement protocols that would be too fast
+//! or too tightly timed for bit-banging: LED strips, extra serial ports,
+//! logic analyzer style captures.
+//!
+//! A BSP exposes each state machine as a [`PioStateMachine`]. Programs are
+//! assembled with the [`Instruction`] encoders into a [`PioProgram`], and
+//! [`StateMachineConfig`] sets the clock and pin mapping the program runs
+//! with. Jump targets in a program are relative to its first instruction;
+//! the state machine relocates them when loading the program.
+//!
+//! Ready made drivers are included for WS2812 LEDs ([`Ws2812`]), an extra
+//! UART transmitter ([`PioSerialTx`]) and parallel pin capture
+//! ([`PioCapture`]).
+
+use core::fmt;
+
+use crate::error::{Error, Result};
+use crate::time::Rate;
+
+/// Instruction memory size in instructions
+pub const MAX_PROGRAM_LEN: usize = 32;
+
+/// Side-set configuration of a program
+///
+/// Side-set drives pins alongside every instruction, using the top bits of
+/// the delay field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SideSet {
+    /// Number of side-set pins
+    pub bits: u8,
+    /// Instructions without a side-set value leave the pins alone
+    pub optional: bool,
+    /// Side-set drives pin directions instead of levels
+    pub pindirs: bool,
+}
+
+impl SideSet {
+    /// No side-set
+    pub const NONE: Self = Self::new(0, false);
+
+    /// Side-set of `bits` pins
+    pub const fn new(bits: u8, optional: bool) -> Self {
+        Self {
+            bits,
+            optional,
+            pindirs: false,
+        }
+    }
+
+    /// Bits of the delay field used for side-set
+    pub const fn field_bits(&self) -> u8 {
+        self.bits + self.optional as u8
+    }
+
+    /// Longest delay instructions can have
+    pub const fn max_delay(&self) -> u8 {
+        (1 << (5 - self.field_bits())) - 1
+    }
+}
+
+/// JMP condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JmpCondition {
+    /// Always
+    Always = 0,
+    /// X is zero
+    XZero = 1,
+    /// X is non-zero, decrementing X
+    XDecrement = 2,
+    /// Y is zero
+    YZero = 3,
+    /// Y is non-zero, decrementing Y
+    YDecrement = 4,
+    /// X differs from Y
+    XNotEqualY = 5,
+    /// The jump pin is high
+    Pin = 6,
+    /// The output shift register is not empty
+    OsrNotEmpty = 7,
+}
+
+/// WAIT source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitSource {
+    /// Absolute GPIO number
+    Gpio = 0,
+    /// Pin relative to the IN pin base
+    Pin = 1,
+    /// IRQ flag
+    Irq = 2,
+}
+
+/// IN source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InSource {
+    Pins = 0,
+    X = 1,
+    Y = 2,
+    Null = 3,
+    Isr = 6,
+    Osr = 7,
+}
+
+/// OUT destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutDestination {
+    Pins = 0,
+    X = 1,
+    Y = 2,
+    Null = 3,
+    PinDirs = 4,
+    Pc = 5,
+    Isr = 6,
+    Exec = 7,
+}
+
+/// MOV destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovDestination {
+    Pins = 0,
+    X = 1,
+    Y = 2,
+    Exec = 4,
+    Pc = 5,
+    Isr = 6,
+    Osr = 7,
+}
+
+/// MOV operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovOp {
+    None = 0,
+    Invert = 1,
+    BitReverse = 2,
+}
+
+/// MOV source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovSource {
+    Pins = 0,
+    X = 1,
+    Y = 2,
+    Null = 3,
+    Status = 5,
+    Isr = 6,
+    Osr = 7,
+}
+
+/// SET destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetDestination {
+    Pins = 0,
+    X = 1,
+    Y = 2,
+    PinDirs = 4,
+}
+
+/// Encoded PIO instruction
+///
+/// Bit counts of 32 for IN and OUT are written as 32 and encoded as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction(pub u16);
+
+impl Instruction {
+    /// Jump to `address`, relative to the program start
+    pub const fn jmp(condition: JmpCondition, address: u8) -> Self {
+        Self(((condition as u16) << 5) | (address as u16 & 0x1F))
+    }
+
+    /// Stall until `source` `index` has `polarity`
+    pub const fn wait(polarity: bool, source: WaitSource, index: u8) -> Self {
+        Self(0x2000 | ((polarity as u16) << 7) | ((source as u16) << 5) | (index as u16 & 0x1F))
+    }
+
+    /// Shift `bits` bits from `source` into the input shift register
+    pub const fn in_(source: InSource, bits: u8) -> Self {
+        Self(0x4000 | ((source as u16) << 5) | (bits as u16 & 0x1F))
+    }
+
+    /// Shift `bits` bits from the output shift register to `destination`
+    pub const fn out(destination: OutDestination, bits: u8) -> Self {
+        Self(0x6000 | ((destination as u16) << 5) | (bits as u16 & 0x1F))
+    }
+
+    /// Push the input shift register to the RX FIFO
+    pub const fn push(if_full: bool, block: bool) -> Self {
+        Self(0x8000 | ((if_full as u16) << 6) | ((block as u16) << 5))
+    }
+
+    /// Pull the TX FIFO into the output shift register
+    pub const fn pull(if_empty: bool, block: bool) -> Self {
+        Self(0x8080 | ((if_empty as u16) << 6) | ((block as u16) << 5))
+    }
+
+    /// Copy `source` to `destination`
+    pub const fn mov(destination: MovDestination, op: MovOp, source: MovSource) -> Self {
+        Self(0xA000 | ((destination as u16) << 5) | ((op as u16) << 3) | source as u16)
+    }
+
+    /// Set or clear IRQ flag `index`, optionally waiting for it to clear
+    pub const fn irq(clear: bool, wait: bool, index: u8) -> Self {
+        Self(0xC000 | ((clear as u16) << 6) | ((wait as u16) << 5) | (index as u16 & 0x1F))
+    }
+
+    /// Write `data` to `destination`
+    pub const fn set(destination: SetDestination, data: u8) -> Self {
+        Self(0xE000 | ((destination as u16) << 5) | (data as u16 & 0x1F))
+    }
+
+    /// Do nothing (`mov y, y`)
+    pub const fn nop() -> Self {
+        Self::mov(MovDestination::Y, MovOp::None, MovSource::Y)
+    }
+
+    /// Drive `value` on the side-set pins
+    pub const fn side(self, value: u8, side_set: SideSet) -> Self {
+        let shift = 13 - side_set.field_bits();
+        let mask = (1u16 << side_set.bits) - 1;
+        let enable = if side_set.optional { 1 << 12 } else { 0 };
+        Self(self.0 | enable | ((value as u16 & mask) << shift))
+    }
+
+    /// Wait `cycles` extra cycles after the instruction
+    pub const fn delay(self, cycles: u8, side_set: SideSet) -> Self {
+        Self(self.0 | ((cycles & side_set.max_delay()) as u16) << 8)
+    }
+
+    /// Whether this is a JMP, whose target needs relocating
+    pub const fn is_jmp(&self) -> bool {
+        self.0 & 0xE000 == 0
+    }
+}
+
+/// Assembled PIO program
+#[derive(Debug, Clone, Copy)]
+pub struct PioProgram<'a> {
+    /// Instructions
+    pub code: &'a [u16],
+    /// Required load address, for programs that must sit at a fixed place
+    pub origin: Option<u8>,
+    /// First instruction of the loop, relative to the program start
+    pub wrap_target: u8,
+    /// Last instruction of the loop, after which execution continues at
+    /// `wrap_target`
+    pub wrap: u8,
+    /// Side-set the instructions were encoded for
+    pub side_set: SideSet,
+}
+
+impl<'a> PioProgram<'a> {
+    /// Program looping over all of `code`
+    pub const fn new(code: &'a [u16], side_set: SideSet) -> Self {
+        Self {
+            code,
+            origin: None,
+            wrap_target: 0,
+            wrap: code.len() as u8 - 1,
+            side_set,
+        }
+    }
+
+    /// Loop over `wrap_target..=wrap` instead
+    pub const fn with_wrap(mut self, wrap_target: u8, wrap: u8) -> Self {
+        self.wrap_target = wrap_target;
+        self.wrap = wrap;
+        self
+    }
+
+    /// Check the program fits the instruction memory and its wrap is valid
+    pub fn validate(&self) -> Result<()> {
+        let len = self.code.len();
+        if len == 0 || len > MAX_PROGRAM_LEN {
+            return Err(Error::InvalidParameter);
+        }
+        if usize::from(self.wrap) >= len || self.wrap_target > self.wrap {
+            return Err(Error::InvalidParameter);
+        }
+        if self.side_set.field_bits() > 5 {
+            return Err(Error::InvalidConfig);
+        }
+        match self.origin {
+            Some(origin) if usize::from(origin) + len > MAX_PROGRAM_LEN => {
+                Err(Error::InvalidParameter)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Instruction `index` as loaded at `offset`, with jumps relocated
+    pub fn relocated(&self, index: usize, offset: u8) -> u16 {
+        let instruction = Instruction(self.code[index]);
+        if instruction.is_jmp() {
+            let target = (instruction.0 + u16::from(offset)) & 0x1F;
+            (instruction.0 & !0x1F) | target
+        } else {
+            instruction.0
+        }
+    }
+}
+
+/// State machine clock divider, in 1/256ths
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockDivider {
+    /// Integer part
+    pub integer: u16,
+    /// Fractional part, in 1/256ths
+    pub fraction: u8,
+}
+
+impl ClockDivider {
+    /// Run at the full system clock
+    pub const NONE: Self = Self {
+        integer: 1,
+        fraction: 0,
+    };
+
+    /// Divider running at `target` from a `source` clock
+    ///
+    /// Returns `None` when `target` is faster than `source` or too slow to
+    /// reach.
+    pub fn from_rates(source: Rate, target: Rate) -> Option<Self> {
+        let target = u64::from(target.as_hz());
+        if target == 0 {
+            return None;
+        }
+        let div = (u64::from(source.as_hz()) * 256 + target / 2) / target;
+        let integer = u16::try_from(div >> 8).ok().filter(|&integer| integer > 0)?;
+        Some(Self {
+            integer,
+            fraction: div as u8,
+        })
+    }
+
+    /// Rate the state machine runs at from a `source` clock
+    pub fn output(&self, source: Rate) -> Rate {
+        let div = u64::from(self.integer) * 256 + u64::from(self.fraction);
+        Rate::from_hz((u64::from(source.as_hz()) * 256 / div) as u32)
+    }
+}
+
+impl Default for ClockDivider {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Consecutive GPIOs used by a state machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PinRange {
+    /// First GPIO
+    pub base: u8,
+    /// Number of GPIOs
+    pub count: u8,
+}
+
+impl PinRange {
+    /// `count` GPIOs from `base`
+    pub const fn new(base: u8, count: u8) -> Self {
+        Self { base, count }
+    }
+}
+
+/// Shift register direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShiftDirection {
+    /// MSB first
+    Left,
+    /// LSB first
+    #[default]
+    Right,
+}
+
+/// Shift register configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShiftConfig {
+    /// Shift direction
+    pub direction: ShiftDirection,
+    /// Push or pull automatically when `threshold` bits were shifted
+    pub auto: bool,
+    /// Bits per FIFO word, 1 to 32
+    pub threshold: u8,
+}
+
+impl Default for ShiftConfig {
+    fn default() -> Self {
+        Self {
+            direction: ShiftDirection::Right,
+            auto: false,
+            threshold: 32,
+        }
+    }
+}
+
+/// FIFO joining, trading one direction for a deeper FIFO in the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FifoJoin {
+    /// Separate TX and RX FIFOs
+    #[default]
+    None,
+    /// Both FIFOs used for TX
+    Tx,
+    /// Both FIFOs used for RX
+    Rx,
+}
+
+/// State machine configuration
+///
+/// The wrap and side-set count come from the [`PioProgram`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StateMachineConfig {
+    /// Clock divider
+    pub clock_divider: ClockDivider,
+    /// Pins written by OUT
+    pub out_pins: PinRange,
+    /// Pins written by SET
+    pub set_pins: PinRange,
+    /// First pin read by IN and WAIT PIN
+    pub in_base: u8,
+    /// First side-set pin
+    pub side_set_base: u8,
+    /// Pin tested by JMP PIN
+    pub jmp_pin: u8,
+    /// Input shift register
+    pub in_shift: ShiftConfig,
+    /// Output shift register
+    pub out_shift: ShiftConfig,
+    /// FIFO joining
+    pub fifo_join: FifoJoin,
+}
+
+/// PIO state machine
+pub trait PioStateMachine {
+    /// Error type
+    type Error;
+
+    /// Load `program` into instruction memory and configure the state
+    /// machine to run it, stopped, from its start
+    ///
+    /// Returns the offset the program was loaded at. Any program loaded
+    /// before is unloaded.
+    fn load(&mut self, program: &PioProgram<'_>, config: &StateMachineConfig) -> Result<u8, Self::Error>;
+
+    /// Stop the state machine and free its instruction memory
+    fn unload(&mut self);
+
+    /// Start or stop the state machine
+    fn set_enabled(&mut self, enabled: bool);
+
+    /// Reset the state machine's internal state, keeping its program and
+    /// configuration
+    fn restart(&mut self);
+
+    /// Execute one instruction immediately
+    fn exec(&mut self, instruction: Instruction);
+
+    /// Drive `pins` high or low
+    fn set_pins(&mut self, pins: PinRange, high: bool) -> Result<(), Self::Error>;
+
+    /// Make `pins` outputs or inputs
+    fn set_pin_dirs(&mut self, pins: PinRange, output: bool) -> Result<(), Self::Error>;
+
+    /// Queue a word in the TX FIFO, returning false when it is full
+    fn try_push(&mut self, word: u32) -> bool;
+
+    /// Take a word from the RX FIFO
+    fn try_pull(&mut self) -> Option<u32>;
+
+    /// Number of words waiting in the RX FIFO
+    fn rx_level(&self) -> usize;
+
+    /// Drop everything in both FIFOs
+    fn clear_fifos(&mut self);
+
+    /// Queue a word, waiting for room in the TX FIFO
+    fn push(&mut self, word: u32) {
+        while !self.try_push(word) {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Take a word, waiting for one to arrive in the RX FIFO
+    fn pull(&mut self) -> u32 {
+        loop {
+            if let Some(word) = self.try_pull() {
+                return word;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// PIO programs used by the drivers in this module
+pub mod programs {
+    use super::*;
+
+    /// WS2812 bit timing, in state machine cycles: T1 low-to-high lead,
+    /// T2 the data bit, T3 the tail
+    const WS2812_T1: u8 = 2;
+    const WS2812_T2: u8 = 5;
+    const WS2812_T3: u8 = 3;
+
+    /// State machine cycles per WS2812 bit
+    pub const WS2812_CYCLES_PER_BIT: u32 = (WS2812_T1 + WS2812_T2 + WS2812_T3) as u32;
+
+    const WS2812_SIDE_SET: SideSet = SideSet::new(1, false);
+
+    const WS2812_CODE: [u16; 4] = [
+        // bitloop: shift out a bit, dropping the pin
+        Instruction::out(OutDestination::X, 1)
+            .side(0, WS2812_SIDE_SET)
+            .delay(WS2812_T3 - 1, WS2812_SIDE_SET)
+            .0,
+        // Raise the pin, a short pulse for a zero bit
+        Instruction::jmp(JmpCondition::XZero, 3)
+            .side(1, WS2812_SIDE_SET)
+            .delay(WS2812_T1 - 1, WS2812_SIDE_SET)
+            .0,
+        // do_one: keep the pin high
+        Instruction::jmp(JmpCondition::Always, 0)
+            .side(1, WS2812_SIDE_SET)
+            .delay(WS2812_T2 - 1, WS2812_SIDE_SET)
+            .0,
+        // do_zero: drop the pin
+        Instruction::nop()
+            .side(0, WS2812_SIDE_SET)
+            .delay(WS2812_T2 - 1, WS2812_SIDE_SET)
+            .0,
+    ];
+
+    /// WS2812 driver, shifting out 24-bit GRB words from the TX FIFO
+    pub const WS2812: PioProgram<'static> = PioProgram::new(&WS2812_CODE, WS2812_SIDE_SET);
+
+    /// State machine cycles per UART bit
+    pub const UART_TX_CYCLES_PER_BIT: u32 = 8;
+
+    const UART_TX_SIDE_SET: SideSet = SideSet::new(1, true);
+
+    const UART_TX_CODE: [u16; 4] = [
+        // Stop bit (or idle), then wait for a byte
+        Instruction::pull(false, true)
+            .side(1, UART_TX_SIDE_SET)
+            .delay(7, UART_TX_SIDE_SET)
+            .0,
+        // Start bit, and eight data bits to go
+        Instruction::set(SetDestination::X, 7)
+            .side(0, UART_TX_SIDE_SET)
+            .delay(7, UART_TX_SIDE_SET)
+            .0,
+        // bitloop: data bits, LSB first
+        Instruction::out(OutDestination::Pins, 1).0,
+        Instruction::jmp(JmpCondition::XDecrement, 2)
+            .delay(6, UART_TX_SIDE_SET)
+            .0,
+    ];
+
+    /// 8N1 UART transmitter, sending bytes from the TX FIFO
+    pub const UART_TX: PioProgram<'static> = PioProgram::new(&UART_TX_CODE, UART_TX_SIDE_SET);
+}
+
+/// 24-bit colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Colour from its components
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Scale by `brightness`/255
+    pub fn dimmed(self, brightness: u8) -> Self {
+        let scale = |c: u8| ((u16::from(c) * u16::from(brightness) + 127) / 255) as u8;
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+}
+
+/// WS2812 (NeoPixel) LED chain driven by a PIO state machine
+///
+/// Colours are sent as they are queued; the chain latches them once the
+/// line stays low for 50 µs (280 µs for newer parts), so wait that long
+/// between frames.
+pub struct Ws2812<S: PioStateMachine> {
+    sm: S,
+}
+
+impl<S: PioStateMachine> Ws2812<S> {
+    /// Bit rate of the chain
+    pub const BIT_RATE: Rate = Rate::from_khz(800);
+
+    /// Drive the chain on `pin`, with the state machine clocked from
+    /// `sys_clock`
+    pub fn new(mut sm: S, pin: u8, sys_clock: Rate) -> Result<Self> {
+        let target = Rate::from_hz(Self::BIT_RATE.as_hz() * programs::WS2812_CYCLES_PER_BIT);
+        let config = StateMachineConfig {
+            clock_divider: ClockDivider::from_rates(sys_clock, target).ok_or(Error::InvalidConfig)?,
+            side_set_base: pin,
+            out_shift: ShiftConfig {
+                direction: ShiftDirection::Left,
+                auto: true,
+                threshold: 24,
+            },
+            fifo_join: FifoJoin::Tx,
+            ..Default::default()
+        };
+        sm.load(&programs::WS2812, &config)
+            .map_err(|_| Error::InvalidConfig)?;
+        sm.set_pin_dirs(PinRange::new(pin, 1), true)
+            .map_err(|_| Error::InvalidConfig)?;
+        sm.set_enabled(true);
+        Ok(Self { sm })
+    }
+
+    /// Send colours down the chain, first LED first
+    pub fn write(&mut self, colors: impl IntoIterator<Item = Rgb>) {
+        for color in colors {
+            // GRB order, left aligned for the 24-bit autopull
+            let word = (u32::from(color.g) << 24) | (u32::from(color.r) << 16) | (u32::from(color.b) << 8);
+            self.sm.push(word);
+        }
+    }
+
+    /// Stop the state machine and return it
+    pub fn free(mut self) -> S {
+        self.sm.unload();
+        self.sm
+    }
+}
+
+/// UART transmitter on any pin, driven by a PIO state machine
+///
+/// 8 data bits, no parity, one stop bit.
+pub struct PioSerialTx<S: PioStateMachine> {
+    sm: S,
+}
+
+impl<S: PioStateMachine> PioSerialTx<S> {
+    /// Transmit on `pin` at `baud`, with the state machine clocked from
+    /// `sys_clock`
+    pub fn new(mut sm: S, pin: u8, sys_clock: Rate, baud: u32) -> Result<Self> {
+        let target = Rate::from_hz(baud.checked_mul(programs::UART_TX_CYCLES_PER_BIT).ok_or(Error::InvalidParameter)?);
+        let config = StateMachineConfig {
+            clock_divider: ClockDivider::from_rates(sys_clock, target).ok_or(Error::InvalidConfig)?,
+            out_pins: PinRange::new(pin, 1),
+            side_set_base: pin,
+            out_shift: ShiftConfig {
+                direction: ShiftDirection::Right,
+                auto: false,
+                threshold: 32,
+            },
+            fifo_join: FifoJoin::Tx,
+            ..Default::default()
+        };
+        sm.load(&programs::UART_TX, &config)
+            .map_err(|_| Error::InvalidConfig)?;
+        // Idle high before the pin becomes an output
+        let pins = PinRange::new(pin, 1);
+        sm.set_pins(pins, true).map_err(|_| Error::InvalidConfig)?;
+        sm.set_pin_dirs(pins, true).map_err(|_| Error::InvalidConfig)?;
+        sm.set_enabled(true);
+        Ok(Self { sm })
+    }
+
+    /// Queue bytes for transmission, waiting for room in the FIFO
+    pub fn write(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.sm.push(u32::from(byte));
+        }
+    }
+
+    /// Stop the state machine and return it
+    pub fn free(mut self) -> S {
+        self.sm.unload();
+        self.sm
+    }
+}
+
+impl<S: PioStateMachine> fmt::Write for PioSerialTx<S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Samples consecutive pins at a fixed rate, like a logic analyzer
+///
+/// Each RX FIFO word packs `32 / pins` samples, the oldest in the lowest
+/// bits. The state machine stalls, and samples are lost, when the FIFO is
+/// not drained fast enough.
+pub struct PioCapture<S: PioStateMachine> {
+    sm: S,
+    pins: u8,
+}
+
+impl<S: PioStateMachine> PioCapture<S> {
+    /// Sample `pins` pins from `base` at `sample_rate`, with the state
+    /// machine clocked from `sys_clock`
+    ///
+    /// `pins` must divide 32, so samples don't straddle words.
+    pub fn new(mut sm: S, base: u8, pins: u8, sys_clock: Rate, sample_rate: Rate) -> Result<Self> {
+        if !matches!(pins, 1 | 2 | 4 | 8 | 16 | 32) {
+            return Err(Error::InvalidParameter);
+        }
+        let code = [Instruction::in_(InSource::Pins, pins).0];
+        let program = PioProgram::new(&code, SideSet::NONE);
+        let config = StateMachineConfig {
+            clock_divider: ClockDivider::from_rates(sys_clock, sample_rate).ok_or(Error::InvalidConfig)?,
+            in_base: base,
+            in_shift: ShiftConfig {
+                direction: ShiftDirection::Right,
+                auto: true,
+                threshold: 32,
+            },
+            fifo_join: FifoJoin::Rx,
+            ..Default::default()
+        };
+        sm.load(&program, &config).map_err(|_| Error::InvalidConfig)?;
+        sm.set_pin_dirs(PinRange::new(base, pins), false)
+            .map_err(|_| Error::InvalidConfig)?;
+        Ok(Self { sm, pins })
+    }
+
+    /// Samples in each word
+    pub fn samples_per_word(&self) -> usize {
+        32 / usize::from(self.pins)
+    }
+
+    /// Start sampling
+    pub fn start(&mut self) {
+        self.sm.clear_fifos();
+        self.sm.restart();
+        self.sm.set_enabled(true);
+    }
+
+    /// Stop sampling
+    pub fn stop(&mut self) {
+        self.sm.set_enabled(false);
+    }
+
+    /// Drain captured words into `buffer` without waiting, returning how
+    /// many were stored
+    pub fn read(&mut self, buffer: &mut [u32]) -> usize {
+        let mut count = 0;
+        for word in buffer.iter_mut() {
+            match self.sm.try_pull() {
+                Some(value) => *word = value,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Stop the state machine and return it
+    pub fn free(mut self) -> S {
+        self.sm.unload();
+        self.sm
+    }
+}
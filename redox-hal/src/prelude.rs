@@ -1,34 +1,70 @@
 //! Prelude module for convenient imports
 
+pub use crate::clocks::{ClockControl, ClockId, ClockManager, ClockTree};
 pub use crate::error::{Error, Result};
 pub use crate::time::{Duration, Instant, Rate};
 
 #[cfg(feature = "gpio")]
-pub use crate::gpio::{Edge, GpioPin, InputPin, Level, OutputPin, PinMode, Pull};
+pub use crate::gpio::{
+    Edge, GpioPin, InputPin, InterruptPin, Level, OutputPin, PinMode, Pull, Trigger,
+};
+
+#[cfg(feature = "pinmux")]
+pub use crate::pinmux::{AltFn, Peripheral, PinAssignment, PinController, PinMap, Pinmux};
 
 #[cfg(feature = "spi")]
 pub use crate::spi::{SpiBus, SpiConfig, SpiMode};
 
+#[cfg(all(feature = "spi", feature = "async"))]
+pub use crate::spi::SpiBusAsync;
+
 #[cfg(feature = "i2c")]
 pub use crate::i2c::{I2c, I2cAddress, I2cConfig, I2cSpeed};
 
+#[cfg(all(feature = "i2c", feature = "async"))]
+pub use crate::i2c::I2cAsync;
+
+#[cfg(feature = "i2s")]
+pub use crate::i2s::{I2s, I2sConfig, SampleFormat};
+
+#[cfg(feature = "onewire")]
+pub use crate::onewire::{OneWire, RomCode};
+
 #[cfg(feature = "uart")]
 pub use crate::uart::{BaudRate, DataBits, Parity, StopBits, Uart, UartConfig};
 
+#[cfg(all(feature = "uart", feature = "async"))]
+pub use crate::uart::UartAsync;
+
+#[cfg(feature = "async")]
+pub use crate::asynch::{Blocking, InterruptSignal};
+
+#[cfg(feature = "embedded-hal")]
+pub use crate::compat::{EmbeddedHal, RedoxHal};
+
 #[cfg(feature = "timer")]
-pub use crate::timer::{Delay, Timer, TimerConfig, TimerMode};
+pub use crate::timer::{Alarm, Delay, Monotonic, Timer, TimerConfig, TimerMode};
+
+#[cfg(all(feature = "timer", feature = "async"))]
+pub use crate::timer::asynch::{AsyncDelay, AsyncTimer};
 
 #[cfg(feature = "pwm")]
 pub use crate::pwm::{Pwm, PwmConfig};
 
+#[cfg(feature = "pio")]
+pub use crate::pio::{PioProgram, PioStateMachine, StateMachineConfig};
+
 #[cfg(feature = "adc")]
 pub use crate::adc::{Adc, AdcConfig};
 
 #[cfg(feature = "dma")]
-pub use crate::dma::{Dma, DmaChannel};
+pub use crate::dma::{Dma, DmaChannel, DmaCompletion, DmaPeripheral, Transfer, TransferDescriptor};
 
 #[cfg(feature = "watchdog")]
 pub use crate::watchdog::Watchdog;
 
+#[cfg(feature = "reset")]
+pub use crate::reset::{BrownoutConfig, BrownoutDetector, ResetCause, ResetController};
+
 #[cfg(feature = "rtc")]
 pub use crate::rtc::Rtc;
@@ -0,0 +1,144 @@
+//! Reset cause and brownout detector HAL traits
+//!
+//! Most chips latch why they last reset in a status register that survives
+//! the reset itself. [`ResetController`] reads it back, so the boot code can
+//! tell a crash (watchdog, lockup) from a normal reboot (power-on, software
+//! reset) or a supply dip caught by the brownout detector.
+
+use crate::error::Result;
+
+/// Why the chip last came out of reset
+///
+/// When the hardware reports several causes at once, as a power-on usually
+/// also sets the pin reset flag, implementations report the most specific
+/// one, in the order the variants are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetCause {
+    /// The brownout detector saw the supply drop below its threshold
+    Brownout,
+    /// The watchdog expired
+    Watchdog,
+    /// The CPU locked up, such as a fault inside a fault handler
+    Lockup,
+    /// The firmware asked for a reset
+    Software,
+    /// The reset pin was pulled
+    External,
+    /// Power was applied
+    PowerOn,
+    /// The hardware doesn't say, or the flags were already cleared
+    Unknown,
+}
+
+impl ResetCause {
+    /// Whether the previous boot ended in a crash
+    pub const fn is_crash(&self) -> bool {
+        matches!(self, ResetCause::Watchdog | ResetCause::Lockup)
+    }
+
+    /// Whether the previous boot lost power
+    pub const fn is_power_loss(&self) -> bool {
+        matches!(self, ResetCause::PowerOn | ResetCause::Brownout)
+    }
+
+    /// Whether the previous boot ended for a known reason other than a crash
+    pub const fn is_normal(&self) -> bool {
+        !self.is_crash() && !matches!(self, ResetCause::Unknown)
+    }
+
+    /// Get the cause name
+    pub const fn name(&self) -> &'static str {
+        match self {
+            ResetCause::Brownout => "brownout",
+            ResetCause::Watchdog => "watchdog",
+            ResetCause::Lockup => "lockup",
+            ResetCause::Software => "software",
+            ResetCause::External => "external",
+            ResetCause::PowerOn => "power-on",
+            ResetCause::Unknown => "unknown",
+        }
+    }
+}
+
+/// Reset status and control
+pub trait ResetController {
+    /// Error type
+    type Error;
+
+    /// Cause of the last reset
+    ///
+    /// Stays the same until [`ResetController::clear_reset_cause`].
+    fn reset_cause(&self) -> ResetCause;
+
+    /// Clear the latched reset flags, so the next reset reports only its own
+    /// cause
+    fn clear_reset_cause(&mut self) -> Result<(), Self::Error>;
+
+    /// Reset the chip, reported as [`ResetCause::Software`] on the next boot
+    fn software_reset(&mut self) -> !;
+}
+
+/// What the brownout detector does when the supply drops below its threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrownoutAction {
+    /// Hold the chip in reset until the supply recovers
+    Reset,
+    /// Raise an interrupt and keep running
+    Interrupt,
+}
+
+/// Brownout detector configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrownoutConfig {
+    /// Supply rail watched by the detector
+    pub supply: &'static str,
+    /// Trip voltage in millivolts
+    pub threshold_mv: u16,
+    /// Action on a trip
+    pub action: BrownoutAction,
+}
+
+impl BrownoutConfig {
+    /// Reset when `supply` drops below `threshold_mv`
+    pub const fn reset(supply: &'static str, threshold_mv: u16) -> Self {
+        Self {
+            supply,
+            threshold_mv,
+            action: BrownoutAction::Reset,
+        }
+    }
+
+    /// Interrupt when `supply` drops below `threshold_mv`
+    pub const fn interrupt(supply: &'static str, threshold_mv: u16) -> Self {
+        Self {
+            supply,
+            threshold_mv,
+            action: BrownoutAction::Interrupt,
+        }
+    }
+}
+
+/// Brownout detector
+pub trait BrownoutDetector {
+    /// Error type
+    type Error;
+
+    /// Arm the detector
+    ///
+    /// Detectors with fixed trip levels round `threshold_mv` up to the next
+    /// level they have, and fail with an invalid configuration if there is
+    /// none.
+    fn configure(&mut self, config: &BrownoutConfig) -> Result<(), Self::Error>;
+
+    /// Disarm the detector (if supported)
+    fn disable(&mut self) -> Result<(), Self::Error>;
+
+    /// Current configuration, `None` if disarmed
+    fn config(&self) -> Option<BrownoutConfig>;
+
+    /// Check if the supply is below the threshold right now
+    fn is_below_threshold(&self) -> bool;
+
+    /// Set the handler for [`BrownoutAction::Interrupt`]
+    fn set_handler(&mut self, handler: fn());
+}
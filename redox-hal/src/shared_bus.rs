@@ -0,0 +1,646 @@
+//! Shared I2C and SPI buses
+//!
+//! A [`BusManager`] owns a bus and hands out device handles, so several
+//! device drivers can use one bus: [`SharedI2c`], owning the device address
+//! and implementing [`I2c`], and [`SharedSpi`], owning the chip select pin and
+//! implementing [`SpiDevice`]. Both lock the bus per transaction, so a
+//! transaction is never interleaved with another driver's.
+//!
+//! Blocking transactions run with the bus locked through [`critical_section`],
+//! so they are safe from interrupt handlers too. Interrupts stay disabled for
+//! the whole transaction: keep transfers on shared buses short, or give
+//! latency sensitive devices their own bus.
+//!
+//! Async transactions keep interrupts enabled and hold the bus across
+//! `.await`s. They get the bus in request order, so a driver issuing back to
+//! back transfers can't starve the others. A blocking transaction never waits
+//! for an async one, since on a single core the async driver could not run to
+//! release the bus: it fails with [`DeviceError::Busy`] instead.
+//!
+//! Each bus has a rank, and an async driver holding one bus takes another only
+//! through [`BusGuard::lock_nested`], which refuses to go down in rank, so two
+//! drivers can never wait on each other's bus.
+//!
+//! ```ignore
+//! static I2C1: BusManager<I2c1> = BusManager::new(i2c1);
+//! static SPI0: BusManager<Spi0> = BusManager::with_rank(spi0, 1);
+//!
+//! let mut sensor = I2C1.acquire_i2c(I2cAddress::SevenBit(0x76))?;
+//! let mut flash = SPI0.acquire_spi(flash_cs);
+//! let mut display = SPI0.acquire_spi(display_cs).with_config(display_config);
+//! sensor.write_read(I2cAddress::SevenBit(0x76), &[0xD0], &mut id)?;
+//! ```
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::cell::UnsafeCell;
+use core::convert::Infallible;
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use crate::critical_section::{self, Mutex};
+use crate::error::{Error, Result};
+
+#[cfg(feature = "i2c")]
+use crate::i2c::{I2c, I2cAddress, I2cConfig, I2cOperation};
+
+#[cfg(all(feature = "spi", feature = "gpio"))]
+use crate::gpio::OutputPin;
+#[cfg(all(feature = "spi", feature = "gpio"))]
+use crate::spi::{SpiBus, SpiConfig, SpiDevice};
+
+#[cfg(all(feature = "i2c", feature = "async"))]
+use crate::i2c::I2cAsync;
+#[cfg(all(feature = "spi", feature = "gpio", feature = "async"))]
+use crate::spi::SpiBusAsync;
+
+/// Ticket queue handing the bus to async drivers in request order
+///
+/// The bus is free when `next == serving`, otherwise the `serving` ticket
+/// holds it.
+struct BusState {
+    next: u32,
+    serving: u32,
+    wakers: BTreeMap<u32, Waker>,
+    abandoned: BTreeSet<u32>,
+    /// Addresses of the I2C devices with a handle
+    #[cfg(feature = "i2c")]
+    claims: BTreeSet<u32>,
+}
+
+impl BusState {
+    fn is_free(&self) -> bool {
+        self.next == self.serving
+    }
+
+    fn take(&mut self) -> u32 {
+        let ticket = self.next;
+        self.next = self.next.wrapping_add(1);
+        ticket
+    }
+
+    /// Serve the next waiting ticket, returning its waker
+    fn advance(&mut self) -> Option<Waker> {
+        self.serving = self.serving.wrapping_add(1);
+        while self.abandoned.remove(&self.serving) {
+            self.serving = self.serving.wrapping_add(1);
+        }
+        self.wakers.remove(&self.serving)
+    }
+}
+
+/// Owner of a bus shared by several drivers
+pub struct BusManager<B> {
+    bus: UnsafeCell<B>,
+    rank: u8,
+    state: Mutex<BusState>,
+}
+
+unsafe impl<B: Send> Sync for BusManager<B> {}
+unsafe impl<B: Send> Send for BusManager<B> {}
+
+impl<B> BusManager<B> {
+    /// Create a manager for `bus`, with lock order 0
+    pub const fn new(bus: B) -> Self {
+        Self::with_rank(bus, 0)
+    }
+
+    /// Create a manager for `bus`, with lock order `rank`
+    ///
+    /// An async driver holding this bus may only take buses of a higher rank.
+    pub const fn with_rank(bus: B, rank: u8) -> Self {
+        Self {
+            bus: UnsafeCell::new(bus),
+            rank,
+            state: Mutex::new(BusState {
+                next: 0,
+                serving: 0,
+                wakers: BTreeMap::new(),
+                abandoned: BTreeSet::new(),
+                #[cfg(feature = "i2c")]
+                claims: BTreeSet::new(),
+            }),
+        }
+    }
+
+    /// Lock order of the bus
+    pub fn rank(&self) -> u8 {
+        self.rank
+    }
+
+    /// Run `f` with exclusive access to the bus, with interrupts disabled
+    ///
+    /// Fails with [`Error::Busy`] rather than waiting if an async driver
+    /// holds the bus.
+    pub fn lock<R>(&self, f: impl FnOnce(&mut B) -> R) -> Result<R> {
+        critical_section::with(|cs| {
+            if !self.state.lock(cs, |state| state.is_free()) {
+                return Err(Error::Busy);
+            }
+            // SAFETY: nobody holds the bus, and nobody can take it before
+            // the critical section ends.
+            Ok(f(unsafe { &mut *self.bus.get() }))
+        })
+    }
+
+    /// Take the bus if nobody holds or waits for it
+    pub fn try_lock(&self) -> Option<BusGuard<'_, B>> {
+        let taken = self.with_state(|state| {
+            if state.is_free() {
+                state.take();
+                true
+            } else {
+                false
+            }
+        });
+        taken.then_some(BusGuard { manager: self })
+    }
+
+    /// Wait for the bus, suspending the task
+    ///
+    /// The place in the queue is taken on the first poll and given up if the
+    /// future is dropped.
+    pub fn lock_async(&self) -> Lock<'_, B> {
+        Lock {
+            manager: self,
+            ticket: None,
+        }
+    }
+
+    /// Take the bus back
+    pub fn into_inner(self) -> B {
+        self.bus.into_inner()
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut BusState) -> R) -> R {
+        critical_section::with(|cs| self.state.lock(cs, f))
+    }
+
+    fn unlock(&self) {
+        // Wake outside the critical section
+        if let Some(waker) = self.with_state(BusState::advance) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<B> BusManager<B> {
+    /// Get the handle of the device at `address` on this I2C bus
+    ///
+    /// Fails with [`Error::Busy`] if another driver owns the address.
+    pub fn acquire_i2c(&self, address: I2cAddress) -> Result<SharedI2c<'_, B>> {
+        if !self.with_state(|state| state.claims.insert(address_key(address))) {
+            return Err(Error::Busy);
+        }
+        Ok(SharedI2c {
+            manager: self,
+            address,
+            config: None,
+        })
+    }
+}
+
+#[cfg(all(feature = "spi", feature = "gpio"))]
+impl<B> BusManager<B> {
+    /// Get the handle of the device selected by `cs` on this SPI bus
+    ///
+    /// The chip select pin is driven high (deasserted) right away.
+    pub fn acquire_spi<CS: OutputPin>(&self, mut cs: CS) -> SharedSpi<'_, B, CS> {
+        let _ = cs.set_high();
+        SharedSpi {
+            manager: self,
+            cs,
+            config: None,
+        }
+    }
+}
+
+/// Exclusive access to a bus taken by [`BusManager::try_lock`] or
+/// [`BusManager::lock_async`], released on drop
+pub struct BusGuard<'a, B> {
+    manager: &'a BusManager<B>,
+}
+
+impl<B> BusGuard<'_, B> {
+    /// Take `other` while holding this bus, suspending the task
+    ///
+    /// Fails with [`Error::InvalidParameter`] unless `other` ranks above this
+    /// bus, since a driver taking the two buses the other way round could
+    /// wait on this one forever.
+    pub fn lock_nested<'b, C>(&'b self, other: &'b BusManager<C>) -> Result<Lock<'b, C>> {
+        if other.rank > self.manager.rank {
+            Ok(other.lock_async())
+        } else {
+            Err(Error::InvalidParameter)
+        }
+    }
+}
+
+impl<B> Deref for BusGuard<'_, B> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        // SAFETY: the guard holds the serving ticket, so it is the only one
+        // accessing the bus.
+        unsafe { &*self.manager.bus.get() }
+    }
+}
+
+impl<B> DerefMut for BusGuard<'_, B> {
+    fn deref_mut(&mut self) -> &mut B {
+        // SAFETY: as above
+        unsafe { &mut *self.manager.bus.get() }
+    }
+}
+
+impl<B> Drop for BusGuard<'_, B> {
+    fn drop(&mut self) {
+        self.manager.unlock();
+    }
+}
+
+/// Future returned by [`BusManager::lock_async`]
+pub struct Lock<'a, B> {
+    manager: &'a BusManager<B>,
+    ticket: Option<u32>,
+}
+
+impl<'a, B> Future for Lock<'a, B> {
+    type Output = BusGuard<'a, B>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let manager = self.manager;
+        let ticket = self.ticket;
+        let (ticket, ready) = manager.with_state(|state| {
+            let ticket = ticket.unwrap_or_else(|| state.take());
+            if state.serving == ticket {
+                state.wakers.remove(&ticket);
+                return (ticket, true);
+            }
+            match state.wakers.get(&ticket) {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                _ => {
+                    state.wakers.insert(ticket, cx.waker().clone());
+                }
+            }
+            (ticket, false)
+        });
+        if ready {
+            self.ticket = None;
+            Poll::Ready(BusGuard { manager })
+        } else {
+            self.ticket = Some(ticket);
+            Poll::Pending
+        }
+    }
+}
+
+impl<B> Drop for Lock<'_, B> {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+        let waker = self.manager.with_state(|state| {
+            state.wakers.remove(&ticket);
+            if state.serving == ticket {
+                // Our turn came but nobody will use it, pass the bus on
+                state.advance()
+            } else {
+                state.abandoned.insert(ticket);
+                None
+            }
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Error of a device handle on a shared bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceError<BusError, PinError = Infallible> {
+    /// The bus failed
+    Bus(BusError),
+    /// Driving the chip select pin failed
+    ChipSelect(PinError),
+    /// An async driver holds the bus
+    Busy,
+    /// The operation addressed another device than the handle's
+    WrongAddress,
+}
+
+/// Handle of the driver of one I2C device on a [`BusManager`]
+///
+/// Implements [`I2c`], and [`I2cAsync`] with the `async` feature, for the
+/// device's address only: a second handle for the same address can't be
+/// acquired until the first is dropped. Its configuration, if any, is applied
+/// at the start of every transaction, so devices may use different bus
+/// speeds.
+#[cfg(feature = "i2c")]
+pub struct SharedI2c<'a, B> {
+    manager: &'a BusManager<B>,
+    address: I2cAddress,
+    config: Option<I2cConfig>,
+}
+
+#[cfg(feature = "i2c")]
+fn address_key(address: I2cAddress) -> u32 {
+    match address {
+        I2cAddress::SevenBit(addr) => u32::from(addr),
+        I2cAddress::TenBit(addr) => 0x1_0000 | u32::from(addr),
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<B> SharedI2c<'_, B> {
+    /// Use `config` for the transactions of this device
+    pub fn with_config(mut self, config: I2cConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Address of the device
+    pub fn address(&self) -> I2cAddress {
+        self.address
+    }
+
+    fn check_address<E>(&self, address: I2cAddress) -> Result<(), DeviceError<E>> {
+        if address == self.address {
+            Ok(())
+        } else {
+            Err(DeviceError::WrongAddress)
+        }
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<B: I2c> SharedI2c<'_, B> {
+    fn locked<R>(
+        &mut self,
+        address: I2cAddress,
+        f: impl FnOnce(&mut B) -> Result<R, B::Error>,
+    ) -> Result<R, DeviceError<B::Error>> {
+        self.check_address(address)?;
+        let config = self.config;
+        self.manager
+            .lock(|bus| {
+                if let Some(config) = config {
+                    bus.configure(config)?;
+                }
+                f(bus)
+            })
+            .map_err(|_| DeviceError::Busy)?
+            .map_err(DeviceError::Bus)
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<B: I2c> I2c for SharedI2c<'_, B> {
+    type Error = DeviceError<B::Error>;
+
+    fn configure(&mut self, config: I2cConfig) -> Result<(), Self::Error> {
+        self.config = Some(config);
+        Ok(())
+    }
+
+    fn write(&mut self, address: I2cAddress, data: &[u8]) -> Result<(), Self::Error> {
+        self.locked(address, |bus| bus.write(address, data))
+    }
+
+    fn read(&mut self, address: I2cAddress, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.locked(address, |bus| bus.read(address, buffer))
+    }
+
+    fn write_read(
+        &mut self,
+        address: I2cAddress,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.locked(address, |bus| bus.write_read(address, write, read))
+    }
+
+    fn transaction(
+        &mut self,
+        address: I2cAddress,
+        operations: &mut [I2cOperation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.locked(address, |bus| bus.transaction(address, operations))
+    }
+}
+
+#[cfg(all(feature = "i2c", feature = "async"))]
+impl<B: I2cAsync> SharedI2c<'_, B> {
+    async fn locked_async(
+        &self,
+        address: I2cAddress,
+    ) -> Result<BusGuard<'_, B>, DeviceError<B::Error>> {
+        self.check_address(address)?;
+        let mut bus = self.manager.lock_async().await;
+        if let Some(config) = self.config {
+            bus.configure(config).map_err(DeviceError::Bus)?;
+        }
+        Ok(bus)
+    }
+}
+
+#[cfg(all(feature = "i2c", feature = "async"))]
+impl<B: I2cAsync> I2cAsync for SharedI2c<'_, B> {
+    type Error = DeviceError<B::Error>;
+
+    fn configure(&mut self, config: I2cConfig) -> Result<(), Self::Error> {
+        self.config = Some(config);
+        Ok(())
+    }
+
+    async fn write(&mut self, address: I2cAddress, data: &[u8]) -> Result<(), Self::Error> {
+        let mut bus = self.locked_async(address).await?;
+        bus.write(address, data).await.map_err(DeviceError::Bus)
+    }
+
+    async fn read(&mut self, address: I2cAddress, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        let mut bus = self.locked_async(address).await?;
+        bus.read(address, buffer).await.map_err(DeviceError::Bus)
+    }
+
+    async fn write_read(
+        &mut self,
+        address: I2cAddress,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let mut bus = self.locked_async(address).await?;
+        bus.write_read(address, write, read)
+            .await
+            .map_err(DeviceError::Bus)
+    }
+
+    async fn transaction(
+        &mut self,
+        address: I2cAddress,
+        operations: &mut [I2cOperation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut bus = self.locked_async(address).await?;
+        bus.transaction(address, operations)
+            .await
+            .map_err(DeviceError::Bus)
+    }
+}
+
+#[cfg(feature = "i2c")]
+impl<B> Drop for SharedI2c<'_, B> {
+    fn drop(&mut self) {
+        let key = address_key(self.address);
+        self.manager.with_state(|state| state.claims.remove(&key));
+    }
+}
+
+/// Handle of the driver of one SPI device on a [`BusManager`]
+///
+/// Implements [`SpiDevice`]: the chip select pin is asserted for each
+/// transaction only, with the bus locked and the handle's configuration
+/// applied, and the bus is flushed before it is deasserted.
+#[cfg(all(feature = "spi", feature = "gpio"))]
+pub struct SharedSpi<'a, B, CS> {
+    manager: &'a BusManager<B>,
+    cs: CS,
+    config: Option<SpiConfig>,
+}
+
+#[cfg(all(feature = "spi", feature = "gpio"))]
+impl<B, CS: OutputPin> SharedSpi<'_, B, CS> {
+    /// Use `config` for the transactions of this device
+    pub fn with_config(mut self, config: SpiConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Release the chip select pin
+    pub fn into_cs(self) -> CS {
+        self.cs
+    }
+}
+
+#[cfg(all(feature = "spi", feature = "gpio"))]
+impl<B: SpiBus, CS: OutputPin> SpiDevice for SharedSpi<'_, B, CS> {
+    type Error = DeviceError<B::Error, CS::Error>;
+    type Bus = B;
+
+    fn transaction<R, F>(&mut self, f: F) -> Result<R, Self::Error>
+    where
+        F: FnOnce(&mut Self::Bus) -> Result<R, <Self::Bus as SpiBus>::Error>,
+    {
+        let config = self.config;
+        let cs = &mut self.cs;
+        self.manager
+            .lock(|bus| {
+                if let Some(config) = config {
+                    bus.configure(config).map_err(DeviceError::Bus)?;
+                }
+                cs.set_low().map_err(DeviceError::ChipSelect)?;
+
+                let result = f(bus).and_then(|value| bus.flush().map(|()| value));
+                // Deassert even if the transfer failed, the bus must be left idle.
+                let deasserted = cs.set_high();
+
+                let value = result.map_err(DeviceError::Bus)?;
+                deasserted.map_err(DeviceError::ChipSelect)?;
+                Ok(value)
+            })
+            .map_err(|_| DeviceError::Busy)?
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(|bus| bus.write(data))
+    }
+
+    fn read(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        self.transaction(|bus| bus.read(data))
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(|bus| bus.transfer(read, write))
+    }
+}
+
+/// Asserted chip select pin, deasserted on drop so a cancelled transfer
+/// doesn't leave the device selected
+#[cfg(all(feature = "spi", feature = "gpio", feature = "async"))]
+struct Selected<'c, CS: OutputPin> {
+    cs: Option<&'c mut CS>,
+}
+
+#[cfg(all(feature = "spi", feature = "gpio", feature = "async"))]
+impl<CS: OutputPin> Selected<'_, CS> {
+    fn deselect(mut self) -> Result<(), CS::Error> {
+        self.cs.take().map_or(Ok(()), |cs| cs.set_high())
+    }
+}
+
+#[cfg(all(feature = "spi", feature = "gpio", feature = "async"))]
+impl<CS: OutputPin> Drop for Selected<'_, CS> {
+    fn drop(&mut self) {
+        if let Some(cs) = self.cs.take() {
+            let _ = cs.set_high();
+        }
+    }
+}
+
+#[cfg(all(feature = "spi", feature = "gpio", feature = "async"))]
+impl<B: SpiBusAsync, CS: OutputPin> SharedSpi<'_, B, CS> {
+    /// Run `op` on the locked and configured bus with the chip select pin
+    /// asserted, then flush the bus and deassert the pin
+    async fn transaction_async<R>(
+        &mut self,
+        op: impl AsyncFnOnce(&mut B) -> Result<R, B::Error>,
+    ) -> Result<R, DeviceError<B::Error, CS::Error>> {
+        let mut bus = self.manager.lock_async().await;
+        if let Some(config) = self.config {
+            bus.configure(config).map_err(DeviceError::Bus)?;
+        }
+        self.cs.set_low().map_err(DeviceError::ChipSelect)?;
+        let selected = Selected {
+            cs: Some(&mut self.cs),
+        };
+
+        let result = match op(&mut bus).await {
+            Ok(value) => bus.flush().await.map(|()| value),
+            Err(err) => Err(err),
+        };
+        let deasserted = selected.deselect();
+
+        let value = result.map_err(DeviceError::Bus)?;
+        deasserted.map_err(DeviceError::ChipSelect)?;
+        Ok(value)
+    }
+
+    /// Write to the device asynchronously
+    pub async fn write_async(
+        &mut self,
+        data: &[u8],
+    ) -> Result<(), DeviceError<B::Error, CS::Error>> {
+        self.transaction_async(async |bus: &mut B| bus.write(data).await)
+            .await
+    }
+
+    /// Read from the device asynchronously
+    pub async fn read_async(
+        &mut self,
+        data: &mut [u8],
+    ) -> Result<(), DeviceError<B::Error, CS::Error>> {
+        self.transaction_async(async |bus: &mut B| bus.read(data).await)
+            .await
+    }
+
+    /// Transfer to and from the device asynchronously
+    pub async fn transfer_async(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+    ) -> Result<(), DeviceError<B::Error, CS::Error>> {
+        self.transaction_async(async |bus: &mut B| bus.transfer(read, write).await)
+            .await
+    }
+}
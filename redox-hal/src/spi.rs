@@ -164,18 +164,29 @@ pub trait SpiController {
 }
 
 /// Async SPI bus trait
+///
+/// The buffers stay borrowed until the returned future completes, so an
+/// implementation may hand them to a DMA engine and wait for the completion
+/// interrupt. If the future is dropped early, the transfer must be stopped
+/// before the borrow ends.
 #[cfg(feature = "async")]
-pub trait AsyncSpiBus {
+pub trait SpiBusAsync {
     /// Error type
     type Error;
 
-    /// Transfer data asynchronously
+    /// Configure the SPI bus
+    fn configure(&mut self, config: SpiConfig) -> Result<(), Self::Error>;
+
+    /// Transfer data asynchronously (simultaneous read/write)
     async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error>;
 
+    /// Transfer data in place asynchronously
+    async fn transfer_in_place(&mut self, data: &mut [u8]) -> Result<(), Self::Error>;
+
     /// Write data asynchronously
     async fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
 
-    /// Read data asynchronously
+    /// Read data asynchronously (sends zeros)
     async fn read(&mut self, data: &mut [u8]) -> Result<(), Self::Error>;
 
     /// Flush asynchronously
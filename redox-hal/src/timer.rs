@@ -1,9 +1,10 @@
 //! Timer HAL traits
 //!
-//! This module defines hardware timer abstractions.
+//! This module defines hardware timer abstractions, plus a monotonic clock and
+//! alarm interface which the async timers and tickless idle support build on.
 
 use crate::error::Result;
-use crate::time::Duration;
+use crate::time::{Duration, Instant};
 
 /// Timer mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -224,3 +225,535 @@ pub trait TimerController {
     /// Get the number of available timers
     fn timer_count(&self) -> u8;
 }
+
+/// Monotonic clock
+///
+/// Unlike [`Timestamp`], which exposes a raw wrapping counter, a monotonic
+/// clock reports nanosecond based [`Instant`]s that never go backwards.
+pub trait Monotonic {
+    /// Get the current time
+    fn now(&self) -> Instant;
+
+    /// Get the time elapsed since `earlier`
+    fn elapsed_since(&self, earlier: Instant) -> Duration {
+        self.now().duration_since(earlier)
+    }
+}
+
+/// Hardware alarm (compare match) on top of a monotonic clock
+///
+/// This is the hook used for tickless operation: instead of a periodic tick,
+/// the alarm is programmed for the next pending deadline only.
+pub trait Alarm: Monotonic {
+    /// Arm the alarm to fire at `deadline`
+    ///
+    /// Returns `false` without arming the alarm if the deadline has already
+    /// passed, so the caller can handle it immediately.
+    fn set_alarm(&mut self, deadline: Instant) -> bool;
+
+    /// Disarm the alarm
+    fn clear_alarm(&mut self);
+}
+
+/// Tickless scheduling hook
+///
+/// Implemented by BSP runtimes that want to stop the periodic tick while idle.
+pub trait Tickless {
+    /// Called before the CPU is put to sleep with the next deadline anything
+    /// is waiting for, or `None` if nothing is waiting.
+    ///
+    /// Returns `false` if the deadline has already passed, in which case
+    /// nothing will wake the CPU for it and the caller must not sleep.
+    #[must_use]
+    fn schedule_wakeup(&mut self, next: Option<Instant>) -> bool;
+}
+
+impl<A: Alarm> Tickless for A {
+    fn schedule_wakeup(&mut self, next: Option<Instant>) -> bool {
+        match next {
+            Some(deadline) => {
+                let armed = self.set_alarm(deadline);
+                if !armed {
+                    self.clear_alarm();
+                }
+                armed
+            }
+            None => {
+                self.clear_alarm();
+                true
+            }
+        }
+    }
+}
+
+/// Async timer support
+#[cfg(all(feature = "async", feature = "critical-section"))]
+pub mod asynch {
+    use alloc::vec::Vec;
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::task::{Context, Poll, Waker};
+
+    use super::{Alarm, Monotonic};
+    use crate::critical_section::{self, Mutex};
+    use crate::time::{Duration, Instant};
+
+    struct Entry {
+        id: usize,
+        deadline: Instant,
+        waker: Waker,
+    }
+
+    struct QueueState {
+        entries: Vec<Entry>,
+        next_id: usize,
+    }
+
+    /// Queue of tasks waiting for a deadline
+    ///
+    /// The BSP calls [`TimerQueue::wake_expired`] from the alarm interrupt and
+    /// re-arms the alarm with the returned deadline. The queue is locked with
+    /// interrupts disabled, so the interrupt never finds it held by the task
+    /// it interrupted.
+    pub struct TimerQueue {
+        state: Mutex<QueueState>,
+    }
+
+    impl TimerQueue {
+        /// Create an empty timer queue
+        pub const fn new() -> Self {
+            Self {
+                state: Mutex::new(QueueState {
+                    entries: Vec::new(),
+                    next_id: 0,
+                }),
+            }
+        }
+
+        fn with_state<R>(&self, f: impl FnOnce(&mut QueueState) -> R) -> R {
+            critical_section::with(|cs| self.state.lock(cs, f))
+        }
+
+        fn allocate_id(&self) -> usize {
+            self.with_state(|state| {
+                let id = state.next_id;
+                state.next_id = state.next_id.wrapping_add(1);
+                id
+            })
+        }
+
+        fn register(&self, id: usize, deadline: Instant, waker: &Waker) {
+            // Clone and drop wakers with interrupts enabled, they may run
+            // arbitrary code.
+            let waker = waker.clone();
+            let unused = self.with_state(|state| {
+                match state.entries.iter_mut().find(|entry| entry.id == id) {
+                    Some(entry) => {
+                        entry.deadline = deadline;
+                        if entry.waker.will_wake(&waker) {
+                            Some(waker)
+                        } else {
+                            Some(core::mem::replace(&mut entry.waker, waker))
+                        }
+                    }
+                    None => {
+                        state.entries.push(Entry {
+                            id,
+                            deadline,
+                            waker,
+                        });
+                        None
+                    }
+                }
+            });
+            drop(unused);
+        }
+
+        fn remove(&self, id: usize) {
+            let entry = self.with_state(|state| {
+                let index = state.entries.iter().position(|entry| entry.id == id)?;
+                Some(state.entries.swap_remove(index))
+            });
+            drop(entry);
+        }
+
+        /// Get the earliest pending deadline
+        pub fn next_deadline(&self) -> Option<Instant> {
+            self.with_state(|state| state.entries.iter().map(|entry| entry.deadline).min())
+        }
+
+        /// Wake all tasks whose deadline is at or before `now`
+        ///
+        /// Returns the next pending deadline, if any.
+        pub fn wake_expired(&self, now: Instant) -> Option<Instant> {
+            let mut expired = Vec::new();
+            let next = self.with_state(|state| {
+                let mut i = 0;
+                while i < state.entries.len() {
+                    if state.entries[i].deadline.has_passed(now) {
+                        expired.push(state.entries.swap_remove(i).waker);
+                    } else {
+                        i += 1;
+                    }
+                }
+                state.entries.iter().map(|entry| entry.deadline).min()
+            });
+            // Wake outside the lock, wakers may poll and re-register.
+            for waker in expired {
+                waker.wake();
+            }
+            next
+        }
+
+        /// Wake expired tasks and arm `alarm` for the next deadline
+        ///
+        /// Call this from the alarm interrupt handler. Loops when a deadline
+        /// expires while the alarm is being programmed.
+        pub fn service<A: Alarm>(&self, alarm: &mut A) {
+            loop {
+                match self.wake_expired(alarm.now()) {
+                    Some(deadline) => {
+                        if alarm.set_alarm(deadline) {
+                            return;
+                        }
+                    }
+                    None => {
+                        alarm.clear_alarm();
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    impl Default for TimerQueue {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// Async timer driven by a monotonic clock and a timer queue
+    pub struct AsyncTimer<'a, M: Monotonic> {
+        clock: &'a M,
+        queue: &'a TimerQueue,
+    }
+
+    impl<'a, M: Monotonic> AsyncTimer<'a, M> {
+        /// Create a new async timer
+        pub const fn new(clock: &'a M, queue: &'a TimerQueue) -> Self {
+            Self { clock, queue }
+        }
+
+        /// Get the current time
+        pub fn now(&self) -> Instant {
+            self.clock.now()
+        }
+
+        /// One-shot timer completing at `deadline`
+        pub fn sleep_until(&self, deadline: Instant) -> Sleep<'a, M> {
+            Sleep {
+                clock: self.clock,
+                queue: self.queue,
+                deadline,
+                id: None,
+            }
+        }
+
+        /// One-shot timer completing after `duration`
+        pub fn sleep(&self, duration: Duration) -> Sleep<'a, M> {
+            self.sleep_until(self.clock.now() + duration)
+        }
+
+        /// Periodic timer firing every `period`, starting one period from now
+        pub fn ticker(&self, period: Duration) -> Ticker<'a, M> {
+            Ticker {
+                clock: self.clock,
+                queue: self.queue,
+                period,
+                next: self.clock.now() + period,
+            }
+        }
+    }
+
+    impl<M: Monotonic> AsyncDelay for AsyncTimer<'_, M> {
+        async fn delay(&mut self, duration: Duration) {
+            self.sleep(duration).await
+        }
+    }
+
+    /// One-shot timer future
+    pub struct Sleep<'a, M: Monotonic> {
+        clock: &'a M,
+        queue: &'a TimerQueue,
+        deadline: Instant,
+        id: Option<usize>,
+    }
+
+    impl<M: Monotonic> Sleep<'_, M> {
+        /// Get the deadline of this timer
+        pub fn deadline(&self) -> Instant {
+            self.deadline
+        }
+    }
+
+    impl<M: Monotonic> Future for Sleep<'_, M> {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let this = self.get_mut();
+            if this.deadline.has_passed(this.clock.now()) {
+                if let Some(id) = this.id.take() {
+                    this.queue.remove(id);
+                }
+                return Poll::Ready(());
+            }
+
+            let queue = this.queue;
+            let id = *this.id.get_or_insert_with(|| queue.allocate_id());
+            this.queue.register(id, this.deadline, cx.waker());
+            Poll::Pending
+        }
+    }
+
+    impl<M: Monotonic> Drop for Sleep<'_, M> {
+        fn drop(&mut self) {
+            if let Some(id) = self.id.take() {
+                self.queue.remove(id);
+            }
+        }
+    }
+
+    /// Periodic timer
+    ///
+    /// Deadlines are computed from the previous deadline rather than from the
+    /// time the task got to run, so the period does not drift.
+    pub struct Ticker<'a, M: Monotonic> {
+        clock: &'a M,
+        queue: &'a TimerQueue,
+        period: Duration,
+        next: Instant,
+    }
+
+    impl<'a, M: Monotonic> Ticker<'a, M> {
+        /// Wait for the next period to elapse
+        pub fn tick(&mut self) -> Sleep<'a, M> {
+            let deadline = self.next;
+            self.next = self.next + self.period;
+            // Skip missed periods instead of firing in a burst.
+            let now = self.clock.now();
+            if self.next.has_passed(now) {
+                self.next = now + self.period;
+            }
+            Sleep {
+                clock: self.clock,
+                queue: self.queue,
+                deadline,
+                id: None,
+            }
+        }
+
+        /// Restart the period from the current time
+        pub fn reset(&mut self) {
+            self.next = self.clock.now() + self.period;
+        }
+    }
+
+    /// Async delay provider
+    pub trait AsyncDelay {
+        /// Delay for the specified duration
+        async fn delay(&mut self, duration: Duration);
+
+        /// Delay for microseconds
+        async fn delay_us(&mut self, us: u32) {
+            self.delay(Duration::from_micros(us as u64)).await
+        }
+
+        /// Delay for milliseconds
+        async fn delay_ms(&mut self, ms: u32) {
+            self.delay(Duration::from_millis(ms as u64)).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+
+    use super::*;
+
+    /// Alarm on a clock that only moves when told to
+    struct FakeAlarm {
+        now: Cell<u64>,
+        armed: Option<Instant>,
+    }
+
+    impl FakeAlarm {
+        fn new(now: u64) -> Self {
+            Self {
+                now: Cell::new(now),
+                armed: None,
+            }
+        }
+    }
+
+    impl Monotonic for FakeAlarm {
+        fn now(&self) -> Instant {
+            Instant::from_ticks(self.now.get())
+        }
+    }
+
+    impl Alarm for FakeAlarm {
+        fn set_alarm(&mut self, deadline: Instant) -> bool {
+            if deadline.has_passed(self.now()) {
+                return false;
+            }
+            self.armed = Some(deadline);
+            true
+        }
+
+        fn clear_alarm(&mut self) {
+            self.armed = None;
+        }
+    }
+
+    #[test]
+    fn schedule_wakeup_arms_future_deadline() {
+        let mut alarm = FakeAlarm::new(100);
+        assert!(alarm.schedule_wakeup(Some(Instant::from_ticks(150))));
+        assert_eq!(alarm.armed, Some(Instant::from_ticks(150)));
+
+        assert!(alarm.schedule_wakeup(None));
+        assert_eq!(alarm.armed, None);
+    }
+
+    #[test]
+    fn schedule_wakeup_refuses_due_deadline() {
+        let mut alarm = FakeAlarm::new(100);
+        assert!(alarm.schedule_wakeup(Some(Instant::from_ticks(150))));
+
+        // Due now and in the past: the caller must not sleep, and the alarm
+        // set for a later deadline must not stay armed
+        assert!(!alarm.schedule_wakeup(Some(Instant::from_ticks(100))));
+        assert_eq!(alarm.armed, None);
+        assert!(!alarm.schedule_wakeup(Some(Instant::from_ticks(50))));
+        assert_eq!(alarm.armed, None);
+    }
+
+    #[cfg(all(feature = "async", feature = "critical-section"))]
+    mod queue {
+        use alloc::sync::Arc;
+        use core::future::Future;
+        use core::pin::Pin;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+        use core::task::{Context, Poll, Waker};
+        use std::task::Wake;
+
+        use super::super::asynch::{AsyncTimer, TimerQueue};
+        use super::*;
+
+        #[derive(Default)]
+        struct CountingWaker(AtomicUsize);
+
+        impl CountingWaker {
+            fn count(&self) -> usize {
+                self.0.load(Ordering::SeqCst)
+            }
+        }
+
+        impl Wake for CountingWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn poll<F: Future + Unpin>(future: &mut F, waker: &Arc<CountingWaker>) -> Poll<F::Output> {
+            let waker = Waker::from(waker.clone());
+            Pin::new(future).poll(&mut Context::from_waker(&waker))
+        }
+
+        #[test]
+        fn wakes_in_deadline_order() {
+            let clock = FakeAlarm::new(0);
+            let queue = TimerQueue::new();
+            let timer = AsyncTimer::new(&clock, &queue);
+
+            // Registered out of order
+            let mut late = timer.sleep_until(Instant::from_ticks(300));
+            let mut early = timer.sleep_until(Instant::from_ticks(100));
+            let mut middle = timer.sleep_until(Instant::from_ticks(200));
+            let wakers: [Arc<CountingWaker>; 3] = Default::default();
+            assert!(poll(&mut late, &wakers[0]).is_pending());
+            assert!(poll(&mut early, &wakers[1]).is_pending());
+            assert!(poll(&mut middle, &wakers[2]).is_pending());
+            assert_eq!(queue.next_deadline(), Some(Instant::from_ticks(100)));
+
+            assert_eq!(
+                queue.wake_expired(Instant::from_ticks(99)),
+                Some(Instant::from_ticks(100))
+            );
+            assert_eq!(wakers.each_ref().map(|waker| waker.count()), [0, 0, 0]);
+
+            assert_eq!(
+                queue.wake_expired(Instant::from_ticks(100)),
+                Some(Instant::from_ticks(200))
+            );
+            assert_eq!(wakers.each_ref().map(|waker| waker.count()), [0, 1, 0]);
+
+            assert_eq!(
+                queue.wake_expired(Instant::from_ticks(250)),
+                Some(Instant::from_ticks(300))
+            );
+            assert_eq!(wakers.each_ref().map(|waker| waker.count()), [0, 1, 1]);
+
+            clock.now.set(250);
+            assert!(poll(&mut early, &wakers[1]).is_ready());
+            assert!(poll(&mut middle, &wakers[2]).is_ready());
+            assert!(poll(&mut late, &wakers[0]).is_pending());
+            assert_eq!(queue.next_deadline(), Some(Instant::from_ticks(300)));
+
+            drop(late);
+            assert_eq!(queue.next_deadline(), None);
+        }
+
+        #[test]
+        fn service_arms_next_deadline() {
+            let clock = FakeAlarm::new(0);
+            let queue = TimerQueue::new();
+            let timer = AsyncTimer::new(&clock, &queue);
+            let mut first = timer.sleep_until(Instant::from_ticks(100));
+            let mut second = timer.sleep_until(Instant::from_ticks(200));
+            let waker = Arc::new(CountingWaker::default());
+            assert!(poll(&mut first, &waker).is_pending());
+            assert!(poll(&mut second, &waker).is_pending());
+
+            let mut alarm = FakeAlarm::new(100);
+            queue.service(&mut alarm);
+            assert_eq!(waker.count(), 1);
+            assert_eq!(alarm.armed, Some(Instant::from_ticks(200)));
+
+            // The second deadline passed while servicing: nothing is left
+            alarm.now.set(200);
+            queue.service(&mut alarm);
+            assert_eq!(waker.count(), 2);
+            assert_eq!(alarm.armed, None);
+        }
+
+        #[test]
+        fn ticker_does_not_drift() {
+            let clock = FakeAlarm::new(0);
+            let queue = TimerQueue::new();
+            let timer = AsyncTimer::new(&clock, &queue);
+            let mut ticker = timer.ticker(Duration::from_nanos(100));
+
+            assert_eq!(ticker.tick().deadline(), Instant::from_ticks(100));
+            clock.now.set(150);
+            assert_eq!(ticker.tick().deadline(), Instant::from_ticks(200));
+            // Missed periods are skipped
+            clock.now.set(450);
+            assert_eq!(ticker.tick().deadline(), Instant::from_ticks(300));
+            assert_eq!(ticker.tick().deadline(), Instant::from_ticks(550));
+        }
+    }
+}
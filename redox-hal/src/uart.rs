@@ -234,19 +234,48 @@ pub trait UartController {
 }
 
 /// Async UART trait
+///
+/// Like [`crate::spi::SpiBusAsync`], buffers may be used for DMA until the
+/// future completes, and dropping the future must abort the transfer.
 #[cfg(feature = "async")]
-pub trait AsyncUart {
+pub trait UartAsync {
     /// Error type
     type Error;
 
-    /// Write bytes asynchronously
+    /// Configure the UART
+    fn configure(&mut self, config: UartConfig) -> Result<(), Self::Error>;
+
+    /// Write bytes asynchronously, completing once at least one byte is queued
     async fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error>;
 
-    /// Read bytes asynchronously
+    /// Read bytes asynchronously, completing once at least one byte is received
+    /// (or the line goes idle with DMA reception)
     async fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error>;
 
+    /// Read a single byte asynchronously
+    async fn read_byte(&mut self) -> Result<u8, Self::Error> {
+        let mut byte = [0u8];
+        while self.read(&mut byte).await? == 0 {}
+        Ok(byte[0])
+    }
+
+    /// Write all bytes asynchronously
+    async fn write_all(&mut self, mut data: &[u8]) -> Result<(), Self::Error> {
+        while !data.is_empty() {
+            let written = self.write(data).await?;
+            data = &data[written..];
+        }
+        Ok(())
+    }
+
     /// Flush asynchronously
     async fn flush(&mut self) -> Result<(), Self::Error>;
+
+    /// Get the number of bytes available in RX buffer
+    fn rx_available(&self) -> usize;
+
+    /// Get the number of bytes free in TX buffer
+    fn tx_free(&self) -> usize;
 }
 
 /// Console/debug UART (always available at boot)
@@ -10,6 +10,7 @@ pub enum NtStatus {
 
     // Information
     Pending = 0x00000103,
+    ObjectNameExists = 0x40000000,
 
     // Warning
     BufferOverflow = 0x80000005,
@@ -40,6 +41,9 @@ pub enum NtStatus {
     ProcessIsTerminating = 0xC000010A,
     ThreadNotInProcess = 0xC000010B,
 
+    // Time errors
+    TimerResolutionNotSet = 0xC0000245,
+
     // Memory errors
     NoMemory = 0xC0000017,
     ConflictingAddresses = 0xC0000018,
@@ -48,6 +52,17 @@ pub enum NtStatus {
     InvalidSystemService = 0xC000001C,
     CommitmentLimit = 0xC000012D,
 
+    // Section errors
+    NotMappedView = 0xC0000019,
+    InvalidViewSize = 0xC000001F,
+    InvalidFileForSection = 0xC0000020,
+    SectionTooBig = 0xC0000040,
+    InvalidPageProtection = 0xC0000045,
+    SectionProtection = 0xC000004E,
+    SectionNotExtended = 0xC0000087,
+    MappedFileSizeZero = 0xC000011E,
+    MappedAlignment = 0xC0000220,
+
     // File errors
     FileInvalid = 0xC0000098,
     FileLockConflict = 0xC0000054,
@@ -100,6 +115,20 @@ impl NtStatus {
     }
 }
 
+impl From<syscall::Error> for NtStatus {
+    fn from(err: syscall::Error) -> Self {
+        match err.errno {
+            syscall::ENOENT => NtStatus::ObjectNameNotFound,
+            syscall::EACCES | syscall::EPERM => NtStatus::AccessDenied,
+            syscall::EEXIST => NtStatus::ObjectNameCollision,
+            syscall::EBADF => NtStatus::InvalidHandle,
+            syscall::EINVAL => NtStatus::InvalidParameter,
+            syscall::ENOMEM => NtStatus::NoMemory,
+            _ => NtStatus::Unsuccessful,
+        }
+    }
+}
+
 impl From<std::io::Error> for NtStatus {
     fn from(err: std::io::Error) -> Self {
         use std::io::ErrorKind;
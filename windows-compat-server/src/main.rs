@@ -49,6 +49,9 @@
 //! ## Memory
 //! - `NtAllocateVirtualMemory`, `NtFreeVirtualMemory`
 //! - `NtProtectVirtualMemory`, `NtQueryVirtualMemory`
+//!
+//! ## Sections
+//! - `NtCreateSection`, `NtOpenSection`, `NtExtendSection`
 //! - `NtMapViewOfSection`, `NtUnmapViewOfSection`
 //!
 //! ## Registry (via file mapping)
@@ -62,7 +65,9 @@ mod errno;
 mod ntdll;
 mod pe_loader;
 mod registry;
+mod section;
 mod syscall_table;
+mod time;
 mod translator;
 
 pub use errno::NtStatus;
@@ -81,6 +86,8 @@ pub struct WacConfig {
     pub debug: bool,
     /// Registry hive path
     pub registry_path: String,
+    /// TZif file describing the local time zone
+    pub timezone_path: String,
 }
 
 impl Default for WacConfig {
@@ -90,12 +97,13 @@ impl Default for WacConfig {
             max_processes: 256,
             debug: false,
             registry_path: "/windows/registry".to_string(),
+            timezone_path: "/etc/localtime".to_string(),
         }
     }
 }
 
 /// Handle type for Windows resources
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Handle(pub u32);
 
 impl Handle {
@@ -183,9 +191,18 @@ pub struct WacServer {
 impl WacServer {
     /// Create a new WAC server
     pub fn new(config: WacConfig) -> Self {
+        let time_zone =
+            time::TimeZoneInformation::load(&config.timezone_path).unwrap_or_else(|e| {
+                eprintln!(
+                    "WAC: Failed to load time zone {}: {}, using UTC",
+                    config.timezone_path, e
+                );
+                time::TimeZoneInformation::utc()
+            });
+
         Self {
             loader: Arc::new(PeLoader::new(config.windows_root.clone())),
-            translator: Arc::new(NtSyscallTranslator::new()),
+            translator: Arc::new(NtSyscallTranslator::new().with_time_zone(time_zone)),
             config,
             processes: RwLock::new(BTreeMap::new()),
             next_pid: AtomicU32::new(1),
@@ -209,6 +226,8 @@ impl WacServer {
 
     /// Remove a process
     pub fn remove_process(&self, pid: u32) -> Option<Arc<WinProcess>> {
+        self.translator.release_timer_resolution(pid);
+        self.translator.release_sections(pid);
         self.processes.write().unwrap().remove(&pid)
     }
 
@@ -150,6 +150,14 @@ pub mod mem_protect {
     pub const PAGE_WRITECOMBINE: u32 = 0x400;
 }
 
+/// Section allocation attributes
+pub mod sec_alloc {
+    pub const SEC_FILE: u32 = 0x0080_0000;
+    pub const SEC_IMAGE: u32 = 0x0100_0000;
+    pub const SEC_RESERVE: u32 = 0x0400_0000;
+    pub const SEC_COMMIT: u32 = 0x0800_0000;
+}
+
 /// Memory allocation types
 pub mod mem_alloc {
     pub const MEM_COMMIT: u32 = 0x1000;
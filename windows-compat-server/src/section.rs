@@ -0,0 +1,419 @@
+//! Section Objects
+//!
+//! A section is memory that can be mapped into address spaces as views
+//! (`NtCreateSection`, `NtMapViewOfSection`). Pagefile-backed sections are
+//! anonymous `shm:` objects, file-backed sections map the file itself, and
+//! views are `fmap`ped from either:
+//!
+//! - Shared views see each other's writes, and write through to the file of
+//!   file-backed sections.
+//! - Copy-on-write views (`PAGE_WRITECOPY`) get private copies of the pages
+//!   they write to.
+//!
+//! Named sections are visible to every process, which is how processes share
+//! memory. A section lives as long as a handle or a view refers to it.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+
+use syscall::{Map, MapFlags};
+
+use crate::Handle;
+use crate::errno::NtStatus;
+use crate::ntdll::mem_protect::*;
+
+/// Page size of views
+pub const PAGE_SIZE: usize = 4096;
+
+/// Alignment of view addresses and section offsets, like on Windows
+pub const ALLOCATION_GRANULARITY: usize = 0x10000;
+
+/// Access allowed by a page protection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    /// Writes go to private copies of the pages
+    pub copy_on_write: bool,
+}
+
+impl Protection {
+    /// Decode a `PAGE_*` value
+    ///
+    /// `PAGE_NOACCESS` and `PAGE_GUARD` can't be used for sections, the
+    /// caching modifiers are ignored.
+    pub fn from_page(protect: u32) -> Result<Self, NtStatus> {
+        if protect & !(0xFF | PAGE_NOCACHE | PAGE_WRITECOMBINE) != 0 {
+            return Err(NtStatus::InvalidPageProtection);
+        }
+        let (read, write, execute, copy_on_write) = match protect & 0xFF {
+            PAGE_READONLY => (true, false, false, false),
+            PAGE_READWRITE => (true, true, false, false),
+            PAGE_WRITECOPY => (true, true, false, true),
+            PAGE_EXECUTE => (false, false, true, false),
+            PAGE_EXECUTE_READ => (true, false, true, false),
+            PAGE_EXECUTE_READWRITE => (true, true, true, false),
+            PAGE_EXECUTE_WRITECOPY => (true, true, true, true),
+            _ => return Err(NtStatus::InvalidPageProtection),
+        };
+        Ok(Self {
+            read,
+            write,
+            execute,
+            copy_on_write,
+        })
+    }
+
+    /// Whether a section with this protection can be mapped with `view`
+    ///
+    /// Copy-on-write views only need read access, shared writable views need
+    /// a writable section.
+    pub fn allows(&self, view: Protection) -> bool {
+        let write = if view.copy_on_write {
+            self.read
+        } else {
+            !view.write || (self.write && !self.copy_on_write)
+        };
+        (self.read || !view.read) && (self.execute || !view.execute) && write
+    }
+
+    fn map_flags(&self) -> MapFlags {
+        let mut flags = if self.copy_on_write {
+            MapFlags::MAP_PRIVATE
+        } else {
+            MapFlags::MAP_SHARED
+        };
+        if self.read {
+            flags |= MapFlags::PROT_READ;
+        }
+        if self.write {
+            flags |= MapFlags::PROT_WRITE;
+        }
+        if self.execute {
+            flags |= MapFlags::PROT_EXEC;
+        }
+        flags
+    }
+}
+
+/// Section object
+pub struct Section {
+    /// Name in the object namespace
+    name: Option<String>,
+    /// `shm:` object or duplicated file descriptor
+    fd: usize,
+    file_backed: bool,
+    /// Maximum protection of views
+    protection: Protection,
+    size: RwLock<u64>,
+}
+
+impl Section {
+    /// Name in the object namespace
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Descriptor views are mapped from
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+
+    /// Whether the section maps a file rather than the pagefile
+    pub fn is_file_backed(&self) -> bool {
+        self.file_backed
+    }
+
+    /// Maximum protection of views
+    pub fn protection(&self) -> Protection {
+        self.protection
+    }
+
+    /// Size in bytes
+    pub fn size(&self) -> u64 {
+        *self.size.read().unwrap()
+    }
+}
+
+impl Drop for Section {
+    fn drop(&mut self) {
+        let _ = syscall::close(self.fd);
+    }
+}
+
+/// Mapped view of a section
+pub struct View {
+    /// Keeps the section alive while it is mapped
+    pub section: Arc<Section>,
+    /// Base address
+    pub base: usize,
+    /// Size in bytes, a multiple of [`PAGE_SIZE`]
+    pub size: usize,
+    /// Offset into the section
+    pub offset: u64,
+    pub protection: Protection,
+}
+
+/// Sections and views of all processes
+pub struct SectionManager {
+    next_id: AtomicU64,
+    /// Named sections by lowercase name
+    named: RwLock<BTreeMap<String, Weak<Section>>>,
+    /// Section handles by process
+    handles: RwLock<BTreeMap<(u32, Handle), Arc<Section>>>,
+    /// Views by process and base address
+    views: RwLock<BTreeMap<(u32, usize), View>>,
+}
+
+impl SectionManager {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            named: RwLock::new(BTreeMap::new()),
+            handles: RwLock::new(BTreeMap::new()),
+            views: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Create a pagefile-backed section of `size` bytes
+    ///
+    /// Returns the existing section instead if `name` is taken and
+    /// `open_existing` is set.
+    pub fn create_pagefile(
+        &self,
+        name: Option<&str>,
+        size: u64,
+        protection: Protection,
+        open_existing: bool,
+    ) -> Result<(Arc<Section>, bool), NtStatus> {
+        if size == 0 {
+            return Err(NtStatus::InvalidParameter);
+        }
+        self.create(name, protection, open_existing, || {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let path = format!("/scheme/shm/wac-section-{}", id);
+            let fd =
+                syscall::open(path, syscall::O_CREAT | syscall::O_RDWR).map_err(NtStatus::from)?;
+            if let Err(err) = syscall::ftruncate(fd, size as usize) {
+                let _ = syscall::close(fd);
+                return Err(NtStatus::from(err));
+            }
+            Ok((fd, false, size))
+        })
+    }
+
+    /// Create a section mapping the file open as `file_fd`
+    ///
+    /// `size` defaults to the file size. A larger size extends the file,
+    /// which needs a writable section.
+    pub fn create_file(
+        &self,
+        name: Option<&str>,
+        file_fd: usize,
+        size: Option<u64>,
+        protection: Protection,
+        open_existing: bool,
+    ) -> Result<(Arc<Section>, bool), NtStatus> {
+        let mut stat = syscall::Stat::default();
+        syscall::fstat(file_fd, &mut stat).map_err(|_| NtStatus::InvalidFileForSection)?;
+        let file_size = stat.st_size;
+        let size = size.unwrap_or(file_size);
+        if size == 0 {
+            return Err(NtStatus::MappedFileSizeZero);
+        }
+        if size > file_size && (!protection.write || protection.copy_on_write) {
+            return Err(NtStatus::SectionTooBig);
+        }
+
+        self.create(name, protection, open_existing, || {
+            // The section outlives the file handle
+            let fd = syscall::dup(file_fd, b"").map_err(NtStatus::from)?;
+            if size > file_size
+                && let Err(err) = syscall::ftruncate(fd, size as usize)
+            {
+                let _ = syscall::close(fd);
+                return Err(NtStatus::from(err));
+            }
+            Ok((fd, true, size))
+        })
+    }
+
+    fn create(
+        &self,
+        name: Option<&str>,
+        protection: Protection,
+        open_existing: bool,
+        backing: impl FnOnce() -> Result<(usize, bool, u64), NtStatus>,
+    ) -> Result<(Arc<Section>, bool), NtStatus> {
+        let mut named = self.named.write().unwrap();
+        let key = name.map(str::to_lowercase);
+        if let Some(existing) = key.as_ref().and_then(|key| named.get(key)?.upgrade()) {
+            return match open_existing {
+                true => Ok((existing, true)),
+                false => Err(NtStatus::ObjectNameCollision),
+            };
+        }
+
+        let (fd, file_backed, size) = backing()?;
+        let section = Arc::new(Section {
+            name: name.map(str::to_string),
+            fd,
+            file_backed,
+            protection,
+            size: RwLock::new(size),
+        });
+        if let Some(key) = key {
+            named.retain(|_, section| section.strong_count() > 0);
+            named.insert(key, Arc::downgrade(&section));
+        }
+        Ok((section, false))
+    }
+
+    /// Look up a named section
+    pub fn open(&self, name: &str) -> Result<Arc<Section>, NtStatus> {
+        self.named
+            .read()
+            .unwrap()
+            .get(&name.to_lowercase())
+            .and_then(Weak::upgrade)
+            .ok_or(NtStatus::ObjectNameNotFound)
+    }
+
+    /// Record `handle` of process `pid` as referring to `section`
+    pub fn insert_handle(&self, pid: u32, handle: Handle, section: Arc<Section>) {
+        self.handles.write().unwrap().insert((pid, handle), section);
+    }
+
+    /// Section behind a handle of process `pid`
+    pub fn get(&self, pid: u32, handle: Handle) -> Option<Arc<Section>> {
+        self.handles.read().unwrap().get(&(pid, handle)).cloned()
+    }
+
+    /// Drop a section handle, returns whether it was one
+    pub fn close(&self, pid: u32, handle: Handle) -> bool {
+        self.handles
+            .write()
+            .unwrap()
+            .remove(&(pid, handle))
+            .is_some()
+    }
+
+    /// Grow a section to `size` bytes, returns the new size
+    ///
+    /// Views mapped before keep their size. Shrinking is ignored, like on
+    /// Windows.
+    pub fn extend(&self, section: &Section, size: u64) -> Result<u64, NtStatus> {
+        let mut current = section.size.write().unwrap();
+        if size <= *current {
+            return Ok(*current);
+        }
+        if !section.protection.write || section.protection.copy_on_write {
+            return Err(NtStatus::SectionNotExtended);
+        }
+        syscall::ftruncate(section.fd, size as usize).map_err(NtStatus::from)?;
+        *current = size;
+        Ok(size)
+    }
+
+    /// Map `size` bytes of `section` at `offset` into process `pid`
+    ///
+    /// `base` 0 lets the kernel pick the address, `size` 0 maps up to the end
+    /// of the section. Returns the base address and the size rounded up to
+    /// whole pages.
+    pub fn map_view(
+        &self,
+        pid: u32,
+        section: &Arc<Section>,
+        base: usize,
+        offset: u64,
+        size: usize,
+        protection: Protection,
+    ) -> Result<(usize, usize), NtStatus> {
+        if !base.is_multiple_of(ALLOCATION_GRANULARITY)
+            || !offset.is_multiple_of(ALLOCATION_GRANULARITY as u64)
+        {
+            return Err(NtStatus::MappedAlignment);
+        }
+        let section_size = section.size();
+        if offset >= section_size {
+            return Err(NtStatus::InvalidViewSize);
+        }
+        let size = match size {
+            0 => usize::try_from(section_size - offset).map_err(|_| NtStatus::InvalidViewSize)?,
+            size if size as u64 > section_size - offset => return Err(NtStatus::InvalidViewSize),
+            size => size,
+        };
+        if !section.protection.allows(protection) {
+            return Err(NtStatus::SectionProtection);
+        }
+
+        let size = size.next_multiple_of(PAGE_SIZE);
+        let mut flags = protection.map_flags();
+        if base != 0 {
+            flags |= MapFlags::MAP_FIXED_NOREPLACE;
+        }
+        let map = Map {
+            offset: offset as usize,
+            size,
+            flags,
+            address: base,
+        };
+        let base = unsafe { syscall::fmap(section.fd, &map) }.map_err(|err| match err.errno {
+            syscall::EEXIST => NtStatus::ConflictingAddresses,
+            _ => NtStatus::from(err),
+        })?;
+
+        self.views.write().unwrap().insert(
+            (pid, base),
+            View {
+                section: section.clone(),
+                base,
+                size,
+                offset,
+                protection,
+            },
+        );
+        Ok((base, size))
+    }
+
+    /// Unmap the view of process `pid` containing `address`
+    pub fn unmap_view(&self, pid: u32, address: usize) -> Result<(), NtStatus> {
+        let mut views = self.views.write().unwrap();
+        let key = views
+            .range((pid, 0)..=(pid, address))
+            .next_back()
+            .filter(|(_, view)| address < view.base + view.size)
+            .map(|(key, _)| *key)
+            .ok_or(NtStatus::NotMappedView)?;
+        let view = views.remove(&key).unwrap();
+        unsafe { syscall::funmap(view.base, view.size) }.map_err(NtStatus::from)?;
+        Ok(())
+    }
+
+    /// Unmap the views and drop the handles of an exited process
+    pub fn release(&self, pid: u32) {
+        self.handles
+            .write()
+            .unwrap()
+            .retain(|(owner, _), _| *owner != pid);
+
+        let mut views = self.views.write().unwrap();
+        let keys: Vec<_> = views
+            .range((pid, 0)..=(pid, usize::MAX))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            if let Some(view) = views.remove(&key) {
+                let _ = unsafe { syscall::funmap(view.base, view.size) };
+            }
+        }
+    }
+}
+
+impl Default for SectionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
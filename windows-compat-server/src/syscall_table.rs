@@ -104,6 +104,8 @@ pub enum NtSyscall {
     NtSetSystemTime = 0x01A6,
     NtQueryPerformanceCounter = 0x0031,
     NtDelayExecution = 0x0034,
+    NtQueryTimerResolution = 0x0169,
+    NtSetTimerResolution = 0x01A8,
 
     // System Information
     NtQuerySystemInformation = 0x0036,
@@ -138,6 +140,11 @@ impl NtSyscall {
             0x0018 => Self::NtAllocateVirtualMemory,
             0x001E => Self::NtFreeVirtualMemory,
             0x0050 => Self::NtProtectVirtualMemory,
+            0x004A => Self::NtCreateSection,
+            0x0037 => Self::NtOpenSection,
+            0x0028 => Self::NtMapViewOfSection,
+            0x002A => Self::NtUnmapViewOfSection,
+            0x0114 => Self::NtExtendSection,
             0x0055 => Self::NtCreateFile,
             0x0033 => Self::NtOpenFile,
             0x000F => Self::NtClose,
@@ -155,6 +162,10 @@ impl NtSyscall {
             0x0004 => Self::NtWaitForSingleObject,
             0x005B => Self::NtWaitForMultipleObjects,
             0x0034 => Self::NtDelayExecution,
+            0x005A => Self::NtQuerySystemTime,
+            0x0031 => Self::NtQueryPerformanceCounter,
+            0x0169 => Self::NtQueryTimerResolution,
+            0x01A8 => Self::NtSetTimerResolution,
             0x0036 => Self::NtQuerySystemInformation,
             _ => Self::Invalid,
         }
@@ -177,6 +188,11 @@ impl NtSyscall {
             Self::NtFreeVirtualMemory => "NtFreeVirtualMemory",
             Self::NtProtectVirtualMemory => "NtProtectVirtualMemory",
             Self::NtQueryVirtualMemory => "NtQueryVirtualMemory",
+            Self::NtCreateSection => "NtCreateSection",
+            Self::NtOpenSection => "NtOpenSection",
+            Self::NtMapViewOfSection => "NtMapViewOfSection",
+            Self::NtUnmapViewOfSection => "NtUnmapViewOfSection",
+            Self::NtExtendSection => "NtExtendSection",
             Self::NtCreateFile => "NtCreateFile",
             Self::NtOpenFile => "NtOpenFile",
             Self::NtClose => "NtClose",
@@ -194,6 +210,10 @@ impl NtSyscall {
             Self::NtWaitForSingleObject => "NtWaitForSingleObject",
             Self::NtWaitForMultipleObjects => "NtWaitForMultipleObjects",
             Self::NtDelayExecution => "NtDelayExecution",
+            Self::NtQuerySystemTime => "NtQuerySystemTime",
+            Self::NtQueryPerformanceCounter => "NtQueryPerformanceCounter",
+            Self::NtQueryTimerResolution => "NtQueryTimerResolution",
+            Self::NtSetTimerResolution => "NtSetTimerResolution",
             Self::NtQuerySystemInformation => "NtQuerySystemInformation",
             _ => "Unknown",
         }
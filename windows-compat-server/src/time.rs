@@ -0,0 +1,455 @@
+//! Time APIs
+//!
+//! NT keeps time in 100ns intervals: system time counts them from
+//! 1601-01-01 UTC, and the performance counter runs at a fixed 10 MHz from an
+//! arbitrary origin, like on Windows 10 and later. Games read both every frame,
+//! so they are served from `Instant`/`SystemTime` without touching the disk.
+//!
+//! The time zone comes from a TZif file (`/etc/localtime` by default). Its
+//! POSIX TZ footer (`CET-1CEST,M3.5.0,M10.5.0/3`) maps directly onto the
+//! rules of a `TIME_ZONE_INFORMATION`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::sync::RwLock;
+use std::time::{Instant, UNIX_EPOCH};
+
+use crate::errno::NtStatus;
+
+/// 100ns intervals per second
+pub const TICKS_PER_SECOND: i64 = 10_000_000;
+
+/// Performance counter frequency
+pub const PERFORMANCE_FREQUENCY: i64 = TICKS_PER_SECOND;
+
+/// 100ns intervals between 1601-01-01 and 1970-01-01
+const UNIX_EPOCH_TICKS: i64 = 116_444_736_000_000_000;
+
+/// Current system time in 100ns intervals since 1601-01-01 UTC
+pub fn system_time() -> i64 {
+    match std::time::SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => UNIX_EPOCH_TICKS + (since_epoch.as_nanos() / 100) as i64,
+        Err(before_epoch) => UNIX_EPOCH_TICKS - (before_epoch.duration().as_nanos() / 100) as i64,
+    }
+}
+
+/// High-resolution monotonic counter backing `QueryPerformanceCounter`
+pub struct PerformanceCounter {
+    origin: Instant,
+}
+
+impl PerformanceCounter {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+
+    /// Counter value in ticks of [`PERFORMANCE_FREQUENCY`]
+    pub fn now(&self) -> i64 {
+        (self.origin.elapsed().as_nanos() / 100) as i64
+    }
+}
+
+impl Default for PerformanceCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Coarsest timer resolution in 100ns units (15.625ms, the default tick)
+pub const MIN_TIMER_RESOLUTION: u32 = 156_250;
+/// Finest timer resolution in 100ns units (0.5ms)
+pub const MAX_TIMER_RESOLUTION: u32 = 5_000;
+
+/// Timer resolution requests (`NtSetTimerResolution`, `timeBeginPeriod`)
+///
+/// Like on Windows, the system runs at the finest resolution any process
+/// asked for, and a process' request goes away when it exits.
+pub struct TimerResolution {
+    requests: RwLock<BTreeMap<u32, u32>>,
+}
+
+impl TimerResolution {
+    pub fn new() -> Self {
+        Self {
+            requests: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Current resolution in 100ns units
+    pub fn current(&self) -> u32 {
+        self.requests
+            .read()
+            .unwrap()
+            .values()
+            .copied()
+            .min()
+            .unwrap_or(MIN_TIMER_RESOLUTION)
+    }
+
+    /// Request (`set`) or release a resolution for process `pid`, returning the
+    /// resolution now in effect
+    pub fn set(&self, pid: u32, desired: u32, set: bool) -> Result<u32, NtStatus> {
+        {
+            let mut requests = self.requests.write().unwrap();
+            if set {
+                let desired = desired.clamp(MAX_TIMER_RESOLUTION, MIN_TIMER_RESOLUTION);
+                requests.insert(pid, desired);
+            } else if requests.remove(&pid).is_none() {
+                return Err(NtStatus::TimerResolutionNotSet);
+            }
+        }
+        Ok(self.current())
+    }
+
+    /// Drop the request of an exited process
+    pub fn release(&self, pid: u32) {
+        self.requests.write().unwrap().remove(&pid);
+    }
+}
+
+impl Default for TimerResolution {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `TIME_ZONE_ID_*` values returned by `GetTimeZoneInformation`
+pub const TIME_ZONE_ID_UNKNOWN: u32 = 0;
+pub const TIME_ZONE_ID_STANDARD: u32 = 1;
+pub const TIME_ZONE_ID_DAYLIGHT: u32 = 2;
+
+/// Windows SYSTEMTIME structure
+///
+/// In time zone rules `year` is 0, `day` is the week of the month (5 meaning
+/// the last one) and `day_of_week` the weekday, 0 being Sunday.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemTime {
+    pub year: u16,
+    pub month: u16,
+    pub day_of_week: u16,
+    pub day: u16,
+    pub hour: u16,
+    pub minute: u16,
+    pub second: u16,
+    pub milliseconds: u16,
+}
+
+/// Windows TIME_ZONE_INFORMATION structure
+///
+/// Biases are in minutes, with UTC = local time + bias.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TimeZoneInformation {
+    pub bias: i32,
+    pub standard_name: [u16; 32],
+    pub standard_date: SystemTime,
+    pub standard_bias: i32,
+    pub daylight_name: [u16; 32],
+    pub daylight_date: SystemTime,
+    pub daylight_bias: i32,
+}
+
+impl TimeZoneInformation {
+    pub fn utc() -> Self {
+        Self {
+            bias: 0,
+            standard_name: utf16_name("UTC"),
+            standard_date: SystemTime::default(),
+            standard_bias: 0,
+            daylight_name: utf16_name("UTC"),
+            daylight_date: SystemTime::default(),
+            daylight_bias: 0,
+        }
+    }
+
+    /// Load a TZif time zone file
+    pub fn load(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        Self::from_tzif(&data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unsupported time zone file"))
+    }
+
+    /// Parse a TZif file, preferring the POSIX TZ rule of version 2+ files
+    pub fn from_tzif(data: &[u8]) -> Option<Self> {
+        let v1 = TzifHeader::parse(data)?;
+        if v1.version >= b'2' {
+            let v2_start = TzifHeader::LEN + v1.data_len(4);
+            let v2 = TzifHeader::parse(data.get(v2_start..)?)?;
+            let footer = data.get(v2_start + TzifHeader::LEN + v2.data_len(8)..)?;
+            let footer = std::str::from_utf8(footer).ok()?.trim_matches('\n');
+            if let Some(tz) = Self::from_posix(footer) {
+                return Some(tz);
+            }
+        }
+
+        // Version 1 files only have transitions, so use the type in effect
+        // after the last one.
+        let body = data.get(TzifHeader::LEN..)?;
+        let timecnt = v1.timecnt as usize;
+        let ty = match timecnt {
+            0 => 0,
+            _ => usize::from(*body.get(timecnt * 4 + timecnt - 1)?),
+        };
+        let ttinfo = body.get(timecnt * 5 + ty * 6..timecnt * 5 + ty * 6 + 6)?;
+        let utoff = i32::from_be_bytes(ttinfo[0..4].try_into().unwrap());
+        let chars = body.get(timecnt * 5 + v1.typecnt as usize * 6..)?;
+        let abbr = chars.get(usize::from(ttinfo[5])..)?;
+        let abbr = abbr.split(|&b| b == 0).next()?;
+        let name = std::str::from_utf8(abbr).ok()?;
+
+        let mut tz = Self::utc();
+        tz.bias = -utoff / 60;
+        tz.standard_name = utf16_name(name);
+        tz.daylight_name = utf16_name(name);
+        Some(tz)
+    }
+
+    /// Parse a POSIX TZ string like `EST5EDT,M3.2.0,M11.1.0`
+    ///
+    /// Only `Mm.w.d` rules can be expressed in a `TIME_ZONE_INFORMATION`;
+    /// zones using Julian day rules get their standard time only.
+    pub fn from_posix(tz: &str) -> Option<Self> {
+        let mut rest = tz;
+        let standard_name = posix_name(&mut rest)?;
+        let bias = posix_offset(&mut rest)?;
+
+        let mut info = Self::utc();
+        info.bias = bias;
+        info.standard_name = utf16_name(standard_name);
+        info.daylight_name = utf16_name(standard_name);
+        if rest.is_empty() {
+            return Some(info);
+        }
+
+        let daylight_name = posix_name(&mut rest)?;
+        let daylight_offset = if rest.starts_with(',') || rest.is_empty() {
+            bias - 60
+        } else {
+            posix_offset(&mut rest)?
+        };
+        info.daylight_name = utf16_name(daylight_name);
+
+        let rules = rest.strip_prefix(',').unwrap_or("M3.2.0,M11.1.0");
+        let (start, end) = rules.split_once(',')?;
+        match (posix_rule(start), posix_rule(end)) {
+            (Some(start), Some(end)) => {
+                info.daylight_date = start;
+                info.standard_date = end;
+                info.daylight_bias = daylight_offset - bias;
+            }
+            _ => eprintln!("WAC: unsupported time zone rule in {tz}, ignoring daylight time"),
+        }
+        Some(info)
+    }
+
+    /// Which `TIME_ZONE_ID_*` applies at `unix_time` (seconds)
+    pub fn id_at(&self, unix_time: i64) -> u32 {
+        if self.daylight_date.month == 0 {
+            return TIME_ZONE_ID_UNKNOWN;
+        }
+
+        let local_standard = unix_time - i64::from(self.bias) * 60;
+        let local_daylight = local_standard - i64::from(self.daylight_bias) * 60;
+        let year = civil_from_days(local_standard.div_euclid(86_400)).0;
+
+        // Transitions are given in the local time in effect before them.
+        let start = transition(year, &self.daylight_date);
+        let end = transition(year, &self.standard_date);
+        let daylight = if start < end {
+            local_standard >= start && local_daylight < end
+        } else {
+            // Southern hemisphere: daylight time spans the new year.
+            local_standard >= start || local_daylight < end
+        };
+        if daylight {
+            TIME_ZONE_ID_DAYLIGHT
+        } else {
+            TIME_ZONE_ID_STANDARD
+        }
+    }
+
+    /// Which `TIME_ZONE_ID_*` applies now
+    pub fn current_id(&self) -> u32 {
+        self.id_at((system_time() - UNIX_EPOCH_TICKS).div_euclid(TICKS_PER_SECOND))
+    }
+
+    /// The structure as laid out in memory for `NtQuerySystemInformation`
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                (self as *const Self).cast::<u8>(),
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+struct TzifHeader {
+    version: u8,
+    isutcnt: u32,
+    isstdcnt: u32,
+    leapcnt: u32,
+    timecnt: u32,
+    typecnt: u32,
+    charcnt: u32,
+}
+
+impl TzifHeader {
+    const LEN: usize = 44;
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::LEN || &data[0..4] != b"TZif" {
+            return None;
+        }
+        let count = |i: usize| {
+            let offset = 20 + i * 4;
+            u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+        };
+        Some(Self {
+            version: data[4],
+            isutcnt: count(0),
+            isstdcnt: count(1),
+            leapcnt: count(2),
+            timecnt: count(3),
+            typecnt: count(4),
+            charcnt: count(5),
+        })
+    }
+
+    /// Length of the data block following the header, with `time_size` byte times
+    fn data_len(&self, time_size: usize) -> usize {
+        self.timecnt as usize * (time_size + 1)
+            + self.typecnt as usize * 6
+            + self.charcnt as usize
+            + self.leapcnt as usize * (time_size + 4)
+            + self.isstdcnt as usize
+            + self.isutcnt as usize
+    }
+}
+
+fn posix_name<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let (name, tail) = if let Some(quoted) = rest.strip_prefix('<') {
+        let (name, tail) = quoted.split_once('>')?;
+        (name, tail)
+    } else {
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        rest.split_at(end)
+    };
+    if name.is_empty() {
+        return None;
+    }
+    *rest = tail;
+    Some(name)
+}
+
+/// Parse `[+-]hh[:mm[:ss]]`, returning minutes
+fn posix_offset(rest: &mut &str) -> Option<i32> {
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '-' | ':')))
+        .unwrap_or(rest.len());
+    let (offset, tail) = rest.split_at(end);
+    *rest = tail;
+    posix_time(offset).map(|seconds| seconds / 60)
+}
+
+/// Parse `[+-]hh[:mm[:ss]]` into seconds
+fn posix_time(time: &str) -> Option<i32> {
+    let (sign, time) = match time.strip_prefix('-') {
+        Some(time) => (-1, time),
+        None => (1, time.strip_prefix('+').unwrap_or(time)),
+    };
+    let mut seconds = 0;
+    for (i, part) in time.split(':').enumerate() {
+        if i > 2 {
+            return None;
+        }
+        seconds += part.parse::<i32>().ok()? * [3600, 60, 1][i];
+    }
+    Some(sign * seconds)
+}
+
+/// Parse an `Mm.w.d[/time]` rule
+fn posix_rule(rule: &str) -> Option<SystemTime> {
+    let (date, time) = rule.split_once('/').unwrap_or((rule, "2"));
+    let mut parts = date.strip_prefix('M')?.split('.');
+    let month = parts.next()?.parse().ok()?;
+    let week = parts.next()?.parse().ok()?;
+    let day_of_week = parts.next()?.parse().ok()?;
+    // Times outside of 0-24h (allowed by RFC 8536) can't be expressed.
+    let seconds = posix_time(time)?.clamp(0, 86_399);
+
+    Some(SystemTime {
+        year: 0,
+        month,
+        day_of_week,
+        day: week,
+        hour: (seconds / 3600) as u16,
+        minute: (seconds / 60 % 60) as u16,
+        second: (seconds % 60) as u16,
+        milliseconds: 0,
+    })
+}
+
+/// Seconds since the Unix epoch of a yearly transition, in local time
+fn transition(year: i64, rule: &SystemTime) -> i64 {
+    let month = i64::from(rule.month);
+    let first = days_from_civil(year, month, 1);
+    // 1970-01-01 was a Thursday.
+    let first_weekday = (first + 4).rem_euclid(7);
+    let mut day = first + (i64::from(rule.day_of_week) - first_weekday).rem_euclid(7);
+    day += 7 * (i64::from(rule.day) - 1);
+    // Week 5 means the last occurrence, which may be the fourth.
+    let next_month = if month == 12 {
+        days_from_civil(year + 1, 1, 1)
+    } else {
+        days_from_civil(year, month + 1, 1)
+    };
+    while day >= next_month {
+        day -= 7;
+    }
+
+    day * 86_400
+        + i64::from(rule.hour) * 3600
+        + i64::from(rule.minute) * 60
+        + i64::from(rule.second)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Proleptic Gregorian (year, month, day) of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn utf16_name(name: &str) -> [u16; 32] {
+    let mut buf = [0; 32];
+    // Leave room for the terminating NUL.
+    for (dst, src) in buf.iter_mut().take(31).zip(name.encode_utf16()) {
+        *dst = src;
+    }
+    buf
+}
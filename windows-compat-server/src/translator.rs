@@ -3,14 +3,35 @@
 //! Translates Windows NT syscalls to their Redox equivalents.
 
 use crate::errno::NtStatus;
+use crate::ntdll::obj_flags::OBJ_OPENIF;
+use crate::ntdll::sec_alloc::SEC_IMAGE;
+use crate::ntdll::{ObjectAttributes, UnicodeString};
+use crate::section::{ALLOCATION_GRANULARITY, Protection, SectionManager};
 use crate::syscall_table::NtSyscall;
+use crate::time::{self, PerformanceCounter, TimeZoneInformation, TimerResolution};
 use crate::{Handle, WinProcess};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// SystemCurrentTimeZoneInformation
+const SYSTEM_CURRENT_TIME_ZONE_INFORMATION: usize = 44;
+
+/// Pseudo-handle of the calling process (`NtCurrentProcess()`)
+const NT_CURRENT_PROCESS: usize = usize::MAX;
 
 /// Syscall translator state
 pub struct NtSyscallTranslator {
     /// Debug mode
     debug: bool,
+    /// Source of QueryPerformanceCounter
+    counter: PerformanceCounter,
+    /// Timer resolution requested by processes
+    timer_resolution: TimerResolution,
+    /// Local time zone
+    time_zone: TimeZoneInformation,
+    /// Section objects and their views
+    sections: SectionManager,
 }
 
 /// Translated syscall result
@@ -26,11 +47,44 @@ pub enum TranslateResult {
 
 impl NtSyscallTranslator {
     pub fn new() -> Self {
-        Self { debug: false }
+        Self::with_debug(false)
     }
 
     pub fn with_debug(debug: bool) -> Self {
-        Self { debug }
+        Self {
+            debug,
+            counter: PerformanceCounter::new(),
+            timer_resolution: TimerResolution::new(),
+            time_zone: TimeZoneInformation::utc(),
+            sections: SectionManager::new(),
+        }
+    }
+
+    /// Use `time_zone` as the local time zone instead of UTC
+    pub fn with_time_zone(mut self, time_zone: TimeZoneInformation) -> Self {
+        self.time_zone = time_zone;
+        self
+    }
+
+    /// Local time zone and the `TIME_ZONE_ID_*` currently in effect, as
+    /// returned by `GetTimeZoneInformation`
+    pub fn time_zone_information(&self) -> (u32, &TimeZoneInformation) {
+        (self.time_zone.current_id(), &self.time_zone)
+    }
+
+    /// Drop the timer resolution request of an exited process
+    pub fn release_timer_resolution(&self, pid: u32) {
+        self.timer_resolution.release(pid);
+    }
+
+    /// Unmap the views and drop the section handles of an exited process
+    pub fn release_sections(&self, pid: u32) {
+        self.sections.release(pid);
+    }
+
+    /// Section objects and their views
+    pub fn sections(&self) -> &SectionManager {
+        &self.sections
     }
 
     /// Translate and execute an NT syscall
@@ -56,6 +110,13 @@ impl NtSyscallTranslator {
             NtSyscall::NtFreeVirtualMemory => self.nt_free_virtual_memory(process, args),
             NtSyscall::NtProtectVirtualMemory => self.nt_protect_virtual_memory(process, args),
 
+            // Sections
+            NtSyscall::NtCreateSection => self.nt_create_section(process, args),
+            NtSyscall::NtOpenSection => self.nt_open_section(process, args),
+            NtSyscall::NtMapViewOfSection => self.nt_map_view_of_section(process, args),
+            NtSyscall::NtUnmapViewOfSection => self.nt_unmap_view_of_section(process, args),
+            NtSyscall::NtExtendSection => self.nt_extend_section(process, args),
+
             // Process/Thread
             NtSyscall::NtTerminateProcess => self.nt_terminate_process(process, args),
 
@@ -63,6 +124,14 @@ impl NtSyscallTranslator {
             NtSyscall::NtWaitForSingleObject => self.nt_wait_for_single_object(process, args),
             NtSyscall::NtDelayExecution => self.nt_delay_execution(process, args),
 
+            // Time
+            NtSyscall::NtQuerySystemTime => self.nt_query_system_time(process, args),
+            NtSyscall::NtQueryPerformanceCounter => {
+                self.nt_query_performance_counter(process, args)
+            }
+            NtSyscall::NtQueryTimerResolution => self.nt_query_timer_resolution(process, args),
+            NtSyscall::NtSetTimerResolution => self.nt_set_timer_resolution(process, args),
+
             // System
             NtSyscall::NtQuerySystemInformation => self.nt_query_system_information(process, args),
 
@@ -82,6 +151,7 @@ impl NtSyscallTranslator {
             // Close the Redox file descriptor
             // TODO: syscall::close(fd)
             process.close_handle(handle);
+            self.sections.close(process.pid, handle);
             TranslateResult::Success(0)
         } else {
             TranslateResult::Error(NtStatus::InvalidHandle)
@@ -189,6 +259,189 @@ impl NtSyscallTranslator {
         TranslateResult::Error(NtStatus::NotImplemented)
     }
 
+    // =========================================================================
+    // Sections
+    // =========================================================================
+
+    fn nt_create_section(&self, process: &Arc<WinProcess>, args: &[usize; 12]) -> TranslateResult {
+        match self.create_section(process, args) {
+            Ok(status) => TranslateResult::Success(status as usize),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
+    fn create_section(
+        &self,
+        process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> Result<NtStatus, NtStatus> {
+        let section_handle = args[0]; // PHANDLE
+        // let desired_access = args[1];
+        let object_attributes = args[2]; // POBJECT_ATTRIBUTES, optional
+        let maximum_size = args[3]; // PLARGE_INTEGER, optional
+        let page_protection = args[4] as u32;
+        let allocation_attributes = args[5] as u32;
+        let file_handle = args[6]; // optional
+
+        // Images are mapped by the PE loader
+        if allocation_attributes & SEC_IMAGE != 0 {
+            return Err(NtStatus::NotImplemented);
+        }
+        let protection = Protection::from_page(page_protection)?;
+        let (name, attributes) = unsafe { read_object_attributes(object_attributes)? };
+        let open_existing = attributes & OBJ_OPENIF != 0;
+        let size = match maximum_size {
+            0 => 0,
+            addr => u64::try_from(unsafe { read_user::<i64>(addr)? })
+                .map_err(|_| NtStatus::InvalidParameter)?,
+        };
+
+        let (section, existed) = match file_handle {
+            0 => self
+                .sections
+                .create_pagefile(name.as_deref(), size, protection, open_existing)?,
+            handle => {
+                let fd = process
+                    .get_fd(Handle(handle as u32))
+                    .ok_or(NtStatus::InvalidHandle)?;
+                let size = (size != 0).then_some(size);
+                self.sections
+                    .create_file(name.as_deref(), fd, size, protection, open_existing)?
+            }
+        };
+
+        let handle = process.alloc_handle(section.fd());
+        self.sections.insert_handle(process.pid, handle, section);
+        if let Err(status) = unsafe { write_user(section_handle, handle.0 as usize) } {
+            process.close_handle(handle);
+            self.sections.close(process.pid, handle);
+            return Err(status);
+        }
+        Ok(if existed {
+            NtStatus::ObjectNameExists
+        } else {
+            NtStatus::Success
+        })
+    }
+
+    fn nt_open_section(&self, process: &Arc<WinProcess>, args: &[usize; 12]) -> TranslateResult {
+        let section_handle = args[0]; // PHANDLE
+        // let desired_access = args[1];
+        let object_attributes = args[2]; // POBJECT_ATTRIBUTES
+
+        let result = unsafe { read_object_attributes(object_attributes) }.and_then(|(name, _)| {
+            let section = self
+                .sections
+                .open(&name.ok_or(NtStatus::ObjectNameInvalid)?)?;
+            let handle = process.alloc_handle(section.fd());
+            self.sections.insert_handle(process.pid, handle, section);
+            unsafe { write_user(section_handle, handle.0 as usize) }
+        });
+        match result {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
+    fn nt_map_view_of_section(
+        &self,
+        process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> TranslateResult {
+        match self.map_view_of_section(process, args) {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
+    fn map_view_of_section(
+        &self,
+        process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> Result<(), NtStatus> {
+        let section_handle = Handle(args[0] as u32);
+        let process_handle = args[1];
+        let base_address = args[2]; // PVOID*, in/out
+        // let zero_bits = args[3];
+        // let commit_size = args[4];
+        let section_offset = args[5]; // PLARGE_INTEGER, optional, in/out
+        let view_size = args[6]; // PSIZE_T, in/out
+        // let inherit_disposition = args[7];
+        // let allocation_type = args[8];
+        let win32_protect = args[9] as u32;
+
+        let section = self
+            .sections
+            .get(process.pid, section_handle)
+            .ok_or(NtStatus::InvalidHandle)?;
+        // TODO: Map into other processes once they run in their own address space
+        if process_handle != NT_CURRENT_PROCESS {
+            return Err(NtStatus::NotImplemented);
+        }
+        let protection = Protection::from_page(win32_protect)?;
+        let base = unsafe { read_user::<usize>(base_address)? };
+        let mut size = unsafe { read_user::<usize>(view_size)? };
+        let offset = match section_offset {
+            0 => 0,
+            addr => u64::try_from(unsafe { read_user::<i64>(addr)? })
+                .map_err(|_| NtStatus::InvalidParameter)?,
+        };
+
+        // The offset is rounded down to the allocation granularity, and the
+        // view grows to still cover the requested range.
+        let aligned = offset - offset % ALLOCATION_GRANULARITY as u64;
+        if size != 0 {
+            size += (offset - aligned) as usize;
+        }
+
+        let (base, size) =
+            self.sections
+                .map_view(process.pid, &section, base, aligned, size, protection)?;
+        unsafe {
+            write_user(base_address, base)?;
+            write_user(view_size, size)?;
+            if section_offset != 0 {
+                write_user(section_offset, aligned as i64)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn nt_unmap_view_of_section(
+        &self,
+        process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> TranslateResult {
+        let process_handle = args[0];
+        let base_address = args[1];
+
+        if process_handle != NT_CURRENT_PROCESS {
+            return TranslateResult::Error(NtStatus::NotImplemented);
+        }
+        match self.sections.unmap_view(process.pid, base_address) {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
+    fn nt_extend_section(&self, process: &Arc<WinProcess>, args: &[usize; 12]) -> TranslateResult {
+        let section_handle = Handle(args[0] as u32);
+        let new_section_size = args[1]; // PLARGE_INTEGER, in/out
+
+        let Some(section) = self.sections.get(process.pid, section_handle) else {
+            return TranslateResult::Error(NtStatus::InvalidHandle);
+        };
+        let result = unsafe { read_user::<i64>(new_section_size) }.and_then(|size| {
+            let size = u64::try_from(size).map_err(|_| NtStatus::InvalidParameter)?;
+            let size = self.sections.extend(&section, size)?;
+            unsafe { write_user(new_section_size, size as i64) }
+        });
+        match result {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
     // =========================================================================
     // Process/Thread Operations
     // =========================================================================
@@ -205,6 +458,8 @@ impl NtSyscallTranslator {
         process
             .exit_code
             .store(exit_status, std::sync::atomic::Ordering::SeqCst);
+        self.timer_resolution.release(process.pid);
+        self.sections.release(process.pid);
 
         // TODO: Actually terminate via Redox syscall
         TranslateResult::Success(0)
@@ -237,10 +492,103 @@ impl NtSyscallTranslator {
         args: &[usize; 12],
     ) -> TranslateResult {
         let _alertable = args[0];
-        let _delay_interval = args[1]; // PLARGE_INTEGER (100ns units, negative = relative)
+        // PLARGE_INTEGER (100ns units, negative = relative, positive = absolute system time)
+        let interval = match unsafe { read_user::<i64>(args[1]) } {
+            Ok(interval) => interval,
+            Err(status) => return TranslateResult::Error(status),
+        };
+
+        let ticks = if interval < 0 {
+            interval.unsigned_abs()
+        } else {
+            interval.saturating_sub(time::system_time()).max(0) as u64
+        };
+        if ticks == 0 {
+            thread::yield_now();
+            return TranslateResult::Success(0);
+        }
 
-        // TODO: Translate to Redox nanosleep
-        TranslateResult::Error(NtStatus::NotImplemented)
+        // Sleeps end on a timer tick, so round up to the resolution in effect.
+        let resolution = u64::from(self.timer_resolution.current());
+        let ticks = ticks.div_ceil(resolution).saturating_mul(resolution);
+        thread::sleep(Duration::from_nanos(ticks.saturating_mul(100)));
+        TranslateResult::Success(0)
+    }
+
+    // =========================================================================
+    // Time
+    // =========================================================================
+
+    fn nt_query_system_time(
+        &self,
+        _process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> TranslateResult {
+        let system_time = args[0]; // PLARGE_INTEGER
+
+        match unsafe { write_user(system_time, time::system_time()) } {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
+    fn nt_query_performance_counter(
+        &self,
+        _process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> TranslateResult {
+        let counter = args[0]; // PLARGE_INTEGER
+        let frequency = args[1]; // PLARGE_INTEGER, optional
+
+        let result = unsafe {
+            write_user(counter, self.counter.now()).and_then(|()| match frequency {
+                0 => Ok(()),
+                _ => write_user(frequency, time::PERFORMANCE_FREQUENCY),
+            })
+        };
+        match result {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
+    fn nt_query_timer_resolution(
+        &self,
+        _process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> TranslateResult {
+        let minimum = args[0]; // PULONG, coarsest
+        let maximum = args[1]; // PULONG, finest
+        let current = args[2]; // PULONG
+
+        let result = unsafe {
+            write_user(minimum, time::MIN_TIMER_RESOLUTION)
+                .and_then(|()| write_user(maximum, time::MAX_TIMER_RESOLUTION))
+                .and_then(|()| write_user(current, self.timer_resolution.current()))
+        };
+        match result {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
+    }
+
+    fn nt_set_timer_resolution(
+        &self,
+        process: &Arc<WinProcess>,
+        args: &[usize; 12],
+    ) -> TranslateResult {
+        let desired = args[0] as u32;
+        let set = args[1] != 0;
+        let current = args[2]; // PULONG
+
+        let result = self
+            .timer_resolution
+            .set(process.pid, desired, set)
+            .and_then(|resolution| unsafe { write_user(current, resolution) });
+        match result {
+            Ok(()) => TranslateResult::Success(0),
+            Err(status) => TranslateResult::Error(status),
+        }
     }
 
     // =========================================================================
@@ -252,14 +600,83 @@ impl NtSyscallTranslator {
         _process: &Arc<WinProcess>,
         args: &[usize; 12],
     ) -> TranslateResult {
-        let _system_information_class = args[0];
-        // let system_information = args[1];
-        // let length = args[2];
-        // let return_length = args[3];
+        let system_information_class = args[0];
+        let system_information = args[1];
+        let length = args[2];
+        let return_length = args[3]; // PULONG, optional
+
+        let data = match system_information_class {
+            SYSTEM_CURRENT_TIME_ZONE_INFORMATION => self.time_zone.as_bytes(),
+            // TODO: Translate other system info queries
+            _ => return TranslateResult::Error(NtStatus::NotImplemented),
+        };
+
+        if return_length != 0 {
+            if let Err(status) = unsafe { write_user(return_length, data.len() as u32) } {
+                return TranslateResult::Error(status);
+            }
+        }
+        if length < data.len() {
+            return TranslateResult::Error(NtStatus::InfoLengthMismatch);
+        }
+        if system_information == 0 {
+            return TranslateResult::Error(NtStatus::AccessViolation);
+        }
 
-        // TODO: Translate system info queries
-        TranslateResult::Error(NtStatus::NotImplemented)
+        // TODO: Copy into the process' address space once processes run separately
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), system_information as *mut u8, data.len());
+        }
+        TranslateResult::Success(0)
+    }
+}
+
+/// Read a `T` from a caller supplied pointer
+///
+/// # Safety
+/// `addr` must be null or valid for reads of `T`.
+unsafe fn read_user<T: Copy>(addr: usize) -> Result<T, NtStatus> {
+    if addr == 0 {
+        return Err(NtStatus::AccessViolation);
+    }
+    Ok(unsafe { (addr as *const T).read_unaligned() })
+}
+
+/// Read the object name and attribute flags from an optional
+/// `OBJECT_ATTRIBUTES`
+///
+/// # Safety
+/// `addr` must be null or point to valid object attributes.
+unsafe fn read_object_attributes(addr: usize) -> Result<(Option<String>, u32), NtStatus> {
+    if addr == 0 {
+        return Ok((None, 0));
+    }
+    let attributes = unsafe { read_user::<ObjectAttributes>(addr)? };
+    if attributes.object_name.is_null() {
+        return Ok((None, attributes.attributes));
+    }
+    let name = unsafe { read_user::<UnicodeString>(attributes.object_name as usize)? };
+    if name.length == 0 {
+        return Ok((None, attributes.attributes));
+    }
+    if name.buffer.is_null() {
+        return Err(NtStatus::AccessViolation);
+    }
+    let units = unsafe { std::slice::from_raw_parts(name.buffer, usize::from(name.length) / 2) };
+    let name = String::from_utf16(units).map_err(|_| NtStatus::ObjectNameInvalid)?;
+    Ok((Some(name), attributes.attributes))
+}
+
+/// Write `value` to a caller supplied pointer
+///
+/// # Safety
+/// `addr` must be null or valid for writes of `T`.
+unsafe fn write_user<T: Copy>(addr: usize, value: T) -> Result<(), NtStatus> {
+    if addr == 0 {
+        return Err(NtStatus::AccessViolation);
     }
+    unsafe { (addr as *mut T).write_unaligned(value) };
+    Ok(())
 }
 
 impl Default for NtSyscallTranslator {